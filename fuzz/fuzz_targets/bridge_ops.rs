@@ -0,0 +1,225 @@
+//! Arbitrary sequences of `BlitzJsBridge` calls against a small page, the way scripts drive
+//! them through the `document` API. Any call may fail, but none may panic or hang, and the
+//! tree they leave must still serialize.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use blitz_dom::DocumentConfig;
+use blitz_html::HtmlDocument;
+use frontier::js::bridge::BlitzJsBridge;
+use libfuzzer_sys::fuzz_target;
+
+const PAGE: &str = "<!DOCTYPE html><html><head><title>fuzz</title></head><body>\
+    <div id=\"a\"><p id=\"b\">text<!--note--></p><svg id=\"c\"><rect/></svg></div>\
+    <ul><li>one</li><li>two</li></ul><input id=\"d\" type=\"checkbox\" checked>\
+    <template id=\"e\"><b id=\"f\">inert</b><template><i>nested</i></template></template>\
+    </body></html>";
+
+/// `insertAdjacentHTML` positions, plus one it must reject.
+const POSITIONS: [&str; 5] = [
+    "beforebegin",
+    "afterbegin",
+    "beforeend",
+    "afterend",
+    "inside",
+];
+
+/// Longer sequences mostly repeat what shorter ones reach, slower.
+const MAX_OPS: usize = 64;
+
+/// A node to operate on: one from the page or created so far, or any id at all.
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum Node {
+    Known(u8),
+    Raw(u16),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Namespace {
+    Html,
+    Svg,
+    MathMl,
+    Xlink,
+    Other(String),
+}
+
+impl Namespace {
+    fn uri(&self) -> &str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::Xlink => "http://www.w3.org/1999/xlink",
+            Namespace::Other(uri) => uri,
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    FindById(String),
+    SetTextContent(Node, String),
+    SetInnerHtml(Node, String),
+    SetOuterHtml(Node, String),
+    InsertAdjacentHtml(Node, u8, String),
+    ParseFragment(Node, String),
+    SetAttribute(Node, String, String),
+    RemoveAttribute(Node, String),
+    SetAttributeNs(Node, Option<Namespace>, String, String),
+    RemoveAttributeNs(Node, Option<Namespace>, String),
+    CreateElement(String, Option<Namespace>),
+    CreateText(String),
+    CreateComment(String),
+    AppendChild(Node, Node),
+    InsertBefore(Node, Node, Option<Node>),
+    RemoveChild(Node, Node),
+    ReplaceChild(Node, Node, Node),
+    CloneNode(Node, bool),
+    SplitText(Node, u16),
+    Normalize(Node),
+    TemplateContent(Node),
+    AttachShadow(Node, bool),
+    SetStyleRules(Node, String),
+    SetAdoptedStyles(Node, String),
+    SetLinkStyles(Node, String),
+    SetCanvasStyles(Node, String),
+    Read(Node),
+}
+
+fn resolve(node: Node, known: &[usize]) -> usize {
+    match node {
+        Node::Known(index) => known[index as usize % known.len()],
+        Node::Raw(id) => id as usize,
+    }
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let mut document = HtmlDocument::from_html(PAGE, DocumentConfig::default());
+    let mut known = Vec::new();
+    let root = document.root_node().id;
+    document.iter_subtree_mut(root, |node_id, _| known.push(node_id));
+    let mut bridge = BlitzJsBridge::new(&mut document);
+
+    for op in ops.into_iter().take(MAX_OPS) {
+        let node = |node: Node| resolve(node, &known);
+        let created = match op {
+            Op::FindById(id) => bridge.find_node_by_html_id(&id).map(Ok),
+            Op::SetTextContent(target, value) => {
+                let _ = bridge.set_text_content(node(target), &value);
+                None
+            }
+            Op::SetInnerHtml(target, value) => {
+                let _ = bridge.set_inner_html(node(target), &value);
+                None
+            }
+            Op::SetOuterHtml(target, value) => {
+                let _ = bridge.set_outer_html(node(target), &value);
+                None
+            }
+            Op::InsertAdjacentHtml(target, position, value) => {
+                let position = POSITIONS[position as usize % POSITIONS.len()];
+                let _ = bridge.insert_adjacent_html(node(target), position, &value);
+                None
+            }
+            Op::ParseFragment(context, value) => {
+                if let Ok(nodes) = bridge.parse_fragment(node(context), &value) {
+                    known.extend(nodes);
+                }
+                None
+            }
+            Op::SetAttribute(target, name, value) => {
+                let _ = bridge.set_attribute(node(target), &name, &value);
+                None
+            }
+            Op::RemoveAttribute(target, name) => {
+                let _ = bridge.remove_attribute(node(target), &name);
+                None
+            }
+            Op::SetAttributeNs(target, namespace, name, value) => {
+                let namespace = namespace.as_ref().map(Namespace::uri);
+                let _ = bridge.set_attribute_ns(node(target), namespace, &name, &value);
+                None
+            }
+            Op::RemoveAttributeNs(target, namespace, name) => {
+                let namespace = namespace.as_ref().map(Namespace::uri);
+                let _ = bridge.remove_attribute_ns(node(target), namespace, &name);
+                None
+            }
+            Op::CreateElement(name, namespace) => {
+                Some(bridge.create_element(&name, namespace.as_ref().map(Namespace::uri)))
+            }
+            Op::CreateText(value) => Some(bridge.create_text_node(&value)),
+            Op::CreateComment(value) => Some(bridge.create_comment_node(&value)),
+            Op::AppendChild(parent, child) => {
+                let _ = bridge.append_child(node(parent), node(child));
+                None
+            }
+            Op::InsertBefore(parent, child, reference) => {
+                let reference = reference.map(node);
+                let _ = bridge.insert_before(node(parent), node(child), reference);
+                None
+            }
+            Op::RemoveChild(parent, child) => {
+                let _ = bridge.remove_child(node(parent), node(child));
+                None
+            }
+            Op::ReplaceChild(parent, new_child, old_child) => {
+                let _ = bridge.replace_child(node(parent), node(new_child), node(old_child));
+                None
+            }
+            Op::CloneNode(source, deep) => Some(bridge.clone_node(node(source), deep)),
+            Op::SplitText(target, offset) => Some(bridge.split_text(node(target), offset.into())),
+            Op::Normalize(target) => {
+                let _ = bridge.normalize(node(target));
+                None
+            }
+            Op::TemplateContent(target) => Some(bridge.template_content(node(target))),
+            Op::AttachShadow(target, open) => Some(bridge.attach_shadow(node(target), open)),
+            Op::SetStyleRules(target, css) => {
+                let _ = bridge.set_style_rules(node(target), &css);
+                None
+            }
+            Op::SetAdoptedStyles(target, css) => {
+                let _ = bridge.set_adopted_styles(node(target), &css);
+                None
+            }
+            Op::SetLinkStyles(target, css) => {
+                let _ = bridge.set_link_styles(node(target), &css);
+                None
+            }
+            Op::SetCanvasStyles(target, css) => {
+                let _ = bridge.set_canvas_styles(node(target), &css);
+                None
+            }
+            Op::Read(target) => {
+                let id = node(target);
+                let _ = bridge.parent_node(id);
+                let _ = bridge.first_child(id);
+                let _ = bridge.next_sibling(id);
+                let _ = bridge.previous_sibling(id);
+                let _ = bridge.child_nodes(id);
+                let _ = bridge.node_name(id);
+                let _ = bridge.node_type(id);
+                let _ = bridge.node_value(id);
+                let _ = bridge.get_attribute(id, "id");
+                let _ = bridge.get_attribute_ns(id, Some(Namespace::Xlink.uri()), "href");
+                let _ = bridge.namespace_uri(id);
+                let _ = bridge.text_content(id);
+                let _ = bridge.inner_html(id);
+                let _ = bridge.outer_html(id);
+                let _ = bridge.shadow_root(id);
+                let _ = bridge.assigned_nodes(id);
+                let _ = bridge.assigned_slot(id);
+                let _ = bridge.event_path(id);
+                let _ = bridge.layout_rect(id);
+                None
+            }
+        };
+        if let Some(Ok(node_id)) = created {
+            known.push(node_id);
+        }
+    }
+
+    let _ = bridge.serialize_document();
+});
@@ -0,0 +1,36 @@
+//! `DomPatch` JSON as a page sends it with `frontier.emitDomPatch`, one patch per line,
+//! deserialized and applied to a small page. Malformed or hostile patches may be rejected but
+//! must not panic, and the document must still serialize afterwards.
+
+#![no_main]
+
+use blitz_dom::DocumentConfig;
+use blitz_html::HtmlDocument;
+use frontier::js::dom::{DomPatch, DomState};
+use libfuzzer_sys::fuzz_target;
+
+const PAGE: &str = "<!DOCTYPE html><html><head><title>fuzz</title></head><body>\
+    <div id=\"a\"><p id=\"b\">text<!--note--></p><svg id=\"c\"><rect/></svg></div>\
+    <ul><li>one</li><li>two</li></ul><input id=\"d\" type=\"checkbox\" checked></body></html>";
+
+const MAX_PATCHES: usize = 64;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let mut document = HtmlDocument::from_html(PAGE, DocumentConfig::default());
+    let mut state = DomState::new(PAGE);
+    // SAFETY: `document` is declared first, so it stays put and outlives the state, whose
+    // bridge points into it.
+    unsafe { state.attach_document(&mut document) };
+
+    for line in text.lines().take(MAX_PATCHES) {
+        if let Ok(patch) = serde_json::from_str::<DomPatch>(line) {
+            let _ = state.apply_patch(patch);
+        }
+    }
+
+    let _ = state.to_html();
+    let _ = state.drain_mutations();
+});
@@ -0,0 +1,544 @@
+//! Accessibility tree computed from the blitz DOM.
+//!
+//! [`AccessibilityTree::from_document`] walks the document and gives every exposed element a
+//! role (an explicit `role` attribute wins over the implicit HTML role), an accessible name and
+//! its ARIA states. Elements without a meaningful role are flattened into their parent, and
+//! hidden subtrees (`hidden`, `aria-hidden="true"`, `<script>` and friends) are pruned, the way
+//! browsers build their platform trees.
+//!
+//! Only automation uses this tree, for role selectors. It is not handed to the platform:
+//! screen readers get the tree blitz-shell's AccessKit adapter builds with its own role and
+//! name rules, which can differ from the ones here.
+
+use std::collections::HashMap;
+
+use blitz_dom::node::NodeData;
+use blitz_dom::{local_name, BaseDocument};
+use serde::Serialize;
+
+/// ARIA states reported for a node; `None` means the state does not apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AccessibleStates {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub multiline: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expanded: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<usize>,
+}
+
+impl AccessibleStates {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessibleNode {
+    /// Blitz node id, usable with the document directly.
+    pub node_id: usize,
+    /// ARIA role name, e.g. `button` or `heading`; `text` for static text runs.
+    pub role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "AccessibleStates::is_empty")]
+    pub states: AccessibleStates,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<AccessibleNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessibilityTree {
+    pub root: AccessibleNode,
+}
+
+impl AccessibilityTree {
+    pub fn from_document(doc: &BaseDocument) -> Self {
+        let builder = Builder::new(doc);
+        let root_id = doc.root_node().id;
+        let children = builder.children_of(root_id);
+        Self {
+            root: AccessibleNode {
+                node_id: root_id,
+                role: "document".to_string(),
+                name: builder.document_title(),
+                states: AccessibleStates::default(),
+                children,
+            },
+        }
+    }
+
+    /// First node in tree order with `role` and, when given, a name equal to `name` ignoring
+    /// case and surrounding whitespace.
+    pub fn find(&self, role: &str, name: Option<&str>) -> Option<&AccessibleNode> {
+        let role = role.trim().to_ascii_lowercase();
+        let name = name.map(normalize_name);
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            if node.role == role
+                && name.as_deref().map_or(true, |wanted| {
+                    node.name.as_deref().map(normalize_name).as_deref() == Some(wanted)
+                })
+            {
+                return Some(node);
+            }
+            stack.extend(node.children.iter().rev());
+        }
+        None
+    }
+}
+
+/// Roles whose name falls back to their text content.
+const NAME_FROM_CONTENT: &[&str] = &[
+    "button",
+    "link",
+    "heading",
+    "cell",
+    "columnheader",
+    "option",
+    "tab",
+    "menuitem",
+    "checkbox",
+    "radio",
+    "listitem",
+];
+
+struct Builder<'a> {
+    doc: &'a BaseDocument,
+    ids: HashMap<String, usize>,
+    /// `<label for=...>` targets to label node ids.
+    labels_for: HashMap<String, Vec<usize>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(doc: &'a BaseDocument) -> Self {
+        let mut builder = Self {
+            doc,
+            ids: HashMap::new(),
+            labels_for: HashMap::new(),
+        };
+        builder.index(doc.root_node().id);
+        builder
+    }
+
+    fn index(&mut self, node_id: usize) {
+        let Some(node) = self.doc.get_node(node_id) else {
+            return;
+        };
+        if let Some(id) = node.attr(local_name!("id")) {
+            self.ids.entry(id.to_string()).or_insert(node_id);
+        }
+        if tag_name(self.doc, node_id) == Some("label") {
+            if let Some(target) = node.attr(local_name!("for")) {
+                self.labels_for
+                    .entry(target.to_string())
+                    .or_default()
+                    .push(node_id);
+            }
+        }
+        for child in node.children.clone() {
+            self.index(child);
+        }
+    }
+
+    fn document_title(&self) -> Option<String> {
+        let title = self.find_tag(self.doc.root_node().id, "title")?;
+        non_empty(&self.text_of(title))
+    }
+
+    fn find_tag(&self, node_id: usize, tag: &str) -> Option<usize> {
+        if tag_name(self.doc, node_id) == Some(tag) {
+            return Some(node_id);
+        }
+        let node = self.doc.get_node(node_id)?;
+        node.children
+            .iter()
+            .find_map(|child| self.find_tag(*child, tag))
+    }
+
+    fn children_of(&self, node_id: usize) -> Vec<AccessibleNode> {
+        let mut out = Vec::new();
+        if let Some(node) = self.doc.get_node(node_id) {
+            for child in &node.children {
+                self.collect(*child, &mut out);
+            }
+        }
+        out
+    }
+
+    /// Append the exposed nodes for `node_id`: itself if it has a role, otherwise its
+    /// exposed descendants.
+    fn collect(&self, node_id: usize, out: &mut Vec<AccessibleNode>) {
+        let Some(node) = self.doc.get_node(node_id) else {
+            return;
+        };
+        match &node.data {
+            NodeData::Text(text) => {
+                if let Some(content) = non_empty(&collapse_whitespace(&text.content)) {
+                    out.push(AccessibleNode {
+                        node_id,
+                        role: "text".to_string(),
+                        name: Some(content),
+                        states: AccessibleStates::default(),
+                        children: Vec::new(),
+                    });
+                }
+            }
+            NodeData::Element(_) => {
+                if self.is_hidden(node_id) {
+                    return;
+                }
+                match self.role_of(node_id) {
+                    Some(role) => {
+                        let states = self.states_of(node_id, &role);
+                        let name = self.name_of(node_id, &role);
+                        let children = if is_leaf_role(&role) {
+                            Vec::new()
+                        } else {
+                            self.children_of(node_id)
+                        };
+                        out.push(AccessibleNode {
+                            node_id,
+                            role,
+                            name,
+                            states,
+                            children,
+                        });
+                    }
+                    None => {
+                        for child in &node.children {
+                            self.collect(*child, out);
+                        }
+                    }
+                }
+            }
+            NodeData::Document | NodeData::AnonymousBlock(_) => {
+                for child in &node.children {
+                    self.collect(*child, out);
+                }
+            }
+            NodeData::Comment => {}
+        }
+    }
+
+    fn is_hidden(&self, node_id: usize) -> bool {
+        let Some(node) = self.doc.get_node(node_id) else {
+            return true;
+        };
+        if node.attr(local_name!("hidden")).is_some()
+            || node.attr(local_name!("aria-hidden")) == Some("true")
+        {
+            return true;
+        }
+        match tag_name(self.doc, node_id) {
+            Some("head" | "script" | "style" | "template" | "noscript" | "meta" | "link") => true,
+            Some("input") => node.attr(local_name!("type")) == Some("hidden"),
+            _ => false,
+        }
+    }
+
+    fn role_of(&self, node_id: usize) -> Option<String> {
+        let node = self.doc.get_node(node_id)?;
+        if let Some(explicit) = node.attr(local_name!("role")) {
+            // The first token is the preferred role; `none`/`presentation` drop the element.
+            let role = explicit.split_whitespace().next()?.to_ascii_lowercase();
+            return match role.as_str() {
+                "none" | "presentation" => None,
+                _ => Some(role),
+            };
+        }
+        let tag = tag_name(self.doc, node_id)?;
+        let role = match tag {
+            "a" | "area" if node.attr(local_name!("href")).is_some() => "link",
+            "button" => "button",
+            "input" => match node
+                .attr(local_name!("type"))
+                .unwrap_or("text")
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "button" | "submit" | "reset" | "image" => "button",
+                "checkbox" => "checkbox",
+                "radio" => "radio",
+                "range" => "slider",
+                "number" => "spinbutton",
+                "search" => "searchbox",
+                _ => "textbox",
+            },
+            "textarea" => "textbox",
+            "select" => {
+                let multiple = node.attr(local_name!("multiple")).is_some();
+                let size = node
+                    .attr(local_name!("size"))
+                    .and_then(|value| value.trim().parse::<u32>().ok())
+                    .unwrap_or(0);
+                if multiple || size > 1 {
+                    "listbox"
+                } else {
+                    "combobox"
+                }
+            }
+            "option" => "option",
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+            "img" => match node.attr(local_name!("alt")) {
+                Some(alt) if alt.is_empty() => return None,
+                _ => "img",
+            },
+            "ul" | "ol" | "menu" => "list",
+            "li" => "listitem",
+            "nav" => "navigation",
+            "main" => "main",
+            "header" => "banner",
+            "footer" => "contentinfo",
+            "aside" => "complementary",
+            "section" if self.has_explicit_label(node_id) => "region",
+            "form" => "form",
+            "article" => "article",
+            "table" => "table",
+            "tr" => "row",
+            "td" => "cell",
+            "th" => "columnheader",
+            "p" => "paragraph",
+            "dialog" => "dialog",
+            "progress" => "progressbar",
+            "fieldset" => "group",
+            _ => return None,
+        };
+        Some(role.to_string())
+    }
+
+    fn has_explicit_label(&self, node_id: usize) -> bool {
+        self.doc.get_node(node_id).is_some_and(|node| {
+            node.attr(local_name!("aria-label")).is_some()
+                || node.attr(local_name!("aria-labelledby")).is_some()
+        })
+    }
+
+    fn name_of(&self, node_id: usize, role: &str) -> Option<String> {
+        let node = self.doc.get_node(node_id)?;
+
+        if let Some(ids) = node.attr(local_name!("aria-labelledby")) {
+            let joined = ids
+                .split_whitespace()
+                .filter_map(|id| self.ids.get(id))
+                .map(|id| self.text_of(*id))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(name) = non_empty(&joined) {
+                return Some(name);
+            }
+        }
+        if let Some(name) = node.attr(local_name!("aria-label")).and_then(non_empty) {
+            return Some(name);
+        }
+
+        match tag_name(self.doc, node_id) {
+            Some("input" | "select" | "textarea") => {
+                if let Some(name) = self.label_text(node_id) {
+                    return Some(name);
+                }
+                let input_type = node.attr(local_name!("type")).unwrap_or("text");
+                if role == "button" {
+                    let fallback = match input_type {
+                        "submit" => "Submit",
+                        "reset" => "Reset",
+                        _ => "",
+                    };
+                    return node
+                        .attr(local_name!("value"))
+                        .and_then(non_empty)
+                        .or_else(|| non_empty(fallback));
+                }
+                if let Some(name) = node.attr(local_name!("placeholder")).and_then(non_empty) {
+                    return Some(name);
+                }
+            }
+            Some("img" | "area") => {
+                if let Some(name) = node.attr(local_name!("alt")).and_then(non_empty) {
+                    return Some(name);
+                }
+            }
+            _ => {}
+        }
+
+        if NAME_FROM_CONTENT.contains(&role) {
+            if let Some(name) = non_empty(&self.text_of(node_id)) {
+                return Some(name);
+            }
+        }
+        node.attr(local_name!("title")).and_then(non_empty)
+    }
+
+    /// Text of a `<label for>` pointing at the control, or of a wrapping `<label>`.
+    fn label_text(&self, node_id: usize) -> Option<String> {
+        let node = self.doc.get_node(node_id)?;
+        if let Some(labels) = node
+            .attr(local_name!("id"))
+            .and_then(|id| self.labels_for.get(id))
+        {
+            let joined = labels
+                .iter()
+                .map(|label| self.text_of(*label))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(name) = non_empty(&joined) {
+                return Some(name);
+            }
+        }
+        let mut current = node.parent;
+        while let Some(parent) = current {
+            if tag_name(self.doc, parent) == Some("label") {
+                return non_empty(&self.text_of(parent));
+            }
+            current = self.doc.get_node(parent).and_then(|node| node.parent);
+        }
+        None
+    }
+
+    fn states_of(&self, node_id: usize, role: &str) -> AccessibleStates {
+        let Some(node) = self.doc.get_node(node_id) else {
+            return AccessibleStates::default();
+        };
+        let aria_bool = |name| match node.attr(name) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+
+        let checked = match role {
+            "checkbox" | "radio" => aria_bool(local_name!("aria-checked"))
+                .or(Some(node.attr(local_name!("checked")).is_some())),
+            _ => aria_bool(local_name!("aria-checked")),
+        };
+        let selected = match role {
+            "option" => aria_bool(local_name!("aria-selected"))
+                .or(Some(node.attr(local_name!("selected")).is_some())),
+            _ => aria_bool(local_name!("aria-selected")),
+        };
+        let tag = tag_name(self.doc, node_id);
+        let level = if role == "heading" {
+            node.attr(local_name!("aria-level"))
+                .and_then(|value| value.trim().parse().ok())
+                .or_else(|| {
+                    tag.and_then(|tag| tag.strip_prefix('h'))
+                        .and_then(|digit| digit.parse().ok())
+                })
+                // ARIA's default for role="heading" without a level.
+                .or(Some(2))
+        } else {
+            None
+        };
+
+        AccessibleStates {
+            disabled: node.attr(local_name!("disabled")).is_some()
+                || aria_bool(local_name!("aria-disabled")) == Some(true),
+            required: node.attr(local_name!("required")).is_some()
+                || aria_bool(local_name!("aria-required")) == Some(true),
+            multiline: role == "textbox"
+                && (tag == Some("textarea")
+                    || aria_bool(local_name!("aria-multiline")) == Some(true)),
+            checked,
+            expanded: aria_bool(local_name!("aria-expanded")),
+            selected,
+            level,
+        }
+    }
+
+    fn text_of(&self, node_id: usize) -> String {
+        self.doc
+            .get_node(node_id)
+            .map(|node| collapse_whitespace(&node.text_content()))
+            .unwrap_or_default()
+    }
+}
+
+/// Controls whose content is a value, not children worth exposing.
+fn is_leaf_role(role: &str) -> bool {
+    matches!(
+        role,
+        "img" | "textbox" | "searchbox" | "slider" | "spinbutton" | "checkbox" | "radio"
+    )
+}
+
+fn tag_name(doc: &BaseDocument, node_id: usize) -> Option<&str> {
+    match &doc.get_node(node_id)?.data {
+        NodeData::Element(data) => Some(data.name.local.as_ref()),
+        _ => None,
+    }
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+fn normalize_name(value: &str) -> String {
+    collapse_whitespace(value).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    fn tree(html: &str) -> AccessibilityTree {
+        let document = HtmlDocument::from_html(html, DocumentConfig::default());
+        AccessibilityTree::from_document(&document)
+    }
+
+    #[test]
+    fn implicit_roles_and_names() {
+        let tree = tree(
+            r#"<html><head><title>Demo</title></head><body>
+                <nav><a href="/home">Home</a></nav>
+                <h2>Settings</h2>
+                <label for="email">Email</label><input id="email" type="email">
+                <label><input type="checkbox" checked> Remember me</label>
+                <button aria-label="Close dialog">×</button>
+                <img src="logo.png" alt="">
+                <div hidden><button>Invisible</button></div>
+            </body></html>"#,
+        );
+        assert_eq!(tree.root.name.as_deref(), Some("Demo"));
+        assert!(tree.find("link", Some("home")).is_some());
+        assert_eq!(
+            tree.find("heading", Some("Settings"))
+                .map(|node| node.states.level),
+            Some(Some(2))
+        );
+        assert!(tree.find("textbox", Some("Email")).is_some());
+        let checkbox = tree.find("checkbox", Some("Remember me")).unwrap();
+        assert_eq!(checkbox.states.checked, Some(true));
+        assert!(tree.find("button", Some("close dialog")).is_some());
+        assert!(tree.find("img", None).is_none());
+        assert!(tree.find("button", Some("Invisible")).is_none());
+    }
+
+    #[test]
+    fn explicit_roles_override_and_presentation_flattens() {
+        let tree = tree(
+            r#"<html><body>
+                <div role="button" aria-disabled="true">Save</div>
+                <ul role="presentation"><li role="none"><span role="tab" aria-selected="true">One</span></li></ul>
+            </body></html>"#,
+        );
+        let save = tree.find("button", Some("Save")).unwrap();
+        assert!(save.states.disabled);
+        assert!(tree.find("list", None).is_none());
+        let tab = tree.find("tab", Some("One")).unwrap();
+        assert_eq!(tab.states.selected, Some(true));
+    }
+}
@@ -0,0 +1,742 @@
+//! Ad and tracker blocking with EasyList-style filter lists.
+//!
+//! Every `.txt` file in `filter-lists/` beside `frontier.toml` is read at startup as an
+//! Adblock Plus filter list, so dropping EasyList or EasyPrivacy there turns blocking on. The
+//! [`SubresourceLoader`](crate::subresource_loader::SubresourceLoader) checks each subresource
+//! request against the network filters before queueing it, and each page gets the element
+//! hiding filters that apply to it as an injected stylesheet.
+//!
+//! Supported: address patterns with `*`, `^`, `|` and `||`; `@@` exceptions; the options
+//! `third-party`, `domain=`, `important` and the `image`, `script`, `stylesheet`, `font` and
+//! `other` types, each negatable; `##` and `#@#` element hiding, generic or limited to
+//! domains; and `$elemhide` and `$generichide` exceptions. Rules that need anything else
+//! (regular expressions, `redirect=`, `csp=`, procedural and scriptlet filters, request types
+//! blitz never makes) are skipped rather than applied approximately. Request types are
+//! guessed from the file extension, as blitz does not say what a request is for.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use url::Url;
+
+use crate::permissions::{site, third_party};
+
+pub const DIR_NAME: &str = "filter-lists";
+
+/// Shortest run of letters and digits used to index filters.
+const MIN_TOKEN: usize = 3;
+const HIDE: &str = " { display: none !important; }\n";
+/// Extended selectors only Adblock Plus and uBlock Origin understand.
+const PROCEDURAL: [&str; 10] = [
+    ":-abp-",
+    ":has-text(",
+    ":contains(",
+    ":matches-css",
+    ":matches-path(",
+    ":min-text-length(",
+    ":remove(",
+    ":style(",
+    ":upward(",
+    ":xpath(",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceType {
+    Image,
+    Script,
+    Stylesheet,
+    Font,
+    Other,
+}
+
+impl ResourceType {
+    /// Blitz does not tag requests with their destination, so classify by extension.
+    pub fn for_url(url: &Url) -> Self {
+        let extension = url
+            .path()
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("css") => ResourceType::Stylesheet,
+            Some("js") | Some("mjs") => ResourceType::Script,
+            Some("woff") | Some("woff2") | Some("ttf") | Some("otf") => ResourceType::Font,
+            Some("png") | Some("jpg") | Some("jpeg") | Some("gif") | Some("webp")
+            | Some("avif") | Some("svg") | Some("ico") | Some("bmp") => ResourceType::Image,
+            _ => ResourceType::Other,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+const ALL_TYPES: u8 = 0b11111;
+
+/// What a `$` option naming a request type refers to.
+enum TypeOption {
+    Seen(ResourceType),
+    /// A type of request that never reaches the subresource loader.
+    Unseen,
+}
+
+fn type_option(name: &str) -> Option<TypeOption> {
+    Some(match name {
+        "image" => TypeOption::Seen(ResourceType::Image),
+        "script" => TypeOption::Seen(ResourceType::Script),
+        "stylesheet" | "css" => TypeOption::Seen(ResourceType::Stylesheet),
+        "font" => TypeOption::Seen(ResourceType::Font),
+        "other" => TypeOption::Seen(ResourceType::Other),
+        "xmlhttprequest" | "xhr" | "subdocument" | "frame" | "media" | "object" | "websocket"
+        | "ping" | "beacon" | "popup" | "document" | "doc" | "webrtc" => TypeOption::Unseen,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Anchor {
+    None,
+    /// `|`: the start of the address.
+    Start,
+    /// `||`: the start of the host name or of one of its labels.
+    Host,
+}
+
+/// What a `$elemhide` or `$generichide` exception turns off on the pages it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unhiding {
+    All,
+    Generic,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DomainList {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl DomainList {
+    fn parse(list: &str, separator: char) -> Self {
+        let mut domains = Self::default();
+        for domain in list
+            .split(separator)
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+        {
+            match domain.strip_prefix('~') {
+                Some(domain) => domains.exclude.push(domain.to_ascii_lowercase()),
+                None => domains.include.push(domain.to_ascii_lowercase()),
+            }
+        }
+        domains
+    }
+
+    fn allows(&self, host: Option<&str>) -> bool {
+        let matches = |domain: &String| host.is_some_and(|host| on_domain(host, domain));
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+fn on_domain(host: &str, domain: &str) -> bool {
+    host.strip_suffix(domain)
+        .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+}
+
+/// A request as the network filters see it.
+struct Request<'a> {
+    /// The address, lowercased.
+    url: &'a str,
+    host: Range<usize>,
+    kind: ResourceType,
+    third_party: bool,
+    page_host: Option<&'a str>,
+}
+
+#[derive(Debug)]
+struct NetworkFilter {
+    /// The rule as written, for the network log.
+    text: String,
+    anchor: Anchor,
+    /// Lowercase, with `*` and `^` wildcards.
+    body: String,
+    end_anchored: bool,
+    third_party: Option<bool>,
+    types: u8,
+    domains: DomainList,
+    important: bool,
+    unhiding: Option<Unhiding>,
+}
+
+impl NetworkFilter {
+    /// Parse a network rule, without its `@@`; `None` when it uses something unsupported.
+    fn parse(text: &str, rule: &str, exception: bool) -> Option<Self> {
+        let (pattern, options) = match rule.rsplit_once('$') {
+            Some((pattern, options)) => (pattern, Some(options)),
+            None => (rule, None),
+        };
+        if pattern.len() > 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+            return None;
+        }
+        let (anchor, rest) = if let Some(rest) = pattern.strip_prefix("||") {
+            (Anchor::Host, rest)
+        } else if let Some(rest) = pattern.strip_prefix('|') {
+            (Anchor::Start, rest)
+        } else {
+            (Anchor::None, pattern)
+        };
+        let (rest, end_anchored) = match rest.strip_suffix('|') {
+            Some(rest) => (rest, true),
+            None => (rest, false),
+        };
+        let mut body = String::with_capacity(rest.len());
+        for c in rest.chars().map(|c| c.to_ascii_lowercase()) {
+            if !(c == '*' && body.ends_with('*')) {
+                body.push(c);
+            }
+        }
+
+        let mut filter = Self {
+            text: text.to_string(),
+            anchor,
+            body,
+            end_anchored,
+            third_party: None,
+            types: ALL_TYPES,
+            domains: DomainList::default(),
+            important: false,
+            unhiding: None,
+        };
+        let (mut included, mut excluded, mut unseen) = (0, 0, false);
+        for option in options.into_iter().flat_map(|options| options.split(',')) {
+            let (negated, name) = match option.trim().strip_prefix('~') {
+                Some(name) => (true, name),
+                None => (false, option.trim()),
+            };
+            match name {
+                "third-party" | "3p" => filter.third_party = Some(!negated),
+                "first-party" | "1p" => filter.third_party = Some(negated),
+                "important" if !negated => filter.important = true,
+                "elemhide" | "ehide" if exception => filter.unhiding = Some(Unhiding::All),
+                "generichide" | "ghide" if exception => filter.unhiding = Some(Unhiding::Generic),
+                _ => {
+                    if let Some(list) = name
+                        .strip_prefix("domain=")
+                        .or_else(|| name.strip_prefix("from="))
+                    {
+                        filter.domains = DomainList::parse(list, '|');
+                        continue;
+                    }
+                    match type_option(name)? {
+                        TypeOption::Seen(kind) if negated => excluded |= kind.bit(),
+                        TypeOption::Seen(kind) => included |= kind.bit(),
+                        TypeOption::Unseen => unseen |= !negated,
+                    }
+                }
+            }
+        }
+        if included != 0 {
+            filter.types = included;
+        } else if unseen {
+            return None;
+        }
+        filter.types &= !excluded;
+        (filter.types != 0).then_some(filter)
+    }
+
+    fn matches(&self, request: &Request<'_>) -> bool {
+        self.types & request.kind.bit() != 0
+            && self
+                .third_party
+                .is_none_or(|third_party| third_party == request.third_party)
+            && self.domains.allows(request.page_host)
+            && self.matches_url(request.url, request.host.clone())
+    }
+
+    fn matches_url(&self, url: &str, host: Range<usize>) -> bool {
+        let (body, text) = (self.body.as_bytes(), url.as_bytes());
+        let end = self.end_anchored;
+        match self.anchor {
+            Anchor::Start => glob(body, text, end),
+            Anchor::Host => host
+                .clone()
+                .filter(|&start| start == host.start || text[start - 1] == b'.')
+                .any(|start| glob(body, &text[start..], end)),
+            Anchor::None => (0..=text.len()).any(|start| glob(body, &text[start..], end)),
+        }
+    }
+
+    /// The longest run of letters and digits a matching address must contain as a whole
+    /// token, used to look the filter up by the tokens of a request.
+    fn token(&self) -> Option<&str> {
+        let body = self.body.as_bytes();
+        let mut best: Option<Range<usize>> = None;
+        let mut index = 0;
+        while index < body.len() {
+            if !is_token_byte(body[index]) {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < body.len() && is_token_byte(body[index]) {
+                index += 1;
+            }
+            let bounded_left = match start {
+                0 => self.anchor != Anchor::None,
+                _ => body[start - 1] != b'*',
+            };
+            let bounded_right = match body.get(index) {
+                None => self.end_anchored,
+                Some(&next) => next != b'*',
+            };
+            let longer = best.as_ref().is_none_or(|best| index - start > best.len());
+            if bounded_left && bounded_right && index - start >= MIN_TOKEN && longer {
+                best = Some(start..index);
+            }
+        }
+        best.map(|range| &self.body[range])
+    }
+}
+
+/// Match `pattern` at the start of `text`, through its end when `end` is set.
+fn glob(pattern: &[u8], text: &[u8], end: bool) -> bool {
+    match pattern.split_first() {
+        None => !end || text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob(rest, &text[skip..], end)),
+        Some((b'^', rest)) => match text.split_first() {
+            None => glob(rest, text, end),
+            Some((&c, tail)) => is_separator(c) && glob(rest, tail, end),
+        },
+        Some((c, rest)) => text.first() == Some(c) && glob(rest, &text[1..], end),
+    }
+}
+
+fn is_separator(c: u8) -> bool {
+    !(c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'.' | b'%'))
+}
+
+fn is_token_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'%'
+}
+
+/// Network filters indexed by [`NetworkFilter::token`].
+#[derive(Debug, Default)]
+struct FilterSet {
+    filters: Vec<NetworkFilter>,
+    by_token: HashMap<String, Vec<usize>>,
+    untokened: Vec<usize>,
+}
+
+impl FilterSet {
+    fn push(&mut self, filter: NetworkFilter) {
+        let index = self.filters.len();
+        match filter.token() {
+            Some(token) => self
+                .by_token
+                .entry(token.to_string())
+                .or_default()
+                .push(index),
+            None => self.untokened.push(index),
+        }
+        self.filters.push(filter);
+    }
+
+    fn find(&self, request: &Request<'_>, tokens: &HashSet<&str>) -> Option<&NetworkFilter> {
+        tokens
+            .iter()
+            .filter_map(|token| self.by_token.get(*token))
+            .flatten()
+            .chain(&self.untokened)
+            .map(|&index| &self.filters[index])
+            .find(|filter| filter.matches(request))
+    }
+}
+
+#[derive(Debug)]
+struct CosmeticFilter {
+    selector: String,
+    domains: DomainList,
+}
+
+impl CosmeticFilter {
+    fn parse(domains: &str, selector: &str) -> Option<Self> {
+        let selector = selector.trim();
+        let unsupported = selector.is_empty()
+            || selector.starts_with('+')
+            || selector.starts_with('^')
+            || selector.contains(['{', '}'])
+            || PROCEDURAL.iter().any(|pseudo| selector.contains(pseudo));
+        (!unsupported).then(|| Self {
+            selector: selector.to_string(),
+            domains: DomainList::parse(domains, ','),
+        })
+    }
+}
+
+/// The filters of every loaded list.
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    important: FilterSet,
+    blocks: FilterSet,
+    exceptions: FilterSet,
+    /// `$elemhide` and `$generichide` exceptions, matched against the page address.
+    unhiding: Vec<NetworkFilter>,
+    /// `##` filters for every page.
+    generic_hiding: Vec<String>,
+    generic_css: String,
+    /// `##` filters limited to or excluding domains.
+    scoped_hiding: Vec<CosmeticFilter>,
+    /// `#@#` exceptions.
+    hiding_exceptions: Vec<CosmeticFilter>,
+    skipped: usize,
+}
+
+impl Blocklist {
+    pub fn default_dir() -> Option<PathBuf> {
+        crate::preferences::data_file(DIR_NAME)
+    }
+
+    /// Read every `.txt` list in `dir`; a missing directory blocks nothing.
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("unable to list filter lists in {}", dir.display()))
+            }
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        paths.sort();
+        let mut blocklist = Self::default();
+        for path in paths {
+            let text = std::fs::read_to_string(&path)
+                .with_context(|| format!("unable to read filter list {}", path.display()))?;
+            blocklist.add_list(&text);
+        }
+        blocklist.finish();
+        Ok(blocklist)
+    }
+
+    fn add_list(&mut self, text: &str) {
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+                continue;
+            }
+            if !self.add_rule(line) {
+                self.skipped += 1;
+            }
+        }
+    }
+
+    /// Add one rule; `false` when it is not supported.
+    fn add_rule(&mut self, line: &str) -> bool {
+        let extended = ["#?#", "#$#", "#%#", "#@?#", "#@$#", "#@%#", "$$", "$@$"];
+        if extended.iter().any(|separator| line.contains(separator)) {
+            return false;
+        }
+        if let Some((domains, selector)) = line.split_once("#@#") {
+            let Some(filter) = CosmeticFilter::parse(domains, selector) else {
+                return false;
+            };
+            self.hiding_exceptions.push(filter);
+            return true;
+        }
+        if let Some((domains, selector)) = line.split_once("##") {
+            let Some(filter) = CosmeticFilter::parse(domains, selector) else {
+                return false;
+            };
+            if domains.trim().is_empty() {
+                self.generic_hiding.push(filter.selector);
+            } else {
+                self.scoped_hiding.push(filter);
+            }
+            return true;
+        }
+        let (exception, rule) = match line.strip_prefix("@@") {
+            Some(rule) => (true, rule),
+            None => (false, line),
+        };
+        let Some(filter) = NetworkFilter::parse(line, rule, exception) else {
+            return false;
+        };
+        match (exception, filter.unhiding, filter.important) {
+            (true, Some(_), _) => self.unhiding.push(filter),
+            (true, None, _) => self.exceptions.push(filter),
+            (false, _, true) => self.important.push(filter),
+            (false, _, false) => self.blocks.push(filter),
+        }
+        true
+    }
+
+    fn finish(&mut self) {
+        self.generic_css = self
+            .generic_hiding
+            .iter()
+            .map(|selector| format!("{selector}{HIDE}"))
+            .collect();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rule_count() == 0
+    }
+
+    /// Rules in use, network and element hiding.
+    pub fn rule_count(&self) -> usize {
+        self.important.filters.len()
+            + self.blocks.filters.len()
+            + self.exceptions.filters.len()
+            + self.unhiding.len()
+            + self.generic_hiding.len()
+            + self.scoped_hiding.len()
+            + self.hiding_exceptions.len()
+    }
+
+    /// Rules that were left out for using unsupported syntax.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// The rule that blocks a request for `url` made by the page at `page`, if any.
+    pub fn blocking_rule(&self, url: &Url, page: Option<&Url>) -> Option<&str> {
+        if self.important.filters.is_empty() && self.blocks.filters.is_empty() {
+            return None;
+        }
+        let lower = url.as_str().to_ascii_lowercase();
+        let page_host = page.and_then(Url::host_str);
+        let request = Request {
+            url: &lower,
+            host: host_range(&lower, url.host_str()),
+            kind: ResourceType::for_url(url),
+            third_party: page.is_some_and(|page| {
+                third_party(page.host_str().map(site).as_deref(), url.host_str())
+            }),
+            page_host,
+        };
+        let tokens: HashSet<&str> = lower
+            .split(|c: char| !is_token_byte(c as u8) || !c.is_ascii())
+            .filter(|token| token.len() >= MIN_TOKEN)
+            .collect();
+        if let Some(filter) = self.important.find(&request, &tokens) {
+            return Some(filter.text.as_str());
+        }
+        let filter = self.blocks.find(&request, &tokens)?;
+        self.exceptions
+            .find(&request, &tokens)
+            .is_none()
+            .then_some(filter.text.as_str())
+    }
+
+    /// The element hiding stylesheet for the page at `page`, or `None` when nothing applies.
+    pub fn hiding_css(&self, page: &Url) -> Option<String> {
+        let host = page.host_str();
+        let lower = page.as_str().to_ascii_lowercase();
+        let request = Request {
+            url: &lower,
+            host: host_range(&lower, host),
+            kind: ResourceType::Other,
+            third_party: false,
+            page_host: host,
+        };
+        let mut generic = true;
+        for filter in self.unhiding.iter().filter(|f| f.matches(&request)) {
+            match filter.unhiding {
+                Some(Unhiding::All) => return None,
+                _ => generic = false,
+            }
+        }
+        let excepted: HashSet<&str> = self
+            .hiding_exceptions
+            .iter()
+            .filter(|filter| filter.domains.allows(host))
+            .map(|filter| filter.selector.as_str())
+            .collect();
+
+        let mut css = String::new();
+        if generic && excepted.is_empty() {
+            css.push_str(&self.generic_css);
+        } else if generic {
+            for selector in &self.generic_hiding {
+                if !excepted.contains(selector.as_str()) {
+                    css.push_str(selector);
+                    css.push_str(HIDE);
+                }
+            }
+        }
+        for filter in &self.scoped_hiding {
+            let applies = (generic || !filter.domains.include.is_empty())
+                && filter.domains.allows(host)
+                && !excepted.contains(filter.selector.as_str());
+            if applies {
+                css.push_str(&filter.selector);
+                css.push_str(HIDE);
+            }
+        }
+        (!css.is_empty()).then_some(css)
+    }
+}
+
+/// Where `host` sits in `url`, the lowercased address it came from.
+fn host_range(url: &str, host: Option<&str>) -> Range<usize> {
+    let after_scheme = url.find("://").map_or(0, |index| index + 3);
+    host.and_then(|host| {
+        let start = after_scheme + url[after_scheme..].find(host)?;
+        Some(start..start + host.len())
+    })
+    .unwrap_or(after_scheme..after_scheme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocklist(text: &str) -> Blocklist {
+        let mut blocklist = Blocklist::default();
+        blocklist.add_list(text);
+        blocklist.finish();
+        blocklist
+    }
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn matches_address_patterns() {
+        let list = blocklist(
+            "[Adblock Plus 2.0]\n\
+             ! comment\n\
+             ||ads.example.com^\n\
+             /banner/*/img^\n\
+             |http://plain.example/\n\
+             swf|\n",
+        );
+        let page = url("https://news.example.org/");
+        let blocked = |raw: &str| list.blocking_rule(&url(raw), Some(&page));
+
+        assert_eq!(
+            blocked("https://ads.example.com/x.js"),
+            Some("||ads.example.com^")
+        );
+        assert!(blocked("https://cdn.ads.example.com/").is_some());
+        assert!(blocked("https://ads.example.com:8080/").is_some());
+        assert!(blocked("https://badads.example.com/").is_none());
+        assert!(blocked("https://ads.example.community/").is_none());
+        assert!(blocked("https://x.example/banner/300/img?x=1").is_some());
+        assert!(blocked("https://x.example/banner/300/img.png").is_none());
+        assert!(blocked("http://plain.example/a").is_some());
+        assert!(blocked("https://plain.example/a").is_none());
+        assert!(blocked("https://x.example/movie.swf").is_some());
+        assert!(blocked("https://x.example/movie.swf?x").is_none());
+        assert!(blocked("https://news.example.org/story.css").is_none());
+    }
+
+    #[test]
+    fn applies_options_and_exceptions() {
+        let list = blocklist(
+            "||tracker.example^$third-party\n\
+             ||cdn.example^$image,domain=a.example|~b.a.example\n\
+             ||cdn.example^$~image,~script,domain=c.example\n\
+             ||widgets.example^\n\
+             @@||widgets.example/allowed^\n\
+             ||always.example^$important\n\
+             @@||always.example^\n\
+             ||xhr.example^$xmlhttprequest\n\
+             ||rewrite.example^$redirect=noop.js\n\
+             /ads[0-9]+/\n",
+        );
+        let blocked = |raw: &str, page: &str| list.blocking_rule(&url(raw), Some(&url(page)));
+
+        assert!(blocked("https://tracker.example/p.gif", "https://a.example/").is_some());
+        assert!(blocked(
+            "https://tracker.example/p.gif",
+            "https://www.tracker.example/"
+        )
+        .is_none());
+        assert!(blocked("https://cdn.example/i.png", "https://a.example/").is_some());
+        assert!(blocked("https://cdn.example/i.png", "https://b.a.example/").is_none());
+        assert!(blocked("https://cdn.example/i.js", "https://a.example/").is_none());
+        assert!(blocked("https://cdn.example/s.css", "https://c.example/").is_some());
+        assert!(blocked("https://cdn.example/i.js", "https://c.example/").is_none());
+        assert!(blocked("https://widgets.example/w.js", "https://a.example/").is_some());
+        assert!(blocked("https://widgets.example/allowed/w.js", "https://a.example/").is_none());
+        assert!(blocked("https://always.example/x", "https://a.example/").is_some());
+        assert!(blocked("https://xhr.example/x", "https://a.example/").is_none());
+        assert!(blocked("https://rewrite.example/x", "https://a.example/").is_none());
+        assert_eq!(list.skipped(), 3);
+        assert_eq!(list.rule_count(), 7);
+    }
+
+    #[test]
+    fn builds_element_hiding_css_per_page() {
+        let list = blocklist(
+            "##.ad-banner\n\
+             ##div[id^=\"sponsor\"]\n\
+             a.example,b.example##.promo\n\
+             ~a.example##.sidebar-ad\n\
+             sub.a.example#@#.promo\n\
+             c.example#@#.ad-banner\n\
+             ##.box:has-text(Sponsored)\n\
+             example.net##+js(abort-on-property-read, ads)\n\
+             @@||quiet.example^$elemhide\n\
+             @@||generic.example^$generichide\n\
+             generic.example##.own-ad\n",
+        );
+        let css = |page: &str| list.hiding_css(&url(page)).unwrap_or_default();
+
+        let a = css("https://a.example/");
+        assert!(
+            a.contains(".ad-banner { display: none !important; }"),
+            "{a}"
+        );
+        assert!(a.contains("div[id^=\"sponsor\"]"), "{a}");
+        assert!(a.contains(".promo"), "{a}");
+        assert!(!a.contains(".sidebar-ad"), "{a}");
+        assert!(!a.contains(":has-text"), "{a}");
+
+        assert!(!css("https://sub.a.example/").contains(".promo"));
+        assert!(css("https://b.example/").contains(".sidebar-ad"));
+        let c = css("https://c.example/");
+        assert!(!c.contains(".ad-banner") && c.contains("sponsor"), "{c}");
+        assert_eq!(list.hiding_css(&url("https://quiet.example/")), None);
+        assert_eq!(css("https://generic.example/"), format!(".own-ad{HIDE}"));
+        assert_eq!(list.skipped(), 2);
+    }
+
+    #[test]
+    fn indexes_filters_by_a_whole_token() {
+        let token = |rule: &str| {
+            NetworkFilter::parse(rule, rule, false)
+                .unwrap()
+                .token()
+                .map(str::to_string)
+        };
+        assert_eq!(token("||doubleclick.net^").as_deref(), Some("doubleclick"));
+        assert_eq!(token("/adserver/*").as_deref(), Some("adserver"));
+        assert_eq!(token("adbanner*"), None);
+        assert_eq!(token("*tracking.js|").as_deref(), None);
+        assert_eq!(token("*/tracking.js|").as_deref(), Some("tracking"));
+        assert_eq!(token("$image,domain=a.example"), None);
+    }
+
+    #[test]
+    fn loads_every_list_in_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("easylist.txt"), "||ads.example^\n").unwrap();
+        std::fs::write(dir.path().join("privacy.txt"), "||tracker.example^\n").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "||docs.example^\n").unwrap();
+
+        let list = Blocklist::load_dir(dir.path()).unwrap();
+        assert_eq!(list.rule_count(), 2);
+        assert!(Blocklist::load_dir(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,485 @@
+//! The application menu: File, Edit, View, History and Help.
+//!
+//! Every entry runs a [`MenuCommand`], the same commands the chrome's back and forward buttons
+//! and the keyboard shortcuts trigger. Each command has a `frontier://command/...` URL, so the
+//! chrome reaches it by navigating there and a native menu item uses it as its id; either way
+//! the command arrives at the event loop as `ReadmeEvent::Command`. Navigations to these URLs
+//! only run when the user clicked the chrome's own link; a page linking to them, or opening
+//! them with `window.open`, is ignored.
+//!
+//! On macOS and Windows the menu is native, built with muda. muda's Linux menus need a GTK
+//! window, which winit does not create, so there the chrome shows the same entries (without
+//! Edit, which the page's own text fields handle) in a dropdown at the end of the toolbar.
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use keyboard_types::Code;
+use url::Url;
+
+pub const COMMAND_URL: &str = "frontier://command";
+/// Where Help > Frontier Help leads.
+pub const HELP_URL: &str = "https://github.com/justinmoon/frontier#readme";
+/// How many recently visited pages the History menu lists.
+pub const RECENT_ENTRIES: usize = 10;
+
+/// Whether the menu is a native menu bar rather than a dropdown in the chrome.
+pub const NATIVE: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuCommand {
+    /// Start another browser process on the homepage.
+    NewWindow,
+    /// The same with `--private`, so the new window writes nothing to the data directory.
+    NewPrivateWindow,
+    OpenFile,
+    /// Show the quick-open palette.
+    QuickOpen,
+    /// Bookmark the current page, or remove its bookmark.
+    Bookmark,
+    CloseWindow,
+    Reload,
+    ZoomIn,
+    ZoomOut,
+    ActualSize,
+    ToggleTheme,
+    Back,
+    Forward,
+    /// Go to a page listed in the History menu.
+    Visit(String),
+    Help,
+    /// Bring back a window hidden in the tray.
+    ShowWindow,
+    /// Exit, even in background mode.
+    Quit,
+}
+
+impl MenuCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            MenuCommand::NewWindow => "new-window",
+            MenuCommand::NewPrivateWindow => "new-private-window",
+            MenuCommand::OpenFile => "open-file",
+            MenuCommand::QuickOpen => "quick-open",
+            MenuCommand::Bookmark => "bookmark",
+            MenuCommand::CloseWindow => "close-window",
+            MenuCommand::Reload => "reload",
+            MenuCommand::ZoomIn => "zoom-in",
+            MenuCommand::ZoomOut => "zoom-out",
+            MenuCommand::ActualSize => "actual-size",
+            MenuCommand::ToggleTheme => "toggle-theme",
+            MenuCommand::Back => "back",
+            MenuCommand::Forward => "forward",
+            MenuCommand::Visit(_) => "visit",
+            MenuCommand::Help => "help",
+            MenuCommand::ShowWindow => "show-window",
+            MenuCommand::Quit => "quit",
+        }
+    }
+
+    /// The `frontier://command/...` URL that runs this command.
+    pub fn url(&self) -> String {
+        match self {
+            MenuCommand::Visit(target) => {
+                let query: String = url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair("url", target)
+                    .finish();
+                format!("{COMMAND_URL}/visit?{query}")
+            }
+            command => format!("{COMMAND_URL}/{}", command.name()),
+        }
+    }
+
+    /// The command a `frontier://command/...` URL runs. `frontier://back` and
+    /// `frontier://forward`, which the chrome's buttons navigate to, count as well.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        if url.scheme() != "frontier" {
+            return None;
+        }
+        let name = match url.host_str()? {
+            "back" | "forward" if url.path().is_empty() || url.path() == "/" => url.host_str()?,
+            "command" => url.path().trim_start_matches('/'),
+            _ => return None,
+        };
+        let command = match name {
+            "new-window" => MenuCommand::NewWindow,
+            "new-private-window" => MenuCommand::NewPrivateWindow,
+            "open-file" => MenuCommand::OpenFile,
+            "quick-open" => MenuCommand::QuickOpen,
+            "bookmark" => MenuCommand::Bookmark,
+            "close-window" => MenuCommand::CloseWindow,
+            "reload" => MenuCommand::Reload,
+            "zoom-in" => MenuCommand::ZoomIn,
+            "zoom-out" => MenuCommand::ZoomOut,
+            "actual-size" => MenuCommand::ActualSize,
+            "toggle-theme" => MenuCommand::ToggleTheme,
+            "back" => MenuCommand::Back,
+            "forward" => MenuCommand::Forward,
+            "help" => MenuCommand::Help,
+            "show-window" => MenuCommand::ShowWindow,
+            "quit" => MenuCommand::Quit,
+            "visit" => {
+                let (_, target) = url.query_pairs().find(|(key, _)| key == "url")?;
+                MenuCommand::Visit(target.into_owned())
+            }
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    /// [`Self::from_url`] for a native menu item id.
+    pub fn parse(id: &str) -> Option<Self> {
+        Self::from_url(&Url::parse(id).ok()?)
+    }
+}
+
+/// One entry of a menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuEntry {
+    Command {
+        command: MenuCommand,
+        label: String,
+        /// Pressed with Cmd on macOS and Ctrl elsewhere.
+        shortcut: Option<Code>,
+        /// Whether the shortcut needs Shift as well.
+        shift: bool,
+    },
+    /// The platform's own clipboard items, which act on the focused text.
+    Copy,
+    Paste,
+    Separator,
+}
+
+fn entry(command: MenuCommand, label: &str, shortcut: Option<Code>) -> MenuEntry {
+    MenuEntry::Command {
+        command,
+        label: label.to_string(),
+        shortcut,
+        shift: false,
+    }
+}
+
+/// An [`entry`] whose shortcut is pressed with Shift too.
+fn shifted_entry(command: MenuCommand, label: &str, shortcut: Code) -> MenuEntry {
+    MenuEntry::Command {
+        command,
+        label: label.to_string(),
+        shortcut: Some(shortcut),
+        shift: true,
+    }
+}
+
+/// The menus in order, with `recent` (most recent first) at the end of History. Reload and
+/// Toggle Theme keep the shortcuts the window already handles, so they show none here.
+pub fn menus(recent: &[String]) -> Vec<(&'static str, Vec<MenuEntry>)> {
+    let mut history = vec![
+        entry(MenuCommand::Back, "Back", Some(Code::BracketLeft)),
+        entry(MenuCommand::Forward, "Forward", Some(Code::BracketRight)),
+    ];
+    if !recent.is_empty() {
+        history.push(MenuEntry::Separator);
+    }
+    history.extend(
+        recent
+            .iter()
+            .take(RECENT_ENTRIES)
+            .map(|target| entry(MenuCommand::Visit(target.clone()), target, None)),
+    );
+    vec![
+        (
+            "File",
+            vec![
+                entry(MenuCommand::NewWindow, "New Window", Some(Code::KeyN)),
+                shifted_entry(
+                    MenuCommand::NewPrivateWindow,
+                    "New Private Window",
+                    Code::KeyN,
+                ),
+                entry(MenuCommand::OpenFile, "Open File…", Some(Code::KeyO)),
+                entry(MenuCommand::QuickOpen, "Quick Open…", Some(Code::KeyK)),
+                MenuEntry::Separator,
+                entry(
+                    MenuCommand::Bookmark,
+                    "Bookmark This Page",
+                    Some(Code::KeyD),
+                ),
+                MenuEntry::Separator,
+                entry(MenuCommand::CloseWindow, "Close Window", Some(Code::KeyW)),
+            ],
+        ),
+        ("Edit", vec![MenuEntry::Copy, MenuEntry::Paste]),
+        (
+            "View",
+            vec![
+                entry(MenuCommand::Reload, "Reload", None),
+                MenuEntry::Separator,
+                entry(MenuCommand::ZoomIn, "Zoom In", Some(Code::Equal)),
+                entry(MenuCommand::ZoomOut, "Zoom Out", Some(Code::Minus)),
+                entry(MenuCommand::ActualSize, "Actual Size", Some(Code::Digit0)),
+                MenuEntry::Separator,
+                entry(MenuCommand::ToggleTheme, "Toggle Theme", None),
+            ],
+        ),
+        ("History", history),
+        (
+            "Help",
+            vec![entry(MenuCommand::Help, "Frontier Help", None)],
+        ),
+    ]
+}
+
+/// The menu of the browser window.
+#[derive(Default)]
+pub struct AppMenu {
+    /// Pages the History menu lists, most recent first.
+    recent: Vec<String>,
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    native: Option<native::NativeMenu>,
+}
+
+impl AppMenu {
+    /// Show the native menu for `window`, passing activated commands to `send`. Does nothing
+    /// where the chrome draws the menu.
+    #[allow(unused_variables)]
+    pub fn install(
+        &mut self,
+        window: &winit::window::Window,
+        send: impl Fn(MenuCommand) + Send + Sync + 'static,
+    ) {
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        if self.native.is_none() {
+            match native::NativeMenu::install(window, &self.recent, send) {
+                Ok(menu) => self.native = Some(menu),
+                Err(err) => tracing::warn!(error = %err, "failed to install the menu bar"),
+            }
+        }
+    }
+
+    /// List `recent` (most recent first) in the History menu.
+    pub fn set_recent(&mut self, recent: Vec<String>) {
+        if recent == self.recent {
+            return;
+        }
+        self.recent = recent;
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        if let Some(menu) = self.native.as_mut() {
+            if let Err(err) = menu.set_recent(&self.recent) {
+                tracing::warn!(error = %err, "failed to update the History menu");
+            }
+        }
+    }
+
+    /// The dropdown the chrome shows in place of a native menu bar, if there is none.
+    pub fn chrome_html(&self) -> Option<String> {
+        (!NATIVE).then(|| chrome_menu(&self.recent))
+    }
+}
+
+/// Pass the commands of activated native menu items, the menu bar's and the tray's, to
+/// `send`. muda keeps the first handler it is given, so later calls do nothing; every caller
+/// sends to the same event loop.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+pub fn forward_menu_events(send: impl Fn(MenuCommand) + Send + Sync + 'static) {
+    muda::MenuEvent::set_event_handler(Some(move |event: muda::MenuEvent| {
+        if let Some(command) = MenuCommand::parse(event.id().as_ref()) {
+            send(command);
+        }
+    }));
+}
+
+/// The menus as a dropdown of `frontier://command/...` links.
+fn chrome_menu(recent: &[String]) -> String {
+    let mut items = String::new();
+    for (title, entries) in menus(recent) {
+        let links: String = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                MenuEntry::Command { command, label, .. } => Some(format!(
+                    "<a role=\"menuitem\" href=\"{}\">{}</a>",
+                    encode_double_quoted_attribute(&command.url()),
+                    encode_text(label)
+                )),
+                _ => None,
+            })
+            .collect();
+        if !links.is_empty() {
+            items.push_str(&format!("<p class=\"app-menu-heading\">{title}</p>{links}"));
+        }
+    }
+    format!(
+        "<div id=\"app-menu\"><button class=\"nav-button\" type=\"button\" title=\"Menu\" \
+         aria-label=\"Menu\" aria-haspopup=\"menu\">&#9776;</button>\
+         <div id=\"app-menu-items\" role=\"menu\">{items}</div></div>"
+    )
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+mod native {
+    use anyhow::Result;
+    use muda::accelerator::{Accelerator, Modifiers, CMD_OR_CTRL};
+    use muda::{Menu, MenuItem, PredefinedMenuItem, Submenu};
+
+    use super::{menus, MenuCommand, MenuEntry};
+
+    pub struct NativeMenu {
+        /// Kept alive for as long as the menu bar shows it.
+        _menu: Menu,
+        history: Submenu,
+        /// How many History items (Back and Forward) stay when the recent pages change.
+        history_fixed: usize,
+    }
+
+    impl NativeMenu {
+        pub fn install(
+            window: &winit::window::Window,
+            recent: &[String],
+            send: impl Fn(MenuCommand) + Send + Sync + 'static,
+        ) -> Result<Self> {
+            let menu = Menu::new();
+            #[cfg(target_os = "macos")]
+            menu.append(&Submenu::with_items(
+                "Frontier",
+                true,
+                &[
+                    &PredefinedMenuItem::about(None, None),
+                    &PredefinedMenuItem::separator(),
+                    &PredefinedMenuItem::hide(None),
+                    &PredefinedMenuItem::hide_others(None),
+                    &PredefinedMenuItem::show_all(None),
+                    &PredefinedMenuItem::separator(),
+                    &PredefinedMenuItem::quit(None),
+                ],
+            )?)?;
+            let mut history = None;
+            for (title, entries) in menus(&[]) {
+                let submenu = Submenu::new(title, true);
+                for entry in &entries {
+                    append(&submenu, entry)?;
+                }
+                if title == "History" {
+                    history = Some(submenu.clone());
+                }
+                menu.append(&submenu)?;
+            }
+            let history = history.expect("the menus include History");
+            let history_fixed = history.items().len();
+
+            #[cfg(target_os = "macos")]
+            {
+                let _ = window;
+                menu.init_for_nsapp();
+            }
+            #[cfg(target_os = "windows")]
+            {
+                use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+                if let RawWindowHandle::Win32(handle) = window.window_handle()?.as_raw() {
+                    // SAFETY: the handle belongs to the live window the menu is attached to.
+                    unsafe { menu.init_for_hwnd(handle.hwnd.get())? };
+                }
+            }
+            super::forward_menu_events(send);
+
+            let mut native = Self {
+                _menu: menu,
+                history,
+                history_fixed,
+            };
+            native.set_recent(recent)?;
+            Ok(native)
+        }
+
+        /// Replace the recent pages at the end of the History menu.
+        pub fn set_recent(&mut self, recent: &[String]) -> Result<()> {
+            while self.history.items().len() > self.history_fixed {
+                self.history.remove_at(self.history_fixed);
+            }
+            let (_, entries) = menus(recent)
+                .into_iter()
+                .find(|(title, _)| *title == "History")
+                .expect("the menus include History");
+            for entry in entries.iter().skip(self.history_fixed) {
+                append(&self.history, entry)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn append(submenu: &Submenu, entry: &MenuEntry) -> muda::Result<()> {
+        match entry {
+            MenuEntry::Command {
+                command,
+                label,
+                shortcut,
+                shift,
+            } => {
+                let modifiers = if *shift {
+                    CMD_OR_CTRL | Modifiers::SHIFT
+                } else {
+                    CMD_OR_CTRL
+                };
+                let accelerator = shortcut.map(|key| Accelerator::new(Some(modifiers), key));
+                submenu.append(&MenuItem::with_id(command.url(), label, true, accelerator))
+            }
+            MenuEntry::Copy => submenu.append(&PredefinedMenuItem::copy(None)),
+            MenuEntry::Paste => submenu.append(&PredefinedMenuItem::paste(None)),
+            MenuEntry::Separator => submenu.append(&PredefinedMenuItem::separator()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_round_trip_through_their_urls() {
+        let commands = [
+            MenuCommand::NewWindow,
+            MenuCommand::NewPrivateWindow,
+            MenuCommand::OpenFile,
+            MenuCommand::QuickOpen,
+            MenuCommand::Bookmark,
+            MenuCommand::CloseWindow,
+            MenuCommand::Reload,
+            MenuCommand::ZoomIn,
+            MenuCommand::ZoomOut,
+            MenuCommand::ActualSize,
+            MenuCommand::ToggleTheme,
+            MenuCommand::Back,
+            MenuCommand::Forward,
+            MenuCommand::Visit("https://example.com/?a=1&b=2#top".to_string()),
+            MenuCommand::Help,
+            MenuCommand::ShowWindow,
+            MenuCommand::Quit,
+        ];
+        for command in commands {
+            assert_eq!(MenuCommand::parse(&command.url()), Some(command));
+        }
+        assert_eq!(
+            MenuCommand::parse("frontier://back"),
+            Some(MenuCommand::Back)
+        );
+        assert_eq!(
+            MenuCommand::parse("frontier://forward"),
+            Some(MenuCommand::Forward)
+        );
+        assert_eq!(MenuCommand::parse("frontier://command/format-disk"), None);
+        assert_eq!(MenuCommand::parse("frontier://settings"), None);
+        assert_eq!(MenuCommand::parse("https://command/reload"), None);
+    }
+
+    #[test]
+    fn history_lists_recent_pages_and_the_chrome_menu_escapes_them() {
+        let recent: Vec<String> = (0..12).map(|n| format!("https://{n}.example/")).collect();
+        let (_, history) = menus(&recent).remove(3);
+        assert_eq!(history.len(), 3 + RECENT_ENTRIES);
+        assert_eq!(history[2], MenuEntry::Separator);
+        assert_eq!(menus(&[])[3].1.len(), 2);
+
+        let html = chrome_menu(&["https://x.example/?q=<b>".to_string()]);
+        assert!(html.contains("href=\"frontier://command/reload\">Reload</a>"));
+        assert!(
+            html.contains(">https://x.example/?q=&lt;b&gt;</a>"),
+            "{html}"
+        );
+        assert!(!html.contains("Edit"));
+    }
+}
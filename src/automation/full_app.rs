@@ -1,13 +1,17 @@
 #![allow(dead_code)]
 #![allow(clippy::disallowed_types)]
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 
+use crate::js::media::ColorScheme;
+use crate::screenshot::CompareThresholds;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum ElementSelector {
@@ -143,13 +147,131 @@ pub enum AutomationCommand {
     ScrollIntoView {
         selector: ElementSelector,
     },
+    /// Make `paths` the selection of a file input, as if picked in the file dialog, which
+    /// never opens under automation.
+    SetInputFiles {
+        selector: ElementSelector,
+        paths: Vec<PathBuf>,
+    },
+    /// Snapshot of the accessibility tree as JSON.
+    AccessibilityTree,
+    /// Resize the content area to the given logical size, e.g. for responsive layouts.
+    SetViewport {
+        width: f64,
+        height: f64,
+    },
+    /// Requests made by the current document, as JSON.
+    NetworkLog,
+    /// The newest `limit` console messages and uncaught exceptions of page scripts, oldest
+    /// first, as JSON.
+    ConsoleLog {
+        limit: usize,
+    },
+    /// Frame rate and frame time percentiles for the current page, as JSON.
+    FrameStats,
+    /// What the frames since the page loaded repainted, for checking that small updates
+    /// damage small regions, as JSON.
+    Damage,
+    /// Approximate memory held by the page's script runtime, DOM and caches, as JSON.
+    MemoryReport,
+    /// Every `window.open` call of this session that asked for a new window, including
+    /// blocked ones, as JSON.
+    Popups,
+    /// Count which functions and lines of page scripts run, from the next page load on,
+    /// starting over from nothing.
+    RecordCoverage,
+    /// The script coverage collected since [`Self::RecordCoverage`], as JSON, or `null` when
+    /// it was never started.
+    Coverage,
+    /// The current page load's network log in HTTP Archive format, with response bodies
+    /// when `content` is set.
+    Har {
+        content: bool,
+    },
+    /// Capture the view and compare it against the baseline PNG, or replace the baseline
+    /// with the capture when `update` is set. Replies with the comparison as JSON.
+    CompareScreenshot {
+        baseline: PathBuf,
+        thresholds: CompareThresholds,
+        update: bool,
+        /// Crop the capture to this element first.
+        selector: Option<ElementSelector>,
+    },
+    /// Where the element is laid out in the viewport, as JSON.
+    ElementRect {
+        selector: ElementSelector,
+    },
+    /// Capture the view, or only the element when a selector is given, as the
+    /// `screenshot.png` artifact.
+    Screenshot {
+        selector: Option<ElementSelector>,
+    },
+    /// Cookies the current page's URL would send, `HttpOnly` ones included, as JSON.
+    Cookies,
+    /// Store a cookie for the current page's URL, written like a `Set-Cookie` header value.
+    SetCookie {
+        cookie: String,
+    },
+    /// Forget every cookie.
+    ClearCookies,
+    /// The current page's origin's `localStorage`, as a JSON object.
+    LocalStorage,
+    /// Add items to the current page's origin's `localStorage`.
+    SetLocalStorage {
+        items: BTreeMap<String, String>,
+    },
+    /// Empty the current page's origin's `localStorage`.
+    ClearLocalStorage,
+    /// Present the page with another device: see [`Emulation`].
+    Emulate {
+        emulation: Emulation,
+    },
+    /// Go back to the window's own device pixel ratio, colour scheme, reduced motion and user
+    /// agent. The viewport keeps its size.
+    ResetEmulation,
+    /// Run the timers, `requestAnimationFrame` timestamps and `performance.now()` of pages
+    /// loaded from now on off a virtual clock that only [`Self::AdvanceClock`] moves.
+    UseVirtualClock,
+    /// Move the virtual clock to `to_ms` milliseconds after it started, firing the timers
+    /// that fall due on the way in order.
+    AdvanceClock {
+        to_ms: u64,
+    },
+    /// Whether a page or frame load or a `fetch()` is still in flight.
+    Busy,
     Shutdown,
 }
 
+/// Device traits an automation session can pretend to have. Fields left out keep their
+/// current value, so one call can switch a test matrix from light to dark without touching
+/// the viewport.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct Emulation {
+    /// Content area in CSS pixels; set together with `height`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<f64>,
+    /// `devicePixelRatio` page script sees and the scale screenshots are rendered at. The
+    /// window itself keeps painting at the display's scale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_scale_factor: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_scheme: Option<ColorScheme>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reduced_motion: Option<bool>,
+    /// Sent with document loads and `fetch()`, and read by `navigator.userAgent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AutomationArtifacts {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dom_html: Option<String>,
+    /// PNG files to write next to the DOM snapshot, by file name.
+    #[serde(skip)]
+    pub images: Vec<(String, Vec<u8>)>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
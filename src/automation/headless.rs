@@ -16,10 +16,14 @@ use blitz_traits::net::DummyNetCallback;
 use tokio::time::sleep;
 use url::Url;
 
+use crate::js::lifecycle::LifecycleEvent;
 use crate::js::runtime_document::RuntimeDocument;
-use crate::js::script::{ScriptExecution, ScriptKind, ScriptSource};
+use crate::js::script::{ScriptKind, ScriptSource};
 use crate::js::session::JsPageRuntime;
 use crate::navigation::{self, FetchError, FetchRequest, FetchSource};
+use crate::privacy;
+
+const BLANK_DOCUMENT: &str = "<!DOCTYPE html><html><head></head><body></body></html>";
 
 /// Utility for creating headless DOM sessions backed by the QuickJS runtime.
 #[derive(Clone)]
@@ -71,18 +75,17 @@ impl HeadlessSession {
         let request = FetchRequest {
             source: FetchSource::Url(url.clone()),
             display_url: url.to_string(),
+            user_agent: None,
         };
         let mut fetched = navigation::execute_fetch(&request, Arc::clone(&net))
             .await
             .context("execute fetch")?;
 
-        hydrate_blocking_scripts(&mut fetched, Arc::clone(&net)).await;
+        hydrate_classic_scripts(&mut fetched, Arc::clone(&net)).await;
 
-        let scripts = fetched.scripts.clone();
-        let mut runtime =
-            JsPageRuntime::new(&fetched.contents, &scripts, Some(fetched.base_url.as_str()))
-                .context("create js runtime")?
-                .ok_or_else(|| anyhow!("document contained no executable scripts"))?;
+        let mut runtime = JsPageRuntime::for_document(&fetched)
+            .context("create js runtime")?
+            .ok_or_else(|| anyhow!("document contained no executable scripts"))?;
 
         let html_doc = HtmlDocument::from_html(
             &fetched.contents,
@@ -92,14 +95,8 @@ impl HeadlessSession {
             },
         );
 
-        // Box the RuntimeDocument to keep it at a stable heap location so the bridge
-        // pointer remains valid even when HeadlessSession is moved
-        let environment = runtime.environment();
-        let runtime_document = RuntimeDocument::new(html_doc, environment.clone());
-        let mut boxed_document = Box::new(runtime_document);
-
-        // Now attach the document at its stable heap location
-        runtime.attach_document(&mut boxed_document);
+        // Boxed, so the bridge stays valid when HeadlessSession is moved.
+        let boxed_document = RuntimeDocument::attach(html_doc, &mut runtime);
         if let Some(summary) = runtime
             .run_blocking_scripts()
             .context("execute inline scripts")?
@@ -112,6 +109,8 @@ impl HeadlessSession {
                 "headless executed scripts"
             );
         }
+        // Headless documents have no net provider, so there are no subresources to wait for.
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
         runtime.environment().pump().context("initial pump")?;
 
         Ok(Self {
@@ -122,6 +121,50 @@ impl HeadlessSession {
         })
     }
 
+    /// An empty document at `url` with its runtime booted and `load` fired, ready for a test
+    /// to fill through script. Must be called inside a tokio runtime, like [`Self::navigate`].
+    pub fn blank(url: Url) -> Result<Self> {
+        let mut runtime =
+            JsPageRuntime::empty(BLANK_DOCUMENT, Some(url.clone())).context("create js runtime")?;
+        let html_doc = HtmlDocument::from_html(
+            BLANK_DOCUMENT,
+            DocumentConfig {
+                base_url: Some(url.to_string()),
+                ..Default::default()
+            },
+        );
+        let document = RuntimeDocument::attach(html_doc, &mut runtime);
+        runtime
+            .run_blocking_scripts()
+            .context("start blank document")?;
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
+        runtime.environment().pump().context("initial pump")?;
+
+        Ok(Self {
+            runtime,
+            document,
+            net_provider: Arc::new(Provider::new(Arc::new(DummyNetCallback))),
+            current_url: url,
+        })
+    }
+
+    /// Run `source` as a script of the page.
+    pub fn eval(&self, source: &str) -> Result<()> {
+        self.runtime.environment().eval(source, "headless-eval.js")
+    }
+
+    /// Run `source` and return its completion value, which must be a string.
+    pub fn eval_string(&self, source: &str) -> Result<String> {
+        self.runtime
+            .environment()
+            .eval_with(source, "headless-eval.js")
+    }
+
+    /// Tear the page down: timers, fetches and listeners stop, as when a tab navigates away.
+    pub fn shutdown(&self) {
+        self.runtime.shutdown();
+    }
+
     pub fn document_html(&self) -> Result<String> {
         self.runtime
             .environment()
@@ -224,7 +267,7 @@ where
     result
 }
 
-async fn hydrate_blocking_scripts(
+async fn hydrate_classic_scripts(
     document: &mut navigation::FetchedDocument,
     net_provider: Arc<Provider<Resource>>,
 ) {
@@ -235,9 +278,7 @@ async fn hydrate_blocking_scripts(
     let base_url = Url::parse(&document.base_url).ok();
 
     for descriptor in document.scripts.iter_mut() {
-        if descriptor.execution != ScriptExecution::Blocking
-            || descriptor.kind != ScriptKind::Classic
-        {
+        if descriptor.kind != ScriptKind::Classic {
             continue;
         }
 
@@ -259,7 +300,7 @@ async fn hydrate_blocking_scripts(
             }
         };
 
-        match fetch_script_source(&resolved, Arc::clone(&net_provider)).await {
+        match fetch_script_source(&resolved, base_url.as_ref(), Arc::clone(&net_provider)).await {
             Ok(code) => descriptor.source = ScriptSource::Inline { code },
             Err(err) => {
                 tracing::error!(
@@ -289,10 +330,13 @@ fn resolve_script_url(src: &str, base: Option<&Url>) -> Result<Url, url::ParseEr
 
 async fn fetch_script_source(
     url: &Url,
+    page: Option<&Url>,
     net_provider: Arc<Provider<Resource>>,
 ) -> Result<String, FetchError> {
+    let mut request = blitz_traits::net::Request::get(url.clone());
+    privacy::apply(&mut request.headers, url, page);
     let (_final_url, bytes) = net_provider
-        .fetch_async(blitz_traits::net::Request::get(url.clone()))
+        .fetch_async(request)
         .await
         .map_err(|err| FetchError::Network(format!("{err:?}")))?;
     let code = std::str::from_utf8(&bytes)?.to_string();
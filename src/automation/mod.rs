@@ -1,10 +1,12 @@
 #![allow(clippy::disallowed_types)]
 
 pub mod full_app;
-pub(crate) mod headless;
+pub mod headless;
+pub mod pool;
 
 pub use full_app::{
     AutomationArtifacts, AutomationCommand, AutomationEvent, AutomationReply, AutomationResponse,
-    AutomationResult, AutomationStateHandle, ElementSelector, KeyboardAction, PointerAction,
-    PointerButton, PointerTarget,
+    AutomationResult, AutomationStateHandle, ElementSelector, Emulation, KeyboardAction,
+    PointerAction, PointerButton, PointerTarget,
 };
+pub use pool::{HeadlessSessionPool, PooledSession};
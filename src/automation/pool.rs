@@ -0,0 +1,251 @@
+//! Pre-warmed headless sessions for integration tests.
+//!
+//! Booting a page runtime evaluates every bootstrap before the first line of a test runs, and
+//! that dominates short DOM tests. [`HeadlessSessionPool`] keeps that cost off the test's
+//! clock: each of its workers owns a thread with its own tokio runtime and a blank session
+//! already booted. [`HeadlessSessionPool::acquire`] hands out an idle one; when the
+//! [`PooledSession`] is dropped the worker throws the page away, clears the `localStorage` and
+//! cookies of its origin, and boots the next blank session while other tests run.
+//!
+//! Sessions are never reused, so nothing a test leaves behind (globals, DOM, timers) reaches
+//! the next one. Each worker's pages live at their own origin,
+//! `http://session-<n>.headless.invalid/`, so parallel tests never share storage either.
+//!
+//! Page runtimes are single-threaded, so a session stays on its worker's thread and a test
+//! drives it through [`PooledSession`], whose calls block until the worker has run them. A
+//! panic inside [`PooledSession::run`] is re-raised on the calling thread, so assertions there
+//! fail the test as usual, and the worker goes on with a fresh session.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use super::headless::HeadlessSession;
+use crate::cookies::CookieJar;
+use crate::js::storage::LocalStorage;
+
+type Job = Box<dyn FnOnce(&mut HeadlessSession, &Runtime) + Send>;
+
+enum Message {
+    Run(Job),
+    Release,
+}
+
+struct Worker {
+    messages: Sender<Message>,
+    thread: Option<JoinHandle<()>>,
+}
+
+pub struct HeadlessSessionPool {
+    workers: Vec<Worker>,
+    /// Indices of workers whose session is booted and not handed out.
+    idle: Mutex<Receiver<usize>>,
+}
+
+impl HeadlessSessionPool {
+    /// Start `size` workers, each booting its first session straight away.
+    pub fn new(size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(anyhow!("a session pool needs at least one worker"));
+        }
+        let (ready, idle) = channel();
+        let mut workers = Vec::with_capacity(size);
+        for index in 0..size {
+            let url = Url::parse(&format!("http://session-{index}.headless.invalid/"))
+                .context("session origin")?;
+            let (messages, inbox) = channel();
+            let ready = ready.clone();
+            let thread = thread::Builder::new()
+                .name(format!("headless-session-{index}"))
+                .spawn(move || run_worker(index, url, inbox, ready))
+                .context("spawn session worker")?;
+            workers.push(Worker {
+                messages,
+                thread: Some(thread),
+            });
+        }
+        Ok(Self {
+            workers,
+            idle: Mutex::new(idle),
+        })
+    }
+
+    /// Wait for an idle session. Fails only when every worker has stopped.
+    pub fn acquire(&self) -> Result<PooledSession<'_>> {
+        let index = self
+            .idle
+            .lock()
+            .unwrap()
+            .recv()
+            .map_err(|_| anyhow!("every headless session worker has stopped"))?;
+        Ok(PooledSession { pool: self, index })
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl Drop for HeadlessSessionPool {
+    fn drop(&mut self) {
+        let threads: Vec<_> = self
+            .workers
+            .drain(..)
+            .filter_map(|mut worker| worker.thread.take())
+            .collect();
+        // The workers' inboxes close with the senders dropped above, which ends their loops.
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A session borrowed from a [`HeadlessSessionPool`]; dropping it returns the worker.
+pub struct PooledSession<'pool> {
+    pool: &'pool HeadlessSessionPool,
+    index: usize,
+}
+
+impl PooledSession<'_> {
+    /// Run `f` against the session on its worker's thread and return what it returns.
+    pub fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut HeadlessSession) -> R + Send + 'static,
+    {
+        self.run_with_runtime(move |session, _| f(session))
+    }
+
+    fn run_with_runtime<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut HeadlessSession, &Runtime) -> R + Send + 'static,
+    {
+        let (reply, result) = channel();
+        let job: Job = Box::new(move |session, runtime| {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| f(session, runtime)));
+            let _ = reply.send(outcome);
+        });
+        self.worker()
+            .messages
+            .send(Message::Run(job))
+            .map_err(|_| anyhow!("headless session worker {} stopped", self.index))?;
+        match result.recv() {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => Err(anyhow!("headless session worker {} stopped", self.index)),
+        }
+    }
+
+    /// Run `source` as a script of the page.
+    pub fn eval(&self, source: &str) -> Result<()> {
+        let source = source.to_string();
+        self.run(move |session| session.eval(&source))?
+    }
+
+    /// Run `source` and return its completion value, which must be a string.
+    pub fn eval_string(&self, source: &str) -> Result<String> {
+        let source = source.to_string();
+        self.run(move |session| session.eval_string(&source))?
+    }
+
+    /// Replace the body's children with `html`. Scripts in it do not run, as with
+    /// `innerHTML`; run them with [`Self::eval`].
+    pub fn set_body_html(&self, html: &str) -> Result<()> {
+        let literal = serde_json::to_string(html)?;
+        self.eval(&format!("document.body.innerHTML = {literal};"))
+    }
+
+    pub fn document_html(&self) -> Result<String> {
+        self.run(|session| session.document_html())?
+    }
+
+    /// Text of the element with the given `#id`.
+    pub fn inner_text(&self, selector: &str) -> Result<String> {
+        let selector = selector.to_string();
+        self.run(move |session| session.inner_text(&selector))?
+    }
+
+    /// Click the element with the given `#id`.
+    pub fn click(&self, selector: &str) -> Result<()> {
+        let selector = selector.to_string();
+        self.run_with_runtime(move |session, runtime| runtime.block_on(session.click(&selector)))?
+    }
+
+    /// Run timers, fetch completions and promise jobs for `duration`.
+    pub fn pump_for(&self, duration: Duration) -> Result<()> {
+        self.run_with_runtime(move |session, runtime| runtime.block_on(session.pump_for(duration)))
+    }
+
+    /// The origin this session's page runs in, `http://session-<n>.headless.invalid/`.
+    pub fn url(&self) -> Result<Url> {
+        self.run(|session| session.current_url().clone())
+    }
+
+    fn worker(&self) -> &Worker {
+        &self.pool.workers[self.index]
+    }
+}
+
+impl Drop for PooledSession<'_> {
+    fn drop(&mut self) {
+        let _ = self.worker().messages.send(Message::Release);
+    }
+}
+
+/// Boot a session, offer it, serve one test, clean up after it, repeat until the pool goes.
+fn run_worker(index: usize, url: Url, inbox: Receiver<Message>, ready: Sender<usize>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            tracing::error!(
+                target = "automation",
+                worker = index,
+                error = %err,
+                "no tokio runtime for pooled sessions"
+            );
+            return;
+        }
+    };
+    // Page runtimes pick up the current tokio handle when they are created.
+    let _guard = runtime.enter();
+    loop {
+        let mut session = match HeadlessSession::blank(url.clone()) {
+            Ok(session) => session,
+            Err(err) => {
+                tracing::error!(
+                    target = "automation",
+                    worker = index,
+                    error = %err,
+                    "failed to boot pooled session"
+                );
+                return;
+            }
+        };
+        if ready.send(index).is_err() {
+            return;
+        }
+        loop {
+            match inbox.recv() {
+                Ok(Message::Run(job)) => job(&mut session, &runtime),
+                Ok(Message::Release) => break,
+                Err(_) => return,
+            }
+        }
+        session.shutdown();
+        drop(session);
+        if let Some(area) = LocalStorage::area(&url) {
+            LocalStorage::shared().clear(&area);
+        }
+        CookieJar::shared().clear_for(&url);
+    }
+}
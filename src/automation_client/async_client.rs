@@ -0,0 +1,646 @@
+//! Non-blocking variant of the automation client for tests that already run on tokio.
+//!
+//! [`AsyncAutomationHost`] drives the same host binary and speaks the same payloads as
+//! [`AutomationHost`](super::AutomationHost), but never blocks the runtime. One HTTP client
+//! (and its keep-alive pool) is shared by every request to the host, and failures come back
+//! as [`AutomationError`] so a test can tell a crashed host from a command the host refused.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{
+    auth_headers, encode_selector_query, launch, replay_timeout, AutomationHostConfig,
+    ComparePayload, CompareThresholds, ConsoleEntry, Cookie, CookiePayload, CoverageReport,
+    CreateSessionPayload, DamageSummary, ElementRect, ElementSelector, Emulation, ExistsResponse,
+    FilesPayload, FrameSummary, HostProcess, KeyboardAction, KeyboardPayload, Launched,
+    MemoryReport, NavigatePayload, NetworkEntry, PdfPayload, PointerAction, PointerPayload,
+    PopupRecord, PrintOptions, PumpPayload, Recording, RemoteHost, ScreenshotComparison,
+    SelectorPayloadOwned, StoragePayload, TextResponse, TypePayload, ViewportPayload, WaitOptions,
+    SESSION_ID,
+};
+
+/// Idle connections to the host are kept this long between commands.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Why an automation request failed.
+#[derive(Debug, Error)]
+pub enum AutomationError {
+    #[error("failed to set up automation host: {0:#}")]
+    Launch(anyhow::Error),
+    /// The host process is gone, usually because the browser under test panicked.
+    #[error("automation host exited ({status}) during {command}")]
+    HostExited { command: String, status: ExitStatus },
+    /// The host is still running but the request never got a response.
+    #[error("automation host unreachable during {command}: {source}")]
+    Unreachable {
+        command: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    /// The host answered, but rejected or failed the command.
+    #[error("{command} failed with {status}: {body}")]
+    CommandFailed {
+        command: String,
+        status: StatusCode,
+        body: String,
+    },
+    #[error("invalid {command} response: {source}")]
+    InvalidResponse {
+        command: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{command} timed out")]
+    Timeout { command: String },
+}
+
+impl AutomationError {
+    /// Whether the host process died, as opposed to a command failing on a live host.
+    pub fn is_host_crash(&self) -> bool {
+        matches!(self, Self::HostExited { .. })
+    }
+}
+
+pub type AutomationResult<T> = std::result::Result<T, AutomationError>;
+
+/// Async handle that owns the automation host process and its HTTP client.
+pub struct AsyncAutomationHost {
+    /// `None` when connected to a host started elsewhere.
+    process: Mutex<Option<HostProcess>>,
+    base_url: Url,
+    client: Client,
+    artifact_root: PathBuf,
+    coverage: bool,
+}
+
+impl AsyncAutomationHost {
+    /// Spawn a fresh automation host process, as [`super::AutomationHost::spawn`] does.
+    pub async fn spawn(config: AutomationHostConfig) -> AutomationResult<Self> {
+        // Waiting for the readiness banner is a blocking read of the child's stdout.
+        let launched = tokio::task::spawn_blocking(move || launch(config))
+            .await
+            .map_err(|err| AutomationError::Launch(err.into()))?
+            .map_err(AutomationError::Launch)?;
+        Self::from_launched(launched).map_err(AutomationError::Launch)
+    }
+
+    /// Drive a host that is already running, e.g. on a lab machine started with a config file.
+    pub fn connect(remote: RemoteHost) -> AutomationResult<Self> {
+        remote
+            .into_launched()
+            .and_then(Self::from_launched)
+            .map_err(AutomationError::Launch)
+    }
+
+    fn from_launched(launched: Launched) -> anyhow::Result<Self> {
+        let Launched {
+            process,
+            base_url,
+            auth_token,
+            ca_cert,
+            artifact_root,
+        } = launched;
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .tcp_nodelay(true)
+            .default_headers(auth_headers(auth_token.as_deref())?);
+        if let Some(cert) = ca_cert {
+            builder = builder.add_root_certificate(cert);
+        }
+        let client = builder.build()?;
+
+        Ok(Self {
+            process: Mutex::new(process),
+            base_url,
+            client,
+            artifact_root,
+            coverage: false,
+        })
+    }
+
+    /// Collect script coverage in the sessions created from now on, as
+    /// [`super::AutomationHost::with_coverage`] does.
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = true;
+        self
+    }
+
+    /// Create a session by navigating to an asset relative to the asset root.
+    pub async fn session_from_asset(
+        &self,
+        file: impl AsRef<str>,
+    ) -> AutomationResult<AsyncAutomationSession<'_>> {
+        let payload = CreateSessionPayload {
+            url: None,
+            file: Some(file.as_ref().to_string()),
+            coverage: self.coverage,
+        };
+        self.create_session(payload).await
+    }
+
+    /// Create a session by navigating to an absolute URL.
+    pub async fn session_from_url(
+        &self,
+        url: impl AsRef<str>,
+    ) -> AutomationResult<AsyncAutomationSession<'_>> {
+        let payload = CreateSessionPayload {
+            url: Some(url.as_ref().to_string()),
+            file: None,
+            coverage: self.coverage,
+        };
+        self.create_session(payload).await
+    }
+
+    async fn create_session(
+        &self,
+        payload: CreateSessionPayload,
+    ) -> AutomationResult<AsyncAutomationSession<'_>> {
+        let request = self.client.post(self.url("/session")).json(&payload);
+        self.send("create session", request).await?;
+        Ok(AsyncAutomationSession {
+            host: self,
+            session_id: SESSION_ID.to_string(),
+            artifact_dir: self.artifact_root.join(SESSION_ID),
+        })
+    }
+
+    /// Whether the host process is still running. Always true for a connected remote host.
+    pub fn is_running(&self) -> bool {
+        self.exit_status().is_none()
+    }
+
+    /// Directory where command artifacts should be written. The host populates it on demand.
+    pub fn artifact_root(&self) -> &Path {
+        &self.artifact_root
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.base_url
+            .join(path)
+            .expect("automation paths are relative")
+    }
+
+    fn exit_status(&self) -> Option<ExitStatus> {
+        let mut process = self.process.lock().unwrap_or_else(|err| err.into_inner());
+        process.as_mut()?.child.try_wait().ok().flatten()
+    }
+
+    /// Send `request` and turn every failure into the matching [`AutomationError`].
+    async fn send(
+        &self,
+        command: &str,
+        request: RequestBuilder,
+    ) -> AutomationResult<reqwest::Response> {
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(source) => {
+                return Err(match self.exit_status() {
+                    Some(status) => AutomationError::HostExited {
+                        command: command.to_string(),
+                        status,
+                    },
+                    None => AutomationError::Unreachable {
+                        command: command.to_string(),
+                        source,
+                    },
+                });
+            }
+        };
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        // A handler that took the host down with it still gets its 500 out first.
+        if let Some(exit) = self.exit_status() {
+            return Err(AutomationError::HostExited {
+                command: command.to_string(),
+                status: exit,
+            });
+        }
+        Err(AutomationError::CommandFailed {
+            command: command.to_string(),
+            status,
+            body,
+        })
+    }
+}
+
+/// Async counterpart of [`AutomationSession`](super::AutomationSession).
+pub struct AsyncAutomationSession<'host> {
+    host: &'host AsyncAutomationHost,
+    session_id: String,
+    artifact_dir: PathBuf,
+}
+
+impl<'host> AsyncAutomationSession<'host> {
+    fn url(&self, path: &str) -> Url {
+        self.host.url(&format!(
+            "/session/{}/{}",
+            self.session_id,
+            path.trim_start_matches('/')
+        ))
+    }
+
+    async fn command<T: Serialize>(&self, path: &str, payload: &T) -> AutomationResult<()> {
+        let request = self.host.client.post(self.url(path)).json(payload);
+        self.host.send(path, request).await?;
+        Ok(())
+    }
+
+    async fn query<T: DeserializeOwned>(&self, path: &str) -> AutomationResult<T> {
+        let request = self.host.client.get(self.url(path));
+        let command = path.split('?').next().unwrap_or(path);
+        self.host
+            .send(command, request)
+            .await?
+            .json()
+            .await
+            .map_err(|source| AutomationError::InvalidResponse {
+                command: command.to_string(),
+                source,
+            })
+    }
+
+    /// Click a selector.
+    pub async fn click(&self, selector: &ElementSelector) -> AutomationResult<()> {
+        self.command(
+            "click",
+            &SelectorPayloadOwned {
+                selector: selector.clone(),
+            },
+        )
+        .await
+    }
+
+    /// Convenience wrapper for clicking using a CSS selector string.
+    pub async fn click_css(&self, selector: &str) -> AutomationResult<()> {
+        self.click(&ElementSelector::css(selector.to_string()))
+            .await
+    }
+
+    /// Type text into the given selector (clicks first to focus).
+    pub async fn type_text(&self, selector: &ElementSelector, text: &str) -> AutomationResult<()> {
+        self.command(
+            "type",
+            &TypePayload {
+                selector: selector.clone(),
+                text: text.to_string(),
+            },
+        )
+        .await
+    }
+
+    pub async fn type_text_css(&self, selector: &str, text: &str) -> AutomationResult<()> {
+        self.type_text(&ElementSelector::css(selector.to_string()), text)
+            .await
+    }
+
+    /// Select `paths` in a file input, as if picked in the file dialog.
+    pub async fn set_input_files(
+        &self,
+        selector: &ElementSelector,
+        paths: &[PathBuf],
+    ) -> AutomationResult<()> {
+        self.command(
+            "files",
+            &FilesPayload {
+                selector: selector.clone(),
+                paths: paths.to_vec(),
+            },
+        )
+        .await
+    }
+
+    /// Navigate to the provided URL.
+    pub async fn navigate_url(&self, url: &str) -> AutomationResult<()> {
+        self.command(
+            "navigate",
+            &NavigatePayload {
+                url: Some(url.to_string()),
+                file: None,
+            },
+        )
+        .await
+    }
+
+    /// Navigate to an asset relative to the asset root.
+    pub async fn navigate_asset(&self, file: &str) -> AutomationResult<()> {
+        self.command(
+            "navigate",
+            &NavigatePayload {
+                url: None,
+                file: Some(file.to_string()),
+            },
+        )
+        .await
+    }
+
+    /// Pump the event loop for the specified duration.
+    pub async fn pump(&self, duration: Duration) -> AutomationResult<()> {
+        self.command(
+            "pump",
+            &PumpPayload {
+                milliseconds: duration.as_millis() as u64,
+            },
+        )
+        .await
+    }
+
+    /// Wait for text to appear on the node identified by `selector`.
+    ///
+    /// Commands the host refuses while the page is loading are retried; a crashed host ends
+    /// the wait at once.
+    pub async fn wait_for_text(
+        &self,
+        selector: &ElementSelector,
+        opts: WaitOptions,
+    ) -> AutomationResult<String> {
+        let end = Instant::now() + opts.timeout;
+        let path = format!("text?{}", encode_selector_query(selector));
+        let mut last_error = None;
+        while Instant::now() <= end {
+            match self.query::<TextResponse>(&path).await {
+                Ok(parsed) => return Ok(parsed.value),
+                Err(err @ AutomationError::CommandFailed { .. }) => last_error = Some(err),
+                Err(err) => return Err(err),
+            }
+            self.pump(opts.poll_interval).await?;
+        }
+        Err(last_error.unwrap_or_else(|| AutomationError::Timeout {
+            command: "wait_for_text".into(),
+        }))
+    }
+
+    pub async fn wait_for_element(
+        &self,
+        selector: &ElementSelector,
+        opts: WaitOptions,
+    ) -> AutomationResult<()> {
+        let end = Instant::now() + opts.timeout;
+        let path = format!("exists?{}", encode_selector_query(selector));
+        let mut last_error = None;
+        while Instant::now() <= end {
+            match self.query::<ExistsResponse>(&path).await {
+                Ok(parsed) if parsed.exists => return Ok(()),
+                Ok(_) => {}
+                Err(err @ AutomationError::CommandFailed { .. }) => last_error = Some(err),
+                Err(err) => return Err(err),
+            }
+            self.pump(opts.poll_interval).await?;
+        }
+        Err(last_error.unwrap_or_else(|| AutomationError::Timeout {
+            command: "wait_for_element".into(),
+        }))
+    }
+
+    pub async fn pointer_sequence(&self, actions: Vec<PointerAction>) -> AutomationResult<()> {
+        self.command("pointer", &PointerPayload { actions }).await
+    }
+
+    pub async fn keyboard_sequence(&self, actions: Vec<KeyboardAction>) -> AutomationResult<()> {
+        self.command("keyboard", &KeyboardPayload { actions }).await
+    }
+
+    pub async fn focus(&self, selector: &ElementSelector) -> AutomationResult<()> {
+        self.command(
+            "focus",
+            &SelectorPayloadOwned {
+                selector: selector.clone(),
+            },
+        )
+        .await
+    }
+
+    pub async fn scroll_into_view(&self, selector: &ElementSelector) -> AutomationResult<()> {
+        self.command(
+            "scroll",
+            &SelectorPayloadOwned {
+                selector: selector.clone(),
+            },
+        )
+        .await
+    }
+
+    /// Resize the content area to `width`×`height` logical pixels.
+    pub async fn set_viewport(&self, width: f64, height: f64) -> AutomationResult<()> {
+        self.command("viewport", &ViewportPayload { width, height })
+            .await
+    }
+
+    /// See [`AutomationSession::emulate`](super::AutomationSession::emulate).
+    pub async fn emulate(&self, emulation: &Emulation) -> AutomationResult<()> {
+        self.command("emulate", emulation).await
+    }
+
+    /// See [`AutomationSession::reset_emulation`](super::AutomationSession::reset_emulation).
+    pub async fn reset_emulation(&self) -> AutomationResult<()> {
+        self.remove("emulate").await
+    }
+
+    /// The page's accessibility tree, in the same shape as the blocking client returns it.
+    pub async fn accessibility_tree(&self) -> AutomationResult<serde_json::Value> {
+        self.query("accessibility").await
+    }
+
+    pub async fn network_log(&self) -> AutomationResult<Vec<NetworkEntry>> {
+        self.query("network").await
+    }
+
+    pub async fn console_log(&self, limit: usize) -> AutomationResult<Vec<ConsoleEntry>> {
+        self.query(&format!("console?limit={limit}")).await
+    }
+
+    pub async fn frame_stats(&self) -> AutomationResult<FrameSummary> {
+        self.query("frames").await
+    }
+
+    pub async fn damage(&self) -> AutomationResult<DamageSummary> {
+        self.query("damage").await
+    }
+
+    pub async fn memory_report(&self) -> AutomationResult<MemoryReport> {
+        self.query("memory").await
+    }
+
+    pub async fn coverage(&self) -> AutomationResult<CoverageReport> {
+        self.query("coverage").await
+    }
+
+    pub async fn popups(&self) -> AutomationResult<Vec<PopupRecord>> {
+        self.query("popups").await
+    }
+
+    /// The page load as an HTTP Archive; see
+    /// [`AutomationSession::har`](super::AutomationSession::har).
+    pub async fn har(&self, content: bool) -> AutomationResult<serde_json::Value> {
+        self.query(&format!("har?content={content}")).await
+    }
+
+    /// See [`AutomationSession::compare_screenshot`](super::AutomationSession::compare_screenshot).
+    pub async fn compare_screenshot(
+        &self,
+        baseline: impl AsRef<Path>,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> AutomationResult<ScreenshotComparison> {
+        self.compare(None, baseline.as_ref(), thresholds, update)
+            .await
+    }
+
+    /// See [`super::AutomationSession::compare_element_screenshot`].
+    pub async fn compare_element_screenshot(
+        &self,
+        selector: &ElementSelector,
+        baseline: impl AsRef<Path>,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> AutomationResult<ScreenshotComparison> {
+        self.compare(Some(selector), baseline.as_ref(), thresholds, update)
+            .await
+    }
+
+    async fn compare(
+        &self,
+        selector: Option<&ElementSelector>,
+        baseline: &Path,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> AutomationResult<ScreenshotComparison> {
+        let payload = ComparePayload {
+            baseline,
+            thresholds,
+            update,
+            selector,
+        };
+        let request = self
+            .host
+            .client
+            .post(self.url("screenshot/compare"))
+            .json(&payload);
+        self.host
+            .send("screenshot/compare", request)
+            .await?
+            .json()
+            .await
+            .map_err(|source| AutomationError::InvalidResponse {
+                command: "screenshot/compare".to_string(),
+                source,
+            })
+    }
+
+    pub async fn element_rect(&self, selector: &ElementSelector) -> AutomationResult<ElementRect> {
+        self.query(&format!("rect?{}", encode_selector_query(selector)))
+            .await
+    }
+
+    /// The window as a PNG, browser chrome included.
+    pub async fn screenshot(&self) -> AutomationResult<Vec<u8>> {
+        self.capture("screenshot").await
+    }
+
+    /// The part of the element in the viewport as a PNG.
+    pub async fn element_screenshot(
+        &self,
+        selector: &ElementSelector,
+    ) -> AutomationResult<Vec<u8>> {
+        self.capture(&format!("screenshot?{}", encode_selector_query(selector)))
+            .await
+    }
+
+    /// See [`AutomationSession::export_pdf`](super::AutomationSession::export_pdf).
+    pub async fn export_pdf(&self, url: &str, options: &PrintOptions) -> AutomationResult<Vec<u8>> {
+        let request = self
+            .host
+            .client
+            .post(self.url("pdf"))
+            .json(&PdfPayload { url, options });
+        let pdf = self
+            .host
+            .send("pdf", request)
+            .await?
+            .bytes()
+            .await
+            .map_err(|source| AutomationError::InvalidResponse {
+                command: "pdf".to_string(),
+                source,
+            })?;
+        Ok(pdf.to_vec())
+    }
+
+    async fn capture(&self, path: &str) -> AutomationResult<Vec<u8>> {
+        let request = self.host.client.get(self.url(path));
+        let png = self
+            .host
+            .send("screenshot", request)
+            .await?
+            .bytes()
+            .await
+            .map_err(|source| AutomationError::InvalidResponse {
+                command: "screenshot".to_string(),
+                source,
+            })?;
+        Ok(png.to_vec())
+    }
+
+    pub async fn cookies(&self) -> AutomationResult<Vec<Cookie>> {
+        self.query("cookies").await
+    }
+
+    /// See [`AutomationSession::set_cookie`](super::AutomationSession::set_cookie).
+    pub async fn set_cookie(&self, cookie: &str) -> AutomationResult<()> {
+        self.command("cookies", &CookiePayload { cookie }).await
+    }
+
+    pub async fn clear_cookies(&self) -> AutomationResult<()> {
+        self.remove("cookies").await
+    }
+
+    pub async fn local_storage(&self) -> AutomationResult<BTreeMap<String, String>> {
+        self.query("storage").await
+    }
+
+    /// See [`AutomationSession::set_local_storage`](super::AutomationSession::set_local_storage).
+    pub async fn set_local_storage(
+        &self,
+        items: &BTreeMap<String, String>,
+    ) -> AutomationResult<()> {
+        self.command("storage", &StoragePayload { items }).await
+    }
+
+    pub async fn clear_local_storage(&self) -> AutomationResult<()> {
+        self.remove("storage").await
+    }
+
+    async fn remove(&self, path: &str) -> AutomationResult<()> {
+        let request = self.host.client.delete(self.url(path));
+        self.host.send(path, request).await?;
+        Ok(())
+    }
+
+    /// Replay a session recorded with `frontier --record`; see
+    /// [`AutomationSession::replay`](super::AutomationSession::replay).
+    pub async fn replay(&self, recording: &Recording) -> AutomationResult<()> {
+        let request = self
+            .host
+            .client
+            .post(self.url("replay"))
+            .timeout(replay_timeout(recording))
+            .json(recording);
+        self.host.send("replay", request).await?;
+        Ok(())
+    }
+
+    pub fn artifact_dir(&self) -> &Path {
+        &self.artifact_dir
+    }
+}
@@ -2,6 +2,7 @@
 //! Tests should rely on this crate instead of hand-rolling HTTP calls so the API
 //! remains stable while the automation host evolves.
 
+use std::collections::BTreeMap;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
@@ -9,12 +10,30 @@ use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
+mod async_client;
+
 pub use crate::automation::full_app::PointerOffset;
 pub use crate::automation::{
-    ElementSelector, KeyboardAction, PointerAction, PointerButton, PointerTarget,
+    ElementSelector, Emulation, KeyboardAction, PointerAction, PointerButton, PointerTarget,
+};
+pub use crate::cookies::Cookie;
+pub use crate::damage::DamageSummary;
+pub use crate::diagnostics::MemoryReport;
+pub use crate::frame_stats::FrameSummary;
+pub use crate::js::console::ConsoleEntry;
+pub use crate::js::coverage::{CoverageReport, FunctionCoverage, LineCoverage, ScriptReport};
+pub use crate::js::media::ColorScheme;
+pub use crate::network_log::NetworkEntry;
+pub use crate::popups::PopupRecord;
+pub use crate::print::{Paper, PrintOptions};
+pub use crate::recording::Recording;
+pub use crate::screenshot::{CompareThresholds, ElementRect, ScreenshotComparison};
+pub use async_client::{
+    AsyncAutomationHost, AsyncAutomationSession, AutomationError, AutomationResult,
 };
 
 /// Default automation session id – the host currently supports a single active session.
@@ -22,11 +41,12 @@ const SESSION_ID: &str = "frontier";
 
 /// Top-level handle that owns the automation host process and HTTP client.
 pub struct AutomationHost {
-    child: Child,
-    _reader: BufReader<std::process::ChildStdout>,
+    /// `None` when connected to a host started elsewhere.
+    _process: Option<HostProcess>,
     base_url: Url,
     client: Client,
     artifact_root: PathBuf,
+    coverage: bool,
 }
 
 impl AutomationHost {
@@ -35,87 +55,52 @@ impl AutomationHost {
     /// The binary is located via `CARGO_BIN_EXE_automation_host`, so integration tests must
     /// ensure the executable is built (Cargo takes care of this automatically).
     pub fn spawn(config: AutomationHostConfig) -> Result<Self> {
-        let AutomationHostConfig {
-            bind_address,
-            initial_target,
-            asset_root,
-            artifact_root,
-        } = config;
-        let binary = match std::env::var("CARGO_BIN_EXE_automation_host") {
-            Ok(path) => PathBuf::from(path),
-            Err(_) => fallback_automation_host_path()
-                .context("automation host binary not built; run cargo test to compile binaries")?,
-        };
-        let mut command = Command::new(&binary);
-        let asset_root = asset_root.unwrap_or_else(default_asset_root);
-        let artifact_root = artifact_root.unwrap_or_else(default_artifact_root);
-        command
-            .env("AUTOMATION_ASSET_ROOT", asset_root.display().to_string())
-            .env(
-                "AUTOMATION_BIND",
-                bind_address.unwrap_or_else(|| "127.0.0.1:0".into()),
-            )
-            .env(
-                "AUTOMATION_INITIAL",
-                initial_target.unwrap_or_else(|| "about:blank".to_string()),
-            )
-            .env(
-                "AUTOMATION_ARTIFACT_ROOT",
-                artifact_root.display().to_string(),
-            )
-            .stderr(Stdio::inherit())
-            .stdout(Stdio::piped())
-            .stdin(Stdio::null());
-
-        let mut child = command.spawn().context("spawn automation host process")?;
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("automation host stdout unavailable"))?;
-        let mut reader = BufReader::new(stdout);
-        let mut banner = String::new();
-        let addr = loop {
-            banner.clear();
-            let bytes_read = reader
-                .read_line(&mut banner)
-                .context("read automation host banner")?;
-            if bytes_read == 0 {
-                return Err(anyhow!("automation host exited before reporting readiness"));
-            }
-            let trimmed = banner.trim();
-            if let Some(addr) = trimmed.strip_prefix("AUTOMATION_HOST_READY ") {
-                break addr.to_string();
-            }
-            // Forward any early stdout noise to stderr so the caller can diagnose issues.
-            if !trimmed.is_empty() {
-                eprintln!("automation_host: {trimmed}");
-            }
-        };
+        Self::from_launched(launch(config)?)
+    }
 
-        let base_url =
-            Url::parse(&format!("http://{addr}")).context("parse automation base url")?;
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .context("build automation http client")?;
+    /// Drive a host that is already running, e.g. on a lab machine started with a config file.
+    pub fn connect(remote: RemoteHost) -> Result<Self> {
+        Self::from_launched(remote.into_launched()?)
+    }
 
-        std::fs::create_dir_all(&artifact_root)
-            .with_context(|| format!("create artifact directory {}", artifact_root.display()))?;
+    fn from_launched(launched: Launched) -> Result<Self> {
+        let Launched {
+            process,
+            base_url,
+            auth_token,
+            ca_cert,
+            artifact_root,
+        } = launched;
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .default_headers(auth_headers(auth_token.as_deref())?);
+        if let Some(cert) = ca_cert {
+            builder = builder.add_root_certificate(cert);
+        }
+        let client = builder.build().context("build automation http client")?;
 
         Ok(Self {
-            child,
-            _reader: reader,
+            _process: process,
             base_url,
             client,
             artifact_root,
+            coverage: false,
         })
     }
 
+    /// Collect script coverage in the sessions created from now on, for
+    /// [`AutomationSession::coverage`].
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = true;
+        self
+    }
+
     /// Create a session by navigating to an asset relative to the asset root.
     pub fn session_from_asset(&self, file: impl AsRef<str>) -> Result<AutomationSession<'_>> {
         let payload = CreateSessionPayload {
             url: None,
             file: Some(file.as_ref().to_string()),
+            coverage: self.coverage,
         };
         self.create_session(payload)
     }
@@ -125,6 +110,7 @@ impl AutomationHost {
         let payload = CreateSessionPayload {
             url: Some(url.as_ref().to_string()),
             file: None,
+            coverage: self.coverage,
         };
         self.create_session(payload)
     }
@@ -157,6 +143,14 @@ impl AutomationHost {
             .context("execute automation GET")
     }
 
+    fn delete(&self, path: &str) -> Result<Response> {
+        let url = self.base_url.join(path).context("build request url")?;
+        self.client
+            .delete(url)
+            .send()
+            .context("execute automation DELETE")
+    }
+
     /// Directory where command artifacts should be written. The host populates it on demand.
     pub fn artifact_root(&self) -> &Path {
         &self.artifact_root
@@ -167,13 +161,6 @@ impl AutomationHost {
     }
 }
 
-impl Drop for AutomationHost {
-    fn drop(&mut self) {
-        let _ = self.child.kill();
-        let _ = self.child.wait();
-    }
-}
-
 /// Launch configuration for the automation host.
 #[derive(Default)]
 pub struct AutomationHostConfig {
@@ -181,6 +168,7 @@ pub struct AutomationHostConfig {
     initial_target: Option<String>,
     asset_root: Option<PathBuf>,
     artifact_root: Option<PathBuf>,
+    auth_token: Option<String>,
 }
 
 impl AutomationHostConfig {
@@ -198,6 +186,68 @@ impl AutomationHostConfig {
         self.artifact_root = Some(path.into());
         self
     }
+
+    /// Require this bearer token on every request, as a remote host would.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+}
+
+/// Connection settings for an automation host the client did not start.
+///
+/// Artifacts are still written by the host, under its own artifact root.
+pub struct RemoteHost {
+    url: String,
+    auth_token: Option<String>,
+    ca_cert: Option<PathBuf>,
+}
+
+impl RemoteHost {
+    /// `url` is the host's base URL, `https://` when it serves TLS.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth_token: None,
+            ca_cert: None,
+        }
+    }
+
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Trust this PEM certificate, for hosts serving a self-signed one.
+    pub fn with_ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_cert = Some(path.into());
+        self
+    }
+
+    fn into_launched(self) -> Result<Launched> {
+        let base_url = Url::parse(&self.url).context("parse automation host url")?;
+        if !matches!(base_url.scheme(), "http" | "https") {
+            return Err(anyhow!("automation host url must be http or https"));
+        }
+        let ca_cert = match self.ca_cert {
+            Some(path) => {
+                let pem = std::fs::read(&path)
+                    .with_context(|| format!("read CA certificate {}", path.display()))?;
+                Some(
+                    reqwest::Certificate::from_pem(&pem)
+                        .with_context(|| format!("parse CA certificate {}", path.display()))?,
+                )
+            }
+            None => None,
+        };
+        Ok(Launched {
+            process: None,
+            base_url,
+            auth_token: self.auth_token,
+            ca_cert,
+            artifact_root: default_artifact_root(),
+        })
+    }
 }
 
 /// Active automation session that exposes higher-level helpers for driving the host.
@@ -226,6 +276,15 @@ impl<'host> AutomationSession<'host> {
         self.host.get(&full_path)
     }
 
+    fn delete(&self, path: &str) -> Result<Response> {
+        let full_path = format!(
+            "/session/{}/{}",
+            self.session_id,
+            path.trim_start_matches('/')
+        );
+        self.host.delete(&full_path)
+    }
+
     /// Click a selector.
     pub fn click(&self, selector: &ElementSelector) -> Result<()> {
         self.post(
@@ -262,6 +321,21 @@ impl<'host> AutomationSession<'host> {
         self.type_text(&ElementSelector::css(selector.to_string()), text)
     }
 
+    /// Select `paths` in a file input, as if picked in the file dialog. Paths are relative to
+    /// the host's asset root; absolute paths and `..` are refused.
+    pub fn set_input_files(&self, selector: &ElementSelector, paths: &[PathBuf]) -> Result<()> {
+        self.post(
+            "files",
+            &FilesPayload {
+                selector: selector.clone(),
+                paths: paths.to_vec(),
+            },
+        )?
+        .error_for_status()
+        .context("files response")?;
+        Ok(())
+    }
+
     /// Navigate to the provided URL.
     pub fn navigate_url(&self, url: &str) -> Result<()> {
         self.post(
@@ -388,6 +462,281 @@ impl<'host> AutomationSession<'host> {
         Ok(())
     }
 
+    /// Resize the content area to `width`×`height` logical pixels; the page relays out and
+    /// receives a `resize` event.
+    pub fn set_viewport(&self, width: f64, height: f64) -> Result<()> {
+        self.post("viewport", &ViewportPayload { width, height })?
+            .error_for_status()
+            .context("viewport response")?;
+        Ok(())
+    }
+
+    /// Present the page with another device: any of viewport size, device pixel ratio,
+    /// colour scheme, reduced motion and user agent. Fields left as `None` keep their current
+    /// value. The user agent applies from the next request, so emulate before navigating.
+    pub fn emulate(&self, emulation: &Emulation) -> Result<()> {
+        self.post("emulate", emulation)?
+            .error_for_status()
+            .context("emulate response")?;
+        Ok(())
+    }
+
+    /// Undo every [`Self::emulate`]: the window's own device pixel ratio, colour scheme,
+    /// reduced motion and user agent apply again. The viewport keeps its size.
+    pub fn reset_emulation(&self) -> Result<()> {
+        self.delete("emulate")?
+            .error_for_status()
+            .context("reset emulation response")?;
+        Ok(())
+    }
+
+    /// The page's accessibility tree: nested `{node_id, role, name, states, children}`
+    /// records rooted at the document.
+    pub fn accessibility_tree(&self) -> Result<serde_json::Value> {
+        self.get("accessibility")?
+            .error_for_status()
+            .context("accessibility response")?
+            .json()
+            .context("parse accessibility tree")
+    }
+
+    /// Every request the current document made, oldest first, for asserting on request
+    /// counts, sizes and timings.
+    pub fn network_log(&self) -> Result<Vec<NetworkEntry>> {
+        self.get("network")?
+            .error_for_status()
+            .context("network response")?
+            .json()
+            .context("parse network log")
+    }
+
+    /// The newest `limit` console messages and uncaught exceptions of page scripts, oldest
+    /// first. The log spans page loads.
+    pub fn console_log(&self, limit: usize) -> Result<Vec<ConsoleEntry>> {
+        self.get(&format!("console?limit={limit}"))?
+            .error_for_status()
+            .context("console response")?
+            .json()
+            .context("parse console log")
+    }
+
+    /// Frame rate, frame time percentiles and jank count since the current page loaded, for
+    /// catching render-path regressions in CI.
+    pub fn frame_stats(&self) -> Result<FrameSummary> {
+        self.get("frames")?
+            .error_for_status()
+            .context("frames response")?
+            .json()
+            .context("parse frame stats")
+    }
+
+    /// How many frames since the current page loaded repainted part, all or none of the
+    /// viewport, and the latest damaged rectangles.
+    pub fn damage(&self) -> Result<DamageSummary> {
+        self.get("damage")?
+            .error_for_status()
+            .context("damage response")?
+            .json()
+            .context("parse damage summary")
+    }
+
+    /// Approximate memory held by the current page: QuickJS heap, DOM node count, loaded
+    /// assets and prefetched documents. Compare reports across navigations to catch leaks.
+    pub fn memory_report(&self) -> Result<MemoryReport> {
+        self.get("memory")?
+            .error_for_status()
+            .context("memory response")?
+            .json()
+            .context("parse memory report")
+    }
+
+    /// Which functions and lines of the page scripts ran, for every page the session loaded.
+    /// Fails unless the host was set up [`AutomationHost::with_coverage`].
+    pub fn coverage(&self) -> Result<CoverageReport> {
+        self.get("coverage")?
+            .error_for_status()
+            .context("coverage response")?
+            .json()
+            .context("parse coverage report")
+    }
+
+    /// Every `window.open` call that asked for a new window, oldest first. Automation
+    /// sessions never open the popups they allow, so assert on `blocked` and `url` here.
+    pub fn popups(&self) -> Result<Vec<PopupRecord>> {
+        self.get("popups")?
+            .error_for_status()
+            .context("popups response")?
+            .json()
+            .context("parse popups")
+    }
+
+    /// The current page load as an HTTP Archive (HAR 1.2), with response bodies when
+    /// `content` is set. The host also writes it to the command's artifact directory.
+    pub fn har(&self, content: bool) -> Result<serde_json::Value> {
+        self.get(&format!("har?content={content}"))?
+            .error_for_status()
+            .context("har response")?
+            .json()
+            .context("parse har")
+    }
+
+    /// Compare the window against a baseline PNG, relative to the host's asset root (absolute
+    /// paths and `..` are refused). A mismatch is not an error: check [`ScreenshotComparison::matched`] and the
+    /// command's `actual.png` and `diff.png` artifacts. With `update` the capture replaces
+    /// the baseline instead.
+    pub fn compare_screenshot(
+        &self,
+        baseline: impl AsRef<Path>,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> Result<ScreenshotComparison> {
+        self.compare(None, baseline.as_ref(), thresholds, update)
+    }
+
+    /// [`Self::compare_screenshot`], with the capture cropped to the part of the element in
+    /// the viewport, so changes elsewhere in the window do not count.
+    pub fn compare_element_screenshot(
+        &self,
+        selector: &ElementSelector,
+        baseline: impl AsRef<Path>,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> Result<ScreenshotComparison> {
+        self.compare(Some(selector), baseline.as_ref(), thresholds, update)
+    }
+
+    fn compare(
+        &self,
+        selector: Option<&ElementSelector>,
+        baseline: &Path,
+        thresholds: CompareThresholds,
+        update: bool,
+    ) -> Result<ScreenshotComparison> {
+        let payload = ComparePayload {
+            baseline,
+            thresholds,
+            update,
+            selector,
+        };
+        self.post("screenshot/compare", &payload)?
+            .error_for_status()
+            .context("screenshot comparison response")?
+            .json()
+            .context("parse screenshot comparison")
+    }
+
+    /// Where the element is laid out, in CSS pixels from the top left of the viewport.
+    pub fn element_rect(&self, selector: &ElementSelector) -> Result<ElementRect> {
+        self.get(&format!("rect?{}", encode_selector_query(selector)))?
+            .error_for_status()
+            .context("rect response")?
+            .json()
+            .context("parse element rect")
+    }
+
+    /// The window as a PNG, browser chrome included.
+    pub fn screenshot(&self) -> Result<Vec<u8>> {
+        self.capture("screenshot")
+    }
+
+    /// The part of the element in the viewport as a PNG.
+    pub fn element_screenshot(&self, selector: &ElementSelector) -> Result<Vec<u8>> {
+        self.capture(&format!("screenshot?{}", encode_selector_query(selector)))
+    }
+
+    /// `url` exported to PDF with its print styles. The page is loaded and laid out on its own,
+    /// so the session's window is left as it is.
+    pub fn export_pdf(&self, url: &str, options: &PrintOptions) -> Result<Vec<u8>> {
+        let pdf = self
+            .post("pdf", &PdfPayload { url, options })?
+            .error_for_status()
+            .context("pdf response")?
+            .bytes()
+            .context("read pdf")?;
+        Ok(pdf.to_vec())
+    }
+
+    fn capture(&self, path: &str) -> Result<Vec<u8>> {
+        let png = self
+            .get(path)?
+            .error_for_status()
+            .context("screenshot response")?
+            .bytes()
+            .context("read screenshot")?;
+        Ok(png.to_vec())
+    }
+
+    /// Cookies the current page would send, `HttpOnly` ones included.
+    pub fn cookies(&self) -> Result<Vec<Cookie>> {
+        self.get("cookies")?
+            .error_for_status()
+            .context("cookies response")?
+            .json()
+            .context("parse cookies")
+    }
+
+    /// Store a cookie for the current page, written like a `Set-Cookie` header value.
+    pub fn set_cookie(&self, cookie: &str) -> Result<()> {
+        self.post("cookies", &CookiePayload { cookie })?
+            .error_for_status()
+            .context("set cookie response")?;
+        Ok(())
+    }
+
+    /// Forget every cookie in the browser's jar.
+    pub fn clear_cookies(&self) -> Result<()> {
+        self.delete("cookies")?
+            .error_for_status()
+            .context("clear cookies response")?;
+        Ok(())
+    }
+
+    /// The current page's origin's `localStorage`.
+    pub fn local_storage(&self) -> Result<BTreeMap<String, String>> {
+        self.get("storage")?
+            .error_for_status()
+            .context("storage response")?
+            .json()
+            .context("parse localStorage")
+    }
+
+    /// Add items to the current page's origin's `localStorage`; pages see them on the next
+    /// read, so seed before navigating to the page that reads them at startup.
+    pub fn set_local_storage(&self, items: &BTreeMap<String, String>) -> Result<()> {
+        self.post("storage", &StoragePayload { items })?
+            .error_for_status()
+            .context("set storage response")?;
+        Ok(())
+    }
+
+    pub fn clear_local_storage(&self) -> Result<()> {
+        self.delete("storage")?
+            .error_for_status()
+            .context("clear storage response")?;
+        Ok(())
+    }
+
+    /// Replay a session recorded with `frontier --record`, returning once its last step ran.
+    /// The request takes as long as the recording did.
+    pub fn replay(&self, recording: &Recording) -> Result<()> {
+        let path = format!("/session/{}/replay", self.session_id);
+        let url = self
+            .host
+            .base_url
+            .join(&path)
+            .context("build request url")?;
+        self.host
+            .client
+            .post(url)
+            .timeout(replay_timeout(recording))
+            .json(recording)
+            .send()
+            .context("execute automation POST")?
+            .error_for_status()
+            .context("replay response")?;
+        Ok(())
+    }
+
     pub fn artifact_dir(&self) -> &Path {
         &self.artifact_dir
     }
@@ -424,11 +773,26 @@ struct TypePayload {
     text: String,
 }
 
+#[derive(Serialize)]
+struct FilesPayload {
+    selector: ElementSelector,
+    paths: Vec<PathBuf>,
+}
+
 #[derive(Serialize)]
 struct PumpPayload {
     milliseconds: u64,
 }
 
+#[derive(Serialize)]
+struct ComparePayload<'a> {
+    baseline: &'a Path,
+    thresholds: CompareThresholds,
+    update: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selector: Option<&'a ElementSelector>,
+}
+
 #[derive(Serialize)]
 struct NavigatePayload {
     url: Option<String>,
@@ -455,6 +819,29 @@ struct KeyboardPayload {
     actions: Vec<KeyboardAction>,
 }
 
+#[derive(Serialize)]
+struct CookiePayload<'a> {
+    cookie: &'a str,
+}
+
+#[derive(Serialize)]
+struct PdfPayload<'a> {
+    url: &'a str,
+    #[serde(flatten)]
+    options: &'a PrintOptions,
+}
+
+#[derive(Serialize)]
+struct StoragePayload<'a> {
+    items: &'a BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct ViewportPayload {
+    width: f64,
+    height: f64,
+}
+
 fn encode_selector_query(selector: &ElementSelector) -> String {
     let mut params: Vec<(String, String)> = Vec::new();
     match selector {
@@ -477,6 +864,127 @@ fn encode_selector_query(selector: &ElementSelector) -> String {
 struct CreateSessionPayload {
     url: Option<String>,
     file: Option<String>,
+    coverage: bool,
+}
+
+/// A child automation host process, killed when dropped.
+struct HostProcess {
+    child: Child,
+    _reader: BufReader<std::process::ChildStdout>,
+}
+
+impl Drop for HostProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Where to reach an automation host, and the process when this client started it.
+struct Launched {
+    process: Option<HostProcess>,
+    base_url: Url,
+    auth_token: Option<String>,
+    ca_cert: Option<reqwest::Certificate>,
+    artifact_root: PathBuf,
+}
+
+/// Start the host binary and block until it prints its `AUTOMATION_HOST_READY` banner.
+fn launch(config: AutomationHostConfig) -> Result<Launched> {
+    let AutomationHostConfig {
+        bind_address,
+        initial_target,
+        asset_root,
+        artifact_root,
+        auth_token,
+    } = config;
+    let binary = match std::env::var("CARGO_BIN_EXE_automation_host") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => fallback_automation_host_path()
+            .context("automation host binary not built; run cargo test to compile binaries")?,
+    };
+    let mut command = Command::new(&binary);
+    let asset_root = asset_root.unwrap_or_else(default_asset_root);
+    let artifact_root = artifact_root.unwrap_or_else(default_artifact_root);
+    command
+        .env("AUTOMATION_ASSET_ROOT", asset_root.display().to_string())
+        .env(
+            "AUTOMATION_BIND",
+            bind_address.unwrap_or_else(|| "127.0.0.1:0".into()),
+        )
+        .env(
+            "AUTOMATION_INITIAL",
+            initial_target.unwrap_or_else(|| "about:blank".to_string()),
+        )
+        .env(
+            "AUTOMATION_ARTIFACT_ROOT",
+            artifact_root.display().to_string(),
+        )
+        .env(
+            "AUTOMATION_TOKEN",
+            auth_token.as_deref().unwrap_or_default(),
+        )
+        .stderr(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null());
+
+    let mut child = command.spawn().context("spawn automation host process")?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("automation host stdout unavailable"))?;
+    let mut reader = BufReader::new(stdout);
+    let mut banner = String::new();
+    let addr = loop {
+        banner.clear();
+        let bytes_read = reader
+            .read_line(&mut banner)
+            .context("read automation host banner")?;
+        if bytes_read == 0 {
+            return Err(anyhow!("automation host exited before reporting readiness"));
+        }
+        let trimmed = banner.trim();
+        if let Some(addr) = trimmed.strip_prefix("AUTOMATION_HOST_READY ") {
+            break addr.to_string();
+        }
+        // Forward any early stdout noise to stderr so the caller can diagnose issues.
+        if !trimmed.is_empty() {
+            eprintln!("automation_host: {trimmed}");
+        }
+    };
+
+    let base_url = Url::parse(&format!("http://{addr}")).context("parse automation base url")?;
+    std::fs::create_dir_all(&artifact_root)
+        .with_context(|| format!("create artifact directory {}", artifact_root.display()))?;
+
+    Ok(Launched {
+        process: Some(HostProcess {
+            child,
+            _reader: reader,
+        }),
+        base_url,
+        auth_token,
+        ca_cert: None,
+        artifact_root,
+    })
+}
+
+/// Long enough for the host to wait out every recorded gap, plus the usual request timeout.
+fn replay_timeout(recording: &Recording) -> Duration {
+    let recorded = recording.steps.last().map_or(0, |step| step.at_ms);
+    Duration::from_millis(recorded) + Duration::from_secs(30)
+}
+
+/// `Authorization: Bearer <token>` for every request, when the host wants one.
+fn auth_headers(token: Option<&str>) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .context("auth token is not a valid header value")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(headers)
 }
 
 fn default_asset_root() -> PathBuf {
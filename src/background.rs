@@ -0,0 +1,172 @@
+//! Background mode: closing the window hides it instead of quitting, and a global shortcut
+//! brings it back on the quick-open palette.
+//!
+//! Starting Frontier means starting the script runtime and connecting to relays, which takes
+//! long enough to notice. With `background = true` in `frontier.toml` (or Keep running in the
+//! background in `frontier://settings`) that is paid once: the process stays up with its
+//! window hidden, and `quick_open_shortcut` (Cmd/Ctrl+Shift+Space unless set) shows it on
+//! [`crate::quick_open`] from any application. On macOS and Windows a tray icon offers Quick
+//! Open, Show Frontier and Quit. Linux has no tray here, for the same reason it has no native
+//! menu (see [`crate::app_menu`]); the shortcut, which needs X11, is the way back, so when it
+//! cannot be registered closing the window quits as usual.
+
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+use crate::app_menu::MenuCommand;
+
+/// The quick-open shortcut unless `frontier.toml` sets another.
+pub const DEFAULT_SHORTCUT: &str = "CmdOrCtrl+Shift+Space";
+
+/// Whether `shortcut` names a key combination a global shortcut can use, such as
+/// `Alt+Space` or `CmdOrCtrl+Shift+K`.
+pub fn is_valid_shortcut(shortcut: &str) -> bool {
+    shortcut.parse::<HotKey>().is_ok()
+}
+
+/// The tray icon and the registered shortcut, while background mode is on.
+#[derive(Default)]
+pub struct Background {
+    hotkeys: Option<GlobalHotKeyManager>,
+    /// The shortcut as registered, to unregister it when it changes.
+    shortcut: Option<(String, HotKey)>,
+    /// Whether the shortcut's events are forwarded yet; global-hotkey takes one handler.
+    listening: bool,
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    tray: Option<tray_icon::TrayIcon>,
+}
+
+impl Background {
+    /// Turn background mode on or off, registering `shortcut` to open the quick-open palette.
+    /// Commands from the shortcut and the tray go to `send`. Failures are logged and leave
+    /// that part off.
+    pub fn sync(
+        &mut self,
+        enabled: bool,
+        shortcut: &str,
+        send: impl Fn(MenuCommand) + Clone + Send + Sync + 'static,
+    ) {
+        if !enabled {
+            self.unregister();
+            #[cfg(any(target_os = "macos", target_os = "windows"))]
+            {
+                self.tray = None;
+            }
+            return;
+        }
+        if self.shortcut.as_ref().map(|(current, _)| current.as_str()) != Some(shortcut) {
+            self.unregister();
+            if let Err(err) = self.register(shortcut, send.clone()) {
+                tracing::warn!(shortcut, error = %err, "failed to register the shortcut");
+            }
+        }
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        if self.tray.is_none() {
+            match tray::build(send) {
+                Ok(tray) => self.tray = Some(tray),
+                Err(err) => tracing::warn!(error = %err, "failed to add the tray icon"),
+            }
+        }
+    }
+
+    /// Whether closing the window should hide it: something can bring it back.
+    pub fn keeps_running(&self) -> bool {
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        if self.tray.is_some() {
+            return true;
+        }
+        self.shortcut.is_some()
+    }
+
+    fn register(
+        &mut self,
+        shortcut: &str,
+        send: impl Fn(MenuCommand) + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        let hotkey: HotKey = shortcut.parse()?;
+        if self.hotkeys.is_none() {
+            self.hotkeys = Some(GlobalHotKeyManager::new()?);
+        }
+        if let Some(manager) = &self.hotkeys {
+            manager.register(hotkey)?;
+        }
+        if !std::mem::replace(&mut self.listening, true) {
+            // Only the quick-open shortcut is ever registered.
+            GlobalHotKeyEvent::set_event_handler(Some(move |event: GlobalHotKeyEvent| {
+                if event.state() == HotKeyState::Pressed {
+                    send(MenuCommand::QuickOpen);
+                }
+            }));
+        }
+        self.shortcut = Some((shortcut.to_string(), hotkey));
+        Ok(())
+    }
+
+    fn unregister(&mut self) {
+        let (Some((_, hotkey)), Some(manager)) = (self.shortcut.take(), &self.hotkeys) else {
+            return;
+        };
+        if let Err(err) = manager.unregister(hotkey) {
+            tracing::warn!(error = %err, "failed to unregister the quick-open shortcut");
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+mod tray {
+    use anyhow::Result;
+    use muda::{Menu, MenuItem, PredefinedMenuItem};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+    use crate::app_menu::{forward_menu_events, MenuCommand};
+
+    const ICON_SIZE: u32 = 32;
+
+    pub fn build(send: impl Fn(MenuCommand) + Send + Sync + 'static) -> Result<TrayIcon> {
+        let item =
+            |command: MenuCommand, label: &str| MenuItem::with_id(command.url(), label, true, None);
+        let menu = Menu::with_items(&[
+            &item(MenuCommand::QuickOpen, "Quick Open…"),
+            &item(MenuCommand::ShowWindow, "Show Frontier"),
+            &PredefinedMenuItem::separator(),
+            &item(MenuCommand::Quit, "Quit Frontier"),
+        ])?;
+        forward_menu_events(send);
+        let tray = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_icon(icon()?)
+            .with_tooltip("Frontier")
+            .build()?;
+        Ok(tray)
+    }
+
+    /// A filled disc, drawn rather than shipped as an image.
+    fn icon() -> Result<Icon> {
+        let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+        let radius = ICON_SIZE as f32 / 2.0 - 2.0;
+        let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+        for y in 0..ICON_SIZE {
+            for x in 0..ICON_SIZE {
+                let distance = (x as f32 - center).hypot(y as f32 - center);
+                let alpha = ((radius + 0.5 - distance).clamp(0.0, 1.0) * 255.0) as u8;
+                rgba.extend_from_slice(&[0x2b, 0x6c, 0xb0, alpha]);
+            }
+        }
+        Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortcuts_parse_with_platform_neutral_modifiers() {
+        assert!(is_valid_shortcut(DEFAULT_SHORTCUT));
+        assert!(is_valid_shortcut("Alt+Space"));
+        assert!(is_valid_shortcut("ctrl+shift+k"));
+        assert!(!is_valid_shortcut("Shift+"));
+        assert!(!is_valid_shortcut("Hyper+Q"));
+        assert!(!Background::default().keeps_running());
+    }
+}
@@ -0,0 +1,449 @@
+//! `frontier bench`: page-load and interaction latency for a set of local pages, as JSON.
+//!
+//! Each page is loaded `--iterations` times the way a window loads it, without the window: the
+//! path is resolved to an address, fetched with its blocking scripts, parsed, its scripts run
+//! through `load`, then styled and laid out at the viewport size and painted with the CPU
+//! renderer (see [`crate::screenshot::capture`]). After each load, every element with a
+//! `data-bench-click` attribute is clicked in document order, and the time from dispatching the
+//! click to the repainted frame is its interaction latency. Pages default to `assets/bench`.
+//!
+//! The report gives the median, mean, min and max of every phase in milliseconds, so CI can
+//! keep one file per run and chart the trend.
+
+use std::fs;
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use blitz_dom::{BaseDocument, DocumentConfig};
+use blitz_html::HtmlDocument;
+use blitz_net::Provider;
+use blitz_traits::events::{
+    BlitzMouseButtonEvent, DomEvent, DomEventData, MouseEventButton, MouseEventButtons,
+};
+use blitz_traits::net::DummyNetCallback;
+use blitz_traits::shell::{ColorScheme, Viewport};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::js::lifecycle::LifecycleEvent;
+use crate::js::runtime_document::RuntimeDocument;
+use crate::js::session::JsPageRuntime;
+use crate::navigation::{execute_fetch, prepare_navigation, FetchedDocument, NavigationPlan};
+use crate::screenshot;
+use crate::search::SearchEngine;
+
+/// Elements carrying this attribute are clicked after each load; its value names the target.
+pub const CLICK_ATTRIBUTE: &str = "data-bench-click";
+
+const DEFAULT_ITERATIONS: usize = 5;
+const DEFAULT_WIDTH: u32 = 1024;
+const DEFAULT_HEIGHT: u32 = 768;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    /// Pages to load, `.html` or `.md`, in the order given.
+    pub pages: Vec<PathBuf>,
+    pub iterations: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Where to write the report; stdout when unset.
+    pub output: Option<PathBuf>,
+}
+
+impl BenchOptions {
+    /// `frontier bench [pages or directories…] [--iterations N] [--width W] [--height H]
+    /// [--output report.json]`.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut inputs = Vec::new();
+        let mut iterations = DEFAULT_ITERATIONS;
+        let mut width = DEFAULT_WIDTH;
+        let mut height = DEFAULT_HEIGHT;
+        let mut output = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{arg} expects a value"))
+            };
+            match arg.as_str() {
+                "--iterations" => {
+                    iterations = value()?.parse().context("invalid --iterations")?;
+                }
+                "--width" => width = value()?.parse().context("invalid --width")?,
+                "--height" => height = value()?.parse().context("invalid --height")?,
+                "--output" => output = Some(PathBuf::from(value()?)),
+                flag if flag.starts_with("--") => bail!("unknown option {flag}"),
+                path => inputs.push(PathBuf::from(path)),
+            }
+        }
+        if iterations == 0 || width == 0 || height == 0 {
+            bail!("--iterations, --width and --height must be at least 1");
+        }
+        if inputs.is_empty() {
+            inputs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/bench"));
+        }
+
+        let mut pages = Vec::new();
+        for input in inputs {
+            pages.extend(expand(&input)?);
+        }
+        if pages.is_empty() {
+            bail!("no .html or .md pages to benchmark");
+        }
+
+        Ok(Self {
+            pages,
+            iterations,
+            width,
+            height,
+            output,
+        })
+    }
+}
+
+/// `path` itself, or the `.html` and `.md` files directly inside it, sorted.
+fn expand(path: &Path) -> Result<Vec<PathBuf>> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("no such page {}", path.display()))?;
+    if !path.is_dir() {
+        return Ok(vec![path]);
+    }
+    let mut pages = Vec::new();
+    for entry in fs::read_dir(&path).with_context(|| format!("read {}", path.display()))? {
+        let page = entry?.path();
+        let extension = page.extension().and_then(|ext| ext.to_str());
+        if page.is_file() && matches!(extension, Some("html" | "md")) {
+            pages.push(page);
+        }
+    }
+    pages.sort();
+    Ok(pages)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub version: String,
+    pub iterations: usize,
+    pub width: u32,
+    pub height: u32,
+    pub pages: Vec<PageReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageReport {
+    pub page: String,
+    pub phases: PhaseTimes,
+    pub interactions: Vec<InteractionReport>,
+}
+
+/// Each phase of a load, and the whole load from resolve to painted frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseTimes {
+    pub resolve: Stats,
+    pub fetch: Stats,
+    pub parse: Stats,
+    /// Blocking scripts, the `load` event and the tasks it queued. Zero for pages without
+    /// scripts.
+    pub scripts: Stats,
+    pub layout: Stats,
+    pub paint: Stats,
+    pub total: Stats,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionReport {
+    /// The `data-bench-click` value, or the element's position among the targets.
+    pub target: String,
+    /// From dispatching the click to the repainted frame.
+    pub latency: Stats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub samples: usize,
+    pub median_ms: f64,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Stats {
+    pub fn of(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self {
+                samples: 0,
+                median_ms: 0.0,
+                mean_ms: 0.0,
+                min_ms: 0.0,
+                max_ms: 0.0,
+            };
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        };
+        Self {
+            samples: sorted.len(),
+            median_ms: median,
+            mean_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            min_ms: sorted[0],
+            max_ms: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Benchmark every page in `options`. Runs on the calling thread; the script runtime is not
+/// `Send`, so await it with `block_on` rather than spawning it.
+pub async fn run(options: &BenchOptions) -> Result<BenchReport> {
+    let mut pages = Vec::with_capacity(options.pages.len());
+    for page in &options.pages {
+        pages.push(
+            bench_page(page, options)
+                .await
+                .with_context(|| format!("benchmark {}", page.display()))?,
+        );
+    }
+    Ok(BenchReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        iterations: options.iterations,
+        width: options.width,
+        height: options.height,
+        pages,
+    })
+}
+
+#[derive(Default)]
+struct PageSamples {
+    resolve: Vec<f64>,
+    fetch: Vec<f64>,
+    parse: Vec<f64>,
+    scripts: Vec<f64>,
+    layout: Vec<f64>,
+    paint: Vec<f64>,
+    total: Vec<f64>,
+    /// Latencies per click target, in document order.
+    interactions: Vec<(String, Vec<f64>)>,
+}
+
+async fn bench_page(page: &Path, options: &BenchOptions) -> Result<PageReport> {
+    let url = Url::from_file_path(page).map_err(|_| anyhow!("not an absolute path"))?;
+    let search = SearchEngine::default();
+    let mut samples = PageSamples::default();
+
+    for _ in 0..options.iterations {
+        let load_started = Instant::now();
+
+        let started = Instant::now();
+        let NavigationPlan::Fetch(request) = prepare_navigation(url.as_str(), &search).await?;
+        samples.resolve.push(millis(started));
+
+        let started = Instant::now();
+        let net = Arc::new(Provider::new(Arc::new(DummyNetCallback)));
+        let fetched = execute_fetch(&request, net).await?;
+        samples.fetch.push(millis(started));
+
+        let started = Instant::now();
+        let html_doc = HtmlDocument::from_html(
+            &fetched.contents,
+            DocumentConfig {
+                base_url: Some(fetched.base_url.clone()),
+                ..Default::default()
+            },
+        );
+        samples.parse.push(millis(started));
+
+        let started = Instant::now();
+        let mut loaded = Loaded::new(html_doc, &fetched, options)?;
+        samples.scripts.push(millis(started));
+
+        let started = Instant::now();
+        loaded.layout();
+        samples.layout.push(millis(started));
+
+        let started = Instant::now();
+        loaded.paint()?;
+        samples.paint.push(millis(started));
+        samples.total.push(millis(load_started));
+
+        for (index, (node_id, target)) in loaded.click_targets().into_iter().enumerate() {
+            let latency = loaded.click_to_paint(node_id)?;
+            match samples.interactions.get_mut(index) {
+                Some((_, latencies)) => latencies.push(latency),
+                None => samples.interactions.push((target, vec![latency])),
+            }
+        }
+    }
+
+    Ok(PageReport {
+        page: page.display().to_string(),
+        phases: PhaseTimes {
+            resolve: Stats::of(&samples.resolve),
+            fetch: Stats::of(&samples.fetch),
+            parse: Stats::of(&samples.parse),
+            scripts: Stats::of(&samples.scripts),
+            layout: Stats::of(&samples.layout),
+            paint: Stats::of(&samples.paint),
+            total: Stats::of(&samples.total),
+        },
+        interactions: samples
+            .interactions
+            .into_iter()
+            .map(|(target, latencies)| InteractionReport {
+                target,
+                latency: Stats::of(&latencies),
+            })
+            .collect(),
+    })
+}
+
+/// A parsed page with its scripts run, if it has any.
+struct Loaded {
+    runtime: Option<JsPageRuntime>,
+    /// A [`RuntimeDocument`] when there is a runtime, otherwise the [`HtmlDocument`].
+    document: Box<dyn DerefMut<Target = BaseDocument>>,
+    width: u32,
+    height: u32,
+}
+
+impl Loaded {
+    fn new(
+        html_doc: HtmlDocument,
+        fetched: &FetchedDocument,
+        options: &BenchOptions,
+    ) -> Result<Self> {
+        let runtime = JsPageRuntime::for_document(fetched).context("create js runtime")?;
+        let Some(mut runtime) = runtime else {
+            return Ok(Self {
+                runtime: None,
+                document: Box::new(html_doc),
+                width: options.width,
+                height: options.height,
+            });
+        };
+        let document = RuntimeDocument::attach(html_doc, &mut runtime);
+        runtime
+            .run_blocking_scripts()
+            .context("execute blocking scripts")?;
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
+        runtime.environment().pump().context("pump after load")?;
+        Ok(Self {
+            runtime: Some(runtime),
+            document,
+            width: options.width,
+            height: options.height,
+        })
+    }
+
+    fn layout(&mut self) {
+        self.document.set_viewport(Viewport::new(
+            self.width,
+            self.height,
+            1.0,
+            ColorScheme::Light,
+        ));
+        self.document.resolve();
+    }
+
+    fn paint(&self) -> Result<()> {
+        screenshot::capture(&self.document, 1.0, self.width, self.height)?;
+        Ok(())
+    }
+
+    /// The elements to click, with the name each is reported under.
+    fn click_targets(&mut self) -> Vec<(usize, String)> {
+        if self.runtime.is_none() {
+            return Vec::new();
+        }
+        let mut targets = Vec::new();
+        let document: &mut BaseDocument = &mut *self.document;
+        let root = document.root_node().id;
+        document.iter_subtree_mut(root, |node_id, doc| {
+            let Some(element) = doc.get_node(node_id).and_then(|node| node.element_data()) else {
+                return;
+            };
+            let value = element
+                .attrs
+                .iter()
+                .find(|attr| &*attr.name.local == CLICK_ATTRIBUTE)
+                .map(|attr| attr.value.to_string());
+            if let Some(value) = value {
+                let name = if value.is_empty() {
+                    format!("target-{}", targets.len())
+                } else {
+                    value
+                };
+                targets.push((node_id, name));
+            }
+        });
+        targets
+    }
+
+    /// Click `node_id`, run what the click queued, and lay out and paint the result.
+    fn click_to_paint(&mut self, node_id: usize) -> Result<f64> {
+        let Some(runtime) = &self.runtime else {
+            return Ok(0.0);
+        };
+        let started = Instant::now();
+        let chain = self.document.node_chain(node_id);
+        let event = DomEvent::new(
+            node_id,
+            DomEventData::Click(BlitzMouseButtonEvent {
+                x: 0.0,
+                y: 0.0,
+                button: MouseEventButton::Main,
+                buttons: MouseEventButtons::Primary,
+                mods: Default::default(),
+            }),
+        );
+        let environment = runtime.environment();
+        environment
+            .dispatch_dom_event(&event, &chain)
+            .context("dispatch click")?;
+        environment.pump().context("pump after click")?;
+        self.document.resolve();
+        self.paint()?;
+        Ok(millis(started))
+    }
+}
+
+fn millis(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_take_the_middle_of_sorted_samples() {
+        let odd = Stats::of(&[9.0, 1.0, 5.0]);
+        assert_eq!((odd.median_ms, odd.min_ms, odd.max_ms), (5.0, 1.0, 9.0));
+        assert_eq!(odd.mean_ms, 5.0);
+
+        let even = Stats::of(&[4.0, 1.0, 2.0, 3.0]);
+        assert_eq!((even.samples, even.median_ms), (4, 2.5));
+        assert_eq!(Stats::of(&[]).samples, 0);
+    }
+
+    #[test]
+    fn options_default_to_the_bundled_pages() {
+        let options = BenchOptions::from_args(&["--iterations".into(), "2".into()]).unwrap();
+        assert_eq!(options.iterations, 2);
+        assert!(options
+            .pages
+            .iter()
+            .any(|page| page.ends_with("counter.html")));
+        assert!(BenchOptions::from_args(&["--iterations".into(), "0".into()]).is_err());
+        assert!(BenchOptions::from_args(&["--frames".into()]).is_err());
+    }
+}
@@ -1,26 +1,39 @@
 #![allow(clippy::disallowed_types)]
 
-use std::io::ErrorKind;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
 use std::net::SocketAddr;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use axum::{
-    extract::{Path as AxumPath, Query, State},
-    http::StatusCode,
+    extract::{Path as AxumPath, Query, Request, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        StatusCode,
+    },
+    middleware::{self, Next},
+    response::Response,
     routing::{get, post},
     Json, Router,
 };
 use frontier::automation::full_app::{AutomationState, AutomationTask};
 use frontier::automation::{
     AutomationCommand, AutomationEvent, AutomationResponse, AutomationResult,
-    AutomationStateHandle, ElementSelector, KeyboardAction, PointerAction,
+    AutomationStateHandle, ElementSelector, Emulation, KeyboardAction, PointerAction,
+    PointerTarget,
 };
 use frontier::{create_default_event_loop, wrap_with_url_bar, ReadmeApplication};
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
+use tokio_rustls::TlsAcceptor;
 use tracing::info;
 use url::Url;
 use winit::event_loop::EventLoopProxy;
@@ -29,9 +42,17 @@ use blitz_net::Provider;
 use blitz_shell::{BlitzShellEvent, BlitzShellNetCallback, WindowConfig};
 use blitz_traits::navigation::{NavigationOptions, NavigationProvider};
 use frontier::navigation::{execute_fetch, prepare_navigation, FetchedDocument, NavigationPlan};
+use frontier::print::{self, PrintOptions};
+use frontier::recording::{RecordedAction, Recording, RECORDING_VERSION};
+use frontier::screenshot::CompareThresholds;
+use frontier::search::SearchEngine;
 use frontier::WindowRenderer;
 
 const SESSION_ID: &str = "frontier";
+/// How long a replay waits for loads and `fetch()` requests before running the next step
+/// anyway, e.g. on a page that keeps a long poll open.
+const REPLAY_SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 #[derive(Clone)]
 struct HostState {
@@ -78,6 +99,17 @@ impl HostState {
 struct CreateSessionPayload {
     url: Option<String>,
     file: Option<String>,
+    /// Collect script coverage from the first page on.
+    #[serde(default)]
+    coverage: bool,
+}
+
+#[derive(Deserialize)]
+struct PdfPayload {
+    url: Option<String>,
+    file: Option<String>,
+    #[serde(flatten)]
+    options: PrintOptions,
 }
 
 #[derive(Serialize)]
@@ -96,6 +128,13 @@ struct TypePayload {
     text: String,
 }
 
+#[derive(Deserialize)]
+struct FilesPayload {
+    selector: ElementSelector,
+    /// Paths relative to the asset root; absolute paths and `..` are refused.
+    paths: Vec<PathBuf>,
+}
+
 #[derive(Deserialize)]
 struct PumpPayload {
     milliseconds: u64,
@@ -125,6 +164,37 @@ struct ExistsResponse {
     exists: bool,
 }
 
+#[derive(Deserialize)]
+struct ConsoleQuery {
+    #[serde(default = "default_console_limit")]
+    limit: usize,
+}
+
+fn default_console_limit() -> usize {
+    100
+}
+
+#[derive(Deserialize)]
+struct HarQuery {
+    /// Include response bodies.
+    #[serde(default)]
+    content: bool,
+}
+
+#[derive(Deserialize)]
+struct ComparePayload {
+    /// Baseline PNG, relative to the asset root; absolute paths and `..` are refused.
+    baseline: PathBuf,
+    #[serde(default)]
+    thresholds: CompareThresholds,
+    /// Replace the baseline with the capture instead of comparing.
+    #[serde(default)]
+    update: bool,
+    /// Compare only this element's part of the window.
+    #[serde(default)]
+    selector: Option<ElementSelector>,
+}
+
 #[derive(Deserialize)]
 struct PointerPayload {
     actions: Vec<PointerAction>,
@@ -135,7 +205,32 @@ struct KeyboardPayload {
     actions: Vec<KeyboardAction>,
 }
 
+#[derive(Deserialize)]
+struct CookiePayload {
+    /// Written like a `Set-Cookie` header value, e.g. `session=abc; Path=/; HttpOnly`.
+    cookie: String,
+}
+
+#[derive(Deserialize)]
+struct StoragePayload {
+    items: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ViewportPayload {
+    width: f64,
+    height: f64,
+}
+
 impl TextQuery {
+    /// The selector, or `None` when the query names none at all.
+    fn into_optional_selector(self) -> Result<Option<ElementSelector>, StatusCode> {
+        if self.kind.is_none() && self.selector.is_none() && self.role.is_none() {
+            return Ok(None);
+        }
+        self.into_selector().map(Some)
+    }
+
     fn into_selector(self) -> Result<ElementSelector, StatusCode> {
         match self.kind.as_deref() {
             Some("css") => self
@@ -163,7 +258,11 @@ impl TextQuery {
 fn main() -> Result<()> {
     setup_tracing();
 
-    let config = HostConfig::from_env()?;
+    let config = HostConfig::load()?;
+    let security = ServerSecurity {
+        auth_token: config.auth_token.clone(),
+        tls: config.tls.as_ref().map(tls_acceptor).transpose()?,
+    };
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -191,6 +290,7 @@ fn main() -> Result<()> {
     runtime.spawn(start_http_server(
         config.bind_addr,
         host_state.clone(),
+        security,
         server_ready_tx,
     ));
     let bound_addr = runtime
@@ -203,7 +303,10 @@ fn main() -> Result<()> {
     let net_provider = Arc::new(Provider::new(net_callback));
 
     let initial_plan = runtime
-        .block_on(prepare_navigation(&config.initial_target))
+        .block_on(prepare_navigation(
+            &config.initial_target,
+            &SearchEngine::default(),
+        ))
         .context("prepare initial navigation")?;
 
     let initial_document = match initial_plan {
@@ -270,35 +373,215 @@ struct HostConfig {
     initial_target: String,
     asset_root: PathBuf,
     artifact_root: PathBuf,
+    /// Bearer token every request must carry; `None` leaves the host open.
+    auth_token: Option<String>,
+    tls: Option<TlsFiles>,
 }
 
-impl HostConfig {
-    fn from_env() -> Result<Self> {
-        let bind_addr = std::env::var("AUTOMATION_BIND")
-            .unwrap_or_else(|_| "127.0.0.1:0".into())
-            .parse::<SocketAddr>()
-            .context("parse AUTOMATION_BIND")?;
+struct TlsFiles {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+/// Settings read from `--config <path>` or `AUTOMATION_CONFIG`. Relative paths are resolved
+/// against the file's directory, and the `AUTOMATION_*` variables override every key.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HostConfigFile {
+    bind: Option<SocketAddr>,
+    initial: Option<String>,
+    asset_root: Option<PathBuf>,
+    artifact_root: Option<PathBuf>,
+    auth_token: Option<String>,
+    /// Read the token from this file instead, so it stays out of the config.
+    auth_token_file: Option<PathBuf>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    /// Serve other machines without a token, which is refused otherwise.
+    allow_unauthenticated: Option<bool>,
+}
+
+impl HostConfigFile {
+    fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("read automation config {}", path.display()))?;
+        let mut file: Self = toml::from_str(&raw)
+            .with_context(|| format!("parse automation config {}", path.display()))?;
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        for path in [
+            &mut file.asset_root,
+            &mut file.artifact_root,
+            &mut file.auth_token_file,
+            &mut file.tls_cert,
+            &mut file.tls_key,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+        Ok(file)
+    }
+}
 
-        let initial_target =
-            std::env::var("AUTOMATION_INITIAL").unwrap_or_else(|_| "about:blank".into());
+impl HostConfig {
+    fn load() -> Result<Self> {
+        let mut args = std::env::args().skip(1);
+        let mut config_path = std::env::var_os("AUTOMATION_CONFIG").map(PathBuf::from);
+        let mut allow_unauthenticated = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    config_path =
+                        Some(PathBuf::from(args.next().context("--config needs a path")?));
+                }
+                "--allow-unauthenticated" => allow_unauthenticated = true,
+                other => return Err(anyhow!("unknown argument {other}")),
+            }
+        }
+        let file = match config_path {
+            Some(path) => HostConfigFile::load(&path)?,
+            None => HostConfigFile::default(),
+        };
+        let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let bind_addr = match env("AUTOMATION_BIND") {
+            Some(bind) => bind.parse().context("parse AUTOMATION_BIND")?,
+            None => file
+                .bind
+                .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], 0))),
+        };
+        let auth_token = match (
+            env("AUTOMATION_TOKEN"),
+            file.auth_token,
+            file.auth_token_file,
+        ) {
+            (Some(token), _, _) | (None, Some(token), _) => Some(token),
+            (None, None, Some(path)) => Some(
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("read auth token {}", path.display()))?
+                    .trim()
+                    .to_string(),
+            ),
+            (None, None, None) => None,
+        };
+        if auth_token.as_deref() == Some("") {
+            return Err(anyhow!("automation auth token is empty"));
+        }
+        let tls_cert = env("AUTOMATION_TLS_CERT")
+            .map(PathBuf::from)
+            .or(file.tls_cert);
+        let tls_key = env("AUTOMATION_TLS_KEY")
+            .map(PathBuf::from)
+            .or(file.tls_key);
+        let tls = match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            (None, None) => None,
+            _ => return Err(anyhow!("TLS needs both a certificate and a private key")),
+        };
+        let allow_unauthenticated = allow_unauthenticated
+            || env("AUTOMATION_ALLOW_UNAUTHENTICATED").is_some_and(|value| value != "0")
+            || file.allow_unauthenticated.unwrap_or(false);
+        check_exposure(bind_addr, auth_token.as_deref(), allow_unauthenticated)?;
 
         Ok(Self {
             bind_addr,
-            initial_target,
-            asset_root: std::env::var("AUTOMATION_ASSET_ROOT")
+            initial_target: env("AUTOMATION_INITIAL")
+                .or(file.initial)
+                .unwrap_or_else(|| "about:blank".into()),
+            asset_root: env("AUTOMATION_ASSET_ROOT")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets")),
-            artifact_root: std::env::var("AUTOMATION_ARTIFACT_ROOT")
+                .or(file.asset_root)
+                .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets")),
+            artifact_root: env("AUTOMATION_ARTIFACT_ROOT")
                 .map(PathBuf::from)
-                .unwrap_or_else(|_| {
+                .or(file.artifact_root)
+                .unwrap_or_else(|| {
                     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
                         .join("target")
                         .join("automation-artifacts")
                 }),
+            auth_token,
+            tls,
         })
     }
 }
 
+/// Refuse to serve other machines without a token unless the user opted in, in which case
+/// only warn.
+fn check_exposure(
+    bind_addr: SocketAddr,
+    auth_token: Option<&str>,
+    allow_unauthenticated: bool,
+) -> Result<()> {
+    if bind_addr.ip().is_loopback() || auth_token.is_some() {
+        return Ok(());
+    }
+    if !allow_unauthenticated {
+        return Err(anyhow!(
+            "refusing to serve {bind_addr} without an auth token; set one, or pass \
+             --allow-unauthenticated to let anyone who can reach it drive the browser"
+        ));
+    }
+    tracing::warn!(
+        target = "automation_host",
+        %bind_addr,
+        "automation host is reachable from other machines without an auth token"
+    );
+    Ok(())
+}
+
+/// Server-side TLS from PEM files.
+fn tls_acceptor(files: &TlsFiles) -> Result<TlsAcceptor> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .with_context(|| format!("open {}", path.display()))
+    };
+    let certs = rustls_pemfile::certs(&mut open(&files.cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parse certificates in {}", files.cert.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates in {}", files.cert.display()));
+    }
+    let key = rustls_pemfile::private_key(&mut open(&files.key)?)
+        .with_context(|| format!("parse private key in {}", files.key.display()))?
+        .ok_or_else(|| anyhow!("no private key in {}", files.key.display()))?;
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::aws_lc_rs::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .context("TLS protocol versions")?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .context("TLS certificate and key do not match")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Reject requests without `Authorization: Bearer <token>`.
+async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 struct MainNavigationProvider {
     proxy: EventLoopProxy<BlitzShellEvent>,
 }
@@ -311,9 +594,16 @@ impl NavigationProvider for MainNavigationProvider {
     }
 }
 
+/// Who may talk to the HTTP server, and how.
+struct ServerSecurity {
+    auth_token: Option<String>,
+    tls: Option<TlsAcceptor>,
+}
+
 async fn start_http_server(
     bind_addr: SocketAddr,
     host_state: HostState,
+    security: ServerSecurity,
     ready_tx: oneshot::Sender<Result<SocketAddr>>,
 ) {
     let listener = match TcpListener::bind(bind_addr).await {
@@ -338,10 +628,92 @@ async fn start_http_server(
         .route("/session/:id/keyboard", post(keyboard_sequence))
         .route("/session/:id/focus", post(focus_element))
         .route("/session/:id/scroll", post(scroll_element))
+        .route("/session/:id/files", post(set_input_files))
+        .route("/session/:id/viewport", post(set_viewport))
+        .route(
+            "/session/:id/emulate",
+            post(emulate).delete(reset_emulation),
+        )
+        .route("/session/:id/accessibility", get(accessibility_tree))
+        .route("/session/:id/network", get(network_log))
+        .route("/session/:id/console", get(console_log))
+        .route("/session/:id/frames", get(frame_stats))
+        .route("/session/:id/damage", get(damage))
+        .route("/session/:id/memory", get(memory_report))
+        .route("/session/:id/popups", get(popups))
+        .route("/session/:id/coverage", get(coverage))
+        .route("/session/:id/har", get(har_export))
+        .route("/session/:id/rect", get(element_rect))
+        .route("/session/:id/screenshot", get(screenshot))
+        .route("/session/:id/screenshot/compare", post(compare_screenshot))
+        .route("/session/:id/pdf", post(export_pdf))
+        .route(
+            "/session/:id/cookies",
+            get(cookies).post(set_cookie).delete(clear_cookies),
+        )
+        .route(
+            "/session/:id/storage",
+            get(local_storage)
+                .post(set_local_storage)
+                .delete(clear_local_storage),
+        )
+        .route("/session/:id/replay", post(replay_recording))
         .with_state(host_state);
+    let app = match security.auth_token {
+        Some(token) => app.layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_token,
+        )),
+        None => app,
+    };
 
-    if let Err(err) = axum::serve(listener, app).await {
-        tracing::error!(target = "automation_host", error = %err, "server error");
+    match security.tls {
+        Some(acceptor) => serve_tls(listener, acceptor, app).await,
+        None => {
+            if let Err(err) = axum::serve(listener, app).await {
+                tracing::error!(target = "automation_host", error = %err, "server error");
+            }
+        }
+    }
+}
+
+/// Accept TLS connections and hand each one to `app` over HTTP/1.1.
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                tracing::error!(target = "automation_host", error = %err, "accept failed");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    tracing::warn!(
+                        target = "automation_host",
+                        %peer,
+                        error = %err,
+                        "TLS handshake failed"
+                    );
+                    return;
+                }
+            };
+            if let Err(err) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(
+                    target = "automation_host",
+                    %peer,
+                    error = %err,
+                    "connection closed"
+                );
+            }
+        });
     }
 }
 
@@ -357,6 +729,12 @@ async fn create_session(
         *guard = true;
     }
 
+    if payload.coverage {
+        send_command(&state, AutomationCommand::RecordCoverage)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
     if let Some(target) = resolve_target(&state.asset_root, payload.url, payload.file)? {
         send_command(&state, AutomationCommand::Navigate { target })
             .await
@@ -498,6 +876,71 @@ async fn scroll_element(
     Ok(StatusCode::NO_CONTENT)
 }
 
+async fn set_input_files(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<FilesPayload>,
+) -> Result<StatusCode, StatusCode> {
+    let paths = payload
+        .paths
+        .iter()
+        .map(|path| asset_path(&state.asset_root, path))
+        .collect::<Result<_, _>>()?;
+    send_command(
+        &state,
+        AutomationCommand::SetInputFiles {
+            selector: payload.selector,
+            paths,
+        },
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_viewport(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<ViewportPayload>,
+) -> Result<StatusCode, StatusCode> {
+    if !(payload.width >= 1.0 && payload.height >= 1.0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    send_command(
+        &state,
+        AutomationCommand::SetViewport {
+            width: payload.width,
+            height: payload.height,
+        },
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Emulate another device for the rest of the session; a `400` names the field it rejected.
+async fn emulate(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(emulation): Json<Emulation>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, AutomationCommand::Emulate { emulation })
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stop emulating: the page sees the window's own traits again.
+async fn reset_emulation(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, AutomationCommand::ResetEmulation)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 fn command_label(command: &AutomationCommand) -> &'static str {
     match command {
         AutomationCommand::Click { .. } => "click",
@@ -510,6 +953,32 @@ fn command_label(command: &AutomationCommand) -> &'static str {
         AutomationCommand::KeyboardSequence { .. } => "keyboard",
         AutomationCommand::Focus { .. } => "focus",
         AutomationCommand::ScrollIntoView { .. } => "scroll",
+        AutomationCommand::SetInputFiles { .. } => "files",
+        AutomationCommand::AccessibilityTree => "accessibility",
+        AutomationCommand::SetViewport { .. } => "viewport",
+        AutomationCommand::NetworkLog => "network",
+        AutomationCommand::ConsoleLog { .. } => "console",
+        AutomationCommand::FrameStats => "frames",
+        AutomationCommand::Damage => "damage",
+        AutomationCommand::MemoryReport => "memory",
+        AutomationCommand::Popups => "popups",
+        AutomationCommand::RecordCoverage => "record_coverage",
+        AutomationCommand::Coverage => "coverage",
+        AutomationCommand::Har { .. } => "har",
+        AutomationCommand::CompareScreenshot { .. } => "compare_screenshot",
+        AutomationCommand::ElementRect { .. } => "rect",
+        AutomationCommand::Screenshot { .. } => "screenshot",
+        AutomationCommand::Cookies => "cookies",
+        AutomationCommand::SetCookie { .. } => "set_cookie",
+        AutomationCommand::ClearCookies => "clear_cookies",
+        AutomationCommand::LocalStorage => "local_storage",
+        AutomationCommand::SetLocalStorage { .. } => "set_local_storage",
+        AutomationCommand::ClearLocalStorage => "clear_local_storage",
+        AutomationCommand::Emulate { .. } => "emulate",
+        AutomationCommand::ResetEmulation => "reset_emulation",
+        AutomationCommand::UseVirtualClock => "virtual_clock",
+        AutomationCommand::AdvanceClock { .. } => "advance_clock",
+        AutomationCommand::Busy => "busy",
         AutomationCommand::Shutdown => "shutdown",
     }
 }
@@ -532,6 +1001,14 @@ fn persist_artifacts(
                 if let Some(dom) = &artifacts.dom_html {
                     std::fs::write(path.join("dom.html"), dom)?;
                 }
+                for (name, png) in &artifacts.images {
+                    std::fs::write(path.join(name), png)?;
+                }
+            }
+            if let (AutomationCommand::Har { .. }, AutomationResponse::Text(archive)) =
+                (command, &reply.response)
+            {
+                std::fs::write(path.join("page.har"), archive)?;
             }
         }
         Err(err) => {
@@ -574,6 +1051,445 @@ async fn element_exists(
     Ok(Json(ExistsResponse { exists }))
 }
 
+async fn accessibility_tree(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::AccessibilityTree)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let tree = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(tree))
+}
+
+async fn network_log(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::NetworkLog)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let entries = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(entries))
+}
+
+/// The newest `?limit=` (default 100) console messages and uncaught exceptions.
+async fn console_log(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Query(query): Query<ConsoleQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    json_reply(&state, AutomationCommand::ConsoleLog { limit: query.limit }).await
+}
+
+async fn frame_stats(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::FrameStats)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let summary = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(summary))
+}
+
+async fn damage(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::Damage)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let summary = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(summary))
+}
+
+async fn memory_report(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::MemoryReport)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let report = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(report))
+}
+
+async fn popups(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::Popups)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let popups = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(popups))
+}
+
+/// Script coverage of the session, or 404 when it was not created with `coverage` set.
+async fn coverage(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let reply = send_command(&state, AutomationCommand::Coverage)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let report: serde_json::Value =
+        serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if report.is_null() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(report))
+}
+
+/// The page load as an HTTP Archive; the same archive is written to the command's artifact
+/// directory as `page.har`.
+async fn har_export(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Query(query): Query<HarQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let command = AutomationCommand::Har {
+        content: query.content,
+    };
+    let reply = send_command(&state, command)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let archive = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(archive))
+}
+
+async fn element_rect(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Query(query): Query<TextQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let selector = query.into_selector()?;
+    let reply = send_command(&state, AutomationCommand::ElementRect { selector })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let rect = serde_json::from_str(&json).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rect))
+}
+
+/// The view as a PNG, cropped to the element when the query names one.
+async fn screenshot(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Query(query): Query<TextQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let selector = query
+        .into_optional_selector()
+        .map_err(|status| (status, "invalid selector".to_string()))?;
+    let reply = send_command(&state, AutomationCommand::Screenshot { selector })
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+
+    let png = reply
+        .artifacts
+        .and_then(|artifacts| artifacts.images.into_iter().next())
+        .map(|(_, png)| png)
+        .ok_or_else(|| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "no screenshot captured".to_string(),
+            )
+        })?;
+    Response::builder()
+        .header(CONTENT_TYPE, "image/png")
+        .body(png.into())
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// Export a URL, or a file under the asset root, to PDF with its print styles. The page is
+/// loaded and laid out apart from the session's window, which is left as it is.
+async fn export_pdf(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<PdfPayload>,
+) -> Result<Response, (StatusCode, String)> {
+    let target = resolve_target(&state.asset_root, payload.url, payload.file)
+        .map_err(|status| (status, "invalid file path".to_string()))?
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "give a url or a file".to_string()))?;
+    let url = Url::parse(&target).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    // Laying out and painting every page is slow, so it stays off the server's worker threads.
+    let runtime = tokio::runtime::Handle::current();
+    let pdf = tokio::task::spawn_blocking(move || {
+        runtime.block_on(print::export_pdf(&url, &payload.options))
+    })
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+    Response::builder()
+        .header(CONTENT_TYPE, "application/pdf")
+        .body(pdf.into())
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// Cookies the current page would send, `HttpOnly` ones included.
+async fn cookies(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    json_reply(&state, AutomationCommand::Cookies).await
+}
+
+/// Store a cookie for the current page's URL; a `400` names why the jar rejected it.
+async fn set_cookie(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<CookiePayload>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let command = AutomationCommand::SetCookie {
+        cookie: payload.cookie,
+    };
+    send_command(&state, command)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn clear_cookies(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, AutomationCommand::ClearCookies)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The current page's origin's `localStorage` as an object.
+async fn local_storage(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    json_reply(&state, AutomationCommand::LocalStorage).await
+}
+
+async fn set_local_storage(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<StoragePayload>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let command = AutomationCommand::SetLocalStorage {
+        items: payload.items,
+    };
+    send_command(&state, command)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn clear_local_storage(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send_command(&state, AutomationCommand::ClearLocalStorage)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn json_reply(
+    state: &HostState,
+    command: AutomationCommand,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let reply = send_command(state, command)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "expected a JSON reply".to_string(),
+        ));
+    };
+    serde_json::from_str(&json)
+        .map(Json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+/// Compare the view against a golden image. A mismatch is still a `200` reply with
+/// `matched: false`; the capture and diff land in the command's artifact directory.
+async fn compare_screenshot(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(payload): Json<ComparePayload>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let baseline = asset_path(&state.asset_root, &payload.baseline).map_err(|status| {
+        (
+            status,
+            "baseline must be a relative path inside the asset root".to_string(),
+        )
+    })?;
+    let command = AutomationCommand::CompareScreenshot {
+        baseline,
+        thresholds: payload.thresholds,
+        update: payload.update,
+        selector: payload.selector,
+    };
+    let reply = send_command(&state, command)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))?;
+
+    let AutomationResponse::Text(json) = reply.response else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "unexpected reply".to_string(),
+        ));
+    };
+    let comparison = serde_json::from_str(&json)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    Ok(Json(comparison))
+}
+
+/// Replay a session recorded with `frontier --record`: restore its viewport and start URL,
+/// then run each step once the page has settled and its virtual clock has been advanced to
+/// the step's timestamp. Timers fire at the same points between steps however long loads
+/// take, so a replay does not depend on the speed of the machine or network. The waiting
+/// happens here rather than in `pump` commands, so the event loop keeps delivering page
+/// loads and redraws between steps.
+async fn replay_recording(
+    State(state): State<HostState>,
+    AxumPath(_id): AxumPath<String>,
+    Json(recording): Json<Recording>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if recording.version != RECORDING_VERSION {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "recording version {} is not supported (expected {RECORDING_VERSION})",
+                recording.version
+            ),
+        ));
+    }
+    let failed = |step: &str, err: anyhow::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("replay {step} failed: {err:#}"),
+        )
+    };
+    if let Some(viewport) = recording.viewport {
+        let command = AutomationCommand::SetViewport {
+            width: viewport.width,
+            height: viewport.height,
+        };
+        send_command(&state, command)
+            .await
+            .map_err(|err| failed("viewport", err))?;
+    }
+    send_command(&state, AutomationCommand::UseVirtualClock)
+        .await
+        .map_err(|err| failed("virtual clock", err))?;
+    send_command(
+        &state,
+        AutomationCommand::Navigate {
+            target: recording.start_url.clone(),
+        },
+    )
+    .await
+    .map_err(|err| failed("start navigation", err))?;
+
+    // Presses and releases target wherever the pointer last moved.
+    let mut cursor: Option<PointerTarget> = None;
+    for (index, step) in recording.steps.into_iter().enumerate() {
+        let label = format!("step {index} at {}ms", step.at_ms);
+        wait_until_settled(&state)
+            .await
+            .map_err(|err| failed(&label, err))?;
+        send_command(
+            &state,
+            AutomationCommand::AdvanceClock { to_ms: step.at_ms },
+        )
+        .await
+        .map_err(|err| failed(&label, err))?;
+        wait_until_settled(&state)
+            .await
+            .map_err(|err| failed(&label, err))?;
+        let command = match step.action {
+            RecordedAction::Pointer { action } => {
+                let actions = match action {
+                    PointerAction::Move { to } => {
+                        cursor = Some(to.clone());
+                        vec![PointerAction::Move { to }]
+                    }
+                    action @ (PointerAction::Down { .. } | PointerAction::Up { .. }) => cursor
+                        .clone()
+                        .map(|to| PointerAction::Move { to })
+                        .into_iter()
+                        .chain([action])
+                        .collect(),
+                    action => vec![action],
+                };
+                AutomationCommand::PointerSequence { actions }
+            }
+            RecordedAction::Keyboard { action } => AutomationCommand::KeyboardSequence {
+                actions: vec![action],
+            },
+            RecordedAction::Navigate { url } => AutomationCommand::Navigate { target: url },
+        };
+        send_command(&state, command)
+            .await
+            .map_err(|err| failed(&label, err))?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Wait until no page load or `fetch()` is in flight, or [`REPLAY_SETTLE_TIMEOUT`] passed.
+async fn wait_until_settled(state: &HostState) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + REPLAY_SETTLE_TIMEOUT;
+    loop {
+        let reply = send_command(state, AutomationCommand::Busy).await?;
+        if matches!(reply.response, AutomationResponse::Bool(false)) {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            eprintln!(
+                "replay: page still loading after {}s; continuing",
+                REPLAY_SETTLE_TIMEOUT.as_secs()
+            );
+            return Ok(());
+        }
+        tokio::time::sleep(REPLAY_POLL_INTERVAL).await;
+    }
+}
+
 async fn send_command(state: &HostState, command: AutomationCommand) -> AutomationResult {
     eprintln!("AUTOMATION_CMD queue {:?}", command);
     let label = command_label(&command);
@@ -627,6 +1543,19 @@ fn resolve_target(
     Ok(None)
 }
 
+/// `path` under `asset_root`, refusing absolute paths and `..` so a client cannot read or
+/// overwrite files outside it.
+fn asset_path(asset_root: &Path, path: impl AsRef<Path>) -> Result<PathBuf, StatusCode> {
+    let path = path.as_ref();
+    let contained = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !contained {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(asset_root.join(path))
+}
+
 fn fallback_document(target: &str) -> FetchedDocument {
     let content = "<main id=\"content\"></main>";
     let wrapped = wrap_with_url_bar(content, target, None);
@@ -636,5 +1565,40 @@ fn fallback_document(target: &str) -> FetchedDocument {
         file_path: None,
         display_url: target.to_string(),
         scripts: Vec::new(),
+        csp_headers: Vec::new(),
+        response: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_paths_stay_under_the_asset_root() {
+        let root = Path::new("/srv/assets");
+        assert_eq!(
+            asset_path(root, "golden/./form.png"),
+            Ok(root.join("golden/./form.png"))
+        );
+        assert_eq!(
+            asset_path(root, "/etc/passwd"),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            asset_path(root, "golden/../../etc/passwd"),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(asset_path(root, ".."), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn open_hosts_beyond_loopback_need_an_opt_in() {
+        let lan = SocketAddr::from(([0, 0, 0, 0], 4444));
+        let error = check_exposure(lan, None, false).unwrap_err().to_string();
+        assert!(error.contains("--allow-unauthenticated"), "{error}");
+        assert!(check_exposure(lan, None, true).is_ok());
+        assert!(check_exposure(lan, Some("secret"), false).is_ok());
+        assert!(check_exposure(SocketAddr::from(([127, 0, 0, 1], 0)), None, false).is_ok());
     }
 }
@@ -1,4 +1,66 @@
 pub fn wrap_with_url_bar(content: &str, display_url: &str, overlay_html: Option<&str>) -> String {
+    wrap_with_chrome(content, display_url, overlay_html, None, None, None)
+}
+
+/// [`wrap_with_url_bar`] plus the account switcher, which shows the identity the page acts as
+/// and opens `frontier://identities`, `menu_html`, the application menu on platforms without
+/// a native menu bar, and `site_info_html`, the site settings dropdown before the URL.
+pub fn wrap_with_chrome(
+    content: &str,
+    display_url: &str,
+    overlay_html: Option<&str>,
+    identity_label: Option<&str>,
+    menu_html: Option<&str>,
+    site_info_html: Option<&str>,
+) -> String {
+    let identity = html_escape::encode_text(identity_label.unwrap_or("No identity"));
+    let navigation = format!(
+        r#"<nav id="url-bar-container" role="navigation" aria-label="Browser navigation">
+        <button id="back-button" class="nav-button" title="Back" aria-label="Go back" type="button">&larr;</button>
+        <button id="forward-button" class="nav-button" title="Forward" aria-label="Go forward" type="button">&rarr;</button>
+        {site_info}
+        <form id="url-form" style="display: flex; flex: 1; gap: 8px;" role="search">
+            <label for="url-input" class="sr-only" style="position: absolute; left: -10000px;">
+                Enter website URL
+            </label>
+            <input
+                type="url"
+                id="url-input"
+                name="url"
+                value="{display_url}"
+                autofocus
+                aria-label="Website URL address bar"
+                placeholder="Enter URL..."
+                required
+            />
+            <input
+                type="submit"
+                id="go-button"
+                value="Go"
+                aria-label="Navigate to URL"
+            />
+        </form>
+        <a id="identity-button" href="frontier://identities" title="Switch identity" aria-label="Current identity: {identity}">{identity}</a>
+        {menu}
+    </nav>"#,
+        menu = menu_html.unwrap_or(""),
+        site_info = site_info_html.unwrap_or(""),
+    );
+    wrap_page(content, display_url, overlay_html, &navigation, "")
+}
+
+/// The page without the URL bar, for kiosk mode: nothing to navigate with but the page itself.
+pub fn wrap_for_kiosk(content: &str, display_url: &str, overlay_html: Option<&str>) -> String {
+    wrap_page(content, display_url, overlay_html, "", "kiosk")
+}
+
+fn wrap_page(
+    content: &str,
+    display_url: &str,
+    overlay_html: Option<&str>,
+    navigation: &str,
+    body_class: &str,
+) -> String {
     format!(
         r#"<!DOCTYPE html>
 <html lang="en">
@@ -107,6 +169,123 @@ pub fn wrap_with_url_bar(content: &str, display_url: &str, overlay_html: Option<
             background: #2c974b;
         }}
 
+        #identity-button {{
+            height: 34px;
+            max-width: 160px;
+            padding: 0 12px;
+            border: 1px solid #d0d7de;
+            border-radius: 6px;
+            background: white;
+            color: #24292f;
+            font-size: 13px;
+            line-height: 34px;
+            white-space: nowrap;
+            overflow: hidden;
+            text-overflow: ellipsis;
+            text-decoration: none;
+        }}
+
+        #app-menu {{
+            position: relative;
+        }}
+
+        #app-menu-items {{
+            display: none;
+            position: absolute;
+            top: 32px;
+            right: 0;
+            min-width: 220px;
+            max-width: 360px;
+            padding: 4px 0;
+            background: white;
+            border: 1px solid #d0d7de;
+            border-radius: 6px;
+            box-shadow: 0 8px 24px rgba(140, 149, 159, 0.2);
+        }}
+
+        #app-menu:hover #app-menu-items {{
+            display: block;
+        }}
+
+        #app-menu-items a {{
+            display: block;
+            padding: 4px 12px;
+            color: #24292f;
+            font-size: 13px;
+            text-decoration: none;
+            white-space: nowrap;
+            overflow: hidden;
+            text-overflow: ellipsis;
+        }}
+
+        #app-menu-items a:hover {{
+            background: #0969da;
+            color: white;
+        }}
+
+        #site-info {{
+            position: relative;
+        }}
+
+        #blocked-count {{
+            margin-left: 2px;
+            padding: 0 4px;
+            border-radius: 8px;
+            background: #cf222e;
+            color: white;
+            font-size: 10px;
+        }}
+
+        #blocked-count:empty {{
+            display: none;
+        }}
+
+        #site-info-items {{
+            display: none;
+            position: absolute;
+            top: 32px;
+            left: 0;
+            min-width: 240px;
+            padding: 4px 0;
+            background: white;
+            border: 1px solid #d0d7de;
+            border-radius: 6px;
+            box-shadow: 0 8px 24px rgba(140, 149, 159, 0.2);
+        }}
+
+        #site-info:hover #site-info-items {{
+            display: block;
+        }}
+
+        #site-info-items a {{
+            display: block;
+            padding: 4px 12px;
+            color: #24292f;
+            font-size: 13px;
+            text-decoration: none;
+            white-space: nowrap;
+        }}
+
+        #site-info-items a:hover {{
+            background: #0969da;
+            color: white;
+        }}
+
+        .site-info-note {{
+            margin: 4px 0 0;
+            padding: 4px 12px;
+            color: #57606a;
+            font-size: 12px;
+        }}
+
+        .app-menu-heading {{
+            margin: 4px 0 0;
+            padding: 4px 12px 2px;
+            color: #57606a;
+            font-size: 12px;
+            font-weight: 600;
+        }}
+
         
         #nns-overlay {{
             position: fixed;
@@ -204,34 +383,191 @@ pub fn wrap_with_url_bar(content: &str, display_url: &str, overlay_html: Option<
             margin-top: 50px;
             padding: 20px;
         }}
+
+        body.kiosk #content {{
+            margin-top: 0;
+        }}
+
+        #network-panel {{
+            position: fixed;
+            left: 0;
+            right: 0;
+            bottom: 0;
+            max-height: 40%;
+            overflow-y: auto;
+            background: #ffffff;
+            border-top: 1px solid #d0d7de;
+            box-shadow: 0 -4px 12px rgba(15, 23, 42, 0.12);
+            font-size: 12px;
+            z-index: 1100;
+        }}
+
+        #network-panel header {{
+            padding: 6px 12px;
+            background: #f6f8fa;
+            border-bottom: 1px solid #d0d7de;
+            font-weight: 600;
+        }}
+
+        #network-panel table {{
+            width: 100%;
+            border-collapse: collapse;
+        }}
+
+        #network-panel th,
+        #network-panel td {{
+            padding: 3px 12px;
+            text-align: left;
+            border-bottom: 1px solid #eaeef2;
+            white-space: nowrap;
+        }}
+
+        #network-panel .network-url {{
+            font-family: "SFMono-Regular", Consolas, "Liberation Mono", Menlo, monospace;
+            white-space: normal;
+            word-break: break-all;
+        }}
+
+        #frame-overlay {{
+            position: fixed;
+            top: 8px;
+            right: 8px;
+            padding: 6px 10px;
+            background: rgba(15, 23, 42, 0.85);
+            color: #e2e8f0;
+            border-radius: 6px;
+            font-family: "SFMono-Regular", Consolas, "Liberation Mono", Menlo, monospace;
+            font-size: 11px;
+            z-index: 1200;
+        }}
+
+        #frame-overlay .frame-fps {{
+            font-size: 16px;
+            font-weight: 600;
+        }}
+
+        #damage-overlay .damage-rect {{
+            position: fixed;
+            box-sizing: border-box;
+            border: 1px solid rgba(220, 38, 38, 0.9);
+            background: rgba(220, 38, 38, 0.15);
+            pointer-events: none;
+            z-index: 1150;
+        }}
+
+        #damage-stats {{
+            position: fixed;
+            bottom: 8px;
+            right: 8px;
+            padding: 6px 10px;
+            background: rgba(127, 29, 29, 0.85);
+            color: #fee2e2;
+            border-radius: 6px;
+            font-family: "SFMono-Regular", Consolas, "Liberation Mono", Menlo, monospace;
+            font-size: 11px;
+            z-index: 1200;
+        }}
+
+        #form-widget {{
+            position: absolute;
+            max-height: 320px;
+            overflow-y: auto;
+            background: white;
+            color: #24292f;
+            border: 1px solid #d0d7de;
+            border-radius: 6px;
+            box-shadow: 0 8px 24px rgba(140, 149, 159, 0.2);
+            font-size: 14px;
+            z-index: 1300;
+        }}
+
+        #form-widget .widget-option {{
+            padding: 4px 12px;
+            white-space: nowrap;
+            cursor: default;
+        }}
+
+        #form-widget .widget-group {{
+            padding: 4px 8px;
+            color: #57606a;
+            font-weight: 600;
+        }}
+
+        #form-widget .widget-group ~ .widget-option {{
+            padding-left: 20px;
+        }}
+
+        #form-widget .selected {{
+            font-weight: 600;
+        }}
+
+        #form-widget .highlighted,
+        #form-widget .widget-option:hover,
+        #form-widget .date-day:hover {{
+            background: #0969da;
+            color: white;
+        }}
+
+        #form-widget .disabled {{
+            color: #8c959f;
+            background: transparent;
+        }}
+
+        #form-widget.date-popup {{
+            padding: 8px;
+        }}
+
+        #form-widget .date-header {{
+            display: flex;
+            align-items: center;
+            justify-content: space-between;
+            margin-bottom: 6px;
+            font-weight: 600;
+        }}
+
+        #form-widget .date-nav {{
+            padding: 0 8px;
+            cursor: pointer;
+        }}
+
+        #form-widget .date-grid {{
+            display: grid;
+            grid-template-columns: repeat(7, 32px);
+            text-align: center;
+            line-height: 28px;
+        }}
+
+        #form-widget .date-weekday {{
+            color: #57606a;
+            font-size: 12px;
+        }}
+
+        #form-widget .date-day {{
+            border-radius: 4px;
+            cursor: default;
+        }}
+
+        iframe {{
+            display: inline-block;
+            width: 300px;
+            height: 150px;
+            overflow: auto;
+            border: 2px inset #d0d7de;
+            vertical-align: bottom;
+        }}
+
+        #chrome-notice {{
+            margin: -20px -20px 16px;
+            padding: 8px 20px;
+            background: #fff8c5;
+            border-bottom: 1px solid #d4a72c;
+            color: #4d2d00;
+            font-size: 13px;
+        }}
     </style>
 </head>
-<body>
-    <nav id="url-bar-container" role="navigation" aria-label="Browser navigation">
-        <button id="back-button" class="nav-button" title="Back" aria-label="Go back" type="button">&larr;</button>
-        <button id="forward-button" class="nav-button" title="Forward" aria-label="Go forward" type="button">&rarr;</button>
-        <form id="url-form" style="display: flex; flex: 1; gap: 8px;" role="search">
-            <label for="url-input" class="sr-only" style="position: absolute; left: -10000px;">
-                Enter website URL
-            </label>
-            <input
-                type="url"
-                id="url-input"
-                name="url"
-                value="{display_url}"
-                autofocus
-                aria-label="Website URL address bar"
-                placeholder="Enter URL..."
-                required
-            />
-            <input
-                type="submit"
-                id="go-button"
-                value="Go"
-                aria-label="Navigate to URL"
-            />
-        </form>
-    </nav>
+<body class="{body_class}">
+    {navigation}
     <main id="content" role="main" aria-label="Page content">
         {content}
     </main>
@@ -278,6 +614,6 @@ pub fn wrap_with_url_bar(content: &str, display_url: &str, overlay_html: Option<
 </html>"#,
         display_url = display_url,
         content = content,
-        overlay = overlay_html.unwrap_or("")
+        overlay = overlay_html.unwrap_or(""),
     )
 }
@@ -0,0 +1,412 @@
+//! The cookie jar shared by every page in the process.
+//!
+//! Cookies arrive in `Set-Cookie` headers of `fetch()` responses, through `document.cookie`
+//! and from automation sessions, and go out with document loads and `fetch()` requests. The
+//! jar follows RFC 6265 closely enough for sign-in flows: `Domain`, `Path`, `Expires`,
+//! `Max-Age`, `Secure` and `HttpOnly` are honored, and a cookie set again with the same name,
+//! domain and path replaces the old one. A `Domain` that is a public suffix, checked against
+//! Mozilla's public suffix list (bundled as `assets/public_suffix_list.dat`, both its ICANN and
+//! private sections), is refused unless it is the host itself, so one site cannot set cookies
+//! for every site under `co.uk` or `github.io`. `SameSite` is not enforced, and nothing is
+//! written to disk, so cookies last until the browser exits.
+//!
+//! This is the only jar. Document loads use a client with [`SharedCookieStore`], so cookies are
+//! sent and `Set-Cookie` is kept on every hop of a redirect. Images, stylesheets and fonts go
+//! through blitz's net provider, which is built without a jar of its own: the subresource loader
+//! adds this jar's `Cookie` header, but the provider hands back only the body, so `Set-Cookie`
+//! on those responses is ignored.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use publicsuffix::{List, Psl};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../assets/public_suffix_list.dat");
+
+fn public_suffixes() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("the bundled public suffix list parses")
+    })
+}
+
+/// Whether no cookie may be scoped to all of `domain`'s subdomains. Names the list doesn't
+/// know, single labels such as `localhost` included, count as top-level domains.
+fn is_public_suffix(domain: &str) -> bool {
+    public_suffixes()
+        .suffix(domain.as_bytes())
+        .is_some_and(|suffix| suffix.as_bytes() == domain.as_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Host the cookie belongs to, without a leading dot.
+    pub domain: String,
+    /// Sent to subdomains of `domain` too, because it was set with a `Domain` attribute.
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    /// Seconds since the Unix epoch, or `None` for a session cookie.
+    pub expires: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn shared() -> &'static CookieJar {
+        static JAR: OnceLock<CookieJar> = OnceLock::new();
+        JAR.get_or_init(CookieJar::default)
+    }
+
+    /// Store `header`, a `Set-Cookie` value, as if it came from a response for `url`. A
+    /// script cannot set or replace `HttpOnly` cookies. Returns whether it was accepted; a
+    /// cookie that has already expired is accepted and deletes the one it replaces.
+    pub fn set(&self, url: &Url, header: &str, from_script: bool) -> bool {
+        self.set_at(url, header, from_script, now())
+    }
+
+    fn set_at(&self, url: &Url, header: &str, from_script: bool, now: u64) -> bool {
+        let Some(cookie) = parse(header, url, now) else {
+            return false;
+        };
+        if from_script && cookie.http_only {
+            return false;
+        }
+        let mut cookies = self.cookies.lock().unwrap();
+        let existing = cookies.iter().position(|stored| {
+            stored.name == cookie.name
+                && stored.domain == cookie.domain
+                && stored.path == cookie.path
+        });
+        if let Some(index) = existing {
+            if from_script && cookies[index].http_only {
+                return false;
+            }
+            cookies.remove(index);
+        }
+        if cookie.expires.is_none_or(|expires| expires > now) {
+            cookies.push(cookie);
+        }
+        true
+    }
+
+    /// The cookies a request to `url` carries, longest path first. Script sees only those
+    /// without `HttpOnly`.
+    pub fn cookies_for(&self, url: &Url, from_script: bool) -> Vec<Cookie> {
+        self.cookies_at(url, from_script, now())
+    }
+
+    fn cookies_at(&self, url: &Url, from_script: bool, now: u64) -> Vec<Cookie> {
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| cookie.expires.is_none_or(|expires| expires > now));
+        let mut matching: Vec<Cookie> = cookies
+            .iter()
+            .filter(|cookie| matches(cookie, url) && !(from_script && cookie.http_only))
+            .cloned()
+            .collect();
+        matching.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        matching
+    }
+
+    /// The `Cookie` header for a request to `url`, if any cookie applies.
+    pub fn header_for(&self, url: &Url) -> Option<String> {
+        let cookies = self.cookies_for(url, false);
+        (!cookies.is_empty()).then(|| serialize(&cookies))
+    }
+
+    /// Delete every cookie a request to `url` would carry. Returns how many went.
+    pub fn clear_for(&self, url: &Url) -> usize {
+        let mut cookies = self.cookies.lock().unwrap();
+        let before = cookies.len();
+        cookies.retain(|cookie| !matches(cookie, url));
+        before - cookies.len()
+    }
+
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+}
+
+/// [`CookieJar::shared`] as reqwest's cookie store, for clients that send and keep cookies on
+/// every hop of a redirect chain by themselves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SharedCookieStore;
+
+impl reqwest::cookie::CookieStore for SharedCookieStore {
+    fn set_cookies(&self, headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        for header in headers {
+            if let Ok(header) = header.to_str() {
+                CookieJar::shared().set(url, header, false);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        CookieJar::shared()
+            .header_for(url)
+            .and_then(|cookie| HeaderValue::from_str(&cookie).ok())
+    }
+}
+
+/// `name=value` pairs joined as a `Cookie` header or `document.cookie` shows them.
+pub fn serialize(cookies: &[Cookie]) -> String {
+    cookies
+        .iter()
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn matches(cookie: &Cookie, url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.to_ascii_lowercase();
+    let domain_matches = host == cookie.domain
+        || (cookie.include_subdomains && host.ends_with(&format!(".{}", cookie.domain)));
+    let secure = matches!(url.scheme(), "https" | "wss");
+    domain_matches && path_matches(url.path(), &cookie.path) && (secure || !cookie.secure)
+}
+
+fn path_matches(request: &str, cookie: &str) -> bool {
+    request == cookie
+        || (request.starts_with(cookie)
+            && (cookie.ends_with('/') || request[cookie.len()..].starts_with('/')))
+}
+
+/// The directory of `url`'s path, the path a cookie without a `Path` attribute gets.
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(end) => url.path()[..end].to_string(),
+    }
+}
+
+fn parse(header: &str, url: &Url, now: u64) -> Option<Cookie> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: value.trim().to_string(),
+        domain: host.clone(),
+        include_subdomains: false,
+        path: default_path(url),
+        secure: false,
+        http_only: false,
+        expires: None,
+    };
+    let mut max_age = None;
+    for attribute in parts {
+        let (key, value) = match attribute.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (attribute.trim(), ""),
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "domain" => {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if domain.is_empty() {
+                    continue;
+                }
+                if host != domain && !host.ends_with(&format!(".{domain}")) {
+                    return None;
+                }
+                // A public suffix or an IP address only ever names the host itself, so the
+                // cookie stays host-only.
+                let ip = matches!(url.host(), Some(Host::Ipv4(_) | Host::Ipv6(_)));
+                if is_public_suffix(&domain) || ip {
+                    if host == domain {
+                        continue;
+                    }
+                    return None;
+                }
+                cookie.domain = domain;
+                cookie.include_subdomains = true;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => cookie.expires = cookie.expires.or_else(|| parse_date(value)),
+            _ => {}
+        }
+    }
+    // `Max-Age` wins over `Expires`; zero or less expires the cookie at once.
+    if let Some(seconds) = max_age {
+        cookie.expires = Some(u64::try_from(seconds).map_or(0, |seconds| now + seconds));
+    }
+    if cookie.secure && !matches!(url.scheme(), "https" | "wss") {
+        return None;
+    }
+    Some(cookie)
+}
+
+/// Seconds since the Unix epoch for a cookie date, read as RFC 6265 section 5.1.1 does: the
+/// first time, day of month, month name and year among the date's tokens, in any order.
+fn parse_date(value: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let (mut time, mut day, mut month, mut year) = (None, None, None, None);
+    let tokens = value
+        .split(|ch: char| !(ch.is_ascii_alphanumeric() || ch == ':'))
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        let digits = token.chars().take_while(char::is_ascii_digit).count();
+        if time.is_none() && token.contains(':') {
+            let fields: Vec<u64> = token
+                .split(':')
+                .filter_map(|field| field.parse().ok())
+                .collect();
+            if let [hour, minute, second] = fields[..] {
+                time = Some((hour, minute, second));
+                continue;
+            }
+        }
+        if day.is_none() && (1..=2).contains(&digits) && digits == token.len() {
+            day = token.parse::<u64>().ok();
+        } else if month.is_none() && token.len() >= 3 {
+            let prefix = token[..3].to_ascii_lowercase();
+            if let Some(index) = MONTHS.iter().position(|name| *name == prefix) {
+                month = Some(index as u64 + 1);
+                continue;
+            }
+            if year.is_none() && (2..=4).contains(&digits) {
+                year = token[..digits].parse::<u64>().ok();
+            }
+        } else if year.is_none() && (2..=4).contains(&digits) {
+            year = token[..digits].parse::<u64>().ok();
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    year += match year {
+        0..=69 => 2000,
+        70..=99 => 1900,
+        _ => 0,
+    };
+    if !(1..=31).contains(&day) || year < 1970 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Days from 1970-01-01 to the given date, after Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn cookies_follow_domain_path_and_secure() {
+        let jar = CookieJar::default();
+        let page = url("https://app.example.com/account/settings");
+        assert!(jar.set_at(&page, "sid=abc; HttpOnly; Secure", false, 100));
+        assert!(jar.set_at(&page, "theme=dark; Domain=.example.com; Path=/", false, 100));
+        assert!(!jar.set_at(&page, "evil=1; Domain=other.com", false, 100));
+
+        let names = |target: &str, from_script: bool| -> Vec<String> {
+            let cookies = jar.cookies_at(&url(target), from_script, 100);
+            cookies.into_iter().map(|cookie| cookie.name).collect()
+        };
+        assert_eq!(
+            names("https://app.example.com/account/x", false),
+            ["sid", "theme"]
+        );
+        assert_eq!(names("https://app.example.com/account/x", true), ["theme"]);
+        assert_eq!(names("https://www.example.com/", false), ["theme"]);
+        assert_eq!(names("http://app.example.com/account/x", false), ["theme"]);
+        assert_eq!(names("https://app.example.com/accounts", false), ["theme"]);
+
+        // Script can neither set nor replace HttpOnly cookies.
+        assert!(!jar.set_at(&page, "sid=stolen", true, 100));
+        assert!(!jar.set_at(&page, "x=1; HttpOnly", true, 100));
+        assert_eq!(jar.clear_for(&url("https://www.example.com/")), 1);
+    }
+
+    #[test]
+    fn public_suffix_and_single_label_domains_are_refused() {
+        let jar = CookieJar::default();
+        let page = url("https://shop.example.co.uk/");
+        assert!(!jar.set_at(&page, "a=1; Domain=co.uk", false, 100));
+        assert!(!jar.set_at(&page, "b=1; Domain=.uk", false, 100));
+        assert!(jar.set_at(&page, "c=1; Domain=example.co.uk", false, 100));
+        let site = url("https://alice.github.io/");
+        assert!(!jar.set_at(&site, "d=1; Domain=github.io", false, 100));
+        let shop = url("https://shop.example.com.de/");
+        assert!(!jar.set_at(&shop, "g=1; Domain=com.de", false, 100));
+        let blog = url("https://me.blogspot.co.uk/");
+        assert!(!jar.set_at(&blog, "h=1; Domain=blogspot.co.uk", false, 100));
+        let ip = url("http://10.0.0.2/");
+        assert!(!jar.set_at(&ip, "e=1; Domain=0.2", false, 100));
+
+        // Naming the host itself keeps the cookie host-only.
+        let local = url("http://localhost:8080/");
+        assert!(jar.set_at(&local, "f=1; Domain=localhost", false, 100));
+        let cookies = jar.cookies_at(&local, false, 100);
+        assert!(!cookies[0].include_subdomains);
+        assert_eq!(
+            serialize(&jar.cookies_at(&url("https://www.example.co.uk/"), false, 100)),
+            "c=1"
+        );
+    }
+
+    #[test]
+    fn expiry_replaces_and_deletes() {
+        let jar = CookieJar::default();
+        let page = url("https://example.com/");
+        assert!(jar.set_at(&page, "a=1; Max-Age=60", false, 1000));
+        assert!(jar.set_at(
+            &page,
+            "b=2; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            false,
+            1000
+        ));
+        assert_eq!(jar.cookies_at(&page, false, 1000)[0].expires, Some(1060));
+        assert_eq!(serialize(&jar.cookies_at(&page, false, 1000)), "a=1; b=2");
+        assert_eq!(jar.cookies_at(&page, false, 1061).len(), 1);
+
+        assert!(jar.set_at(&page, "b=gone; Max-Age=0", false, 1000));
+        assert_eq!(serialize(&jar.cookies_at(&page, false, 1000)), "a=1");
+        assert_eq!(
+            parse_date("Wed, 21-Oct-15 07:28:00 GMT"),
+            Some(1_445_412_480)
+        );
+        assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(parse_date("not a date"), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Session recovery after a crash.
+//!
+//! The browser keeps the latest [`SessionSnapshot`] (URL, scroll position, history and the
+//! form fields of the current page) in memory with [`record`]. The panic hook installed by
+//! [`install_panic_hook`] writes it to `session-recovery.json` in the data directory before
+//! the default hook runs, and a clean exit removes the file again through [`clean_exit`].
+//! When `frontier` starts without a URL and the file is still there, it opens
+//! `frontier://restore`, which offers to bring the session back.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError, TryLockError};
+
+use anyhow::{anyhow, Context, Result};
+use blitz_dom::{local_name, ns, BaseDocument, DocumentMutator, QualName};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::{Deserialize, Serialize};
+
+use crate::storage_policy::StoragePolicy;
+
+pub const RESTORE_URL: &str = "frontier://restore";
+const FILE_NAME: &str = "session-recovery.json";
+
+/// Input types whose values are never written to disk.
+const UNSAVED_INPUT_TYPES: &[&str] = &[
+    "password", "hidden", "file", "submit", "reset", "button", "image",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub url: String,
+    pub scroll_x: f64,
+    pub scroll_y: f64,
+    pub back_history: Vec<String>,
+    pub forward_history: Vec<String>,
+    #[serde(default)]
+    pub form_fields: Vec<FormField>,
+}
+
+/// The value of one `<input>` or `<textarea>`, keyed by its `name`, its `id`, or its position
+/// among the page's fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormField {
+    pub key: String,
+    pub value: String,
+}
+
+static LATEST: Mutex<Option<SessionSnapshot>> = Mutex::new(None);
+
+/// Remember `snapshot` as the session to save if the browser panics.
+pub fn record(snapshot: SessionSnapshot) {
+    *LATEST.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+}
+
+/// Save the recorded session when any thread panics, then defer to the previous hook.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Err(err) = save_latest() {
+            eprintln!("Failed to save session for recovery: {err:?}");
+        }
+        previous(info);
+    }));
+}
+
+fn save_latest() -> Result<()> {
+    // The panic may have happened while the snapshot was being recorded; never block on it.
+    let guard = match LATEST.try_lock() {
+        Ok(guard) => guard,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return Err(anyhow!("session snapshot is locked")),
+    };
+    let Some(snapshot) = guard.as_ref() else {
+        return Ok(());
+    };
+    let path = recovery_path().context("no data directory for the recovery file")?;
+    write_snapshot(&path, snapshot)
+}
+
+/// Remove the recovery file after a normal shutdown. A session that never recorded anything,
+/// e.g. one that quit from the restore page, leaves an earlier file in place.
+pub fn clean_exit() {
+    let recorded = LATEST
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .is_some();
+    if recorded {
+        discard();
+    }
+}
+
+/// `None` in a private session, which neither saves a snapshot nor restores one.
+fn recovery_path() -> Option<PathBuf> {
+    StoragePolicy::current()
+        .persists()
+        .then(|| crate::preferences::data_file(FILE_NAME))?
+}
+
+/// The session a previous run saved when it crashed, if any.
+pub fn pending() -> Option<SessionSnapshot> {
+    read_snapshot(&recovery_path()?)
+}
+
+pub fn discard() {
+    let Some(path) = recovery_path() else {
+        return;
+    };
+    if let Err(err) = fs::remove_file(&path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!(path = %path.display(), error = %err, "failed to remove recovery file");
+        }
+    }
+}
+
+fn write_snapshot(path: &Path, snapshot: &SessionSnapshot) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("unable to create {}", parent.display()))?;
+    }
+    let json = serde_json::to_vec_pretty(snapshot).context("serialize session snapshot")?;
+    fs::write(path, json).with_context(|| format!("unable to write {}", path.display()))
+}
+
+fn read_snapshot(path: &Path) -> Option<SessionSnapshot> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// `frontier://restore`: offer to reopen `snapshot`.
+pub fn restore_page(snapshot: Option<&SessionSnapshot>) -> String {
+    let Some(snapshot) = snapshot else {
+        return r#"<section class="restore">
+<h1>Nothing to restore</h1>
+<p>There is no saved session.</p>
+</section>"#
+            .to_string();
+    };
+    let history = snapshot.back_history.len() + snapshot.forward_history.len();
+    format!(
+        r#"<section class="restore">
+<h1>Frontier closed unexpectedly — restore your session?</h1>
+<p>You were on <a href="{href}">{url}</a>{history}{fields}.</p>
+<p><a href="{RESTORE_URL}?action=restore">Restore session</a> · <a href="{RESTORE_URL}?action=discard">Start fresh</a></p>
+</section>"#,
+        href = encode_double_quoted_attribute(&snapshot.url),
+        url = encode_text(&snapshot.url),
+        history = match history {
+            0 => String::new(),
+            1 => ", with 1 page of history".to_string(),
+            count => format!(", with {count} pages of history"),
+        },
+        fields = match snapshot.form_fields.len() {
+            0 => String::new(),
+            1 => " and 1 filled-in form field".to_string(),
+            count => format!(" and {count} filled-in form fields"),
+        },
+    )
+}
+
+/// Text fields of `doc` in document order, with the value the DOM holds for each.
+pub fn collect_form_fields(doc: &BaseDocument) -> Vec<FormField> {
+    form_field_nodes(doc)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, node_id)| {
+            let value = field_value(doc, node_id)?;
+            (!value.is_empty()).then(|| FormField {
+                key: field_key(doc, node_id, index),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Put saved values back into the matching fields of `doc`.
+pub fn restore_form_fields(doc: &mut BaseDocument, fields: &[FormField]) {
+    let targets: Vec<(usize, String, bool)> = form_field_nodes(doc)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, node_id)| {
+            let key = field_key(doc, node_id, index);
+            let value = fields.iter().find(|field| field.key == key)?.value.clone();
+            let is_textarea = doc
+                .get_node(node_id)
+                .and_then(|node| node.element_data())
+                .is_some_and(|element| element.name.local == local_name!("textarea"));
+            Some((node_id, value, is_textarea))
+        })
+        .collect();
+    let mut mutator = DocumentMutator::new(doc);
+    for (node_id, value, is_textarea) in targets {
+        if is_textarea {
+            mutator.set_inner_html(node_id, &encode_text(&value));
+        } else {
+            mutator.set_attribute(
+                node_id,
+                QualName::new(None, ns!(html), local_name!("value")),
+                &value,
+            );
+        }
+    }
+}
+
+fn form_field_nodes(doc: &BaseDocument) -> Vec<usize> {
+    let mut fields = Vec::new();
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        if let Some(element) = node.element_data() {
+            let saved = match element.name.local.as_ref() {
+                "textarea" => true,
+                "input" => {
+                    let input_type = node.attr(local_name!("type")).unwrap_or("text");
+                    !UNSAVED_INPUT_TYPES
+                        .iter()
+                        .any(|skipped| input_type.eq_ignore_ascii_case(skipped))
+                }
+                _ => false,
+            };
+            if saved {
+                fields.push(node_id);
+            }
+        }
+        stack.extend(node.children.iter().rev().copied());
+    }
+    fields
+}
+
+fn field_value(doc: &BaseDocument, node_id: usize) -> Option<String> {
+    let node = doc.get_node(node_id)?;
+    if node.element_data()?.name.local == local_name!("textarea") {
+        Some(node.text_content())
+    } else {
+        Some(
+            node.attr(local_name!("value"))
+                .unwrap_or_default()
+                .to_string(),
+        )
+    }
+}
+
+fn field_key(doc: &BaseDocument, node_id: usize, index: usize) -> String {
+    let node = doc.get_node(node_id);
+    let attr = |name| {
+        node.and_then(|node| node.attr(name))
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(name) = attr(local_name!("name")) {
+        format!("name:{name}")
+    } else if let Some(id) = attr(local_name!("id")) {
+        format!("id:{id}")
+    } else {
+        format!("index:{index}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    fn document(html: &str) -> HtmlDocument {
+        HtmlDocument::from_html(html, DocumentConfig::default())
+    }
+
+    #[test]
+    fn snapshot_round_trips_through_the_recovery_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        let snapshot = SessionSnapshot {
+            url: "https://example.com/post".to_string(),
+            scroll_y: 420.0,
+            back_history: vec!["https://example.com/".to_string()],
+            form_fields: vec![FormField {
+                key: "name:comment".to_string(),
+                value: "half-written".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        write_snapshot(&path, &snapshot).unwrap();
+        assert_eq!(read_snapshot(&path), Some(snapshot.clone()));
+        let page = restore_page(Some(&snapshot));
+        assert!(page.contains("restore your session?"), "{page}");
+        assert!(page.contains("1 page of history and 1 filled-in form field"));
+    }
+
+    #[test]
+    fn form_fields_skip_passwords_and_restore_by_key() {
+        let html = r#"<html><body><form>
+            <input name="title" value="Draft">
+            <input type="password" name="secret" value="hunter2">
+            <input id="tags" value="rust">
+            <textarea name="body">Some text</textarea>
+        </form></body></html>"#;
+        let doc = document(html);
+        let fields = collect_form_fields(&doc);
+        assert_eq!(
+            fields
+                .iter()
+                .map(|field| (field.key.as_str(), field.value.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("name:title", "Draft"),
+                ("id:tags", "rust"),
+                ("name:body", "Some text"),
+            ]
+        );
+
+        let mut fresh = document(
+            r#"<html><body><form><input name="title"><input type="password" name="secret"><input id="tags"><textarea name="body"></textarea></form></body></html>"#,
+        );
+        restore_form_fields(&mut fresh, &fields);
+        assert_eq!(collect_form_fields(&fresh), fields);
+    }
+}
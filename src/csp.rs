@@ -0,0 +1,625 @@
+//! Content Security Policy parsing with `script-src`, `style-src` and `connect-src`
+//! enforcement.
+//!
+//! Policies come from the `Content-Security-Policy` headers of an http(s) document's response
+//! and from its `<meta http-equiv="Content-Security-Policy">` tags; a load must satisfy all of
+//! them. Scripts that a policy refuses are dropped from the document's script list before
+//! anything is fetched or evaluated, and each refusal is logged on the `quickjs` target next to
+//! page `console.log` output. `connect-src` gates `fetch()` from page scripts, `style-src`
+//! the stylesheets they link, and `prefetch-src` the pages the document prefetches. Without
+//! `'unsafe-eval'`, `eval` and the `Function` constructors throw an `EvalError`.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use kuchiki::parse_html;
+use kuchiki::traits::*;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use tracing::warn;
+use url::Url;
+
+use crate::js::script::{ScriptDescriptor, ScriptSource};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SourceExpression {
+    None,
+    SelfOrigin,
+    UnsafeInline,
+    UnsafeEval,
+    Nonce(String),
+    Hash {
+        algorithm: HashAlgorithm,
+        digest: String,
+    },
+    Scheme(String),
+    Host(HostSource),
+    /// Keywords we parse but never match on, such as `'strict-dynamic'`.
+    Keyword,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HostSource {
+    scheme: Option<String>,
+    host: String,
+    port: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Policy {
+    directives: Vec<(String, Vec<SourceExpression>)>,
+}
+
+/// Every policy delivered with a document. A load must satisfy all of them.
+#[derive(Debug, Clone, Default)]
+pub struct ContentSecurityPolicy {
+    policies: Vec<Policy>,
+}
+
+impl ContentSecurityPolicy {
+    /// Parse one serialized policy (a header value or meta `content` attribute).
+    pub fn parse(serialized: &str) -> Self {
+        let mut policy = Self::default();
+        policy.add(serialized);
+        policy
+    }
+
+    /// Parse `Content-Security-Policy` header values, each of which may hold several
+    /// comma-separated policies.
+    pub fn from_headers<S: AsRef<str>>(values: &[S]) -> Self {
+        let mut policy = Self::default();
+        for value in values {
+            for serialized in value.as_ref().split(',') {
+                policy.add(serialized);
+            }
+        }
+        policy
+    }
+
+    /// Collect policies from `<meta http-equiv="Content-Security-Policy">` tags.
+    pub fn from_html(html: &str) -> Self {
+        let mut policy = Self::default();
+        let document = parse_html().one(html);
+        if let Ok(metas) = document.select("meta[http-equiv]") {
+            for meta in metas {
+                let attributes = meta.attributes.borrow();
+                let is_csp = attributes
+                    .get("http-equiv")
+                    .map(|value| value.trim().eq_ignore_ascii_case("content-security-policy"))
+                    .unwrap_or(false);
+                if let (true, Some(content)) = (is_csp, attributes.get("content")) {
+                    policy.policies.extend(Self::parse(content).policies);
+                }
+            }
+        }
+        policy
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Add every policy of `other`, which loads must then satisfy as well.
+    pub fn extend(&mut self, other: ContentSecurityPolicy) {
+        self.policies.extend(other.policies);
+    }
+
+    fn add(&mut self, serialized: &str) {
+        let mut parsed = Policy::default();
+        for directive in serialized.split(';') {
+            let mut tokens = directive.split_ascii_whitespace();
+            let Some(name) = tokens.next() else {
+                continue;
+            };
+            let name = name.to_ascii_lowercase();
+            // The first occurrence of a directive wins.
+            if parsed
+                .directives
+                .iter()
+                .any(|(existing, _)| *existing == name)
+            {
+                continue;
+            }
+            let sources = tokens.map(parse_source_expression).collect();
+            parsed.directives.push((name, sources));
+        }
+        if !parsed.directives.is_empty() {
+            self.policies.push(parsed);
+        }
+    }
+
+    /// Whether an inline `<script>` with this body and nonce may run.
+    pub fn allows_inline_script(&self, code: &str, nonce: Option<&str>) -> bool {
+        self.policies.iter().all(|policy| {
+            let Some(sources) = policy.script_sources() else {
+                return true;
+            };
+            if matches_nonce(sources, nonce) || matches_hash(sources, code) {
+                return true;
+            }
+            let has_nonce_or_hash = sources.iter().any(|source| {
+                matches!(
+                    source,
+                    SourceExpression::Nonce(_) | SourceExpression::Hash { .. }
+                )
+            });
+            !has_nonce_or_hash && sources.contains(&SourceExpression::UnsafeInline)
+        })
+    }
+
+    /// Whether page script may compile strings into code with `eval` or `new Function`.
+    pub fn allows_eval(&self) -> bool {
+        self.policies.iter().all(|policy| {
+            match policy
+                .directive("script-src")
+                .or_else(|| policy.directive("default-src"))
+            {
+                Some(sources) => sources.contains(&SourceExpression::UnsafeEval),
+                None => true,
+            }
+        })
+    }
+
+    /// Whether an external script at `url` may be loaded into a document at `document_url`.
+    pub fn allows_script_url(
+        &self,
+        url: &Url,
+        document_url: Option<&Url>,
+        nonce: Option<&str>,
+    ) -> bool {
+        self.policies.iter().all(|policy| {
+            let Some(sources) = policy.script_sources() else {
+                return true;
+            };
+            matches_nonce(sources, nonce)
+                || sources
+                    .iter()
+                    .any(|source| source_matches_url(source, url, document_url))
+        })
+    }
+
+    /// Whether a stylesheet linked from page script may be loaded from `url`.
+    pub fn allows_style_url(&self, url: &Url, document_url: Option<&Url>) -> bool {
+        self.policies.iter().all(|policy| {
+            let Some(sources) = policy
+                .directive("style-src-elem")
+                .or_else(|| policy.directive("style-src"))
+                .or_else(|| policy.directive("default-src"))
+            else {
+                return true;
+            };
+            sources
+                .iter()
+                .any(|source| source_matches_url(source, url, document_url))
+        })
+    }
+
+    /// Whether page script may open a connection to `url` via `fetch()`.
+    pub fn allows_connect_url(&self, url: &Url, document_url: Option<&Url>) -> bool {
+        self.policies.iter().all(|policy| {
+            let Some(sources) = policy
+                .directive("connect-src")
+                .or_else(|| policy.directive("default-src"))
+            else {
+                return true;
+            };
+            sources
+                .iter()
+                .any(|source| source_matches_url(source, url, document_url))
+        })
+    }
+
+    /// Whether the document may prefetch the page at `url`.
+    pub fn allows_prefetch_url(&self, url: &Url, document_url: Option<&Url>) -> bool {
+        self.policies.iter().all(|policy| {
+            let Some(sources) = policy
+                .directive("prefetch-src")
+                .or_else(|| policy.directive("default-src"))
+            else {
+                return true;
+            };
+            sources
+                .iter()
+                .any(|source| source_matches_url(source, url, document_url))
+        })
+    }
+
+    /// Drop scripts the policy refuses, logging a violation for each.
+    pub fn filter_scripts(
+        &self,
+        scripts: Vec<ScriptDescriptor>,
+        document_url: Option<&Url>,
+    ) -> Vec<ScriptDescriptor> {
+        if self.is_empty() {
+            return scripts;
+        }
+
+        scripts
+            .into_iter()
+            .filter(|descriptor| {
+                let nonce = descriptor.nonce.as_deref();
+                match &descriptor.source {
+                    ScriptSource::Inline { code } => {
+                        let allowed = self.allows_inline_script(code, nonce);
+                        if !allowed {
+                            warn!(
+                                target = "quickjs",
+                                script_index = descriptor.index,
+                                directive = "script-src",
+                                "Refused to execute inline script because it violates the Content Security Policy"
+                            );
+                        }
+                        allowed
+                    }
+                    ScriptSource::External { src } => {
+                        let resolved = match document_url {
+                            Some(base) => base.join(src),
+                            None => Url::parse(src),
+                        };
+                        let allowed = resolved
+                            .as_ref()
+                            .map(|url| self.allows_script_url(url, document_url, nonce))
+                            .unwrap_or(false);
+                        if !allowed {
+                            warn!(
+                                target = "quickjs",
+                                src = %src,
+                                directive = "script-src",
+                                "Refused to load script because it violates the Content Security Policy"
+                            );
+                        }
+                        allowed
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+impl Policy {
+    fn directive(&self, name: &str) -> Option<&[SourceExpression]> {
+        self.directives
+            .iter()
+            .find(|(directive, _)| directive == name)
+            .map(|(_, sources)| sources.as_slice())
+    }
+
+    fn script_sources(&self) -> Option<&[SourceExpression]> {
+        self.directive("script-src-elem")
+            .or_else(|| self.directive("script-src"))
+            .or_else(|| self.directive("default-src"))
+    }
+}
+
+fn parse_source_expression(token: &str) -> SourceExpression {
+    let lowered = token.to_ascii_lowercase();
+    match lowered.as_str() {
+        "'none'" => return SourceExpression::None,
+        "'self'" => return SourceExpression::SelfOrigin,
+        "'unsafe-inline'" => return SourceExpression::UnsafeInline,
+        "'unsafe-eval'" => return SourceExpression::UnsafeEval,
+        _ => {}
+    }
+
+    if let Some(inner) = token.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        if let Some(nonce) = inner.strip_prefix("nonce-") {
+            return SourceExpression::Nonce(nonce.to_string());
+        }
+        for (prefix, algorithm) in [
+            ("sha256-", HashAlgorithm::Sha256),
+            ("sha384-", HashAlgorithm::Sha384),
+            ("sha512-", HashAlgorithm::Sha512),
+        ] {
+            if let Some(digest) = inner.strip_prefix(prefix) {
+                return SourceExpression::Hash {
+                    algorithm,
+                    digest: digest.to_string(),
+                };
+            }
+        }
+        return SourceExpression::Keyword;
+    }
+
+    if let Some(scheme) = lowered.strip_suffix(':') {
+        if !scheme.contains('/') {
+            return SourceExpression::Scheme(scheme.to_string());
+        }
+    }
+
+    let (scheme, rest) = match lowered.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme.to_string()), rest),
+        None => (None, lowered.as_str()),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], Some(rest[index..].to_string())),
+        None => (rest, None),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host.to_string(), Some(port.to_string())),
+        None => (authority.to_string(), None),
+    };
+    SourceExpression::Host(HostSource {
+        scheme,
+        host,
+        port,
+        path,
+    })
+}
+
+fn matches_nonce(sources: &[SourceExpression], nonce: Option<&str>) -> bool {
+    let Some(nonce) = nonce.filter(|nonce| !nonce.is_empty()) else {
+        return false;
+    };
+    sources
+        .iter()
+        .any(|source| matches!(source, SourceExpression::Nonce(expected) if expected == nonce))
+}
+
+fn matches_hash(sources: &[SourceExpression], code: &str) -> bool {
+    sources.iter().any(|source| match source {
+        SourceExpression::Hash { algorithm, digest } => {
+            let actual = match algorithm {
+                HashAlgorithm::Sha256 => BASE64.encode(Sha256::digest(code.as_bytes())),
+                HashAlgorithm::Sha384 => BASE64.encode(Sha384::digest(code.as_bytes())),
+                HashAlgorithm::Sha512 => BASE64.encode(Sha512::digest(code.as_bytes())),
+            };
+            actual == *digest
+        }
+        _ => false,
+    })
+}
+
+fn source_matches_url(source: &SourceExpression, url: &Url, document_url: Option<&Url>) -> bool {
+    match source {
+        SourceExpression::SelfOrigin => document_url
+            .map(|document| document.origin() == url.origin() || same_file_origin(document, url))
+            .unwrap_or(false),
+        SourceExpression::Scheme(scheme) => scheme_matches(scheme, url.scheme()),
+        SourceExpression::Host(host) => host_source_matches(host, url, document_url),
+        SourceExpression::None
+        | SourceExpression::UnsafeInline
+        | SourceExpression::UnsafeEval
+        | SourceExpression::Nonce(_)
+        | SourceExpression::Hash { .. }
+        | SourceExpression::Keyword => false,
+    }
+}
+
+/// `file:` documents have opaque origins; treat sibling files as same-origin so local pages
+/// with `'self'` policies keep working.
+fn same_file_origin(document: &Url, url: &Url) -> bool {
+    document.scheme() == "file" && url.scheme() == "file"
+}
+
+/// Scheme matching upgrades insecure schemes to their secure counterparts.
+fn scheme_matches(expected: &str, actual: &str) -> bool {
+    expected == actual
+        || matches!(
+            (expected, actual),
+            ("http", "https") | ("ws", "wss") | ("ws", "https") | ("wss", "https")
+        )
+}
+
+fn host_source_matches(source: &HostSource, url: &Url, document_url: Option<&Url>) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    match &source.scheme {
+        Some(scheme) => {
+            if !scheme_matches(scheme, url.scheme()) {
+                return false;
+            }
+        }
+        None => {
+            let document_scheme = document_url.map(Url::scheme).unwrap_or("https");
+            let allowed = scheme_matches(document_scheme, url.scheme())
+                || (document_scheme != "https" && matches!(url.scheme(), "http" | "https"));
+            if !allowed {
+                return false;
+            }
+        }
+    }
+
+    let host = host.to_ascii_lowercase();
+    let host_matches = if source.host == "*" {
+        true
+    } else if let Some(suffix) = source.host.strip_prefix("*.") {
+        host.ends_with(&format!(".{suffix}"))
+    } else {
+        host == source.host
+    };
+    if !host_matches {
+        return false;
+    }
+
+    match source.port.as_deref() {
+        Some("*") => {}
+        Some(port) => {
+            if url
+                .port_or_known_default()
+                .map(|p| p.to_string())
+                .as_deref()
+                != Some(port)
+            {
+                return false;
+            }
+        }
+        None => {
+            if url.port().is_some() {
+                return false;
+            }
+        }
+    }
+
+    match &source.path {
+        Some(path) if path.ends_with('/') => url.path().starts_with(path.as_str()),
+        Some(path) => url.path() == path,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn inline_requires_unsafe_inline_nonce_or_hash() {
+        let code = "console.log('hi')";
+        let hash = BASE64.encode(Sha256::digest(code.as_bytes()));
+
+        let strict = ContentSecurityPolicy::parse("script-src 'self'");
+        assert!(!strict.allows_inline_script(code, None));
+
+        let unsafe_inline = ContentSecurityPolicy::parse("default-src 'self' 'unsafe-inline'");
+        assert!(unsafe_inline.allows_inline_script(code, None));
+
+        let nonce = ContentSecurityPolicy::parse("script-src 'nonce-abc' 'unsafe-inline'");
+        assert!(nonce.allows_inline_script(code, Some("abc")));
+        assert!(!nonce.allows_inline_script(code, None));
+
+        let hashed = ContentSecurityPolicy::parse(&format!("script-src 'sha256-{hash}'"));
+        assert!(hashed.allows_inline_script(code, None));
+        assert!(!hashed.allows_inline_script("alert(1)", None));
+    }
+
+    #[test]
+    fn external_scripts_match_self_hosts_and_schemes() {
+        let document = url("https://site.example/index.html");
+        let policy = ContentSecurityPolicy::parse(
+            "script-src 'self' *.cdn.example https://other.example/js/",
+        );
+
+        assert!(policy.allows_script_url(
+            &url("https://site.example/app.js"),
+            Some(&document),
+            None
+        ));
+        assert!(policy.allows_script_url(
+            &url("https://a.cdn.example/lib.js"),
+            Some(&document),
+            None
+        ));
+        assert!(policy.allows_script_url(
+            &url("https://other.example/js/x.js"),
+            Some(&document),
+            None
+        ));
+        assert!(!policy.allows_script_url(
+            &url("https://other.example/x.js"),
+            Some(&document),
+            None
+        ));
+        assert!(!policy.allows_script_url(
+            &url("https://evil.example/x.js"),
+            Some(&document),
+            None
+        ));
+
+        let none = ContentSecurityPolicy::parse("script-src 'none'");
+        assert!(!none.allows_script_url(
+            &url("https://site.example/app.js"),
+            Some(&document),
+            None
+        ));
+    }
+
+    #[test]
+    fn connect_src_falls_back_to_default_src() {
+        let document = url("https://site.example/");
+        let policy = ContentSecurityPolicy::parse("default-src 'self'; script-src 'none'");
+        assert!(policy.allows_connect_url(&url("https://site.example/api"), Some(&document)));
+        assert!(!policy.allows_connect_url(&url("https://api.example/"), Some(&document)));
+
+        let connect = ContentSecurityPolicy::parse("default-src 'none'; connect-src https:");
+        assert!(connect.allows_connect_url(&url("https://api.example/"), Some(&document)));
+        assert!(!connect.allows_connect_url(&url("http://api.example/"), Some(&document)));
+    }
+
+    #[test]
+    fn style_src_falls_back_to_default_src() {
+        let document = url("https://site.example/");
+        let policy = ContentSecurityPolicy::parse("default-src 'self'");
+        assert!(policy.allows_style_url(&url("https://site.example/a.css"), Some(&document)));
+        assert!(!policy.allows_style_url(&url("https://cdn.example/a.css"), Some(&document)));
+
+        let style = ContentSecurityPolicy::parse("default-src 'none'; style-src cdn.example");
+        assert!(style.allows_style_url(&url("https://cdn.example/a.css"), Some(&document)));
+        assert!(!style.allows_style_url(&url("https://site.example/a.css"), Some(&document)));
+    }
+
+    #[test]
+    fn meta_policies_all_apply() {
+        let html = r#"<html><head>
+            <meta http-equiv="Content-Security-Policy" content="script-src 'self' 'unsafe-inline'">
+            <meta http-equiv="content-security-policy" content="script-src https://cdn.example">
+        </head><body></body></html>"#;
+        let policy = ContentSecurityPolicy::from_html(html);
+        let document = url("https://site.example/");
+        assert!(!policy.allows_script_url(
+            &url("https://site.example/a.js"),
+            Some(&document),
+            None
+        ));
+        assert!(!policy.allows_inline_script("1", None));
+    }
+
+    #[test]
+    fn header_policies_split_on_commas_and_join_meta_policies() {
+        let document = url("https://site.example/");
+        let mut policy =
+            ContentSecurityPolicy::from_headers(&["script-src 'self', connect-src 'none'", ""]);
+        assert!(policy.allows_script_url(&url("https://site.example/a.js"), Some(&document), None));
+        assert!(!policy.allows_connect_url(&url("https://site.example/api"), Some(&document)));
+
+        policy.extend(ContentSecurityPolicy::from_html(
+            r#"<meta http-equiv="Content-Security-Policy" content="script-src https://cdn.example">"#,
+        ));
+        assert!(!policy.allows_script_url(
+            &url("https://site.example/a.js"),
+            Some(&document),
+            None
+        ));
+    }
+
+    #[test]
+    fn eval_needs_unsafe_eval_in_script_src_or_default_src() {
+        assert!(ContentSecurityPolicy::default().allows_eval());
+        assert!(ContentSecurityPolicy::parse("style-src 'self'").allows_eval());
+        assert!(!ContentSecurityPolicy::parse("default-src 'self'").allows_eval());
+        assert!(ContentSecurityPolicy::parse("default-src 'self' 'unsafe-eval'").allows_eval());
+        assert!(
+            !ContentSecurityPolicy::parse("default-src 'unsafe-eval'; script-src 'self'")
+                .allows_eval()
+        );
+        assert!(!ContentSecurityPolicy::from_headers(&[
+            "script-src 'unsafe-eval', script-src 'self'"
+        ])
+        .allows_eval());
+    }
+
+    #[test]
+    fn filter_drops_refused_scripts() {
+        let html = r#"<html><head>
+            <meta http-equiv="Content-Security-Policy" content="script-src 'nonce-ok'">
+            <script nonce="ok">window.a = 1;</script>
+            <script>window.b = 1;</script>
+        </head></html>"#;
+        let scripts = crate::js::processor::collect_scripts(html).unwrap();
+        assert_eq!(scripts.len(), 2);
+        let policy = ContentSecurityPolicy::from_html(html);
+        let allowed = policy.filter_scripts(scripts, None);
+        assert_eq!(allowed.len(), 1);
+        assert_eq!(allowed[0].nonce.as_deref(), Some("ok"));
+    }
+}
@@ -0,0 +1,397 @@
+//! Damage tracking: which parts of the viewport a frame actually changed, behind the damage
+//! overlay (Cmd/Ctrl+Shift+D) and the automation `damage` endpoint.
+//!
+//! After blitz lays out and paints a frame, [`DamageTracker::record_frame`] takes a snapshot
+//! of every element's border box and a signature of what it paints from its own node: its
+//! attributes (so `class` and `style` changes count), the text of its text children, and its
+//! hover, active and focus state. Elements whose box or signature changed since the previous
+//! frame, and elements that appeared or went away, contribute their old and new boxes. The
+//! boxes are moved into viewport coordinates, clipped, and merged into a few rectangles, so a
+//! script updating a counter damages the counter's line and nothing else. Scrolling, resizing,
+//! zooming and a new document damage the whole viewport.
+//!
+//! anyrender hands the renderer a whole scene, so vello still rasterizes every pixel of every
+//! frame; the rectangles are what a partial redraw would have to redo, and
+//! [`DamageSummary::mean_damaged_fraction`] is how much of that work it would keep. Changes
+//! made only through stylesheets (a rule edited from script, the colour scheme switching) do
+//! not show in the signature, so [`DamageTracker::invalidate`] marks such frames as full.
+//!
+//! The snapshot costs a walk over the document per frame, so tracking only runs while the
+//! overlay is open or an automation session is attached.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use blitz_dom::{BaseDocument, Node, NodeData};
+use serde::{Deserialize, Serialize};
+
+/// Damage split into more rectangles than this is reported as their bounding box.
+const MAX_RECTS: usize = 16;
+
+/// A rectangle in CSS pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DamageRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl DamageRect {
+    pub fn new(x: f64, y: f64, width: f64, height: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        self.width * self.height
+    }
+
+    fn is_empty(&self) -> bool {
+        self.width <= 0.0 || self.height <= 0.0
+    }
+
+    fn right(&self) -> f64 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> f64 {
+        self.y + self.height
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Self::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Self::new(
+            x,
+            y,
+            (self.right().min(other.right()) - x).max(0.0),
+            (self.bottom().min(other.bottom()) - y).max(0.0),
+        )
+    }
+}
+
+/// What an element painted in one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Painted {
+    /// Border box in document coordinates.
+    rect: DamageRect,
+    signature: u64,
+}
+
+/// Where the viewport was: scroll offset and size, in CSS pixels.
+type ViewportState = (f64, f64, f64, f64);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DamageSummary {
+    pub frames: u64,
+    /// Frames that changed nothing on screen.
+    pub clean_frames: u64,
+    /// Frames that changed part of the viewport.
+    pub partial_frames: u64,
+    /// Frames that changed all of it, or could not tell.
+    pub full_frames: u64,
+    /// Share of the viewport's area damaged per frame, averaged over the frames, from 0 to 1.
+    pub mean_damaged_fraction: f64,
+    /// The damage of the latest frame that changed anything, in viewport CSS pixels.
+    pub last_damage: Vec<DamageRect>,
+}
+
+#[derive(Debug, Default)]
+pub struct DamageTracker {
+    previous: HashMap<usize, Painted>,
+    previous_viewport: Option<ViewportState>,
+    /// The next frame damages the whole viewport.
+    invalidated: bool,
+    summary: DamageSummary,
+    damaged_fraction_total: f64,
+}
+
+impl DamageTracker {
+    /// Start over for a new document.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Count the next frame as damaging the whole viewport, for changes the snapshot cannot
+    /// see.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    /// Compare the frame just painted with the previous one. `viewport` is the viewport's
+    /// size in CSS pixels; the subtree at `skip`, the overlay host the damage is drawn in, is
+    /// left out so drawing the damage does not cause more.
+    pub fn record_frame(&mut self, doc: &BaseDocument, viewport: (f64, f64), skip: Option<usize>) {
+        let scroll = doc.viewport_scroll();
+        let state = (scroll.x, scroll.y, viewport.0, viewport.1);
+        let current = snapshot(doc, skip);
+        let full = std::mem::take(&mut self.invalidated)
+            || self.previous_viewport != Some(state)
+            || self.previous.is_empty();
+        let screen = DamageRect::new(0.0, 0.0, viewport.0, viewport.1);
+        let damage = if full {
+            vec![screen]
+        } else {
+            let offset = |rect: DamageRect| {
+                DamageRect::new(
+                    rect.x - scroll.x,
+                    rect.y - scroll.y,
+                    rect.width,
+                    rect.height,
+                )
+                .intersection(&screen)
+            };
+            merge(
+                changed(&self.previous, &current)
+                    .into_iter()
+                    .map(offset)
+                    .collect(),
+            )
+        };
+        self.previous = current;
+        self.previous_viewport = Some(state);
+        self.count(damage, screen.area());
+    }
+
+    fn count(&mut self, damage: Vec<DamageRect>, screen_area: f64) {
+        let damaged: f64 = damage.iter().map(DamageRect::area).sum();
+        let fraction = if screen_area > 0.0 {
+            (damaged / screen_area).min(1.0)
+        } else {
+            1.0
+        };
+        self.summary.frames += 1;
+        if damage.is_empty() {
+            self.summary.clean_frames += 1;
+        } else if fraction >= 1.0 {
+            self.summary.full_frames += 1;
+        } else {
+            self.summary.partial_frames += 1;
+        }
+        self.damaged_fraction_total += fraction;
+        self.summary.mean_damaged_fraction =
+            self.damaged_fraction_total / self.summary.frames as f64;
+        if !damage.is_empty() {
+            self.summary.last_damage = damage;
+        }
+    }
+
+    pub fn summary(&self) -> DamageSummary {
+        self.summary.clone()
+    }
+}
+
+/// Every element's box and signature, except those under `skip`.
+fn snapshot(doc: &BaseDocument, skip: Option<usize>) -> HashMap<usize, Painted> {
+    let mut painted = HashMap::new();
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        if Some(node_id) == skip {
+            continue;
+        }
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        if node.element_data().is_some() {
+            let position = node.absolute_position(0.0, 0.0);
+            let size = node.final_layout.size;
+            let rect = DamageRect::new(
+                f64::from(position.x),
+                f64::from(position.y),
+                f64::from(size.width),
+                f64::from(size.height),
+            );
+            let signature = signature(doc, node);
+            painted.insert(node_id, Painted { rect, signature });
+        }
+        stack.extend(node.children.iter().copied());
+    }
+    painted
+}
+
+/// A hash of what `node` paints that does not show in its box.
+fn signature(doc: &BaseDocument, node: &Node) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(element) = node.element_data() {
+        let name: &str = &element.name.local;
+        name.hash(&mut hasher);
+        for attr in element.attrs.iter() {
+            let (name, value): (&str, &str) = (&attr.name.local, &attr.value);
+            (name, value).hash(&mut hasher);
+        }
+    }
+    (node.is_hovered(), node.is_active(), node.is_focussed()).hash(&mut hasher);
+    for &child in &node.children {
+        if let Some(NodeData::Text(text)) = doc.get_node(child).map(|child| &child.data) {
+            text.content.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// The boxes, old and new, of the elements that changed, appeared or went away.
+fn changed(
+    previous: &HashMap<usize, Painted>,
+    current: &HashMap<usize, Painted>,
+) -> Vec<DamageRect> {
+    let mut damage = Vec::new();
+    for (node_id, now) in current {
+        match previous.get(node_id) {
+            Some(before) if before == now => {}
+            Some(before) => damage.extend([before.rect, now.rect]),
+            None => damage.push(now.rect),
+        }
+    }
+    damage.extend(
+        previous
+            .iter()
+            .filter(|(node_id, _)| !current.contains_key(node_id))
+            .map(|(_, before)| before.rect),
+    );
+    damage
+}
+
+/// Drop empty rectangles and merge overlapping ones, down to [`MAX_RECTS`].
+fn merge(rects: Vec<DamageRect>) -> Vec<DamageRect> {
+    let mut merged: Vec<DamageRect> = Vec::new();
+    for mut rect in rects.into_iter().filter(|rect| !rect.is_empty()) {
+        // A merged rectangle can reach others it did not overlap before.
+        while let Some(index) = merged.iter().position(|other| other.overlaps(&rect)) {
+            rect = rect.union(&merged.swap_remove(index));
+        }
+        merged.push(rect);
+    }
+    if merged.len() > MAX_RECTS {
+        let bounds = merged
+            .iter()
+            .skip(1)
+            .fold(merged[0], |bounds, rect| bounds.union(rect));
+        return vec![bounds];
+    }
+    merged.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    merged
+}
+
+/// HTML for the overlay, placed in the chrome's overlay host: an outline per rectangle of the
+/// latest damage and the running totals.
+pub fn overlay_html(summary: &DamageSummary) -> String {
+    let outlines: String = summary
+        .last_damage
+        .iter()
+        .map(|rect| {
+            format!(
+                r#"<div class="damage-rect" style="left: {:.0}px; top: {:.0}px; width: {:.0}px; height: {:.0}px;"></div>"#,
+                rect.x, rect.y, rect.width, rect.height
+            )
+        })
+        .collect();
+    format!(
+        r#"<div id="damage-overlay" aria-hidden="true">{outlines}</div>
+<aside id="damage-stats" aria-label="Damage">
+<div>{partial} partial · {full} full · {clean} clean</div>
+<div>{percent:.1}% of the viewport damaged per frame</div>
+</aside>"#,
+        partial = summary.partial_frames,
+        full = summary.full_frames,
+        clean = summary.clean_frames,
+        percent = summary.mean_damaged_fraction * 100.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn painted(x: f64, y: f64, width: f64, height: f64, signature: u64) -> Painted {
+        Painted {
+            rect: DamageRect::new(x, y, width, height),
+            signature,
+        }
+    }
+
+    #[test]
+    fn changed_elements_damage_their_old_and_new_boxes() {
+        let before = HashMap::from([
+            (1, painted(0.0, 0.0, 800.0, 2000.0, 1)),
+            (2, painted(10.0, 100.0, 60.0, 20.0, 7)),
+            (3, painted(10.0, 300.0, 200.0, 40.0, 3)),
+            (4, painted(10.0, 500.0, 100.0, 20.0, 4)),
+        ]);
+        let mut after = before.clone();
+        // A counter's text changed; a box moved; one element went away and one appeared.
+        after.insert(2, painted(10.0, 100.0, 60.0, 20.0, 8));
+        after.insert(3, painted(10.0, 320.0, 200.0, 40.0, 3));
+        after.remove(&4);
+        after.insert(5, painted(400.0, 100.0, 50.0, 20.0, 5));
+
+        let mut rects = changed(&before, &after);
+        rects.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+        assert_eq!(
+            merge(rects),
+            [
+                DamageRect::new(10.0, 100.0, 60.0, 20.0),
+                DamageRect::new(400.0, 100.0, 50.0, 20.0),
+                DamageRect::new(10.0, 300.0, 200.0, 60.0),
+                DamageRect::new(10.0, 500.0, 100.0, 20.0),
+            ]
+        );
+        assert!(changed(&before, &before).is_empty());
+
+        let scattered = (0..20)
+            .map(|index| DamageRect::new(0.0, index as f64 * 50.0, 10.0, 10.0))
+            .collect();
+        assert_eq!(merge(scattered), [DamageRect::new(0.0, 0.0, 10.0, 960.0)]);
+    }
+
+    #[test]
+    fn summary_counts_partial_full_and_clean_frames() {
+        let mut tracker = DamageTracker::default();
+        let screen = 800.0 * 600.0;
+        tracker.count(vec![DamageRect::new(0.0, 0.0, 800.0, 600.0)], screen);
+        tracker.count(vec![DamageRect::new(10.0, 10.0, 80.0, 60.0)], screen);
+        tracker.count(Vec::new(), screen);
+        let summary = tracker.summary();
+        assert_eq!(
+            (
+                summary.frames,
+                summary.full_frames,
+                summary.partial_frames,
+                summary.clean_frames
+            ),
+            (3, 1, 1, 1)
+        );
+        assert!((summary.mean_damaged_fraction - 1.01 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            summary.last_damage,
+            [DamageRect::new(10.0, 10.0, 80.0, 60.0)]
+        );
+        assert!(overlay_html(&summary).contains("1 partial · 1 full · 1 clean"));
+
+        tracker.reset();
+        assert_eq!(tracker.summary(), DamageSummary::default());
+    }
+}
@@ -0,0 +1,386 @@
+//! Remote debugging over Nostr direct messages, for kiosks that accept no inbound connections.
+//!
+//! Started with [`FLAG`], the browser reads the relays from `FRONTIER_RELAY_CONFIG` or the
+//! saved preferences and polls them for NIP-17 direct messages (NIP-59 gift wraps) to the
+//! debug identity in `FRONTIER_DEBUG_SECRET_KEY`. Messages whose seal is signed by a key in
+//! `FRONTIER_DEBUG_ALLOW` (npubs or hex keys, comma-separated) are run as commands and
+//! answered with a direct message; everything else is dropped unanswered. Only a few
+//! commands exist, each mapped onto an [`AutomationCommand`] the browser runs on its event
+//! loop:
+//!
+//! - `navigate <url>` loads a page, as if typed into the URL bar.
+//! - `screenshot` uploads a PNG of the window to the Blossom server in
+//!   `FRONTIER_DEBUG_BLOSSOM` and replies with its URL.
+//! - `console [n]` replies with the newest `n` (default [`DEFAULT_CONSOLE_LINES`]) console
+//!   messages and uncaught exceptions.
+//! - `help` lists the commands.
+//!
+//! Messages written before the browser started are ignored, so a restart does not replay
+//! old commands.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use blitz_shell::BlitzShellEvent;
+use nostr_sdk::nips::nip59::{extract_rumor, UnwrappedGift};
+use nostr_sdk::prelude::{
+    Client, Event, EventBuilder, EventId, Filter, FromBech32, Keys, Kind, PublicKey, Tag,
+    Timestamp, ToBech32,
+};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use url::Url;
+use winit::event_loop::EventLoopProxy;
+
+use crate::automation::full_app::{AutomationState, AutomationTask};
+use crate::automation::{
+    AutomationCommand, AutomationEvent, AutomationReply, AutomationResponse, AutomationStateHandle,
+};
+use crate::js::console::ConsoleEntry;
+use crate::publish::{self, SiteFile};
+
+pub const FLAG: &str = "--debug-channel";
+pub const DEFAULT_CONSOLE_LINES: usize = 20;
+/// Upper bound for `console <n>`, which keeps a reply within what relays accept.
+const MAX_CONSOLE_LINES: usize = 200;
+/// NIP-17 chat message, the rumor inside a gift wrap.
+const PRIVATE_MESSAGE_KIND: u16 = 14;
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How far NIP-59 lets a gift wrap's timestamp be backdated to hide when it was sent.
+const GIFT_WRAP_BACKDATE: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+const HELP: &str = "Commands: navigate <url>, screenshot, console [n], help";
+
+/// Where to listen, whom to obey and where screenshots go; see the module docs.
+pub struct DebugChannelConfig {
+    keys: Keys,
+    allowed: Vec<PublicKey>,
+    relays: Vec<Url>,
+    blossom: Option<Url>,
+}
+
+impl DebugChannelConfig {
+    pub fn from_env() -> Result<Self> {
+        let secret = std::env::var("FRONTIER_DEBUG_SECRET_KEY")
+            .context("FRONTIER_DEBUG_SECRET_KEY must hold the debug identity's nsec")?;
+        let keys = Keys::parse(secret.trim())
+            .map_err(|err| anyhow!("invalid FRONTIER_DEBUG_SECRET_KEY: {err}"))?;
+        let allowed = parse_allowlist(&std::env::var("FRONTIER_DEBUG_ALLOW").unwrap_or_default())?;
+        let relays = publish::relays_from_env()?;
+        if relays.is_empty() {
+            bail!("the debug channel needs at least one relay");
+        }
+        let blossom = std::env::var("FRONTIER_DEBUG_BLOSSOM")
+            .ok()
+            .map(|raw| Url::parse(raw.trim()))
+            .transpose()
+            .context("invalid FRONTIER_DEBUG_BLOSSOM")?;
+        Ok(Self {
+            keys,
+            allowed,
+            relays,
+            blossom,
+        })
+    }
+}
+
+/// The keys in a comma-separated list of npubs or hex public keys, of which there must be at
+/// least one.
+pub fn parse_allowlist(raw: &str) -> Result<Vec<PublicKey>> {
+    let allowed = raw
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            PublicKey::from_bech32(key)
+                .or_else(|_| PublicKey::from_hex(key))
+                .map_err(|err| anyhow!("invalid key {key} in FRONTIER_DEBUG_ALLOW: {err}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if allowed.is_empty() {
+        bail!("FRONTIER_DEBUG_ALLOW must list the npubs allowed to send debug commands");
+    }
+    Ok(allowed)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugCommand {
+    Navigate(String),
+    Screenshot,
+    Console(usize),
+    Help,
+}
+
+impl DebugCommand {
+    /// Parse a message; the error is the reply explaining what was wrong with it.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (name, argument) = match text.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (text, ""),
+        };
+        match (name.to_ascii_lowercase().as_str(), argument) {
+            ("navigate", "") => Err("navigate needs a URL".to_string()),
+            ("navigate", target) => Ok(Self::Navigate(target.to_string())),
+            ("screenshot", "") => Ok(Self::Screenshot),
+            ("console", "") => Ok(Self::Console(DEFAULT_CONSOLE_LINES)),
+            ("console", lines) => match lines.parse::<usize>() {
+                Ok(lines) if lines > 0 => Ok(Self::Console(lines.min(MAX_CONSOLE_LINES))),
+                _ => Err(format!("console takes a line count, not {lines:?}")),
+            },
+            ("help", "") => Ok(Self::Help),
+            _ => Err(format!("unknown command {text:?}. {HELP}")),
+        }
+    }
+}
+
+/// Queue for the browser to attach with `ReadmeApplication::attach_debug_channel`, and start
+/// polling the relays for commands on `handle`.
+pub fn spawn(
+    handle: &Handle,
+    config: DebugChannelConfig,
+    proxy: EventLoopProxy<BlitzShellEvent>,
+) -> AutomationStateHandle {
+    let state = Arc::new(AutomationState::new());
+    let channel = Channel {
+        config,
+        state: Arc::clone(&state),
+        proxy: Mutex::new(proxy),
+    };
+    handle.spawn(async move {
+        if let Err(err) = channel.run().await {
+            warn!(target = "debug_channel", error = %err, "debug channel stopped");
+        }
+    });
+    state
+}
+
+struct Channel {
+    config: DebugChannelConfig,
+    state: AutomationStateHandle,
+    /// Behind a mutex because the proxy is not `Sync` on every platform.
+    proxy: Mutex<EventLoopProxy<BlitzShellEvent>>,
+}
+
+impl Channel {
+    async fn run(&self) -> Result<()> {
+        let started = Timestamp::now();
+        let client = crate::relays::connect(&self.config.relays).await?;
+        let filter = Filter::new()
+            .kind(Kind::GiftWrap)
+            .pubkey(self.config.keys.public_key());
+        info!(
+            target = "debug_channel",
+            npub = %self.config.keys.public_key().to_bech32()?,
+            relays = self.config.relays.len(),
+            "listening for debug commands"
+        );
+
+        let mut seen: HashMap<EventId, Timestamp> = HashMap::new();
+        loop {
+            let since = Timestamp::now() - GIFT_WRAP_BACKDATE;
+            seen.retain(|_, created_at| *created_at >= since);
+            match client
+                .get_events_of(vec![filter.clone().since(since)], Some(QUERY_TIMEOUT))
+                .await
+            {
+                Ok(events) => {
+                    for event in events {
+                        if seen.insert(event.id(), event.created_at).is_none() {
+                            self.handle_gift_wrap(&client, &event, started).await;
+                        }
+                    }
+                }
+                Err(err) => warn!(target = "debug_channel", error = %err, "relay query failed"),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn handle_gift_wrap(&self, client: &Client, event: &Event, started: Timestamp) {
+        let Ok(UnwrappedGift { sender, rumor }) = extract_rumor(&self.config.keys, event) else {
+            return;
+        };
+        // The seal's signature vouches for `sender`; a rumor naming anyone else is forged.
+        if !self.config.allowed.contains(&sender) || rumor.pubkey != sender {
+            warn!(
+                target = "debug_channel",
+                sender = %sender.to_bech32().unwrap_or_else(|_| sender.to_hex()),
+                "ignoring a message from a key that is not allowed to debug"
+            );
+            return;
+        }
+        if rumor.kind != Kind::from(PRIVATE_MESSAGE_KIND) || rumor.created_at < started {
+            return;
+        }
+
+        info!(target = "debug_channel", command = %rumor.content, "debug command");
+        let reply = match DebugCommand::parse(&rumor.content) {
+            Ok(command) => self
+                .execute(command)
+                .await
+                .unwrap_or_else(|err| format!("failed: {err:#}")),
+            Err(message) => message,
+        };
+        if let Err(err) = self.reply(client, sender, &reply).await {
+            warn!(target = "debug_channel", error = %err, "could not send the reply");
+        }
+    }
+
+    async fn execute(&self, command: DebugCommand) -> Result<String> {
+        match command {
+            DebugCommand::Navigate(target) => {
+                let command = AutomationCommand::Navigate {
+                    target: target.clone(),
+                };
+                self.run_command(command).await?;
+                Ok(format!("navigating to {target}"))
+            }
+            DebugCommand::Screenshot => {
+                let reply = self
+                    .run_command(AutomationCommand::Screenshot { selector: None })
+                    .await?;
+                let png = reply
+                    .artifacts
+                    .and_then(|artifacts| artifacts.images.into_iter().next())
+                    .map(|(_, png)| png)
+                    .context("the browser returned no screenshot")?;
+                self.upload_screenshot(png).await
+            }
+            DebugCommand::Console(limit) => {
+                let reply = self
+                    .run_command(AutomationCommand::ConsoleLog { limit })
+                    .await?;
+                let AutomationResponse::Text(json) = reply.response else {
+                    bail!("the browser returned no console log");
+                };
+                let entries: Vec<ConsoleEntry> = serde_json::from_str(&json)?;
+                Ok(format_console(&entries))
+            }
+            DebugCommand::Help => Ok(HELP.to_string()),
+        }
+    }
+
+    /// Hand `command` to the browser's event loop and wait for its reply.
+    async fn run_command(&self, command: AutomationCommand) -> Result<AutomationReply> {
+        let (responder, reply) = oneshot::channel();
+        self.state.enqueue(AutomationTask::new(command, responder));
+        self.proxy
+            .lock()
+            .unwrap()
+            .send_event(BlitzShellEvent::Embedder(Arc::new(AutomationEvent)))
+            .map_err(|_| anyhow!("the browser has exited"))?;
+        reply
+            .await
+            .map_err(|_| anyhow!("the browser dropped the command"))?
+    }
+
+    async fn upload_screenshot(&self, png: Vec<u8>) -> Result<String> {
+        let Some(server) = &self.config.blossom else {
+            return Ok(format!(
+                "took a {} KiB screenshot, but FRONTIER_DEBUG_BLOSSOM is not set to upload it",
+                png.len().div_ceil(1024)
+            ));
+        };
+        let file = SiteFile {
+            path: "/screenshot.png".to_string(),
+            sha256: publish::sha256_hex(&png),
+            bytes: png,
+            mime_type: "image/png",
+        };
+        let http = crate::http_client::shared()?;
+        publish::upload_blob(&http, server, &self.config.keys, &file)
+            .await
+            .with_context(|| format!("uploading the screenshot to {server}"))?;
+        Ok(server.join(&format!("{}.png", file.sha256))?.to_string())
+    }
+
+    /// Send `text` to `receiver` as a NIP-17 direct message from the debug identity.
+    async fn reply(&self, client: &Client, receiver: PublicKey, text: &str) -> Result<()> {
+        let keys = &self.config.keys;
+        let rumor = EventBuilder::new(
+            Kind::from(PRIVATE_MESSAGE_KIND),
+            text,
+            [Tag::public_key(receiver)],
+        )
+        .to_unsigned_event(keys.public_key());
+        let wrap = EventBuilder::gift_wrap(keys, &receiver, rumor, None)?;
+        client.send_event(wrap).await?;
+        Ok(())
+    }
+}
+
+/// Console entries as `[level] message` lines.
+fn format_console(entries: &[ConsoleEntry]) -> String {
+    if entries.is_empty() {
+        return "the console is empty".to_string();
+    }
+    entries
+        .iter()
+        .map(|entry| format!("[{}] {}", entry.level, entry.message))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_allowed_commands() {
+        assert_eq!(
+            DebugCommand::parse("  navigate https://example.com/ "),
+            Ok(DebugCommand::Navigate("https://example.com/".to_string()))
+        );
+        assert_eq!(
+            DebugCommand::parse("Screenshot"),
+            Ok(DebugCommand::Screenshot)
+        );
+        assert_eq!(
+            DebugCommand::parse("console"),
+            Ok(DebugCommand::Console(DEFAULT_CONSOLE_LINES))
+        );
+        assert_eq!(
+            DebugCommand::parse("console 5000"),
+            Ok(DebugCommand::Console(MAX_CONSOLE_LINES))
+        );
+        assert_eq!(DebugCommand::parse("help"), Ok(DebugCommand::Help));
+
+        assert!(DebugCommand::parse("navigate").is_err());
+        assert!(DebugCommand::parse("console -1").is_err());
+        assert!(DebugCommand::parse("screenshot now").is_err());
+        assert!(DebugCommand::parse("eval alert(1)").is_err());
+    }
+
+    #[test]
+    fn allowlist_takes_npubs_and_hex_keys() {
+        let first = Keys::generate().public_key();
+        let second = Keys::generate().public_key();
+        let raw = format!("{}, {}", first.to_bech32().unwrap(), second.to_hex());
+        assert_eq!(parse_allowlist(&raw).unwrap(), vec![first, second]);
+
+        assert!(parse_allowlist(" , ").is_err());
+        assert!(parse_allowlist("npub1nope").is_err());
+    }
+
+    #[test]
+    fn formats_console_lines() {
+        let entry = |level: &str, message: &str| ConsoleEntry {
+            level: level.to_string(),
+            message: message.to_string(),
+            timestamp_ms: 0,
+        };
+        assert_eq!(format_console(&[]), "the console is empty");
+        assert_eq!(
+            format_console(&[
+                entry("log", "ready"),
+                entry("error", "Uncaught TypeError: x")
+            ]),
+            "[log] ready\n[error] Uncaught TypeError: x"
+        );
+    }
+}
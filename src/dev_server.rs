@@ -0,0 +1,233 @@
+//! Static file server backing `frontier serve <dir>`.
+//!
+//! Pages loaded over `file://` cannot use root-relative paths or `fetch()` against sibling
+//! files, so during development we expose the directory on a loopback HTTP origin instead.
+
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use percent_encoding::percent_decode_str;
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+const INDEX_FILE: &str = "index.html";
+
+struct DevServerState {
+    root: PathBuf,
+}
+
+/// Running static file server. Dropping the handle also stops the server; use
+/// [`DevServerHandle::shutdown`] to wait for in-flight requests to finish.
+pub struct DevServerHandle {
+    addr: SocketAddr,
+    root: PathBuf,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl DevServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Base URL the browser should open, always ending in `/`.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        let _ = self.task.await;
+    }
+}
+
+/// Serve `root` over HTTP on `addr` (use port 0 for an ephemeral port).
+pub async fn start_dev_server(root: impl AsRef<Path>, addr: SocketAddr) -> Result<DevServerHandle> {
+    let root = root.as_ref();
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("unable to resolve serve directory {}", root.display()))?;
+    if !root.is_dir() {
+        return Err(anyhow!("{} is not a directory", root.display()));
+    }
+
+    let state = Arc::new(DevServerState { root: root.clone() });
+    let router = Router::new().fallback(serve_path).with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("unable to bind dev server to {addr}"))?;
+    let local_addr = listener.local_addr()?;
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+    let task = tokio::spawn(async move {
+        let graceful =
+            axum::serve(listener, router.into_make_service()).with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            });
+        if let Err(err) = graceful.await {
+            error!(target = "dev_server", error = %err, "dev server error");
+        }
+    });
+
+    Ok(DevServerHandle {
+        addr: local_addr,
+        root,
+        shutdown_tx: Some(shutdown_tx),
+        task,
+    })
+}
+
+async fn serve_path(
+    State(state): State<Arc<DevServerState>>,
+    method: Method,
+    uri: Uri,
+) -> Response {
+    if method != Method::GET && method != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let Some(relative) = sanitize_request_path(uri.path()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let Some(path) = resolve_file(&state.root, &relative).await else {
+        debug!(target = "dev_server", path = %uri.path(), "not found");
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => {
+            let body = if method == Method::HEAD {
+                Body::empty()
+            } else {
+                Body::from(bytes)
+            };
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, mime_type_for(&path))
+                .header(header::CACHE_CONTROL, "no-cache")
+                .body(body)
+                .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(err) => {
+            error!(target = "dev_server", path = %path.display(), error = %err, "failed to read file");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Decode a request path into a relative filesystem path, rejecting anything that would
+/// escape the served root.
+fn sanitize_request_path(raw: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(raw).decode_utf8().ok()?;
+    let mut relative = PathBuf::new();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(relative)
+}
+
+/// Map a relative request path to a file. Directories resolve to their `index.html`, and
+/// extensionless paths that do not exist fall back to the root `index.html` so client-side
+/// routers keep working after a reload.
+async fn resolve_file(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let candidate = root.join(relative);
+    match tokio::fs::metadata(&candidate).await {
+        Ok(meta) if meta.is_file() => return Some(candidate),
+        Ok(meta) if meta.is_dir() => {
+            let index = candidate.join(INDEX_FILE);
+            return is_file(&index).await.then_some(index);
+        }
+        _ => {}
+    }
+
+    if relative.extension().is_none() {
+        let index = root.join(INDEX_FILE);
+        if is_file(&index).await {
+            return Some(index);
+        }
+    }
+
+    None
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .map(|meta| meta.is_file())
+        .unwrap_or(false)
+}
+
+pub fn mime_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_parent_traversal() {
+        assert!(sanitize_request_path("/../secret").is_none());
+        assert!(sanitize_request_path("/a/%2e%2e/%2e%2e/secret").is_none());
+        assert_eq!(
+            sanitize_request_path("/css/site.css"),
+            Some(PathBuf::from("css/site.css"))
+        );
+        assert_eq!(sanitize_request_path("/"), Some(PathBuf::new()));
+    }
+
+    #[test]
+    fn mime_types_cover_common_assets() {
+        assert_eq!(
+            mime_type_for(Path::new("a.JS")),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(mime_type_for(Path::new("a.wasm")), "application/wasm");
+        assert_eq!(mime_type_for(Path::new("a")), "application/octet-stream");
+    }
+}
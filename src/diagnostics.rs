@@ -0,0 +1,334 @@
+//! Approximate memory held by each subsystem, behind `about:memory` and the automation `memory`
+//! endpoint used by leak-detection tests.
+//!
+//! The figures are what each subsystem can cheaply account for, not an allocator-level
+//! breakdown: QuickJS reports its own heap through its allocator hooks, the DOM is counted in
+//! nodes (including detached ones the document still holds), and loaded assets and prefetched
+//! documents are counted by the bytes that were received for them. Below that, each origin
+//! that ran scripts is listed with its runtime quota and how often its scripts hit it (see
+//! [`crate::js::isolation`]).
+//!
+//! `about:version` names the build and the renderer drawing the window, with the reason when
+//! it fell back from the GPU (see [`crate::renderer`]). `frontier://gpu` adds the adapters
+//! wgpu can see and what pages get of them: canvases draw on the CPU and WebGL is not
+//! offered, so `getContext('webgl')` gives `null`.
+
+use blitz_dom::BaseDocument;
+use html_escape::encode_text;
+use serde::{Deserialize, Serialize};
+
+use crate::js::isolation::OriginUsage;
+use crate::js::runtime::JsHeapUsage;
+use crate::network_log::{format_size, Initiator, NetworkEntry};
+use crate::renderer::{Backend, GpuAdapter, Selection};
+
+pub const MEMORY_URL: &str = "about:memory";
+pub const VERSION_URL: &str = "about:version";
+pub const GPU_URL: &str = "frontier://gpu";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryReport {
+    /// Page the report describes.
+    pub url: String,
+    /// `None` when the page has no script runtime.
+    pub js_heap: Option<JsHeapUsage>,
+    /// Nodes allocated in the document, attached or not.
+    pub dom_nodes: usize,
+    /// Images, fonts and stylesheets the page loaded.
+    pub asset_count: usize,
+    pub asset_bytes: u64,
+    /// Documents loaded ahead of navigation and not used yet.
+    pub prefetched_documents: usize,
+    pub prefetched_bytes: u64,
+    /// Script runtime quotas and what hit them, by origin.
+    pub origins: Vec<OriginUsage>,
+}
+
+impl MemoryReport {
+    pub fn dom_nodes(doc: &BaseDocument) -> usize {
+        doc.tree().len()
+    }
+
+    /// Count the subresources in `entries` that completed.
+    pub fn record_assets(&mut self, entries: &[NetworkEntry]) {
+        for entry in entries {
+            if entry.initiator == Initiator::Subresource {
+                if let Some(size) = entry.size {
+                    self.asset_count += 1;
+                    self.asset_bytes += size;
+                }
+            }
+        }
+    }
+}
+
+pub fn memory_page(report: &MemoryReport) -> String {
+    let js = match &report.js_heap {
+        Some(heap) => format!(
+            "<tr><td>QuickJS heap</td><td>{}</td><td>{} allocations, {} objects, {} functions</td></tr>",
+            format_size(heap.allocated_bytes),
+            heap.allocations,
+            heap.objects,
+            heap.functions,
+        ),
+        None => "<tr><td>QuickJS heap</td><td>—</td><td>no script runtime</td></tr>".to_string(),
+    };
+    let origins = if report.origins.is_empty() {
+        "<tr><td colspan=\"6\">No page has run scripts yet.</td></tr>".to_string()
+    } else {
+        report
+            .origins
+            .iter()
+            .map(|usage| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{} s</td><td>{}</td><td>{}</td></tr>",
+                    encode_text(&usage.origin),
+                    usage.runtimes,
+                    format_size(usage.memory_limit_bytes),
+                    usage.script_budget_ms as f64 / 1000.0,
+                    usage.out_of_memory,
+                    usage.interrupted_scripts,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    format!(
+        r#"<section class="memory">
+<h1>Memory</h1>
+<p>Snapshot of <code>{url}</code>, taken when it was left for this page.</p>
+<table>
+<thead><tr><th>Subsystem</th><th>Size</th><th>Detail</th></tr></thead>
+<tbody>
+{js}
+<tr><td>DOM</td><td>—</td><td>{nodes} nodes</td></tr>
+<tr><td>Images, fonts and stylesheets</td><td>{asset_bytes}</td><td>{assets} loaded</td></tr>
+<tr><td>Prefetched documents</td><td>{prefetched_bytes}</td><td>{prefetched} held</td></tr>
+</tbody>
+</table>
+<h2>Script isolation</h2>
+<table>
+<thead><tr>
+<th>Origin</th><th>Runtimes</th><th>Heap limit</th><th>Time budget</th>
+<th>Out of memory</th><th>Interrupted</th>
+</tr></thead>
+<tbody>
+{origins}
+</tbody>
+</table>
+</section>"#,
+        url = encode_text(&report.url),
+        nodes = report.dom_nodes,
+        asset_bytes = format_size(report.asset_bytes),
+        assets = report.asset_count,
+        prefetched_bytes = format_size(report.prefetched_bytes),
+        prefetched = report.prefetched_documents,
+    )
+}
+
+/// The renderer drawing the window and how it was picked, as table cells.
+fn renderer_cells(renderer: Option<&Selection>) -> (&'static str, String) {
+    match renderer {
+        Some(selection) => {
+            let backend = match selection.backend {
+                Backend::Gpu => "GPU (vello)",
+                Backend::Cpu => "CPU (vello_cpu)",
+            };
+            let detail = match (&selection.fallback, &selection.adapter) {
+                _ if selection.forced => "chosen with <code>--renderer</code>".to_string(),
+                (Some(reason), _) => format!("fell back from the GPU: {}", encode_text(reason)),
+                (None, Some(adapter)) => encode_text(adapter).into_owned(),
+                (None, None) => "the only renderer in this build".to_string(),
+            };
+            (backend, detail)
+        }
+        None => ("—", "not chosen at runtime".to_string()),
+    }
+}
+
+/// HTML body of `about:version`; `renderer` is `None` when no backend was picked at runtime.
+pub fn version_page(renderer: Option<&Selection>) -> String {
+    let (backend, detail) = renderer_cells(renderer);
+    format!(
+        r#"<section class="version">
+<h1>Frontier {version}</h1>
+<table>
+<tbody>
+<tr><td>Platform</td><td>{os} {arch}</td></tr>
+<tr><td>Renderer</td><td>{backend}</td></tr>
+<tr><td>Renderer detail</td><td>{detail}</td></tr>
+</tbody>
+</table>
+</section>"#,
+        version = env!("CARGO_PKG_VERSION"),
+        os = std::env::consts::OS,
+        arch = std::env::consts::ARCH,
+    )
+}
+
+/// HTML body of `frontier://gpu`: the renderer, what pages can use, and `adapters`, the
+/// adapters wgpu found or why it could not look.
+pub fn gpu_page(
+    renderer: Option<&Selection>,
+    adapters: &Result<Vec<GpuAdapter>, String>,
+) -> String {
+    let (backend, detail) = renderer_cells(renderer);
+    let adapters = match adapters {
+        Ok(adapters) if adapters.is_empty() => "<p>wgpu found no adapters.</p>".to_string(),
+        Ok(adapters) => {
+            let rows: String = adapters
+                .iter()
+                .map(|adapter| {
+                    let driver = [adapter.driver.as_str(), adapter.driver_info.as_str()]
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    format!(
+                        "<tr><td>{name}</td><td>{kind}</td><td>{backend}</td><td>{driver}</td>\
+                         <td>{size}px</td><td><code>{vendor:04x}:{device:04x}</code></td></tr>\n",
+                        name = encode_text(&adapter.name),
+                        kind = adapter.device_type,
+                        backend = encode_text(&adapter.backend),
+                        driver = encode_text(&driver),
+                        size = adapter.max_texture_size,
+                        vendor = adapter.vendor,
+                        device = adapter.device,
+                    )
+                })
+                .collect();
+            format!(
+                "<table>\n<thead><tr><th>Adapter</th><th>Type</th><th>Backend</th><th>Driver</th>\
+                 <th>Largest texture</th><th>Vendor:device</th></tr></thead>\n<tbody>\n{rows}\
+                 </tbody>\n</table>"
+            )
+        }
+        Err(reason) => format!(
+            "<p>Adapters could not be listed: {}.</p>",
+            encode_text(reason)
+        ),
+    };
+    format!(
+        r#"<section class="gpu">
+<h1>GPU</h1>
+<table>
+<tbody>
+<tr><td>Renderer</td><td>{backend}</td></tr>
+<tr><td>Renderer detail</td><td>{detail}</td></tr>
+<tr><td>Canvas 2D</td><td>drawn on the CPU (vello_cpu)</td></tr>
+<tr><td>WebGL</td><td>not available: <code>getContext('webgl')</code> and
+<code>getContext('webgl2')</code> return <code>null</code> after a
+<code>webglcontextcreationerror</code> event</td></tr>
+<tr><td>WebGPU</td><td>not available: <code>navigator.gpu</code> is undefined</td></tr>
+</tbody>
+</table>
+<h2>Adapters</h2>
+{adapters}
+</section>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_log::RequestSource;
+
+    fn entry(initiator: Initiator, size: Option<u64>) -> NetworkEntry {
+        NetworkEntry {
+            url: "https://a.example/x".to_string(),
+            method: "GET".to_string(),
+            initiator,
+            source: RequestSource::Http,
+            status: None,
+            size,
+            start_ms: 0.0,
+            duration_ms: size.map(|_| 1.0),
+            error: None,
+            response_headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    #[test]
+    fn counts_completed_subresources_only() {
+        let mut report = MemoryReport {
+            url: "https://a.example/".to_string(),
+            ..Default::default()
+        };
+        report.record_assets(&[
+            entry(Initiator::Document, Some(4096)),
+            entry(Initiator::Subresource, Some(1024)),
+            entry(Initiator::Subresource, Some(2048)),
+            entry(Initiator::Subresource, None),
+            entry(Initiator::Fetch, Some(10)),
+        ]);
+
+        assert_eq!(report.asset_count, 2);
+        assert_eq!(report.asset_bytes, 3072);
+        let page = memory_page(&report);
+        assert!(page.contains("3.0 KB"), "{page}");
+        assert!(page.contains("no script runtime"), "{page}");
+        assert!(page.contains("No page has run scripts yet."), "{page}");
+    }
+
+    #[test]
+    fn lists_script_quotas_by_origin() {
+        let report = MemoryReport {
+            url: "https://a.example/".to_string(),
+            origins: vec![OriginUsage {
+                origin: "https://a.example".to_string(),
+                runtimes: 2,
+                memory_limit_bytes: 256 * 1024 * 1024,
+                script_budget_ms: 2500,
+                storage_area: Some("https://a.example".to_string()),
+                interrupted_scripts: 3,
+                out_of_memory: 1,
+            }],
+            ..Default::default()
+        };
+        let page = memory_page(&report);
+        assert!(
+            page.contains(
+                "<tr><td>https://a.example</td><td>2</td><td>256.0 MB</td><td>2.5 s</td>\
+                 <td>1</td><td>3</td></tr>"
+            ),
+            "{page}"
+        );
+    }
+
+    #[test]
+    fn version_page_explains_a_renderer_fallback() {
+        let fallback = Selection {
+            backend: Backend::Cpu,
+            forced: false,
+            adapter: None,
+            fallback: Some("only a software adapter: <llvmpipe>".to_string()),
+        };
+        let page = version_page(Some(&fallback));
+        assert!(page.contains("CPU (vello_cpu)"), "{page}");
+        assert!(page.contains("fell back from the GPU: only a software adapter: &lt;llvmpipe&gt;"));
+        assert!(version_page(None).contains("not chosen at runtime"));
+    }
+
+    #[test]
+    fn gpu_page_lists_adapters_or_why_there_are_none() {
+        let adapter = GpuAdapter {
+            name: "Test <GPU>".to_string(),
+            device_type: "discrete",
+            backend: "vulkan".to_string(),
+            vendor: 0x10de,
+            device: 0x2684,
+            driver: "NVIDIA".to_string(),
+            driver_info: String::new(),
+            max_texture_size: 32768,
+        };
+        let page = gpu_page(None, &Ok(vec![adapter]));
+        assert!(page.contains("<td>Test &lt;GPU&gt;</td><td>discrete</td><td>vulkan</td>"));
+        assert!(page.contains("<td>NVIDIA</td><td>32768px</td><td><code>10de:2684</code>"));
+        assert!(page.contains("webglcontextcreationerror"), "{page}");
+
+        let page = gpu_page(None, &Err("built without the gpu feature".to_string()));
+        assert!(page.contains("could not be listed: built without the gpu feature."));
+        assert!(gpu_page(None, &Ok(Vec::new())).contains("wgpu found no adapters"));
+    }
+}
@@ -0,0 +1,250 @@
+//! The `:hover`, `:active` and `:focus` state of elements, kept in step with the pointer.
+//!
+//! blitz's own hit testing marks only the node under the pointer as hovered, usually a text
+//! node with no style of its own, so rules such as `button:hover` or `a:active` did not apply.
+//! [`ElementStates`] tracks the elements in those states the way browsers define them: the
+//! element under the pointer and all its ancestors are hovered, the same chain becomes active
+//! while the primary button is down, and pressing on a focusable element focuses it. Only
+//! elements whose state changed get a restyle hint, so the next frame restyles those subtrees
+//! instead of the whole document.
+
+use blitz_dom::{local_name, BaseDocument, Node};
+
+/// Elements currently hovered, active and focused in one document. Node ids are only valid for
+/// the document they came from, so start a new tracker when the document is replaced.
+#[derive(Debug, Default)]
+pub struct ElementStates {
+    /// The element under the pointer followed by its ancestors.
+    hovered: Vec<usize>,
+    active: Vec<usize>,
+    focused: Option<usize>,
+}
+
+impl ElementStates {
+    /// Hover the elements at and above `target`, the node under the pointer, or none when the
+    /// pointer left the page. Returns whether any element's state changed.
+    pub fn hover(&mut self, doc: &mut BaseDocument, target: Option<usize>) -> bool {
+        let chain = target.map_or_else(Vec::new, |target| element_chain(doc, target));
+        let changed = update(
+            doc,
+            &self.hovered,
+            &chain,
+            Node::is_hovered,
+            Node::hover,
+            Node::unhover,
+        );
+        self.hovered = chain;
+        changed
+    }
+
+    /// The primary button went down: the hovered elements become active, and the nearest
+    /// focusable one takes the focus (or the focus is dropped when there is none).
+    pub fn press(&mut self, doc: &mut BaseDocument) -> bool {
+        let chain = self.hovered.clone();
+        let mut changed = update(
+            doc,
+            &self.active,
+            &chain,
+            Node::is_active,
+            Node::active,
+            Node::unactive,
+        );
+        self.active = chain;
+        let focusable = self
+            .hovered
+            .iter()
+            .copied()
+            .find(|&node_id| doc.get_node(node_id).is_some_and(is_focusable));
+        changed |= self.focus(doc, focusable);
+        changed
+    }
+
+    /// The primary button came up, or the window lost it: nothing is active any more.
+    pub fn release(&mut self, doc: &mut BaseDocument) -> bool {
+        let changed = update(
+            doc,
+            &self.active,
+            &[],
+            Node::is_active,
+            Node::active,
+            Node::unactive,
+        );
+        self.active.clear();
+        changed
+    }
+
+    /// Move the focus to `target`, or drop it.
+    pub fn focus(&mut self, doc: &mut BaseDocument, target: Option<usize>) -> bool {
+        let old: Vec<usize> = self.focused.into_iter().collect();
+        let new: Vec<usize> = target.into_iter().collect();
+        self.focused = target;
+        update(doc, &old, &new, Node::is_focussed, Node::focus, Node::blur)
+    }
+
+    /// The focused element, if any.
+    pub fn focused(&self) -> Option<usize> {
+        self.focused
+    }
+}
+
+/// Clear the state on the elements of `old` that are not in `new` and set it on the elements of
+/// `new`, touching only those whose state is wrong. blitz may have hovered or unhovered some of
+/// them itself, so the state is checked on the node rather than inferred from `old`.
+fn update(
+    doc: &mut BaseDocument,
+    old: &[usize],
+    new: &[usize],
+    has: fn(&Node) -> bool,
+    set: fn(&mut Node),
+    clear: fn(&mut Node),
+) -> bool {
+    let mut changed = false;
+    for &node_id in old.iter().filter(|node_id| !new.contains(node_id)) {
+        let Some(node) = doc.get_node_mut(node_id) else {
+            continue;
+        };
+        if has(node) {
+            clear(node);
+            changed = true;
+        }
+    }
+    for &node_id in new {
+        let Some(node) = doc.get_node_mut(node_id) else {
+            continue;
+        };
+        if !has(node) {
+            set(node);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Whether the focused element at `node_id` uses arrow keys, Space and the like itself, so
+/// they should not scroll the page: form controls and editable content.
+pub fn takes_keys(doc: &BaseDocument, node_id: usize) -> bool {
+    let Some(node) = doc.get_node(node_id) else {
+        return false;
+    };
+    let editable = node
+        .attr(local_name!("contenteditable"))
+        .is_some_and(|value| !value.eq_ignore_ascii_case("false"));
+    let control = node.element_data().is_some_and(|element| {
+        matches!(
+            element.name.local,
+            local_name!("input")
+                | local_name!("textarea")
+                | local_name!("select")
+                | local_name!("button")
+        )
+    });
+    editable || control
+}
+
+/// Whether the element at `node_id` is an `<input type=password>`, whose value must not be
+/// written anywhere.
+pub fn is_password_field(doc: &BaseDocument, node_id: usize) -> bool {
+    doc.get_node(node_id).is_some_and(|node| {
+        node.element_data()
+            .is_some_and(|element| element.name.local == local_name!("input"))
+            && node
+                .attr(local_name!("type"))
+                .is_some_and(|kind| kind.eq_ignore_ascii_case("password"))
+    })
+}
+
+/// `node_id` (or its parent, for a text node) and every element above it.
+fn element_chain(doc: &BaseDocument, node_id: usize) -> Vec<usize> {
+    let mut chain = Vec::new();
+    let mut current = doc.get_node(node_id);
+    while let Some(node) = current {
+        if node.is_element() {
+            chain.push(node.id);
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    chain
+}
+
+/// Whether clicking the element gives it the focus: links, enabled form controls, and anything
+/// with a `tabindex`.
+fn is_focusable(node: &Node) -> bool {
+    let Some(element) = node.element_data() else {
+        return false;
+    };
+    if node.attr(local_name!("tabindex")).is_some() {
+        return true;
+    }
+    let enabled = node.attr(local_name!("disabled")).is_none();
+    match element.name.local {
+        local_name!("a") | local_name!("area") => node.attr(local_name!("href")).is_some(),
+        local_name!("input") => {
+            enabled
+                && !node
+                    .attr(local_name!("type"))
+                    .is_some_and(|kind| kind.eq_ignore_ascii_case("hidden"))
+        }
+        local_name!("button") | local_name!("select") | local_name!("textarea") => enabled,
+        local_name!("summary") => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    fn find(doc: &BaseDocument, id: &str) -> usize {
+        let mut stack = vec![doc.root_node().id];
+        while let Some(node_id) = stack.pop() {
+            let node = doc.get_node(node_id).unwrap();
+            if node.attr(local_name!("id")) == Some(id) {
+                return node_id;
+            }
+            stack.extend(node.children.iter().copied());
+        }
+        panic!("no #{id}");
+    }
+
+    #[test]
+    fn pointer_hovers_activates_and_focuses_element_chains() {
+        let html = r#"<html><body>
+            <div id="bar"><button id="save"><span id="label">Save</span></button></div>
+            <p id="text">Plain text</p>
+        </body></html>"#;
+        let mut doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        let (bar, save, label, text) = (
+            find(&doc, "bar"),
+            find(&doc, "save"),
+            find(&doc, "label"),
+            find(&doc, "text"),
+        );
+        let label_text = doc.get_node(label).unwrap().children[0];
+        let mut states = ElementStates::default();
+
+        assert!(states.hover(&mut doc, Some(label_text)));
+        assert!(!states.hover(&mut doc, Some(label_text)));
+        for node_id in [label, save, bar] {
+            assert!(doc.get_node(node_id).unwrap().is_hovered());
+        }
+
+        assert!(states.press(&mut doc));
+        assert!(doc.get_node(save).unwrap().is_active());
+        assert!(doc.get_node(save).unwrap().is_focussed());
+        assert_eq!(states.focused(), Some(save));
+        assert!(states.release(&mut doc));
+        assert!(!doc.get_node(bar).unwrap().is_active());
+
+        assert!(states.hover(&mut doc, Some(text)));
+        assert!(!doc.get_node(save).unwrap().is_hovered());
+        assert!(doc.get_node(text).unwrap().is_hovered());
+        states.press(&mut doc);
+        assert_eq!(states.focused(), None);
+        assert!(!doc.get_node(save).unwrap().is_focussed());
+
+        assert!(states.hover(&mut doc, None));
+        assert!(!doc.get_node(text).unwrap().is_hovered());
+    }
+}
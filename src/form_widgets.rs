@@ -0,0 +1,1355 @@
+//! How the host operates the form controls blitz draws but cannot operate: the dropdown of a
+//! `<select>`, the calendar of an `<input type=date>`, checkboxes and radio groups, dragging a
+//! range slider, and arrow-key stepping for number, date and range inputs.
+//!
+//! A [`FormWidget`] opens for a control the user clicked (or focused and pressed Enter, Space
+//! or Alt+Down) and is drawn as HTML in the chrome's overlay host, just below the control. Keys
+//! and clicks go to the open widget until it commits a value or closes. A [`Commit`] is written
+//! to the document the way markup states it, as the `selected` attribute of the chosen
+//! `<option>`, the `checked` attribute of a checkbox or radio button, or the input's `value`,
+//! and the host then fires `input` and `change` at the control through the page's runtime.
+//! Script sees the same attributes through `value`, `checked` and `selectedIndex`.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use blitz_dom::{local_name, ns, BaseDocument, DocumentMutator, LocalName, Node, QualName};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = ["Su", "Mo", "Tu", "We", "Th", "Fr", "Sa"];
+
+/// Keys an open widget or a focused control responds to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Enter,
+    Space,
+    Escape,
+    Character(char),
+}
+
+/// What a key or click did to an open widget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WidgetOutcome {
+    /// The widget handled it and needs redrawing.
+    Redraw,
+    /// The user chose a value: close the widget and apply it.
+    Commit(Commit),
+    /// Close the widget without changing the control.
+    Close,
+    /// Not meant for the widget, which should close and let the page have the event.
+    Ignored,
+}
+
+/// A value chosen for a control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Commit {
+    /// Select `option` in the `<select>` at `select`.
+    Option { select: usize, option: usize },
+    /// Make `value` the value of the input at `input`.
+    Value { input: usize, value: String },
+    /// Check or uncheck the checkbox or radio button at `input`.
+    Checked { input: usize, checked: bool },
+}
+
+impl Commit {
+    /// The control the value is for, where `input` and `change` are fired.
+    pub fn control(&self) -> usize {
+        match self {
+            Commit::Option { select, .. } => *select,
+            Commit::Value { input, .. } | Commit::Checked { input, .. } => *input,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlKind {
+    Select,
+    Number,
+    Date,
+    Range,
+    Checkbox,
+    Radio,
+}
+
+/// The enabled form control the host operates at or above `node_id`, e.g. the select whose
+/// text the pointer is over.
+pub fn control_at(doc: &BaseDocument, node_id: usize) -> Option<usize> {
+    let mut current = doc.get_node(node_id);
+    while let Some(node) = current {
+        if control_kind(doc, node.id).is_some() {
+            return Some(node.id);
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    None
+}
+
+fn control_kind(doc: &BaseDocument, node_id: usize) -> Option<ControlKind> {
+    let node = doc.get_node(node_id)?;
+    let element = node.element_data()?;
+    if node.attr(local_name!("disabled")).is_some() {
+        return None;
+    }
+    if element.name.local == local_name!("select") {
+        return Some(ControlKind::Select);
+    }
+    if element.name.local != local_name!("input") || node.attr(local_name!("readonly")).is_some() {
+        return None;
+    }
+    let kind = node.attr(local_name!("type")).unwrap_or("text");
+    [
+        ("number", ControlKind::Number),
+        ("date", ControlKind::Date),
+        ("range", ControlKind::Range),
+        ("checkbox", ControlKind::Checkbox),
+        ("radio", ControlKind::Radio),
+    ]
+    .into_iter()
+    .find(|(name, _)| kind.eq_ignore_ascii_case(name))
+    .map(|(_, control)| control)
+}
+
+fn is_element(node: &Node, name: LocalName) -> bool {
+    node.element_data()
+        .is_some_and(|element| element.name.local == name)
+}
+
+fn is_input_of_type(node: &Node, kind: &str) -> bool {
+    is_element(node, local_name!("input"))
+        && node
+            .attr(local_name!("type"))
+            .is_some_and(|value| value.eq_ignore_ascii_case(kind))
+}
+
+fn is_checked(doc: &BaseDocument, node_id: usize) -> bool {
+    doc.get_node(node_id)
+        .is_some_and(|node| node.attr(local_name!("checked")).is_some())
+}
+
+/// The [`step`] direction an arrow key means for the control at `node_id`, or `None` when the
+/// control leaves that key to the page. Selects and radio groups are lists, so Down moves to the
+/// next entry; only ranges and radio buttons take Left and Right, which move the text cursor in
+/// other inputs.
+pub fn arrow_direction(doc: &BaseDocument, node_id: usize, key: WidgetKey) -> Option<i32> {
+    let kind = control_kind(doc, node_id)?;
+    let horizontal = matches!(kind, ControlKind::Range | ControlKind::Radio);
+    let direction = match key {
+        WidgetKey::Up => 1,
+        WidgetKey::Down => -1,
+        WidgetKey::Right if horizontal => 1,
+        WidgetKey::Left if horizontal => -1,
+        _ => return None,
+    };
+    Some(match (kind, key) {
+        (ControlKind::Select, _) | (ControlKind::Radio, WidgetKey::Up | WidgetKey::Down) => {
+            -direction
+        }
+        (ControlKind::Checkbox, _) => return None,
+        _ => direction,
+    })
+}
+
+/// The value one step up (`direction` 1) or down (-1) from the current value of the control at
+/// `node_id`: the neighbouring enabled option of a select or radio button, or the input's value
+/// moved by its `step` within `min` and `max`. `None` when the control is already at the end of
+/// its range, or is a checkbox.
+pub fn step(doc: &BaseDocument, node_id: usize, direction: i32) -> Option<Commit> {
+    let node = doc.get_node(node_id)?;
+    let attr = |name| node.attr(name);
+    match control_kind(doc, node_id)? {
+        ControlKind::Select => {
+            if attr(local_name!("multiple")).is_some() {
+                return None;
+            }
+            let options = select_options(doc, node_id);
+            let current = selected_index(&options);
+            let next = next_enabled(&options, current, direction)?;
+            Some(Commit::Option {
+                select: node_id,
+                option: options[next].node_id,
+            })
+        }
+        ControlKind::Number => step_number(
+            attr(local_name!("value")).unwrap_or_default(),
+            attr(local_name!("step")),
+            attr(local_name!("min")),
+            attr(local_name!("max")),
+            direction,
+        )
+        .map(|value| Commit::Value {
+            input: node_id,
+            value,
+        }),
+        ControlKind::Date => step_date(
+            attr(local_name!("value")).unwrap_or_default(),
+            attr(local_name!("step")),
+            attr(local_name!("min")),
+            attr(local_name!("max")),
+            direction,
+        )
+        .map(|value| Commit::Value {
+            input: node_id,
+            value,
+        }),
+        ControlKind::Range => {
+            let (min, max) = range_bounds(node);
+            step_number(
+                &range_value(node).to_string(),
+                attr(local_name!("step")),
+                Some(&min.to_string()),
+                Some(&max.to_string()),
+                direction,
+            )
+            .map(|value| Commit::Value {
+                input: node_id,
+                value,
+            })
+        }
+        ControlKind::Radio => {
+            let mut group = radio_group(doc, node_id);
+            group.retain(|&radio| {
+                radio == node_id || control_kind(doc, radio) == Some(ControlKind::Radio)
+            });
+            let index = group.iter().position(|&radio| radio == node_id)? as i32;
+            let next = group[(index + direction).rem_euclid(group.len() as i32) as usize];
+            (next != node_id).then_some(Commit::Checked {
+                input: next,
+                checked: true,
+            })
+        }
+        ControlKind::Checkbox => None,
+    }
+}
+
+/// What clicking the node at `node_id` does to a checkbox or radio button: the node itself, or
+/// the control of a `<label>` the node is in. Checkboxes toggle; radio buttons become checked.
+pub fn activate(doc: &BaseDocument, node_id: usize) -> Option<Commit> {
+    let mut current = doc.get_node(node_id);
+    while let Some(node) = current {
+        let label = is_element(node, local_name!("label"));
+        let target = if label {
+            labeled_control(doc, node.id)
+        } else {
+            Some(node.id)
+        };
+        match target.map(|input| (input, control_kind(doc, input))) {
+            Some((input, Some(ControlKind::Checkbox))) => {
+                return Some(Commit::Checked {
+                    input,
+                    checked: !is_checked(doc, input),
+                })
+            }
+            Some((input, Some(ControlKind::Radio))) => {
+                return Some(Commit::Checked {
+                    input,
+                    checked: true,
+                })
+            }
+            _ if label => return None,
+            _ => {}
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    None
+}
+
+/// The control a `<label>` labels: the element its `for` names, or else the first input
+/// inside it.
+fn labeled_control(doc: &BaseDocument, label: usize) -> Option<usize> {
+    let node = doc.get_node(label)?;
+    let (root, wanted) = match node.attr(local_name!("for")) {
+        Some(id) => (doc.root_node().id, Some(id)),
+        None => (label, None),
+    };
+    let mut stack = vec![root];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        let found = match wanted {
+            Some(id) => node.attr(local_name!("id")) == Some(id),
+            None => is_element(node, local_name!("input")),
+        };
+        if found {
+            return Some(node_id);
+        }
+        stack.extend(node.children.iter().rev().copied());
+    }
+    None
+}
+
+/// The value of the range input at `node_id` for a pointer at document x-coordinate `x`,
+/// snapped to its `step`.
+pub fn range_value_at(doc: &BaseDocument, node_id: usize, x: f32) -> Option<Commit> {
+    if !is_range(doc, node_id) {
+        return None;
+    }
+    let node = doc.get_node(node_id)?;
+    let anchor = Anchor::of(doc, node_id);
+    let (min, max) = range_bounds(node);
+    let fraction = if anchor.width > 0.0 {
+        ((x - anchor.x) / anchor.width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    let mut value = min + f64::from(fraction) * (max - min);
+    let step = node.attr(local_name!("step"));
+    let value = if step.is_some_and(|step| step.trim().eq_ignore_ascii_case("any")) {
+        value.to_string()
+    } else {
+        let size = step
+            .and_then(parse_number)
+            .filter(|size| *size > 0.0)
+            .unwrap_or(1.0);
+        value = min + ((value - min) / size).round() * size;
+        if value > max {
+            value -= size;
+        }
+        format_number(value, [step, node.attr(local_name!("min"))])
+    };
+    Some(Commit::Value {
+        input: node_id,
+        value,
+    })
+}
+
+/// Whether the node at `node_id` is an enabled range input, which the host lets the user drag.
+pub fn is_range(doc: &BaseDocument, node_id: usize) -> bool {
+    control_kind(doc, node_id) == Some(ControlKind::Range)
+}
+
+/// `min` and `max` of a range input, 0 and 100 unless given.
+fn range_bounds(node: &Node) -> (f64, f64) {
+    let bound = |name, default| node.attr(name).and_then(parse_number).unwrap_or(default);
+    let min = bound(local_name!("min"), 0.0);
+    (min, bound(local_name!("max"), 100.0).max(min))
+}
+
+/// The value of a range input: its `value` kept within `min` and `max`, or halfway between
+/// them when it has none.
+fn range_value(node: &Node) -> f64 {
+    let (min, max) = range_bounds(node);
+    node.attr(local_name!("value"))
+        .and_then(parse_number)
+        .map_or(min + (max - min) / 2.0, |value| value.clamp(min, max))
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Check or uncheck the checkbox or radio button at `node_id`. Returns whether it changed.
+pub fn set_checked(doc: &mut BaseDocument, node_id: usize, checked: bool) -> bool {
+    let changed = is_checked(doc, node_id) != checked;
+    {
+        let mut mutator = DocumentMutator::new(doc);
+        if checked {
+            mutator.set_attribute(node_id, html_name(local_name!("checked")), "");
+        } else {
+            mutator.clear_attribute(node_id, html_name(local_name!("checked")));
+        }
+    }
+    sync_checkedness(doc, node_id);
+    changed
+}
+
+/// Bring the checkbox state blitz paints in line with the `checked` attribute of the node at
+/// `node_id`, and uncheck the rest of its group when it is a checked radio button. Runs
+/// whenever `checked` is set or removed, by the host or by script.
+pub fn sync_checkedness(doc: &mut BaseDocument, node_id: usize) {
+    let checked = is_checked(doc, node_id);
+    let radio = doc
+        .get_node(node_id)
+        .is_some_and(|node| is_input_of_type(node, "radio"));
+    let others: Vec<usize> = if checked && radio {
+        radio_group(doc, node_id)
+            .into_iter()
+            .filter(|&other| other != node_id && is_checked(doc, other))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    paint_checked(doc, node_id, checked);
+    for other in others {
+        DocumentMutator::new(doc).clear_attribute(other, html_name(local_name!("checked")));
+        paint_checked(doc, other, false);
+    }
+}
+
+fn paint_checked(doc: &mut BaseDocument, node_id: usize, checked: bool) {
+    let state = doc
+        .get_node_mut(node_id)
+        .and_then(|node| node.element_data_mut())
+        .and_then(|element| element.checkbox_input_checked_mut());
+    if let Some(state) = state {
+        *state = checked;
+    }
+}
+
+/// The radio buttons in the group of the one at `node_id`, in tree order: those with the same
+/// `name` in the same form. A radio button without a name is a group of its own.
+fn radio_group(doc: &BaseDocument, node_id: usize) -> Vec<usize> {
+    let name = doc
+        .get_node(node_id)
+        .and_then(|node| node.attr(local_name!("name")))
+        .filter(|name| !name.is_empty());
+    let Some(name) = name else {
+        return vec![node_id];
+    };
+    let owner = form_owner(doc, node_id);
+    let mut group = Vec::new();
+    let mut stack = vec![owner.unwrap_or(doc.root_node().id)];
+    while let Some(id) = stack.pop() {
+        let Some(node) = doc.get_node(id) else {
+            continue;
+        };
+        if is_input_of_type(node, "radio")
+            && node.attr(local_name!("name")) == Some(name)
+            && form_owner(doc, id) == owner
+        {
+            group.push(id);
+        }
+        stack.extend(node.children.iter().rev().copied());
+    }
+    group
+}
+
+/// The `<form>` the control at `node_id` is in.
+fn form_owner(doc: &BaseDocument, node_id: usize) -> Option<usize> {
+    let mut current = doc
+        .get_node(node_id)
+        .and_then(|node| node.parent)
+        .and_then(|parent| doc.get_node(parent));
+    while let Some(node) = current {
+        if is_element(node, local_name!("form")) {
+            return Some(node.id);
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    None
+}
+
+/// Write `commit` into the document. Returns whether the control's value changed, which is
+/// when `input` and `change` are due.
+pub fn apply(doc: &mut BaseDocument, commit: &Commit) -> bool {
+    match commit {
+        Commit::Option { select, option } => {
+            let options = select_options(doc, *select);
+            let current = selected_index(&options).map(|index| options[index].node_id);
+            let marked: Vec<usize> = options
+                .iter()
+                .filter(|entry| entry.marked && entry.node_id != *option)
+                .map(|entry| entry.node_id)
+                .collect();
+            let mut mutator = DocumentMutator::new(doc);
+            for node_id in marked {
+                mutator.clear_attribute(node_id, html_name(local_name!("selected")));
+            }
+            mutator.set_attribute(*option, html_name(local_name!("selected")), "");
+            current != Some(*option)
+        }
+        Commit::Value { input, value } => {
+            let current = doc
+                .get_node(*input)
+                .and_then(|node| node.attr(local_name!("value")))
+                .unwrap_or_default();
+            if current == value {
+                return false;
+            }
+            DocumentMutator::new(doc).set_attribute(*input, html_name(local_name!("value")), value);
+            true
+        }
+        Commit::Checked { input, checked } => set_checked(doc, *input, *checked),
+    }
+}
+
+fn html_name(local: LocalName) -> QualName {
+    QualName::new(None, ns!(html), local)
+}
+
+/// Where a widget is drawn: the control's border box in document coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Anchor {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Anchor {
+    fn of(doc: &BaseDocument, node_id: usize) -> Self {
+        let Some(node) = doc.get_node(node_id) else {
+            return Self::default();
+        };
+        let position = node.absolute_position(0.0, 0.0);
+        Self {
+            x: position.x,
+            y: position.y,
+            width: node.final_layout.size.width,
+            height: node.final_layout.size.height,
+        }
+    }
+
+    fn style(&self) -> String {
+        format!(
+            "left: {:.0}px; top: {:.0}px; min-width: {:.0}px;",
+            self.x,
+            self.y + self.height,
+            self.width
+        )
+    }
+}
+
+/// An open dropdown or calendar.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormWidget {
+    Select(SelectPopup),
+    Date(DatePopup),
+}
+
+impl FormWidget {
+    /// The widget for the control at `node_id`, if it has one: single-choice selects with at
+    /// least one option, and date inputs.
+    pub fn open(doc: &BaseDocument, node_id: usize) -> Option<Self> {
+        let node = doc.get_node(node_id)?;
+        let anchor = Anchor::of(doc, node_id);
+        match control_kind(doc, node_id)? {
+            ControlKind::Select => {
+                let listbox = node.attr(local_name!("multiple")).is_some()
+                    || node
+                        .attr(local_name!("size"))
+                        .and_then(|size| size.trim().parse::<u32>().ok())
+                        .is_some_and(|size| size > 1);
+                if listbox {
+                    return None;
+                }
+                let options = select_options(doc, node_id);
+                let selected = selected_index(&options);
+                let highlighted = selected.or_else(|| next_enabled(&options, None, 1))?;
+                Some(Self::Select(SelectPopup {
+                    select: node_id,
+                    options,
+                    selected,
+                    highlighted,
+                    anchor,
+                }))
+            }
+            ControlKind::Date => {
+                let attr = |name| node.attr(name).and_then(Date::parse);
+                let selected = attr(local_name!("value"));
+                let min = attr(local_name!("min"));
+                let max = attr(local_name!("max"));
+                let cursor = clamp_date(selected.unwrap_or_else(Date::today), min, max);
+                Some(Self::Date(DatePopup {
+                    input: node_id,
+                    selected,
+                    cursor,
+                    min,
+                    max,
+                    anchor,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// The control the widget belongs to.
+    pub fn control(&self) -> usize {
+        match self {
+            FormWidget::Select(popup) => popup.select,
+            FormWidget::Date(popup) => popup.input,
+        }
+    }
+
+    pub fn key(&mut self, key: WidgetKey) -> WidgetOutcome {
+        match self {
+            FormWidget::Select(popup) => popup.key(key),
+            FormWidget::Date(popup) => popup.key(key),
+        }
+    }
+
+    /// A click on `node_id`, which may be part of the widget or anywhere else in the window.
+    pub fn click(&mut self, doc: &BaseDocument, node_id: usize) -> WidgetOutcome {
+        let mut inside = false;
+        let mut current = doc.get_node(node_id);
+        while let Some(node) = current {
+            let attr = |name: &str| node.attr(LocalName::from(name));
+            match self {
+                FormWidget::Select(popup) => {
+                    if let Some(index) = attr("data-widget-option").and_then(|i| i.parse().ok()) {
+                        return popup.choose(index);
+                    }
+                }
+                FormWidget::Date(popup) => {
+                    if let Some(date) = attr("data-widget-date").and_then(Date::parse) {
+                        return popup.choose(date);
+                    }
+                    if let Some(months) = attr("data-widget-month").and_then(|m| m.parse().ok()) {
+                        popup.move_cursor(popup.cursor.add_months(months));
+                        return WidgetOutcome::Redraw;
+                    }
+                }
+            }
+            if node.attr(local_name!("id")) == Some("form-widget") {
+                inside = true;
+                break;
+            }
+            current = node.parent.and_then(|parent| doc.get_node(parent));
+        }
+        if inside {
+            WidgetOutcome::Redraw
+        } else {
+            WidgetOutcome::Ignored
+        }
+    }
+
+    /// Markup for the overlay host.
+    pub fn html(&self) -> String {
+        match self {
+            FormWidget::Select(popup) => popup.html(),
+            FormWidget::Date(popup) => popup.html(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SelectOption {
+    node_id: usize,
+    label: String,
+    /// Label of the `<optgroup>` the option is in.
+    group: Option<String>,
+    disabled: bool,
+    /// Whether the option has a `selected` attribute.
+    marked: bool,
+}
+
+/// The dropdown of a `<select>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectPopup {
+    select: usize,
+    options: Vec<SelectOption>,
+    /// Index of the option the select currently has.
+    selected: Option<usize>,
+    /// Index of the option Enter would choose.
+    highlighted: usize,
+    anchor: Anchor,
+}
+
+impl SelectPopup {
+    fn key(&mut self, key: WidgetKey) -> WidgetOutcome {
+        let moved = match key {
+            WidgetKey::Up => next_enabled(&self.options, Some(self.highlighted), -1),
+            WidgetKey::Down => next_enabled(&self.options, Some(self.highlighted), 1),
+            WidgetKey::Home | WidgetKey::PageUp => next_enabled(&self.options, None, 1),
+            WidgetKey::End | WidgetKey::PageDown => next_enabled(&self.options, None, -1),
+            WidgetKey::Enter | WidgetKey::Space => return self.choose(self.highlighted),
+            WidgetKey::Escape => return WidgetOutcome::Close,
+            WidgetKey::Character(typed) => self.type_ahead(typed),
+            WidgetKey::Left | WidgetKey::Right => return WidgetOutcome::Redraw,
+        };
+        if let Some(index) = moved {
+            self.highlighted = index;
+        }
+        WidgetOutcome::Redraw
+    }
+
+    /// The next enabled option after the highlighted one whose label starts with `typed`.
+    fn type_ahead(&self, typed: char) -> Option<usize> {
+        let typed = typed.to_lowercase().to_string();
+        let count = self.options.len();
+        (1..=count)
+            .map(|offset| (self.highlighted + offset) % count)
+            .find(|&index| {
+                let option = &self.options[index];
+                !option.disabled && option.label.to_lowercase().starts_with(&typed)
+            })
+    }
+
+    fn choose(&self, index: usize) -> WidgetOutcome {
+        match self.options.get(index) {
+            Some(option) if !option.disabled => WidgetOutcome::Commit(Commit::Option {
+                select: self.select,
+                option: option.node_id,
+            }),
+            _ => WidgetOutcome::Redraw,
+        }
+    }
+
+    fn html(&self) -> String {
+        let mut html = format!(
+            r#"<div id="form-widget" class="select-popup" role="listbox" style="{}">"#,
+            self.anchor.style()
+        );
+        let mut group = None;
+        for (index, option) in self.options.iter().enumerate() {
+            if option.group != group {
+                if let Some(label) = &option.group {
+                    html.push_str(&format!(
+                        r#"<div class="widget-group">{}</div>"#,
+                        encode_text(label)
+                    ));
+                }
+                group = option.group.clone();
+            }
+            let mut class = String::from("widget-option");
+            for (flag, name) in [
+                (Some(index) == self.selected, " selected"),
+                (index == self.highlighted, " highlighted"),
+                (option.disabled, " disabled"),
+            ] {
+                if flag {
+                    class.push_str(name);
+                }
+            }
+            html.push_str(&format!(
+                r#"<div class="{class}" role="option" aria-selected="{selected}" "#,
+                selected = Some(index) == self.selected,
+            ));
+            html.push_str(&format!(
+                r#"data-widget-option="{index}">{}</div>"#,
+                encode_text(&option.label)
+            ));
+        }
+        html.push_str("</div>");
+        html
+    }
+}
+
+/// The options of the select at `select` in tree order, including those in `<optgroup>`s.
+fn select_options(doc: &BaseDocument, select: usize) -> Vec<SelectOption> {
+    let mut options = Vec::new();
+    let Some(node) = doc.get_node(select) else {
+        return options;
+    };
+    for &child_id in &node.children {
+        let Some(child) = doc.get_node(child_id) else {
+            continue;
+        };
+        let Some(element) = child.element_data() else {
+            continue;
+        };
+        if element.name.local == local_name!("option") {
+            options.extend(select_option(doc, child_id, None, false));
+        } else if element.name.local == local_name!("optgroup") {
+            let label = child.attr(local_name!("label")).unwrap_or_default();
+            let disabled = child.attr(local_name!("disabled")).is_some();
+            for &option_id in &child.children {
+                options.extend(select_option(doc, option_id, Some(label), disabled));
+            }
+        }
+    }
+    options
+}
+
+fn select_option(
+    doc: &BaseDocument,
+    node_id: usize,
+    group: Option<&str>,
+    group_disabled: bool,
+) -> Option<SelectOption> {
+    let node = doc.get_node(node_id)?;
+    if node.element_data()?.name.local != local_name!("option") {
+        return None;
+    }
+    let label = match node.attr(local_name!("label")) {
+        Some(label) if !label.is_empty() => label.to_string(),
+        _ => node.text_content(),
+    };
+    Some(SelectOption {
+        node_id,
+        label: label.split_whitespace().collect::<Vec<_>>().join(" "),
+        group: group.map(str::to_string),
+        disabled: group_disabled || node.attr(local_name!("disabled")).is_some(),
+        marked: node.attr(local_name!("selected")).is_some(),
+    })
+}
+
+/// The option a single-choice select shows: the last one marked `selected`, or else the
+/// first enabled one.
+fn selected_index(options: &[SelectOption]) -> Option<usize> {
+    options
+        .iter()
+        .rposition(|option| option.marked)
+        .or_else(|| next_enabled(options, None, 1))
+}
+
+/// The first enabled option after `from` in `direction`, or from the start (or end) of the
+/// list when `from` is `None`.
+fn next_enabled(options: &[SelectOption], from: Option<usize>, direction: i32) -> Option<usize> {
+    let candidates: Box<dyn Iterator<Item = usize>> = match (from, direction > 0) {
+        (Some(from), true) => Box::new(from + 1..options.len()),
+        (Some(from), false) => Box::new((0..from).rev()),
+        (None, true) => Box::new(0..options.len()),
+        (None, false) => Box::new((0..options.len()).rev()),
+    };
+    candidates.find(|&index| !options[index].disabled)
+}
+
+/// The calendar of an `<input type=date>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatePopup {
+    input: usize,
+    /// The input's current value.
+    selected: Option<Date>,
+    /// The day Enter would choose; its month is the one shown.
+    cursor: Date,
+    min: Option<Date>,
+    max: Option<Date>,
+    anchor: Anchor,
+}
+
+impl DatePopup {
+    fn key(&mut self, key: WidgetKey) -> WidgetOutcome {
+        let cursor = self.cursor;
+        let moved = match key {
+            WidgetKey::Left => cursor.add_days(-1),
+            WidgetKey::Right => cursor.add_days(1),
+            WidgetKey::Up => cursor.add_days(-7),
+            WidgetKey::Down => cursor.add_days(7),
+            WidgetKey::PageUp => cursor.add_months(-1),
+            WidgetKey::PageDown => cursor.add_months(1),
+            WidgetKey::Home => Date { day: 1, ..cursor },
+            WidgetKey::End => Date {
+                day: days_in_month(cursor.year, cursor.month),
+                ..cursor
+            },
+            WidgetKey::Enter | WidgetKey::Space => return self.choose(cursor),
+            WidgetKey::Escape => return WidgetOutcome::Close,
+            WidgetKey::Character(_) => return WidgetOutcome::Redraw,
+        };
+        self.move_cursor(moved);
+        WidgetOutcome::Redraw
+    }
+
+    fn move_cursor(&mut self, date: Date) {
+        self.cursor = clamp_date(date, self.min, self.max);
+    }
+
+    fn in_range(&self, date: Date) -> bool {
+        self.min.is_none_or(|min| date >= min) && self.max.is_none_or(|max| date <= max)
+    }
+
+    fn choose(&self, date: Date) -> WidgetOutcome {
+        if !self.in_range(date) {
+            return WidgetOutcome::Redraw;
+        }
+        WidgetOutcome::Commit(Commit::Value {
+            input: self.input,
+            value: date.to_string(),
+        })
+    }
+
+    fn html(&self) -> String {
+        let Date { year, month, .. } = self.cursor;
+        let name = MONTHS[month as usize - 1];
+        let mut html = String::from(
+            r#"<div id="form-widget" class="date-popup" role="dialog" aria-label="Choose a date" "#,
+        );
+        html.push_str(&format!(
+            r#"style="{}"><div class="date-header">"#,
+            self.anchor.style()
+        ));
+        html.push_str(r#"<div class="date-nav" role="button" aria-label="Previous month" "#);
+        html.push_str(r#"data-widget-month="-1">&lsaquo;</div>"#);
+        html.push_str(&format!(r#"<div class="date-title">{name} {year}</div>"#));
+        html.push_str(r#"<div class="date-nav" role="button" aria-label="Next month" "#);
+        html.push_str(r#"data-widget-month="1">&rsaquo;</div></div><div class="date-grid">"#);
+        for weekday in WEEKDAYS {
+            html.push_str(&format!(r#"<div class="date-weekday">{weekday}</div>"#));
+        }
+        let first = Date {
+            year,
+            month,
+            day: 1,
+        };
+        for _ in 0..first.weekday() {
+            html.push_str(r#"<div class="date-blank"></div>"#);
+        }
+        for day in 1..=days_in_month(year, month) {
+            let date = Date { day, ..first };
+            let mut class = String::from("date-day");
+            for (flag, name) in [
+                (Some(date) == self.selected, " selected"),
+                (date == self.cursor, " highlighted"),
+                (!self.in_range(date), " disabled"),
+            ] {
+                if flag {
+                    class.push_str(name);
+                }
+            }
+            let label = encode_double_quoted_attribute(&format!("{day} {name} {year}"));
+            html.push_str(&format!(
+                r#"<div class="{class}" data-widget-date="{date}" aria-label="{label}">{day}</div>"#
+            ));
+        }
+        html.push_str("</div></div>");
+        html
+    }
+}
+
+/// A calendar date, as `<input type=date>` spells it (`YYYY-MM-DD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    year: i32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Parse a valid date string; anything else is `None`, as for the input's value.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(3, '-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if year.len() < 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        if !digits(year) || !digits(month) || !digits(day) {
+            return None;
+        }
+        let date = Self {
+            year: year.parse().ok().filter(|year| *year > 0)?,
+            month: month
+                .parse()
+                .ok()
+                .filter(|month| (1..=12).contains(month))?,
+            day: day.parse().ok()?,
+        };
+        (1..=days_in_month(date.year, date.month))
+            .contains(&date.day)
+            .then_some(date)
+    }
+
+    /// Today in UTC.
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self::from_days((seconds / 86_400) as i64)
+    }
+
+    /// Days since 1970-01-01.
+    fn days(self) -> i64 {
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from(self.month);
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    fn from_days(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            (shifted_month + 3) as u32
+        } else {
+            (shifted_month - 9) as u32
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self {
+            year: year as i32,
+            month,
+            day,
+        }
+    }
+
+    fn add_days(self, days: i64) -> Self {
+        Self::from_days(self.days() + days)
+    }
+
+    /// The same day `months` later, or the month's last day when it is shorter.
+    fn add_months(self, months: i32) -> Self {
+        let index = self.year * 12 + self.month as i32 - 1 + months;
+        let (year, month) = (index.div_euclid(12), index.rem_euclid(12) as u32 + 1);
+        Self {
+            year,
+            month,
+            day: self.day.min(days_in_month(year, month)),
+        }
+    }
+
+    /// Day of the week, 0 for Sunday.
+    fn weekday(self) -> u32 {
+        (self.days() + 4).rem_euclid(7) as u32
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn clamp_date(date: Date, min: Option<Date>, max: Option<Date>) -> Date {
+    let date = min.map_or(date, |min| date.max(min));
+    max.map_or(date, |max| date.min(max))
+}
+
+/// `value` stepped by `step` days (default 1) within `min` and `max`. An empty or invalid
+/// value starts from today.
+fn step_date(
+    value: &str,
+    step: Option<&str>,
+    min: Option<&str>,
+    max: Option<&str>,
+    direction: i32,
+) -> Option<String> {
+    let step = step
+        .and_then(|step| step.trim().parse::<i64>().ok())
+        .filter(|step| *step > 0)
+        .unwrap_or(1);
+    let (min, max) = (min.and_then(Date::parse), max.and_then(Date::parse));
+    let next = match Date::parse(value) {
+        Some(current) => {
+            let next = clamp_date(current.add_days(step * i64::from(direction)), min, max);
+            let forward = if direction > 0 {
+                next > current
+            } else {
+                next < current
+            };
+            forward.then_some(next)?
+        }
+        None => clamp_date(Date::today(), min, max),
+    };
+    Some(next.to_string())
+}
+
+/// `value` stepped by `step` (default 1) within `min` and `max`, snapped onto the steps
+/// counted from `min` (or 0) as browsers do. An empty or invalid value counts as 0.
+fn step_number(
+    value: &str,
+    step: Option<&str>,
+    min: Option<&str>,
+    max: Option<&str>,
+    direction: i32,
+) -> Option<String> {
+    let step = step.filter(|step| !step.trim().eq_ignore_ascii_case("any"));
+    let size = step
+        .and_then(parse_number)
+        .filter(|size| *size > 0.0)
+        .unwrap_or(1.0);
+    let (min_value, max_value) = (min.and_then(parse_number), max.and_then(parse_number));
+    if let (Some(min), Some(max)) = (min_value, max_value) {
+        if min > max {
+            return None;
+        }
+    }
+    let base = min_value.unwrap_or(0.0);
+    let current = parse_number(value).unwrap_or(0.0);
+    let steps = (current - base) / size;
+    let target = if (steps - steps.round()).abs() < 1e-9 {
+        steps.round() + f64::from(direction)
+    } else if direction > 0 {
+        steps.floor() + 1.0
+    } else {
+        steps.ceil() - 1.0
+    };
+    let mut next = base + target * size;
+    if let Some(max) = max_value {
+        if next > max {
+            next = base + ((max - base) / size + 1e-9).floor() * size;
+        }
+    }
+    if let Some(min) = min_value {
+        next = next.max(min);
+    }
+    let forward = if direction > 0 {
+        next > current
+    } else {
+        next < current
+    };
+    if parse_number(value).is_some() && !forward {
+        return None;
+    }
+    Some(format_number(next, [step, min]))
+}
+
+/// `value` with as many decimals as the more precise of `sources` has, and no trailing zeros.
+fn format_number(value: f64, sources: [Option<&str>; 2]) -> String {
+    let decimals = sources
+        .into_iter()
+        .flatten()
+        .map(|text| {
+            text.trim()
+                .split_once('.')
+                .map_or(0, |(_, frac)| frac.len())
+        })
+        .max()
+        .unwrap_or(0);
+    let mut text = format!("{value:.decimals$}");
+    if text.contains('.') {
+        text = text.trim_end_matches('0').trim_end_matches('.').to_string();
+    }
+    if text == "-0" {
+        String::from("0")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    fn find(doc: &BaseDocument, id: &str) -> usize {
+        let mut stack = vec![doc.root_node().id];
+        while let Some(node_id) = stack.pop() {
+            let node = doc.get_node(node_id).unwrap();
+            if node.attr(local_name!("id")) == Some(id) {
+                return node_id;
+            }
+            stack.extend(node.children.iter().copied());
+        }
+        panic!("no #{id}");
+    }
+
+    #[test]
+    fn select_popup_moves_past_disabled_options_and_commits() {
+        let html = r#"<html><body><select id="fruit">
+            <option value="a">Apple</option>
+            <option value="b" disabled>Banana</option>
+            <optgroup label="Stone fruit">
+                <option value="c" selected>Cherry</option>
+                <option value="p">Peach</option>
+            </optgroup>
+        </select></body></html>"#;
+        let mut doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        let select = find(&doc, "fruit");
+        let mut widget = FormWidget::open(&doc, select).expect("select popup");
+        assert!(widget
+            .html()
+            .contains(r#"<div class="widget-group">Stone fruit</div>"#));
+
+        assert_eq!(widget.key(WidgetKey::Up), WidgetOutcome::Redraw);
+        assert_eq!(widget.key(WidgetKey::Character('P')), WidgetOutcome::Redraw);
+        let WidgetOutcome::Commit(commit) = widget.key(WidgetKey::Enter) else {
+            panic!("expected a commit");
+        };
+        assert!(apply(&mut doc, &commit));
+        assert!(!apply(&mut doc, &commit));
+        let options = select_options(&doc, select);
+        let chosen: Vec<&str> = options
+            .iter()
+            .filter(|option| option.marked)
+            .map(|option| option.label.as_str())
+            .collect();
+        assert_eq!(chosen, ["Peach"]);
+
+        let Some(Commit::Option { option, .. }) = step(&doc, select, -1) else {
+            panic!("expected to step up");
+        };
+        assert_eq!(option, options[2].node_id);
+        assert_eq!(step(&doc, select, 1), None);
+    }
+
+    #[test]
+    fn date_popup_navigates_within_range() {
+        let html = r#"<html><body>
+            <input id="day" type="date" value="2024-01-31" min="2024-01-10" max="2024-02-20">
+        </body></html>"#;
+        let doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        let input = find(&doc, "day");
+        let mut widget = FormWidget::open(&doc, input).expect("date popup");
+        let markup = widget.html();
+        assert!(markup.contains("January 2024"), "{markup}");
+        assert!(markup.contains(r#"class="date-day selected highlighted""#));
+
+        widget.key(WidgetKey::PageDown);
+        assert!(widget.html().contains("February 2024"));
+        widget.key(WidgetKey::End);
+        assert_eq!(
+            widget.key(WidgetKey::Enter),
+            WidgetOutcome::Commit(Commit::Value {
+                input,
+                value: String::from("2024-02-20"),
+            })
+        );
+        assert_eq!(widget.key(WidgetKey::Escape), WidgetOutcome::Close);
+    }
+
+    #[test]
+    fn radio_groups_stay_exclusive_within_their_form() {
+        let html = r#"<html><body>
+            <form><input id="a" type="radio" name="pick" checked>
+                <label id="b-label" for="b">B</label>
+                <input id="b" type="radio" name="pick">
+                <input id="off" type="radio" name="pick" disabled></form>
+            <form><input id="other" type="radio" name="pick" checked></form>
+            <input id="box" type="checkbox">
+        </body></html>"#;
+        let mut doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        let (a, b, other) = (find(&doc, "a"), find(&doc, "b"), find(&doc, "other"));
+
+        let commit = activate(&doc, find(&doc, "b-label")).expect("label picks its radio");
+        assert_eq!(
+            commit,
+            Commit::Checked {
+                input: b,
+                checked: true
+            }
+        );
+        assert!(apply(&mut doc, &commit));
+        assert!(!is_checked(&doc, a));
+        assert!(is_checked(&doc, other));
+
+        assert_eq!(arrow_direction(&doc, b, WidgetKey::Down), Some(1));
+        assert_eq!(arrow_direction(&doc, b, WidgetKey::Left), Some(-1));
+        assert_eq!(
+            step(&doc, b, 1),
+            Some(Commit::Checked {
+                input: a,
+                checked: true
+            })
+        );
+
+        let checkbox = find(&doc, "box");
+        assert_eq!(arrow_direction(&doc, checkbox, WidgetKey::Up), None);
+        let commit = activate(&doc, checkbox).expect("checkbox toggles");
+        assert!(apply(&mut doc, &commit));
+        assert_eq!(
+            activate(&doc, checkbox),
+            Some(Commit::Checked {
+                input: checkbox,
+                checked: false
+            })
+        );
+    }
+
+    #[test]
+    fn ranges_default_to_their_midpoint_and_step_within_bounds() {
+        let html = r#"<html><body>
+            <input id="volume" type="range" min="0" max="10" step="4">
+            <input id="any" type="range" value="120">
+        </body></html>"#;
+        let doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        let volume = find(&doc, "volume");
+        assert_eq!(arrow_direction(&doc, volume, WidgetKey::Right), Some(1));
+        assert_eq!(
+            step(&doc, volume, 1),
+            Some(Commit::Value {
+                input: volume,
+                value: String::from("8")
+            })
+        );
+        // Without layout the slider has no width, so any pointer lands on its middle.
+        assert_eq!(
+            range_value_at(&doc, volume, 3.0),
+            Some(Commit::Value {
+                input: volume,
+                value: String::from("4")
+            })
+        );
+        let any = find(&doc, "any");
+        assert!(is_range(&doc, any));
+        assert_eq!(step(&doc, any, 1), None);
+        assert_eq!(
+            step(&doc, any, -1),
+            Some(Commit::Value {
+                input: any,
+                value: String::from("99")
+            })
+        );
+    }
+
+    #[test]
+    fn dates_parse_and_do_arithmetic() {
+        let leap = Date::parse("2024-02-29").unwrap();
+        assert_eq!(leap.add_days(1).to_string(), "2024-03-01");
+        assert_eq!(leap.add_months(12).to_string(), "2025-02-28");
+        assert_eq!(
+            Date::parse("2024-01-31")
+                .unwrap()
+                .add_months(-2)
+                .to_string(),
+            "2023-11-30"
+        );
+        assert_eq!(Date::parse("1970-01-01").unwrap().days(), 0);
+        assert_eq!(Date::parse("2026-10-17").unwrap().weekday(), 6);
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::parse("2024-2-01"), None);
+    }
+
+    #[test]
+    fn numbers_step_on_their_grid_and_stay_in_range() {
+        assert_eq!(step_number("", None, None, None, 1).as_deref(), Some("1"));
+        assert_eq!(
+            step_number("1.5", Some("0.5"), None, None, 1).as_deref(),
+            Some("2")
+        );
+        assert_eq!(
+            step_number("0.2", Some("0.1"), None, None, 1).as_deref(),
+            Some("0.3")
+        );
+        assert_eq!(
+            step_number("4", Some("3"), Some("1"), None, 1).as_deref(),
+            Some("7")
+        );
+        assert_eq!(
+            step_number("5", Some("3"), Some("1"), None, -1).as_deref(),
+            Some("4")
+        );
+        assert_eq!(step_number("9", Some("3"), Some("1"), Some("9"), 1), None);
+        assert_eq!(step_number("8", Some("3"), Some("1"), Some("9"), 1), None);
+        assert_eq!(
+            step_number("2", Some("any"), None, None, -1).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            step_date("2024-12-31", None, None, None, 1).as_deref(),
+            Some("2025-01-01")
+        );
+        assert_eq!(
+            step_date("2024-01-05", Some("7"), Some("2024-01-01"), None, -1).as_deref(),
+            Some("2024-01-01")
+        );
+        assert_eq!(
+            step_date("2024-01-01", None, Some("2024-01-01"), None, -1),
+            None
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Frame timing for the current page, behind the frame overlay (Cmd/Ctrl+Shift+F) and the
+//! automation `frames` endpoint.
+//!
+//! A frame is one `RedrawRequested` handled by blitz: style, layout, paint and handing the
+//! scene to the renderer. [`FrameStats::record`] stores how long that took and when it
+//! started; [`FrameStats::summary`] reduces the samples to a rate and percentiles. Frames that
+//! painted a new scroll position are also summarised on their own, since scrolling a long
+//! document is where rebuilding the whole scene every frame shows. The statistics cover one
+//! page session and are reset when a new document is installed.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Frames slower than this (two frame budgets at 60 Hz) count as janky.
+pub const JANK_THRESHOLD: Duration = Duration::from_micros(33_333);
+
+/// Oldest samples are dropped beyond this so a long session stays bounded.
+const MAX_SAMPLES: usize = 10_000;
+
+/// The frame rate is measured over this trailing window.
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct FrameSample {
+    started: Instant,
+    duration: Duration,
+    /// The frame painted a different scroll position than the one before.
+    scrolled: bool,
+}
+
+pub struct FrameStats {
+    session_started: Instant,
+    samples: VecDeque<FrameSample>,
+    /// Frames recorded this session, including ones dropped from `samples`.
+    total_frames: u64,
+    janky_frames: u64,
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self {
+            session_started: Instant::now(),
+            samples: VecDeque::new(),
+            total_frames: 0,
+            janky_frames: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FramePercentiles {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameSummary {
+    pub frames: u64,
+    pub janky_frames: u64,
+    /// Frames started during the last second.
+    pub fps: f64,
+    pub frame_time: FramePercentiles,
+    /// Time between the starts of consecutive frames.
+    pub frame_interval: FramePercentiles,
+    /// Frames that painted a new scroll position, and how long those took.
+    #[serde(default)]
+    pub scroll_frames: u64,
+    #[serde(default)]
+    pub scroll_frame_time: FramePercentiles,
+    /// Milliseconds since the page session started.
+    pub session_ms: f64,
+}
+
+impl FrameStats {
+    /// Start a new page session.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn record(&mut self, started: Instant, duration: Duration, scrolled: bool) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(FrameSample {
+            started,
+            duration,
+            scrolled,
+        });
+        self.total_frames += 1;
+        if duration > JANK_THRESHOLD {
+            self.janky_frames += 1;
+        }
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        self.summary_at(Instant::now())
+    }
+
+    fn summary_at(&self, now: Instant) -> FrameSummary {
+        let durations: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|sample| millis(sample.duration))
+            .collect();
+        let scrolling: Vec<f64> = self
+            .samples
+            .iter()
+            .filter(|sample| sample.scrolled)
+            .map(|sample| millis(sample.duration))
+            .collect();
+        let intervals: Vec<f64> = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(previous, next)| millis(next.started.duration_since(previous.started)))
+            .collect();
+        let recent = self
+            .samples
+            .iter()
+            .filter(|sample| now.saturating_duration_since(sample.started) <= FPS_WINDOW)
+            .count();
+        FrameSummary {
+            frames: self.total_frames,
+            janky_frames: self.janky_frames,
+            fps: recent as f64 / FPS_WINDOW.as_secs_f64(),
+            frame_time: percentiles(durations),
+            frame_interval: percentiles(intervals),
+            scroll_frames: scrolling.len() as u64,
+            scroll_frame_time: percentiles(scrolling),
+            session_ms: millis(now.saturating_duration_since(self.session_started)),
+        }
+    }
+}
+
+fn percentiles(mut values: Vec<f64>) -> FramePercentiles {
+    if values.is_empty() {
+        return FramePercentiles::default();
+    }
+    values.sort_by(f64::total_cmp);
+    let rank = |p: f64| {
+        let index = ((p * values.len() as f64).ceil() as usize).saturating_sub(1);
+        values[index.min(values.len() - 1)]
+    };
+    FramePercentiles {
+        p50_ms: rank(0.50),
+        p90_ms: rank(0.90),
+        p99_ms: rank(0.99),
+        max_ms: values[values.len() - 1],
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// HTML for the overlay, placed in the chrome's overlay host.
+pub fn overlay_html(summary: &FrameSummary) -> String {
+    let scrolling = if summary.scroll_frames > 0 {
+        format!(
+            "<div>scrolling p50 {:.1} ms · p99 {:.1} ms</div>\n",
+            summary.scroll_frame_time.p50_ms, summary.scroll_frame_time.p99_ms
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<aside id="frame-overlay" aria-label="Frame timing">
+<div class="frame-fps">{fps:.0} fps</div>
+<div>frame p50 {p50:.1} ms · p99 {p99:.1} ms · max {max:.1} ms</div>
+{scrolling}<div>{frames} frames, {janky} janky</div>
+</aside>"#,
+        fps = summary.fps,
+        p50 = summary.frame_time.p50_ms,
+        p99 = summary.frame_time.p99_ms,
+        max = summary.frame_time.max_ms,
+        frames = summary.frames,
+        janky = summary.janky_frames,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarises_frame_times_and_jank() {
+        let mut stats = FrameStats::default();
+        let start = Instant::now();
+        for index in 0..100u64 {
+            let duration = if index == 99 {
+                Duration::from_millis(50)
+            } else {
+                Duration::from_millis(index % 10 + 1)
+            };
+            let scrolled = index >= 90;
+            stats.record(
+                start + Duration::from_millis(index * 16),
+                duration,
+                scrolled,
+            );
+        }
+
+        let summary = stats.summary_at(start + Duration::from_millis(99 * 16));
+        assert_eq!(summary.frames, 100);
+        assert_eq!(summary.janky_frames, 1);
+        assert_eq!(summary.frame_time.p50_ms, 5.0);
+        assert_eq!(summary.frame_time.p90_ms, 9.0);
+        assert_eq!(summary.frame_time.max_ms, 50.0);
+        assert_eq!(summary.frame_interval.p50_ms, 16.0);
+        // Frames started 0..=1000 ms before `now`: 63 of them at 16 ms spacing.
+        assert_eq!(summary.fps, 63.0);
+        assert_eq!(summary.scroll_frames, 10);
+        assert_eq!(summary.scroll_frame_time.p50_ms, 5.0);
+        assert_eq!(summary.scroll_frame_time.max_ms, 50.0);
+        let html = overlay_html(&summary);
+        assert!(html.contains("100 frames, 1 janky"));
+        assert!(html.contains("scrolling p50 5.0 ms"), "{html}");
+
+        stats.reset();
+        assert_eq!(stats.summary().frames, 0);
+    }
+}
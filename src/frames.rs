@@ -0,0 +1,636 @@
+//! `<iframe>` support: every iframe of the page gets its own nested browsing context.
+//!
+//! A child frame owns an [`HtmlDocument`] and, when it has scripts, a [`JsPageRuntime`] of
+//! its own, so the node handles its scripts hold always refer to the child document and
+//! never to the parent's. The parent shows a copy of the child's body inside the iframe
+//! element, refreshed whenever the child's DOM changes: the child's scripts, styles and ids
+//! are left out of the copy and its URLs are resolved against the child. Input on the copy
+//! goes to the parent. Only the top-level page's iframes are loaded.
+//!
+//! `postMessage` between the page and its frames goes through [`FrameTree::route_messages`],
+//! which drops messages whose `targetOrigin` does not match the receiving window.
+
+use std::sync::Arc;
+use std::task::Waker;
+use std::time::Duration;
+
+use blitz_dom::{
+    local_name, ns, BaseDocument, DocumentConfig, DocumentMutator, NodeData, QualName,
+};
+use blitz_html::HtmlDocument;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+use url::{Origin, Url};
+
+use crate::adblock::Blocklist;
+use crate::js::clock::VirtualClock;
+use crate::js::lifecycle::LifecycleEvent;
+use crate::js::messaging::{target_origin_allows, PostedMessage, WindowRef};
+use crate::js::processor;
+use crate::js::session::JsPageRuntime;
+use crate::js::teardown::TeardownCheck;
+use crate::js::viewport::ViewportMetrics;
+use crate::navigation::FetchedDocument;
+use crate::network_log::NetworkLog;
+use crate::permissions::ContentRules;
+
+/// Size of an iframe without `width`/`height` attributes, in CSS pixels.
+pub const DEFAULT_FRAME_WIDTH: f64 = 300.0;
+pub const DEFAULT_FRAME_HEIGHT: f64 = 150.0;
+
+/// Elements of the child document that are not copied into the parent.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "link", "meta", "title", "template", "noscript", "iframe",
+];
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "source", "track", "wbr",
+];
+const URL_ATTRIBUTES: &[&str] = &["src", "href", "action", "poster"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameRequest {
+    Url(Url),
+    Srcdoc(String),
+}
+
+/// An iframe of the parent document and what to load into it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSource {
+    pub node_id: usize,
+    pub request: FrameRequest,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+}
+
+impl FrameSource {
+    fn viewport(&self, scale: f64) -> ViewportMetrics {
+        ViewportMetrics::new(
+            self.width.unwrap_or(DEFAULT_FRAME_WIDTH),
+            self.height.unwrap_or(DEFAULT_FRAME_HEIGHT),
+            scale,
+        )
+    }
+}
+
+/// The iframes of `doc` that have something to load, in document order. `srcdoc` wins over
+/// `src`; `src` must be http(s), or a `file:` URL inside a `file:` document.
+pub fn collect_frames(doc: &BaseDocument, base_url: &str) -> Vec<FrameSource> {
+    let base = Url::parse(base_url).ok();
+    let parent_is_file = base.as_ref().is_some_and(|base| base.scheme() == "file");
+    let mut frames = Vec::new();
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        stack.extend(node.children.iter().rev().copied());
+        let is_iframe = node
+            .element_data()
+            .is_some_and(|element| element.name.local == local_name!("iframe"));
+        if !is_iframe {
+            continue;
+        }
+        let request = if let Some(srcdoc) = node.attr(local_name!("srcdoc")) {
+            FrameRequest::Srcdoc(srcdoc.to_string())
+        } else {
+            let Some(src) = node.attr(local_name!("src")).map(str::trim) else {
+                continue;
+            };
+            let resolved = match &base {
+                Some(base) => base.join(src),
+                None => Url::parse(src),
+            };
+            let Ok(url) = resolved else {
+                warn!(src, "ignoring iframe with an invalid src");
+                continue;
+            };
+            let allowed = match url.scheme() {
+                "http" | "https" => true,
+                "file" => parent_is_file,
+                _ => false,
+            };
+            if !allowed {
+                continue;
+            }
+            FrameRequest::Url(url)
+        };
+        frames.push(FrameSource {
+            node_id,
+            request,
+            width: node.attr(local_name!("width")).and_then(parse_dimension),
+            height: node.attr(local_name!("height")).and_then(parse_dimension),
+        });
+    }
+    frames
+}
+
+fn parse_dimension(raw: &str) -> Option<f64> {
+    let value: f64 = raw.trim().trim_end_matches("px").parse().ok()?;
+    (value.is_finite() && value >= 0.0).then_some(value)
+}
+
+/// The document an `<iframe srcdoc>` shows. It shares the parent's base URL and origin.
+pub fn srcdoc_document(parent_base_url: &str, html: String) -> FetchedDocument {
+    let scripts = processor::collect_scripts(&html).unwrap_or_else(|err| {
+        warn!(error = %err, "failed to collect srcdoc scripts");
+        Vec::new()
+    });
+    FetchedDocument {
+        base_url: parent_base_url.to_string(),
+        contents: html,
+        file_path: None,
+        display_url: "about:srcdoc".to_string(),
+        scripts,
+        csp_headers: Vec::new(),
+        response: None,
+    }
+}
+
+pub struct FrameOptions {
+    pub javascript: bool,
+    pub scale: f64,
+    pub network_log: NetworkLog,
+    /// Site settings of the top-level page, which the frame's own requests are held to.
+    pub content_rules: ContentRules,
+    pub blocklist: Arc<Blocklist>,
+    /// The clock automation replays sessions on, which the frame's timers follow too.
+    pub virtual_clock: Option<VirtualClock>,
+}
+
+struct ChildFrame {
+    source: FrameSource,
+    url: String,
+    base: Option<Url>,
+    origin: Origin,
+    /// Declared before `document` so it drops first: its DOM bridge points into the document.
+    runtime: Option<JsPageRuntime>,
+    document: Box<HtmlDocument>,
+    /// Body markup last copied into the parent; `None` until the first copy.
+    rendered: Option<String>,
+}
+
+/// The child frames of the current page.
+#[derive(Default)]
+pub struct FrameTree {
+    frames: Vec<ChildFrame>,
+    /// Fetches of frame documents still in flight.
+    loads: Vec<JoinHandle<()>>,
+    waker: Option<Waker>,
+}
+
+impl FrameTree {
+    /// Wake the host whenever a child's timers or fetches complete.
+    pub fn set_waker(&mut self, waker: Waker) {
+        self.waker = Some(waker);
+        self.register_wakers();
+    }
+
+    pub fn track_load(&mut self, task: JoinHandle<()>) {
+        self.loads.retain(|task| !task.is_finished());
+        self.loads.push(task);
+    }
+
+    /// Build the child document for `source`, run its scripts and fire `load`.
+    pub fn attach(
+        &mut self,
+        source: FrameSource,
+        document: FetchedDocument,
+        parent_origin: &Origin,
+        options: &FrameOptions,
+    ) {
+        let base = Url::parse(&document.base_url).ok();
+        let origin = match (&source.request, &base) {
+            (FrameRequest::Srcdoc(_), _) => parent_origin.clone(),
+            (FrameRequest::Url(_), Some(base)) => base.origin(),
+            (FrameRequest::Url(_), None) => Url::parse("about:blank").expect("static url").origin(),
+        };
+        let mut html = Box::new(HtmlDocument::from_html(
+            &document.contents,
+            DocumentConfig {
+                base_url: Some(document.base_url.clone()),
+                ..Default::default()
+            },
+        ));
+
+        let runtime = if options.javascript {
+            match JsPageRuntime::for_document(&document) {
+                Ok(Some(mut runtime)) => {
+                    runtime.set_framed();
+                    runtime.set_network_log(options.network_log.clone());
+                    runtime.set_content_rules(options.content_rules.clone());
+                    runtime.set_blocklist(Arc::clone(&options.blocklist));
+                    if let Some(clock) = &options.virtual_clock {
+                        runtime.set_virtual_clock(clock.clone());
+                    }
+                    runtime.set_viewport(source.viewport(options.scale));
+                    // SAFETY: the document is boxed and kept beside the runtime in its
+                    // `ChildFrame`, which shuts the runtime down, or drops it, first.
+                    unsafe { runtime.attach_document(&mut html) };
+                    if let Err(err) = runtime.run_blocking_scripts() {
+                        error!(
+                            target = "quickjs",
+                            url = %document.display_url,
+                            error = %err,
+                            "failed to execute frame scripts"
+                        );
+                    }
+                    runtime.dispatch_lifecycle(LifecycleEvent::Load);
+                    Some(runtime)
+                }
+                Ok(None) => None,
+                Err(err) => {
+                    error!(
+                        target = "quickjs",
+                        url = %document.display_url,
+                        error = %err,
+                        "failed to initialize frame runtime"
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(index) = self
+            .frames
+            .iter()
+            .position(|frame| frame.source.node_id == source.node_id)
+        {
+            let previous = self.frames.remove(index);
+            if let Some(runtime) = previous.runtime {
+                runtime.dispatch_lifecycle(LifecycleEvent::Unload);
+                runtime.shutdown();
+            }
+        }
+        self.frames.push(ChildFrame {
+            source,
+            url: document.display_url,
+            base,
+            origin,
+            document: html,
+            runtime,
+            rendered: None,
+        });
+        self.register_wakers();
+    }
+
+    /// Run the children's due timers, settled fetches and queued jobs. Returns whether any
+    /// ran.
+    pub fn pump(&mut self) -> bool {
+        let mut did_work = false;
+        for frame in &self.frames {
+            let Some(runtime) = frame.runtime.as_ref() else {
+                continue;
+            };
+            match runtime.environment().pump() {
+                Ok(ran) => did_work |= ran,
+                Err(err) => {
+                    error!(
+                        target = "quickjs",
+                        url = %frame.url,
+                        error = %err,
+                        "failed to pump frame runtime"
+                    );
+                }
+            }
+        }
+        self.register_wakers();
+        did_work
+    }
+
+    /// When the children's next timer falls due on the virtual clock.
+    pub fn next_timer_due(&self) -> Option<Duration> {
+        self.frames
+            .iter()
+            .filter_map(|frame| frame.runtime.as_ref()?.environment().next_timer_due())
+            .min()
+    }
+
+    /// Whether any child has `fetch()` requests or a document load in flight.
+    pub fn is_loading(&self) -> bool {
+        self.loads.iter().any(|task| !task.is_finished())
+            || self.frames.iter().any(|frame| {
+                frame
+                    .runtime
+                    .as_ref()
+                    .is_some_and(|runtime| runtime.environment().has_requests_in_flight())
+            })
+    }
+
+    fn register_wakers(&self) {
+        let Some(waker) = self.waker.as_ref() else {
+            return;
+        };
+        for runtime in self
+            .frames
+            .iter()
+            .filter_map(|frame| frame.runtime.as_ref())
+        {
+            runtime.environment().register_waker(waker);
+        }
+    }
+
+    /// Copy each child's body into its iframe in `doc` if it changed since the last copy.
+    /// Returns whether `doc` was modified.
+    pub fn render_into(&mut self, doc: &mut BaseDocument) -> bool {
+        let mut changed = false;
+        for frame in &mut self.frames {
+            let markup = body_markup(&frame.document, frame.base.as_ref());
+            if frame.rendered.as_deref() == Some(markup.as_str()) {
+                continue;
+            }
+            let Some(iframe) = doc.get_node(frame.source.node_id) else {
+                continue;
+            };
+            let sized_style = (frame.rendered.is_none()
+                && (frame.source.width.is_some() || frame.source.height.is_some()))
+            .then(|| {
+                let mut style = String::new();
+                if let Some(width) = frame.source.width {
+                    style.push_str(&format!("width:{width}px;"));
+                }
+                if let Some(height) = frame.source.height {
+                    style.push_str(&format!("height:{height}px;"));
+                }
+                style.push_str(iframe.attr(local_name!("style")).unwrap_or_default());
+                style
+            });
+
+            let mut mutator = DocumentMutator::new(doc);
+            if let Some(style) = sized_style {
+                mutator.set_attribute(
+                    frame.source.node_id,
+                    QualName::new(None, ns!(html), local_name!("style")),
+                    &style,
+                );
+            }
+            mutator.remove_and_drop_all_children(frame.source.node_id);
+            let container = mutator.create_element(
+                QualName::new(None, ns!(html), local_name!("div")),
+                Vec::new(),
+            );
+            mutator.set_attribute(
+                container,
+                QualName::new(None, ns!(html), local_name!("class")),
+                "frontier-frame",
+            );
+            mutator.append_children(frame.source.node_id, &[container]);
+            mutator.set_inner_html(container, &markup);
+            drop(mutator);
+
+            frame.rendered = Some(markup);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Tell `parent` which of its iframes have a window to post to.
+    pub fn expose_to(&self, parent: &JsPageRuntime) {
+        for frame in &self.frames {
+            parent.set_frame_loaded(frame.source.node_id, frame.runtime.is_some());
+        }
+    }
+
+    /// Deliver `parent_messages`, taken from the page, and the `postMessage` calls of the
+    /// frames. Returns whether any message was delivered.
+    pub fn route_messages(
+        &self,
+        parent: Option<&JsPageRuntime>,
+        parent_messages: Vec<PostedMessage>,
+        parent_origin: &Origin,
+    ) -> bool {
+        let mut delivered = false;
+        if let Some(parent) = parent {
+            for message in parent_messages {
+                let receiver = match message.target {
+                    WindowRef::Own | WindowRef::Parent => {
+                        Some((parent, parent_origin, WindowRef::Own))
+                    }
+                    WindowRef::Frame(node_id) => self
+                        .frames
+                        .iter()
+                        .find(|frame| frame.source.node_id == node_id)
+                        .and_then(|frame| {
+                            Some((frame.runtime.as_ref()?, &frame.origin, WindowRef::Parent))
+                        }),
+                    // Popup windows are the host's business, see `crate::popups`.
+                    WindowRef::Popup(_) | WindowRef::Opener => None,
+                };
+                delivered |= deliver(&message, parent_origin, receiver);
+            }
+        }
+        for frame in &self.frames {
+            let Some(runtime) = frame.runtime.as_ref() else {
+                continue;
+            };
+            for message in runtime.take_posted_messages() {
+                let receiver = match message.target {
+                    WindowRef::Own => Some((runtime, &frame.origin, WindowRef::Own)),
+                    WindowRef::Parent => parent.map(|parent| {
+                        (
+                            parent,
+                            parent_origin,
+                            WindowRef::Frame(frame.source.node_id),
+                        )
+                    }),
+                    // Frames inside frames are never loaded, and frames cannot open popups.
+                    WindowRef::Frame(_) | WindowRef::Popup(_) | WindowRef::Opener => None,
+                };
+                delivered |= deliver(&message, &frame.origin, receiver);
+            }
+        }
+        delivered
+    }
+
+    /// Unload every child and abort pending frame loads.
+    pub fn shutdown(&mut self) -> Vec<TeardownCheck> {
+        for task in self.loads.drain(..) {
+            task.abort();
+        }
+        self.frames
+            .drain(..)
+            .filter_map(|frame| {
+                let runtime = frame.runtime?;
+                runtime.dispatch_lifecycle(LifecycleEvent::Unload);
+                Some(runtime.shutdown())
+            })
+            .collect()
+    }
+}
+
+fn deliver(
+    message: &PostedMessage,
+    sender: &Origin,
+    receiver: Option<(&JsPageRuntime, &Origin, WindowRef)>,
+) -> bool {
+    let Some((runtime, receiver_origin, source)) = receiver else {
+        return false;
+    };
+    if !target_origin_allows(&message.target_origin, sender, receiver_origin) {
+        warn!(
+            target = "quickjs",
+            target_origin = %message.target_origin,
+            recipient = %receiver_origin.ascii_serialization(),
+            "postMessage target origin does not match the recipient window's origin"
+        );
+        return false;
+    }
+    runtime.deliver_message(&message.data, &sender.ascii_serialization(), source);
+    true
+}
+
+/// The children of the first `<body>` in `doc`, without scripts, styles or ids, and with URL
+/// attributes made absolute against `base`.
+fn body_markup(doc: &BaseDocument, base: Option<&Url>) -> String {
+    let mut output = String::new();
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        if node
+            .element_data()
+            .is_some_and(|element| element.name.local == local_name!("body"))
+        {
+            for child in &node.children {
+                serialize_node(doc, *child, base, &mut output);
+            }
+            break;
+        }
+        stack.extend(node.children.iter().rev().copied());
+    }
+    output
+}
+
+fn serialize_node(doc: &BaseDocument, node_id: usize, base: Option<&Url>, output: &mut String) {
+    let Some(node) = doc.get_node(node_id) else {
+        return;
+    };
+    match &node.data {
+        NodeData::Element(data) => {
+            let name = data.name.local.as_ref();
+            if SKIPPED_ELEMENTS.contains(&name) {
+                return;
+            }
+            output.push('<');
+            output.push_str(name);
+            for attr in data.attrs.iter() {
+                let attr_name = attr.name.local.as_ref();
+                // Ids would clash with the parent's; handler attributes would run as the parent.
+                if attr_name == "id" || attr_name.starts_with("on") {
+                    continue;
+                }
+                let value = if URL_ATTRIBUTES.contains(&attr_name) {
+                    match absolute_url(base, &attr.value) {
+                        Some(value) => value,
+                        None => continue,
+                    }
+                } else {
+                    attr.value.to_string()
+                };
+                output.push(' ');
+                output.push_str(attr_name);
+                output.push_str("=\"");
+                output.push_str(&encode_double_quoted_attribute(&value));
+                output.push('"');
+            }
+            output.push('>');
+            if VOID_ELEMENTS.contains(&name) {
+                return;
+            }
+            for child in &node.children {
+                serialize_node(doc, *child, base, output);
+            }
+            output.push_str("</");
+            output.push_str(name);
+            output.push('>');
+        }
+        NodeData::AnonymousBlock(_) => {
+            for child in &node.children {
+                serialize_node(doc, *child, base, output);
+            }
+        }
+        NodeData::Text(text) => output.push_str(&encode_text(&text.content)),
+        _ => {}
+    }
+}
+
+/// `value` resolved against `base`; `None` for `javascript:` URLs.
+fn absolute_url(base: Option<&Url>, value: &str) -> Option<String> {
+    let resolved = match base {
+        Some(base) => base.join(value.trim()),
+        None => Url::parse(value.trim()),
+    };
+    match resolved {
+        Ok(url) if url.scheme() == "javascript" => None,
+        Ok(url) => Some(url.to_string()),
+        Err(_) => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(html: &str, base_url: &str) -> HtmlDocument {
+        HtmlDocument::from_html(
+            html,
+            DocumentConfig {
+                base_url: Some(base_url.to_string()),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn collects_loadable_iframes() {
+        let doc = document(
+            r#"<html><body>
+                <iframe src="widget.html" width="400" height="200"></iframe>
+                <iframe srcdoc="<p>inline</p>" src="ignored.html"></iframe>
+                <iframe src="javascript:alert(1)"></iframe>
+                <iframe></iframe>
+            </body></html>"#,
+            "https://example.com/page/",
+        );
+        let frames = collect_frames(&doc, "https://example.com/page/");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[0].request,
+            FrameRequest::Url(Url::parse("https://example.com/page/widget.html").unwrap())
+        );
+        assert_eq!(
+            (frames[0].width, frames[0].height),
+            (Some(400.0), Some(200.0))
+        );
+        assert_eq!(
+            frames[1].request,
+            FrameRequest::Srcdoc("<p>inline</p>".to_string())
+        );
+        assert_eq!(frames[1].viewport(1.0).width, DEFAULT_FRAME_WIDTH);
+    }
+
+    #[test]
+    fn body_copy_drops_scripts_and_ids_and_resolves_urls() {
+        let doc = document(
+            r#"<html><head><style>p { color: red }</style></head><body>
+                <p id="greeting" class="hello" onclick="steal()">Hi &amp; welcome<br></p>
+                <img src="logo.png"><a href="javascript:void(0)">x</a>
+                <script>document.title = 'child';</script>
+            </body></html>"#,
+            "https://widgets.example.net/embed/",
+        );
+        let base = Url::parse("https://widgets.example.net/embed/").unwrap();
+        let markup = body_markup(&doc, Some(&base));
+        assert!(
+            markup.contains(r#"<p class="hello">Hi &amp; welcome<br></p>"#),
+            "{markup}"
+        );
+        assert!(markup.contains(r#"<img src="https://widgets.example.net/embed/logo.png">"#));
+        assert!(markup.contains("<a>x</a>"), "{markup}");
+        assert!(!markup.contains("script"));
+        assert!(!markup.contains("color: red"));
+    }
+}
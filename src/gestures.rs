@@ -0,0 +1,182 @@
+//! Trackpad gestures: pinching zooms the page and a two-finger horizontal swipe goes back or
+//! forward.
+//!
+//! winit reports pinches as `PinchGesture` (macOS and iOS) and two-finger swipes as pixel
+//! `MouseWheel` deltas bracketed by touch phases (or `PanGesture` on iOS). The host offers each
+//! to the page first, as the non-standard `gesturestart`, `gesturechange` and `gestureend`
+//! events Safari fires for pinches and a `swipe` event for swipes (see
+//! [`crate::js::gestures`]); cancelling them keeps the browser from zooming or navigating. A
+//! swipe only navigates when the page did not scroll sideways during it, so horizontally
+//! scrolling content still scrolls. [`GestureSettings`] in `frontier.toml` turns either
+//! gesture off and sets how far a swipe must travel. The View menu's Zoom In and Zoom Out step
+//! through the same range with [`step_zoom`].
+
+use serde::{Deserialize, Serialize};
+use winit::event::TouchPhase;
+
+/// Page zoom stays within these bounds, as in most browsers.
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 5.0;
+
+/// The `[gestures]` table of `frontier.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureSettings {
+    /// Pinching zooms the page.
+    pub pinch_zoom: bool,
+    /// Swiping sideways with two fingers goes back or forward.
+    pub swipe_navigation: bool,
+    /// CSS pixels a swipe must travel sideways before it navigates.
+    pub swipe_threshold: u32,
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        Self {
+            pinch_zoom: true,
+            swipe_navigation: true,
+            swipe_threshold: 120,
+        }
+    }
+}
+
+/// The steps View > Zoom In and Zoom Out move between.
+const ZOOM_LEVELS: [f32; 17] = [
+    0.25, 0.33, 0.5, 0.67, 0.75, 0.8, 0.9, 1.0, 1.1, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0, 5.0,
+];
+
+/// The next zoom level above (or below) `zoom`, which a pinch may have left between levels.
+pub fn step_zoom(zoom: f32, zoom_in: bool) -> f32 {
+    let next = if zoom_in {
+        ZOOM_LEVELS.iter().find(|&&level| level > zoom + 0.001)
+    } else {
+        ZOOM_LEVELS
+            .iter()
+            .rev()
+            .find(|&&level| level < zoom - 0.001)
+    };
+    next.copied().unwrap_or(zoom).clamp(MIN_ZOOM, MAX_ZOOM)
+}
+
+/// The page zoom after a pinch step of `delta` (positive magnifies) from `zoom`.
+pub fn pinch_zoom(zoom: f32, delta: f64) -> f32 {
+    (zoom * (1.0 + delta as f32)).clamp(MIN_ZOOM, MAX_ZOOM)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    /// Fingers moved right: go back.
+    Back,
+    /// Fingers moved left: go forward.
+    Forward,
+}
+
+impl SwipeDirection {
+    /// The `direction` of the page's `swipe` event: the way the fingers moved.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SwipeDirection::Back => "right",
+            SwipeDirection::Forward => "left",
+        }
+    }
+}
+
+/// Follows one two-finger swipe from its first to its last scroll event.
+#[derive(Debug, Default)]
+pub struct SwipeTracker {
+    /// Travel so far in CSS pixels, in wheel terms (positive is right and down), and the
+    /// horizontal scroll offset of the page when the swipe began; `None` between swipes.
+    swipe: Option<((f64, f64), f64)>,
+}
+
+impl SwipeTracker {
+    /// Feed one phased scroll delta, with the page's horizontal scroll offset at the time.
+    /// Returns the direction when the swipe ends having travelled far enough, mostly
+    /// sideways, without scrolling the page.
+    pub fn update(
+        &mut self,
+        phase: TouchPhase,
+        delta: (f64, f64),
+        scroll_x: f64,
+        threshold: u32,
+    ) -> Option<SwipeDirection> {
+        match phase {
+            TouchPhase::Started => {
+                self.swipe = Some((delta, scroll_x));
+                None
+            }
+            TouchPhase::Moved => {
+                if let Some(((dx, dy), _)) = self.swipe.as_mut() {
+                    *dx += delta.0;
+                    *dy += delta.1;
+                }
+                None
+            }
+            TouchPhase::Cancelled => {
+                self.swipe = None;
+                None
+            }
+            TouchPhase::Ended => {
+                let ((dx, dy), start_x) = self.swipe.take()?;
+                let (dx, dy) = (dx + delta.0, dy + delta.1);
+                let sideways = dx.abs() >= f64::from(threshold) && dx.abs() > 2.0 * dy.abs();
+                if !sideways || (scroll_x - start_x).abs() > 0.5 {
+                    return None;
+                }
+                Some(if dx > 0.0 {
+                    SwipeDirection::Back
+                } else {
+                    SwipeDirection::Forward
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinches_scale_within_bounds() {
+        assert_eq!(pinch_zoom(1.0, 0.5), 1.5);
+        assert_eq!(pinch_zoom(1.0, -0.5), 0.5);
+        assert_eq!(pinch_zoom(4.0, 1.0), MAX_ZOOM);
+        assert_eq!(pinch_zoom(0.3, -0.5), MIN_ZOOM);
+    }
+
+    #[test]
+    fn zoom_steps_snap_to_the_next_level() {
+        assert_eq!(step_zoom(1.0, true), 1.1);
+        assert_eq!(step_zoom(1.0, false), 0.9);
+        assert_eq!(step_zoom(1.3, true), 1.5);
+        assert_eq!(step_zoom(1.3, false), 1.25);
+        assert_eq!(step_zoom(MAX_ZOOM, true), MAX_ZOOM);
+        assert_eq!(step_zoom(MIN_ZOOM, false), MIN_ZOOM);
+    }
+
+    #[test]
+    fn sideways_swipes_navigate_unless_the_page_scrolled() {
+        let mut tracker = SwipeTracker::default();
+        let swipe = |tracker: &mut SwipeTracker, dx: f64, dy: f64, end_scroll: f64| {
+            tracker.update(TouchPhase::Started, (0.0, 0.0), 0.0, 120);
+            tracker.update(TouchPhase::Moved, (dx / 2.0, dy / 2.0), 0.0, 120);
+            tracker.update(TouchPhase::Ended, (dx / 2.0, dy / 2.0), end_scroll, 120)
+        };
+        assert_eq!(
+            swipe(&mut tracker, 200.0, 10.0, 0.0),
+            Some(SwipeDirection::Back)
+        );
+        assert_eq!(
+            swipe(&mut tracker, -200.0, 0.0, 0.0),
+            Some(SwipeDirection::Forward)
+        );
+        assert_eq!(swipe(&mut tracker, 100.0, 0.0, 0.0), None);
+        assert_eq!(swipe(&mut tracker, 200.0, 150.0, 0.0), None);
+        assert_eq!(swipe(&mut tracker, -200.0, 0.0, 200.0), None);
+        assert_eq!(
+            tracker.update(TouchPhase::Ended, (500.0, 0.0), 0.0, 120),
+            None
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! HTTP Archive (HAR 1.2) export of a page's [`NetworkLog`](crate::network_log::NetworkLog),
+//! served by the automation `har` endpoint so a failing run's page load can be opened in any
+//! HAR viewer.
+//!
+//! The log only knows what blitz reports: documents and subresources have no status or
+//! headers, so they export with status `0`, and connection timings are always unknown (`-1`).
+//! The whole request time is reported as `wait`. Frontier's own fields (`_initiator`,
+//! `_source`, `_error`) follow the HAR convention of a leading underscore.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::network_log::NetworkEntry;
+use crate::page_cache::civil_date;
+
+const PAGE_ID: &str = "page_1";
+
+/// Build the archive for one page load. Response bodies are included when `include_content`
+/// is set and the log captured them; text bodies are inlined, anything else is base64.
+pub fn har(
+    entries: &[NetworkEntry],
+    page_url: &str,
+    started_at: SystemTime,
+    include_content: bool,
+) -> Value {
+    let page_started = started_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        * 1000.0;
+    let on_load = entries
+        .iter()
+        .filter_map(|entry| Some(entry.start_ms + entry.duration_ms?))
+        .fold(None, |latest: Option<f64>, end| {
+            Some(latest.map_or(end, |latest| latest.max(end)))
+        });
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "Frontier", "version": env!("CARGO_PKG_VERSION") },
+            "pages": [{
+                "startedDateTime": iso_timestamp(page_started),
+                "id": PAGE_ID,
+                "title": page_url,
+                "pageTimings": { "onContentLoad": -1, "onLoad": on_load.unwrap_or(-1.0) },
+            }],
+            "entries": entries
+                .iter()
+                .map(|entry| har_entry(entry, page_started, include_content))
+                .collect::<Vec<_>>(),
+        }
+    })
+}
+
+fn har_entry(entry: &NetworkEntry, page_started: f64, include_content: bool) -> Value {
+    let time = entry.duration_ms.unwrap_or(-1.0);
+    let headers: Vec<Value> = entry
+        .response_headers
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+    let mime_type = entry
+        .response_headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| value.as_str())
+        .unwrap_or("");
+    let mut content = json!({
+        "size": entry.size.unwrap_or(0),
+        "mimeType": mime_type,
+    });
+    if include_content {
+        if let Some(body) = &entry.body {
+            match std::str::from_utf8(body) {
+                Ok(text) => content["text"] = json!(text),
+                Err(_) => {
+                    content["text"] = json!(BASE64.encode(body));
+                    content["encoding"] = json!("base64");
+                }
+            }
+        }
+    }
+    let mut value = json!({
+        "pageref": PAGE_ID,
+        "startedDateTime": iso_timestamp(page_started + entry.start_ms),
+        "time": time.max(0.0),
+        "request": {
+            "method": entry.method,
+            "url": entry.url,
+            "httpVersion": "",
+            "cookies": [],
+            "headers": [],
+            "queryString": query_string(&entry.url),
+            "headersSize": -1,
+            "bodySize": -1,
+        },
+        "response": {
+            "status": entry.status.unwrap_or(0),
+            "statusText": "",
+            "httpVersion": "",
+            "cookies": [],
+            "headers": headers,
+            "content": content,
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": entry.size.map_or(-1, |size| size as i64),
+        },
+        "cache": {},
+        "timings": {
+            "blocked": -1,
+            "dns": -1,
+            "connect": -1,
+            "ssl": -1,
+            "send": 0,
+            "wait": time,
+            "receive": 0,
+        },
+        "_initiator": entry.initiator,
+        "_source": entry.source,
+    });
+    if let Some(error) = &entry.error {
+        value["_error"] = json!(error);
+    }
+    value
+}
+
+fn query_string(url: &str) -> Vec<Value> {
+    Url::parse(url)
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `YYYY-MM-DDTHH:MM:SS.mmmZ` for milliseconds since the Unix epoch.
+fn iso_timestamp(unix_ms: f64) -> String {
+    let unix_ms = unix_ms.max(0.0) as u64;
+    let seconds = unix_ms / 1000;
+    let (year, month, day) = civil_date(seconds);
+    let seconds_of_day = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3_600,
+        (seconds_of_day % 3_600) / 60,
+        seconds_of_day % 60,
+        unix_ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::network_log::{Initiator, RequestSource};
+
+    fn entry(url: &str, body: &[u8]) -> NetworkEntry {
+        NetworkEntry {
+            url: url.into(),
+            method: "GET".into(),
+            initiator: Initiator::Fetch,
+            source: RequestSource::Http,
+            status: Some(200),
+            size: Some(body.len() as u64),
+            start_ms: 12.0,
+            duration_ms: Some(30.0),
+            error: None,
+            response_headers: vec![("content-type".into(), "application/json".into())],
+            body: Some(body.to_vec()),
+        }
+    }
+
+    #[test]
+    fn exports_entries_with_timings_and_optional_bodies() {
+        let started_at = UNIX_EPOCH + Duration::from_millis(1_700_000_000_250);
+        let entries = [
+            entry("https://example.com/api?q=1", b"{}"),
+            entry("https://example.com/img", &[0xff, 0x00]),
+        ];
+
+        let archive = har(&entries, "https://example.com/", started_at, true);
+        let log = &archive["log"];
+        assert_eq!(log["version"], "1.2");
+        assert_eq!(
+            log["pages"][0]["startedDateTime"],
+            "2023-11-14T22:13:20.250Z"
+        );
+        assert_eq!(log["pages"][0]["pageTimings"]["onLoad"], 42.0);
+        let first = &log["entries"][0];
+        assert_eq!(first["startedDateTime"], "2023-11-14T22:13:20.262Z");
+        assert_eq!(first["request"]["queryString"][0]["value"], "1");
+        assert_eq!(first["response"]["status"], 200);
+        assert_eq!(first["response"]["content"]["mimeType"], "application/json");
+        assert_eq!(first["response"]["content"]["text"], "{}");
+        assert_eq!(first["timings"]["wait"], 30.0);
+        assert_eq!(first["_initiator"], "fetch");
+        assert_eq!(
+            log["entries"][1]["response"]["content"]["encoding"],
+            "base64"
+        );
+
+        let without_bodies = har(&entries, "https://example.com/", started_at, false);
+        assert!(without_bodies["log"]["entries"][0]["response"]["content"]
+            .get("text")
+            .is_none());
+    }
+}
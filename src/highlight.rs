@@ -0,0 +1,176 @@
+//! Syntax highlighting shared by the markdown renderer and `view-source:` documents.
+//!
+//! Highlighted code is emitted as class-annotated spans rather than inline colours. The
+//! stylesheet from [`theme_stylesheet`] carries a light palette plus a
+//! `prefers-color-scheme: dark` override, so toggling the chrome theme recolours code without
+//! re-rendering the document.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use sha2::{Digest, Sha256};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+const CACHE_CAPACITY: usize = 256;
+
+type CacheKey = [u8; 32];
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn cache() -> &'static Mutex<HashMap<CacheKey, Arc<str>>> {
+    static CACHE: OnceLock<Mutex<HashMap<CacheKey, Arc<str>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// CSS for the highlight classes, covering both the light and dark chrome themes.
+pub fn theme_stylesheet() -> &'static str {
+    static STYLESHEET: OnceLock<String> = OnceLock::new();
+    STYLESHEET.get_or_init(|| {
+        let themes = ThemeSet::load_defaults();
+        let light = themes
+            .themes
+            .get(LIGHT_THEME)
+            .and_then(|theme| css_for_theme_with_class_style(theme, CLASS_STYLE).ok())
+            .unwrap_or_default();
+        let dark = themes
+            .themes
+            .get(DARK_THEME)
+            .and_then(|theme| css_for_theme_with_class_style(theme, CLASS_STYLE).ok())
+            .unwrap_or_default();
+        format!("{light}\n@media (prefers-color-scheme: dark) {{\n{dark}\n}}\n")
+    })
+}
+
+/// Highlight `code` as HTML spans. `language` is a fence token (`rust`, `js`) or file
+/// extension; unknown languages fall back to escaped plain text.
+pub fn highlight_to_html(code: &str, language: Option<&str>) -> Arc<str> {
+    let key = cache_key(code, language);
+    if let Some(hit) = cache()
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(&key).cloned())
+    {
+        return hit;
+    }
+
+    let syntaxes = syntax_set();
+    let syntax = find_syntax(syntaxes, language);
+    let rendered: Arc<str> = render_classed(syntaxes, syntax, code).into();
+
+    if let Ok(mut cache) = cache().lock() {
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(key, Arc::clone(&rendered));
+    }
+
+    rendered
+}
+
+/// Wrap a fetched document's source in a highlighted, script-free page for `view-source:`.
+pub fn view_source_document(source: &str, url: &str) -> String {
+    let language = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(url)
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext)
+        .filter(|ext| find_syntax(syntax_set(), Some(ext)).name != "Plain Text")
+        .unwrap_or("html");
+    let highlighted = highlight_to_html(source, Some(language));
+    let title = html_escape::encode_text(url);
+    let styles = theme_stylesheet();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>view-source:{title}</title>
+<style>
+body {{ margin: 0; }}
+pre.view-source {{ margin: 0; padding: 12px 16px; font-family: ui-monospace, Menlo, Consolas, monospace; font-size: 13px; line-height: 1.45; white-space: pre-wrap; }}
+{styles}
+</style>
+</head>
+<body>
+<pre class="view-source hl-code">{highlighted}</pre>
+</body>
+</html>"#
+    )
+}
+
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, language: Option<&str>) -> &'a SyntaxReference {
+    language
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .and_then(|token| {
+            syntaxes
+                .find_syntax_by_token(token)
+                .or_else(|| syntaxes.find_syntax_by_extension(token))
+        })
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+fn render_classed(syntaxes: &SyntaxSet, syntax: &SyntaxReference, code: &str) -> String {
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        if generator
+            .parse_html_for_line_which_includes_newline(line)
+            .is_err()
+        {
+            return html_escape::encode_text(code).into_owned();
+        }
+    }
+    generator.finalize()
+}
+
+fn cache_key(code: &str, language: Option<&str>) -> CacheKey {
+    let mut hasher = Sha256::new();
+    hasher.update(language.unwrap_or_default().as_bytes());
+    hasher.update([0]);
+    hasher.update(code.as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages_with_classes() {
+        let html = highlight_to_html("fn main() {}\n", Some("rust"));
+        assert!(html.contains("hl-"));
+        assert!(html.contains("main"));
+    }
+
+    #[test]
+    fn repeated_highlights_hit_the_cache() {
+        let first = highlight_to_html("let x = 1;\n", Some("js"));
+        let second = highlight_to_html("let x = 1;\n", Some("js"));
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn stylesheet_covers_dark_scheme() {
+        assert!(theme_stylesheet().contains("prefers-color-scheme: dark"));
+    }
+
+    #[test]
+    fn view_source_escapes_markup() {
+        let page = view_source_document("<script>alert(1)</script>", "https://example.com/");
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("view-source:https://example.com/"));
+    }
+}
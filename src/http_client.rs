@@ -0,0 +1,252 @@
+//! Process-wide HTTP clients.
+//!
+//! A reqwest client owns its connection pool, so building one per request throws away warm
+//! TCP/TLS connections and HTTP/2 sessions. Everything that fetches on behalf of a page (external
+//! scripts, `fetch()`, Blossom uploads) shares these clients instead; a document and its
+//! subresources on the same host then ride a single multiplexed HTTP/2 connection when the
+//! server negotiates it. Page subresources use [`shared`] too, leaving only non-http schemes to
+//! the blitz net provider.
+//! Document loads get a client of their own that keeps cookies in the shared
+//! [`CookieJar`](crate::cookies::CookieJar); the others leave cookies to their callers, since
+//! `fetch()` decides per request whether to send them.
+//!
+//! A client that cannot be built is an error for every request made with it, never a plain
+//! client in its place, which would connect around the configured proxy.
+//!
+//! When pinned-TLS clients land they need their own pools keyed by the pinned key, since a
+//! connection verified against one key must never be reused for another.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use reqwest::redirect::Policy;
+use thiserror::Error;
+
+use crate::cookies::SharedCookieStore;
+use crate::proxy;
+
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+/// A shared client could not be built, typically because the configured proxy could not be
+/// applied. Requests fail with this instead of connecting directly.
+#[derive(Debug, Clone, Error)]
+#[error("HTTP client unavailable: {0}")]
+pub struct ClientError(String);
+
+/// Shared async client with the configured proxy applied.
+pub fn shared() -> Result<reqwest::Client, ClientError> {
+    static CLIENT: OnceLock<Result<reqwest::Client, ClientError>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| build(proxy::client_builder(), Policy::default()))
+        .clone()
+}
+
+/// Shared async client that hands redirects back instead of following them, for `fetch()`,
+/// which checks every hop against the same-origin policy itself.
+pub fn shared_manual_redirects() -> Result<reqwest::Client, ClientError> {
+    static CLIENT: OnceLock<Result<reqwest::Client, ClientError>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| build(proxy::client_builder(), Policy::none()))
+        .clone()
+}
+
+/// Shared async client for top-level documents, which sends the jar's cookies and stores
+/// `Set-Cookie` from every response of a redirect chain.
+pub fn documents() -> Result<reqwest::Client, ClientError> {
+    static CLIENT: OnceLock<Result<reqwest::Client, ClientError>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            let builder = proxy::client_builder()
+                .map(|builder| builder.cookie_provider(Arc::new(SharedCookieStore)));
+            build(builder, Policy::default())
+        })
+        .clone()
+}
+
+fn build(
+    builder: anyhow::Result<reqwest::ClientBuilder>,
+    redirects: Policy,
+) -> Result<reqwest::Client, ClientError> {
+    builder
+        .map_err(|err| ClientError(format!("{err:#}")))?
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .http2_adaptive_window(true)
+        .redirect(redirects)
+        .build()
+        .map_err(|err| ClientError(err.to_string()))
+}
+
+/// Shared blocking client for script loads that run before the event loop starts.
+pub fn shared_blocking() -> Result<reqwest::blocking::Client, ClientError> {
+    static CLIENT: OnceLock<Result<reqwest::blocking::Client, ClientError>> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            proxy::blocking_client_builder()
+                .map_err(|err| ClientError(format!("{err:#}")))?
+                .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+                .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+                .build()
+                .map_err(|err| ClientError(err.to_string()))
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use blitz_traits::net::DummyNetCallback;
+    use nostr_sdk::prelude::Keys;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use url::Url;
+
+    use super::*;
+    use crate::js::environment::JsDomEnvironment;
+    use crate::navigation::{execute_fetch, FetchRequest, FetchSource};
+    use crate::publish::{sha256_hex, upload_blob, SiteFile};
+
+    /// Each request the server answered, with the connection it came in on.
+    type Seen = Arc<Mutex<Vec<(usize, String)>>>;
+
+    /// A keep-alive HTTP/1.1 server for a page with one script, a Blossom upload endpoint
+    /// and a `fetch()` target.
+    async fn serve(listener: TcpListener, seen: Seen, upload_hash: String) {
+        let mut next = 0;
+        while let Ok((socket, _)) = listener.accept().await {
+            next += 1;
+            tokio::spawn(serve_connection(
+                next,
+                socket,
+                Arc::clone(&seen),
+                upload_hash.clone(),
+            ));
+        }
+    }
+
+    async fn serve_connection(id: usize, mut socket: TcpStream, seen: Seen, upload_hash: String) {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") else {
+                match socket.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                }
+                continue;
+            };
+            let head = String::from_utf8_lossy(&buffer[..end]).to_ascii_lowercase();
+            let length = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:")?.trim().parse().ok())
+                .unwrap_or(0usize);
+            while buffer.len() < end + 4 + length {
+                match socket.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                }
+            }
+            let line = head.lines().next().unwrap_or_default().to_string();
+            buffer.drain(..end + 4 + length);
+            let body = if line.contains("/page.html") {
+                r#"<html><body><script src="/app.js"></script></body></html>"#.to_string()
+            } else if line.contains("/app.js") {
+                "window.loaded = true;".to_string()
+            } else if line.contains("/upload") {
+                format!(r#"{{"sha256":"{upload_hash}"}}"#)
+            } else {
+                "ok".to_string()
+            };
+            seen.lock().unwrap().push((id, line));
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-length: {}\r\naccess-control-allow-origin: *\r\n\r\n{body}",
+                body.len()
+            );
+            if socket.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn connection_of(seen: &Seen, request: &str) -> usize {
+        seen.lock()
+            .unwrap()
+            .iter()
+            .find(|(_, line)| line.starts_with(request))
+            .map(|(id, _)| *id)
+            .unwrap_or_else(|| panic!("no {request:?} in {seen:?}"))
+    }
+
+    #[tokio::test]
+    async fn scripts_uploads_and_fetch_reuse_pooled_connections() {
+        let file = SiteFile {
+            path: "/index.html".into(),
+            sha256: sha256_hex(b"hello"),
+            bytes: b"hello".to_vec(),
+            mime_type: "text/html",
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let seen = Seen::default();
+        tokio::spawn(serve(listener, Arc::clone(&seen), file.sha256.clone()));
+        // Connections go back to the pool just after their response is read.
+        let settle = || tokio::time::sleep(Duration::from_millis(50));
+
+        // The page's script comes through `shared`, and so does the Blossom upload after it.
+        let page = base.join("page.html").unwrap();
+        let request = FetchRequest {
+            source: FetchSource::Url(page.clone()),
+            display_url: page.to_string(),
+            user_agent: None,
+        };
+        let net_provider = Arc::new(blitz_net::Provider::new(Arc::new(DummyNetCallback)));
+        execute_fetch(&request, net_provider).await.unwrap();
+        settle().await;
+        upload_blob(&shared().unwrap(), &base, &Keys::generate(), &file)
+            .await
+            .unwrap();
+
+        // `fetch()` from two pages shares the redirect-free client.
+        for _ in 0..2 {
+            let environment =
+                JsDomEnvironment::new_with_url("<html></html>", Some(base.clone())).unwrap();
+            environment.eval("fetch('/data');", "fetch.js").unwrap();
+            for _ in 0..100 {
+                settle().await;
+                environment.pump().unwrap();
+                if !environment.has_requests_in_flight() {
+                    break;
+                }
+            }
+        }
+
+        let script = connection_of(&seen, "get /app.js");
+        assert_eq!(connection_of(&seen, "put /upload"), script);
+        let fetches: Vec<usize> = seen
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, line)| line.starts_with("get /data"))
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(fetches.len(), 2, "{seen:?}");
+        assert_eq!(fetches[0], fetches[1], "{seen:?}");
+    }
+
+    #[test]
+    fn a_client_that_cannot_be_built_is_an_error() {
+        let unapplied = build(Err(anyhow::anyhow!("invalid proxy URL")), Policy::default());
+        assert_eq!(
+            unapplied.unwrap_err().to_string(),
+            "HTTP client unavailable: invalid proxy URL"
+        );
+
+        let unbuildable = build(
+            Ok(reqwest::Client::builder().use_preconfigured_tls(())),
+            Policy::default(),
+        );
+        assert!(unbuildable.is_err());
+    }
+}
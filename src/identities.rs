@@ -0,0 +1,455 @@
+//! Nostr identities: several keypairs, each stored encrypted (NIP-49 `ncryptsec`) or as a
+//! read-only npub, plus a default and per-origin choice of which one a site sees.
+//!
+//! The store lives next to `frontier.toml` as `identities.toml` and is edited from
+//! `frontier://identities`, whose forms submit back to that URL like the settings page.
+//! Secret keys are only decrypted by [`IdentityStore::unlock`], which the page signer and
+//! `frontier publish` call with a password supplied by the user. The browser keeps unlocked
+//! keys in memory for the session; `action=unlock` and `action=lock` are handled there.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use nostr_sdk::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr_sdk::prelude::{FromBech32, Keys, PublicKey, ToBech32};
+use serde::{Deserialize, Serialize};
+
+use crate::storage_policy::StoragePolicy;
+
+pub const IDENTITIES_URL: &str = "frontier://identities";
+const FILE_NAME: &str = "identities.toml";
+/// NIP-49 scrypt cost; 16 keeps unlocking well under a second.
+const SCRYPT_LOG_N: u8 = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub label: String,
+    pub npub: String,
+    /// `ncryptsec1...`; absent for read-only profiles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_secret: Option<String>,
+}
+
+impl Identity {
+    pub fn can_sign(&self) -> bool {
+        self.encrypted_secret.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdentityStore {
+    pub identities: Vec<Identity>,
+    /// npub used where no per-origin choice exists.
+    pub default: Option<String>,
+    /// Origin (`https://example.com`) to npub.
+    pub sites: BTreeMap<String, String>,
+}
+
+impl IdentityStore {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::preferences::data_file(FILE_NAME)
+    }
+
+    /// Read the store from `path`; a missing file yields an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(raw) => toml::from_str(&raw)
+                .with_context(|| format!("invalid identities file {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("unable to read identities {}", path.display()))
+            }
+        }
+    }
+
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Refused in a private session, so a key generated there is not silently lost on exit.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !StoragePolicy::current().persists() {
+            bail!("identities cannot be changed in a private window");
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let raw = toml::to_string_pretty(self).context("serialize identities")?;
+        std::fs::write(path, raw)
+            .with_context(|| format!("unable to write identities {}", path.display()))
+    }
+
+    pub fn get(&self, npub: &str) -> Option<&Identity> {
+        self.identities
+            .iter()
+            .find(|identity| identity.npub == npub)
+    }
+
+    /// Generate a fresh keypair and store it encrypted with `password`. Returns its npub.
+    pub fn generate(&mut self, label: &str, password: &str) -> Result<String> {
+        self.insert_keys(label, &Keys::generate(), password)
+    }
+
+    /// Import an `nsec` (or hex secret) and store it encrypted with `password`.
+    pub fn import_secret(&mut self, label: &str, secret: &str, password: &str) -> Result<String> {
+        let keys =
+            Keys::parse(secret.trim()).map_err(|err| anyhow!("invalid secret key: {err}"))?;
+        self.insert_keys(label, &keys, password)
+    }
+
+    /// Add a read-only profile that can be selected but never signs.
+    pub fn add_public(&mut self, label: &str, npub: &str) -> Result<String> {
+        let public_key = parse_public_key(npub)?;
+        let npub = public_key.to_bech32()?;
+        self.insert(Identity {
+            label: label_or_default(label, &npub),
+            npub: npub.clone(),
+            encrypted_secret: None,
+        })?;
+        Ok(npub)
+    }
+
+    fn insert_keys(&mut self, label: &str, keys: &Keys, password: &str) -> Result<String> {
+        if password.is_empty() {
+            bail!("a password is required to store a secret key");
+        }
+        let npub = keys.public_key().to_bech32()?;
+        let encrypted = EncryptedSecretKey::new(
+            keys.secret_key()?,
+            password,
+            SCRYPT_LOG_N,
+            KeySecurity::Unknown,
+        )
+        .map_err(|err| anyhow!("unable to encrypt secret key: {err}"))?;
+        self.insert(Identity {
+            label: label_or_default(label, &npub),
+            npub: npub.clone(),
+            encrypted_secret: Some(encrypted.to_bech32()?),
+        })?;
+        Ok(npub)
+    }
+
+    fn insert(&mut self, identity: Identity) -> Result<()> {
+        if let Some(existing) = self.get(&identity.npub) {
+            bail!("{} is already stored as {}", identity.npub, existing.label);
+        }
+        if self.default.is_none() {
+            self.default = Some(identity.npub.clone());
+        }
+        self.identities.push(identity);
+        Ok(())
+    }
+
+    /// Forget an identity along with every site that used it.
+    pub fn remove(&mut self, npub: &str) -> bool {
+        let before = self.identities.len();
+        self.identities.retain(|identity| identity.npub != npub);
+        self.sites.retain(|_, selected| selected != npub);
+        if self.default.as_deref() == Some(npub) {
+            self.default = self
+                .identities
+                .first()
+                .map(|identity| identity.npub.clone());
+        }
+        self.identities.len() != before
+    }
+
+    pub fn set_default(&mut self, npub: &str) -> Result<()> {
+        self.require(npub)?;
+        self.default = Some(npub.to_string());
+        Ok(())
+    }
+
+    /// Choose the identity `origin` sees; `None` falls back to the default again.
+    pub fn select_for_origin(&mut self, origin: &str, npub: Option<&str>) -> Result<()> {
+        match npub {
+            Some(npub) => {
+                self.require(npub)?;
+                self.sites.insert(origin.to_string(), npub.to_string());
+            }
+            None => {
+                self.sites.remove(origin);
+            }
+        }
+        Ok(())
+    }
+
+    /// The identity a page from `origin` acts as.
+    pub fn for_origin(&self, origin: &str) -> Option<&Identity> {
+        self.sites
+            .get(origin)
+            .or(self.default.as_ref())
+            .and_then(|npub| self.get(npub))
+    }
+
+    /// Decrypt the secret key of `npub`.
+    pub fn unlock(&self, npub: &str, password: &str) -> Result<Keys> {
+        let identity = self.require(npub)?;
+        let encrypted = identity
+            .encrypted_secret
+            .as_deref()
+            .ok_or_else(|| anyhow!("{} is a read-only profile", identity.label))?;
+        let secret = EncryptedSecretKey::from_bech32(encrypted)
+            .map_err(|err| anyhow!("corrupt secret key for {}: {err}", identity.label))?
+            .to_secret_key(password)
+            .map_err(|_| anyhow!("wrong password for {}", identity.label))?;
+        Ok(Keys::new(secret))
+    }
+
+    fn require(&self, npub: &str) -> Result<&Identity> {
+        self.get(npub).ok_or_else(|| anyhow!("no identity {npub}"))
+    }
+
+    /// Apply a `frontier://identities?action=...` submission. Returns the status line to show.
+    pub fn apply_form(&mut self, query: &str) -> Result<String> {
+        let fields: BTreeMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+            .map(|(key, value)| (key.into_owned(), value.trim().to_string()))
+            .collect();
+        let field = |name: &str| fields.get(name).map(String::as_str).unwrap_or("");
+        match field("action") {
+            "generate" => {
+                let npub = self.generate(field("label"), field("password"))?;
+                Ok(format!("Generated {npub}."))
+            }
+            "import" => {
+                let npub = self.import_secret(field("label"), field("nsec"), field("password"))?;
+                Ok(format!("Imported {npub}."))
+            }
+            "watch" => {
+                let npub = self.add_public(field("label"), field("npub"))?;
+                Ok(format!("Added read-only profile {npub}."))
+            }
+            "remove" => {
+                if self.remove(field("npub")) {
+                    Ok(format!("Removed {}.", field("npub")))
+                } else {
+                    bail!("no identity {}", field("npub"))
+                }
+            }
+            "default" => {
+                self.set_default(field("npub"))?;
+                Ok(format!("{} is now the default identity.", field("npub")))
+            }
+            "site" => {
+                let origin = field("origin");
+                if origin.is_empty() {
+                    bail!("enter the origin to assign");
+                }
+                let npub = Some(field("npub")).filter(|npub| !npub.is_empty());
+                self.select_for_origin(origin, npub)?;
+                Ok(format!("Updated the identity for {origin}."))
+            }
+            "" => Ok(String::new()),
+            other => bail!("unknown identity action {other}"),
+        }
+    }
+
+    /// HTML body of `frontier://identities`. `unlocked` holds the npubs whose secret keys are
+    /// decrypted for page signing in this session.
+    pub fn page(&self, status: Option<&str>, unlocked: &[String]) -> String {
+        let rows = if self.identities.is_empty() {
+            "<tr><td colspan=\"4\">No identities yet.</td></tr>".to_string()
+        } else {
+            self.identities
+                .iter()
+                .map(|identity| {
+                    let is_default = self.default.as_deref() == Some(identity.npub.as_str());
+                    let default_link = if is_default {
+                        "default".to_string()
+                    } else {
+                        format!(
+                            "<a href=\"{}\">Make default</a>",
+                            action_href("default", &identity.npub)
+                        )
+                    };
+                    let kind = if !identity.can_sign() {
+                        "read-only".to_string()
+                    } else if unlocked.contains(&identity.npub) {
+                        format!(
+                            "signing, unlocked <a href=\"{}\">Lock</a>",
+                            action_href("lock", &identity.npub)
+                        )
+                    } else {
+                        format!(
+                            "<form action=\"{IDENTITIES_URL}\" method=\"get\"><input type=\"hidden\" name=\"action\" value=\"unlock\"><input type=\"hidden\" name=\"npub\" value=\"{npub}\"><input type=\"password\" name=\"password\" aria-label=\"Password\"> <input type=\"submit\" value=\"Unlock\"></form>",
+                            npub = encode_double_quoted_attribute(&identity.npub),
+                        )
+                    };
+                    format!(
+                        "<tr><td>{label}</td><td><code>{npub}</code></td><td>{kind}</td><td>{default_link} <a href=\"{remove}\">Remove</a></td></tr>",
+                        label = encode_text(&identity.label),
+                        npub = encode_text(&identity.npub),
+                        remove = action_href("remove", &identity.npub),
+                    )
+                })
+                .collect()
+        };
+        let sites = self
+            .sites
+            .iter()
+            .map(|(origin, npub)| {
+                let label = self
+                    .get(npub)
+                    .map(|identity| identity.label.as_str())
+                    .unwrap_or(npub);
+                format!(
+                    "<li><code>{}</code> uses {}</li>",
+                    encode_text(origin),
+                    encode_text(label)
+                )
+            })
+            .collect::<String>();
+        let options = self
+            .identities
+            .iter()
+            .map(|identity| {
+                format!(
+                    "<option value=\"{}\">{}</option>",
+                    encode_double_quoted_attribute(&identity.npub),
+                    encode_text(&identity.label)
+                )
+            })
+            .collect::<String>();
+        let status = status
+            .filter(|message| !message.is_empty())
+            .map(|message| {
+                format!(
+                    "<p class=\"identities-status\" role=\"status\">{}</p>",
+                    encode_text(message)
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            r#"<section class="identities">
+<h1>Identities</h1>
+{status}
+<table>
+<thead><tr><th>Label</th><th>Public key</th><th>Kind</th><th></th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+<h2>Per-site identities</h2>
+<ul>{sites}</ul>
+<form action="{IDENTITIES_URL}" method="get">
+<input type="hidden" name="action" value="site">
+<p><label for="site-origin">Origin</label><br><input type="url" id="site-origin" name="origin" placeholder="https://example.com">
+<select name="npub" aria-label="Identity"><option value="">Use default</option>{options}</select> <input type="submit" value="Assign"></p>
+</form>
+<h2>Add an identity</h2>
+<form action="{IDENTITIES_URL}" method="get">
+<input type="hidden" name="action" value="generate">
+<p><label for="generate-label">Label</label><br><input type="text" id="generate-label" name="label">
+<label for="generate-password">Password</label> <input type="password" id="generate-password" name="password"> <input type="submit" value="Generate key"></p>
+</form>
+<form action="{IDENTITIES_URL}" method="get">
+<input type="hidden" name="action" value="import">
+<p><label for="import-label">Label</label><br><input type="text" id="import-label" name="label">
+<label for="import-nsec">nsec</label> <input type="password" id="import-nsec" name="nsec">
+<label for="import-password">Password</label> <input type="password" id="import-password" name="password"> <input type="submit" value="Import"></p>
+</form>
+<form action="{IDENTITIES_URL}" method="get">
+<input type="hidden" name="action" value="watch">
+<p><label for="watch-label">Label</label><br><input type="text" id="watch-label" name="label">
+<label for="watch-npub">npub</label> <input type="text" id="watch-npub" name="npub"> <input type="submit" value="Add read-only"></p>
+</form>
+</section>"#
+        )
+    }
+}
+
+fn action_href(action: &str, npub: &str) -> String {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("action", action)
+        .append_pair("npub", npub)
+        .finish();
+    encode_double_quoted_attribute(&format!("{IDENTITIES_URL}?{query}")).into_owned()
+}
+
+fn label_or_default(label: &str, npub: &str) -> String {
+    let label = label.trim();
+    if label.is_empty() {
+        npub.chars().take(16).collect()
+    } else {
+        label.to_string()
+    }
+}
+
+fn parse_public_key(raw: &str) -> Result<PublicKey> {
+    let raw = raw.trim();
+    PublicKey::from_bech32(raw)
+        .or_else(|_| PublicKey::from_hex(raw))
+        .map_err(|err| anyhow!("invalid npub {raw}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_encrypted_keys_and_unlocks_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        let keys = Keys::generate();
+        let nsec = keys.secret_key().unwrap().to_bech32().unwrap();
+
+        let mut store = IdentityStore::default();
+        let npub = store.import_secret("work", &nsec, "hunter2").unwrap();
+        assert_eq!(store.default.as_deref(), Some(npub.as_str()));
+        store.save(&path).unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains(&nsec), "secret key is stored encrypted");
+        let store = IdentityStore::load(&path).unwrap();
+        assert_eq!(
+            store.unlock(&npub, "hunter2").unwrap().public_key(),
+            keys.public_key()
+        );
+        assert!(store.unlock(&npub, "wrong").is_err());
+    }
+
+    #[test]
+    fn origins_fall_back_to_the_default_identity() {
+        let mut store = IdentityStore::default();
+        let first = store
+            .add_public("alice", &Keys::generate().public_key().to_bech32().unwrap())
+            .unwrap();
+        let second = store
+            .add_public("bob", &Keys::generate().public_key().to_bech32().unwrap())
+            .unwrap();
+        store
+            .select_for_origin("https://b.example", Some(&second))
+            .unwrap();
+
+        assert_eq!(store.for_origin("https://a.example").unwrap().npub, first);
+        assert_eq!(store.for_origin("https://b.example").unwrap().npub, second);
+        assert!(
+            store.unlock(&first, "").is_err(),
+            "read-only profiles never sign"
+        );
+
+        store.remove(&second);
+        assert!(store.sites.is_empty());
+        assert_eq!(store.for_origin("https://b.example").unwrap().npub, first);
+    }
+
+    #[test]
+    fn form_actions_report_status() {
+        let mut store = IdentityStore::default();
+        let npub = Keys::generate().public_key().to_bech32().unwrap();
+        let status = store
+            .apply_form(&format!("action=watch&label=Alice&npub={npub}"))
+            .unwrap();
+        assert!(status.contains(&npub));
+        assert!(store
+            .apply_form("action=generate&label=x&password=")
+            .is_err());
+        assert!(store.page(Some(&status), &[]).contains("read-only"));
+    }
+}
@@ -0,0 +1,637 @@
+//! `Blob`, `File` and `FormData` for page scripts.
+//!
+//! Blob bytes live in a [`BlobStore`] on the Rust side; the JS objects only hold an id, so a
+//! large upload is never copied through JS strings. A blob is a list of shared chunks, which
+//! makes `slice()` and building a blob out of other blobs free. `fetch()` sends blob and
+//! `FormData` bodies by id: the shim frames multipart bodies as a blob of text chunks and the
+//! parts' blobs, and [`FetchManager`](super::fetch::FetchManager) resolves the id to bytes.
+//! A `FinalizationRegistry` releases the bytes once the JS object is collected.
+//!
+//! Files the user hands to the page, by dropping them onto the window or picking them for a
+//! file input, are read by the host as [`LocalFile`]s and stored the same way.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use rquickjs::{ArrayBuffer, Ctx, Function};
+use serde::Serialize;
+
+use crate::dev_server::mime_type_for;
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    data: Rc<[u8]>,
+    range: Range<usize>,
+}
+
+#[derive(Debug, Default)]
+struct Blobs {
+    next_id: u32,
+    blobs: HashMap<u32, Vec<Chunk>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlobStore {
+    inner: Rc<RefCell<Blobs>>,
+}
+
+impl BlobStore {
+    /// Start an empty blob and return its id.
+    pub(crate) fn create(&self) -> u32 {
+        let mut inner = self.inner.borrow_mut();
+        inner.next_id = inner.next_id.wrapping_add(1).max(1);
+        let id = inner.next_id;
+        inner.blobs.insert(id, Vec::new());
+        id
+    }
+
+    pub(crate) fn push_bytes(&self, id: u32, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if let Some(chunks) = self.inner.borrow_mut().blobs.get_mut(&id) {
+            chunks.push(Chunk {
+                data: Rc::from(bytes),
+                range: 0..bytes.len(),
+            });
+        }
+    }
+
+    /// Append bytes `start..end` of blob `source` to blob `id`, sharing its chunks.
+    pub(crate) fn push_blob(&self, id: u32, source: u32, start: usize, end: usize) {
+        let mut inner = self.inner.borrow_mut();
+        let Some(chunks) = inner.blobs.get(&source) else {
+            return;
+        };
+        let mut shared = Vec::new();
+        let mut offset = 0;
+        for chunk in chunks {
+            let len = chunk.range.len();
+            let from = start.max(offset).min(offset + len) - offset;
+            let to = end.max(offset).min(offset + len) - offset;
+            if from < to {
+                shared.push(Chunk {
+                    data: Rc::clone(&chunk.data),
+                    range: chunk.range.start + from..chunk.range.start + to,
+                });
+            }
+            offset += len;
+        }
+        if let Some(target) = inner.blobs.get_mut(&id) {
+            target.extend(shared);
+        }
+    }
+
+    pub(crate) fn size(&self, id: u32) -> usize {
+        self.inner.borrow().blobs.get(&id).map_or(0, |chunks| {
+            chunks.iter().map(|chunk| chunk.range.len()).sum()
+        })
+    }
+
+    /// The blob's contents, or `None` for an unknown or released id.
+    pub(crate) fn bytes(&self, id: u32) -> Option<Vec<u8>> {
+        let inner = self.inner.borrow();
+        let chunks = inner.blobs.get(&id)?;
+        let mut bytes = Vec::with_capacity(chunks.iter().map(|chunk| chunk.range.len()).sum());
+        for chunk in chunks {
+            bytes.extend_from_slice(&chunk.data[chunk.range.clone()]);
+        }
+        Some(bytes)
+    }
+
+    pub(crate) fn release(&self, id: u32) {
+        self.inner.borrow_mut().blobs.remove(&id);
+    }
+
+    /// Drop every blob when the page goes away.
+    pub(crate) fn clear(&self) {
+        self.inner.borrow_mut().blobs.clear();
+    }
+}
+
+/// A file read from disk for page script. Directories read as empty files with no type, which
+/// is what pages see for them in browsers too.
+#[derive(Debug, Clone)]
+pub struct LocalFile {
+    pub name: String,
+    pub mime: String,
+    /// Modification time in milliseconds since the Unix epoch.
+    pub last_modified: f64,
+    pub bytes: Vec<u8>,
+}
+
+impl LocalFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let last_modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0.0, |since| since.as_secs_f64() * 1000.0);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if metadata.is_dir() {
+            return Ok(Self {
+                name,
+                mime: String::new(),
+                last_modified,
+                bytes: Vec::new(),
+            });
+        }
+        let bytes =
+            std::fs::read(path).with_context(|| format!("unable to read {}", path.display()))?;
+        // `File.type` is a bare MIME type, without the charset the dev server serves it with.
+        let mime = mime_type_for(path)
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Ok(Self {
+            name,
+            mime,
+            last_modified,
+            bytes,
+        })
+    }
+}
+
+/// A [`LocalFile`] stored in the [`BlobStore`], as `frontier.__adoptFile` takes it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LocalFileRecord<'a> {
+    blob: u32,
+    name: &'a str,
+    #[serde(rename = "type")]
+    mime: &'a str,
+    last_modified: f64,
+}
+
+impl BlobStore {
+    /// Store each file's bytes as a new blob.
+    pub(crate) fn store_files<'a>(&self, files: &'a [LocalFile]) -> Vec<LocalFileRecord<'a>> {
+        files
+            .iter()
+            .map(|file| {
+                let blob = self.create();
+                self.push_bytes(blob, &file.bytes);
+                LocalFileRecord {
+                    blob,
+                    name: &file.name,
+                    mime: &file.mime,
+                    last_modified: file.last_modified,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Register the natives [`BLOB_BOOTSTRAP`] stores blob bytes through.
+pub(crate) fn install_blob_bindings<'js>(
+    ctx: &Ctx<'js>,
+    store: &BlobStore,
+) -> rquickjs::Result<()> {
+    let global = ctx.globals();
+    let blobs = store.clone();
+    let func = Function::new(ctx.clone(), move || -> rquickjs::Result<u32> {
+        Ok(blobs.create())
+    })?
+    .with_name("__frontier_blob_create")?;
+    global.set("__frontier_blob_create", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |id: u32, text: String| -> rquickjs::Result<()> {
+            blobs.push_bytes(id, text.as_bytes());
+            Ok(())
+        },
+    )?
+    .with_name("__frontier_blob_push_text")?;
+    global.set("__frontier_blob_push_text", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |id: u32, buffer: ArrayBuffer<'js>| -> rquickjs::Result<()> {
+            blobs.push_bytes(id, buffer.as_bytes().unwrap_or_default());
+            Ok(())
+        },
+    )?
+    .with_name("__frontier_blob_push_bytes")?;
+    global.set("__frontier_blob_push_bytes", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |id: u32, source: u32, start: f64, end: f64| -> rquickjs::Result<()> {
+            blobs.push_blob(id, source, start as usize, end as usize);
+            Ok(())
+        },
+    )?
+    .with_name("__frontier_blob_push_blob")?;
+    global.set("__frontier_blob_push_blob", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(ctx.clone(), move |id: u32| -> rquickjs::Result<f64> {
+        Ok(blobs.size(id) as f64)
+    })?
+    .with_name("__frontier_blob_size")?;
+    global.set("__frontier_blob_size", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(ctx.clone(), move |id: u32| -> rquickjs::Result<String> {
+        let bytes = blobs.bytes(id).unwrap_or_default();
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    })?
+    .with_name("__frontier_blob_text")?;
+    global.set("__frontier_blob_text", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, id: u32| -> rquickjs::Result<ArrayBuffer<'js>> {
+            ArrayBuffer::new(ctx, blobs.bytes(id).unwrap_or_default())
+        },
+    )?
+    .with_name("__frontier_blob_bytes")?;
+    global.set("__frontier_blob_bytes", func)?;
+
+    let blobs = store.clone();
+    let func = Function::new(ctx.clone(), move |id: u32| -> rquickjs::Result<()> {
+        blobs.release(id);
+        Ok(())
+    })?
+    .with_name("__frontier_blob_release")?;
+    global.set("__frontier_blob_release", func)?;
+    Ok(())
+}
+
+/// JS half: `Blob`, `File` and `FormData`, plus `frontier.__blobId(blob)` and
+/// `frontier.__formDataBody(formData)` for the fetch shim, and `frontier.__adoptBlob(id, type)`
+/// and `frontier.__adoptFile(id, ...)` for bytes the host stored itself.
+pub(crate) const BLOB_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const blobBytes = global.__frontier_blob_bytes;
+    const blobCreate = global.__frontier_blob_create;
+    const blobPushBlob = global.__frontier_blob_push_blob;
+    const blobPushBytes = global.__frontier_blob_push_bytes;
+    const blobPushText = global.__frontier_blob_push_text;
+    const blobRelease = global.__frontier_blob_release;
+    const blobSize = global.__frontier_blob_size;
+    const blobText = global.__frontier_blob_text;
+    const frontier = global.frontier;
+    const IDS = new WeakMap();
+    const registry =
+        typeof global.FinalizationRegistry === 'function'
+            ? new global.FinalizationRegistry((id) => blobRelease(id))
+            : null;
+
+    const normalizeType = (type) => {
+        const value = type === undefined ? '' : String(type);
+        return /^[\x20-\x7e]*$/.test(value) ? value.toLowerCase() : '';
+    };
+
+    const clampIndex = (value, size, fallback) => {
+        if (value === undefined) {
+            return fallback;
+        }
+        const index = Math.trunc(Number(value)) || 0;
+        return index < 0 ? Math.max(size + index, 0) : Math.min(index, size);
+    };
+
+    const pushPart = (id, part) => {
+        if (part instanceof Blob) {
+            blobPushBlob(id, IDS.get(part), 0, part.size);
+        } else if (part instanceof ArrayBuffer) {
+            blobPushBytes(id, part);
+        } else if (ArrayBuffer.isView(part)) {
+            const start = part.byteOffset;
+            blobPushBytes(id, part.buffer.slice(start, start + part.byteLength));
+        } else {
+            blobPushText(id, String(part));
+        }
+    };
+
+    class Blob {
+        constructor(parts = [], options = {}) {
+            if (parts === null || typeof parts !== 'object' || !(Symbol.iterator in parts)) {
+                throw new TypeError("Failed to construct 'Blob': parts must be a sequence.");
+            }
+            const id = blobCreate();
+            for (const part of parts) {
+                pushPart(id, part);
+            }
+            IDS.set(this, id);
+            if (registry) {
+                registry.register(this, id);
+            }
+            this._size = blobSize(id);
+            this._type = normalizeType(options && options.type);
+        }
+        get size() {
+            return this._size;
+        }
+        get type() {
+            return this._type;
+        }
+        slice(start, end, contentType) {
+            const from = clampIndex(start, this.size, 0);
+            const to = clampIndex(end, this.size, this.size);
+            const blob = Object.create(Blob.prototype);
+            const id = blobCreate();
+            blobPushBlob(id, IDS.get(this), from, Math.max(to, from));
+            IDS.set(blob, id);
+            if (registry) {
+                registry.register(blob, id);
+            }
+            blob._size = blobSize(id);
+            blob._type = normalizeType(contentType);
+            return blob;
+        }
+        text() {
+            return Promise.resolve(blobText(IDS.get(this)));
+        }
+        arrayBuffer() {
+            return Promise.resolve(blobBytes(IDS.get(this)));
+        }
+        bytes() {
+            return this.arrayBuffer().then((buffer) => new Uint8Array(buffer));
+        }
+        get [Symbol.toStringTag]() {
+            return 'Blob';
+        }
+    }
+
+    class File extends Blob {
+        constructor(parts, name, options = {}) {
+            if (arguments.length < 2) {
+                throw new TypeError("Failed to construct 'File': 2 arguments required.");
+            }
+            super(parts, options);
+            this._name = String(name);
+            const lastModified = options ? options.lastModified : undefined;
+            this._lastModified = lastModified !== undefined ? Number(lastModified) : Date.now();
+        }
+        get name() {
+            return this._name;
+        }
+        get lastModified() {
+            return this._lastModified;
+        }
+        get webkitRelativePath() {
+            return '';
+        }
+        get [Symbol.toStringTag]() {
+            return 'File';
+        }
+    }
+
+    const entryValue = (value, filename) => {
+        if (value instanceof Blob) {
+            if (value instanceof File && filename === undefined) {
+                return value;
+            }
+            const name = filename !== undefined ? String(filename) : 'blob';
+            return new File([value], name, {
+                type: value.type,
+                lastModified: value instanceof File ? value.lastModified : undefined,
+            });
+        }
+        return String(value);
+    };
+
+    function* descendants(node) {
+        for (const child of Array.from(node.children || [])) {
+            yield child;
+            yield* descendants(child);
+        }
+    }
+
+    const CONTROLS = ['input', 'select', 'textarea', 'button'];
+    const SKIPPED_TYPES = ['submit', 'reset', 'button', 'image'];
+
+    // The form's successful controls, in tree order. A file input with nothing selected
+    // submits an empty, unnamed file.
+    const formEntries = (form) => {
+        const entries = [];
+        for (const control of descendants(form)) {
+            const tag = String(control.localName || '').toLowerCase();
+            const name = control.getAttribute('name');
+            if (!CONTROLS.includes(tag) || !name || control.hasAttribute('disabled')) {
+                continue;
+            }
+            const type = String(control.getAttribute('type') || '').toLowerCase();
+            if (tag === 'button' || SKIPPED_TYPES.includes(type)) {
+                continue;
+            }
+            if (type === 'file') {
+                const files = Array.from(control.files || []);
+                if (files.length === 0) {
+                    files.push(new File([], '', { type: 'application/octet-stream' }));
+                }
+                files.forEach((file) => entries.push([name, file]));
+            } else if (type === 'checkbox' || type === 'radio') {
+                const checked = control.checked ?? control.hasAttribute('checked');
+                if (checked) {
+                    entries.push([name, control.getAttribute('value') ?? 'on']);
+                }
+            } else if (tag === 'select') {
+                for (const option of descendants(control)) {
+                    if (option.localName === 'option' && option.hasAttribute('selected')) {
+                        entries.push([name, option.getAttribute('value') ?? option.textContent]);
+                    }
+                }
+            } else if (tag === 'textarea') {
+                entries.push([name, control.value ?? control.textContent]);
+            } else {
+                entries.push([name, control.value ?? control.getAttribute('value') ?? '']);
+            }
+        }
+        return entries;
+    };
+
+    class FormData {
+        constructor(form) {
+            this._entries = [];
+            if (form !== undefined && form !== null) {
+                if (String(form.localName || '').toLowerCase() !== 'form') {
+                    throw new TypeError("Failed to construct 'FormData': not a form element.");
+                }
+                this._entries = formEntries(form);
+            }
+        }
+        append(name, value, filename) {
+            this._entries.push([String(name), entryValue(value, filename)]);
+        }
+        set(name, value, filename) {
+            const key = String(name);
+            const entry = [key, entryValue(value, filename)];
+            const index = this._entries.findIndex((existing) => existing[0] === key);
+            if (index === -1) {
+                this._entries.push(entry);
+                return;
+            }
+            // Only later entries are dropped, so `index` still points at the first match.
+            this._entries = this._entries.filter((entry, i) => entry[0] !== key || i === index);
+            this._entries[index] = entry;
+        }
+        delete(name) {
+            const key = String(name);
+            this._entries = this._entries.filter((entry) => entry[0] !== key);
+        }
+        get(name) {
+            const entry = this._entries.find((existing) => existing[0] === String(name));
+            return entry ? entry[1] : null;
+        }
+        getAll(name) {
+            const key = String(name);
+            return this._entries.filter((entry) => entry[0] === key).map((entry) => entry[1]);
+        }
+        has(name) {
+            return this._entries.some((entry) => entry[0] === String(name));
+        }
+        *entries() {
+            for (const entry of this._entries.slice()) {
+                yield [entry[0], entry[1]];
+            }
+        }
+        *keys() {
+            for (const entry of this.entries()) {
+                yield entry[0];
+            }
+        }
+        *values() {
+            for (const entry of this.entries()) {
+                yield entry[1];
+            }
+        }
+        forEach(callback, thisArg) {
+            for (const [name, value] of this.entries()) {
+                callback.call(thisArg, value, name, this);
+            }
+        }
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+        get [Symbol.toStringTag]() {
+            return 'FormData';
+        }
+    }
+
+    const escapeName = (name) =>
+        name.replace(/\r/g, '%0D').replace(/\n/g, '%0A').replace(/"/g, '%22');
+
+    // A `multipart/form-data` body as one blob, and its content type.
+    frontier.__formDataBody = (formData) => {
+        const random = Math.random().toString(16).slice(2).padEnd(16, '0');
+        const boundary = `----FrontierFormBoundary${random}`;
+        const parts = [];
+        for (const [name, value] of formData.entries()) {
+            let head = `--${boundary}\r\nContent-Disposition: form-data`;
+            head += `; name="${escapeName(name)}"`;
+            if (value instanceof File) {
+                head += `; filename="${escapeName(value.name)}"\r\n`;
+                head += `Content-Type: ${value.type || 'application/octet-stream'}\r\n\r\n`;
+                parts.push(head, value, '\r\n');
+            } else {
+                parts.push(`${head}\r\n\r\n`, value.replace(/\r?\n|\r/g, '\r\n'), '\r\n');
+            }
+        }
+        parts.push(`--${boundary}--\r\n`);
+        return { blob: new Blob(parts), contentType: `multipart/form-data; boundary=${boundary}` };
+    };
+
+    frontier.__blobId = (blob) => IDS.get(blob);
+
+    // A `Blob` over one the host already filled, such as a fetched response body.
+    frontier.__adoptBlob = (id, type) => {
+        const blob = Object.create(Blob.prototype);
+        IDS.set(blob, id);
+        if (registry) {
+            registry.register(blob, id);
+        }
+        blob._size = blobSize(id);
+        blob._type = normalizeType(type);
+        return blob;
+    };
+
+    // A `File` over a blob the host already filled, such as a file dropped onto the window.
+    frontier.__adoptFile = (id, name, type, lastModified) => {
+        const file = Object.create(File.prototype);
+        IDS.set(file, id);
+        if (registry) {
+            registry.register(file, id);
+        }
+        file._size = blobSize(id);
+        file._type = normalizeType(type);
+        file._name = String(name);
+        file._lastModified = Number(lastModified) || 0;
+        return file;
+    };
+
+    global.Blob = Blob;
+    global.File = File;
+    global.FormData = FormData;
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slices_share_chunks_across_blobs() {
+        let store = BlobStore::default();
+        let first = store.create();
+        store.push_bytes(first, b"hello ");
+        store.push_bytes(first, b"world");
+        assert_eq!(store.size(first), 11);
+
+        let slice = store.create();
+        store.push_blob(slice, first, 4, 8);
+        assert_eq!(store.bytes(slice).as_deref(), Some(b"o wo".as_slice()));
+
+        let joined = store.create();
+        store.push_blob(joined, slice, 0, 99);
+        store.push_bytes(joined, b"!");
+        assert_eq!(store.bytes(joined).as_deref(), Some(b"o wo!".as_slice()));
+
+        store.release(first);
+        assert_eq!(store.bytes(first), None);
+        assert_eq!(store.bytes(slice).as_deref(), Some(b"o wo".as_slice()));
+    }
+
+    #[test]
+    fn reads_local_files_and_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "# Notes\n").unwrap();
+
+        let file = LocalFile::read(&path).unwrap();
+        assert_eq!(file.name, "notes.md");
+        assert_eq!(file.mime, "text/markdown");
+        assert_eq!(file.bytes, b"# Notes\n");
+        assert!(file.last_modified > 0.0);
+
+        let folder = LocalFile::read(dir.path()).unwrap();
+        assert!(folder.mime.is_empty());
+        assert!(folder.bytes.is_empty());
+        assert!(LocalFile::read(&dir.path().join("missing")).is_err());
+
+        let store = BlobStore::default();
+        let records = store.store_files(std::slice::from_ref(&file));
+        assert_eq!(
+            store.bytes(records[0].blob).as_deref(),
+            Some(file.bytes.as_slice())
+        );
+    }
+}
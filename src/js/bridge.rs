@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::ptr::NonNull;
 
 use anyhow::{anyhow, Result};
@@ -10,30 +11,134 @@ use style::invalidation::element::restyle_hints::RestyleHint;
 use style::properties::{style_structs::Font, ComputedValues};
 use style::selector_parser::RestyleDamage;
 
+use super::canvas::CANVAS_ATTRIBUTE;
+use super::custom_elements::is_valid_custom_element_name;
+use super::shadow::{self, HOST_ATTRIBUTE};
+
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+/// The HTML elements `attachShadow` accepts besides autonomous custom elements.
+const SHADOW_HOST_NAMES: [&str; 18] = [
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "main",
+    "nav",
+    "p",
+    "section",
+    "span",
+];
+
 pub struct BlitzJsBridge {
+    /// The live document. `RuntimeDocument::attach` only hands over a boxed document and its
+    /// `Drop` detaches the bridge. Shared ownership is blocked on blitz; what it needs is in
+    /// `notes/document_ownership.md`.
     document: NonNull<BaseDocument>,
     id_index: HashMap<String, usize>,
     comment_payloads: HashMap<usize, String>,
+    /// Each `<template>`'s content: a detached holder its parsed children were moved into,
+    /// so they are neither rendered nor found by lookups on the live tree.
+    template_contents: HashMap<usize, usize>,
+    /// The content holders (and their clones), which scripts see as document fragments.
+    template_fragments: HashSet<usize>,
+    /// Shadow roots by host. A host's children in the Blitz tree are its shadow tree, with
+    /// each light child moved into the `<slot>` it is assigned to or detached, so layout sees
+    /// the composed tree while scripts walk the logical one.
+    shadow_hosts: HashMap<usize, ShadowHost>,
+    /// The host of each shadow root. A root is a detached holder with no children of its own.
+    shadow_root_hosts: HashMap<usize, usize>,
+    /// The fallback children of each `<slot>` in a shadow tree, which it holds in the Blitz
+    /// tree only while nothing is assigned to it.
+    slot_fallbacks: HashMap<usize, Vec<usize>>,
+    /// The `<style>` elements whose rules Stylo sees in another form than their text: scoped
+    /// to their shadow host, or edited through the CSSOM.
+    rendered_styles: HashMap<usize, RenderedStyle>,
+    /// The `<style>` holding the `adoptedStyleSheets` of the document, keyed by its node, or
+    /// of a shadow root. It is the last child of the document node or of the host's shadow
+    /// tree in the Blitz tree, and scripts never see it.
+    adopted_styles: HashMap<usize, usize>,
+    /// The `<style>` the bridge keeps inside an element: the stylesheet a `<link
+    /// rel="stylesheet">` inserted by script loaded, or the bitmap a `<canvas>` was drawn
+    /// with. It is the element's last child in the Blitz tree, and scripts never see it.
+    element_styles: HashMap<usize, usize>,
+    /// The `<style>` elements rendered empty because they are out of the document, with their
+    /// text kept in `rendered_styles`.
+    detached_styles: HashSet<usize>,
+}
+
+struct RenderedStyle {
+    /// The text scripts see.
+    text: String,
+    /// The rules as edited through the CSSOM, which replace the text until it is set again.
+    rules: Option<String>,
+    /// What the element holds in the Blitz tree: the rules, or the text, scoped to the host
+    /// when the element is in a shadow tree.
+    rendered: String,
+}
+
+struct ShadowHost {
+    root: usize,
+    open: bool,
+    /// The host's children as scripts see them.
+    light: Vec<usize>,
 }
 
 impl BlitzJsBridge {
-    pub fn new(document: &mut BaseDocument) -> Self {
+    /// Operate on `document` through a pointer to it.
+    ///
+    /// # Safety
+    ///
+    /// `document` must not move or be dropped while the bridge exists, and nothing else may
+    /// hold a reference to it while the bridge is used.
+    pub unsafe fn new(document: &mut BaseDocument) -> Self {
         let pointer = NonNull::new(document as *mut BaseDocument).expect("document pointer");
         let mut id_index = HashMap::new();
         Self::reindex_internal(document, &mut id_index);
         let root_id = document.root_node().id;
         Self::seed_stylo_data_for_subtree(document, root_id);
-        Self {
+        let mut bridge = Self {
             document: pointer,
             id_index,
             comment_payloads: HashMap::new(),
-        }
+            template_contents: HashMap::new(),
+            template_fragments: HashSet::new(),
+            shadow_hosts: HashMap::new(),
+            shadow_root_hosts: HashMap::new(),
+            slot_fallbacks: HashMap::new(),
+            rendered_styles: HashMap::new(),
+            adopted_styles: HashMap::new(),
+            element_styles: HashMap::new(),
+            detached_styles: HashSet::new(),
+        };
+        bridge.adopt_template_contents(&[root_id]);
+        bridge
+    }
+
+    /// Whether this bridge operates on `document`.
+    pub fn is_attached_to(&self, document: &BaseDocument) -> bool {
+        std::ptr::eq(self.document.as_ptr(), document)
     }
 
     fn with_document_mut<T>(
         &mut self,
         f: impl FnOnce(&mut BaseDocument, &mut HashMap<String, usize>, &mut HashMap<usize, String>) -> T,
     ) -> T {
+        // SAFETY: the document is boxed before the bridge is attached and detaches the bridge
+        // when it drops, so the pointer is live. The runtime is single-threaded and only calls
+        // in here while the view is not touching the document, or through the mutator
+        // `RuntimeDocument::handle_ui_event` re-pointed the bridge at.
         unsafe {
             let document = self.document.as_mut();
             f(document, &mut self.id_index, &mut self.comment_payloads)
@@ -44,6 +149,7 @@ impl BlitzJsBridge {
         &self,
         f: impl FnOnce(&BaseDocument, &HashMap<String, usize>) -> T,
     ) -> T {
+        // SAFETY: as in `with_document_mut`.
         unsafe { f(self.document.as_ref(), &self.id_index) }
     }
 
@@ -84,6 +190,748 @@ impl BlitzJsBridge {
         }
     }
 
+    /// Move the children of every `<template>` under `roots` that has no content yet into a
+    /// fresh holder, the way the HTML parser builds a template's content fragment.
+    fn adopt_template_contents(&mut self, roots: &[usize]) {
+        let mut templates =
+            self.with_document_mut(|document, _, _| Self::collect_templates(document, roots));
+        templates.retain(|id| {
+            !self.template_contents.contains_key(id) && !self.template_fragments.contains(id)
+        });
+        if templates.is_empty() {
+            return;
+        }
+
+        let adopted = self.with_document_mut(|document, id_index, _| {
+            let mut adopted = Vec::new();
+            for template_id in templates {
+                let children = document
+                    .get_node(template_id)
+                    .map(|node| node.children.clone())
+                    .unwrap_or_default();
+                let holder = {
+                    let mut mutator = DocumentMutator::new(document);
+                    let holder = mutator.create_element(Self::html_name("template"), Vec::new());
+                    for &child in &children {
+                        mutator.remove_node(child);
+                    }
+                    mutator.append_children(holder, &children);
+                    holder
+                };
+                adopted.push((template_id, holder));
+            }
+            Self::reindex_internal(document, id_index);
+            adopted
+        });
+        let mut holders = Vec::new();
+        for (template_id, holder) in adopted {
+            self.template_contents.insert(template_id, holder);
+            self.template_fragments.insert(holder);
+            holders.push(holder);
+        }
+        self.sync_styles(&holders, false);
+    }
+
+    /// Forget the contents of templates below `node_id` before its children are dropped, so
+    /// a node that later reuses one of their ids is not mistaken for a template.
+    fn forget_templates_below(&mut self, node_id: usize) {
+        let templates =
+            self.with_document_mut(|document, _, _| Self::collect_templates(document, &[node_id]));
+        for template_id in templates {
+            if template_id != node_id {
+                self.template_contents.remove(&template_id);
+            }
+        }
+    }
+
+    fn collect_templates(document: &mut BaseDocument, roots: &[usize]) -> Vec<usize> {
+        let mut templates = Vec::new();
+        for &root in roots {
+            if document.get_node(root).is_none() {
+                continue;
+            }
+            document.iter_subtree_mut(root, |node_id, doc| {
+                let is_template = doc.get_node(node_id).is_some_and(|node| {
+                    node.element_data().is_some_and(|data| {
+                        data.name.ns == ns!(html) && data.name.local == local_name!("template")
+                    })
+                });
+                if is_template {
+                    templates.push(node_id);
+                }
+            });
+        }
+        templates
+    }
+
+    /// The host whose light children include `node_id`.
+    fn light_host(&self, node_id: usize) -> Option<usize> {
+        self.shadow_hosts
+            .iter()
+            .find_map(|(host, shadow)| shadow.light.contains(&node_id).then_some(*host))
+    }
+
+    /// The slot whose fallback children include `node_id`.
+    fn fallback_slot(&self, node_id: usize) -> Option<usize> {
+        self.slot_fallbacks
+            .iter()
+            .find_map(|(slot, fallback)| fallback.contains(&node_id).then_some(*slot))
+    }
+
+    /// Whether the logical children of `node_id` differ from its children in the Blitz tree.
+    fn has_virtual_children(&self, node_id: usize) -> bool {
+        self.shadow_hosts.contains_key(&node_id)
+            || self.shadow_root_hosts.contains_key(&node_id)
+            || self.slot_fallbacks.contains_key(&node_id)
+            || self.adopted_styles.contains_key(&node_id)
+            || self.element_styles.contains_key(&node_id)
+    }
+
+    /// Whether the Blitz tree holds anything scripts do not see: shadow trees, or the
+    /// `<style>` of some `adoptedStyleSheets`, linked stylesheet or canvas bitmap.
+    fn is_composed(&self) -> bool {
+        !self.shadow_hosts.is_empty()
+            || !self.adopted_styles.is_empty()
+            || !self.element_styles.is_empty()
+    }
+
+    /// Whether scripts never see the attribute `name`, one the bridge marks elements with for
+    /// the styles it renders.
+    fn is_hidden_attribute(name: &str) -> bool {
+        name == HOST_ATTRIBUTE || name == CANVAS_ATTRIBUTE
+    }
+
+    /// Whether `node_id` is a `<style>` holding some `adoptedStyleSheets`, the stylesheet of a
+    /// link or the bitmap of a canvas.
+    fn is_hidden_style(&self, node_id: usize) -> bool {
+        self.adopted_styles
+            .values()
+            .chain(self.element_styles.values())
+            .any(|style| *style == node_id)
+    }
+
+    /// `node_id`'s parent as scripts see it: a light child's is its host, a slot's fallback
+    /// child's is the slot, and the top of a shadow tree's is the shadow root.
+    fn logical_parent(&self, document: &BaseDocument, node_id: usize) -> Option<usize> {
+        if !self.is_composed() {
+            return document.get_node(node_id)?.parent;
+        }
+        if let Some(host) = self.light_host(node_id) {
+            return Some(host);
+        }
+        if let Some(slot) = self.fallback_slot(node_id) {
+            return Some(slot);
+        }
+        let parent = document.get_node(node_id)?.parent?;
+        match self.shadow_hosts.get(&parent) {
+            Some(shadow) => Some(shadow.root),
+            None => Some(parent),
+        }
+    }
+
+    /// `node_id`'s children as scripts see them.
+    fn logical_children(&self, document: &BaseDocument, node_id: usize) -> Vec<usize> {
+        if !self.is_composed() {
+            return document
+                .get_node(node_id)
+                .map(|node| node.children.clone())
+                .unwrap_or_default();
+        }
+        if let Some(shadow) = self.shadow_hosts.get(&node_id) {
+            return shadow.light.clone();
+        }
+        if let Some(fallback) = self.slot_fallbacks.get(&node_id) {
+            return fallback.clone();
+        }
+        let node_id = self
+            .shadow_root_hosts
+            .get(&node_id)
+            .copied()
+            .unwrap_or(node_id);
+        let mut children = document
+            .get_node(node_id)
+            .map(|node| node.children.clone())
+            .unwrap_or_default();
+        if !self.adopted_styles.is_empty() || !self.element_styles.is_empty() {
+            children.retain(|child| !self.is_hidden_style(*child));
+        }
+        children
+    }
+
+    /// The parent to walk to for shadow-including ancestry: the logical parent, or the host
+    /// of a shadow root or template content.
+    fn host_including_parent(&self, document: &BaseDocument, node_id: usize) -> Option<usize> {
+        self.logical_parent(document, node_id)
+            .or_else(|| self.shadow_root_hosts.get(&node_id).copied())
+            .or_else(|| {
+                self.template_contents
+                    .iter()
+                    .find_map(|(template, content)| (*content == node_id).then_some(*template))
+            })
+    }
+
+    /// The HTML elements of `host_id`'s shadow tree in tree order, without those of shadow
+    /// trees nested in it.
+    fn shadow_tree_elements(
+        &self,
+        document: &BaseDocument,
+        host_id: usize,
+    ) -> Vec<(usize, LocalName)> {
+        let mut found = Vec::new();
+        let mut stack = document
+            .get_node(host_id)
+            .map(|node| node.children.clone())
+            .unwrap_or_default();
+        stack.reverse();
+        while let Some(node_id) = stack.pop() {
+            let Some(node) = document.get_node(node_id) else {
+                continue;
+            };
+            if let Some(data) = node.element_data().filter(|data| data.name.ns == ns!(html)) {
+                found.push((node_id, data.name.local.clone()));
+            }
+            stack.extend(self.logical_children(document, node_id).into_iter().rev());
+            stack.extend(self.element_styles.get(&node_id));
+        }
+        found
+    }
+
+    /// Take `node_id` out of its logical parent, and out of the Blitz tree.
+    fn detach_logical(&mut self, node_id: usize) {
+        for shadow in self.shadow_hosts.values_mut() {
+            shadow.light.retain(|id| *id != node_id);
+        }
+        for fallback in self.slot_fallbacks.values_mut() {
+            fallback.retain(|id| *id != node_id);
+        }
+        self.with_document_mut(|document, _, _| {
+            if document
+                .get_node(node_id)
+                .is_some_and(|node| node.parent.is_some())
+            {
+                DocumentMutator::new(document).remove_node(node_id);
+            }
+        });
+    }
+
+    /// Insert `child_id` among the logical children of `parent_id`, before `reference_id` or
+    /// last. The caller checks the insertion is valid and redistributes afterwards.
+    fn insert_logical(&mut self, parent_id: usize, child_id: usize, reference_id: Option<usize>) {
+        self.detach_logical(child_id);
+        let list = match self.shadow_hosts.get_mut(&parent_id) {
+            Some(shadow) => Some(&mut shadow.light),
+            None => self.slot_fallbacks.get_mut(&parent_id),
+        };
+        if let Some(list) = list {
+            let at = reference_id
+                .and_then(|reference| list.iter().position(|id| *id == reference))
+                .unwrap_or(list.len());
+            list.insert(at, child_id);
+            return;
+        }
+        let parent_id = self
+            .shadow_root_hosts
+            .get(&parent_id)
+            .copied()
+            .unwrap_or(parent_id);
+        // Appended nodes go before the `<style>` of the adopted sheets, which stays last.
+        let reference_id = reference_id.or_else(|| {
+            let last = self.with_document_ref(|document, _| {
+                document.get_node(parent_id)?.children.last().copied()
+            })?;
+            self.is_hidden_style(last).then_some(last)
+        });
+        self.with_document_mut(|document, _, _| {
+            let mut mutator = DocumentMutator::new(document);
+            match reference_id {
+                Some(reference) => mutator.insert_nodes_before(reference, &[child_id]),
+                None => mutator.append_children(parent_id, &[child_id]),
+            }
+        });
+    }
+
+    /// The pre-insertion checks against the logical tree, used while shadow trees exist.
+    fn ensure_logically_insertable(
+        &self,
+        parent_id: usize,
+        child_id: usize,
+        reference_id: Option<usize>,
+    ) -> Result<()> {
+        if self.shadow_root_hosts.contains_key(&child_id) {
+            return Err(anyhow!("a shadow root cannot be inserted"));
+        }
+        self.ensure_outside_template_content(parent_id, child_id)?;
+        self.with_document_ref(|document, _| {
+            let parent = document
+                .get_node(parent_id)
+                .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
+            if !matches!(parent.data, NodeData::Element(_) | NodeData::Document) {
+                return Err(anyhow!("node {parent_id} cannot have children"));
+            }
+            let child = document
+                .get_node(child_id)
+                .ok_or_else(|| anyhow!("missing child node {child_id}"))?;
+            if matches!(child.data, NodeData::Document) {
+                return Err(anyhow!("the document node cannot be inserted"));
+            }
+            if let Some(reference) = reference_id {
+                if self.logical_parent(document, reference) != Some(parent_id) {
+                    return Err(anyhow!(
+                        "reference node {reference} is not a child of parent {parent_id}"
+                    ));
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Replace the logical children of `node_id` with `nodes`. The old ones are detached.
+    fn replace_logical_children(&mut self, node_id: usize, nodes: &[usize]) {
+        let old = self.with_document_ref(|document, _| self.logical_children(document, node_id));
+        for &child in &old {
+            self.detach_logical(child);
+        }
+        for &child in nodes {
+            self.insert_logical(node_id, child, None);
+        }
+        self.sync_styles(&old, false);
+        self.sync_styles(nodes, false);
+        self.refresh_shadow_trees(nodes);
+    }
+
+    /// Put every slot below `node_id` back to its fallback children and forget the shadow
+    /// state of the nodes below it, before they are dropped. Light children of hosts above
+    /// it are left detached instead of being dropped with the slots that showed them.
+    fn forget_shadows_below(&mut self, node_id: usize) {
+        if self.shadow_hosts.is_empty() {
+            return;
+        }
+        let mut below = Vec::new();
+        self.with_document_mut(|document, _, _| {
+            document.iter_subtree_mut(node_id, |id, _| below.push(id));
+        });
+        below.retain(|id| *id != node_id);
+        for &id in &below {
+            if let Some(fallback) = self.slot_fallbacks.remove(&id) {
+                self.with_document_mut(|document, _, _| {
+                    let shown = document
+                        .get_node(id)
+                        .map(|node| node.children.clone())
+                        .unwrap_or_default();
+                    let mut mutator = DocumentMutator::new(document);
+                    for child in shown {
+                        mutator.remove_node(child);
+                    }
+                    mutator.append_children(id, &fallback);
+                });
+            }
+            if let Some(shadow) = self.shadow_hosts.remove(&id) {
+                self.shadow_root_hosts.remove(&shadow.root);
+                self.adopted_styles.remove(&shadow.root);
+            }
+            self.rendered_styles.remove(&id);
+        }
+    }
+
+    /// Bring the Blitz tree back in line with the logical one after a change: redistribute
+    /// light children, rescope shadow styles, seed the styles of `inserted` and reindex.
+    fn refresh_shadow_trees(&mut self, inserted: &[usize]) {
+        self.distribute_shadow_trees();
+        self.scope_shadow_styles();
+        self.with_document_mut(|document, index, _| {
+            for &node_id in inserted {
+                if document.get_node(node_id).is_some() {
+                    Self::seed_stylo_data_for_subtree(document, node_id);
+                }
+            }
+            Self::reindex_internal(document, index);
+        });
+    }
+
+    /// Move each light child into the first slot of its host's shadow tree whose name matches
+    /// its `slot` attribute. Slots nothing is assigned to show their fallback children, and
+    /// light children with no slot are not rendered.
+    fn distribute_shadow_trees(&mut self) {
+        let mut live_slots = HashSet::new();
+        let mut plan = Vec::new();
+        let mut unassigned = Vec::new();
+        // Fallback children of slots seen for the first time, kept once the tree is read.
+        let mut fallbacks = HashMap::new();
+        self.with_document_ref(|document, _| {
+            let hosts: Vec<usize> = self.shadow_hosts.keys().copied().collect();
+            for host in hosts {
+                let slots: Vec<usize> = self
+                    .shadow_tree_elements(document, host)
+                    .into_iter()
+                    .filter(|(_, name)| *name == local_name!("slot"))
+                    .map(|(id, _)| id)
+                    .collect();
+                let mut names = Vec::with_capacity(slots.len());
+                for &slot in &slots {
+                    live_slots.insert(slot);
+                    let node = document.get_node(slot);
+                    if !self.slot_fallbacks.contains_key(&slot) {
+                        fallbacks.entry(slot).or_insert_with(|| {
+                            node.map(|node| node.children.clone()).unwrap_or_default()
+                        });
+                    }
+                    let name = node.and_then(|node| node.attr(local_name!("name")));
+                    names.push(name.unwrap_or("").to_string());
+                }
+
+                let mut assigned = vec![Vec::new(); slots.len()];
+                for &light in &self.shadow_hosts[&host].light {
+                    let wanted = document.get_node(light).and_then(|node| match &node.data {
+                        NodeData::Element(_) => node.attr(local_name!("slot")).or(Some("")),
+                        NodeData::Text(_) => Some(""),
+                        _ => None,
+                    });
+                    match wanted.and_then(|wanted| names.iter().position(|name| name == wanted)) {
+                        Some(at) => assigned[at].push(light),
+                        None => unassigned.push(light),
+                    }
+                }
+                for (slot, nodes) in slots.into_iter().zip(assigned) {
+                    let nodes = if nodes.is_empty() {
+                        fallbacks
+                            .get(&slot)
+                            .unwrap_or_else(|| &self.slot_fallbacks[&slot])
+                            .clone()
+                    } else {
+                        nodes
+                    };
+                    plan.push((slot, nodes));
+                }
+            }
+        });
+        self.slot_fallbacks.extend(fallbacks);
+
+        // A slot that left its shadow tree gets its own children back.
+        let stale: Vec<usize> = self
+            .slot_fallbacks
+            .keys()
+            .filter(|slot| !live_slots.contains(*slot))
+            .copied()
+            .collect();
+        for slot in stale {
+            let fallback = self.slot_fallbacks.remove(&slot).unwrap_or_default();
+            plan.push((slot, fallback));
+        }
+
+        self.with_document_mut(|document, _, _| {
+            plan.retain(|(slot, nodes)| {
+                document
+                    .get_node(*slot)
+                    .is_some_and(|node| node.children != *nodes)
+            });
+            let mut detach = Vec::new();
+            let mut seen = HashSet::new();
+            for (slot, _) in &plan {
+                if let Some(node) = document.get_node(*slot) {
+                    detach.extend(node.children.iter().filter(|id| seen.insert(**id)));
+                }
+            }
+            for light in unassigned {
+                let attached = document
+                    .get_node(light)
+                    .is_some_and(|node| node.parent.is_some());
+                if attached && seen.insert(light) {
+                    detach.push(light);
+                }
+            }
+
+            let mut mutator = DocumentMutator::new(document);
+            for node_id in detach {
+                mutator.remove_node(node_id);
+            }
+            for (slot, nodes) in &plan {
+                mutator.append_children(*slot, nodes);
+            }
+        });
+    }
+
+    /// Give every `<style>` in a shadow tree a copy of its rules scoped to its host, and give a
+    /// style that left its shadow tree its own rules back.
+    fn scope_shadow_styles(&mut self) {
+        let mut shadow_styles = Vec::new();
+        self.with_document_ref(|document, _| {
+            for &host in self.shadow_hosts.keys() {
+                for (style, name) in self.shadow_tree_elements(document, host) {
+                    if name == local_name!("style") && !self.detached_styles.contains(&style) {
+                        shadow_styles.push((host, style, Self::style_text(document, style)));
+                    }
+                }
+            }
+        });
+
+        let mut live = HashSet::new();
+        let mut rewrites = Vec::new();
+        for (host, style, current) in shadow_styles {
+            live.insert(style);
+            // Text that is not the last rendered copy was set since, by a script.
+            let (text, rules) = match self.rendered_styles.remove(&style) {
+                Some(entry) if entry.rendered == current => (entry.text, entry.rules),
+                _ => (current.clone(), None),
+            };
+            let selector = shadow::host_selector(host);
+            let scoped = shadow::scope_css(rules.as_deref().unwrap_or(&text), &selector);
+            if scoped != current {
+                rewrites.push((style, scoped.clone()));
+            }
+            self.rendered_styles.insert(
+                style,
+                RenderedStyle {
+                    text,
+                    rules,
+                    rendered: scoped,
+                },
+            );
+        }
+
+        // Styles out of the document stay empty until they are back.
+        let stale: Vec<usize> = self
+            .rendered_styles
+            .keys()
+            .filter(|style| !live.contains(*style) && !self.detached_styles.contains(*style))
+            .copied()
+            .collect();
+        for style in stale {
+            let Some(entry) = self.rendered_styles.remove(&style) else {
+                continue;
+            };
+            let current = self.with_document_ref(|document, _| Self::style_text(document, style));
+            if current != entry.rendered {
+                continue;
+            }
+            let unscoped = entry.rules.clone().unwrap_or_else(|| entry.text.clone());
+            if unscoped != entry.rendered {
+                rewrites.push((style, unscoped.clone()));
+            }
+            if entry.rules.is_some() {
+                self.rendered_styles.insert(
+                    style,
+                    RenderedStyle {
+                        rendered: unscoped,
+                        ..entry
+                    },
+                );
+            }
+        }
+
+        self.with_document_mut(|document, _, _| {
+            for (style, text) in rewrites {
+                Self::write_style_text(document, style, &text);
+            }
+        });
+    }
+
+    fn style_text(document: &BaseDocument, style: usize) -> String {
+        document
+            .get_node(style)
+            .map(|node| node.text_content())
+            .unwrap_or_default()
+    }
+
+    /// [`Self::write_style_text`] on the attached document.
+    fn write_style(&mut self, style: usize, text: &str) {
+        self.with_document_mut(|document, _, _| Self::write_style_text(document, style, text));
+    }
+
+    /// Replace the text of `style`, which makes Blitz parse its stylesheet again.
+    fn write_style_text(document: &mut BaseDocument, style: usize, text: &str) {
+        let mut mutator = DocumentMutator::new(document);
+        mutator.remove_and_drop_all_children(style);
+        if !text.is_empty() {
+            let text_id = mutator.create_text_node(text);
+            mutator.append_children(style, &[text_id]);
+        }
+    }
+
+    fn is_html_element(document: &BaseDocument, node_id: usize, name: &str) -> bool {
+        document
+            .get_node(node_id)
+            .and_then(|node| node.element_data())
+            .is_some_and(|data| data.name == Self::html_name(name))
+    }
+
+    /// Whether `node_id` is in the document, shadow trees included.
+    fn is_connected(&self, document: &BaseDocument, node_id: usize) -> bool {
+        let root_id = document.root_node().id;
+        let mut current = node_id;
+        while current != root_id {
+            match self
+                .shadow_root_hosts
+                .get(&current)
+                .copied()
+                .or_else(|| self.logical_parent(document, current))
+            {
+                Some(parent) => current = parent,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Bring the `<style>` elements at or below `roots`, or holding a root as their text, in
+    /// line with whether they are in the document. Blitz keeps the rules of a style wherever
+    /// it is, so one out of the document is rendered empty, and gets its text back once it
+    /// returns. With `reparse`, the styles in the document are rendered again too, for nodes
+    /// just parsed from markup, whose stylesheets Blitz parsed before they were attached.
+    fn sync_styles(&mut self, roots: &[usize], reparse: bool) {
+        let mut styles = Vec::new();
+        self.with_document_mut(|document, _, _| {
+            for &root in roots {
+                let Some(node) = document.get_node(root) else {
+                    continue;
+                };
+                let start = node
+                    .parent
+                    .filter(|parent| Self::is_html_element(document, *parent, "style"))
+                    .unwrap_or(root);
+                document.iter_subtree_mut(start, |node_id, doc| {
+                    if Self::is_html_element(doc, node_id, "style") {
+                        styles.push(node_id);
+                    }
+                });
+            }
+        });
+
+        for style in styles {
+            let (current, connected) = self.with_document_ref(|document, _| {
+                let current = Self::style_text(document, style);
+                (current, self.is_connected(document, style))
+            });
+            if !connected {
+                if current.is_empty() {
+                    continue;
+                }
+                // Text added to a style already out of the document goes after what it had.
+                let (text, rules) = match self.rendered_styles.remove(&style) {
+                    Some(entry) if entry.rendered == current => (entry.text, entry.rules),
+                    Some(entry) if self.detached_styles.contains(&style) => {
+                        (entry.text + &current, None)
+                    }
+                    _ => (current, None),
+                };
+                self.rendered_styles.insert(
+                    style,
+                    RenderedStyle {
+                        text,
+                        rules,
+                        rendered: String::new(),
+                    },
+                );
+                self.detached_styles.insert(style);
+                self.write_style(style, "");
+            } else if self.detached_styles.remove(&style) {
+                let Some(entry) = self.rendered_styles.remove(&style) else {
+                    continue;
+                };
+                if entry.rendered != current {
+                    continue;
+                }
+                let shown = entry.rules.clone().unwrap_or_else(|| entry.text.clone());
+                self.write_style(style, &shown);
+                if entry.rules.is_some() {
+                    self.rendered_styles.insert(
+                        style,
+                        RenderedStyle {
+                            rendered: shown,
+                            ..entry
+                        },
+                    );
+                }
+            } else if reparse && !current.is_empty() {
+                self.write_style(style, &current);
+            }
+        }
+    }
+
+    /// Empty the styles below `node_id` and forget their state, before they are dropped.
+    fn forget_styles_below(&mut self, node_id: usize) {
+        let mut styles = Vec::new();
+        self.with_document_mut(|document, _, _| {
+            document.iter_subtree_mut(node_id, |id, doc| {
+                if id != node_id && Self::is_html_element(doc, id, "style") {
+                    styles.push(id);
+                }
+            });
+        });
+        for style in styles {
+            self.rendered_styles.remove(&style);
+            self.detached_styles.remove(&style);
+            self.element_styles.retain(|_, kept| *kept != style);
+            self.adopted_styles.retain(|_, adopted| *adopted != style);
+            self.write_style(style, "");
+        }
+    }
+
+    /// Forget the text kept for `node_id` while it is out of the document, when its children
+    /// are about to be replaced.
+    fn forget_detached_text(&mut self, node_id: usize) {
+        if self.detached_styles.remove(&node_id) {
+            self.rendered_styles.remove(&node_id);
+        }
+    }
+
+    /// Create, rewrite or, for empty `css`, drop the `<style>` scripts never see that renders
+    /// `css` as the last child of `parent_id`. Returns the style unless it was dropped.
+    fn render_hidden_style(
+        &mut self,
+        parent_id: usize,
+        existing: Option<usize>,
+        css: &str,
+    ) -> Option<usize> {
+        match existing {
+            Some(style) if css.is_empty() => {
+                self.rendered_styles.remove(&style);
+                self.detached_styles.remove(&style);
+                self.with_document_mut(|document, _, _| {
+                    DocumentMutator::new(document).remove_and_drop_node(style);
+                });
+                None
+            }
+            Some(style) => {
+                self.write_style(style, css);
+                Some(style)
+            }
+            None if css.is_empty() => None,
+            None => self.with_document_mut(|document, _, _| {
+                let mut mutator = DocumentMutator::new(document);
+                let style = mutator.create_element(Self::html_name("style"), Vec::new());
+                let text_id = mutator.create_text_node(css);
+                mutator.append_children(style, &[text_id]);
+                mutator.append_children(parent_id, &[style]);
+                Some(style)
+            }),
+        }
+    }
+
+    /// The text scripts see for a `<style>` whose rules Stylo sees in another form, unless a
+    /// script has set its text since.
+    fn style_source<'a>(&'a self, document: &BaseDocument, node_id: usize) -> Option<&'a str> {
+        let entry = self.rendered_styles.get(&node_id)?;
+        (Self::style_text(document, node_id) == entry.rendered).then_some(entry.text.as_str())
+    }
+
+    /// The concatenated text below `node_id` in the logical tree, with the text scripts gave
+    /// the styles Stylo sees in another form.
+    fn logical_text(&self, document: &BaseDocument, node_id: usize, output: &mut String) {
+        if let Some(text) = self.style_source(document, node_id) {
+            output.push_str(text);
+            return;
+        }
+        for child in self.logical_children(document, node_id) {
+            match document.get_node(child).map(|node| &node.data) {
+                Some(NodeData::Text(text)) => output.push_str(&text.content),
+                Some(NodeData::Element(_) | NodeData::AnonymousBlock(_)) => {
+                    self.logical_text(document, child, output);
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn collect_comment_nodes(document: &mut BaseDocument, root_id: usize) -> Vec<usize> {
         let mut collected = Vec::new();
         document.iter_subtree_mut(root_id, |node_id, doc| {
@@ -261,6 +1109,32 @@ impl BlitzJsBridge {
         tracing::trace!(root = root_id, nodes = seeded, "seeded stylo subtree");
     }
 
+    /// The DOM's pre-insertion checks: nodes go only into elements and the document, never
+    /// into themselves or their own descendants, and the document itself never moves. Without
+    /// them a script could make the tree cyclic and hang every later traversal.
+    fn ensure_insertable(document: &BaseDocument, parent_id: usize, child_id: usize) -> Result<()> {
+        let parent = document
+            .get_node(parent_id)
+            .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
+        if !matches!(parent.data, NodeData::Element(_) | NodeData::Document) {
+            return Err(anyhow!("node {parent_id} cannot have children"));
+        }
+        let child = document
+            .get_node(child_id)
+            .ok_or_else(|| anyhow!("missing child node {child_id}"))?;
+        if matches!(child.data, NodeData::Document) {
+            return Err(anyhow!("the document node cannot be inserted"));
+        }
+        let mut ancestor = Some(parent_id);
+        while let Some(id) = ancestor {
+            if id == child_id {
+                return Err(anyhow!("node {child_id} contains parent {parent_id}"));
+            }
+            ancestor = document.get_node(id).and_then(|node| node.parent);
+        }
+        Ok(())
+    }
+
     fn html_name(name: &str) -> QualName {
         Self::qualify_name(name, None)
     }
@@ -281,16 +1155,120 @@ impl BlitzJsBridge {
         }
     }
 
+    /// The name `setAttributeNS(namespace, qualified)` stores. `qualified` may carry a prefix
+    /// (`xlink:href`); without a namespace it is an ordinary attribute name.
+    fn attribute_name_ns(namespace: Option<&str>, qualified: &str) -> Result<QualName> {
+        let (prefix, local) = match qualified.split_once(':') {
+            Some((prefix, local)) => (Some(prefix), local),
+            None => (None, qualified),
+        };
+        if local.is_empty() || prefix == Some("") || local.contains(':') {
+            return Err(anyhow!(
+                "InvalidCharacterError: '{qualified}' is not a valid qualified name"
+            ));
+        }
+        let xmlns = prefix == Some("xmlns") || (prefix.is_none() && local == "xmlns");
+        if (prefix.is_some() && namespace.is_none())
+            || (prefix == Some("xml") && namespace != Some(XML_NAMESPACE))
+            || xmlns != (namespace == Some(XMLNS_NAMESPACE))
+        {
+            return Err(anyhow!(
+                "NamespaceError: '{qualified}' cannot be in namespace {}",
+                namespace.unwrap_or("null")
+            ));
+        }
+        let Some(uri) = namespace else {
+            return Ok(Self::html_name(qualified));
+        };
+        let ns = match uri {
+            XLINK_NAMESPACE => ns!(xlink),
+            XML_NAMESPACE => ns!(xml),
+            XMLNS_NAMESPACE => ns!(xmlns),
+            other => other.into(),
+        };
+        Ok(QualName::new(
+            prefix.map(Into::into),
+            ns,
+            LocalName::from(local),
+        ))
+    }
+
+    /// Whether an attribute called `name` is in `namespace`. Attributes without one are stored
+    /// with no namespace when parsed and with the HTML one when set by scripts.
+    fn attribute_in_namespace(name: &QualName, namespace: Option<&str>) -> bool {
+        match namespace {
+            Some(uri) => &*name.ns == uri,
+            None => name.ns == ns!() || name.ns == ns!(html),
+        }
+    }
+
+    /// The existing attribute of `node_id` with `local` name in `namespace`, with its prefix.
+    fn find_attribute_ns(
+        document: &BaseDocument,
+        node_id: usize,
+        namespace: Option<&str>,
+        local: &str,
+    ) -> Result<Option<QualName>> {
+        let node = document
+            .get_node(node_id)
+            .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+        Ok(node.element_data().and_then(|element| {
+            element
+                .attrs
+                .iter()
+                .find(|attr| {
+                    attr.name.local.as_ref() == local
+                        && Self::attribute_in_namespace(&attr.name, namespace)
+                })
+                .map(|attr| attr.name.clone())
+        }))
+    }
+
     pub fn find_node_by_html_id(&mut self, id: &str) -> Option<usize> {
+        if !self.shadow_hosts.is_empty() {
+            // The index also holds ids in shadow trees, which the document does not see.
+            let root_id = self.document_handle();
+            return self.find_node_by_html_id_in(root_id, id);
+        }
         self.with_document_mut(|document, index, _| {
             Self::lookup_node_id_internal(document, index, id)
         })
     }
 
+    /// The first element with `id` below `root_id` in the logical tree, for lookups on a
+    /// shadow root and on documents with shadow trees.
+    pub fn find_node_by_html_id_in(&self, root_id: usize, id: &str) -> Option<usize> {
+        self.with_document_ref(|document, _| {
+            let mut stack = self.logical_children(document, root_id);
+            stack.reverse();
+            while let Some(node_id) = stack.pop() {
+                let Some(node) = document.get_node(node_id) else {
+                    continue;
+                };
+                if node.attr(local_name!("id")) == Some(id) {
+                    return Some(node_id);
+                }
+                stack.extend(self.logical_children(document, node_id).into_iter().rev());
+            }
+            None
+        })
+    }
+
     pub fn text_content(&self, node_id: usize) -> Option<String> {
         if let Some(payload) = self.comment_payloads.get(&node_id) {
             return Some(payload.clone());
         }
+        if self.is_composed() || !self.rendered_styles.is_empty() {
+            return self.with_document_ref(|document, _| {
+                let node = document.get_node(node_id)?;
+                if let NodeData::Text(text) = &node.data {
+                    return Some(text.content.clone());
+                }
+                let mut output = String::new();
+                self.logical_text(document, node_id, &mut output);
+                Some(output)
+            });
+        }
 
         self.with_document_ref(|document, _| {
             document.get_node(node_id).map(|node| node.text_content())
@@ -305,7 +1283,35 @@ impl BlitzJsBridge {
         })
     }
 
+    pub fn outer_html(&self, node_id: usize) -> Result<String> {
+        self.with_document_ref(|document, _| {
+            let mut output = String::new();
+            self.serialize_node(document, node_id, &mut output)?;
+            Ok(output)
+        })
+    }
+
     pub fn set_text_content(&mut self, node_id: usize, value: &str) -> Result<()> {
+        self.forget_detached_text(node_id);
+        if self.has_virtual_children(node_id) {
+            let nodes = match value {
+                "" => Vec::new(),
+                value => vec![self.create_text_node(value)?],
+            };
+            self.replace_logical_children(node_id, &nodes);
+            return Ok(());
+        }
+        let drops_children = self.with_document_ref(|document, _| {
+            document.get_node(node_id).is_some_and(|node| {
+                !matches!(node.data, NodeData::Comment | NodeData::Text(_))
+                    && node.text_content() != value
+            })
+        });
+        if drops_children {
+            self.forget_templates_below(node_id);
+            self.forget_shadows_below(node_id);
+            self.forget_styles_below(node_id);
+        }
         self.with_document_mut(|document, index, comments| {
             let Some(node) = document.get_node(node_id) else {
                 return Err(anyhow!("missing node {node_id}"));
@@ -319,6 +1325,11 @@ impl BlitzJsBridge {
                 return Ok(());
             }
 
+            if matches!(node.data, NodeData::Text(_)) {
+                DocumentMutator::new(document).set_node_text(node_id, value);
+                return Ok(());
+            }
+
             let current_text = node.text_content();
             let _ = node;
             if current_text == value {
@@ -344,14 +1355,44 @@ impl BlitzJsBridge {
 
             Self::refresh_node_index_internal(document, index, node_id);
             Ok(())
-        })
+        })?;
+        self.sync_styles(&[node_id], false);
+        if self.is_composed() || !self.rendered_styles.is_empty() {
+            self.refresh_shadow_trees(&[]);
+        }
+        Ok(())
     }
 
+    /// A `<template>`'s markup goes into its content rather than under the element itself.
     pub fn set_inner_html(&mut self, node_id: usize, value: &str) -> Result<()> {
-        self.with_document_mut(|document, index, comments| {
+        let node_id = self
+            .template_contents
+            .get(&node_id)
+            .copied()
+            .unwrap_or(node_id);
+        self.forget_detached_text(node_id);
+        if self.has_virtual_children(node_id) {
+            let nodes = self.parse_fragment(node_id, value)?;
+            self.replace_logical_children(node_id, &nodes);
+            return Ok(());
+        }
+        let is_element = self.with_document_ref(|document, _| {
             document
+                .get_node(node_id)
+                .is_some_and(|node| matches!(node.data, NodeData::Element(_)))
+        });
+        if is_element {
+            self.forget_templates_below(node_id);
+            self.forget_shadows_below(node_id);
+            self.forget_styles_below(node_id);
+        }
+        self.with_document_mut(|document, index, comments| {
+            let node = document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            if !matches!(node.data, NodeData::Element(_)) {
+                return Err(anyhow!("node {node_id} is not an element"));
+            }
             let removed_comments = Self::collect_comment_nodes(document, node_id);
             {
                 let mut mutator = DocumentMutator::new(document);
@@ -377,7 +1418,237 @@ impl BlitzJsBridge {
             Self::seed_stylo_data_for_subtree(document, node_id);
             Self::reindex_internal(document, index);
             Ok(())
-        })
+        })?;
+        self.adopt_template_contents(&[node_id]);
+        self.sync_styles(&[node_id], true);
+        if self.is_composed() || !self.rendered_styles.is_empty() {
+            self.refresh_shadow_trees(&[]);
+        }
+        Ok(())
+    }
+
+    /// Parse `html` as the children of an element like `context_id`, whose tag decides how as
+    /// for `innerHTML` (non-elements parse like `<body>`), and return the new top-level nodes.
+    /// They are not attached anywhere.
+    pub fn parse_fragment(&mut self, context_id: usize, html: &str) -> Result<Vec<usize>> {
+        let nodes = self.with_document_mut(|document, _, comments| {
+            Self::parse_fragment_internal(document, comments, context_id, html)
+        })?;
+        self.adopt_template_contents(&nodes);
+        self.sync_styles(&nodes, false);
+        Ok(nodes)
+    }
+
+    fn parse_fragment_internal(
+        document: &mut BaseDocument,
+        comments: &mut HashMap<usize, String>,
+        context_id: usize,
+        html: &str,
+    ) -> Result<Vec<usize>> {
+        let context = document
+            .get_node(context_id)
+            .ok_or_else(|| anyhow!("missing node {context_id}"))?;
+        let name = match &context.data {
+            NodeData::Element(data) => data.name.clone(),
+            _ => Self::html_name("body"),
+        };
+
+        let holder = {
+            let mut mutator = DocumentMutator::new(document);
+            let holder = mutator.create_element(name, Vec::new());
+            mutator.set_inner_html(holder, html);
+            holder
+        };
+        let new_comments = Self::collect_comment_nodes(document, holder);
+        let payloads = Self::extract_comment_payloads(html);
+        for (comment_id, payload) in new_comments.into_iter().zip(payloads) {
+            comments.insert(comment_id, payload);
+        }
+
+        let nodes = document
+            .get_node(holder)
+            .map(|node| node.children.clone())
+            .unwrap_or_default();
+        let mut mutator = DocumentMutator::new(document);
+        for &node_id in &nodes {
+            mutator.remove_node(node_id);
+        }
+        mutator.remove_and_drop_node(holder);
+        Ok(nodes)
+    }
+
+    /// `insertAdjacentHTML`: parse `html` and insert it `position` (`beforebegin`,
+    /// `afterbegin`, `beforeend` or `afterend`) relative to the element.
+    pub fn insert_adjacent_html(
+        &mut self,
+        node_id: usize,
+        position: &str,
+        html: &str,
+    ) -> Result<()> {
+        if self.is_composed() {
+            return self.insert_html_logically(node_id, position, html, false);
+        }
+        let nodes = self.with_document_mut(|document, index, comments| {
+            let node = document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            if !matches!(node.data, NodeData::Element(_)) {
+                return Err(anyhow!("node {node_id} is not an element"));
+            }
+            let parent_id = node.parent;
+            let first_child = node.children.first().copied();
+            let position = position.to_ascii_lowercase();
+            let outside = matches!(position.as_str(), "beforebegin" | "afterend");
+            let context_id = if outside {
+                Self::element_parent(document, parent_id)?
+            } else if matches!(position.as_str(), "afterbegin" | "beforeend") {
+                node_id
+            } else {
+                return Err(anyhow!(
+                    "SyntaxError: '{position}' is not a valid insertAdjacentHTML position"
+                ));
+            };
+            let next_sibling = document.get_node(context_id).and_then(|parent| {
+                let at = parent.children.iter().position(|id| *id == node_id)?;
+                parent.children.get(at + 1).copied()
+            });
+
+            let nodes = Self::parse_fragment_internal(document, comments, context_id, html)?;
+            {
+                let mut mutator = DocumentMutator::new(document);
+                match (position.as_str(), first_child, next_sibling) {
+                    ("beforebegin", _, _) => mutator.insert_nodes_before(node_id, &nodes),
+                    ("afterbegin", Some(first), _) => mutator.insert_nodes_before(first, &nodes),
+                    ("afterend", _, Some(next)) => mutator.insert_nodes_before(next, &nodes),
+                    ("afterend", _, None) => mutator.append_children(context_id, &nodes),
+                    _ => mutator.append_children(node_id, &nodes),
+                }
+            }
+
+            for &inserted in &nodes {
+                Self::seed_stylo_data_for_subtree(document, inserted);
+            }
+            Self::reindex_internal(document, index);
+            Ok(nodes)
+        })?;
+        self.adopt_template_contents(&nodes);
+        self.sync_styles(&nodes, true);
+        Ok(())
+    }
+
+    /// `outerHTML = html`: replace the element with the nodes parsed from `html` in the
+    /// context of its parent.
+    pub fn set_outer_html(&mut self, node_id: usize, html: &str) -> Result<()> {
+        if self.is_composed() {
+            return self.insert_html_logically(node_id, "beforebegin", html, true);
+        }
+        let nodes = self.with_document_mut(|document, index, comments| {
+            let node = document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            if !matches!(node.data, NodeData::Element(_)) {
+                return Err(anyhow!("node {node_id} is not an element"));
+            }
+            let parent_id = Self::element_parent(document, node.parent)?;
+
+            let nodes = Self::parse_fragment_internal(document, comments, parent_id, html)?;
+            let removed_comments = Self::collect_comment_nodes(document, node_id);
+            {
+                let mut mutator = DocumentMutator::new(document);
+                if nodes.is_empty() {
+                    mutator.remove_node(node_id);
+                } else {
+                    mutator.replace_node_with(node_id, &nodes);
+                }
+            }
+            for comment_id in removed_comments {
+                comments.remove(&comment_id);
+            }
+
+            for &inserted in &nodes {
+                Self::seed_stylo_data_for_subtree(document, inserted);
+            }
+            Self::reindex_internal(document, index);
+            Ok(nodes)
+        })?;
+        self.adopt_template_contents(&nodes);
+        self.sync_styles(&nodes, true);
+        self.sync_styles(&[node_id], false);
+        Ok(())
+    }
+
+    /// `insertAdjacentHTML`, or `outerHTML` when `replace` is set, on the logical tree.
+    fn insert_html_logically(
+        &mut self,
+        node_id: usize,
+        position: &str,
+        html: &str,
+        replace: bool,
+    ) -> Result<()> {
+        let position = position.to_ascii_lowercase();
+        let (parent_id, next_sibling, first_child) = self.with_document_ref(|document, _| {
+            let node = document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            if !matches!(node.data, NodeData::Element(_)) {
+                return Err(anyhow!("node {node_id} is not an element"));
+            }
+            let parent_id = self.logical_parent(document, node_id);
+            let siblings = parent_id
+                .map(|parent_id| self.logical_children(document, parent_id))
+                .unwrap_or_default();
+            let next_sibling = siblings
+                .iter()
+                .position(|id| *id == node_id)
+                .and_then(|at| siblings.get(at + 1).copied());
+            let first_child = self.logical_children(document, node_id).first().copied();
+            Ok((parent_id, next_sibling, first_child))
+        })?;
+        let (target, reference) = match position.as_str() {
+            "beforebegin" | "afterend" => {
+                let parent_id = self
+                    .with_document_ref(|document, _| Self::element_parent(document, parent_id))?;
+                let reference = if position == "beforebegin" {
+                    Some(node_id)
+                } else {
+                    next_sibling
+                };
+                (parent_id, reference)
+            }
+            "afterbegin" => (node_id, first_child),
+            "beforeend" => (node_id, None),
+            _ => {
+                return Err(anyhow!(
+                    "SyntaxError: '{position}' is not a valid insertAdjacentHTML position"
+                ))
+            }
+        };
+
+        let nodes = self.parse_fragment(target, html)?;
+        for &inserted in &nodes {
+            self.insert_logical(target, inserted, reference);
+        }
+        if replace {
+            self.detach_logical(node_id);
+        }
+        self.sync_styles(&nodes, false);
+        if replace {
+            self.sync_styles(&[node_id], false);
+        }
+        self.refresh_shadow_trees(&nodes);
+        Ok(())
+    }
+
+    /// The parent that HTML inserted next to a node is parsed in. Nodes with no parent, or
+    /// directly under the document, have nowhere to put siblings.
+    fn element_parent(document: &BaseDocument, parent_id: Option<usize>) -> Result<usize> {
+        let parent = parent_id.and_then(|parent_id| document.get_node(parent_id));
+        match parent {
+            Some(parent) if matches!(parent.data, NodeData::Element(_)) => Ok(parent.id),
+            _ => Err(anyhow!(
+                "NoModificationAllowedError: the element has no parent element"
+            )),
+        }
     }
 
     pub fn set_attribute(&mut self, node_id: usize, name: &str, value: &str) -> Result<()> {
@@ -391,6 +1662,9 @@ impl BlitzJsBridge {
                 let mut mutator = DocumentMutator::new(document);
                 mutator.set_attribute(node_id, Self::html_name(&normalized), value);
             }
+            if normalized == "checked" {
+                crate::form_widgets::sync_checkedness(document, node_id);
+            }
 
             if normalized == "id" {
                 Self::reindex_internal(document, index);
@@ -399,7 +1673,13 @@ impl BlitzJsBridge {
             }
 
             Ok(())
-        })
+        })?;
+        if !self.shadow_hosts.is_empty()
+            && matches!(name.to_ascii_lowercase().as_str(), "slot" | "name")
+        {
+            self.refresh_shadow_trees(&[]);
+        }
+        Ok(())
     }
 
     pub fn remove_attribute(&mut self, node_id: usize, name: &str) -> Result<()> {
@@ -413,6 +1693,9 @@ impl BlitzJsBridge {
                 let mut mutator = DocumentMutator::new(document);
                 mutator.clear_attribute(node_id, Self::html_name(&normalized));
             }
+            if normalized == "checked" {
+                crate::form_widgets::sync_checkedness(document, node_id);
+            }
 
             if normalized == "id" {
                 Self::reindex_internal(document, index);
@@ -420,22 +1703,175 @@ impl BlitzJsBridge {
                 Self::refresh_node_index_internal(document, index, node_id);
             }
 
-            Ok(())
-        })
+            Ok(())
+        })?;
+        if !self.shadow_hosts.is_empty()
+            && matches!(name.to_ascii_lowercase().as_str(), "slot" | "name")
+        {
+            self.refresh_shadow_trees(&[]);
+        }
+        Ok(())
+    }
+
+    /// `setAttributeNS`. An attribute with the same namespace and local name is replaced, even
+    /// under another prefix. An empty namespace means none.
+    pub fn set_attribute_ns(
+        &mut self,
+        node_id: usize,
+        namespace: Option<&str>,
+        qualified: &str,
+        value: &str,
+    ) -> Result<()> {
+        let namespace = namespace.filter(|uri| !uri.is_empty());
+        let name = Self::attribute_name_ns(namespace, qualified)?;
+        if namespace.is_none() {
+            return self.set_attribute(node_id, qualified, value);
+        }
+        self.with_document_mut(|document, index, _| {
+            let existing =
+                Self::find_attribute_ns(document, node_id, namespace, name.local.as_ref())?;
+            {
+                let mut mutator = DocumentMutator::new(document);
+                if let Some(existing) = existing.filter(|existing| *existing != name) {
+                    mutator.clear_attribute(node_id, existing);
+                }
+                mutator.set_attribute(node_id, name, value);
+            }
+            Self::refresh_node_index_internal(document, index, node_id);
+            Ok(())
+        })
+    }
+
+    pub fn remove_attribute_ns(
+        &mut self,
+        node_id: usize,
+        namespace: Option<&str>,
+        local: &str,
+    ) -> Result<()> {
+        let namespace = namespace.filter(|uri| !uri.is_empty());
+        if namespace.is_none() {
+            return self.remove_attribute(node_id, local);
+        }
+        self.with_document_mut(|document, index, _| {
+            let Some(existing) = Self::find_attribute_ns(document, node_id, namespace, local)?
+            else {
+                return Ok(());
+            };
+            DocumentMutator::new(document).clear_attribute(node_id, existing);
+            Self::refresh_node_index_internal(document, index, node_id);
+            Ok(())
+        })
+    }
+
+    pub fn create_element(&mut self, tag: &str, namespace: Option<&str>) -> Result<usize> {
+        self.with_document_mut(|document, index, _| {
+            let qual = Self::qualify_name(tag, namespace);
+            let node_id = {
+                let mut mutator = DocumentMutator::new(document);
+                mutator.create_element(qual, Vec::new())
+            };
+
+            Self::seed_stylo_data_for_subtree(document, node_id);
+
+            Self::refresh_node_index_internal(document, index, node_id);
+            Ok(node_id)
+        })
+    }
+
+    /// Split the text node at `offset`, counted in UTF-16 code units as scripts count, and
+    /// return the new node holding the rest. It goes right after the original when that has a
+    /// parent.
+    pub fn split_text(&mut self, node_id: usize, offset: usize) -> Result<usize> {
+        let next_id = self.next_sibling(node_id)?;
+        let shadowed = self.is_composed();
+        let new_id = self.with_document_mut(|document, _, _| {
+            let node = document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            let NodeData::Text(text) = &node.data else {
+                return Err(anyhow!("node {node_id} is not a text node"));
+            };
+            let split = utf16_byte_offset(&text.content, offset).ok_or_else(|| {
+                anyhow!("offset {offset} is past the end of the text or inside a character")
+            })?;
+            let (head, tail) = text.content.split_at(split);
+            let (head, tail) = (head.to_string(), tail.to_string());
+            // With shadow trees the new node is inserted logically below.
+            let parent_id = node.parent.filter(|_| !shadowed);
+
+            let new_id = {
+                let mut mutator = DocumentMutator::new(document);
+                let new_id = mutator.create_text_node(&tail);
+                mutator.set_node_text(node_id, &head);
+                match (parent_id, next_id) {
+                    (Some(_), Some(next_id)) => mutator.insert_nodes_before(next_id, &[new_id]),
+                    (Some(parent_id), None) => mutator.append_children(parent_id, &[new_id]),
+                    (None, _) => {}
+                }
+                new_id
+            };
+
+            if parent_id.is_some() {
+                Self::seed_stylo_data_for_subtree(document, new_id);
+            }
+            Ok::<_, anyhow::Error>(new_id)
+        })?;
+        if shadowed {
+            if let Some(parent_id) = self.parent_node(node_id)? {
+                self.insert_logical(parent_id, new_id, next_id);
+                self.refresh_shadow_trees(&[new_id]);
+            }
+        }
+        Ok(new_id)
     }
 
-    pub fn create_element(&mut self, tag: &str, namespace: Option<&str>) -> Result<usize> {
-        self.with_document_mut(|document, index, _| {
-            let qual = Self::qualify_name(tag, namespace);
-            let node_id = {
-                let mut mutator = DocumentMutator::new(document);
-                mutator.create_element(qual, Vec::new())
-            };
+    /// `Node.normalize()`: in the subtree of `node_id`, merge each run of adjacent text nodes
+    /// into its first node and remove empty text nodes. Removed nodes are detached, not
+    /// dropped, since scripts may still hold them.
+    pub fn normalize(&mut self, node_id: usize) -> Result<()> {
+        self.with_document_mut(|document, _, _| {
+            document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
 
-            Self::seed_stylo_data_for_subtree(document, node_id);
+            let mut merged = Vec::new();
+            let mut removed = Vec::new();
+            let mut stack = vec![node_id];
+            while let Some(parent_id) = stack.pop() {
+                let Some(parent) = document.get_node(parent_id) else {
+                    continue;
+                };
+                let mut run: Option<(usize, String)> = None;
+                for &child_id in &parent.children {
+                    let Some(child) = document.get_node(child_id) else {
+                        continue;
+                    };
+                    match &child.data {
+                        NodeData::Text(text) if text.content.is_empty() => removed.push(child_id),
+                        NodeData::Text(text) => match run.as_mut() {
+                            Some((_, content)) => {
+                                content.push_str(&text.content);
+                                removed.push(child_id);
+                            }
+                            None => run = Some((child_id, text.content.clone())),
+                        },
+                        _ => {
+                            merged.extend(run.take());
+                            stack.push(child_id);
+                        }
+                    }
+                }
+                merged.extend(run);
+            }
 
-            Self::refresh_node_index_internal(document, index, node_id);
-            Ok(node_id)
+            let mut mutator = DocumentMutator::new(document);
+            for (text_id, content) in merged {
+                mutator.set_node_text(text_id, &content);
+            }
+            for text_id in removed {
+                mutator.remove_node(text_id);
+            }
+            Ok(())
         })
     }
 
@@ -455,14 +1891,50 @@ impl BlitzJsBridge {
         })
     }
 
-    pub fn append_child(&mut self, parent_id: usize, child_id: usize) -> Result<()> {
-        self.with_document_mut(|document, index, _| {
-            document
-                .get_node(parent_id)
-                .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
+    /// The template half of the pre-insertion checks: a content fragment is never inserted
+    /// itself, and a template never goes into its own content, which would make serializing
+    /// it recurse forever.
+    fn ensure_outside_template_content(&self, parent_id: usize, child_id: usize) -> Result<()> {
+        if self.template_fragments.contains(&child_id) {
+            return Err(anyhow!("a template's content cannot be inserted"));
+        }
+        self.with_document_ref(|document, _| {
+            let mut ancestor = Some(parent_id);
+            while let Some(id) = ancestor {
+                if id == child_id {
+                    return Err(anyhow!("node {child_id} contains parent {parent_id}"));
+                }
+                ancestor = self.host_including_parent(document, id);
+            }
+            Ok(())
+        })
+    }
+
+    fn ensure_logical_child(&self, parent_id: usize, child_id: usize) -> Result<()> {
+        self.with_document_ref(|document, _| {
             document
                 .get_node(child_id)
                 .ok_or_else(|| anyhow!("missing child node {child_id}"))?;
+            if self.logical_parent(document, child_id) != Some(parent_id) {
+                return Err(anyhow!(
+                    "node {child_id} is not a child of parent {parent_id}"
+                ));
+            }
+            Ok(())
+        })
+    }
+
+    pub fn append_child(&mut self, parent_id: usize, child_id: usize) -> Result<()> {
+        if self.is_composed() {
+            self.ensure_logically_insertable(parent_id, child_id, None)?;
+            self.insert_logical(parent_id, child_id, None);
+            self.sync_styles(&[child_id], false);
+            self.refresh_shadow_trees(&[child_id]);
+            return Ok(());
+        }
+        self.ensure_outside_template_content(parent_id, child_id)?;
+        self.with_document_mut(|document, index, _| {
+            Self::ensure_insertable(document, parent_id, child_id)?;
 
             {
                 let mut mutator = DocumentMutator::new(document);
@@ -472,7 +1944,9 @@ impl BlitzJsBridge {
             Self::seed_stylo_data_for_subtree(document, child_id);
             Self::reindex_internal(document, index);
             Ok(())
-        })
+        })?;
+        self.sync_styles(&[child_id], false);
+        Ok(())
     }
 
     pub fn insert_before(
@@ -481,13 +1955,21 @@ impl BlitzJsBridge {
         child_id: usize,
         reference_id: Option<usize>,
     ) -> Result<()> {
+        // Inserting a node before itself inserts it before its next sibling.
+        let reference_id = match reference_id {
+            Some(reference) if reference == child_id => self.next_sibling(child_id)?,
+            other => other,
+        };
+        if self.is_composed() {
+            self.ensure_logically_insertable(parent_id, child_id, reference_id)?;
+            self.insert_logical(parent_id, child_id, reference_id);
+            self.sync_styles(&[child_id], false);
+            self.refresh_shadow_trees(&[child_id]);
+            return Ok(());
+        }
+        self.ensure_outside_template_content(parent_id, child_id)?;
         self.with_document_mut(|document, index, _| {
-            document
-                .get_node(parent_id)
-                .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
-            document
-                .get_node(child_id)
-                .ok_or_else(|| anyhow!("missing child node {child_id}"))?;
+            Self::ensure_insertable(document, parent_id, child_id)?;
 
             if let Some(reference) = reference_id {
                 let reference_node = document
@@ -512,10 +1994,19 @@ impl BlitzJsBridge {
             Self::seed_stylo_data_for_subtree(document, child_id);
             Self::reindex_internal(document, index);
             Ok(())
-        })
+        })?;
+        self.sync_styles(&[child_id], false);
+        Ok(())
     }
 
     pub fn remove_child(&mut self, parent_id: usize, child_id: usize) -> Result<()> {
+        if self.is_composed() {
+            self.ensure_logical_child(parent_id, child_id)?;
+            self.detach_logical(child_id);
+            self.sync_styles(&[child_id], false);
+            self.refresh_shadow_trees(&[]);
+            return Ok(());
+        }
         self.with_document_mut(|document, index, comments| {
             let Some(node) = document.get_node(child_id) else {
                 return Err(anyhow!("missing child node {child_id}"));
@@ -538,7 +2029,9 @@ impl BlitzJsBridge {
 
             Self::reindex_internal(document, index);
             Ok(())
-        })
+        })?;
+        self.sync_styles(&[child_id], false);
+        Ok(())
     }
 
     pub fn replace_child(
@@ -547,6 +2040,19 @@ impl BlitzJsBridge {
         new_child_id: usize,
         old_child_id: usize,
     ) -> Result<()> {
+        if self.is_composed() {
+            self.ensure_logical_child(parent_id, old_child_id)?;
+            self.ensure_logically_insertable(parent_id, new_child_id, None)?;
+            if new_child_id == old_child_id {
+                return Ok(());
+            }
+            self.insert_logical(parent_id, new_child_id, Some(old_child_id));
+            self.detach_logical(old_child_id);
+            self.sync_styles(&[new_child_id, old_child_id], false);
+            self.refresh_shadow_trees(&[new_child_id]);
+            return Ok(());
+        }
+        self.ensure_outside_template_content(parent_id, new_child_id)?;
         self.with_document_mut(|document, index, comments| {
             document
                 .get_node(parent_id)
@@ -561,11 +2067,12 @@ impl BlitzJsBridge {
                 ));
             }
 
-            document
-                .get_node(new_child_id)
-                .ok_or_else(|| anyhow!("missing replacement node {new_child_id}"))?;
-
             let _ = old_node;
+            Self::ensure_insertable(document, parent_id, new_child_id)?;
+            if new_child_id == old_child_id {
+                return Ok(());
+            }
+
             let removed_comments = Self::collect_comment_nodes(document, old_child_id);
 
             {
@@ -580,14 +2087,27 @@ impl BlitzJsBridge {
             Self::seed_stylo_data_for_subtree(document, new_child_id);
             Self::reindex_internal(document, index);
             Ok(())
-        })
+        })?;
+        self.sync_styles(&[new_child_id, old_child_id], false);
+        Ok(())
     }
 
     pub fn clone_node(&mut self, node_id: usize, deep: bool) -> Result<usize> {
+        if self.shadow_root_hosts.contains_key(&node_id) {
+            return Err(anyhow!("NotSupportedError: shadow roots cannot be cloned"));
+        }
+        if deep && (self.is_composed() || !self.rendered_styles.is_empty()) {
+            let cloned_id = self.clone_logically(node_id)?;
+            self.sync_styles(&[cloned_id], false);
+            return Ok(cloned_id);
+        }
         let cloned_id = self.with_document_mut(|document, index, _| -> Result<usize> {
-            document
+            let node = document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            if matches!(node.data, NodeData::Document) {
+                return Err(anyhow!("cloning the document node is not supported"));
+            }
 
             let cloned_id = {
                 let mut mutator = DocumentMutator::new(document);
@@ -619,77 +2139,394 @@ impl BlitzJsBridge {
             self.comment_payloads.insert(id, payload);
         }
 
+        if self.template_fragments.contains(&node_id) {
+            self.template_fragments.insert(cloned_id);
+        }
+        if self.shadow_hosts.contains_key(&node_id) {
+            // Shadow roots are not cloned, so neither is what marks the host for its styles.
+            self.with_document_mut(|document, _, _| {
+                DocumentMutator::new(document)
+                    .clear_attribute(cloned_id, Self::html_name(HOST_ATTRIBUTE));
+            });
+        }
+        if self.element_styles.contains_key(&node_id) {
+            // Nor is a canvas's bitmap: the clone starts blank.
+            self.with_document_mut(|document, _, _| {
+                DocumentMutator::new(document)
+                    .clear_attribute(cloned_id, Self::html_name(CANVAS_ATTRIBUTE));
+            });
+        }
+        if deep {
+            let pairs = self.with_document_ref(|document, _| {
+                Self::gather_clone_pairs(document, node_id, cloned_id, true)
+            });
+            for (source_id, clone_id) in pairs {
+                if let Some(&content) = self.template_contents.get(&source_id) {
+                    let content_clone = self.clone_node(content, true)?;
+                    self.template_contents.insert(clone_id, content_clone);
+                }
+            }
+            self.sync_styles(&[cloned_id], false);
+        }
+
         Ok(cloned_id)
     }
 
-    pub fn parent_node(&self, node_id: usize) -> Result<Option<usize>> {
-        self.with_document_ref(|document, _| {
+    /// A deep clone of the logical subtree of `node_id`, which is what scripts see: light
+    /// children rather than the shadow tree, and the text scripts gave styles.
+    fn clone_logically(&mut self, node_id: usize) -> Result<usize> {
+        let cloned_id = self.clone_node(node_id, false)?;
+        let source = self.with_document_ref(|document, _| {
+            self.style_source(document, node_id).map(str::to_string)
+        });
+        let children = match source {
+            Some(text) => vec![self.create_text_node(&text)?],
+            None => {
+                let children =
+                    self.with_document_ref(|document, _| self.logical_children(document, node_id));
+                children
+                    .into_iter()
+                    .map(|child| self.clone_logically(child))
+                    .collect::<Result<Vec<_>>>()?
+            }
+        };
+        self.with_document_mut(|document, _, _| {
+            DocumentMutator::new(document).append_children(cloned_id, &children);
+            Self::seed_stylo_data_for_subtree(document, cloned_id);
+        });
+        if let Some(&content) = self.template_contents.get(&node_id) {
+            let content_clone = self.clone_logically(content)?;
+            self.template_contents.insert(cloned_id, content_clone);
+        }
+        Ok(cloned_id)
+    }
+
+    /// A `<template>`'s content fragment. Templates made by scripts get an empty one the first
+    /// time it is asked for.
+    pub fn template_content(&mut self, node_id: usize) -> Result<usize> {
+        if let Some(holder) = self.template_contents.get(&node_id) {
+            return Ok(*holder);
+        }
+        let is_template = self.with_document_ref(|document, _| {
             let node = document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            Ok(node.parent)
-        })
+            Ok::<_, anyhow::Error>(node.element_data().is_some_and(|data| {
+                data.name.ns == ns!(html) && data.name.local == local_name!("template")
+            }))
+        })?;
+        if !is_template || self.template_fragments.contains(&node_id) {
+            return Err(anyhow!("node {node_id} is not a template"));
+        }
+        self.adopt_template_contents(&[node_id]);
+        self.template_contents
+            .get(&node_id)
+            .copied()
+            .ok_or_else(|| anyhow!("template {node_id} has no content"))
     }
 
-    pub fn first_child(&self, node_id: usize) -> Result<Option<usize>> {
-        self.with_document_ref(|document, _| {
+    /// `attachShadow`: give `host_id` an empty shadow root and return it. The host's children
+    /// stay its children for scripts, but only render once the shadow tree has slots for them.
+    pub fn attach_shadow(&mut self, host_id: usize, open: bool) -> Result<usize> {
+        let name = self.with_document_ref(|document, _| {
             let node = document
+                .get_node(host_id)
+                .ok_or_else(|| anyhow!("missing node {host_id}"))?;
+            Ok::<_, anyhow::Error>(
+                node.element_data()
+                    .filter(|data| data.name.ns == ns!(html))
+                    .map(|data| data.name.local.to_string()),
+            )
+        })?;
+        let can_host = name.as_deref().is_some_and(|name| {
+            SHADOW_HOST_NAMES.contains(&name) || is_valid_custom_element_name(name)
+        });
+        if !can_host
+            || self.template_fragments.contains(&host_id)
+            || self.shadow_root_hosts.contains_key(&host_id)
+        {
+            return Err(anyhow!(
+                "NotSupportedError: node {host_id} cannot have a shadow root"
+            ));
+        }
+        if self.shadow_hosts.contains_key(&host_id) {
+            return Err(anyhow!(
+                "NotSupportedError: node {host_id} already has a shadow root"
+            ));
+        }
+
+        let (root, light) = self.with_document_mut(|document, _, _| {
+            let light = document
+                .get_node(host_id)
+                .map(|node| node.children.clone())
+                .unwrap_or_default();
+            let mut mutator = DocumentMutator::new(document);
+            let root = mutator.create_element(Self::html_name("div"), Vec::new());
+            for &child in &light {
+                mutator.remove_node(child);
+            }
+            mutator.set_attribute(
+                host_id,
+                Self::html_name(HOST_ATTRIBUTE),
+                &host_id.to_string(),
+            );
+            (root, light)
+        });
+        self.shadow_hosts
+            .insert(host_id, ShadowHost { root, open, light });
+        self.shadow_root_hosts.insert(root, host_id);
+        self.refresh_shadow_trees(&[]);
+        Ok(root)
+    }
+
+    /// Render the `<style>` element `node_id` with `rules`, as edited through its CSSOM sheet,
+    /// while its text stays what scripts set. Setting the text again drops the rules.
+    pub fn set_style_rules(&mut self, node_id: usize, rules: &str) -> Result<()> {
+        let (is_style, text) = self.with_document_ref(|document, _| {
+            let is_style = document
                 .get_node(node_id)
-                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            Ok(node.children.first().copied())
+                .and_then(|node| node.element_data())
+                .is_some_and(|data| data.name == Self::html_name("style"));
+            let text = match self.style_source(document, node_id) {
+                Some(text) => text.to_string(),
+                None => Self::style_text(document, node_id),
+            };
+            (is_style, text)
+        });
+        if !is_style {
+            return Err(anyhow!("node {node_id} is not a <style> element"));
+        }
+        self.rendered_styles.insert(
+            node_id,
+            RenderedStyle {
+                text,
+                rules: Some(rules.to_string()),
+                rendered: rules.to_string(),
+            },
+        );
+        self.write_style(node_id, rules);
+        self.sync_styles(&[node_id], false);
+        if !self.shadow_hosts.is_empty() {
+            self.refresh_shadow_trees(&[]);
+        }
+        Ok(())
+    }
+
+    /// Render `css`, the rules of the `adoptedStyleSheets` of `tree_id`, the document or a
+    /// shadow root, after every other style of that tree. Empty `css` removes them.
+    pub fn set_adopted_styles(&mut self, tree_id: usize, css: &str) -> Result<()> {
+        let parent_id = if tree_id == self.document_handle() {
+            tree_id
+        } else {
+            *self
+                .shadow_root_hosts
+                .get(&tree_id)
+                .ok_or_else(|| anyhow!("node {tree_id} is not a document or shadow root"))?
+        };
+        let existing = self.adopted_styles.get(&tree_id).copied();
+        if existing.is_none() && css.is_empty() {
+            return Ok(());
+        }
+        let inserted = match self.render_hidden_style(parent_id, existing, css) {
+            Some(style) if existing.is_none() => {
+                self.adopted_styles.insert(tree_id, style);
+                vec![style]
+            }
+            Some(_) => Vec::new(),
+            None => {
+                self.adopted_styles.remove(&tree_id);
+                Vec::new()
+            }
+        };
+        self.refresh_shadow_trees(&inserted);
+        Ok(())
+    }
+
+    /// Render `css`, the stylesheet fetched for the `<link>` element `link_id`, in a `<style>`
+    /// scripts never see inside the link, so it applies while the link is in the document and
+    /// goes with it. Empty `css` removes it.
+    pub fn set_link_styles(&mut self, link_id: usize, css: &str) -> Result<()> {
+        let is_link =
+            self.with_document_ref(|document, _| Self::is_html_element(document, link_id, "link"));
+        if !is_link {
+            return Err(anyhow!("node {link_id} is not a <link> element"));
+        }
+        self.set_element_styles(link_id, css);
+        Ok(())
+    }
+
+    /// Show the bitmap a script drew on the `<canvas>` element `canvas_id`: mark the canvas
+    /// with a hidden [`CANVAS_ATTRIBUTE`] and render `css`, which paints the bitmap on it, in
+    /// a `<style>` scripts never see inside the canvas. Empty `css` removes it.
+    pub fn set_canvas_styles(&mut self, canvas_id: usize, css: &str) -> Result<()> {
+        let is_canvas = self
+            .with_document_ref(|document, _| Self::is_html_element(document, canvas_id, "canvas"));
+        if !is_canvas {
+            return Err(anyhow!("node {canvas_id} is not a <canvas> element"));
+        }
+        self.with_document_mut(|document, _, _| {
+            let mut mutator = DocumentMutator::new(document);
+            let name = Self::html_name(CANVAS_ATTRIBUTE);
+            if css.is_empty() {
+                mutator.clear_attribute(canvas_id, name);
+            } else {
+                mutator.set_attribute(canvas_id, name, &canvas_id.to_string());
+            }
+        });
+        self.set_element_styles(canvas_id, css);
+        Ok(())
+    }
+
+    fn set_element_styles(&mut self, element_id: usize, css: &str) {
+        let existing = self.element_styles.get(&element_id).copied();
+        if existing.is_none() && css.is_empty() {
+            return;
+        }
+        let inserted = match self.render_hidden_style(element_id, existing, css) {
+            Some(style) => {
+                self.element_styles.insert(element_id, style);
+                // A new carrier parses as if it were in the document; empty it if it is not.
+                self.sync_styles(&[style], false);
+                vec![style]
+            }
+            None => {
+                self.element_styles.remove(&element_id);
+                Vec::new()
+            }
+        };
+        self.refresh_shadow_trees(&inserted);
+    }
+
+    /// The shadow root of `host_id` if it is open, as `Element.shadowRoot` exposes it.
+    pub fn shadow_root(&self, host_id: usize) -> Option<usize> {
+        self.shadow_hosts
+            .get(&host_id)
+            .filter(|shadow| shadow.open)
+            .map(|shadow| shadow.root)
+    }
+
+    /// The host of the shadow root `root_id`, and whether the root is open.
+    pub fn shadow_host(&self, root_id: usize) -> Option<(usize, bool)> {
+        let host = *self.shadow_root_hosts.get(&root_id)?;
+        Some((host, self.shadow_hosts.get(&host)?.open))
+    }
+
+    /// The light children assigned to the slot `slot_id`, in order.
+    pub fn assigned_nodes(&self, slot_id: usize) -> Vec<usize> {
+        if !self.slot_fallbacks.contains_key(&slot_id) {
+            return Vec::new();
+        }
+        self.with_document_ref(|document, _| {
+            document
+                .get_node(slot_id)
+                .map(|slot| slot.children.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|id| self.light_host(*id).is_some())
+                .collect()
         })
     }
 
-    pub fn next_sibling(&self, node_id: usize) -> Result<Option<usize>> {
+    /// The slot `node_id` is assigned to, closed shadow trees included.
+    pub fn assigned_slot(&self, node_id: usize) -> Option<usize> {
+        self.light_host(node_id)?;
+        let parent = self.with_document_ref(|document, _| document.get_node(node_id)?.parent)?;
+        self.slot_fallbacks.contains_key(&parent).then_some(parent)
+    }
+
+    pub fn has_shadow_roots(&self) -> bool {
+        !self.shadow_hosts.is_empty()
+    }
+
+    /// The path a composed event at `node_id` takes to the document: light children go
+    /// through the slot they are assigned to, and shadow roots on to their hosts.
+    pub fn event_path(&self, node_id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut current = Some(node_id);
+        while let Some(id) = current {
+            path.push(id);
+            current = self.assigned_slot(id).or_else(|| {
+                self.with_document_ref(|document, _| {
+                    self.logical_parent(document, id)
+                        .or_else(|| self.shadow_root_hosts.get(&id).copied())
+                })
+            });
+        }
+        path
+    }
+
+    /// The border box of `node_id` from the last layout, relative to the viewport, as
+    /// `(x, y, width, height)`. `None` when the node is not rendered: detached, unstyled or
+    /// under `display: none`. Scroll containers other than the viewport are not accounted for.
+    pub fn layout_rect(&self, node_id: usize) -> Option<(f64, f64, f64, f64)> {
         self.with_document_ref(|document, _| {
-            let node = document
-                .get_node(node_id)
-                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            let parent_id = match node.parent {
-                Some(id) => id,
-                None => return Ok(None),
-            };
-            let parent = document
-                .get_node(parent_id)
-                .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
-            let position = parent.children.iter().position(|id| *id == node_id);
-            Ok(position
-                .and_then(|idx| parent.children.get(idx + 1))
-                .copied())
+            let root = document.root_node().id;
+            let mut current = node_id;
+            while current != root {
+                let node = document.get_node(current)?;
+                if node.element_data().is_some() {
+                    let styles = node.primary_styles()?;
+                    if styles.get_box().display.is_none() {
+                        return None;
+                    }
+                }
+                current = node.parent?;
+            }
+            let node = document.get_node(node_id)?;
+            let position = node.absolute_position(0.0, 0.0);
+            let size = node.final_layout.size;
+            let scroll = document.viewport_scroll();
+            Some((
+                f64::from(position.x) - scroll.x,
+                f64::from(position.y) - scroll.y,
+                f64::from(size.width),
+                f64::from(size.height),
+            ))
         })
     }
 
-    pub fn previous_sibling(&self, node_id: usize) -> Result<Option<usize>> {
+    pub fn parent_node(&self, node_id: usize) -> Result<Option<usize>> {
         self.with_document_ref(|document, _| {
-            let node = document
+            document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            let parent_id = match node.parent {
-                Some(id) => id,
-                None => return Ok(None),
-            };
-            let parent = document
-                .get_node(parent_id)
-                .ok_or_else(|| anyhow!("missing parent node {parent_id}"))?;
-            let position = parent.children.iter().position(|id| *id == node_id);
-            Ok(position
-                .and_then(|idx| {
-                    if idx == 0 {
-                        None
-                    } else {
-                        parent.children.get(idx - 1)
-                    }
-                })
-                .copied())
+            Ok(self.logical_parent(document, node_id))
         })
     }
 
+    pub fn first_child(&self, node_id: usize) -> Result<Option<usize>> {
+        Ok(self.child_nodes(node_id)?.first().copied())
+    }
+
+    pub fn next_sibling(&self, node_id: usize) -> Result<Option<usize>> {
+        let siblings = self.siblings(node_id)?;
+        let position = siblings.iter().position(|id| *id == node_id);
+        Ok(position.and_then(|idx| siblings.get(idx + 1)).copied())
+    }
+
+    pub fn previous_sibling(&self, node_id: usize) -> Result<Option<usize>> {
+        let siblings = self.siblings(node_id)?;
+        let position = siblings.iter().position(|id| *id == node_id);
+        Ok(position
+            .and_then(|idx| idx.checked_sub(1))
+            .and_then(|idx| siblings.get(idx))
+            .copied())
+    }
+
+    /// The children of `node_id`'s parent, or none when it has no parent.
+    fn siblings(&self, node_id: usize) -> Result<Vec<usize>> {
+        match self.parent_node(node_id)? {
+            Some(parent_id) => self.child_nodes(parent_id),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn child_nodes(&self, node_id: usize) -> Result<Vec<usize>> {
         self.with_document_ref(|document, _| {
-            let node = document
+            document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            Ok(node.children.clone())
+            Ok(self.logical_children(document, node_id))
         })
     }
 
@@ -700,6 +2537,11 @@ impl BlitzJsBridge {
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
             let name = match &node.data {
                 NodeData::Document => "#document".to_string(),
+                _ if self.template_fragments.contains(&node_id)
+                    || self.shadow_root_hosts.contains_key(&node_id) =>
+                {
+                    "#document-fragment".to_string()
+                }
                 NodeData::Element(data) => data.name.local.as_ref().to_ascii_uppercase(),
                 NodeData::AnonymousBlock(data) => data.name.local.as_ref().to_ascii_uppercase(),
                 NodeData::Text(_) => "#text".to_string(),
@@ -716,6 +2558,11 @@ impl BlitzJsBridge {
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
             let ty = match node.data {
                 NodeData::Document => 9,
+                _ if self.template_fragments.contains(&node_id)
+                    || self.shadow_root_hosts.contains_key(&node_id) =>
+                {
+                    11
+                }
                 NodeData::Element(_) | NodeData::AnonymousBlock(_) => 1,
                 NodeData::Text(_) => 3,
                 NodeData::Comment => 8,
@@ -747,8 +2594,41 @@ impl BlitzJsBridge {
             let node = document
                 .get_node(node_id)
                 .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-            let attr_name = LocalName::from(name);
-            let value = node.attr(attr_name).map(|s| s.to_string());
+            // Matched on the qualified name, so `href` does not find `xlink:href`.
+            let value = node.element_data().and_then(|element| {
+                element
+                    .attrs
+                    .iter()
+                    .find(|attr| attribute_qualified_name(&attr.name) == name)
+                    .filter(|_| !Self::is_hidden_attribute(name))
+                    .map(|attr| attr.value.to_string())
+            });
+            Ok(value)
+        })
+    }
+
+    pub fn get_attribute_ns(
+        &self,
+        node_id: usize,
+        namespace: Option<&str>,
+        local: &str,
+    ) -> Result<Option<String>> {
+        let namespace = namespace.filter(|uri| !uri.is_empty());
+        self.with_document_ref(|document, _| {
+            let node = document
+                .get_node(node_id)
+                .ok_or_else(|| anyhow!("missing node {node_id}"))?;
+            let value = node.element_data().and_then(|element| {
+                element
+                    .attrs
+                    .iter()
+                    .find(|attr| {
+                        attr.name.local.as_ref() == local
+                            && Self::attribute_in_namespace(&attr.name, namespace)
+                    })
+                    .filter(|attr| !Self::is_hidden_attribute(&attr.name.local))
+                    .map(|attr| attr.value.to_string())
+            });
             Ok(value)
         })
     }
@@ -776,6 +2656,49 @@ impl BlitzJsBridge {
         })
     }
 
+    /// The HTML elements in the document and the shadow trees in it whose local name passes
+    /// `filter`, in shadow-including tree order.
+    pub fn connected_elements_where(&self, filter: impl Fn(&str) -> bool) -> Vec<(usize, String)> {
+        self.with_document_ref(|document, _| {
+            let mut found = Vec::new();
+            let mut stack = vec![document.root_node().id];
+            while let Some(node_id) = stack.pop() {
+                let Some(node) = document.get_node(node_id) else {
+                    continue;
+                };
+                if let Some(data) = node.element_data() {
+                    if data.name.ns == ns!(html) && filter(data.name.local.as_ref()) {
+                        found.push((node_id, data.name.local.to_string()));
+                    }
+                }
+                stack.extend(self.logical_children(document, node_id).into_iter().rev());
+                if let Some(shadow) = self.shadow_hosts.get(&node_id) {
+                    stack.extend(
+                        self.logical_children(document, shadow.root)
+                            .into_iter()
+                            .rev(),
+                    );
+                }
+            }
+            found
+        })
+    }
+
+    /// Whether `node_id` is `ancestor_id` or inside it, counting shadow trees as inside their
+    /// hosts.
+    pub fn is_inclusive_descendant(&self, node_id: usize, ancestor_id: usize) -> bool {
+        self.with_document_ref(|document, _| {
+            let mut current = Some(node_id);
+            while let Some(id) = current {
+                if id == ancestor_id {
+                    return true;
+                }
+                current = self.host_including_parent(document, id);
+            }
+            false
+        })
+    }
+
     pub fn document_handle(&self) -> usize {
         self.with_document_ref(|document, _| document.root_node().id)
     }
@@ -795,11 +2718,20 @@ impl BlitzJsBridge {
         node_id: usize,
         output: &mut String,
     ) -> Result<()> {
-        let node = doc
-            .get_node(node_id)
+        // A template serializes its content, so its markup round-trips.
+        let node_id = self
+            .template_contents
+            .get(&node_id)
+            .copied()
+            .unwrap_or(node_id);
+        doc.get_node(node_id)
             .ok_or_else(|| anyhow!("missing node {node_id}"))?;
-        for child in &node.children {
-            self.serialize_node(doc, *child, output)?;
+        if let Some(text) = self.style_source(doc, node_id) {
+            output.push_str(&encode_text(text));
+            return Ok(());
+        }
+        for child in self.logical_children(doc, node_id) {
+            self.serialize_node(doc, child, output)?;
         }
         Ok(())
     }
@@ -822,8 +2754,11 @@ impl BlitzJsBridge {
                 output.push('<');
                 output.push_str(data.name.local.as_ref());
                 for attr in data.attrs.iter() {
+                    if Self::is_hidden_attribute(&attr.name.local) {
+                        continue;
+                    }
                     output.push(' ');
-                    output.push_str(attr.name.local.as_ref());
+                    output.push_str(&attribute_qualified_name(&attr.name));
                     output.push_str("=\"");
                     let encoded = encode_double_quoted_attribute(&attr.value);
                     output.push_str(&encoded);
@@ -850,3 +2785,37 @@ impl BlitzJsBridge {
         Ok(())
     }
 }
+
+/// An attribute's name as written in markup. `xml:`, `xmlns:` and `xlink:` attributes get
+/// their conventional prefix, as HTML serialization requires; other namespaces keep theirs.
+fn attribute_qualified_name(name: &QualName) -> Cow<'_, str> {
+    let local = name.local.as_ref();
+    let prefix = match &*name.ns {
+        XML_NAMESPACE => Some("xml"),
+        XMLNS_NAMESPACE if local == "xmlns" => None,
+        XMLNS_NAMESPACE => Some("xmlns"),
+        XLINK_NAMESPACE => Some("xlink"),
+        _ if name.ns == ns!() || name.ns == ns!(html) => None,
+        _ => name.prefix.as_deref(),
+    };
+    match prefix {
+        Some(prefix) => Cow::Owned(format!("{prefix}:{local}")),
+        None => Cow::Borrowed(local),
+    }
+}
+
+/// Byte index of the position `offset` UTF-16 code units into `text`, or `None` if that is past
+/// the end or between the halves of a surrogate pair.
+fn utf16_byte_offset(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+    for (index, ch) in text.char_indices() {
+        if units == offset {
+            return Some(index);
+        }
+        units += ch.len_utf16();
+        if units > offset {
+            return None;
+        }
+    }
+    (units == offset).then_some(text.len())
+}
@@ -0,0 +1,227 @@
+//! Compiled QuickJS bytecode for large page scripts, so framework bundles are parsed once
+//! rather than on every visit.
+//!
+//! Entries live in `bytecode-cache/` under the data directory, named by the SHA-256 of the
+//! script text, the name it runs under (the name is baked into the bytecode for stack traces)
+//! and the build of Frontier that compiled it. Each file starts with a magic tag and a checksum
+//! of the bytecode: QuickJS does not validate bytecode it reads, so a torn or foreign file must
+//! never reach it. Nor does it check which engine version wrote the bytecode, so an upgraded
+//! binary looks under different keys and the old entries age out of the cache.
+//!
+//! Set `FRONTIER_BYTECODE_CACHE=off` to always compile from source.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::storage_policy::StoragePolicy;
+
+const DIR_NAME: &str = "bytecode-cache";
+const MAGIC: &[u8; 8] = b"FQJSBC02";
+/// The rquickjs release `Cargo.toml` pins, which decides the QuickJS bytecode format.
+const RQUICKJS_VERSION: &str = "0.7";
+const CHECKSUM_LEN: usize = 32;
+/// Smaller scripts compile faster than their cache file can be read.
+pub const MIN_CACHED_BYTES: usize = 16 * 1024;
+/// Oldest entries are dropped past this size when the cache is opened.
+const MAX_CACHE_BYTES: u64 = 128 * 1024 * 1024;
+
+/// Compile timings for the page scripts large enough to be cached, reported with the script
+/// summary of each load so repeat visits can be compared against the first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompileStats {
+    /// Scripts loaded from the cache.
+    pub cache_hits: u32,
+    /// Scripts compiled from source.
+    pub cache_misses: u32,
+    /// Time spent parsing and compiling source.
+    pub compile_ms: f64,
+    /// Time spent reading and loading cached bytecode.
+    pub load_ms: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BytecodeCache {
+    dir: PathBuf,
+}
+
+impl BytecodeCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The process-wide cache under the data directory, unless disabled, in a private session
+    /// or no data directory can be determined. Opening it trims the cache back under its size
+    /// limit.
+    pub fn shared() -> Option<&'static BytecodeCache> {
+        static SHARED: OnceLock<Option<BytecodeCache>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| {
+                let disabled = std::env::var("FRONTIER_BYTECODE_CACHE")
+                    .is_ok_and(|value| matches!(value.trim(), "off" | "0" | "false"));
+                if disabled || !StoragePolicy::current().persists() {
+                    return None;
+                }
+                let cache = crate::preferences::data_file(DIR_NAME).map(Self::new)?;
+                if let Err(err) = cache.prune(MAX_CACHE_BYTES) {
+                    tracing::debug!(
+                        target = "quickjs",
+                        error = %err,
+                        "unable to prune bytecode cache"
+                    );
+                }
+                Some(cache)
+            })
+            .as_ref()
+    }
+
+    /// Cache key for `script` evaluated as `filename` by this build.
+    pub fn key(script: &[u8], filename: &str) -> String {
+        Self::key_for_build(build_id(), script, filename)
+    }
+
+    fn key_for_build(build: &str, script: &[u8], filename: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(build.as_bytes());
+        hasher.update([0]);
+        hasher.update(filename.as_bytes());
+        hasher.update([0]);
+        hasher.update(script);
+        hex::encode(hasher.finalize())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.qbc"))
+    }
+
+    /// The bytecode stored under `key`, if present and intact.
+    pub fn load(&self, key: &str) -> Option<Vec<u8>> {
+        let raw = std::fs::read(self.entry_path(key)).ok()?;
+        let body = raw.strip_prefix(MAGIC.as_slice())?;
+        if body.len() < CHECKSUM_LEN {
+            return None;
+        }
+        let (checksum, bytecode) = body.split_at(CHECKSUM_LEN);
+        (Sha256::digest(bytecode).as_slice() == checksum).then(|| bytecode.to_vec())
+    }
+
+    pub fn store(&self, key: &str, bytecode: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("unable to create {}", self.dir.display()))?;
+        let mut raw = Vec::with_capacity(MAGIC.len() + CHECKSUM_LEN + bytecode.len());
+        raw.extend_from_slice(MAGIC);
+        raw.extend_from_slice(&Sha256::digest(bytecode));
+        raw.extend_from_slice(bytecode);
+        let path = self.entry_path(key);
+        let partial = path.with_extension("qbc.partial");
+        std::fs::write(&partial, raw)
+            .with_context(|| format!("unable to write {}", partial.display()))?;
+        std::fs::rename(&partial, &path)
+            .with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Drop an entry QuickJS refused to load, so it is rewritten on the next compile.
+    pub fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.entry_path(key));
+    }
+
+    /// Delete the least recently written entries until the cache fits in `max_bytes`.
+    fn prune(&self, max_bytes: u64) -> Result<()> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        for entry in entries {
+            let entry = entry.context("read bytecode cache entry")?;
+            let metadata = entry.metadata().context("read bytecode cache entry")?;
+            if metadata.is_file() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, metadata.len(), entry.path()));
+            }
+        }
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        files.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, path) in files {
+            if total <= max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("unable to remove {}", path.display()))?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies the QuickJS that compiles page scripts: the crate version and its bundled
+/// rquickjs, plus the size and modification time of the executable so a rebuild against a
+/// different engine never loads bytecode it did not write.
+fn build_id() -> &'static str {
+    static BUILD: OnceLock<String> = OnceLock::new();
+    BUILD.get_or_init(|| {
+        let executable = std::env::current_exe()
+            .and_then(std::fs::metadata)
+            .map(|metadata| {
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map_or(0, |since| since.as_nanos());
+                format!("{}-{modified}", metadata.len())
+            })
+            .unwrap_or_default();
+        format!(
+            "frontier-{}/rquickjs-{RQUICKJS_VERSION}/{executable}",
+            env!("CARGO_PKG_VERSION")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_damaged_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BytecodeCache::new(dir.path().join(DIR_NAME));
+        let key = BytecodeCache::key(b"let x = 1;", "app.js");
+        assert_ne!(key, BytecodeCache::key(b"let x = 1;", "other.js"));
+        assert_ne!(
+            BytecodeCache::key_for_build("a", b"let x = 1;", "app.js"),
+            BytecodeCache::key_for_build("b", b"let x = 1;", "app.js")
+        );
+        assert!(cache.load(&key).is_none());
+
+        cache.store(&key, b"\x02bytecode").unwrap();
+        assert_eq!(
+            cache.load(&key).as_deref(),
+            Some(b"\x02bytecode".as_slice())
+        );
+
+        let path = cache.entry_path(&key);
+        let mut raw = std::fs::read(&path).unwrap();
+        *raw.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&path, &raw).unwrap();
+        assert!(cache.load(&key).is_none());
+        std::fs::write(&path, &raw[..20]).unwrap();
+        assert!(cache.load(&key).is_none());
+    }
+
+    #[test]
+    fn prune_drops_oldest_entries_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BytecodeCache::new(dir.path().to_path_buf());
+        cache.store("old", &[0; 100]).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.store("new", &[0; 100]).unwrap();
+
+        cache.prune(200).unwrap();
+        assert!(cache.load("old").is_none());
+        assert!(cache.load("new").is_some());
+    }
+}
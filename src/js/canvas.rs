@@ -0,0 +1,3168 @@
+//! `<canvas>` 2D contexts for page script.
+//!
+//! A context records what the page draws as commands and hands them over when the script
+//! yields, or sooner when the page reads pixels back. [`CanvasStore`] keeps a bitmap per canvas
+//! and paints each batch over it with the CPU renderer screenshots use. The canvas element shows
+//! its bitmap as a PNG background, in a stylesheet the bridge keeps inside it (see
+//! [`canvas_image_css`]), so a page that redraws every frame encodes a PNG per frame, one
+//! compressed for speed rather than size. Bitmaps past `MAX_AREA` pixels, or `MAX_SIDE` on a
+//! side, are never allocated: such canvases draw nothing and reading them back throws.
+//!
+//! Text is shaped with parley, the text engine Blitz lays pages out with, in the context's
+//! `font`, so libraries that size labels through a canvas get the widths the page renders
+//! with. Fonts come from the system; `@font-face` fonts the page loads are not seen here. The
+//! actual bounding box `measureText()` reports is the font's ascent and descent rather than the
+//! ink of the glyphs. Shadows and filters are kept but not drawn, clips always use the nonzero
+//! rule, and `drawImage()` and `createPattern()` take canvases, offscreen canvases, image
+//! bitmaps and `data:` images.
+//!
+//! `OffscreenCanvas` and `ImageBitmap` keep their pixels in the same store, under keys of their
+//! own. There are no workers to hand them to, so an offscreen canvas draws on the page's
+//! runtime; one a `<canvas>` transferred control to draws on that canvas's bitmap.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::io::Cursor;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::Result;
+use anyrender::{render_to_buffer, Glyph, PaintScene};
+use anyrender_vello_cpu::VelloCpuImageRenderer;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ExtendedColorType, ImageEncoder, ImageReader, Limits, RgbaImage};
+use parley::{
+    FontContext, FontStack, FontStyle, FontWeight, Layout, LayoutContext, PositionedLayoutItem,
+    StyleProperty,
+};
+use peniko::color::{parse_color, AlphaColor, Rgba8, Srgb};
+use peniko::kurbo::{self, Affine, BezPath, Cap, Join, Point, Rect, Shape, Stroke, StrokeOpts};
+use peniko::{
+    BlendMode, Blob, Brush, Compose, Extend, Fill, Gradient, ImageAlphaType, ImageBrush, ImageData,
+    ImageQuality, Mix,
+};
+use percent_encoding::percent_decode_str;
+use rquickjs::{ArrayBuffer, Ctx, Function};
+use serde::Deserialize;
+
+use super::isolation::NativeMemory;
+use crate::screenshot::encode_png;
+
+/// The attribute marking a canvas the bridge shows a bitmap on, with the canvas's node id, for
+/// [`canvas_image_css`] to select it by. Scripts never see it.
+pub(crate) const CANVAS_ATTRIBUTE: &str = "frontier-canvas";
+
+/// How far a pattern that repeats along one axis reaches along it.
+const UNBOUNDED: f64 = 1e9;
+
+/// The widest or tallest bitmap a canvas gets, as in Chromium.
+const MAX_SIDE: u32 = 32_767;
+
+/// The most pixels a bitmap gets, 16384×16384: a gigabyte of RGBA.
+const MAX_AREA: u64 = 1 << 28;
+
+/// Whether a `width`×`height` bitmap is small enough to allocate. Larger canvases draw
+/// nothing and read back nothing, and the script side refuses image data this big.
+fn fits(width: u32, height: u32) -> bool {
+    width <= MAX_SIDE && height <= MAX_SIDE && u64::from(width) * u64::from(height) <= MAX_AREA
+}
+
+/// The part of `start..start + len` inside a source `source_len` long that lands inside a
+/// target `target_len` long when placed at `origin`.
+fn span(start: u32, len: u32, source_len: u32, origin: i64, target_len: u32) -> Range<i64> {
+    let from = i64::from(start).max(origin.saturating_neg());
+    let to = (i64::from(start) + i64::from(len))
+        .min(i64::from(source_len))
+        .min(i64::from(target_len).saturating_sub(origin));
+    from..to.max(from)
+}
+
+/// The stylesheet that shows `png`, a `width`×`height` bitmap, on the canvas `canvas_id`. It is
+/// also what sizes the canvas, with no specificity so page styles win.
+pub(crate) fn canvas_image_css(canvas_id: usize, png: &[u8], width: u32, height: u32) -> String {
+    format!(
+        ":where([{CANVAS_ATTRIBUTE}=\"{canvas_id}\"]) {{ display: inline-block; \
+         width: {width}px; height: {height}px; \
+         background-image: url(\"data:image/png;base64,{}\"); \
+         background-size: 100% 100%; background-repeat: no-repeat; }}",
+        BASE64.encode(png)
+    )
+}
+
+/// A parsed CSS `font` shorthand, as `ctx.font` accepts it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanvasFont {
+    pub italic: bool,
+    pub weight: f32,
+    /// In CSS pixels.
+    pub size: f64,
+    /// The family list as written, such as `"Helvetica Neue", sans-serif`.
+    pub family: String,
+}
+
+impl Default for CanvasFont {
+    fn default() -> Self {
+        Self {
+            italic: false,
+            weight: 400.0,
+            size: 10.0,
+            family: "sans-serif".to_string(),
+        }
+    }
+}
+
+impl CanvasFont {
+    /// Parse `[style] [variant] [weight] [stretch] size[/line-height] family`. Relative sizes
+    /// are taken against the initial 16px font size; values this cannot read give `None`,
+    /// which `ctx.font` ignores.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut font = CanvasFont::default();
+        let mut rest = value.trim();
+        loop {
+            let (token, after) = rest.split_once(char::is_whitespace)?;
+            match token.to_ascii_lowercase().as_str() {
+                "normal" | "small-caps" | "condensed" | "semi-condensed" | "expanded"
+                | "semi-expanded" => {}
+                "italic" | "oblique" => font.italic = true,
+                "bold" | "bolder" => font.weight = 700.0,
+                "lighter" => font.weight = 100.0,
+                other => match other.parse::<f32>() {
+                    Ok(weight) if (1.0..=1000.0).contains(&weight) => font.weight = weight,
+                    _ => break,
+                },
+            }
+            rest = after.trim_start();
+        }
+
+        let (size, family) = rest.split_once(char::is_whitespace)?;
+        let size = size.split_once('/').map_or(size, |(size, _)| size);
+        font.size = parse_font_size(size)?;
+        let family = family.trim();
+        if family.is_empty() {
+            return None;
+        }
+        font.family = family.to_string();
+        Some(font)
+    }
+
+    /// How `ctx.font` reads back: the size in pixels and only what differs from `normal`.
+    pub fn to_css(&self) -> String {
+        let mut css = String::new();
+        if self.italic {
+            css.push_str("italic ");
+        }
+        if self.weight == 700.0 {
+            css.push_str("bold ");
+        } else if self.weight != 400.0 {
+            css.push_str(&format!("{} ", self.weight));
+        }
+        css.push_str(&format!("{}px {}", self.size, self.family));
+        css
+    }
+}
+
+fn parse_font_size(value: &str) -> Option<f64> {
+    let value = value.to_ascii_lowercase();
+    let keyword = match value.as_str() {
+        "xx-small" => Some(9.0),
+        "x-small" => Some(10.0),
+        "small" => Some(13.0),
+        "medium" => Some(16.0),
+        "large" => Some(18.0),
+        "x-large" => Some(24.0),
+        "xx-large" => Some(32.0),
+        _ => None,
+    };
+    if keyword.is_some() {
+        return keyword;
+    }
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let scale = match unit {
+        "px" => 1.0,
+        "pt" => 4.0 / 3.0,
+        "pc" => 16.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        "em" | "rem" => 16.0,
+        "%" => 0.16,
+        _ => return None,
+    };
+    Some(number * scale)
+}
+
+/// What `measureText()` reports before the context's alignment and baseline are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextMeasurement {
+    pub width: f64,
+    /// From the alphabetic baseline up to the top of the font's box.
+    pub ascent: f64,
+    /// From the alphabetic baseline down to the bottom of the font's box.
+    pub descent: f64,
+}
+
+thread_local! {
+    static TEXT_CONTEXT: RefCell<(FontContext, LayoutContext<()>)> =
+        RefCell::new((FontContext::new(), LayoutContext::new()));
+}
+
+/// Shape `text` on one line in `font` and hand the layout to `f`.
+fn with_text_layout<T>(text: &str, font: &CanvasFont, f: impl FnOnce(&Layout<()>) -> T) -> T {
+    TEXT_CONTEXT.with(|context| {
+        let (font_cx, layout_cx) = &mut *context.borrow_mut();
+        let mut builder = layout_cx.ranged_builder(font_cx, text, 1.0, true);
+        builder.push_default(StyleProperty::FontSize(font.size as f32));
+        builder.push_default(StyleProperty::FontStack(FontStack::Source(Cow::Borrowed(
+            font.family.as_str(),
+        ))));
+        builder.push_default(StyleProperty::FontWeight(FontWeight::new(font.weight)));
+        if font.italic {
+            builder.push_default(StyleProperty::FontStyle(FontStyle::Italic));
+        }
+        let mut layout: Layout<()> = builder.build(text);
+        layout.break_all_lines(None);
+        f(&layout)
+    })
+}
+
+fn measure_layout(layout: &Layout<()>, font: &CanvasFont) -> TextMeasurement {
+    let (ascent, descent) = layout
+        .lines()
+        .next()
+        .map(|line| {
+            let metrics = line.metrics();
+            (f64::from(metrics.ascent), f64::from(metrics.descent))
+        })
+        // An empty string has no line; browsers still report the font's box.
+        .unwrap_or((font.size * 0.8, font.size * 0.2));
+    TextMeasurement {
+        width: f64::from(layout.full_width()),
+        ascent,
+        descent,
+    }
+}
+
+/// Shape `text` on one line in `font`.
+pub fn measure_text(text: &str, font: &CanvasFont) -> TextMeasurement {
+    with_text_layout(text, font, |layout| measure_layout(layout, font))
+}
+
+fn css_color(value: &str) -> Option<AlphaColor<Srgb>> {
+    parse_color(value)
+        .ok()
+        .map(|color| color.to_alpha_color::<Srgb>())
+}
+
+/// `value` as `fillStyle` and `strokeStyle` read a color back: `#rrggbb` when it is opaque
+/// and `rgba()` otherwise, or `None` when it is not a color.
+pub fn normalize_color(value: &str) -> Option<String> {
+    let color = css_color(value)?;
+    let Rgba8 { r, g, b, a } = color.to_rgba8();
+    if a == 255 {
+        return Some(format!("#{r:02x}{g:02x}{b:02x}"));
+    }
+    let alpha = (f64::from(color.components[3]) * 1000.0).round() / 1000.0;
+    Some(format!("rgba({r}, {g}, {b}, {alpha})"))
+}
+
+/// What a context flushes: what it drew since it last did, on a canvas of this size.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CanvasBatch {
+    width: u32,
+    height: u32,
+    /// The canvas was resized or reset since, so its bitmap starts over transparent.
+    #[serde(default)]
+    reset: bool,
+    #[serde(default)]
+    ops: Vec<CanvasOp>,
+}
+
+/// One drawing call, resolved against the context's state when it was made. Paths are SVG
+/// path data in the user space of `state.transform`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum CanvasOp {
+    #[serde(rename_all = "camelCase")]
+    Fill {
+        path: String,
+        #[serde(default)]
+        even_odd: bool,
+        paint: CanvasPaint,
+        state: DrawState,
+    },
+    Stroke {
+        path: String,
+        line: LineStyle,
+        paint: CanvasPaint,
+        state: DrawState,
+    },
+    /// `fillText()`, or `strokeText()` when it has a `line`.
+    #[serde(rename_all = "camelCase")]
+    Text {
+        text: String,
+        font: String,
+        x: f64,
+        y: f64,
+        /// `left`, `center` or `right`, with `start` and `end` resolved.
+        align: String,
+        baseline: String,
+        max_width: Option<f64>,
+        line: Option<LineStyle>,
+        paint: CanvasPaint,
+        state: DrawState,
+    },
+    /// `drawImage()`: the `from` rectangle of `source` into the `to` one, as `[x, y, w, h]`.
+    Image {
+        source: ImageSource,
+        from: [f64; 4],
+        to: [f64; 4],
+        state: DrawState,
+    },
+    /// `clearRect()`.
+    Clear { rect: [f64; 4], state: DrawState },
+}
+
+impl CanvasOp {
+    fn state(&self) -> &DrawState {
+        match self {
+            CanvasOp::Fill { state, .. }
+            | CanvasOp::Stroke { state, .. }
+            | CanvasOp::Text { state, .. }
+            | CanvasOp::Image { state, .. }
+            | CanvasOp::Clear { state, .. } => state,
+        }
+    }
+
+    fn sources(&self) -> impl Iterator<Item = &ImageSource> + '_ {
+        let paint = match self {
+            CanvasOp::Fill { paint, .. }
+            | CanvasOp::Stroke { paint, .. }
+            | CanvasOp::Text { paint, .. } => Some(paint),
+            _ => None,
+        };
+        let pattern = paint.and_then(|paint| match paint {
+            CanvasPaint::Pattern { source, .. } => Some(source),
+            _ => None,
+        });
+        let image = match self {
+            CanvasOp::Image { source, .. } => Some(source),
+            _ => None,
+        };
+        pattern.into_iter().chain(image)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DrawState {
+    /// `[a, b, c, d, e, f]`, as `setTransform()` takes it.
+    transform: [f64; 6],
+    alpha: f32,
+    composite: String,
+    /// The clip paths in canvas pixels, outermost first.
+    #[serde(default)]
+    clips: Vec<String>,
+    smoothing: bool,
+}
+
+impl DrawState {
+    fn transform(&self) -> Affine {
+        Affine::new(self.transform)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct LineStyle {
+    width: f64,
+    cap: String,
+    join: String,
+    miter_limit: f64,
+    #[serde(default)]
+    dash: Vec<f64>,
+    #[serde(default)]
+    dash_offset: f64,
+}
+
+impl LineStyle {
+    fn stroke(&self) -> Stroke {
+        let cap = match self.cap.as_str() {
+            "round" => Cap::Round,
+            "square" => Cap::Square,
+            _ => Cap::Butt,
+        };
+        let join = match self.join.as_str() {
+            "round" => Join::Round,
+            "bevel" => Join::Bevel,
+            _ => Join::Miter,
+        };
+        let stroke = Stroke::new(self.width)
+            .with_caps(cap)
+            .with_join(join)
+            .with_miter_limit(self.miter_limit);
+        if self.dash.is_empty() {
+            stroke
+        } else {
+            stroke.with_dashes(self.dash_offset, self.dash.iter().copied())
+        }
+    }
+}
+
+/// A `fillStyle` or `strokeStyle`. Gradient stops are `[offset, color]`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum CanvasPaint {
+    Color {
+        color: String,
+    },
+    /// `[x0, y0, x1, y1]`.
+    Linear {
+        points: [f64; 4],
+        stops: Vec<(f32, String)>,
+    },
+    /// `[x0, y0, r0, x1, y1, r1]`.
+    Radial {
+        points: [f64; 6],
+        stops: Vec<(f32, String)>,
+    },
+    /// `[startAngle, x, y]`.
+    Conic {
+        points: [f64; 3],
+        stops: Vec<(f32, String)>,
+    },
+    Pattern {
+        source: ImageSource,
+        repetition: String,
+        transform: [f64; 6],
+    },
+}
+
+/// What `drawImage()`, `createPattern()` and `createImageBitmap()` read pixels from: a bitmap
+/// in the store, which a canvas context flushes under or an `ImageBitmap` holds, or a `data:`
+/// image.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum ImageSource {
+    Canvas { key: String },
+    Url { url: String },
+}
+
+type Sources<'a> = HashMap<&'a ImageSource, ImageData>;
+
+struct CanvasBrush {
+    brush: Brush,
+    /// From user space to the brush's, for patterns.
+    transform: Option<Affine>,
+    /// What a pattern that does not repeat both ways covers, in its own space.
+    bounds: Option<Rect>,
+}
+
+fn gradient_brush(gradient: Gradient, stops: &[(f32, String)]) -> Option<Brush> {
+    let stops: Vec<(f32, AlphaColor<Srgb>)> = stops
+        .iter()
+        .filter_map(|(offset, color)| Some((*offset, css_color(color)?)))
+        .collect();
+    // A gradient without stops paints nothing.
+    (!stops.is_empty()).then(|| Brush::Gradient(gradient.with_stops(stops.as_slice())))
+}
+
+fn canvas_brush(paint: &CanvasPaint, sources: &Sources<'_>) -> Option<CanvasBrush> {
+    let brush = match paint {
+        CanvasPaint::Color { color } => Brush::Solid(css_color(color)?),
+        CanvasPaint::Linear {
+            points: [x0, y0, x1, y1],
+            stops,
+        } => gradient_brush(Gradient::new_linear((*x0, *y0), (*x1, *y1)), stops)?,
+        CanvasPaint::Radial {
+            points: [x0, y0, r0, x1, y1, r1],
+            stops,
+        } => gradient_brush(
+            Gradient::new_two_point_radial((*x0, *y0), *r0 as f32, (*x1, *y1), *r1 as f32),
+            stops,
+        )?,
+        CanvasPaint::Conic {
+            points: [angle, x, y],
+            stops,
+        } => gradient_brush(
+            Gradient::new_sweep((*x, *y), *angle as f32, (*angle + TAU) as f32),
+            stops,
+        )?,
+        CanvasPaint::Pattern {
+            source,
+            repetition,
+            transform,
+        } => {
+            let image = sources.get(source)?.clone();
+            let (width, height) = (f64::from(image.width), f64::from(image.height));
+            let (x_extend, y_extend, bounds) = match repetition.as_str() {
+                "repeat-x" => (
+                    Extend::Repeat,
+                    Extend::Pad,
+                    Some(Rect::new(-UNBOUNDED, 0.0, UNBOUNDED, height)),
+                ),
+                "repeat-y" => (
+                    Extend::Pad,
+                    Extend::Repeat,
+                    Some(Rect::new(0.0, -UNBOUNDED, width, UNBOUNDED)),
+                ),
+                "no-repeat" => (
+                    Extend::Pad,
+                    Extend::Pad,
+                    Some(Rect::new(0.0, 0.0, width, height)),
+                ),
+                _ => (Extend::Repeat, Extend::Repeat, None),
+            };
+            let brush = ImageBrush::new(image)
+                .with_x_extend(x_extend)
+                .with_y_extend(y_extend);
+            return Some(CanvasBrush {
+                brush: Brush::Image(brush),
+                transform: Some(Affine::new(*transform)),
+                bounds,
+            });
+        }
+    };
+    Some(CanvasBrush {
+        brush,
+        transform: None,
+        bounds: None,
+    })
+}
+
+fn composite_mode(operation: &str) -> BlendMode {
+    let compose = match operation {
+        "source-in" => Compose::SrcIn,
+        "source-out" => Compose::SrcOut,
+        "source-atop" => Compose::SrcAtop,
+        "destination-over" => Compose::DestOver,
+        "destination-in" => Compose::DestIn,
+        "destination-out" => Compose::DestOut,
+        "destination-atop" => Compose::DestAtop,
+        "lighter" => Compose::Plus,
+        "copy" => Compose::Copy,
+        "xor" => Compose::Xor,
+        _ => Compose::SrcOver,
+    };
+    let mix = match operation {
+        "multiply" => Mix::Multiply,
+        "screen" => Mix::Screen,
+        "overlay" => Mix::Overlay,
+        "darken" => Mix::Darken,
+        "lighten" => Mix::Lighten,
+        "color-dodge" => Mix::ColorDodge,
+        "color-burn" => Mix::ColorBurn,
+        "hard-light" => Mix::HardLight,
+        "soft-light" => Mix::SoftLight,
+        "difference" => Mix::Difference,
+        "exclusion" => Mix::Exclusion,
+        "hue" => Mix::Hue,
+        "saturation" => Mix::Saturation,
+        "color" => Mix::Color,
+        "luminosity" => Mix::Luminosity,
+        _ => Mix::Normal,
+    };
+    BlendMode::new(mix, compose)
+}
+
+fn image_data(image: &RgbaImage) -> ImageData {
+    ImageData {
+        data: Blob::new(Arc::new(image.as_raw().clone())),
+        format: peniko::ImageFormat::Rgba8,
+        alpha_type: ImageAlphaType::Alpha,
+        width: image.width(),
+        height: image.height(),
+    }
+}
+
+/// The renderer hands back premultiplied pixels; bitmaps keep them straight, as
+/// `getImageData()` reads them.
+fn unpremultiply(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = u16::from(pixel[3]);
+        if alpha == 0 {
+            pixel.0 = [0; 4];
+        } else if alpha < 255 {
+            for channel in &mut pixel.0[..3] {
+                *channel = ((u16::from(*channel) * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+}
+
+fn decode_data_image(url: &str) -> Option<RgbaImage> {
+    let (metadata, payload) = url.strip_prefix("data:")?.split_once(',')?;
+    let bytes = if metadata.ends_with(";base64") {
+        BASE64.decode(payload.trim()).ok()?
+    } else {
+        percent_decode_str(payload).collect()
+    };
+    image::load_from_memory(&bytes)
+        .ok()
+        .map(|image| image.to_rgba8())
+}
+
+/// The `width`×`height` part of `image` from `origin`, transparent outside it.
+fn crop(image: &RgbaImage, [x, y]: [i64; 2], width: u32, height: u32) -> RgbaImage {
+    let mut part = RgbaImage::new(width, height);
+    for (column, row, pixel) in part.enumerate_pixels_mut() {
+        let (Ok(source_x), Ok(source_y)) = (
+            u32::try_from(x + i64::from(column)),
+            u32::try_from(y + i64::from(row)),
+        ) else {
+            continue;
+        };
+        if let Some(source) = image.get_pixel_checked(source_x, source_y) {
+            *pixel = *source;
+        }
+    }
+    part
+}
+
+/// Paint `ops` over `bitmap`.
+fn paint(bitmap: RgbaImage, ops: &[CanvasOp], sources: &Sources<'_>) -> RgbaImage {
+    let (width, height) = bitmap.dimensions();
+    let area = Rect::new(0.0, 0.0, f64::from(width), f64::from(height));
+    let previous =
+        Brush::Image(ImageBrush::new(image_data(&bitmap)).with_quality(ImageQuality::Low));
+    let pixels = render_to_buffer::<VelloCpuImageRenderer, _>(
+        |scene| {
+            scene.fill(Fill::NonZero, Affine::IDENTITY, &previous, None, &area);
+            for op in ops {
+                paint_op(scene, op, sources, area);
+            }
+        },
+        width,
+        height,
+    );
+    match RgbaImage::from_raw(width, height, pixels) {
+        Some(mut painted) => {
+            unpremultiply(&mut painted);
+            painted
+        }
+        None => bitmap,
+    }
+}
+
+fn paint_op<S: PaintScene>(scene: &mut S, op: &CanvasOp, sources: &Sources<'_>, area: Rect) {
+    let state = op.state();
+    let mut layers = 0;
+    for clip in &state.clips {
+        if let Ok(clip) = BezPath::from_svg(clip) {
+            scene.push_layer(BlendMode::default(), 1.0, Affine::IDENTITY, &clip);
+            layers += 1;
+        }
+    }
+    if let CanvasOp::Clear {
+        rect: [x, y, w, h], ..
+    } = op
+    {
+        let rect = Rect::from_origin_size((*x, *y), (*w, *h)).abs();
+        scene.push_layer(Compose::Clear, 1.0, state.transform(), &rect);
+        let clear = Brush::Solid(AlphaColor::TRANSPARENT);
+        scene.fill(Fill::NonZero, state.transform(), &clear, None, &rect);
+        scene.pop_layer();
+    } else {
+        let blend = composite_mode(&state.composite);
+        if state.alpha < 1.0 || blend != BlendMode::default() {
+            // Composited across the whole canvas, so `source-in` and the like also clear
+            // what the shape does not cover.
+            scene.push_layer(blend, state.alpha, Affine::IDENTITY, &area);
+            layers += 1;
+        }
+        draw(scene, op, sources);
+    }
+    for _ in 0..layers {
+        scene.pop_layer();
+    }
+}
+
+fn draw<S: PaintScene>(scene: &mut S, op: &CanvasOp, sources: &Sources<'_>) {
+    let transform = op.state().transform();
+    match op {
+        CanvasOp::Fill {
+            path,
+            even_odd,
+            paint,
+            ..
+        } => {
+            let Ok(path) = BezPath::from_svg(path) else {
+                return;
+            };
+            let rule = if *even_odd {
+                Fill::EvenOdd
+            } else {
+                Fill::NonZero
+            };
+            with_brush(scene, paint, sources, transform, |scene, brush| {
+                scene.fill(rule, transform, &brush.brush, brush.transform, &path);
+            });
+        }
+        CanvasOp::Stroke {
+            path, line, paint, ..
+        } => {
+            let Ok(path) = BezPath::from_svg(path) else {
+                return;
+            };
+            let stroke = line.stroke();
+            with_brush(scene, paint, sources, transform, |scene, brush| {
+                scene.stroke(&stroke, transform, &brush.brush, brush.transform, &path);
+            });
+        }
+        CanvasOp::Text {
+            text,
+            font,
+            x,
+            y,
+            align,
+            baseline,
+            max_width,
+            line,
+            paint,
+            ..
+        } => {
+            let font = CanvasFont::parse(font).unwrap_or_default();
+            let stroke = line.as_ref().map(LineStyle::stroke);
+            with_text_layout(text, &font, |layout| {
+                let measured = measure_layout(layout, &font);
+                let line_baseline = layout
+                    .lines()
+                    .next()
+                    .map_or(0.0, |line| f64::from(line.metrics().baseline));
+                let offset_x = match align.as_str() {
+                    "center" => -measured.width / 2.0,
+                    "right" => -measured.width,
+                    _ => 0.0,
+                };
+                let offset_y = match baseline.as_str() {
+                    "top" => measured.ascent,
+                    "hanging" => measured.ascent * 0.8,
+                    "middle" => (measured.ascent - measured.descent) / 2.0,
+                    "ideographic" | "bottom" => -measured.descent,
+                    _ => 0.0,
+                };
+                // Squeezed horizontally into `maxWidth`.
+                let squeeze = max_width
+                    .filter(|max| *max < measured.width)
+                    .map_or(1.0, |max| max / measured.width);
+                let text_transform = transform
+                    * Affine::translate((*x, *y))
+                    * Affine::scale_non_uniform(squeeze, 1.0)
+                    * Affine::translate((offset_x, offset_y - line_baseline));
+                with_brush(scene, paint, sources, transform, |scene, brush| {
+                    for line in layout.lines() {
+                        for item in line.items() {
+                            let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
+                                continue;
+                            };
+                            let run = glyph_run.run();
+                            let glyphs = glyph_run.positioned_glyphs().map(|glyph| Glyph {
+                                id: glyph.id as _,
+                                x: glyph.x,
+                                y: glyph.y,
+                            });
+                            let font = run.font();
+                            let coords = run.normalized_coords();
+                            let size = run.font_size();
+                            match &stroke {
+                                Some(stroke) => scene.draw_glyphs(
+                                    font,
+                                    size,
+                                    false,
+                                    coords,
+                                    stroke,
+                                    &brush.brush,
+                                    1.0,
+                                    text_transform,
+                                    None,
+                                    glyphs,
+                                ),
+                                None => scene.draw_glyphs(
+                                    font,
+                                    size,
+                                    false,
+                                    coords,
+                                    Fill::NonZero,
+                                    &brush.brush,
+                                    1.0,
+                                    text_transform,
+                                    None,
+                                    glyphs,
+                                ),
+                            }
+                        }
+                    }
+                });
+            });
+        }
+        CanvasOp::Image {
+            source,
+            from: [sx, sy, sw, sh],
+            to: [dx, dy, dw, dh],
+            state,
+        } => {
+            let Some(image) = sources.get(source) else {
+                return;
+            };
+            if *sw == 0.0 || *sh == 0.0 {
+                return;
+            }
+            let quality = if state.smoothing {
+                ImageQuality::Medium
+            } else {
+                ImageQuality::Low
+            };
+            let brush = Brush::Image(ImageBrush::new(image.clone()).with_quality(quality));
+            let placement = Affine::translate((*dx, *dy))
+                * Affine::scale_non_uniform(dw / sw, dh / sh)
+                * Affine::translate((-sx, -sy));
+            let rect = Rect::from_origin_size((*dx, *dy), (*dw, *dh)).abs();
+            scene.fill(Fill::NonZero, transform, &brush, Some(placement), &rect);
+        }
+        CanvasOp::Clear { .. } => {}
+    }
+}
+
+/// Draw with `paint`, clipped to what a pattern that does not repeat both ways covers.
+fn with_brush<S: PaintScene>(
+    scene: &mut S,
+    paint: &CanvasPaint,
+    sources: &Sources<'_>,
+    transform: Affine,
+    draw: impl FnOnce(&mut S, &CanvasBrush),
+) {
+    let Some(brush) = canvas_brush(paint, sources) else {
+        return;
+    };
+    let bounds = brush.bounds.zip(brush.transform);
+    if let Some((bounds, pattern)) = bounds {
+        scene.push_layer(BlendMode::default(), 1.0, transform * pattern, &bounds);
+    }
+    draw(scene, &brush);
+    if bounds.is_some() {
+        scene.pop_layer();
+    }
+}
+
+/// Whether `(x, y)` is inside `path`, SVG path data, by the nonzero or even-odd rule.
+pub(crate) fn point_in_path(path: &str, x: f64, y: f64, even_odd: bool) -> bool {
+    let Ok(path) = BezPath::from_svg(path) else {
+        return false;
+    };
+    let winding = path.winding(Point::new(x, y));
+    if even_odd {
+        winding % 2 != 0
+    } else {
+        winding != 0
+    }
+}
+
+/// Whether `(x, y)` is on the outline of `path` that stroking it with `line` paints.
+pub(crate) fn point_in_stroke(path: &str, line: &LineStyle, x: f64, y: f64) -> bool {
+    let Ok(path) = BezPath::from_svg(path) else {
+        return false;
+    };
+    kurbo::stroke(path.iter(), &line.stroke(), &StrokeOpts::default(), 0.1)
+        .contains(Point::new(x, y))
+}
+
+/// Bitmaps and decoded images, each charged to the page's memory budget while it is kept.
+#[derive(Default)]
+struct Canvases {
+    bitmaps: HashMap<String, RgbaImage>,
+    /// `data:` images decoded for `drawImage()`, by URL; `None` for those that do not decode
+    /// or do not fit the budget.
+    images: HashMap<String, Option<Rc<RgbaImage>>>,
+    /// The budget, once the page's runtime is isolated.
+    memory: Option<NativeMemory<'static>>,
+}
+
+impl Canvases {
+    fn decode(&mut self, url: &str) -> Option<Rc<RgbaImage>> {
+        if let Some(image) = self.images.get(url) {
+            return image.clone();
+        }
+        let image = decode_data_image(url)
+            .filter(|image| self.charge(image.dimensions()))
+            .map(Rc::new);
+        self.images.insert(url.to_string(), image.clone());
+        image
+    }
+
+    /// Charge a `width`×`height` bitmap about to be kept. Returns false, charging nothing,
+    /// when it is too large or the budget has no room for it.
+    fn charge(&self, (width, height): (u32, u32)) -> bool {
+        let bytes = width as usize * height as usize * 4;
+        fits(width, height)
+            && self
+                .memory
+                .as_ref()
+                .map_or(true, |memory| memory.charge(bytes))
+    }
+
+    /// Bytes a new bitmap may take: what the budget has left, and never more than the
+    /// largest bitmap.
+    fn available(&self) -> u64 {
+        let largest = MAX_AREA * 4;
+        self.memory
+            .as_ref()
+            .map_or(largest, |memory| memory.available() as u64)
+            .min(largest)
+    }
+
+    fn uncharge(&self, image: &RgbaImage) {
+        if let Some(memory) = &self.memory {
+            memory.release(image.as_raw().len());
+        }
+    }
+
+    /// Keep `bitmap`, already charged, as `key`.
+    fn insert(&mut self, key: &str, bitmap: RgbaImage) {
+        if let Some(old) = self.bitmaps.insert(key.to_string(), bitmap) {
+            self.uncharge(&old);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.bitmaps.remove(key) {
+            self.uncharge(&old);
+        }
+    }
+}
+
+/// The bitmaps of the page's canvases, by the key their context flushes under.
+#[derive(Clone, Default)]
+pub(crate) struct CanvasStore {
+    inner: Rc<RefCell<Canvases>>,
+}
+
+impl CanvasStore {
+    /// Charge what the page's canvases keep to `memory`. The page is isolated before its
+    /// scripts run, so nothing is kept yet.
+    pub(crate) fn set_memory(&self, memory: NativeMemory<'static>) {
+        self.inner.borrow_mut().memory = Some(memory);
+    }
+
+    /// Paint `batch` over the bitmap of `key`.
+    pub(crate) fn draw(&self, key: &str, batch: &CanvasBatch) {
+        let mut inner = self.inner.borrow_mut();
+        let (width, height) = (batch.width, batch.height);
+        let mut bitmap = match inner.bitmaps.remove(key) {
+            Some(bitmap) if !batch.reset && bitmap.dimensions() == (width, height) => bitmap,
+            old => {
+                if let Some(old) = old {
+                    inner.uncharge(&old);
+                }
+                if !inner.charge((width, height)) {
+                    tracing::warn!(
+                        target = "quickjs",
+                        width,
+                        height,
+                        "canvas is too large to draw on"
+                    );
+                    return;
+                }
+                RgbaImage::new(width, height)
+            }
+        };
+        if !batch.ops.is_empty() && width > 0 && height > 0 {
+            let mut sources = Sources::new();
+            for source in batch.ops.iter().flat_map(CanvasOp::sources) {
+                let image = match source {
+                    ImageSource::Canvas { key: source_key } if source_key == key => {
+                        Some(image_data(&bitmap))
+                    }
+                    ImageSource::Canvas { key: source_key } => {
+                        inner.bitmaps.get(source_key).map(image_data)
+                    }
+                    ImageSource::Url { url } => inner.decode(url).map(|image| image_data(&image)),
+                };
+                if let Some(image) = image {
+                    sources.insert(source, image);
+                }
+            }
+            bitmap = paint(bitmap, &batch.ops, &sources);
+        }
+        inner.bitmaps.insert(key.to_string(), bitmap);
+    }
+
+    /// The size of the `data:` image at `url`, for `drawImage()` to default to.
+    pub(crate) fn image_size(&self, url: &str) -> Option<(u32, u32)> {
+        self.inner
+            .borrow_mut()
+            .decode(url)
+            .map(|image| image.dimensions())
+    }
+
+    /// `width`×`height` straight RGBA pixels of `key`'s bitmap from `(x, y)`, transparent
+    /// outside it, or `None` when that many pixels are too many.
+    pub(crate) fn pixels(
+        &self,
+        key: &str,
+        x: i64,
+        y: i64,
+        width: u32,
+        height: u32,
+    ) -> Option<Vec<u8>> {
+        if !fits(width, height) {
+            return None;
+        }
+        Some(match self.inner.borrow().bitmaps.get(key) {
+            Some(bitmap) => crop(bitmap, [x, y], width, height).into_raw(),
+            None => vec![0; width as usize * height as usize * 4],
+        })
+    }
+
+    /// `createImageBitmap()`: copy `source`, a `width`×`height` image, into a new bitmap
+    /// `key`, cut to `area` (`[x, y, w, h]`) when there is one. Returns the copy's size, or
+    /// `None` when an image does not decode or the copy would be too large.
+    pub(crate) fn snapshot(
+        &self,
+        key: &str,
+        source: &ImageSource,
+        (width, height): (u32, u32),
+        area: Option<[i64; 4]>,
+    ) -> Option<(u32, u32)> {
+        if !fits(width, height) {
+            return None;
+        }
+        if let Some([_, _, w, h]) = area {
+            let (Ok(w), Ok(h)) = (u32::try_from(w), u32::try_from(h)) else {
+                return None;
+            };
+            if !fits(w, h) {
+                return None;
+            }
+        }
+        let mut inner = self.inner.borrow_mut();
+        let image = match source {
+            // A canvas nothing was drawn on yet is transparent.
+            ImageSource::Canvas { key: source_key } => inner
+                .bitmaps
+                .get(source_key)
+                .cloned()
+                .map(Rc::new)
+                .unwrap_or_else(|| Rc::new(RgbaImage::new(width, height))),
+            ImageSource::Url { url } => inner.decode(url)?,
+        };
+        let copy = match area {
+            Some([x, y, w, h]) => crop(&image, [x, y], w as u32, h as u32),
+            None => RgbaImage::clone(&image),
+        };
+        let size = copy.dimensions();
+        if !inner.charge(size) {
+            return None;
+        }
+        inner.insert(key, copy);
+        Some(size)
+    }
+
+    /// `createImageBitmap()` of a blob: decode `bytes` into a new bitmap `key`. Returns its
+    /// size, or `None` when the bytes are not an image or it is too large.
+    pub(crate) fn decode(&self, key: &str, bytes: &[u8]) -> Option<(u32, u32)> {
+        // A small file can inflate to a huge bitmap, so the decoder is held to what the
+        // budget has left rather than charged afterwards.
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_SIDE);
+        limits.max_image_height = Some(MAX_SIDE);
+        limits.max_alloc = Some(self.inner.borrow().available());
+        let mut reader = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .ok()?;
+        reader.limits(limits);
+        let image = reader.decode().ok()?.into_rgba8();
+        let size = image.dimensions();
+        let mut inner = self.inner.borrow_mut();
+        if !inner.charge(size) {
+            return None;
+        }
+        inner.insert(key, image);
+        Some(size)
+    }
+
+    /// `putImageData()`: write the `dirty` part, `[x, y, w, h]`, of `data`, an RGBA image
+    /// `data_width` pixels wide, onto `key`'s `width`×`height` bitmap with its origin at
+    /// `(x, y)`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn put_pixels(
+        &self,
+        key: &str,
+        (width, height): (u32, u32),
+        data: &[u8],
+        data_width: u32,
+        (x, y): (i64, i64),
+        dirty: [u32; 4],
+    ) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.bitmaps.get(key).map(RgbaImage::dimensions) != Some((width, height)) {
+            inner.remove(key);
+            if !inner.charge((width, height)) {
+                return;
+            }
+            inner.insert(key, RgbaImage::new(width, height));
+        }
+        let Some(bitmap) = inner.bitmaps.get_mut(key) else {
+            return;
+        };
+        let [dirty_x, dirty_y, dirty_width, dirty_height] = dirty;
+        if data_width == 0 {
+            return;
+        }
+        let data_height = u32::try_from(data.len() / 4 / data_width as usize).unwrap_or(u32::MAX);
+        // Script picks every number here, so walk only the pixels in both images.
+        let columns = span(dirty_x, dirty_width, data_width, x, width);
+        let rows = span(dirty_y, dirty_height, data_height, y, height);
+        for row in rows {
+            for column in columns.clone() {
+                let offset = (row as usize * data_width as usize + column as usize) * 4;
+                let Some(pixel) = data.get(offset..offset + 4) else {
+                    continue;
+                };
+                // `span` keeps these inside the bitmap.
+                let (target_x, target_y) = ((x + column) as u32, (y + row) as u32);
+                bitmap
+                    .get_pixel_mut(target_x, target_y)
+                    .0
+                    .copy_from_slice(pixel);
+            }
+        }
+    }
+
+    /// `key`'s bitmap as a PNG to show on the page, with its size, unless it is empty. It
+    /// is encoded for speed over size, since a canvas that animates is encoded every frame.
+    pub(crate) fn png(&self, key: &str) -> Result<Option<(Vec<u8>, u32, u32)>> {
+        let inner = self.inner.borrow();
+        let Some(bitmap) = inner.bitmaps.get(key) else {
+            return Ok(None);
+        };
+        let (width, height) = bitmap.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(None);
+        }
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::NoFilter)
+            .write_image(bitmap.as_raw(), width, height, ExtendedColorType::Rgba8)?;
+        Ok(Some((png, width, height)))
+    }
+
+    /// The PNG `toBlob()` exports for `key`'s `width`×`height` canvas, which is transparent
+    /// where nothing was drawn, or `None` for a canvas with no pixels or too many.
+    pub(crate) fn export(&self, key: &str, width: u32, height: u32) -> Result<Option<Vec<u8>>> {
+        if width == 0 || height == 0 || !fits(width, height) {
+            return Ok(None);
+        }
+        match self.inner.borrow().bitmaps.get(key) {
+            Some(bitmap) if bitmap.width() > 0 && bitmap.height() > 0 => {
+                encode_png(bitmap).map(Some)
+            }
+            _ => encode_png(&RgbaImage::new(width, height)).map(Some),
+        }
+    }
+
+    /// `toDataURL()` for `key`'s `width`×`height` canvas.
+    pub(crate) fn data_url(&self, key: &str, width: u32, height: u32) -> Result<String> {
+        Ok(match self.export(key, width, height)? {
+            Some(png) => format!("data:image/png;base64,{}", BASE64.encode(png)),
+            None => "data:,".to_string(),
+        })
+    }
+
+    pub(crate) fn release(&self, key: &str) {
+        self.inner.borrow_mut().remove(key);
+    }
+
+    /// Drop every bitmap when the page goes away.
+    pub(crate) fn clear(&self) {
+        let mut inner = self.inner.borrow_mut();
+        for bitmap in std::mem::take(&mut inner.bitmaps).into_values() {
+            inner.uncharge(&bitmap);
+        }
+        for image in std::mem::take(&mut inner.images).into_values().flatten() {
+            inner.uncharge(&image);
+        }
+    }
+}
+
+pub(crate) fn install_canvas_bindings<'js>(
+    ctx: &Ctx<'js>,
+    store: &CanvasStore,
+) -> rquickjs::Result<()> {
+    let global = ctx.globals();
+    let func = Function::new(ctx.clone(), |font: String| {
+        CanvasFont::parse(&font).map(|font| font.to_css())
+    })?
+    .with_name("__frontier_canvas_parse_font")?;
+    global.set("__frontier_canvas_parse_font", func)?;
+
+    let func = Function::new(ctx.clone(), |text: String, font: String| {
+        let font = CanvasFont::parse(&font).unwrap_or_default();
+        let measured = measure_text(&text, &font);
+        vec![measured.width, measured.ascent, measured.descent]
+    })?
+    .with_name("__frontier_canvas_measure_text")?;
+    global.set("__frontier_canvas_measure_text", func)?;
+
+    let func = Function::new(ctx.clone(), |color: String| normalize_color(&color))?
+        .with_name("__frontier_canvas_parse_color")?;
+    global.set("__frontier_canvas_parse_color", func)?;
+
+    // `new Path2D(d)`: SVG path data with arcs and relative commands resolved, or nothing
+    // for data that does not parse.
+    let func = Function::new(ctx.clone(), |path: String| {
+        BezPath::from_svg(&path)
+            .map(|path| path.to_svg())
+            .unwrap_or_default()
+    })?
+    .with_name("__frontier_canvas_parse_path")?;
+    global.set("__frontier_canvas_parse_path", func)?;
+
+    let func = Function::new(
+        ctx.clone(),
+        |path: String, x: f64, y: f64, even_odd: bool| point_in_path(&path, x, y, even_odd),
+    )?
+    .with_name("__frontier_canvas_point_in_path")?;
+    global.set("__frontier_canvas_point_in_path", func)?;
+
+    let func = Function::new(ctx.clone(), |path: String, line: String, x: f64, y: f64| {
+        serde_json::from_str::<LineStyle>(&line)
+            .is_ok_and(|line| point_in_stroke(&path, &line, x, y))
+    })?
+    .with_name("__frontier_canvas_point_in_stroke")?;
+    global.set("__frontier_canvas_point_in_stroke", func)?;
+
+    let canvases = store.clone();
+    let func =
+        Function::new(
+            ctx.clone(),
+            move |key: String, batch: String| match serde_json::from_str::<CanvasBatch>(&batch) {
+                Ok(batch) => canvases.draw(&key, &batch),
+                Err(err) => tracing::warn!(target = "quickjs", "malformed canvas batch: {err}"),
+            },
+        )?
+        .with_name("__frontier_canvas_draw")?;
+    global.set("__frontier_canvas_draw", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(ctx.clone(), move |url: String| {
+        canvases
+            .image_size(&url)
+            .map(|(width, height)| vec![width, height])
+    })?
+    .with_name("__frontier_canvas_image_size")?;
+    global.set("__frontier_canvas_image_size", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, key: String, x: f64, y: f64, width: u32, height: u32| {
+            canvases
+                .pixels(&key, x as i64, y as i64, width, height)
+                .map(|pixels| ArrayBuffer::new(ctx, pixels))
+                .transpose()
+        },
+    )?
+    .with_name("__frontier_canvas_get_pixels")?;
+    global.set("__frontier_canvas_get_pixels", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |key: String,
+              size: Vec<u32>,
+              data: ArrayBuffer<'js>,
+              data_width: u32,
+              origin: Vec<f64>,
+              dirty: Vec<u32>| {
+            let (&[width, height], &[x, y], &[dirty_x, dirty_y, dirty_width, dirty_height]) =
+                (size.as_slice(), origin.as_slice(), dirty.as_slice())
+            else {
+                return;
+            };
+            canvases.put_pixels(
+                &key,
+                (width, height),
+                data.as_bytes().unwrap_or_default(),
+                data_width,
+                (x as i64, y as i64),
+                [dirty_x, dirty_y, dirty_width, dirty_height],
+            );
+        },
+    )?
+    .with_name("__frontier_canvas_put_pixels")?;
+    global.set("__frontier_canvas_put_pixels", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |key: String, width: u32, height: u32| -> rquickjs::Result<String> {
+            canvases.data_url(&key, width, height).or_else(|err| {
+                tracing::warn!(target = "quickjs", "canvas toDataURL failed: {err}");
+                Ok("data:,".to_string())
+            })
+        },
+    )?
+    .with_name("__frontier_canvas_data_url")?;
+    global.set("__frontier_canvas_data_url", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |ctx: Ctx<'js>, key: String, width: u32, height: u32| match canvases
+            .export(&key, width, height)
+        {
+            Ok(png) => png.map(|png| ArrayBuffer::new(ctx, png)).transpose(),
+            Err(err) => {
+                tracing::warn!(target = "quickjs", "canvas toBlob failed: {err}");
+                Ok(None)
+            }
+        },
+    )?
+    .with_name("__frontier_canvas_png")?;
+    global.set("__frontier_canvas_png", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(
+        ctx.clone(),
+        move |key: String, source: String, size: Vec<u32>, area: Option<Vec<f64>>| {
+            let source = serde_json::from_str::<ImageSource>(&source).ok()?;
+            let &[width, height] = size.as_slice() else {
+                return None;
+            };
+            let area = match area.as_deref() {
+                Some(&[x, y, w, h]) => Some([x as i64, y as i64, w as i64, h as i64]),
+                _ => None,
+            };
+            canvases
+                .snapshot(&key, &source, (width, height), area)
+                .map(|(width, height)| vec![width, height])
+        },
+    )?
+    .with_name("__frontier_canvas_snapshot")?;
+    global.set("__frontier_canvas_snapshot", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(ctx.clone(), move |key: String, bytes: ArrayBuffer<'js>| {
+        canvases
+            .decode(&key, bytes.as_bytes().unwrap_or_default())
+            .map(|(width, height)| vec![width, height])
+    })?
+    .with_name("__frontier_canvas_decode")?;
+    global.set("__frontier_canvas_decode", func)?;
+
+    let canvases = store.clone();
+    let func = Function::new(ctx.clone(), move |key: String| canvases.release(&key))?
+        .with_name("__frontier_canvas_release")?;
+    global.set("__frontier_canvas_release", func)?;
+    Ok(())
+}
+
+/// JS half: `HTMLCanvasElement`'s `width`, `height`, `getContext('2d')`, `toDataURL()`,
+/// `toBlob()` and `transferControlToOffscreen()`, `CanvasRenderingContext2D`, `Path2D`,
+/// `CanvasGradient`, `CanvasPattern`, `ImageData`, `TextMetrics`, `OffscreenCanvas`,
+/// `ImageBitmap` and `createImageBitmap()`. Each canvas has one context; other context types
+/// give `null`, WebGL ones after a `webglcontextcreationerror` event. A context keeps its
+/// current path in canvas pixels, as the spec transforms points when they are added, and
+/// flushes what it drew to `__frontier_canvas_draw` in a microtask.
+pub(crate) const CANVAS_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const canvasDataUrl = global.__frontier_canvas_data_url;
+    const canvasDecode = global.__frontier_canvas_decode;
+    const canvasDraw = global.__frontier_canvas_draw;
+    const canvasGetPixels = global.__frontier_canvas_get_pixels;
+    const canvasImageSize = global.__frontier_canvas_image_size;
+    const canvasMeasureText = global.__frontier_canvas_measure_text;
+    const canvasParseColor = global.__frontier_canvas_parse_color;
+    const canvasParseFont = global.__frontier_canvas_parse_font;
+    const canvasParsePath = global.__frontier_canvas_parse_path;
+    const canvasPng = global.__frontier_canvas_png;
+    const canvasPointInPath = global.__frontier_canvas_point_in_path;
+    const canvasPointInStroke = global.__frontier_canvas_point_in_stroke;
+    const canvasPutPixels = global.__frontier_canvas_put_pixels;
+    const canvasRelease = global.__frontier_canvas_release;
+    const canvasSnapshot = global.__frontier_canvas_snapshot;
+    const domSetCanvasImage = global.__frontier_dom_set_canvas_image;
+    const frontier = global.frontier;
+    const CONTEXTS = new WeakMap();
+    const ALIGNS = new Set(['start', 'end', 'left', 'right', 'center']);
+    const BASELINES = new Set(['top', 'hanging', 'middle', 'alphabetic', 'ideographic', 'bottom']);
+    const DIRECTIONS = new Set(['ltr', 'rtl', 'inherit']);
+    const CAPS = new Set(['butt', 'round', 'square']);
+    const JOINS = new Set(['round', 'bevel', 'miter']);
+    const QUALITIES = new Set(['low', 'medium', 'high']);
+    const REPETITIONS = new Set(['repeat', 'repeat-x', 'repeat-y', 'no-repeat']);
+    const WEBGL_CONTEXTS = new Set(['webgl', 'experimental-webgl', 'webgl2']);
+    const COMPOSITES = new Set([
+        'source-over', 'source-in', 'source-out', 'source-atop',
+        'destination-over', 'destination-in', 'destination-out', 'destination-atop',
+        'lighter', 'copy', 'xor', 'multiply', 'screen', 'overlay', 'darken', 'lighten',
+        'color-dodge', 'color-burn', 'hard-light', 'soft-light', 'difference', 'exclusion',
+        'hue', 'saturation', 'color', 'luminosity',
+    ]);
+    const IDENTITY = [1, 0, 0, 1, 0, 0];
+    const TAU = Math.PI * 2;
+
+    const finite = (...values) => values.every((value) => Number.isFinite(value));
+
+    const domError = (message, name) =>
+        typeof global.DOMException === 'function'
+            ? new global.DOMException(message, name)
+            : new Error(message);
+
+    // Matrices are `[a, b, c, d, e, f]`; `multiply(m, n)` applies `n` first.
+    const multiply = (m, n) => [
+        m[0] * n[0] + m[2] * n[1],
+        m[1] * n[0] + m[3] * n[1],
+        m[0] * n[2] + m[2] * n[3],
+        m[1] * n[2] + m[3] * n[3],
+        m[0] * n[4] + m[2] * n[5] + m[4],
+        m[1] * n[4] + m[3] * n[5] + m[5],
+    ];
+
+    const apply = (m, x, y) => [m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5]];
+
+    const invert = (m) => {
+        const det = m[0] * m[3] - m[1] * m[2];
+        if (!Number.isFinite(det) || det === 0) {
+            return null;
+        }
+        return [
+            m[3] / det,
+            -m[1] / det,
+            -m[2] / det,
+            m[0] / det,
+            (m[2] * m[5] - m[3] * m[4]) / det,
+            (m[1] * m[4] - m[0] * m[5]) / det,
+        ];
+    };
+
+    // A `DOMMatrix2DInit`, or `null` when it is not finite.
+    const matrixFrom = (init) => {
+        const pick = (short, long, fallback) => {
+            const value = init[short] ?? init[long];
+            return value === undefined ? fallback : Number(value);
+        };
+        const matrix = [
+            pick('a', 'm11', 1),
+            pick('b', 'm12', 0),
+            pick('c', 'm21', 0),
+            pick('d', 'm22', 1),
+            pick('e', 'm41', 0),
+            pick('f', 'm42', 0),
+        ];
+        return finite(...matrix) ? matrix : null;
+    };
+
+    // `width` and `height` are unsigned longs.
+    const MAX_DIMENSION = 0xffffffff;
+
+    // The rules for parsing non-negative integers, as the `width` and `height` attributes
+    // are read.
+    const dimension = (canvas, name, fallback) => {
+        const value = canvas.getAttribute(name);
+        const match = value === null ? null : /^[\t\n\f\r ]*\+?(\d+)/.exec(value);
+        return match && Number(match[1]) <= MAX_DIMENSION ? Number(match[1]) : fallback;
+    };
+
+    const isCanvas = (node) =>
+        node && node.nodeType === 1 && String(node.localName || '') === 'canvas';
+
+    const canvasSize = (canvas) => [
+        dimension(canvas, 'width', 300),
+        dimension(canvas, 'height', 150),
+    ];
+
+    const sizeOf = (canvas) =>
+        canvas instanceof OffscreenCanvas ? [canvas._width, canvas._height] : canvasSize(canvas);
+
+    const toDimension = (value) =>
+        Math.min(Math.max(Math.trunc(Number(value)) || 0, 0), MAX_DIMENSION);
+
+    // Pixels are only allocated for bitmaps of at most this size; see `fits` in Rust.
+    const MAX_SIDE = 32767;
+    const MAX_AREA = 268435456;
+
+    const fitsSize = (width, height) =>
+        width <= MAX_SIDE && height <= MAX_SIDE && width * height <= MAX_AREA;
+
+    const tooLarge = (width, height, what) =>
+        new RangeError(`${what}: A ${width}x${height} image is too large.`);
+
+    const checkSize = (width, height, what) => {
+        if (!fitsSize(width, height)) {
+            throw tooLarge(width, height, what);
+        }
+    };
+
+    // Offscreen canvases and image bitmaps keep their pixels in the store under keys of their
+    // own, until they are closed or collected.
+    const registry =
+        typeof global.FinalizationRegistry === 'function'
+            ? new global.FinalizationRegistry((key) => canvasRelease(key))
+            : null;
+    let lastKey = 0;
+    const storeKey = (kind) => {
+        lastKey += 1;
+        return `${kind}:${lastKey}`;
+    };
+
+    // The offscreen canvas each `<canvas>` handed its drawing to.
+    const TRANSFERRED = new WeakMap();
+
+    // Segments are `[command, ...points]`, the points already in the path's space: canvas
+    // pixels for a context's current path, user space for a `Path2D`. `matrix` gives the
+    // transform points are added under.
+    class PathData {
+        constructor(matrix) {
+            this._matrix = matrix;
+            this._segments = [];
+            this._start = null;
+            this._current = null;
+        }
+
+        _map(x, y) {
+            return apply(this._matrix(), x, y);
+        }
+
+        _push(command, ...points) {
+            const mapped = [];
+            for (let i = 0; i < points.length; i += 2) {
+                mapped.push(...this._map(points[i], points[i + 1]));
+            }
+            this._segments.push([command, ...mapped]);
+            if (mapped.length) {
+                this._current = mapped.slice(-2);
+            }
+        }
+
+        moveTo(x, y) {
+            [x, y] = [Number(x), Number(y)];
+            if (!finite(x, y)) {
+                return;
+            }
+            this._push('M', x, y);
+            this._start = this._current;
+        }
+
+        lineTo(x, y) {
+            [x, y] = [Number(x), Number(y)];
+            if (!finite(x, y)) {
+                return;
+            }
+            if (this._current === null) {
+                this.moveTo(x, y);
+                return;
+            }
+            this._push('L', x, y);
+        }
+
+        quadraticCurveTo(cx, cy, x, y) {
+            const values = [cx, cy, x, y].map(Number);
+            if (!finite(...values)) {
+                return;
+            }
+            if (this._current === null) {
+                this.moveTo(values[0], values[1]);
+            }
+            this._push('Q', ...values);
+        }
+
+        bezierCurveTo(c1x, c1y, c2x, c2y, x, y) {
+            const values = [c1x, c1y, c2x, c2y, x, y].map(Number);
+            if (!finite(...values)) {
+                return;
+            }
+            if (this._current === null) {
+                this.moveTo(values[0], values[1]);
+            }
+            this._push('C', ...values);
+        }
+
+        closePath() {
+            if (this._current === null) {
+                return;
+            }
+            // Anything drawn next starts a subpath where this one started.
+            this._segments.push(['Z'], ['M', ...this._start]);
+            this._current = this._start;
+        }
+
+        rect(x, y, w, h) {
+            [x, y, w, h] = [x, y, w, h].map(Number);
+            if (!finite(x, y, w, h)) {
+                return;
+            }
+            this.moveTo(x, y);
+            this.lineTo(x + w, y);
+            this.lineTo(x + w, y + h);
+            this.lineTo(x, y + h);
+            this.closePath();
+        }
+
+        roundRect(x, y, w, h, radii = 0) {
+            [x, y, w, h] = [x, y, w, h].map(Number);
+            if (!finite(x, y, w, h)) {
+                return;
+            }
+            const list = typeof radii === 'object' && radii !== null && Symbol.iterator in radii
+                ? Array.from(radii)
+                : [radii];
+            if (list.length < 1 || list.length > 4) {
+                throw new RangeError(
+                    `Failed to execute 'roundRect': ${list.length} radii provided. ` +
+                        'Between one and four radii are necessary.',
+                );
+            }
+            // Elliptical `DOMPointInit` corners are drawn circular, with their `x`.
+            const values = list.map((radius) =>
+                typeof radius === 'object' && radius !== null
+                    ? Number(radius.x ?? 0)
+                    : Number(radius),
+            );
+            if (!finite(...values)) {
+                return;
+            }
+            if (values.some((radius) => radius < 0)) {
+                throw new RangeError("Failed to execute 'roundRect': Radius value is negative.");
+            }
+            const [a, b = a, c = a, d = b] = values;
+            let [topLeft, topRight, bottomRight, bottomLeft] = [a, b, c, d];
+            if (w < 0) {
+                x += w;
+                w = -w;
+                [topLeft, topRight, bottomRight, bottomLeft] =
+                    [topRight, topLeft, bottomLeft, bottomRight];
+            }
+            if (h < 0) {
+                y += h;
+                h = -h;
+                [topLeft, topRight, bottomRight, bottomLeft] =
+                    [bottomLeft, bottomRight, topRight, topLeft];
+            }
+            const scale = Math.min(
+                1,
+                w / (topLeft + topRight) || 1,
+                w / (bottomLeft + bottomRight) || 1,
+                h / (topLeft + bottomLeft) || 1,
+                h / (topRight + bottomRight) || 1,
+            );
+            [topLeft, topRight, bottomRight, bottomLeft] =
+                [topLeft, topRight, bottomRight, bottomLeft].map((radius) => radius * scale);
+            const quarter = Math.PI / 2;
+            this.moveTo(x + topLeft, y);
+            this._ellipse(
+                x + w - topRight, y + topRight, topRight, topRight, 0, -quarter, 0, false,
+            );
+            this._ellipse(
+                x + w - bottomRight, y + h - bottomRight, bottomRight, bottomRight,
+                0, 0, quarter, false,
+            );
+            this._ellipse(
+                x + bottomLeft, y + h - bottomLeft, bottomLeft, bottomLeft,
+                0, quarter, Math.PI, false,
+            );
+            this._ellipse(
+                x + topLeft, y + topLeft, topLeft, topLeft, 0, Math.PI, 3 * quarter, false,
+            );
+            this.closePath();
+        }
+
+        arc(x, y, radius, start, end, anticlockwise = false) {
+            const values = [x, y, radius, start, end].map(Number);
+            if (!finite(...values)) {
+                return;
+            }
+            if (values[2] < 0) {
+                throw domError(
+                    `The radius provided (${values[2]}) is negative.`,
+                    'IndexSizeError',
+                );
+            }
+            this._ellipse(
+                values[0], values[1], values[2], values[2], 0, values[3], values[4],
+                Boolean(anticlockwise),
+            );
+        }
+
+        ellipse(x, y, rx, ry, rotation, start, end, anticlockwise = false) {
+            const values = [x, y, rx, ry, rotation, start, end].map(Number);
+            if (!finite(...values)) {
+                return;
+            }
+            if (values[2] < 0 || values[3] < 0) {
+                throw domError('The radius provided is negative.', 'IndexSizeError');
+            }
+            this._ellipse(...values, Boolean(anticlockwise));
+        }
+
+        // Cubic Béziers of at most a quarter turn each, joined to the current point by a line.
+        _ellipse(x, y, rx, ry, rotation, start, end, anticlockwise) {
+            let sweep = end - start;
+            if (!anticlockwise && sweep >= TAU) {
+                sweep = TAU;
+            } else if (anticlockwise && -sweep >= TAU) {
+                sweep = -TAU;
+            } else {
+                sweep %= TAU;
+                if (!anticlockwise && sweep < 0) {
+                    sweep += TAU;
+                } else if (anticlockwise && sweep > 0) {
+                    sweep -= TAU;
+                }
+            }
+            const cos = Math.cos(rotation);
+            const sin = Math.sin(rotation);
+            const point = (angle) => {
+                const px = rx * Math.cos(angle);
+                const py = ry * Math.sin(angle);
+                return [x + px * cos - py * sin, y + px * sin + py * cos];
+            };
+            const tangent = (angle) => {
+                const dx = -rx * Math.sin(angle);
+                const dy = ry * Math.cos(angle);
+                return [dx * cos - dy * sin, dx * sin + dy * cos];
+            };
+            this.lineTo(...point(start));
+            if (sweep === 0) {
+                return;
+            }
+            const parts = Math.max(1, Math.ceil(Math.abs(sweep) / (Math.PI / 2) - 1e-9));
+            const step = sweep / parts;
+            const k = (4 / 3) * Math.tan(step / 4);
+            let angle = start;
+            for (let i = 0; i < parts; i += 1) {
+                const next = angle + step;
+                const [x0, y0] = point(angle);
+                const [x3, y3] = point(next);
+                const [d0x, d0y] = tangent(angle);
+                const [d3x, d3y] = tangent(next);
+                this.bezierCurveTo(
+                    x0 + k * d0x, y0 + k * d0y, x3 - k * d3x, y3 - k * d3y, x3, y3,
+                );
+                angle = next;
+            }
+        }
+
+        arcTo(x1, y1, x2, y2, radius) {
+            [x1, y1, x2, y2, radius] = [x1, y1, x2, y2, radius].map(Number);
+            if (!finite(x1, y1, x2, y2, radius)) {
+                return;
+            }
+            if (radius < 0) {
+                throw domError(`The radius provided (${radius}) is negative.`, 'IndexSizeError');
+            }
+            if (this._current === null) {
+                this.moveTo(x1, y1);
+            }
+            const inverse = invert(this._matrix());
+            if (!inverse) {
+                return;
+            }
+            const [x0, y0] = apply(inverse, ...this._current);
+            const v1x = x0 - x1;
+            const v1y = y0 - y1;
+            const v2x = x2 - x1;
+            const v2y = y2 - y1;
+            const len1 = Math.hypot(v1x, v1y);
+            const len2 = Math.hypot(v2x, v2y);
+            const cross = v1x * v2y - v1y * v2x;
+            if (radius === 0 || len1 === 0 || len2 === 0 || Math.abs(cross) <= 1e-9 * len1 * len2) {
+                this.lineTo(x1, y1);
+                return;
+            }
+            const cosine = Math.min(Math.max((v1x * v2x + v1y * v2y) / (len1 * len2), -1), 1);
+            const half = Math.acos(cosine) / 2;
+            const reach = radius / Math.tan(half);
+            const bx = v1x / len1 + v2x / len2;
+            const by = v1y / len1 + v2y / len2;
+            const blen = Math.hypot(bx, by);
+            const distance = radius / Math.sin(half);
+            const cx = x1 + (bx / blen) * distance;
+            const cy = y1 + (by / blen) * distance;
+            const t1x = x1 + (v1x / len1) * reach;
+            const t1y = y1 + (v1y / len1) * reach;
+            const t2x = x1 + (v2x / len2) * reach;
+            const t2y = y1 + (v2y / len2) * reach;
+            this._ellipse(
+                cx, cy, radius, radius, 0,
+                Math.atan2(t1y - cy, t1x - cx), Math.atan2(t2y - cy, t2x - cx), cross > 0,
+            );
+        }
+
+        addPath(path, transform) {
+            if (!(path instanceof Path2D)) {
+                throw new TypeError("Failed to execute 'addPath': parameter 1 is not a Path2D.");
+            }
+            const matrix = transform === undefined ? IDENTITY : matrixFrom(transform);
+            if (!matrix) {
+                return;
+            }
+            const target = multiply(this._matrix(), matrix);
+            for (const [command, ...points] of path._path._segments) {
+                const mapped = [];
+                for (let i = 0; i < points.length; i += 2) {
+                    mapped.push(...apply(target, points[i], points[i + 1]));
+                }
+                this._segments.push([command, ...mapped]);
+            }
+            this._start = path._path._start && apply(target, ...path._path._start);
+            this._current = path._path._current && apply(target, ...path._path._current);
+        }
+
+        // SVG path data, with every point taken through `matrix` when there is one.
+        _svg(matrix) {
+            let svg = '';
+            for (const [command, ...points] of this._segments) {
+                svg += command;
+                for (let i = 0; i < points.length; i += 2) {
+                    const [x, y] = matrix
+                        ? apply(matrix, points[i], points[i + 1])
+                        : [points[i], points[i + 1]];
+                    svg += ` ${x} ${y}`;
+                }
+                svg += ' ';
+            }
+            return svg;
+        }
+    }
+
+    const PATH_METHODS = [
+        'moveTo', 'lineTo', 'quadraticCurveTo', 'bezierCurveTo', 'arcTo', 'arc', 'ellipse',
+        'rect', 'roundRect', 'closePath',
+    ];
+
+    class Path2D {
+        constructor(path) {
+            this._path = new PathData(() => IDENTITY);
+            if (path instanceof Path2D) {
+                this._path.addPath(path);
+            } else if (path !== undefined) {
+                const svg = canvasParsePath(String(path));
+                const number = /[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?/;
+                const tokens = svg.match(new RegExp(`[A-Za-z]|${number.source}`, 'g')) || [];
+                let segment = null;
+                for (const token of tokens) {
+                    if (/^[A-Za-z]$/.test(token)) {
+                        segment = [token];
+                        this._path._segments.push(segment);
+                    } else if (segment) {
+                        segment.push(Number(token));
+                    }
+                }
+                for (const [command, ...points] of this._path._segments) {
+                    if (points.length) {
+                        this._path._current = points.slice(-2);
+                    }
+                    if (command === 'M') {
+                        this._path._start = this._path._current;
+                    } else if (command === 'Z') {
+                        this._path._current = this._path._start;
+                    }
+                }
+            }
+        }
+
+        addPath(path, transform) {
+            this._path.addPath(path, transform);
+        }
+    }
+
+    for (const name of PATH_METHODS) {
+        Path2D.prototype[name] = function (...args) {
+            return this._path[name](...args);
+        };
+    }
+
+    class CanvasGradient {
+        constructor(kind, points) {
+            this._kind = kind;
+            this._points = points;
+            this._stops = [];
+        }
+
+        addColorStop(offset, color) {
+            offset = Number(offset);
+            if (!(offset >= 0 && offset <= 1)) {
+                throw domError(
+                    `The provided value (${offset}) is outside the range (0.0, 1.0).`,
+                    'IndexSizeError',
+                );
+            }
+            const normalized = canvasParseColor(String(color));
+            if (normalized === null || normalized === undefined) {
+                throw domError(
+                    `The value provided ('${color}') could not be parsed as a color.`,
+                    'SyntaxError',
+                );
+            }
+            this._stops.push([offset, normalized]);
+        }
+
+        _paint() {
+            const stops = this._stops.slice().sort((a, b) => a[0] - b[0]);
+            return { kind: this._kind, points: this._points, stops };
+        }
+    }
+
+    class CanvasPattern {
+        constructor(source, repetition) {
+            this._source = source;
+            this._repetition = repetition;
+            this._transform = IDENTITY;
+        }
+
+        setTransform(transform = {}) {
+            const matrix = matrixFrom(transform);
+            if (matrix) {
+                this._transform = matrix;
+            }
+        }
+
+        _paint() {
+            return {
+                kind: 'pattern',
+                source: this._source,
+                repetition: this._repetition,
+                transform: this._transform,
+            };
+        }
+    }
+
+    const paintOf = (style) =>
+        style instanceof CanvasGradient || style instanceof CanvasPattern
+            ? style._paint()
+            : { kind: 'color', color: style };
+
+    class ImageData {
+        constructor(data, width, height) {
+            if (data instanceof Uint8ClampedArray) {
+                width = Math.trunc(Number(width)) || 0;
+                const pixels = data.length / 4;
+                if (width <= 0 || !Number.isInteger(pixels) || pixels % width !== 0) {
+                    throw domError(
+                        "Failed to construct 'ImageData': The input data length is not a " +
+                            'multiple of (4 * width).',
+                        'IndexSizeError',
+                    );
+                }
+                height = height === undefined ? pixels / width : Math.trunc(Number(height)) || 0;
+                if (width * height !== pixels) {
+                    throw domError(
+                        "Failed to construct 'ImageData': The input data length is not equal " +
+                            'to (4 * width * height).',
+                        'IndexSizeError',
+                    );
+                }
+            } else {
+                height = Math.trunc(Number(width)) || 0;
+                width = Math.trunc(Number(data)) || 0;
+                if (width <= 0 || height <= 0) {
+                    throw domError(
+                        "Failed to construct 'ImageData': The source width and height must be " +
+                            'greater than zero.',
+                        'IndexSizeError',
+                    );
+                }
+                checkSize(width, height, "Failed to construct 'ImageData'");
+                data = new Uint8ClampedArray(width * height * 4);
+            }
+            Object.defineProperties(this, {
+                width: { value: width, enumerable: true },
+                height: { value: height, enumerable: true },
+                data: { value: data, enumerable: true },
+                colorSpace: { value: 'srgb', enumerable: true },
+            });
+        }
+    }
+
+    class TextMetrics {}
+
+    const BITMAP = Symbol('ImageBitmap');
+
+    class ImageBitmap {
+        constructor(token, key, width, height) {
+            if (token !== BITMAP) {
+                throw new TypeError('Illegal constructor');
+            }
+            this._key = key;
+            this._width = width;
+            this._height = height;
+            if (registry) {
+                registry.register(this, key, this);
+            }
+        }
+
+        get width() {
+            return this._key === null ? 0 : this._width;
+        }
+
+        get height() {
+            return this._key === null ? 0 : this._height;
+        }
+
+        close() {
+            if (this._key !== null) {
+                canvasRelease(this._key);
+                if (registry) {
+                    registry.unregister(this);
+                }
+                this._key = null;
+            }
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'ImageBitmap';
+        }
+    }
+
+    // What `drawImage()`, `createPattern()` and `createImageBitmap()` read: a canvas, an
+    // offscreen canvas, an image bitmap, or an `<img>` whose `src` is a `data:` URL. `null`
+    // when there is nothing to draw yet.
+    const imageSourceOf = (image, method) => {
+        if (image instanceof OffscreenCanvas) {
+            if (image._context) {
+                image._context._draw();
+            }
+            return {
+                source: { kind: 'canvas', key: image._key },
+                width: image._width,
+                height: image._height,
+            };
+        }
+        if (image instanceof ImageBitmap) {
+            if (image._key === null) {
+                throw domError(
+                    `Failed to execute '${method}': The image source is detached.`,
+                    'InvalidStateError',
+                );
+            }
+            return {
+                source: { kind: 'canvas', key: image._key },
+                width: image._width,
+                height: image._height,
+            };
+        }
+        if (isCanvas(image)) {
+            if (TRANSFERRED.has(image)) {
+                return imageSourceOf(TRANSFERRED.get(image), method);
+            }
+            const context = CONTEXTS.get(image);
+            if (context) {
+                context._draw();
+            }
+            const [width, height] = canvasSize(image);
+            return {
+                source: { kind: 'canvas', key: String(frontier.__handleOf(image)) },
+                width,
+                height,
+            };
+        }
+        if (image && image.nodeType === 1 && String(image.localName || '') === 'img') {
+            const url = String(image.getAttribute('src') || '').trim();
+            const size = url.startsWith('data:') ? canvasImageSize(url) : null;
+            if (!size) {
+                return null;
+            }
+            return { source: { kind: 'url', url }, width: size[0], height: size[1] };
+        }
+        throw new TypeError(
+            `Failed to execute '${method}': The provided value is not of a supported image type.`,
+        );
+    };
+
+    const defaultState = () => ({
+        transform: IDENTITY,
+        fillStyle: '#000000',
+        strokeStyle: '#000000',
+        lineWidth: 1,
+        lineCap: 'butt',
+        lineJoin: 'miter',
+        miterLimit: 10,
+        lineDash: [],
+        lineDashOffset: 0,
+        globalAlpha: 1,
+        globalCompositeOperation: 'source-over',
+        font: '10px sans-serif',
+        textAlign: 'start',
+        textBaseline: 'alphabetic',
+        direction: 'inherit',
+        imageSmoothingEnabled: true,
+        imageSmoothingQuality: 'low',
+        shadowColor: 'rgba(0, 0, 0, 0)',
+        shadowBlur: 0,
+        shadowOffsetX: 0,
+        shadowOffsetY: 0,
+        filter: 'none',
+        clips: [],
+    });
+
+    // Which of `(path, ...rest)` and `(...rest)` a call used.
+    const pathArguments = (args) =>
+        args[0] instanceof Path2D ? [args[0], args.slice(1)] : [null, args];
+
+    class CanvasRenderingContext2D {
+        // `key` is the bitmap the context draws on; `shown` the handle of the `<canvas>` that
+        // shows it, or `null` for an offscreen canvas of its own.
+        constructor(canvas, key, shown) {
+            Object.defineProperty(this, 'canvas', { value: canvas, enumerable: true });
+            this._key = key;
+            this._shown = shown;
+            this._state = defaultState();
+            this._stack = [];
+            this._path = new PathData(() => this._state.transform);
+            this._ops = [];
+            this._reset = false;
+            this._dirty = false;
+            this._queued = false;
+        }
+
+        _record(op) {
+            this._ops.push(op);
+            this._changed();
+        }
+
+        _changed() {
+            this._dirty = true;
+            if (!this._queued) {
+                this._queued = true;
+                Promise.resolve().then(() => this._flush());
+            }
+        }
+
+        // Hand what was drawn to Rust, so pixels read back include it.
+        _draw() {
+            if (this._ops.length === 0 && !this._reset) {
+                return;
+            }
+            const [width, height] = sizeOf(this.canvas);
+            const batch = JSON.stringify({ width, height, reset: this._reset, ops: this._ops });
+            this._ops = [];
+            this._reset = false;
+            canvasDraw(this._key, batch);
+        }
+
+        _flush() {
+            this._queued = false;
+            this._draw();
+            if (this._dirty) {
+                this._dirty = false;
+                if (this._shown !== null) {
+                    domSetCanvasImage(this._shown);
+                }
+            }
+        }
+
+        _drawState() {
+            const state = this._state;
+            return {
+                transform: state.transform,
+                alpha: state.globalAlpha,
+                composite: state.globalCompositeOperation,
+                clips: state.clips,
+                smoothing: state.imageSmoothingEnabled,
+            };
+        }
+
+        _lineStyle() {
+            const state = this._state;
+            // A dash pattern of zeros draws the line solid.
+            const dashed = state.lineDash.some((length) => length > 0);
+            return {
+                width: state.lineWidth,
+                cap: state.lineCap,
+                join: state.lineJoin,
+                miterLimit: state.miterLimit,
+                dash: dashed ? state.lineDash : [],
+                dashOffset: state.lineDashOffset,
+            };
+        }
+
+        // The path to paint in user space: a `Path2D`'s own, or the current path brought back
+        // from canvas pixels. `null` when the transform cannot be inverted.
+        _userPath(path) {
+            if (path) {
+                return path._path._svg(null);
+            }
+            const inverse = invert(this._state.transform);
+            return inverse ? this._path._svg(inverse) : null;
+        }
+
+        // The path in canvas pixels, for clipping and hit testing.
+        _pixelPath(path) {
+            return path ? path._path._svg(this._state.transform) : this._path._svg(null);
+        }
+
+        _resolvedAlign() {
+            const rtl = this._state.direction === 'rtl';
+            const align = this._state.textAlign;
+            if (align === 'start') {
+                return rtl ? 'right' : 'left';
+            }
+            if (align === 'end') {
+                return rtl ? 'left' : 'right';
+            }
+            return align;
+        }
+
+        _clear() {
+            this._state = defaultState();
+            this._stack = [];
+            this._path = new PathData(() => this._state.transform);
+            this._ops = [];
+            this._reset = true;
+            this._changed();
+        }
+
+        get font() {
+            return this._state.font;
+        }
+
+        set font(value) {
+            const normalized = canvasParseFont(String(value));
+            if (normalized !== null && normalized !== undefined) {
+                this._state.font = normalized;
+            }
+        }
+
+        save() {
+            this._stack.push({ ...this._state });
+        }
+
+        restore() {
+            if (this._stack.length) {
+                this._state = this._stack.pop();
+            }
+        }
+
+        reset() {
+            this._clear();
+        }
+
+        getContextAttributes() {
+            return {
+                alpha: true,
+                colorSpace: 'srgb',
+                desynchronized: false,
+                willReadFrequently: false,
+            };
+        }
+
+        _transform(matrix) {
+            if (finite(...matrix)) {
+                this._state.transform = multiply(this._state.transform, matrix);
+            }
+        }
+
+        scale(x, y) {
+            this._transform([Number(x), 0, 0, Number(y), 0, 0]);
+        }
+
+        rotate(angle) {
+            angle = Number(angle);
+            const [cos, sin] = [Math.cos(angle), Math.sin(angle)];
+            this._transform([cos, sin, -sin, cos, 0, 0]);
+        }
+
+        translate(x, y) {
+            this._transform([1, 0, 0, 1, Number(x), Number(y)]);
+        }
+
+        transform(a, b, c, d, e, f) {
+            this._transform([a, b, c, d, e, f].map(Number));
+        }
+
+        setTransform(...args) {
+            const matrix =
+                args.length === 0 || (typeof args[0] === 'object' && args[0] !== null)
+                    ? matrixFrom(args[0] || {})
+                    : args.slice(0, 6).map(Number);
+            if (matrix && matrix.length === 6 && finite(...matrix)) {
+                this._state.transform = matrix;
+            }
+        }
+
+        resetTransform() {
+            this._state.transform = IDENTITY;
+        }
+
+        getTransform() {
+            const [a, b, c, d, e, f] = this._state.transform;
+            return {
+                a, b, c, d, e, f,
+                m11: a, m12: b, m21: c, m22: d, m41: e, m42: f,
+                is2D: true,
+                isIdentity: a === 1 && b === 0 && c === 0 && d === 1 && e === 0 && f === 0,
+            };
+        }
+
+        setLineDash(segments) {
+            const lengths = Array.from(segments, Number);
+            if (lengths.some((length) => !Number.isFinite(length) || length < 0)) {
+                return;
+            }
+            this._state.lineDash = lengths.length % 2 ? lengths.concat(lengths) : lengths;
+        }
+
+        getLineDash() {
+            return this._state.lineDash.slice();
+        }
+
+        createLinearGradient(x0, y0, x1, y1) {
+            const points = [x0, y0, x1, y1].map(Number);
+            if (!finite(...points)) {
+                throw new TypeError("Failed to execute 'createLinearGradient': non-finite value.");
+            }
+            return new CanvasGradient('linear', points);
+        }
+
+        createRadialGradient(x0, y0, r0, x1, y1, r1) {
+            const points = [x0, y0, r0, x1, y1, r1].map(Number);
+            if (!finite(...points)) {
+                throw new TypeError("Failed to execute 'createRadialGradient': non-finite value.");
+            }
+            if (points[2] < 0 || points[5] < 0) {
+                throw domError('The radius provided is negative.', 'IndexSizeError');
+            }
+            return new CanvasGradient('radial', points);
+        }
+
+        createConicGradient(angle, x, y) {
+            const points = [angle, x, y].map(Number);
+            if (!finite(...points)) {
+                throw new TypeError("Failed to execute 'createConicGradient': non-finite value.");
+            }
+            return new CanvasGradient('conic', points);
+        }
+
+        createPattern(image, repetition) {
+            repetition = repetition === null || repetition === '' ? 'repeat' : String(repetition);
+            if (!REPETITIONS.has(repetition)) {
+                throw domError(
+                    `The provided type ('${repetition}') is not one of 'repeat', 'repeat-x', ` +
+                        "'repeat-y', or 'no-repeat'.",
+                    'SyntaxError',
+                );
+            }
+            const found = imageSourceOf(image, 'createPattern');
+            return found ? new CanvasPattern(found.source, repetition) : null;
+        }
+
+        beginPath() {
+            this._path = new PathData(() => this._state.transform);
+        }
+
+        fill(...args) {
+            const [path, [rule]] = pathArguments(args);
+            const svg = this._userPath(path);
+            if (svg) {
+                this._record({
+                    op: 'fill',
+                    path: svg,
+                    evenOdd: rule === 'evenodd',
+                    paint: paintOf(this._state.fillStyle),
+                    state: this._drawState(),
+                });
+            }
+        }
+
+        stroke(path) {
+            const svg = this._userPath(path instanceof Path2D ? path : null);
+            if (svg) {
+                this._record({
+                    op: 'stroke',
+                    path: svg,
+                    line: this._lineStyle(),
+                    paint: paintOf(this._state.strokeStyle),
+                    state: this._drawState(),
+                });
+            }
+        }
+
+        clip(...args) {
+            const [path] = pathArguments(args);
+            this._state.clips = [...this._state.clips, this._pixelPath(path)];
+        }
+
+        isPointInPath(...args) {
+            const [path, [x, y, rule]] = pathArguments(args);
+            if (!finite(Number(x), Number(y))) {
+                return false;
+            }
+            return canvasPointInPath(
+                this._pixelPath(path),
+                Number(x),
+                Number(y),
+                rule === 'evenodd',
+            );
+        }
+
+        isPointInStroke(...args) {
+            const [path, [x, y]] = pathArguments(args);
+            const inverse = invert(this._state.transform);
+            const svg = this._userPath(path);
+            if (!inverse || !svg || !finite(Number(x), Number(y))) {
+                return false;
+            }
+            const [userX, userY] = apply(inverse, Number(x), Number(y));
+            return canvasPointInStroke(
+                svg,
+                JSON.stringify(this._lineStyle()),
+                userX,
+                userY,
+            );
+        }
+
+        fillRect(x, y, w, h) {
+            const rect = new Path2D();
+            rect.rect(x, y, w, h);
+            this.fill(rect);
+        }
+
+        strokeRect(x, y, w, h) {
+            const rect = new Path2D();
+            rect.rect(x, y, w, h);
+            this.stroke(rect);
+        }
+
+        clearRect(x, y, w, h) {
+            const rect = [x, y, w, h].map(Number);
+            if (finite(...rect)) {
+                this._record({ op: 'clear', rect, state: this._drawState() });
+            }
+        }
+
+        _text(text, x, y, maxWidth, line) {
+            [x, y] = [Number(x), Number(y)];
+            if (!finite(x, y)) {
+                return;
+            }
+            if (maxWidth !== undefined) {
+                maxWidth = Number(maxWidth);
+                if (!Number.isFinite(maxWidth) || maxWidth <= 0) {
+                    return;
+                }
+            }
+            const state = this._state;
+            this._record({
+                op: 'text',
+                text: String(text).replace(/[\t\n\f\r]/g, ' '),
+                font: state.font,
+                x,
+                y,
+                align: this._resolvedAlign(),
+                baseline: state.textBaseline,
+                maxWidth: maxWidth === undefined ? null : maxWidth,
+                line,
+                paint: paintOf(line ? state.strokeStyle : state.fillStyle),
+                state: this._drawState(),
+            });
+        }
+
+        fillText(text, x, y, maxWidth) {
+            this._text(text, x, y, maxWidth, null);
+        }
+
+        strokeText(text, x, y, maxWidth) {
+            this._text(text, x, y, maxWidth, this._lineStyle());
+        }
+
+        measureText(text) {
+            const [width, ascent, descent] = canvasMeasureText(
+                String(text),
+                this._state.font,
+            );
+            const align = this._resolvedAlign();
+            const left = align === 'center' ? width / 2 : align === 'right' ? width : 0;
+            // How far the context's baseline sits below the alphabetic one. The baselines
+            // below are how far it sits below each of them.
+            const shift = {
+                top: -ascent,
+                hanging: -ascent * 0.8,
+                middle: (descent - ascent) / 2,
+                alphabetic: 0,
+                ideographic: descent,
+                bottom: descent,
+            }[this._state.textBaseline];
+            const metrics = new TextMetrics();
+            Object.assign(metrics, {
+                width,
+                actualBoundingBoxLeft: left,
+                actualBoundingBoxRight: width - left,
+                actualBoundingBoxAscent: ascent + shift,
+                actualBoundingBoxDescent: descent - shift,
+                fontBoundingBoxAscent: ascent + shift,
+                fontBoundingBoxDescent: descent - shift,
+                emHeightAscent: ascent + shift,
+                emHeightDescent: descent - shift,
+                alphabeticBaseline: shift,
+                hangingBaseline: shift + ascent * 0.8,
+                ideographicBaseline: shift - descent,
+            });
+            return Object.freeze(metrics);
+        }
+
+        drawImage(image, ...args) {
+            const found = imageSourceOf(image, 'drawImage');
+            if (!found) {
+                return;
+            }
+            const { source, width, height } = found;
+            const values = args.map(Number);
+            let sx = 0;
+            let sy = 0;
+            let sw = width;
+            let sh = height;
+            let dx;
+            let dy;
+            let dw = width;
+            let dh = height;
+            if (args.length === 2) {
+                [dx, dy] = values;
+            } else if (args.length === 4) {
+                [dx, dy, dw, dh] = values;
+            } else if (args.length === 8) {
+                [sx, sy, sw, sh, dx, dy, dw, dh] = values;
+            } else {
+                throw new TypeError(
+                    "Failed to execute 'drawImage': Valid arities are: [3, 5, 9], but " +
+                        `${args.length + 1} arguments provided.`,
+                );
+            }
+            if (!finite(sx, sy, sw, sh, dx, dy, dw, dh) || width === 0 || height === 0) {
+                return;
+            }
+            this._record({
+                op: 'image',
+                source,
+                from: [sx, sy, sw, sh],
+                to: [dx, dy, dw, dh],
+                state: this._drawState(),
+            });
+        }
+
+        createImageData(width, height) {
+            if (width instanceof ImageData) {
+                return new ImageData(width.width, width.height);
+            }
+            return new ImageData(Math.abs(Number(width)), Math.abs(Number(height)));
+        }
+
+        getImageData(sx, sy, sw, sh) {
+            [sx, sy, sw, sh] = [sx, sy, sw, sh].map((value) => Math.trunc(Number(value)));
+            if (!finite(sx, sy, sw, sh)) {
+                throw new TypeError("Failed to execute 'getImageData': non-finite value.");
+            }
+            if (sw === 0 || sh === 0) {
+                throw domError(
+                    `The source ${sw === 0 ? 'width' : 'height'} is 0.`,
+                    'IndexSizeError',
+                );
+            }
+            if (sw < 0) {
+                sx += sw;
+                sw = -sw;
+            }
+            if (sh < 0) {
+                sy += sh;
+                sh = -sh;
+            }
+            checkSize(sw, sh, "Failed to execute 'getImageData'");
+            this._draw();
+            const buffer = canvasGetPixels(this._key, sx, sy, sw, sh);
+            return new ImageData(new Uint8ClampedArray(buffer), sw, sh);
+        }
+
+        putImageData(imageData, dx, dy, dirtyX = 0, dirtyY = 0, dirtyWidth, dirtyHeight) {
+            if (!(imageData instanceof ImageData)) {
+                throw new TypeError(
+                    "Failed to execute 'putImageData': parameter 1 is not of type 'ImageData'.",
+                );
+            }
+            const { width, height, data } = imageData;
+            const values = [
+                dx, dy, dirtyX, dirtyY,
+                dirtyWidth === undefined ? width : dirtyWidth,
+                dirtyHeight === undefined ? height : dirtyHeight,
+            ].map((value) => Math.trunc(Number(value)));
+            if (!finite(...values)) {
+                return;
+            }
+            let [x, y, fromX, fromY, fromWidth, fromHeight] = values;
+            if (fromWidth < 0) {
+                fromX += fromWidth;
+                fromWidth = -fromWidth;
+            }
+            if (fromHeight < 0) {
+                fromY += fromHeight;
+                fromHeight = -fromHeight;
+            }
+            const left = Math.max(fromX, 0);
+            const top = Math.max(fromY, 0);
+            const right = Math.min(fromX + fromWidth, width);
+            const bottom = Math.min(fromY + fromHeight, height);
+            if (right <= left || bottom <= top) {
+                return;
+            }
+            this._draw();
+            canvasPutPixels(
+                this._key,
+                sizeOf(this.canvas),
+                data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength),
+                width,
+                [x, y],
+                [left, top, right - left, bottom - top],
+            );
+            this._changed();
+        }
+    }
+
+    // Setters ignore what the spec has them ignore, as browsers do.
+    const stateProperty = (name, accept) =>
+        Object.defineProperty(CanvasRenderingContext2D.prototype, name, {
+            get() {
+                return this._state[name];
+            },
+            set(value) {
+                const accepted = accept(value);
+                if (accepted !== undefined) {
+                    this._state[name] = accepted;
+                }
+            },
+            configurable: true,
+        });
+    const number = (test) => (value) => {
+        const parsed = Number(value);
+        return Number.isFinite(parsed) && test(parsed) ? parsed : undefined;
+    };
+    const oneOf = (values) => (value) => (values.has(String(value)) ? String(value) : undefined);
+    const color = (value) => canvasParseColor(String(value)) ?? undefined;
+    const style = (value) =>
+        value instanceof CanvasGradient || value instanceof CanvasPattern ? value : color(value);
+
+    stateProperty('fillStyle', style);
+    stateProperty('strokeStyle', style);
+    stateProperty('lineWidth', number((value) => value > 0));
+    stateProperty('lineCap', oneOf(CAPS));
+    stateProperty('lineJoin', oneOf(JOINS));
+    stateProperty('miterLimit', number((value) => value > 0));
+    stateProperty('lineDashOffset', number(() => true));
+    stateProperty('globalAlpha', number((value) => value >= 0 && value <= 1));
+    stateProperty('globalCompositeOperation', oneOf(COMPOSITES));
+    stateProperty('textAlign', oneOf(ALIGNS));
+    stateProperty('textBaseline', oneOf(BASELINES));
+    stateProperty('direction', oneOf(DIRECTIONS));
+    stateProperty('imageSmoothingEnabled', Boolean);
+    stateProperty('imageSmoothingQuality', oneOf(QUALITIES));
+    stateProperty('shadowColor', color);
+    stateProperty('shadowBlur', number((value) => value >= 0));
+    stateProperty('shadowOffsetX', number(() => true));
+    stateProperty('shadowOffsetY', number(() => true));
+    stateProperty('filter', String);
+
+    for (const name of PATH_METHODS) {
+        CanvasRenderingContext2D.prototype[name] = function (...args) {
+            return this._path[name](...args);
+        };
+    }
+
+    class OffscreenCanvasRenderingContext2D extends CanvasRenderingContext2D {}
+
+    class OffscreenCanvas {
+        constructor(width, height) {
+            if (arguments.length < 2) {
+                throw new TypeError(
+                    "Failed to construct 'OffscreenCanvas': 2 arguments required, but only " +
+                        `${arguments.length} present.`,
+                );
+            }
+            this._width = toDimension(width);
+            this._height = toDimension(height);
+            this._key = storeKey('offscreen');
+            this._shown = null;
+            this._context = null;
+            if (registry) {
+                registry.register(this, this._key);
+            }
+        }
+
+        get width() {
+            return this._width;
+        }
+
+        set width(value) {
+            this._width = toDimension(value);
+            if (this._context) {
+                this._context._clear();
+            }
+        }
+
+        get height() {
+            return this._height;
+        }
+
+        set height(value) {
+            this._height = toDimension(value);
+            if (this._context) {
+                this._context._clear();
+            }
+        }
+
+        getContext(type) {
+            if (String(type) !== '2d') {
+                return null;
+            }
+            if (!this._context) {
+                this._context = new OffscreenCanvasRenderingContext2D(
+                    this,
+                    this._key,
+                    this._shown,
+                );
+            }
+            return this._context;
+        }
+
+        // The bitmap moves to the `ImageBitmap`; the canvas starts over transparent.
+        transferToImageBitmap() {
+            if (!this._context) {
+                throw domError(
+                    "Failed to execute 'transferToImageBitmap' on 'OffscreenCanvas': Cannot " +
+                        'transfer an ImageBitmap from an OffscreenCanvas with no context',
+                    'InvalidStateError',
+                );
+            }
+            this._context._draw();
+            const key = storeKey('bitmap');
+            const [width, height] = canvasSnapshot(
+                key,
+                JSON.stringify({ kind: 'canvas', key: this._key }),
+                [this._width, this._height],
+                null,
+            );
+            canvasRelease(this._key);
+            return new ImageBitmap(BITMAP, key, width, height);
+        }
+
+        // Always a PNG, which is what browsers fall back to for types they cannot encode.
+        convertToBlob() {
+            if (this._width === 0 || this._height === 0) {
+                return Promise.reject(
+                    domError(
+                        "Failed to execute 'convertToBlob' on 'OffscreenCanvas': The canvas " +
+                            'has no pixels.',
+                        'IndexSizeError',
+                    ),
+                );
+            }
+            if (this._context) {
+                this._context._draw();
+            }
+            const png = canvasPng(this._key, this._width, this._height);
+            return Promise.resolve(new global.Blob([png], { type: 'image/png' }));
+        }
+
+        get [Symbol.toStringTag]() {
+            return 'OffscreenCanvas';
+        }
+    }
+
+    // `(options)` or `(sx, sy, sw, sh, options)`: the crop area, if any, and the options.
+    const bitmapArguments = (args) => {
+        if (args.length < 4) {
+            return [null, args[0] || {}];
+        }
+        let [x, y, width, height] = args.slice(0, 4).map((value) => Math.trunc(Number(value)));
+        if (!finite(x, y, width, height)) {
+            throw new TypeError("Failed to execute 'createImageBitmap': non-finite value.");
+        }
+        if (width === 0 || height === 0) {
+            throw new RangeError(
+                "Failed to execute 'createImageBitmap': The crop rect " +
+                    `${width === 0 ? 'width' : 'height'} is 0.`,
+            );
+        }
+        if (width < 0) {
+            x += width;
+            width = -width;
+        }
+        if (height < 0) {
+            y += height;
+            height = -height;
+        }
+        checkSize(width, height, "Failed to execute 'createImageBitmap'");
+        return [[x, y, width, height], args[4] || {}];
+    };
+
+    // The bitmap `key` holds, a `width`×`height` image, scaled to `resizeWidth` and
+    // `resizeHeight` when they ask for another size. Given one, the other keeps the ratio.
+    const finishBitmap = (key, size, options) => {
+        if (!size) {
+            canvasRelease(key);
+            throw domError(
+                "Failed to execute 'createImageBitmap': The source image could not be decoded.",
+                'InvalidStateError',
+            );
+        }
+        const [width, height] = size;
+        const { resizeWidth, resizeHeight, resizeQuality } = options;
+        if (resizeWidth === undefined && resizeHeight === undefined) {
+            return new ImageBitmap(BITMAP, key, width, height);
+        }
+        const targetWidth =
+            resizeWidth === undefined
+                ? Math.ceil((width * Number(resizeHeight)) / height)
+                : toDimension(resizeWidth);
+        const targetHeight =
+            resizeHeight === undefined
+                ? Math.ceil((height * Number(resizeWidth)) / width)
+                : toDimension(resizeHeight);
+        if (!(targetWidth > 0 && targetHeight > 0)) {
+            canvasRelease(key);
+            throw domError(
+                "Failed to execute 'createImageBitmap': The resize width or height is 0.",
+                'InvalidStateError',
+            );
+        }
+        if (!fitsSize(targetWidth, targetHeight)) {
+            canvasRelease(key);
+            throw tooLarge(targetWidth, targetHeight, "Failed to execute 'createImageBitmap'");
+        }
+        const resized = storeKey('bitmap');
+        const ops = [{
+            op: 'image',
+            source: { kind: 'canvas', key },
+            from: [0, 0, width, height],
+            to: [0, 0, targetWidth, targetHeight],
+            state: {
+                transform: IDENTITY,
+                alpha: 1,
+                composite: 'source-over',
+                clips: [],
+                smoothing: resizeQuality !== 'pixelated',
+            },
+        }];
+        canvasDraw(
+            resized,
+            JSON.stringify({ width: targetWidth, height: targetHeight, reset: true, ops }),
+        );
+        canvasRelease(key);
+        return new ImageBitmap(BITMAP, resized, targetWidth, targetHeight);
+    };
+
+    // Copies the source's pixels as they are now. Blobs decode asynchronously; everything
+    // else is read before this returns, as browsers do.
+    const createImageBitmap = (image, ...args) => {
+        try {
+            const [area, options] = bitmapArguments(args);
+            const key = storeKey('bitmap');
+            const self = JSON.stringify({ kind: 'canvas', key });
+            const cropped = (size) =>
+                size && area ? canvasSnapshot(key, self, size, area) : size;
+            if (typeof global.Blob === 'function' && image instanceof global.Blob) {
+                return image.arrayBuffer().then((buffer) => {
+                    const size = canvasDecode(key, buffer);
+                    return finishBitmap(key, cropped(size), options);
+                });
+            }
+            let size;
+            if (image instanceof ImageData) {
+                const { width, height, data } = image;
+                canvasPutPixels(
+                    key,
+                    [width, height],
+                    data.buffer.slice(data.byteOffset, data.byteOffset + data.byteLength),
+                    width,
+                    [0, 0],
+                    [0, 0, width, height],
+                );
+                size = cropped([width, height]);
+            } else {
+                const found = imageSourceOf(image, 'createImageBitmap');
+                if (found && (found.width === 0 || found.height === 0)) {
+                    throw domError(
+                        "Failed to execute 'createImageBitmap': The source image width or " +
+                            'height is 0.',
+                        'InvalidStateError',
+                    );
+                }
+                size = found
+                    ? canvasSnapshot(
+                          key,
+                          JSON.stringify(found.source),
+                          [found.width, found.height],
+                          area,
+                      )
+                    : null;
+            }
+            return Promise.resolve(finishBitmap(key, size, options));
+        } catch (error) {
+            return Promise.reject(error);
+        }
+    };
+
+    // Browsers without WebGL fire this at the canvas before `getContext()` gives `null`.
+    const WebGLContextEvent =
+        typeof global.Event === 'function'
+            ? class WebGLContextEvent extends global.Event {
+                  constructor(type, init = {}) {
+                      super(type, init);
+                      const message = init && init.statusMessage;
+                      this.statusMessage = message === undefined ? '' : String(message);
+                  }
+              }
+            : null;
+
+    global.TextMetrics = TextMetrics;
+    global.CanvasRenderingContext2D = CanvasRenderingContext2D;
+    global.OffscreenCanvasRenderingContext2D = OffscreenCanvasRenderingContext2D;
+    global.OffscreenCanvas = OffscreenCanvas;
+    global.ImageBitmap = ImageBitmap;
+    global.createImageBitmap = createImageBitmap;
+    if (WebGLContextEvent) {
+        global.WebGLContextEvent = WebGLContextEvent;
+    }
+    global.CanvasGradient = CanvasGradient;
+    global.CanvasPattern = CanvasPattern;
+    global.Path2D = Path2D;
+    global.ImageData = ImageData;
+
+    const elementProto = global.Element && global.Element.prototype;
+    if (elementProto) {
+        // A canvas that handed its drawing to an offscreen canvas only shows what that draws.
+        const ensureNotTransferred = (canvas, method) => {
+            if (TRANSFERRED.has(canvas)) {
+                throw domError(
+                    `Failed to execute '${method}' on 'HTMLCanvasElement': The canvas has ` +
+                        'transferred control to an OffscreenCanvas.',
+                    'InvalidStateError',
+                );
+            }
+        };
+
+        elementProto.getContext = function (type) {
+            if (!isCanvas(this)) {
+                return null;
+            }
+            ensureNotTransferred(this, 'getContext');
+            if (WEBGL_CONTEXTS.has(String(type))) {
+                if (WebGLContextEvent) {
+                    const statusMessage = 'WebGL is not supported; see frontier://gpu.';
+                    this.dispatchEvent(
+                        new WebGLContextEvent('webglcontextcreationerror', { statusMessage }),
+                    );
+                }
+                return null;
+            }
+            if (String(type) !== '2d') {
+                return null;
+            }
+            let context = CONTEXTS.get(this);
+            if (!context) {
+                const key = String(frontier.__handleOf(this));
+                context = new CanvasRenderingContext2D(this, key, key);
+                CONTEXTS.set(this, context);
+            }
+            return context;
+        };
+
+        // The canvas's PNG, once what its context drew is in the store.
+        const exportCanvas = (canvas, method) => {
+            if (!isCanvas(canvas)) {
+                throw new TypeError('Illegal invocation');
+            }
+            ensureNotTransferred(canvas, method);
+            const context = CONTEXTS.get(canvas);
+            if (context) {
+                context._draw();
+            }
+            return [String(frontier.__handleOf(canvas)), ...canvasSize(canvas)];
+        };
+
+        elementProto.toDataURL = function () {
+            const [key, width, height] = exportCanvas(this, 'toDataURL');
+            return canvasDataUrl(key, width, height);
+        };
+
+        elementProto.toBlob = function (callback) {
+            if (typeof callback !== 'function') {
+                throw new TypeError(
+                    "Failed to execute 'toBlob' on 'HTMLCanvasElement': The callback provided " +
+                        'as parameter 1 is not a function.',
+                );
+            }
+            const png = canvasPng(...exportCanvas(this, 'toBlob'));
+            const blob = png ? new global.Blob([png], { type: 'image/png' }) : null;
+            global.setTimeout(() => callback.call(this, blob), 0);
+        };
+
+        elementProto.transferControlToOffscreen = function () {
+            if (!isCanvas(this)) {
+                throw new TypeError('Illegal invocation');
+            }
+            ensureNotTransferred(this, 'transferControlToOffscreen');
+            if (CONTEXTS.has(this)) {
+                throw domError(
+                    "Failed to execute 'transferControlToOffscreen' on 'HTMLCanvasElement': " +
+                        'Cannot transfer control from a canvas that has a rendering context.',
+                    'InvalidStateError',
+                );
+            }
+            // The offscreen canvas draws on the element's own bitmap, so the page shows it.
+            const key = String(frontier.__handleOf(this));
+            const [width, height] = canvasSize(this);
+            const offscreen = Object.create(OffscreenCanvas.prototype);
+            Object.assign(offscreen, {
+                _width: width,
+                _height: height,
+                _key: key,
+                _shown: key,
+                _context: null,
+            });
+            TRANSFERRED.set(this, offscreen);
+            return offscreen;
+        };
+
+        // Setting either dimension, even to its current value, clears the canvas. Elements
+        // other than canvases keep a plain property, as they did before.
+        [['width', 0], ['height', 1]].forEach(([name, index]) => {
+            Object.defineProperty(elementProto, name, {
+                get() {
+                    return isCanvas(this) ? canvasSize(this)[index] : undefined;
+                },
+                set(value) {
+                    if (!isCanvas(this)) {
+                        Object.defineProperty(this, name, {
+                            value,
+                            writable: true,
+                            enumerable: true,
+                            configurable: true,
+                        });
+                        return;
+                    }
+                    ensureNotTransferred(this, name);
+                    this.setAttribute(name, String(toDimension(value)));
+                    const context = CONTEXTS.get(this);
+                    if (context) {
+                        context._clear();
+                    }
+                },
+                configurable: true,
+            });
+        });
+    }
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_shorthand_parses_and_normalizes() {
+        let font = CanvasFont::parse("italic bold 12pt/1.5 \"Helvetica Neue\", serif").unwrap();
+        assert!(font.italic);
+        assert_eq!(font.weight, 700.0);
+        assert_eq!(font.size, 16.0);
+        assert_eq!(font.to_css(), "italic bold 16px \"Helvetica Neue\", serif");
+        assert_eq!(
+            CanvasFont::parse("300 2em monospace").unwrap().to_css(),
+            "300 32px monospace"
+        );
+        assert_eq!(CanvasFont::parse("12px").map(|font| font.to_css()), None);
+        assert_eq!(
+            CanvasFont::parse("bold serif").map(|font| font.to_css()),
+            None
+        );
+        assert_eq!(
+            CanvasFont::parse("12 px serif").map(|font| font.to_css()),
+            None
+        );
+    }
+
+    #[test]
+    fn colors_normalize_as_canvases_serialize_them() {
+        assert_eq!(normalize_color("red").as_deref(), Some("#ff0000"));
+        assert_eq!(
+            normalize_color("rgb(0 128 255 / 50%)").as_deref(),
+            Some("rgba(0, 128, 255, 0.5)")
+        );
+        assert_eq!(normalize_color("not a color"), None);
+    }
+
+    #[test]
+    fn oversized_canvases_allocate_nothing() {
+        let store = CanvasStore::default();
+        let batch: CanvasBatch =
+            serde_json::from_str(r#"{"width": 20000, "height": 20000, "reset": true}"#).unwrap();
+        store.draw("huge", &batch);
+        assert!(store.inner.borrow().bitmaps.get("huge").is_none());
+        assert_eq!(store.pixels("huge", 0, 0, 40_000, 1), None);
+        assert_eq!(store.pixels("huge", 0, 0, 20_000, 20_000), None);
+        assert_eq!(store.export("huge", 20_000, 20_000).unwrap(), None);
+        assert_eq!(store.pixels("huge", 0, 0, 2, 2), Some(vec![0; 16]));
+    }
+
+    #[test]
+    fn put_pixels_stays_inside_both_images() {
+        let store = CanvasStore::default();
+        let data = [255u8; 4 * 4];
+        store.put_pixels("c", (2, 2), &data, 2, (1, -1), [0, 0, 2, 2]);
+        assert_eq!(
+            store.pixels("c", 0, 0, 2, 2),
+            Some(vec![0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+        store.put_pixels(
+            "c",
+            (2, 2),
+            &data,
+            2,
+            (i64::MIN, i64::MAX),
+            [u32::MAX, u32::MAX, u32::MAX, u32::MAX],
+        );
+        store.put_pixels("c", (2, 2), &data, 0, (0, 0), [0, 0, u32::MAX, u32::MAX]);
+        store.put_pixels("c", (2, 2), &data, 2, (0, 0), [1, 1, u32::MAX, u32::MAX]);
+        assert_eq!(
+            store.pixels("c", 0, 0, 2, 2),
+            Some(vec![
+                0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 255, 255, 255, 255
+            ])
+        );
+    }
+
+    #[test]
+    fn decoded_blobs_stay_within_the_budget() {
+        use crate::js::isolation::{Isolation, OriginQuota};
+
+        let page = url::Url::parse("https://canvas-decode.test/").unwrap();
+        let quota = OriginQuota {
+            memory_limit: 64 * 1024,
+            ..Default::default()
+        };
+        let lease = Isolation::shared().admit(Some(&page), quota);
+        let store = CanvasStore::default();
+        store.set_memory(lease.native_memory());
+
+        // A few hundred bytes of PNG that decode to 256KiB of pixels.
+        let large = encode_png(&RgbaImage::new(256, 256)).unwrap();
+        assert!(large.len() < 4096);
+        assert_eq!(store.decode("large", &large), None);
+        let small = encode_png(&RgbaImage::new(16, 16)).unwrap();
+        assert_eq!(store.decode("small", &small), Some((16, 16)));
+    }
+
+    #[test]
+    fn point_in_path_follows_the_fill_rule() {
+        let rings = "M0 0 L10 0 L10 10 L0 10 Z M2 2 L8 2 L8 8 L2 8 Z";
+        assert!(point_in_path(rings, 5.0, 5.0, false));
+        assert!(!point_in_path(rings, 5.0, 5.0, true));
+        assert!(point_in_path(rings, 1.0, 1.0, true));
+        assert!(!point_in_path(rings, 20.0, 5.0, false));
+    }
+}
@@ -0,0 +1,28 @@
+//! A clock automation moves by hand, for replaying recorded sessions deterministically.
+//!
+//! Page timers normally sleep on tokio's wall clock. A page given a [`VirtualClock`] schedules
+//! its timers, `requestAnimationFrame` timestamps and `performance.now()` on it instead: time
+//! stands still until the host calls [`VirtualClock::set`], and timers then fire in the order
+//! they fall due, however long the host took between steps. `Date.now()` and network loads
+//! still follow the wall clock.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Time since the clock was created, shared by every page that follows it.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    now: Rc<Cell<Duration>>,
+}
+
+impl VirtualClock {
+    pub fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    /// Move the clock to `at`. It never goes backwards.
+    pub fn set(&self, at: Duration) {
+        self.now.set(self.now.get().max(at));
+    }
+}
@@ -0,0 +1,83 @@
+//! What page scripts printed: recent `console` calls and uncaught exceptions.
+//!
+//! Every QuickJS runtime records into one process-wide [`ConsoleLog`] besides forwarding to
+//! tracing, so automation and the remote debug channel can show a page's console without a
+//! terminal attached. Only the newest [`CAPACITY`] entries are kept.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+pub const CAPACITY: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsoleEntry {
+    /// `log`, `info`, `warn`, `error` or `debug`; uncaught exceptions are `error`.
+    pub level: String,
+    pub message: String,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ConsoleLog {
+    entries: Mutex<VecDeque<ConsoleEntry>>,
+}
+
+impl ConsoleLog {
+    pub fn shared() -> &'static ConsoleLog {
+        static LOG: OnceLock<ConsoleLog> = OnceLock::new();
+        LOG.get_or_init(ConsoleLog::default)
+    }
+
+    pub fn record(&self, level: &str, message: &str) {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(ConsoleEntry {
+            level: level.to_string(),
+            message: message.to_string(),
+            timestamp_ms,
+        });
+    }
+
+    /// The newest `limit` entries, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<ConsoleEntry> {
+        let entries = self.entries.lock().unwrap();
+        let skip = entries.len().saturating_sub(limit);
+        entries.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_newest_entries() {
+        let log = ConsoleLog::default();
+        for i in 0..CAPACITY + 2 {
+            log.record("log", &i.to_string());
+        }
+        let recent = log.recent(2);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].message, CAPACITY.to_string());
+        assert_eq!(recent[1].message, (CAPACITY + 1).to_string());
+        assert_eq!(log.recent(usize::MAX).len(), CAPACITY);
+        assert_eq!(log.recent(usize::MAX)[0].message, "2");
+
+        log.clear();
+        assert!(log.recent(10).is_empty());
+    }
+}
@@ -0,0 +1,850 @@
+//! Script coverage for automation sessions: which functions and lines of a page's scripts ran.
+//!
+//! QuickJS has no coverage hooks, so scripts are rewritten before they are compiled.
+//! [`instrument`] scans a script without building a syntax tree, keeping track of comments,
+//! strings, template literals, regular expressions and which braces hold statements. It inserts
+//! a call to the native `__frontier_cover` at the start of every braced function body, and
+//! before every statement that starts after a `;`, a block's `{` or a `}`.
+//! Each call is a site. A function's entry site counts its calls, and a line's count is the
+//! largest count of the sites on it. Insertions never add newlines, so stack line numbers still
+//! match the source; columns after an insertion shift.
+//!
+//! Some statements are not counted, because no call can be placed before them safely: `case`
+//! clauses, statements that follow another without a semicolon, and the bodies of arrow
+//! functions without braces (which are not counted as functions either). A rewritten script
+//! that does not compile is run as it was and reported with `instrumented: false`.
+//!
+//! [`JsCoverage`] collects the counts of every page runtime an automation session creates, so a
+//! test can navigate freely and read the totals from `GET /session/{id}/coverage`.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+/// The native function counters call, with the script's index and the site's.
+pub(crate) const COUNTER: &str = "__frontier_cover";
+
+const ANONYMOUS: &str = "(anonymous)";
+
+/// Words after which a `/` starts a regular expression rather than dividing.
+const BEFORE_EXPRESSION: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+/// Words that continue the statement a `}` ended, so nothing may be inserted before them.
+const CONTINUATIONS: &[&str] = &[
+    "else",
+    "catch",
+    "finally",
+    "while",
+    "in",
+    "of",
+    "instanceof",
+];
+
+/// Everything one session's scripts counted, shared by the engines that run them.
+#[derive(Debug, Clone, Default)]
+pub struct JsCoverage {
+    scripts: Rc<RefCell<Vec<ScriptCoverage>>>,
+}
+
+#[derive(Debug)]
+struct ScriptCoverage {
+    url: String,
+    fingerprint: u64,
+    sites: Vec<Site>,
+    hits: Vec<u64>,
+    instrumented: bool,
+}
+
+/// A counter inserted into a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Site {
+    line: u32,
+    /// Set on a function's entry counter: its name, or `(anonymous)`.
+    function: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub scripts: Vec<ScriptReport>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptReport {
+    /// The URL the script was loaded from, or the name an inline script runs under.
+    pub url: String,
+    /// `false` when the script could not be rewritten and ran without counters.
+    pub instrumented: bool,
+    pub functions: Vec<FunctionCoverage>,
+    /// Every line with a counter, in order.
+    pub lines: Vec<LineCoverage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// Where its parameters start.
+    pub line: u32,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineCoverage {
+    pub line: u32,
+    pub count: u64,
+}
+
+impl ScriptReport {
+    /// Functions that ran at least once.
+    pub fn covered_functions(&self) -> usize {
+        self.functions.iter().filter(|f| f.count > 0).count()
+    }
+
+    /// Lines that ran at least once.
+    pub fn covered_lines(&self) -> usize {
+        self.lines.iter().filter(|line| line.count > 0).count()
+    }
+}
+
+impl CoverageReport {
+    pub fn script(&self, url: &str) -> Option<&ScriptReport> {
+        self.scripts.iter().find(|script| script.url == url)
+    }
+}
+
+impl JsCoverage {
+    /// Register `source`, evaluated as `url`, and rewrite it to count into this collection.
+    /// Returns the script's index and the rewritten source, which is `None` when the script
+    /// could not be scanned. A script seen before keeps counting into the same entry.
+    pub(crate) fn instrument(&self, source: &str, url: &str) -> (usize, Option<String>) {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        let fingerprint = hasher.finish();
+        let mut scripts = self.scripts.borrow_mut();
+        let existing = scripts
+            .iter()
+            .position(|script| script.url == url && script.fingerprint == fingerprint);
+        let index = existing.unwrap_or(scripts.len());
+        let rewritten = instrument(source, index);
+        match (existing, rewritten) {
+            (Some(_), Some((rewritten, _))) if scripts[index].instrumented => {
+                (index, Some(rewritten))
+            }
+            (Some(_), _) => (index, None),
+            (None, rewritten) => {
+                let (rewritten, sites) = rewritten.unzip();
+                let sites = sites.unwrap_or_default();
+                scripts.push(ScriptCoverage {
+                    url: url.to_string(),
+                    fingerprint,
+                    hits: vec![0; sites.len()],
+                    instrumented: rewritten.is_some(),
+                    sites,
+                });
+                (index, rewritten)
+            }
+        }
+    }
+
+    /// Record that the rewritten script did not compile and ran as it was.
+    pub(crate) fn uninstrumented(&self, script: usize) {
+        if let Some(script) = self.scripts.borrow_mut().get_mut(script) {
+            script.instrumented = false;
+            script.hits.iter_mut().for_each(|count| *count = 0);
+        }
+    }
+
+    /// Count one pass through `site` of `script`.
+    pub(crate) fn hit(&self, script: usize, site: usize) {
+        let mut scripts = self.scripts.borrow_mut();
+        if let Some(count) = scripts
+            .get_mut(script)
+            .and_then(|script| script.hits.get_mut(site))
+        {
+            *count += 1;
+        }
+    }
+
+    pub fn report(&self) -> CoverageReport {
+        let scripts = self.scripts.borrow();
+        CoverageReport {
+            scripts: scripts.iter().map(ScriptCoverage::report).collect(),
+        }
+    }
+}
+
+impl ScriptCoverage {
+    fn report(&self) -> ScriptReport {
+        let mut functions = Vec::new();
+        let mut lines = BTreeMap::new();
+        if self.instrumented {
+            for (site, &count) in self.sites.iter().zip(&self.hits) {
+                match &site.function {
+                    Some(name) => functions.push(FunctionCoverage {
+                        name: name.clone(),
+                        line: site.line,
+                        count,
+                    }),
+                    None => {
+                        let line = lines.entry(site.line).or_insert(0);
+                        *line = count.max(*line);
+                    }
+                }
+            }
+        }
+        ScriptReport {
+            url: self.url.clone(),
+            instrumented: self.instrumented,
+            functions,
+            lines: lines
+                .into_iter()
+                .map(|(line, count)| LineCoverage { line, count })
+                .collect(),
+        }
+    }
+}
+
+/// Rewrite `source`, the script at `script` in a [`JsCoverage`], to call [`COUNTER`] at each
+/// site. `None` when the scan lost its place, such as at an unterminated string.
+fn instrument(source: &str, script: usize) -> Option<(String, Vec<Site>)> {
+    let mut scanner = Scanner::new(source, script);
+    while let Some(token) = scanner.next_token()? {
+        scanner.accept(token)?;
+    }
+    if !scanner.frames.is_empty() {
+        return None;
+    }
+    let mut rewritten = String::with_capacity(source.len() + scanner.insertions.len() * 24);
+    let mut copied = 0;
+    for (offset, counters) in &scanner.insertions {
+        rewritten.push_str(&source[copied..*offset]);
+        rewritten.push_str(counters);
+        copied = *offset;
+    }
+    rewritten.push_str(&source[copied..]);
+    Some((rewritten, scanner.sites))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Identifiers and keywords.
+    Word,
+    Punct,
+    /// Strings, numbers and regular expressions.
+    Literal,
+    /// A template literal, or its tail after the last `${...}`.
+    Template,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+    /// For `)`, the index of its `(`.
+    opener: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    /// Braces holding statements: a script, function body or block.
+    Code,
+    /// Any other braces: object literals, class bodies, `switch` bodies.
+    Other,
+    Paren(usize),
+    Bracket,
+    /// `${` inside a template literal.
+    Substitution,
+}
+
+/// Counters waiting for the token they go before.
+#[derive(Debug, Clone)]
+struct Pending {
+    function: Option<Site>,
+    /// At the start of a script or function body, where directives such as `'use strict'`
+    /// must stay first.
+    directives: bool,
+    /// A directive string was just passed.
+    after_string: bool,
+    /// After a `}`, where only a word surely starts a new statement.
+    after_block: bool,
+}
+
+impl Pending {
+    fn statement() -> Self {
+        Self {
+            function: None,
+            directives: false,
+            after_string: false,
+            after_block: false,
+        }
+    }
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    script: usize,
+    line_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    frames: Vec<Frame>,
+    /// Frame depths at which a `class` is waiting for its body.
+    classes: Vec<usize>,
+    /// Frame depths at which a `do` without braces is waiting for its `while`.
+    loops: Vec<usize>,
+    pending: Option<Pending>,
+    insertions: Vec<(usize, String)>,
+    sites: Vec<Site>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str, script: usize) -> Self {
+        let bytes = source.as_bytes();
+        let line_starts = std::iter::once(0)
+            .chain(
+                bytes
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, &b)| (b == b'\n').then_some(i + 1)),
+            )
+            .collect();
+        let mut pending = Pending::statement();
+        pending.directives = true;
+        let pos = if source.starts_with("#!") {
+            source.find('\n').unwrap_or(source.len())
+        } else {
+            0
+        };
+        Self {
+            source,
+            bytes,
+            pos,
+            script,
+            line_starts,
+            tokens: Vec::new(),
+            frames: Vec::new(),
+            classes: Vec::new(),
+            loops: Vec::new(),
+            pending: Some(pending),
+            insertions: Vec::new(),
+            sites: Vec::new(),
+        }
+    }
+
+    fn line(&self, offset: usize) -> u32 {
+        self.line_starts.partition_point(|&start| start <= offset) as u32
+    }
+
+    fn text(&self, index: usize) -> &'a str {
+        self.tokens
+            .get(index)
+            .map_or("", |token| &self.source[token.start..token.end])
+    }
+
+    fn word(&self, index: usize) -> Option<&'a str> {
+        let token = self.tokens.get(index)?;
+        (token.kind == Kind::Word).then(|| &self.source[token.start..token.end])
+    }
+
+    fn in_code(&self) -> bool {
+        matches!(self.frames.last(), None | Some(Frame::Code))
+    }
+
+    fn peek(&self, ahead: usize) -> u8 {
+        self.bytes.get(self.pos + ahead).copied().unwrap_or(0)
+    }
+
+    fn next_token(&mut self) -> Option<Option<Token>> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let Some(&byte) = self.bytes.get(start) else {
+            return Some(None);
+        };
+        let kind = match byte {
+            b'"' | b'\'' => {
+                self.string(byte)?;
+                Kind::Literal
+            }
+            b'`' => {
+                self.pos += 1;
+                return self.template(start).map(Some);
+            }
+            b'}' if self.frames.last() == Some(&Frame::Substitution) => {
+                self.frames.pop();
+                self.pos += 1;
+                return self.template(start).map(Some);
+            }
+            b'/' if self.regex_allowed() => {
+                self.regex()?;
+                Kind::Literal
+            }
+            b'0'..=b'9' => {
+                self.word_chars();
+                Kind::Literal
+            }
+            b'.' if self.peek(1).is_ascii_digit() => {
+                self.pos += 1;
+                self.word_chars();
+                Kind::Literal
+            }
+            b'=' if self.peek(1) == b'>' => {
+                self.pos += 2;
+                Kind::Punct
+            }
+            b'?' if self.peek(1) == b'.' && !self.peek(2).is_ascii_digit() => {
+                self.pos += 2;
+                Kind::Punct
+            }
+            _ if is_word_byte(byte) || byte == b'#' => {
+                self.pos += 1;
+                self.word_chars();
+                Kind::Word
+            }
+            _ => {
+                self.pos += 1;
+                Kind::Punct
+            }
+        };
+        Some(Some(Token {
+            kind,
+            start,
+            end: self.pos,
+            opener: None,
+        }))
+    }
+
+    fn skip_trivia(&mut self) -> Option<()> {
+        loop {
+            match self.peek(0) {
+                b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c => self.pos += 1,
+                b'/' if self.peek(1) == b'/' => self.skip_line(),
+                b'/' if self.peek(1) == b'*' => {
+                    let end = self.source[self.pos + 2..].find("*/")?;
+                    self.pos += end + 4;
+                }
+                b'<' if self.source[self.pos..].starts_with("<!--") => self.skip_line(),
+                byte if byte >= 0x80 => {
+                    let ch = self.source[self.pos..].chars().next()?;
+                    if !ch.is_whitespace() && ch != '\u{feff}' {
+                        return Some(());
+                    }
+                    self.pos += ch.len_utf8();
+                }
+                _ => return Some(()),
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.pos = self.source[self.pos..]
+            .find('\n')
+            .map_or(self.source.len(), |end| self.pos + end);
+    }
+
+    fn word_chars(&mut self) {
+        while is_word_byte(self.peek(0)) || self.peek(0).is_ascii_digit() {
+            self.pos += 1;
+        }
+    }
+
+    fn string(&mut self, quote: u8) -> Option<()> {
+        self.pos += 1;
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'\n' => return None,
+                &byte if byte == quote => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// Scan template text up to its end or its next `${`, from just past a backtick or the
+    /// `}` closing a substitution.
+    fn template(&mut self, start: usize) -> Option<Token> {
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'`' => {
+                    self.pos += 1;
+                    return Some(Token {
+                        kind: Kind::Template,
+                        start,
+                        end: self.pos,
+                        opener: None,
+                    });
+                }
+                b'$' if self.peek(1) == b'{' => {
+                    self.pos += 2;
+                    self.frames.push(Frame::Substitution);
+                    return Some(Token {
+                        kind: Kind::Punct,
+                        start,
+                        end: self.pos,
+                        opener: None,
+                    });
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn regex_allowed(&self) -> bool {
+        let Some(previous) = self.tokens.last() else {
+            return true;
+        };
+        let text = &self.source[previous.start..previous.end];
+        match previous.kind {
+            Kind::Literal | Kind::Template => false,
+            Kind::Punct => !matches!(text, ")" | "]"),
+            Kind::Word => BEFORE_EXPRESSION.contains(&text),
+        }
+    }
+
+    fn regex(&mut self) -> Option<()> {
+        self.pos += 1;
+        let mut in_class = false;
+        loop {
+            match self.bytes.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b'\n' => return None,
+                b'[' => {
+                    in_class = true;
+                    self.pos += 1;
+                }
+                b']' => {
+                    in_class = false;
+                    self.pos += 1;
+                }
+                b'/' if !in_class => {
+                    self.pos += 1;
+                    self.word_chars();
+                    return Some(());
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn accept(&mut self, mut token: Token) -> Option<()> {
+        let index = self.tokens.len();
+        let source = self.source;
+        let text = &source[token.start..token.end];
+        if let Some(pending) = self.pending.take() {
+            self.place(pending, &token, text);
+        }
+        match (token.kind, text) {
+            (Kind::Punct, "(") => self.frames.push(Frame::Paren(index)),
+            (Kind::Punct, ")") => match self.frames.pop()? {
+                Frame::Paren(opener) => token.opener = Some(opener),
+                _ => return None,
+            },
+            (Kind::Punct, "[") => self.frames.push(Frame::Bracket),
+            (Kind::Punct, "]") => {
+                if self.frames.pop()? != Frame::Bracket {
+                    return None;
+                }
+            }
+            (Kind::Punct, "{") => {
+                let (frame, function) = self.block();
+                self.frames.push(frame);
+                if frame == Frame::Code {
+                    self.pending = Some(Pending {
+                        directives: function.is_some(),
+                        function,
+                        ..Pending::statement()
+                    });
+                }
+            }
+            (Kind::Punct, "}") => {
+                let closed = self.frames.pop()?;
+                if !matches!(closed, Frame::Code | Frame::Other) {
+                    return None;
+                }
+                let depth = self.frames.len();
+                self.classes.retain(|&class| class <= depth);
+                self.loops.retain(|&open| open <= depth);
+                if self.in_code() {
+                    self.pending = Some(Pending {
+                        after_block: true,
+                        ..Pending::statement()
+                    });
+                }
+            }
+            (Kind::Punct, ";") if self.in_code() && self.pending.is_none() => {
+                self.pending = Some(Pending::statement());
+            }
+            (Kind::Word, "class") if !matches!(self.text(index.wrapping_sub(1)), "." | "?.") => {
+                self.classes.push(self.frames.len());
+            }
+            (Kind::Word, "do") if !matches!(self.text(index.wrapping_sub(1)), "." | "?.") => {
+                self.loops.push(self.frames.len());
+            }
+            (Kind::Word, "while") if self.closes_loop() => {
+                self.loops.pop();
+            }
+            _ => {}
+        }
+        self.tokens.push(token);
+        Some(())
+    }
+
+    /// Put the counters `pending` holds before `token`, or keep waiting past directives.
+    fn place(&mut self, pending: Pending, token: &Token, text: &str) {
+        if pending.directives {
+            if token.kind == Kind::Literal && matches!(text.as_bytes()[0], b'"' | b'\'') {
+                self.pending = Some(Pending {
+                    after_string: true,
+                    ..pending
+                });
+                return;
+            }
+            if pending.after_string {
+                if text == ";" {
+                    self.pending = Some(Pending {
+                        after_string: false,
+                        ..pending
+                    });
+                    return;
+                }
+                // Without a semicolon, only a word on a later line surely starts a statement.
+                let previous_end = self.tokens.last().map_or(0, |previous| previous.end);
+                if token.kind != Kind::Word || self.line(token.start) == self.line(previous_end) {
+                    return;
+                }
+            }
+        }
+        if pending.after_block && (token.kind != Kind::Word || CONTINUATIONS.contains(&text)) {
+            return;
+        }
+        // `if (a) b; else c` and `do a; while (b)` go on past the semicolon.
+        if text == "else" || (text == "while" && self.closes_loop()) {
+            return;
+        }
+        let mut counters = String::new();
+        if let Some(function) = pending.function {
+            self.counter(&mut counters, function);
+        }
+        if text != "}" {
+            let line = self.line(token.start);
+            self.counter(
+                &mut counters,
+                Site {
+                    line,
+                    function: None,
+                },
+            );
+        }
+        if !counters.is_empty() {
+            self.insertions.push((token.start, counters));
+        }
+    }
+
+    /// Whether a `while` here ends a `do` loop rather than starting a loop of its own.
+    fn closes_loop(&self) -> bool {
+        self.loops.last() == Some(&self.frames.len())
+    }
+
+    fn counter(&mut self, counters: &mut String, site: Site) {
+        counters.push_str(&format!("{COUNTER}({},{});", self.script, self.sites.len()));
+        self.sites.push(site);
+    }
+
+    /// What the `{` about to be pushed opens, and the function whose body it is, if any.
+    fn block(&mut self) -> (Frame, Option<Site>) {
+        if self.classes.last() == Some(&self.frames.len()) {
+            self.classes.pop();
+            return (Frame::Other, None);
+        }
+        let Some(previous) = self.tokens.last().copied() else {
+            return (Frame::Code, None);
+        };
+        let last = self.tokens.len() - 1;
+        match (previous.kind, self.text(last)) {
+            (Kind::Punct, ")") => {
+                let Some(opener) = previous.opener else {
+                    return (Frame::Other, None);
+                };
+                let before = opener.checked_sub(1).and_then(|i| self.word(i));
+                match before {
+                    Some("if" | "for" | "while" | "with" | "catch") => (Frame::Code, None),
+                    Some("await") if self.word(opener.wrapping_sub(2)) == Some("for") => {
+                        (Frame::Code, None)
+                    }
+                    Some("switch") => (Frame::Other, None),
+                    _ => {
+                        let site = Site {
+                            line: self.line(self.tokens[opener].start),
+                            function: Some(self.function_name(opener)),
+                        };
+                        (Frame::Code, Some(site))
+                    }
+                }
+            }
+            (Kind::Punct, "=>") => {
+                let start = match self.tokens.get(last.wrapping_sub(1)) {
+                    Some(Token {
+                        opener: Some(opener),
+                        ..
+                    }) => *opener,
+                    _ => last.saturating_sub(1),
+                };
+                let start = match self.word(start.wrapping_sub(1)) {
+                    Some("async") => start - 1,
+                    _ => start,
+                };
+                let site = Site {
+                    line: self.line(self.tokens[start].start),
+                    function: Some(self.assigned_name(start)),
+                };
+                (Frame::Code, Some(site))
+            }
+            (Kind::Word, "else" | "try" | "finally" | "do") => (Frame::Code, None),
+            (Kind::Punct, ";" | "{" | "}") if self.in_code() => (Frame::Code, None),
+            _ => (Frame::Other, None),
+        }
+    }
+
+    /// The name of the function whose parameters open at `opener`.
+    fn function_name(&self, opener: usize) -> String {
+        match self.word(opener.wrapping_sub(1)) {
+            Some(name) if name != "function" => name.to_string(),
+            _ => {
+                let mut start = opener;
+                while start > 0 && matches!(self.text(start - 1), "function" | "*" | "async") {
+                    start -= 1;
+                }
+                self.assigned_name(start)
+            }
+        }
+    }
+
+    /// The name a function expression starting at `start` is assigned to, as in `x = ...`
+    /// or `x: ...`.
+    fn assigned_name(&self, start: usize) -> String {
+        if start >= 2 && matches!(self.text(start - 1), "=" | ":") {
+            let name = self.text(start - 2);
+            let token = self.tokens[start - 2];
+            if token.kind == Kind::Word || (token.kind == Kind::Literal && name.len() >= 2) {
+                return name.trim_matches(|ch| ch == '"' || ch == '\'').to_string();
+            }
+        }
+        ANONYMOUS.to_string()
+    }
+}
+
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || matches!(byte, b'_' | b'$' | b'\\') || byte >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::js::runtime::QuickJsEngine;
+
+    fn counters(rewritten: &str) -> usize {
+        rewritten.matches(COUNTER).count()
+    }
+
+    #[test]
+    fn counters_go_before_statements_and_after_directives() {
+        let source = "'use strict';\nfunction add(a, b) {\n  'use strict'\n  return a + b;\n}\n\
+                      const twice = (x) => { return add(x, x); };\nif (twice(1) > 1) { done(); } \
+                      else { other(); }\n";
+        let (rewritten, sites) = instrument(source, 3).unwrap();
+        assert!(rewritten.starts_with("'use strict';\n__frontier_cover(3,0);function add"));
+        assert!(rewritten.contains("'use strict'\n  __frontier_cover(3,1);__frontier_cover(3,2);"));
+        assert!(
+            rewritten.contains("done(); } else { __frontier_cover"),
+            "{rewritten}"
+        );
+        let functions: Vec<_> = sites
+            .iter()
+            .filter_map(|site| site.function.clone())
+            .collect();
+        assert_eq!(functions, ["add", "twice"]);
+        assert_eq!(counters(&rewritten), sites.len());
+    }
+
+    #[test]
+    fn literals_classes_and_switches_are_left_alone() {
+        let source = r#"
+const re = /[;{}]/g, text = `a;${ {b: 1}.b }{`, half = 4 / 2 / 1;
+class Point extends Base { x = 1; get y() { return 2; } }
+const shape = { area() { return 1; }, size: 2 };
+switch (half) { case 2: hit(); break; default: miss(); }
+"#;
+        let (rewritten, sites) = instrument(source, 0).unwrap();
+        assert!(
+            rewritten.contains("/[;{}]/g, text = `a;${ {b: 1}.b }{`"),
+            "{rewritten}"
+        );
+        assert!(
+            rewritten.contains("{ x = 1; get y() { __frontier_cover"),
+            "{rewritten}"
+        );
+        assert!(rewritten.contains("{ case 2: hit(); break; default: miss(); }"));
+        let functions: Vec<_> = sites
+            .iter()
+            .filter_map(|site| site.function.clone())
+            .collect();
+        assert_eq!(functions, ["y", "area"]);
+        let (rewritten, _) = instrument("if (a) b(); else c(); do d(); while (e);", 0).unwrap();
+        assert!(rewritten.contains("b(); else c(); __frontier_cover(0,1);do d(); while"));
+        assert!(instrument("let s = 'open;", 0).is_none());
+        assert!(instrument("if (a) {", 0).is_none());
+    }
+
+    #[test]
+    fn rewritten_scripts_count_what_ran() {
+        let engine = QuickJsEngine::new().unwrap();
+        let coverage = JsCoverage::default();
+        engine.set_coverage(coverage.clone()).unwrap();
+        let source =
+            "function used(n) {\n  return n * 2;\n}\nfunction unused() {\n  return 0;\n}\n\
+                      var total = 0;\nfor (let i = 0; i < 3; i++) { total += used(i); }\n";
+        engine.eval_script(source, "page.js").unwrap();
+        engine
+            .eval_script("var broken = ;", "broken.js")
+            .unwrap_err();
+
+        let report = coverage.report();
+        let page = report.script("page.js").unwrap();
+        assert!(page.instrumented);
+        let count = |name: &str| {
+            page.functions
+                .iter()
+                .find(|f| f.name == name)
+                .unwrap()
+                .count
+        };
+        assert_eq!((count("used"), count("unused")), (3, 0));
+        let line = |line: u32| page.lines.iter().find(|l| l.line == line).map(|l| l.count);
+        assert_eq!((line(2), line(5), line(8)), (Some(3), Some(0), Some(3)));
+        assert_eq!(page.covered_functions(), 1);
+        assert!(!report.script("broken.js").unwrap().instrumented);
+        assert_eq!(engine.eval_with::<i32>("total", "read.js").unwrap(), 6);
+    }
+}
@@ -0,0 +1,839 @@
+//! The CSS Object Model: `document.styleSheets`, the `sheet` of `<style>` and
+//! `<link rel="stylesheet">` elements, and constructable `CSSStyleSheet`s adopted through
+//! `adoptedStyleSheets`.
+//!
+//! Sheets are parsed in script from the text of their element. Editing the rules of a
+//! `<style>`'s sheet hands the serialized rules to
+//! [`BlitzJsBridge::set_style_rules`](super::bridge::BlitzJsBridge::set_style_rules), which
+//! renders them in place of the element's text while `textContent` keeps what the page wrote;
+//! setting the text again starts a fresh sheet from it. The adopted sheets of the document or
+//! a shadow root are rendered together by
+//! [`BlitzJsBridge::set_adopted_styles`](super::bridge::BlitzJsBridge::set_adopted_styles),
+//! after every other style of that tree.
+//!
+//! The parser splits rules and declarations without validating them: Stylo drops what it does
+//! not understand when it parses the rendered text. The rules of linked sheets are not
+//! exposed, as if they were cross-origin.
+
+/// JS half: `CSSStyleSheet`, the rule classes, `StyleSheetList`, `sheet` on elements, and
+/// `styleSheets` and `adoptedStyleSheets` on documents and shadow roots.
+pub(crate) const CSSOM_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const domSetAdoptedStyles = global.__frontier_dom_set_adopted_styles;
+    const domSetStyleRules = global.__frontier_dom_set_style_rules;
+    const frontier = global.frontier;
+    if (!global.Element || !global.Document || !global.ShadowRoot) {
+        return;
+    }
+    const SHEETS = new WeakMap();
+    const RULES = new WeakMap();
+    // Owner element -> { key, sheet }, where key is the text or href the sheet was made from.
+    const ELEMENT_SHEETS = new WeakMap();
+    // Document or shadow root -> its adopted sheets.
+    const ADOPTED = new WeakMap();
+
+    const illegalConstructor = () => {
+        throw new TypeError('Illegal constructor');
+    };
+
+    const sheetState = (sheet) => {
+        const state = SHEETS.get(sheet);
+        if (!state) {
+            throw new TypeError('Illegal invocation');
+        }
+        return state;
+    };
+
+    const ruleState = (rule) => {
+        const state = RULES.get(rule);
+        if (!state) {
+            throw new TypeError('Illegal invocation');
+        }
+        return state;
+    };
+
+    // Index just past the string or comment starting at `index`, or `index` if none does.
+    const skipOpaque = (text, index) => {
+        const ch = text[index];
+        if (ch === '"' || ch === "'") {
+            let i = index + 1;
+            while (i < text.length && text[i] !== ch) {
+                i += text[i] === '\\' ? 2 : 1;
+            }
+            return Math.min(i + 1, text.length);
+        }
+        if (ch === '/' && text[index + 1] === '*') {
+            const end = text.indexOf('*/', index + 2);
+            return end === -1 ? text.length : end + 2;
+        }
+        return index;
+    };
+
+    // Index of the first of `stops` outside brackets, strings and comments, from `index`.
+    const scan = (text, index, stops) => {
+        let depth = 0;
+        let i = index;
+        while (i < text.length) {
+            const skipped = skipOpaque(text, i);
+            if (skipped !== i) {
+                i = skipped;
+                continue;
+            }
+            const ch = text[i];
+            if (depth === 0 && stops.includes(ch)) {
+                return i;
+            }
+            if (ch === '{' || ch === '(' || ch === '[') {
+                depth += 1;
+            } else if (ch === '}' || ch === ')' || ch === ']') {
+                depth -= 1;
+            }
+            i += 1;
+        }
+        return text.length;
+    };
+
+    const stripComments = (text) => {
+        let output = '';
+        let i = 0;
+        while (i < text.length) {
+            const skipped = skipOpaque(text, i);
+            if (skipped === i) {
+                output += text[i];
+                i += 1;
+            } else {
+                output += text[i] === '/' ? ' ' : text.slice(i, skipped);
+                i = skipped;
+            }
+        }
+        return output;
+    };
+
+    const collapse = (text) => stripComments(text).trim().replace(/\s+/g, ' ');
+
+    const parseDeclarations = (text) => {
+        const declarations = [];
+        const source = stripComments(text);
+        let i = 0;
+        while (i < source.length) {
+            const end = scan(source, i, ';');
+            const part = source.slice(i, end);
+            i = end + 1;
+            const colon = part.indexOf(':');
+            if (colon === -1) {
+                continue;
+            }
+            const name = propertyName(part.slice(0, colon).trim());
+            let value = part.slice(colon + 1).trim();
+            const bang = /!\s*important\s*$/i.exec(value);
+            if (bang) {
+                value = value.slice(0, bang.index).trim();
+            }
+            if (name && value) {
+                setDeclaration(declarations, name, value, bang ? 'important' : '');
+            }
+        }
+        return declarations;
+    };
+
+    // Custom properties keep their case.
+    const propertyName = (name) => (name.startsWith('--') ? name : name.toLowerCase());
+
+    const setDeclaration = (declarations, name, value, priority) => {
+        const existing = declarations.find((declaration) => declaration.name === name);
+        if (existing) {
+            existing.value = value;
+            existing.priority = priority;
+        } else {
+            declarations.push({ name, value, priority });
+        }
+    };
+
+    const declarationsText = (declarations) =>
+        declarations
+            .map(({ name, value, priority }) =>
+                priority ? `${name}: ${value} !${priority};` : `${name}: ${value};`,
+            )
+            .join(' ');
+
+    // Rules as plain descriptions; a style rule without a selector is null.
+    const parseRules = (text) => {
+        const rules = [];
+        let i = 0;
+        while (i < text.length) {
+            const open = scan(text, i, '{;');
+            const prelude = collapse(text.slice(i, open));
+            if (open >= text.length) {
+                if (prelude !== '') {
+                    rules.push(null);
+                }
+                break;
+            }
+            if (text[open] === ';') {
+                rules.push(prelude.startsWith('@') ? { kind: 'at', text: `${prelude};` } : null);
+                i = open + 1;
+                continue;
+            }
+            const close = scan(text, open + 1, '}');
+            rules.push(describeRule(prelude, text.slice(open + 1, close)));
+            i = close + 1;
+        }
+        return rules;
+    };
+
+    const describeRule = (prelude, body) => {
+        const at = /^@([-\w]+)\s*(.*)$/.exec(prelude);
+        if (!at) {
+            return prelude === ''
+                ? null
+                : { kind: 'style', selector: prelude, declarations: parseDeclarations(body) };
+        }
+        const name = at[1].toLowerCase();
+        if (name === 'media' || name === 'supports') {
+            const rules = parseRules(body).filter((rule) => rule !== null);
+            return { kind: 'group', name, condition: at[2], rules };
+        }
+        const inner = collapse(body);
+        return { kind: 'at', text: inner ? `${prelude} { ${inner} }` : `${prelude} { }` };
+    };
+
+    const AT_RULE_TYPES = {
+        charset: 2,
+        import: 3,
+        'font-face': 5,
+        page: 6,
+        keyframes: 7,
+        namespace: 10,
+    };
+
+    const ruleText = (state) => {
+        switch (state.kind) {
+            case 'style': {
+                const body = declarationsText(state.declarations);
+                return body ? `${state.selector} { ${body} }` : `${state.selector} { }`;
+            }
+            case 'group': {
+                const inner = state.rules.map((rule) => `  ${ruleText(ruleState(rule))}\n`);
+                return `@${state.name} ${state.condition} {\n${inner.join('')}}`;
+            }
+            default:
+                return state.text;
+        }
+    };
+
+    const rulesText = (rules) => rules.map((rule) => ruleText(ruleState(rule))).join('\n');
+
+    // `rule.style`: the declarations of a style rule, also reachable as camelCase properties.
+    const createStyleDeclaration = (state, rule) => {
+        const cssName = (prop) =>
+            prop === 'cssFloat' ? 'float' : prop.replace(/[A-Z]/g, (ch) => `-${ch.toLowerCase()}`);
+        const find = (name) =>
+            state.declarations.find((declaration) => declaration.name === propertyName(name));
+        const api = {
+            getPropertyValue(name) {
+                return find(String(name))?.value ?? '';
+            },
+            getPropertyPriority(name) {
+                return find(String(name))?.priority ?? '';
+            },
+            setProperty(name, value, priority = '') {
+                const text = value == null ? '' : String(value).trim();
+                if (text === '') {
+                    api.removeProperty(name);
+                    return;
+                }
+                const level = String(priority).toLowerCase();
+                if (level !== '' && level !== 'important') {
+                    return;
+                }
+                setDeclaration(state.declarations, propertyName(String(name)), text, level);
+                touch(state);
+            },
+            removeProperty(name) {
+                const declaration = find(String(name));
+                if (!declaration) {
+                    return '';
+                }
+                state.declarations.splice(state.declarations.indexOf(declaration), 1);
+                touch(state);
+                return declaration.value;
+            },
+            item(index) {
+                return state.declarations[index]?.name ?? '';
+            },
+            get length() {
+                return state.declarations.length;
+            },
+            get cssText() {
+                return declarationsText(state.declarations);
+            },
+            set cssText(value) {
+                state.declarations = parseDeclarations(String(value));
+                touch(state);
+            },
+            get parentRule() {
+                return rule;
+            },
+        };
+        return new Proxy(api, {
+            get(target, prop, receiver) {
+                if (typeof prop !== 'string' || prop in target) {
+                    return Reflect.get(target, prop, receiver);
+                }
+                if (/^\d+$/.test(prop)) {
+                    return state.declarations[Number(prop)]?.name;
+                }
+                return target.getPropertyValue(cssName(prop));
+            },
+            set(target, prop, value, receiver) {
+                if (typeof prop !== 'string' || prop in target) {
+                    return Reflect.set(target, prop, value, receiver);
+                }
+                target.setProperty(cssName(prop), value);
+                return true;
+            },
+        });
+    };
+
+    const createRule = (description, sheet, parentRule) => {
+        let proto = CSSRule.prototype;
+        if (description.kind === 'style') {
+            proto = CSSStyleRule.prototype;
+        } else if (description.kind === 'group') {
+            const group = description.name === 'media' ? CSSMediaRule : CSSSupportsRule;
+            proto = group.prototype;
+        }
+        const rule = Object.create(proto);
+        const state = { ...description, sheet, parentRule, list: null };
+        RULES.set(rule, state);
+        if (description.kind === 'group') {
+            state.rules = description.rules.map((child) => createRule(child, sheet, rule));
+        } else if (description.kind === 'style') {
+            state.declarations = description.declarations.map((declaration) => ({
+                ...declaration,
+            }));
+            state.style = createStyleDeclaration(state, rule);
+        }
+        return rule;
+    };
+
+    // Cut a removed rule, and the rules in it, loose from its sheet.
+    const orphan = (rule) => {
+        const state = ruleState(rule);
+        state.sheet = null;
+        state.parentRule = null;
+        (state.rules ?? []).forEach(orphan);
+    };
+
+    // Hand the rules of an edited sheet to the style engine.
+    const touch = (state) => {
+        if (state.sheet) {
+            sheetChanged(state.sheet);
+        }
+    };
+
+    const ruleList = (owner) => {
+        if (!owner.list) {
+            const list = Object.create(CSSRuleList.prototype);
+            owner.rules.forEach((rule, index) => {
+                list[index] = rule;
+            });
+            Object.defineProperty(list, 'length', { value: owner.rules.length });
+            owner.list = Object.freeze(list);
+        }
+        return owner.list;
+    };
+
+    const insertInto = (owner, sheet, parentRule, rule, index, where) => {
+        const at = index === undefined ? 0 : Number(index) >>> 0;
+        if (at > owner.rules.length) {
+            throw new global.DOMException(
+                `Failed to execute 'insertRule' on '${where}': The index provided (${at}) is ` +
+                    `larger than the maximum index (${owner.rules.length}).`,
+                'IndexSizeError',
+            );
+        }
+        const text = String(rule);
+        const parsed = parseRules(text);
+        const valid =
+            parsed.length === 1 &&
+            parsed[0] !== null &&
+            !(parentRule && parsed[0].kind === 'at' && /^@import\b/i.test(parsed[0].text));
+        if (!valid) {
+            throw new global.DOMException(
+                `Failed to execute 'insertRule' on '${where}': Failed to parse the rule '${text}'.`,
+                'SyntaxError',
+            );
+        }
+        owner.rules.splice(at, 0, createRule(parsed[0], sheet, parentRule));
+        owner.list = null;
+        if (sheet) {
+            sheetChanged(sheet);
+        }
+        return at;
+    };
+
+    const deleteFrom = (owner, sheet, index, where) => {
+        const at = Number(index) >>> 0;
+        if (at >= owner.rules.length) {
+            throw new global.DOMException(
+                `Failed to execute 'deleteRule' on '${where}': The index provided (${at}) is ` +
+                    `larger than the maximum index (${owner.rules.length - 1}).`,
+                'IndexSizeError',
+            );
+        }
+        const [removed] = owner.rules.splice(at, 1);
+        orphan(removed);
+        owner.list = null;
+        if (sheet) {
+            sheetChanged(sheet);
+        }
+    };
+
+    class CSSRuleList {
+        constructor() {
+            illegalConstructor();
+        }
+
+        item(index) {
+            return this[index] ?? null;
+        }
+    }
+    CSSRuleList.prototype[Symbol.iterator] = Array.prototype[Symbol.iterator];
+
+    class CSSRule {
+        constructor() {
+            illegalConstructor();
+        }
+
+        get type() {
+            const state = ruleState(this);
+            if (state.kind !== 'at') {
+                return 0;
+            }
+            const name = /^@([-\w]+)/.exec(state.text)[1].toLowerCase();
+            return AT_RULE_TYPES[name] ?? 0;
+        }
+
+        get cssText() {
+            return ruleText(ruleState(this));
+        }
+
+        set cssText(_value) {}
+
+        get parentStyleSheet() {
+            return ruleState(this).sheet;
+        }
+
+        get parentRule() {
+            return ruleState(this).parentRule;
+        }
+    }
+    Object.assign(CSSRule, {
+        STYLE_RULE: 1,
+        CHARSET_RULE: 2,
+        IMPORT_RULE: 3,
+        MEDIA_RULE: 4,
+        FONT_FACE_RULE: 5,
+        PAGE_RULE: 6,
+        KEYFRAMES_RULE: 7,
+        NAMESPACE_RULE: 10,
+        SUPPORTS_RULE: 12,
+    });
+
+    class CSSStyleRule extends CSSRule {
+        get type() {
+            return 1;
+        }
+
+        get selectorText() {
+            return ruleState(this).selector;
+        }
+
+        set selectorText(value) {
+            const selector = collapse(String(value));
+            if (selector !== '' && !/[{};]/.test(selector)) {
+                const state = ruleState(this);
+                state.selector = selector;
+                touch(state);
+            }
+        }
+
+        get style() {
+            return ruleState(this).style;
+        }
+
+        set style(value) {
+            ruleState(this).style.cssText = value;
+        }
+    }
+
+    class CSSGroupingRule extends CSSRule {
+        get cssRules() {
+            return ruleList(ruleState(this));
+        }
+
+        insertRule(rule, index) {
+            const state = ruleState(this);
+            return insertInto(state, state.sheet, this, rule, index, this.constructor.name);
+        }
+
+        deleteRule(index) {
+            const state = ruleState(this);
+            deleteFrom(state, state.sheet, index, this.constructor.name);
+        }
+
+        get conditionText() {
+            return ruleState(this).condition;
+        }
+    }
+
+    class CSSMediaRule extends CSSGroupingRule {
+        get type() {
+            return 4;
+        }
+
+        get media() {
+            const condition = ruleState(this).condition;
+            return { mediaText: condition, length: condition ? 1 : 0, toString: () => condition };
+        }
+    }
+
+    class CSSSupportsRule extends CSSGroupingRule {
+        get type() {
+            return 12;
+        }
+    }
+
+    const mediaList = (state) => ({
+        get mediaText() {
+            return state.media;
+        },
+        set mediaText(value) {
+            state.media = String(value ?? '');
+        },
+        get length() {
+            return state.media ? state.media.split(',').length : 0;
+        },
+        toString() {
+            return state.media;
+        },
+    });
+
+    const makeSheet = (init) => {
+        const sheet = Object.create(CSSStyleSheet.prototype);
+        const state = {
+            constructed: false,
+            ownerNode: null,
+            href: null,
+            title: null,
+            media: '',
+            disabled: false,
+            rules: [],
+            list: null,
+            adopters: new Set(),
+            ...init,
+        };
+        SHEETS.set(sheet, state);
+        return sheet;
+    };
+
+    const accessibleRules = (sheet, where) => {
+        const state = sheetState(sheet);
+        if (state.rules === null) {
+            throw new global.DOMException(
+                `Failed to ${where} on 'CSSStyleSheet': Cannot access rules`,
+                'SecurityError',
+            );
+        }
+        return state;
+    };
+
+    const replaceRules = (sheet, text) => {
+        const state = sheetState(sheet);
+        if (!state.constructed) {
+            throw new global.DOMException(
+                "Failed to replace the rules of 'CSSStyleSheet': Can't call replace on " +
+                    'non-constructed CSSStyleSheets.',
+                'NotAllowedError',
+            );
+        }
+        // Constructed sheets cannot import.
+        const parsed = parseRules(String(text)).filter(
+            (rule) => rule !== null && !(rule.kind === 'at' && /^@import\b/i.test(rule.text)),
+        );
+        state.rules.forEach(orphan);
+        state.rules = parsed.map((rule) => createRule(rule, sheet, null));
+        state.list = null;
+        sheetChanged(sheet);
+    };
+
+    class StyleSheet {
+        constructor() {
+            illegalConstructor();
+        }
+    }
+
+    class CSSStyleSheet extends StyleSheet {
+        constructor(options = {}) {
+            const init = options ?? {};
+            const sheet = makeSheet({
+                constructed: true,
+                media: String(init.media ?? ''),
+                disabled: Boolean(init.disabled),
+            });
+            return sheet;
+        }
+
+        get type() {
+            return 'text/css';
+        }
+
+        get href() {
+            return sheetState(this).href;
+        }
+
+        get ownerNode() {
+            return sheetState(this).ownerNode;
+        }
+
+        get ownerRule() {
+            return null;
+        }
+
+        get parentStyleSheet() {
+            return null;
+        }
+
+        get title() {
+            return sheetState(this).title;
+        }
+
+        get media() {
+            return mediaList(sheetState(this));
+        }
+
+        get disabled() {
+            return sheetState(this).disabled;
+        }
+
+        set disabled(value) {
+            const state = sheetState(this);
+            if (state.disabled !== Boolean(value)) {
+                state.disabled = Boolean(value);
+                sheetChanged(this);
+            }
+        }
+
+        get cssRules() {
+            return ruleList(accessibleRules(this, "read the 'cssRules' property"));
+        }
+
+        get rules() {
+            return this.cssRules;
+        }
+
+        insertRule(rule, index) {
+            const state = accessibleRules(this, "execute 'insertRule'");
+            return insertInto(state, this, null, rule, index, 'CSSStyleSheet');
+        }
+
+        deleteRule(index) {
+            const state = accessibleRules(this, "execute 'deleteRule'");
+            deleteFrom(state, this, index, 'CSSStyleSheet');
+        }
+
+        replaceSync(text) {
+            replaceRules(this, text);
+        }
+
+        replace(text) {
+            try {
+                replaceRules(this, text);
+                return Promise.resolve(this);
+            } catch (err) {
+                return Promise.reject(err);
+            }
+        }
+    }
+
+    const sheetCss = (sheet) => {
+        const state = sheetState(sheet);
+        return state.disabled || state.rules === null ? '' : rulesText(state.rules);
+    };
+
+    const sheetChanged = (sheet) => {
+        const state = sheetState(sheet);
+        const owner = state.ownerNode;
+        if (owner && ELEMENT_SHEETS.get(owner)?.sheet === sheet) {
+            const handle = frontier.__handleOf(owner);
+            if (handle != null) {
+                domSetStyleRules(handle, sheetCss(sheet));
+            }
+        }
+        state.adopters.forEach(renderAdopted);
+    };
+
+    const renderAdopted = (tree) => {
+        const handle = frontier.__handleOf(tree);
+        if (handle == null) {
+            return;
+        }
+        const sheets = ADOPTED.get(tree) ?? [];
+        const css = sheets
+            .map(sheetCss)
+            .filter((text) => text !== '')
+            .join('\n');
+        domSetAdoptedStyles(handle, css);
+    };
+
+    const isStylesheetLink = (element) =>
+        String(element.getAttribute('rel') ?? '')
+            .toLowerCase()
+            .split(/\s+/)
+            .includes('stylesheet');
+
+    // The sheet of a connected `<style>`, or of a `<link rel="stylesheet">`, made again once
+    // the text or href it came from changes.
+    const elementSheet = (element) => {
+        const name = element.localName;
+        const isStyle = name === 'style';
+        if (!(isStyle || (name === 'link' && isStylesheetLink(element))) || !element.isConnected) {
+            ELEMENT_SHEETS.delete(element);
+            return null;
+        }
+        const key = isStyle ? element.textContent ?? '' : element.getAttribute('href') ?? '';
+        const cached = ELEMENT_SHEETS.get(element);
+        if (cached && cached.key === key) {
+            return cached.sheet;
+        }
+        const sheet = makeSheet({
+            ownerNode: element,
+            href: isStyle ? null : key,
+            title: element.getAttribute('title'),
+            media: element.getAttribute('media') ?? '',
+            rules: null,
+        });
+        if (isStyle) {
+            const state = sheetState(sheet);
+            state.rules = parseRules(key)
+                .filter((rule) => rule !== null)
+                .map((rule) => createRule(rule, sheet, null));
+        }
+        ELEMENT_SHEETS.set(element, { key, sheet });
+        return sheet;
+    };
+
+    class StyleSheetList {
+        constructor() {
+            illegalConstructor();
+        }
+
+        item(index) {
+            return this[index] ?? null;
+        }
+    }
+    StyleSheetList.prototype[Symbol.iterator] = Array.prototype[Symbol.iterator];
+
+    // The sheets of the style and link elements of a tree, in tree order.
+    const treeSheets = (root) => {
+        const sheets = [];
+        const visit = (node) => {
+            for (const child of node.childNodes) {
+                if (child.nodeType !== 1) {
+                    continue;
+                }
+                const sheet = elementSheet(child);
+                if (sheet) {
+                    sheets.push(sheet);
+                }
+                visit(child);
+            }
+        };
+        visit(root);
+        const list = Object.create(StyleSheetList.prototype);
+        sheets.forEach((sheet, index) => {
+            list[index] = sheet;
+        });
+        Object.defineProperty(list, 'length', { value: sheets.length });
+        return Object.freeze(list);
+    };
+
+    const adoptSheets = (tree, value) => {
+        if (value == null || typeof value[Symbol.iterator] !== 'function') {
+            throw new TypeError("Failed to set the 'adoptedStyleSheets' property: not a sequence.");
+        }
+        const sheets = Array.from(value);
+        for (const sheet of sheets) {
+            if (!SHEETS.has(sheet) || !sheetState(sheet).constructed) {
+                throw new global.DOMException(
+                    "Failed to set the 'adoptedStyleSheets' property: Can't adopt " +
+                        'non-constructed stylesheets.',
+                    'NotAllowedError',
+                );
+            }
+        }
+        (ADOPTED.get(tree) ?? []).forEach((sheet) => sheetState(sheet).adopters.delete(tree));
+        sheets.forEach((sheet) => sheetState(sheet).adopters.add(tree));
+        ADOPTED.set(tree, sheets);
+        renderAdopted(tree);
+    };
+
+    // `adoptedStyleSheets` reads as an array whose in-place edits are adopted too.
+    const adoptedArray = (tree) =>
+        new Proxy(ADOPTED.get(tree) ?? [], {
+            set(target, prop, value) {
+                const next = target.slice();
+                next[prop] = value;
+                adoptSheets(tree, next.filter((sheet) => sheet !== undefined));
+                target[prop] = value;
+                return true;
+            },
+            deleteProperty(target, prop) {
+                const next = target.slice();
+                delete next[prop];
+                adoptSheets(tree, next.filter((sheet) => sheet !== undefined));
+                delete target[prop];
+                return true;
+            },
+        });
+
+    for (const proto of [global.Document.prototype, global.ShadowRoot.prototype]) {
+        Object.defineProperty(proto, 'styleSheets', {
+            get() {
+                return treeSheets(this);
+            },
+            configurable: true,
+        });
+        Object.defineProperty(proto, 'adoptedStyleSheets', {
+            get() {
+                return adoptedArray(this);
+            },
+            set(value) {
+                adoptSheets(this, value);
+            },
+            configurable: true,
+        });
+    }
+
+    Object.defineProperty(global.Element.prototype, 'sheet', {
+        get() {
+            return elementSheet(this);
+        },
+        configurable: true,
+    });
+
+    Object.assign(global, {
+        StyleSheet,
+        CSSStyleSheet,
+        StyleSheetList,
+        CSSRuleList,
+        CSSRule,
+        CSSStyleRule,
+        CSSGroupingRule,
+        CSSMediaRule,
+        CSSSupportsRule,
+    });
+})();
+"#;
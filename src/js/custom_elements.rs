@@ -0,0 +1,581 @@
+//! Custom elements (`customElements.define` and friends).
+//!
+//! The registry lives next to the DOM state so the mutation pipeline can drive it: every
+//! structural change [`DomState`](super::dom::DomState) records compares the connected
+//! elements with the registry's instances and queues upgrades and connected/disconnected
+//! [`Reaction`]s, and changes to observed attributes queue `attributeChangedCallback`s with
+//! the old value. The JS half takes the queue when the outermost DOM call returns, the way
+//! `[CEReactions]` does, and runs the constructors and callbacks. Only autonomous custom
+//! elements (`<my-element>`) are supported, not customized built-ins (`is="..."`).
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+/// Names the HTML spec reserves even though they look like custom element names.
+const RESERVED_NAMES: [&str; 8] = [
+    "annotation-xml",
+    "color-profile",
+    "font-face",
+    "font-face-src",
+    "font-face-uri",
+    "font-face-format",
+    "font-face-name",
+    "missing-glyph",
+];
+
+/// Whether `name` is a valid custom element name: a lowercase ASCII letter, then name
+/// characters including at least one hyphen, and not one of the reserved names.
+pub fn is_valid_custom_element_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_lowercase = chars.next().is_some_and(|ch| ch.is_ascii_lowercase());
+    starts_lowercase
+        && name.contains('-')
+        && chars.all(|ch| matches!(ch, 'a'..='z' | '0'..='9' | '-' | '.' | '_') || !ch.is_ascii())
+        && !RESERVED_NAMES.contains(&name)
+}
+
+/// Work the JS half has to do for a custom element, in the order it was queued.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reaction {
+    /// Run the definition's constructor on an element that existed before it was defined.
+    Upgrade {
+        handle: String,
+    },
+    Connected {
+        handle: String,
+    },
+    Disconnected {
+        handle: String,
+    },
+    AttributeChanged {
+        handle: String,
+        name: String,
+        old_value: Option<String>,
+        new_value: Option<String>,
+        namespace: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Instance {
+    name: String,
+    connected: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct CustomElementRegistry {
+    /// Defined names and their observed attributes.
+    definitions: HashMap<String, Vec<String>>,
+    /// Elements running a definition's constructor, keyed by node id.
+    instances: BTreeMap<usize, Instance>,
+    reactions: Vec<Reaction>,
+}
+
+impl CustomElementRegistry {
+    pub fn has_definitions(&self) -> bool {
+        !self.definitions.is_empty()
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    /// Register `name`. Elements already in the document are upgraded by the next
+    /// [`sync`](Self::sync).
+    pub fn define(&mut self, name: &str, observed_attributes: Vec<String>) -> Result<()> {
+        if !is_valid_custom_element_name(name) {
+            return Err(anyhow!(
+                "SyntaxError: '{name}' is not a valid custom element name"
+            ));
+        }
+        if self.is_defined(name) {
+            return Err(anyhow!(
+                "NotSupportedError: '{name}' has already been defined"
+            ));
+        }
+        self.definitions
+            .insert(name.to_string(), observed_attributes);
+        Ok(())
+    }
+
+    /// `node_id` was created as a `name` element, so it got its constructor run right away.
+    pub fn created(&mut self, node_id: usize, name: &str) {
+        let name = name.to_ascii_lowercase();
+        if self.is_defined(&name) {
+            self.instances.insert(
+                node_id,
+                Instance {
+                    name,
+                    connected: false,
+                },
+            );
+        }
+    }
+
+    /// Whether a change to the attribute `local_name` of `node_id` calls its
+    /// `attributeChangedCallback`.
+    pub fn observes(&self, node_id: usize, local_name: &str) -> bool {
+        self.instances.get(&node_id).is_some_and(|instance| {
+            self.definitions
+                .get(&instance.name)
+                .is_some_and(|observed| observed.iter().any(|name| name == local_name))
+        })
+    }
+
+    pub fn attribute_changed(
+        &mut self,
+        node_id: usize,
+        name: &str,
+        old_value: Option<String>,
+        new_value: Option<String>,
+        namespace: Option<String>,
+    ) {
+        self.reactions.push(Reaction::AttributeChanged {
+            handle: node_id.to_string(),
+            name: name.to_string(),
+            old_value,
+            new_value,
+            namespace,
+        });
+    }
+
+    /// Ids of the instances `contains` picks.
+    pub fn instances_where(&self, contains: impl Fn(usize) -> bool) -> Vec<usize> {
+        self.instances
+            .keys()
+            .copied()
+            .filter(|&id| contains(id))
+            .collect()
+    }
+
+    /// Forget instances whose nodes are about to be dropped, disconnecting the connected ones,
+    /// so an element that later reuses an id is upgraded as the new element it is.
+    pub fn release(&mut self, node_ids: &[usize]) {
+        for node_id in node_ids {
+            if let Some(instance) = self.instances.remove(node_id) {
+                if instance.connected {
+                    self.reactions.push(Reaction::Disconnected {
+                        handle: node_id.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Catch up with the document, given its elements with custom element names in tree
+    /// order: instances that left it are disconnected, ones that came back are connected,
+    /// and elements of defined names it has not seen are upgraded and connected.
+    pub fn sync(&mut self, connected: &[(usize, String)]) {
+        let present: HashSet<usize> = connected.iter().map(|(id, _)| *id).collect();
+        for (node_id, instance) in &mut self.instances {
+            if instance.connected && !present.contains(node_id) {
+                instance.connected = false;
+                self.reactions.push(Reaction::Disconnected {
+                    handle: node_id.to_string(),
+                });
+            }
+        }
+
+        for (node_id, name) in connected {
+            let handle = node_id.to_string();
+            match self.instances.get_mut(node_id) {
+                Some(instance) if !instance.connected => {
+                    instance.connected = true;
+                    self.reactions.push(Reaction::Connected { handle });
+                }
+                Some(_) => {}
+                None if self.definitions.contains_key(name) => {
+                    self.instances.insert(
+                        *node_id,
+                        Instance {
+                            name: name.clone(),
+                            connected: true,
+                        },
+                    );
+                    self.reactions.push(Reaction::Upgrade {
+                        handle: handle.clone(),
+                    });
+                    self.reactions.push(Reaction::Connected { handle });
+                }
+                None => {}
+            }
+        }
+    }
+
+    pub fn take_reactions(&mut self) -> Vec<Reaction> {
+        std::mem::take(&mut self.reactions)
+    }
+}
+
+/// JS half: `customElements`, an `HTMLElement` that custom element classes can extend, and
+/// `[CEReactions]` wrappers on the DOM methods that mutate the tree.
+pub(crate) const CUSTOM_ELEMENTS_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const domCreateElement = global.__frontier_dom_create_element;
+    const domDefineCustomElement = global.__frontier_dom_define_custom_element;
+    const domIsValidCustomElementName = global.__frontier_dom_is_valid_custom_element_name;
+    const domTakeCustomElementReactions = global.__frontier_dom_take_custom_element_reactions;
+    const frontier = global.frontier;
+    const STATE = Symbol('frontierCustomElementState');
+    const definitionsByName = new Map();
+    const definitionsByConstructor = new Map();
+    const whenDefinedPromises = new Map();
+    const instances = new Map();
+    let upgrading = null;
+    let depth = 0;
+
+    const report = (err) => {
+        console.error(`Uncaught ${err && err.stack ? err.stack : err}`);
+    };
+
+    const callback = (element, name, args) => {
+        const definition = element[STATE];
+        const fn = definition && definition.callbacks[name];
+        if (typeof fn !== 'function') {
+            return;
+        }
+        try {
+            fn.apply(element, args);
+        } catch (err) {
+            report(err);
+        }
+    };
+
+    const upgrade = (element, definition) => {
+        if (element[STATE]) {
+            return;
+        }
+        const original = Object.getPrototypeOf(element);
+        Object.setPrototypeOf(element, definition.constructor.prototype);
+        upgrading = element;
+        try {
+            const result = new definition.constructor();
+            if (result !== element) {
+                throw new TypeError(`${definition.name} constructor did not return the element`);
+            }
+        } catch (err) {
+            Object.setPrototypeOf(element, original);
+            report(err);
+            return;
+        } finally {
+            upgrading = null;
+        }
+        element[STATE] = definition;
+        instances.set(frontier.__handleOf(element), element);
+        for (const name of definition.observedAttributes) {
+            const value = element.getAttribute(name);
+            if (value != null) {
+                callback(element, 'attributeChangedCallback', [name, null, value, null]);
+            }
+        }
+    };
+
+    const runReactions = () => {
+        if (definitionsByName.size === 0) {
+            return;
+        }
+        for (;;) {
+            const reactions = JSON.parse(domTakeCustomElementReactions());
+            if (reactions.length === 0) {
+                return;
+            }
+            for (const reaction of reactions) {
+                if (reaction.type === 'upgrade') {
+                    const element = frontier.wrapHandle(reaction.handle);
+                    const definition = definitionsByName.get(element.localName);
+                    if (definition) {
+                        upgrade(element, definition);
+                    }
+                    continue;
+                }
+                const element = instances.get(reaction.handle);
+                if (!element) {
+                    continue;
+                }
+                switch (reaction.type) {
+                    case 'connected':
+                        callback(element, 'connectedCallback', []);
+                        break;
+                    case 'disconnected':
+                        callback(element, 'disconnectedCallback', []);
+                        break;
+                    case 'attribute_changed':
+                        callback(element, 'attributeChangedCallback', [
+                            reaction.name,
+                            reaction.old_value,
+                            reaction.new_value,
+                            reaction.namespace,
+                        ]);
+                        break;
+                }
+            }
+        }
+    };
+
+    const withReactions = (fn) =>
+        function (...args) {
+            depth += 1;
+            try {
+                return fn.apply(this, args);
+            } finally {
+                depth -= 1;
+                if (depth === 0) {
+                    runReactions();
+                }
+            }
+        };
+
+    const MUTATORS = [
+        'appendChild',
+        'insertBefore',
+        'removeChild',
+        'replaceChild',
+        'normalize',
+        'textContent',
+        'data',
+        'nodeValue',
+        'splitText',
+        'innerHTML',
+        'outerHTML',
+        'insertAdjacentHTML',
+        'setAttribute',
+        'setAttributeNS',
+        'removeAttribute',
+        'removeAttributeNS',
+        'toggleAttribute',
+        'append',
+        'prepend',
+        'before',
+        'after',
+        'replaceWith',
+        'remove',
+        'replaceChildren',
+    ];
+    const textProto = global.Text.prototype;
+    for (const proto of [
+        global.Node.prototype,
+        Object.getPrototypeOf(textProto),
+        textProto,
+        global.Element.prototype,
+        global.Document.prototype,
+        global.ShadowRoot.prototype,
+    ]) {
+        for (const name of MUTATORS) {
+            const descriptor = Object.getOwnPropertyDescriptor(proto, name);
+            if (!descriptor) {
+                continue;
+            }
+            if (typeof descriptor.value === 'function') {
+                descriptor.value = withReactions(descriptor.value);
+            } else if (typeof descriptor.set === 'function') {
+                descriptor.set = withReactions(descriptor.set);
+            } else {
+                continue;
+            }
+            Object.defineProperty(proto, name, descriptor);
+        }
+    }
+
+    const documentProto = global.Document.prototype;
+    const createElement = documentProto.createElement;
+    documentProto.createElement = function (name, options) {
+        const element = createElement.call(this, name, options);
+        const definition = definitionsByName.get(String(name).toLowerCase());
+        if (definition) {
+            upgrade(element, definition);
+        }
+        return element;
+    };
+
+    const HTMLElementCtor = function HTMLElement() {
+        const definition = new.target && definitionsByConstructor.get(new.target);
+        if (!definition) {
+            throw new TypeError('Illegal constructor');
+        }
+        if (upgrading) {
+            return upgrading;
+        }
+        // `new MyElement()`: a fresh element, which is already its own instance.
+        const handle = domCreateElement(definition.name);
+        const element = frontier.wrapHandle(handle, 1);
+        Object.setPrototypeOf(element, new.target.prototype);
+        element[STATE] = definition;
+        instances.set(frontier.__handleOf(element), element);
+        return element;
+    };
+    HTMLElementCtor.prototype = global.Element.prototype;
+    global.HTMLElement = HTMLElementCtor;
+
+    const invalidName = (name) =>
+        new global.DOMException(`'${name}' is not a valid custom element name`, 'SyntaxError');
+
+    const RegistryProto = {
+        define(name, constructor, options) {
+            name = String(name);
+            if (typeof constructor !== 'function') {
+                throw new TypeError('customElements.define requires a constructor');
+            }
+            if (!domIsValidCustomElementName(name)) {
+                throw invalidName(name);
+            }
+            if (definitionsByName.has(name)) {
+                throw new global.DOMException(
+                    `'${name}' has already been defined`,
+                    'NotSupportedError',
+                );
+            }
+            if (definitionsByConstructor.has(constructor)) {
+                throw new global.DOMException(
+                    'this constructor has already been defined',
+                    'NotSupportedError',
+                );
+            }
+            if (options && options.extends != null) {
+                throw new global.DOMException(
+                    'customized built-in elements are not supported',
+                    'NotSupportedError',
+                );
+            }
+            const proto = constructor.prototype;
+            const callbacks = {};
+            for (const key of [
+                'connectedCallback',
+                'disconnectedCallback',
+                'adoptedCallback',
+                'attributeChangedCallback',
+            ]) {
+                callbacks[key] = proto[key];
+            }
+            const observed = callbacks.attributeChangedCallback
+                ? Array.from(constructor.observedAttributes ?? [], String)
+                : [];
+            const definition = { name, constructor, callbacks, observedAttributes: observed };
+            definitionsByName.set(name, definition);
+            definitionsByConstructor.set(constructor, definition);
+            domDefineCustomElement(name, observed);
+            runReactions();
+            const pending = whenDefinedPromises.get(name);
+            if (pending) {
+                whenDefinedPromises.delete(name);
+                pending.resolve(constructor);
+            }
+        },
+        get(name) {
+            const definition = definitionsByName.get(String(name));
+            return definition ? definition.constructor : undefined;
+        },
+        getName(constructor) {
+            const definition = definitionsByConstructor.get(constructor);
+            return definition ? definition.name : null;
+        },
+        whenDefined(name) {
+            name = String(name);
+            if (!domIsValidCustomElementName(name)) {
+                return Promise.reject(invalidName(name));
+            }
+            const definition = definitionsByName.get(name);
+            if (definition) {
+                return Promise.resolve(definition.constructor);
+            }
+            let pending = whenDefinedPromises.get(name);
+            if (!pending) {
+                pending = {};
+                pending.promise = new Promise((resolve) => {
+                    pending.resolve = resolve;
+                });
+                whenDefinedPromises.set(name, pending);
+            }
+            return pending.promise;
+        },
+        upgrade(root) {
+            const stack = root ? [root] : [];
+            while (stack.length) {
+                const node = stack.pop();
+                if (node.nodeType === 1) {
+                    const definition = definitionsByName.get(node.localName);
+                    if (definition) {
+                        upgrade(node, definition);
+                    }
+                }
+                stack.push(...node.childNodes);
+            }
+        },
+    };
+    const CustomElementRegistryCtor = function CustomElementRegistry() {
+        throw new TypeError('Illegal constructor');
+    };
+    CustomElementRegistryCtor.prototype = RegistryProto;
+    Object.defineProperty(RegistryProto, 'constructor', { value: CustomElementRegistryCtor });
+    global.CustomElementRegistry = CustomElementRegistryCtor;
+    global.customElements = Object.create(RegistryProto);
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_need_a_hyphen_and_a_lowercase_start() {
+        assert!(is_valid_custom_element_name("my-element"));
+        assert!(is_valid_custom_element_name("x-\u{1F600}"));
+        assert!(!is_valid_custom_element_name("element"));
+        assert!(!is_valid_custom_element_name("My-element"));
+        assert!(!is_valid_custom_element_name("my-Element"));
+        assert!(!is_valid_custom_element_name("1-element"));
+        assert!(!is_valid_custom_element_name("font-face"));
+    }
+
+    #[test]
+    fn sync_upgrades_then_tracks_connection() {
+        let mut registry = CustomElementRegistry::default();
+        registry
+            .define("x-a", vec!["open".to_string()])
+            .expect("define");
+        assert!(registry.define("x-a", Vec::new()).is_err());
+        assert!(registry.define("nohyphen", Vec::new()).is_err());
+
+        registry.sync(&[(4, "x-a".to_string()), (7, "x-b".to_string())]);
+        assert_eq!(
+            registry.take_reactions(),
+            vec![
+                Reaction::Upgrade {
+                    handle: "4".to_string()
+                },
+                Reaction::Connected {
+                    handle: "4".to_string()
+                },
+            ]
+        );
+        assert!(registry.observes(4, "open"));
+        assert!(!registry.observes(4, "closed"));
+
+        registry.sync(&[]);
+        registry.sync(&[(4, "x-a".to_string())]);
+        assert_eq!(
+            registry.take_reactions(),
+            vec![
+                Reaction::Disconnected {
+                    handle: "4".to_string()
+                },
+                Reaction::Connected {
+                    handle: "4".to_string()
+                },
+            ]
+        );
+
+        registry.created(9, "X-A");
+        registry.release(&[4, 9]);
+        assert_eq!(
+            registry.take_reactions(),
+            vec![Reaction::Disconnected {
+                handle: "4".to_string()
+            }]
+        );
+        assert!(registry.instances_where(|_| true).is_empty());
+    }
+}
@@ -3,8 +3,11 @@ use std::collections::HashMap;
 use anyhow::{anyhow, Result};
 use blitz_dom::BaseDocument;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use super::bridge::BlitzJsBridge;
+use super::canvas::canvas_image_css;
+use super::custom_elements::{is_valid_custom_element_name, CustomElementRegistry, Reaction};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -17,14 +20,29 @@ pub enum DomPatch {
         handle: String,
         value: String,
     },
+    OuterHtml {
+        handle: String,
+        value: String,
+    },
+    InsertAdjacentHtml {
+        handle: String,
+        position: String,
+        value: String,
+    },
+    /// With a `namespace`, `name` is qualified (`xlink:href`) as in `setAttributeNS`.
     Attribute {
         handle: String,
         name: String,
         value: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
     },
+    /// With a `namespace`, `name` is the local name as in `removeAttributeNS`.
     RemoveAttribute {
         handle: String,
         name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
     },
     AppendChild {
         parent: String,
@@ -62,13 +80,59 @@ pub enum DomPatch {
         handle: String,
         deep: bool,
     },
+    SplitText {
+        handle: String,
+        offset: usize,
+        new_handle: String,
+    },
+    Normalize {
+        handle: String,
+    },
+    ParseFragment {
+        context: String,
+        value: String,
+        handles: Vec<String>,
+    },
+    AttachShadow {
+        handle: String,
+        root: String,
+        mode: String,
+    },
+    StyleRules {
+        handle: String,
+        css: String,
+    },
+    AdoptedStyles {
+        handle: String,
+        css: String,
+    },
+    LinkStyles {
+        handle: String,
+        css: String,
+    },
+    CanvasStyles {
+        handle: String,
+        css: String,
+    },
 }
 
 pub struct DomState {
     initial_html: String,
     mutations: Vec<DomPatch>,
     bridge: Option<BlitzJsBridge>,
+    /// Set once the document is gone (replaced by navigation, or dropped). From then on every
+    /// DOM binding fails with [`DomError::Detached`] and the bridge is never reattached.
+    detached: bool,
     event_listener_counts: HashMap<String, usize>,
+    custom_elements: CustomElementRegistry,
+}
+
+/// DOM binding failures that are expected rather than bugs.
+#[derive(Debug, Error)]
+pub enum DomError {
+    /// What scripts get from DOM bindings after their document was replaced.
+    #[error("document is detached")]
+    Detached,
 }
 
 impl DomState {
@@ -77,18 +141,63 @@ impl DomState {
             initial_html: html.to_string(),
             mutations: Vec::new(),
             bridge: None,
+            detached: false,
             event_listener_counts: HashMap::new(),
+            custom_elements: CustomElementRegistry::default(),
         }
     }
 
-    pub fn attach_document(&mut self, document: &mut BaseDocument) {
-        if self.bridge.is_none() {
+    /// Operate on `document` from now on, unless a document is attached already.
+    ///
+    /// # Safety
+    ///
+    /// As for [`BlitzJsBridge::new`]: `document` must stay where it is and alive until it is
+    /// detached, with [`Self::detach_document`] or [`Self::detach`], or this state is dropped.
+    pub unsafe fn attach_document(&mut self, document: &mut BaseDocument) {
+        if self.bridge.is_none() && !self.detached {
             self.bridge = Some(BlitzJsBridge::new(document));
         }
     }
 
-    pub fn reattach_document(&mut self, document: &mut BaseDocument) {
-        self.bridge = Some(BlitzJsBridge::new(document));
+    /// Operate on `document` in place of the attached one.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Self::attach_document`].
+    pub unsafe fn reattach_document(&mut self, document: &mut BaseDocument) {
+        if !self.detached {
+            self.bridge = Some(BlitzJsBridge::new(document));
+        }
+    }
+
+    /// Detach if the bridge points at `document`, which is going away.
+    pub fn detach_document(&mut self, document: &BaseDocument) {
+        if self
+            .bridge
+            .as_ref()
+            .is_some_and(|bridge| bridge.is_attached_to(document))
+        {
+            self.detach();
+        }
+    }
+
+    /// Cut the page off from its document for good.
+    pub fn detach(&mut self) {
+        self.bridge = None;
+        self.detached = true;
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// Fails with [`DomError::Detached`] once the document is gone. Bindings that answer `null`
+    /// while no document is attached yet call this so a detached page throws instead.
+    pub fn ensure_not_detached(&self) -> Result<()> {
+        if self.detached {
+            return Err(DomError::Detached.into());
+        }
+        Ok(())
     }
 
     pub fn listen(&mut self, event_type: &str) {
@@ -112,20 +221,111 @@ impl DomState {
         self.event_listener_counts.contains_key(&key)
     }
 
+    pub fn clear_listeners(&mut self) {
+        self.event_listener_counts.clear();
+    }
+
     fn bridge_mut(&mut self) -> Result<&mut BlitzJsBridge> {
+        self.ensure_not_detached()?;
         self.bridge
             .as_mut()
             .ok_or_else(|| anyhow!("DOM bridge not attached"))
     }
 
     fn bridge_ref(&self) -> Result<&BlitzJsBridge> {
+        self.ensure_not_detached()?;
         self.bridge
             .as_ref()
             .ok_or_else(|| anyhow!("DOM bridge not attached"))
     }
 
     fn record_mutation(&mut self, patch: DomPatch) {
+        let structural = matches!(
+            patch,
+            DomPatch::TextContent { .. }
+                | DomPatch::InnerHtml { .. }
+                | DomPatch::OuterHtml { .. }
+                | DomPatch::InsertAdjacentHtml { .. }
+                | DomPatch::AppendChild { .. }
+                | DomPatch::InsertBefore { .. }
+                | DomPatch::RemoveChild { .. }
+                | DomPatch::ReplaceChild { .. }
+        );
         self.mutations.push(patch);
+        if structural {
+            self.sync_custom_elements();
+        }
+    }
+
+    /// `customElements.define`: register `name` and upgrade the elements already using it.
+    pub fn define_custom_element(&mut self, name: &str, observed: Vec<String>) -> Result<()> {
+        self.bridge_ref()?;
+        self.custom_elements.define(name, observed)?;
+        self.sync_custom_elements();
+        Ok(())
+    }
+
+    pub fn take_custom_element_reactions(&mut self) -> Vec<Reaction> {
+        self.custom_elements.take_reactions()
+    }
+
+    fn sync_custom_elements(&mut self) {
+        if !self.custom_elements.has_definitions() {
+            return;
+        }
+        if let Some(bridge) = self.bridge.as_ref() {
+            let connected = bridge.connected_elements_where(is_valid_custom_element_name);
+            self.custom_elements.sync(&connected);
+        }
+    }
+
+    /// Release the custom elements below `node_id`, whose children are about to be dropped.
+    fn release_custom_elements_below(&mut self, node_id: usize) {
+        let Some(bridge) = self.bridge.as_ref() else {
+            return;
+        };
+        let below = self
+            .custom_elements
+            .instances_where(|id| id != node_id && bridge.is_inclusive_descendant(id, node_id));
+        self.custom_elements.release(&below);
+    }
+
+    /// The attribute a patch changes, if it is one a custom element observes: the node, the
+    /// attribute's local name and its value before the change.
+    fn observed_attribute(&self, patch: &DomPatch) -> Option<(usize, String, Option<String>)> {
+        let (handle, name, namespace) = match patch {
+            DomPatch::Attribute {
+                handle,
+                name,
+                namespace,
+                ..
+            }
+            | DomPatch::RemoveAttribute {
+                handle,
+                name,
+                namespace,
+            } => (handle, name, namespace),
+            _ => return None,
+        };
+        let node_id = parse_handle(handle).ok()?;
+        let bridge = self.bridge.as_ref()?;
+        let (local_name, old_value) = match namespace {
+            None => {
+                let name = name.to_ascii_lowercase();
+                let old_value = bridge.get_attribute(node_id, &name).ok()?;
+                (name, old_value)
+            }
+            Some(namespace) => {
+                let local_name = name.rsplit(':').next().unwrap_or(name).to_string();
+                let old_value = bridge
+                    .get_attribute_ns(node_id, Some(namespace), &local_name)
+                    .ok()?;
+                (local_name, old_value)
+            }
+        };
+        self.custom_elements
+            .observes(node_id, &local_name)
+            .then_some((node_id, local_name, old_value))
     }
 
     pub fn handle_from_element_id(&mut self, id: &str) -> Option<String> {
@@ -143,6 +343,16 @@ impl DomState {
 
     pub fn normalize_chain(&self, chain: &[usize]) -> Result<Vec<String>> {
         let bridge = self.bridge_ref()?;
+        // Hit testing walks the rendered tree, which skips shadow roots and puts shadow trees
+        // directly under their hosts; listeners expect the event path instead.
+        let event_path;
+        let chain = match chain.first() {
+            Some(&target) if bridge.has_shadow_roots() => {
+                event_path = bridge.event_path(target);
+                event_path.as_slice()
+            }
+            _ => chain,
+        };
         let mut handles = Vec::with_capacity(chain.len() + 1);
 
         for &node_id in chain {
@@ -177,6 +387,141 @@ impl DomState {
         bridge.inner_html(node_id).ok()
     }
 
+    pub fn outer_html(&self, handle: &str) -> Option<String> {
+        let bridge = self.bridge.as_ref()?;
+        let node_id = parse_handle(handle).ok()?;
+        bridge.outer_html(node_id).ok()
+    }
+
+    /// Handle to a `<template>`'s content fragment.
+    pub fn template_content(&mut self, handle: &str) -> Result<String> {
+        let node_id = parse_handle(handle)?;
+        let holder = self.bridge_mut()?.template_content(node_id)?;
+        Ok(format_handle(holder))
+    }
+
+    /// `attachShadow({ mode })`: handle to the new shadow root.
+    pub fn attach_shadow(&mut self, handle: &str, mode: &str) -> Result<String> {
+        let open = match mode {
+            "open" => true,
+            "closed" => false,
+            _ => {
+                return Err(anyhow!(
+                    "TypeError: '{mode}' is not a valid shadow root mode"
+                ))
+            }
+        };
+        let node_id = parse_handle(handle)?;
+        let root = format_handle(self.bridge_mut()?.attach_shadow(node_id, open)?);
+        self.record_mutation(DomPatch::AttachShadow {
+            handle: handle.to_string(),
+            root: root.clone(),
+            mode: mode.to_string(),
+        });
+        Ok(root)
+    }
+
+    /// Render a `<style>` with the rules its CSSOM sheet was edited to.
+    pub fn set_style_rules(&mut self, handle: &str, css: &str) -> Result<()> {
+        let node_id = parse_handle(handle)?;
+        self.bridge_mut()?.set_style_rules(node_id, css)?;
+        self.record_mutation(DomPatch::StyleRules {
+            handle: handle.to_string(),
+            css: css.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Render the `adoptedStyleSheets` of the document or a shadow root.
+    pub fn set_adopted_styles(&mut self, handle: &str, css: &str) -> Result<()> {
+        let node_id = parse_handle(handle)?;
+        self.bridge_mut()?.set_adopted_styles(node_id, css)?;
+        self.record_mutation(DomPatch::AdoptedStyles {
+            handle: handle.to_string(),
+            css: css.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Render the stylesheet fetched for a `<link rel=stylesheet>`.
+    pub fn set_link_styles(&mut self, handle: &str, css: &str) -> Result<()> {
+        let node_id = parse_handle(handle)?;
+        self.bridge_mut()?.set_link_styles(node_id, css)?;
+        self.record_mutation(DomPatch::LinkStyles {
+            handle: handle.to_string(),
+            css: css.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Show `image`, the PNG of the `width`×`height` bitmap a canvas's context drew, on the
+    /// canvas, or nothing for a canvas with no pixels.
+    pub fn set_canvas_image(
+        &mut self,
+        handle: &str,
+        image: Option<(&[u8], u32, u32)>,
+    ) -> Result<()> {
+        let node_id = parse_handle(handle)?;
+        let css = image
+            .map(|(png, width, height)| canvas_image_css(node_id, png, width, height))
+            .unwrap_or_default();
+        self.bridge_mut()?.set_canvas_styles(node_id, &css)?;
+        self.record_mutation(DomPatch::CanvasStyles {
+            handle: handle.to_string(),
+            css,
+        });
+        Ok(())
+    }
+
+    /// Handle to the element's open shadow root.
+    pub fn shadow_root(&self, handle: &str) -> Option<String> {
+        let bridge = self.bridge.as_ref()?;
+        let node_id = parse_handle(handle).ok()?;
+        bridge.shadow_root(node_id).map(format_handle)
+    }
+
+    /// The host of a shadow root and the root's mode.
+    pub fn shadow_host(&self, handle: &str) -> Option<(String, &'static str)> {
+        let bridge = self.bridge.as_ref()?;
+        let node_id = parse_handle(handle).ok()?;
+        let (host, open) = bridge.shadow_host(node_id)?;
+        Some((format_handle(host), if open { "open" } else { "closed" }))
+    }
+
+    pub fn assigned_nodes(&self, handle: &str) -> Vec<String> {
+        let (Some(bridge), Ok(node_id)) = (self.bridge.as_ref(), parse_handle(handle)) else {
+            return Vec::new();
+        };
+        bridge
+            .assigned_nodes(node_id)
+            .into_iter()
+            .map(format_handle)
+            .collect()
+    }
+
+    pub fn assigned_slot(&self, handle: &str) -> Option<String> {
+        let bridge = self.bridge.as_ref()?;
+        let node_id = parse_handle(handle).ok()?;
+        bridge.assigned_slot(node_id).map(format_handle)
+    }
+
+    /// Viewport-relative `[x, y, width, height]` of the node from the last layout.
+    pub fn layout_rect(&self, handle: &str) -> Option<Vec<f64>> {
+        let bridge = self.bridge.as_ref()?;
+        let node_id = parse_handle(handle).ok()?;
+        let (x, y, width, height) = bridge.layout_rect(node_id)?;
+        Some(vec![x, y, width, height])
+    }
+
+    /// `getElementById` on a shadow root.
+    pub fn element_by_id_in(&self, root: &str, id: &str) -> Option<String> {
+        let bridge = self.bridge.as_ref()?;
+        let root_id = parse_handle(root).ok()?;
+        bridge
+            .find_node_by_html_id_in(root_id, id)
+            .map(format_handle)
+    }
+
     pub fn set_text_content_direct(&mut self, handle: &str, value: &str) -> Result<()> {
         self.apply_patch(DomPatch::TextContent {
             handle: handle.to_string(),
@@ -193,11 +538,63 @@ impl DomState {
         Ok(())
     }
 
+    pub fn set_outer_html_direct(&mut self, handle: &str, value: &str) -> Result<()> {
+        self.apply_patch(DomPatch::OuterHtml {
+            handle: handle.to_string(),
+            value: value.to_string(),
+        })?;
+        Ok(())
+    }
+
+    pub fn insert_adjacent_html_direct(
+        &mut self,
+        handle: &str,
+        position: &str,
+        value: &str,
+    ) -> Result<()> {
+        self.apply_patch(DomPatch::InsertAdjacentHtml {
+            handle: handle.to_string(),
+            position: position.to_string(),
+            value: value.to_string(),
+        })?;
+        Ok(())
+    }
+
+    /// Parse `value` in the context of `context` and return handles to the unattached nodes.
+    pub fn parse_fragment(&mut self, context: &str, value: &str) -> Result<Vec<String>> {
+        let context_id = parse_handle(context)?;
+        let nodes = self.bridge_mut()?.parse_fragment(context_id, value)?;
+        let handles: Vec<String> = nodes.into_iter().map(format_handle).collect();
+        self.record_mutation(DomPatch::ParseFragment {
+            context: context.to_string(),
+            value: value.to_string(),
+            handles: handles.clone(),
+        });
+        Ok(handles)
+    }
+
     pub fn set_attribute_direct(&mut self, handle: &str, name: &str, value: &str) -> Result<()> {
         self.apply_patch(DomPatch::Attribute {
             handle: handle.to_string(),
             name: name.to_string(),
             value: value.to_string(),
+            namespace: None,
+        })?;
+        Ok(())
+    }
+
+    pub fn set_attribute_ns_direct(
+        &mut self,
+        handle: &str,
+        namespace: Option<&str>,
+        name: &str,
+        value: &str,
+    ) -> Result<()> {
+        self.apply_patch(DomPatch::Attribute {
+            handle: handle.to_string(),
+            name: name.to_string(),
+            value: value.to_string(),
+            namespace: namespace.map(str::to_string),
         })?;
         Ok(())
     }
@@ -206,6 +603,21 @@ impl DomState {
         self.apply_patch(DomPatch::RemoveAttribute {
             handle: handle.to_string(),
             name: name.to_string(),
+            namespace: None,
+        })?;
+        Ok(())
+    }
+
+    pub fn remove_attribute_ns_direct(
+        &mut self,
+        handle: &str,
+        namespace: Option<&str>,
+        name: &str,
+    ) -> Result<()> {
+        self.apply_patch(DomPatch::RemoveAttribute {
+            handle: handle.to_string(),
+            name: name.to_string(),
+            namespace: namespace.map(str::to_string),
         })?;
         Ok(())
     }
@@ -213,6 +625,9 @@ impl DomState {
     pub fn create_element(&mut self, name: &str, namespace: Option<&str>) -> Result<String> {
         let node_id = self.bridge_mut()?.create_element(name, namespace)?;
         let handle = format_handle(node_id);
+        if namespace.is_none_or(|ns| ns.eq_ignore_ascii_case("http://www.w3.org/1999/xhtml")) {
+            self.custom_elements.created(node_id, name);
+        }
         self.record_mutation(DomPatch::CreateElement {
             handle: handle.clone(),
             name: name.to_string(),
@@ -312,6 +727,27 @@ impl DomState {
         Ok(cloned_handle)
     }
 
+    pub fn split_text(&mut self, handle: &str, offset: usize) -> Result<String> {
+        let node_id = parse_handle(handle)?;
+        let new_id = self.bridge_mut()?.split_text(node_id, offset)?;
+        let new_handle = format_handle(new_id);
+        self.record_mutation(DomPatch::SplitText {
+            handle: handle.to_string(),
+            offset,
+            new_handle: new_handle.clone(),
+        });
+        Ok(new_handle)
+    }
+
+    pub fn normalize(&mut self, handle: &str) -> Result<()> {
+        let node_id = parse_handle(handle)?;
+        self.bridge_mut()?.normalize(node_id)?;
+        self.record_mutation(DomPatch::Normalize {
+            handle: handle.to_string(),
+        });
+        Ok(())
+    }
+
     pub fn parent_handle(&self, handle: &str) -> Result<Option<String>> {
         let node_id = parse_handle(handle)?;
         let parent = self.bridge_ref()?.parent_node(node_id)?;
@@ -362,6 +798,17 @@ impl DomState {
         self.bridge_ref()?.get_attribute(node_id, name)
     }
 
+    pub fn get_attribute_ns(
+        &self,
+        handle: &str,
+        namespace: Option<&str>,
+        name: &str,
+    ) -> Result<Option<String>> {
+        let node_id = parse_handle(handle)?;
+        self.bridge_ref()?
+            .get_attribute_ns(node_id, namespace, name)
+    }
+
     pub fn namespace_uri(&self, handle: &str) -> Result<Option<String>> {
         let node_id = parse_handle(handle)?;
         let ns = self.bridge_ref()?.namespace_uri(node_id)?;
@@ -374,6 +821,20 @@ impl DomState {
     }
 
     pub fn apply_patch(&mut self, patch: DomPatch) -> Result<bool> {
+        self.bridge_ref()?;
+        let observed = self.observed_attribute(&patch);
+        match &patch {
+            DomPatch::InnerHtml { handle, .. } => {
+                self.release_custom_elements_below(parse_handle(handle)?);
+            }
+            DomPatch::TextContent { handle, value } => {
+                let node_id = parse_handle(handle)?;
+                if self.text_content(handle).as_deref() != Some(value.as_str()) {
+                    self.release_custom_elements_below(node_id);
+                }
+            }
+            _ => {}
+        }
         let bridge = self.bridge_mut()?;
 
         match &patch {
@@ -383,15 +844,38 @@ impl DomState {
             DomPatch::InnerHtml { handle, value } => {
                 bridge.set_inner_html(parse_handle(handle)?, value)?;
             }
+            DomPatch::OuterHtml { handle, value } => {
+                bridge.set_outer_html(parse_handle(handle)?, value)?;
+            }
+            DomPatch::InsertAdjacentHtml {
+                handle,
+                position,
+                value,
+            } => {
+                bridge.insert_adjacent_html(parse_handle(handle)?, position, value)?;
+            }
             DomPatch::Attribute {
                 handle,
                 name,
                 value,
+                namespace: None,
             } => {
                 bridge.set_attribute(parse_handle(handle)?, name, value)?;
             }
-            DomPatch::RemoveAttribute { handle, name } => {
-                bridge.remove_attribute(parse_handle(handle)?, name)?;
+            DomPatch::Attribute {
+                handle,
+                name,
+                value,
+                namespace: Some(namespace),
+            } => {
+                bridge.set_attribute_ns(parse_handle(handle)?, Some(namespace), name, value)?;
+            }
+            DomPatch::RemoveAttribute {
+                handle,
+                name,
+                namespace,
+            } => {
+                bridge.remove_attribute_ns(parse_handle(handle)?, namespace.as_deref(), name)?;
             }
             other => {
                 // Record-only variants (created outside the patch API).
@@ -400,6 +884,20 @@ impl DomState {
             }
         }
 
+        if let Some((node_id, name, old_value)) = observed {
+            let (new_value, namespace) = match &patch {
+                DomPatch::Attribute {
+                    value, namespace, ..
+                } => (Some(value.clone()), namespace.clone()),
+                DomPatch::RemoveAttribute { namespace, .. } => (None, namespace.clone()),
+                _ => (None, None),
+            };
+            // Removing an attribute that was not there changes nothing.
+            if old_value.is_some() || new_value.is_some() {
+                self.custom_elements
+                    .attribute_changed(node_id, &name, old_value, new_value, namespace);
+            }
+        }
         self.record_mutation(patch);
         Ok(true)
     }
@@ -0,0 +1,138 @@
+//! Files dropped onto the window, delivered to page script as HTML drag-and-drop events.
+//!
+//! The host collects winit's `DroppedFile` events and hands the batch to
+//! [`JsDomEnvironment::drop_files`](super::environment::JsDomEnvironment::drop_files) with the
+//! element under the pointer. Each file's bytes are stored as a blob, and the bootstrap fires
+//! `dragenter` and `dragover` at the element, then `drop` if `dragover` was cancelled, each
+//! carrying a `DataTransfer` of `File`s. As in browsers the files are only readable during
+//! `drop`; the earlier events see just their count and types. A drop the page does not cancel
+//! falls through to the host, which opens the first file.
+
+/// JS half: `DataTransfer`, and `frontier.__dropFiles(handle, path, files, x, y)`, which fires
+/// the drag events and returns whether the page cancelled the drop.
+pub(crate) const DRAG_DROP_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+
+    const indexed = (target, items) => {
+        items.forEach((item, index) => {
+            Object.defineProperty(target, index, { value: item, configurable: true });
+        });
+        return target;
+    };
+
+    class FileList {
+        constructor(files) {
+            this._files = files;
+            indexed(this, files);
+        }
+        get length() {
+            return this._files.length;
+        }
+        item(index) {
+            return this._files[index] ?? null;
+        }
+        [Symbol.iterator]() {
+            return this._files[Symbol.iterator]();
+        }
+        get [Symbol.toStringTag]() {
+            return 'FileList';
+        }
+    }
+
+    class DataTransferItem {
+        constructor(transfer, file) {
+            this._transfer = transfer;
+            this._file = file;
+        }
+        get kind() {
+            return 'file';
+        }
+        get type() {
+            return this._file.type;
+        }
+        getAsFile() {
+            return this._transfer._mode === 'read-only' ? this._file : null;
+        }
+        getAsString() {}
+    }
+
+    class DataTransferItemList {
+        constructor(items) {
+            this._items = items;
+            indexed(this, items);
+        }
+        get length() {
+            return this._items.length;
+        }
+        [Symbol.iterator]() {
+            return this._items[Symbol.iterator]();
+        }
+    }
+
+    class DataTransfer {
+        constructor() {
+            this._files = [];
+            this._mode = 'read-write';
+            this.dropEffect = 'none';
+            this.effectAllowed = 'all';
+        }
+        get files() {
+            return new FileList(this._mode === 'protected' ? [] : this._files.slice());
+        }
+        get items() {
+            return new DataTransferItemList(
+                this._files.map((file) => new DataTransferItem(this, file)),
+            );
+        }
+        get types() {
+            return this._files.length > 0 ? ['Files'] : [];
+        }
+        getData() {
+            return '';
+        }
+        setData() {}
+        clearData() {}
+        setDragImage() {}
+        get [Symbol.toStringTag]() {
+            return 'DataTransfer';
+        }
+    }
+
+    frontier.__dropFiles = (handle, pathHandles, files, x, y) => {
+        const transfer = new DataTransfer();
+        transfer._files = files.map((file) =>
+            frontier.__adoptFile(file.blob, file.name, file.type, file.lastModified),
+        );
+        const fire = (type, mode) => {
+            transfer._mode = mode;
+            const detail = {
+                bubbles: true,
+                cancelable: true,
+                composed: true,
+                clientX: x,
+                clientY: y,
+                x,
+                y,
+                dataTransfer: transfer,
+            };
+            return frontier.__dispatchDomEvent(handle, type, detail, pathHandles);
+        };
+        transfer.dropEffect = 'copy';
+        fire('dragenter', 'protected');
+        const over = fire('dragover', 'protected');
+        if (!over.defaultPrevented) {
+            return false;
+        }
+        const dropped = fire('drop', 'read-only');
+        transfer._mode = 'protected';
+        return dropped.defaultPrevented;
+    };
+
+    global.DataTransfer = DataTransfer;
+    global.DataTransferItem = DataTransferItem;
+    global.DataTransferItemList = DataTransferItemList;
+    global.FileList = FileList;
+})();
+"#;
@@ -0,0 +1,249 @@
+//! Execution of `<script>` elements inserted by page script.
+//!
+//! Parser-inserted scripts are collected up front and run by
+//! [`JsPageRuntime`](super::session::JsPageRuntime). Scripts created with `createElement` and
+//! attached with `appendChild`, `insertBefore` and friends run here instead: inline scripts
+//! execute synchronously during insertion if the page's `script-src` allows them, external
+//! ones load through `__frontier_fetch_script`, which the bootstrap keeps to itself. External
+//! scripts run as soon as they arrive unless their `async` property was set to `false`, in
+//! which case they run in insertion order. Scripts parsed from markup set with `innerHTML`,
+//! `outerHTML` or `insertAdjacentHTML` never run, as in other browsers.
+
+/// Hooks DOM insertion so connected, not-yet-started script elements execute.
+/// `frontier.__markParserScripts()` flags scripts already in the document so moving them later
+/// does not run them twice.
+pub(crate) const DYNAMIC_SCRIPT_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    const fetchScript = global.__frontier_fetch_script;
+    const blobText = global.__frontier_blob_text;
+    const blobRelease = global.__frontier_blob_release;
+    const allowsInlineScript = global.__frontier_allows_inline_script;
+    // Taken now so a page replacing `eval` never sees the source of a script it inserted.
+    const indirectEval = global.eval;
+    const STARTED = Symbol('frontierScriptStarted');
+    const CLASSIC_TYPES = new Set([
+        '',
+        'text/javascript',
+        'application/javascript',
+        'text/ecmascript',
+        'application/ecmascript',
+    ]);
+    const orderedQueue = [];
+    const PENDING_LOADS = new Map();
+    let currentScript = null;
+
+    if (global.Document && global.Document.prototype) {
+        Object.defineProperty(global.Document.prototype, 'currentScript', {
+            get: () => currentScript,
+            configurable: true,
+        });
+    }
+
+    const isScript = (node) =>
+        node && node.nodeType === 1 && String(node.tagName || '').toUpperCase() === 'SCRIPT';
+
+    const collectScripts = (node, out) => {
+        if (!node || typeof node !== 'object') {
+            return out;
+        }
+        if (isScript(node)) {
+            out.push(node);
+            return out;
+        }
+        const children = node.childNodes;
+        if (children) {
+            for (let i = 0; i < children.length; i += 1) {
+                collectScripts(children[i], out);
+            }
+        }
+        return out;
+    };
+
+    // Shadow-including, so scripts inserted into shadow trees run too.
+    const isConnected = (node) => node.isConnected === true;
+
+    const fireElementEvent = (el, type) => {
+        if (typeof el.dispatchEvent === 'function' && typeof global.Event === 'function') {
+            el.dispatchEvent(new global.Event(type));
+        }
+        const handler = el['on' + type];
+        if (typeof handler === 'function') {
+            handler.call(el, { type, target: el });
+        }
+    };
+
+    const evaluate = (el, code) => {
+        const previous = currentScript;
+        currentScript = el;
+        try {
+            indirectEval(code);
+        } catch (err) {
+            console.error(`Uncaught ${err && err.stack ? err.stack : err}`);
+        } finally {
+            currentScript = previous;
+        }
+    };
+
+    const loadSource = (src) =>
+        new Promise((resolve, reject) => {
+            const id = fetchScript(String(src));
+            PENDING_LOADS.set(id, { resolve, reject });
+        });
+
+    const flushOrdered = () => {
+        while (orderedQueue.length && orderedQueue[0].state !== 'pending') {
+            const entry = orderedQueue.shift();
+            if (entry.state === 'ready') {
+                evaluate(entry.el, entry.code);
+                fireElementEvent(entry.el, 'load');
+            } else {
+                fireElementEvent(entry.el, 'error');
+            }
+        }
+    };
+
+    const prepare = (el) => {
+        if (el[STARTED] || !isConnected(el)) {
+            return;
+        }
+        el[STARTED] = true;
+        const type = String(el.getAttribute('type') || '').trim().toLowerCase();
+        if (!CLASSIC_TYPES.has(type)) {
+            return;
+        }
+        const src = el.getAttribute('src');
+        if (src === null || src === undefined) {
+            const code = el.textContent || '';
+            if (!allowsInlineScript(code, el.getAttribute('nonce'))) {
+                console.error(
+                    'Refused to execute inline script because it violates the ' +
+                        'Content Security Policy directive script-src',
+                );
+                return;
+            }
+            evaluate(el, code);
+            return;
+        }
+        if (el.async === false) {
+            const entry = { el, state: 'pending', code: '' };
+            orderedQueue.push(entry);
+            loadSource(src).then(
+                (code) => {
+                    entry.state = 'ready';
+                    entry.code = code;
+                    flushOrdered();
+                },
+                () => {
+                    entry.state = 'failed';
+                    flushOrdered();
+                },
+            );
+            return;
+        }
+        loadSource(src).then(
+            (code) => {
+                evaluate(el, code);
+                fireElementEvent(el, 'load');
+            },
+            () => fireElementEvent(el, 'error'),
+        );
+    };
+
+    const previousResolve = frontier.__resolveFetch;
+    frontier.__resolveFetch = (id, json) => {
+        const entry = PENDING_LOADS.get(id);
+        if (!entry) {
+            return previousResolve(id, json);
+        }
+        PENDING_LOADS.delete(id);
+        const data = JSON.parse(json);
+        const body = blobText(data.blob);
+        blobRelease(data.blob);
+        if (data.status >= 200 && data.status < 300) {
+            entry.resolve(body);
+        } else {
+            entry.reject(new Error(`script load failed with status ${data.status}`));
+        }
+    };
+
+    const previousReject = frontier.__rejectFetch;
+    frontier.__rejectFetch = (id, message) => {
+        const entry = PENDING_LOADS.get(id);
+        if (!entry) {
+            return previousReject(id, message);
+        }
+        PENDING_LOADS.delete(id);
+        console.error(message);
+        entry.reject(new Error(message));
+    };
+
+    const hook = (proto, name) => {
+        if (!proto || typeof proto[name] !== 'function') {
+            return;
+        }
+        const original = proto[name];
+        proto[name] = function (...args) {
+            const scripts = [];
+            for (const arg of args) {
+                collectScripts(arg, scripts);
+            }
+            const result = original.apply(this, args);
+            for (const script of scripts) {
+                prepare(script);
+            }
+            return result;
+        };
+    };
+
+    // Scripts parsed from markup are marked started, so moving them later does not run them.
+    const hookMarkup = (proto, name, scope) => {
+        const descriptor = proto && Object.getOwnPropertyDescriptor(proto, name);
+        if (!descriptor) {
+            return;
+        }
+        const key = typeof descriptor.value === 'function' ? 'value' : 'set';
+        const original = descriptor[key];
+        if (typeof original !== 'function') {
+            return;
+        }
+        descriptor[key] = function (...args) {
+            const root = scope(this);
+            const before = new Set(collectScripts(root, []));
+            const result = original.apply(this, args);
+            for (const script of collectScripts(root, [])) {
+                if (!before.has(script)) {
+                    script[STARTED] = true;
+                }
+            }
+            return result;
+        };
+        Object.defineProperty(proto, name, descriptor);
+    };
+
+    const nodeProto = global.Node && global.Node.prototype;
+    const elementProto = global.Element && global.Element.prototype;
+    for (const name of ['appendChild', 'insertBefore', 'replaceChild']) {
+        hook(nodeProto, name);
+    }
+    for (const name of ['append', 'prepend', 'before', 'after', 'replaceWith']) {
+        if (elementProto && Object.prototype.hasOwnProperty.call(elementProto, name)) {
+            hook(elementProto, name);
+        }
+    }
+    const itself = (node) => node;
+    const parentOrSelf = (node) => node.parentNode || node;
+    hookMarkup(elementProto, 'innerHTML', itself);
+    hookMarkup(elementProto, 'outerHTML', parentOrSelf);
+    hookMarkup(elementProto, 'insertAdjacentHTML', parentOrSelf);
+
+    frontier.__markParserScripts = () => {
+        if (global.document) {
+            for (const script of collectScripts(global.document, [])) {
+                script[STARTED] = true;
+            }
+        }
+    };
+})();
+"#;
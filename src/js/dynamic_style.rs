@@ -0,0 +1,205 @@
+//! Stylesheets added and removed by page script.
+//!
+//! A `<style>` applies while it is in the document: the bridge empties the stylesheet Blitz
+//! parsed for one that leaves, and gives it back its text when it returns (see
+//! [`BlitzJsBridge`](super::bridge::BlitzJsBridge)). A `<link rel="stylesheet">` attached by
+//! script, or whose `href` or `rel` changes while attached, loads through
+//! `__frontier_fetch_style`, which the bootstrap keeps to itself, and its rules are rendered in a
+//! `<style>` the bridge keeps inside the link, so they go with it. `url()`s in a linked sheet
+//! resolve against the document rather than the sheet.
+
+/// Hooks DOM insertion, the markup setters and attribute changes so connected stylesheet links
+/// load, firing `load` or `error` on the link. `frontier.__markParserStyles()` flags links
+/// already in the document, which Blitz loaded itself, so moving them does not load them again.
+pub(crate) const DYNAMIC_STYLE_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const domSetLinkStyles = global.__frontier_dom_set_link_styles;
+    const frontier = global.frontier;
+    const fetchStyle = global.__frontier_fetch_style;
+    const blobText = global.__frontier_blob_text;
+    const blobRelease = global.__frontier_blob_release;
+    const LOADED = Symbol('frontierStyleLoaded');
+
+    const isLink = (node) =>
+        node && node.nodeType === 1 && String(node.tagName || '').toUpperCase() === 'LINK';
+
+    const collectLinks = (node, out) => {
+        if (!node || typeof node !== 'object') {
+            return out;
+        }
+        if (isLink(node)) {
+            out.push(node);
+            return out;
+        }
+        const children = node.childNodes;
+        if (children) {
+            for (let i = 0; i < children.length; i += 1) {
+                collectLinks(children[i], out);
+            }
+        }
+        return out;
+    };
+
+    const isStylesheet = (link) =>
+        String(link.getAttribute('rel') || '')
+            .toLowerCase()
+            .split(/\s+/)
+            .includes('stylesheet');
+
+    const fireElementEvent = (el, type) => {
+        if (typeof el.dispatchEvent === 'function' && typeof global.Event === 'function') {
+            el.dispatchEvent(new global.Event(type));
+        }
+        const handler = el['on' + type];
+        if (typeof handler === 'function') {
+            handler.call(el, { type, target: el });
+        }
+    };
+
+    const PENDING_LOADS = new Map();
+
+    const setLinkStyles = (link, css) => {
+        const handle = frontier.__handleOf(link);
+        if (handle != null) {
+            domSetLinkStyles(handle, css);
+        }
+    };
+
+    const prepare = (link) => {
+        if (link.isConnected !== true) {
+            return;
+        }
+        const href = isStylesheet(link) ? String(link.getAttribute('href') || '').trim() : '';
+        if (href === link[LOADED]) {
+            return;
+        }
+        link[LOADED] = href;
+        if (href === '') {
+            setLinkStyles(link, '');
+            return;
+        }
+        const id = fetchStyle(href);
+        PENDING_LOADS.set(id, { link, href });
+    };
+
+    // A link whose href changed while it loaded keeps the newer load's rules.
+    const settle = (entry, css) => {
+        const { link, href } = entry;
+        if (link[LOADED] !== href) {
+            return;
+        }
+        if (css === null) {
+            fireElementEvent(link, 'error');
+            return;
+        }
+        setLinkStyles(link, css);
+        fireElementEvent(link, 'load');
+    };
+
+    const previousResolve = frontier.__resolveFetch;
+    frontier.__resolveFetch = (id, json) => {
+        const entry = PENDING_LOADS.get(id);
+        if (!entry) {
+            return previousResolve(id, json);
+        }
+        PENDING_LOADS.delete(id);
+        const data = JSON.parse(json);
+        const body = blobText(data.blob);
+        blobRelease(data.blob);
+        settle(entry, data.status >= 200 && data.status < 300 ? body : null);
+    };
+
+    const previousReject = frontier.__rejectFetch;
+    frontier.__rejectFetch = (id, message) => {
+        const entry = PENDING_LOADS.get(id);
+        if (!entry) {
+            return previousReject(id, message);
+        }
+        PENDING_LOADS.delete(id);
+        console.error(message);
+        settle(entry, null);
+    };
+
+    const hook = (proto, name) => {
+        if (!proto || typeof proto[name] !== 'function') {
+            return;
+        }
+        const original = proto[name];
+        proto[name] = function (...args) {
+            const links = [];
+            for (const arg of args) {
+                collectLinks(arg, links);
+            }
+            const result = original.apply(this, args);
+            for (const link of links) {
+                prepare(link);
+            }
+            return result;
+        };
+    };
+
+    // Markup setters: the links to load are only there once the markup is parsed.
+    const hookMarkup = (proto, name, scope) => {
+        const descriptor = proto && Object.getOwnPropertyDescriptor(proto, name);
+        if (!descriptor) {
+            return;
+        }
+        const key = typeof descriptor.value === 'function' ? 'value' : 'set';
+        const original = descriptor[key];
+        if (typeof original !== 'function') {
+            return;
+        }
+        descriptor[key] = function (...args) {
+            const root = scope(this);
+            const result = original.apply(this, args);
+            for (const link of collectLinks(root, [])) {
+                prepare(link);
+            }
+            return result;
+        };
+        Object.defineProperty(proto, name, descriptor);
+    };
+
+    const nodeProto = global.Node && global.Node.prototype;
+    const elementProto = global.Element && global.Element.prototype;
+    for (const name of ['appendChild', 'insertBefore', 'replaceChild']) {
+        hook(nodeProto, name);
+    }
+    for (const name of ['append', 'prepend', 'before', 'after', 'replaceWith']) {
+        if (elementProto && Object.prototype.hasOwnProperty.call(elementProto, name)) {
+            hook(elementProto, name);
+        }
+    }
+    const itself = (node) => node;
+    const parentOrSelf = (node) => node.parentNode || node;
+    hookMarkup(elementProto, 'innerHTML', itself);
+    hookMarkup(elementProto, 'outerHTML', parentOrSelf);
+    hookMarkup(elementProto, 'insertAdjacentHTML', parentOrSelf);
+
+    for (const name of ['setAttribute', 'removeAttribute']) {
+        const original = elementProto && elementProto[name];
+        if (typeof original !== 'function') {
+            continue;
+        }
+        elementProto[name] = function (attribute, ...rest) {
+            const result = original.call(this, attribute, ...rest);
+            const changed = String(attribute).toLowerCase();
+            if (isLink(this) && (changed === 'href' || changed === 'rel')) {
+                prepare(this);
+            }
+            return result;
+        };
+    }
+
+    frontier.__markParserStyles = () => {
+        if (global.document) {
+            for (const link of collectLinks(global.document, [])) {
+                if (isStylesheet(link)) {
+                    link[LOADED] = String(link.getAttribute('href') || '').trim();
+                }
+            }
+        }
+    };
+})();
+"#;
@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -11,7 +11,7 @@ use blitz_traits::events::{
     BlitzImeEvent, BlitzKeyEvent, BlitzMouseButtonEvent, DomEvent, DomEventData, MouseEventButton,
 };
 use futures_util::task::AtomicWaker;
-use keyboard_types::{Location, Modifiers};
+use keyboard_types::{Key, Location, Modifiers};
 use rquickjs::function::{Args as FunctionArgs, Opt};
 use rquickjs::{Ctx, Function, IntoJs, Value};
 use serde_json::{json, to_string as to_json_string, Map as JsonMap, Value as JsonValue};
@@ -19,15 +19,71 @@ use tokio::runtime::Handle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::task::JoinHandle;
 use tokio::time::sleep;
-use tracing::error;
-
-use super::dom::{DomPatch, DomState};
-use super::runtime::QuickJsEngine;
+use tracing::{error, warn};
+use url::Url;
+
+use super::blob::{install_blob_bindings, BlobStore, LocalFile, BLOB_BOOTSTRAP};
+use super::bytecode_cache::CompileStats;
+use super::canvas::{install_canvas_bindings, CanvasStore, CANVAS_BOOTSTRAP};
+use super::clock::VirtualClock;
+use super::coverage::JsCoverage;
+use super::cssom::CSSOM_BOOTSTRAP;
+use super::custom_elements::{is_valid_custom_element_name, CUSTOM_ELEMENTS_BOOTSTRAP};
+use super::dom::{DomError, DomPatch, DomState};
+use super::drag_drop::DRAG_DROP_BOOTSTRAP;
+use super::dynamic_script::DYNAMIC_SCRIPT_BOOTSTRAP;
+use super::dynamic_style::DYNAMIC_STYLE_BOOTSTRAP;
+use super::eval_policy::REFUSE_EVAL_BOOTSTRAP;
+use super::fetch::{Destination, FetchInit, FetchManager, FETCH_BOOTSTRAP, SEAL_NATIVE_HOOKS};
+use super::file_input::FILE_INPUT_BOOTSTRAP;
+use super::form_controls::FORM_CONTROLS_BOOTSTRAP;
+use super::gestures::{GestureEvent, GESTURE_BOOTSTRAP};
+use super::intersection::INTERSECTION_BOOTSTRAP;
+use super::isolation::OriginQuota;
+use super::lifecycle::{LifecycleEvent, LIFECYCLE_BOOTSTRAP};
+use super::media::{MediaPreferences, MEDIA_BOOTSTRAP};
+use super::messaging::{PostedMessage, WindowRef, MESSAGING_BOOTSTRAP};
+use super::navigator::{install_navigator_bindings, NAVIGATOR_BOOTSTRAP};
+use super::nostr::{SignerRequest, NOSTR_BOOTSTRAP};
+use super::p2p::{P2pRequest, P2P_BOOTSTRAP};
+use super::performance::{NavigationTiming, PERFORMANCE_BOOTSTRAP};
+use super::runtime::{JsHeapUsage, QuickJsEngine};
+use super::script::ScriptDescriptor;
+use super::scroll::{ScrollAlign, ScrollIntoView, SCROLL_BOOTSTRAP};
+use super::sourcemap::{self, SourceMap};
+use super::storage::{install_storage_bindings, STORAGE_BOOTSTRAP};
+use super::structured_clone::{detach_array_buffer, STRUCTURED_CLONE_BOOTSTRAP};
+use super::teardown::{TaskTracker, TeardownCheck};
+use super::viewport::{ViewportMetrics, VIEWPORT_BOOTSTRAP};
+use super::window_open::{
+    targets_current_window, WindowRequest, USER_ACTIVATION_TIMEOUT, WINDOW_OPEN_BOOTSTRAP,
+};
+use crate::adblock::Blocklist;
+use crate::csp::ContentSecurityPolicy;
+use crate::network_log::NetworkLog;
+use crate::p2p::ChannelEvent;
+use crate::permissions::ContentRules;
 
 pub struct JsDomEnvironment {
     engine: QuickJsEngine,
     state: Rc<RefCell<DomState>>,
     timers: Rc<TimerManager>,
+    fetches: Rc<FetchManager>,
+    blobs: BlobStore,
+    canvases: CanvasStore,
+    signer_requests: Rc<RefCell<Vec<SignerRequest>>>,
+    p2p_requests: Rc<RefCell<Vec<P2pRequest>>>,
+    posted_messages: Rc<RefCell<Vec<PostedMessage>>>,
+    window_requests: Rc<RefCell<Vec<WindowRequest>>>,
+    /// File inputs whose native dialog the page asked for, by node id.
+    file_picker_requests: Rc<RefCell<Vec<usize>>>,
+    scroll_requests: Rc<RefCell<Vec<ScrollIntoView>>>,
+    /// Set by `IntersectionObserver.observe()` until the next [`Self::update_intersections`].
+    intersection_update: Rc<Cell<bool>>,
+    user_activation: Rc<Cell<Option<Instant>>>,
+    time_origin: Rc<Cell<Instant>>,
+    tasks: TaskTracker,
+    closed: Cell<bool>,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -38,24 +94,121 @@ pub struct DispatchOutcome {
 }
 impl JsDomEnvironment {
     pub fn new(html: &str) -> Result<Self> {
+        Self::new_with_url(html, None)
+    }
+
+    /// Create an environment whose scripts run in the origin of `document_url`. Without a
+    /// URL the document gets an opaque origin and every `fetch()` is cross-origin.
+    pub fn new_with_url(html: &str, document_url: Option<Url>) -> Result<Self> {
         let state = Rc::new(RefCell::new(DomState::new(html)));
+        let signer_requests = Rc::new(RefCell::new(Vec::new()));
+        let p2p_requests = Rc::new(RefCell::new(Vec::new()));
+        let posted_messages = Rc::new(RefCell::new(Vec::new()));
+        let window_requests = Rc::new(RefCell::new(Vec::new()));
+        let file_picker_requests = Rc::new(RefCell::new(Vec::new()));
+        let scroll_requests = Rc::new(RefCell::new(Vec::new()));
+        let intersection_update = Rc::new(Cell::new(false));
+        let user_activation = Rc::new(Cell::new(None));
+        let time_origin = Rc::new(Cell::new(Instant::now()));
         let engine = QuickJsEngine::new()?;
-        let timers = Rc::new(TimerManager::new(Handle::current()));
-        install_dom_bindings(&engine, Rc::clone(&state), Rc::clone(&timers))?;
-        Ok(Self {
+        let tasks = TaskTracker::default();
+        let timers = Rc::new(TimerManager::new(Handle::current(), tasks.clone()));
+        let blobs = BlobStore::default();
+        let canvases = CanvasStore::default();
+        let fetches = Rc::new(FetchManager::new(
+            Handle::current(),
+            document_url,
+            ContentSecurityPolicy::from_html(html),
+            timers.waker(),
+            blobs.clone(),
+            tasks.clone(),
+        ));
+        install_dom_bindings(
+            &engine,
+            Rc::clone(&state),
+            Rc::clone(&timers),
+            Rc::clone(&fetches),
+            blobs.clone(),
+            canvases.clone(),
+            Rc::clone(&signer_requests),
+            Rc::clone(&p2p_requests),
+            Rc::clone(&posted_messages),
+            Rc::clone(&window_requests),
+            Rc::clone(&file_picker_requests),
+            Rc::clone(&scroll_requests),
+            Rc::clone(&intersection_update),
+            Rc::clone(&user_activation),
+            Rc::clone(&time_origin),
+        )?;
+        let environment = Self {
             engine,
             state,
             timers,
-        })
+            fetches,
+            blobs,
+            canvases,
+            signer_requests,
+            p2p_requests,
+            posted_messages,
+            window_requests,
+            file_picker_requests,
+            scroll_requests,
+            intersection_update,
+            user_activation,
+            time_origin,
+            tasks,
+            closed: Cell::new(false),
+        };
+        environment.enforce_eval_policy()?;
+        Ok(environment)
+    }
+
+    /// Tear the page down when its document is replaced: abort timers and fetches, drop
+    /// queued promise jobs and forget every event listener. The environment is inert
+    /// afterwards; events, lifecycle dispatches and pumps do nothing.
+    pub fn shutdown(&self) -> TeardownCheck {
+        if !self.closed.replace(true) {
+            let _ = self.engine.with_context(|ctx| {
+                let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+                let teardown: Function = frontier.get("__teardown")?;
+                teardown.call::<_, ()>(())
+            });
+            self.timers.shutdown();
+            self.fetches.shutdown();
+            self.blobs.clear();
+            self.canvases.clear();
+            self.signer_requests.borrow_mut().clear();
+            self.p2p_requests.borrow_mut().clear();
+            self.posted_messages.borrow_mut().clear();
+            self.window_requests.borrow_mut().clear();
+            let mut state = self.state.borrow_mut();
+            state.clear_listeners();
+            state.detach();
+        }
+        self.tasks
+            .check(self.fetches.document_url().map(Url::to_string))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.get()
     }
 
     pub fn is_listening(&self, event_type: &str) -> bool {
-        self.state.borrow().is_listening(event_type)
+        !self.closed.get() && self.state.borrow().is_listening(event_type)
+    }
+
+    /// Whether the page listens for `data` or for the pointer event fired ahead of it.
+    pub fn listens_for(&self, data: &DomEventData) -> bool {
+        self.is_listening(data.name())
+            || pointer_event_name(data).is_some_and(|name| self.is_listening(name))
     }
 
     pub fn dispatch_dom_event(&self, event: &DomEvent, chain: &[usize]) -> Result<DispatchOutcome> {
         let event_name = event.data.name();
-        if !self.is_listening(event_name) {
+        if !self.closed.get() && activates_user(&event.data) {
+            self.user_activation.set(Some(Instant::now()));
+        }
+        if !self.listens_for(&event.data) {
             return Ok(DispatchOutcome::default());
         }
 
@@ -142,10 +295,98 @@ impl JsDomEnvironment {
         Ok(outcome)
     }
 
+    /// Drop `files` onto node `target` at `(x, y)` in CSS pixels, firing `dragenter`,
+    /// `dragover` and `drop` along `chain`. Returns whether the page cancelled the drop, in
+    /// which case the host must not open the files itself.
+    pub fn drop_files(
+        &self,
+        files: &[LocalFile],
+        target: usize,
+        chain: &[usize],
+        (x, y): (f64, f64),
+    ) -> Result<bool> {
+        if self.closed.get() {
+            return Ok(false);
+        }
+        let (target_handle, mut path_handles) = {
+            let state = self.state.borrow();
+            let Some(target) = state.normalize_handle(target)? else {
+                return Ok(false);
+            };
+            (target, state.normalize_chain(chain)?)
+        };
+        if path_handles.is_empty() {
+            path_handles.push(target_handle.clone());
+        }
+
+        let payload = to_json_string(&self.blobs.store_files(files))?;
+        let taken = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let drop_files: Function = frontier.get("__dropFiles")?;
+            let files = ctx.json_parse(payload.as_bytes())?;
+            drop_files.call::<_, bool>((target_handle, path_handles, files, x, y))
+        })?;
+        self.pump()?;
+        Ok(taken)
+    }
+
+    /// Fire a trackpad gesture event at node `target` at `(x, y)` in CSS pixels, bubbling along
+    /// `chain`. Returns whether the page cancelled it, in which case the host must not zoom or
+    /// navigate.
+    pub fn dispatch_gesture(
+        &self,
+        event: GestureEvent,
+        target: usize,
+        chain: &[usize],
+        (x, y): (f64, f64),
+    ) -> Result<bool> {
+        if !self.is_listening(event.name()) {
+            return Ok(false);
+        }
+        let (target_handle, mut path_handles) = {
+            let state = self.state.borrow();
+            let Some(target) = state.normalize_handle(target)? else {
+                return Ok(false);
+            };
+            (target, state.normalize_chain(chain)?)
+        };
+        if path_handles.is_empty() {
+            path_handles.push(target_handle.clone());
+        }
+        let (scale, direction) = match event {
+            GestureEvent::Pinch { scale, .. } => (scale, ""),
+            GestureEvent::Swipe(direction) => (1.0, direction.as_str()),
+        };
+        let cancelled = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let fire: Function = frontier.get("__fireGesture")?;
+            fire.call::<_, bool>((
+                target_handle,
+                path_handles,
+                event.name(),
+                scale,
+                direction,
+                x,
+                y,
+            ))
+        })?;
+        self.pump()?;
+        Ok(cancelled)
+    }
+
     pub fn eval(&self, source: &str, filename: &str) -> Result<()> {
         self.engine.eval(source, filename)
     }
 
+    /// Evaluate a script the page itself supplied, through the bytecode cache.
+    pub fn eval_script(&self, source: &str, filename: &str) -> Result<()> {
+        self.engine.eval_script(source, filename)
+    }
+
+    pub fn compile_stats(&self) -> CompileStats {
+        self.engine.compile_stats()
+    }
+
     #[allow(dead_code)]
     pub fn eval_with<V>(&self, source: &str, filename: &str) -> Result<V>
     where
@@ -154,6 +395,55 @@ impl JsDomEnvironment {
         self.engine.eval_with(source, filename)
     }
 
+    pub fn heap_usage(&self) -> JsHeapUsage {
+        self.engine.heap_usage()
+    }
+
+    /// Hold this environment's scripts to `quota`, counted against its document's origin,
+    /// together with the canvas bitmaps and `fetch()` bodies it holds.
+    pub fn isolate(&self, quota: OriginQuota) {
+        self.engine.isolate(self.fetches.document_url(), quota);
+        if let Some(memory) = self.engine.native_memory() {
+            self.canvases.set_memory(memory.clone());
+            self.fetches.set_memory(memory);
+        }
+    }
+
+    pub fn register_source_map(&self, filename: &str, map: SourceMap) {
+        self.engine.source_maps().register(filename, map);
+    }
+
+    /// Register the source map of a script about to run as `filename`: the map navigation
+    /// fetched with it, or an inline `data:` map at the end of its code.
+    pub fn load_source_map(&self, code: &str, filename: &str, descriptor: &ScriptDescriptor) {
+        let mapping_url = sourcemap::source_mapping_url(code);
+        let json = match (&descriptor.source_map, mapping_url) {
+            (Some(json), _) => Ok(json.clone()),
+            (None, Some(url)) => match sourcemap::decode_inline_map(url) {
+                Some(decoded) => decoded,
+                None => return,
+            },
+            (None, None) => return,
+        };
+        let script_url = descriptor
+            .url
+            .as_deref()
+            .and_then(|url| Url::parse(url).ok());
+        let map_url = match (&script_url, mapping_url) {
+            (Some(script_url), Some(mapping_url)) => script_url.join(mapping_url).ok(),
+            _ => script_url.clone(),
+        };
+        match json.and_then(|json| SourceMap::parse(&json, map_url.as_ref())) {
+            Ok(map) => self.register_source_map(filename, map),
+            Err(err) => warn!(
+                target = "quickjs",
+                script = %filename,
+                error = %err,
+                "failed to load source map"
+            ),
+        }
+    }
+
     pub fn drain_mutations(&self) -> Vec<DomPatch> {
         self.state.borrow_mut().drain_mutations()
     }
@@ -162,7 +452,16 @@ impl JsDomEnvironment {
         self.state.borrow().to_html()
     }
 
-    pub fn attach_document(&self, document: &mut BaseDocument) {
+    /// Let the page's scripts operate on `document`. Outside this module, use
+    /// [`RuntimeDocument::attach`](super::runtime_document::RuntimeDocument::attach) or
+    /// [`RuntimeDocument::attach_to`](super::runtime_document::RuntimeDocument::attach_to),
+    /// which box the document and detach it when it drops.
+    ///
+    /// # Safety
+    ///
+    /// `document` must stay where it is and alive until [`Self::detach_document`] is called
+    /// with it or the environment is shut down.
+    pub unsafe fn attach_document(&self, document: &mut BaseDocument) {
         self.state.borrow_mut().attach_document(document);
         let _ = self.engine.with_context(|ctx| {
             let global = ctx.globals();
@@ -175,7 +474,17 @@ impl JsDomEnvironment {
         });
     }
 
-    pub fn reattach_document(&self, document: &mut BaseDocument) {
+    /// Drop the bridge if it points at `document`, which is going away.
+    pub(crate) fn detach_document(&self, document: &BaseDocument) {
+        self.state.borrow_mut().detach_document(document);
+    }
+
+    /// Point the page's scripts at `document` in place of the attached one.
+    ///
+    /// # Safety
+    ///
+    /// As for [`Self::attach_document`].
+    pub(crate) unsafe fn reattach_document(&self, document: &mut BaseDocument) {
         self.state.borrow_mut().reattach_document(document);
         let _ = self.engine.with_context(|ctx| {
             let global = ctx.globals();
@@ -189,40 +498,410 @@ impl JsDomEnvironment {
     }
 
     pub fn pump(&self) -> Result<bool> {
+        if self.closed.get() {
+            // Promise jobs still queued belong to the old page and are never run.
+            return Ok(false);
+        }
         let mut did_work = false;
         loop {
             let timers_ran = self.timers.run_due(&self.engine)?;
+            let fetches_ran = self.fetches.run_completed(&self.engine)?;
             let jobs_ran = self.engine.drain_jobs()?;
-            if timers_ran || jobs_ran {
+            if timers_ran || fetches_ran || jobs_ran {
                 did_work = true;
-            }
-            if !timers_ran && !jobs_ran {
+            } else {
                 break;
             }
         }
         Ok(did_work)
     }
 
+    /// Timers and fetches wake `waker` when they become ready; promise jobs are drained by the
+    /// same pump as the work that queued them. A host that pumps only when woken never polls.
     pub fn register_waker(&self, waker: &Waker) {
         self.timers.register_waker(waker);
     }
+
+    /// Whether timers are still scheduled. They wake the registered waker when they fire.
+    pub fn has_pending_timers(&self) -> bool {
+        !self.closed.get() && self.timers.has_pending()
+    }
+
+    /// Whether the page has anything left to run: scheduled timers, requests in flight or
+    /// queued promise jobs. A static page has none once it has loaded.
+    pub fn has_pending_work(&self) -> bool {
+        !self.closed.get()
+            && (self.timers.has_pending()
+                || self.fetches.has_inflight()
+                || self.engine.has_pending_jobs())
+    }
+
+    /// Fire a lifecycle event and run any work it queues. Returns `true` only for
+    /// `beforeunload` when the page asked the user to confirm leaving.
+    pub fn dispatch_lifecycle(&self, event: LifecycleEvent) -> Result<bool> {
+        if self.closed.get() {
+            return Ok(false);
+        }
+        let prompt = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let fire: Function = frontier.get("__fireLifecycle")?;
+            fire.call::<_, bool>((event.name(), event.argument()))
+        })?;
+        self.pump()?;
+        Ok(prompt)
+    }
+
+    /// Report the viewport in CSS pixels and its device pixel ratio. Fires `resize` on
+    /// `window` when the size changed; returns whether any metric did.
+    pub fn set_viewport(&self, metrics: ViewportMetrics) -> Result<bool> {
+        let changed = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setViewport")?;
+            set.call::<_, bool>((metrics.width, metrics.height, metrics.device_pixel_ratio))
+        })?;
+        if changed {
+            self.pump()?;
+        }
+        Ok(changed)
+    }
+
+    /// Update the user preferences media queries evaluate against, firing `change` on every
+    /// `MediaQueryList` whose result flipped.
+    pub fn set_media_preferences(&self, preferences: MediaPreferences) -> Result<()> {
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setMediaPreferences")?;
+            set.call::<_, ()>((
+                preferences.color_scheme.as_str(),
+                preferences.reduced_motion,
+            ))
+        })?;
+        self.pump()?;
+        Ok(())
+    }
+
+    /// Move the time origin to the start of the navigation and publish its
+    /// `PerformanceNavigationTiming` entry. Call before any page script runs so
+    /// `performance.now()` never goes backwards.
+    pub fn set_navigation_timing(&self, timing: &NavigationTiming) -> Result<()> {
+        self.time_origin.set(timing.time_origin);
+        let payload = serde_json::to_string(&timing.payload())?;
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setNavigationTiming")?;
+            set.call::<_, ()>((payload,))
+        })?;
+        Ok(())
+    }
+
+    /// Record `fetch()` requests in `log`.
+    pub fn set_network_log(&self, log: NetworkLog) {
+        self.fetches.set_network_log(log);
+    }
+
+    /// Hold the page to `policy`, delivered with its response headers, on top of the policies
+    /// in its meta tags. Call before any page script runs.
+    pub fn add_content_security_policy(&self, policy: ContentSecurityPolicy) -> Result<()> {
+        self.fetches.add_csp(policy);
+        self.enforce_eval_policy()
+    }
+
+    /// Take `eval` and the `Function` constructors away from page script when the policy
+    /// does not allow `'unsafe-eval'`.
+    fn enforce_eval_policy(&self) -> Result<()> {
+        if self.fetches.csp().allows_eval() {
+            return Ok(());
+        }
+        self.engine
+            .eval(REFUSE_EVAL_BOOTSTRAP, "frontier-csp-eval.js")
+    }
+
+    /// Send `user_agent` with `fetch()` and report it from `navigator.userAgent` in place of
+    /// Frontier's own, or stop with `None`.
+    pub fn set_user_agent(&self, user_agent: Option<String>) {
+        self.fetches.set_user_agent(user_agent);
+    }
+
+    /// The `User-Agent` the page's requests go out with.
+    pub fn user_agent(&self) -> String {
+        self.fetches.user_agent()
+    }
+
+    /// Refuse `fetch()` and dynamically inserted script and stylesheet loads that `rules`
+    /// block.
+    pub fn set_content_rules(&self, rules: ContentRules) {
+        self.fetches.set_content_rules(rules);
+    }
+
+    /// Refuse `fetch()` and dynamically inserted script and stylesheet loads that match a
+    /// network rule of `blocklist`.
+    pub fn set_blocklist(&self, blocklist: Arc<Blocklist>) {
+        self.fetches.set_blocklist(blocklist);
+    }
+
+    /// Run timers, `requestAnimationFrame` timestamps and `performance.now()` off `clock`
+    /// instead of the wall clock. Call before any page script runs; the page starts at the
+    /// clock's current time.
+    pub fn set_virtual_clock(&self, clock: VirtualClock) {
+        self.timers.set_clock(clock);
+    }
+
+    /// When the next timer falls due on the virtual clock, if the page follows one.
+    pub fn next_timer_due(&self) -> Option<Duration> {
+        if self.closed.get() {
+            return None;
+        }
+        self.timers.next_due()
+    }
+
+    /// Whether `fetch()` requests are still in flight.
+    pub fn has_requests_in_flight(&self) -> bool {
+        !self.closed.get() && self.fetches.has_inflight()
+    }
+
+    /// Count the page scripts evaluated from now on into `coverage`.
+    pub fn set_coverage(&self, coverage: JsCoverage) -> Result<()> {
+        self.engine.set_coverage(coverage)
+    }
+
+    /// `window.nostr` calls made since the last call, oldest first.
+    pub fn take_signer_requests(&self) -> Vec<SignerRequest> {
+        std::mem::take(&mut *self.signer_requests.borrow_mut())
+    }
+
+    /// `frontier.connect` and channel calls made since the last call, oldest first.
+    pub fn take_p2p_requests(&self) -> Vec<P2pRequest> {
+        std::mem::take(&mut *self.p2p_requests.borrow_mut())
+    }
+
+    /// `postMessage` calls made since the last call, oldest first.
+    pub fn take_posted_messages(&self) -> Vec<PostedMessage> {
+        std::mem::take(&mut *self.posted_messages.borrow_mut())
+    }
+
+    /// `window.open` and `close()` calls made since the last call, oldest first.
+    pub fn take_window_requests(&self) -> Vec<WindowRequest> {
+        std::mem::take(&mut *self.window_requests.borrow_mut())
+    }
+
+    /// File inputs whose dialog the page asked for since the last call, by node id.
+    pub fn take_file_picker_requests(&self) -> Vec<usize> {
+        std::mem::take(&mut *self.file_picker_requests.borrow_mut())
+    }
+
+    /// Whether an `IntersectionObserver` started observing since the last
+    /// [`Self::update_intersections`]; the host should lay out and call it even if nothing
+    /// else needs a new frame.
+    pub fn wants_intersection_update(&self) -> bool {
+        !self.closed.get() && self.intersection_update.get()
+    }
+
+    /// Compute every `IntersectionObserver` against the current layout and deliver the entries.
+    /// Call after each layout the host resolves. Returns whether any entries were delivered, in
+    /// which case the page may have changed and needs another frame.
+    pub fn update_intersections(&self) -> Result<bool> {
+        self.intersection_update.set(false);
+        if self.closed.get() {
+            return Ok(false);
+        }
+        let queued = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let update: Function = frontier.get("__updateIntersections")?;
+            update.call::<_, bool>(())
+        })?;
+        self.pump()?;
+        Ok(queued)
+    }
+
+    /// `scrollIntoView` calls made since the last call, oldest first.
+    pub fn take_scroll_requests(&self) -> Vec<ScrollIntoView> {
+        std::mem::take(&mut *self.scroll_requests.borrow_mut())
+    }
+
+    /// Make `files` the selection of the file input at `node`, firing `input` and `change`.
+    /// Returns `false` when the node is not a file input.
+    pub fn set_input_files(&self, node: usize, files: &[LocalFile]) -> Result<bool> {
+        if self.closed.get() {
+            return Ok(false);
+        }
+        let Some(handle) = self.state.borrow().normalize_handle(node)? else {
+            return Ok(false);
+        };
+        let payload = to_json_string(&self.blobs.store_files(files))?;
+        let updated = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setInputFiles")?;
+            let files = ctx.json_parse(payload.as_bytes())?;
+            set.call::<_, bool>((handle, files))
+        })?;
+        self.pump()?;
+        Ok(updated)
+    }
+
+    /// Fire `input` and `change` at the form control at `node`, after the user picked a new
+    /// value for it in one of the host's widgets. Returns `false` when the node is gone.
+    pub fn control_changed(&self, node: usize) -> Result<bool> {
+        self.fire_control_events(node, true)
+    }
+
+    /// Fire just `input` at the form control at `node`, while the user is still changing it,
+    /// e.g. dragging a range slider.
+    pub fn control_input(&self, node: usize) -> Result<bool> {
+        self.fire_control_events(node, false)
+    }
+
+    fn fire_control_events(&self, node: usize, change: bool) -> Result<bool> {
+        if self.closed.get() {
+            return Ok(false);
+        }
+        let Some(handle) = self.state.borrow().normalize_handle(node)? else {
+            return Ok(false);
+        };
+        let fired = self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let changed: Function = frontier.get("__controlChanged")?;
+            changed.call::<_, bool>((handle, change))
+        })?;
+        self.pump()?;
+        Ok(fired)
+    }
+
+    /// Mark the document as opened by another window with `window.open`, so `window.opener`
+    /// refers to it. Call before any page script runs.
+    pub fn set_opened(&self, opened: bool) -> Result<()> {
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setOpened")?;
+            set.call::<_, ()>((opened,))
+        })?;
+        Ok(())
+    }
+
+    /// Report the popup `id` as closed on the proxy `window.open` returned for it.
+    pub fn set_popup_closed(&self, id: u32) -> Result<()> {
+        if self.closed.get() {
+            return Ok(());
+        }
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setPopupClosed")?;
+            set.call::<_, ()>((id,))
+        })?;
+        Ok(())
+    }
+
+    /// Mark the document as loaded in an iframe, so `window.parent` refers to the embedder.
+    /// Call before any page script runs.
+    pub fn set_framed(&self, framed: bool) -> Result<()> {
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setFramed")?;
+            set.call::<_, ()>((framed,))
+        })?;
+        Ok(())
+    }
+
+    /// Expose (or withdraw) `contentWindow` on the iframe element `node_id`.
+    pub fn set_frame_loaded(&self, node_id: usize, loaded: bool) -> Result<()> {
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let set: Function = frontier.get("__setFrameLoaded")?;
+            set.call::<_, ()>((node_id.to_string(), loaded))
+        })?;
+        Ok(())
+    }
+
+    /// Fire `message` on `window` with JSON `data` posted by `source` from `origin`.
+    pub fn deliver_message(&self, data: &str, origin: &str, source: WindowRef) -> Result<()> {
+        if self.closed.get() {
+            return Ok(());
+        }
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let deliver: Function = frontier.get("__deliverMessage")?;
+            deliver.call::<_, ()>((data, origin, source.to_js()))
+        })?;
+        self.pump()?;
+        Ok(())
+    }
+
+    /// Resolve (with a JSON value) or reject (with a message) the promise of a `window.nostr`
+    /// call.
+    pub fn settle_signer_request(
+        &self,
+        id: u32,
+        result: std::result::Result<JsonValue, String>,
+    ) -> Result<()> {
+        let (ok, payload) = match result {
+            Ok(value) => (true, to_json_string(&value)?),
+            Err(message) => (false, message),
+        };
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let settle: Function = frontier.get("__nostrSettle")?;
+            settle.call::<_, ()>((id, ok, payload))
+        })?;
+        self.pump()?;
+        Ok(())
+    }
+
+    /// Report what happened to the `frontier.connect` channel `channel`: the promise settles
+    /// on the first event, later ones fire on the channel.
+    pub fn deliver_p2p_event(&self, channel: u32, event: &ChannelEvent) -> Result<()> {
+        if self.closed.get() {
+            return Ok(());
+        }
+        let (kind, payload) = match event {
+            ChannelEvent::Opened => ("open", ""),
+            ChannelEvent::Message(data) => ("message", data.as_str()),
+            ChannelEvent::Closed(reason) => ("close", reason.as_str()),
+        };
+        self.engine.with_context(|ctx| {
+            let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+            let deliver: Function = frontier.get("__p2pEvent")?;
+            deliver.call::<_, ()>((channel, kind, payload))
+        })?;
+        self.pump()?;
+        Ok(())
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn install_dom_bindings(
     engine: &QuickJsEngine,
     state: Rc<RefCell<DomState>>,
     timers: Rc<TimerManager>,
+    fetches: Rc<FetchManager>,
+    blobs: BlobStore,
+    canvases: CanvasStore,
+    signer_requests: Rc<RefCell<Vec<SignerRequest>>>,
+    p2p_requests: Rc<RefCell<Vec<P2pRequest>>>,
+    posted_messages: Rc<RefCell<Vec<PostedMessage>>>,
+    window_requests: Rc<RefCell<Vec<WindowRequest>>>,
+    file_picker_requests: Rc<RefCell<Vec<usize>>>,
+    scroll_requests: Rc<RefCell<Vec<ScrollIntoView>>>,
+    intersection_update: Rc<Cell<bool>>,
+    user_activation: Rc<Cell<Option<Instant>>>,
+    time_origin: Rc<Cell<Instant>>,
 ) -> Result<()> {
     engine.with_context(|ctx| {
         let global = ctx.globals();
+        install_blob_bindings(&ctx, &blobs)?;
+        install_canvas_bindings(&ctx, &canvases)?;
+        install_storage_bindings(&ctx, fetches.document_url().cloned())?;
+        install_navigator_bindings(&ctx, Rc::clone(&fetches))?;
 
         // Lookup helpers
         {
             let state_ref = Rc::clone(&state);
             let func = Function::new(
                 ctx.clone(),
-                move |id: String| -> rquickjs::Result<Option<String>> {
-                    Ok(state_ref.borrow_mut().handle_from_element_id(&id))
+                move |ctx: Ctx<'_>, id: String| -> rquickjs::Result<Option<String>> {
+                    let mut state = state_ref.borrow_mut();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.handle_from_element_id(&id))
                 },
             )?
             .with_name("__frontier_dom_get_handle_by_id")?;
@@ -233,8 +912,12 @@ fn install_dom_bindings(
             let state_ref = Rc::clone(&state);
             let func = Function::new(
                 ctx.clone(),
-                move |handle: String| -> rquickjs::Result<Option<String>> {
-                    Ok(state_ref.borrow().text_content(&handle))
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.text_content(&handle))
                 },
             )?
             .with_name("__frontier_dom_get_text")?;
@@ -245,14 +928,34 @@ fn install_dom_bindings(
             let state_ref = Rc::clone(&state);
             let func = Function::new(
                 ctx.clone(),
-                move |handle: String| -> rquickjs::Result<Option<String>> {
-                    Ok(state_ref.borrow().inner_html(&handle))
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.inner_html(&handle))
                 },
             )?
             .with_name("__frontier_dom_get_html")?;
             global.set("__frontier_dom_get_html", func)?;
         }
 
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.outer_html(&handle))
+                },
+            )?
+            .with_name("__frontier_dom_get_outer_html")?;
+            global.set("__frontier_dom_get_outer_html", func)?;
+        }
+
         // Mutation helpers
         {
             let state_ref = Rc::clone(&state);
@@ -295,6 +998,215 @@ fn install_dom_bindings(
             global.set("__frontier_dom_set_inner_html", func)?;
         }
 
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      handle: String,
+                      value: Option<String>|
+                      -> rquickjs::Result<()> {
+                    let html = value.unwrap_or_default();
+                    match state_ref.borrow_mut().set_outer_html_direct(&handle, &html) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_outer_html")?;
+            global.set("__frontier_dom_set_outer_html", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      handle: String,
+                      position: String,
+                      value: Option<String>|
+                      -> rquickjs::Result<()> {
+                    let html = value.unwrap_or_default();
+                    match state_ref
+                        .borrow_mut()
+                        .insert_adjacent_html_direct(&handle, &position, &html)
+                    {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_insert_adjacent_html")?;
+            global.set("__frontier_dom_insert_adjacent_html", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      context: String,
+                      value: Option<String>|
+                      -> rquickjs::Result<Vec<String>> {
+                    let html = value.unwrap_or_default();
+                    match state_ref.borrow_mut().parse_fragment(&context, &html) {
+                        Ok(handles) => Ok(handles),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_parse_fragment")?;
+            global.set("__frontier_dom_parse_fragment", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<String> {
+                    match state_ref.borrow_mut().template_content(&handle) {
+                        Ok(content) => Ok(content),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_template_content")?;
+            global.set("__frontier_dom_template_content", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, mode: String| -> rquickjs::Result<String> {
+                    match state_ref.borrow_mut().attach_shadow(&handle, &mode) {
+                        Ok(root) => Ok(root),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_attach_shadow")?;
+            global.set("__frontier_dom_attach_shadow", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.shadow_root(&handle))
+                },
+            )?
+            .with_name("__frontier_dom_shadow_root")?;
+            global.set("__frontier_dom_shadow_root", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<Vec<String>>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state
+                        .shadow_host(&handle)
+                        .map(|(host, mode)| vec![host, mode.to_string()]))
+                },
+            )?
+            .with_name("__frontier_dom_shadow_host")?;
+            global.set("__frontier_dom_shadow_host", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Vec<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.assigned_nodes(&handle))
+                },
+            )?
+            .with_name("__frontier_dom_assigned_nodes")?;
+            global.set("__frontier_dom_assigned_nodes", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.assigned_slot(&handle))
+                },
+            )?
+            .with_name("__frontier_dom_assigned_slot")?;
+            global.set("__frontier_dom_assigned_slot", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, root: String, id: String| -> rquickjs::Result<Option<String>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.element_by_id_in(&root, &id))
+                },
+            )?
+            .with_name("__frontier_dom_shadow_element_by_id")?;
+            global.set("__frontier_dom_shadow_element_by_id", func)?;
+        }
+
+        {
+            let func = Function::new(ctx.clone(), |name: String| {
+                is_valid_custom_element_name(&name)
+            })?
+            .with_name("__frontier_dom_is_valid_custom_element_name")?;
+            global.set("__frontier_dom_is_valid_custom_element_name", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, name: String, observed: Vec<String>| -> rquickjs::Result<()> {
+                    match state_ref
+                        .borrow_mut()
+                        .define_custom_element(&name, observed)
+                    {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_define_custom_element")?;
+            global.set("__frontier_dom_define_custom_element", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(ctx.clone(), move || -> String {
+                let reactions = state_ref.borrow_mut().take_custom_element_reactions();
+                serde_json::to_string(&reactions).unwrap_or_else(|_| "[]".to_string())
+            })?
+            .with_name("__frontier_dom_take_custom_element_reactions")?;
+            global.set("__frontier_dom_take_custom_element_reactions", func)?;
+        }
+
         {
             let state_ref = Rc::clone(&state);
             let func = Function::new(
@@ -314,26 +1226,75 @@ fn install_dom_bindings(
                     }
                 },
             )?
-            .with_name("__frontier_dom_set_attribute")?;
-            global.set("__frontier_dom_set_attribute", func)?;
+            .with_name("__frontier_dom_set_attribute")?;
+            global.set("__frontier_dom_set_attribute", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, name: String| -> rquickjs::Result<()> {
+                    match state_ref
+                        .borrow_mut()
+                        .remove_attribute_direct(&handle, &name)
+                    {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_remove_attribute")?;
+            global.set("__frontier_dom_remove_attribute", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      handle: String,
+                      namespace: Option<String>,
+                      name: String,
+                      value: Option<String>|
+                      -> rquickjs::Result<()> {
+                    let attr_value = value.unwrap_or_default();
+                    match state_ref.borrow_mut().set_attribute_ns_direct(
+                        &handle,
+                        namespace.as_deref(),
+                        &name,
+                        &attr_value,
+                    ) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_attribute_ns")?;
+            global.set("__frontier_dom_set_attribute_ns", func)?;
         }
 
         {
             let state_ref = Rc::clone(&state);
             let func = Function::new(
                 ctx.clone(),
-                move |ctx: Ctx<'_>, handle: String, name: String| -> rquickjs::Result<()> {
-                    match state_ref
-                        .borrow_mut()
-                        .remove_attribute_direct(&handle, &name)
-                    {
+                move |ctx: Ctx<'_>,
+                      handle: String,
+                      namespace: Option<String>,
+                      name: String|
+                      -> rquickjs::Result<()> {
+                    match state_ref.borrow_mut().remove_attribute_ns_direct(
+                        &handle,
+                        namespace.as_deref(),
+                        &name,
+                    ) {
                         Ok(()) => Ok(()),
                         Err(err) => dom_error(&ctx, err),
                     }
                 },
             )?
-            .with_name("__frontier_dom_remove_attribute")?;
-            global.set("__frontier_dom_remove_attribute", func)?;
+            .with_name("__frontier_dom_remove_attribute_ns")?;
+            global.set("__frontier_dom_remove_attribute_ns", func)?;
         }
 
         // Node creation
@@ -498,6 +1459,36 @@ fn install_dom_bindings(
             global.set("__frontier_dom_clone_node", func)?;
         }
 
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, offset: u32| -> rquickjs::Result<String> {
+                    match state_ref.borrow_mut().split_text(&handle, offset as usize) {
+                        Ok(new_handle) => Ok(new_handle),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_split_text")?;
+            global.set("__frontier_dom_split_text", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<()> {
+                    match state_ref.borrow_mut().normalize(&handle) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_normalize")?;
+            global.set("__frontier_dom_normalize", func)?;
+        }
+
         // Tree reads
         {
             let state_ref = Rc::clone(&state);
@@ -637,6 +1628,28 @@ fn install_dom_bindings(
             global.set("__frontier_dom_get_attribute", func)?;
         }
 
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      handle: String,
+                      namespace: Option<String>,
+                      name: String|
+                      -> rquickjs::Result<Option<String>> {
+                    match state_ref
+                        .borrow()
+                        .get_attribute_ns(&handle, namespace.as_deref(), &name)
+                    {
+                        Ok(value) => Ok(value),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_get_attribute_ns")?;
+            global.set("__frontier_dom_get_attribute_ns", func)?;
+        }
+
         {
             let state_ref = Rc::clone(&state);
             let func = Function::new(
@@ -733,6 +1746,362 @@ fn install_dom_bindings(
             global.set("__frontier_cancel_timer", func)?;
         }
 
+        // Fetch helpers
+        {
+            let fetches_ref = Rc::clone(&fetches);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, json: String| -> rquickjs::Result<u32> {
+                    let init: FetchInit = match serde_json::from_str(&json) {
+                        Ok(init) => init,
+                        Err(err) => {
+                            let message = format!("TypeError: invalid fetch request: {err}");
+                            return Err(ctx.throw(message.into_js(&ctx)?));
+                        }
+                    };
+                    Ok(fetches_ref.start(init, Destination::Empty))
+                },
+            )?
+            .with_name("__frontier_fetch_start")?;
+            global.set("__frontier_fetch_start", func)?;
+        }
+
+        {
+            let fetches_ref = Rc::clone(&fetches);
+            let func = Function::new(
+                ctx.clone(),
+                move |code: String, nonce: Option<String>| -> rquickjs::Result<bool> {
+                    Ok(fetches_ref
+                        .csp()
+                        .allows_inline_script(&code, nonce.as_deref()))
+                },
+            )?
+            .with_name("__frontier_allows_inline_script")?;
+            global.set("__frontier_allows_inline_script", func)?;
+        }
+
+        for (name, destination) in [
+            ("__frontier_fetch_script", Destination::Script),
+            ("__frontier_fetch_style", Destination::Style),
+        ] {
+            let fetches_ref = Rc::clone(&fetches);
+            let func = Function::new(ctx.clone(), move |url: String| -> rquickjs::Result<u32> {
+                Ok(fetches_ref.start(FetchInit::subresource(url), destination))
+            })?
+            .with_name(name)?;
+            global.set(name, func)?;
+        }
+
+        {
+            let fetches_ref = Rc::clone(&fetches);
+            let func = Function::new(ctx.clone(), move |id: u32| -> rquickjs::Result<()> {
+                fetches_ref.abort(id);
+                Ok(())
+            })?
+            .with_name("__frontier_fetch_abort")?;
+            global.set("__frontier_fetch_abort", func)?;
+        }
+
+        // NIP-07 signer
+        {
+            let requests_ref = Rc::clone(&signer_requests);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      id: u32,
+                      method: String,
+                      params: String|
+                      -> rquickjs::Result<()> {
+                    match SignerRequest::parse(id, &method, &params) {
+                        Ok(request) => {
+                            requests_ref.borrow_mut().push(request);
+                            Ok(())
+                        }
+                        Err(message) => {
+                            let message = format!("TypeError: {message}");
+                            Err(ctx.throw(message.into_js(&ctx)?))
+                        }
+                    }
+                },
+            )?
+            .with_name("__frontier_nostr_request")?;
+            global.set("__frontier_nostr_request", func)?;
+        }
+
+        // Nostr channels
+        {
+            let requests_ref = Rc::clone(&p2p_requests);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, method: String, params: String| -> rquickjs::Result<()> {
+                    match P2pRequest::parse(&method, &params) {
+                        Ok(request) => {
+                            requests_ref.borrow_mut().push(request);
+                            Ok(())
+                        }
+                        Err(message) => {
+                            let message = format!("TypeError: {message}");
+                            Err(ctx.throw(message.into_js(&ctx)?))
+                        }
+                    }
+                },
+            )?
+            .with_name("__frontier_p2p_request")?;
+            global.set("__frontier_p2p_request", func)?;
+        }
+
+        // Cross-document messaging
+        {
+            let messages_ref = Rc::clone(&posted_messages);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>,
+                      target: String,
+                      data: String,
+                      target_origin: String|
+                      -> rquickjs::Result<()> {
+                    match WindowRef::parse(&target) {
+                        Ok(target) => {
+                            messages_ref.borrow_mut().push(PostedMessage {
+                                target,
+                                data,
+                                target_origin,
+                            });
+                            Ok(())
+                        }
+                        Err(message) => {
+                            let message = format!("TypeError: {message}");
+                            Err(ctx.throw(message.into_js(&ctx)?))
+                        }
+                    }
+                },
+            )?
+            .with_name("__frontier_post_message")?;
+            global.set("__frontier_post_message", func)?;
+
+            let func = Function::new(ctx.clone(), |ctx: Ctx<'_>, buffer: Value<'_>| {
+                detach_array_buffer(&ctx, &buffer);
+            })?
+            .with_name("__frontier_detach_array_buffer")?;
+            global.set("__frontier_detach_array_buffer", func)?;
+        }
+
+        // window.open
+        {
+            let requests_ref = Rc::clone(&window_requests);
+            let activation_ref = Rc::clone(&user_activation);
+            let fetches_ref = Rc::clone(&fetches);
+            let next_id = Cell::new(0u32);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, url: String, target: String| -> rquickjs::Result<Option<u32>> {
+                    let base = fetches_ref
+                        .document_url()
+                        .cloned()
+                        .unwrap_or_else(|| Url::parse("about:blank").expect("valid URL"));
+                    let url = if url.is_empty() {
+                        Url::parse("about:blank").expect("valid URL")
+                    } else {
+                        match base.join(&url) {
+                            Ok(url) => url,
+                            Err(err) => {
+                                let message =
+                                    format!("SyntaxError: '{url}' is not a valid URL: {err}");
+                                return Err(ctx.throw(message.into_js(&ctx)?));
+                            }
+                        }
+                    };
+                    if targets_current_window(&target) {
+                        requests_ref.borrow_mut().push(WindowRequest::Open {
+                            id: 0,
+                            url,
+                            target,
+                            user_activated: true,
+                        });
+                        return Ok(Some(0));
+                    }
+                    let user_activated = activation_ref
+                        .take()
+                        .is_some_and(|at| at.elapsed() <= USER_ACTIVATION_TIMEOUT);
+                    let id = next_id.get() + 1;
+                    next_id.set(id);
+                    requests_ref.borrow_mut().push(WindowRequest::Open {
+                        id,
+                        url,
+                        target,
+                        user_activated,
+                    });
+                    Ok(user_activated.then_some(id))
+                },
+            )?
+            .with_name("__frontier_window_open")?;
+            global.set("__frontier_window_open", func)?;
+
+            let requests_ref = Rc::clone(&window_requests);
+            let func = Function::new(ctx.clone(), move |id: u32| {
+                requests_ref.borrow_mut().push(WindowRequest::Close { id });
+            })?
+            .with_name("__frontier_popup_close")?;
+            global.set("__frontier_popup_close", func)?;
+
+            let requests_ref = Rc::clone(&window_requests);
+            let func = Function::new(ctx.clone(), move || {
+                requests_ref.borrow_mut().push(WindowRequest::CloseSelf);
+            })?
+            .with_name("__frontier_window_close")?;
+            global.set("__frontier_window_close", func)?;
+        }
+
+        // File input dialogs
+        {
+            let requests_ref = Rc::clone(&file_picker_requests);
+            let activation_ref = Rc::clone(&user_activation);
+            let func = Function::new(ctx.clone(), move |handle: String| -> bool {
+                let user_activated = activation_ref
+                    .get()
+                    .is_some_and(|at| at.elapsed() <= USER_ACTIVATION_TIMEOUT);
+                let Ok(node_id) = handle.parse::<usize>() else {
+                    return false;
+                };
+                if user_activated {
+                    requests_ref.borrow_mut().push(node_id);
+                }
+                user_activated
+            })?
+            .with_name("__frontier_request_file_picker")?;
+            global.set("__frontier_request_file_picker", func)?;
+        }
+
+        // scrollIntoView
+        {
+            let requests_ref = Rc::clone(&scroll_requests);
+            let func = Function::new(
+                ctx.clone(),
+                move |handle: String, smooth: bool, block: String, inline: String| {
+                    let (Ok(node), Some(block), Some(inline)) = (
+                        handle.parse::<usize>(),
+                        ScrollAlign::parse(&block),
+                        ScrollAlign::parse(&inline),
+                    ) else {
+                        return;
+                    };
+                    requests_ref.borrow_mut().push(ScrollIntoView {
+                        node,
+                        smooth,
+                        block,
+                        inline,
+                    });
+                },
+            )?
+            .with_name("__frontier_scroll_into_view")?;
+            global.set("__frontier_scroll_into_view", func)?;
+        }
+
+        // IntersectionObserver
+        {
+            let update_ref = Rc::clone(&intersection_update);
+            let func = Function::new(ctx.clone(), move || update_ref.set(true))?
+                .with_name("__frontier_request_intersection_update")?;
+            global.set("__frontier_request_intersection_update", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<Option<Vec<f64>>> {
+                    let state = state_ref.borrow();
+                    if let Err(err) = state.ensure_not_detached() {
+                        return dom_error(&ctx, err);
+                    }
+                    Ok(state.layout_rect(&handle))
+                },
+            )?
+            .with_name("__frontier_dom_layout_rect")?;
+            global.set("__frontier_dom_layout_rect", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, css: String| -> rquickjs::Result<()> {
+                    match state_ref.borrow_mut().set_style_rules(&handle, &css) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_style_rules")?;
+            global.set("__frontier_dom_set_style_rules", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, css: String| -> rquickjs::Result<()> {
+                    match state_ref.borrow_mut().set_adopted_styles(&handle, &css) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_adopted_styles")?;
+            global.set("__frontier_dom_set_adopted_styles", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String, css: String| -> rquickjs::Result<()> {
+                    match state_ref.borrow_mut().set_link_styles(&handle, &css) {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_link_styles")?;
+            global.set("__frontier_dom_set_link_styles", func)?;
+        }
+
+        {
+            let state_ref = Rc::clone(&state);
+            let func = Function::new(
+                ctx.clone(),
+                move |ctx: Ctx<'_>, handle: String| -> rquickjs::Result<()> {
+                    let shown = canvases.png(&handle).and_then(|image| {
+                        let image = image
+                            .as_ref()
+                            .map(|(png, width, height)| (png.as_slice(), *width, *height));
+                        state_ref.borrow_mut().set_canvas_image(&handle, image)
+                    });
+                    match shown {
+                        Ok(()) => Ok(()),
+                        Err(err) => dom_error(&ctx, err),
+                    }
+                },
+            )?
+            .with_name("__frontier_dom_set_canvas_image")?;
+            global.set("__frontier_dom_set_canvas_image", func)?;
+        }
+
+        // High-resolution time
+        {
+            let origin_ref = Rc::clone(&time_origin);
+            let timers_ref = Rc::clone(&timers);
+            let func = Function::new(ctx.clone(), move || -> rquickjs::Result<f64> {
+                let elapsed = match timers_ref.clock.borrow().is_some() {
+                    true => timers_ref.elapsed(),
+                    false => origin_ref.get().elapsed(),
+                };
+                Ok(elapsed.as_secs_f64() * 1000.0)
+            })?
+            .with_name("__frontier_performance_now")?;
+            global.set("__frontier_performance_now", func)?;
+        }
+
         // Legacy patch interface retained for compatibility
         {
             let state_ref = Rc::clone(&state);
@@ -756,7 +2125,34 @@ fn install_dom_bindings(
             global.set("__frontier_dom_apply_patch", func)?;
         }
 
-        match ctx.eval::<(), _>(DOM_BOOTSTRAP.as_bytes()) {
+        match ctx
+            .eval::<(), _>(DOM_BOOTSTRAP.as_bytes())
+            .and_then(|()| ctx.eval::<(), _>(BLOB_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(DRAG_DROP_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(FILE_INPUT_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(FORM_CONTROLS_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(FETCH_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(LIFECYCLE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(PERFORMANCE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(VIEWPORT_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(SCROLL_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(INTERSECTION_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(CSSOM_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(CANVAS_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(GESTURE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(MEDIA_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(NOSTR_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(P2P_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(STRUCTURED_CLONE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(MESSAGING_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(WINDOW_OPEN_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(DYNAMIC_SCRIPT_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(DYNAMIC_STYLE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(CUSTOM_ELEMENTS_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(STORAGE_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(NAVIGATOR_BOOTSTRAP.as_bytes()))
+            .and_then(|()| ctx.eval::<(), _>(SEAL_NATIVE_HOOKS.as_bytes()))
+        {
             Ok(()) => Ok(()),
             Err(err) => {
                 if let rquickjs::Error::Exception = err {
@@ -770,6 +2166,15 @@ fn install_dom_bindings(
 }
 
 fn dom_error<T>(ctx: &Ctx<'_>, err: anyhow::Error) -> rquickjs::Result<T> {
+    if let Some(DomError::Detached) = err.downcast_ref::<DomError>() {
+        // Expected from timers and promises that outlive their page; nothing to report.
+        tracing::debug!(
+            target = "quickjs",
+            "DOM access after the document was detached"
+        );
+        let value = DomError::Detached.to_string().into_js(ctx)?;
+        return Err(ctx.throw(value));
+    }
     tracing::error!(target = "quickjs", "DOM mutation failed: {err}");
     let message = format!("DOM mutation failed: {err}");
     let value = message.into_js(ctx)?;
@@ -787,29 +2192,40 @@ struct TimerEntry {
     kind: TimerKind,
     repeating: bool,
     task: Option<JoinHandle<()>>,
+    /// When the timer next falls due on the virtual clock, which then fires it instead of a
+    /// task.
+    due: Option<Duration>,
+    interval: Duration,
 }
 
 struct TimerManager {
     handle: Handle,
     start: Instant,
+    /// The virtual clock timers follow, with its time when the page started following it.
+    clock: RefCell<Option<(VirtualClock, Duration)>>,
     next_id: RefCell<u32>,
     timers: RefCell<HashMap<u32, TimerEntry>>,
     fired_rx: RefCell<UnboundedReceiver<u32>>,
     fired_tx: UnboundedSender<u32>,
     waker: Arc<AtomicWaker>,
+    tasks: TaskTracker,
+    closed: Cell<bool>,
 }
 
 impl TimerManager {
-    fn new(handle: Handle) -> Self {
+    fn new(handle: Handle, tasks: TaskTracker) -> Self {
         let (tx, rx) = unbounded_channel();
         Self {
             handle,
             start: Instant::now(),
+            clock: RefCell::new(None),
             next_id: RefCell::new(1),
             timers: RefCell::new(HashMap::new()),
             fired_rx: RefCell::new(rx),
             fired_tx: tx,
             waker: Arc::new(AtomicWaker::new()),
+            tasks,
+            closed: Cell::new(false),
         }
     }
 
@@ -824,12 +2240,40 @@ impl TimerManager {
         self.waker.register(waker);
     }
 
-    fn wake(&self) {
-        self.waker.wake();
+    /// Shared with other async sources so any completion wakes the document.
+    fn waker(&self) -> Arc<AtomicWaker> {
+        Arc::clone(&self.waker)
+    }
+
+    /// Schedule timers registered from now on on `clock`.
+    fn set_clock(&self, clock: VirtualClock) {
+        let start = clock.now();
+        *self.clock.borrow_mut() = Some((clock, start));
+    }
+
+    /// Time since the page started, on the virtual clock if it follows one.
+    fn elapsed(&self) -> Duration {
+        match self.clock.borrow().as_ref() {
+            Some((clock, start)) => clock.now().saturating_sub(*start),
+            None => self.start.elapsed(),
+        }
+    }
+
+    /// When the next timer falls due on the virtual clock.
+    fn next_due(&self) -> Option<Duration> {
+        self.timers
+            .borrow()
+            .values()
+            .filter_map(|entry| entry.due)
+            .min()
     }
 
     fn register_timer(&self, delay_ms: f64, kind: TimerKind, repeating: bool) -> u32 {
         let id = self.next_id();
+        if self.closed.get() {
+            // The page is gone; hand out an id that never fires.
+            return id;
+        }
         let mut duration = if delay_ms <= 0.0 {
             Duration::from_millis(0)
         } else {
@@ -844,10 +2288,24 @@ impl TimerManager {
             duration = Duration::from_millis(1);
         }
 
+        if let Some((clock, _)) = self.clock.borrow().as_ref() {
+            let entry = TimerEntry {
+                kind,
+                repeating,
+                task: None,
+                due: Some(clock.now() + duration),
+                interval: duration,
+            };
+            self.timers.borrow_mut().insert(id, entry);
+            return id;
+        }
+
         let tx = self.fired_tx.clone();
         let waker = Arc::clone(&self.waker);
+        let guard = self.tasks.guard();
         let join = if repeating {
             self.handle.spawn(async move {
+                let _guard = guard;
                 let interval = duration;
                 loop {
                     sleep(interval).await;
@@ -859,6 +2317,7 @@ impl TimerManager {
             })
         } else {
             self.handle.spawn(async move {
+                let _guard = guard;
                 sleep(duration).await;
                 if tx.send(id).is_ok() {
                     waker.wake();
@@ -870,10 +2329,12 @@ impl TimerManager {
             kind,
             repeating,
             task: Some(join),
+            due: None,
+            interval: duration,
         };
 
+        // No wake here: the task wakes the document when the timer is due.
         self.timers.borrow_mut().insert(id, entry);
-        self.wake();
         id
     }
 
@@ -883,7 +2344,27 @@ impl TimerManager {
                 task.abort();
             }
         }
-        self.wake();
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.timers.borrow().is_empty()
+    }
+
+    /// Abort every pending timer and drop fires that have not run yet. Later registrations
+    /// get ids that never fire.
+    fn shutdown(&self) {
+        self.closed.set(true);
+        self.abort_all();
+        let mut rx = self.fired_rx.borrow_mut();
+        while rx.try_recv().is_ok() {}
+    }
+
+    fn abort_all(&self) {
+        for (_, entry) in self.timers.borrow_mut().drain() {
+            if let Some(task) = entry.task {
+                task.abort();
+            }
+        }
     }
 
     fn run_due(&self, engine: &QuickJsEngine) -> Result<bool> {
@@ -894,6 +2375,16 @@ impl TimerManager {
                 fired.push(id);
             }
         }
+        if let Some((clock, _)) = self.clock.borrow().as_ref() {
+            let now = clock.now();
+            let timers = self.timers.borrow();
+            let mut due: Vec<(Duration, u32)> = timers
+                .iter()
+                .filter_map(|(id, entry)| Some((entry.due.filter(|due| *due <= now)?, *id)))
+                .collect();
+            due.sort_unstable();
+            fired.extend(due.into_iter().map(|(_, id)| id));
+        }
 
         let mut ran = false;
         for id in fired {
@@ -923,6 +2414,8 @@ impl TimerManager {
                         handle.abort();
                     }
                 }
+            } else if let Some(entry) = self.timers.borrow_mut().get_mut(&id) {
+                entry.due = entry.due.map(|due| due + entry.interval);
             }
         }
 
@@ -942,7 +2435,7 @@ impl TimerManager {
             let mut builder = FunctionArgs::new(ctx.clone(), arg_count);
             builder.push_arg(id)?;
             if matches!(kind, TimerKind::AnimationFrame) {
-                let timestamp = self.start.elapsed().as_secs_f64() * 1_000.0;
+                let timestamp = self.elapsed().as_secs_f64() * 1_000.0;
                 builder.push_arg(timestamp)?;
             }
 
@@ -973,10 +2466,18 @@ impl TimerManager {
     }
 }
 
+impl Drop for TimerManager {
+    fn drop(&mut self) {
+        self.abort_all();
+    }
+}
+
 fn build_event_detail(event: &DomEvent) -> JsonValue {
     let mut map = JsonMap::new();
     map.insert("bubbles".to_string(), JsonValue::Bool(event.bubbles));
     map.insert("cancelable".to_string(), JsonValue::Bool(event.cancelable));
+    // Every UI event the embedder delivers crosses shadow boundaries.
+    map.insert("composed".to_string(), JsonValue::Bool(true));
 
     match &event.data {
         DomEventData::MouseMove(data)
@@ -995,6 +2496,16 @@ fn build_event_detail(event: &DomEvent) -> JsonValue {
     JsonValue::Object(map)
 }
 
+/// The pointer event fired ahead of a compatibility mouse event.
+fn pointer_event_name(data: &DomEventData) -> Option<&'static str> {
+    match data {
+        DomEventData::MouseDown(_) => Some("pointerdown"),
+        DomEventData::MouseUp(_) => Some("pointerup"),
+        DomEventData::MouseMove(_) => Some("pointermove"),
+        _ => None,
+    }
+}
+
 fn insert_mouse_event(map: &mut JsonMap<String, JsonValue>, event: &BlitzMouseButtonEvent) {
     map.insert("clientX".to_string(), json!(event.x));
     map.insert("clientY".to_string(), json!(event.y));
@@ -1005,6 +2516,15 @@ fn insert_mouse_event(map: &mut JsonMap<String, JsonValue>, event: &BlitzMouseBu
     insert_modifier_flags(map, &event.mods);
 }
 
+/// Whether `data` is a gesture that lets the page open a popup.
+fn activates_user(data: &DomEventData) -> bool {
+    match data {
+        DomEventData::MouseDown(_) | DomEventData::Click(_) => true,
+        DomEventData::KeyDown(event) => event.key != Key::Escape,
+        _ => false,
+    }
+}
+
 fn insert_key_event(map: &mut JsonMap<String, JsonValue>, event: &BlitzKeyEvent) {
     insert_modifier_flags(map, &event.modifiers);
     map.insert("key".to_string(), JsonValue::String(event.key.to_string()));
@@ -1078,6 +2598,51 @@ fn location_code(location: Location) -> i32 {
 const DOM_BOOTSTRAP: &str = r#"
 (() => {
     const global = globalThis;
+    const domAppendChild = global.__frontier_dom_append_child;
+    const domAssignedNodes = global.__frontier_dom_assigned_nodes;
+    const domAssignedSlot = global.__frontier_dom_assigned_slot;
+    const domAttachShadow = global.__frontier_dom_attach_shadow;
+    const domChildNodes = global.__frontier_dom_child_nodes;
+    const domCloneNode = global.__frontier_dom_clone_node;
+    const domCreateComment = global.__frontier_dom_create_comment;
+    const domCreateElement = global.__frontier_dom_create_element;
+    const domCreateElementNs = global.__frontier_dom_create_element_ns;
+    const domCreateText = global.__frontier_dom_create_text;
+    const domDocumentHandle = global.__frontier_dom_document_handle;
+    const domFirstChild = global.__frontier_dom_first_child;
+    const domGetAttribute = global.__frontier_dom_get_attribute;
+    const domGetAttributeNs = global.__frontier_dom_get_attribute_ns;
+    const domGetHandleById = global.__frontier_dom_get_handle_by_id;
+    const domGetHtml = global.__frontier_dom_get_html;
+    const domGetOuterHtml = global.__frontier_dom_get_outer_html;
+    const domGetText = global.__frontier_dom_get_text;
+    const domInsertAdjacentHtml = global.__frontier_dom_insert_adjacent_html;
+    const domInsertBefore = global.__frontier_dom_insert_before;
+    const domListen = global.__frontier_dom_listen;
+    const domNamespaceUri = global.__frontier_dom_namespace_uri;
+    const domNextSibling = global.__frontier_dom_next_sibling;
+    const domNodeName = global.__frontier_dom_node_name;
+    const domNodeType = global.__frontier_dom_node_type;
+    const domNormalize = global.__frontier_dom_normalize;
+    const domParent = global.__frontier_dom_parent;
+    const domParseFragment = global.__frontier_dom_parse_fragment;
+    const domPreviousSibling = global.__frontier_dom_previous_sibling;
+    const domRemoveAttribute = global.__frontier_dom_remove_attribute;
+    const domRemoveAttributeNs = global.__frontier_dom_remove_attribute_ns;
+    const domRemoveChild = global.__frontier_dom_remove_child;
+    const domReplaceChild = global.__frontier_dom_replace_child;
+    const domSetAttribute = global.__frontier_dom_set_attribute;
+    const domSetAttributeNs = global.__frontier_dom_set_attribute_ns;
+    const domSetInnerHtml = global.__frontier_dom_set_inner_html;
+    const domSetOuterHtml = global.__frontier_dom_set_outer_html;
+    const domSetText = global.__frontier_dom_set_text;
+    const domShadowElementById = global.__frontier_dom_shadow_element_by_id;
+    const domShadowHost = global.__frontier_dom_shadow_host;
+    const domShadowRoot = global.__frontier_dom_shadow_root;
+    const domSplitText = global.__frontier_dom_split_text;
+    const domTemplateContent = global.__frontier_dom_template_content;
+    const domUnlisten = global.__frontier_dom_unlisten;
+
     if (typeof global.self !== 'object' || global.self === null) {
         global.self = global;
     }
@@ -1184,7 +2749,7 @@ const DOM_BOOTSTRAP: &str = r#"
         if (previous) {
             record.counts.forEach((count, type) => {
                 if (count > 0) {
-                    global.__frontier_dom_unlisten(previous, type);
+                    domUnlisten(previous, type);
                 }
             });
         }
@@ -1192,7 +2757,7 @@ const DOM_BOOTSTRAP: &str = r#"
         if (normalized) {
             record.counts.forEach((count, type) => {
                 if (count > 0) {
-                    global.__frontier_dom_listen(normalized, type);
+                    domListen(normalized, type);
                 }
             });
         }
@@ -1212,7 +2777,7 @@ const DOM_BOOTSTRAP: &str = r#"
         const counts = record.counts;
         const current = counts.get(type) ?? 0;
         if (handle && current === 0) {
-            global.__frontier_dom_listen(String(handle), type);
+            domListen(String(handle), type);
         }
         counts.set(type, current + 1);
     }
@@ -1225,7 +2790,7 @@ const DOM_BOOTSTRAP: &str = r#"
             return;
         }
         if (handle && current === 1) {
-            global.__frontier_dom_unlisten(String(handle), type);
+            domUnlisten(String(handle), type);
             counts.delete(type);
         } else if (current > 1) {
             counts.set(type, current - 1);
@@ -1533,6 +3098,7 @@ const DOM_BOOTSTRAP: &str = r#"
         event._redrawRequested = false;
         event._passiveListener = false;
         event._path = path.slice();
+        event._hiddenSubtrees = path.map((node, index) => hiddenSubtreeMarker(path, index));
         event.target = target;
         event.srcElement = target;
         event.currentTarget = null;
@@ -1544,9 +3110,78 @@ const DOM_BOOTSTRAP: &str = r#"
         event.currentTarget = null;
         event.eventPhase = 0;
         event._path = [];
+        event._hiddenSubtrees = [];
         event._passiveListener = false;
     }
 
+    function isManagedNode(node) {
+        return node != null && typeof node === 'object' && HANDLE in node;
+    }
+
+    // 'root' for a closed shadow root on the path and 'slot' for a slot in a closed tree that
+    // the previous entry is assigned to; composedPath() hides what lies between them.
+    function hiddenSubtreeMarker(path, index) {
+        const node = path[index];
+        if (isShadowRoot(node)) {
+            return node.mode === 'closed' ? 'root' : null;
+        }
+        if (index === 0 || !isManagedNode(node) || node.localName !== 'slot') {
+            return null;
+        }
+        const previous = path[index - 1];
+        if (!isManagedNode(previous) || isShadowRoot(previous)) {
+            return null;
+        }
+        const slot = domAssignedSlot(previous[HANDLE]);
+        if (slot == null || String(slot) !== node[HANDLE]) {
+            return null;
+        }
+        return node.getRootNode().mode === 'closed' ? 'slot' : null;
+    }
+
+    function isShadowIncludingInclusiveAncestor(ancestor, node) {
+        let current = node;
+        while (isManagedNode(current)) {
+            if (current === ancestor) {
+                return true;
+            }
+            current = isShadowRoot(current) ? current.host : current.parentNode;
+        }
+        return false;
+    }
+
+    // What `target` looks like from `node`: the outermost shadow host that hides it.
+    function retarget(target, node) {
+        let current = target;
+        while (isManagedNode(current)) {
+            const root = current.getRootNode();
+            if (!isShadowRoot(root) || isShadowIncludingInclusiveAncestor(root, node)) {
+                return current;
+            }
+            current = root.host;
+        }
+        return current;
+    }
+
+    // The node an event moves to after `node`: the slot a light child is assigned to, the host
+    // of a shadow root unless the event is not composed and started in that tree, or the parent.
+    function eventParent(node, event, target) {
+        if (!isManagedNode(node) || node === global.document) {
+            return null;
+        }
+        if (isShadowRoot(node)) {
+            if (!event.composed && isManagedNode(target) && target.getRootNode() === node) {
+                return null;
+            }
+            return node.host;
+        }
+        const slot = domAssignedSlot(node[HANDLE]);
+        if (slot != null) {
+            return wrapHandle(slot, 1);
+        }
+        return node.parentNode;
+    }
+
     function activeListeners(record, type, capture) {
         const buckets = getListenerBuckets(record, type, false);
         if (!buckets) {
@@ -1617,7 +3252,8 @@ const DOM_BOOTSTRAP: &str = r#"
         }
     }
 
-    function buildPropagationPath(targetNode, providedHandles) {
+    function buildPropagationPath(targetNode, providedHandles, event) {
+        const composed = !event || !!event.composed;
         if (Array.isArray(providedHandles) && providedHandles.length > 0) {
             const path = providedHandles
                 .map((handle) => wrapHandle(handle))
@@ -1628,6 +3264,13 @@ const DOM_BOOTSTRAP: &str = r#"
             if (path[0] !== targetNode) {
                 path.unshift(targetNode);
             }
+            if (!composed && isManagedNode(targetNode)) {
+                const root = targetNode.getRootNode();
+                const stop = isShadowRoot(root) ? path.indexOf(root) : -1;
+                if (stop >= 0) {
+                    return path.slice(0, stop + 1);
+                }
+            }
             const last = path[path.length - 1];
             if (last !== global.document) {
                 path.push(global.document);
@@ -1639,15 +3282,13 @@ const DOM_BOOTSTRAP: &str = r#"
         let current = targetNode;
         while (current) {
             path.push(current);
-            if (!current.parentNode || current === global.document) {
-                break;
-            }
-            current = current.parentNode;
+            current = event ? eventParent(current, event, targetNode) : current.parentNode;
         }
         const last = path[path.length - 1];
         const shouldAppendDocument =
             global.document &&
             last !== global.document &&
+            !isShadowRoot(last) &&
             targetNode &&
             typeof targetNode === 'object' &&
             targetNode !== global &&
@@ -1684,57 +3325,62 @@ const DOM_BOOTSTRAP: &str = r#"
         if (targetRecordInitial) {
             // Removal during dispatch may prevent additional bubbling but should not abort future events.
         }
-        const path = providedPath ?? buildPropagationPath(target, null);
+        const path = providedPath ?? buildPropagationPath(target, null, event);
         prepareEventForDispatch(event, target, path);
 
-        const ancestors = path.slice(1);
-        const captureTargets = ancestors.slice().reverse();
+        // Listeners outside a shadow tree see its host as the target; a host whose tree holds
+        // the real target handles the event at target.
+        const targets = path.map((node) => retarget(target, node));
+        const setTarget = (index) => {
+            event.target = targets[index];
+            event.srcElement = targets[index];
+        };
 
-        for (const node of captureTargets) {
+        for (let index = path.length - 1; index >= 0; index -= 1) {
             if (event._propagationStopped) {
                 break;
             }
-            const record = getEventTargetRecord(node, false);
+            const node = path[index];
+            const record = index === 0 ? targetRecordInitial : getEventTargetRecord(node, false);
             if (!record) {
                 continue;
             }
             const listeners = activeListeners(record, normalizedType, true);
-            invokeListenerList(node, normalizedType, event, listeners, CAPTURING_PHASE);
-        }
-
-        if (!event._propagationStopped) {
-            const targetRecord = targetRecordInitial;
-            if (targetRecord) {
-                const captureListeners = activeListeners(targetRecord, normalizedType, true);
-                if (captureListeners.length > 0) {
-                    invokeListenerList(target, normalizedType, event, captureListeners, AT_TARGET);
-                }
-                if (!event._propagationStopped) {
-                    const bubbleListeners = activeListeners(targetRecord, normalizedType, false);
-                    if (bubbleListeners.length > 0) {
-                        invokeListenerList(target, normalizedType, event, bubbleListeners, AT_TARGET);
-                    }
-                }
+            if (listeners.length === 0) {
+                continue;
             }
+            setTarget(index);
+            const phase = targets[index] === node ? AT_TARGET : CAPTURING_PHASE;
+            invokeListenerList(node, normalizedType, event, listeners, phase);
         }
 
-        if (!event._propagationStopped && event.bubbles) {
-            for (const node of ancestors) {
-                if (event._propagationStopped) {
-                    break;
-                }
-                const record = getEventTargetRecord(node, false);
-                if (!record) {
-                    continue;
-                }
-                const bubbleListeners = activeListeners(record, normalizedType, false);
-                if (bubbleListeners.length === 0) {
-                    continue;
-                }
-                invokeListenerList(node, normalizedType, event, bubbleListeners, BUBBLING_PHASE);
+        for (let index = 0; index < path.length; index += 1) {
+            if (event._propagationStopped) {
+                break;
+            }
+            const node = path[index];
+            const atTarget = targets[index] === node;
+            if (!atTarget && !event.bubbles) {
+                continue;
+            }
+            const record = index === 0 ? targetRecordInitial : getEventTargetRecord(node, false);
+            if (!record) {
+                continue;
             }
+            const listeners = activeListeners(record, normalizedType, false);
+            if (listeners.length === 0) {
+                continue;
+            }
+            setTarget(index);
+            const phase = atTarget ? AT_TARGET : BUBBLING_PHASE;
+            invokeListenerList(node, normalizedType, event, listeners, phase);
         }
 
+        // Once dispatch is over, a target inside a shadow tree is not exposed any more.
+        const hidden = isManagedNode(target) && isShadowRoot(target.getRootNode());
+        event.target = hidden ? null : target;
+        event.srcElement = event.target;
+
         const result = {
             defaultPrevented: !!event.defaultPrevented,
             redrawRequested: !!event._redrawRequested,
@@ -1769,7 +3415,7 @@ const DOM_BOOTSTRAP: &str = r#"
         const stack = [String(handle)];
         while (stack.length > 0) {
             const current = stack.pop();
-            const children = mapHandles(global.__frontier_dom_child_nodes(current));
+            const children = mapHandles(domChildNodes(current));
             for (const child of children) {
                 result.push(child);
                 stack.push(child);
@@ -1793,6 +3439,15 @@ const DOM_BOOTSTRAP: &str = r#"
         return node && node.nodeType === 11 && typeof node.__flush === 'function';
     }
 
+    function isShadowRoot(node) {
+        return node != null && Object.getPrototypeOf(node) === ShadowRootProto;
+    }
+
+    function isTemplate(node) {
+        const xhtml = 'http://www.w3.org/1999/xhtml';
+        return node.localName === 'template' && node.namespaceURI === xhtml;
+    }
+
     function wrapHandle(handle, typeHint) {
         if (handle == null) {
             return null;
@@ -1807,7 +3462,7 @@ const DOM_BOOTSTRAP: &str = r#"
     }
 
     function createWrapper(handle, typeHint) {
-        const type = typeHint ?? global.__frontier_dom_node_type(handle);
+        const type = typeHint ?? domNodeType(handle);
         let proto;
         switch (type) {
             case 1:
@@ -1822,6 +3477,11 @@ const DOM_BOOTSTRAP: &str = r#"
             case 9:
                 proto = DocumentProto;
                 break;
+            case 11:
+                proto = domShadowHost(handle)
+                    ? ShadowRootProto
+                    : ContentFragmentProto;
+                break;
             default:
                 proto = NodeProto;
                 break;
@@ -1834,43 +3494,43 @@ const DOM_BOOTSTRAP: &str = r#"
 
     const NodeProto = {
         get nodeType() {
-            return global.__frontier_dom_node_type(this[HANDLE]);
+            return domNodeType(this[HANDLE]);
         },
         get nodeName() {
-            return global.__frontier_dom_node_name(this[HANDLE]);
+            return domNodeName(this[HANDLE]);
         },
         get ownerDocument() {
             return global.document;
         },
         get parentNode() {
-            const handle = global.__frontier_dom_parent(this[HANDLE]);
+            const handle = domParent(this[HANDLE]);
             return wrapHandle(handle);
         },
         get firstChild() {
-            const handle = global.__frontier_dom_first_child(this[HANDLE]);
+            const handle = domFirstChild(this[HANDLE]);
             return wrapHandle(handle);
         },
         get nextSibling() {
-            const handle = global.__frontier_dom_next_sibling(this[HANDLE]);
+            const handle = domNextSibling(this[HANDLE]);
             return wrapHandle(handle);
         },
         get previousSibling() {
-            const handle = global.__frontier_dom_previous_sibling(this[HANDLE]);
+            const handle = domPreviousSibling(this[HANDLE]);
             return wrapHandle(handle);
         },
         get childNodes() {
-            const handles = mapHandles(global.__frontier_dom_child_nodes(this[HANDLE]));
+            const handles = mapHandles(domChildNodes(this[HANDLE]));
             return handles.map((handle) => wrapHandle(handle));
         },
         hasChildNodes() {
-            return (global.__frontier_dom_child_nodes(this[HANDLE]) || []).length > 0;
+            return (domChildNodes(this[HANDLE]) || []).length > 0;
         },
         appendChild(node) {
             if (isFragment(node)) {
                 node.__flush(this, null);
                 return node;
             }
-            global.__frontier_dom_append_child(this[HANDLE], toHandle(node));
+            domAppendChild(this[HANDLE], toHandle(node));
             return node;
         },
         insertBefore(node, reference) {
@@ -1879,28 +3539,28 @@ const DOM_BOOTSTRAP: &str = r#"
                 return node;
             }
             const referenceHandle = reference == null ? null : toHandle(reference);
-            global.__frontier_dom_insert_before(this[HANDLE], toHandle(node), referenceHandle);
+            domInsertBefore(this[HANDLE], toHandle(node), referenceHandle);
             return node;
         },
         removeChild(node) {
-            global.__frontier_dom_remove_child(this[HANDLE], toHandle(node));
+            domRemoveChild(this[HANDLE], toHandle(node));
             return node;
         },
         replaceChild(newNode, oldNode) {
-            global.__frontier_dom_replace_child(this[HANDLE], toHandle(newNode), toHandle(oldNode));
+            domReplaceChild(this[HANDLE], toHandle(newNode), toHandle(oldNode));
             return oldNode;
         },
         cloneNode(deep = false) {
-            const handle = global.__frontier_dom_clone_node(this[HANDLE], !!deep);
+            const handle = domCloneNode(this[HANDLE], !!deep);
             return wrapHandle(handle);
         },
         get textContent() {
-            const value = global.__frontier_dom_get_text(this[HANDLE]);
+            const value = domGetText(this[HANDLE]);
             return value == null ? null : value;
         },
         set textContent(value) {
             const stale = collectDescendants(this[HANDLE]);
-            global.__frontier_dom_set_text(this[HANDLE], value == null ? '' : String(value));
+            domSetText(this[HANDLE], value == null ? '' : String(value));
             for (const handle of stale) {
                 NODE_CACHE.delete(handle);
             }
@@ -1919,16 +3579,28 @@ const DOM_BOOTSTRAP: &str = r#"
             return false;
         },
         get isConnected() {
-            let current = this;
-            while (current) {
-                if (current === global.document) {
-                    return true;
+            return this.getRootNode({ composed: true }) === global.document;
+        },
+        getRootNode(options) {
+            let node = this;
+            for (;;) {
+                const parent = node.parentNode;
+                if (parent) {
+                    node = parent;
+                } else if (options && options.composed && isShadowRoot(node)) {
+                    node = node.host;
+                } else {
+                    return node;
                 }
-                current = current.parentNode;
             }
-            return false;
         },
-        normalize() {},
+        get assignedSlot() {
+            const slot = wrapHandle(domAssignedSlot(this[HANDLE]), 1);
+            return slot && slot.getRootNode().mode === 'open' ? slot : null;
+        },
+        normalize() {
+            domNormalize(this[HANDLE]);
+        },
         addEventListener(type, listener, options) {
             EventTargetProto.addEventListener.call(this, type, listener, options);
         },
@@ -1945,12 +3617,12 @@ const DOM_BOOTSTRAP: &str = r#"
     const CharacterDataProto = Object.create(NodeProto);
     Object.defineProperty(CharacterDataProto, 'data', {
         get() {
-            const value = global.__frontier_dom_get_text(this[HANDLE]);
+            const value = domGetText(this[HANDLE]);
             return value == null ? '' : value;
         },
         set(value) {
             const stale = collectDescendants(this[HANDLE]);
-            global.__frontier_dom_set_text(this[HANDLE], value == null ? '' : String(value));
+            domSetText(this[HANDLE], value == null ? '' : String(value));
             for (const handle of stale) {
                 NODE_CACHE.delete(handle);
             }
@@ -1965,7 +3637,34 @@ const DOM_BOOTSTRAP: &str = r#"
         },
     });
 
+    Object.defineProperty(CharacterDataProto, 'length', {
+        get() {
+            return this.data.length;
+        },
+    });
+
     const TextProto = Object.create(CharacterDataProto);
+    TextProto.splitText = function (offset) {
+        const index = Number(offset) >>> 0;
+        if (index > this.length) {
+            throw domException('IndexSizeError', `offset ${index} is past the end of the text`);
+        }
+        const handle = domSplitText(this[HANDLE], index);
+        return wrapHandle(handle, 3);
+    };
+    Object.defineProperty(TextProto, 'wholeText', {
+        get() {
+            let first = this;
+            while (first.previousSibling && first.previousSibling.nodeType === 3) {
+                first = first.previousSibling;
+            }
+            let text = '';
+            for (let node = first; node && node.nodeType === 3; node = node.nextSibling) {
+                text += node.data;
+            }
+            return text;
+        },
+    });
     const CommentProto = Object.create(CharacterDataProto);
 
     const ElementProto = Object.create(NodeProto);
@@ -2005,21 +3704,86 @@ const DOM_BOOTSTRAP: &str = r#"
     });
     Object.defineProperty(ElementProto, 'namespaceURI', {
         get() {
-            return global.__frontier_dom_namespace_uri(this[HANDLE]) ?? null;
+            return domNamespaceUri(this[HANDLE]) ?? null;
         },
     });
     Object.defineProperty(ElementProto, 'innerHTML', {
         get() {
-            return global.__frontier_dom_get_html(this[HANDLE]) ?? '';
+            return domGetHtml(this[HANDLE]) ?? '';
         },
         set(value) {
-            const stale = collectDescendants(this[HANDLE]);
-            global.__frontier_dom_set_inner_html(this[HANDLE], value == null ? '' : String(value));
+            // A template's markup replaces its content, not its own (always empty) children.
+            const root = isTemplate(this) ? this.content[HANDLE] : this[HANDLE];
+            const stale = collectDescendants(root);
+            domSetInnerHtml(this[HANDLE], value == null ? '' : String(value));
             for (const handle of stale) {
                 NODE_CACHE.delete(handle);
             }
         },
     });
+    Object.defineProperty(ElementProto, 'outerHTML', {
+        get() {
+            return domGetOuterHtml(this[HANDLE]) ?? '';
+        },
+        set(value) {
+            // The element and its old children stay alive, detached, for scripts holding them.
+            domSetOuterHtml(this[HANDLE], value == null ? '' : String(value));
+        },
+    });
+    Object.defineProperty(ElementProto, 'content', {
+        get() {
+            if (!isTemplate(this)) {
+                return undefined;
+            }
+            return wrapHandle(domTemplateContent(this[HANDLE]), 11);
+        },
+    });
+    ElementProto.attachShadow = function (init) {
+        const mode = init == null ? undefined : init.mode;
+        if (mode !== 'open' && mode !== 'closed') {
+            throw new TypeError(`'${mode}' is not a valid shadow root mode`);
+        }
+        let root;
+        try {
+            root = domAttachShadow(this[HANDLE], mode);
+        } catch (err) {
+            const [, message] = String(err).split('NotSupportedError: ');
+            throw message === undefined ? err : domException('NotSupportedError', message);
+        }
+        return wrapHandle(root, 11);
+    };
+    Object.defineProperty(ElementProto, 'shadowRoot', {
+        get() {
+            return wrapHandle(domShadowRoot(this[HANDLE]), 11);
+        },
+    });
+    Object.defineProperty(ElementProto, 'slot', {
+        get() {
+            return this.getAttribute('slot') ?? '';
+        },
+        set(value) {
+            this.setAttribute('slot', value);
+        },
+    });
+    // `<slot>`: the light children shown in it. With `flatten`, an empty slot gives its
+    // fallback children instead.
+    ElementProto.assignedNodes = function (options) {
+        const handles = mapHandles(domAssignedNodes(this[HANDLE]));
+        if (handles.length === 0 && options && options.flatten) {
+            return this.childNodes;
+        }
+        return handles.map((handle) => wrapHandle(handle));
+    };
+    ElementProto.assignedElements = function (options) {
+        return this.assignedNodes(options).filter((node) => node && node.nodeType === 1);
+    };
+    ElementProto.insertAdjacentHTML = function (position, html) {
+        domInsertAdjacentHtml(
+            this[HANDLE],
+            String(position),
+            html == null ? '' : String(html),
+        );
+    };
     Object.defineProperty(ElementProto, 'children', {
         get() {
             return this.childNodes.filter((node) => node && node.nodeType === 1);
@@ -2055,20 +3819,40 @@ const DOM_BOOTSTRAP: &str = r#"
         },
     });
     ElementProto.getAttribute = function (name) {
-        const value = global.__frontier_dom_get_attribute(this[HANDLE], String(name));
+        const value = domGetAttribute(this[HANDLE], String(name));
         return value == null ? null : value;
     };
     ElementProto.setAttribute = function (name, value) {
-        global.__frontier_dom_set_attribute(this[HANDLE], String(name), value == null ? '' : String(value));
+        domSetAttribute(this[HANDLE], String(name), value == null ? '' : String(value));
+    };
+    ElementProto.getAttributeNS = function (namespace, localName) {
+        const value = domGetAttributeNs(
+            this[HANDLE],
+            namespace == null ? null : String(namespace),
+            String(localName),
+        );
+        return value == null ? null : value;
+    };
+    ElementProto.setAttributeNS = function (namespace, name, value) {
+        domSetAttributeNs(
+            this[HANDLE],
+            namespace == null ? null : String(namespace),
+            String(name),
+            value == null ? '' : String(value),
+        );
     };
-    ElementProto.setAttributeNS = function (_ns, name, value) {
-        this.setAttribute(name, value);
+    ElementProto.hasAttributeNS = function (namespace, localName) {
+        return this.getAttributeNS(namespace, localName) != null;
     };
     ElementProto.removeAttribute = function (name) {
-        global.__frontier_dom_remove_attribute(this[HANDLE], String(name));
+        domRemoveAttribute(this[HANDLE], String(name));
     };
-    ElementProto.removeAttributeNS = function (_ns, name) {
-        this.removeAttribute(name);
+    ElementProto.removeAttributeNS = function (namespace, localName) {
+        domRemoveAttributeNs(
+            this[HANDLE],
+            namespace == null ? null : String(namespace),
+            String(localName),
+        );
     };
     ElementProto.hasAttribute = function (name) {
         return this.getAttribute(name) != null;
@@ -2224,22 +4008,22 @@ const DOM_BOOTSTRAP: &str = r#"
 
     const DocumentProto = Object.create(NodeProto);
     DocumentProto.createElement = function (name) {
-        const handle = global.__frontier_dom_create_element(String(name));
+        const handle = domCreateElement(String(name));
         return wrapHandle(handle, 1);
     };
     DocumentProto.createElementNS = function (namespace, name) {
-        const handle = global.__frontier_dom_create_element_ns(
+        const handle = domCreateElementNs(
             namespace == null ? null : String(namespace),
             String(name),
         );
         return wrapHandle(handle, 1);
     };
     DocumentProto.createTextNode = function (value) {
-        const handle = global.__frontier_dom_create_text(value == null ? '' : String(value));
+        const handle = domCreateText(value == null ? '' : String(value));
         return wrapHandle(handle, 3);
     };
     DocumentProto.createComment = function (value) {
-        const handle = global.__frontier_dom_create_comment(value == null ? '' : String(value));
+        const handle = domCreateComment(value == null ? '' : String(value));
         return wrapHandle(handle, 8);
     };
     DocumentProto.createDocumentFragment = function () {
@@ -2266,12 +4050,12 @@ const DOM_BOOTSTRAP: &str = r#"
         return event;
     };
     DocumentProto.getElementById = function (id) {
-        const handle = global.__frontier_dom_get_handle_by_id(String(id));
+        const handle = domGetHandleById(String(id));
         return wrapHandle(handle, 1);
     };
     Object.defineProperty(DocumentProto, 'documentElement', {
         get() {
-            const handles = mapHandles(global.__frontier_dom_child_nodes(this[HANDLE]));
+            const handles = mapHandles(domChildNodes(this[HANDLE]));
             for (const handle of handles) {
                 const node = wrapHandle(handle);
                 if (node && node.nodeType === 1) {
@@ -2404,6 +4188,54 @@ const DOM_BOOTSTRAP: &str = r#"
         },
     });
 
+    // A template's content: a fragment that lives in the document's tree like any node, so
+    // its children keep their handles when the content is appended somewhere.
+    const ContentFragmentProto = Object.create(NodeProto);
+    ContentFragmentProto.__flush = function (target, reference) {
+        for (const child of this.childNodes) {
+            if (reference) {
+                target.insertBefore(child, reference);
+            } else {
+                target.appendChild(child);
+            }
+        }
+    };
+    Object.defineProperty(ContentFragmentProto, 'children', {
+        get() {
+            return this.childNodes.filter((node) => node && node.nodeType === 1);
+        },
+    });
+    Object.defineProperty(ContentFragmentProto, 'firstElementChild', {
+        get() {
+            return this.children[0] ?? null;
+        },
+    });
+
+    // A shadow root. Its children are the host's shadow tree, which is what renders; the
+    // host's own children only render where slots in it place them.
+    const ShadowRootProto = Object.create(NodeProto);
+    Object.defineProperty(ShadowRootProto, 'host', {
+        get() {
+            return wrapHandle(domShadowHost(this[HANDLE])[0], 1);
+        },
+    });
+    Object.defineProperty(ShadowRootProto, 'mode', {
+        get() {
+            return domShadowHost(this[HANDLE])[1];
+        },
+    });
+    for (const [name, source] of [
+        ['innerHTML', ElementProto],
+        ['children', ContentFragmentProto],
+        ['firstElementChild', ContentFragmentProto],
+    ]) {
+        Object.defineProperty(ShadowRootProto, name, Object.getOwnPropertyDescriptor(source, name));
+    }
+    ShadowRootProto.getElementById = function (id) {
+        const handle = domShadowElementById(this[HANDLE], String(id));
+        return wrapHandle(handle, 1);
+    };
+
     function createDocumentFragment() {
         const fragment = Object.create(FragmentProto);
         fragment.__children = [];
@@ -2415,18 +4247,350 @@ const DOM_BOOTSTRAP: &str = r#"
     const DocumentFragmentCtor = function DocumentFragment() {};
     DocumentFragmentCtor.prototype = FragmentProto;
     Object.defineProperty(FragmentProto, 'constructor', { value: DocumentFragmentCtor });
+    Object.defineProperty(DocumentFragmentCtor, Symbol.hasInstance, {
+        value: (node) => isFragment(node) || isShadowRoot(node),
+    });
     global.DocumentFragment = DocumentFragmentCtor;
 
+    function nodeIndex(node) {
+        const parent = node.parentNode;
+        return parent ? parent.childNodes.indexOf(node) : 0;
+    }
+
+    function nodeLength(node) {
+        if (node.nodeType === 3 || node.nodeType === 8) {
+            return node.data.length;
+        }
+        return node.childNodes.length;
+    }
+
+    function isCharacterData(node) {
+        return node.nodeType === 3 || node.nodeType === 8;
+    }
+
+    function ancestorsOf(node) {
+        const chain = [];
+        for (let current = node; current; current = current.parentNode) {
+            chain.unshift(current);
+        }
+        return chain;
+    }
+
+    // Where the boundary point (nodeA, offsetA) is relative to (nodeB, offsetB): -1 before,
+    // 0 equal, 1 after. Both must share a root.
+    function compareBoundaryPoints(nodeA, offsetA, nodeB, offsetB) {
+        if (nodeA === nodeB) {
+            return Math.sign(offsetA - offsetB);
+        }
+        const chainA = ancestorsOf(nodeA);
+        const chainB = ancestorsOf(nodeB);
+        let depth = 0;
+        while (depth < chainA.length && depth < chainB.length && chainA[depth] === chainB[depth]) {
+            depth += 1;
+        }
+        if (depth === chainA.length) {
+            return nodeIndex(chainB[depth]) < offsetA ? 1 : -1;
+        }
+        if (depth === chainB.length) {
+            return nodeIndex(chainA[depth]) < offsetB ? -1 : 1;
+        }
+        return nodeIndex(chainA[depth]) < nodeIndex(chainB[depth]) ? -1 : 1;
+    }
+
+    function checkBoundary(node, offset) {
+        if (!node || node[HANDLE] == null) {
+            throw new TypeError('Range boundary must be a Node');
+        }
+        const index = Number(offset) >>> 0;
+        if (index > nodeLength(node)) {
+            throw domException('IndexSizeError', `offset ${index} is past the end of the node`);
+        }
+        return index;
+    }
+
+    function parentOf(node) {
+        const parent = node && node.parentNode;
+        if (!parent) {
+            throw domException('InvalidNodeTypeError', 'the node has no parent');
+        }
+        return parent;
+    }
+
+    // Nodes wholly inside `range` whose parent is not, in tree order.
+    function containedNodes(range) {
+        const result = [];
+        const start = range.startContainer;
+        const end = range.endContainer;
+        const visit = (node) => {
+            for (const child of node.childNodes) {
+                const afterStart =
+                    compareBoundaryPoints(child, 0, start, range.startOffset) > 0;
+                const beforeEnd =
+                    compareBoundaryPoints(child, nodeLength(child), end, range.endOffset) < 0;
+                if (afterStart && beforeEnd) {
+                    result.push(child);
+                } else if (child.contains(start) || child.contains(end)) {
+                    visit(child);
+                }
+            }
+        };
+        visit(range.commonAncestorContainer);
+        return result;
+    }
+
+    // Ranges are static: unlike a browser's, they are not updated when the tree changes
+    // underneath them.
+    const RangeProto = {
+        get collapsed() {
+            return this.startContainer === this.endContainer && this.startOffset === this.endOffset;
+        },
+        get commonAncestorContainer() {
+            const startChain = ancestorsOf(this.startContainer);
+            const endChain = ancestorsOf(this.endContainer);
+            let common = null;
+            for (let depth = 0; depth < startChain.length; depth += 1) {
+                if (startChain[depth] !== endChain[depth]) {
+                    break;
+                }
+                common = startChain[depth];
+            }
+            return common;
+        },
+        setStart(node, offset) {
+            const index = checkBoundary(node, offset);
+            const end = this.endContainer;
+            if (
+                ancestorsOf(end)[0] !== ancestorsOf(node)[0] ||
+                compareBoundaryPoints(node, index, end, this.endOffset) > 0
+            ) {
+                this.endContainer = node;
+                this.endOffset = index;
+            }
+            this.startContainer = node;
+            this.startOffset = index;
+        },
+        setEnd(node, offset) {
+            const index = checkBoundary(node, offset);
+            const start = this.startContainer;
+            if (
+                ancestorsOf(start)[0] !== ancestorsOf(node)[0] ||
+                compareBoundaryPoints(node, index, start, this.startOffset) < 0
+            ) {
+                this.startContainer = node;
+                this.startOffset = index;
+            }
+            this.endContainer = node;
+            this.endOffset = index;
+        },
+        setStartBefore(node) {
+            this.setStart(parentOf(node), nodeIndex(node));
+        },
+        setStartAfter(node) {
+            this.setStart(parentOf(node), nodeIndex(node) + 1);
+        },
+        setEndBefore(node) {
+            this.setEnd(parentOf(node), nodeIndex(node));
+        },
+        setEndAfter(node) {
+            this.setEnd(parentOf(node), nodeIndex(node) + 1);
+        },
+        collapse(toStart = false) {
+            if (toStart) {
+                this.endContainer = this.startContainer;
+                this.endOffset = this.startOffset;
+            } else {
+                this.startContainer = this.endContainer;
+                this.startOffset = this.endOffset;
+            }
+        },
+        selectNode(node) {
+            const parent = parentOf(node);
+            const index = nodeIndex(node);
+            this.startContainer = parent;
+            this.startOffset = index;
+            this.endContainer = parent;
+            this.endOffset = index + 1;
+        },
+        selectNodeContents(node) {
+            checkBoundary(node, 0);
+            this.startContainer = node;
+            this.startOffset = 0;
+            this.endContainer = node;
+            this.endOffset = nodeLength(node);
+        },
+        compareBoundaryPoints(how, source) {
+            how = Number(how);
+            const useEnd = how === RANGE_START_TO_END || how === RANGE_END_TO_END;
+            const useSourceEnd = how === RANGE_END_TO_END || how === RANGE_END_TO_START;
+            if (how < RANGE_START_TO_START || how > RANGE_END_TO_START) {
+                throw domException('NotSupportedError', `unknown comparison ${how}`);
+            }
+            return compareBoundaryPoints(
+                useEnd ? this.endContainer : this.startContainer,
+                useEnd ? this.endOffset : this.startOffset,
+                useSourceEnd ? source.endContainer : source.startContainer,
+                useSourceEnd ? source.endOffset : source.startOffset,
+            );
+        },
+        comparePoint(node, offset) {
+            const index = checkBoundary(node, offset);
+            if (compareBoundaryPoints(node, index, this.startContainer, this.startOffset) < 0) {
+                return -1;
+            }
+            if (compareBoundaryPoints(node, index, this.endContainer, this.endOffset) > 0) {
+                return 1;
+            }
+            return 0;
+        },
+        isPointInRange(node, offset) {
+            if (!node || ancestorsOf(node)[0] !== ancestorsOf(this.startContainer)[0]) {
+                return false;
+            }
+            return this.comparePoint(node, offset) === 0;
+        },
+        cloneRange() {
+            const range = createRange();
+            range.startContainer = this.startContainer;
+            range.startOffset = this.startOffset;
+            range.endContainer = this.endContainer;
+            range.endOffset = this.endOffset;
+            return range;
+        },
+        detach() {},
+        createContextualFragment(html) {
+            // Text and comments parse in their parent element; documents like `<body>`.
+            let context = this.startContainer;
+            if (context.nodeType !== 1) {
+                const parent = context.parentNode;
+                context = parent && parent.nodeType === 1 ? parent : global.document;
+            }
+            const handles = domParseFragment(
+                context[HANDLE],
+                html == null ? '' : String(html),
+            );
+            const fragment = createDocumentFragment();
+            for (const handle of mapHandles(handles)) {
+                fragment.appendChild(wrapHandle(handle));
+            }
+            return fragment;
+        },
+        insertNode(node) {
+            const start = this.startContainer;
+            const orphanText = start.nodeType === 3 && !start.parentNode;
+            if (start.nodeType === 8 || start === node || orphanText) {
+                throw domException('HierarchyRequestError', 'cannot insert at the range start');
+            }
+            const wasCollapsed = this.collapsed;
+            let parent;
+            let reference;
+            if (start.nodeType === 3) {
+                parent = start.parentNode;
+                const endInStart = this.endContainer === start;
+                reference = start.splitText(this.startOffset);
+                if (endInStart) {
+                    this.endContainer = reference;
+                    this.endOffset -= this.startOffset;
+                }
+            } else {
+                parent = start;
+                reference = start.childNodes[this.startOffset] ?? null;
+            }
+            if (reference === node) {
+                reference = node.nextSibling;
+            }
+            parent.insertBefore(node, reference);
+            if (wasCollapsed) {
+                this.endContainer = parent;
+                this.endOffset = reference ? nodeIndex(reference) : parent.childNodes.length;
+            }
+        },
+        deleteContents() {
+            if (this.collapsed) {
+                return;
+            }
+            const start = this.startContainer;
+            const end = this.endContainer;
+            if (start === end && isCharacterData(start)) {
+                const data = start.data;
+                start.data = data.slice(0, this.startOffset) + data.slice(this.endOffset);
+                this.collapse(true);
+                return;
+            }
+            const contained = containedNodes(this);
+            if (isCharacterData(start)) {
+                start.data = start.data.slice(0, this.startOffset);
+            }
+            for (const node of contained) {
+                node.parentNode.removeChild(node);
+            }
+            if (isCharacterData(end)) {
+                end.data = end.data.slice(this.endOffset);
+            }
+            this.collapse(true);
+        },
+        toString() {
+            const start = this.startContainer;
+            const end = this.endContainer;
+            if (start === end && start.nodeType === 3) {
+                return start.data.slice(this.startOffset, this.endOffset);
+            }
+            let text = start.nodeType === 3 ? start.data.slice(this.startOffset) : '';
+            for (const node of containedNodes(this)) {
+                if (node.nodeType === 3 || node.nodeType === 1) {
+                    text += node.textContent ?? '';
+                }
+            }
+            if (end.nodeType === 3) {
+                text += end.data.slice(0, this.endOffset);
+            }
+            return text;
+        },
+    };
+
+    const RANGE_START_TO_START = 0;
+    const RANGE_START_TO_END = 1;
+    const RANGE_END_TO_END = 2;
+    const RANGE_END_TO_START = 3;
+
+    function createRange() {
+        const range = Object.create(RangeProto);
+        range.startContainer = global.document;
+        range.startOffset = 0;
+        range.endContainer = global.document;
+        range.endOffset = 0;
+        return range;
+    }
+
+    const RangeCtor = function Range() {
+        return createRange();
+    };
+    RangeCtor.prototype = RangeProto;
+    Object.defineProperty(RangeProto, 'constructor', { value: RangeCtor });
+    for (const [name, value] of [
+        ['START_TO_START', RANGE_START_TO_START],
+        ['START_TO_END', RANGE_START_TO_END],
+        ['END_TO_END', RANGE_END_TO_END],
+        ['END_TO_START', RANGE_END_TO_START],
+    ]) {
+        RangeCtor[name] = value;
+        RangeProto[name] = value;
+    }
+    global.Range = RangeCtor;
+    DocumentProto.createRange = function () {
+        return createRange();
+    };
+
     defineConstructor('Node', NodeProto);
     defineConstructor('Element', ElementProto);
     defineConstructor('Text', TextProto);
     defineConstructor('Comment', CommentProto);
     defineConstructor('Document', DocumentProto);
+    defineConstructor('ShadowRoot', ShadowRootProto);
     global.HTMLElement = global.Element;
 
     function ensureDocument() {
         try {
-            const docHandle = global.__frontier_dom_document_handle();
+            const docHandle = domDocumentHandle();
             let document = global.document;
             if (typeof document !== 'object' || document === null) {
                 document = {};
@@ -2447,7 +4611,7 @@ const DOM_BOOTSTRAP: &str = r#"
         if (!documentHandle) {
             return;
         }
-        const children = mapHandles(global.__frontier_dom_child_nodes(documentHandle));
+        const children = mapHandles(domChildNodes(documentHandle));
         for (const handle of children) {
             wrapHandle(handle);
         }
@@ -2544,7 +4708,58 @@ const DOM_BOOTSTRAP: &str = r#"
                 this._immediatePropagationStopped = true;
             },
             composedPath() {
-                return Array.isArray(this._path) ? this._path.slice() : [];
+                const path = Array.isArray(this._path) ? this._path : [];
+                if (path.length === 0) {
+                    return [];
+                }
+                // Hide the parts of the path inside closed shadow trees that the current target
+                // cannot see, counting how deep into closed trees each entry sits.
+                const markers = Array.isArray(this._hiddenSubtrees) ? this._hiddenSubtrees : [];
+                const current = this.currentTarget;
+                let currentIndex = 0;
+                let currentLevel = 0;
+                for (let index = path.length - 1; index >= 0; index -= 1) {
+                    if (markers[index] === 'root') {
+                        currentLevel += 1;
+                    }
+                    if (path[index] === current) {
+                        currentIndex = index;
+                        break;
+                    }
+                    if (markers[index] === 'slot') {
+                        currentLevel -= 1;
+                    }
+                }
+                const result = [current ?? path[currentIndex]];
+                let level = currentLevel;
+                let maxLevel = currentLevel;
+                for (let index = currentIndex - 1; index >= 0; index -= 1) {
+                    if (markers[index] === 'root') {
+                        level += 1;
+                    }
+                    if (level <= maxLevel) {
+                        result.unshift(path[index]);
+                    }
+                    if (markers[index] === 'slot') {
+                        level -= 1;
+                        maxLevel = Math.min(maxLevel, level);
+                    }
+                }
+                level = currentLevel;
+                maxLevel = currentLevel;
+                for (let index = currentIndex + 1; index < path.length; index += 1) {
+                    if (markers[index] === 'slot') {
+                        level += 1;
+                    }
+                    if (level <= maxLevel) {
+                        result.push(path[index]);
+                    }
+                    if (markers[index] === 'root') {
+                        level -= 1;
+                        maxLevel = Math.min(maxLevel, level);
+                    }
+                }
+                return result;
             },
             requestRedraw() {
                 this._redrawRequested = true;
@@ -2769,7 +4984,9 @@ const DOM_BOOTSTRAP: &str = r#"
             }
             FrontierMessagePort.prototype = {
                 constructor: FrontierMessagePort,
-                postMessage(message) {
+                postMessage(message, transfer) {
+                    const options = Array.isArray(transfer) ? { transfer } : transfer;
+                    const data = global.structuredClone(message, options);
                     const target = this._entangled;
                     if (!target) {
                         return;
@@ -2777,7 +4994,7 @@ const DOM_BOOTSTRAP: &str = r#"
                     Promise.resolve().then(() => {
                         if (typeof target.onmessage === 'function') {
                             try {
-                                const event = createEvent('message', target, { data: message, source: this });
+                                const event = createEvent('message', target, { data, source: this });
                                 target.onmessage.call(target, event);
                             } catch (error) {
                                 throw error;
@@ -2850,19 +5067,64 @@ const DOM_BOOTSTRAP: &str = r#"
         }
     }
 
+    frontier.__handleOf = (node) =>
+        node != null && typeof node[HANDLE] === 'string' ? node[HANDLE] : null;
+
+    const POINTER_EVENT_TYPES = {
+        mousedown: 'pointerdown',
+        mouseup: 'pointerup',
+        mousemove: 'pointermove',
+    };
+    // Set when a page cancels pointerdown, which suppresses the compatibility mouse events
+    // until the pointer is released.
+    let compatMouseSuppressed = false;
+
+    function dispatchTrustedEvent(target, type, detail, pathHandles) {
+        const event = createEvent(type, target, detail, true);
+        const path = buildPropagationPath(target, pathHandles, event);
+        return dispatchEventInternal(target, event, path);
+    }
+
     frontier.__dispatchDomEvent = function (handle, type, detail, pathHandles) {
         const target = wrapHandle(handle);
+        const result = {
+            defaultPrevented: false,
+            redrawRequested: false,
+            propagationStopped: false,
+        };
         if (!target) {
-            return {
-                defaultPrevented: false,
-                redrawRequested: false,
-                propagationStopped: false,
-            };
+            return result;
+        }
+        const init = detail || {};
+        const pointerType = POINTER_EVENT_TYPES[type];
+        if (pointerType) {
+            const pointer = dispatchTrustedEvent(
+                target,
+                pointerType,
+                Object.assign({}, init, {
+                    pointerId: 1,
+                    pointerType: 'mouse',
+                    isPrimary: true,
+                    width: 1,
+                    height: 1,
+                    pressure: init.buttons ? 0.5 : 0,
+                }),
+                pathHandles,
+            );
+            result.redrawRequested = pointer.redrawRequested;
+            if (pointerType === 'pointerdown') {
+                compatMouseSuppressed = pointer.defaultPrevented;
+            }
+            if (compatMouseSuppressed) {
+                if (pointerType === 'pointerup') {
+                    compatMouseSuppressed = false;
+                }
+                return result;
+            }
         }
-        const event = createEvent(type, target, detail || {}, true);
-        const path = buildPropagationPath(target, pathHandles);
-        const result = dispatchEventInternal(target, event, path);
-        return result;
+        const mouse = dispatchTrustedEvent(target, type, init, pathHandles);
+        mouse.redrawRequested = mouse.redrawRequested || result.redrawRequested;
+        return mouse;
     };
 
     const TIMER_STORE = new Map();
@@ -2927,6 +5189,21 @@ const DOM_BOOTSTRAP: &str = r#"
         cancelNativeTimer(timerId);
     }
 
+    // Called when the document is replaced: forget every listener and timer callback so
+    // nothing the page closed over stays reachable from the runtime.
+    frontier.__teardown = function () {
+        const targets = [global, ...NODE_CACHE.values()];
+        for (const target of targets) {
+            const record = EVENT_TARGET_DATA.get(target);
+            if (record) {
+                record.listeners.clear();
+                record.counts.clear();
+            }
+        }
+        NODE_CACHE.clear();
+        TIMER_STORE.clear();
+    };
+
     global.setTimeout = function (callback, delay, ...args) {
         return scheduleTimer('timeout', delay ?? 0, false, callback, args);
     };
@@ -2969,7 +5246,7 @@ const DOM_BOOTSTRAP: &str = r#"
         const handle =
             patch.handle ??
             (typeof patch.id === 'string'
-                ? global.__frontier_dom_get_handle_by_id(patch.id)
+                ? domGetHandleById(patch.id)
                 : undefined);
         if (handle == null) {
             throw new TypeError('Patch requires a "handle" field');
@@ -2978,7 +5255,7 @@ const DOM_BOOTSTRAP: &str = r#"
         switch (patch.type) {
             case 'text_content': {
                 const stale = collectDescendants(normalizedHandle);
-                global.__frontier_dom_set_text(
+                domSetText(
                     normalizedHandle,
                     patch.value == null ? '' : String(patch.value),
                 );
@@ -2989,7 +5266,7 @@ const DOM_BOOTSTRAP: &str = r#"
             }
             case 'inner_html': {
                 const stale = collectDescendants(normalizedHandle);
-                global.__frontier_dom_set_inner_html(
+                domSetInnerHtml(
                     normalizedHandle,
                     patch.value == null ? '' : String(patch.value),
                 );
@@ -2999,15 +5276,30 @@ const DOM_BOOTSTRAP: &str = r#"
                 break;
             }
             case 'attribute': {
-                global.__frontier_dom_set_attribute(
-                    normalizedHandle,
-                    String(patch.name),
-                    patch.value == null ? '' : String(patch.value),
-                );
+                const value = patch.value == null ? '' : String(patch.value);
+                if (patch.namespace == null) {
+                    const name = String(patch.name);
+                    domSetAttribute(normalizedHandle, name, value);
+                } else {
+                    domSetAttributeNs(
+                        normalizedHandle,
+                        String(patch.namespace),
+                        String(patch.name),
+                        value,
+                    );
+                }
                 break;
             }
             case 'remove_attribute': {
-                global.__frontier_dom_remove_attribute(normalizedHandle, String(patch.name));
+                if (patch.namespace == null) {
+                    domRemoveAttribute(normalizedHandle, String(patch.name));
+                } else {
+                    domRemoveAttributeNs(
+                        normalizedHandle,
+                        String(patch.namespace),
+                        String(patch.name),
+                    );
+                }
                 break;
             }
             default:
@@ -0,0 +1,49 @@
+//! `'unsafe-eval'` enforcement.
+//!
+//! QuickJS has no hook for refusing string compilation, and the engine needs `eval` for its own
+//! scripts, so a page whose Content Security Policy leaves out `'unsafe-eval'` gets `eval` and
+//! every `Function` constructor swapped for ones that throw, as other browsers do. Bootstrap
+//! code that must still run inserted scripts takes `eval` before this is installed.
+
+/// Replace `eval`, `Function` and the generator and async function constructors. Each
+/// replacement keeps the original's `prototype`, so `instanceof Function` still holds.
+pub(crate) const REFUSE_EVAL_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    if (global.frontier.__evalRefused) {
+        return;
+    }
+    const message =
+        "Refused to evaluate a string as JavaScript because 'unsafe-eval' is not an allowed " +
+        "source of script in the Content Security Policy";
+    // Plain functions rather than methods, so `new Function(...)` throws the EvalError too.
+    const refuse = (name) => {
+        const refused = function () {
+            console.error(message);
+            throw new EvalError(message);
+        };
+        Object.defineProperty(refused, 'name', { value: name });
+        return refused;
+    };
+    const constructors = [
+        Function,
+        Object.getPrototypeOf(function* () {}).constructor,
+        Object.getPrototypeOf(async function () {}).constructor,
+        Object.getPrototypeOf(async function* () {}).constructor,
+    ];
+    for (const original of constructors) {
+        const refused = refuse(original.name);
+        Object.defineProperty(refused, 'prototype', { value: original.prototype });
+        Object.defineProperty(original.prototype, 'constructor', {
+            value: refused,
+            writable: true,
+            configurable: true,
+        });
+        if (original === Function) {
+            global.Function = refused;
+        }
+    }
+    global.eval = refuse('eval');
+    Object.defineProperty(global.frontier, '__evalRefused', { value: true });
+})();
+"#;
@@ -24,7 +24,7 @@ impl EventHandler for JsEventHandler {
         _mutr: &mut DocumentMutator<'_>,
         event_state: &mut EventState,
     ) {
-        if !self.environment.is_listening(event.data.name()) {
+        if !self.environment.listens_for(&event.data) {
             return;
         }
 
@@ -0,0 +1,1556 @@
+//! `fetch()` for page scripts, with the same-origin policy and CORS applied.
+//!
+//! Requests run on the tokio runtime like timers do: the task reports back over a channel,
+//! wakes the document, and [`FetchManager::run_completed`] settles the JS promise during the
+//! next pump. Navigations and the document's own subresource loads never come through here.
+//! Scripts and stylesheets inserted by page script do, through hooks of their own, and stay
+//! exempt from CORS exactly as in other browsers; `fetch()` always gets it.
+
+use std::cell::{Cell, Ref, RefCell};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::task::AtomicWaker;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::warn;
+use url::{Origin, Url};
+
+use super::blob::BlobStore;
+use super::isolation::{NativeMemory, Reservation};
+use super::runtime::QuickJsEngine;
+use super::teardown::TaskTracker;
+use crate::adblock::Blocklist;
+use crate::cookies::CookieJar;
+use crate::csp::ContentSecurityPolicy;
+use crate::http_client::ClientError;
+use crate::network_log::{Initiator, NetworkLog, RequestSource};
+use crate::permissions::ContentRules;
+use crate::privacy;
+use crate::user_agent;
+
+/// Most redirects a request follows, as in other browsers.
+const MAX_REDIRECTS: usize = 20;
+
+const SAFELISTED_RESPONSE_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-language",
+    "content-length",
+    "content-type",
+    "expires",
+    "last-modified",
+    "pragma",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RequestMode {
+    Cors,
+    NoCors,
+    SameOrigin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CredentialsMode {
+    Omit,
+    SameOrigin,
+    Include,
+}
+
+/// What the response is for. Script and stylesheet loads for dynamically inserted `<script>`
+/// and `<link rel="stylesheet">` elements are exempt from CORS like any other subresource, and
+/// are checked against `script-src` and `style-src`. Each destination has its own native hook
+/// (see [`SEAL_NATIVE_HOOKS`]), so page input never picks one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Destination {
+    Empty,
+    Script,
+    Style,
+}
+
+impl Destination {
+    fn is_subresource(self) -> bool {
+        matches!(self, Destination::Script | Destination::Style)
+    }
+}
+
+/// Request body as the shim sends it: text inline, anything else as a blob id.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum FetchBody {
+    Text(String),
+    Blob { blob: u32 },
+}
+
+/// Request as serialized by the JS `fetch()` shim.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FetchInit {
+    url: String,
+    method: String,
+    headers: Vec<(String, String)>,
+    body: Option<FetchBody>,
+    mode: RequestMode,
+    credentials: CredentialsMode,
+}
+
+impl FetchInit {
+    /// The `GET` for an inserted script or stylesheet, of which the page only supplies `url`.
+    pub(crate) fn subresource(url: String) -> Self {
+        Self {
+            url,
+            method: "GET".to_string(),
+            headers: Vec::new(),
+            body: None,
+            mode: RequestMode::NoCors,
+            credentials: CredentialsMode::SameOrigin,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FetchResponse {
+    url: String,
+    status: u16,
+    status_text: String,
+    headers: Vec<(String, String)>,
+    /// The raw body, handed to the page as the blob [`Self::blob`] names.
+    #[serde(skip)]
+    body: Vec<u8>,
+    /// Id of the [`BlobStore`] blob holding the body, filled in by
+    /// [`FetchManager::run_completed`].
+    blob: u32,
+    #[serde(rename = "type")]
+    response_type: &'static str,
+    redirected: bool,
+    /// What the body is charged to the page's memory budget, until the page has it.
+    #[serde(skip)]
+    charge: Option<Reservation<'static>>,
+}
+
+enum FetchOutcome {
+    Response(FetchResponse),
+    Failed(String),
+}
+
+struct PreparedFetch {
+    url: Url,
+    method: Method,
+    headers: HeaderMap,
+    body: Option<Vec<u8>>,
+    mode: RequestMode,
+    credentials: CredentialsMode,
+    origin: Origin,
+    cross_origin: bool,
+    destination: Destination,
+    /// The document the request is made for, which its `Referer` is derived from.
+    referrer: Option<Url>,
+    /// `User-Agent` the window emulates, if it emulates one.
+    user_agent: Option<String>,
+    /// The document's policy, which every redirect is checked against.
+    csp: ContentSecurityPolicy,
+}
+
+pub(crate) struct FetchManager {
+    handle: Handle,
+    /// The shared client, or why it could not be built; requests then fail rather than go out
+    /// without the configured proxy.
+    client: Result<reqwest::Client, ClientError>,
+    document_url: Option<Url>,
+    csp: RefCell<ContentSecurityPolicy>,
+    next_id: Cell<u32>,
+    inflight: RefCell<HashMap<u32, JoinHandle<()>>>,
+    completed_rx: RefCell<UnboundedReceiver<(u32, FetchOutcome)>>,
+    completed_tx: UnboundedSender<(u32, FetchOutcome)>,
+    waker: Arc<AtomicWaker>,
+    blobs: BlobStore,
+    network_log: RefCell<Option<NetworkLog>>,
+    /// The page's site settings, which stop requests like its subresource loads.
+    rules: RefCell<ContentRules>,
+    /// Filter lists the page's requests are checked against, as its subresource loads are.
+    blocklist: RefCell<Option<Arc<Blocklist>>>,
+    /// The page's memory budget, which response bodies are charged to as they arrive.
+    memory: RefCell<Option<NativeMemory<'static>>>,
+    /// `User-Agent` the window emulates, sent and shown in place of Frontier's own.
+    user_agent: RefCell<Option<String>>,
+    tasks: TaskTracker,
+    closed: Cell<bool>,
+}
+
+impl FetchManager {
+    pub(crate) fn new(
+        handle: Handle,
+        document_url: Option<Url>,
+        csp: ContentSecurityPolicy,
+        waker: Arc<AtomicWaker>,
+        blobs: BlobStore,
+        tasks: TaskTracker,
+    ) -> Self {
+        let (tx, rx) = unbounded_channel();
+        Self {
+            handle,
+            client: crate::http_client::shared_manual_redirects(),
+            document_url,
+            csp: RefCell::new(csp),
+            next_id: Cell::new(1),
+            inflight: RefCell::new(HashMap::new()),
+            completed_rx: RefCell::new(rx),
+            completed_tx: tx,
+            waker,
+            blobs,
+            network_log: RefCell::new(None),
+            rules: RefCell::new(ContentRules::default()),
+            blocklist: RefCell::new(None),
+            memory: RefCell::new(None),
+            user_agent: RefCell::new(None),
+            tasks,
+            closed: Cell::new(false),
+        }
+    }
+
+    /// Record requests in `log` from now on.
+    pub(crate) fn set_network_log(&self, log: NetworkLog) {
+        *self.network_log.borrow_mut() = Some(log);
+    }
+
+    /// Refuse requests that `rules` block from now on.
+    pub(crate) fn set_content_rules(&self, rules: ContentRules) {
+        *self.rules.borrow_mut() = rules;
+    }
+
+    /// Refuse requests that match a network rule of `blocklist` from now on.
+    pub(crate) fn set_blocklist(&self, blocklist: Arc<Blocklist>) {
+        *self.blocklist.borrow_mut() = Some(blocklist);
+    }
+
+    /// Charge response bodies to `memory` from now on, and fail those that do not fit.
+    pub(crate) fn set_memory(&self, memory: NativeMemory<'static>) {
+        *self.memory.borrow_mut() = Some(memory);
+    }
+
+    /// Send `user_agent` in place of Frontier's own from now on, or stop with `None`.
+    pub(crate) fn set_user_agent(&self, user_agent: Option<String>) {
+        *self.user_agent.borrow_mut() = user_agent;
+    }
+
+    /// The `User-Agent` requests go out with, which `navigator.userAgent` reports too.
+    pub(crate) fn user_agent(&self) -> String {
+        user_agent::resolve(self.user_agent.borrow().as_deref())
+    }
+
+    /// The policy of the document the requests are made for.
+    pub(crate) fn csp(&self) -> Ref<'_, ContentSecurityPolicy> {
+        self.csp.borrow()
+    }
+
+    /// Hold requests to `policy` as well from now on.
+    pub(crate) fn add_csp(&self, policy: ContentSecurityPolicy) {
+        self.csp.borrow_mut().extend(policy);
+    }
+
+    pub(crate) fn document_url(&self) -> Option<&Url> {
+        self.document_url.as_ref()
+    }
+
+    /// The origin scripts run in. `file:` and `about:` documents get an opaque origin, which
+    /// serializes as `null` in the `Origin` header.
+    pub(crate) fn document_origin(&self) -> Origin {
+        self.document_url
+            .as_ref()
+            .map(origin_of)
+            .unwrap_or_else(|| Url::parse("about:blank").expect("static url").origin())
+    }
+
+    /// Begin a request for `destination` and return the id the JS shim uses to settle its
+    /// promise.
+    pub(crate) fn start(&self, init: FetchInit, destination: Destination) -> u32 {
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1).max(1));
+        if self.closed.get() {
+            // The page is gone; the promise stays pending like any request it never sees.
+            return id;
+        }
+
+        let requested = self.resolve_url(&init.url).ok();
+        let method = init.method.to_ascii_uppercase();
+        let logged = match (self.network_log.borrow().as_ref(), &requested) {
+            (Some(log), Some(url)) => {
+                Some(log.begin(&method, url, Initiator::Fetch, RequestSource::for_url(url)))
+            }
+            _ => None,
+        };
+
+        let started = match &self.client {
+            Ok(client) => self
+                .prepare(init, destination)
+                .map(|prepared| (client.clone(), prepared)),
+            Err(err) => Err(err.to_string()),
+        };
+        let (client, prepared) = match started {
+            Ok(started) => started,
+            Err(message) => {
+                if let Some(logged) = logged {
+                    logged.fail(message.clone());
+                }
+                warn!(target = "quickjs", error = %message, "fetch blocked");
+                let _ = self.completed_tx.send((id, FetchOutcome::Failed(message)));
+                self.waker.wake();
+                return id;
+            }
+        };
+
+        let memory = self.memory.borrow().clone();
+        let tx = self.completed_tx.clone();
+        let waker = Arc::clone(&self.waker);
+        let guard = self.tasks.guard();
+        let task = self.handle.spawn(async move {
+            let _guard = guard;
+            let outcome = match perform(&client, prepared, memory).await {
+                Ok(response) => {
+                    if let Some(logged) = logged {
+                        logged.finish(
+                            Some(response.status),
+                            response.headers.clone(),
+                            &response.body,
+                        );
+                    }
+                    FetchOutcome::Response(response)
+                }
+                Err(message) => {
+                    if let Some(logged) = logged {
+                        logged.fail(message.clone());
+                    }
+                    warn!(target = "quickjs", error = %message, "fetch failed");
+                    FetchOutcome::Failed(message)
+                }
+            };
+            if tx.send((id, outcome)).is_ok() {
+                waker.wake();
+            }
+        });
+        self.inflight.borrow_mut().insert(id, task);
+        id
+    }
+
+    pub(crate) fn abort(&self, id: u32) {
+        if let Some(task) = self.inflight.borrow_mut().remove(&id) {
+            task.abort();
+        }
+    }
+
+    /// Whether any request has not settled yet.
+    pub(crate) fn has_inflight(&self) -> bool {
+        !self.inflight.borrow().is_empty()
+    }
+
+    /// Abort every request in flight and drop results that have not been delivered. Requests
+    /// started afterwards never settle.
+    pub(crate) fn shutdown(&self) {
+        self.closed.set(true);
+        for (_, task) in self.inflight.borrow_mut().drain() {
+            task.abort();
+        }
+        let mut rx = self.completed_rx.borrow_mut();
+        while rx.try_recv().is_ok() {}
+    }
+
+    /// Settle promises for every request that finished since the last pump.
+    pub(crate) fn run_completed(&self, engine: &QuickJsEngine) -> Result<bool> {
+        let mut completed = Vec::new();
+        {
+            let mut rx = self.completed_rx.borrow_mut();
+            while let Ok(entry) = rx.try_recv() {
+                completed.push(entry);
+            }
+        }
+
+        let mut ran = false;
+        for (id, mut outcome) in completed {
+            self.inflight.borrow_mut().remove(&id);
+            if let FetchOutcome::Response(response) = &mut outcome {
+                response.blob = self.blobs.create();
+                self.blobs.push_bytes(response.blob, &response.body);
+            }
+            engine.with_context(|ctx| {
+                let frontier: rquickjs::Object = ctx.globals().get("frontier")?;
+                match &outcome {
+                    FetchOutcome::Response(response) => {
+                        let settle: rquickjs::Function = frontier.get("__resolveFetch")?;
+                        let payload = serde_json::to_string(response).unwrap_or_default();
+                        settle.call::<_, ()>((id, payload))
+                    }
+                    FetchOutcome::Failed(message) => {
+                        let settle: rquickjs::Function = frontier.get("__rejectFetch")?;
+                        settle.call::<_, ()>((id, message.as_str()))
+                    }
+                }
+            })?;
+            ran = true;
+        }
+        Ok(ran)
+    }
+
+    fn resolve_url(&self, raw: &str) -> Result<Url, url::ParseError> {
+        match &self.document_url {
+            Some(base) => base.join(raw),
+            None => Url::parse(raw),
+        }
+    }
+
+    fn prepare(&self, init: FetchInit, destination: Destination) -> Result<PreparedFetch, String> {
+        let url = self
+            .resolve_url(&init.url)
+            .map_err(|err| format!("Failed to parse URL from {}: {err}", init.url))?;
+
+        if let Some(setting) = self.rules.borrow().blocks(&url, false) {
+            return Err(format!(
+                "Refused to load '{url}' because it is blocked by site settings ({})",
+                setting.name()
+            ));
+        }
+        if let Some(blocklist) = self.blocklist.borrow().as_ref() {
+            if let Some(rule) = blocklist.blocking_rule(&url, self.document_url.as_ref()) {
+                return Err(format!(
+                    "Refused to load '{url}' because it is blocked by filter {rule}"
+                ));
+            }
+        }
+
+        if destination.is_subresource() {
+            let script = destination == Destination::Script;
+            let what = if script { "script" } else { "stylesheet" };
+            let document_is_file = self
+                .document_url
+                .as_ref()
+                .is_some_and(|document| document.scheme() == "file");
+            let scheme_allowed = match url.scheme() {
+                "http" | "https" => true,
+                "file" => document_is_file,
+                _ => false,
+            };
+            if !scheme_allowed {
+                return Err(format!("Cannot load {what} {url}: unsupported scheme"));
+            }
+            let document_url = self.document_url.as_ref();
+            let csp = self.csp();
+            let (allowed, directive) = if script {
+                (
+                    csp.allows_script_url(&url, document_url, None),
+                    "script-src",
+                )
+            } else {
+                (csp.allows_style_url(&url, document_url), "style-src")
+            };
+            if !allowed {
+                return Err(format!(
+                    "Refused to load the {what} '{url}' because it violates the Content Security Policy directive {directive}"
+                ));
+            }
+        } else {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("Fetch API cannot load {url}: unsupported scheme"));
+            }
+
+            if !self
+                .csp()
+                .allows_connect_url(&url, self.document_url.as_ref())
+            {
+                return Err(format!(
+                    "Refused to connect to '{url}' because it violates the Content Security Policy directive connect-src"
+                ));
+            }
+        }
+
+        let method = Method::from_bytes(init.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| format!("'{}' is not a valid HTTP method", init.method))?;
+
+        let headers = request_headers(&init.headers, init.mode)?;
+
+        let origin = self.document_origin();
+        let cross_origin = origin_of(&url) != origin;
+
+        match init.mode {
+            RequestMode::SameOrigin if cross_origin => {
+                return Err(format!(
+                    "Fetch API cannot load {url}. Request mode is \"same-origin\" but the URL's origin is not same as the request origin {}",
+                    origin.ascii_serialization()
+                ));
+            }
+            RequestMode::NoCors if !is_cors_safelisted_method(&method) => {
+                return Err(format!("'{method}' is unsupported in no-cors mode"));
+            }
+            _ => {}
+        }
+
+        Ok(PreparedFetch {
+            url,
+            method,
+            headers,
+            body: match init.body {
+                Some(FetchBody::Text(text)) => Some(text.into_bytes()),
+                Some(FetchBody::Blob { blob }) => Some(
+                    self.blobs
+                        .bytes(blob)
+                        .ok_or_else(|| "The request body blob was released".to_string())?,
+                ),
+                None => None,
+            },
+            mode: init.mode,
+            credentials: init.credentials,
+            origin,
+            cross_origin,
+            destination,
+            referrer: self.document_url.clone(),
+            user_agent: self.user_agent.borrow().clone(),
+            csp: self.csp().clone(),
+        })
+    }
+}
+
+impl Drop for FetchManager {
+    fn drop(&mut self) {
+        for (_, task) in self.inflight.borrow_mut().drain() {
+            task.abort();
+        }
+    }
+}
+
+async fn perform(
+    client: &reqwest::Client,
+    mut request: PreparedFetch,
+    memory: Option<NativeMemory<'static>>,
+) -> Result<FetchResponse, String> {
+    if request.url.scheme() == "file" {
+        return read_file_subresource(&request.url).await;
+    }
+
+    let requested_url = request.url.clone();
+    let credentials = request.credentials == CredentialsMode::Include;
+    let subresource = request.destination.is_subresource();
+    // After a redirect from one foreign origin to another the request's origin is `null`.
+    let mut tainted = false;
+    let mut redirects = 0;
+    loop {
+        let origin = if tainted {
+            "null".to_string()
+        } else {
+            request.origin.ascii_serialization()
+        };
+        let cors = request.cross_origin && request.mode == RequestMode::Cors && !subresource;
+        let blocked = |reason: String| {
+            format!(
+                "Access to fetch at '{requested_url}' from origin '{origin}' has been blocked by CORS policy: {reason}"
+            )
+        };
+
+        if cors && needs_preflight(&request.method, &request.headers) {
+            preflight(client, &request, &origin, credentials).await?;
+        }
+
+        let with_cookies = match request.credentials {
+            CredentialsMode::Omit => false,
+            CredentialsMode::SameOrigin => !request.cross_origin,
+            CredentialsMode::Include => true,
+        };
+        let mut headers = request.headers.clone();
+        headers.remove(reqwest::header::COOKIE);
+        if with_cookies {
+            let cookie = CookieJar::shared()
+                .header_for(&request.url)
+                .and_then(|cookie| HeaderValue::from_str(&cookie).ok());
+            if let Some(cookie) = cookie {
+                headers.insert(reqwest::header::COOKIE, cookie);
+            }
+        }
+        if request.cross_origin {
+            if let Ok(value) = HeaderValue::from_str(&origin) {
+                headers.insert(reqwest::header::ORIGIN, value);
+            }
+        }
+        privacy::apply_as(
+            &mut headers,
+            &request.url,
+            request.referrer.as_ref(),
+            request.user_agent.as_deref(),
+        );
+        let mut builder = client
+            .request(request.method.clone(), request.url.clone())
+            .headers(headers);
+        if let Some(body) = request.body.clone() {
+            builder = builder.body(body);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|err| format!("Failed to fetch {requested_url}: {err}"))?;
+
+        let status = response.status();
+        let response_headers = response.headers().clone();
+        if with_cookies {
+            for value in response_headers.get_all(reqwest::header::SET_COOKIE) {
+                if let Ok(value) = value.to_str() {
+                    CookieJar::shared().set(&request.url, value, false);
+                }
+            }
+        }
+
+        if let Some(location) = redirect_location(status, &response_headers) {
+            if cors {
+                check_access_control(&response_headers, &origin, credentials).map_err(blocked)?;
+            }
+            redirects += 1;
+            if redirects > MAX_REDIRECTS {
+                return Err(format!(
+                    "Failed to fetch {requested_url}: too many redirects"
+                ));
+            }
+            let next = request
+                .url
+                .join(location)
+                .map_err(|err| format!("Failed to fetch {requested_url}: bad redirect: {err}"))?;
+            request.follow(next, status, &mut tainted)?;
+            continue;
+        }
+
+        // A client that followed redirects on its own reports where it ended up.
+        if *response.url() != request.url {
+            request.url = response.url().clone();
+            request.cross_origin = tainted || origin_of(&request.url) != request.origin;
+        }
+        let final_url = request.url.clone();
+        let cors = request.cross_origin && request.mode == RequestMode::Cors && !subresource;
+
+        if request.cross_origin && request.mode == RequestMode::NoCors && !subresource {
+            return Ok(FetchResponse {
+                url: String::new(),
+                status: 0,
+                status_text: String::new(),
+                headers: Vec::new(),
+                body: Vec::new(),
+                blob: 0,
+                response_type: "opaque",
+                redirected: false,
+                charge: None,
+            });
+        }
+
+        if cors {
+            check_access_control(&response_headers, &origin, credentials).map_err(blocked)?;
+        }
+
+        let (body, charge) = read_body(response, memory.as_ref(), &final_url).await?;
+
+        let headers = if cors {
+            exposed_headers(&response_headers)
+        } else {
+            header_pairs(&response_headers, |_| true)
+        };
+
+        return Ok(FetchResponse {
+            url: final_url.to_string(),
+            status: status.as_u16(),
+            status_text: status.canonical_reason().unwrap_or_default().to_string(),
+            headers,
+            body,
+            blob: 0,
+            response_type: if cors { "cors" } else { "basic" },
+            redirected: final_url != requested_url,
+            charge,
+        });
+    }
+}
+
+/// Read `response`'s body, charging it to `memory` chunk by chunk so a body too large for the
+/// page's budget fails before it is all read. The bytes reach the page as they are; only
+/// `Response.text()` and `json()` decode them.
+async fn read_body(
+    mut response: reqwest::Response,
+    memory: Option<&NativeMemory<'static>>,
+    url: &Url,
+) -> Result<(Vec<u8>, Option<Reservation<'static>>), String> {
+    let too_large = || format!("Response body from {url} exceeds the page's memory quota");
+    let mut charge = match memory {
+        Some(memory) => Some(memory.reserve(0).ok_or_else(too_large)?),
+        None => None,
+    };
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| format!("Failed to read response body from {url}: {err}"))?
+    {
+        if let Some(charge) = charge.as_mut() {
+            if !charge.grow(chunk.len()) {
+                return Err(too_large());
+            }
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((body, charge))
+}
+
+/// The `Location` of a redirect response, if `status` is one.
+fn redirect_location(status: StatusCode, headers: &HeaderMap) -> Option<&str> {
+    if !matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308) {
+        return None;
+    }
+    header_str(headers, reqwest::header::LOCATION)
+}
+
+/// Whether a redirect with `status` turns a `method` request into a `GET` without a body.
+fn redirect_drops_body(status: StatusCode, method: &Method) -> bool {
+    match status.as_u16() {
+        301 | 302 => *method == Method::POST,
+        303 => *method != Method::HEAD,
+        _ => false,
+    }
+}
+
+impl PreparedFetch {
+    /// Point the request at `next`, where a `status` redirect sent it, after checking the hop
+    /// like the first request. Sets `tainted` once the request went from one foreign origin to
+    /// another.
+    fn follow(&mut self, next: Url, status: StatusCode, tainted: &mut bool) -> Result<(), String> {
+        if !matches!(next.scheme(), "http" | "https") {
+            return Err(format!(
+                "Failed to fetch {next}: redirect to an unsupported scheme"
+            ));
+        }
+        let next_origin = origin_of(&next);
+        if self.mode == RequestMode::SameOrigin && next_origin != self.origin {
+            return Err(format!(
+                "Fetch API cannot load {next}: a \"same-origin\" request was redirected away"
+            ));
+        }
+        if self.mode == RequestMode::Cors
+            && next_origin != self.origin
+            && (!next.username().is_empty() || next.password().is_some())
+        {
+            return Err(format!(
+                "Fetch API cannot load {next}: redirect location includes credentials"
+            ));
+        }
+        if !self.allows(&next) {
+            return Err(format!(
+                "Refused to follow the redirect to '{next}': blocked by the Content Security Policy"
+            ));
+        }
+        if self.cross_origin && next_origin != origin_of(&self.url) {
+            *tainted = true;
+        }
+        if redirect_drops_body(status, &self.method) {
+            self.method = Method::GET;
+            self.body = None;
+            for name in [
+                "content-encoding",
+                "content-language",
+                "content-location",
+                "content-type",
+            ] {
+                self.headers.remove(name);
+            }
+        }
+        self.cross_origin = *tainted || next_origin != self.origin;
+        self.url = next;
+        Ok(())
+    }
+
+    /// Whether the document's Content Security Policy lets the request go to `url`.
+    fn allows(&self, url: &Url) -> bool {
+        let document = self.referrer.as_ref();
+        match self.destination {
+            Destination::Empty => self.csp.allows_connect_url(url, document),
+            Destination::Script => self.csp.allows_script_url(url, document, None),
+            Destination::Style => self.csp.allows_style_url(url, document),
+        }
+    }
+}
+
+/// Local pages may load sibling scripts and stylesheets; `prepare` only lets `file:` through
+/// for subresources inserted into `file:` documents.
+async fn read_file_subresource(url: &Url) -> Result<FetchResponse, String> {
+    let path = url
+        .to_file_path()
+        .map_err(|_| format!("invalid file URL for subresource: {url}"))?;
+    let body = tokio::fs::read(&path)
+        .await
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    Ok(FetchResponse {
+        url: url.to_string(),
+        status: 200,
+        status_text: "OK".to_string(),
+        headers: Vec::new(),
+        body,
+        blob: 0,
+        response_type: "basic",
+        redirected: false,
+        charge: None,
+    })
+}
+
+async fn preflight(
+    client: &reqwest::Client,
+    request: &PreparedFetch,
+    origin: &str,
+    credentials: bool,
+) -> Result<(), String> {
+    let requested_headers = unsafe_request_header_names(&request.headers);
+    let mut headers = HeaderMap::new();
+    privacy::apply_as(
+        &mut headers,
+        &request.url,
+        request.referrer.as_ref(),
+        request.user_agent.as_deref(),
+    );
+    let mut builder = client
+        .request(Method::OPTIONS, request.url.clone())
+        .headers(headers)
+        .header(reqwest::header::ORIGIN, origin)
+        .header(
+            reqwest::header::ACCESS_CONTROL_REQUEST_METHOD,
+            request.method.as_str(),
+        );
+    if !requested_headers.is_empty() {
+        builder = builder.header(
+            reqwest::header::ACCESS_CONTROL_REQUEST_HEADERS,
+            requested_headers.join(","),
+        );
+    }
+
+    let blocked = |reason: String| {
+        format!(
+            "Access to fetch at '{}' from origin '{origin}' has been blocked by CORS policy: Response to preflight request doesn't pass access control check: {reason}",
+            request.url
+        )
+    };
+
+    let response = builder
+        .send()
+        .await
+        .map_err(|err| blocked(format!("preflight failed: {err}")))?;
+    if !response.status().is_success() {
+        return Err(blocked(format!(
+            "It does not have HTTP ok status ({}).",
+            response.status()
+        )));
+    }
+
+    let headers = response.headers();
+    check_access_control(headers, origin, credentials).map_err(blocked)?;
+    check_preflight_grants(headers, &request.method, &requested_headers, credentials)
+        .map_err(blocked)
+}
+
+/// Origin of `url`. `nostr://<name>` and `blossom://<hash>` documents are keyed by their
+/// name or root hash so each published site is its own origin rather than sharing `null`.
+pub(crate) fn origin_of(url: &Url) -> Origin {
+    match (url.scheme(), url.host()) {
+        ("nostr" | "blossom", Some(host)) => {
+            Origin::Tuple(url.scheme().to_string(), host.to_owned(), 0)
+        }
+        _ => url.origin(),
+    }
+}
+
+/// The headers page script set on a request, without the ones only the browser may set. A
+/// `no-cors` request keeps only CORS-safelisted headers, so it can't carry anything a form
+/// submission couldn't.
+fn request_headers(pairs: &[(String, String)], mode: RequestMode) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("'{name}' is not a valid header name"))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| format!("'{value}' is not a valid header value"))?;
+        if is_forbidden_request_header(name.as_str(), &value) {
+            continue;
+        }
+        if mode == RequestMode::NoCors && !is_cors_safelisted_request_header(name.as_str(), &value)
+        {
+            continue;
+        }
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// The Fetch standard's forbidden request headers. `name` is lowercase, as [`HeaderName`]
+/// stores it.
+fn is_forbidden_request_header(name: &str, value: &HeaderValue) -> bool {
+    const FORBIDDEN: &[&str] = &[
+        "accept-charset",
+        "accept-encoding",
+        "access-control-request-headers",
+        "access-control-request-method",
+        "connection",
+        "content-length",
+        "cookie",
+        "cookie2",
+        "date",
+        "dnt",
+        "expect",
+        "host",
+        "keep-alive",
+        "origin",
+        "referer",
+        "set-cookie",
+        "te",
+        "trailer",
+        "transfer-encoding",
+        "upgrade",
+        "via",
+    ];
+    if FORBIDDEN.contains(&name) || name.starts_with("proxy-") || name.starts_with("sec-") {
+        return true;
+    }
+    // Method overrides are forbidden when they name a method `fetch()` itself refuses.
+    matches!(
+        name,
+        "x-http-method" | "x-http-method-override" | "x-method-override"
+    ) && value.to_str().map_or(true, |methods| {
+        methods.split(',').any(|method| {
+            matches!(
+                method.trim().to_ascii_uppercase().as_str(),
+                "CONNECT" | "TRACE" | "TRACK"
+            )
+        })
+    })
+}
+
+fn is_cors_safelisted_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::POST)
+}
+
+/// Header names outside the CORS-safelist, lowercased and sorted as sent in
+/// `Access-Control-Request-Headers`.
+fn unsafe_request_header_names(headers: &HeaderMap) -> Vec<String> {
+    let mut names: Vec<String> = headers
+        .iter()
+        .filter(|(name, value)| !is_cors_safelisted_request_header(name.as_str(), value))
+        .map(|(name, _)| name.as_str().to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn is_cors_safelisted_request_header(name: &str, value: &HeaderValue) -> bool {
+    match name {
+        "accept" | "accept-language" | "content-language" => true,
+        "content-type" => {
+            let essence = value
+                .to_str()
+                .unwrap_or_default()
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            matches!(
+                essence.as_str(),
+                "application/x-www-form-urlencoded" | "multipart/form-data" | "text/plain"
+            )
+        }
+        _ => false,
+    }
+}
+
+fn needs_preflight(method: &Method, headers: &HeaderMap) -> bool {
+    !is_cors_safelisted_method(method) || !unsafe_request_header_names(headers).is_empty()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn check_access_control(
+    headers: &HeaderMap,
+    origin: &str,
+    credentials: bool,
+) -> Result<(), String> {
+    let allow_origin = header_str(headers, reqwest::header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .map(str::trim)
+        .ok_or_else(|| {
+            "No 'Access-Control-Allow-Origin' header is present on the requested resource."
+                .to_string()
+        })?;
+
+    if allow_origin == "*" {
+        if credentials {
+            return Err("The value of the 'Access-Control-Allow-Origin' header must not be the wildcard '*' when the request's credentials mode is 'include'.".to_string());
+        }
+        return Ok(());
+    }
+
+    if allow_origin != origin {
+        return Err(format!(
+            "The 'Access-Control-Allow-Origin' header has a value '{allow_origin}' that is not equal to the supplied origin."
+        ));
+    }
+
+    if credentials {
+        let allow_credentials =
+            header_str(headers, reqwest::header::ACCESS_CONTROL_ALLOW_CREDENTIALS).map(str::trim);
+        if allow_credentials != Some("true") {
+            return Err("The value of the 'Access-Control-Allow-Credentials' header must be 'true' when the request's credentials mode is 'include'.".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+fn check_preflight_grants(
+    headers: &HeaderMap,
+    method: &Method,
+    requested_headers: &[String],
+    credentials: bool,
+) -> Result<(), String> {
+    let allowed_methods = header_list(headers, reqwest::header::ACCESS_CONTROL_ALLOW_METHODS);
+    let method_allowed = is_cors_safelisted_method(method)
+        || allowed_methods
+            .iter()
+            .any(|allowed| allowed == method.as_str() || (allowed == "*" && !credentials));
+    if !method_allowed {
+        return Err(format!(
+            "Method {method} is not allowed by Access-Control-Allow-Methods."
+        ));
+    }
+
+    let allowed_headers: Vec<String> =
+        header_list(headers, reqwest::header::ACCESS_CONTROL_ALLOW_HEADERS)
+            .into_iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+    let wildcard = allowed_headers.iter().any(|name| name == "*") && !credentials;
+    for name in requested_headers {
+        let granted = allowed_headers.contains(name) || (wildcard && name != "authorization");
+        if !granted {
+            return Err(format!(
+                "Request header field {name} is not allowed by Access-Control-Allow-Headers."
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn header_list(headers: &HeaderMap, name: HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Headers a cross-origin response exposes to script: the safelist plus whatever
+/// `Access-Control-Expose-Headers` names.
+fn exposed_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    let exposed: Vec<String> = header_list(headers, reqwest::header::ACCESS_CONTROL_EXPOSE_HEADERS)
+        .into_iter()
+        .map(|name| name.to_ascii_lowercase())
+        .collect();
+    header_pairs(headers, |name| {
+        SAFELISTED_RESPONSE_HEADERS.contains(&name) || exposed.iter().any(|item| item == name)
+    })
+}
+
+fn header_pairs(headers: &HeaderMap, include: impl Fn(&str) -> bool) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(name, _)| include(name.as_str()))
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.as_str().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// JS half of `fetch()`: `Headers`, `Request`, `Response` and the promise bookkeeping that
+/// `__frontier_fetch_start` and `frontier.__resolveFetch` talk through.
+pub(crate) const FETCH_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    const fetchStart = global.__frontier_fetch_start;
+    const fetchAbort = global.__frontier_fetch_abort;
+    const blobRelease = global.__frontier_blob_release;
+    const PENDING = new Map();
+
+    const normalizeName = (name) => String(name).toLowerCase();
+
+    class Headers {
+        constructor(init) {
+            this._list = [];
+            if (init instanceof Headers) {
+                init.forEach((value, name) => this.append(name, value));
+            } else if (Array.isArray(init)) {
+                for (const pair of init) {
+                    this.append(pair[0], pair[1]);
+                }
+            } else if (init && typeof init === 'object') {
+                for (const name of Object.keys(init)) {
+                    this.append(name, init[name]);
+                }
+            }
+        }
+        append(name, value) {
+            this._list.push([normalizeName(name), String(value)]);
+        }
+        set(name, value) {
+            this.delete(name);
+            this.append(name, value);
+        }
+        delete(name) {
+            const key = normalizeName(name);
+            this._list = this._list.filter((entry) => entry[0] !== key);
+        }
+        get(name) {
+            const key = normalizeName(name);
+            const values = this._list.filter((entry) => entry[0] === key).map((entry) => entry[1]);
+            return values.length ? values.join(', ') : null;
+        }
+        has(name) {
+            const key = normalizeName(name);
+            return this._list.some((entry) => entry[0] === key);
+        }
+        *entries() {
+            const names = Array.from(new Set(this._list.map((entry) => entry[0]))).sort();
+            for (const name of names) {
+                yield [name, this.get(name)];
+            }
+        }
+        *keys() {
+            for (const entry of this.entries()) {
+                yield entry[0];
+            }
+        }
+        *values() {
+            for (const entry of this.entries()) {
+                yield entry[1];
+            }
+        }
+        forEach(callback, thisArg) {
+            for (const [name, value] of this.entries()) {
+                callback.call(thisArg, value, name, this);
+            }
+        }
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+    }
+
+    // The body to keep on the request, and the content type it implies.
+    const extractBody = (body) => {
+        if (body === undefined || body === null) {
+            return [null, null];
+        }
+        const { URLSearchParams } = global;
+        if (typeof URLSearchParams === 'function' && body instanceof URLSearchParams) {
+            return [String(body), 'application/x-www-form-urlencoded;charset=UTF-8'];
+        }
+        if (typeof global.FormData === 'function' && body instanceof global.FormData) {
+            const multipart = frontier.__formDataBody(body);
+            return [multipart.blob, multipart.contentType];
+        }
+        if (typeof global.Blob === 'function' && body instanceof global.Blob) {
+            return [body, body.type || null];
+        }
+        if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+            return [new global.Blob([body]), null];
+        }
+        return [String(body), 'text/plain;charset=UTF-8'];
+    };
+
+    const serializeBody = (body) =>
+        typeof global.Blob === 'function' && body instanceof global.Blob
+            ? { blob: frontier.__blobId(body) }
+            : body;
+
+    class Request {
+        constructor(input, init = {}) {
+            const source = input instanceof Request ? input : null;
+            this.url = source ? source.url : String(input);
+            this.method = String(init.method || (source && source.method) || 'GET').toUpperCase();
+            this.headers = new Headers(init.headers || (source && source.headers));
+            this.mode = init.mode || (source && source.mode) || 'cors';
+            this.credentials = init.credentials || (source && source.credentials) || 'same-origin';
+            this.signal = init.signal || (source && source.signal) || null;
+            const body = init.body !== undefined ? init.body : source ? source._body : null;
+            if (body !== null && body !== undefined && (this.method === 'GET' || this.method === 'HEAD')) {
+                throw new TypeError('Request with GET/HEAD method cannot have body.');
+            }
+            const [extracted, contentType] = extractBody(body);
+            if (contentType !== null && !this.headers.has('content-type')) {
+                this.headers.set('content-type', contentType);
+            }
+            this._body = extracted;
+        }
+    }
+
+    // A response body is a string or, for anything binary, a `Blob`; it is decoded only by
+    // `text()` and `json()`.
+    const responseBody = (body) => {
+        if (body === undefined || body === null) {
+            return '';
+        }
+        const { Blob } = global;
+        if (typeof Blob === 'function') {
+            if (body instanceof Blob) {
+                return body;
+            }
+            if (body instanceof ArrayBuffer || ArrayBuffer.isView(body)) {
+                return new Blob([body]);
+            }
+        }
+        return String(body);
+    };
+
+    class Response {
+        constructor(body, init = {}) {
+            this._body = responseBody(body);
+            this.status = init.status === undefined ? 200 : init.status;
+            this.statusText = init.statusText || '';
+            this.headers = new Headers(init.headers);
+            this.type = init.type || 'default';
+            this.url = init.url || '';
+            this.redirected = !!init.redirected;
+            this.bodyUsed = false;
+        }
+        get ok() {
+            return this.status >= 200 && this.status < 300;
+        }
+        _consume() {
+            if (this.bodyUsed) {
+                return Promise.reject(new TypeError('Body has already been consumed.'));
+            }
+            this.bodyUsed = true;
+            return Promise.resolve(this._body);
+        }
+        text() {
+            return this._consume().then((body) => (typeof body === 'string' ? body : body.text()));
+        }
+        json() {
+            return this.text().then((text) => JSON.parse(text));
+        }
+        blob() {
+            const type = this.headers.get('content-type') || '';
+            return this._consume().then((body) =>
+                typeof body === 'string'
+                    ? new global.Blob([body], { type })
+                    : body.slice(0, body.size, type)
+            );
+        }
+        arrayBuffer() {
+            return this.blob().then((blob) => blob.arrayBuffer());
+        }
+        clone() {
+            if (this.bodyUsed) {
+                throw new TypeError('Response body is already used');
+            }
+            return new Response(this._body, this);
+        }
+    }
+
+    const abortReason = (signal) =>
+        signal.reason !== undefined
+            ? signal.reason
+            : new DOMException('The operation was aborted.', 'AbortError');
+
+    global.Headers = Headers;
+    global.Request = Request;
+    global.Response = Response;
+
+    global.fetch = function fetch(input, init) {
+        let request;
+        try {
+            request = new Request(input, init || {});
+        } catch (err) {
+            return Promise.reject(err);
+        }
+        const signal = request.signal;
+        if (signal && signal.aborted) {
+            return Promise.reject(abortReason(signal));
+        }
+
+        return new Promise((resolve, reject) => {
+            const payload = {
+                url: request.url,
+                method: request.method,
+                headers: Array.from(request.headers.entries()),
+                body: serializeBody(request._body),
+                mode: request.mode,
+                credentials: request.credentials,
+            };
+            const id = fetchStart(JSON.stringify(payload));
+            PENDING.set(id, { resolve, reject });
+            if (signal) {
+                signal.addEventListener('abort', () => {
+                    if (!PENDING.has(id)) {
+                        return;
+                    }
+                    PENDING.delete(id);
+                    fetchAbort(id);
+                    reject(abortReason(signal));
+                });
+            }
+        });
+    };
+
+    frontier.__resolveFetch = (id, json) => {
+        const entry = PENDING.get(id);
+        const data = JSON.parse(json);
+        if (!entry) {
+            blobRelease(data.blob);
+            return;
+        }
+        PENDING.delete(id);
+        entry.resolve(new Response(frontier.__adoptBlob(data.blob), data));
+    };
+
+    frontier.__rejectFetch = (id, message) => {
+        const entry = PENDING.get(id);
+        if (!entry) {
+            return;
+        }
+        PENDING.delete(id);
+        entry.reject(new TypeError(message));
+    };
+})();
+"#;
+
+/// Run after every bootstrap has taken the native hooks it needs: drops every `__frontier_*`
+/// hook from the global object and pins the callbacks [`FetchManager::run_completed`] settles
+/// through, so page script can neither call into the DOM, canvas or storage internals directly,
+/// start a script or stylesheet load that skips CORS, nor read another's response.
+pub(crate) const SEAL_NATIVE_HOOKS: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    for (const name of Object.getOwnPropertyNames(global)) {
+        if (name.startsWith('__frontier_')) {
+            delete global[name];
+        }
+    }
+    for (const name of ['__resolveFetch', '__rejectFetch']) {
+        Object.defineProperty(frontier, name, {
+            value: frontier[name],
+            writable: false,
+            configurable: false,
+        });
+    }
+    Object.defineProperty(global, 'frontier', {
+        value: frontier,
+        writable: false,
+        configurable: false,
+    });
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    fn prepared(url: &str, method: Method, mode: RequestMode) -> PreparedFetch {
+        let document = Url::parse("https://app.example/").unwrap();
+        let url = Url::parse(url).unwrap();
+        PreparedFetch {
+            cross_origin: origin_of(&url) != origin_of(&document),
+            url,
+            method,
+            headers: headers(&[("content-type", "text/plain")]),
+            body: Some(b"hi".to_vec()),
+            mode,
+            credentials: CredentialsMode::SameOrigin,
+            origin: origin_of(&document),
+            destination: Destination::Empty,
+            referrer: Some(document),
+            user_agent: None,
+            csp: ContentSecurityPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn redirects_are_checked_per_hop() {
+        let see_other = StatusCode::SEE_OTHER;
+        let mut tainted = false;
+        let mut request = prepared("https://app.example/go", Method::POST, RequestMode::Cors);
+        let next = Url::parse("https://api.example/data").unwrap();
+        request
+            .follow(next.clone(), see_other, &mut tainted)
+            .unwrap();
+        assert!(
+            request.cross_origin,
+            "leaving the origin makes the request cross-origin"
+        );
+        assert!(!tainted);
+        assert_eq!(request.method, Method::GET);
+        assert!(request.body.is_none() && request.headers.is_empty());
+
+        let elsewhere = Url::parse("https://cdn.example/data").unwrap();
+        let kept = StatusCode::TEMPORARY_REDIRECT;
+        request.follow(elsewhere, kept, &mut tainted).unwrap();
+        assert!(tainted, "one foreign origin to another taints the origin");
+
+        let mut same = prepared(
+            "https://app.example/go",
+            Method::GET,
+            RequestMode::SameOrigin,
+        );
+        assert!(same.follow(next, kept, &mut false).is_err());
+        let mut cors = prepared("https://app.example/go", Method::GET, RequestMode::Cors);
+        let file = Url::parse("file:///etc/passwd").unwrap();
+        assert!(cors.follow(file, kept, &mut false).is_err());
+        let with_credentials = Url::parse("https://user:pw@api.example/").unwrap();
+        assert!(cors.follow(with_credentials, kept, &mut false).is_err());
+    }
+
+    #[test]
+    fn published_sites_get_tuple_origins() {
+        let site = Url::parse("nostr://alice/index.html").unwrap();
+        let other = Url::parse("nostr://bob/index.html").unwrap();
+        assert_eq!(origin_of(&site).ascii_serialization(), "nostr://alice");
+        assert_ne!(origin_of(&site), origin_of(&other));
+        assert_eq!(
+            origin_of(&Url::parse("file:///tmp/a.html").unwrap()).ascii_serialization(),
+            "null"
+        );
+    }
+
+    #[test]
+    fn simple_requests_skip_preflight() {
+        assert!(!needs_preflight(
+            &Method::GET,
+            &headers(&[("accept", "application/json")])
+        ));
+        assert!(!needs_preflight(
+            &Method::POST,
+            &headers(&[("content-type", "text/plain;charset=UTF-8")])
+        ));
+        assert!(needs_preflight(
+            &Method::POST,
+            &headers(&[("content-type", "application/json")])
+        ));
+        assert!(needs_preflight(&Method::PUT, &HeaderMap::new()));
+        assert_eq!(
+            unsafe_request_header_names(&headers(&[("x-b", "1"), ("x-a", "1"), ("accept", "*/*")])),
+            vec!["x-a".to_string(), "x-b".to_string()]
+        );
+    }
+
+    #[test]
+    fn page_script_cannot_set_forbidden_or_unsafe_no_cors_headers() {
+        let pairs = |list: &[(&str, &str)]| -> Vec<(String, String)> {
+            list.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        let set = pairs(&[
+            ("Cookie", "session=stolen"),
+            ("X-Custom", "1"),
+            ("Accept", "text/plain"),
+        ]);
+        let no_cors = request_headers(&set, RequestMode::NoCors).unwrap();
+        assert!(no_cors.get("cookie").is_none());
+        assert!(no_cors.get("x-custom").is_none());
+        assert_eq!(no_cors.get("accept").unwrap(), "text/plain");
+
+        let cors = request_headers(&set, RequestMode::Cors).unwrap();
+        assert!(cors.get("cookie").is_none());
+        assert_eq!(cors.get("x-custom").unwrap(), "1");
+
+        let forbidden = pairs(&[
+            ("Host", "evil.example"),
+            ("Origin", "https://evil.example"),
+            ("Referer", "https://evil.example/"),
+            ("Proxy-Authorization", "Basic eA=="),
+            ("Sec-Fetch-Site", "same-origin"),
+            ("X-HTTP-Method-Override", "TRACE"),
+        ]);
+        assert!(request_headers(&forbidden, RequestMode::Cors)
+            .unwrap()
+            .is_empty());
+        assert!(request_headers(&pairs(&[("bad name", "1")]), RequestMode::Cors).is_err());
+    }
+
+    #[test]
+    fn allow_origin_must_match_or_be_wildcard() {
+        let origin = "https://app.example";
+        assert!(check_access_control(&HeaderMap::new(), origin, false).is_err());
+        assert!(check_access_control(
+            &headers(&[("access-control-allow-origin", "*")]),
+            origin,
+            false
+        )
+        .is_ok());
+        assert!(check_access_control(
+            &headers(&[("access-control-allow-origin", "*")]),
+            origin,
+            true
+        )
+        .is_err());
+        assert!(check_access_control(
+            &headers(&[("access-control-allow-origin", "https://other.example")]),
+            origin,
+            false
+        )
+        .is_err());
+        assert!(check_access_control(
+            &headers(&[
+                ("access-control-allow-origin", "https://app.example"),
+                ("access-control-allow-credentials", "true"),
+            ]),
+            origin,
+            true
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn preflight_grants_cover_method_and_headers() {
+        let granted = headers(&[
+            ("access-control-allow-methods", "PUT, DELETE"),
+            ("access-control-allow-headers", "X-Token"),
+        ]);
+        assert!(check_preflight_grants(&granted, &Method::PUT, &["x-token".into()], false).is_ok());
+        assert!(check_preflight_grants(&granted, &Method::PATCH, &[], false).is_err());
+        assert!(
+            check_preflight_grants(&granted, &Method::PUT, &["x-other".into()], false).is_err()
+        );
+    }
+
+    #[test]
+    fn cross_origin_responses_only_expose_safelisted_headers() {
+        let response = headers(&[
+            ("content-type", "application/json"),
+            ("x-secret", "1"),
+            ("x-visible", "1"),
+            ("access-control-expose-headers", "X-Visible"),
+        ]);
+        let names: Vec<String> = exposed_headers(&response)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(names.contains(&"content-type".to_string()));
+        assert!(names.contains(&"x-visible".to_string()));
+        assert!(!names.contains(&"x-secret".to_string()));
+    }
+}
@@ -0,0 +1,146 @@
+//! `<input type=file>`: the selection page script sees, and which inputs the host opens the
+//! native file dialog for.
+//!
+//! Clicking a file input, or calling `click()` or `showPicker()` on one during a user gesture,
+//! asks the host for the dialog; the host then stores the chosen files through
+//! [`JsDomEnvironment::set_input_files`](super::environment::JsDomEnvironment::set_input_files),
+//! which fires `input` and `change`. The selection is the input's `files`, and `FormData`
+//! submits it with the rest of the form. Automation sessions never get a dialog and set the
+//! selection directly instead.
+
+use blitz_dom::{local_name, BaseDocument};
+
+/// The attributes of a file input that shape its dialog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInput {
+    pub multiple: bool,
+    /// File extensions the dialog offers, or `None` when any file is accepted.
+    pub extensions: Option<Vec<String>>,
+}
+
+/// The enabled `<input type=file>` at `node_id`, if that is what it is.
+pub fn file_input(doc: &BaseDocument, node_id: usize) -> Option<FileInput> {
+    let node = doc.get_node(node_id)?;
+    let is_file_input = node.element_data()?.name.local == local_name!("input")
+        && node
+            .attr(local_name!("type"))
+            .is_some_and(|kind| kind.eq_ignore_ascii_case("file"));
+    if !is_file_input || node.attr(local_name!("disabled")).is_some() {
+        return None;
+    }
+    Some(FileInput {
+        multiple: node.attr(local_name!("multiple")).is_some(),
+        extensions: node.attr(local_name!("accept")).and_then(accept_extensions),
+    })
+}
+
+/// The extensions an `accept` attribute lists. MIME types such as `image/*` cannot be mapped
+/// onto a dialog filter faithfully, so any of them leaves the dialog unfiltered.
+pub fn accept_extensions(accept: &str) -> Option<Vec<String>> {
+    let mut extensions = Vec::new();
+    for token in accept
+        .split(',')
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+    {
+        let extension = token.strip_prefix('.')?;
+        extensions.push(extension.to_ascii_lowercase());
+    }
+    (!extensions.is_empty()).then_some(extensions)
+}
+
+/// JS half: `files` on file inputs, `click()` and `showPicker()`, and
+/// `frontier.__setInputFiles(handle, files)` for the host.
+pub(crate) const FILE_INPUT_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const requestFilePicker = global.__frontier_request_file_picker;
+    const frontier = global.frontier;
+    const proto = global.Element && global.Element.prototype;
+    if (!proto) {
+        return;
+    }
+    const SELECTIONS = new WeakMap();
+
+    const isFileInput = (element) =>
+        String(element.localName || '').toLowerCase() === 'input' &&
+        String(element.getAttribute('type') || '').toLowerCase() === 'file';
+
+    const notAllowed = (message) => {
+        if (typeof global.DOMException === 'function') {
+            return new global.DOMException(message, 'NotAllowedError');
+        }
+        const error = new Error(message);
+        error.name = 'NotAllowedError';
+        return error;
+    };
+
+    // Whether the host will show the dialog, which needs a recent user gesture.
+    const requestPicker = (input) =>
+        !input.hasAttribute('disabled') &&
+        requestFilePicker(frontier.__handleOf(input));
+
+    Object.defineProperty(proto, 'files', {
+        get() {
+            if (!isFileInput(this)) {
+                return undefined;
+            }
+            return SELECTIONS.get(this) ?? new global.FileList([]);
+        },
+        set(list) {
+            if (!isFileInput(this)) {
+                return;
+            }
+            if (!(list instanceof global.FileList)) {
+                throw new TypeError("Failed to set 'files': value is not a FileList.");
+            }
+            SELECTIONS.set(this, list);
+        },
+        configurable: true,
+    });
+
+    proto.showPicker = function showPicker() {
+        if (isFileInput(this) && !requestPicker(this)) {
+            throw notAllowed('showPicker() requires a user gesture.');
+        }
+    };
+
+    if (typeof proto.click !== 'function') {
+        proto.click = function click() {
+            const event = new global.Event('click', { bubbles: true, cancelable: true });
+            if (this.dispatchEvent(event) && isFileInput(this)) {
+                requestPicker(this);
+            }
+        };
+    }
+
+    frontier.__setInputFiles = (handle, files) => {
+        const input = frontier.wrapHandle(handle);
+        if (!input || !isFileInput(input)) {
+            return false;
+        }
+        const selection = files.map((file) =>
+            frontier.__adoptFile(file.blob, file.name, file.type, file.lastModified),
+        );
+        SELECTIONS.set(input, new global.FileList(selection));
+        input.dispatchEvent(new global.Event('input', { bubbles: true, composed: true }));
+        input.dispatchEvent(new global.Event('change', { bubbles: true }));
+        return true;
+    };
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_lists_extensions_only() {
+        assert_eq!(
+            accept_extensions(".png, .JPG"),
+            Some(vec!["png".to_string(), "jpg".to_string()])
+        );
+        assert_eq!(accept_extensions(".png,image/*"), None);
+        assert_eq!(accept_extensions(" "), None);
+    }
+}
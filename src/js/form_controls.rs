@@ -0,0 +1,198 @@
+//! Script access to the form control state the host's widgets change, and the events the host
+//! fires when the user changes it.
+//!
+//! The state lives in the document: the `selected` attribute of the chosen `<option>`, the
+//! `checked` attribute of checkboxes and radio buttons, and the `value` attribute of number,
+//! date and range inputs. The host's widgets and the properties here both read and write those
+//! attributes, so forms, `FormData` and script agree on them, and checking a radio button
+//! unchecks the rest of its group either way. After the user commits a value, the host calls
+//! [`JsDomEnvironment::control_changed`](super::environment::JsDomEnvironment::control_changed)
+//! to fire `input` and `change` at the control; while a range slider is dragged it calls
+//! [`JsDomEnvironment::control_input`](super::environment::JsDomEnvironment::control_input),
+//! which fires only `input`.
+
+/// JS half: `options`, `selectedIndex` and `value` on selects, `selected` on options, `checked`,
+/// `indeterminate` and `value` on inputs, and `frontier.__controlChanged(handle, change)` for
+/// the host.
+pub(crate) const FORM_CONTROLS_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    const proto = global.Element && global.Element.prototype;
+    if (!proto) {
+        return;
+    }
+
+    const isSelect = (element) => String(element.localName || '') === 'select';
+    const isOption = (element) => String(element.localName || '') === 'option';
+    const inputType = (element) =>
+        String(element.localName || '') === 'input'
+            ? String(element.getAttribute('type') || 'text').toLowerCase()
+            : null;
+    const isCheckable = (element) => ['checkbox', 'radio'].includes(inputType(element));
+    const isSteppable = (element) => ['number', 'date', 'range'].includes(inputType(element));
+    const INDETERMINATE = new WeakMap();
+
+    const toggleAttribute = (element, name, on) => {
+        if (on) {
+            element.setAttribute(name, '');
+        } else if (element.hasAttribute(name)) {
+            element.removeAttribute(name);
+        }
+    };
+
+    // Options in tree order, including those inside <optgroup>.
+    const optionsOf = (select) =>
+        select.children.flatMap((child) => {
+            if (child.localName === 'optgroup') {
+                return child.children.filter(isOption);
+            }
+            return isOption(child) ? [child] : [];
+        });
+
+    // The option a single-choice select shows: the last one marked selected, else the first
+    // enabled one.
+    const selectedIndexOf = (select) => {
+        const options = optionsOf(select);
+        for (let index = options.length - 1; index >= 0; index -= 1) {
+            if (options[index].hasAttribute('selected')) {
+                return index;
+            }
+        }
+        return options.findIndex((option) => !option.hasAttribute('disabled'));
+    };
+
+    const selectIndex = (select, index) => {
+        optionsOf(select).forEach((option, position) => {
+            if (position === index) {
+                option.setAttribute('selected', '');
+            } else if (option.hasAttribute('selected')) {
+                option.removeAttribute('selected');
+            }
+        });
+    };
+
+    const optionValue = (option) => option.getAttribute('value') ?? option.textContent.trim();
+
+    // A range input always has a value: its attribute within min and max, else the midpoint.
+    const rangeValue = (input) => {
+        const bound = (name, fallback) => {
+            const number = Number.parseFloat(input.getAttribute(name));
+            return Number.isFinite(number) ? number : fallback;
+        };
+        const min = bound('min', 0);
+        const max = Math.max(bound('max', 100), min);
+        const value = Number.parseFloat(input.getAttribute('value'));
+        if (!Number.isFinite(value)) {
+            return String((min + max) / 2);
+        }
+        return String(Math.min(Math.max(value, min), max));
+    };
+
+    // Elements a property does not apply to keep it as a plain property, as they did before
+    // it was defined here.
+    const accessor = (name, applies, get, set) =>
+        Object.defineProperty(proto, name, {
+            get() {
+                return applies(this) ? get(this) : undefined;
+            },
+            set(value) {
+                if (applies(this)) {
+                    set(this, value);
+                    return;
+                }
+                Object.defineProperty(this, name, {
+                    value,
+                    writable: true,
+                    enumerable: true,
+                    configurable: true,
+                });
+            },
+            configurable: true,
+        });
+
+    accessor('options', isSelect, optionsOf, () => {});
+
+    accessor('selectedIndex', isSelect, selectedIndexOf, (select, index) =>
+        selectIndex(select, Number(index)),
+    );
+
+    accessor(
+        'value',
+        (element) => isSelect(element) || isOption(element) || isCheckable(element) ||
+            isSteppable(element),
+        (element) => {
+            if (isOption(element)) {
+                return optionValue(element);
+            }
+            if (isCheckable(element)) {
+                return element.getAttribute('value') ?? 'on';
+            }
+            if (inputType(element) === 'range') {
+                return rangeValue(element);
+            }
+            if (isSteppable(element)) {
+                return element.getAttribute('value') ?? '';
+            }
+            const option = optionsOf(element)[selectedIndexOf(element)];
+            return option ? optionValue(option) : '';
+        },
+        (element, value) => {
+            const text = String(value);
+            if (!isSelect(element)) {
+                element.setAttribute('value', text);
+                return;
+            }
+            const options = optionsOf(element);
+            selectIndex(element, options.findIndex((option) => optionValue(option) === text));
+        },
+    );
+
+    accessor(
+        'selected',
+        isOption,
+        (option) => {
+            let select = option.parentNode;
+            if (select && select.localName === 'optgroup') {
+                select = select.parentNode;
+            }
+            if (!select || !isSelect(select)) {
+                return option.hasAttribute('selected');
+            }
+            return optionsOf(select)[selectedIndexOf(select)] === option;
+        },
+        (option, selected) => toggleAttribute(option, 'selected', selected),
+    );
+
+    accessor(
+        'checked',
+        isCheckable,
+        (input) => input.hasAttribute('checked'),
+        (input, checked) => toggleAttribute(input, 'checked', Boolean(checked)),
+    );
+
+    // Only script sets `indeterminate`; the user toggling the checkbox clears it.
+    accessor(
+        'indeterminate',
+        (element) => inputType(element) === 'checkbox',
+        (input) => INDETERMINATE.get(input) ?? false,
+        (input, indeterminate) => INDETERMINATE.set(input, Boolean(indeterminate)),
+    );
+
+    // `change` is false while the user is still dragging a range slider.
+    frontier.__controlChanged = (handle, change) => {
+        const control = frontier.wrapHandle(handle);
+        if (!control) {
+            return false;
+        }
+        if (inputType(control) === 'checkbox') {
+            INDETERMINATE.delete(control);
+        }
+        control.dispatchEvent(new global.Event('input', { bubbles: true, composed: true }));
+        if (change !== false) {
+            control.dispatchEvent(new global.Event('change', { bubbles: true }));
+        }
+        return true;
+    };
+})();
+"#;
@@ -0,0 +1,77 @@
+//! Trackpad gestures as page events.
+//!
+//! These are not standard DOM events. Pinches follow Safari's: `gesturestart`, then a
+//! `gesturechange` per step, then `gestureend`, each carrying `scale` (the pinch's
+//! magnification so far, starting at 1) and `rotation` (always 0). A two-finger sideways swipe
+//! fires `swipe` with `direction` set to `'left'` or `'right'`, the way the fingers moved. All
+//! of them bubble from the element under the pointer with `clientX`/`clientY`, and cancelling
+//! one keeps the browser from zooming or navigating, so pages such as maps can handle the
+//! gesture themselves. See [`crate::gestures`] for how the host recognises them.
+
+use crate::gestures::SwipeDirection;
+
+/// Which event of a pinch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinchPhase {
+    Start,
+    Change,
+    End,
+}
+
+/// One gesture event for the page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GestureEvent {
+    /// `scale` is the magnification since the pinch started.
+    Pinch {
+        phase: PinchPhase,
+        scale: f64,
+    },
+    Swipe(SwipeDirection),
+}
+
+impl GestureEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GestureEvent::Pinch {
+                phase: PinchPhase::Start,
+                ..
+            } => "gesturestart",
+            GestureEvent::Pinch {
+                phase: PinchPhase::Change,
+                ..
+            } => "gesturechange",
+            GestureEvent::Pinch {
+                phase: PinchPhase::End,
+                ..
+            } => "gestureend",
+            GestureEvent::Swipe(_) => "swipe",
+        }
+    }
+}
+
+/// JS half: `frontier.__fireGesture(handle, path, type, scale, direction, x, y)`, which fires
+/// one gesture event and returns whether the page cancelled it.
+pub(crate) const GESTURE_BOOTSTRAP: &str = r#"
+(() => {
+    const frontier = globalThis.frontier;
+
+    frontier.__fireGesture = (handle, pathHandles, type, scale, direction, x, y) => {
+        const detail = {
+            bubbles: true,
+            cancelable: true,
+            composed: true,
+            clientX: x,
+            clientY: y,
+            x,
+            y,
+        };
+        if (type === 'swipe') {
+            detail.direction = direction;
+        } else {
+            detail.scale = scale;
+            detail.rotation = 0;
+        }
+        return frontier.__dispatchDomEvent(handle, type, detail, pathHandles).defaultPrevented;
+    };
+})();
+"#;
@@ -0,0 +1,298 @@
+//! `IntersectionObserver`.
+//!
+//! Observers are evaluated against the geometry of the last layout: the host calls
+//! [`JsDomEnvironment::update_intersections`](super::environment::JsDomEnvironment::update_intersections)
+//! after every frame it resolves, which covers scrolling, resizing and DOM changes. `observe()`
+//! asks for such a frame through
+//! [`JsDomEnvironment::wants_intersection_update`](super::environment::JsDomEnvironment::wants_intersection_update)
+//! so the first entries arrive even on a page that would otherwise not repaint. Entries are
+//! delivered to the callback in a microtask.
+//!
+//! Intersections are clipped by the root only: the viewport, or the explicit `root` element's
+//! border box. Overflow clipping of the elements in between is ignored.
+
+/// JS half: `IntersectionObserver` plus `frontier.__updateIntersections()`, which computes every
+/// observation, queues entries for the targets that crossed a threshold and returns whether
+/// there were any.
+pub(crate) const INTERSECTION_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const domLayoutRect = global.__frontier_dom_layout_rect;
+    const requestIntersectionUpdate = global.__frontier_request_intersection_update;
+    const frontier = global.frontier;
+    const INTERNALS = new WeakMap();
+    // Observers with at least one target, in creation order of their first observe().
+    const ACTIVE = new Set();
+
+    const makeRect = (x, y, width, height) =>
+        Object.freeze({
+            x,
+            y,
+            width,
+            height,
+            top: y,
+            left: x,
+            right: x + width,
+            bottom: y + height,
+            toJSON() {
+                const right = x + width;
+                const bottom = y + height;
+                return { x, y, width, height, top: y, left: x, right, bottom };
+            },
+        });
+
+    const layoutRect = (node) => {
+        const handle = frontier.__handleOf(node);
+        const rect = handle == null ? null : domLayoutRect(handle);
+        return rect ? { x: rect[0], y: rect[1], width: rect[2], height: rect[3] } : null;
+    };
+
+    const internals = (observer) => {
+        const state = INTERNALS.get(observer);
+        if (!state) {
+            throw new TypeError('Illegal invocation');
+        }
+        return state;
+    };
+
+    const constructError = (message) =>
+        `Failed to construct 'IntersectionObserver': ${message}`;
+
+    // CSS margin shorthand of one to four px or % lengths.
+    const parseMargin = (value) => {
+        const parts = String(value).trim().split(/\s+/).filter(Boolean);
+        if (parts.length > 4) {
+            throw new global.DOMException(
+                constructError('rootMargin must be specified in pixels or percent.'),
+                'SyntaxError',
+            );
+        }
+        const lengths = parts.map((part) => {
+            const match = /^([+-]?(?:\d+\.?\d*|\.\d+))(px|%)$/.exec(part);
+            if (!match && part !== '0') {
+                throw new global.DOMException(
+                    constructError('rootMargin must be specified in pixels or percent.'),
+                    'SyntaxError',
+                );
+            }
+            return match ? { value: Number(match[1]), unit: match[2] } : { value: 0, unit: 'px' };
+        });
+        const [top = { value: 0, unit: 'px' }, right = top, bottom = top, left = right] = lengths;
+        return [top, right, bottom, left];
+    };
+
+    const parseThresholds = (value) => {
+        const list = (Array.isArray(value) ? value : [value]).map(Number);
+        for (const threshold of list) {
+            if (!(threshold >= 0 && threshold <= 1)) {
+                throw new RangeError(
+                    constructError('Threshold values must be numbers between 0 and 1'),
+                );
+            }
+        }
+        return list.length > 0 ? list.sort((a, b) => a - b) : [0];
+    };
+
+    const isImplicitRoot = (root) => root === null || root === global.document;
+
+    // The root's box grown by rootMargin, percentages resolved against the root.
+    const rootBounds = (state) => {
+        let bounds;
+        if (isImplicitRoot(state.root)) {
+            bounds = { x: 0, y: 0, width: global.innerWidth, height: global.innerHeight };
+        } else {
+            bounds = layoutRect(state.root);
+        }
+        if (!bounds) {
+            return null;
+        }
+        const resolve = (length, base) =>
+            length.unit === '%' ? (length.value * base) / 100 : length.value;
+        const [top, right, bottom, left] = state.margin;
+        const growTop = resolve(top, bounds.height);
+        const growLeft = resolve(left, bounds.width);
+        return {
+            x: bounds.x - growLeft,
+            y: bounds.y - growTop,
+            width: Math.max(0, bounds.width + growLeft + resolve(right, bounds.width)),
+            height: Math.max(0, bounds.height + growTop + resolve(bottom, bounds.height)),
+        };
+    };
+
+    const isInsideRoot = (root, target) => {
+        if (isImplicitRoot(root)) {
+            return target.isConnected;
+        }
+        let node = target.parentNode;
+        while (node) {
+            if (node === root) {
+                return true;
+            }
+            node = node.parentNode ?? (node.host || null);
+        }
+        return false;
+    };
+
+    const updateObservation = (observer, state, target, registration, time) => {
+        const bounds = rootBounds(state);
+        const targetRect = layoutRect(target);
+        let intersection = null;
+        if (bounds && targetRect && isInsideRoot(state.root, target)) {
+            const left = Math.max(targetRect.x, bounds.x);
+            const top = Math.max(targetRect.y, bounds.y);
+            const right = Math.min(targetRect.x + targetRect.width, bounds.x + bounds.width);
+            const bottom = Math.min(targetRect.y + targetRect.height, bounds.y + bounds.height);
+            // Edge-adjacent boxes intersect too, with zero area.
+            if (right >= left && bottom >= top) {
+                intersection = { x: left, y: top, width: right - left, height: bottom - top };
+            }
+        }
+        const isIntersecting = intersection !== null;
+        const box = targetRect ?? { x: 0, y: 0, width: 0, height: 0 };
+        const targetArea = box.width * box.height;
+        let ratio = 0;
+        if (isIntersecting) {
+            ratio = targetArea > 0 ? (intersection.width * intersection.height) / targetArea : 1;
+        }
+        let index = state.thresholds.findIndex((threshold) => threshold > ratio);
+        if (index === -1) {
+            index = state.thresholds.length;
+        }
+        if (index === registration.index && isIntersecting === registration.intersecting) {
+            return false;
+        }
+        registration.index = index;
+        registration.intersecting = isIntersecting;
+        const hit = intersection ?? { x: 0, y: 0, width: 0, height: 0 };
+        state.records.push(
+            Object.freeze({
+                time,
+                rootBounds: bounds
+                    ? makeRect(bounds.x, bounds.y, bounds.width, bounds.height)
+                    : null,
+                boundingClientRect: makeRect(box.x, box.y, box.width, box.height),
+                intersectionRect: makeRect(hit.x, hit.y, hit.width, hit.height),
+                isIntersecting,
+                intersectionRatio: ratio,
+                target,
+            }),
+        );
+        if (!state.scheduled) {
+            state.scheduled = true;
+            queueMicrotask(() => deliver(observer, state));
+        }
+        return true;
+    };
+
+    const deliver = (observer, state) => {
+        state.scheduled = false;
+        if (state.records.length === 0) {
+            return;
+        }
+        const records = state.records;
+        state.records = [];
+        try {
+            state.callback.call(observer, records, observer);
+        } catch (err) {
+            console.error(
+                `IntersectionObserver callback threw: ${err && err.stack ? err.stack : err}`,
+            );
+        }
+    };
+
+    class IntersectionObserver {
+        constructor(callback, options = {}) {
+            if (typeof callback !== 'function') {
+                throw new TypeError(
+                    constructError('The callback provided as parameter 1 is not a function.'),
+                );
+            }
+            const init = options ?? {};
+            const root = init.root ?? null;
+            if (root !== null && !(root instanceof global.Element) && root !== global.document) {
+                throw new TypeError(
+                    constructError("The provided value is not of type '(Document or Element)'."),
+                );
+            }
+            INTERNALS.set(this, {
+                callback,
+                root,
+                margin: parseMargin(init.rootMargin ?? '0px'),
+                thresholds: parseThresholds(init.threshold ?? 0),
+                targets: new Map(),
+                records: [],
+                scheduled: false,
+            });
+        }
+
+        get root() {
+            return internals(this).root;
+        }
+
+        get rootMargin() {
+            return internals(this)
+                .margin.map((length) => `${length.value}${length.unit}`)
+                .join(' ');
+        }
+
+        get thresholds() {
+            return Object.freeze(internals(this).thresholds.slice());
+        }
+
+        observe(target) {
+            const state = internals(this);
+            if (!(target instanceof global.Element)) {
+                throw new TypeError(
+                    "Failed to execute 'observe' on 'IntersectionObserver': " +
+                        "parameter 1 is not of type 'Element'.",
+                );
+            }
+            if (state.targets.has(target)) {
+                return;
+            }
+            state.targets.set(target, { index: -1, intersecting: false });
+            ACTIVE.add(this);
+            requestIntersectionUpdate();
+        }
+
+        unobserve(target) {
+            const state = internals(this);
+            state.targets.delete(target);
+            if (state.targets.size === 0) {
+                ACTIVE.delete(this);
+            }
+        }
+
+        disconnect() {
+            internals(this).targets.clear();
+            ACTIVE.delete(this);
+        }
+
+        takeRecords() {
+            const state = internals(this);
+            const records = state.records;
+            state.records = [];
+            return records;
+        }
+    }
+
+    Object.defineProperty(IntersectionObserver.prototype, Symbol.toStringTag, {
+        value: 'IntersectionObserver',
+        configurable: true,
+    });
+
+    global.IntersectionObserver = IntersectionObserver;
+
+    frontier.__updateIntersections = () => {
+        let queued = false;
+        const time = global.performance ? global.performance.now() : Date.now();
+        for (const observer of ACTIVE) {
+            const state = internals(observer);
+            for (const [target, registration] of state.targets) {
+                queued = updateObservation(observer, state, target, registration, time) || queued;
+            }
+        }
+        return queued;
+    };
+})();
+"#;
@@ -0,0 +1,375 @@
+//! Per-origin limits on page script runtimes.
+//!
+//! Every page gets its own QuickJS runtime, all inside the browser process. A page runtime is
+//! isolated with its origin's [`OriginQuota`] when it is created: the memory limit is one
+//! budget for all of the origin's runtimes together, so a runaway allocation fails inside the
+//! page as `InternalError: out of memory` instead of taking the browser down, however many
+//! frames or windows of the origin share the load. Each entry into a runtime's scripts (a
+//! script, an event handler, a timer callback, a promise job) gets a wall-clock budget after
+//! which QuickJS interrupts it with an error scripts cannot catch. Storage is already
+//! namespaced the same way: pages use the `localStorage` area of their origin (see
+//! [`LocalStorage::area`]).
+//!
+//! Runtimes take turns on the main thread, so each one's heap limit is set when a script is
+//! entered to what the origin's other heaps, as of their last entry, leave over. Big
+//! allocations Rust makes for a page, canvas bitmaps and `fetch()` bodies, are charged to the
+//! same budget through [`NativeMemory`]; the rest of the DOM is not.
+//!
+//! [`Isolation::shared`] tallies what each origin's runtimes ran into for `about:memory`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::storage::LocalStorage;
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+pub const DEFAULT_SCRIPT_BUDGET: Duration = Duration::from_secs(10);
+
+/// Origin key for documents whose origin is opaque, such as `data:` URLs.
+const OPAQUE_ORIGIN: &str = "null";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OriginQuota {
+    /// Most bytes the origin's runtime heaps and native allocations may hold together.
+    pub memory_limit: usize,
+    /// Longest one entry into the runtime's scripts may run.
+    pub script_budget: Duration,
+}
+
+impl Default for OriginQuota {
+    fn default() -> Self {
+        Self {
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            script_budget: DEFAULT_SCRIPT_BUDGET,
+        }
+    }
+}
+
+/// What one origin's runtimes were allowed and what they ran into.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OriginUsage {
+    pub origin: String,
+    /// Runtimes of the origin alive now.
+    pub runtimes: usize,
+    pub memory_limit_bytes: u64,
+    pub script_budget_ms: u64,
+    /// `localStorage` area the origin's pages use; `None` when they have none.
+    pub storage_area: Option<String>,
+    /// Scripts stopped for running past the budget.
+    pub interrupted_scripts: u64,
+    /// Scripts that ended with the heap limit reached.
+    pub out_of_memory: u64,
+}
+
+/// Memory one origin's runtimes hold, by lease: each runtime's heap as of its last entry into
+/// scripts, and what Rust allocated for it.
+#[derive(Debug, Default)]
+struct OriginMemory {
+    heaps: HashMap<u64, usize>,
+    native: HashMap<u64, usize>,
+}
+
+impl OriginMemory {
+    fn used(&self) -> usize {
+        self.heaps.values().sum::<usize>() + self.native.values().sum::<usize>()
+    }
+}
+
+#[derive(Debug, Default)]
+struct OriginState {
+    usage: OriginUsage,
+    memory: OriginMemory,
+}
+
+#[derive(Debug, Default)]
+pub struct Isolation {
+    origins: Mutex<BTreeMap<String, OriginState>>,
+    next_lease: AtomicU64,
+}
+
+impl Isolation {
+    pub fn shared() -> &'static Isolation {
+        static ISOLATION: OnceLock<Isolation> = OnceLock::new();
+        ISOLATION.get_or_init(Isolation::default)
+    }
+
+    /// Count a runtime for the origin of `document_url` under `quota` until the returned lease
+    /// is dropped.
+    pub fn admit(&self, document_url: Option<&Url>, quota: OriginQuota) -> OriginLease<'_> {
+        let storage_area = document_url.and_then(LocalStorage::area);
+        let origin = storage_area
+            .clone()
+            .unwrap_or_else(|| OPAQUE_ORIGIN.to_string());
+        let id = self.next_lease.fetch_add(1, Ordering::Relaxed);
+        let mut origins = self.origins.lock().unwrap();
+        let state = origins
+            .entry(origin.clone())
+            .or_insert_with(|| OriginState {
+                usage: OriginUsage {
+                    origin: origin.clone(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        let usage = &mut state.usage;
+        usage.runtimes += 1;
+        usage.memory_limit_bytes = quota.memory_limit as u64;
+        usage.script_budget_ms = quota.script_budget.as_millis() as u64;
+        usage.storage_area = storage_area;
+        state.memory.heaps.insert(id, 0);
+        OriginLease {
+            isolation: self,
+            origin,
+            id,
+        }
+    }
+
+    /// Every origin that has had a runtime, alive or not, by origin.
+    pub fn usage(&self) -> Vec<OriginUsage> {
+        let origins = self.origins.lock().unwrap();
+        origins.values().map(|state| state.usage.clone()).collect()
+    }
+
+    fn update<T>(&self, origin: &str, update: impl FnOnce(&mut OriginState) -> T) -> Option<T> {
+        self.origins.lock().unwrap().get_mut(origin).map(update)
+    }
+}
+
+/// One runtime counted against an origin by [`Isolation::admit`].
+#[derive(Debug)]
+pub struct OriginLease<'a> {
+    isolation: &'a Isolation,
+    origin: String,
+    id: u64,
+}
+
+impl<'a> OriginLease<'a> {
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn record_interrupt(&self) {
+        self.isolation
+            .update(&self.origin, |state| state.usage.interrupted_scripts += 1);
+    }
+
+    pub fn record_out_of_memory(&self) {
+        self.isolation
+            .update(&self.origin, |state| state.usage.out_of_memory += 1);
+    }
+
+    /// The most the runtime's heap, now `heap` bytes, may grow to: the origin's limit less
+    /// what its other runtimes and native allocations hold, and never less than `heap`.
+    pub fn heap_limit(&self, heap: usize) -> usize {
+        self.isolation
+            .update(&self.origin, |state| {
+                let memory = &mut state.memory;
+                memory.heaps.insert(self.id, heap);
+                let others = memory.used() - heap;
+                (state.usage.memory_limit_bytes as usize)
+                    .saturating_sub(others)
+                    .max(heap)
+            })
+            .unwrap_or(heap)
+    }
+
+    /// Note that the runtime's heap holds `heap` bytes after a script returned.
+    pub fn record_heap(&self, heap: usize) {
+        self.isolation.update(&self.origin, |state| {
+            state.memory.heaps.insert(self.id, heap);
+        });
+    }
+
+    /// A handle that charges what Rust allocates for the runtime to the origin's budget.
+    pub fn native_memory(&self) -> NativeMemory<'a> {
+        NativeMemory {
+            isolation: self.isolation,
+            origin: self.origin.clone(),
+            lease: self.id,
+        }
+    }
+}
+
+impl Drop for OriginLease<'_> {
+    fn drop(&mut self) {
+        self.isolation.update(&self.origin, |state| {
+            state.usage.runtimes = state.usage.runtimes.saturating_sub(1);
+            state.memory.heaps.remove(&self.id);
+            state.memory.native.remove(&self.id);
+        });
+    }
+}
+
+/// Charges native allocations for one runtime, from [`OriginLease::native_memory`]. It can be
+/// sent to the threads that read network responses; once the runtime is gone every charge
+/// is refused.
+#[derive(Debug, Clone)]
+pub struct NativeMemory<'a> {
+    isolation: &'a Isolation,
+    origin: String,
+    lease: u64,
+}
+
+impl<'a> NativeMemory<'a> {
+    /// Count `bytes` against the origin's budget. Returns false, charging nothing, when they
+    /// do not fit.
+    pub fn charge(&self, bytes: usize) -> bool {
+        self.isolation
+            .update(&self.origin, |state| {
+                let limit = state.usage.memory_limit_bytes as usize;
+                let memory = &mut state.memory;
+                if !memory.heaps.contains_key(&self.lease)
+                    || memory.used().saturating_add(bytes) > limit
+                {
+                    return false;
+                }
+                *memory.native.entry(self.lease).or_default() += bytes;
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Bytes [`Self::charge`] would still accept; none once the runtime is gone.
+    pub fn available(&self) -> usize {
+        self.isolation
+            .update(&self.origin, |state| {
+                let limit = state.usage.memory_limit_bytes as usize;
+                let memory = &state.memory;
+                if !memory.heaps.contains_key(&self.lease) {
+                    return 0;
+                }
+                limit.saturating_sub(memory.used())
+            })
+            .unwrap_or(0)
+    }
+
+    /// Give back `bytes` of an earlier [`Self::charge`].
+    pub fn release(&self, bytes: usize) {
+        self.isolation.update(&self.origin, |state| {
+            if let Some(native) = state.memory.native.get_mut(&self.lease) {
+                *native = native.saturating_sub(bytes);
+            }
+        });
+    }
+
+    /// Charge `bytes` until the returned reservation is dropped, if they fit.
+    pub fn reserve(&self, bytes: usize) -> Option<Reservation<'a>> {
+        self.charge(bytes).then(|| Reservation {
+            memory: self.clone(),
+            bytes,
+        })
+    }
+}
+
+/// Bytes charged by [`NativeMemory::reserve`], given back when dropped.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    memory: NativeMemory<'a>,
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    /// Charge `bytes` more. Returns false, charging nothing, when they do not fit.
+    pub fn grow(&mut self, bytes: usize) -> bool {
+        let fits = self.memory.charge(bytes);
+        if fits {
+            self.bytes += bytes;
+        }
+        fits
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.memory.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies_runtimes_and_failures_per_origin() {
+        let isolation = Isolation::default();
+        let quota = OriginQuota {
+            memory_limit: 1024,
+            script_budget: Duration::from_millis(250),
+        };
+        let page = Url::parse("https://a.example/page").unwrap();
+        let other = Url::parse("https://a.example:8443/").unwrap();
+        let data = Url::parse("data:text/html,hi").unwrap();
+
+        let first = isolation.admit(Some(&page), quota);
+        let second = isolation.admit(Some(&page), quota);
+        let elsewhere = isolation.admit(Some(&other), OriginQuota::default());
+        let opaque = isolation.admit(Some(&data), quota);
+        first.record_interrupt();
+        second.record_out_of_memory();
+        drop(second);
+
+        let usage = isolation.usage();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(
+            usage[0],
+            OriginUsage {
+                origin: "https://a.example".to_string(),
+                runtimes: 1,
+                memory_limit_bytes: 1024,
+                script_budget_ms: 250,
+                storage_area: Some("https://a.example".to_string()),
+                interrupted_scripts: 1,
+                out_of_memory: 1,
+            }
+        );
+        assert_eq!(usage[1].origin, "https://a.example:8443");
+        assert_eq!(usage[1].memory_limit_bytes, DEFAULT_MEMORY_LIMIT as u64);
+        assert_eq!(usage[2].origin, OPAQUE_ORIGIN);
+        assert_eq!(usage[2].storage_area, None);
+
+        drop((first, elsewhere, opaque));
+        assert!(isolation.usage().iter().all(|usage| usage.runtimes == 0));
+        assert_eq!(isolation.usage()[0].interrupted_scripts, 1);
+    }
+
+    #[test]
+    fn an_origins_runtimes_and_native_allocations_share_one_budget() {
+        let isolation = Isolation::default();
+        let quota = OriginQuota {
+            memory_limit: 1000,
+            ..Default::default()
+        };
+        let page = Url::parse("https://shared.example/").unwrap();
+        let frame = Url::parse("https://shared.example/frame").unwrap();
+        let other = Url::parse("https://other.example/").unwrap();
+
+        let first = isolation.admit(Some(&page), quota);
+        let second = isolation.admit(Some(&frame), quota);
+        let elsewhere = isolation.admit(Some(&other), quota);
+        assert_eq!(first.heap_limit(100), 1000);
+        first.record_heap(600);
+        assert_eq!(second.heap_limit(50), 400);
+        assert_eq!(elsewhere.heap_limit(50), 1000);
+
+        let memory = second.native_memory();
+        assert_eq!(memory.available(), 400);
+        assert!(!memory.charge(400));
+        let mut body = memory.reserve(200).unwrap();
+        assert!(!body.grow(200));
+        assert_eq!(first.heap_limit(600), 750);
+        drop(body);
+        assert_eq!(first.heap_limit(600), 950);
+
+        // What a runtime held goes back to the origin with it.
+        assert!(memory.charge(300));
+        drop(second);
+        assert_eq!(first.heap_limit(600), 1000);
+        assert!(!memory.charge(1));
+        assert_eq!(memory.available(), 0);
+    }
+}
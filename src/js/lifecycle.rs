@@ -0,0 +1,152 @@
+//! Page lifecycle events (`DOMContentLoaded`, `load`, `beforeunload`, `unload`,
+//! `visibilitychange`) and the `document.readyState` transitions that go with them.
+//!
+//! The host decides when each milestone is reached and calls
+//! [`JsDomEnvironment::dispatch_lifecycle`](super::environment::JsDomEnvironment::dispatch_lifecycle);
+//! the bootstrap below turns that into DOM events on `document` or `window`, including the
+//! `window.on*` handler properties pages commonly assign.
+
+/// Lifecycle milestone reported by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// Parsing and blocking scripts are done; `readyState` becomes `interactive` before
+    /// deferred scripts run.
+    Interactive,
+    /// Deferred scripts have run.
+    DomContentLoaded,
+    /// Subresources requested while loading have settled; `readyState` becomes `complete`.
+    Load,
+    /// The user is about to leave; the page may ask for confirmation.
+    BeforeUnload,
+    /// The page is being torn down.
+    Unload,
+    /// The window was hidden (minimised or occluded) or shown again.
+    VisibilityChange { visible: bool },
+}
+
+impl LifecycleEvent {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            LifecycleEvent::Interactive => "interactive",
+            LifecycleEvent::DomContentLoaded => "DOMContentLoaded",
+            LifecycleEvent::Load => "load",
+            LifecycleEvent::BeforeUnload => "beforeunload",
+            LifecycleEvent::Unload => "unload",
+            LifecycleEvent::VisibilityChange { .. } => "visibilitychange",
+        }
+    }
+
+    pub(crate) fn argument(self) -> Option<&'static str> {
+        match self {
+            LifecycleEvent::VisibilityChange { visible: true } => Some("visible"),
+            LifecycleEvent::VisibilityChange { visible: false } => Some("hidden"),
+            _ => None,
+        }
+    }
+}
+
+/// JS half of the lifecycle: `frontier.__fireLifecycle(name, arg)` returns `true` when a
+/// `beforeunload` listener asked the user to confirm leaving.
+pub(crate) const LIFECYCLE_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    let visibilityState = 'visible';
+    let readyState = 'loading';
+
+    if (global.Document && global.Document.prototype) {
+        Object.defineProperty(global.Document.prototype, 'readyState', {
+            get: () => readyState,
+            configurable: true,
+        });
+        Object.defineProperty(global.Document.prototype, 'visibilityState', {
+            get: () => visibilityState,
+            configurable: true,
+        });
+        Object.defineProperty(global.Document.prototype, 'hidden', {
+            get: () => visibilityState === 'hidden',
+            configurable: true,
+        });
+    }
+
+    const makeEvent = (type, init) => {
+        if (typeof global.Event === 'function') {
+            return new global.Event(type, init);
+        }
+        return { type, bubbles: !!init.bubbles, cancelable: !!init.cancelable, defaultPrevented: false };
+    };
+
+    const callHandlerProperty = (target, type, event) => {
+        const handler = target && target['on' + type];
+        if (typeof handler !== 'function') {
+            return undefined;
+        }
+        try {
+            return handler.call(target, event);
+        } catch (err) {
+            console.error(`on${type} handler threw: ${err && err.stack ? err.stack : err}`);
+            return undefined;
+        }
+    };
+
+    const fire = (target, type, init) => {
+        if (!target || typeof target.dispatchEvent !== 'function') {
+            return { event: null, handlerResult: undefined };
+        }
+        const event = makeEvent(type, init);
+        target.dispatchEvent(event);
+        const handlerResult = callHandlerProperty(target, type, event);
+        return { event, handlerResult };
+    };
+
+    const advanceReadyState = (state) => {
+        const order = ['loading', 'interactive', 'complete'];
+        if (order.indexOf(state) <= order.indexOf(readyState)) {
+            return;
+        }
+        readyState = state;
+        fire(global.document, 'readystatechange', {});
+    };
+
+    frontier.__fireLifecycle = (name, arg) => {
+        switch (name) {
+            case 'interactive':
+                advanceReadyState('interactive');
+                return false;
+            case 'DOMContentLoaded':
+                advanceReadyState('interactive');
+                fire(global.document, 'DOMContentLoaded', { bubbles: true });
+                return false;
+            case 'load':
+                advanceReadyState('complete');
+                fire(global, 'load', {});
+                return false;
+            case 'beforeunload': {
+                const { event, handlerResult } = fire(global, 'beforeunload', { cancelable: true });
+                if (!event) {
+                    return false;
+                }
+                const returnValue = event.returnValue;
+                return (
+                    event.defaultPrevented === true ||
+                    (typeof returnValue === 'string' && returnValue.length > 0) ||
+                    (typeof handlerResult === 'string' && handlerResult.length > 0)
+                );
+            }
+            case 'unload':
+                fire(global, 'pagehide', {});
+                fire(global, 'unload', {});
+                return false;
+            case 'visibilitychange':
+                if (arg === visibilityState) {
+                    return false;
+                }
+                visibilityState = arg === 'hidden' ? 'hidden' : 'visible';
+                fire(global.document, 'visibilitychange', { bubbles: true });
+                return false;
+            default:
+                return false;
+        }
+    };
+})();
+"#;
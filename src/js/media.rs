@@ -0,0 +1,299 @@
+//! User media preferences (`prefers-color-scheme`, `prefers-reduced-motion`) and
+//! `window.matchMedia`.
+//!
+//! Stylesheets already follow the window theme through blitz. Page script learns about it
+//! here: the host pushes [`MediaPreferences`] through
+//! [`JsDomEnvironment::set_media_preferences`](super::environment::JsDomEnvironment::set_media_preferences)
+//! when a document is attached, when the OS theme changes and when the user toggles the theme,
+//! and every live `MediaQueryList` whose result flipped receives a `change` event. Queries on
+//! the viewport (`width`, `height`, `aspect-ratio`, `orientation`, `resolution`) read the
+//! metrics from [`viewport`](super::viewport) and are evaluated again whenever the window is
+//! resized or its scale factor changes.
+
+use serde::{Deserialize, Serialize};
+
+/// Effective colour scheme of the window, including the user's override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MediaPreferences {
+    pub color_scheme: ColorScheme,
+    /// The user asked for less motion; smooth scrolling and other animated effects are skipped.
+    pub reduced_motion: bool,
+}
+
+impl MediaPreferences {
+    /// winit does not report the OS motion setting, so it comes from
+    /// `FRONTIER_REDUCED_MOTION=1`.
+    pub fn reduced_motion_from_env() -> bool {
+        std::env::var("FRONTIER_REDUCED_MOTION")
+            .map(|value| matches!(value.trim(), "1" | "true" | "yes" | "reduce"))
+            .unwrap_or(false)
+    }
+}
+
+/// JS half: `window.matchMedia` plus `frontier.__setMediaPreferences(scheme, reducedMotion)`
+/// and `frontier.__refreshMediaQueries()`, which the viewport bootstrap calls after a resize.
+/// Queries are parsed once; unknown features never match, as in browsers. Range features take
+/// `min-`/`max-` prefixes as well as range syntax such as `(400px <= width < 800px)`.
+pub(crate) const MEDIA_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    const env = {
+        colorScheme: 'light',
+        reducedMotion: false,
+    };
+
+    const FEATURES = {
+        'prefers-color-scheme': (value) => value === undefined || value === env.colorScheme,
+        'prefers-reduced-motion': (value) => {
+            if (value === undefined) {
+                return env.reducedMotion;
+            }
+            return value === (env.reducedMotion ? 'reduce' : 'no-preference');
+        },
+        orientation: (value) => {
+            const current = global.innerHeight >= global.innerWidth ? 'portrait' : 'landscape';
+            return value === undefined || value === current;
+        },
+    };
+
+    // `em` and `rem` are the initial font size, as media queries never see page styles.
+    const parseLength = (text) => {
+        const match = /^(-?\d*\.?\d+)(px|em|rem)?$/.exec(text);
+        if (!match || (match[2] === undefined && Number(match[1]) !== 0)) {
+            return NaN;
+        }
+        return Number(match[1]) * (match[2] === 'em' || match[2] === 'rem' ? 16 : 1);
+    };
+
+    const parseRatio = (text) => {
+        const match = /^(\d*\.?\d+)\s*(?:\/\s*(\d*\.?\d+))?$/.exec(text);
+        return match ? Number(match[1]) / Number(match[2] ?? 1) : NaN;
+    };
+
+    const RESOLUTION_UNITS = { dppx: 1, x: 1, dpi: 1 / 96, dpcm: 2.54 / 96 };
+    const parseResolution = (text) => {
+        const match = /^(\d*\.?\d+)(dppx|x|dpi|dpcm)$/.exec(text);
+        return match ? Number(match[1]) * RESOLUTION_UNITS[match[2]] : NaN;
+    };
+
+    const parseNumber = (text) => (/^\d*\.?\d+$/.test(text) ? Number(text) : NaN);
+
+    // Features compared against a value: what the window has now, and how to read a value.
+    const RANGE_FEATURES = {
+        width: { read: () => global.innerWidth, parse: parseLength },
+        height: { read: () => global.innerHeight, parse: parseLength },
+        'aspect-ratio': {
+            read: () => global.innerWidth / Math.max(1, global.innerHeight),
+            parse: parseRatio,
+        },
+        resolution: { read: () => global.devicePixelRatio, parse: parseResolution },
+        '-webkit-device-pixel-ratio': { read: () => global.devicePixelRatio, parse: parseNumber },
+    };
+
+    const COMPARE = {
+        '<': (a, b) => a < b,
+        '<=': (a, b) => a <= b + 1e-9,
+        '>': (a, b) => a > b,
+        '>=': (a, b) => a + 1e-9 >= b,
+        '=': (a, b) => Math.abs(a - b) < 1e-9,
+    };
+    const FLIPPED = { '<': '>', '<=': '>=', '>': '<', '>=': '<=', '=': '=' };
+
+    // `(name)`, `(name: value)`, `(min-name: value)` or range syntax, as
+    // `{ name, value }` for discrete features and `{ name, comparisons }` for range ones.
+    const parseCondition = (part) => {
+        const inner = /^\((.*)\)$/.exec(part);
+        if (!inner) {
+            return null;
+        }
+        const body = inner[1].trim();
+        const plain = /^([a-z-]+)(?:\s*:\s*(.+))?$/.exec(body);
+        if (plain) {
+            const [, name, value] = plain;
+            // WebKit puts the prefix first: `-webkit-min-device-pixel-ratio`.
+            const prefix = /^(-webkit-)?(min|max)-(.+)$/.exec(name);
+            const base = prefix && (prefix[1] ?? '') + prefix[3];
+            if (prefix && RANGE_FEATURES[base] && value !== undefined) {
+                const op = prefix[2] === 'min' ? '>=' : '<=';
+                return { name: base, comparisons: [[op, value.trim()]] };
+            }
+            if (RANGE_FEATURES[name]) {
+                return { name, comparisons: value === undefined ? [] : [['=', value.trim()]] };
+            }
+            return { name, value: value && value.trim() };
+        }
+        const tokens = body.split(/\s*(<=|>=|<|>|=)\s*/);
+        if (tokens.length === 3) {
+            const [left, op, right] = tokens;
+            if (RANGE_FEATURES[left]) {
+                return { name: left, comparisons: [[op, right]] };
+            }
+            if (RANGE_FEATURES[right]) {
+                return { name: right, comparisons: [[FLIPPED[op], left]] };
+            }
+        } else if (tokens.length === 5 && RANGE_FEATURES[tokens[2]]) {
+            const [low, lowOp, name, highOp, high] = tokens;
+            return { name, comparisons: [[FLIPPED[lowOp], low], [highOp, high]] };
+        }
+        return null;
+    };
+
+    const evaluateCondition = (condition) => {
+        const range = RANGE_FEATURES[condition.name];
+        if (range && condition.comparisons) {
+            const actual = range.read();
+            if (condition.comparisons.length === 0) {
+                return actual !== 0;
+            }
+            return condition.comparisons.every(([op, text]) => {
+                const expected = range.parse(text);
+                return !Number.isNaN(expected) && COMPARE[op](actual, expected);
+            });
+        }
+        const feature = FEATURES[condition.name];
+        return typeof feature === 'function' && feature(condition.value) === true;
+    };
+
+    const MEDIA_TYPES = new Set(['all', 'screen']);
+
+    // One comma-separated alternative: optional `not`/`only`, optional media type, then
+    // `and`-joined `(feature: value)` conditions.
+    const parseAlternative = (text) => {
+        let rest = text.trim().toLowerCase();
+        let negated = false;
+        if (rest.startsWith('not ')) {
+            negated = true;
+            rest = rest.slice(4).trim();
+        } else if (rest.startsWith('only ')) {
+            rest = rest.slice(5).trim();
+        }
+        const parts = rest.split(/\s+and\s+/).map((part) => part.trim()).filter(Boolean);
+        const conditions = [];
+        let mediaType = 'all';
+        for (const part of parts) {
+            const condition = parseCondition(part);
+            if (condition) {
+                conditions.push(condition);
+            } else if (/^[a-z]+$/.test(part) && conditions.length === 0) {
+                mediaType = part;
+            } else {
+                return { negated, invalid: true };
+            }
+        }
+        return { negated, mediaType, conditions, invalid: false };
+    };
+
+    const evaluateAlternative = (alt) => {
+        if (alt.invalid) {
+            // Unparseable queries are `not all`.
+            return false;
+        }
+        const result = MEDIA_TYPES.has(alt.mediaType) && alt.conditions.every(evaluateCondition);
+        return alt.negated ? !result : result;
+    };
+
+    const evaluate = (alternatives) =>
+        alternatives.length === 0 || alternatives.some(evaluateAlternative);
+
+    const liveLists = [];
+
+    class MediaQueryList {
+        constructor(media) {
+            this.media = media;
+            this._alternatives = media.trim() === '' ? [] : media.split(',').map(parseAlternative);
+            this._matches = evaluate(this._alternatives);
+            this._listeners = [];
+            this.onchange = null;
+        }
+
+        get matches() {
+            return this._matches;
+        }
+
+        addEventListener(type, listener) {
+            if (type !== 'change' || typeof listener !== 'function') {
+                return;
+            }
+            if (!this._listeners.includes(listener)) {
+                this._listeners.push(listener);
+            }
+        }
+
+        removeEventListener(type, listener) {
+            if (type !== 'change') {
+                return;
+            }
+            this._listeners = this._listeners.filter((entry) => entry !== listener);
+        }
+
+        addListener(listener) {
+            this.addEventListener('change', listener);
+        }
+
+        removeListener(listener) {
+            this.removeEventListener('change', listener);
+        }
+
+        dispatchEvent(event) {
+            const handlers = this._listeners.slice();
+            if (typeof this.onchange === 'function') {
+                handlers.push(this.onchange);
+            }
+            for (const handler of handlers) {
+                try {
+                    handler.call(this, event);
+                } catch (err) {
+                    console.error(`media query listener threw: ${err && err.stack ? err.stack : err}`);
+                }
+            }
+            return true;
+        }
+
+        _refresh() {
+            const next = evaluate(this._alternatives);
+            if (next === this._matches) {
+                return;
+            }
+            this._matches = next;
+            this.dispatchEvent({ type: 'change', media: this.media, matches: next, target: this });
+        }
+    }
+
+    global.MediaQueryList = MediaQueryList;
+    global.matchMedia = (query) => {
+        const list = new MediaQueryList(String(query));
+        liveLists.push(list);
+        return list;
+    };
+
+    frontier.__refreshMediaQueries = () => {
+        for (const list of liveLists.slice()) {
+            list._refresh();
+        }
+    };
+
+    frontier.__setMediaPreferences = (colorScheme, reducedMotion) => {
+        env.colorScheme = colorScheme === 'dark' ? 'dark' : 'light';
+        env.reducedMotion = reducedMotion === true;
+        frontier.__refreshMediaQueries();
+    };
+})();
+"#;
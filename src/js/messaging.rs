@@ -0,0 +1,232 @@
+//! `postMessage` between a page and the documents in its iframes.
+//!
+//! Each document only sees its own side: `window.postMessage`, `window.parent.postMessage`
+//! and `iframe.contentWindow.postMessage` queue a [`PostedMessage`] on the sending
+//! environment, and the host (see [`crate::frames`]) checks `targetOrigin` and hands it to
+//! the receiving environment with
+//! [`JsDomEnvironment::deliver_message`](super::environment::JsDomEnvironment::deliver_message).
+//! Message data crosses documents as JSON, with values JSON cannot represent wrapped in the
+//! tagged encoding of [`super::structured_clone`]. Popups opened with `window.open` use the same
+//! queue with [`WindowRef::Popup`] and [`WindowRef::Opener`] targets (see [`crate::popups`]).
+
+use url::{Origin, Url};
+
+/// A window as one document refers to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowRef {
+    /// The document's own window.
+    Own,
+    /// The window of the document embedding this one.
+    Parent,
+    /// The window of the iframe with this node id in the referring document.
+    Frame(usize),
+    /// The popup this document opened with `window.open`, by popup id.
+    Popup(u32),
+    /// The window that opened this document as a popup.
+    Opener,
+}
+
+impl WindowRef {
+    pub(crate) fn parse(raw: &str) -> Result<Self, String> {
+        match raw {
+            "self" => Ok(Self::Own),
+            "parent" => Ok(Self::Parent),
+            "opener" => Ok(Self::Opener),
+            popup if popup.starts_with("popup:") => popup["popup:".len()..]
+                .parse()
+                .map(Self::Popup)
+                .map_err(|_| format!("'{popup}' is not a window")),
+            handle => handle
+                .parse()
+                .map(Self::Frame)
+                .map_err(|_| format!("'{handle}' is not a window")),
+        }
+    }
+
+    pub(crate) fn to_js(self) -> String {
+        match self {
+            Self::Own => "self".to_string(),
+            Self::Parent => "parent".to_string(),
+            Self::Frame(node_id) => node_id.to_string(),
+            Self::Popup(id) => format!("popup:{id}"),
+            Self::Opener => "opener".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostedMessage {
+    pub target: WindowRef,
+    /// The message as JSON, or as a `$frontierClone` record when plain JSON would lose
+    /// part of it.
+    pub data: String,
+    pub target_origin: String,
+}
+
+/// Whether a message `sender` posted with `target_origin` may reach a window of `receiver`:
+/// `*` matches anything, `/` the sender's own origin, anything else must parse as a URL
+/// with the receiver's origin.
+pub fn target_origin_allows(target_origin: &str, sender: &Origin, receiver: &Origin) -> bool {
+    match target_origin {
+        "*" => true,
+        "/" => sender == receiver,
+        other => {
+            Url::parse(other).is_ok_and(|url| receiver.is_tuple() && url.origin() == *receiver)
+        }
+    }
+}
+
+/// JS half: `window.postMessage`, `window.parent`/`window.top`/`window.opener`,
+/// `HTMLIFrameElement.contentWindow` and the `message` event, plus `frontier.__setFramed(bool)`,
+/// `frontier.__setOpened(bool)`, `frontier.__setFrameLoaded(handle, bool)` and
+/// `frontier.__deliverMessage(data, origin, source)` for the host. `frontier.__postMessage` and
+/// `frontier.__registerWindow` let the `window.open` bootstrap share the message path.
+pub(crate) const MESSAGING_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const nativePostMessage = global.__frontier_post_message;
+    const frontier = global.frontier;
+    const proxies = new Map();
+    const loadedFrames = new Set();
+    let framed = false;
+    let opened = false;
+
+    const post = (target, message, options, transfer) => {
+        let targetOrigin = options;
+        if (options !== null && typeof options === 'object') {
+            targetOrigin = options.targetOrigin;
+            transfer = options.transfer;
+        }
+        if (targetOrigin === undefined) {
+            targetOrigin = '/';
+        }
+        const data = frontier.__serializeMessage(message, transfer);
+        nativePostMessage(target, data, String(targetOrigin));
+    };
+
+    const windowProxy = (target) => {
+        let proxy = proxies.get(target);
+        if (!proxy) {
+            proxy = Object.freeze({
+                postMessage: (message, options, transfer) =>
+                    post(target, message, options, transfer),
+                get closed() {
+                    return false;
+                },
+            });
+            proxies.set(target, proxy);
+        }
+        return proxy;
+    };
+
+    global.postMessage = (message, options, transfer) => post('self', message, options, transfer);
+
+    const parentWindow = () => (framed ? windowProxy('parent') : global);
+    Object.defineProperty(global, 'parent', { get: parentWindow, configurable: true });
+    Object.defineProperty(global, 'top', { get: parentWindow, configurable: true });
+    Object.defineProperty(global, 'frameElement', { get: () => null, configurable: true });
+    Object.defineProperty(global, 'opener', {
+        get: () => (opened ? windowProxy('opener') : null),
+        configurable: true,
+    });
+
+    if (global.Element && global.Element.prototype) {
+        const proto = global.Element.prototype;
+        Object.defineProperty(proto, 'contentWindow', {
+            get() {
+                if (this.localName !== 'iframe') {
+                    return undefined;
+                }
+                const handle = frontier.__handleOf(this);
+                return handle !== null && loadedFrames.has(handle) ? windowProxy(handle) : null;
+            },
+            configurable: true,
+        });
+        Object.defineProperty(proto, 'contentDocument', {
+            get() {
+                return this.localName === 'iframe' ? null : undefined;
+            },
+            configurable: true,
+        });
+    }
+
+    frontier.__setFramed = (value) => {
+        framed = !!value;
+    };
+
+    frontier.__setOpened = (value) => {
+        opened = !!value;
+    };
+
+    frontier.__postMessage = post;
+
+    frontier.__registerWindow = (target, proxy) => {
+        proxies.set(target, proxy);
+    };
+
+    frontier.__setFrameLoaded = (handle, loaded) => {
+        if (loaded) {
+            loadedFrames.add(String(handle));
+        } else {
+            loadedFrames.delete(String(handle));
+        }
+    };
+
+    frontier.__deliverMessage = (data, origin, source) => {
+        const event = new global.MessageEvent('message', {
+            data: frontier.__deserializeMessage(data),
+            origin,
+            source: source === 'self' ? global : windowProxy(source),
+        });
+        global.dispatchEvent(event);
+        if (typeof global.onmessage === 'function') {
+            try {
+                global.onmessage.call(global, event);
+            } catch (err) {
+                console.error(`onmessage handler threw: ${err && err.stack ? err.stack : err}`);
+            }
+        }
+    };
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_origin_must_match_the_receiver() {
+        let parent = Url::parse("https://example.com/page").unwrap().origin();
+        let child = Url::parse("https://widgets.example.net/frame")
+            .unwrap()
+            .origin();
+
+        assert!(target_origin_allows("*", &parent, &child));
+        assert!(target_origin_allows("/", &parent, &parent));
+        assert!(!target_origin_allows("/", &parent, &child));
+        assert!(target_origin_allows(
+            "https://widgets.example.net",
+            &parent,
+            &child
+        ));
+        assert!(!target_origin_allows(
+            "https://widgets.example.net:8443",
+            &parent,
+            &child
+        ));
+        assert!(!target_origin_allows("not a url", &parent, &child));
+
+        let opaque = Url::parse("about:blank").unwrap().origin();
+        assert!(!target_origin_allows("null", &parent, &opaque));
+        assert_eq!(WindowRef::parse("42"), Ok(WindowRef::Frame(42)));
+        assert_eq!(
+            WindowRef::parse(&WindowRef::Parent.to_js()),
+            Ok(WindowRef::Parent)
+        );
+        assert_eq!(
+            WindowRef::parse(&WindowRef::Popup(3).to_js()),
+            Ok(WindowRef::Popup(3))
+        );
+        assert!(WindowRef::parse("popup:x").is_err());
+    }
+}
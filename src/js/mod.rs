@@ -1,9 +1,42 @@
+pub mod blob;
 pub mod bridge;
+pub mod bytecode_cache;
+pub mod canvas;
+pub mod clock;
+pub mod console;
+pub mod coverage;
+pub mod cssom;
+pub mod custom_elements;
 pub mod dom;
+pub mod drag_drop;
+pub mod dynamic_script;
+pub mod dynamic_style;
 pub mod environment;
+pub mod eval_policy;
 pub mod events;
+pub mod fetch;
+pub mod file_input;
+pub mod form_controls;
+pub mod gestures;
+pub mod intersection;
+pub mod isolation;
+pub mod lifecycle;
+pub mod media;
+pub mod messaging;
+pub mod navigator;
+pub mod nostr;
+pub mod p2p;
+pub mod performance;
 pub mod processor;
 pub mod runtime;
 pub mod runtime_document;
 pub mod script;
+pub mod scroll;
 pub mod session;
+pub mod shadow;
+pub mod sourcemap;
+pub mod storage;
+pub mod structured_clone;
+pub mod teardown;
+pub mod viewport;
+pub mod window_open;
@@ -0,0 +1,85 @@
+//! `navigator`: the user agent string and the few other facts pages commonly read before
+//! deciding what to render.
+//!
+//! `userAgent` and `appVersion` report the `User-Agent` the page's `fetch()` sends (see
+//! [`FetchManager::user_agent`]), so an automation session's emulated user agent shows up
+//! without reloading the page. Feature objects such as `navigator.gpu` or
+//! `navigator.serviceWorker` are left undefined, which is how pages detect they are missing.
+
+use std::rc::Rc;
+
+use rquickjs::{Ctx, Function};
+
+use super::fetch::FetchManager;
+
+pub(crate) fn install_navigator_bindings(
+    ctx: &Ctx<'_>,
+    fetches: Rc<FetchManager>,
+) -> rquickjs::Result<()> {
+    let func = Function::new(ctx.clone(), move || fetches.user_agent())?
+        .with_name("__frontier_user_agent")?;
+    ctx.globals().set("__frontier_user_agent", func)?;
+    Ok(())
+}
+
+pub(crate) const NAVIGATOR_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const nativeUserAgent = global.__frontier_user_agent;
+    const userAgent = () => nativeUserAgent();
+    const platform = () => {
+        const agent = userAgent();
+        if (agent.includes('Mac OS X')) {
+            return 'MacIntel';
+        }
+        if (agent.includes('Windows')) {
+            return 'Win32';
+        }
+        return agent.includes('Android') ? 'Linux armv8l' : 'Linux x86_64';
+    };
+
+    class Navigator {
+        get userAgent() {
+            return userAgent();
+        }
+        get appVersion() {
+            return userAgent().replace(/^Mozilla\//, '');
+        }
+        get appName() {
+            return 'Netscape';
+        }
+        get appCodeName() {
+            return 'Mozilla';
+        }
+        get product() {
+            return 'Gecko';
+        }
+        get platform() {
+            return platform();
+        }
+        get vendor() {
+            return '';
+        }
+        get language() {
+            return 'en-US';
+        }
+        get languages() {
+            return ['en-US', 'en'];
+        }
+        get onLine() {
+            return true;
+        }
+        get cookieEnabled() {
+            return true;
+        }
+        get hardwareConcurrency() {
+            return 4;
+        }
+        get maxTouchPoints() {
+            return 0;
+        }
+    }
+    global.Navigator = Navigator;
+    global.navigator = new Navigator();
+})();
+"#;
@@ -0,0 +1,93 @@
+//! `window.nostr` (NIP-07) for page script.
+//!
+//! Calls are queued as [`SignerRequest`]s on the environment and the returned promises stay
+//! pending until the host settles them with
+//! [`JsDomEnvironment::settle_signer_request`](super::environment::JsDomEnvironment::settle_signer_request).
+//! The page never sees key material: `ReadmeApplication` picks the origin's identity, asks the
+//! user and signs on the UI thread.
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+/// Event fields a page asks to have signed. `pubkey`, `id` and `sig` are filled in by the signer.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UnsignedEventTemplate {
+    pub kind: u16,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<Vec<String>>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignerMethod {
+    GetPublicKey,
+    SignEvent(UnsignedEventTemplate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignerRequest {
+    pub id: u32,
+    pub method: SignerMethod,
+}
+
+impl SignerRequest {
+    pub(crate) fn parse(id: u32, method: &str, params: &str) -> Result<Self, String> {
+        let method = match method {
+            "getPublicKey" => SignerMethod::GetPublicKey,
+            "signEvent" => {
+                let params: JsonValue =
+                    serde_json::from_str(params).map_err(|err| err.to_string())?;
+                let template = serde_json::from_value(params)
+                    .map_err(|err| format!("invalid event: {err}"))?;
+                SignerMethod::SignEvent(template)
+            }
+            other => return Err(format!("window.nostr.{other} is not supported")),
+        };
+        Ok(Self { id, method })
+    }
+}
+
+/// JS half: `window.nostr.getPublicKey()` and `window.nostr.signEvent(event)`, plus
+/// `frontier.__nostrSettle(id, ok, payloadJson)` for the host.
+pub(crate) const NOSTR_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const nostrRequest = global.__frontier_nostr_request;
+    const frontier = global.frontier;
+    const pending = new Map();
+    let nextId = 1;
+
+    const request = (method, params) =>
+        new Promise((resolve, reject) => {
+            const id = nextId++;
+            pending.set(id, { resolve, reject });
+            try {
+                nostrRequest(id, method, JSON.stringify(params === undefined ? null : params));
+            } catch (err) {
+                pending.delete(id);
+                reject(err);
+            }
+        });
+
+    frontier.__nostrSettle = (id, ok, payload) => {
+        const entry = pending.get(id);
+        if (!entry) {
+            return;
+        }
+        pending.delete(id);
+        if (ok) {
+            entry.resolve(JSON.parse(payload));
+        } else {
+            entry.reject(new Error(payload));
+        }
+    };
+
+    global.nostr = {
+        getPublicKey: () => request('getPublicKey'),
+        signEvent: (event) => request('signEvent', event),
+    };
+})();
+"#;
@@ -0,0 +1,169 @@
+//! `frontier.connect(npub)`: an encrypted channel to another nostr user, relayed without DNS
+//! or a server of the app's own (see [`crate::p2p`]).
+//!
+//! `connect` resolves with a channel object once the peer's page connects back, and rejects
+//! when the user denies it, no identity can sign for the origin or the peer never answers.
+//! The channel is an `EventTarget` with `peer`, `readyState`, `send(string)` and `close()`,
+//! firing `message` (a `MessageEvent` whose `data` is the string sent) and `close`, with
+//! matching `onmessage` and `onclose` properties. Calls are queued as [`P2pRequest`]s for the
+//! host, which reports back with
+//! [`JsDomEnvironment::deliver_p2p_event`](super::environment::JsDomEnvironment::deliver_p2p_event).
+
+use nostr_sdk::prelude::{FromBech32, PublicKey};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum P2pRequest {
+    Connect { channel: u32, peer: PublicKey },
+    Send { channel: u32, data: String },
+    Close { channel: u32 },
+}
+
+#[derive(Deserialize)]
+struct Params {
+    channel: u32,
+    #[serde(default)]
+    peer: String,
+    #[serde(default)]
+    data: String,
+}
+
+impl P2pRequest {
+    pub(crate) fn parse(method: &str, params: &str) -> Result<Self, String> {
+        let Params {
+            channel,
+            peer,
+            data,
+        } = serde_json::from_str(params).map_err(|err| err.to_string())?;
+        match method {
+            "connect" => {
+                let peer = PublicKey::from_bech32(peer.trim())
+                    .or_else(|_| PublicKey::from_hex(peer.trim()))
+                    .map_err(|_| format!("'{peer}' is not an npub"))?;
+                Ok(Self::Connect { channel, peer })
+            }
+            "send" => Ok(Self::Send { channel, data }),
+            "close" => Ok(Self::Close { channel }),
+            other => Err(format!("unknown channel call {other}")),
+        }
+    }
+}
+
+/// JS half: `frontier.connect` and the channel objects, plus
+/// `frontier.__p2pEvent(channel, type, payload)` for the host, where `type` is `open`,
+/// `message` or `close`.
+pub(crate) const P2P_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const p2pRequest = global.__frontier_p2p_request;
+    const frontier = global.frontier;
+    const pending = new Map();
+    const channels = new Map();
+    let nextId = 1;
+
+    const request = (method, params) =>
+        p2pRequest(method, JSON.stringify(params));
+
+    const fire = (channel, event) => {
+        channel.dispatchEvent(event);
+        const handler = channel['on' + event.type];
+        if (typeof handler === 'function') {
+            handler.call(channel, event);
+        }
+    };
+
+    class NostrChannel extends EventTarget {
+        constructor(id, peer) {
+            super();
+            Object.defineProperty(this, '__id', { value: id });
+            this.peer = peer;
+            this.readyState = 'connecting';
+            this.onmessage = null;
+            this.onclose = null;
+        }
+
+        send(data) {
+            if (this.readyState !== 'open') {
+                throw new DOMException('The channel is not open', 'InvalidStateError');
+            }
+            request('send', { channel: this.__id, data: String(data) });
+        }
+
+        close() {
+            if (this.readyState === 'closed') {
+                return;
+            }
+            this.readyState = 'closed';
+            channels.delete(this.__id);
+            request('close', { channel: this.__id });
+        }
+    }
+
+    frontier.connect = (peer) =>
+        new Promise((resolve, reject) => {
+            const id = nextId++;
+            try {
+                request('connect', { channel: id, peer: String(peer) });
+            } catch (err) {
+                reject(err);
+                return;
+            }
+            pending.set(id, { resolve, reject });
+            channels.set(id, new NostrChannel(id, String(peer)));
+        });
+
+    frontier.__p2pEvent = (id, type, payload) => {
+        const channel = channels.get(id);
+        const waiting = pending.get(id);
+        pending.delete(id);
+        if (type === 'open' && channel && waiting) {
+            channel.readyState = 'open';
+            waiting.resolve(channel);
+        } else if (type === 'message' && channel && channel.readyState === 'open') {
+            const origin = 'nostr:' + channel.peer;
+            fire(channel, new MessageEvent('message', { data: payload, origin }));
+        } else if (type === 'close' && channel) {
+            channels.delete(id);
+            channel.readyState = 'closed';
+            if (waiting) {
+                waiting.reject(new Error(payload));
+            } else {
+                fire(channel, new Event('close'));
+            }
+        }
+    };
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use nostr_sdk::prelude::{Keys, ToBech32};
+
+    use super::*;
+
+    #[test]
+    fn parses_channel_calls() {
+        let peer = Keys::generate().public_key();
+        let npub = peer.to_bech32().unwrap();
+        assert_eq!(
+            P2pRequest::parse("connect", &format!(r#"{{"channel":1,"peer":"{npub}"}}"#)),
+            Ok(P2pRequest::Connect { channel: 1, peer })
+        );
+        assert_eq!(
+            P2pRequest::parse(
+                "connect",
+                &format!(r#"{{"channel":2,"peer":"{}"}}"#, peer.to_hex())
+            ),
+            Ok(P2pRequest::Connect { channel: 2, peer })
+        );
+        assert_eq!(
+            P2pRequest::parse("send", r#"{"channel":1,"data":"hi"}"#),
+            Ok(P2pRequest::Send {
+                channel: 1,
+                data: "hi".to_string()
+            })
+        );
+        assert!(P2pRequest::parse("connect", r#"{"channel":3,"peer":"bob"}"#).is_err());
+        assert!(P2pRequest::parse("listen", r#"{"channel":4}"#).is_err());
+    }
+}
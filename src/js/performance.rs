@@ -0,0 +1,313 @@
+//! High-resolution time for page script: `performance.now()`, user timing
+//! (`performance.mark`/`measure`) and a `PerformanceNavigationTiming` entry.
+//!
+//! `performance.now()` counts from the document's time origin, a monotonic [`Instant`] the
+//! environment captures when it is created. The host moves it back to the start of the
+//! navigation with
+//! [`JsDomEnvironment::set_navigation_timing`](super::environment::JsDomEnvironment::set_navigation_timing)
+//! before any script runs, and supplies the fetch timestamps the navigation entry reports. The
+//! DOM and load milestones are stamped in JS as lifecycle events fire.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// What the fetch pipeline measured for the current document, in milliseconds from
+/// `time_origin`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavigationTiming {
+    /// When the navigation started; `performance.now()` is 0 here.
+    pub time_origin: Instant,
+    pub url: String,
+    pub fetch_start: f64,
+    pub response_end: f64,
+    /// Size of the document response body in bytes.
+    pub transfer_size: u64,
+}
+
+impl NavigationTiming {
+    /// Timing for a document that was not fetched through the pipeline, e.g. an internal page.
+    pub fn immediate(url: impl Into<String>) -> Self {
+        Self {
+            time_origin: Instant::now(),
+            url: url.into(),
+            fetch_start: 0.0,
+            response_end: 0.0,
+            transfer_size: 0,
+        }
+    }
+
+    /// `performance.timeOrigin`: milliseconds since the Unix epoch at `time_origin`.
+    pub(crate) fn unix_time_origin(&self) -> f64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+            .unwrap_or_default();
+        now - self.time_origin.elapsed().as_secs_f64() * 1000.0
+    }
+
+    pub(crate) fn payload(&self) -> NavigationTimingPayload<'_> {
+        NavigationTimingPayload {
+            time_origin: self.unix_time_origin(),
+            name: &self.url,
+            fetch_start: self.fetch_start,
+            response_end: self.response_end,
+            transfer_size: self.transfer_size,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NavigationTimingPayload<'a> {
+    time_origin: f64,
+    name: &'a str,
+    fetch_start: f64,
+    response_end: f64,
+    transfer_size: u64,
+}
+
+/// JS half: the `performance` object, the `Performance*` entry classes and
+/// `frontier.__setNavigationTiming(json)`. Relies on `__frontier_performance_now` and wraps
+/// `frontier.__fireLifecycle` to stamp the navigation entry's DOM and load milestones.
+pub(crate) const PERFORMANCE_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const performanceNow = global.__frontier_performance_now;
+    const frontier = global.frontier;
+    const now = () => performanceNow();
+    let timeOrigin = Date.now() - now();
+    let userEntries = [];
+    let navigation = null;
+
+    const syntaxError = (message) =>
+        typeof global.DOMException === 'function'
+            ? new global.DOMException(message, 'SyntaxError')
+            : new SyntaxError(message);
+
+    class PerformanceEntry {
+        constructor(name, entryType, startTime, duration) {
+            Object.defineProperties(this, {
+                name: { value: String(name), enumerable: true },
+                entryType: { value: entryType, enumerable: true },
+                startTime: { value: startTime, enumerable: true },
+                duration: { value: duration, enumerable: true, writable: true },
+            });
+        }
+
+        toJSON() {
+            const json = {};
+            for (const key of Object.keys(this)) {
+                json[key] = this[key];
+            }
+            return json;
+        }
+    }
+
+    class PerformanceMark extends PerformanceEntry {
+        constructor(name, options = {}) {
+            const startTime = options.startTime === undefined ? now() : Number(options.startTime);
+            if (!Number.isFinite(startTime) || startTime < 0) {
+                throw new TypeError(`Failed to construct 'PerformanceMark': invalid startTime`);
+            }
+            super(name, 'mark', startTime, 0);
+            Object.defineProperty(this, 'detail', {
+                value: options.detail === undefined ? null : options.detail,
+                enumerable: true,
+            });
+        }
+    }
+
+    class PerformanceMeasure extends PerformanceEntry {
+        constructor(name, startTime, duration, detail) {
+            super(name, 'measure', startTime, duration);
+            Object.defineProperty(this, 'detail', {
+                value: detail === undefined ? null : detail,
+                enumerable: true,
+            });
+        }
+    }
+
+    const NAVIGATION_FIELDS = [
+        'fetchStart',
+        'responseEnd',
+        'domInteractive',
+        'domContentLoadedEventStart',
+        'domContentLoadedEventEnd',
+        'domComplete',
+        'loadEventStart',
+        'loadEventEnd',
+    ];
+
+    class PerformanceNavigationTiming extends PerformanceEntry {
+        constructor(name, timing) {
+            super(name, 'navigation', 0, 0);
+            this.initiatorType = 'navigation';
+            this.type = 'navigate';
+            this.redirectCount = 0;
+            this.transferSize = timing.transferSize || 0;
+            for (const field of NAVIGATION_FIELDS) {
+                this[field] = timing[field] || 0;
+            }
+        }
+    }
+
+    const sorted = (entries) => entries.slice().sort((a, b) => a.startTime - b.startTime);
+
+    const allEntries = () => sorted(navigation ? [navigation, ...userEntries] : userEntries);
+
+    // A mark name, or a navigation timing attribute such as 'fetchStart'.
+    const resolveMark = (mark) => {
+        if (typeof mark === 'number') {
+            if (mark < 0) {
+                throw new TypeError(`'${mark}' is negative`);
+            }
+            return mark;
+        }
+        const name = String(mark);
+        for (let index = userEntries.length - 1; index >= 0; index -= 1) {
+            const entry = userEntries[index];
+            if (entry.entryType === 'mark' && entry.name === name) {
+                return entry.startTime;
+            }
+        }
+        if (NAVIGATION_FIELDS.includes(name)) {
+            const value = navigation ? navigation[name] : 0;
+            if (!value) {
+                throw new TypeError(`'${name}' is empty: the event has not happened yet`);
+            }
+            return value;
+        }
+        throw syntaxError(`The mark '${name}' does not exist.`);
+    };
+
+    const performance = {
+        get timeOrigin() {
+            return timeOrigin;
+        },
+        now,
+        mark(name, options) {
+            if (arguments.length === 0) {
+                throw new TypeError(`Failed to execute 'mark' on 'Performance': 1 argument required`);
+            }
+            if (NAVIGATION_FIELDS.includes(String(name))) {
+                throw syntaxError(`'${name}' is part of the PerformanceTiming interface`);
+            }
+            const entry = new PerformanceMark(name, options || {});
+            userEntries.push(entry);
+            return entry;
+        },
+        measure(name, startOrOptions, endMark) {
+            if (arguments.length === 0) {
+                throw new TypeError(`Failed to execute 'measure' on 'Performance': 1 argument required`);
+            }
+            let start = 0;
+            let end;
+            let detail;
+            if (startOrOptions !== null && typeof startOrOptions === 'object') {
+                const options = startOrOptions;
+                if (endMark !== undefined) {
+                    throw new TypeError('end mark must not be given alongside measure options');
+                }
+                if (options.start !== undefined && options.end !== undefined && options.duration !== undefined) {
+                    throw new TypeError('start, end and duration cannot all be given');
+                }
+                detail = options.detail;
+                if (options.start !== undefined) {
+                    start = resolveMark(options.start);
+                }
+                if (options.end !== undefined) {
+                    end = resolveMark(options.end);
+                } else if (options.duration !== undefined) {
+                    end = start + Number(options.duration);
+                }
+                if (options.start === undefined && options.duration !== undefined && end !== undefined) {
+                    start = end - Number(options.duration);
+                }
+            } else if (startOrOptions !== undefined) {
+                start = resolveMark(startOrOptions);
+            }
+            if (end === undefined) {
+                end = endMark === undefined ? now() : resolveMark(endMark);
+            }
+            const entry = new PerformanceMeasure(name, start, end - start, detail);
+            userEntries.push(entry);
+            return entry;
+        },
+        getEntries() {
+            return allEntries();
+        },
+        getEntriesByType(type) {
+            return allEntries().filter((entry) => entry.entryType === String(type));
+        },
+        getEntriesByName(name, type) {
+            return allEntries().filter(
+                (entry) => entry.name === String(name) && (type === undefined || entry.entryType === String(type)),
+            );
+        },
+        clearMarks(name) {
+            userEntries = userEntries.filter(
+                (entry) => entry.entryType !== 'mark' || (name !== undefined && entry.name !== String(name)),
+            );
+        },
+        clearMeasures(name) {
+            userEntries = userEntries.filter(
+                (entry) => entry.entryType !== 'measure' || (name !== undefined && entry.name !== String(name)),
+            );
+        },
+        toJSON() {
+            return { timeOrigin };
+        },
+    };
+
+    global.PerformanceEntry = PerformanceEntry;
+    global.PerformanceMark = PerformanceMark;
+    global.PerformanceMeasure = PerformanceMeasure;
+    global.PerformanceNavigationTiming = PerformanceNavigationTiming;
+    Object.defineProperty(global, 'performance', {
+        value: performance,
+        configurable: true,
+        writable: true,
+    });
+
+    frontier.__setNavigationTiming = (json) => {
+        const timing = JSON.parse(json);
+        timeOrigin = timing.timeOrigin;
+        navigation = new PerformanceNavigationTiming(timing.name, timing);
+    };
+
+    const stamp = (field) => {
+        if (navigation && !navigation[field]) {
+            navigation[field] = now();
+        }
+    };
+
+    const fireLifecycle = frontier.__fireLifecycle;
+    frontier.__fireLifecycle = (name, arg) => {
+        switch (name) {
+            case 'interactive':
+                stamp('domInteractive');
+                return fireLifecycle(name, arg);
+            case 'DOMContentLoaded': {
+                stamp('domInteractive');
+                stamp('domContentLoadedEventStart');
+                const result = fireLifecycle(name, arg);
+                stamp('domContentLoadedEventEnd');
+                return result;
+            }
+            case 'load': {
+                stamp('domComplete');
+                stamp('loadEventStart');
+                const result = fireLifecycle(name, arg);
+                stamp('loadEventEnd');
+                if (navigation) {
+                    navigation.duration = navigation.loadEventEnd;
+                }
+                return result;
+            }
+            default:
+                return fireLifecycle(name, arg);
+        }
+    };
+})();
+"#;
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use anyhow::{anyhow, Context as AnyhowContext, Result};
 use blitz_dom::DocumentConfig;
 use blitz_html::HtmlDocument;
@@ -5,7 +7,10 @@ use kuchiki::parse_html;
 use kuchiki::traits::*;
 use tracing::{debug, error};
 
+use super::bytecode_cache::CompileStats;
 use super::environment::JsDomEnvironment;
+use super::runtime::{log_uncaught, JsException};
+use super::runtime_document::RuntimeDocument;
 use super::script::{ScriptDescriptor, ScriptExecution, ScriptKind, ScriptSource};
 use crate::navigation::FetchedDocument;
 
@@ -13,6 +18,8 @@ use crate::navigation::FetchedDocument;
 pub struct ScriptExecutionSummary {
     pub executed_scripts: usize,
     pub dom_mutations: usize,
+    /// Compile timings of the scripts large enough for the bytecode cache.
+    pub compile: CompileStats,
 }
 
 #[allow(dead_code)]
@@ -28,17 +35,21 @@ pub fn execute_inline_scripts(
         return Ok(None);
     }
 
-    let environment = JsDomEnvironment::new(&document.contents)
-        .context("failed to initialize QuickJS environment")?;
+    let environment = Rc::new(
+        JsDomEnvironment::new(&document.contents)
+            .context("failed to initialize QuickJS environment")?,
+    );
 
-    let mut temp_doc = HtmlDocument::from_html(
-        &document.contents,
-        DocumentConfig {
-            base_url: Some(document.base_url.clone()),
-            ..Default::default()
-        },
+    let _temp_doc = RuntimeDocument::attach_to(
+        HtmlDocument::from_html(
+            &document.contents,
+            DocumentConfig {
+                base_url: Some(document.base_url.clone()),
+                ..Default::default()
+            },
+        ),
+        Rc::clone(&environment),
     );
-    environment.attach_document(&mut temp_doc);
 
     let summary = run_inline_scripts(&environment, &inline_scripts)?;
 
@@ -56,6 +67,7 @@ pub fn collect_scripts(html: &str) -> Result<Vec<ScriptDescriptor>> {
         let attributes = script.attributes.borrow();
         let kind = classify_kind(attributes.get("type"));
         let execution = determine_execution(&attributes, kind);
+        let nonce = attributes.get("nonce").map(|nonce| nonce.to_string());
 
         if let Some(src) = attributes
             .get("src")
@@ -69,6 +81,9 @@ pub fn collect_scripts(html: &str) -> Result<Vec<ScriptDescriptor>> {
                 source: ScriptSource::External {
                     src: src.to_string(),
                 },
+                nonce,
+                url: None,
+                source_map: None,
             });
             continue;
         }
@@ -78,7 +93,10 @@ pub fn collect_scripts(html: &str) -> Result<Vec<ScriptDescriptor>> {
         if code.trim().is_empty() {
             continue;
         }
-        collected.push(ScriptDescriptor::inline(index, code, kind));
+        collected.push(ScriptDescriptor {
+            nonce,
+            ..ScriptDescriptor::inline(index, code, kind)
+        });
     }
 
     Ok(collected)
@@ -133,14 +151,16 @@ pub(super) fn run_inline_scripts(
     let mut executed = 0usize;
 
     for descriptor in scripts {
-        let filename = format!("inline-script-{}.js", descriptor.index);
+        let filename = descriptor.filename();
         let source = match &descriptor.source {
             ScriptSource::Inline { code } => code,
             ScriptSource::External { .. } => continue,
         };
+        environment.load_source_map(source, &filename, descriptor);
 
-        match environment.eval(source, &filename) {
+        match environment.eval_script(source, &filename) {
             Ok(_) => executed += 1,
+            Err(err) if err.is::<JsException>() => log_uncaught(&err, &filename),
             Err(err) => {
                 error!(target = "quickjs", %filename, error = %err, "inline script execution failed");
             }
@@ -150,6 +170,7 @@ pub(super) fn run_inline_scripts(
     Ok(ScriptExecutionSummary {
         executed_scripts: executed,
         dom_mutations: environment.drain_mutations().len(),
+        compile: environment.compile_stats(),
     })
 }
 
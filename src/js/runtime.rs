@@ -1,5 +1,100 @@
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
 use anyhow::{Context as AnyhowContext, Result};
-use rquickjs::{Context, Ctx, Error as JsError, Function, Runtime, Value};
+use rquickjs::{qjs, Context, Ctx, Error as JsError, Function, Runtime, Value};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::bytecode_cache::{BytecodeCache, CompileStats, MIN_CACHED_BYTES};
+use super::console::ConsoleLog;
+use super::coverage::{JsCoverage, COUNTER};
+use super::isolation::{Isolation, NativeMemory, OriginLease, OriginQuota};
+use super::sourcemap::{SourceMapRegistry, StackFrame};
+
+/// Heap statistics QuickJS keeps in its allocator hooks for one runtime.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsHeapUsage {
+    /// Bytes currently allocated through the runtime's allocator.
+    pub allocated_bytes: u64,
+    /// Live allocations.
+    pub allocations: u64,
+    pub objects: u64,
+    pub functions: u64,
+}
+
+/// An exception a script threw and nothing caught, with its stack already mapped through
+/// any registered source maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsException {
+    pub name: String,
+    pub message: String,
+    pub frames: Vec<StackFrame>,
+}
+
+impl JsException {
+    /// Where the exception was thrown, as `file:line[:column]`.
+    pub fn location(&self) -> Option<String> {
+        let frame = self.frames.first()?;
+        Some(match frame.column {
+            Some(column) => format!("{}:{}:{column}", frame.file, frame.line),
+            None => format!("{}:{}", frame.file, frame.line),
+        })
+    }
+
+    /// Whether QuickJS threw this because the runtime reached its memory limit.
+    pub fn is_out_of_memory(&self) -> bool {
+        self.name == "InternalError" && self.message == "out of memory"
+    }
+
+    pub fn stack(&self) -> String {
+        self.frames
+            .iter()
+            .map(|frame| format!("    {frame}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for JsException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)?;
+        for frame in &self.frames {
+            write!(f, "\n    {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsException {}
+
+/// Log an uncaught [`JsException`] from the script evaluated as `script`, with its mapped
+/// location and stack as separate fields.
+pub fn log_uncaught(err: &anyhow::Error, script: &str) {
+    let Some(exception) = err.downcast_ref::<JsException>() else {
+        return;
+    };
+    tracing::error!(
+        target = "quickjs",
+        script = %script,
+        name = %exception.name,
+        message = %exception.message,
+        location = exception.location().as_deref().unwrap_or("unknown"),
+        stack = %exception.stack(),
+        "uncaught exception"
+    );
+    let location = exception.location().unwrap_or_else(|| script.to_string());
+    ConsoleLog::shared().record(
+        "error",
+        &format!(
+            "Uncaught {}: {} ({location})",
+            exception.name, exception.message
+        ),
+    );
+}
 
 /// JavaScript runtime backed by QuickJS.
 ///
@@ -9,6 +104,74 @@ use rquickjs::{Context, Ctx, Error as JsError, Function, Runtime, Value};
 pub struct QuickJsEngine {
     _runtime: Runtime,
     context: Context,
+    source_maps: SourceMapRegistry,
+    bytecode_cache: Option<BytecodeCache>,
+    compile_stats: Cell<CompileStats>,
+    coverage: RefCell<Option<JsCoverage>>,
+    clock: Rc<ScriptClock>,
+    /// The origin the runtime is counted against once [`Self::isolate`] has run.
+    lease: RefCell<Option<Rc<OriginLease<'static>>>>,
+}
+
+/// Times each entry into the runtime against the script budget. Entries nest when a script
+/// runs from inside another; only the outermost one is timed.
+#[derive(Default)]
+struct ScriptClock {
+    budget: Cell<Option<Duration>>,
+    started: Cell<Option<Instant>>,
+    depth: Cell<usize>,
+    interrupted: Cell<bool>,
+}
+
+impl ScriptClock {
+    fn enter(&self) -> ClockEntry<'_> {
+        if self.depth.get() == 0 {
+            self.started.set(Some(Instant::now()));
+            self.interrupted.set(false);
+        }
+        self.depth.set(self.depth.get() + 1);
+        ClockEntry(self)
+    }
+
+    /// Whether the current entry has run past the budget. Returns `Some(true)` the first time
+    /// it finds so, `Some(false)` on later calls during the same entry.
+    fn overrun(&self) -> Option<bool> {
+        let elapsed = self.started.get()?.elapsed();
+        if elapsed < self.budget.get()? {
+            return None;
+        }
+        Some(!self.interrupted.replace(true))
+    }
+}
+
+struct ClockEntry<'a>(&'a ScriptClock);
+
+/// One entry into the runtime's scripts: timed by its clock, and with the heap limit set to
+/// what the origin's budget leaves over while it runs.
+struct Entry<'a> {
+    engine: &'a QuickJsEngine,
+    _clock: ClockEntry<'a>,
+}
+
+impl Drop for Entry<'_> {
+    fn drop(&mut self) {
+        // The clock entry is dropped after this, so depth 1 is the outermost entry.
+        if self.engine.clock.depth.get() == 1 {
+            if let Some(lease) = self.engine.lease.borrow().as_ref() {
+                lease.record_heap(self.engine.heap_bytes());
+            }
+        }
+    }
+}
+
+impl Drop for ClockEntry<'_> {
+    fn drop(&mut self) {
+        let depth = self.0.depth.get() - 1;
+        self.0.depth.set(depth);
+        if depth == 0 {
+            self.0.started.set(None);
+        }
+    }
 }
 
 impl QuickJsEngine {
@@ -20,15 +183,76 @@ impl QuickJsEngine {
         let engine = Self {
             _runtime: runtime,
             context,
+            source_maps: SourceMapRegistry::default(),
+            bytecode_cache: BytecodeCache::shared().cloned(),
+            compile_stats: Cell::new(CompileStats::default()),
+            coverage: RefCell::new(None),
+            clock: Rc::new(ScriptClock::default()),
+            lease: RefCell::new(None),
         };
         engine.init_console()?;
         Ok(engine)
     }
 
+    /// Cap the runtime's heap and script time at `quota` and count it against the origin of
+    /// `document_url`, whose runtimes share the memory limit (see [`super::isolation`]).
+    pub fn isolate(&self, document_url: Option<&Url>, quota: OriginQuota) {
+        let lease = Rc::new(Isolation::shared().admit(document_url, quota));
+        self._runtime
+            .set_memory_limit(lease.heap_limit(self.heap_bytes()));
+        self.clock.budget.set(Some(quota.script_budget));
+        let clock = Rc::clone(&self.clock);
+        let interrupted = Rc::clone(&lease);
+        self._runtime
+            .set_interrupt_handler(Some(Box::new(move || match clock.overrun() {
+                Some(first) => {
+                    if first {
+                        tracing::warn!(
+                            target = "quickjs",
+                            origin = %interrupted.origin(),
+                            "interrupted a script that ran past its time budget"
+                        );
+                        interrupted.record_interrupt();
+                    }
+                    true
+                }
+                None => false,
+            })));
+        *self.lease.borrow_mut() = Some(lease);
+    }
+
+    /// Charges for what Rust allocates on the page's behalf, once [`Self::isolate`] has run.
+    pub fn native_memory(&self) -> Option<NativeMemory<'static>> {
+        self.lease
+            .borrow()
+            .as_ref()
+            .map(|lease| lease.native_memory())
+    }
+
+    fn heap_bytes(&self) -> usize {
+        usize::try_from(self._runtime.memory_usage().malloc_size).unwrap_or_default()
+    }
+
+    /// Enter the runtime's scripts. The outermost entry gets the heap limit that the origin's
+    /// other runtimes and native allocations leave over.
+    fn enter(&self) -> Entry<'_> {
+        if self.clock.depth.get() == 0 {
+            if let Some(lease) = self.lease.borrow().as_ref() {
+                self._runtime
+                    .set_memory_limit(lease.heap_limit(self.heap_bytes()));
+            }
+        }
+        Entry {
+            engine: self,
+            _clock: self.clock.enter(),
+        }
+    }
+
     /// Execute any pending microtasks/jobs queued inside the QuickJS runtime until exhausted.
     pub fn drain_jobs(&self) -> Result<bool> {
         let mut executed = false;
         loop {
+            let _entry = self.enter();
             let pending = self._runtime.execute_pending_job().map_err(|err| {
                 let message = err.0.with(|ctx| format!("{:#?}", ctx.catch()));
                 anyhow::anyhow!("quickjs pending job raised: {message}")
@@ -41,6 +265,23 @@ impl QuickJsEngine {
         Ok(executed)
     }
 
+    /// Whether promise jobs are queued and waiting for [`Self::drain_jobs`].
+    pub fn has_pending_jobs(&self) -> bool {
+        self._runtime.is_job_pending()
+    }
+
+    /// Current heap statistics of the QuickJS runtime.
+    pub fn heap_usage(&self) -> JsHeapUsage {
+        let usage = self._runtime.memory_usage();
+        let count = |value: i64| u64::try_from(value).unwrap_or_default();
+        JsHeapUsage {
+            allocated_bytes: count(usage.malloc_size),
+            allocations: count(usage.malloc_count),
+            objects: count(usage.obj_count),
+            functions: count(usage.js_func_count),
+        }
+    }
+
     /// Evaluate a script and discard the result.
     pub fn eval(&self, source: &str, filename: &str) -> Result<()> {
         self.eval_with::<()>(source, filename)
@@ -52,29 +293,149 @@ impl QuickJsEngine {
         V: for<'js> rquickjs::FromJs<'js>,
     {
         let script = Self::with_source_url(source, filename);
+        let _entry = self.enter();
         let eval_result = self.context.with(|ctx| ctx.eval::<V, _>(script.clone()));
 
-        match eval_result {
-            Ok(value) => Ok(value),
-            Err(JsError::Exception) => {
-                let message = self
+        eval_result.map_err(|err| self.eval_error(err))
+    }
+
+    /// Evaluate a page script, loading its compiled bytecode from the cache when the script
+    /// is large enough for that to beat parsing it. While coverage is collected, the script
+    /// is rewritten to count into it instead and the cache is not used.
+    pub fn eval_script(&self, source: &str, filename: &str) -> Result<()> {
+        let coverage = self.coverage.borrow().clone();
+        if let Some(coverage) = coverage {
+            return self.eval_covered(&coverage, source, filename);
+        }
+        match &self.bytecode_cache {
+            Some(cache) if source.len() >= MIN_CACHED_BYTES => {
+                self.eval_cached(cache, source, filename)
+            }
+            _ => self.eval(source, filename),
+        }
+    }
+
+    /// Count the page scripts evaluated from now on into `coverage`.
+    pub fn set_coverage(&self, coverage: JsCoverage) -> Result<()> {
+        let counts = coverage.clone();
+        self.with_context(|ctx| {
+            let counter = Function::new(ctx.clone(), move |script: u32, site: u32| {
+                counts.hit(script as usize, site as usize);
+            })?
+            .with_name(COUNTER)?;
+            ctx.globals().set(COUNTER, counter)
+        })?;
+        *self.coverage.borrow_mut() = Some(coverage);
+        Ok(())
+    }
+
+    /// Compile timings of the page scripts evaluated through [`Self::eval_script`].
+    pub fn compile_stats(&self) -> CompileStats {
+        self.compile_stats.get()
+    }
+
+    fn eval_cached(&self, cache: &BytecodeCache, source: &str, filename: &str) -> Result<()> {
+        let script = Self::with_source_url(source, filename);
+        let key = BytecodeCache::key(&script, filename);
+        let _entry = self.enter();
+        let result = self.context.with(|ctx| -> rquickjs::Result<()> {
+            let mut stats = self.compile_stats.get();
+            let started = Instant::now();
+            let cached = cache.load(&key).and_then(|bytecode| {
+                let function = read_bytecode(&ctx, &bytecode);
+                if function.is_none() {
+                    cache.remove(&key);
+                }
+                function
+            });
+            let function = match cached {
+                Some(function) => {
+                    stats.cache_hits += 1;
+                    stats.load_ms += elapsed_ms(started);
+                    function
+                }
+                None => {
+                    let started = Instant::now();
+                    let function = compile(&ctx, &script, filename)?;
+                    stats.cache_misses += 1;
+                    stats.compile_ms += elapsed_ms(started);
+                    if let Some(bytecode) = write_bytecode(&ctx, &function) {
+                        if let Err(err) = cache.store(&key, &bytecode) {
+                            tracing::debug!(
+                                target = "quickjs",
+                                script = %filename,
+                                error = %err,
+                                "unable to cache bytecode"
+                            );
+                        }
+                    }
+                    function
+                }
+            };
+            self.compile_stats.set(stats);
+            run_compiled(&ctx, function)
+        });
+        result.map_err(|err| self.eval_error(err))
+    }
+
+    fn eval_covered(&self, coverage: &JsCoverage, source: &str, filename: &str) -> Result<()> {
+        let (script, rewritten) = coverage.instrument(source, filename);
+        let Some(rewritten) = rewritten else {
+            return self.eval(source, filename);
+        };
+        let rewritten = Self::with_source_url(&rewritten, filename);
+        let _entry = self.enter();
+        let compiled = self
+            .context
+            .with(|ctx| match compile(&ctx, &rewritten, filename) {
+                Ok(function) => Some(run_compiled(&ctx, function)),
+                Err(_) => {
+                    ctx.catch();
+                    None
+                }
+            });
+        match compiled {
+            Some(result) => result.map_err(|err| self.eval_error(err)),
+            None => {
+                tracing::debug!(
+                    target = "quickjs",
+                    script = %filename,
+                    "instrumented script did not compile; running it without coverage"
+                );
+                coverage.uninstrumented(script);
+                self.eval(source, filename)
+            }
+        }
+    }
+
+    fn eval_error(&self, err: JsError) -> anyhow::Error {
+        match err {
+            JsError::Exception => {
+                let exception = self
                     .context
-                    .with(|ctx| -> Result<Option<String>, JsError> {
-                        Ok(capture_exception_message(&ctx))
-                    })
-                    .unwrap_or(None)
-                    .unwrap_or_else(|| "QuickJS exception".to_string());
-                Err(anyhow::anyhow!(message))
+                    .with(|ctx| capture_exception(&ctx, &self.source_maps));
+                if exception.is_out_of_memory() {
+                    if let Some(lease) = self.lease.borrow().as_ref() {
+                        lease.record_out_of_memory();
+                    }
+                }
+                anyhow::Error::new(exception)
             }
-            Err(err) => Err(anyhow::Error::from(err)),
+            err => anyhow::Error::from(err),
         }
     }
 
+    /// Source maps consulted when reporting exceptions and console output.
+    pub fn source_maps(&self) -> &SourceMapRegistry {
+        &self.source_maps
+    }
+
     /// Provide access to the underlying QuickJS context for advanced integrations.
     pub fn with_context<T, F>(&self, f: F) -> Result<T>
     where
         F: for<'js> FnOnce(Ctx<'js>) -> rquickjs::Result<T>,
     {
+        let _entry = self.enter();
         self.context.with(f).map_err(anyhow::Error::from)
     }
 
@@ -82,8 +443,15 @@ impl QuickJsEngine {
         self.context
             .with(|ctx| {
                 let global = ctx.globals();
-                let log_fn =
-                    Function::new(ctx.clone(), log_from_js)?.with_name("__frontier_log")?;
+                let source_maps = self.source_maps.clone();
+                let log_fn = Function::new(
+                    ctx.clone(),
+                    move |level: String, message: String| -> rquickjs::Result<()> {
+                        log_from_js(&level, &source_maps.symbolicate_text(&message));
+                        Ok(())
+                    },
+                )?
+                .with_name("__frontier_log")?;
                 global.set("__frontier_log", log_fn)?;
                 ctx.eval::<(), _>(CONSOLE_BOOTSTRAP.as_bytes())
             })
@@ -103,19 +471,131 @@ impl QuickJsEngine {
     }
 }
 
-fn log_from_js(message: String) -> rquickjs::Result<()> {
-    tracing::info!(target = "quickjs", message = %message);
-    Ok(())
+fn log_from_js(level: &str, message: &str) {
+    ConsoleLog::shared().record(level, message);
+    match level {
+        "error" => tracing::error!(target = "quickjs", message = %message),
+        "warn" => tracing::warn!(target = "quickjs", message = %message),
+        "debug" => tracing::debug!(target = "quickjs", message = %message),
+        _ => tracing::info!(target = "quickjs", message = %message),
+    }
+}
+
+fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Compile a global script without running it, with the same strict-mode flag `Ctx::eval`
+/// uses.
+fn compile<'js>(ctx: &Ctx<'js>, script: &[u8], filename: &str) -> rquickjs::Result<Value<'js>> {
+    let source = CString::new(script)?;
+    let filename = CString::new(filename)?;
+    let flags = (qjs::JS_EVAL_TYPE_GLOBAL
+        | qjs::JS_EVAL_FLAG_STRICT
+        | qjs::JS_EVAL_FLAG_COMPILE_ONLY) as i32;
+    // SAFETY: both strings are NUL-terminated and outlive the call.
+    let raw = unsafe {
+        qjs::JS_Eval(
+            ctx.as_raw().as_ptr(),
+            source.as_ptr(),
+            script.len(),
+            filename.as_ptr(),
+            flags,
+        )
+    };
+    owned_value(ctx, raw)
+}
+
+fn write_bytecode(ctx: &Ctx<'_>, function: &Value<'_>) -> Option<Vec<u8>> {
+    let raw_ctx = ctx.as_raw().as_ptr();
+    let mut len = 0;
+    // SAFETY: on success QuickJS returns a buffer of `len` bytes allocated in this context,
+    // which is copied and then freed with the context's allocator.
+    unsafe {
+        let buffer = qjs::JS_WriteObject(
+            raw_ctx,
+            &mut len,
+            function.as_raw(),
+            qjs::JS_WRITE_OBJ_BYTECODE as i32,
+        );
+        if buffer.is_null() {
+            let _ = ctx.catch();
+            return None;
+        }
+        let bytecode = std::slice::from_raw_parts(buffer, len).to_vec();
+        qjs::js_free(raw_ctx, buffer.cast());
+        Some(bytecode)
+    }
+}
+
+/// Load cached bytecode; `None` when QuickJS rejects it, e.g. after an engine upgrade.
+fn read_bytecode<'js>(ctx: &Ctx<'js>, bytecode: &[u8]) -> Option<Value<'js>> {
+    // SAFETY: the buffer outlives the call and passed the cache's checksum, so it holds
+    // exactly what `write_bytecode` produced.
+    let raw = unsafe {
+        qjs::JS_ReadObject(
+            ctx.as_raw().as_ptr(),
+            bytecode.as_ptr(),
+            bytecode.len(),
+            qjs::JS_READ_OBJ_BYTECODE as i32,
+        )
+    };
+    match owned_value(ctx, raw) {
+        Ok(function) => Some(function),
+        Err(_) => {
+            let _ = ctx.catch();
+            None
+        }
+    }
+}
+
+fn run_compiled(ctx: &Ctx<'_>, function: Value<'_>) -> rquickjs::Result<()> {
+    // SAFETY: `JS_EvalFunction` takes ownership of the function and returns an owned value.
+    let raw = unsafe { qjs::JS_EvalFunction(ctx.as_raw().as_ptr(), function.into_raw()) };
+    owned_value(ctx, raw).map(drop)
 }
 
-fn capture_exception_message(ctx: &Ctx<'_>) -> Option<String> {
+/// Wrap a value QuickJS just returned to us, turning the exception marker into an error.
+fn owned_value<'js>(ctx: &Ctx<'js>, raw: qjs::JSValue) -> rquickjs::Result<Value<'js>> {
+    // SAFETY: `raw` is an owned value of this context that nothing else references.
+    unsafe {
+        if qjs::JS_IsException(raw) {
+            return Err(JsError::Exception);
+        }
+        Ok(Value::from_raw(ctx.clone(), raw))
+    }
+}
+
+fn capture_exception(ctx: &Ctx<'_>, source_maps: &SourceMapRegistry) -> JsException {
     let value: Value = ctx.catch();
-    Some(format!("{:?}", value))
+    let Some(object) = value.as_object() else {
+        return JsException {
+            name: "Uncaught".to_string(),
+            message: format!("{value:?}"),
+            frames: Vec::new(),
+        };
+    };
+    let field = |name: &str| object.get::<_, Option<String>>(name).ok().flatten();
+    let frames = field("stack")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(StackFrame::parse)
+        .map(|mut frame| {
+            source_maps.symbolicate(&mut frame);
+            frame
+        })
+        .collect();
+    JsException {
+        name: field("name").unwrap_or_else(|| "Error".to_string()),
+        message: field("message").unwrap_or_else(|| format!("{value:?}")),
+        frames,
+    }
 }
 
 const CONSOLE_BOOTSTRAP: &str = r#"
 (() => {
     const global = globalThis;
+    const nativeLog = global.__frontier_log;
     const stringify = (value) => {
         try {
             if (typeof value === 'string') {
@@ -133,10 +613,10 @@ const CONSOLE_BOOTSTRAP: &str = r#"
         }
     };
 
-    const logImpl = (...args) => {
+    const logAt = (level) => (...args) => {
         try {
             const joined = args.map(stringify).join(' ');
-            global.__frontier_log(joined);
+            nativeLog(level, joined);
         } catch (err) {
             // Swallow logging errors; console must never throw.
         }
@@ -146,6 +626,147 @@ const CONSOLE_BOOTSTRAP: &str = r#"
         global.console = {};
     }
 
-    global.console.log = logImpl;
+    global.console.log = logAt('info');
+    global.console.info = logAt('info');
+    global.console.debug = logAt('debug');
+    global.console.warn = logAt('warn');
+    global.console.error = logAt('error');
 })();
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle() -> String {
+        let mut source = String::from("var total = 0;\n");
+        while source.len() < MIN_CACHED_BYTES {
+            source.push_str("total += [1, 2, 3].map((n) => n * 2).length;\n");
+        }
+        source.push_str("if (total > 0) { throw new TypeError('done ' + total); }\n");
+        source
+    }
+
+    #[test]
+    fn cached_bytecode_runs_like_source_on_the_next_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = BytecodeCache::new(dir.path().to_path_buf());
+        let source = bundle();
+
+        let first = QuickJsEngine::new().unwrap();
+        let err = first.eval_cached(&cache, &source, "bundle.js").unwrap_err();
+        let first_error = err.downcast::<JsException>().unwrap();
+        assert_eq!(first_error.name, "TypeError");
+        assert_eq!(first.compile_stats().cache_misses, 1);
+
+        let second = QuickJsEngine::new().unwrap();
+        let err = second
+            .eval_cached(&cache, &source, "bundle.js")
+            .unwrap_err();
+        assert_eq!(err.downcast::<JsException>().unwrap(), first_error);
+        let stats = second.compile_stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (1, 0));
+        let total: i32 = second.eval_with("total", "read.js").unwrap();
+        assert!(total > 0);
+    }
+
+    fn usage(origin: &str) -> crate::js::isolation::OriginUsage {
+        Isolation::shared()
+            .usage()
+            .into_iter()
+            .find(|usage| usage.origin == origin)
+            .unwrap()
+    }
+
+    #[test]
+    fn runaway_allocation_fails_inside_the_page() {
+        let url = Url::parse("https://heap-quota.example/").unwrap();
+        let engine = QuickJsEngine::new().unwrap();
+        engine.isolate(
+            Some(&url),
+            OriginQuota {
+                memory_limit: 32 * 1024 * 1024,
+                ..Default::default()
+            },
+        );
+
+        let err = engine
+            .eval(
+                "(() => { const hoard = []; while (true) hoard.push('x'.repeat(1 << 20)); })()",
+                "hoard.js",
+            )
+            .unwrap_err();
+        assert!(err.downcast::<JsException>().unwrap().is_out_of_memory());
+        assert_eq!(usage("https://heap-quota.example").out_of_memory, 1);
+
+        let sum: i32 = engine.eval_with("1 + 2", "after.js").unwrap();
+        assert_eq!(sum, 3);
+        assert!(engine.heap_usage().allocated_bytes < 32 * 1024 * 1024);
+    }
+
+    #[test]
+    fn runtimes_of_one_origin_share_the_heap_limit() {
+        let url = Url::parse("https://shared-quota.example/").unwrap();
+        let quota = OriginQuota {
+            memory_limit: 48 * 1024 * 1024,
+            ..Default::default()
+        };
+        let first = QuickJsEngine::new().unwrap();
+        first.isolate(Some(&url), quota);
+        let second = QuickJsEngine::new().unwrap();
+        second.isolate(Some(&url), quota);
+
+        first
+            .eval(
+                "globalThis.hoard = [];
+                 for (let i = 0; i < 32; i++) hoard.push('x'.repeat(1 << 20));",
+                "hoard.js",
+            )
+            .unwrap();
+        let err = second
+            .eval("const more = 'y'.repeat(32 << 20);", "more.js")
+            .unwrap_err();
+        assert!(err.downcast::<JsException>().unwrap().is_out_of_memory());
+
+        first.eval("hoard.length = 0;", "free.js").unwrap();
+        second
+            .eval("const after = 'y'.repeat(32 << 20);", "after.js")
+            .unwrap();
+    }
+
+    #[test]
+    fn scripts_past_their_budget_are_interrupted() {
+        let url = Url::parse("https://time-quota.example/").unwrap();
+        let engine = QuickJsEngine::new().unwrap();
+        engine.isolate(
+            Some(&url),
+            OriginQuota {
+                script_budget: Duration::from_millis(100),
+                ..Default::default()
+            },
+        );
+
+        let started = Instant::now();
+        let err = engine
+            .eval("try { while (true) {} } catch (err) {}", "spin.js")
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            err.downcast::<JsException>().unwrap().message,
+            "interrupted"
+        );
+        assert_eq!(usage("https://time-quota.example").interrupted_scripts, 1);
+
+        // Each entry gets a fresh budget.
+        let done: bool = engine
+            .eval_with(
+                "let n = 0; for (let i = 0; i < 1000; i++) n += i; n > 0",
+                "ok.js",
+            )
+            .unwrap();
+        assert!(done);
+        assert_eq!(usage("https://time-quota.example").runtimes, 1);
+        drop(engine);
+        assert_eq!(usage("https://time-quota.example").runtimes, 0);
+    }
+}
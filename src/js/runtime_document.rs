@@ -9,6 +9,7 @@ use blitz_traits::events::UiEvent;
 
 use super::environment::JsDomEnvironment;
 use super::events::JsEventHandler;
+use super::session::JsPageRuntime;
 
 /// Wraps an [`HtmlDocument`] and forwards UI events into the JS runtime so DOM event
 /// listeners can observe user input.
@@ -21,6 +22,31 @@ impl RuntimeDocument {
     pub fn new(inner: HtmlDocument, environment: Rc<JsDomEnvironment>) -> Self {
         Self { inner, environment }
     }
+
+    /// Box `inner` and attach `runtime`'s DOM bridge to it. The bridge keeps a pointer to the
+    /// document, so it is only ever given the boxed copy, which does not move.
+    pub fn attach(inner: HtmlDocument, runtime: &mut JsPageRuntime) -> Box<Self> {
+        let mut document = Box::new(Self::new(inner, runtime.environment()));
+        // SAFETY: the document is boxed, so it stays put, and detaches itself when it drops.
+        unsafe { runtime.attach_document(&mut document) };
+        document
+    }
+
+    /// [`Self::attach`] for an environment without a page runtime around it.
+    pub fn attach_to(inner: HtmlDocument, environment: Rc<JsDomEnvironment>) -> Box<Self> {
+        let mut document = Box::new(Self::new(inner, environment));
+        // SAFETY: as in `attach`.
+        unsafe { document.environment.attach_document(&mut document.inner) };
+        document
+    }
+}
+
+impl Drop for RuntimeDocument {
+    fn drop(&mut self) {
+        // The runtime can outlive the document; its scripts then see a detached DOM instead
+        // of freed memory.
+        self.environment.detach_document(&self.inner);
+    }
 }
 
 impl Deref for RuntimeDocument {
@@ -48,7 +74,9 @@ impl Document for RuntimeDocument {
     fn handle_ui_event(&mut self, event: UiEvent) {
         let handler = JsEventHandler::new(Rc::clone(&self.environment));
         let mutator = self.inner.mutate();
-        self.environment.reattach_document(mutator.doc);
+        // SAFETY: `mutator.doc` is this document's own, boxed by `attach`, and is only used
+        // through the bridge during the dispatch below, while the mutator lends it.
+        unsafe { self.environment.reattach_document(mutator.doc) };
         let mut driver = EventDriver::new(mutator, handler);
         driver.handle_ui_event(event);
         if let Err(err) = self.environment.pump() {
@@ -135,6 +163,7 @@ mod tests {
             let request = FetchRequest {
                 source: FetchSource::Url(timer_url.clone()),
                 display_url: timer_url.to_string(),
+                user_agent: None,
             };
 
             let fetched = navigation::execute_fetch(&request, Arc::clone(&net))
@@ -147,23 +176,22 @@ mod tests {
                     .expect("create runtime")
                     .expect("runtime available");
 
-            let mut document = HtmlDocument::from_html(
-                &fetched.contents,
-                DocumentConfig {
-                    base_url: Some(fetched.base_url.clone()),
-                    ..Default::default()
-                },
+            let mut runtime_document = RuntimeDocument::attach(
+                HtmlDocument::from_html(
+                    &fetched.contents,
+                    DocumentConfig {
+                        base_url: Some(fetched.base_url.clone()),
+                        ..Default::default()
+                    },
+                ),
+                &mut runtime,
             );
-
-            runtime.attach_document(&mut document);
             runtime
                 .run_blocking_scripts()
                 .expect("run blocking scripts");
             runtime.environment().pump().expect("initial pump");
 
             let environment = runtime.environment();
-            let mut runtime_document = RuntimeDocument::new(document, environment.clone());
-            environment.reattach_document(&mut runtime_document);
 
             let start_id = lookup_node_id(&mut runtime_document, "start-timer").expect("start id");
             let chain = runtime_document.node_chain(start_id);
@@ -217,6 +245,92 @@ mod tests {
         });
     }
 
+    #[test]
+    fn static_pages_never_wake_and_timers_wake_only_when_due() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let html = "<!DOCTYPE html><html><body><p id=\"note\">static</p></body></html>";
+            let scripts = crate::js::processor::collect_scripts(html).expect("collect scripts");
+            let mut runtime = JsPageRuntime::new(html, &scripts, None)
+                .expect("create runtime")
+                .expect("runtime available");
+            let mut document = RuntimeDocument::attach(
+                HtmlDocument::from_html(html, DocumentConfig::default()),
+                &mut runtime,
+            );
+            runtime.run_blocking_scripts().expect("run scripts");
+            let environment = runtime.environment();
+
+            let hits = Arc::new(AtomicUsize::new(0));
+            let waker = futures_util::task::waker(Arc::new(CountingWaker {
+                hits: Arc::clone(&hits),
+            }));
+            document.poll(Some(std::task::Context::from_waker(&waker)));
+            assert!(!environment.has_pending_work());
+            sleep(Duration::from_millis(50)).await;
+            assert_eq!(
+                hits.load(Ordering::SeqCst),
+                0,
+                "a static page must stay idle"
+            );
+
+            environment
+                .eval(
+                    "clearTimeout(setTimeout(() => {}, 10));\
+                     setTimeout(() => { globalThis.fired = true; }, 30);",
+                    "timers.js",
+                )
+                .expect("schedule timers");
+            assert!(environment.has_pending_timers());
+            assert_eq!(
+                hits.load(Ordering::SeqCst),
+                0,
+                "scheduling or clearing a timer must not wake the loop"
+            );
+
+            sleep(Duration::from_millis(100)).await;
+            assert_eq!(
+                hits.load(Ordering::SeqCst),
+                1,
+                "one wake for the one due timer"
+            );
+            assert!(document.poll(Some(std::task::Context::from_waker(&waker))));
+            assert!(!environment.has_pending_work());
+        });
+    }
+
+    #[test]
+    fn scripts_see_a_detached_dom_once_the_document_is_dropped() {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let html = "<!DOCTYPE html><html><body><p id=\"note\">before</p>\
+                        <script>document.getElementById('note').textContent = 'after';</script>\
+                        </body></html>";
+            let scripts = crate::js::processor::collect_scripts(html).expect("collect scripts");
+            let mut runtime = JsPageRuntime::new(html, &scripts, None)
+                .expect("create runtime")
+                .expect("runtime available");
+            let document = RuntimeDocument::attach(
+                HtmlDocument::from_html(html, DocumentConfig::default()),
+                &mut runtime,
+            );
+            runtime.run_blocking_scripts().expect("run scripts");
+            let environment = runtime.environment();
+            assert!(environment.document_html().unwrap().contains(">after<"));
+
+            drop(document);
+            let detached = environment.eval(
+                "document.getElementById('note').textContent = 'gone';",
+                "detached.js",
+            );
+            assert!(
+                detached.is_err(),
+                "the dropped document must be unreachable"
+            );
+            assert!(!environment.document_html().unwrap().contains("gone"));
+        });
+    }
+
     fn lookup_node_id(document: &mut RuntimeDocument, target_id: &str) -> Option<usize> {
         let mut result = None;
         let root = document.root_node().id;
@@ -48,6 +48,16 @@ pub struct ScriptDescriptor {
     pub kind: ScriptKind,
     pub execution: ScriptExecution,
     pub source: ScriptSource,
+    /// Value of the `nonce` attribute, checked against the page's Content Security Policy.
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Where an external script was fetched from before navigation inlined it. Names the
+    /// script in stack traces.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Source map fetched alongside an external script, as JSON.
+    #[serde(default)]
+    pub source_map: Option<String>,
 }
 
 impl ScriptDescriptor {
@@ -57,6 +67,16 @@ impl ScriptDescriptor {
             kind,
             execution: ScriptExecution::Blocking,
             source: ScriptSource::Inline { code },
+            nonce: None,
+            url: None,
+            source_map: None,
         }
     }
+
+    /// Name the inlined script is evaluated under.
+    pub fn filename(&self) -> String {
+        self.url
+            .clone()
+            .unwrap_or_else(|| format!("inline-script-{}.js", self.index))
+    }
 }
@@ -0,0 +1,77 @@
+//! `element.scrollIntoView()`.
+//!
+//! Script cannot scroll the document itself: the call queues a [`ScrollIntoView`] that the host
+//! takes after the script finishes, and which [`crate::smooth_scroll`] turns into a jump or,
+//! with `behavior: 'smooth'`, an animation.
+
+/// Where an element should end up along one axis of the viewport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollAlign {
+    Start,
+    Center,
+    End,
+    /// Scroll only if the element is not already visible, as little as possible.
+    Nearest,
+}
+
+impl ScrollAlign {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "start" => Some(ScrollAlign::Start),
+            "center" => Some(ScrollAlign::Center),
+            "end" => Some(ScrollAlign::End),
+            "nearest" => Some(ScrollAlign::Nearest),
+            _ => None,
+        }
+    }
+}
+
+/// A `scrollIntoView` call of the page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollIntoView {
+    pub node: usize,
+    /// `behavior: 'smooth'`; `'auto'` and `'instant'` jump.
+    pub smooth: bool,
+    pub block: ScrollAlign,
+    pub inline: ScrollAlign,
+}
+
+/// JS half: `Element.prototype.scrollIntoView`, which validates its options the way browsers
+/// do and hands them to `__frontier_scroll_into_view`.
+pub(crate) const SCROLL_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const nativeScrollIntoView = global.__frontier_scroll_into_view;
+    const frontier = global.frontier;
+    const proto = global.Element && global.Element.prototype;
+    if (!proto) {
+        return;
+    }
+    const BEHAVIORS = ['auto', 'instant', 'smooth'];
+    const ALIGNMENTS = ['start', 'center', 'end', 'nearest'];
+
+    const member = (list, value, name) => {
+        const text = String(value);
+        if (!list.includes(text)) {
+            throw new TypeError(
+                `Failed to execute 'scrollIntoView' on 'Element': '${text}' is not a valid ` +
+                    `value for '${name}'.`,
+            );
+        }
+        return text;
+    };
+
+    proto.scrollIntoView = function scrollIntoView(arg) {
+        // A boolean argument picks block alignment only: true (the default) is 'start'.
+        const options =
+            arg !== null && typeof arg === 'object'
+                ? arg
+                : { block: arg === false ? 'end' : 'start' };
+        const behavior = member(BEHAVIORS, options.behavior ?? 'auto', 'behavior');
+        const block = member(ALIGNMENTS, options.block ?? 'start', 'block');
+        const inline = member(ALIGNMENTS, options.inline ?? 'nearest', 'inline');
+        const handle = frontier.__handleOf(this);
+        nativeScrollIntoView(handle, behavior === 'smooth', block, inline);
+    };
+})();
+"#;
@@ -1,20 +1,44 @@
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Context as AnyhowContext, Result};
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine;
 use blitz_dom::BaseDocument;
 use percent_encoding::percent_decode_str;
-use reqwest::blocking::Client;
 use tracing::{error, warn};
 use url::Url;
 
+use super::blob::LocalFile;
+use super::clock::VirtualClock;
+use super::coverage::JsCoverage;
 use super::environment::JsDomEnvironment;
+use super::gestures::GestureEvent;
+use super::isolation::OriginQuota;
+use super::lifecycle::LifecycleEvent;
+use super::media::MediaPreferences;
+use super::messaging::{PostedMessage, WindowRef};
+use super::nostr::SignerRequest;
+use super::p2p::P2pRequest;
+use super::performance::NavigationTiming;
 use super::processor::ScriptExecutionSummary;
+use super::runtime::{log_uncaught, JsException, JsHeapUsage};
 use super::script::{ScriptDescriptor, ScriptExecution, ScriptKind, ScriptSource};
+use super::scroll::ScrollIntoView;
+use super::sourcemap::{self, SourceMap};
+use super::teardown::TeardownCheck;
+use super::viewport::ViewportMetrics;
+use super::window_open::WindowRequest;
+use crate::adblock::Blocklist;
+use crate::csp::ContentSecurityPolicy;
+use crate::navigation::FetchedDocument;
+use crate::network_log::NetworkLog;
+use crate::p2p::ChannelEvent;
+use crate::permissions::ContentRules;
+use crate::privacy;
 
 /// Owns the JavaScript runtime for a page and coordinates script execution.
 pub struct JsPageRuntime {
@@ -26,7 +50,8 @@ pub struct JsPageRuntime {
 }
 
 impl JsPageRuntime {
-    /// Construct a runtime for the supplied HTML/script manifest.
+    /// Construct a runtime for the supplied HTML/script manifest, held to its origin's
+    /// [`OriginQuota`] (see [`super::isolation`]).
     pub fn new(
         html: &str,
         scripts: &[ScriptDescriptor],
@@ -36,9 +61,6 @@ impl JsPageRuntime {
             return Ok(None);
         }
 
-        let environment = JsDomEnvironment::new(html)
-            .context("failed to create QuickJS environment for page runtime")?;
-
         let base_url = base_url.and_then(|raw| match Url::parse(raw) {
             Ok(url) => Some(url),
             Err(err) => {
@@ -52,66 +74,421 @@ impl JsPageRuntime {
             }
         });
 
-        Ok(Some(Self {
+        let mut runtime = Self::empty(html, base_url)?;
+        runtime.scripts = scripts.to_vec();
+        Ok(Some(runtime))
+    }
+
+    /// A runtime for a fetched document, held to the `Content-Security-Policy` headers it
+    /// arrived with as well as its meta tags.
+    pub fn for_document(document: &FetchedDocument) -> Result<Option<Self>> {
+        let Some(runtime) = Self::new(
+            &document.contents,
+            &document.scripts,
+            Some(document.base_url.as_str()),
+        )?
+        else {
+            return Ok(None);
+        };
+        runtime
+            .environment
+            .add_content_security_policy(ContentSecurityPolicy::from_headers(
+                &document.csp_headers,
+            ))?;
+        Ok(Some(runtime))
+    }
+
+    /// A runtime for a document with no scripts of its own, for callers that drive the page
+    /// from outside, such as pooled headless sessions.
+    pub fn empty(html: &str, base_url: Option<Url>) -> Result<Self> {
+        let environment = JsDomEnvironment::new_with_url(html, base_url.clone())
+            .context("failed to create QuickJS environment for page runtime")?;
+        environment.isolate(OriginQuota::default());
+
+        Ok(Self {
             environment: Rc::new(environment),
-            scripts: scripts.to_vec(),
+            scripts: Vec::new(),
             base_url,
             executed_blocking: false,
             bridge_attached: false,
-        }))
+        })
     }
 
-    /// Execute all classic blocking scripts in document order.
+    /// Execute the page's classic scripts in HTML order: blocking scripts, then
+    /// `readyState = "interactive"` and deferred scripts, then `DOMContentLoaded`, then async
+    /// scripts. Browsers give async scripts no ordering guarantee; running them last keeps
+    /// them from ever delaying `DOMContentLoaded`.
     pub fn run_blocking_scripts(&mut self) -> Result<Option<ScriptExecutionSummary>> {
         if self.executed_blocking {
             return Ok(None);
         }
+        self.executed_blocking = true;
+        self.environment.eval(
+            "frontier.__markParserScripts(); frontier.__markParserStyles();",
+            "frontier-mark-scripts.js",
+        )?;
 
-        let mut executed = 0usize;
-        let mut saw_blocking = false;
+        let (saw_blocking, blocking) = self.run_scripts(ScriptExecution::Blocking);
+        self.environment.pump()?;
+
+        self.dispatch_lifecycle(LifecycleEvent::Interactive);
+        let (saw_deferred, deferred) = self.run_scripts(ScriptExecution::Defer);
+        self.environment.pump()?;
+        self.dispatch_lifecycle(LifecycleEvent::DomContentLoaded);
 
+        let (saw_async, asynchronous) = self.run_scripts(ScriptExecution::Async);
+        self.environment.pump()?;
+
+        if !(saw_blocking || saw_deferred || saw_async) {
+            return Ok(None);
+        }
+
+        let dom_mutations = self.environment.drain_mutations().len();
+        Ok(Some(ScriptExecutionSummary {
+            executed_scripts: blocking + deferred + asynchronous,
+            dom_mutations,
+            compile: self.environment.compile_stats(),
+        }))
+    }
+
+    /// Run every classic script with the given scheduling in document order. Returns whether
+    /// any matched and how many evaluated without error.
+    fn run_scripts(&self, execution: ScriptExecution) -> (bool, usize) {
+        let mut saw_any = false;
+        let mut executed = 0usize;
         for descriptor in self.scripts.iter().filter(|descriptor| {
-            descriptor.execution == ScriptExecution::Blocking
-                && descriptor.kind == ScriptKind::Classic
+            descriptor.execution == execution && descriptor.kind == ScriptKind::Classic
         }) {
-            saw_blocking = true;
-            match self.evaluate_blocking_script(descriptor) {
+            saw_any = true;
+            match self.evaluate_script(descriptor) {
                 Ok(()) => executed += 1,
+                Err(err) if err.is::<JsException>() => {
+                    log_uncaught(&err, &descriptor.filename());
+                }
                 Err(err) => {
                     error!(
                         target = "quickjs",
                         script_index = descriptor.index,
                         source = ?descriptor.source,
+                        execution = ?execution,
                         error = %err,
-                        "blocking script execution failed"
+                        "script execution failed"
                     );
                 }
             }
         }
+        (saw_any, executed)
+    }
 
-        if !saw_blocking {
-            self.executed_blocking = true;
-            return Ok(None);
+    /// Fire a lifecycle event, logging script failures instead of propagating them. Returns
+    /// whether a `beforeunload` handler asked to confirm leaving.
+    pub fn dispatch_lifecycle(&self, event: LifecycleEvent) -> bool {
+        match self.environment.dispatch_lifecycle(event) {
+            Ok(prompt) => prompt,
+            Err(err) => {
+                error!(
+                    target = "quickjs",
+                    event = event.name(),
+                    error = %err,
+                    "lifecycle event failed"
+                );
+                false
+            }
         }
+    }
 
-        self.environment.pump()?;
-        let dom_mutations = self.environment.drain_mutations().len();
-        self.executed_blocking = true;
-        Ok(Some(ScriptExecutionSummary {
-            executed_scripts: executed,
-            dom_mutations,
-        }))
+    /// Tell the page its viewport changed, logging script failures instead of propagating them.
+    pub fn set_viewport(&self, metrics: ViewportMetrics) {
+        if let Err(err) = self.environment.set_viewport(metrics) {
+            error!(
+                target = "quickjs",
+                width = metrics.width,
+                height = metrics.height,
+                dpr = metrics.device_pixel_ratio,
+                error = %err,
+                "viewport update failed"
+            );
+        }
+    }
+
+    /// Tell the page the user's colour scheme or motion preference changed, logging script
+    /// failures instead of propagating them.
+    pub fn set_media_preferences(&self, preferences: MediaPreferences) {
+        if let Err(err) = self.environment.set_media_preferences(preferences) {
+            error!(
+                target = "quickjs",
+                color_scheme = preferences.color_scheme.as_str(),
+                reduced_motion = preferences.reduced_motion,
+                error = %err,
+                "media preference update failed"
+            );
+        }
+    }
+
+    /// Start `performance.now()` at the navigation and publish its timing entry.
+    pub fn set_navigation_timing(&self, timing: &NavigationTiming) {
+        if let Err(err) = self.environment.set_navigation_timing(timing) {
+            error!(
+                target = "quickjs",
+                url = %timing.url,
+                error = %err,
+                "navigation timing update failed"
+            );
+        }
+    }
+
+    /// Record the page's `fetch()` requests in `log`.
+    pub fn set_network_log(&self, log: NetworkLog) {
+        self.environment.set_network_log(log);
+    }
+
+    /// Hold the page's own requests to its site settings `rules`.
+    pub fn set_content_rules(&self, rules: ContentRules) {
+        self.environment.set_content_rules(rules);
+    }
+
+    /// Send `user_agent`, which the window emulates, with the page's own requests and show it
+    /// to its script; `None` goes back to Frontier's own.
+    pub fn set_user_agent(&self, user_agent: Option<String>) {
+        self.environment.set_user_agent(user_agent);
+    }
+
+    /// Check the page's own requests against the filter lists in `blocklist`.
+    pub fn set_blocklist(&self, blocklist: Arc<Blocklist>) {
+        self.environment.set_blocklist(blocklist);
+    }
+
+    /// Run the page's timers off `clock`, which automation moves by hand.
+    pub fn set_virtual_clock(&self, clock: VirtualClock) {
+        self.environment.set_virtual_clock(clock);
+    }
+
+    /// Count the page's scripts, from now on, into `coverage`.
+    pub fn set_coverage(&self, coverage: JsCoverage) {
+        if let Err(err) = self.environment.set_coverage(coverage) {
+            error!(target = "quickjs", error = %err, "coverage counter install failed");
+        }
+    }
+
+    /// Stop everything the page started; see [`JsDomEnvironment::shutdown`].
+    pub fn shutdown(&self) -> TeardownCheck {
+        self.environment.shutdown()
+    }
+
+    /// Memory the page's QuickJS runtime holds.
+    pub fn heap_usage(&self) -> JsHeapUsage {
+        self.environment.heap_usage()
     }
 
-    fn evaluate_blocking_script(&self, descriptor: &ScriptDescriptor) -> Result<()> {
+    /// `window.nostr` calls waiting for the host.
+    pub fn take_signer_requests(&self) -> Vec<SignerRequest> {
+        self.environment.take_signer_requests()
+    }
+
+    /// Answer a `window.nostr` call, logging script failures instead of propagating them.
+    pub fn settle_signer_request(&self, id: u32, result: Result<serde_json::Value, String>) {
+        if let Err(err) = self.environment.settle_signer_request(id, result) {
+            error!(
+                target = "quickjs",
+                request = id,
+                error = %err,
+                "signer response failed"
+            );
+        }
+    }
+
+    /// `frontier.connect` and channel calls waiting for the host.
+    pub fn take_p2p_requests(&self) -> Vec<P2pRequest> {
+        self.environment.take_p2p_requests()
+    }
+
+    /// Report a channel event to the page, logging script failures instead of propagating
+    /// them.
+    pub fn deliver_p2p_event(&self, channel: u32, event: &ChannelEvent) {
+        if let Err(err) = self.environment.deliver_p2p_event(channel, event) {
+            error!(
+                target = "quickjs",
+                channel,
+                error = %err,
+                "channel event failed"
+            );
+        }
+    }
+
+    /// `postMessage` calls waiting for the host.
+    pub fn take_posted_messages(&self) -> Vec<PostedMessage> {
+        self.environment.take_posted_messages()
+    }
+
+    /// Mark the page as loaded in an iframe. Call before [`Self::run_blocking_scripts`].
+    pub fn set_framed(&self) {
+        if let Err(err) = self.environment.set_framed(true) {
+            error!(target = "quickjs", error = %err, "frame setup failed");
+        }
+    }
+
+    /// `window.open` and `close()` calls waiting for the host.
+    pub fn take_window_requests(&self) -> Vec<WindowRequest> {
+        self.environment.take_window_requests()
+    }
+
+    /// Whether an `IntersectionObserver` is waiting for a layout.
+    pub fn wants_intersection_update(&self) -> bool {
+        self.environment.wants_intersection_update()
+    }
+
+    /// Deliver `IntersectionObserver` entries for the layout just resolved, logging script
+    /// failures instead of propagating them. Returns whether any entries were delivered.
+    pub fn update_intersections(&self) -> bool {
+        self.environment
+            .update_intersections()
+            .unwrap_or_else(|err| {
+                error!(target = "quickjs", error = %err, "intersection update failed");
+                false
+            })
+    }
+
+    /// `scrollIntoView` calls waiting for the host.
+    pub fn take_scroll_requests(&self) -> Vec<ScrollIntoView> {
+        self.environment.take_scroll_requests()
+    }
+
+    /// File inputs waiting for the host to show their dialog, by node id.
+    pub fn take_file_picker_requests(&self) -> Vec<usize> {
+        self.environment.take_file_picker_requests()
+    }
+
+    /// Read `paths` and make them the selection of the file input at `node`.
+    pub fn set_input_files(&self, node: usize, paths: &[PathBuf]) -> Result<()> {
+        let files = paths
+            .iter()
+            .map(|path| LocalFile::read(path))
+            .collect::<Result<Vec<_>>>()?;
+        if !self.environment.set_input_files(node, &files)? {
+            return Err(anyhow!("node {node} is not a file input"));
+        }
+        Ok(())
+    }
+
+    /// Tell the page the user changed the value of the form control at `node`.
+    pub fn control_changed(&self, node: usize) {
+        if let Err(err) = self.environment.control_changed(node) {
+            error!(target = "quickjs", node, error = %err, "form control change failed");
+        }
+    }
+
+    /// Tell the page the user is changing the value of the form control at `node`.
+    pub fn control_input(&self, node: usize) {
+        if let Err(err) = self.environment.control_input(node) {
+            error!(target = "quickjs", node, error = %err, "form control input failed");
+        }
+    }
+
+    /// Mark the page as a popup, so `window.opener` refers to the window that opened it.
+    /// Call before [`Self::run_blocking_scripts`].
+    pub fn set_opened(&self) {
+        if let Err(err) = self.environment.set_opened(true) {
+            error!(target = "quickjs", error = %err, "popup setup failed");
+        }
+    }
+
+    /// Report the popup `id` as closed to the page that opened it.
+    pub fn set_popup_closed(&self, id: u32) {
+        if let Err(err) = self.environment.set_popup_closed(id) {
+            error!(target = "quickjs", popup = id, error = %err, "popup close failed");
+        }
+    }
+
+    /// Expose or withdraw `contentWindow` on the iframe element `node_id`.
+    pub fn set_frame_loaded(&self, node_id: usize, loaded: bool) {
+        if let Err(err) = self.environment.set_frame_loaded(node_id, loaded) {
+            error!(
+                target = "quickjs",
+                frame = node_id,
+                error = %err,
+                "frame update failed"
+            );
+        }
+    }
+
+    /// Fire `message` on the page's window, logging script failures instead of propagating
+    /// them.
+    pub fn deliver_message(&self, data: &str, origin: &str, source: WindowRef) {
+        if let Err(err) = self.environment.deliver_message(data, origin, source) {
+            error!(
+                target = "quickjs",
+                origin,
+                error = %err,
+                "message delivery failed"
+            );
+        }
+    }
+
+    /// Offer a trackpad gesture to the page, logging script failures instead of propagating
+    /// them. Returns whether the page cancelled it.
+    pub fn dispatch_gesture(
+        &self,
+        event: GestureEvent,
+        target: usize,
+        chain: &[usize],
+        position: (f64, f64),
+    ) -> bool {
+        match self
+            .environment
+            .dispatch_gesture(event, target, chain, position)
+        {
+            Ok(cancelled) => cancelled,
+            Err(err) => {
+                error!(target = "quickjs", error = %err, "gesture dispatch failed");
+                false
+            }
+        }
+    }
+
+    /// Offer files dropped onto the window to the page, logging failures instead of
+    /// propagating them. Files are only read when the page listens for `drop`. Returns
+    /// whether the page took the drop.
+    pub fn drop_files(
+        &self,
+        paths: &[PathBuf],
+        target: usize,
+        chain: &[usize],
+        position: (f64, f64),
+    ) -> bool {
+        if !self.environment.is_listening("drop") {
+            return false;
+        }
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            match LocalFile::read(path) {
+                Ok(file) => files.push(file),
+                Err(err) => warn!(target = "quickjs", error = %err, "skipping dropped file"),
+            }
+        }
+        if files.is_empty() {
+            return false;
+        }
+        match self.environment.drop_files(&files, target, chain, position) {
+            Ok(taken) => taken,
+            Err(err) => {
+                error!(target = "quickjs", error = %err, "file drop failed");
+                false
+            }
+        }
+    }
+
+    fn evaluate_script(&self, descriptor: &ScriptDescriptor) -> Result<()> {
         match &descriptor.source {
             ScriptSource::Inline { code } => {
-                let filename = format!("inline-script-{}.js", descriptor.index);
-                self.environment.eval(code, &filename)
+                let filename = descriptor.filename();
+                self.environment
+                    .load_source_map(code, &filename, descriptor);
+                self.environment.eval_script(code, &filename)
             }
             ScriptSource::External { src } => {
                 let (code, filename) = self.load_external_script(src)?;
-                self.environment.eval(&code, &filename)
+                self.load_external_source_map(src, &code, &filename);
+                self.environment.eval_script(&code, &filename)
             }
         }
     }
@@ -126,6 +503,33 @@ impl JsPageRuntime {
         }
     }
 
+    /// Register the source map of a script loaded here rather than during navigation.
+    fn load_external_source_map(&self, src: &str, code: &str, filename: &str) {
+        let Some(mapping_url) = sourcemap::source_mapping_url(code) else {
+            return;
+        };
+        let script_url = self.resolve_script_url(src).ok();
+        let map_url = match script_url.as_ref().map(|url| url.join(mapping_url)) {
+            Some(Ok(url)) => url,
+            _ => return,
+        };
+        let json = match sourcemap::decode_inline_map(mapping_url) {
+            Some(decoded) => decoded,
+            None => self
+                .load_external_script(map_url.as_str())
+                .map(|(json, _)| json),
+        };
+        match json.and_then(|json| SourceMap::parse(&json, Some(&map_url))) {
+            Ok(map) => self.environment.register_source_map(filename, map),
+            Err(err) => warn!(
+                target = "quickjs",
+                script = %filename,
+                error = %err,
+                "failed to load source map"
+            ),
+        }
+    }
+
     fn resolve_script_url(&self, src: &str) -> Result<Url> {
         if src.trim().is_empty() {
             return Err(anyhow!("script src attribute cannot be empty"));
@@ -194,11 +598,17 @@ impl JsPageRuntime {
     }
 
     fn fetch_script_over_http(&self, url: &Url) -> Result<(String, String)> {
-        let client = Client::builder()
-            .build()
-            .context("building HTTP client for external script")?;
-        let response = client
+        let mut headers = reqwest::header::HeaderMap::new();
+        let user_agent = self.environment.user_agent();
+        privacy::apply_as(
+            &mut headers,
+            url,
+            self.base_url.as_ref(),
+            Some(user_agent.as_str()),
+        );
+        let response = crate::http_client::shared_blocking()?
             .get(url.clone())
+            .headers(headers)
             .send()
             .with_context(|| format!("fetching external script {}", url))?
             .error_for_status()
@@ -253,8 +663,13 @@ impl JsPageRuntime {
     }
 
     /// Attach the runtime to the live Blitz document so subsequent mutations
-    /// operate on the rendered tree.
-    pub fn attach_document(&mut self, document: &mut BaseDocument) {
+    /// operate on the rendered tree. Outside this module, use
+    /// [`RuntimeDocument::attach`](super::runtime_document::RuntimeDocument::attach).
+    ///
+    /// # Safety
+    ///
+    /// As for [`JsDomEnvironment::attach_document`].
+    pub unsafe fn attach_document(&mut self, document: &mut BaseDocument) {
         if self.bridge_attached {
             return;
         }
@@ -0,0 +1,247 @@
+//! Style scoping for shadow trees.
+//!
+//! Blitz renders the composed tree and has one cascade for the whole document, so a
+//! `<style>` inside a shadow root would style the page too. The bridge instead gives every
+//! shadow host a hidden [`HOST_ATTRIBUTE`] and feeds Stylo a copy of each shadow tree
+//! stylesheet whose selectors only match below that host: `p` becomes
+//! `[frontier-shadow-host="7"] p`, `:host(.on)` becomes `[frontier-shadow-host="7"].on` and
+//! `::slotted(span)` becomes `[frontier-shadow-host="7"] slot > span`. Scripts keep seeing
+//! the original text. Document styles still reach into shadow trees.
+
+/// Marks shadow hosts for the scoped selectors. Scripts and serialization never see it.
+pub const HOST_ATTRIBUTE: &str = "frontier-shadow-host";
+
+/// At-rules whose blocks hold style rules that need scoping too.
+const NESTED_AT_RULES: [&str; 5] = ["@media", "@supports", "@layer", "@container", "@document"];
+
+/// The selector matching the shadow host `host_id`.
+pub fn host_selector(host_id: usize) -> String {
+    format!("[{HOST_ATTRIBUTE}=\"{host_id}\"]")
+}
+
+/// `css` with every style rule restricted to the shadow tree of the host `host` selects.
+pub fn scope_css(css: &str, host: &str) -> String {
+    let mut output = String::with_capacity(css.len() + css.len() / 4);
+    scope_rules(css, host, &mut output);
+    output
+}
+
+fn scope_rules(mut rest: &str, host: &str, output: &mut String) {
+    loop {
+        let trimmed = rest.trim_start();
+        output.push_str(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+        if rest.starts_with("/*") {
+            let end = rest.find("*/").map_or(rest.len(), |end| end + 2);
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.is_empty() {
+            return;
+        }
+
+        let Some((prelude, body, after)) = split_rule(rest) else {
+            // An unterminated rule; Stylo drops it anyway.
+            output.push_str(rest);
+            return;
+        };
+        match body {
+            None => output.push_str(&rest[..rest.len() - after.len()]),
+            Some(body) if prelude.starts_with('@') => {
+                output.push_str(prelude);
+                output.push('{');
+                let name = prelude.split_whitespace().next().unwrap_or("");
+                if NESTED_AT_RULES.contains(&name.to_ascii_lowercase().as_str()) {
+                    scope_rules(body, host, output);
+                } else {
+                    output.push_str(body);
+                }
+                output.push('}');
+            }
+            Some(body) => {
+                output.push_str(&scope_selector_list(prelude, host));
+                output.push('{');
+                output.push_str(body);
+                output.push('}');
+            }
+        }
+        rest = after;
+    }
+}
+
+/// Split the rule at the start of `input` into its prelude, its block's contents (`None`
+/// for a `;`-terminated at-rule) and what follows it.
+pub(crate) fn split_rule(input: &str) -> Option<(&str, Option<&str>, &str)> {
+    let mut depth = 0usize;
+    let mut open = None;
+    let mut quote = None;
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        if let Some(q) = quote {
+            if ch == '\\' {
+                chars.next();
+            } else if ch == q {
+                quote = None;
+            }
+            continue;
+        }
+        match ch {
+            '"' | '\'' => quote = Some(ch),
+            '/' if chars.peek().is_some_and(|(_, next)| *next == '*') => {
+                let end = input[index + 2..].find("*/")? + index + 4;
+                while chars.peek().is_some_and(|(at, _)| *at < end) {
+                    chars.next();
+                }
+            }
+            ';' if depth == 0 && open.is_none() => {
+                return Some((&input[..index], None, &input[index + 1..]));
+            }
+            '{' => {
+                if depth == 0 && open.is_none() {
+                    open = Some(index);
+                }
+                depth += 1;
+            }
+            '}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    let open = open?;
+                    return Some((
+                        &input[..open],
+                        Some(&input[open + 1..index]),
+                        &input[index + 1..],
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn scope_selector_list(prelude: &str, host: &str) -> String {
+    split_top_level(prelude, ',')
+        .into_iter()
+        .map(|selector| scope_selector(selector.trim(), host))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn scope_selector(selector: &str, host: &str) -> String {
+    if selector.is_empty() {
+        return String::new();
+    }
+    if selector.contains(":host") {
+        // `:host-context()` is treated as plain `:host`.
+        let selector = replace_functional(selector, ":host-context(", |_| String::new());
+        let selector = replace_functional(&selector, ":host(", |inner| inner.to_string());
+        return selector.replace(":host", host);
+    }
+    if let Some(at) = selector.find("::slotted(") {
+        let before = selector[..at].trim_end();
+        let slot = if before.is_empty() || before.ends_with(['>', '+', '~']) {
+            format!("{before} slot")
+        } else {
+            before.to_string()
+        };
+        let inner = replace_functional(&selector[at..], "::slotted(", |inner| inner.to_string());
+        return format!("{host} {} > {}", slot.trim_start(), inner.trim());
+    }
+    format!("{host} {selector}")
+}
+
+/// Replace each `name(argument)` in `selector` with `map(argument)`, after `:host` for the
+/// `:host` functions.
+fn replace_functional(selector: &str, name: &str, map: impl Fn(&str) -> String) -> String {
+    let mut output = String::new();
+    let mut rest = selector;
+    while let Some(start) = rest.find(name) {
+        output.push_str(&rest[..start]);
+        let inner_start = start + name.len();
+        let mut depth = 1usize;
+        let mut end = None;
+        for (index, ch) in rest[inner_start..].char_indices() {
+            match ch {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(inner_start + index);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+        if name.starts_with(":host") {
+            output.push_str(":host");
+        }
+        output.push_str(&map(&rest[inner_start..end]));
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+pub(crate) fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, ch) in input.char_indices() {
+        match ch {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if ch == separator && depth == 0 => {
+                parts.push(&input[start..index]);
+                start = index + ch.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors_are_anchored_on_the_host() {
+        let host = host_selector(7);
+        assert_eq!(host, "[frontier-shadow-host=\"7\"]");
+        assert_eq!(
+            scope_css("p, .a > b { color: red }", &host),
+            "[frontier-shadow-host=\"7\"] p, [frontier-shadow-host=\"7\"] .a > b{ color: red }"
+        );
+        assert_eq!(
+            scope_css(":host { display: block } :host(.on) p{x:y}", &host),
+            "[frontier-shadow-host=\"7\"]{ display: block } \
+             [frontier-shadow-host=\"7\"].on p{x:y}"
+        );
+        assert_eq!(
+            scope_css("::slotted(span){a:b} slot[name=t]::slotted(*){c:d}", &host),
+            "[frontier-shadow-host=\"7\"] slot > span{a:b} \
+             [frontier-shadow-host=\"7\"] slot[name=t] > *{c:d}"
+        );
+    }
+
+    #[test]
+    fn at_rules_keep_their_shape() {
+        let host = "[h]";
+        assert_eq!(
+            scope_css(
+                "@import url(x.css); @media (min-width: 1px) { p { a: b } } \
+                 @keyframes spin { from { c: d } } /* p { } */ i { e: \"}\" }",
+                host
+            ),
+            "@import url(x.css); @media (min-width: 1px) { [h] p{ a: b } } \
+             @keyframes spin { from { c: d } } /* p { } */ [h] i{ e: \"}\" }"
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Source map (v3) support for readable JavaScript exception locations.
+//!
+//! Bundled scripts end with `//# sourceMappingURL=...`, either an inline base64 `data:` URL or
+//! a sibling `.map` file. Navigation fetches sibling maps alongside external scripts; inline
+//! maps are decoded when the script runs. Each map is registered in the engine's
+//! [`SourceMapRegistry`] under the script's filename, and exception stacks and `console` output
+//! have their `file:line:column` frames rewritten to the original sources.
+//!
+//! Index maps (`sections`) are not supported.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use url::Url;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    #[serde(default)]
+    source_root: Option<String>,
+    #[serde(default)]
+    sources: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    mappings: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mapping {
+    generated_column: u32,
+    source: u32,
+    line: u32,
+    column: u32,
+    name: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalPosition {
+    pub source: String,
+    /// 1-based.
+    pub line: u32,
+    /// 1-based.
+    pub column: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    sources: Vec<String>,
+    names: Vec<String>,
+    /// Mappings per generated line, sorted by generated column.
+    lines: Vec<Vec<Mapping>>,
+}
+
+impl SourceMap {
+    /// Parse a map. Sources resolve against `sourceRoot` and then `map_url` when given.
+    pub fn parse(json: &str, map_url: Option<&Url>) -> Result<Self> {
+        let raw: RawSourceMap = serde_json::from_str(json).context("parsing source map")?;
+        if raw.version != 3 {
+            return Err(anyhow!("unsupported source map version {}", raw.version));
+        }
+        let root = raw.source_root.unwrap_or_default();
+        let sources = raw
+            .sources
+            .into_iter()
+            .map(|source| {
+                let source = source.unwrap_or_default();
+                let joined = if root.is_empty() || source.contains("://") {
+                    source
+                } else {
+                    format!("{}/{source}", root.trim_end_matches('/'))
+                };
+                map_url
+                    .and_then(|base| base.join(&joined).ok())
+                    .map(|url| url.to_string())
+                    .unwrap_or(joined)
+            })
+            .collect();
+        let lines = decode_mappings(&raw.mappings)?;
+        Ok(Self {
+            sources,
+            names: raw.names,
+            lines,
+        })
+    }
+
+    /// Original position of a 1-based generated `line` and `column`. Without a column, the
+    /// first mapping on the line is used.
+    pub fn lookup(&self, line: u32, column: Option<u32>) -> Option<OriginalPosition> {
+        let mappings = self.lines.get(line.checked_sub(1)? as usize)?;
+        let mapping = match column {
+            Some(column) => {
+                let column = column.saturating_sub(1);
+                let index = mappings.partition_point(|m| m.generated_column <= column);
+                mappings.get(index.checked_sub(1)?)?
+            }
+            None => mappings.first()?,
+        };
+        Some(OriginalPosition {
+            source: self.sources.get(mapping.source as usize)?.clone(),
+            line: mapping.line + 1,
+            column: mapping.column + 1,
+            name: mapping
+                .name
+                .and_then(|name| self.names.get(name as usize).cloned()),
+        })
+    }
+}
+
+fn decode_mappings(mappings: &str) -> Result<Vec<Vec<Mapping>>> {
+    let mut lines = Vec::new();
+    // Every field but the generated column is relative to the previous segment of the file.
+    let (mut source, mut line, mut column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    for encoded_line in mappings.split(';') {
+        let mut generated_column = 0i64;
+        let mut segments = Vec::new();
+        for segment in encoded_line
+            .split(',')
+            .filter(|segment| !segment.is_empty())
+        {
+            let fields = decode_vlq(segment)?;
+            generated_column += fields[0];
+            if fields.len() < 4 {
+                continue;
+            }
+            source += fields[1];
+            line += fields[2];
+            column += fields[3];
+            let mapped_name = if let Some(delta) = fields.get(4) {
+                name += delta;
+                Some(to_u32(name)?)
+            } else {
+                None
+            };
+            segments.push(Mapping {
+                generated_column: to_u32(generated_column)?,
+                source: to_u32(source)?,
+                line: to_u32(line)?,
+                column: to_u32(column)?,
+                name: mapped_name,
+            });
+        }
+        segments.sort_by_key(|mapping| mapping.generated_column);
+        lines.push(segments);
+    }
+    Ok(lines)
+}
+
+fn to_u32(value: i64) -> Result<u32> {
+    u32::try_from(value).map_err(|_| anyhow!("source map offset {value} out of range"))
+}
+
+fn decode_vlq(segment: &str) -> Result<Vec<i64>> {
+    let mut values = Vec::with_capacity(5);
+    let mut value = 0i64;
+    let mut shift = 0u32;
+    for byte in segment.bytes() {
+        let digit = BASE64_ALPHABET
+            .iter()
+            .position(|&candidate| candidate == byte)
+            .ok_or_else(|| anyhow!("invalid source map character {:?}", byte as char))?
+            as i64;
+        if shift > 60 {
+            return Err(anyhow!("source map value too large"));
+        }
+        value += (digit & 0b1_1111) << shift;
+        if digit & 0b10_0000 != 0 {
+            shift += 5;
+            continue;
+        }
+        let magnitude = value >> 1;
+        values.push(if value & 1 == 1 {
+            -magnitude
+        } else {
+            magnitude
+        });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        return Err(anyhow!("truncated source map segment {segment}"));
+    }
+    if values.is_empty() {
+        return Err(anyhow!("empty source map segment"));
+    }
+    Ok(values)
+}
+
+/// The URL of the last `//# sourceMappingURL=` comment in `code`.
+pub fn source_mapping_url(code: &str) -> Option<&str> {
+    code.lines().rev().find_map(|line| {
+        let line = line.trim();
+        let rest = line
+            .strip_prefix("//# sourceMappingURL=")
+            .or_else(|| line.strip_prefix("//@ sourceMappingURL="))?;
+        let url = rest.trim();
+        (!url.is_empty()).then_some(url)
+    })
+}
+
+/// Decode a `data:` source mapping URL. Returns `None` for any other URL.
+pub fn decode_inline_map(url: &str) -> Option<Result<String>> {
+    let rest = url.strip_prefix("data:")?;
+    Some((|| {
+        let (metadata, payload) = rest
+            .split_once(',')
+            .ok_or_else(|| anyhow!("inline source map has no payload"))?;
+        let bytes = if metadata.ends_with(";base64") {
+            BASE64
+                .decode(payload.trim())
+                .context("decoding inline source map")?
+        } else {
+            percent_decode_str(payload).collect()
+        };
+        String::from_utf8(bytes).context("inline source map is not UTF-8")
+    })())
+}
+
+/// Source maps by the filename their script was evaluated under.
+#[derive(Clone, Default)]
+pub struct SourceMapRegistry {
+    maps: Rc<RefCell<HashMap<String, SourceMap>>>,
+}
+
+impl SourceMapRegistry {
+    pub fn register(&self, filename: &str, map: SourceMap) {
+        self.maps.borrow_mut().insert(filename.to_string(), map);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.maps.borrow().is_empty()
+    }
+
+    pub fn lookup(
+        &self,
+        filename: &str,
+        line: u32,
+        column: Option<u32>,
+    ) -> Option<OriginalPosition> {
+        self.maps.borrow().get(filename)?.lookup(line, column)
+    }
+
+    /// Map the frame's location to its original source, when a map covers it.
+    pub fn symbolicate(&self, frame: &mut StackFrame) {
+        let Some(original) = self.lookup(&frame.file, frame.line, frame.column) else {
+            return;
+        };
+        frame.generated = Some(format!("{}:{}", frame.file, frame.line));
+        frame.file = original.source;
+        frame.line = original.line;
+        frame.column = Some(original.column);
+        if let Some(name) = original.name {
+            frame.function = Some(name);
+        }
+    }
+
+    /// Rewrite every `at ... (file:line:col)` frame in a printed stack trace.
+    pub fn symbolicate_text(&self, text: &str) -> String {
+        if self.is_empty() {
+            return text.to_string();
+        }
+        text.lines()
+            .map(|line| match StackFrame::parse(line) {
+                Some(mut frame) => {
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    self.symbolicate(&mut frame);
+                    format!("{indent}{frame}")
+                }
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// One `at function (file:line:column)` line of a QuickJS stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: Option<String>,
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+    /// `file:line` in the evaluated script, once mapped to an original source.
+    pub generated: Option<String>,
+}
+
+impl StackFrame {
+    pub fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim().strip_prefix("at ")?;
+        let (function, location) = match rest.strip_suffix(')').and_then(|r| r.rsplit_once(" (")) {
+            Some((function, location)) => (Some(function.to_string()), location),
+            None => (None, rest),
+        };
+        let (head, last) = location.rsplit_once(':')?;
+        let last: u32 = last.parse().ok()?;
+        let (file, line, column) = match head.rsplit_once(':') {
+            Some((file, line)) if line.parse::<u32>().is_ok() => {
+                (file, line.parse().ok()?, Some(last))
+            }
+            _ => (head, last, None),
+        };
+        Some(Self {
+            function: function.filter(|name| !name.is_empty() && name != "<anonymous>"),
+            file: file.to_string(),
+            line,
+            column,
+            generated: None,
+        })
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at ")?;
+        if let Some(function) = &self.function {
+            write!(f, "{function} (")?;
+        }
+        write!(f, "{}:{}", self.file, self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{column}")?;
+        }
+        if self.function.is_some() {
+            write!(f, ")")?;
+        }
+        if let Some(generated) = &self.generated {
+            write!(f, " [{generated}]")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `function add(a, b) {\n  return a + b;\n}` minified to one line.
+    const MAP: &str = r#"{
+        "version": 3,
+        "sources": ["src/math.ts"],
+        "names": ["add", "a", "b"],
+        "mappings": "AAAA,SAASA,IAAIC,EAAGC,GACd,OAAOD,EAAIC"
+    }"#;
+
+    #[test]
+    fn maps_minified_positions_to_original_lines() {
+        let base = Url::parse("https://example.com/dist/app.min.js.map").unwrap();
+        let map = SourceMap::parse(MAP, Some(&base)).unwrap();
+        let position = map.lookup(1, Some(23)).unwrap();
+        assert_eq!(position.source, "https://example.com/dist/src/math.ts");
+        assert_eq!((position.line, position.column), (2, 3));
+        assert_eq!(
+            map.lookup(1, Some(10)).unwrap().name.as_deref(),
+            Some("add")
+        );
+        assert!(map.lookup(2, Some(1)).is_none());
+    }
+
+    #[test]
+    fn rewrites_stack_frames_and_finds_inline_maps() {
+        let encoded = BASE64.encode(MAP);
+        let code = format!(
+            "function add(a,b){{return a+b}}\n\
+             //# sourceMappingURL=data:application/json;base64,{encoded}\n"
+        );
+        let url = source_mapping_url(&code).unwrap();
+        let json = decode_inline_map(url).unwrap().unwrap();
+        let registry = SourceMapRegistry::default();
+        registry.register("app.min.js", SourceMap::parse(&json, None).unwrap());
+
+        let stack = "Error: boom\n    at add (app.min.js:1:23)\n    at other.js:4";
+        assert_eq!(
+            registry.symbolicate_text(stack),
+            "Error: boom\n    at add (src/math.ts:2:3) [app.min.js:1]\n    at other.js:4"
+        );
+        assert_eq!(source_mapping_url("let x = 1;"), None);
+        assert!(decode_inline_map("app.js.map").is_none());
+    }
+}
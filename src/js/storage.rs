@@ -0,0 +1,294 @@
+//! `localStorage` and `document.cookie`.
+//!
+//! Storage areas live in [`LocalStorage::shared`] for the life of the process, one per origin,
+//! so a page finds what an earlier page of its origin stored and automation sessions can read
+//! and seed it. `file:` pages share one area, as in browsers; pages with an opaque origin get
+//! none, and touching `localStorage` there throws a `SecurityError`. Nothing is written to
+//! disk. An area holds at most [`QUOTA_BYTES`] of keys and values; a `setItem` past that
+//! throws a `QuotaExceededError`. `storage` events are not fired in other pages.
+//!
+//! `document.cookie` reads and writes [`CookieJar::shared`] for the document's URL, without
+//! seeing or touching `HttpOnly` cookies.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+use rquickjs::{Ctx, Function};
+use url::{Origin, Url};
+
+use crate::cookies::{self, CookieJar};
+
+/// Bytes of keys and values one storage area may hold, counted in UTF-16 code units like
+/// browsers count them.
+pub const QUOTA_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct LocalStorage {
+    areas: Mutex<HashMap<String, BTreeMap<String, String>>>,
+}
+
+impl LocalStorage {
+    pub fn shared() -> &'static LocalStorage {
+        static STORAGE: OnceLock<LocalStorage> = OnceLock::new();
+        STORAGE.get_or_init(LocalStorage::default)
+    }
+
+    /// The area pages at `url` use, or `None` when their origin is opaque.
+    pub fn area(url: &Url) -> Option<String> {
+        if url.scheme() == "file" {
+            return Some("file://".to_string());
+        }
+        match url.origin() {
+            origin @ Origin::Tuple(..) => Some(origin.ascii_serialization()),
+            Origin::Opaque(_) => None,
+        }
+    }
+
+    pub fn items(&self, area: &str) -> BTreeMap<String, String> {
+        let areas = self.areas.lock().unwrap();
+        areas.get(area).cloned().unwrap_or_default()
+    }
+
+    pub fn get(&self, area: &str, key: &str) -> Option<String> {
+        let areas = self.areas.lock().unwrap();
+        areas.get(area).and_then(|items| items.get(key).cloned())
+    }
+
+    /// Store `value` under `key`, or return `false` when that would take the area over
+    /// [`QUOTA_BYTES`].
+    pub fn set(&self, area: &str, key: &str, value: &str) -> bool {
+        let mut areas = self.areas.lock().unwrap();
+        let items = areas.entry(area.to_string()).or_default();
+        let replaced = items.get(key).map_or(0, |old| units(key) + units(old));
+        if used(items) - replaced + units(key) + units(value) > QUOTA_BYTES {
+            return false;
+        }
+        items.insert(key.to_string(), value.to_string());
+        true
+    }
+
+    pub fn remove(&self, area: &str, key: &str) {
+        if let Some(items) = self.areas.lock().unwrap().get_mut(area) {
+            items.remove(key);
+        }
+    }
+
+    pub fn clear(&self, area: &str) {
+        self.areas.lock().unwrap().remove(area);
+    }
+
+    /// Empty every area.
+    pub fn clear_all(&self) {
+        self.areas.lock().unwrap().clear();
+    }
+}
+
+fn units(text: &str) -> usize {
+    text.encode_utf16().count() * 2
+}
+
+fn used(items: &BTreeMap<String, String>) -> usize {
+    items
+        .iter()
+        .map(|(key, value)| units(key) + units(value))
+        .sum()
+}
+
+/// Natives behind `localStorage` and `document.cookie` for a document at `document_url`.
+pub(crate) fn install_storage_bindings(
+    ctx: &Ctx<'_>,
+    document_url: Option<Url>,
+) -> rquickjs::Result<()> {
+    let global = ctx.globals();
+    if let Some(area) = document_url.as_ref().and_then(LocalStorage::area) {
+        let key = area.clone();
+        let func = Function::new(ctx.clone(), move |name: String| -> Option<String> {
+            LocalStorage::shared().get(&key, &name)
+        })?
+        .with_name("__frontier_storage_get")?;
+        global.set("__frontier_storage_get", func)?;
+
+        let key = area.clone();
+        let func = Function::new(ctx.clone(), move |name: String, value: String| -> bool {
+            LocalStorage::shared().set(&key, &name, &value)
+        })?
+        .with_name("__frontier_storage_set")?;
+        global.set("__frontier_storage_set", func)?;
+
+        let key = area.clone();
+        let func = Function::new(ctx.clone(), move |name: String| {
+            LocalStorage::shared().remove(&key, &name);
+        })?
+        .with_name("__frontier_storage_remove")?;
+        global.set("__frontier_storage_remove", func)?;
+
+        let key = area.clone();
+        let func = Function::new(ctx.clone(), move || {
+            LocalStorage::shared().clear(&key);
+        })?
+        .with_name("__frontier_storage_clear")?;
+        global.set("__frontier_storage_clear", func)?;
+
+        let func = Function::new(ctx.clone(), move || -> Vec<String> {
+            LocalStorage::shared().items(&area).into_keys().collect()
+        })?
+        .with_name("__frontier_storage_keys")?;
+        global.set("__frontier_storage_keys", func)?;
+    }
+
+    let url = document_url.clone();
+    let func = Function::new(ctx.clone(), move || -> String {
+        url.as_ref().map_or_else(String::new, |url| {
+            cookies::serialize(&CookieJar::shared().cookies_for(url, true))
+        })
+    })?
+    .with_name("__frontier_cookie_get")?;
+    global.set("__frontier_cookie_get", func)?;
+
+    let func = Function::new(ctx.clone(), move |cookie: String| {
+        if let Some(url) = &document_url {
+            CookieJar::shared().set(url, &cookie, true);
+        }
+    })?
+    .with_name("__frontier_cookie_set")?;
+    global.set("__frontier_cookie_set", func)?;
+    Ok(())
+}
+
+/// JS half: a `Storage` object behind a proxy, so `localStorage.key = value` and
+/// `localStorage.key` work as well as the methods, and the `document.cookie` accessor.
+pub(crate) const STORAGE_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const cookieGet = global.__frontier_cookie_get;
+    const cookieSet = global.__frontier_cookie_set;
+    const storageClear = global.__frontier_storage_clear;
+    const storageGet = global.__frontier_storage_get;
+    const storageKeys = global.__frontier_storage_keys;
+    const storageRemove = global.__frontier_storage_remove;
+    const storageSet = global.__frontier_storage_set;
+
+    if (global.document) {
+        Object.defineProperty(global.document, 'cookie', {
+            get: () => cookieGet(),
+            set: (value) => cookieSet(String(value)),
+            configurable: true,
+        });
+    }
+
+    const available = typeof storageGet === 'function';
+    const denied = () =>
+        new DOMException('Storage is not available in an opaque origin', 'SecurityError');
+
+    class Storage {
+        get length() {
+            return storageKeys().length;
+        }
+        key(index) {
+            const keys = storageKeys();
+            const i = Number(index);
+            return i >= 0 && i < keys.length ? keys[i] : null;
+        }
+        getItem(key) {
+            const value = storageGet(String(key));
+            return value === undefined ? null : value;
+        }
+        setItem(key, value) {
+            if (!storageSet(String(key), String(value))) {
+                throw new DOMException(
+                    `Setting the value of '${key}' exceeded the quota.`,
+                    'QuotaExceededError'
+                );
+            }
+        }
+        removeItem(key) {
+            storageRemove(String(key));
+        }
+        clear() {
+            storageClear();
+        }
+    }
+    global.Storage = Storage;
+
+    const storage = new Storage();
+    const own = (target, prop) => typeof prop === 'symbol' || prop in target;
+    const local = available
+        ? new Proxy(storage, {
+              get(target, prop) {
+                  if (own(target, prop)) {
+                      const value = Reflect.get(target, prop, target);
+                      return typeof value === 'function' ? value.bind(target) : value;
+                  }
+                  const value = target.getItem(prop);
+                  return value === null ? undefined : value;
+              },
+              set(target, prop, value) {
+                  if (typeof prop === 'symbol') {
+                      return Reflect.set(target, prop, value, target);
+                  }
+                  target.setItem(prop, value);
+                  return true;
+              },
+              deleteProperty(target, prop) {
+                  if (typeof prop !== 'symbol') {
+                      target.removeItem(prop);
+                  }
+                  return true;
+              },
+              has(target, prop) {
+                  return own(target, prop) || target.getItem(prop) !== null;
+              },
+              ownKeys() {
+                  return storageKeys();
+              },
+              getOwnPropertyDescriptor(target, prop) {
+                  const value = typeof prop === 'symbol' ? null : target.getItem(prop);
+                  if (value === null) {
+                      return undefined;
+                  }
+                  return { value, writable: true, enumerable: true, configurable: true };
+              },
+          })
+        : null;
+
+    Object.defineProperty(global, 'localStorage', {
+        get() {
+            if (!local) {
+                throw denied();
+            }
+            return local;
+        },
+        configurable: true,
+    });
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn areas_are_per_origin_and_capped() {
+        let storage = LocalStorage::default();
+        let area = LocalStorage::area(&Url::parse("https://a.example:8443/x").unwrap()).unwrap();
+        assert_eq!(area, "https://a.example:8443");
+        assert_eq!(
+            LocalStorage::area(&Url::parse("file:///tmp/page.html").unwrap()).as_deref(),
+            Some("file://")
+        );
+        assert_eq!(
+            LocalStorage::area(&Url::parse("data:text/html,hi").unwrap()),
+            None
+        );
+
+        assert!(storage.set(&area, "token", "abc"));
+        assert_eq!(storage.get(&area, "token").as_deref(), Some("abc"));
+        assert_eq!(storage.get("https://b.example", "token"), None);
+
+        let big = "x".repeat(QUOTA_BYTES / 2);
+        assert!(!storage.set(&area, "big", &big));
+        assert!(storage.set(&area, "token", &"y".repeat(QUOTA_BYTES / 4)));
+        storage.clear(&area);
+        assert!(storage.items(&area).is_empty());
+    }
+}
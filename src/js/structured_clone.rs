@@ -0,0 +1,360 @@
+//! `structuredClone`, and the encoding `postMessage` uses to carry message data between
+//! documents.
+//!
+//! Values JSON can represent exactly cross as plain JSON, so the host and popups that only
+//! read JSON keep working. Anything else (`undefined`, `NaN`, `-0`, BigInts, Dates, RegExps,
+//! ArrayBuffers and their views, Maps, Sets, Errors, boxed primitives, shared or cyclic
+//! references) is sent as `{"$frontierClone": <record>}`, a tagged tree that the receiving
+//! document rebuilds. Functions, symbols, DOM nodes, events and other host objects throw a
+//! `DataCloneError`, as in browsers.
+//!
+//! ArrayBuffers in the transfer list are copied and then detached, so the sender observes the
+//! same zero-length buffer it would after a real transfer.
+
+use rquickjs::{qjs, Ctx, Value};
+
+/// Detach `buffer` after its contents were copied into a message. Anything that is not an
+/// ArrayBuffer is left alone.
+pub(crate) fn detach_array_buffer(ctx: &Ctx<'_>, buffer: &Value<'_>) {
+    // SAFETY: `buffer` is a live value of this context; QuickJS ignores non-ArrayBuffer objects.
+    unsafe { qjs::JS_DetachArrayBuffer(ctx.as_raw().as_ptr(), buffer.as_raw()) }
+}
+
+/// JS half: `structuredClone(value, { transfer })`, plus `frontier.__serializeMessage(value,
+/// transfer)` and `frontier.__deserializeMessage(text)` for the messaging bootstrap.
+pub(crate) const STRUCTURED_CLONE_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const detachArrayBuffer = global.__frontier_detach_array_buffer;
+    const frontier = global.frontier;
+    const WRAPPER = '$frontierClone';
+    const ERROR_NAMES = [
+        'Error',
+        'EvalError',
+        'RangeError',
+        'ReferenceError',
+        'SyntaxError',
+        'TypeError',
+        'URIError',
+    ];
+    const BASE64 = 'ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/';
+
+    const cloneError = (message) => {
+        if (typeof global.DOMException === 'function') {
+            return new global.DOMException(message, 'DataCloneError');
+        }
+        const error = new Error(message);
+        error.name = 'DataCloneError';
+        return error;
+    };
+    const classOf = (value) => Object.prototype.toString.call(value).slice(8, -1);
+    const isHostObject = (value) =>
+        [
+            'Node',
+            'Event',
+            'EventTarget',
+            'Window',
+            'AbortSignal',
+            'OffscreenCanvas',
+            'ImageBitmap',
+            'CanvasRenderingContext2D',
+        ]
+            .some((name) => typeof global[name] === 'function' && value instanceof global[name]) ||
+        value === global;
+
+    const toBase64 = (bytes) => {
+        let out = '';
+        for (let i = 0; i < bytes.length; i += 3) {
+            const chunk = (bytes[i] << 16) | ((bytes[i + 1] ?? 0) << 8) | (bytes[i + 2] ?? 0);
+            out += BASE64[(chunk >> 18) & 63] + BASE64[(chunk >> 12) & 63];
+            out += i + 1 < bytes.length ? BASE64[(chunk >> 6) & 63] : '=';
+            out += i + 2 < bytes.length ? BASE64[chunk & 63] : '=';
+        }
+        return out;
+    };
+
+    const fromBase64 = (text) => {
+        const padding = text.endsWith('==') ? 2 : text.endsWith('=') ? 1 : 0;
+        const bytes = new Uint8Array((text.length / 4) * 3 - padding);
+        let offset = 0;
+        for (let i = 0; i < text.length; i += 4) {
+            const chunk = [0, 1, 2, 3].reduce(
+                (acc, n) => (acc << 6) | Math.max(BASE64.indexOf(text[i + n]), 0),
+                0,
+            );
+            for (const shift of [16, 8, 0]) {
+                if (offset < bytes.length) {
+                    bytes[offset++] = (chunk >> shift) & 255;
+                }
+            }
+        }
+        return bytes;
+    };
+
+    const encode = (input) => {
+        const ids = new Map();
+        let plain = true;
+
+        const walk = (value) => {
+            switch (typeof value) {
+                case 'undefined':
+                    plain = false;
+                    return { t: 'u' };
+                case 'boolean':
+                case 'string':
+                    return value;
+                case 'number':
+                    if (Number.isFinite(value) && !Object.is(value, -0)) {
+                        return value;
+                    }
+                    plain = false;
+                    return { t: 'n', v: Object.is(value, -0) ? '-0' : String(value) };
+                case 'bigint':
+                    plain = false;
+                    return { t: 'bi', v: value.toString() };
+                case 'symbol':
+                    throw cloneError('Symbol values cannot be cloned');
+                case 'function':
+                    throw cloneError(`${value.name || 'function'} could not be cloned`);
+            }
+            if (value === null) {
+                return null;
+            }
+            if (ids.has(value)) {
+                plain = false;
+                return { t: 'r', id: ids.get(value) };
+            }
+            if (isHostObject(value)) {
+                throw cloneError(`${classOf(value)} object could not be cloned`);
+            }
+            const id = ids.size;
+            ids.set(value, id);
+            const kind = classOf(value);
+            if (kind === 'ArrayBuffer') {
+                plain = false;
+                let bytes;
+                try {
+                    bytes = new Uint8Array(value);
+                } catch (err) {
+                    throw cloneError('A detached ArrayBuffer could not be cloned');
+                }
+                return { t: 'ab', id, v: toBase64(bytes) };
+            }
+            if (ArrayBuffer.isView(value)) {
+                plain = false;
+                const view = kind === 'DataView';
+                return {
+                    t: view ? 'dv' : 'ta',
+                    id,
+                    c: kind,
+                    b: walk(value.buffer),
+                    o: value.byteOffset,
+                    l: view ? value.byteLength : value.length,
+                };
+            }
+            switch (kind) {
+                case 'Date':
+                    plain = false;
+                    return { t: 'd', id, v: value.getTime() };
+                case 'RegExp':
+                    plain = false;
+                    return { t: 're', id, s: value.source, f: value.flags };
+                case 'Boolean':
+                case 'Number':
+                case 'String':
+                case 'BigInt':
+                    plain = false;
+                    return { t: 'bx', id, v: walk(value.valueOf()) };
+                case 'Map': {
+                    plain = false;
+                    const entries = [];
+                    for (const [key, item] of value) {
+                        entries.push([walk(key), walk(item)]);
+                    }
+                    return { t: 'm', id, v: entries };
+                }
+                case 'Set': {
+                    plain = false;
+                    const items = [];
+                    for (const item of value) {
+                        items.push(walk(item));
+                    }
+                    return { t: 's', id, v: items };
+                }
+                case 'Error':
+                    plain = false;
+                    return {
+                        t: 'e',
+                        id,
+                        n: ERROR_NAMES.includes(value.name) ? value.name : 'Error',
+                        m: value.message === undefined ? '' : String(value.message),
+                        s: typeof value.stack === 'string' ? value.stack : undefined,
+                    };
+                case 'Promise':
+                case 'WeakMap':
+                case 'WeakSet':
+                case 'WeakRef':
+                    throw cloneError(`${kind} object could not be cloned`);
+                case 'Array': {
+                    const items = [];
+                    for (let i = 0; i < value.length; i += 1) {
+                        items.push(walk(value[i]));
+                    }
+                    return { t: 'a', id, v: items };
+                }
+            }
+            if (typeof value.toJSON === 'function') {
+                plain = false;
+            }
+            const fields = {};
+            for (const key of Object.keys(value)) {
+                fields[key] = walk(value[key]);
+            }
+            return { t: 'o', id, v: fields };
+        };
+
+        const tree = walk(input);
+        if (plain && !(input !== null && typeof input === 'object' && WRAPPER in input)) {
+            return JSON.stringify(input);
+        }
+        return JSON.stringify({ [WRAPPER]: tree });
+    };
+
+    const define = (target, key, value) =>
+        Object.defineProperty(target, key, {
+            value,
+            writable: true,
+            enumerable: true,
+            configurable: true,
+        });
+
+    const decode = (tree) => {
+        const seen = new Map();
+        const remember = (node, value) => {
+            seen.set(node.id, value);
+            return value;
+        };
+        const read = (node) => {
+            if (node === null || typeof node !== 'object') {
+                return node;
+            }
+            switch (node.t) {
+                case 'u':
+                    return undefined;
+                case 'n':
+                    return node.v === '-0' ? -0 : Number(node.v);
+                case 'bi':
+                    return BigInt(node.v);
+                case 'r':
+                    return seen.get(node.id);
+                case 'ab':
+                    return remember(node, fromBase64(node.v).buffer);
+                case 'ta': {
+                    const Ctor = typeof global[node.c] === 'function' ? global[node.c] : Uint8Array;
+                    return remember(node, new Ctor(read(node.b), node.o, node.l));
+                }
+                case 'dv':
+                    return remember(node, new DataView(read(node.b), node.o, node.l));
+                case 'd':
+                    return remember(node, new Date(node.v));
+                case 're':
+                    return remember(node, new RegExp(node.s, node.f));
+                case 'bx':
+                    return remember(node, Object(read(node.v)));
+                case 'm': {
+                    const map = remember(node, new Map());
+                    for (const [key, item] of node.v) {
+                        map.set(read(key), read(item));
+                    }
+                    return map;
+                }
+                case 's': {
+                    const set = remember(node, new Set());
+                    for (const item of node.v) {
+                        set.add(read(item));
+                    }
+                    return set;
+                }
+                case 'e': {
+                    const Ctor = ERROR_NAMES.includes(node.n) ? global[node.n] : Error;
+                    const error = remember(node, new Ctor(node.m));
+                    if (node.s !== undefined) {
+                        Object.defineProperty(error, 'stack', {
+                            value: node.s,
+                            writable: true,
+                            configurable: true,
+                        });
+                    }
+                    return error;
+                }
+                case 'a': {
+                    const array = remember(node, new Array(node.v.length));
+                    node.v.forEach((item, index) => {
+                        array[index] = read(item);
+                    });
+                    return array;
+                }
+                case 'o': {
+                    const object = remember(node, {});
+                    for (const key of Object.keys(node.v)) {
+                        define(object, key, read(node.v[key]));
+                    }
+                    return object;
+                }
+            }
+            throw cloneError(`Unknown clone record '${node.t}'`);
+        };
+        return read(tree);
+    };
+
+    const transferList = (transfer) => {
+        if (transfer === undefined || transfer === null) {
+            return [];
+        }
+        if (typeof transfer[Symbol.iterator] !== 'function') {
+            throw new TypeError('The transfer list must be iterable');
+        }
+        const list = [...transfer];
+        list.forEach((item, index) => {
+            if (classOf(item) !== 'ArrayBuffer') {
+                throw cloneError('Only ArrayBuffers can be transferred');
+            }
+            if (list.indexOf(item) !== index) {
+                throw cloneError('An ArrayBuffer is listed twice in the transfer list');
+            }
+        });
+        return list;
+    };
+
+    const serialize = (value, transfer) => {
+        const list = transferList(transfer);
+        const text = encode(value);
+        list.forEach((buffer) => detachArrayBuffer(buffer));
+        return text;
+    };
+
+    const deserialize = (text) => {
+        const parsed = JSON.parse(text);
+        if (
+            parsed !== null &&
+            typeof parsed === 'object' &&
+            !Array.isArray(parsed) &&
+            Object.keys(parsed).length === 1 &&
+            WRAPPER in parsed
+        ) {
+            return decode(parsed[WRAPPER]);
+        }
+        return parsed;
+    };
+
+    global.structuredClone = function structuredClone(value, options) {
+        if (arguments.length === 0) {
+            throw new TypeError('structuredClone requires a value to clone');
+        }
+        return deserialize(serialize(value, options ? options.transfer : undefined));
+    };
+
+    frontier.__serializeMessage = serialize;
+    frontier.__deserializeMessage = deserialize;
+})();
+"#;
@@ -0,0 +1,94 @@
+//! Accounting for the async work a page spawns, so replacing the document can show that none
+//! of it outlives the page.
+//!
+//! Every timer and `fetch()` task holds a [`TaskGuard`] from the page's [`TaskTracker`] for as
+//! long as its future exists. [`JsDomEnvironment::shutdown`](super::environment::JsDomEnvironment::shutdown)
+//! aborts them and returns a [`TeardownCheck`]; once tokio has dropped the aborted futures
+//! its count reaches zero.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long aborted tasks get to be dropped before [`TeardownCheck::verify`] complains.
+pub const TEARDOWN_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Default)]
+pub(crate) struct TaskTracker {
+    live: Arc<AtomicUsize>,
+}
+
+impl TaskTracker {
+    /// Token for one spawned task; move it into the task's future.
+    pub(crate) fn guard(&self) -> TaskGuard {
+        self.live.fetch_add(1, Ordering::SeqCst);
+        TaskGuard {
+            live: Arc::clone(&self.live),
+        }
+    }
+
+    pub(crate) fn check(&self, url: Option<String>) -> TeardownCheck {
+        TeardownCheck {
+            live: Arc::clone(&self.live),
+            url,
+        }
+    }
+}
+
+pub(crate) struct TaskGuard {
+    live: Arc<AtomicUsize>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Outstanding tasks of a page that has been shut down.
+pub struct TeardownCheck {
+    live: Arc<AtomicUsize>,
+    url: Option<String>,
+}
+
+impl TeardownCheck {
+    pub fn outstanding(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    /// Log tasks that survived their page; debug builds treat that as a bug and panic.
+    pub fn verify(&self) {
+        let outstanding = self.outstanding();
+        if outstanding == 0 {
+            return;
+        }
+        let url = self.url.as_deref().unwrap_or("about:blank");
+        tracing::error!(
+            target = "quickjs",
+            url = %url,
+            outstanding,
+            "page tasks outlived their document"
+        );
+        debug_assert_eq!(outstanding, 0, "{outstanding} tasks outlived {url}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_guards_until_dropped() {
+        let tracker = TaskTracker::default();
+        let check = tracker.check(Some("https://example.com/".to_string()));
+        let first = tracker.guard();
+        let second = tracker.guard();
+        assert_eq!(check.outstanding(), 2);
+        drop(first);
+        drop(tracker);
+        assert_eq!(check.outstanding(), 1);
+        drop(second);
+        assert_eq!(check.outstanding(), 0);
+        check.verify();
+    }
+}
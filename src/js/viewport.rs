@@ -0,0 +1,106 @@
+//! Window viewport metrics exposed to page script.
+//!
+//! The host reports the logical size of the content area through
+//! [`JsDomEnvironment::set_viewport`](super::environment::JsDomEnvironment::set_viewport) when a
+//! document is attached and whenever the window is resized. The bootstrap keeps
+//! `innerWidth`/`innerHeight` (and the `outer*` and `documentElement.client*` mirrors) and
+//! `devicePixelRatio` in sync, fires `resize` on `window` when the size actually changes, and
+//! has `matchMedia` lists evaluated again after any change.
+
+/// Viewport as page script sees it: CSS pixels plus the device pixels per CSS pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportMetrics {
+    pub width: f64,
+    pub height: f64,
+    /// Window scale factor times page zoom.
+    pub device_pixel_ratio: f64,
+}
+
+impl ViewportMetrics {
+    pub fn new(width: f64, height: f64, device_pixel_ratio: f64) -> Self {
+        Self {
+            width,
+            height,
+            device_pixel_ratio,
+        }
+    }
+}
+
+/// JS half of the viewport: `frontier.__setViewport(width, height, dpr)` returns whether any
+/// metric changed. Only a size change dispatches `resize`; a DPR change alone (dragging the
+/// window to another monitor) does not, though it still reaches `resolution` media queries.
+pub(crate) const VIEWPORT_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const frontier = global.frontier;
+    // winit's default window size, used until the host reports the real one.
+    let width = 800;
+    let height = 600;
+    let dpr = 1;
+
+    const defineMetric = (target, name, read) => {
+        Object.defineProperty(target, name, { get: read, configurable: true });
+    };
+
+    defineMetric(global, 'innerWidth', () => width);
+    defineMetric(global, 'innerHeight', () => height);
+    defineMetric(global, 'outerWidth', () => width);
+    defineMetric(global, 'outerHeight', () => height);
+    defineMetric(global, 'devicePixelRatio', () => dpr);
+
+    if (global.Element && global.Element.prototype) {
+        const proto = global.Element.prototype;
+        const isRoot = (el) => global.document && el === global.document.documentElement;
+        const previousWidth = Object.getOwnPropertyDescriptor(proto, 'clientWidth');
+        const previousHeight = Object.getOwnPropertyDescriptor(proto, 'clientHeight');
+        defineMetric(proto, 'clientWidth', function () {
+            if (isRoot(this)) {
+                return width;
+            }
+            return previousWidth && previousWidth.get ? previousWidth.get.call(this) : 0;
+        });
+        defineMetric(proto, 'clientHeight', function () {
+            if (isRoot(this)) {
+                return height;
+            }
+            return previousHeight && previousHeight.get ? previousHeight.get.call(this) : 0;
+        });
+    }
+
+    // Media queries are evaluated after `resize` handlers ran, as browsers do.
+    const refreshMediaQueries = () => {
+        if (typeof frontier.__refreshMediaQueries === 'function') {
+            frontier.__refreshMediaQueries();
+        }
+    };
+
+    frontier.__setViewport = (nextWidth, nextHeight, nextDpr) => {
+        const w = Math.max(0, Math.round(Number(nextWidth) || 0));
+        const h = Math.max(0, Math.round(Number(nextHeight) || 0));
+        const ratio = Number(nextDpr) > 0 ? Number(nextDpr) : dpr;
+        const dprChanged = ratio !== dpr;
+        dpr = ratio;
+        if (w === width && h === height) {
+            if (dprChanged) {
+                refreshMediaQueries();
+            }
+            return dprChanged;
+        }
+        width = w;
+        height = h;
+        if (typeof global.dispatchEvent === 'function' && typeof global.Event === 'function') {
+            global.dispatchEvent(new global.Event('resize'));
+        }
+        const handler = global.onresize;
+        if (typeof handler === 'function') {
+            try {
+                handler.call(global, { type: 'resize', target: global });
+            } catch (err) {
+                console.error(`onresize handler threw: ${err && err.stack ? err.stack : err}`);
+            }
+        }
+        refreshMediaQueries();
+        return true;
+    };
+})();
+"#;
@@ -0,0 +1,105 @@
+//! `window.open` and the popup-blocking policy.
+//!
+//! A page may open a popup only within [`USER_ACTIVATION_TIMEOUT`] of a click or key press
+//! it received, and each popup uses up that activation. The environment queues a
+//! [`WindowRequest`] for every call, blocked or not, and the host (see [`crate::popups`])
+//! opens the window, records blocked calls and reports popups that went away.
+
+use std::time::Duration;
+
+use url::Url;
+
+/// How long after a click or key press a page may call `window.open`.
+pub const USER_ACTIVATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A `window.open` or `close()` call of the page.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowRequest {
+    /// `window.open(url, target)`. `id` names the popup in `postMessage` and later requests;
+    /// a call without user activation is queued with `user_activated: false` and returned
+    /// `null` to the page.
+    Open {
+        id: u32,
+        url: Url,
+        target: String,
+        user_activated: bool,
+    },
+    /// `close()` on the proxy `window.open` returned.
+    Close { id: u32 },
+    /// `window.close()` from the page itself; only honoured in popups.
+    CloseSelf,
+}
+
+/// Whether `window.open` with this target replaces the current page instead of opening a
+/// new window.
+pub fn targets_current_window(target: &str) -> bool {
+    target.eq_ignore_ascii_case("_self")
+        || target.eq_ignore_ascii_case("_top")
+        || target.eq_ignore_ascii_case("_parent")
+}
+
+/// JS half: `window.open`, `window.close` and `frontier.__setPopupClosed(id)` for the host.
+/// Popups get a frozen proxy with `postMessage`, `close()` and `closed`.
+pub(crate) const WINDOW_OPEN_BOOTSTRAP: &str = r#"
+(() => {
+    const global = globalThis;
+    const popupClose = global.__frontier_popup_close;
+    const windowClose = global.__frontier_window_close;
+    const windowOpen = global.__frontier_window_open;
+    const frontier = global.frontier;
+    const popups = new Map();
+
+    global.open = function (url = '', target = '_blank') {
+        const name = target === undefined || target === null || target === '' ? '_blank' : String(target);
+        const id = windowOpen(String(url ?? ''), name);
+        if (id === null || id === undefined) {
+            return null;
+        }
+        if (id === 0) {
+            return global;
+        }
+        const state = { closed: false };
+        const key = `popup:${id}`;
+        const proxy = Object.freeze({
+            postMessage: (message, options, transfer) =>
+                frontier.__postMessage(key, message, options, transfer),
+            close: () => {
+                if (!state.closed) {
+                    state.closed = true;
+                    popupClose(id);
+                }
+            },
+            get closed() {
+                return state.closed;
+            },
+        });
+        popups.set(id, state);
+        frontier.__registerWindow(key, proxy);
+        return proxy;
+    };
+
+    global.close = function () {
+        windowClose();
+    };
+
+    frontier.__setPopupClosed = (id) => {
+        const state = popups.get(Number(id));
+        if (state) {
+            state.closed = true;
+        }
+    };
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_targets_open_new_windows() {
+        assert!(targets_current_window("_self"));
+        assert!(targets_current_window("_TOP"));
+        assert!(!targets_current_window("_blank"));
+        assert!(!targets_current_window("preview"));
+    }
+}
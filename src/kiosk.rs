@@ -0,0 +1,140 @@
+//! `frontier --kiosk <url>`: one site, fullscreen, for demo booths and embedded displays.
+//!
+//! The window opens borderless fullscreen on `<url>` without the URL bar. Navigation stays on
+//! that URL's origin and the origins listed as `kiosk_origins` in `frontier.toml`; anything
+//! else, `frontier://` pages included, is refused, and a redirect off the list shows an error
+//! instead of the page. Browser shortcuts, the application menu, blitz's debugging views, popups
+//! and background mode are all off.
+//!
+//! The process started with [`FLAG`] only supervises: it runs the browser as a child with
+//! [`SUPERVISED_FLAG`] and, whenever the child exits abnormally (a panic, a crash, being
+//! killed), starts it again on the kiosk URL. Crashes that follow each other quickly wait
+//! longer before the restart, up to [`MAX_RESTART_DELAY`]. A clean exit ends the kiosk.
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use url::{Origin, Url};
+
+pub const FLAG: &str = "--kiosk";
+/// Marks the browser process a kiosk supervisor started.
+pub const SUPERVISED_FLAG: &str = "--kiosk-supervised";
+
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+/// A browser that ran at least this long before crashing is restarted straight away.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// Where a kiosk may go: the start page's origin plus the configured extra origins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KioskPolicy {
+    home: Url,
+    origins: Vec<Origin>,
+}
+
+impl KioskPolicy {
+    pub fn new(home: Url, extra_origins: &[Url]) -> Self {
+        let origins = std::iter::once(&home)
+            .chain(extra_origins)
+            .map(Url::origin)
+            .filter(Origin::is_tuple)
+            .collect();
+        Self { home, origins }
+    }
+
+    pub fn home(&self) -> &Url {
+        &self.home
+    }
+
+    /// Whether `target`, a URL or URL bar input, may be loaded. Local files have no origin to
+    /// compare, so the only one allowed is the start page itself.
+    pub fn allows(&self, target: &str) -> bool {
+        let Ok(url) = Url::parse(target.trim()) else {
+            return false;
+        };
+        if url.scheme() == "file" {
+            return self.home.scheme() == "file" && url.path() == self.home.path();
+        }
+        self.origins.contains(&url.origin())
+    }
+}
+
+/// Run `frontier` with `args` plus [`SUPERVISED_FLAG`] until it exits cleanly.
+pub fn supervise(args: &[String]) -> Result<()> {
+    let exe = std::env::current_exe().context("failed to locate the frontier executable")?;
+    let mut quick_crashes = 0;
+    loop {
+        let started = Instant::now();
+        let status = Command::new(&exe)
+            .arg(SUPERVISED_FLAG)
+            .args(args)
+            .status()
+            .context("failed to start the kiosk browser")?;
+        if status.success() {
+            return Ok(());
+        }
+        quick_crashes = if started.elapsed() < STABLE_RUN {
+            quick_crashes + 1
+        } else {
+            0
+        };
+        let delay = restart_delay(quick_crashes);
+        eprintln!(
+            "Kiosk browser exited with {status}; restarting in {}s",
+            delay.as_secs()
+        );
+        thread::sleep(delay);
+    }
+}
+
+/// How long to wait before restarting after `quick_crashes` crashes in a row that each came
+/// soon after the browser started.
+pub fn restart_delay(quick_crashes: u32) -> Duration {
+    if quick_crashes == 0 {
+        return Duration::ZERO;
+    }
+    MIN_RESTART_DELAY
+        .saturating_mul(1 << (quick_crashes - 1).min(8))
+        .min(MAX_RESTART_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins_navigation_to_the_allowed_origins() {
+        let home = Url::parse("https://booth.example/welcome").unwrap();
+        let extra = [Url::parse("https://cdn.example:8443/").unwrap()];
+        let policy = KioskPolicy::new(home, &extra);
+
+        assert!(policy.allows("https://booth.example/"));
+        assert!(policy.allows("https://booth.example/products?id=2#top"));
+        assert!(policy.allows("https://cdn.example:8443/video"));
+        assert!(!policy.allows("http://booth.example/"));
+        assert!(!policy.allows("https://cdn.example/video"));
+        assert!(!policy.allows("https://elsewhere.example/"));
+        assert!(!policy.allows("frontier://settings"));
+        assert!(!policy.allows("about:memory"));
+        assert!(!policy.allows("~name"));
+    }
+
+    #[test]
+    fn file_kiosks_stay_on_their_page() {
+        let home = Url::parse("file:///srv/kiosk/index.html").unwrap();
+        let policy = KioskPolicy::new(home, &[]);
+        assert!(policy.allows("file:///srv/kiosk/index.html#slide-2"));
+        assert!(!policy.allows("file:///etc/passwd"));
+    }
+
+    #[test]
+    fn quick_crashes_back_off() {
+        assert_eq!(restart_delay(0), Duration::ZERO);
+        assert_eq!(restart_delay(1), Duration::from_secs(1));
+        assert_eq!(restart_delay(3), Duration::from_secs(4));
+        assert_eq!(restart_delay(6), MAX_RESTART_DELAY);
+        assert_eq!(restart_delay(u32::MAX), MAX_RESTART_DELAY);
+    }
+}
@@ -1,20 +1,78 @@
 // Library exports for integration tests, automation harnesses, and WebDriver glue.
 
 #[cfg(feature = "gpu")]
-pub use anyrender_vello::VelloWindowRenderer as WindowRenderer;
+pub use anyrender_vello::VelloWindowRenderer as GpuRenderer;
 #[cfg(feature = "cpu-base")]
-pub use anyrender_vello_cpu::VelloCpuWindowRenderer as WindowRenderer;
+pub use anyrender_vello_cpu::VelloCpuWindowRenderer as CpuRenderer;
+/// The renderer a window gets when none is chosen at runtime (see [`renderer::select`]).
+#[cfg(feature = "gpu")]
+pub type WindowRenderer = GpuRenderer;
+#[cfg(all(feature = "cpu-base", not(feature = "gpu")))]
+pub type WindowRenderer = CpuRenderer;
 
 pub use blitz_shell::{create_default_event_loop, WindowConfig};
 
+pub mod accessibility;
+pub mod adblock;
+pub mod app_menu;
 pub mod automation;
 pub mod automation_client;
+pub mod background;
+pub mod bench;
 pub mod chrome;
+pub mod cookies;
+pub mod crash_recovery;
+pub mod csp;
+pub mod damage;
+pub mod debug_channel;
+pub mod dev_server;
+pub mod diagnostics;
+pub mod element_state;
+pub mod form_widgets;
+pub mod frame_stats;
+pub mod frames;
+pub mod gestures;
+pub mod har;
+pub mod highlight;
+pub mod http_client;
+pub mod identities;
 pub mod input;
 pub mod js;
+pub mod kiosk;
+pub mod load_trace;
+pub mod markdown;
+pub mod name_search;
 pub mod navigation;
+pub mod network_log;
+pub mod p2p;
+pub mod page_cache;
+pub mod pdf;
+pub mod permissions;
+pub mod popups;
+pub mod preferences;
+pub mod prefetch;
+pub mod print;
+pub mod privacy;
+pub mod proxy;
+pub mod proxy_tunnel;
+pub mod publish;
+pub mod publish_queue;
+pub mod quick_open;
 pub mod readme_application;
+pub mod recording;
+pub mod relays;
+pub mod renderer;
+pub mod screenshot;
+pub mod scroll_anchor;
+pub mod search;
+pub mod signer;
+pub mod smooth_scroll;
+pub mod startup;
+pub mod storage_policy;
+pub mod subresource_loader;
+pub mod user_agent;
 pub mod webdriver;
+pub mod window_state;
 pub mod wpt;
 
 pub use chrome::wrap_with_url_bar;
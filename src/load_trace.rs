@@ -0,0 +1,176 @@
+//! Timing for the phases of one page load: resolve → fetch → parse → scripts → first paint
+//! (blitz resolves style and layout inside the first redraw, so they are part of it).
+//!
+//! Every phase runs inside a tracing span named after it and carrying the load's `load_id`,
+//! so `frontier --trace-output trace.json` produces a Chrome trace-event file in which one load
+//! can be picked out across threads. Durations are also collected here and logged as a
+//! single summary line once the first paint finishes.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, info_span, Instrument, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Turning the typed input into a request.
+    Resolve,
+    /// Network or file load, including blocking external scripts.
+    Fetch,
+    /// Building the DOM from HTML.
+    Parse,
+    /// Creating the JS runtime and running blocking scripts.
+    Scripts,
+    /// Style, layout and paint of the first frame.
+    FirstPaint,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Resolve,
+        Phase::Fetch,
+        Phase::Parse,
+        Phase::Scripts,
+        Phase::FirstPaint,
+    ];
+
+    fn span(self, load_id: u64) -> Span {
+        match self {
+            Phase::Resolve => info_span!("resolve", load_id),
+            Phase::Fetch => info_span!("fetch", load_id),
+            Phase::Parse => info_span!("parse", load_id),
+            Phase::Scripts => info_span!("scripts", load_id),
+            Phase::FirstPaint => info_span!("first_paint", load_id),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Resolve => "resolve",
+            Phase::Fetch => "fetch",
+            Phase::Parse => "parse",
+            Phase::Scripts => "scripts",
+            Phase::FirstPaint => "first_paint",
+        }
+    }
+}
+
+struct TraceState {
+    started: Instant,
+    durations: [Duration; 5],
+    finished: bool,
+}
+
+/// One page load. Cheap to clone so async fetch tasks can record into it.
+#[derive(Clone)]
+pub struct LoadTrace {
+    load_id: u64,
+    url: Arc<str>,
+    state: Arc<Mutex<TraceState>>,
+}
+
+impl LoadTrace {
+    pub fn start(load_id: u64, url: &str) -> Self {
+        Self {
+            load_id,
+            url: Arc::from(url),
+            state: Arc::new(Mutex::new(TraceState {
+                started: Instant::now(),
+                durations: [Duration::ZERO; 5],
+                finished: false,
+            })),
+        }
+    }
+
+    fn record(&self, phase: Phase, elapsed: Duration) {
+        let index = Phase::ALL
+            .iter()
+            .position(|candidate| *candidate == phase)
+            .expect("every phase is listed");
+        self.state.lock().unwrap().durations[index] += elapsed;
+    }
+
+    /// Run `work` inside `phase`'s span and add its duration to the phase.
+    pub fn time<T>(&self, phase: Phase, work: impl FnOnce() -> T) -> T {
+        let _entered = phase.span(self.load_id).entered();
+        let started = Instant::now();
+        let result = work();
+        self.record(phase, started.elapsed());
+        result
+    }
+
+    /// [`LoadTrace::time`] for a future.
+    pub async fn time_async<F: Future>(&self, phase: Phase, work: F) -> F::Output {
+        let started = Instant::now();
+        let result = work.instrument(phase.span(self.load_id)).await;
+        self.record(phase, started.elapsed());
+        result
+    }
+
+    /// Log the summary line. Only the first call does anything.
+    pub fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        state.finished = true;
+        let [resolve, fetch, parse, scripts, first_paint] = state.durations.map(millis);
+        info!(
+            target = "load",
+            load_id = self.load_id,
+            url = %self.url,
+            resolve_ms = resolve,
+            fetch_ms = fetch,
+            parse_ms = parse,
+            scripts_ms = scripts,
+            first_paint_ms = first_paint,
+            total_ms = millis(state.started.elapsed()),
+            "page load"
+        );
+    }
+
+    /// `phase=ms` pairs in load order, for tests and diagnostics.
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        Phase::ALL
+            .iter()
+            .zip(state.durations)
+            .map(|(phase, duration)| format!("{}={:.1}", phase.name(), millis(duration)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_accumulate_and_finish_once() {
+        let trace = LoadTrace::start(7, "https://example.com/");
+        let value = trace.time(Phase::Parse, || {
+            std::thread::sleep(Duration::from_millis(2));
+            42
+        });
+        trace.time(Phase::Scripts, || {});
+        trace.time(Phase::Scripts, || {
+            std::thread::sleep(Duration::from_millis(1))
+        });
+
+        assert_eq!(value, 42);
+        let summary = trace.summary();
+        assert!(
+            summary.starts_with("resolve=0.0 fetch=0.0 parse="),
+            "{summary}"
+        );
+        assert!(!summary.contains("scripts=0.0"), "{summary}");
+        trace.finish();
+        trace.finish();
+        assert!(trace.state.lock().unwrap().finished);
+    }
+}
@@ -1,30 +1,94 @@
+#[allow(dead_code)]
+mod accessibility;
+mod adblock;
+mod app_menu;
 mod automation;
+mod background;
+mod bench;
 #[allow(dead_code)]
 mod chrome;
+mod cookies;
+mod crash_recovery;
+mod csp;
+mod damage;
+mod debug_channel;
+#[allow(dead_code)]
+mod dev_server;
+mod diagnostics;
+mod element_state;
+mod form_widgets;
+mod frame_stats;
+mod frames;
+mod gestures;
+mod har;
+mod highlight;
+mod http_client;
+mod identities;
 mod input;
 mod js;
+mod kiosk;
+mod load_trace;
+mod markdown;
+mod name_search;
 mod navigation;
+mod network_log;
+mod p2p;
+mod page_cache;
+mod permissions;
+mod popups;
+mod preferences;
+mod prefetch;
+mod privacy;
+mod proxy;
+mod proxy_tunnel;
+mod publish;
+mod publish_queue;
+mod quick_open;
 mod readme_application;
+mod recording;
+mod relays;
+mod renderer;
+mod screenshot;
+mod scroll_anchor;
+mod search;
+mod signer;
+mod smooth_scroll;
+mod startup;
+mod storage_policy;
+mod subresource_loader;
+mod user_agent;
+mod window_state;
 
+use anyrender::WindowRenderer;
 #[cfg(feature = "gpu")]
-use anyrender_vello::VelloWindowRenderer as WindowRenderer;
+use anyrender_vello::VelloWindowRenderer as GpuRenderer;
 #[cfg(feature = "cpu-base")]
-use anyrender_vello_cpu::VelloCpuWindowRenderer as WindowRenderer;
+use anyrender_vello_cpu::VelloCpuWindowRenderer as CpuRenderer;
 
 use anyhow::Result;
+use blitz_dom::net::Resource;
 use blitz_net::Provider;
 use blitz_traits::navigation::{NavigationOptions, NavigationProvider};
 use notify::{Error as NotifyError, Event as NotifyEvent, RecursiveMode, Watcher as _};
 use readme_application::{ReadmeApplication, ReadmeEvent};
+use renderer::Backend;
+use startup::Stage;
+use storage_policy::StoragePolicy;
 
-use crate::navigation::{execute_fetch, prepare_navigation, NavigationPlan};
+use crate::navigation::{execute_fetch, prepare_navigation, FetchedDocument, NavigationPlan};
 use blitz_shell::{
     create_default_event_loop, BlitzShellEvent, BlitzShellNetCallback, WindowConfig,
 };
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tracing_subscriber::layer::SubscriberExt as _;
+use tracing_subscriber::util::SubscriberInitExt as _;
 use tracing_subscriber::EnvFilter;
-use winit::event_loop::EventLoopProxy;
-use winit::window::WindowAttributes;
+use url::Url;
+use winit::event_loop::{EventLoop, EventLoopProxy};
+use winit::window::{Fullscreen, WindowAttributes};
 
 struct ReadmeNavigationProvider {
     proxy: EventLoopProxy<BlitzShellEvent>,
@@ -39,79 +103,530 @@ impl NavigationProvider for ReadmeNavigationProvider {
 }
 
 fn main() {
-    let target = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| String::from("https://example.com"));
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // A kiosk supervisor starts the browser again with the arguments it was given.
+    let launch_args = args.clone();
+    startup::begin(take_profile_startup_flag(&mut args));
+    let popup = take_popup_flag(&mut args);
+    if take_private_flag(&mut args) {
+        if let Err(err) = StoragePolicy::set(StoragePolicy::Private) {
+            eprintln!("Frontier exited with error: {err:?}");
+            std::process::exit(1);
+        }
+    }
+    let reset_window = take_reset_window_flag(&mut args);
+    let supervised = take_kiosk_supervised_flag(&mut args);
+    let kiosk = match take_kiosk_url(&mut args) {
+        Ok(kiosk) => kiosk,
+        Err(err) => {
+            eprintln!("Frontier exited with error: {err:?}");
+            std::process::exit(1);
+        }
+    };
+    if kiosk.is_some() && !supervised {
+        if let Err(err) = kiosk::supervise(&launch_args) {
+            eprintln!("Frontier exited with error: {err:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let debug_channel = match take_debug_channel_flag(&mut args)
+        .then(debug_channel::DebugChannelConfig::from_env)
+        .transpose()
+    {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Frontier exited with error: {err:?}");
+            std::process::exit(1);
+        }
+    };
+    let (trace_output, record, requested_renderer) = match take_trace_output(&mut args)
+        .and_then(|trace| Ok((trace, take_record_path(&mut args)?)))
+        .and_then(|(trace, record)| Ok((trace, record, take_renderer_flag(&mut args)?)))
+    {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Frontier exited with error: {err:?}");
+            std::process::exit(1);
+        }
+    };
 
-    let subscriber_result = tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
-        )
-        .with_target(false)
+    // Flushes the Chrome trace file when dropped at the end of `main`.
+    let (chrome_layer, _trace_guard) = match trace_output {
+        Some(path) => {
+            let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let subscriber_result = tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(chrome_layer)
         .try_init();
     if subscriber_result.is_err() {
         // tracing was already initialised; continue silently
     }
 
-    let rt = tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap();
+    crash_recovery::install_panic_hook();
+
+    if reset_window {
+        if let Some(path) = window_state::WindowState::default_path() {
+            if let Err(err) = window_state::WindowState::forget(&path) {
+                eprintln!("Could not reset the window state: {err:?}");
+            }
+        }
+    }
+
+    if let Err(err) = startup::time(Stage::Proxy, proxy::init) {
+        eprintln!("Frontier exited with error: {err:?}");
+        std::process::exit(1);
+    }
+
+    let rt = startup::time(Stage::Runtime, || {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    });
 
     let _guard = rt.enter();
 
-    if let Err(err) = run_standard_browser(&rt, target) {
+    let result = match args.first().map(String::as_str) {
+        Some("serve") => run_dev_server(
+            &rt,
+            args.get(1).map(String::as_str).unwrap_or("."),
+            requested_renderer,
+        ),
+        Some("publish") => run_publish(&rt, &args[1..]),
+        Some("publish-queue") => run_publish_queue(&rt, &args[1..]),
+        Some("bench") => run_bench(&rt, &args[1..]),
+        target => {
+            // A kiosk shows its own URL whatever else is on the command line.
+            let target = match kiosk.as_ref().map(Url::as_str).or(target) {
+                Some(target) => target.to_string(),
+                None if crash_recovery::pending().is_some() => {
+                    crash_recovery::RESTORE_URL.to_string()
+                }
+                None => preferences::Preferences::load_default()
+                    .map(|prefs| prefs.homepage)
+                    .unwrap_or_else(|err| {
+                        eprintln!("Ignoring unreadable preferences: {err:?}");
+                        preferences::Preferences::default().homepage
+                    }),
+            };
+            let launch = Launch {
+                raw_input: target,
+                watch_root: None,
+                popup,
+                record,
+                debug_channel,
+                kiosk,
+            };
+            run_standard_browser(&rt, launch, requested_renderer)
+        }
+    };
+
+    if let Err(err) = result {
         eprintln!("Frontier exited with error: {err:?}");
         std::process::exit(1);
     }
 }
 
-fn run_standard_browser(rt: &tokio::runtime::Runtime, raw_input: String) -> Result<()> {
-    let event_loop = create_default_event_loop();
+/// Remove `--trace-output <path>` from `args`. When given, every span (including the page
+/// load phases in [`load_trace`]) is written to `path` as Chrome trace-event JSON, which
+/// `chrome://tracing` and Perfetto open as a flamegraph.
+fn take_trace_output(args: &mut Vec<String>) -> Result<Option<PathBuf>> {
+    let Some(index) = args.iter().position(|arg| arg == "--trace-output") else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        anyhow::bail!("--trace-output needs a file path");
+    }
+    let path = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(PathBuf::from(path)))
+}
+
+/// Remove `--record <path>` from `args`; see [`recording`].
+fn take_record_path(args: &mut Vec<String>) -> Result<Option<PathBuf>> {
+    let Some(index) = args.iter().position(|arg| arg == recording::RECORD_FLAG) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        anyhow::bail!("{} needs a file path", recording::RECORD_FLAG);
+    }
+    let path = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(PathBuf::from(path)))
+}
+
+/// Remove `--renderer=gpu|cpu` (or `--renderer gpu`) from `args`; see [`renderer`].
+fn take_renderer_flag(args: &mut Vec<String>) -> Result<Option<Backend>> {
+    let flag = renderer::RENDERER_FLAG;
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == flag || arg.starts_with(&format!("{flag}=")))
+    else {
+        return Ok(None);
+    };
+    let value = match args.remove(index).split_once('=') {
+        Some((_, value)) => value.to_string(),
+        None if index < args.len() => args.remove(index),
+        None => anyhow::bail!("{flag} needs gpu or cpu"),
+    };
+    value.parse().map(Some)
+}
+
+/// Remove [`startup::PROFILE_FLAG`] from `args`. When given, the time each startup stage
+/// took is printed once the first frame is painted.
+fn take_profile_startup_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == startup::PROFILE_FLAG) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Remove `--kiosk <url>` from `args`; see [`kiosk`].
+fn take_kiosk_url(args: &mut Vec<String>) -> Result<Option<Url>> {
+    let Some(index) = args.iter().position(|arg| arg == kiosk::FLAG) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        anyhow::bail!("{} needs the URL to show", kiosk::FLAG);
+    }
+    let raw = args.remove(index + 1);
+    args.remove(index);
+    let url = Url::parse(&raw).map_err(|err| {
+        anyhow::anyhow!("{} needs an absolute URL, not {raw}: {err}", kiosk::FLAG)
+    })?;
+    Ok(Some(url))
+}
+
+/// Remove [`kiosk::SUPERVISED_FLAG`] from `args`. The kiosk supervisor passes it to the
+/// browser process it watches.
+fn take_kiosk_supervised_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == kiosk::SUPERVISED_FLAG) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Remove [`debug_channel::FLAG`] from `args`. When given, allowlisted npubs can drive the
+/// browser with Nostr direct messages.
+fn take_debug_channel_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == debug_channel::FLAG) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Remove [`popups::POPUP_FLAG`] from `args`. The opener passes it when it starts this
+/// process for `window.open`.
+fn take_popup_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == popups::POPUP_FLAG) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Remove [`storage_policy::PRIVATE_FLAG`] from `args`. When given, the session writes
+/// nothing to the data directory.
+fn take_private_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == storage_policy::PRIVATE_FLAG)
+    else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// Remove [`window_state::RESET_FLAG`] from `args`. When given, the window opens with the
+/// default size and position instead of the saved ones.
+fn take_reset_window_flag(args: &mut Vec<String>) -> bool {
+    let Some(index) = args.iter().position(|arg| arg == window_state::RESET_FLAG) else {
+        return false;
+    };
+    args.remove(index);
+    true
+}
+
+/// The document `raw_input` names, or a message saying why it could not be loaded.
+async fn load_initial_document(
+    raw_input: String,
+    net_provider: Arc<Provider<Resource>>,
+) -> Result<FetchedDocument, String> {
+    if raw_input == crash_recovery::RESTORE_URL {
+        return Ok(FetchedDocument {
+            base_url: raw_input.clone(),
+            contents: crash_recovery::restore_page(crash_recovery::pending().as_ref()),
+            file_path: None,
+            display_url: raw_input,
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        });
+    }
+    // ReadmeApplication reports unreadable preferences; fall back to the default engine.
+    let search = preferences::Preferences::load_default()
+        .map(|prefs| prefs.search)
+        .unwrap_or_default();
+    let initial_plan = prepare_navigation(&raw_input, &search)
+        .await
+        .map_err(|err| format!("Failed to prepare initial navigation target: {err}"))?;
+    match initial_plan {
+        NavigationPlan::Fetch(request) => execute_fetch(&request, net_provider)
+            .await
+            .map_err(|err| format!("Failed to load initial document: {err}")),
+    }
+}
+
+/// `frontier serve <dir>`: expose `dir` on a loopback HTTP origin and open the browser on it,
+/// reloading whenever anything under the directory changes.
+fn run_dev_server(
+    rt: &tokio::runtime::Runtime,
+    dir: &str,
+    requested_renderer: Option<Backend>,
+) -> Result<()> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = rt.block_on(dev_server::start_dev_server(Path::new(dir), addr))?;
+    println!("Serving {} at {}", server.root().display(), server.url());
+
+    let watch_root = server.root().to_path_buf();
+    let launch = Launch {
+        raw_input: server.url(),
+        watch_root: Some(watch_root),
+        popup: false,
+        record: None,
+        debug_channel: None,
+        kiosk: None,
+    };
+    let result = run_standard_browser(rt, launch, requested_renderer);
+    rt.block_on(server.shutdown());
+    result
+}
+
+/// `frontier publish <dir> --name <name> --server <url>`: upload a site to Blossom and
+/// announce it on the configured relays.
+fn run_publish(rt: &tokio::runtime::Runtime, args: &[String]) -> Result<()> {
+    let options = publish::PublishOptions::from_args(args)?;
+    let keys = publish::keys_from_env()?;
+    if let Err(err) = rt.block_on(publish_queue::flush(false)) {
+        eprintln!("Could not retry queued publishes: {err:#}");
+    }
+    let report = rt.block_on(publish::publish_site(&options, &keys))?;
+
+    println!(
+        "Published {} files ({} events) for {}",
+        report.files,
+        report.events.len(),
+        report.author
+    );
+    println!("Root document : {}", report.root_hash);
+    println!("Open in Frontier: {}", report.name);
+    if report.queued > 0 {
+        println!(
+            "{} events could not be published and were queued; they are retried on the next launch or with `frontier publish-queue --retry`",
+            report.queued
+        );
+    }
+    Ok(())
+}
+
+/// `frontier publish-queue [--retry]`: list events waiting for a relay, sending them all
+/// first with `--retry`.
+fn run_publish_queue(rt: &tokio::runtime::Runtime, args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        None => {}
+        Some("--retry") => {
+            let report = rt.block_on(publish_queue::flush(true))?;
+            println!(
+                "Sent {} events; {} still waiting.",
+                report.sent, report.waiting
+            );
+        }
+        Some(other) => anyhow::bail!("unknown option {other}"),
+    }
+    let queue = match publish_queue::PublishQueue::default_path() {
+        Some(path) => publish_queue::PublishQueue::load(&path)?,
+        None => publish_queue::PublishQueue::default(),
+    };
+    println!("{}", publish_queue::describe(&queue));
+    Ok(())
+}
+
+/// `frontier bench [pages…] [--iterations N] [--output report.json]`: load each page headlessly
+/// and report per-phase and interaction latency as JSON.
+fn run_bench(rt: &tokio::runtime::Runtime, args: &[String]) -> Result<()> {
+    let options = bench::BenchOptions::from_args(args)?;
+    let report = rt.block_on(bench::run(&options))?;
+    let json = serde_json::to_string_pretty(&report)?;
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, json)?;
+            eprintln!("Wrote {} pages to {}", report.pages.len(), path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// What the browser window opens on, set before the renderer is chosen.
+struct Launch {
+    raw_input: String,
+    watch_root: Option<PathBuf>,
+    popup: bool,
+    record: Option<PathBuf>,
+    /// Set with [`debug_channel::FLAG`].
+    debug_channel: Option<debug_channel::DebugChannelConfig>,
+    /// The start page of [`kiosk`] mode.
+    kiosk: Option<Url>,
+}
+
+fn run_standard_browser(
+    rt: &tokio::runtime::Runtime,
+    launch: Launch,
+    requested_renderer: Option<Backend>,
+) -> Result<()> {
+    let event_loop = startup::time(Stage::EventLoop, create_default_event_loop);
     let proxy = event_loop.create_proxy();
 
     let net_callback = BlitzShellNetCallback::shared(proxy.clone());
     let net_provider = Arc::new(Provider::new(net_callback));
 
-    let initial_plan = rt
-        .block_on(prepare_navigation(&raw_input))
-        .unwrap_or_else(|err| {
-            eprintln!("Failed to prepare initial navigation target: {err}");
-            std::process::exit(1);
-        });
+    // The first document loads while the rest of the window is set up.
+    let initial_fetch = rt.spawn(startup::time_async(
+        Stage::InitialFetch,
+        load_initial_document(launch.raw_input.clone(), Arc::clone(&net_provider)),
+    ));
 
-    let initial_document = match initial_plan {
-        NavigationPlan::Fetch(request) => rt
-            .block_on(execute_fetch(&request, Arc::clone(&net_provider)))
-            .unwrap_or_else(|err| {
-                eprintln!("Failed to load initial document: {err}");
-                std::process::exit(1);
-            }),
-    };
+    let selection = startup::time(Stage::Renderer, || renderer::select(requested_renderer));
+    match selection.backend {
+        #[cfg(feature = "gpu")]
+        Backend::Gpu => open_browser(
+            rt,
+            event_loop,
+            net_provider,
+            initial_fetch,
+            launch,
+            GpuRenderer::new,
+        ),
+        #[cfg(feature = "cpu-base")]
+        Backend::Cpu => open_browser(
+            rt,
+            event_loop,
+            net_provider,
+            initial_fetch,
+            launch,
+            CpuRenderer::new,
+        ),
+        #[allow(unreachable_patterns)]
+        backend => anyhow::bail!("this build has no {} renderer", backend.name()),
+    }
+}
 
-    let title = String::from("Frontier Browser");
+/// The rest of [`run_standard_browser`], drawing with the chosen renderer.
+fn open_browser<R: WindowRenderer>(
+    rt: &tokio::runtime::Runtime,
+    event_loop: EventLoop<BlitzShellEvent>,
+    net_provider: Arc<Provider<Resource>>,
+    initial_fetch: JoinHandle<Result<FetchedDocument, String>>,
+    launch: Launch,
+    new_renderer: fn() -> R,
+) -> Result<()> {
+    let Launch {
+        raw_input,
+        watch_root,
+        popup,
+        record,
+        debug_channel,
+        kiosk,
+    } = launch;
+    let proxy = event_loop.create_proxy();
+
+    let title = match StoragePolicy::current() {
+        StoragePolicy::Persistent => String::from("Frontier Browser"),
+        StoragePolicy::Private => String::from("Frontier Browser (Private)"),
+    };
 
     let navigation_provider: Arc<dyn NavigationProvider> = Arc::new(ReadmeNavigationProvider {
         proxy: event_loop.create_proxy(),
     });
 
-    let mut application = ReadmeApplication::new(
-        proxy.clone(),
-        raw_input.clone(),
-        Arc::clone(&net_provider),
-        Arc::clone(&navigation_provider),
-    );
+    let mut application = startup::time(Stage::AppState, || {
+        ReadmeApplication::new(
+            proxy.clone(),
+            raw_input.clone(),
+            Arc::clone(&net_provider),
+            Arc::clone(&navigation_provider),
+        )
+    });
 
-    application.prepare_initial_state(initial_document.clone());
+    if popup {
+        application.set_popup();
+    } else {
+        // Queued publishes wait until a page needs relays, or the first page has had a head
+        // start.
+        let start =
+            publish_queue::spawn_deferred_flush(rt.handle(), publish_queue::DEFERRED_FLUSH_DELAY);
+        if relays::uses_relays(&raw_input) {
+            let _ = start.send(());
+        } else {
+            application.set_relay_bootstrap(start);
+        }
+    }
+    if let Some(path) = record {
+        application.set_recording(path);
+    }
+    if let Some(config) = debug_channel {
+        let state = debug_channel::spawn(rt.handle(), config, proxy.clone());
+        application.attach_debug_channel(state);
+    }
+    let kiosk_mode = kiosk.is_some();
+    if let Some(home) = kiosk {
+        application.set_kiosk(home);
+    }
+    let renderer = new_renderer();
+    let mut attrs = application.restore_window_state(WindowAttributes::default().with_title(title));
+    if kiosk_mode {
+        attrs = attrs
+            .with_fullscreen(Some(Fullscreen::Borderless(None)))
+            .with_decorations(false);
+    }
 
-    let doc = application.take_initial_document();
-    let renderer = WindowRenderer::new();
-    let attrs = WindowAttributes::default().with_title(title);
+    let initial_document = rt
+        .block_on(initial_fetch)
+        .expect("initial document task panicked")
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            std::process::exit(1);
+        });
+    let doc = startup::time(Stage::Document, || {
+        application.prepare_initial_state(initial_document.clone());
+        application.take_initial_document()
+    });
     let window = WindowConfig::with_attributes(doc, renderer, attrs);
 
     application.add_window(window);
 
-    if let Some(path) = initial_document.file_path.clone() {
+    let watch_target = match watch_root {
+        Some(root) => Some((root, RecursiveMode::Recursive)),
+        None => initial_document
+            .file_path
+            .clone()
+            .map(|path| (path, RecursiveMode::NonRecursive)),
+    };
+
+    if let Some((path, mode)) = watch_target {
         let watcher_proxy = proxy.clone();
         let mut watcher =
             notify::recommended_watcher(move |_: Result<NotifyEvent, NotifyError>| {
@@ -119,10 +634,12 @@ fn run_standard_browser(rt: &tokio::runtime::Runtime, raw_input: String) -> Resu
                 let _ = watcher_proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
             })
             .unwrap();
-        watcher.watch(&path, RecursiveMode::NonRecursive).unwrap();
+        watcher.watch(&path, mode).unwrap();
         Box::leak(Box::new(watcher));
     }
 
     event_loop.run_app(&mut application).unwrap();
+    application.save_window_state();
+    crash_recovery::clean_exit();
     Ok(())
 }
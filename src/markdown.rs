@@ -0,0 +1,148 @@
+//! Markdown documents rendered to HTML before they reach the DOM.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use comrak::adapters::SyntaxHighlighterAdapter;
+use comrak::html::write_opening_tag;
+use comrak::{markdown_to_html_with_plugins, Options, Plugins};
+use url::Url;
+
+use crate::highlight;
+
+const MARKDOWN_STYLES: &str = include_str!("../assets/markdown/markdown.css");
+
+/// Whether a URL points at a markdown document by its path extension, for responses and files
+/// without a markdown `Content-Type`.
+pub fn is_markdown_url(url: &Url) -> bool {
+    is_markdown_path(Path::new(url.path()))
+}
+
+/// Whether a `Content-Type` header value names markdown.
+pub fn is_markdown_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.eq_ignore_ascii_case("text/markdown") || essence.eq_ignore_ascii_case("text/x-markdown")
+}
+
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| {
+            let ext = ext.to_ascii_lowercase();
+            ext == "md" || ext == "markdown"
+        })
+        .unwrap_or(false)
+}
+
+/// Render GitHub-flavoured markdown into a standalone HTML document.
+pub fn markdown_to_html(contents: &str) -> String {
+    let mut options = Options::default();
+    options.extension.strikethrough = true;
+    options.extension.table = true;
+    options.extension.autolink = true;
+    options.extension.tasklist = true;
+    options.extension.footnotes = true;
+    options.extension.tagfilter = true;
+    options.render.github_pre_lang = true;
+
+    let mut plugins = Plugins::default();
+    plugins.render.codefence_syntax_highlighter = Some(&CodeFenceHighlighter);
+
+    let body = markdown_to_html_with_plugins(contents, &options, &plugins);
+    let code_styles = highlight::theme_stylesheet();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<style>{MARKDOWN_STYLES}</style>
+<style>{code_styles}</style>
+</head>
+<body>
+<article class="markdown-body">
+{body}
+</article>
+</body>
+</html>"#
+    )
+}
+
+/// Routes fenced code blocks through the shared highlighter so markdown and view-source
+/// agree on colours and share the highlight cache.
+struct CodeFenceHighlighter;
+
+impl SyntaxHighlighterAdapter for CodeFenceHighlighter {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> io::Result<()> {
+        output.write_all(highlight::highlight_to_html(code, lang).as_bytes())
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        mut attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        let class = attributes.entry(String::from("class")).or_default();
+        if !class.is_empty() {
+            class.push(' ');
+        }
+        class.push_str("hl-code");
+        write_opening_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        write_opening_tag(output, "code", attributes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_markdown_by_extension() {
+        assert!(is_markdown_url(
+            &Url::parse("https://example.com/README.md").unwrap()
+        ));
+        assert!(is_markdown_path(Path::new("/tmp/notes.MARKDOWN")));
+        assert!(!is_markdown_url(
+            &Url::parse("https://example.com/index.html").unwrap()
+        ));
+    }
+
+    #[test]
+    fn detects_markdown_by_content_type() {
+        assert!(is_markdown_content_type("text/markdown; charset=UTF-8"));
+        assert!(is_markdown_content_type("Text/X-Markdown"));
+        assert!(!is_markdown_content_type("text/plain"));
+    }
+
+    #[test]
+    fn renders_github_extensions() {
+        let html = markdown_to_html(
+            "# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n\nNote[^1]\n\n[^1]: footnote\n",
+        );
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<table>"));
+        assert!(html.contains(r#"type="checkbox""#));
+        assert!(html.contains("footnote"));
+        assert!(html.contains("markdown-body"));
+    }
+
+    #[test]
+    fn code_fences_use_shared_highlighter() {
+        let html = markdown_to_html("```rust\nfn main() {}\n```\n");
+        assert!(html.contains(r#"class="hl-code""#));
+        assert!(html.contains("hl-source"));
+    }
+}
@@ -0,0 +1,305 @@
+//! `~term` in the URL bar: find published names starting with `term`.
+//!
+//! Relays only match `d` tags exactly, so the search asks the saved relays for recent
+//! kind-34256 service events (and for NIP-50 full-text matches where relays support it) and
+//! filters the identifiers locally. There is no web-of-trust scoring in this tree yet (see
+//! `notes/nns_followups.md`), so exact matches come first, then shorter names, then the claim
+//! that was first published. Choosing a candidate opens its root document on the first
+//! Blossom server it lists.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use nostr_sdk::prelude::{Event, Filter, Kind, ToBech32};
+use url::Url;
+
+use crate::page_cache::format_timestamp;
+use crate::publish::SERVICE_KIND;
+
+pub const NAMES_URL: &str = "frontier://names";
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(8);
+/// Service events asked of each relay; identifiers are matched against these.
+const EVENT_LIMIT: usize = 500;
+const MAX_CANDIDATES: usize = 50;
+
+/// One name claim matching the search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameCandidate {
+    pub name: String,
+    /// Claimant as an npub.
+    pub author: String,
+    /// Root document hash from the newest claim.
+    pub root: Option<String>,
+    pub servers: Vec<Url>,
+    /// When this claimant first published the name, in unix seconds.
+    pub first_seen: u64,
+}
+
+impl NameCandidate {
+    /// Where the claimed site's root document is served.
+    ///
+    /// Servers and roots come from relay events and end up as links on an internal page, so
+    /// only a relative root under an `http`/`https` server is accepted.
+    pub fn root_url(&self) -> Option<Url> {
+        let root = self.root.as_deref()?;
+        if Url::parse(root).is_ok() {
+            return None;
+        }
+        let server = self.servers.first().filter(|server| is_web_url(server))?;
+        let url = server.join(root).ok()?;
+        (url.scheme() == server.scheme() && url.host() == server.host()).then_some(url)
+    }
+}
+
+fn is_web_url(url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// The search term of URL bar input: `~term` or `frontier://names?q=term`.
+pub fn term_from_input(input: &str) -> Option<String> {
+    let input = input.trim();
+    let term = match input.strip_prefix('~') {
+        Some(term) => term.to_string(),
+        None => {
+            let query = input.strip_prefix(NAMES_URL)?.strip_prefix('?')?;
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "q")
+                .map(|(_, value)| value.into_owned())?
+        }
+    };
+    Some(term.trim().to_lowercase())
+}
+
+/// `frontier://names?q=term`, the address shown while the results are up.
+pub fn names_url(term: &str) -> String {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("q", term)
+        .finish();
+    format!("{NAMES_URL}?{query}")
+}
+
+/// Ask `relays` for service events and return the ranked claims on names starting with `term`.
+pub async fn search_names(term: &str, relays: &[Url]) -> Result<Vec<NameCandidate>> {
+    let client = crate::relays::connect(relays).await?;
+    let kind = Kind::from(SERVICE_KIND);
+    let filters = vec![
+        Filter::new().kind(kind).limit(EVENT_LIMIT),
+        Filter::new().kind(kind).search(term).limit(EVENT_LIMIT),
+    ];
+    let events = client
+        .get_events_of(filters, Some(SEARCH_TIMEOUT))
+        .await
+        .context("query service events");
+    let _ = client.disconnect().await;
+
+    let claims = events?.iter().filter_map(claim_from_event).collect();
+    Ok(rank(term, claims))
+}
+
+/// A claim as `(name, npub, created_at, tags)`.
+type Claim = (String, String, u64, Vec<Vec<String>>);
+
+fn claim_from_event(event: &Event) -> Option<Claim> {
+    let name = event.identifier()?.to_lowercase();
+    let author = event.pubkey.to_bech32().ok()?;
+    let tags = event.tags().iter().map(|tag| tag.as_vec()).collect();
+    Some((name, author, event.created_at.as_u64(), tags))
+}
+
+/// Keep claims on names starting with `term`, one per name and claimant with the newest
+/// servers and root, ordered exact match first, then shorter names, then oldest claim.
+fn rank(term: &str, claims: Vec<Claim>) -> Vec<NameCandidate> {
+    let mut merged: HashMap<(String, String), (u64, NameCandidate)> = HashMap::new();
+    for (name, author, created_at, tags) in claims {
+        if !name.starts_with(term) {
+            continue;
+        }
+        let tag_values = |key: &str| {
+            tags.iter()
+                .filter(|tag| tag.len() > 1 && tag[0] == key)
+                .map(|tag| tag[1].clone())
+                .collect::<Vec<_>>()
+        };
+        let candidate = NameCandidate {
+            name: name.clone(),
+            author: author.clone(),
+            root: tag_values("blossom").into_iter().next(),
+            servers: tag_values("server")
+                .iter()
+                .filter_map(|server| Url::parse(server).ok())
+                .filter(is_web_url)
+                .collect(),
+            first_seen: created_at,
+        };
+        match merged.get_mut(&(name.clone(), author.clone())) {
+            Some((newest, existing)) => {
+                let first_seen = existing.first_seen.min(created_at);
+                if created_at > *newest {
+                    *newest = created_at;
+                    *existing = candidate;
+                }
+                existing.first_seen = first_seen;
+            }
+            None => {
+                merged.insert((name, author), (created_at, candidate));
+            }
+        }
+    }
+    let mut candidates: Vec<NameCandidate> = merged
+        .into_values()
+        .map(|(_, candidate)| candidate)
+        .collect();
+    candidates.sort_by(|a, b| {
+        (a.name != term)
+            .cmp(&(b.name != term))
+            .then(a.name.len().cmp(&b.name.len()))
+            .then(a.first_seen.cmp(&b.first_seen))
+            .then(a.name.cmp(&b.name))
+    });
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+/// HTML body of the results for `term`.
+pub fn names_page(term: &str, result: &Result<Vec<NameCandidate>, String>) -> String {
+    let rows = match result {
+        Err(err) => format!(
+            "<tr><td colspan=\"4\">Search failed: {}</td></tr>",
+            encode_text(err)
+        ),
+        Ok(candidates) if candidates.is_empty() => {
+            "<tr><td colspan=\"4\">No published names start with this.</td></tr>".to_string()
+        }
+        Ok(candidates) => candidates
+            .iter()
+            .map(|candidate| {
+                let open = match candidate.root_url() {
+                    Some(url) => format!(
+                        "<a href=\"{}\">Open</a>",
+                        encode_double_quoted_attribute(url.as_str())
+                    ),
+                    None => "no server listed".to_string(),
+                };
+                let timestamp = format_timestamp(candidate.first_seen);
+                let claimed = timestamp.split(' ').next().unwrap_or_default();
+                format!(
+                    "<tr><td>{name}</td><td><code>{author}</code></td><td>{claimed}</td><td>{open}</td></tr>",
+                    name = encode_text(&candidate.name),
+                    author = encode_text(&candidate.author),
+                    claimed = encode_text(claimed),
+                )
+            })
+            .collect(),
+    };
+    format!(
+        r#"<section class="names">
+<h1>Names starting with “{term}”</h1>
+<table>
+<thead><tr><th>Name</th><th>Claimed by</th><th>Since</th><th></th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+<form action="{NAMES_URL}" method="get">
+<p><label for="name-search">Search names</label><br><input type="text" id="name-search" name="q" value="{value}"> <input type="submit" value="Search"></p>
+</form>
+</section>"#,
+        term = encode_text(term),
+        value = encode_double_quoted_attribute(term),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(name: &str, author: &str, created_at: u64, server: &str) -> Claim {
+        (
+            name.to_string(),
+            author.to_string(),
+            created_at,
+            vec![
+                vec!["d".to_string(), name.to_string()],
+                vec!["blossom".to_string(), "abcd".to_string()],
+                vec!["server".to_string(), server.to_string()],
+            ],
+        )
+    }
+
+    #[test]
+    fn ranks_exact_then_short_then_oldest_claims() {
+        let claims = vec![
+            claim("moonlight", "npub1a", 10, "https://a.example/"),
+            claim("moon", "npub1b", 30, "https://b.example/"),
+            claim("moon", "npub1c", 20, "https://c.example/"),
+            claim("moon", "npub1c", 40, "https://c2.example/"),
+            claim("moons", "npub1d", 5, "https://d.example/"),
+            claim("sun", "npub1e", 1, "https://e.example/"),
+        ];
+        let ranked = rank("moon", claims);
+        let order: Vec<(&str, &str)> = ranked
+            .iter()
+            .map(|candidate| (candidate.name.as_str(), candidate.author.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("moon", "npub1c"),
+                ("moon", "npub1b"),
+                ("moons", "npub1d"),
+                ("moonlight", "npub1a"),
+            ]
+        );
+        assert_eq!(ranked[0].first_seen, 20);
+        assert_eq!(
+            ranked[0].root_url().unwrap().as_str(),
+            "https://c2.example/abcd"
+        );
+    }
+
+    #[test]
+    fn ignores_servers_and_roots_that_leave_the_web() {
+        let ranked = rank(
+            "moon",
+            vec![
+                claim("moon", "npub1a", 10, "frontier://settings?reset"),
+                claim("moon", "npub1b", 10, "javascript:alert(1)"),
+            ],
+        );
+        assert!(ranked.iter().all(|candidate| candidate.servers.is_empty()));
+        assert!(ranked
+            .iter()
+            .all(|candidate| candidate.root_url().is_none()));
+
+        let server = Url::parse("https://a.example/").unwrap();
+        let candidate = |root: &str, server: &Url| NameCandidate {
+            name: "moon".to_string(),
+            author: "npub1a".to_string(),
+            root: Some(root.to_string()),
+            servers: vec![server.clone()],
+            first_seen: 0,
+        };
+        let frontier = Url::parse("frontier://settings").unwrap();
+        assert_eq!(candidate("abcd", &frontier).root_url(), None);
+        assert_eq!(
+            candidate("frontier://settings?reset", &server).root_url(),
+            None
+        );
+        assert_eq!(candidate("javascript:alert(1)", &server).root_url(), None);
+        assert_eq!(candidate("//evil.example/abcd", &server).root_url(), None);
+        assert_eq!(
+            candidate("abcd", &server).root_url().unwrap().as_str(),
+            "https://a.example/abcd"
+        );
+    }
+
+    #[test]
+    fn reads_terms_from_the_url_bar_and_the_form() {
+        assert_eq!(term_from_input(" ~Moon "), Some("moon".to_string()));
+        assert_eq!(
+            term_from_input(&names_url("moon")),
+            Some("moon".to_string())
+        );
+        assert_eq!(term_from_input("moon"), None);
+    }
+}
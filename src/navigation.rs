@@ -1,26 +1,42 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use ::url::Url;
 use blitz_dom::net::Resource;
 use blitz_net::Provider;
 use blitz_traits::net::Request;
+use reqwest::header::{HeaderMap, CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
+use crate::csp::ContentSecurityPolicy;
+use crate::highlight;
+use crate::http_client;
 use crate::input::{parse_input, ParseInputError, ParsedInput};
 use crate::js::processor;
-use crate::js::script::{ScriptDescriptor, ScriptExecution, ScriptKind, ScriptSource};
+use crate::js::script::{ScriptDescriptor, ScriptKind, ScriptSource};
+use crate::js::sourcemap;
+use crate::markdown;
+use crate::network_log::{Initiator, NetworkLog, RequestSource};
+use crate::privacy;
+use crate::search::SearchEngine;
 
 #[derive(Debug, Clone)]
 pub struct FetchRequest {
     pub source: FetchSource,
     pub display_url: String,
+    /// `User-Agent` the window emulates, sent with the document and its blocking scripts in
+    /// place of [`user_agent::current`](crate::user_agent::current).
+    pub user_agent: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum FetchSource {
     Url(Url),
+    /// Show the highlighted source of `Url` instead of rendering it.
+    ViewSource(Url),
 }
 
 #[derive(Debug, Clone)]
@@ -28,13 +44,55 @@ pub enum NavigationPlan {
     Fetch(FetchRequest),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FetchedDocument {
     pub base_url: String,
     pub contents: String,
     pub file_path: Option<PathBuf>,
     pub display_url: String,
     pub scripts: Vec<ScriptDescriptor>,
+    /// `Content-Security-Policy` header values of the response, which apply alongside any
+    /// policies in the document's meta tags.
+    #[serde(default)]
+    pub csp_headers: Vec<String>,
+    /// What the server said about the document besides its body; `None` for documents that
+    /// did not come from an http(s) response.
+    #[serde(default)]
+    pub response: Option<ResponseInfo>,
+}
+
+/// Status, type and caching headers of the response an http(s) document came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseInfo {
+    pub status: u16,
+    /// The `Content-Type` header, when there was one.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// The `Cache-Control` headers, joined, when there were any.
+    pub cache_control: Option<String>,
+}
+
+impl ResponseInfo {
+    /// Whether the response may be kept on disk: a success that is neither `no-store` nor
+    /// `private`.
+    pub fn is_storable(&self) -> bool {
+        let forbidden = self.cache_control.as_deref().is_some_and(|value| {
+            value.split(',').any(|directive| {
+                let name = directive.split('=').next().unwrap_or_default().trim();
+                name.eq_ignore_ascii_case("no-store") || name.eq_ignore_ascii_case("private")
+            })
+        });
+        (200..300).contains(&self.status) && !forbidden
+    }
+}
+
+impl FetchedDocument {
+    /// Every policy the document was delivered with, from its headers and its meta tags.
+    pub fn content_security_policy(&self) -> ContentSecurityPolicy {
+        let mut policy = ContentSecurityPolicy::from_headers(&self.csp_headers);
+        policy.extend(ContentSecurityPolicy::from_html(&self.contents));
+        policy
+    }
 }
 
 #[derive(Debug, Error)]
@@ -56,8 +114,79 @@ pub enum FetchError {
     File(#[from] std::io::Error),
 }
 
-pub async fn prepare_navigation(raw_input: &str) -> Result<NavigationPlan, NavigationError> {
+const VIEW_SOURCE_PREFIX: &str = "view-source:";
+/// Files a directory opens as, in order of preference.
+const DIRECTORY_INDEXES: [&str; 3] = ["index.html", "index.htm", "README.md"];
+
+/// The window's navigations: each one gets the next generation, and starting one aborts the
+/// task still loading the last. Aborting drops the fetch future, which closes its connection;
+/// a result that was already on its way is told apart by its generation.
+#[derive(Debug, Default)]
+pub struct Navigations {
+    generation: u64,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Navigations {
+    /// Abort the navigation in flight, if any, and return the generation of the next one.
+    pub fn begin(&mut self) -> u64 {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.generation += 1;
+        self.generation
+    }
+
+    /// The task loading the navigation [`Self::begin`] started.
+    pub fn track(&mut self, task: JoinHandle<()>) {
+        self.task = Some(task);
+    }
+
+    /// The generation of the latest navigation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether a navigation is still loading.
+    pub fn is_loading(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// Take the result of navigation `generation`: `false` when a later navigation
+    /// superseded it and the result is to be dropped.
+    pub fn finish(&mut self, generation: u64) -> bool {
+        if generation != self.generation {
+            return false;
+        }
+        self.task = None;
+        true
+    }
+}
+
+/// Turn URL bar input into a fetch. Input that is not an address goes to `search`.
+pub async fn prepare_navigation(
+    raw_input: &str,
+    search: &SearchEngine,
+) -> Result<NavigationPlan, NavigationError> {
     let trimmed = raw_input.trim().to_string();
+
+    if let Some(url) = search.url_for(&trimmed) {
+        return Ok(NavigationPlan::Fetch(FetchRequest {
+            display_url: url.to_string(),
+            source: FetchSource::Url(url),
+            user_agent: None,
+        }));
+    }
+
+    if let Some(inner) = trimmed.strip_prefix(VIEW_SOURCE_PREFIX) {
+        let (ParsedInput::Url(url) | ParsedInput::DirectIp(url)) = parse_input(inner)?;
+        return Ok(NavigationPlan::Fetch(FetchRequest {
+            source: FetchSource::ViewSource(url),
+            display_url: trimmed,
+            user_agent: None,
+        }));
+    }
+
     let parsed = parse_input(raw_input)?;
 
     match parsed {
@@ -69,6 +198,7 @@ pub async fn prepare_navigation(raw_input: &str) -> Result<NavigationPlan, Navig
                 } else {
                     trimmed
                 },
+                user_agent: None,
             };
             Ok(NavigationPlan::Fetch(request))
         }
@@ -79,30 +209,78 @@ pub async fn execute_fetch(
     request: &FetchRequest,
     net_provider: Arc<Provider<Resource>>,
 ) -> Result<FetchedDocument, FetchError> {
-    let mut document = match &request.source {
-        FetchSource::Url(url) => {
-            fetch_url(url, &request.display_url, Arc::clone(&net_provider)).await?
+    fetch_document(request, net_provider, None).await
+}
+
+/// [`execute_fetch`], recording the document and its blocking scripts in `network_log`.
+pub async fn execute_fetch_logged(
+    request: &FetchRequest,
+    net_provider: Arc<Provider<Resource>>,
+    network_log: &NetworkLog,
+) -> Result<FetchedDocument, FetchError> {
+    fetch_document(request, net_provider, Some(network_log)).await
+}
+
+async fn fetch_document(
+    request: &FetchRequest,
+    net_provider: Arc<Provider<Resource>>,
+    network_log: Option<&NetworkLog>,
+) -> Result<FetchedDocument, FetchError> {
+    let (FetchSource::Url(url) | FetchSource::ViewSource(url)) = &request.source;
+    let logged = network_log
+        .map(|log| log.begin("GET", url, Initiator::Document, RequestSource::for_url(url)));
+    let user_agent = request.user_agent.as_deref();
+    let loaded = load_url(
+        url,
+        &request.display_url,
+        user_agent,
+        Arc::clone(&net_provider),
+    )
+    .await;
+    let mut document = match loaded {
+        Ok(document) => {
+            if let Some(logged) = logged {
+                logged.finish(None, Vec::new(), document.contents.as_bytes());
+            }
+            document
+        }
+        Err(err) => {
+            if let Some(logged) = logged {
+                logged.fail(err.to_string());
+            }
+            return Err(err);
         }
     };
 
-    hydrate_blocking_scripts(&mut document, net_provider).await;
+    if let FetchSource::ViewSource(url) = &request.source {
+        document.contents = highlight::view_source_document(&document.contents, url.as_str());
+        return Ok(document);
+    }
+
+    finish_document(&mut document);
+    hydrate_classic_scripts(&mut document, user_agent, net_provider, network_log).await;
 
     Ok(document)
 }
 
-async fn fetch_url(
+/// Fetch the raw response body without rendering markdown or collecting scripts.
+async fn load_url(
     url: &Url,
     display_url: &str,
+    user_agent: Option<&str>,
     net_provider: Arc<Provider<Resource>>,
 ) -> Result<FetchedDocument, FetchError> {
-    if url.scheme() == "file" {
-        return fetch_file_url(url, display_url);
+    match url.scheme() {
+        "file" => return read_file_url(url, display_url),
+        "http" | "https" => return load_http_url(url, display_url, user_agent).await,
+        _ => {}
     }
 
     let (tx, rx) = oneshot::channel();
     let fetch_url = url.clone();
 
-    let req = Request::get(fetch_url);
+    let mut req = Request::get(fetch_url);
+    privacy::apply_as(&mut req.headers, url, None, user_agent);
     net_provider.fetch_with_callback(
         req,
         Box::new(move |result| match result {
@@ -120,19 +298,84 @@ async fn fetch_url(
 
     let contents = std::str::from_utf8(&bytes)?.to_string();
 
-    let mut document = FetchedDocument {
+    Ok(FetchedDocument {
         base_url: response_url,
         contents,
         file_path: None,
         display_url: display_url.to_string(),
         scripts: Vec::new(),
+        csp_headers: Vec::new(),
+        response: None,
+    })
+}
+
+/// Load an http(s) document with [`http_client::documents`], so `Set-Cookie` on it, and on every
+/// redirect before it, lands in the shared [`CookieJar`](crate::cookies::CookieJar). blitz's net
+/// provider hands back only the body.
+async fn load_http_url(
+    url: &Url,
+    display_url: &str,
+    user_agent: Option<&str>,
+) -> Result<FetchedDocument, FetchError> {
+    let mut headers = HeaderMap::new();
+    privacy::apply_as(&mut headers, url, None, user_agent);
+    let response = http_client::documents()
+        .map_err(|err| FetchError::Network(err.to_string()))?
+        .get(url.clone())
+        .headers(headers)
+        .send()
+        .await
+        .map_err(|err| FetchError::Network(err.to_string()))?;
+    let response_url = response.url().to_string();
+    let csp_headers = response
+        .headers()
+        .get_all(CONTENT_SECURITY_POLICY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(str::to_string)
+        .collect();
+    let info = ResponseInfo {
+        status: response.status().as_u16(),
+        content_type: response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        cache_control: Some(
+            response
+                .headers()
+                .get_all(CACHE_CONTROL)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+        .filter(|value| !value.is_empty()),
     };
-    collect_document_scripts(&mut document);
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|err| FetchError::Network(err.to_string()))?;
+    let contents = std::str::from_utf8(&bytes)?.to_string();
 
-    Ok(document)
+    Ok(FetchedDocument {
+        base_url: response_url,
+        contents,
+        file_path: None,
+        display_url: display_url.to_string(),
+        scripts: Vec::new(),
+        csp_headers,
+        response: Some(info),
+    })
 }
 
 fn fetch_file_url(url: &Url, display_url: &str) -> Result<FetchedDocument, FetchError> {
+    let mut document = read_file_url(url, display_url)?;
+    finish_document(&mut document);
+    Ok(document)
+}
+
+fn read_file_url(url: &Url, display_url: &str) -> Result<FetchedDocument, FetchError> {
     let path = url.to_file_path().map_err(|_| {
         FetchError::File(std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
@@ -141,25 +384,109 @@ fn fetch_file_url(url: &Url, display_url: &str) -> Result<FetchedDocument, Fetch
     })?;
 
     if path.is_dir() {
-        return Err(FetchError::File(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "path is a directory",
-        )));
+        return read_directory(&path, display_url);
     }
 
     let base_url = url.as_str().to_string();
     let contents = std::fs::read_to_string(&path)?;
 
-    let mut document = FetchedDocument {
+    Ok(FetchedDocument {
         base_url,
         contents,
         file_path: Some(path),
         display_url: display_url.to_string(),
         scripts: Vec::new(),
+        csp_headers: Vec::new(),
+        response: None,
+    })
+}
+
+/// A directory opens as its index page or README, or else as a listing of its entries.
+fn read_directory(path: &Path, display_url: &str) -> Result<FetchedDocument, FetchError> {
+    let invalid = || {
+        FetchError::File(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "directory is not an absolute path",
+        ))
     };
-    collect_document_scripts(&mut document);
+    for name in DIRECTORY_INDEXES {
+        let index = path.join(name);
+        if index.is_file() {
+            let url = Url::from_file_path(&index).map_err(|_| invalid())?;
+            return read_file_url(&url, display_url);
+        }
+    }
 
-    Ok(document)
+    let base_url = Url::from_directory_path(path).map_err(|_| invalid())?;
+    Ok(FetchedDocument {
+        contents: directory_listing(path, &base_url)?,
+        base_url: base_url.to_string(),
+        file_path: None,
+        display_url: display_url.to_string(),
+        scripts: Vec::new(),
+        csp_headers: Vec::new(),
+        response: None,
+    })
+}
+
+/// Links to the entries of `path`, subdirectories first, each group sorted by name.
+fn directory_listing(path: &Path, base_url: &Url) -> Result<String, FetchError> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        entries.push((!entry.path().is_dir(), name));
+    }
+    entries.sort();
+
+    let title = html_escape::encode_text(&path.display().to_string()).into_owned();
+    let mut items = String::new();
+    if path.parent().is_some() {
+        items.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for (is_file, name) in entries {
+        let suffix = if is_file { "" } else { "/" };
+        let mut href = base_url.clone();
+        if let Ok(mut segments) = href.path_segments_mut() {
+            segments.pop_if_empty().push(&name);
+            if !is_file {
+                segments.push("");
+            }
+        }
+        items.push_str(&format!(
+            "<li><a href=\"{}\">{}{suffix}</a></li>\n",
+            html_escape::encode_double_quoted_attribute(href.as_str()),
+            html_escape::encode_text(&name),
+        ));
+    }
+    Ok(format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Index of {title}</title>\n</head>\n<body>\n<h1>Index of {title}</h1>\n\
+         <ul>\n{items}</ul>\n</body>\n</html>\n"
+    ))
+}
+
+/// Turn a raw response into the document we hand to blitz: markdown is rendered to HTML and
+/// scripts are collected from the final markup. A response is markdown when its `Content-Type`
+/// says so, whatever its URL; otherwise the file or URL extension decides.
+fn finish_document(document: &mut FetchedDocument) {
+    let declared = document
+        .response
+        .as_ref()
+        .and_then(|response| response.content_type.as_deref())
+        .is_some_and(markdown::is_markdown_content_type);
+    let is_markdown = declared
+        || match &document.file_path {
+            Some(path) => markdown::is_markdown_path(path),
+            None => Url::parse(&document.base_url)
+                .map(|url| markdown::is_markdown_url(&url))
+                .unwrap_or(false),
+        };
+    if is_markdown {
+        document.contents = markdown::markdown_to_html(&document.contents);
+    }
+
+    collect_document_scripts(document);
 }
 
 fn collect_document_scripts(document: &mut FetchedDocument) {
@@ -176,12 +503,17 @@ fn collect_document_scripts(document: &mut FetchedDocument) {
         }
     };
 
-    document.scripts = scripts;
+    let document_url = Url::parse(&document.base_url).ok();
+    document.scripts = document
+        .content_security_policy()
+        .filter_scripts(scripts, document_url.as_ref());
 }
 
-async fn hydrate_blocking_scripts(
+async fn hydrate_classic_scripts(
     document: &mut FetchedDocument,
+    user_agent: Option<&str>,
     net_provider: Arc<Provider<Resource>>,
+    network_log: Option<&NetworkLog>,
 ) {
     if document.scripts.is_empty() {
         return;
@@ -190,9 +522,7 @@ async fn hydrate_blocking_scripts(
     let base_url = Url::parse(&document.base_url).ok();
 
     for descriptor in document.scripts.iter_mut() {
-        if descriptor.execution != ScriptExecution::Blocking
-            || descriptor.kind != ScriptKind::Classic
-        {
+        if descriptor.kind != ScriptKind::Classic {
             continue;
         }
 
@@ -214,11 +544,35 @@ async fn hydrate_blocking_scripts(
             }
         };
 
-        match fetch_script_source(&resolved, Arc::clone(&net_provider)).await {
+        let logged = network_log.map(|log| {
+            log.begin(
+                "GET",
+                &resolved,
+                Initiator::Script,
+                RequestSource::for_url(&resolved),
+            )
+        });
+        let fetched = fetch_script_source(
+            &resolved,
+            base_url.as_ref(),
+            user_agent,
+            Arc::clone(&net_provider),
+        )
+        .await;
+        match fetched {
             Ok(code) => {
+                if let Some(logged) = logged {
+                    logged.finish(None, Vec::new(), code.as_bytes());
+                }
+                descriptor.source_map =
+                    fetch_source_map(&code, &resolved, user_agent, Arc::clone(&net_provider)).await;
+                descriptor.url = Some(resolved.to_string());
                 descriptor.source = ScriptSource::Inline { code };
             }
             Err(err) => {
+                if let Some(logged) = logged {
+                    logged.fail(err.to_string());
+                }
                 tracing::error!(
                     target = "quickjs",
                     url = %resolved,
@@ -246,16 +600,64 @@ fn resolve_script_url(src: &str, base: Option<&Url>) -> Result<Url, url::ParseEr
 
 async fn fetch_script_source(
     url: &Url,
+    page: Option<&Url>,
+    user_agent: Option<&str>,
     net_provider: Arc<Provider<Resource>>,
 ) -> Result<String, FetchError> {
-    let (_final_url, bytes) = net_provider
-        .fetch_async(Request::get(url.clone()))
-        .await
-        .map_err(|err| FetchError::Network(format!("{err:?}")))?;
+    let mut request = Request::get(url.clone());
+    privacy::apply_as(&mut request.headers, url, page, user_agent);
+    // http(s) goes through our own client so it takes the configured proxy.
+    let bytes = if matches!(url.scheme(), "http" | "https") {
+        let response = http_client::shared()
+            .map_err(|err| FetchError::Network(err.to_string()))?
+            .get(url.clone())
+            .headers(request.headers)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| FetchError::Network(err.to_string()))?;
+        response
+            .bytes()
+            .await
+            .map_err(|err| FetchError::Network(err.to_string()))?
+    } else {
+        net_provider
+            .fetch_async(request)
+            .await
+            .map_err(|err| FetchError::Network(format!("{err:?}")))?
+            .1
+    };
     let code = std::str::from_utf8(&bytes)?.to_string();
     Ok(code)
 }
 
+/// Fetch the sibling `.map` named at the end of `code`. Inline `data:` maps are left for the
+/// runtime to decode.
+async fn fetch_source_map(
+    code: &str,
+    script_url: &Url,
+    user_agent: Option<&str>,
+    net_provider: Arc<Provider<Resource>>,
+) -> Option<String> {
+    let mapping_url = sourcemap::source_mapping_url(code)?;
+    if mapping_url.starts_with("data:") {
+        return None;
+    }
+    let map_url = script_url.join(mapping_url).ok()?;
+    match fetch_script_source(&map_url, None, user_agent, net_provider).await {
+        Ok(json) => Some(json),
+        Err(err) => {
+            tracing::warn!(
+                target = "quickjs",
+                url = %map_url,
+                error = %err,
+                "failed to fetch source map"
+            );
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,4 +678,145 @@ mod tests {
         ));
         assert!(document.contents.contains("<script>"));
     }
+
+    #[test]
+    fn file_fetch_renders_markdown() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("README.md");
+        std::fs::write(&path, "# Hello\n\n- [ ] todo\n").expect("write markdown");
+        let file_url = Url::from_file_path(&path).expect("file url");
+
+        let document = fetch_file_url(&file_url, file_url.as_str()).expect("file fetch");
+
+        assert!(document.contents.contains("<h1>Hello</h1>"));
+        assert!(document.contents.contains("markdown-body"));
+        assert_eq!(document.file_path.as_deref(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn file_fetch_opens_directories() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        std::fs::create_dir(dir.path().join("docs")).expect("create subdirectory");
+        std::fs::write(dir.path().join("a #1.md"), "# One\n").expect("write markdown");
+        let dir_url = Url::from_file_path(dir.path()).expect("file url");
+
+        let listing = fetch_file_url(&dir_url, dir_url.as_str()).expect("directory listing");
+        assert!(listing.base_url.ends_with('/'));
+        let docs = listing
+            .contents
+            .find("docs/</a>")
+            .expect("subdirectory link");
+        let file = listing.contents.find("a #1.md</a>").expect("file link");
+        assert!(docs < file, "subdirectories come first");
+        assert!(listing.contents.contains("a%20%231.md"));
+
+        std::fs::write(dir.path().join("README.md"), "# Readme\n").expect("write readme");
+        let readme = fetch_file_url(&dir_url, dir_url.as_str()).expect("directory readme");
+        assert!(readme.contents.contains("<h1>Readme</h1>"));
+        assert_eq!(
+            readme.file_path.as_deref(),
+            Some(dir.path().join("README.md").as_path())
+        );
+    }
+
+    #[tokio::test]
+    async fn view_source_prefix_wraps_inner_url() {
+        let plan = prepare_navigation(
+            "view-source:https://example.com/app.js",
+            &SearchEngine::default(),
+        )
+        .await
+        .expect("view-source plan");
+        let NavigationPlan::Fetch(request) = plan;
+        assert_eq!(
+            request.display_url,
+            "view-source:https://example.com/app.js"
+        );
+        match request.source {
+            FetchSource::ViewSource(url) => assert_eq!(url.as_str(), "https://example.com/app.js"),
+            other => panic!("expected view-source request, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn markdown_content_type_renders_without_an_extension() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/notes", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut connection, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = connection.read(&mut request).await;
+            let body = "# Notes\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/markdown; charset=utf-8\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            connection.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let request = FetchRequest {
+            source: FetchSource::Url(url.clone()),
+            display_url: url.to_string(),
+            user_agent: None,
+        };
+        let net_provider = Arc::new(Provider::new(Arc::new(blitz_traits::net::DummyNetCallback)));
+        let document = execute_fetch(&request, net_provider).await.unwrap();
+        assert!(
+            document.contents.contains("<h1>Notes</h1>"),
+            "{}",
+            document.contents
+        );
+    }
+
+    #[tokio::test]
+    async fn superseded_navigation_is_aborted_and_its_result_dropped() {
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+
+        // A server that takes the first navigation's request and never answers it.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/slow", listener.local_addr().unwrap())).unwrap();
+        let net_provider = Arc::new(Provider::new(Arc::new(blitz_traits::net::DummyNetCallback)));
+        let (results_tx, mut results) = tokio::sync::mpsc::unbounded_channel();
+        let mut navigations = Navigations::default();
+
+        let first = navigations.begin();
+        let request = FetchRequest {
+            source: FetchSource::Url(url.clone()),
+            display_url: url.to_string(),
+            user_agent: None,
+        };
+        let tx = results_tx.clone();
+        navigations.track(tokio::spawn(async move {
+            let result = execute_fetch(&request, net_provider).await;
+            let _ = tx.send((first, result.is_ok()));
+        }));
+        let (mut connection, _) = listener.accept().await.unwrap();
+
+        let second = navigations.begin();
+        navigations.track(tokio::spawn(async move {
+            let _ = results_tx.send((second, true));
+        }));
+
+        // Aborting dropped the first fetch, which closed its connection unanswered.
+        let mut request_bytes = Vec::new();
+        let closed = tokio::time::timeout(
+            Duration::from_secs(5),
+            connection.read_to_end(&mut request_bytes),
+        )
+        .await;
+        assert!(closed.is_ok(), "the first fetch is still waiting");
+
+        assert_eq!(results.recv().await, Some((second, true)));
+        assert!(navigations.finish(second));
+        assert!(!navigations.is_loading());
+        assert_eq!(results.recv().await, None, "the first fetch never finished");
+
+        // Its result arriving after the second navigation's is dropped.
+        assert!(!navigations.finish(first));
+        assert_eq!(navigations.generation(), second);
+    }
 }
@@ -0,0 +1,380 @@
+//! Per-document record of every request a page makes, behind the network inspector panel
+//! (Cmd/Ctrl+Shift+E) and the automation `network` endpoint.
+//!
+//! [`NetworkLog::reset`] starts a fresh log when a navigation begins. Each request takes a
+//! [`PendingRequest`] from [`NetworkLog::begin`] and settles it with
+//! [`PendingRequest::finish`] or [`PendingRequest::fail`]; one dropped unsettled is recorded
+//! as cancelled. Requests begun before the latest reset are ignored when they settle, so a
+//! slow response from the previous page never lands in the current log.
+//!
+//! Blitz does not report response status or headers for documents and subresources, so
+//! `status` and `response_headers` are only filled for `fetch()` requests. Response bodies are
+//! only kept after [`NetworkLog::set_capture_bodies`], for the automation HAR export.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+use html_escape::encode_text;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestSource {
+    Http,
+    File,
+    /// Served from the offline page cache.
+    Cache,
+    /// Served from a copy loaded ahead of navigation.
+    Prefetch,
+}
+
+impl RequestSource {
+    pub fn for_url(url: &Url) -> Self {
+        if url.scheme() == "file" {
+            RequestSource::File
+        } else {
+            RequestSource::Http
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            RequestSource::Http => "http",
+            RequestSource::File => "file",
+            RequestSource::Cache => "cache",
+            RequestSource::Prefetch => "prefetch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Initiator {
+    Document,
+    /// A blocking `<script src>` inlined before the document is built.
+    Script,
+    /// Stylesheets, fonts and images requested by blitz.
+    Subresource,
+    /// `fetch()` from page script.
+    Fetch,
+}
+
+impl Initiator {
+    fn label(self) -> &'static str {
+        match self {
+            Initiator::Document => "document",
+            Initiator::Script => "script",
+            Initiator::Subresource => "subresource",
+            Initiator::Fetch => "fetch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkEntry {
+    pub url: String,
+    pub method: String,
+    pub initiator: Initiator,
+    pub source: RequestSource,
+    pub status: Option<u16>,
+    /// Response body size in bytes.
+    pub size: Option<u64>,
+    /// Milliseconds from the start of the navigation.
+    pub start_ms: f64,
+    /// `None` while the request is in flight.
+    pub duration_ms: Option<f64>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub response_headers: Vec<(String, String)>,
+    /// Response body, while body capture is on; at most [`MAX_CAPTURED_BODY`] bytes.
+    #[serde(skip)]
+    pub body: Option<Vec<u8>>,
+}
+
+/// Larger responses are logged without their body.
+pub const MAX_CAPTURED_BODY: usize = 2 * 1024 * 1024;
+
+struct LogState {
+    generation: u64,
+    started: Instant,
+    /// Wall-clock time of `started`, for exports that need real timestamps.
+    started_at: SystemTime,
+    capture_bodies: bool,
+    /// Bumped on every change so the panel only re-renders when there is something new.
+    revision: u64,
+    entries: Vec<NetworkEntry>,
+}
+
+#[derive(Clone)]
+pub struct NetworkLog {
+    state: Arc<Mutex<LogState>>,
+}
+
+impl Default for NetworkLog {
+    fn default() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(LogState {
+                generation: 0,
+                started: Instant::now(),
+                started_at: SystemTime::now(),
+                capture_bodies: false,
+                revision: 0,
+                entries: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl NetworkLog {
+    /// Forget the previous document's requests and restart the clock.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.started = Instant::now();
+        state.started_at = SystemTime::now();
+        state.revision += 1;
+        state.entries.clear();
+    }
+
+    pub fn begin(
+        &self,
+        method: &str,
+        url: &Url,
+        initiator: Initiator,
+        source: RequestSource,
+    ) -> PendingRequest {
+        let started = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let start_ms = started.duration_since(state.started).as_secs_f64() * 1000.0;
+        state.entries.push(NetworkEntry {
+            url: url.to_string(),
+            method: method.to_string(),
+            initiator,
+            source,
+            status: None,
+            size: None,
+            start_ms,
+            duration_ms: None,
+            error: None,
+            response_headers: Vec::new(),
+            body: None,
+        });
+        state.revision += 1;
+        PendingRequest {
+            log: self.clone(),
+            generation: state.generation,
+            index: state.entries.len() - 1,
+            started,
+            settled: false,
+        }
+    }
+
+    /// When the log was last reset, i.e. when the current navigation started.
+    pub fn started(&self) -> Instant {
+        self.state.lock().unwrap().started
+    }
+
+    /// Wall-clock time the current navigation started.
+    pub fn started_at(&self) -> SystemTime {
+        self.state.lock().unwrap().started_at
+    }
+
+    /// Keep response bodies from now on. Off by default, since they stay in memory until
+    /// the next navigation.
+    pub fn set_capture_bodies(&self, capture: bool) {
+        self.state.lock().unwrap().capture_bodies = capture;
+    }
+
+    pub fn entries(&self) -> Vec<NetworkEntry> {
+        self.state.lock().unwrap().entries.clone()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.state.lock().unwrap().revision
+    }
+
+    fn settle(
+        &self,
+        generation: u64,
+        index: usize,
+        started: Instant,
+        update: impl FnOnce(&mut NetworkEntry, bool),
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        let capture_bodies = state.capture_bodies;
+        if let Some(entry) = state.entries.get_mut(index) {
+            entry.duration_ms = Some(started.elapsed().as_secs_f64() * 1000.0);
+            update(entry, capture_bodies);
+            state.revision += 1;
+        }
+    }
+}
+
+/// A request recorded in a [`NetworkLog`] that has not completed yet.
+pub struct PendingRequest {
+    log: NetworkLog,
+    generation: u64,
+    index: usize,
+    started: Instant,
+    settled: bool,
+}
+
+impl PendingRequest {
+    /// Settle with the response; its body is kept when the log captures bodies.
+    pub fn finish(mut self, status: Option<u16>, headers: Vec<(String, String)>, body: &[u8]) {
+        self.settled = true;
+        self.log.settle(
+            self.generation,
+            self.index,
+            self.started,
+            |entry, capture| {
+                entry.status = status;
+                entry.size = Some(body.len() as u64);
+                entry.response_headers = headers;
+                if capture && body.len() <= MAX_CAPTURED_BODY {
+                    entry.body = Some(body.to_vec());
+                }
+            },
+        );
+    }
+
+    pub fn fail(mut self, error: impl Into<String>) {
+        self.settled = true;
+        let error = error.into();
+        self.log
+            .settle(self.generation, self.index, self.started, |entry, _| {
+                entry.error = Some(error);
+            });
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.log
+                .settle(self.generation, self.index, self.started, |entry, _| {
+                    entry.error = Some("cancelled".to_string());
+                });
+        }
+    }
+}
+
+/// HTML for the inspector panel, placed in the chrome's overlay host.
+pub fn panel_html(entries: &[NetworkEntry]) -> String {
+    let rows = if entries.is_empty() {
+        "<tr><td colspan=\"7\">No requests yet.</td></tr>".to_string()
+    } else {
+        entries
+            .iter()
+            .map(|entry| {
+                let status = match (&entry.error, entry.status) {
+                    (Some(error), _) => encode_text(error).into_owned(),
+                    (None, Some(status)) => status.to_string(),
+                    (None, None) if entry.duration_ms.is_some() => "done".to_string(),
+                    (None, None) => "pending".to_string(),
+                };
+                format!(
+                    "<tr><td>{method}</td><td class=\"network-url\">{url}</td><td>{status}</td><td>{initiator}</td><td>{source}</td><td>{size}</td><td>{timing}</td></tr>",
+                    method = encode_text(&entry.method),
+                    url = encode_text(&entry.url),
+                    initiator = entry.initiator.label(),
+                    source = entry.source.label(),
+                    size = entry.size.map(format_size).unwrap_or_default(),
+                    timing = match entry.duration_ms {
+                        Some(duration) => format!("{:.0} ms +{duration:.0} ms", entry.start_ms),
+                        None => format!("{:.0} ms", entry.start_ms),
+                    },
+                )
+            })
+            .collect()
+    };
+    let total: u64 = entries.iter().filter_map(|entry| entry.size).sum();
+    format!(
+        r#"<aside id="network-panel" aria-label="Network requests">
+<header>{count} requests, {total} transferred</header>
+<table>
+<thead><tr><th>Method</th><th>URL</th><th>Status</th><th>Type</th><th>Source</th><th>Size</th><th>Start / duration</th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+</aside>"#,
+        count = entries.len(),
+        total = format_size(total),
+    )
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn records_completion_failure_and_cancellation() {
+        let log = NetworkLog::default();
+        let page = url("https://a.example/");
+        log.begin("GET", &page, Initiator::Document, RequestSource::Http)
+            .finish(None, Vec::new(), &[0; 2048]);
+        log.begin(
+            "POST",
+            &url("https://a.example/api"),
+            Initiator::Fetch,
+            RequestSource::Http,
+        )
+        .fail("Failed to fetch");
+        drop(log.begin(
+            "GET",
+            &url("https://a.example/a.png"),
+            Initiator::Subresource,
+            RequestSource::Http,
+        ));
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].size, Some(2048));
+        assert!(entries[0].duration_ms.is_some());
+        assert_eq!(entries[1].error.as_deref(), Some("Failed to fetch"));
+        assert_eq!(entries[2].error.as_deref(), Some("cancelled"));
+        assert!(panel_html(&entries).contains("3 requests, 2.0 KB transferred"));
+    }
+
+    #[test]
+    fn requests_from_before_a_reset_are_ignored() {
+        let log = NetworkLog::default();
+        let stale = log.begin(
+            "GET",
+            &url("https://a.example/slow.css"),
+            Initiator::Subresource,
+            RequestSource::Http,
+        );
+        log.reset();
+        let current = log.begin(
+            "GET",
+            &url("https://b.example/"),
+            Initiator::Document,
+            RequestSource::Http,
+        );
+        stale.finish(None, Vec::new(), &[0; 10]);
+        current.finish(None, Vec::new(), &[0; 20]);
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, "https://b.example/");
+        assert_eq!(entries[0].size, Some(20));
+    }
+}
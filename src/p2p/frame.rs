@@ -0,0 +1,131 @@
+//! The wire format of a channel: one signed ephemeral event per frame, addressed to the peer
+//! with a `p` tag, its content the NIP-44 encryption of a JSON envelope naming the origin the
+//! channel belongs to and the frame's place in the channel. Each side picks a random id for its
+//! end of a channel and names both ends on every frame once it knows the peer's, so frames
+//! from an earlier channel never match a new one; it numbers the frames it sends from zero, so
+//! the receiver can drop repeated and reordered ones.
+
+use anyhow::{bail, Context, Result};
+use nostr_sdk::nips::nip44::{self, Version};
+use nostr_sdk::prelude::{Event, EventBuilder, Keys, Kind, PublicKey, Tag};
+use serde::{Deserialize, Serialize};
+
+/// Ephemeral (20000–29999), so relays pass frames on to subscribers and store none of them.
+pub const CHANNEL_KIND: u16 = 25_050;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Frame {
+    /// Sent by `frontier.connect`: "I want a channel with you for this origin".
+    Hello,
+    /// The answer to a `Hello` from a side that wants the channel too.
+    Ready,
+    Message {
+        data: String,
+    },
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub origin: String,
+    /// The sender's id for its end of the channel.
+    pub channel: String,
+    /// The receiver's id for its end, once the sender has heard from it; `None` only on
+    /// `Hello`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_channel: Option<String>,
+    /// How many frames the sender sent on the channel before this one.
+    pub seq: u64,
+    #[serde(flatten)]
+    pub frame: Frame,
+}
+
+/// `envelope` encrypted to `peer` and signed by `keys`.
+pub fn seal(keys: &Keys, peer: &PublicKey, envelope: &Envelope) -> Result<Event> {
+    let envelope = serde_json::to_string(envelope)?;
+    let content = nip44::encrypt(keys.secret_key()?, peer, envelope, Version::V2)
+        .context("encrypt channel frame")?;
+    Ok(
+        EventBuilder::new(Kind::from(CHANNEL_KIND), content, [Tag::public_key(*peer)])
+            .to_event(keys)?,
+    )
+}
+
+/// The sender and envelope of a channel event addressed to `keys`. Fails for events that are
+/// not channel frames, are forged, or were encrypted for someone else.
+pub fn open(keys: &Keys, event: &Event) -> Result<(PublicKey, Envelope)> {
+    if event.kind() != Kind::from(CHANNEL_KIND) {
+        bail!("not a channel frame");
+    }
+    event.verify().context("invalid signature")?;
+    let sender = event.pubkey;
+    if sender == keys.public_key() {
+        bail!("frame sent by this identity");
+    }
+    let envelope = nip44::decrypt(keys.secret_key()?, &sender, event.content())
+        .context("decrypt channel frame")?;
+    let envelope = serde_json::from_str(&envelope).context("invalid channel frame")?;
+    Ok((sender, envelope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_reach_only_the_peer_they_are_sealed_for() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let eve = Keys::generate();
+        let envelope = Envelope {
+            origin: "https://chess.example".to_string(),
+            channel: "a1".to_string(),
+            peer_channel: Some("b1".to_string()),
+            seq: 3,
+            frame: Frame::Message {
+                data: "{\"move\":\"e4\"}".to_string(),
+            },
+        };
+
+        let event = seal(&alice, &bob.public_key(), &envelope).unwrap();
+        assert_eq!(event.kind(), Kind::from(CHANNEL_KIND));
+        assert!(!event.content().contains("e4"), "content is encrypted");
+        let tags: Vec<Vec<String>> = event.tags().iter().map(|tag| tag.as_vec()).collect();
+        assert_eq!(tags, vec![vec!["p".to_string(), bob.public_key().to_hex()]]);
+
+        let (sender, opened) = open(&bob, &event).unwrap();
+        assert_eq!(sender, alice.public_key());
+        assert_eq!(opened, envelope);
+        assert!(open(&eve, &event).is_err());
+        assert!(open(&alice, &event).is_err(), "own frames are ignored");
+    }
+
+    #[test]
+    fn envelopes_are_tagged_json() {
+        let envelope = Envelope {
+            origin: "https://a.example".to_string(),
+            channel: "a1".to_string(),
+            peer_channel: None,
+            seq: 0,
+            frame: Frame::Hello,
+        };
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            r#"{"origin":"https://a.example","channel":"a1","seq":0,"type":"hello"}"#
+        );
+        let message: Envelope = serde_json::from_str(
+            r#"{"origin":"o","channel":"b1","peer_channel":"a1","seq":1,"type":"message","data":"hi"}"#,
+        )
+        .unwrap();
+        assert_eq!(message.seq, 1);
+        assert_eq!(message.peer_channel.as_deref(), Some("a1"));
+        assert!(serde_json::from_str::<Envelope>(r#"{"origin":"o","type":"hello"}"#).is_err());
+        assert_eq!(
+            message.frame,
+            Frame::Message {
+                data: "hi".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Direct channels between two nostr users for `frontier.connect(npub)`, with no DNS name or
+//! server of the app's own in between.
+//!
+//! A page asks for a channel to a peer's npub; after the user agrees (see [`prompt`]), the
+//! browser signs as the identity the page's origin uses and relays every frame through the
+//! relays saved in preferences as an ephemeral event (see [`frame`]). Frames are NIP-44
+//! encrypted between the two identities, so relays see who talks to whom and when, but not
+//! what is said or which site it is for. A channel opens once the peer's browser, on a page
+//! of the same origin, calls `frontier.connect` back; until then, and for at most
+//! [`CONNECT_TIMEOUT`], the promise stays pending.
+//!
+//! One [`P2pSession`] carries the channels of the page in the window; it is dropped, closing
+//! them, when the page goes away.
+
+pub mod frame;
+mod session;
+
+use std::time::Duration;
+
+use crate::signer::{custom_buttons, pressed_button};
+
+pub use session::P2pSession;
+
+/// How long `frontier.connect` waits for the peer to connect back.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What happened to a page's channel, reported from the session's task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct P2pEvent {
+    /// The [`P2pSession::id`] the channel belongs to.
+    pub session: u64,
+    pub channel: u32,
+    pub event: ChannelEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelEvent {
+    Opened,
+    Message(String),
+    /// The channel ended, or never opened, for this reason.
+    Closed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectDecision {
+    Allow,
+    /// Allow, and stop asking when this origin connects to this peer.
+    AlwaysAllow,
+    Deny,
+}
+
+/// Ask the user with a native dialog. Blocks until answered.
+pub fn prompt(origin: &str, identity: &str, peer: &str) -> ConnectDecision {
+    let labels = ["Connect", "Always allow", "Deny"];
+    let result = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title(format!("{origin} wants to connect as {identity}"))
+        .set_description(format!(
+            "Open an encrypted channel to {peer} through your relays. The relays will see that \
+             the two of you are talking, but not what you say."
+        ))
+        .set_buttons(custom_buttons(labels))
+        .show();
+    match pressed_button(&result, labels) {
+        Some(0) => ConnectDecision::Allow,
+        Some(1) => ConnectDecision::AlwaysAllow,
+        _ => ConnectDecision::Deny,
+    }
+}
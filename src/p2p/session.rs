@@ -0,0 +1,543 @@
+//! The relay connection behind one page's channels.
+//!
+//! A frame only reaches the page if it names this end's random channel id, has not been seen on
+//! the channel before and is numbered after the last one that did, so a relay (or anyone who
+//! captured the events) cannot replay an earlier conversation into a new channel or repeat
+//! messages within one. Only a `Hello` names no id: it gets a `Ready` naming the peer's end
+//! back, and the channel opens on the first `Ready` that names this one. Nothing depends on
+//! the two clocks agreeing.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use nostr_sdk::prelude::{
+    Client, Event, EventId, Filter, Keys, Kind, PublicKey, RelayPoolNotification, Timestamp,
+    ToBech32,
+};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use super::frame::{self, Envelope, Frame, CHANNEL_KIND};
+use super::{ChannelEvent, P2pEvent, CONNECT_TIMEOUT};
+
+/// How often unanswered `connect` calls are checked against [`CONNECT_TIMEOUT`].
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How far back the relay subscription reaches, so a peer whose clock runs behind is still
+/// heard. Channel ids, not timestamps, keep old frames out.
+const CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+enum Command {
+    Connect { channel: u32, peer: PublicKey },
+    Send { channel: u32, data: String },
+    Close { channel: u32 },
+    Incoming(Box<Event>),
+    CheckTimeouts,
+    Shutdown,
+}
+
+/// Channels one identity holds for the page of one origin, over the user's relays. Dropping
+/// the session closes them all.
+pub struct P2pSession {
+    id: u64,
+    identity: PublicKey,
+    commands: UnboundedSender<Command>,
+}
+
+impl P2pSession {
+    /// Connect to `relays` as `keys` for pages of `origin`. `events` is called from the
+    /// session's task for everything that happens to its channels.
+    pub fn start(
+        handle: &Handle,
+        keys: Keys,
+        origin: String,
+        relays: Vec<Url>,
+        events: impl Fn(P2pEvent) + Send + 'static,
+    ) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let identity = keys.public_key();
+        let (commands, receiver) = unbounded_channel();
+        let task = Task {
+            session: id,
+            keys,
+            origin,
+            commands: commands.clone(),
+            events: Mutex::new(Box::new(events)),
+            channels: HashMap::new(),
+        };
+        handle.spawn(task.run(relays, receiver));
+        Self {
+            id,
+            identity,
+            commands,
+        }
+    }
+
+    /// Tags the session's [`P2pEvent`]s, so events of a page that is gone can be told apart.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn identity(&self) -> PublicKey {
+        self.identity
+    }
+
+    /// Open `channel` to `peer`; it opens once the peer's page connects back.
+    pub fn connect(&self, channel: u32, peer: PublicKey) {
+        let _ = self.commands.send(Command::Connect { channel, peer });
+    }
+
+    pub fn send(&self, channel: u32, data: String) {
+        let _ = self.commands.send(Command::Send { channel, data });
+    }
+
+    pub fn close(&self, channel: u32) {
+        let _ = self.commands.send(Command::Close { channel });
+    }
+}
+
+impl Drop for P2pSession {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+    }
+}
+
+struct Channel {
+    peer: PublicKey,
+    /// Random id of this end, which the peer names on every frame after its `Hello`.
+    id: String,
+    /// The peer's id for its end, once heard from.
+    peer_id: Option<String>,
+    open: bool,
+    /// Whether this side sent `Ready` to the peer's current end.
+    answered: bool,
+    started: Instant,
+    /// How many frames this side has sent.
+    sent: u64,
+    /// The number of the last frame accepted from the peer's current end.
+    received: Option<u64>,
+    seen: HashSet<EventId>,
+}
+
+impl Channel {
+    fn new(peer: PublicKey) -> Self {
+        Self {
+            peer,
+            id: Uuid::new_v4().simple().to_string(),
+            peer_id: None,
+            open: false,
+            answered: false,
+            started: Instant::now(),
+            sent: 0,
+            received: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// The next frame this side sends, naming both ends.
+    fn envelope(&mut self, origin: &str, frame: Frame) -> Envelope {
+        let seq = self.sent;
+        self.sent += 1;
+        Envelope {
+            origin: origin.to_string(),
+            channel: self.id.clone(),
+            peer_channel: self.peer_id.clone(),
+            seq,
+            frame,
+        }
+    }
+
+    /// How well a frame from `sender`'s end `from`, addressed to this end as `to`, fits this
+    /// channel: `Some(0)` for the end it already talks to, `Some(1)` for a `Hello` it can take
+    /// up, `Some(2)` for a `Hello` from another end before it opened, `None` if not at all.
+    fn fit(&self, sender: &PublicKey, from: &str, to: Option<&str>) -> Option<u8> {
+        if self.peer != *sender || to.is_some_and(|to| to != self.id) {
+            return None;
+        }
+        match &self.peer_id {
+            Some(peer_id) if peer_id == from => Some(0),
+            _ if to.is_some() => (!self.open).then_some(0),
+            None => Some(1),
+            Some(_) => (!self.open).then_some(2),
+        }
+    }
+
+    /// Whether the frame `event`, numbered `seq` by the peer's end `from`, is new to the
+    /// channel; accepting it records it. Until the channel opens, a frame from another end of
+    /// the peer's replaces the one heard from before, and the numbering starts over.
+    fn accept(&mut self, event: EventId, from: &str, seq: u64) -> bool {
+        if self.seen.contains(&event) {
+            return false;
+        }
+        if self.peer_id.as_deref() != Some(from) {
+            if self.open {
+                return false;
+            }
+            self.peer_id = Some(from.to_string());
+            self.answered = false;
+            self.received = None;
+        } else if self.received.is_some_and(|last| seq <= last) {
+            return false;
+        }
+        self.seen.insert(event);
+        self.received = Some(seq);
+        true
+    }
+}
+
+/// The channel a frame from `sender`'s end `from`, addressed to `to`, belongs to. Channels to
+/// the same peer are told apart by their ids; a `Hello` goes to a channel that has not heard
+/// from the peer yet before one that has.
+fn channel_for(
+    channels: &HashMap<u32, Channel>,
+    sender: &PublicKey,
+    from: &str,
+    to: Option<&str>,
+) -> Option<u32> {
+    channels
+        .iter()
+        .filter_map(|(&id, channel)| Some((channel.fit(sender, from, to)?, id)))
+        .min()
+        .map(|(_, id)| id)
+}
+
+struct Task {
+    session: u64,
+    keys: Keys,
+    origin: String,
+    /// Where the relay and timer feeds queue what they see.
+    commands: UnboundedSender<Command>,
+    /// Behind a mutex because the event loop proxy is not `Sync` on every platform.
+    events: Mutex<Box<dyn Fn(P2pEvent) + Send>>,
+    channels: HashMap<u32, Channel>,
+}
+
+impl Task {
+    async fn run(mut self, relays: Vec<Url>, mut commands: UnboundedReceiver<Command>) {
+        let client = match crate::relays::connect(&relays).await {
+            Ok(client) => client,
+            Err(err) => {
+                let reason = format!("could not reach the relays: {err:#}");
+                while let Some(command) = commands.recv().await {
+                    match command {
+                        Command::Connect { channel, .. } => {
+                            self.emit(channel, ChannelEvent::Closed(reason.clone()))
+                        }
+                        Command::Shutdown => return,
+                        _ => {}
+                    }
+                }
+                return;
+            }
+        };
+        let filter = Filter::new()
+            .kind(Kind::from(CHANNEL_KIND))
+            .pubkey(self.keys.public_key())
+            .since(Timestamp::from(
+                Timestamp::now()
+                    .as_u64()
+                    .saturating_sub(CLOCK_SKEW.as_secs()),
+            ));
+        let _ = client.subscribe(vec![filter], None).await;
+        info!(
+            target = "p2p",
+            identity = %self.keys.public_key().to_bech32().unwrap_or_default(),
+            origin = %self.origin,
+            "listening for channel frames"
+        );
+        let feeds = [self.forward_frames(&client), self.check_timeouts()];
+
+        while let Some(command) = commands.recv().await {
+            match command {
+                Command::Connect { channel, peer } => {
+                    let mut opened = Channel::new(peer);
+                    let hello = opened.envelope(&self.origin, Frame::Hello);
+                    self.channels.insert(channel, opened);
+                    self.send(&client, peer, hello).await;
+                }
+                Command::Send { channel, data } => {
+                    let origin = &self.origin;
+                    let message = self
+                        .channels
+                        .get_mut(&channel)
+                        .filter(|channel| channel.open)
+                        .map(|channel| {
+                            let envelope = channel.envelope(origin, Frame::Message { data });
+                            (channel.peer, envelope)
+                        });
+                    if let Some((peer, envelope)) = message {
+                        self.send(&client, peer, envelope).await;
+                    }
+                }
+                Command::Close { channel } => {
+                    if let Some(mut closed) = self.channels.remove(&channel) {
+                        let close = closed.envelope(&self.origin, Frame::Close);
+                        self.send(&client, closed.peer, close).await;
+                    }
+                }
+                Command::Incoming(event) => self.receive(&client, &event).await,
+                Command::CheckTimeouts => self.expire_unanswered(),
+                Command::Shutdown => break,
+            }
+        }
+
+        for (_, mut channel) in std::mem::take(&mut self.channels) {
+            if channel.open {
+                let close = channel.envelope(&self.origin, Frame::Close);
+                self.send(&client, channel.peer, close).await;
+            }
+        }
+        for feed in feeds {
+            feed.abort();
+        }
+        let _ = client.disconnect().await;
+        debug!(target = "p2p", session = self.session, "session closed");
+    }
+
+    /// Queue the frames relays deliver as [`Command::Incoming`].
+    fn forward_frames(&self, client: &Client) -> JoinHandle<()> {
+        let mut notifications = client.notifications();
+        let commands = self.commands.clone();
+        tokio::spawn(async move {
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayPoolNotification::Event { event, .. } = notification {
+                    if commands.send(Command::Incoming(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    fn check_timeouts(&self) -> JoinHandle<()> {
+        let commands = self.commands.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TIMEOUT_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if commands.send(Command::CheckTimeouts).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+
+    async fn receive(&mut self, client: &Client, event: &Event) {
+        let (sender, envelope) = match frame::open(&self.keys, event) {
+            Ok(opened) => opened,
+            Err(err) => {
+                debug!(target = "p2p", error = %err, "dropping channel event");
+                return;
+            }
+        };
+        let Envelope {
+            origin,
+            channel: from,
+            peer_channel: to,
+            seq,
+            frame,
+        } = envelope;
+        // Only a `Hello` may leave out which end it is for.
+        if origin != self.origin || (to.is_none() && frame != Frame::Hello) {
+            return;
+        }
+        let Some(id) = channel_for(&self.channels, &sender, &from, to.as_deref()) else {
+            return;
+        };
+        let channel = self.channels.get_mut(&id).expect("channel just found");
+        if !channel.accept(event.id(), &from, seq) {
+            debug!(
+                target = "p2p",
+                seq, "dropping a replayed or reordered channel frame"
+            );
+            return;
+        }
+        let answer = match &frame {
+            Frame::Hello => true,
+            Frame::Ready => !channel.open,
+            _ => false,
+        };
+        let ready = (answer && !std::mem::replace(&mut channel.answered, true))
+            .then(|| channel.envelope(&self.origin, Frame::Ready));
+        let opened = matches!(frame, Frame::Ready) && !std::mem::replace(&mut channel.open, true);
+        let open = channel.open;
+        if let Some(ready) = ready {
+            self.send(client, sender, ready).await;
+        }
+        match frame {
+            Frame::Ready if opened => self.emit(id, ChannelEvent::Opened),
+            Frame::Message { data } if open => self.emit(id, ChannelEvent::Message(data)),
+            Frame::Close => {
+                self.channels.remove(&id);
+                self.emit(id, ChannelEvent::Closed("closed by the peer".to_string()));
+            }
+            Frame::Hello | Frame::Ready | Frame::Message { .. } => {}
+        }
+    }
+
+    fn expire_unanswered(&mut self) {
+        let expired: Vec<u32> = self
+            .channels
+            .iter()
+            .filter(|(_, channel)| !channel.open && channel.started.elapsed() >= CONNECT_TIMEOUT)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            self.channels.remove(&id);
+            let reason = format!(
+                "the peer did not connect back within {}s",
+                CONNECT_TIMEOUT.as_secs()
+            );
+            self.emit(id, ChannelEvent::Closed(reason));
+        }
+    }
+
+    /// Send `envelope` to `peer`.
+    async fn send(&self, client: &Client, peer: PublicKey, envelope: Envelope) {
+        let sent = match frame::seal(&self.keys, &peer, &envelope) {
+            Ok(event) => client
+                .send_event(event)
+                .await
+                .map(|_| ())
+                .map_err(anyhow::Error::from),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            warn!(target = "p2p", error = %err, "could not send a channel frame");
+        }
+    }
+
+    fn emit(&self, channel: u32, event: ChannelEvent) {
+        let events = self.events.lock().unwrap();
+        events(P2pEvent {
+            session: self.session,
+            channel,
+            event,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr_sdk::prelude::EventBuilder;
+
+    use super::*;
+
+    /// `envelope` as `from` would send it to `to`, signed `age` seconds ago.
+    fn sealed(from: &Keys, to: &Keys, envelope: &Envelope, age: u64) -> Event {
+        let content = nostr_sdk::nips::nip44::encrypt(
+            from.secret_key().unwrap(),
+            &to.public_key(),
+            serde_json::to_string(envelope).unwrap(),
+            nostr_sdk::nips::nip44::Version::V2,
+        )
+        .unwrap();
+        EventBuilder::new(Kind::from(CHANNEL_KIND), content, [])
+            .custom_created_at(Timestamp::from(Timestamp::now().as_u64() - age))
+            .to_event(from)
+            .unwrap()
+    }
+
+    /// Deliver `event` to `channels` as the session does, returning the channel it was
+    /// accepted on.
+    fn deliver(keys: &Keys, channels: &mut HashMap<u32, Channel>, event: &Event) -> Option<u32> {
+        let (sender, envelope) = frame::open(keys, event).unwrap();
+        let id = channel_for(
+            channels,
+            &sender,
+            &envelope.channel,
+            envelope.peer_channel.as_deref(),
+        )?;
+        channels
+            .get_mut(&id)
+            .unwrap()
+            .accept(event.id(), &envelope.channel, envelope.seq)
+            .then_some(id)
+    }
+
+    #[test]
+    fn channels_drop_repeated_and_reordered_frames() {
+        let mut channel = Channel::new(Keys::generate().public_key());
+        let id = |byte: u8| EventId::from_slice(&[byte; 32]).unwrap();
+
+        assert!(channel.accept(id(2), "b1", 0));
+        assert!(!channel.accept(id(2), "b1", 0), "the same event twice");
+        assert!(!channel.accept(id(3), "b1", 0), "a repeated number");
+        assert!(channel.accept(id(4), "b1", 2));
+        assert!(!channel.accept(id(5), "b1", 1), "out of order");
+        channel.open = true;
+        assert!(!channel.accept(id(6), "b2", 3), "another end once open");
+        assert!(channel.accept(id(7), "b1", 3));
+        let first = channel.envelope("o", Frame::Hello);
+        let second = channel.envelope("o", Frame::Ready);
+        assert_eq!((first.seq, second.seq), (0, 1));
+        assert_eq!(second.peer_channel.as_deref(), Some("b1"));
+    }
+
+    #[test]
+    fn a_peer_whose_clock_runs_behind_still_opens_the_channel() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut alice_channels = HashMap::from([(1, Channel::new(bob.public_key()))]);
+        let mut bob_channels = HashMap::from([(7, Channel::new(alice.public_key()))]);
+
+        // Bob's clock is a minute behind, so everything he signs predates Alice's channel.
+        let hello = alice_channels
+            .get_mut(&1)
+            .unwrap()
+            .envelope("o", Frame::Hello);
+        let hello = sealed(&alice, &bob, &hello, 0);
+        assert_eq!(deliver(&bob, &mut bob_channels, &hello), Some(7));
+        let ready = bob_channels
+            .get_mut(&7)
+            .unwrap()
+            .envelope("o", Frame::Ready);
+        let ready = sealed(&bob, &alice, &ready, 60);
+        assert_eq!(deliver(&alice, &mut alice_channels, &ready), Some(1));
+
+        // A frame from an earlier channel between them names an end that no longer exists.
+        let mut earlier = Channel::new(alice.public_key());
+        earlier.peer_id = Some("gone".to_string());
+        let stale = earlier.envelope("o", Frame::Ready);
+        let stale = sealed(&bob, &alice, &stale, 0);
+        assert_eq!(deliver(&alice, &mut alice_channels, &stale), None);
+    }
+
+    #[test]
+    fn channels_to_the_same_peer_keep_their_own_frames() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let mut channels = HashMap::from([
+            (1, Channel::new(bob.public_key())),
+            (2, Channel::new(bob.public_key())),
+        ]);
+        let mut first = Channel::new(alice.public_key());
+        let mut second = Channel::new(alice.public_key());
+
+        let hello = sealed(&bob, &alice, &first.envelope("o", Frame::Hello), 0);
+        let taken = deliver(&alice, &mut channels, &hello).unwrap();
+        let hello = sealed(&bob, &alice, &second.envelope("o", Frame::Hello), 0);
+        let other = deliver(&alice, &mut channels, &hello).unwrap();
+        assert_ne!(taken, other);
+
+        // Each of Bob's ends only reaches the channel that answered it.
+        first.peer_id = Some(channels[&taken].id.clone());
+        second.peer_id = Some(channels[&other].id.clone());
+        for (end, expected) in [(&mut second, other), (&mut first, taken)] {
+            let message = end.envelope(
+                "o",
+                Frame::Message {
+                    data: "hi".to_string(),
+                },
+            );
+            let message = sealed(&bob, &alice, &message, 0);
+            assert_eq!(deliver(&alice, &mut channels, &message), Some(expected));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Last good copy of every http(s) document, kept so navigation can fall back to it when the
+//! network is unreachable or the user chose to work offline.
+//!
+//! Entries are JSON files in `page-cache/` under the data directory, named by the SHA-256 of
+//! the requested URL. Each holds the finished [`FetchedDocument`] (markdown rendered, external
+//! blocking scripts inlined) so a cached page runs the same scripts it did online. The cache
+//! is kept under the size set in preferences by dropping the least recently stored entries.
+//!
+//! Only successful responses are kept, so an error page never replaces the last good copy, and
+//! nothing the server marked `no-store` or `private` is written to disk.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::navigation::{FetchedDocument, ResponseInfo};
+use crate::storage_policy::StoragePolicy;
+
+const DIR_NAME: &str = "page-cache";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// Seconds since the Unix epoch.
+    pub fetched_at: u64,
+    pub document: FetchedDocument,
+}
+
+#[derive(Debug, Clone)]
+pub struct PageCache {
+    dir: PathBuf,
+    /// Total size of the entries kept; 0 stores nothing.
+    max_bytes: u64,
+}
+
+impl PageCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            max_bytes: u64::MAX,
+        }
+    }
+
+    /// The same cache holding at most `max_bytes`; the next store trims it.
+    pub fn with_limit(self, max_bytes: u64) -> Self {
+        Self { max_bytes, ..self }
+    }
+
+    /// The cache under the data directory, if one can be determined.
+    pub fn open_default() -> Option<Self> {
+        crate::preferences::data_file(DIR_NAME).map(Self::new)
+    }
+
+    /// Only network documents are cached; `file:` and internal pages are always available.
+    pub fn is_cacheable(url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+    }
+
+    fn entry_path(&self, url: &Url) -> PathBuf {
+        let digest = Sha256::digest(url.as_str().as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(digest)))
+    }
+
+    /// Does nothing in a private session, or for a response that is not a success or may not
+    /// be stored (see [`ResponseInfo::is_storable`]).
+    pub fn store(&self, url: &Url, document: &FetchedDocument) -> Result<()> {
+        if self.max_bytes == 0 || !StoragePolicy::current().persists() {
+            return Ok(());
+        }
+        if document
+            .response
+            .as_ref()
+            .is_some_and(|response| !response.is_storable())
+        {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("unable to create {}", self.dir.display()))?;
+        let entry = CachedPage {
+            fetched_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            document: document.clone(),
+        };
+        let path = self.entry_path(url);
+        let raw = serde_json::to_vec(&entry).context("serialize cached page")?;
+        // Write beside the entry and rename so a crash never leaves a torn copy behind.
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, raw)
+            .with_context(|| format!("unable to write {}", partial.display()))?;
+        std::fs::rename(&partial, &path)
+            .with_context(|| format!("unable to write {}", path.display()))?;
+        self.trim(&path)
+    }
+
+    /// Remove the oldest entries other than `kept`, the one just stored, until the rest fit in
+    /// the limit.
+    fn trim(&self, kept: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        let dir = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("unable to list {}", self.dir.display()))?;
+        for entry in dir.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            total += metadata.len();
+            if entry.path() == kept {
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            entries.push((modified, metadata.len(), entry.path()));
+        }
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("unable to remove {}", path.display()))?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    pub fn load(&self, url: &Url) -> Option<CachedPage> {
+        let raw = std::fs::read(self.entry_path(url)).ok()?;
+        serde_json::from_slice(&raw).ok()
+    }
+}
+
+/// `YYYY-MM-DD HH:MM UTC` for the offline banner.
+pub fn format_timestamp(unix_seconds: u64) -> String {
+    let (year, month, day) = civil_date(unix_seconds);
+    let seconds_of_day = unix_seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        seconds_of_day / 3_600,
+        (seconds_of_day % 3_600) / 60
+    )
+}
+
+/// `(year, month, day)` of a Unix timestamp, in UTC.
+pub(crate) fn civil_date(unix_seconds: u64) -> (i64, i64, i64) {
+    let days = (unix_seconds / 86_400) as i64;
+    // Civil-from-days (Howard Hinnant), valid for every date after 1970.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_documents_by_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PageCache::new(dir.path().join(DIR_NAME));
+        let url = Url::parse("https://example.com/page").unwrap();
+        let document = FetchedDocument {
+            base_url: url.to_string(),
+            contents: "<p>cached</p>".into(),
+            file_path: None,
+            display_url: url.to_string(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+
+        assert!(cache.load(&url).is_none());
+        cache.store(&url, &document).unwrap();
+        let cached = cache.load(&url).expect("cached copy");
+        assert_eq!(cached.document.contents, "<p>cached</p>");
+        assert!(cache
+            .load(&Url::parse("https://example.com/other").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn stays_under_its_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = |url: &Url| FetchedDocument {
+            base_url: url.to_string(),
+            contents: "x".repeat(1_000),
+            file_path: None,
+            display_url: url.to_string(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        let first = Url::parse("https://example.com/1").unwrap();
+        let second = Url::parse("https://example.com/2").unwrap();
+
+        let cache = PageCache::new(dir.path().join(DIR_NAME)).with_limit(1_500);
+        cache.store(&first, &document(&first)).unwrap();
+        assert!(cache.load(&first).is_some());
+        cache.store(&second, &document(&second)).unwrap();
+        let kept = [&first, &second]
+            .into_iter()
+            .filter(|url| cache.load(url).is_some())
+            .count();
+        assert_eq!(kept, 1, "one entry fits");
+        assert!(cache.load(&second).is_some(), "the newest entry is kept");
+
+        let off = PageCache::new(dir.path().join("off")).with_limit(0);
+        off.store(&first, &document(&first)).unwrap();
+        assert!(off.load(&first).is_none());
+    }
+
+    #[test]
+    fn keeps_only_storable_successes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = PageCache::new(dir.path().join(DIR_NAME));
+        let url = Url::parse("https://example.com/dashboard").unwrap();
+        let document = |contents: &str, status: u16, cache_control: Option<&str>| FetchedDocument {
+            base_url: url.to_string(),
+            contents: contents.into(),
+            file_path: None,
+            display_url: url.to_string(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: Some(ResponseInfo {
+                status,
+                content_type: None,
+                cache_control: cache_control.map(str::to_string),
+            }),
+        };
+
+        cache
+            .store(&url, &document("good", 200, Some("max-age=60")))
+            .unwrap();
+        cache.store(&url, &document("missing", 404, None)).unwrap();
+        cache.store(&url, &document("broken", 500, None)).unwrap();
+        assert_eq!(cache.load(&url).unwrap().document.contents, "good");
+
+        let private = Url::parse("https://example.com/private").unwrap();
+        for cache_control in ["no-store", "max-age=0, Private"] {
+            cache
+                .store(&private, &document("secret", 200, Some(cache_control)))
+                .unwrap();
+            assert!(cache.load(&private).is_none(), "{cache_control} was stored");
+        }
+    }
+
+    #[test]
+    fn formats_banner_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_timestamp(1_709_210_096), "2024-02-29 12:34 UTC");
+    }
+}
@@ -0,0 +1,246 @@
+//! A minimal PDF writer for exported pages.
+//!
+//! Each page is one raster image, painted by the CPU renderer, placed inside the page's
+//! margins. The image data is the zlib stream of a PNG encoding of the page: PDF's
+//! `FlateDecode` filter with PNG predictors reads PNG scanlines as they are, so the `IDAT`
+//! chunks are copied into the file without decoding and no compression library is needed
+//! beyond the PNG encoder screenshots already use. Text is not selectable and links are
+//! not clickable.
+
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use anyhow::{anyhow, Context, Result};
+use image::{ImageFormat, RgbImage};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// One page: `image` drawn over the area inside `margin` of a `width`×`height` page, all in
+/// PDF points (1/72 inch).
+pub struct PdfPage {
+    pub image: RgbImage,
+    pub width: f64,
+    pub height: f64,
+    pub margin: f64,
+}
+
+/// Serialize `pages` as a PDF document titled `title`.
+pub fn write_pdf(pages: &[PdfPage], title: &str) -> Result<Vec<u8>> {
+    if pages.is_empty() {
+        return Err(anyhow!("a PDF needs at least one page"));
+    }
+    let mut writer = Writer::default();
+    writer
+        .output
+        .extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+
+    // Objects 1 to 3 are the catalog, the page tree and the document info; each page then
+    // takes three: the page, its content stream and its image.
+    let page_ids: Vec<usize> = (0..pages.len()).map(|index| 4 + index * 3).collect();
+    writer.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids = page_ids
+        .iter()
+        .map(|id| format!("{id} 0 R"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writer.object(
+        2,
+        format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", pages.len()).as_bytes(),
+    );
+    writer.object(
+        3,
+        format!("<< /Title {} /Producer (Frontier) >>", text_string(title)).as_bytes(),
+    );
+
+    for (page, id) in pages.iter().zip(page_ids) {
+        let (content_id, image_id) = (id + 1, id + 2);
+        writer.object(
+            id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents {content_id} 0 R \
+                 /Resources << /XObject << /Page {image_id} 0 R >> >> >>",
+                number(page.width),
+                number(page.height)
+            )
+            .as_bytes(),
+        );
+
+        let draw = format!(
+            "q {} 0 0 {} {} {} cm /Page Do Q",
+            number(page.width - 2.0 * page.margin),
+            number(page.height - 2.0 * page.margin),
+            number(page.margin),
+            number(page.margin)
+        );
+        writer.stream(content_id, "", draw.as_bytes());
+
+        let (width, height) = page.image.dimensions();
+        let data = png_image_data(&page.image)?;
+        writer.stream(
+            image_id,
+            &format!(
+                "/Type /XObject /Subtype /Image /Width {width} /Height {height} \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode \
+                 /DecodeParms << /Predictor 15 /Colors 3 /BitsPerComponent 8 /Columns {width} >> "
+            ),
+            &data,
+        );
+    }
+
+    Ok(writer.finish())
+}
+
+#[derive(Default)]
+struct Writer {
+    output: Vec<u8>,
+    /// Byte offset of each object, indexed by object number minus one.
+    offsets: Vec<usize>,
+}
+
+impl Writer {
+    fn object(&mut self, id: usize, body: &[u8]) {
+        self.begin(id);
+        self.output.extend_from_slice(body);
+        self.output.extend_from_slice(b"\nendobj\n");
+    }
+
+    /// A stream object; `dictionary` holds the entries besides `/Length`.
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        self.begin(id);
+        self.output.extend_from_slice(
+            format!("<< {dictionary}/Length {} >>\nstream\n", data.len()).as_bytes(),
+        );
+        self.output.extend_from_slice(data);
+        self.output.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn begin(&mut self, id: usize) {
+        if self.offsets.len() < id {
+            self.offsets.resize(id, 0);
+        }
+        self.offsets[id - 1] = self.output.len();
+        self.output
+            .extend_from_slice(format!("{id} 0 obj\n").as_bytes());
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.output.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(table, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            table,
+            "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len() + 1
+        );
+        self.output.extend_from_slice(table.as_bytes());
+        self.output
+    }
+}
+
+/// The zlib stream of `image` encoded as a PNG: its `IDAT` chunks, concatenated.
+fn png_image_data(image: &RgbImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("encode page image")?;
+    let mut rest = png
+        .strip_prefix(PNG_SIGNATURE)
+        .ok_or_else(|| anyhow!("PNG encoder wrote no signature"))?;
+    let mut data = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into()?) as usize;
+        let kind = &rest[4..8];
+        let chunk = rest
+            .get(8..8 + length)
+            .ok_or_else(|| anyhow!("truncated PNG chunk"))?;
+        if kind == b"IDAT" {
+            data.extend_from_slice(chunk);
+        }
+        rest = &rest[12 + length..];
+    }
+    Ok(data)
+}
+
+/// `text` as a PDF text string: UTF-16BE with a byte order mark, in hex.
+fn text_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        let _ = write!(hex, "{unit:04X}");
+    }
+    hex.push('>');
+    hex
+}
+
+/// `value` with at most two decimals, as PDF has no exponent notation.
+fn number(value: f64) -> String {
+    let formatted = format!("{value:.2}");
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .rposition(|window| window == needle)
+    }
+
+    #[test]
+    fn writes_one_image_per_page_with_a_valid_xref() {
+        let pages: Vec<PdfPage> = (0..2)
+            .map(|shade| PdfPage {
+                image: RgbImage::from_pixel(4, 6, image::Rgb([shade * 100, 0, 0])),
+                width: 595.28,
+                height: 841.89,
+                margin: 28.35,
+            })
+            .collect();
+        let pdf = write_pdf(&pages, "Report – Q3").unwrap();
+
+        assert!(pdf.starts_with(b"%PDF-1.4\n"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(find(&pdf, b"/Count 2").is_some());
+        assert!(find(&pdf, b"/MediaBox [0 0 595.28 841.89]").is_some());
+        assert!(find(&pdf, b"q 538.58 0 0 785.19 28.35 28.35 cm").is_some());
+        assert!(find(
+            &pdf,
+            b"/Title <FEFF005200650070006F0072007400202013002000510033>"
+        )
+        .is_some());
+
+        // Everything from the cross-reference table on is ASCII.
+        let xref = find(&pdf, b"\nxref\n").unwrap() + 1;
+        let tail = std::str::from_utf8(&pdf[xref..]).unwrap();
+        let startxref: usize = tail
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|rest| rest.lines().next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(startxref, xref);
+        assert!(tail.starts_with("xref\n0 10\n"));
+        let entries = tail.lines().skip(3).take(9);
+        for (index, entry) in entries.enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            let header = format!("{} 0 obj\n", index + 1);
+            assert!(
+                pdf[offset..].starts_with(header.as_bytes()),
+                "object {}",
+                index + 1
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_an_empty_document() {
+        assert!(write_pdf(&[], "empty").is_err());
+    }
+}
@@ -0,0 +1,378 @@
+//! Per-origin permissions the user granted from browser prompts, and the content they turned
+//! off for a site, persisted in `permissions.toml` beside `frontier.toml`.
+//!
+//! A site can have JavaScript, images or third-party requests blocked from the site-info
+//! dropdown at the start of the URL bar; each toggle goes through [`SITE_SETTINGS_URL`] and
+//! reloads the page. Blocked JavaScript means the page gets no script runtime, like turning
+//! JavaScript off in settings but for one origin. Images are recognised by their extension,
+//! as blitz does not say what a request is for. A request is third-party when its host is on
+//! another site than the page, comparing the last two labels of the host names, so
+//! `cdn.example.com` is first-party on `www.example.com`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::storage_policy::StoragePolicy;
+
+const FILE_NAME: &str = "permissions.toml";
+/// Navigating here with `?toggle=<setting>` flips that setting for the current page's origin.
+pub const SITE_SETTINGS_URL: &str = "frontier://site-settings";
+
+/// Content a site can be stopped from running or loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentSetting {
+    Javascript,
+    Images,
+    ThirdParty,
+}
+
+impl ContentSetting {
+    pub const ALL: [ContentSetting; 3] = [
+        ContentSetting::Javascript,
+        ContentSetting::Images,
+        ContentSetting::ThirdParty,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentSetting::Javascript => "javascript",
+            ContentSetting::Images => "images",
+            ContentSetting::ThirdParty => "third-party",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|setting| setting.name() == name)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ContentSetting::Javascript => "JavaScript",
+            ContentSetting::Images => "Images",
+            ContentSetting::ThirdParty => "Third-party requests",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SitePermissions {
+    /// Event kinds `window.nostr.signEvent` may sign without asking.
+    pub sign_kinds: BTreeSet<u16>,
+    /// Whether `window.nostr.getPublicKey` may read the identity's key without asking.
+    pub share_public_key: bool,
+    /// Npubs `frontier.connect` may open channels to without asking.
+    pub connect_peers: BTreeSet<String>,
+    /// Content the user turned off for the site.
+    pub blocked: BTreeSet<ContentSetting>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Keyed by origin (`https://example.com`).
+    pub sites: BTreeMap<String, SitePermissions>,
+}
+
+impl Permissions {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::preferences::data_file(FILE_NAME)
+    }
+
+    /// Read permissions from `path`; a missing file grants nothing.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(raw) => toml::from_str(&raw)
+                .with_context(|| format!("invalid permissions file {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("unable to read permissions {}", path.display()))
+            }
+        }
+    }
+
+    /// Does nothing in a private session; the rules last until it exits.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !StoragePolicy::current().persists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let raw = toml::to_string_pretty(self).context("serialize permissions")?;
+        std::fs::write(path, raw)
+            .with_context(|| format!("unable to write permissions {}", path.display()))
+    }
+
+    pub fn allows_signing(&self, origin: &str, kind: u16) -> bool {
+        self.sites
+            .get(origin)
+            .is_some_and(|site| site.sign_kinds.contains(&kind))
+    }
+
+    pub fn allow_signing(&mut self, origin: &str, kind: u16) {
+        self.sites
+            .entry(origin.to_string())
+            .or_default()
+            .sign_kinds
+            .insert(kind);
+    }
+
+    pub fn allows_public_key(&self, origin: &str) -> bool {
+        self.sites
+            .get(origin)
+            .is_some_and(|site| site.share_public_key)
+    }
+
+    pub fn allow_public_key(&mut self, origin: &str) {
+        self.sites
+            .entry(origin.to_string())
+            .or_default()
+            .share_public_key = true;
+    }
+
+    pub fn allows_connecting(&self, origin: &str, npub: &str) -> bool {
+        self.sites
+            .get(origin)
+            .is_some_and(|site| site.connect_peers.contains(npub))
+    }
+
+    pub fn allow_connecting(&mut self, origin: &str, npub: &str) {
+        self.sites
+            .entry(origin.to_string())
+            .or_default()
+            .connect_peers
+            .insert(npub.to_string());
+    }
+
+    /// What is turned off for `origin`.
+    pub fn blocked(&self, origin: &str) -> BTreeSet<ContentSetting> {
+        self.sites
+            .get(origin)
+            .map(|site| site.blocked.clone())
+            .unwrap_or_default()
+    }
+
+    /// Block `setting` for `origin` if it is allowed and allow it if it is blocked. Returns
+    /// whether it is blocked now.
+    pub fn toggle_blocked(&mut self, origin: &str, setting: ContentSetting) -> bool {
+        let site = self.sites.entry(origin.to_string()).or_default();
+        let blocked = site.blocked.insert(setting);
+        if !blocked {
+            site.blocked.remove(&setting);
+        }
+        if *site == SitePermissions::default() {
+            self.sites.remove(origin);
+        }
+        blocked
+    }
+
+    /// The settings that apply to the page at `page`.
+    pub fn content_rules(&self, page: &Url) -> ContentRules {
+        ContentRules {
+            site: page.host_str().map(site),
+            blocked: self.blocked(&page.origin().ascii_serialization()),
+        }
+    }
+}
+
+/// One page's blocked content, checked against its script runtime and subresource requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentRules {
+    /// Site of the page's host; `None` when it has no host, like `file:` pages.
+    site: Option<String>,
+    blocked: BTreeSet<ContentSetting>,
+}
+
+impl ContentRules {
+    pub fn allows_javascript(&self) -> bool {
+        !self.blocked.contains(&ContentSetting::Javascript)
+    }
+
+    /// The setting that stops a request for `url`, if any; `image` says whether it fetches an
+    /// image.
+    pub fn blocks(&self, url: &Url, image: bool) -> Option<ContentSetting> {
+        if image && self.blocked.contains(&ContentSetting::Images) {
+            return Some(ContentSetting::Images);
+        }
+        let third_party = third_party(self.site.as_deref(), url.host_str());
+        (third_party && self.blocked.contains(&ContentSetting::ThirdParty))
+            .then_some(ContentSetting::ThirdParty)
+    }
+}
+
+/// Whether a request to `host` leaves `page_site`, the [`site`] of a page, or `None` for a
+/// page without a host.
+pub(crate) fn third_party(page_site: Option<&str>, host: Option<&str>) -> bool {
+    match (page_site, host) {
+        (Some(page), Some(host)) => site(host) != page,
+        (None, Some(_)) => true,
+        (_, None) => false,
+    }
+}
+
+/// The site `host` belongs to: its last two labels, or the whole host for IP addresses.
+pub(crate) fn site(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok() {
+        return host;
+    }
+    let labels: Vec<&str> = host.rsplitn(3, '.').collect();
+    match labels.as_slice() {
+        [tld, domain, ..] => format!("{domain}.{tld}"),
+        _ => host,
+    }
+}
+
+/// The chrome's site-info dropdown for a page of `origin` with `blocked` turned off: one link
+/// per setting that toggles it, under a button whose `#blocked-count` badge the browser fills
+/// with the number of requests blocked on the page.
+pub fn site_info_html(origin: &str, blocked: &BTreeSet<ContentSetting>) -> String {
+    let toggles: String = ContentSetting::ALL
+        .into_iter()
+        .map(|setting| {
+            let state = if blocked.contains(&setting) {
+                "Blocked"
+            } else {
+                "Allowed"
+            };
+            format!(
+                "<a role=\"menuitemcheckbox\" aria-checked=\"{checked}\" \
+                 href=\"{SITE_SETTINGS_URL}?toggle={name}\">{label}: {state}</a>",
+                checked = !blocked.contains(&setting),
+                name = setting.name(),
+                label = setting.label(),
+            )
+        })
+        .collect();
+    format!(
+        "<div id=\"site-info\"><button class=\"nav-button\" type=\"button\" \
+         title=\"Site settings\" aria-label=\"Site settings for {attribute}\" \
+         aria-haspopup=\"menu\">&#9432;<span id=\"blocked-count\" \
+         title=\"Requests blocked on this page\"></span></button>\
+         <div id=\"site-info-items\" role=\"menu\"><p class=\"app-menu-heading\">{text}</p>\
+         {toggles}<p class=\"site-info-note\">Changes reload the page.</p></div></div>",
+        attribute = encode_double_quoted_attribute(origin),
+        text = encode_text(origin),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_rules_are_per_origin_and_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        let mut permissions = Permissions::load(&path).unwrap();
+        permissions.allow_signing("https://a.example", 1);
+        permissions.allow_connecting("https://a.example", "npub1peer");
+        permissions.allow_public_key("https://a.example");
+        permissions.save(&path).unwrap();
+
+        let permissions = Permissions::load(&path).unwrap();
+        assert!(permissions.allows_signing("https://a.example", 1));
+        assert!(!permissions.allows_signing("https://a.example", 7));
+        assert!(!permissions.allows_signing("https://b.example", 1));
+        assert!(permissions.allows_connecting("https://a.example", "npub1peer"));
+        assert!(!permissions.allows_connecting("https://b.example", "npub1peer"));
+        assert!(permissions.allows_public_key("https://a.example"));
+        assert!(!permissions.allows_public_key("https://b.example"));
+    }
+
+    #[test]
+    fn content_settings_toggle_per_origin_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        let mut permissions = Permissions::default();
+        assert!(permissions.toggle_blocked("https://a.example", ContentSetting::Javascript));
+        assert!(permissions.toggle_blocked("https://a.example", ContentSetting::Images));
+        assert!(permissions.toggle_blocked("https://b.example", ContentSetting::ThirdParty));
+        assert!(!permissions.toggle_blocked("https://b.example", ContentSetting::ThirdParty));
+        assert!(!permissions.sites.contains_key("https://b.example"));
+        permissions.save(&path).unwrap();
+
+        let permissions = Permissions::load(&path).unwrap();
+        let rules = permissions.content_rules(&Url::parse("https://a.example/page").unwrap());
+        assert!(!rules.allows_javascript());
+        assert!(permissions
+            .content_rules(&Url::parse("https://b.example/").unwrap())
+            .allows_javascript());
+        assert_eq!(
+            ContentSetting::parse("third-party"),
+            Some(ContentSetting::ThirdParty)
+        );
+        assert_eq!(ContentSetting::parse("cookies"), None);
+    }
+
+    #[test]
+    fn rules_block_images_and_other_sites() {
+        let url = |raw: &str| Url::parse(raw).unwrap();
+        let mut permissions = Permissions::default();
+        permissions.toggle_blocked("https://www.example.com", ContentSetting::ThirdParty);
+        let rules = permissions.content_rules(&url("https://www.example.com/"));
+
+        assert_eq!(
+            rules.blocks(&url("https://cdn.example.com/app.css"), false),
+            None
+        );
+        assert_eq!(
+            rules.blocks(&url("https://EXAMPLE.com./logo.png"), true),
+            None
+        );
+        assert_eq!(
+            rules.blocks(&url("https://tracker.example.net/pixel.gif"), true),
+            Some(ContentSetting::ThirdParty)
+        );
+        assert_eq!(rules.blocks(&url("data:image/png;base64,AAAA"), true), None);
+
+        permissions.toggle_blocked("https://www.example.com", ContentSetting::Images);
+        let rules = permissions.content_rules(&url("https://www.example.com/"));
+        assert_eq!(
+            rules.blocks(&url("https://www.example.com/logo.png"), true),
+            Some(ContentSetting::Images)
+        );
+
+        let local = Permissions::default().content_rules(&url("http://127.0.0.1:8080/"));
+        assert_eq!(local.blocks(&url("http://127.0.0.2/x.js"), false), None);
+        let mut permissions = Permissions::default();
+        permissions.toggle_blocked("http://127.0.0.1:8080", ContentSetting::ThirdParty);
+        let rules = permissions.content_rules(&url("http://127.0.0.1:8080/"));
+        assert_eq!(
+            rules.blocks(&url("http://127.0.0.1:9000/x.js"), false),
+            None
+        );
+        assert_eq!(
+            rules.blocks(&url("http://127.0.0.2/x.js"), false),
+            Some(ContentSetting::ThirdParty)
+        );
+    }
+
+    #[test]
+    fn site_info_lists_each_setting_with_its_toggle() {
+        let blocked = BTreeSet::from([ContentSetting::Images]);
+        let html = site_info_html("https://a.example", &blocked);
+        assert!(
+            html.contains("Site settings for https://a.example"),
+            "{html}"
+        );
+        assert!(html.contains(
+            "aria-checked=\"true\" href=\"frontier://site-settings?toggle=javascript\">\
+             JavaScript: Allowed</a>"
+        ));
+        assert!(html.contains(
+            "aria-checked=\"false\" href=\"frontier://site-settings?toggle=images\">\
+             Images: Blocked</a>"
+        ));
+        assert!(html.contains("Third-party requests: Allowed"));
+    }
+}
@@ -0,0 +1,439 @@
+//! Popup windows opened with `window.open`.
+//!
+//! Each popup is a separate `frontier` process started with [`POPUP_FLAG`], so it gets its own
+//! window and event loop. `postMessage` between the opener and the popup travels over a
+//! loopback socket the opener listens on: its address and a one-time token reach the popup
+//! in [`CHANNEL_ENV`], and both sides exchange length-prefixed JSON frames on it. Stdout and
+//! stderr stay plain log output, so nothing a page prints can pass for a message. Neither
+//! side trusts a sender's claim about its origin: each stamps incoming messages with the
+//! origin of the page the other process last loaded. Which calls may open a popup at all is
+//! decided in [`crate::js::window_open`]; every call, allowed or blocked, ends up in the
+//! [`PopupRecord`] log that automation reads.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+
+use crate::storage_policy::{StoragePolicy, PRIVATE_FLAG};
+
+/// Command-line flag that starts `frontier` as a popup of the process that launched it.
+pub const POPUP_FLAG: &str = "--popup";
+
+/// Environment variable holding the opener's socket address and token, separated by a space.
+pub const CHANNEL_ENV: &str = "FRONTIER_POPUP_CHANNEL";
+
+/// How long the opener waits for a popup process to connect.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection has to present its token.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames longer than this end the channel.
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// Messages posted before the popup connects are held up to this many.
+const MAX_PENDING: usize = 100;
+
+/// Oldest records are dropped past this many.
+const MAX_RECORDS: usize = 200;
+
+/// A `postMessage` between an opener and its popup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WireMessage {
+    /// The message, serialized as JSON.
+    pub data: String,
+    pub target_origin: String,
+}
+
+/// What travels on the channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Frame {
+    /// The popup's first frame; proves it was started by this opener.
+    Hello {
+        token: String,
+    },
+    /// The sender loaded a page of this serialized origin.
+    Location {
+        origin: String,
+    },
+    Message(WireMessage),
+}
+
+fn write_frame(writer: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let json = serde_json::to_vec(frame).expect("frames serialize");
+    let len = u32::try_from(json.len())
+        .ok()
+        .filter(|&len| len as usize <= MAX_FRAME)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&json)?;
+    writer.flush()
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<Frame> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    let mut json = vec![0; len];
+    reader.read_exact(&mut json)?;
+    serde_json::from_slice(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// One `window.open` call that asked for a new window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PopupRecord {
+    pub url: String,
+    pub target: String,
+    /// Serialized origin of the page that called `window.open`.
+    pub opener: String,
+    pub blocked: bool,
+    /// Why the popup was blocked or failed to open.
+    pub reason: Option<String>,
+}
+
+/// Something a popup process did, reported to the opener's event loop.
+#[derive(Debug, Clone)]
+pub enum PopupEvent {
+    /// `origin` is the popup's current origin as the opener tracks it.
+    Message {
+        id: u32,
+        origin: String,
+        message: WireMessage,
+    },
+    Closed {
+        id: u32,
+    },
+}
+
+/// The opener's end of a popup's channel.
+#[derive(Default)]
+struct Channel {
+    /// Set once the popup connected.
+    stream: Option<TcpStream>,
+    pending: Vec<WireMessage>,
+    detached: bool,
+}
+
+struct Popup {
+    child: Child,
+    channel: Arc<Mutex<Channel>>,
+}
+
+/// The popups of this window and the log of `window.open` calls.
+#[derive(Default)]
+pub struct Popups {
+    open: HashMap<u32, Popup>,
+    records: Vec<PopupRecord>,
+}
+
+impl Popups {
+    pub fn record(&mut self, record: PopupRecord) {
+        if self.records.len() == MAX_RECORDS {
+            self.records.remove(0);
+        }
+        self.records.push(record);
+    }
+
+    pub fn records(&self) -> Vec<PopupRecord> {
+        self.records.clone()
+    }
+
+    /// Start the popup `id` on `url` for a page of the serialized origin `opener`. `events`
+    /// is called from a reader thread for each message the popup posts and once when it
+    /// exits.
+    pub fn launch(
+        &mut self,
+        id: u32,
+        url: &Url,
+        opener: &str,
+        events: impl Fn(PopupEvent) + Send + 'static,
+    ) -> Result<()> {
+        let exe = std::env::current_exe().context("failed to locate the frontier executable")?;
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .context("failed to open a channel for the popup")?;
+        let address = listener.local_addr()?;
+        let token = Uuid::new_v4().simple().to_string();
+        let mut command = Command::new(exe);
+        command.arg(POPUP_FLAG);
+        if !StoragePolicy::current().persists() {
+            command.arg(PRIVATE_FLAG);
+        }
+        let child = command
+            .arg(url.as_str())
+            .env(CHANNEL_ENV, format!("{address} {token}"))
+            .stdin(Stdio::null())
+            .spawn()
+            .context("failed to start popup process")?;
+        let channel = Arc::new(Mutex::new(Channel::default()));
+        let shared = Arc::clone(&channel);
+        let mut origin = url.origin().ascii_serialization();
+        let opener = opener.to_string();
+        thread::spawn(move || {
+            let mut stream = match accept_popup(&listener, &token) {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!(popup = id, error = %err, "popup never connected to its opener");
+                    return;
+                }
+            };
+            {
+                let mut channel = shared.lock().expect("popup channel lock");
+                if channel.detached {
+                    return;
+                }
+                let Ok(mut writer) = stream.try_clone() else {
+                    return;
+                };
+                let mut sent = write_frame(&mut writer, &Frame::Location { origin: opener });
+                for message in channel.pending.drain(..) {
+                    sent = sent.and_then(|()| write_frame(&mut writer, &Frame::Message(message)));
+                }
+                if let Err(err) = sent {
+                    warn!(popup = id, error = %err, "failed to post message to popup");
+                }
+                channel.stream = Some(writer);
+            }
+            while let Ok(frame) = read_frame(&mut stream) {
+                match frame {
+                    Frame::Location { origin: loaded } => origin = loaded,
+                    Frame::Message(message) => {
+                        if shared.lock().expect("popup channel lock").detached {
+                            return;
+                        }
+                        let origin = origin.clone();
+                        events(PopupEvent::Message {
+                            id,
+                            origin,
+                            message,
+                        });
+                    }
+                    Frame::Hello { .. } => {}
+                }
+            }
+            if !shared.lock().expect("popup channel lock").detached {
+                events(PopupEvent::Closed { id });
+            }
+        });
+        self.open.insert(id, Popup { child, channel });
+        Ok(())
+    }
+
+    /// Hand `message` to the popup `id`. Returns false when the popup is gone.
+    pub fn post(&mut self, id: u32, message: &WireMessage) -> bool {
+        let Some(popup) = self.open.get(&id) else {
+            return false;
+        };
+        let mut channel = popup.channel.lock().expect("popup channel lock");
+        let Some(stream) = channel.stream.as_mut() else {
+            if channel.pending.len() == MAX_PENDING {
+                warn!(
+                    popup = id,
+                    "dropped message to a popup that has not connected yet"
+                );
+                return false;
+            }
+            channel.pending.push(message.clone());
+            return true;
+        };
+        if let Err(err) = write_frame(stream, &Frame::Message(message.clone())) {
+            warn!(popup = id, error = %err, "failed to post message to popup");
+            return false;
+        }
+        true
+    }
+
+    /// Close the popup `id` on behalf of its opener.
+    pub fn close(&mut self, id: u32) {
+        if let Some(mut popup) = self.open.remove(&id) {
+            detach(&popup.channel);
+            let _ = popup.child.kill();
+            let _ = popup.child.wait();
+        }
+    }
+
+    /// Forget the popup `id` after its process exited.
+    pub fn exited(&mut self, id: u32) {
+        if let Some(mut popup) = self.open.remove(&id) {
+            let _ = popup.child.wait();
+        }
+    }
+
+    /// Let every popup outlive the page that opened it; only the message channel goes away.
+    pub fn detach_all(&mut self) {
+        for (_, mut popup) in self.open.drain() {
+            detach(&popup.channel);
+            thread::spawn(move || {
+                let _ = popup.child.wait();
+            });
+        }
+    }
+}
+
+fn detach(channel: &Mutex<Channel>) {
+    let mut channel = channel.lock().expect("popup channel lock");
+    channel.detached = true;
+    channel.pending.clear();
+    if let Some(stream) = channel.stream.take() {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Wait for the popup to connect with `token`; other connections are dropped.
+fn accept_popup(listener: &TcpListener, token: &str) -> Result<TcpStream> {
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+                if let Ok(Frame::Hello { token: presented }) = read_frame(&mut stream) {
+                    if presented == token {
+                        stream.set_read_timeout(None)?;
+                        return Ok(stream);
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    bail!("timed out after {}s", CONNECT_TIMEOUT.as_secs());
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// A popup's end of the channel to its opener.
+struct OpenerLink {
+    stream: Mutex<TcpStream>,
+}
+
+static OPENER: OnceLock<OpenerLink> = OnceLock::new();
+
+/// In a popup: connect to the opener named in [`CHANNEL_ENV`] and call `deliver` with the
+/// opener's origin for every message it sends.
+pub fn connect_to_opener(deliver: impl Fn(String, WireMessage) + Send + 'static) -> Result<()> {
+    let channel =
+        std::env::var(CHANNEL_ENV).with_context(|| format!("{CHANNEL_ENV} is not set"))?;
+    let (address, token) = channel
+        .split_once(' ')
+        .with_context(|| format!("malformed {CHANNEL_ENV}"))?;
+    let mut stream = TcpStream::connect(address).context("failed to reach the opener")?;
+    write_frame(
+        &mut stream,
+        &Frame::Hello {
+            token: token.to_string(),
+        },
+    )?;
+    let mut reader = stream.try_clone()?;
+    let link = OpenerLink {
+        stream: Mutex::new(stream),
+    };
+    if OPENER.set(link).is_err() {
+        bail!("already connected to the opener");
+    }
+    thread::spawn(move || {
+        let mut origin = "null".to_string();
+        while let Ok(frame) = read_frame(&mut reader) {
+            match frame {
+                Frame::Location { origin: loaded } => origin = loaded,
+                Frame::Message(message) => deliver(origin.clone(), message),
+                Frame::Hello { .. } => {}
+            }
+        }
+    });
+    Ok(())
+}
+
+fn send_to_opener(frame: &Frame) {
+    let Some(link) = OPENER.get() else {
+        return;
+    };
+    let mut stream = link.stream.lock().expect("opener channel lock");
+    if let Err(err) = write_frame(&mut *stream, frame) {
+        warn!(error = %err, "failed to post message to opener");
+    }
+}
+
+/// In a popup: tell the opener this window now shows a page of the serialized `origin`.
+pub fn report_location(origin: &str) {
+    send_to_opener(&Frame::Location {
+        origin: origin.to_string(),
+    });
+}
+
+/// In a popup: hand `message` to the opener.
+pub fn post_to_opener(message: &WireMessage) {
+    send_to_opener(&Frame::Message(message.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip_and_reject_oversized_lengths() {
+        let message = Frame::Message(WireMessage {
+            data: r#"{"ready":true}"#.to_string(),
+            target_origin: "*".to_string(),
+        });
+        let mut wire = Vec::new();
+        write_frame(&mut wire, &message).unwrap();
+        assert_eq!(read_frame(&mut wire.as_slice()).unwrap(), message);
+
+        let mut oversized = ((MAX_FRAME + 1) as u32).to_be_bytes().to_vec();
+        oversized.extend_from_slice(b"{}");
+        assert!(read_frame(&mut oversized.as_slice()).is_err());
+        assert!(read_frame(&mut b"INFO loaded page".as_slice()).is_err());
+    }
+
+    #[test]
+    fn only_a_connection_with_the_token_is_accepted() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stranger = TcpStream::connect(address).unwrap();
+            let hello = Frame::Hello {
+                token: "guess".to_string(),
+            };
+            write_frame(&mut stranger, &hello).unwrap();
+            let mut popup = TcpStream::connect(address).unwrap();
+            let hello = Frame::Hello {
+                token: "secret".to_string(),
+            };
+            write_frame(&mut popup, &hello).unwrap();
+            let origin = Frame::Location {
+                origin: "https://popup.example".to_string(),
+            };
+            write_frame(&mut popup, &origin).unwrap();
+            popup
+        });
+        let mut stream = accept_popup(&listener, "secret").unwrap();
+        let _popup = client.join().unwrap();
+        assert_eq!(
+            read_frame(&mut stream).unwrap(),
+            Frame::Location {
+                origin: "https://popup.example".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! User preferences persisted in `frontier.toml`, and the `frontier://settings` page that
+//! edits them.
+//!
+//! The file lives in `FRONTIER_DATA_DIR` when set, otherwise in the platform config directory.
+//! The settings page is a plain GET form whose action is `frontier://settings`;
+//! `ReadmeApplication::navigate` intercepts the submission, applies it with
+//! [`Preferences::apply_form`], writes the file and re-renders the page, so changes reach the
+//! running window without a restart.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::background;
+use crate::gestures::GestureSettings;
+use crate::privacy::{PrivacySettings, ReferrerPolicy};
+use crate::search::SearchEngine;
+use crate::smooth_scroll::ScrollEasing;
+
+pub const SETTINGS_URL: &str = "frontier://settings";
+const FILE_NAME: &str = "frontier.toml";
+const DEFAULT_HOMEPAGE: &str = "https://example.com";
+/// What the page cache may hold unless the user picks another size.
+const DEFAULT_CACHE_SIZE_MB: u64 = 256;
+
+/// `name` inside `FRONTIER_DATA_DIR`, or inside the platform config directory.
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    if let Ok(dir) = std::env::var("FRONTIER_DATA_DIR") {
+        if !dir.trim().is_empty() {
+            return Some(PathBuf::from(dir).join(name));
+        }
+    }
+    ProjectDirs::from("", "", "frontier").map(|dirs| dirs.config_dir().join(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemePreference {
+    /// Follow the OS theme.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemePreference {
+    fn as_str(self) -> &'static str {
+        match self {
+            ThemePreference::System => "system",
+            ThemePreference::Light => "light",
+            ThemePreference::Dark => "dark",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "system" => Some(ThemePreference::System),
+            "light" => Some(ThemePreference::Light),
+            "dark" => Some(ThemePreference::Dark),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Run page scripts. Takes effect from the next document load.
+    pub javascript_enabled: bool,
+    /// Opened when `frontier` starts without a target.
+    pub homepage: String,
+    pub theme: ThemePreference,
+    /// Serve http(s) pages from the page cache instead of the network.
+    pub work_offline: bool,
+    /// Most the page cache may hold, in megabytes; 0 keeps no copies.
+    pub cache_size_mb: u64,
+    /// Load hovered links and `<link rel="prefetch">` targets ahead of navigation.
+    pub prefetch: bool,
+    /// How wheel notches, scrolling keys and smooth `scrollIntoView` animate.
+    pub scroll_easing: ScrollEasing,
+    /// Reopen the window with the size and position it had when it last closed.
+    pub remember_window: bool,
+    /// Keep running with the window hidden when it closes, for the quick-open shortcut.
+    pub background: bool,
+    /// The global shortcut that opens the quick-open palette in background mode.
+    pub quick_open_shortcut: String,
+    /// Relays used by `frontier publish` when `FRONTIER_RELAY_CONFIG` is not set.
+    pub relays: Vec<Url>,
+    /// Origins `--kiosk` mode may navigate to besides the kiosk URL's own.
+    pub kiosk_origins: Vec<Url>,
+    /// Trackpad pinch zoom and swipe navigation. Kept after the plain values: it is a TOML
+    /// table.
+    pub gestures: GestureSettings,
+    /// What outgoing requests reveal. Kept after the plain values: it is a TOML table.
+    pub privacy: PrivacySettings,
+    /// Where URL bar input that is not an address goes. Kept last: it is a TOML table.
+    pub search: SearchEngine,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            javascript_enabled: true,
+            homepage: DEFAULT_HOMEPAGE.to_string(),
+            theme: ThemePreference::System,
+            work_offline: false,
+            cache_size_mb: DEFAULT_CACHE_SIZE_MB,
+            prefetch: true,
+            scroll_easing: ScrollEasing::default(),
+            remember_window: true,
+            background: false,
+            quick_open_shortcut: background::DEFAULT_SHORTCUT.to_string(),
+            relays: Vec::new(),
+            kiosk_origins: Vec::new(),
+            gestures: GestureSettings::default(),
+            privacy: PrivacySettings::default(),
+            search: SearchEngine::default(),
+        }
+    }
+}
+
+impl Preferences {
+    /// Where `frontier.toml` lives, if a data or config directory can be determined.
+    pub fn default_path() -> Option<PathBuf> {
+        data_file(FILE_NAME)
+    }
+
+    /// Read preferences from `path`; a missing file yields the defaults.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(raw) => toml::from_str(&raw)
+                .with_context(|| format!("invalid preferences file {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("unable to read preferences {}", path.display()))
+            }
+        }
+    }
+
+    /// Preferences from [`Preferences::default_path`], or the defaults when there is no file.
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// [`Self::cache_size_mb`] in bytes.
+    pub fn cache_size_bytes(&self) -> u64 {
+        self.cache_size_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let raw = toml::to_string_pretty(self).context("serialize preferences")?;
+        std::fs::write(path, raw)
+            .with_context(|| format!("unable to write preferences {}", path.display()))
+    }
+
+    /// Apply a settings form submission. Unchecked checkboxes are absent from the query, so
+    /// every field the form renders is overwritten. Returns the values that were rejected.
+    pub fn apply_form(&mut self, query: &str) -> Vec<String> {
+        let mut rejected = Vec::new();
+        let mut javascript_enabled = false;
+        let mut work_offline = false;
+        let mut prefetch = false;
+        let mut remember_window = false;
+        let mut run_in_background = false;
+        let mut reduce_user_agent = false;
+        let mut strip_cross_origin_headers = false;
+        let mut relays = Vec::new();
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            let value = value.trim();
+            match key.as_ref() {
+                "javascript" => javascript_enabled = value == "on",
+                "offline" => work_offline = value == "on",
+                "prefetch" => prefetch = value == "on",
+                "window" => remember_window = value == "on",
+                "background" => run_in_background = value == "on",
+                "reduce-ua" => reduce_user_agent = value == "on",
+                "strip-headers" => strip_cross_origin_headers = value == "on",
+                "shortcut" if background::is_valid_shortcut(value) => {
+                    self.quick_open_shortcut = value.to_string()
+                }
+                "shortcut" => rejected.push(format!("shortcut {value}")),
+                "homepage" if !value.is_empty() => self.homepage = value.to_string(),
+                "cache-size" => match value.parse() {
+                    Ok(size) => self.cache_size_mb = size,
+                    Err(_) => rejected.push(format!("cache size {value}")),
+                },
+                "search" if SearchEngine::is_valid_template(value) => {
+                    self.search.template = value.to_string()
+                }
+                "search" => rejected.push(format!("search engine {value}")),
+                "theme" => match ThemePreference::parse(value) {
+                    Some(theme) => self.theme = theme,
+                    None => rejected.push(format!("theme {value}")),
+                },
+                "scrolling" => match ScrollEasing::parse(value) {
+                    Some(easing) => self.scroll_easing = easing,
+                    None => rejected.push(format!("scrolling {value}")),
+                },
+                "referrer" => match ReferrerPolicy::parse(value) {
+                    Some(policy) => self.privacy.referrer_policy = policy,
+                    None => rejected.push(format!("referrer policy {value}")),
+                },
+                "relays" => {
+                    for line in value.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                        match Url::parse(line) {
+                            Ok(url) if matches!(url.scheme(), "ws" | "wss") => relays.push(url),
+                            _ => rejected.push(format!("relay {line}")),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.javascript_enabled = javascript_enabled;
+        self.work_offline = work_offline;
+        self.prefetch = prefetch;
+        self.remember_window = remember_window;
+        self.background = run_in_background;
+        self.privacy.reduce_user_agent = reduce_user_agent;
+        self.privacy.strip_cross_origin_headers = strip_cross_origin_headers;
+        self.relays = relays;
+        rejected
+    }
+
+    /// HTML body of `frontier://settings`. `status` is shown above the form after a save.
+    pub fn settings_page(&self, path: Option<&Path>, status: Option<&str>) -> String {
+        let theme_options = [
+            ThemePreference::System,
+            ThemePreference::Light,
+            ThemePreference::Dark,
+        ]
+        .iter()
+        .map(|theme| {
+            format!(
+                "<option value=\"{value}\"{selected}>{value}</option>",
+                value = theme.as_str(),
+                selected = if *theme == self.theme {
+                    " selected"
+                } else {
+                    ""
+                },
+            )
+        })
+        .collect::<String>();
+        let scrolling_options = ScrollEasing::ALL
+            .iter()
+            .map(|easing| {
+                format!(
+                    "<option value=\"{value}\"{selected}>{value}</option>",
+                    value = easing.as_str(),
+                    selected = if *easing == self.scroll_easing {
+                        " selected"
+                    } else {
+                        ""
+                    },
+                )
+            })
+            .collect::<String>();
+        let referrer_options = ReferrerPolicy::ALL
+            .iter()
+            .map(|policy| {
+                format!(
+                    "<option value=\"{value}\"{selected}>{value}</option>",
+                    value = policy.as_str(),
+                    selected = if *policy == self.privacy.referrer_policy {
+                        " selected"
+                    } else {
+                        ""
+                    },
+                )
+            })
+            .collect::<String>();
+        let relays = self
+            .relays
+            .iter()
+            .map(Url::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let location = match path {
+            Some(path) => format!(
+                "<p class=\"settings-path\">Saved to <code>{}</code></p>",
+                encode_text(&path.display().to_string())
+            ),
+            None => "<p class=\"settings-path\">No preferences directory; changes last until the window closes.</p>".to_string(),
+        };
+        let status = status
+            .map(|message| {
+                format!(
+                    "<p class=\"settings-status\" role=\"status\">{}</p>",
+                    encode_text(message)
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            r#"<section class="settings">
+<h1>Settings</h1>
+{status}
+<form action="{SETTINGS_URL}" method="get">
+<p><label><input type="checkbox" name="javascript" value="on"{javascript}> Enable JavaScript</label></p>
+<p><label><input type="checkbox" name="offline" value="on"{offline}> Work offline (show cached copies of pages)</label></p>
+<p><label for="cache-size">Page cache size in MB (0 keeps no copies)</label><br><input type="number" id="cache-size" name="cache-size" min="0" value="{cache_size}"></p>
+<p><label><input type="checkbox" name="prefetch" value="on"{prefetch}> Prefetch links on hover</label></p>
+<p><label><input type="checkbox" name="window" value="on"{window}> Remember window size and position</label></p>
+<p><label><input type="checkbox" name="background" value="on"{background}> Keep running in the background when the window closes</label></p>
+<p><label for="shortcut">Quick open shortcut</label><br><input type="text" id="shortcut" name="shortcut" value="{shortcut}"></p>
+<p><label for="homepage">Homepage</label><br><input type="url" id="homepage" name="homepage" value="{homepage}"></p>
+<p><label for="search">Search engine (<code>{{query}}</code> marks the search terms)</label><br><input type="text" id="search" name="search" value="{search}"></p>
+<p><label for="theme">Theme</label><br><select id="theme" name="theme">{theme_options}</select></p>
+<p><label for="scrolling">Smooth scrolling</label><br><select id="scrolling" name="scrolling">{scrolling_options}</select></p>
+<p><label for="referrer">Referrer policy</label><br><select id="referrer" name="referrer">{referrer_options}</select></p>
+<p><label><input type="checkbox" name="reduce-ua" value="on"{reduce_ua}> Send a reduced user agent</label></p>
+<p><label><input type="checkbox" name="strip-headers" value="on"{strip_headers}> Send no cookies, credentials or referrer to other sites</label></p>
+<p><label for="relays">Relays (one per line)</label><br><textarea id="relays" name="relays" rows="4">{relays}</textarea></p>
+<p><input type="submit" value="Save"></p>
+</form>
+{location}
+</section>"#,
+            javascript = if self.javascript_enabled {
+                " checked"
+            } else {
+                ""
+            },
+            offline = if self.work_offline { " checked" } else { "" },
+            prefetch = if self.prefetch { " checked" } else { "" },
+            window = if self.remember_window { " checked" } else { "" },
+            background = if self.background { " checked" } else { "" },
+            reduce_ua = if self.privacy.reduce_user_agent {
+                " checked"
+            } else {
+                ""
+            },
+            strip_headers = if self.privacy.strip_cross_origin_headers {
+                " checked"
+            } else {
+                ""
+            },
+            cache_size = self.cache_size_mb,
+            shortcut = encode_double_quoted_attribute(&self.quick_open_shortcut),
+            homepage = encode_double_quoted_attribute(&self.homepage),
+            search = encode_double_quoted_attribute(&self.search.template),
+            relays = encode_text(&relays),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_file_yields_defaults_and_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(FILE_NAME);
+        let mut prefs = Preferences::load(&path).unwrap();
+        assert_eq!(prefs, Preferences::default());
+
+        prefs.theme = ThemePreference::Dark;
+        prefs.relays = vec![Url::parse("wss://relay.example").unwrap()];
+        prefs.gestures.swipe_navigation = false;
+        prefs.gestures.swipe_threshold = 200;
+        prefs.privacy.strip_cross_origin_headers = true;
+        prefs.save(&path).unwrap();
+        assert_eq!(Preferences::load(&path).unwrap(), prefs);
+    }
+
+    #[test]
+    fn form_submission_overwrites_rendered_fields() {
+        let mut prefs = Preferences::default();
+        let rejected = prefs.apply_form(
+            "homepage=https%3A%2F%2Fnews.example&theme=light&relays=wss%3A%2F%2Fa.example%0D%0Ahttps%3A%2F%2Fnot-a-relay",
+        );
+        assert!(!prefs.javascript_enabled, "unchecked box disables JS");
+        assert_eq!(prefs.homepage, "https://news.example");
+        assert_eq!(prefs.theme, ThemePreference::Light);
+        assert_eq!(prefs.relays.len(), 1);
+        assert_eq!(rejected, vec!["relay https://not-a-relay".to_string()]);
+
+        assert!(!prefs.remember_window);
+        prefs.apply_form("javascript=on&theme=sepia&window=on");
+        assert!(prefs.javascript_enabled);
+        assert!(prefs.remember_window);
+        assert_eq!(prefs.theme, ThemePreference::Light);
+
+        assert_eq!(
+            prefs.apply_form("background=on&shortcut=Alt%2BSpace"),
+            Vec::<String>::new()
+        );
+        assert!(prefs.background);
+        assert_eq!(prefs.quick_open_shortcut, "Alt+Space");
+        assert_eq!(prefs.apply_form("shortcut=Hyper"), vec!["shortcut Hyper"]);
+        assert!(!prefs.background);
+        assert_eq!(prefs.quick_open_shortcut, "Alt+Space");
+
+        let rejected = prefs.apply_form("scrolling=off");
+        assert!(rejected.is_empty());
+        assert_eq!(prefs.scroll_easing, ScrollEasing::Off);
+        assert_eq!(
+            prefs.apply_form("scrolling=bouncy"),
+            vec!["scrolling bouncy"]
+        );
+        assert_eq!(prefs.scroll_easing, ScrollEasing::Off);
+
+        let rejected = prefs.apply_form("search=https%3A%2F%2Fsearch.example%2F");
+        assert_eq!(prefs.search, SearchEngine::default());
+        assert_eq!(
+            rejected,
+            vec!["search engine https://search.example/".to_string()]
+        );
+        prefs.apply_form("search=https%3A%2F%2Fsearch.example%2F%3Fq%3D%7Bquery%7D");
+        assert_eq!(prefs.search.template, "https://search.example/?q={query}");
+
+        let rejected = prefs.apply_form("referrer=no-referrer&reduce-ua=on");
+        assert!(rejected.is_empty());
+        assert_eq!(prefs.privacy.referrer_policy, ReferrerPolicy::NoReferrer);
+        assert!(prefs.privacy.reduce_user_agent);
+        assert!(!prefs.privacy.strip_cross_origin_headers);
+        assert_eq!(
+            prefs.apply_form("referrer=leaky"),
+            vec!["referrer policy leaky"]
+        );
+        assert_eq!(prefs.privacy.referrer_policy, ReferrerPolicy::NoReferrer);
+        assert!(!prefs.privacy.reduce_user_agent);
+
+        assert!(prefs.apply_form("cache-size=64").is_empty());
+        assert_eq!(prefs.cache_size_bytes(), 64 * 1024 * 1024);
+        assert_eq!(prefs.apply_form("cache-size=lots"), vec!["cache size lots"]);
+        assert_eq!(prefs.cache_size_mb, 64);
+    }
+
+    #[test]
+    fn settings_page_reflects_and_escapes_values() {
+        let prefs = Preferences {
+            homepage: "https://example.com/?a=\"b\"".into(),
+            ..Preferences::default()
+        };
+        let html = prefs.settings_page(None, Some("Saved"));
+        assert!(html.contains("name=\"javascript\" value=\"on\" checked"));
+        assert!(html.contains("&quot;b&quot;"));
+        assert!(html.contains("<option value=\"system\" selected>"));
+        assert!(html.contains("name=\"cache-size\" min=\"0\" value=\"256\""));
+        assert!(html.contains("<option value=\"ease-out\" selected>"));
+        assert!(html.contains("<option value=\"strict-origin-when-cross-origin\" selected>"));
+        assert!(html.contains("role=\"status\">Saved"));
+    }
+}
@@ -0,0 +1,337 @@
+//! Speculative document loads for links the user is likely to follow next.
+//!
+//! Targets come from hovering a link for [`HOVER_DELAY`] and from `<link rel="prefetch">` in
+//! the current document. Prefetches run through the normal fetch pipeline, at most
+//! [`MAX_CONCURRENT`] at a time, and land in an in-memory store that the next navigation to
+//! the same URL consumes instead of going to the network. Hit rate is tracked in
+//! [`PrefetchStats`] and logged on every lookup.
+//!
+//! Prefetches carry the user's cookies, so each target is first checked against the page's
+//! [`PrefetchScope`]: the filter lists, its site settings and its Content Security Policy, as
+//! its subresources are. Hovered links are prefetched only on the page's own origin.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use blitz_dom::net::Resource;
+use blitz_net::Provider;
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
+use tracing::debug;
+use url::Url;
+
+use crate::adblock::Blocklist;
+use crate::csp::ContentSecurityPolicy;
+use crate::navigation::{execute_fetch, FetchRequest, FetchSource, FetchedDocument};
+use crate::permissions::ContentRules;
+
+/// How long the pointer has to rest on a link before it is prefetched.
+pub const HOVER_DELAY: Duration = Duration::from_millis(100);
+const MAX_CONCURRENT: usize = 2;
+const MAX_ENTRIES: usize = 32;
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// Prefetches started.
+    pub started: u64,
+    /// Navigations served from a prefetched copy.
+    pub hits: u64,
+    /// Navigations that found nothing usable.
+    pub misses: u64,
+}
+
+impl PrefetchStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// The page prefetches are made from, and what its requests are checked against.
+#[derive(Debug, Clone, Default)]
+pub struct PrefetchScope {
+    pub page: Option<Url>,
+    pub rules: ContentRules,
+    pub blocklist: Arc<Blocklist>,
+    pub policy: ContentSecurityPolicy,
+}
+
+impl PrefetchScope {
+    /// Why the page may not prefetch `url`, if it may not.
+    pub fn block_reason(&self, url: &Url) -> Option<String> {
+        if let Some(setting) = self.rules.blocks(url, false) {
+            return Some(format!("blocked by site settings ({})", setting.name()));
+        }
+        if let Some(rule) = self.blocklist.blocking_rule(url, self.page.as_ref()) {
+            return Some(format!("blocked by filter {rule}"));
+        }
+        (!self.policy.allows_prefetch_url(url, self.page.as_ref()))
+            .then(|| "refused by the Content Security Policy".to_string())
+    }
+
+    /// Whether `url` has the page's origin.
+    pub fn is_same_origin(&self, url: &Url) -> bool {
+        self.page
+            .as_ref()
+            .is_some_and(|page| page.origin() == url.origin())
+    }
+}
+
+struct Entry {
+    stored_at: Instant,
+    document: FetchedDocument,
+}
+
+pub struct Prefetcher {
+    ttl: Duration,
+    entries: Mutex<HashMap<Url, Entry>>,
+    in_flight: Mutex<HashSet<Url>>,
+    permits: Arc<Semaphore>,
+    started: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for Prefetcher {
+    fn default() -> Self {
+        Self::with_ttl(DEFAULT_TTL)
+    }
+}
+
+impl Prefetcher {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashSet::new()),
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT)),
+            started: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Start loading `url` in the background unless it is already stored or loading, or
+    /// `scope` does not allow it.
+    pub fn prefetch(
+        self: &Arc<Self>,
+        handle: &Handle,
+        url: Url,
+        scope: &PrefetchScope,
+        net_provider: Arc<Provider<Resource>>,
+    ) {
+        if !matches!(url.scheme(), "http" | "https") || self.is_fresh(&url) {
+            return;
+        }
+        if let Some(reason) = scope.block_reason(&url) {
+            debug!(target = "prefetch", url = %url, %reason, "not prefetching");
+            return;
+        }
+        if !self.in_flight.lock().unwrap().insert(url.clone()) {
+            return;
+        }
+        self.started.fetch_add(1, Ordering::Relaxed);
+        let this = Arc::clone(self);
+        handle.spawn(async move {
+            let Ok(_permit) = Arc::clone(&this.permits).acquire_owned().await else {
+                return;
+            };
+            let request = FetchRequest {
+                source: FetchSource::Url(url.clone()),
+                display_url: url.to_string(),
+                user_agent: None,
+            };
+            match execute_fetch(&request, net_provider).await {
+                Ok(document) => {
+                    debug!(target = "prefetch", url = %url, "prefetched");
+                    this.insert(url.clone(), document);
+                }
+                Err(err) => {
+                    debug!(target = "prefetch", url = %url, error = %err, "prefetch failed")
+                }
+            }
+            this.in_flight.lock().unwrap().remove(&url);
+        });
+    }
+
+    fn is_fresh(&self, url: &Url) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(url)
+            .is_some_and(|entry| entry.stored_at.elapsed() < self.ttl)
+    }
+
+    pub(crate) fn insert(&self, url: Url, document: FetchedDocument) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.stored_at.elapsed() < self.ttl);
+        if entries.len() >= MAX_ENTRIES {
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored_at)
+                .map(|(url, _)| url.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            url,
+            Entry {
+                stored_at: Instant::now(),
+                document,
+            },
+        );
+    }
+
+    /// Consume the prefetched copy of `url`, if one is still fresh.
+    pub fn take(&self, url: &Url) -> Option<FetchedDocument> {
+        let entry = self
+            .entries
+            .lock()
+            .unwrap()
+            .remove(url)
+            .filter(|entry| entry.stored_at.elapsed() < self.ttl);
+        match entry {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        let stats = self.stats();
+        debug!(
+            target = "prefetch",
+            url = %url,
+            hit = entry.is_some(),
+            hits = stats.hits,
+            misses = stats.misses,
+            hit_rate = stats.hit_rate(),
+            "prefetch lookup"
+        );
+        entry.map(|entry| entry.document)
+    }
+
+    /// Documents waiting to be used and the size of their contents in bytes.
+    pub fn held(&self) -> (usize, u64) {
+        let entries = self.entries.lock().unwrap();
+        let bytes = entries
+            .values()
+            .map(|entry| entry.document.contents.len() as u64)
+            .sum();
+        (entries.len(), bytes)
+    }
+
+    pub fn stats(&self) -> PrefetchStats {
+        PrefetchStats {
+            started: self.started.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Whether a `rel` attribute value contains the `prefetch` keyword.
+pub fn is_prefetch_rel(rel: &str) -> bool {
+    rel.split_ascii_whitespace()
+        .any(|token| token.eq_ignore_ascii_case("prefetch"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_traits::net::DummyNetCallback;
+    use tokio::net::TcpListener;
+
+    fn document(url: &Url) -> FetchedDocument {
+        FetchedDocument {
+            base_url: url.to_string(),
+            contents: "<p>next</p>".into(),
+            file_path: None,
+            display_url: url.to_string(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        }
+    }
+
+    #[test]
+    fn prefetched_documents_are_consumed_once() {
+        let prefetcher = Prefetcher::default();
+        let url = Url::parse("https://example.com/next").unwrap();
+        prefetcher.insert(url.clone(), document(&url));
+
+        assert!(prefetcher.take(&url).is_some());
+        assert!(prefetcher.take(&url).is_none());
+        let stats = prefetcher.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn stale_entries_miss() {
+        let prefetcher = Prefetcher::with_ttl(Duration::ZERO);
+        let url = Url::parse("https://example.com/next").unwrap();
+        prefetcher.insert(url.clone(), document(&url));
+        assert!(prefetcher.take(&url).is_none());
+    }
+
+    #[test]
+    fn blocked_links_are_not_prefetched() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::write(dir.path().join("privacy.txt"), "||localhost^\n").unwrap();
+            let scope = PrefetchScope {
+                page: Some(Url::parse("https://site.example/").unwrap()),
+                blocklist: Arc::new(Blocklist::load_dir(dir.path()).unwrap()),
+                ..PrefetchScope::default()
+            };
+            let prefetcher = Arc::new(Prefetcher::default());
+            let target = Url::parse(&format!("http://localhost:{}/next", addr.port())).unwrap();
+            prefetcher.prefetch(
+                &Handle::current(),
+                target,
+                &scope,
+                Arc::new(Provider::new(Arc::new(DummyNetCallback))),
+            );
+
+            let accepted =
+                tokio::time::timeout(Duration::from_millis(200), listener.accept()).await;
+            assert!(accepted.is_err(), "the blocked host was contacted");
+            assert_eq!(prefetcher.stats().started, 0);
+        });
+    }
+
+    #[test]
+    fn scope_applies_the_page_csp() {
+        let page = Url::parse("https://site.example/").unwrap();
+        let scope = PrefetchScope {
+            page: Some(page.clone()),
+            policy: ContentSecurityPolicy::parse("default-src 'self'"),
+            ..PrefetchScope::default()
+        };
+        assert!(scope
+            .block_reason(&Url::parse("https://site.example/next").unwrap())
+            .is_none());
+        assert!(scope
+            .block_reason(&Url::parse("https://other.example/next").unwrap())
+            .is_some());
+        assert!(!scope.is_same_origin(&Url::parse("https://other.example/").unwrap()));
+    }
+
+    #[test]
+    fn recognises_prefetch_rel_tokens() {
+        assert!(is_prefetch_rel("prefetch"));
+        assert!(is_prefetch_rel("noopener PREFETCH"));
+        assert!(!is_prefetch_rel("dns-prefetch"));
+    }
+}
@@ -0,0 +1,932 @@
+//! Exporting pages to PDF as they are styled for paper.
+//!
+//! Stylo always evaluates media queries for the `screen` media type, so [`export_pdf`] hands
+//! it a copy of the page with the media types swapped instead: `@media print` blocks, `print`
+//! media lists on `@import`, `<link>` and `<style>`, and their `not`/`only` forms then apply,
+//! while `screen` ones stop applying. Linked stylesheets are fetched and inlined for this, with
+//! their `url()`s made absolute; sheets they `@import` are loaded unchanged.
+//!
+//! The page is laid out at the width of the paper inside its margins and cut into pages of
+//! its height. `break-before`/`break-after` (and the legacy `page-break-*` properties) with
+//! `page`, `always`, `left` or `right` start a new page, and `break-inside: avoid` moves the
+//! break above an element that would otherwise be split, if it fits on a page. These come from
+//! the print stylesheets' own rules rather than the cascade, so a later `auto` cannot turn
+//! a break off again. Each page is painted by the CPU renderer and written with [`pdf`].
+//! Scripts do not run; `@page` rules are ignored in favour of [`PrintOptions`].
+//!
+//! [`pdf`]: crate::pdf
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use blitz_dom::{BaseDocument, DocumentConfig, LocalName};
+use blitz_html::HtmlDocument;
+use blitz_net::{MpscCallback, Provider};
+use blitz_traits::net::DummyNetCallback;
+use blitz_traits::shell::{ColorScheme, Viewport};
+use image::{Rgb, RgbImage};
+use kuchiki::traits::*;
+use kuchiki::{parse_html, ElementData, NodeDataRef, NodeRef};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::http_client;
+use crate::js::shadow::{split_rule, split_top_level};
+use crate::navigation::{self, FetchRequest, FetchSource};
+use crate::pdf::{self, PdfPage};
+use crate::privacy;
+use crate::screenshot;
+
+/// Marks elements whose print styles ask for page breaks, with [`PageBreak`] keywords.
+pub const BREAK_ATTRIBUTE: &str = "frontier-print-break";
+
+const CSS_PX_PER_MM: f64 = 96.0 / 25.4;
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+/// At-rules whose blocks can hold `@media` rules.
+const NESTED_AT_RULES: [&str; 5] = ["@media", "@supports", "@layer", "@container", "@document"];
+const BREAK_VALUES: [&str; 6] = ["page", "always", "left", "right", "recto", "verso"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Paper {
+    #[default]
+    A4,
+    Letter,
+    Legal,
+}
+
+impl Paper {
+    /// Portrait width and height in millimetres.
+    fn size_mm(self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+            Paper::Legal => (215.9, 355.6),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrintOptions {
+    pub paper: Paper,
+    pub landscape: bool,
+    /// Blank space around every page, in millimetres.
+    pub margin_mm: f64,
+    /// Device pixels per CSS pixel the pages are painted at; 2 is 192 dpi.
+    pub pixel_ratio: f64,
+    /// How long to wait for images, fonts and stylesheets before painting anyway.
+    pub load_timeout_ms: u64,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            paper: Paper::A4,
+            landscape: false,
+            margin_mm: 10.0,
+            pixel_ratio: 2.0,
+            load_timeout_ms: 10_000,
+        }
+    }
+}
+
+impl PrintOptions {
+    fn validate(&self) -> Result<()> {
+        let (width, height) = self.paper_mm();
+        if !(0.0..width.min(height) / 2.0).contains(&self.margin_mm) {
+            bail!("a {} mm margin leaves nothing to print on", self.margin_mm);
+        }
+        if !(self.pixel_ratio > 0.0 && self.pixel_ratio <= 8.0) {
+            bail!("pixel ratio {} is outside 0 to 8", self.pixel_ratio);
+        }
+        Ok(())
+    }
+
+    fn paper_mm(&self) -> (f64, f64) {
+        let (width, height) = self.paper.size_mm();
+        if self.landscape {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Width and height of the area inside the margins, in CSS pixels.
+    pub fn content_size(&self) -> (f64, f64) {
+        let (width, height) = self.paper_mm();
+        (
+            (width - 2.0 * self.margin_mm) * CSS_PX_PER_MM,
+            (height - 2.0 * self.margin_mm) * CSS_PX_PER_MM,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageBreak {
+    Before,
+    After,
+    Avoid,
+}
+
+impl PageBreak {
+    fn keyword(self) -> &'static str {
+        match self {
+            PageBreak::Before => "before",
+            PageBreak::After => "after",
+            PageBreak::Avoid => "avoid",
+        }
+    }
+
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "before" => Some(PageBreak::Before),
+            "after" => Some(PageBreak::After),
+            "avoid" => Some(PageBreak::Avoid),
+            _ => None,
+        }
+    }
+}
+
+/// A laid-out element with a page break, in CSS pixels from the top of the document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakHint {
+    pub top: f64,
+    pub bottom: f64,
+    pub kind: PageBreak,
+}
+
+/// The part of the document one page shows, in CSS pixels from the top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSlice {
+    pub top: f64,
+    pub bottom: f64,
+}
+
+/// A page rewritten for print layout by [`prepare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintDocument {
+    pub html: String,
+    pub title: String,
+}
+
+/// Load `url` and export it as a PDF.
+pub async fn export_pdf(url: &Url, options: &PrintOptions) -> Result<Vec<u8>> {
+    options.validate()?;
+    let request = FetchRequest {
+        source: FetchSource::Url(url.clone()),
+        display_url: url.to_string(),
+        user_agent: None,
+    };
+    let net = Arc::new(Provider::new(Arc::new(DummyNetCallback)));
+    let fetched = navigation::execute_fetch(&request, net)
+        .await
+        .with_context(|| format!("failed to load {url}"))?;
+    let base = Url::parse(&fetched.base_url).unwrap_or_else(|_| url.clone());
+
+    let mut sheets = HashMap::new();
+    for sheet in stylesheet_links(&fetched.contents, &base) {
+        match fetch_text(&sheet).await {
+            Ok(css) => {
+                sheets.insert(sheet, css);
+            }
+            Err(err) => tracing::warn!(
+                target = "print",
+                url = %sheet,
+                error = %err,
+                "stylesheet left for the renderer to load, without its print rules"
+            ),
+        }
+    }
+    let document = prepare(&fetched.contents, &base, &sheets);
+    let images = render_pages(&document.html, &base, options).await?;
+
+    let (width, height) = options.paper_mm();
+    let pages: Vec<PdfPage> = images
+        .into_iter()
+        .map(|image| PdfPage {
+            image,
+            width: width * POINTS_PER_MM,
+            height: height * POINTS_PER_MM,
+            margin: options.margin_mm * POINTS_PER_MM,
+        })
+        .collect();
+    let title = if document.title.is_empty() {
+        url.as_str()
+    } else {
+        &document.title
+    };
+    pdf::write_pdf(&pages, title)
+}
+
+async fn fetch_text(url: &Url) -> Result<String> {
+    if url.scheme() == "file" {
+        let path = url
+            .to_file_path()
+            .map_err(|_| anyhow!("{url} is not a local path"))?;
+        return tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()));
+    }
+    let mut headers = reqwest::header::HeaderMap::new();
+    privacy::apply(&mut headers, url, None);
+    let response = http_client::shared()?
+        .get(url.clone())
+        .headers(headers)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.text().await?)
+}
+
+/// Absolute URLs of the `<link rel="stylesheet">`s in `html`.
+pub fn stylesheet_links(html: &str, base: &Url) -> Vec<Url> {
+    let document = parse_html().one(html);
+    let Ok(links) = document.select("link[href]") else {
+        return Vec::new();
+    };
+    links
+        .filter_map(|link| stylesheet_href(&link.attributes.borrow(), base))
+        .collect()
+}
+
+fn stylesheet_href(attributes: &kuchiki::Attributes, base: &Url) -> Option<Url> {
+    let is_stylesheet = attributes.get("rel").is_some_and(|rel| {
+        rel.split_ascii_whitespace()
+            .any(|token| token.eq_ignore_ascii_case("stylesheet"))
+    });
+    if !is_stylesheet {
+        return None;
+    }
+    base.join(attributes.get("href")?.trim()).ok()
+}
+
+/// `html` ready for print layout: `sheets`, fetched for [`stylesheet_links`], inlined, media
+/// types swapped and [`BREAK_ATTRIBUTE`] set on the elements print styles break pages at.
+pub fn prepare(html: &str, base: &Url, sheets: &HashMap<Url, String>) -> PrintDocument {
+    let document = parse_html().one(html);
+
+    if let Ok(links) = document.select("link[href]") {
+        for link in links.collect::<Vec<_>>() {
+            let attributes = link.attributes.borrow();
+            let Some(url) = stylesheet_href(&attributes, base) else {
+                continue;
+            };
+            let Some(css) = sheets.get(&url) else {
+                continue;
+            };
+            let style = style_element(&absolutize_urls(css, &url), attributes.get("media"));
+            drop(attributes);
+            link.as_node().insert_before(style);
+            link.as_node().detach();
+        }
+    }
+
+    let mut rules = Vec::new();
+    if let Ok(styles) = document.select("style") {
+        for style in styles.collect::<Vec<_>>() {
+            let css = style.text_contents();
+            let media = style.attributes.borrow().get("media").map(str::to_string);
+            if media.as_deref().is_none_or(media_list_matches_print) {
+                rules.extend(break_rules(&css));
+            }
+            let node = style.as_node();
+            for child in node.children().collect::<Vec<_>>() {
+                child.detach();
+            }
+            node.append(NodeRef::new_text(print_media_css(&css)));
+        }
+    }
+    if let Ok(elements) = document.select("link[media], style[media]") {
+        for element in elements {
+            let mut attributes = element.attributes.borrow_mut();
+            if let Some(media) = attributes.get_mut("media") {
+                *media = swap_media_types(media);
+            }
+        }
+    }
+
+    for (selector, kind) in &rules {
+        // Selectors kuchiki cannot match, such as pseudo-elements, are skipped.
+        if let Ok(elements) = document.select(selector) {
+            for element in elements.collect::<Vec<_>>() {
+                mark_break(&element, *kind);
+            }
+        }
+    }
+    if let Ok(elements) = document.select("[style]") {
+        for element in elements.collect::<Vec<_>>() {
+            let style = element.attributes.borrow().get("style").map(page_breaks);
+            for kind in style.unwrap_or_default() {
+                mark_break(&element, kind);
+            }
+        }
+    }
+
+    let title = document
+        .select_first("title")
+        .map(|title| title.text_contents().trim().to_string())
+        .unwrap_or_default();
+    PrintDocument {
+        html: document.to_string(),
+        title,
+    }
+}
+
+fn mark_break(element: &NodeDataRef<ElementData>, kind: PageBreak) {
+    let mut attributes = element.attributes.borrow_mut();
+    let keywords = attributes.get(BREAK_ATTRIBUTE).unwrap_or_default();
+    if keywords
+        .split_whitespace()
+        .any(|keyword| keyword == kind.keyword())
+    {
+        return;
+    }
+    let keywords = format!("{keywords} {}", kind.keyword())
+        .trim_start()
+        .to_string();
+    attributes.insert(BREAK_ATTRIBUTE, keywords);
+}
+
+/// A detached `<style>` holding `css`.
+fn style_element(css: &str, media: Option<&str>) -> NodeRef {
+    // kuchiki builds elements from names of its own html5ever version, so parse one instead.
+    let style = parse_html()
+        .one("<style></style>")
+        .select_first("style")
+        .expect("parsed a style element")
+        .as_node()
+        .clone();
+    style.detach();
+    if let (Some(media), Some(element)) = (media, style.as_element()) {
+        element
+            .attributes
+            .borrow_mut()
+            .insert("media", media.to_string());
+    }
+    style.append(NodeRef::new_text(css));
+    style
+}
+
+/// `css` with the `print` and `screen` media types swapped in `@media` and `@import` rules.
+pub fn print_media_css(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    swap_rules(css, &mut output);
+    output
+}
+
+fn swap_rules(mut rest: &str, output: &mut String) {
+    loop {
+        let trimmed = rest.trim_start();
+        output.push_str(&rest[..rest.len() - trimmed.len()]);
+        rest = trimmed;
+        if rest.starts_with("/*") {
+            let end = rest.find("*/").map_or(rest.len(), |end| end + 2);
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.is_empty() {
+            return;
+        }
+        let Some((prelude, body, after)) = split_rule(rest) else {
+            output.push_str(rest);
+            return;
+        };
+        let name = at_rule_name(prelude);
+        match body {
+            None if name == "@import" => {
+                output.push_str(&swap_media_types(prelude));
+                output.push(';');
+            }
+            None => output.push_str(&rest[..rest.len() - after.len()]),
+            Some(body) if NESTED_AT_RULES.contains(&name.as_str()) => {
+                if name == "@media" {
+                    output.push_str(&swap_media_types(prelude));
+                } else {
+                    output.push_str(prelude);
+                }
+                output.push('{');
+                swap_rules(body, output);
+                output.push('}');
+            }
+            Some(_) => output.push_str(&rest[..rest.len() - after.len()]),
+        }
+        rest = after;
+    }
+}
+
+fn at_rule_name(prelude: &str) -> String {
+    if !prelude.starts_with('@') {
+        return String::new();
+    }
+    prelude
+        .split(|ch: char| ch.is_whitespace() || ch == '(' || ch == '"' || ch == '\'')
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// `list` with every `print` media type turned into `screen` and the other way round. Words
+/// in parentheses, such as `url(print.css)` or a feature, and in strings are left alone.
+fn swap_media_types(list: &str) -> String {
+    fn swapped(word: &str) -> &str {
+        if word.eq_ignore_ascii_case("print") {
+            "screen"
+        } else if word.eq_ignore_ascii_case("screen") {
+            "print"
+        } else {
+            word
+        }
+    }
+
+    let mut output = String::with_capacity(list.len() + 8);
+    let mut word = String::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    for ch in list.chars() {
+        let in_word = ch.is_ascii_alphanumeric() || ch == '-' || ch == '_';
+        if quote.is_none() && depth == 0 && in_word {
+            word.push(ch);
+            continue;
+        }
+        output.push_str(swapped(&word));
+        word.clear();
+        match quote {
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None => match ch {
+                '"' | '\'' => quote = Some(ch),
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ => {}
+            },
+        }
+        output.push(ch);
+    }
+    output.push_str(swapped(&word));
+    output
+}
+
+/// Whether a media query list holds for print. Media features are taken to hold.
+fn media_list_matches_print(list: &str) -> bool {
+    let list = list.trim();
+    list.is_empty()
+        || split_top_level(list, ',')
+            .into_iter()
+            .any(query_matches_print)
+}
+
+fn query_matches_print(query: &str) -> bool {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .take_while(|word| !word.starts_with('('))
+        .map(str::to_ascii_lowercase)
+        .collect();
+    let (negated, rest) = match words.first().map(String::as_str) {
+        Some("not") => (true, &words[1..]),
+        Some("only") => (false, &words[1..]),
+        _ => (false, &words[..]),
+    };
+    let media_type = rest
+        .first()
+        .map(String::as_str)
+        .filter(|word| *word != "and")
+        .unwrap_or("all");
+    matches!(media_type, "all" | "print") != negated
+}
+
+/// The selectors of `css`'s print style rules that break pages, with the break each asks for.
+pub fn break_rules(css: &str) -> Vec<(String, PageBreak)> {
+    let mut rules = Vec::new();
+    collect_break_rules(css, &mut rules);
+    rules
+}
+
+fn collect_break_rules(mut rest: &str, rules: &mut Vec<(String, PageBreak)>) {
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("/*") {
+            rest = rest.find("*/").map_or("", |end| &rest[end + 2..]);
+            continue;
+        }
+        let Some((prelude, body, after)) = split_rule(rest) else {
+            return;
+        };
+        rest = after;
+        let Some(body) = body else {
+            continue;
+        };
+        if prelude.starts_with('@') {
+            let name = at_rule_name(prelude);
+            let applies = match name.as_str() {
+                "@media" => media_list_matches_print(&prelude[name.len()..]),
+                "@supports" | "@layer" => true,
+                _ => false,
+            };
+            if applies {
+                collect_break_rules(body, rules);
+            }
+            continue;
+        }
+        let selector = prelude.trim();
+        for kind in page_breaks(body) {
+            rules.push((selector.to_string(), kind));
+        }
+    }
+}
+
+/// The page breaks a declaration block asks for.
+pub fn page_breaks(declarations: &str) -> Vec<PageBreak> {
+    let mut breaks = Vec::new();
+    for declaration in declarations.split(';') {
+        let Some((name, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim().to_ascii_lowercase();
+        let value = value.trim_end_matches("!important").trim();
+        let kind = match name.as_str() {
+            "break-before" | "page-break-before" if BREAK_VALUES.contains(&value) => {
+                PageBreak::Before
+            }
+            "break-after" | "page-break-after" if BREAK_VALUES.contains(&value) => PageBreak::After,
+            "break-inside" | "page-break-inside" if matches!(value, "avoid" | "avoid-page") => {
+                PageBreak::Avoid
+            }
+            _ => continue,
+        };
+        if !breaks.contains(&kind) {
+            breaks.push(kind);
+        }
+    }
+    breaks
+}
+
+/// `css` with its relative `url()`s resolved against `sheet`, the URL it was loaded from.
+fn absolutize_urls(css: &str, sheet: &Url) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        let inner_start = start + "url(".len();
+        let Some(length) = rest[inner_start..].find(')') else {
+            break;
+        };
+        output.push_str(&rest[..inner_start]);
+        let inner = &rest[inner_start..inner_start + length];
+        let target = inner.trim().trim_matches(|ch| ch == '"' || ch == '\'');
+        match sheet.join(target) {
+            Ok(url) if !target.is_empty() && !target.starts_with("data:") => {
+                output.push_str(&format!("\"{url}\""));
+            }
+            _ => output.push_str(inner),
+        }
+        rest = &rest[inner_start + length..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Cut a document `total` CSS pixels tall into pages of `page_height`, breaking where `hints`
+/// force or avoid it.
+pub fn paginate(total: f64, page_height: f64, hints: &[BreakHint]) -> Vec<PageSlice> {
+    // Breaks closer than half a pixel to the edge of a page would leave it blank.
+    const SLACK: f64 = 0.5;
+
+    let mut forced: Vec<f64> = hints
+        .iter()
+        .filter_map(|hint| match hint.kind {
+            PageBreak::Before => Some(hint.top),
+            PageBreak::After => Some(hint.bottom),
+            PageBreak::Avoid => None,
+        })
+        .collect();
+    forced.sort_by(f64::total_cmp);
+
+    let mut pages = Vec::new();
+    let mut top = 0.0;
+    loop {
+        let limit = top + page_height;
+        let forced_break = forced
+            .iter()
+            .copied()
+            .find(|&at| at > top + SLACK && at < total - SLACK && at <= limit);
+        let bottom = match forced_break {
+            Some(at) => at,
+            None if limit >= total => total,
+            None => hints
+                .iter()
+                .filter(|hint| {
+                    hint.kind == PageBreak::Avoid
+                        && hint.top > top + SLACK
+                        && hint.top < limit
+                        && hint.bottom > limit
+                        && hint.bottom - hint.top <= page_height
+                })
+                .map(|hint| hint.top)
+                .reduce(f64::min)
+                .unwrap_or(limit),
+        };
+        pages.push(PageSlice {
+            top,
+            bottom: bottom.max(top),
+        });
+        if bottom >= total {
+            return pages;
+        }
+        top = bottom;
+    }
+}
+
+async fn render_pages(html: &str, base: &Url, options: &PrintOptions) -> Result<Vec<RgbImage>> {
+    let (mut resources, callback) = MpscCallback::new();
+    let net = Arc::new(Provider::new(Arc::new(callback)));
+    let mut document = HtmlDocument::from_html(
+        html,
+        DocumentConfig {
+            base_url: Some(base.to_string()),
+            net_provider: Some(net.clone()),
+            ..Default::default()
+        },
+    );
+
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(options.load_timeout_ms);
+    while !net.is_empty() {
+        match tokio::time::timeout_at(deadline, resources.recv()).await {
+            Ok(Some((_, resource))) => document.load_resource(resource),
+            Ok(None) => break,
+            Err(_) => {
+                tracing::warn!(
+                    target = "print",
+                    url = %base,
+                    "printing before every subresource loaded"
+                );
+                break;
+            }
+        }
+    }
+
+    let (width, height) = options.content_size();
+    let ratio = options.pixel_ratio;
+    let pixel_width = (width * ratio).round() as u32;
+    let pixel_height = (height * ratio).round() as u32;
+    document.set_viewport(Viewport::new(
+        pixel_width,
+        pixel_height,
+        ratio as f32,
+        ColorScheme::Light,
+    ));
+    document.resolve();
+
+    let total = f64::from(document.root_element().final_layout.size.height);
+    let pages = paginate(total, height, &break_hints(&document));
+    pages
+        .iter()
+        .map(|page| render_page(&mut document, page, ratio, pixel_width, pixel_height))
+        .collect()
+}
+
+fn break_hints(doc: &BaseDocument) -> Vec<BreakHint> {
+    let attribute = LocalName::from(BREAK_ATTRIBUTE);
+    let mut hints = Vec::new();
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        stack.extend(node.children.iter().copied());
+        let Some(keywords) = node.attr(attribute.clone()) else {
+            continue;
+        };
+        let top = f64::from(node.absolute_position(0.0, 0.0).y);
+        let bottom = top + f64::from(node.final_layout.size.height);
+        hints.extend(
+            keywords
+                .split_whitespace()
+                .filter_map(PageBreak::from_keyword)
+                .map(|kind| BreakHint { top, bottom, kind }),
+        );
+    }
+    hints
+}
+
+/// Paint the part of `doc` that `page` covers onto a white page of the viewport's size.
+fn render_page(
+    doc: &mut BaseDocument,
+    page: &PageSlice,
+    ratio: f64,
+    width: u32,
+    height: u32,
+) -> Result<RgbImage> {
+    let mut scroll = doc.viewport_scroll();
+    scroll.y = page.top;
+    doc.set_viewport_scroll(scroll);
+    // The last page may not scroll as far down as it starts; skip what the previous page showed.
+    let skip = ((page.top - doc.viewport_scroll().y) * ratio)
+        .round()
+        .max(0.0) as u32;
+    let rows = (((page.bottom - page.top) * ratio).round() as u32).min(height);
+
+    let capture = screenshot::capture(doc, ratio, width, height)?;
+    let mut image = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    for y in 0..rows {
+        let source_y = y + skip;
+        if source_y >= height {
+            break;
+        }
+        for x in 0..width {
+            let [red, green, blue, alpha] = capture.get_pixel(x, source_y).0;
+            let over_white = |channel: u8| {
+                let alpha = u32::from(alpha);
+                ((u32::from(channel) * alpha + 255 * (255 - alpha)) / 255) as u8
+            };
+            image.put_pixel(
+                x,
+                y,
+                Rgb([over_white(red), over_white(green), over_white(blue)]),
+            );
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_print_and_screen_media() {
+        assert_eq!(
+            print_media_css(
+                "@media print { nav { display: none } }\n\
+                 @media only screen and (min-width: 600px) { .wide { float: left } }\n\
+                 @import url(print.css) screen;\n\
+                 @supports (display: grid) { @media not print { p { color: red } } }\n\
+                 p { content: \"screen\" }"
+            ),
+            "@media screen { nav { display: none } }\n\
+             @media only print and (min-width: 600px) { .wide { float: left } }\n\
+             @import url(print.css) print;\n\
+             @supports (display: grid) { @media not screen { p { color: red } } }\n\
+             p { content: \"screen\" }"
+        );
+    }
+
+    #[test]
+    fn evaluates_media_lists_for_print() {
+        assert!(media_list_matches_print(""));
+        assert!(media_list_matches_print("print"));
+        assert!(media_list_matches_print(
+            "screen, print and (min-width: 1px)"
+        ));
+        assert!(media_list_matches_print("not screen"));
+        assert!(media_list_matches_print("(orientation: portrait)"));
+        assert!(!media_list_matches_print("screen"));
+        assert!(!media_list_matches_print("ONLY screen and (color)"));
+        assert!(!media_list_matches_print("not print"));
+    }
+
+    #[test]
+    fn collects_break_rules_from_print_styles() {
+        let css = "h1, .chapter { break-before: page }\n\
+                   /* a comment */ table { page-break-inside: avoid !important; color: red }\n\
+                   @media print { .end { page-break-after: always } }\n\
+                   @media screen { .screen-only { break-before: page } }\n\
+                   .soft { break-before: auto; break-after: avoid }";
+        assert_eq!(
+            break_rules(css),
+            [
+                ("h1, .chapter".to_string(), PageBreak::Before),
+                ("table".to_string(), PageBreak::Avoid),
+                (".end".to_string(), PageBreak::After),
+            ]
+        );
+    }
+
+    #[test]
+    fn prepares_documents_for_print() {
+        let base = Url::parse("https://docs.example/guide/intro.html").unwrap();
+        let sheet = Url::parse("https://docs.example/css/site.css").unwrap();
+        let html = r#"<html><head><title> Guide </title>
+            <link rel="stylesheet" href="../css/site.css" media="print">
+            <link rel="stylesheet" href="missing.css" media="screen">
+            <style>@media print { h2 { break-before: page } }</style>
+            </head><body><h1>Intro</h1><h2 id="a">A</h2>
+            <section style="break-inside: avoid"><h2>B</h2></section></body></html>"#;
+        assert_eq!(
+            stylesheet_links(html, &base),
+            [
+                sheet.clone(),
+                Url::parse("https://docs.example/guide/missing.css").unwrap()
+            ]
+        );
+
+        let sheets = HashMap::from([(
+            sheet,
+            ".logo { background: url('img/logo.png') } .toc { break-after: page }".to_string(),
+        )]);
+        let document = prepare(html, &base, &sheets);
+        assert_eq!(document.title, "Guide");
+
+        let parsed = parse_html().one(document.html.as_str());
+        let attribute = |selector: &str, name: &str| {
+            let element = parsed.select_first(selector).unwrap();
+            let attributes = element.attributes.borrow();
+            attributes.get(name).map(str::to_string)
+        };
+        let inlined = parsed.select_first(r#"style[media="screen"]"#).unwrap();
+        assert!(inlined
+            .text_contents()
+            .contains(r#".logo { background: url("https://docs.example/css/img/logo.png") }"#));
+        assert_eq!(
+            attribute(r#"link[href="missing.css"]"#, "media").as_deref(),
+            Some("print")
+        );
+        assert!(document
+            .html
+            .contains("@media screen { h2 { break-before: page } }"));
+        assert_eq!(attribute("#a", BREAK_ATTRIBUTE).as_deref(), Some("before"));
+        assert_eq!(
+            attribute("section", BREAK_ATTRIBUTE).as_deref(),
+            Some("avoid")
+        );
+        assert_eq!(
+            attribute("section h2", BREAK_ATTRIBUTE).as_deref(),
+            Some("before")
+        );
+        assert_eq!(attribute("h1", BREAK_ATTRIBUTE), None);
+    }
+
+    #[test]
+    fn paginates_at_forced_and_avoided_breaks() {
+        let slice = |top, bottom| PageSlice { top, bottom };
+        assert_eq!(
+            paginate(250.0, 100.0, &[]),
+            [slice(0.0, 100.0), slice(100.0, 200.0), slice(200.0, 250.0)]
+        );
+
+        let hints = [
+            // A heading at the very top asks for nothing.
+            BreakHint {
+                top: 0.0,
+                bottom: 20.0,
+                kind: PageBreak::Before,
+            },
+            BreakHint {
+                top: 60.0,
+                bottom: 80.0,
+                kind: PageBreak::Before,
+            },
+            // Straddles the break at 160, fits on a page: moved to its top.
+            BreakHint {
+                top: 130.0,
+                bottom: 190.0,
+                kind: PageBreak::Avoid,
+            },
+            // Taller than a page, so it is split anyway.
+            BreakHint {
+                top: 200.0,
+                bottom: 320.0,
+                kind: PageBreak::Avoid,
+            },
+            BreakHint {
+                top: 330.0,
+                bottom: 340.0,
+                kind: PageBreak::After,
+            },
+        ];
+        assert_eq!(
+            paginate(340.0, 100.0, &hints),
+            [
+                slice(0.0, 60.0),
+                slice(60.0, 130.0),
+                slice(130.0, 230.0),
+                slice(230.0, 330.0),
+                slice(330.0, 340.0)
+            ]
+        );
+
+        assert_eq!(paginate(0.0, 100.0, &[]), [slice(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn page_sizes_follow_paper_and_margins() {
+        let options = PrintOptions {
+            paper: Paper::Letter,
+            landscape: true,
+            margin_mm: 0.0,
+            ..Default::default()
+        };
+        let (width, height) = options.content_size();
+        assert_eq!((width.round(), height.round()), (1056.0, 816.0));
+        assert!(options.validate().is_ok());
+        assert!(PrintOptions {
+            margin_mm: 150.0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,269 @@
+//! What outgoing requests reveal about the user and the page that made them.
+//!
+//! Every request Frontier builds itself (document loads, subresources, `fetch()`, external
+//! scripts, Blossom uploads) finishes its headers with [`apply`], which:
+//!
+//! - sends [`user_agent::current`], which is the reduced string when
+//!   [`PrivacySettings::reduce_user_agent`] is on, or the string an automation session
+//!   emulates when made through [`apply_as`];
+//! - sends a `Referer` derived from the initiating page under the configured
+//!   [`ReferrerPolicy`], never one the caller set itself;
+//! - with [`PrivacySettings::strip_cross_origin_headers`], drops `Cookie`, `Authorization`,
+//!   `From` and `Referer` from requests to another origin than the page's.
+//!
+//! The settings are read from `frontier.toml` on first use and replaced by [`configure`] when
+//! the settings page saves. Top-level navigations have no initiating page and send no
+//! `Referer`.
+
+use std::sync::{OnceLock, RwLock};
+
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE, FROM, REFERER, USER_AGENT};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::preferences::Preferences;
+use crate::user_agent;
+
+/// Longest `Referer` sent in full; longer ones are cut to the origin, as browsers do.
+const MAX_REFERRER_LEN: usize = 4096;
+
+/// How much of the initiating page's URL a request's `Referer` carries, with the meanings of
+/// the `Referrer-Policy` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferrerPolicy {
+    NoReferrer,
+    NoReferrerWhenDowngrade,
+    Origin,
+    OriginWhenCrossOrigin,
+    SameOrigin,
+    StrictOrigin,
+    #[default]
+    StrictOriginWhenCrossOrigin,
+    UnsafeUrl,
+}
+
+impl ReferrerPolicy {
+    pub const ALL: [ReferrerPolicy; 8] = [
+        ReferrerPolicy::NoReferrer,
+        ReferrerPolicy::NoReferrerWhenDowngrade,
+        ReferrerPolicy::Origin,
+        ReferrerPolicy::OriginWhenCrossOrigin,
+        ReferrerPolicy::SameOrigin,
+        ReferrerPolicy::StrictOrigin,
+        ReferrerPolicy::StrictOriginWhenCrossOrigin,
+        ReferrerPolicy::UnsafeUrl,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReferrerPolicy::NoReferrer => "no-referrer",
+            ReferrerPolicy::NoReferrerWhenDowngrade => "no-referrer-when-downgrade",
+            ReferrerPolicy::Origin => "origin",
+            ReferrerPolicy::OriginWhenCrossOrigin => "origin-when-cross-origin",
+            ReferrerPolicy::SameOrigin => "same-origin",
+            ReferrerPolicy::StrictOrigin => "strict-origin",
+            ReferrerPolicy::StrictOriginWhenCrossOrigin => "strict-origin-when-cross-origin",
+            ReferrerPolicy::UnsafeUrl => "unsafe-url",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(raw.trim()))
+    }
+}
+
+/// The `[privacy]` table of `frontier.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    pub referrer_policy: ReferrerPolicy,
+    /// Present the reduced user agent, which names no real platform or version.
+    pub reduce_user_agent: bool,
+    /// Send no cookies, credentials or `Referer` to origins other than the page's.
+    pub strip_cross_origin_headers: bool,
+}
+
+fn settings() -> &'static RwLock<PrivacySettings> {
+    static SETTINGS: OnceLock<RwLock<PrivacySettings>> = OnceLock::new();
+    SETTINGS.get_or_init(|| {
+        let settings = Preferences::load_default()
+            .map(|prefs| prefs.privacy)
+            .unwrap_or_else(|err| {
+                warn!(target = "privacy", error = %err, "using default privacy settings");
+                PrivacySettings::default()
+            });
+        RwLock::new(settings)
+    })
+}
+
+pub fn current() -> PrivacySettings {
+    *settings().read().unwrap()
+}
+
+/// Use `privacy` for every request from now on.
+pub fn configure(privacy: PrivacySettings) {
+    *settings().write().unwrap() = privacy;
+}
+
+/// Finish the headers of a request for `url`. `initiator` is the page the request is made
+/// for, or `None` when the browser makes it on its own behalf.
+pub fn apply(headers: &mut HeaderMap, url: &Url, initiator: Option<&Url>) {
+    apply_as(headers, url, initiator, None);
+}
+
+/// [`apply`] for a window that emulates another device, whose `emulated` user agent is sent
+/// in place of [`user_agent::current`].
+pub fn apply_as(
+    headers: &mut HeaderMap,
+    url: &Url,
+    initiator: Option<&Url>,
+    emulated: Option<&str>,
+) {
+    if !headers.contains_key(USER_AGENT) {
+        if let Ok(value) = HeaderValue::from_str(&user_agent::resolve(emulated)) {
+            headers.insert(USER_AGENT, value);
+        }
+    }
+    apply_with(&current(), headers, url, initiator);
+}
+
+fn apply_with(
+    privacy: &PrivacySettings,
+    headers: &mut HeaderMap,
+    url: &Url,
+    initiator: Option<&Url>,
+) {
+    headers.remove(REFERER);
+    let Some(page) = initiator else {
+        return;
+    };
+    if privacy.strip_cross_origin_headers && page.origin() != url.origin() {
+        for name in [COOKIE, AUTHORIZATION, FROM, REFERER] {
+            headers.remove(name);
+        }
+        return;
+    }
+    let referrer = referrer(privacy.referrer_policy, page, url)
+        .and_then(|referrer| HeaderValue::from_str(&referrer).ok());
+    if let Some(referrer) = referrer {
+        headers.insert(REFERER, referrer);
+    }
+}
+
+/// The `Referer` a request from `page` to `target` carries under `policy`, if any. Only
+/// http(s) pages send one, without credentials or fragment.
+pub fn referrer(policy: ReferrerPolicy, page: &Url, target: &Url) -> Option<String> {
+    if !matches!(page.scheme(), "http" | "https") {
+        return None;
+    }
+    let mut full = page.clone();
+    let _ = full.set_username("");
+    let _ = full.set_password(None);
+    full.set_fragment(None);
+    let origin = format!("{}/", page.origin().ascii_serialization());
+    let full = if full.as_str().len() > MAX_REFERRER_LEN {
+        origin.clone()
+    } else {
+        full.to_string()
+    };
+    let same_origin = page.origin() == target.origin();
+    let downgrade = page.scheme() == "https" && target.scheme() != "https";
+
+    match policy {
+        ReferrerPolicy::NoReferrer => None,
+        ReferrerPolicy::NoReferrerWhenDowngrade => (!downgrade).then_some(full),
+        ReferrerPolicy::Origin => Some(origin),
+        ReferrerPolicy::OriginWhenCrossOrigin if same_origin => Some(full),
+        ReferrerPolicy::OriginWhenCrossOrigin => Some(origin),
+        ReferrerPolicy::SameOrigin => same_origin.then_some(full),
+        ReferrerPolicy::StrictOrigin => (!downgrade).then_some(origin),
+        ReferrerPolicy::StrictOriginWhenCrossOrigin if same_origin => Some(full),
+        ReferrerPolicy::StrictOriginWhenCrossOrigin => (!downgrade).then_some(origin),
+        ReferrerPolicy::UnsafeUrl => Some(full),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn referrers_follow_the_policy() {
+        let page = url("https://user:pw@news.example/story?id=7#comments");
+        let same = url("https://news.example/img.png");
+        let other = url("https://cdn.example/app.js");
+        let insecure = url("http://cdn.example/app.js");
+        let full = "https://news.example/story?id=7";
+        let origin = "https://news.example/";
+
+        let sent = |policy, target| referrer(policy, &page, target);
+        let default = ReferrerPolicy::default();
+        assert_eq!(sent(default, &same).as_deref(), Some(full));
+        assert_eq!(sent(default, &other).as_deref(), Some(origin));
+        assert_eq!(sent(default, &insecure), None);
+        assert_eq!(sent(ReferrerPolicy::NoReferrer, &same), None);
+        assert_eq!(sent(ReferrerPolicy::SameOrigin, &other), None);
+        assert_eq!(sent(ReferrerPolicy::Origin, &same).as_deref(), Some(origin));
+        assert_eq!(
+            sent(ReferrerPolicy::UnsafeUrl, &insecure).as_deref(),
+            Some(full)
+        );
+        assert_eq!(
+            referrer(default, &url("file:///tmp/a.html"), &same),
+            None,
+            "local pages never leak their path"
+        );
+
+        assert_eq!(
+            ReferrerPolicy::parse("No-Referrer"),
+            Some(ReferrerPolicy::NoReferrer)
+        );
+        assert_eq!(ReferrerPolicy::parse("sometimes"), None);
+    }
+
+    #[test]
+    fn cross_origin_requests_lose_identifying_headers_when_asked() {
+        let page = url("https://news.example/story");
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("session=1"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+        headers.insert(
+            REFERER,
+            HeaderValue::from_static("https://spoofed.example/"),
+        );
+
+        let mut privacy = PrivacySettings::default();
+        let mut same = headers.clone();
+        apply_with(
+            &privacy,
+            &mut same,
+            &url("https://news.example/a"),
+            Some(&page),
+        );
+        assert_eq!(same[REFERER], "https://news.example/story");
+        assert_eq!(same[COOKIE], "session=1");
+
+        privacy.strip_cross_origin_headers = true;
+        let mut cross = headers.clone();
+        apply_with(
+            &privacy,
+            &mut cross,
+            &url("https://ads.example/"),
+            Some(&page),
+        );
+        assert!(cross.is_empty());
+
+        let mut own = headers;
+        apply_with(&privacy, &mut own, &url("https://ads.example/"), None);
+        assert!(!own.contains_key(REFERER));
+        assert_eq!(own[AUTHORIZATION], "Bearer t");
+    }
+}
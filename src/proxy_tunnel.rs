@@ -0,0 +1,407 @@
+//! Relay WebSockets through the configured proxy.
+//!
+//! The nostr relay connector only speaks SOCKS5, and takes one proxy address for every relay
+//! of a client. [`relay_proxy`] therefore starts a small SOCKS5 listener on loopback in front
+//! of the configured proxy: each connection it accepts is opened with [`connect`], which
+//! reaches hosts on the `FRONTIER_NO_PROXY` list directly, tunnels through an `http://` proxy
+//! with CONNECT, and hands everything else to a SOCKS5 proxy. `socks5h://` proxies resolve the
+//! relay's hostname themselves; for `socks5://` it is resolved locally first.
+//!
+//! An `https://` proxy would need TLS to the proxy itself, which the tunnel does not do, so
+//! relay connections fail with an error saying so.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+use crate::proxy::{self, ProxyConfig};
+
+/// Longest CONNECT response head we read before giving up on the proxy.
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+/// Address of the loopback SOCKS5 listener relay clients should use, or `None` to connect
+/// directly because no proxy is configured.
+pub fn relay_proxy() -> Result<Option<SocketAddr>> {
+    let Some(config) = proxy::current() else {
+        return Ok(None);
+    };
+    static LISTENER: OnceLock<Result<SocketAddr, String>> = OnceLock::new();
+    LISTENER
+        .get_or_init(|| start(config.clone()).map_err(|err| format!("{err:#}")))
+        .clone()
+        .map(Some)
+        .map_err(|err| anyhow!("unable to start the relay proxy listener: {err}"))
+}
+
+/// Open a TCP connection to `host:port` the way relay traffic should travel.
+pub async fn connect(host: &str, port: u16) -> Result<TcpStream> {
+    open(proxy::current(), host, port).await
+}
+
+/// Run the SOCKS5 listener for `config` on a thread and runtime of its own, so it outlives
+/// whichever runtime first asked for it.
+fn start(config: ProxyConfig) -> Result<SocketAddr> {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .context("unable to listen on loopback")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("unable to start the relay proxy runtime")?;
+    std::thread::Builder::new()
+        .name("relay-proxy".into())
+        .spawn(move || {
+            runtime.block_on(async move {
+                match TcpListener::from_std(listener) {
+                    Ok(listener) => accept_loop(listener, config).await,
+                    Err(err) => {
+                        warn!(target = "proxy", error = %err, "relay proxy listener failed")
+                    }
+                }
+            })
+        })
+        .context("unable to spawn the relay proxy thread")?;
+    debug!(target = "proxy", %addr, "relay proxy listening");
+    Ok(addr)
+}
+
+async fn accept_loop(listener: TcpListener, config: ProxyConfig) {
+    loop {
+        let (client, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!(target = "proxy", error = %err, "relay proxy accept failed");
+                continue;
+            }
+        };
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve(client, &config).await {
+                debug!(target = "proxy", error = %err, "relay tunnel closed");
+            }
+        });
+    }
+}
+
+/// Answer one SOCKS5 CONNECT from a relay client and splice it onto the upstream connection.
+async fn serve(mut client: TcpStream, config: &ProxyConfig) -> Result<()> {
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await?;
+    if greeting[0] != 5 {
+        bail!("not a SOCKS5 client");
+    }
+    let mut methods = vec![0u8; greeting[1] as usize];
+    client.read_exact(&mut methods).await?;
+    if !methods.contains(&0) {
+        client.write_all(&[5, 0xff]).await?;
+        bail!("client offered no unauthenticated method");
+    }
+    client.write_all(&[5, 0]).await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    if request[1] != 1 {
+        reply(&mut client, 7).await?;
+        bail!("only CONNECT is supported");
+    }
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        3 => {
+            let mut len = [0u8; 1];
+            client.read_exact(&mut len).await?;
+            let mut name = vec![0u8; len[0] as usize];
+            client.read_exact(&mut name).await?;
+            String::from_utf8(name).context("hostname is not UTF-8")?
+        }
+        4 => {
+            let mut ip = [0u8; 16];
+            client.read_exact(&mut ip).await?;
+            Ipv6Addr::from(ip).to_string()
+        }
+        _ => {
+            reply(&mut client, 8).await?;
+            bail!("unknown address type {}", request[3]);
+        }
+    };
+    let mut port = [0u8; 2];
+    client.read_exact(&mut port).await?;
+    let port = u16::from_be_bytes(port);
+
+    let mut upstream = match open(Some(config), &host, port).await {
+        Ok(upstream) => upstream,
+        Err(err) => {
+            reply(&mut client, 5).await?;
+            return Err(err);
+        }
+    };
+    reply(&mut client, 0).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+async fn reply(client: &mut TcpStream, code: u8) -> Result<()> {
+    client.write_all(&[5, code, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    Ok(())
+}
+
+async fn open(config: Option<&ProxyConfig>, host: &str, port: u16) -> Result<TcpStream> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let config = match config {
+        Some(config) if !config.bypasses(host) => config,
+        _ => {
+            return TcpStream::connect((host, port))
+                .await
+                .with_context(|| format!("unable to connect to {host}:{port}"));
+        }
+    };
+    let scheme = config.url.scheme();
+    if !matches!(scheme, "http" | "socks5" | "socks5h") {
+        bail!(
+            "relay connections cannot go through an {scheme}:// proxy; use http:// or socks5h://"
+        );
+    }
+    let (proxy_host, proxy_port) = config.endpoint();
+    let mut stream = TcpStream::connect((proxy_host.as_str(), proxy_port))
+        .await
+        .with_context(|| format!("unable to reach proxy {proxy_host}:{proxy_port}"))?;
+    match scheme {
+        "http" => http_connect(&mut stream, host, port, config.credentials()).await?,
+        "socks5" => {
+            let resolved = tokio::net::lookup_host((host, port))
+                .await
+                .with_context(|| format!("unable to resolve {host}"))?
+                .next()
+                .ok_or_else(|| anyhow!("{host} did not resolve"))?;
+            let ip = resolved.ip().to_string();
+            socks_connect(&mut stream, &ip, port, config.credentials()).await?
+        }
+        _ => socks_connect(&mut stream, host, port, config.credentials()).await?,
+    }
+    Ok(stream)
+}
+
+/// Ask an HTTP proxy to open a tunnel to `host:port`.
+async fn http_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let authority = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some((user, password)) = credentials {
+        let token = BASE64.encode(format!("{user}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {token}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Byte at a time, so nothing past the head is taken from the tunnel.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            bail!("proxy sent an oversized CONNECT response");
+        }
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            bail!("proxy closed the connection during CONNECT");
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status = head.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => bail!("proxy refused CONNECT to {authority}: {status}"),
+    }
+}
+
+/// Ask a SOCKS5 proxy to connect to `host:port`, by name unless `host` is an IP address.
+async fn socks_connect<S>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<(String, String)>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let method = if credentials.is_some() { 2 } else { 0 };
+    stream.write_all(&[5, 1, method]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [5, method] {
+        bail!("SOCKS proxy rejected the authentication method");
+    }
+    if let Some((user, password)) = credentials {
+        let (user, password) = (user.as_bytes(), password.as_bytes());
+        if user.len() > 255 || password.len() > 255 {
+            bail!("SOCKS credentials are too long");
+        }
+        let mut auth = vec![1, user.len() as u8];
+        auth.extend_from_slice(user);
+        auth.push(password.len() as u8);
+        auth.extend_from_slice(password);
+        stream.write_all(&auth).await?;
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).await?;
+        if status[1] != 0 {
+            bail!("SOCKS proxy rejected the credentials");
+        }
+    }
+
+    let mut request = vec![5, 1, 0];
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        request.push(1);
+        request.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        request.push(4);
+        request.extend_from_slice(&ip.octets());
+    } else {
+        if host.len() > 255 {
+            bail!("hostname {host} is too long for SOCKS");
+        }
+        request.push(3);
+        request.push(host.len() as u8);
+        request.extend_from_slice(host.as_bytes());
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        bail!("SOCKS proxy refused {host}:{port} (code {})", head[1]);
+    }
+    let bound = match head[3] {
+        1 => 4 + 2,
+        4 => 16 + 2,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize + 2
+        }
+        other => bail!("SOCKS proxy sent unknown address type {other}"),
+    };
+    let mut skip = vec![0u8; bound];
+    stream.read_exact(&mut skip).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server that echoes one line back.
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    let read = socket.read(&mut buf).await.unwrap();
+                    socket.write_all(&buf[..read]).await.unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    /// An HTTP proxy that only answers CONNECT, recording the request heads it saw.
+    async fn connect_proxy() -> (SocketAddr, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (seen, requests) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0u8; 1];
+                        socket.read_exact(&mut byte).await.unwrap();
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8(head).unwrap();
+                    let target = head.split_whitespace().nth(1).unwrap().to_string();
+                    seen.send(head).unwrap();
+                    let mut upstream = TcpStream::connect(target).await.unwrap();
+                    socket
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .await
+                        .unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut socket, &mut upstream).await;
+                });
+            }
+        });
+        (addr, requests)
+    }
+
+    async fn round_trip(mut stream: TcpStream) -> Vec<u8> {
+        stream.write_all(b"ping").await.unwrap();
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await.unwrap();
+        reply.to_vec()
+    }
+
+    #[tokio::test]
+    async fn socks_clients_tunnel_through_an_http_proxy() {
+        let target = echo_server().await;
+        let (proxy, mut requests) = connect_proxy().await;
+        let config = ProxyConfig::parse(&format!("http://relay:s3cret@{proxy}"), None).unwrap();
+        let bridge = start(config).unwrap();
+
+        let mut client = TcpStream::connect(bridge).await.unwrap();
+        socks_connect(&mut client, "127.0.0.1", target.port(), None)
+            .await
+            .unwrap();
+        assert_eq!(round_trip(client).await, b"ping");
+
+        let head = requests.recv().await.unwrap();
+        assert!(head.starts_with(&format!("CONNECT 127.0.0.1:{} HTTP/1.1", target.port())));
+        assert!(head.contains(&format!(
+            "Proxy-Authorization: Basic {}",
+            BASE64.encode("relay:s3cret")
+        )));
+    }
+
+    #[tokio::test]
+    async fn bypassed_hosts_skip_the_proxy() {
+        let target = echo_server().await;
+        let (proxy, mut requests) = connect_proxy().await;
+        let config =
+            ProxyConfig::parse(&format!("http://{proxy}"), Some("127.0.0.1".into())).unwrap();
+
+        let stream = open(Some(&config), "127.0.0.1", target.port())
+            .await
+            .unwrap();
+        assert_eq!(round_trip(stream).await, b"ping");
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn https_proxies_are_refused_for_relays() {
+        let config = ProxyConfig::parse("https://127.0.0.1:1", None).unwrap();
+        let err = open(Some(&config), "relay.example", 443).await.unwrap_err();
+        assert!(err.to_string().contains("https:// proxy"), "{err}");
+    }
+}
@@ -0,0 +1,443 @@
+//! `frontier publish`: upload a site directory to Blossom servers and announce it on Nostr.
+//!
+//! Each file becomes a Blossom blob (BUD-01/BUD-02 `PUT /upload`, authorised with a kind-24242
+//! event) plus a kind-34128 manifest event mapping its path to the blob hash. A kind-34256
+//! service event ties the name to the root document, the Blossom servers and, optionally, the
+//! site's TLS key so the read path can pin it. Events no relay accepted are kept in the
+//! [`publish_queue`](crate::publish_queue) and sent again later.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use nostr_sdk::prelude::{
+    ClientBuilder, Event, EventBuilder, Keys, Kind, Options, Tag, Timestamp, ToBech32,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::dev_server::mime_type_for;
+use crate::identities::IdentityStore;
+use crate::preferences::Preferences;
+use crate::privacy;
+
+pub const MANIFEST_KIND: u16 = 34128;
+pub const SERVICE_KIND: u16 = 34256;
+pub const BLOSSOM_AUTH_KIND: u16 = 24242;
+
+const AUTH_EXPIRATION: Duration = Duration::from_secs(300);
+const INDEX_FILE: &str = "index.html";
+
+#[derive(Debug, Clone)]
+pub struct PublishOptions {
+    pub site_dir: PathBuf,
+    pub name: String,
+    pub servers: Vec<Url>,
+    pub relays: Vec<Url>,
+    pub tls_cert: Option<PathBuf>,
+}
+
+impl PublishOptions {
+    /// Parse `<dir> --name <name> --server <url>... [--relay <url>...] [--tls-cert <pem>]`.
+    /// Relays fall back to `FRONTIER_RELAY_CONFIG` when none are given.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut site_dir = None;
+        let mut name = None;
+        let mut servers = Vec::new();
+        let mut relays = Vec::new();
+        let mut tls_cert = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{arg} expects a value"))
+            };
+            match arg.as_str() {
+                "--name" => name = Some(value()?),
+                "--server" => servers.push(Url::parse(&value()?).context("invalid --server")?),
+                "--relay" => relays.push(Url::parse(&value()?).context("invalid --relay")?),
+                "--tls-cert" => tls_cert = Some(PathBuf::from(value()?)),
+                flag if flag.starts_with("--") => bail!("unknown option {flag}"),
+                path if site_dir.is_none() => site_dir = Some(PathBuf::from(path)),
+                extra => bail!("unexpected argument {extra}"),
+            }
+        }
+
+        if relays.is_empty() {
+            relays = relays_from_env()?;
+        }
+
+        Ok(Self {
+            site_dir: site_dir.ok_or_else(|| {
+                anyhow!("usage: frontier publish <dir> --name <name> --server <url>")
+            })?,
+            name: name.ok_or_else(|| anyhow!("--name is required"))?,
+            servers,
+            relays,
+            tls_cert,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SiteFile {
+    /// Absolute request path, e.g. `/css/site.css`.
+    pub path: String,
+    pub sha256: String,
+    pub bytes: Vec<u8>,
+    pub mime_type: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct PublishReport {
+    pub name: String,
+    pub author: String,
+    pub root_hash: String,
+    pub files: usize,
+    pub events: Vec<Event>,
+    /// Events left in the publish queue because no relay took them.
+    pub queued: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlobDescriptor {
+    sha256: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RelayConfig {
+    relays: Vec<Url>,
+}
+
+/// Relays listed in `FRONTIER_RELAY_CONFIG`, the same YAML file the browser reads, falling
+/// back to the list saved from `frontier://settings`.
+pub fn relays_from_env() -> Result<Vec<Url>> {
+    let Ok(path) = std::env::var("FRONTIER_RELAY_CONFIG") else {
+        return Ok(Preferences::load_default()?.relays);
+    };
+    let raw = std::fs::read_to_string(&path)
+        .with_context(|| format!("unable to read relay config {path}"))?;
+    let config: RelayConfig =
+        serde_yaml::from_str(&raw).with_context(|| format!("invalid relay config {path}"))?;
+    Ok(config.relays)
+}
+
+/// Signing keys from `FRONTIER_SECRET_KEY` (nsec or hex), or else the default identity from
+/// `frontier://identities` unlocked with `FRONTIER_IDENTITY_PASSWORD`.
+pub fn keys_from_env() -> Result<Keys> {
+    if let Ok(secret) = std::env::var("FRONTIER_SECRET_KEY") {
+        return Keys::parse(secret.trim())
+            .map_err(|err| anyhow!("invalid FRONTIER_SECRET_KEY: {err}"));
+    }
+    let identities = IdentityStore::load_default()?;
+    let npub = identities.default.as_deref().context(
+        "set FRONTIER_SECRET_KEY to an nsec or hex secret key, or add an identity in frontier://identities",
+    )?;
+    let password = std::env::var("FRONTIER_IDENTITY_PASSWORD")
+        .context("FRONTIER_IDENTITY_PASSWORD must be set to unlock the default identity")?;
+    identities.unlock(npub, &password)
+}
+
+pub async fn publish_site(options: &PublishOptions, keys: &Keys) -> Result<PublishReport> {
+    if options.servers.is_empty() {
+        bail!("at least one Blossom server is required");
+    }
+    if options.relays.is_empty() {
+        bail!("at least one relay is required");
+    }
+
+    let files = collect_site_files(&options.site_dir)?;
+    let root_hash = files
+        .iter()
+        .find(|file| file.path == format!("/{INDEX_FILE}"))
+        .map(|file| file.sha256.clone())
+        .ok_or_else(|| anyhow!("{} has no {INDEX_FILE}", options.site_dir.display()))?;
+
+    let http = crate::http_client::shared()?;
+    for file in &files {
+        for server in &options.servers {
+            upload_blob(&http, server, keys, file)
+                .await
+                .with_context(|| format!("uploading {} to {server}", file.path))?;
+        }
+        info!(target = "publish", path = %file.path, hash = %file.sha256, "uploaded");
+    }
+
+    let tls_key = options
+        .tls_cert
+        .as_deref()
+        .map(tls_key_fingerprint)
+        .transpose()?;
+
+    let mut events = files
+        .iter()
+        .map(|file| manifest_event(keys, file))
+        .collect::<Result<Vec<_>>>()?;
+    events.push(service_event(
+        keys,
+        &options.name,
+        &root_hash,
+        &options.servers,
+        tls_key.as_deref(),
+    )?);
+
+    let unsent = match broadcast(&options.relays, &events).await {
+        Ok(unsent) => unsent,
+        Err(err) => {
+            warn!(target = "publish", error = %err, "relays unreachable");
+            events.clone()
+        }
+    };
+    let queued = unsent.len();
+    if !unsent.is_empty() {
+        crate::publish_queue::enqueue(&options.name, &options.relays, unsent)?;
+    }
+
+    Ok(PublishReport {
+        name: options.name.clone(),
+        author: keys.public_key().to_bech32()?,
+        root_hash,
+        files: files.len(),
+        events,
+        queued,
+    })
+}
+
+/// Walk `root` and hash every non-hidden file.
+pub fn collect_site_files(root: &Path) -> Result<Vec<SiteFile>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("unable to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+                continue;
+            }
+
+            let relative = path.strip_prefix(root)?;
+            let request_path = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("unable to read {}", path.display()))?;
+            files.push(SiteFile {
+                path: format!("/{request_path}"),
+                sha256: sha256_hex(&bytes),
+                mime_type: mime_type_for(&path),
+                bytes,
+            });
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+pub(crate) async fn upload_blob(
+    http: &reqwest::Client,
+    server: &Url,
+    keys: &Keys,
+    file: &SiteFile,
+) -> Result<()> {
+    let endpoint = server.join("upload")?;
+    let auth = upload_auth_event(keys, file)?;
+    let header = format!("Nostr {}", BASE64.encode(serde_json::to_vec(&auth)?));
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    privacy::apply(&mut headers, &endpoint, None);
+    let response = http
+        .put(endpoint)
+        .headers(headers)
+        .header(reqwest::header::AUTHORIZATION, header)
+        .header(reqwest::header::CONTENT_TYPE, file.mime_type)
+        .body(file.bytes.clone())
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        bail!(
+            "server responded {status}: {}",
+            String::from_utf8_lossy(&body).trim()
+        );
+    }
+
+    let descriptor: BlobDescriptor =
+        serde_json::from_slice(&body).context("server returned an invalid blob descriptor")?;
+    if descriptor.sha256 != file.sha256 {
+        bail!(
+            "server stored hash {} but {} was expected",
+            descriptor.sha256,
+            file.sha256
+        );
+    }
+    Ok(())
+}
+
+/// BUD-01 authorisation event scoped to a single upload.
+pub fn upload_auth_event(keys: &Keys, file: &SiteFile) -> Result<Event> {
+    let expiration = Timestamp::now() + AUTH_EXPIRATION;
+    let tags = vec![
+        Tag::parse(&["t", "upload"])?,
+        Tag::parse(&["x", file.sha256.as_str()])?,
+        Tag::expiration(expiration),
+    ];
+    Ok(EventBuilder::new(
+        Kind::from(BLOSSOM_AUTH_KIND),
+        format!("Upload {}", file.path),
+        tags,
+    )
+    .to_event(keys)?)
+}
+
+pub fn manifest_event(keys: &Keys, file: &SiteFile) -> Result<Event> {
+    let tags = vec![
+        Tag::identifier(&file.path),
+        Tag::parse(&["sha256", file.sha256.as_str()])?,
+        Tag::parse(&["m", file.mime_type])?,
+    ];
+    Ok(EventBuilder::new(Kind::from(MANIFEST_KIND), "", tags).to_event(keys)?)
+}
+
+pub fn service_event(
+    keys: &Keys,
+    name: &str,
+    root_hash: &str,
+    servers: &[Url],
+    tls_key: Option<&str>,
+) -> Result<Event> {
+    let mut tags = vec![Tag::identifier(name), Tag::parse(&["blossom", root_hash])?];
+    for server in servers {
+        tags.push(Tag::parse(&["server", server.as_str()])?);
+    }
+    if let Some(key) = tls_key {
+        tags.push(Tag::parse(&["tls", key])?);
+    }
+    Ok(EventBuilder::new(Kind::from(SERVICE_KIND), "", tags).to_event(keys)?)
+}
+
+/// Send signed `events` to `relays`, returning the events no relay accepted.
+pub(crate) async fn broadcast(relays: &[Url], events: &[Event]) -> Result<Vec<Event>> {
+    let opts = Options::new().proxy(crate::proxy_tunnel::relay_proxy()?);
+    let client = ClientBuilder::new().opts(opts).build();
+    for relay in relays {
+        client.add_relay(relay.as_str()).await?;
+    }
+    client.connect().await;
+
+    let mut unsent = Vec::new();
+    for event in events {
+        if let Err(err) = client.send_event(event.clone()).await {
+            warn!(target = "publish", id = %event.id(), error = %err, "relay rejected event");
+            unsent.push(event.clone());
+        }
+    }
+    let _ = client.disconnect().await;
+    Ok(unsent)
+}
+
+/// SHA-256 of the certificate's SubjectPublicKeyInfo, hex encoded.
+pub fn tls_key_fingerprint(cert_path: &Path) -> Result<String> {
+    let pem = std::fs::read(cert_path)
+        .with_context(|| format!("unable to read {}", cert_path.display()))?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem)
+        .map_err(|err| anyhow!("invalid PEM in {}: {err}", cert_path.display()))?;
+    let cert = pem
+        .parse_x509()
+        .map_err(|err| anyhow!("invalid certificate in {}: {err}", cert_path.display()))?;
+    Ok(sha256_hex(cert.public_key().raw))
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cli_arguments() {
+        let args: Vec<String> = [
+            "site",
+            "--name",
+            "mysite",
+            "--server",
+            "https://blossom.example",
+            "--relay",
+            "wss://relay.example",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        let options = PublishOptions::from_args(&args).unwrap();
+        assert_eq!(options.site_dir, PathBuf::from("site"));
+        assert_eq!(options.name, "mysite");
+        assert_eq!(options.servers.len(), 1);
+        assert_eq!(options.relays.len(), 1);
+
+        assert!(PublishOptions::from_args(&["site".to_string()]).is_err());
+    }
+
+    #[test]
+    fn collects_nested_files_with_request_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>hi</h1>").unwrap();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("css/site.css"), "h1{}").unwrap();
+        std::fs::write(dir.path().join(".DS_Store"), "junk").unwrap();
+
+        let files = collect_site_files(dir.path()).unwrap();
+        let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["/css/site.css", "/index.html"]);
+        assert_eq!(files[1].sha256, sha256_hex(b"<h1>hi</h1>"));
+        assert_eq!(files[0].mime_type, "text/css; charset=utf-8");
+    }
+
+    #[test]
+    fn service_event_carries_servers_and_tls_key() {
+        let keys = Keys::generate();
+        let servers = vec![Url::parse("https://blossom.example/").unwrap()];
+        let event = service_event(&keys, "mysite", "abcd", &servers, Some("beef")).unwrap();
+        assert_eq!(event.kind(), Kind::from(SERVICE_KIND));
+        let tags: Vec<Vec<String>> = event.tags().iter().map(|tag| tag.as_vec()).collect();
+        assert!(tags.contains(&vec!["d".to_string(), "mysite".to_string()]));
+        assert!(tags.contains(&vec!["blossom".to_string(), "abcd".to_string()]));
+        assert!(tags.contains(&vec![
+            "server".to_string(),
+            "https://blossom.example/".to_string()
+        ]));
+        assert!(tags.contains(&vec!["tls".to_string(), "beef".to_string()]));
+    }
+
+    #[test]
+    fn upload_auth_is_scoped_to_blob_hash() {
+        let keys = Keys::generate();
+        let file = SiteFile {
+            path: "/index.html".into(),
+            sha256: sha256_hex(b"x"),
+            bytes: b"x".to_vec(),
+            mime_type: "text/html; charset=utf-8",
+        };
+        let event = upload_auth_event(&keys, &file).unwrap();
+        assert_eq!(event.kind(), Kind::from(BLOSSOM_AUTH_KIND));
+        let tags: Vec<Vec<String>> = event.tags().iter().map(|tag| tag.as_vec()).collect();
+        assert!(tags.contains(&vec!["x".to_string(), file.sha256.clone()]));
+        assert!(tags.iter().any(|tag| tag[0] == "expiration"));
+    }
+}
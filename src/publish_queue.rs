@@ -0,0 +1,313 @@
+//! Signed publish events waiting for a relay to take them.
+//!
+//! When `frontier publish` cannot reach its relays, the already signed manifest and service
+//! events are written to `publish-queue.json` beside `frontier.toml` instead of being dropped.
+//! Every browser launch and every `frontier publish` run retries the batches whose backoff has
+//! passed (the browser once a page needs relays, or shortly after startup);
+//! `frontier publish-queue [--retry]` and `frontier://publish-queue` show what is still waiting.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use html_escape::encode_text;
+use nostr_sdk::prelude::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::page_cache::format_timestamp;
+use crate::storage_policy::StoragePolicy;
+
+pub const PUBLISH_QUEUE_URL: &str = "frontier://publish-queue";
+const FILE_NAME: &str = "publish-queue.json";
+/// Wait before the first retry; doubles with every failed attempt.
+const FIRST_RETRY: Duration = Duration::from_secs(60);
+const MAX_RETRY: Duration = Duration::from_secs(6 * 60 * 60);
+/// How long the browser holds retries back so relay connections do not compete with the
+/// first page load.
+pub const DEFERRED_FLUSH_DELAY: Duration = Duration::from_secs(10);
+
+/// Events from one `frontier publish` run, with the relays they were meant for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedBatch {
+    pub name: String,
+    pub relays: Vec<Url>,
+    pub events: Vec<Event>,
+    /// Seconds since the Unix epoch.
+    pub queued_at: u64,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PublishQueue {
+    pub batches: Vec<QueuedBatch>,
+}
+
+/// Outcome of [`flush`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushReport {
+    pub sent: usize,
+    pub waiting: usize,
+}
+
+impl PublishQueue {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::preferences::data_file(FILE_NAME)
+    }
+
+    /// Read the queue from `path`; a missing file is an empty queue.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(raw) => serde_json::from_str(&raw)
+                .with_context(|| format!("invalid publish queue {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("unable to read publish queue {}", path.display()))
+            }
+        }
+    }
+
+    /// Write the queue through a temporary file, so a crash mid-write keeps the old queue.
+    /// Does nothing in a private session.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !StoragePolicy::current().persists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let raw = serde_json::to_string_pretty(self).context("serialize publish queue")?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, raw)
+            .with_context(|| format!("unable to write publish queue {}", temp.display()))?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("unable to replace publish queue {}", path.display()))
+    }
+
+    pub fn push(&mut self, name: &str, relays: &[Url], events: Vec<Event>, now: u64) {
+        self.batches.push(QueuedBatch {
+            name: name.to_string(),
+            relays: relays.to_vec(),
+            events,
+            queued_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+}
+
+impl QueuedBatch {
+    /// Record a failed attempt at `now`, keeping `unsent` for the next one.
+    fn retry_later(&mut self, unsent: Vec<Event>, error: String, now: u64) {
+        self.attempts += 1;
+        let delay = FIRST_RETRY
+            .saturating_mul(1u32 << self.attempts.saturating_sub(1).min(16))
+            .min(MAX_RETRY);
+        self.next_attempt_at = now + delay.as_secs();
+        self.events = unsent;
+        self.last_error = Some(error);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Add `events` to the queue file for the next retry.
+pub fn enqueue(name: &str, relays: &[Url], events: Vec<Event>) -> Result<()> {
+    let path = PublishQueue::default_path()
+        .context("no data directory to keep unpublished events in; set FRONTIER_DATA_DIR")?;
+    let mut queue = PublishQueue::load(&path)?;
+    queue.push(name, relays, events, unix_now());
+    queue.save(&path)?;
+    info!(target = "publish", path = %path.display(), "queued unpublished events");
+    Ok(())
+}
+
+/// Send the batches that are due (all of them with `force`) and write back what is left.
+pub async fn flush(force: bool) -> Result<FlushReport> {
+    let Some(path) = PublishQueue::default_path() else {
+        return Ok(FlushReport::default());
+    };
+    let mut queue = PublishQueue::load(&path)?;
+    if queue.is_empty() {
+        return Ok(FlushReport::default());
+    }
+    let now = unix_now();
+    let mut report = FlushReport::default();
+    for batch in &mut queue.batches {
+        if !force && batch.next_attempt_at > now {
+            continue;
+        }
+        let total = batch.events.len();
+        match crate::publish::broadcast(&batch.relays, &batch.events).await {
+            Ok(unsent) if unsent.is_empty() => {
+                report.sent += total;
+                batch.events.clear();
+            }
+            Ok(unsent) => {
+                report.sent += total - unsent.len();
+                let error = format!("{} of {total} events rejected", unsent.len());
+                batch.retry_later(unsent, error, now);
+            }
+            Err(err) => {
+                let events = std::mem::take(&mut batch.events);
+                batch.retry_later(events, format!("{err:#}"), now);
+            }
+        }
+    }
+    queue.batches.retain(|batch| !batch.events.is_empty());
+    report.waiting = queue.batches.iter().map(|batch| batch.events.len()).sum();
+    queue.save(&path)?;
+    if report.sent > 0 {
+        info!(
+            target = "publish",
+            sent = report.sent,
+            waiting = report.waiting,
+            "flushed publish queue"
+        );
+    }
+    Ok(report)
+}
+
+/// Retry due batches in the background once the returned sender fires (or is dropped), or
+/// after `delay`, whichever comes first; failures only log.
+pub fn spawn_deferred_flush(
+    handle: &tokio::runtime::Handle,
+    delay: Duration,
+) -> oneshot::Sender<()> {
+    let (start, started) = oneshot::channel();
+    handle.spawn(async move {
+        let _ = tokio::time::timeout(delay, started).await;
+        if let Err(err) = flush(false).await {
+            warn!(target = "publish", error = %err, "failed to retry queued publishes");
+        }
+    });
+    start
+}
+
+/// Plain-text queue state for `frontier publish-queue`.
+pub fn describe(queue: &PublishQueue) -> String {
+    if queue.is_empty() {
+        return "Publish queue is empty.".to_string();
+    }
+    queue
+        .batches
+        .iter()
+        .map(|batch| {
+            format!(
+                "{name}: {events} events, queued {queued}, {attempts} attempts, next {next}{error}",
+                name = batch.name,
+                events = batch.events.len(),
+                queued = format_timestamp(batch.queued_at),
+                attempts = batch.attempts,
+                next = format_timestamp(batch.next_attempt_at),
+                error = batch
+                    .last_error
+                    .as_deref()
+                    .map(|error| format!(" ({error})"))
+                    .unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// HTML body of `frontier://publish-queue`.
+pub fn queue_page(queue: &Result<PublishQueue, String>, status: Option<&str>) -> String {
+    let rows = match queue {
+        Err(err) => format!("<tr><td colspan=\"6\">{}</td></tr>", encode_text(err)),
+        Ok(queue) if queue.is_empty() => {
+            "<tr><td colspan=\"6\">Nothing is waiting to be published.</td></tr>".to_string()
+        }
+        Ok(queue) => queue
+            .batches
+            .iter()
+            .map(|batch| {
+                let relays = batch
+                    .relays
+                    .iter()
+                    .map(Url::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "<tr><td>{name}</td><td>{events}</td><td>{queued}</td><td>{attempts}</td><td>{next}</td><td><code>{relays}</code><br>{error}</td></tr>",
+                    name = encode_text(&batch.name),
+                    events = batch.events.len(),
+                    queued = format_timestamp(batch.queued_at),
+                    attempts = batch.attempts,
+                    next = format_timestamp(batch.next_attempt_at),
+                    relays = encode_text(&relays),
+                    error = encode_text(batch.last_error.as_deref().unwrap_or("")),
+                )
+            })
+            .collect(),
+    };
+    let status = status
+        .map(|message| {
+            format!(
+                "<p class=\"publish-queue-status\" role=\"status\">{}</p>",
+                encode_text(message)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<section class="publish-queue">
+<h1>Publish queue</h1>
+{status}
+<table>
+<thead><tr><th>Name</th><th>Events</th><th>Queued</th><th>Attempts</th><th>Next attempt</th><th>Relays</th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+<p><a href="{PUBLISH_QUEUE_URL}?retry=now">Retry now</a></p>
+</section>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::{EventBuilder, Keys};
+
+    #[test]
+    fn queue_round_trips_and_backs_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        assert!(PublishQueue::load(&path).unwrap().is_empty());
+
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("hello", [])
+            .to_event(&keys)
+            .unwrap();
+        let relays = vec![Url::parse("wss://relay.example").unwrap()];
+        let mut queue = PublishQueue::default();
+        queue.push("mysite", &relays, vec![event.clone()], 1_000);
+        queue.save(&path).unwrap();
+        let mut loaded = PublishQueue::load(&path).unwrap();
+        assert_eq!(loaded, queue);
+
+        let batch = &mut loaded.batches[0];
+        batch.retry_later(vec![event.clone()], "offline".into(), 1_000);
+        assert_eq!(batch.next_attempt_at, 1_060);
+        batch.retry_later(vec![event.clone()], "offline".into(), 1_000);
+        assert_eq!(batch.next_attempt_at, 1_120);
+        for _ in 0..20 {
+            batch.retry_later(vec![event.clone()], "offline".into(), 1_000);
+        }
+        assert_eq!(batch.next_attempt_at, 1_000 + MAX_RETRY.as_secs());
+        assert!(describe(&loaded).contains("mysite: 1 events"));
+    }
+}
@@ -0,0 +1,329 @@
+//! The quick-open palette at `frontier://quick-open`: a fuzzy search over bookmarks and
+//! recently visited pages.
+//!
+//! Every page the window finishes loading is recorded with its title in `quick-open.json` in
+//! the data directory, next to the bookmarks that File > Bookmark This Page toggles. Typing in
+//! the palette matches the query's characters in order against titles and addresses, ranking
+//! matches at word starts and in runs higher, and bookmarks above history. The palette is what
+//! the global shortcut of background mode opens (see [`crate::background`]), so a page is a
+//! keystroke away without paying for a cold start.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use blitz_dom::{local_name, BaseDocument};
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use serde::{Deserialize, Serialize};
+
+use crate::storage_policy::StoragePolicy;
+
+pub const QUICK_OPEN_URL: &str = "frontier://quick-open";
+const FILE_NAME: &str = "quick-open.json";
+
+/// How many visited pages are remembered.
+const MAX_RECENT: usize = 200;
+/// How many matches the palette lists.
+const MAX_RESULTS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// The page's `<title>`, empty when it had none.
+    #[serde(default)]
+    pub title: String,
+}
+
+/// One palette row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<'a> {
+    pub entry: &'a Entry,
+    pub bookmarked: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuickOpenStore {
+    #[serde(default)]
+    bookmarks: Vec<Entry>,
+    /// Most recent first, each address once.
+    #[serde(default)]
+    recent: Vec<Entry>,
+}
+
+impl QuickOpenStore {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::preferences::data_file(FILE_NAME)
+    }
+
+    /// The store at `path`; empty when there is none or it cannot be read.
+    pub fn load(path: &Path) -> Self {
+        let Ok(bytes) = fs::read(path) else {
+            return Self::default();
+        };
+        serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            tracing::warn!(path = %path.display(), error = %err, "ignoring quick-open history");
+            Self::default()
+        })
+    }
+
+    /// Does nothing in a private session.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !StoragePolicy::current().persists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_vec_pretty(self).context("serialize quick-open history")?;
+        fs::write(path, json).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Move `url` to the top of the recent pages. Returns whether anything changed.
+    pub fn record_visit(&mut self, url: &str, title: &str) -> bool {
+        let entry = Entry {
+            url: url.to_string(),
+            title: title.trim().to_string(),
+        };
+        if self.recent.first() == Some(&entry) {
+            return false;
+        }
+        self.recent.retain(|recent| recent.url != url);
+        self.recent.insert(0, entry);
+        self.recent.truncate(MAX_RECENT);
+        true
+    }
+
+    pub fn is_bookmarked(&self, url: &str) -> bool {
+        self.bookmarks.iter().any(|bookmark| bookmark.url == url)
+    }
+
+    /// Bookmark `url`, or remove the bookmark it has. Returns whether it is now bookmarked.
+    pub fn toggle_bookmark(&mut self, url: &str, title: &str) -> bool {
+        if self.is_bookmarked(url) {
+            self.bookmarks.retain(|bookmark| bookmark.url != url);
+            return false;
+        }
+        self.bookmarks.push(Entry {
+            url: url.to_string(),
+            title: title.trim().to_string(),
+        });
+        true
+    }
+
+    /// Bookmarks and recent pages matching `query`, best first. An empty query lists the
+    /// bookmarks, then the recent pages.
+    pub fn search(&self, query: &str) -> Vec<Match<'_>> {
+        let query = query.trim().to_lowercase();
+        let recent = self
+            .recent
+            .iter()
+            .filter(|entry| !self.is_bookmarked(&entry.url));
+        let mut scored: Vec<(u32, Match<'_>)> = self
+            .bookmarks
+            .iter()
+            .map(|entry| (entry, true))
+            .chain(recent.map(|entry| (entry, false)))
+            .filter_map(|(entry, bookmarked)| {
+                let score = score(&query, &entry.title).max(score(&query, &entry.url))?;
+                let bonus = if bookmarked { BOOKMARK_BONUS } else { 0 };
+                Some((score + bonus, Match { entry, bookmarked }))
+            })
+            .collect();
+        // Stable, so equal scores keep bookmarks first and recent pages newest first.
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        scored
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|(_, found)| found)
+            .collect()
+    }
+}
+
+/// Ranks a bookmark above a recent page that matches about as well.
+const BOOKMARK_BONUS: u32 = 4;
+
+/// How well `text` matches `query` (lowercase): `None` unless the query's characters all
+/// appear in order, otherwise more for characters at the start of a word and for runs.
+fn score(query: &str, text: &str) -> Option<u32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut total = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&c| c == wanted)?;
+        total += 1;
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            total += 8;
+        }
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            total += 5;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(total)
+}
+
+/// The text of the document's `<title>`, if it has a non-empty one.
+pub fn document_title(doc: &BaseDocument) -> Option<String> {
+    let mut stack = vec![doc.root_node().id];
+    while let Some(node_id) = stack.pop() {
+        let Some(node) = doc.get_node(node_id) else {
+            continue;
+        };
+        if node
+            .element_data()
+            .is_some_and(|element| element.name.local == local_name!("title"))
+        {
+            let title = node
+                .text_content()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            return (!title.is_empty()).then_some(title);
+        }
+        stack.extend(node.children.iter().rev().copied());
+    }
+    None
+}
+
+/// The query of `frontier://quick-open?q=...`, or an empty one for the bare palette.
+pub fn query_from_input(input: &str) -> Option<String> {
+    let rest = input.trim().strip_prefix(QUICK_OPEN_URL)?;
+    if rest.is_empty() {
+        return Some(String::new());
+    }
+    let query = rest.strip_prefix('?')?;
+    Some(
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "q")
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_default(),
+    )
+}
+
+/// `frontier://quick-open?q=query`, the address shown while the matches are up.
+pub fn quick_open_url(query: &str) -> String {
+    if query.is_empty() {
+        return QUICK_OPEN_URL.to_string();
+    }
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("q", query)
+        .finish();
+    format!("{QUICK_OPEN_URL}?{query}")
+}
+
+/// HTML body of the palette for `query`.
+pub fn quick_open_page(query: &str, matches: &[Match<'_>]) -> String {
+    let rows: String = if matches.is_empty() {
+        "<li>Nothing you have visited or bookmarked matches.</li>".to_string()
+    } else {
+        matches
+            .iter()
+            .map(|found| {
+                let title = if found.entry.title.is_empty() {
+                    &found.entry.url
+                } else {
+                    &found.entry.title
+                };
+                format!(
+                    "<li><a href=\"{href}\">{star}{title}</a> <code>{url}</code></li>",
+                    href = encode_double_quoted_attribute(&found.entry.url),
+                    star = if found.bookmarked { "★ " } else { "" },
+                    title = encode_text(title),
+                    url = encode_text(&found.entry.url),
+                )
+            })
+            .collect()
+    };
+    format!(
+        r#"<section class="quick-open">
+<h1>Quick Open</h1>
+<form action="{QUICK_OPEN_URL}" method="get">
+<p><label for="quick-open-query">Search bookmarks and history</label><br><input type="search" id="quick-open-query" name="q" value="{value}" autofocus> <input type="submit" value="Search"></p>
+</form>
+<ol class="quick-open-results">{rows}</ol>
+</section>"#,
+        value = encode_double_quoted_attribute(query),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::DocumentConfig;
+    use blitz_html::HtmlDocument;
+
+    #[test]
+    fn fuzzy_search_ranks_word_starts_and_bookmarks_first() {
+        let mut store = QuickOpenStore::default();
+        store.record_visit("https://docs.rs/winit", "winit - Rust");
+        store.record_visit("https://news.example/", "Daily News");
+        store.record_visit("https://github.com/rust-lang/rust", "The Rust language");
+        store.toggle_bookmark("https://nostr.example/", "Nostr client");
+        assert!(!store.record_visit("https://github.com/rust-lang/rust", "The Rust language"));
+
+        let urls = |query: &str| -> Vec<String> {
+            store
+                .search(query)
+                .iter()
+                .map(|found| found.entry.url.clone())
+                .collect()
+        };
+        assert_eq!(
+            urls(""),
+            [
+                "https://nostr.example/",
+                "https://github.com/rust-lang/rust",
+                "https://news.example/",
+                "https://docs.rs/winit",
+            ]
+        );
+        assert_eq!(urls("RUST")[0], "https://github.com/rust-lang/rust");
+        assert_eq!(urls("dn")[0], "https://news.example/");
+        assert_eq!(urls("ns")[0], "https://nostr.example/");
+        assert!(urls("zzz").is_empty());
+
+        assert!(!store.toggle_bookmark("https://nostr.example/", ""));
+        assert!(store.search("").iter().all(|found| !found.bookmarked));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(FILE_NAME);
+        assert_eq!(QuickOpenStore::load(&path), QuickOpenStore::default());
+        store.save(&path).unwrap();
+        assert_eq!(QuickOpenStore::load(&path), store);
+    }
+
+    #[test]
+    fn palette_reads_its_query_and_escapes_entries() {
+        assert_eq!(query_from_input(QUICK_OPEN_URL), Some(String::new()));
+        assert_eq!(
+            query_from_input(&quick_open_url("rust lang")),
+            Some("rust lang".to_string())
+        );
+        assert_eq!(query_from_input("frontier://quick-openers"), None);
+        assert_eq!(quick_open_url(""), QUICK_OPEN_URL);
+
+        let entry = Entry {
+            url: "https://x.example/?a=\"b\"".to_string(),
+            title: "<b>X</b>".to_string(),
+        };
+        let html = quick_open_page(
+            "a\"b",
+            &[Match {
+                entry: &entry,
+                bookmarked: true,
+            }],
+        );
+        assert!(html.contains("href=\"https://x.example/?a=&quot;b&quot;\">★ &lt;b&gt;X"));
+        assert!(html.contains("value=\"a&quot;b\""));
+        assert!(quick_open_page("", &[]).contains("Nothing you have visited"));
+
+        let html = "<html><head><title>\n  Frontier\n  Docs </title></head><body></body></html>";
+        let doc = HtmlDocument::from_html(html, DocumentConfig::default());
+        assert_eq!(document_title(&doc), Some("Frontier Docs".to_string()));
+        let doc = HtmlDocument::from_html("<p>untitled</p>", DocumentConfig::default());
+        assert_eq!(document_title(&doc), None);
+    }
+}
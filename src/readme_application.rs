@@ -1,67 +1,195 @@
 #![allow(clippy::disallowed_types)]
 
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 #[allow(clippy::disallowed_types)]
+use crate::accessibility::AccessibilityTree;
+use crate::adblock::Blocklist;
+use crate::app_menu::{self, AppMenu, MenuCommand};
 use crate::automation::{
     AutomationArtifacts, AutomationCommand, AutomationEvent, AutomationReply, AutomationResponse,
-    AutomationResult, AutomationStateHandle, ElementSelector, KeyboardAction, PointerAction,
-    PointerButton, PointerTarget,
+    AutomationResult, AutomationStateHandle, ElementSelector, Emulation, KeyboardAction,
+    PointerAction, PointerButton, PointerTarget,
 };
-use crate::chrome::wrap_with_url_bar;
+use crate::background::Background;
+use crate::chrome::{wrap_for_kiosk, wrap_with_chrome};
+use crate::cookies::CookieJar;
+use crate::crash_recovery::{self, SessionSnapshot, RESTORE_URL};
+use crate::damage::{self, DamageTracker};
+use crate::diagnostics::{MemoryReport, GPU_URL, MEMORY_URL, VERSION_URL};
+use crate::element_state::{self, ElementStates};
+use crate::form_widgets::{self, Commit, FormWidget, WidgetKey, WidgetOutcome};
+use crate::frame_stats::{self, FrameStats};
+use crate::frames::{self, FrameOptions, FrameRequest, FrameSource, FrameTree};
+use crate::gestures::{self, SwipeDirection, SwipeTracker};
+use crate::har;
+use crate::identities::{IdentityStore, IDENTITIES_URL};
+use crate::js::clock::VirtualClock;
+use crate::js::console::ConsoleLog;
+use crate::js::coverage::JsCoverage;
+use crate::js::file_input;
+use crate::js::gestures::{GestureEvent, PinchPhase};
+use crate::js::isolation::Isolation;
+use crate::js::lifecycle::LifecycleEvent;
+use crate::js::media::{ColorScheme, MediaPreferences};
+use crate::js::messaging::{target_origin_allows, PostedMessage, WindowRef};
+use crate::js::nostr::SignerMethod;
+use crate::js::p2p::P2pRequest;
+use crate::js::performance::NavigationTiming;
 use crate::js::processor::ScriptExecutionSummary;
 use crate::js::runtime_document::RuntimeDocument;
+use crate::js::scroll::ScrollIntoView;
 use crate::js::session::JsPageRuntime;
+use crate::js::storage::LocalStorage;
+use crate::js::teardown::TEARDOWN_GRACE;
+use crate::js::viewport::ViewportMetrics;
+use crate::js::window_open::{targets_current_window, WindowRequest};
+use crate::kiosk::KioskPolicy;
+use crate::load_trace::{LoadTrace, Phase};
+use crate::name_search;
 use crate::navigation::{
-    execute_fetch, prepare_navigation, FetchRequest, FetchedDocument, NavigationPlan,
+    execute_fetch_logged, prepare_navigation, FetchError, FetchRequest, FetchSource,
+    FetchedDocument, NavigationPlan, Navigations,
 };
-use crate::WindowRenderer;
-use anyhow::{anyhow, Context};
+use crate::network_log::{self, Initiator, NetworkLog, RequestSource};
+use crate::p2p::{self, ChannelEvent, ConnectDecision, P2pEvent, P2pSession};
+use crate::page_cache::{format_timestamp, CachedPage, PageCache};
+use crate::permissions::{
+    site_info_html, ContentRules, ContentSetting, Permissions, SITE_SETTINGS_URL,
+};
+use crate::popups::{self, PopupEvent, PopupRecord, Popups, WireMessage};
+use crate::preferences::{Preferences, ThemePreference, SETTINGS_URL};
+use crate::prefetch::{self, PrefetchScope, Prefetcher};
+use crate::privacy;
+use crate::publish_queue::{self, PublishQueue, PUBLISH_QUEUE_URL};
+use crate::quick_open::{self, QuickOpenStore, QUICK_OPEN_URL};
+use crate::recording::Recorder;
+use crate::relays::{self, RelayAction, RELAYS_URL};
+use crate::screenshot::{self, CompareThresholds, ElementRect, ScreenshotComparison};
+use crate::scroll_anchor::ScrollAnchor;
+use crate::signer::{
+    KeyDecision, PromptDecision, SignatureOutcome, SignatureRecord, SIGNATURES_URL,
+};
+use crate::smooth_scroll::{self, ScrollTarget, SmoothScroll};
+use crate::startup::{self, Stage};
+use crate::storage_policy::PRIVATE_FLAG;
+use crate::subresource_loader::SubresourceLoader;
+use crate::window_state::{MonitorArea, WindowState};
+use anyhow::{anyhow, bail, Context};
+use anyrender::WindowRenderer;
 use blitz_dom::net::Resource;
-use blitz_dom::{local_name, Document, DocumentConfig};
+use blitz_dom::{local_name, BaseDocument, Document, DocumentConfig, DocumentMutator};
 use blitz_html::HtmlDocument;
 use blitz_net::Provider;
-use blitz_shell::{BlitzApplication, BlitzShellEvent, View, WindowConfig};
+use blitz_shell::{BlitzApplication, BlitzShellEvent, BlitzShellNetCallback, View, WindowConfig};
 use blitz_traits::events::{BlitzInputEvent, DomEvent, DomEventData};
 use blitz_traits::navigation::{NavigationOptions, NavigationProvider};
+use futures_util::task::ArcWake;
 use html_escape::encode_text;
+use image::RgbaImage;
 use keyboard_types::Modifiers;
+use nostr_sdk::prelude::{FromBech32, Keys, PublicKey, ToBech32};
 use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
+use url::{Origin, Url};
 use winit::application::ApplicationHandler;
-use winit::dpi::{LogicalPosition, PhysicalPosition};
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
 use winit::event::{
-    DeviceId, ElementState, Ime, Modifiers as WinitModifiers, MouseButton, MouseScrollDelta,
-    StartCause, TouchPhase, WindowEvent,
+    DeviceId, ElementState, Ime, KeyEvent, Modifiers as WinitModifiers, MouseButton,
+    MouseScrollDelta, StartCause, TouchPhase, WindowEvent,
 };
 use winit::event_loop::{ActiveEventLoop, EventLoopProxy};
-use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::{Theme, WindowId};
+use winit::keyboard::{Key, KeyCode, ModifiersState, NamedKey, PhysicalKey};
+use winit::window::{Theme, WindowAttributes, WindowId};
+
+const CONNECTIVITY_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the session saved for crash recovery is refreshed.
+const SESSION_RECORD_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the window must keep its size and position before they are saved.
+const WINDOW_STATE_SAVE_DELAY: Duration = Duration::from_secs(1);
+/// How often the open frame overlay picks up new statistics.
+const FRAME_OVERLAY_REFRESH: Duration = Duration::from_millis(500);
+/// CSS pixels per line for wheels that scroll by lines, when recording or animating them.
+const SCROLL_LINE_HEIGHT: f64 = 20.0;
+/// Highest device pixel ratio automation may emulate; screenshots grow with its square.
+const MAX_EMULATED_SCALE: f64 = 8.0;
+/// Most timer runs one `AdvanceClock` command performs, so a page that keeps scheduling
+/// zero-delay timers cannot hang automation.
+const MAX_CLOCK_STEPS: usize = 10_000;
 
 #[derive(Debug, Clone)]
 pub enum ReadmeEvent {
     Refresh,
     Navigation(Box<NavigationMessage>),
+    /// A probe started after a navigation found the network unreachable succeeded.
+    ConnectivityRestored,
+    /// The pointer has rested on the link to this URL for [`prefetch::HOVER_DELAY`].
+    HoverSettled(Url),
+    /// Outcome of a `frontier://relays?import=...` lookup, merged on the UI thread.
+    RelayListImported {
+        npub: String,
+        result: Result<Vec<Url>, String>,
+        generation: u64,
+    },
+    /// The document for an iframe of the page from navigation `generation` was fetched.
+    FrameLoaded {
+        source: FrameSource,
+        result: Result<Box<FetchedDocument>, String>,
+        generation: u64,
+    },
+    /// A child frame's timers or fetches completed.
+    FrameActivity,
+    /// A popup this page opened posted a message or exited.
+    Popup(PopupEvent),
+    /// The window that opened this popup posted a message.
+    /// A message from the opener, with the opener's origin.
+    OpenerMessage(String, WireMessage),
+    /// A command from the application menu or the chrome.
+    Command(MenuCommand),
+    /// A `frontier.connect` channel of the page opened, received a message or closed.
+    P2p(P2pEvent),
 }
 
-fn runtime_document_with_environment(
-    runtime: &JsPageRuntime,
-    doc: HtmlDocument,
-) -> RuntimeDocument {
-    let environment = runtime.environment();
-    RuntimeDocument::new(doc, environment.clone())
+/// Wakes the event loop on behalf of the page's child frames.
+struct FrameWaker(Mutex<EventLoopProxy<BlitzShellEvent>>);
+
+impl ArcWake for FrameWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Ok(proxy) = arc_self.0.lock() {
+            let event = ReadmeEvent::FrameActivity;
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        }
+    }
 }
 
+/// Result of a navigation task. `generation` identifies the navigation that produced it so
+/// results from superseded navigations can be dropped.
 #[derive(Debug, Clone)]
 pub enum NavigationMessage {
     Completed {
         document: Box<FetchedDocument>,
         retain_scroll: bool,
+        generation: u64,
     },
     Failed {
         message: String,
+        generation: u64,
+    },
+    /// The network was unreachable (or the user is working offline). `cached` is the last good
+    /// copy of the page, if there is one.
+    Offline {
+        input: String,
+        url: Url,
+        reason: String,
+        cached: Option<Box<CachedPage>>,
+        retain_scroll: bool,
+        generation: u64,
     },
 }
 
@@ -69,10 +197,15 @@ struct AutomationBindings {
     state: AutomationStateHandle,
 }
 
-pub struct ReadmeApplication {
-    inner: BlitzApplication<WindowRenderer>,
+pub struct ReadmeApplication<R: WindowRenderer> {
+    inner: BlitzApplication<R>,
     handle: Handle,
     net_provider: Arc<Provider<Resource>>,
+    subresources: Arc<SubresourceLoader<Resource>>,
+    /// Filter lists checked by `subresources` and injected as element hiding CSS.
+    blocklist: Arc<Blocklist>,
+    /// Blocked request count the chrome shows, when it is current.
+    blocked_count_shown: Option<usize>,
     navigation_provider: Arc<dyn NavigationProvider>,
     keyboard_modifiers: WinitModifiers,
     current_input: String,
@@ -83,19 +216,208 @@ pub struct ReadmeApplication {
     chrome_handles: Option<DocumentChromeHandles>,
     back_history: Vec<String>,
     forward_history: Vec<String>,
+    navigations: Navigations,
+    /// Phase timings for the most recent navigation.
+    load_trace: LoadTrace,
+    /// Set when a new document is installed; the next redraw is its first paint.
+    awaiting_first_paint: bool,
+    load_fired: bool,
+    unload_prompted: bool,
+    reduced_motion: bool,
+    /// The running wheel, key or `scrollIntoView` scroll animation.
+    smooth_scroll: SmoothScroll,
+    /// Magnification of the trackpad pinch in progress and whether the page cancelled it;
+    /// `None` between pinches.
+    pinch: Option<(f64, bool)>,
+    /// The two-finger swipe in progress, which may navigate back or forward.
+    swipe: SwipeTracker,
+    preferences: Preferences,
+    preferences_path: Option<PathBuf>,
+    /// Outcome of the last settings form submission, shown once on the settings page.
+    settings_status: Option<String>,
+    /// Outcome of the last relay add/remove/import, shown once on the relays page.
+    relays_status: Option<String>,
+    /// npub whose NIP-65 relay list the next `frontier://relays` load imports.
+    pending_relay_import: Option<String>,
+    /// Set by "Retry now"; the next `frontier://publish-queue` load sends every queued batch.
+    publish_queue_retry: bool,
+    identities: IdentityStore,
+    identities_path: Option<PathBuf>,
+    /// Outcome of the last identities form submission, shown once on the identities page.
+    identities_status: Option<String>,
+    /// Secret keys decrypted from `frontier://identities` for page signing, keyed by npub.
+    /// Never written to disk.
+    unlocked_identities: HashMap<String, Keys>,
+    /// Origins the user let read their public key until the browser closes.
+    public_key_origins: HashSet<String>,
+    permissions: Permissions,
+    permissions_path: Option<PathBuf>,
+    page_cache: Option<PageCache>,
+    /// Shown above the page content, e.g. when it is a cached copy.
+    chrome_notice: Option<String>,
+    /// Polls for connectivity after a navigation found the network unreachable.
+    connectivity_probe: Option<JoinHandle<()>>,
+    /// Navigation to repeat once connectivity returns.
+    offline_retry: Option<String>,
+    prefetcher: Arc<Prefetcher>,
+    /// What the current page's prefetches are checked against.
+    prefetch_scope: PrefetchScope,
+    /// Target of the link under the pointer.
+    hovered_link: Option<Url>,
+    /// Elements of the current document in the `:hover`, `:active` or `:focus` state.
+    element_states: ElementStates,
+    /// Last pointer position in CSS pixels, where dropped files land.
+    pointer: LogicalPosition<f64>,
+    /// Files dropped onto a window since the event loop last went idle. winit reports each
+    /// file of a drop separately, so they are handed over together from `about_to_wait`.
+    dropped_files: Vec<(WindowId, PathBuf)>,
+    /// The open `<select>` dropdown or date picker, drawn in the overlay host.
+    form_widget: Option<FormWidget>,
+    /// The form control clicked last, which the arrow keys step and Space toggles.
+    focused_control: Option<usize>,
+    /// The node the last click landed on, which tells whether the navigation it started came
+    /// from the chrome (see [`Self::navigation_source`]).
+    clicked_node: Option<usize>,
+    /// The range input being dragged, and its value when the drag started.
+    range_drag: Option<(usize, String)>,
+    network_log: NetworkLog,
+    network_panel_open: bool,
+    /// Log revision the open panel last rendered; `None` when it needs rendering.
+    network_panel_revision: Option<u64>,
+    frame_stats: FrameStats,
+    frame_overlay_open: bool,
+    /// When the frame or damage overlay was last rendered; `None` when it needs rendering.
+    frame_overlay_drawn: Option<Instant>,
+    damage: DamageTracker,
+    damage_overlay_open: bool,
+    /// The viewport scroll offset the last frame painted.
+    painted_scroll: Option<(f64, f64)>,
+    /// The element kept in place on screen while content above it changes size.
+    scroll_anchor: ScrollAnchor,
+    /// When the crash recovery snapshot was last taken; `None` after a navigation.
+    session_recorded_at: Option<Instant>,
+    /// Scroll position and form values to put back once a restored page has painted.
+    pending_restore: Option<SessionSnapshot>,
+    frames: FrameTree,
+    /// Set when a new document is installed; its iframes load once it is in the window.
+    frames_pending: bool,
+    /// Set by [`FrameWaker`]; frame runtimes are pumped only when it is set or a message was
+    /// delivered to them, never on every turn of the event loop.
+    frames_woken: bool,
+    popups: Popups,
+    /// Relay connection behind the page's `frontier.connect` channels, started by the first.
+    p2p: Option<P2pSession>,
+    /// Script coverage an automation session asked for, kept across page loads.
+    js_coverage: Option<JsCoverage>,
+    /// Clock an automation session replaying a recording moves by hand; pages loaded once
+    /// it is set run their timers off it.
+    virtual_clock: Option<VirtualClock>,
+    /// Device pixel ratio an automation session emulates in place of the window's.
+    emulated_scale: Option<f64>,
+    /// `User-Agent` an automation session emulates, for this window's requests and page
+    /// script only.
+    emulated_user_agent: Option<String>,
+    /// Set when this process was started by another window's `window.open`.
+    is_popup: bool,
+    /// Set with `--kiosk`: where navigation may go, and no chrome, shortcuts or menus.
+    kiosk: Option<KioskPolicy>,
+    /// The File, Edit, View, History and Help menus.
+    app_menu: AppMenu,
+    /// The tray icon and quick-open shortcut, while background mode is on.
+    background: Background,
+    /// Bookmarks and visited pages for the quick-open palette.
+    quick_open: QuickOpenStore,
+    quick_open_path: Option<PathBuf>,
+    /// The navigation whose page was last recorded in [`Self::quick_open`].
+    visit_recorded: u64,
+    /// Starts the relay work held back at startup; taken once a page needs relays.
+    relay_bootstrap: Option<oneshot::Sender<()>>,
+    /// Latest geometry of the main window, saved for the next launch.
+    window_state: Option<WindowState>,
+    window_state_path: Option<PathBuf>,
+    /// When the window last moved or resized without the result being saved yet.
+    window_state_changed_at: Option<Instant>,
+    /// Set when the window opened at a saved position that must be checked against the
+    /// monitors once it exists.
+    window_needs_placing: bool,
+    /// Set with `--record`; captures this window's input until the browser exits.
+    recorder: Option<Recorder>,
     automation: Option<AutomationBindings>,
+    /// Commands from the remote debug channel, run like automation commands without putting
+    /// the browser under automation.
+    debug_channel: Option<AutomationStateHandle>,
 }
 
-impl ReadmeApplication {
+impl<R: WindowRenderer> ReadmeApplication<R> {
     pub fn new(
         proxy: EventLoopProxy<BlitzShellEvent>,
         initial_input: String,
         net_provider: Arc<Provider<Resource>>,
         navigation_provider: Arc<dyn NavigationProvider>,
     ) -> Self {
+        let quick_open_path = QuickOpenStore::default_path();
+        let preferences_path = Preferences::default_path();
+        let preferences = preferences_path
+            .as_deref()
+            .map(Preferences::load)
+            .transpose()
+            .unwrap_or_else(|err| {
+                warn!(error = %err, "ignoring unreadable preferences");
+                None
+            })
+            .unwrap_or_default();
+        privacy::configure(preferences.privacy);
+        let page_cache =
+            PageCache::open_default().map(|cache| cache.with_limit(preferences.cache_size_bytes()));
+        let identities_path = IdentityStore::default_path();
+        let identities = identities_path
+            .as_deref()
+            .map(IdentityStore::load)
+            .transpose()
+            .unwrap_or_else(|err| {
+                warn!(error = %err, "ignoring unreadable identities");
+                None
+            })
+            .unwrap_or_default();
+        let permissions_path = Permissions::default_path();
+        let permissions = permissions_path
+            .as_deref()
+            .map(Permissions::load)
+            .transpose()
+            .unwrap_or_else(|err| {
+                warn!(error = %err, "ignoring unreadable permissions");
+                None
+            })
+            .unwrap_or_default();
+        let blocklist = Blocklist::default_dir()
+            .map(|dir| Blocklist::load_dir(&dir))
+            .transpose()
+            .unwrap_or_else(|err| {
+                warn!(error = %err, "ignoring unreadable filter lists");
+                None
+            })
+            .unwrap_or_default();
+        if !blocklist.is_empty() {
+            info!(
+                rules = blocklist.rule_count(),
+                skipped = blocklist.skipped(),
+                "loaded filter lists"
+            );
+        }
+        let blocklist = Arc::new(blocklist);
+        let network_log = NetworkLog::default();
+        let net_callback = BlitzShellNetCallback::shared(proxy.clone());
         Self {
             inner: BlitzApplication::new(proxy),
             handle: Handle::current(),
+            subresources: Arc::new(SubresourceLoader::new(
+                Arc::clone(&net_provider),
+                net_callback,
+                Some(network_log.clone()),
+                Arc::clone(&blocklist),
+            )),
+            blocklist,
+            blocked_count_shown: None,
             net_provider,
             navigation_provider,
             keyboard_modifiers: Default::default(),
@@ -107,20 +429,257 @@ impl ReadmeApplication {
             chrome_handles: None,
             back_history: Vec::new(),
             forward_history: Vec::new(),
+            navigations: Navigations::default(),
+            load_trace: LoadTrace::start(0, ""),
+            awaiting_first_paint: false,
+            load_fired: false,
+            unload_prompted: false,
+            reduced_motion: MediaPreferences::reduced_motion_from_env(),
+            smooth_scroll: SmoothScroll::new(preferences.scroll_easing),
+            pinch: None,
+            swipe: SwipeTracker::default(),
+            preferences,
+            preferences_path,
+            settings_status: None,
+            relays_status: None,
+            pending_relay_import: None,
+            publish_queue_retry: false,
+            identities,
+            identities_path,
+            identities_status: None,
+            unlocked_identities: HashMap::new(),
+            public_key_origins: HashSet::new(),
+            permissions,
+            permissions_path,
+            page_cache,
+            chrome_notice: None,
+            connectivity_probe: None,
+            offline_retry: None,
+            prefetcher: Arc::new(Prefetcher::default()),
+            prefetch_scope: PrefetchScope::default(),
+            hovered_link: None,
+            element_states: ElementStates::default(),
+            pointer: LogicalPosition::new(0.0, 0.0),
+            dropped_files: Vec::new(),
+            form_widget: None,
+            focused_control: None,
+            clicked_node: None,
+            range_drag: None,
+            network_log,
+            network_panel_open: false,
+            network_panel_revision: None,
+            frame_stats: FrameStats::default(),
+            frame_overlay_open: false,
+            frame_overlay_drawn: None,
+            damage: DamageTracker::default(),
+            damage_overlay_open: false,
+            painted_scroll: None,
+            scroll_anchor: ScrollAnchor::default(),
+            session_recorded_at: None,
+            pending_restore: None,
+            frames: FrameTree::default(),
+            frames_pending: false,
+            frames_woken: false,
+            popups: Popups::default(),
+            p2p: None,
+            js_coverage: None,
+            virtual_clock: None,
+            emulated_scale: None,
+            emulated_user_agent: None,
+            is_popup: false,
+            kiosk: None,
+            app_menu: AppMenu::default(),
+            background: Background::default(),
+            quick_open: quick_open_path
+                .as_deref()
+                .map(QuickOpenStore::load)
+                .unwrap_or_default(),
+            quick_open_path,
+            visit_recorded: 0,
+            relay_bootstrap: None,
+            window_state: None,
+            window_state_path: WindowState::default_path(),
+            window_state_changed_at: None,
+            window_needs_placing: false,
+            recorder: None,
             automation: None,
+            debug_channel: None,
         }
     }
 
+    /// Run as a popup of the process that started this one: `window.opener` refers to it and
+    /// its messages arrive on the channel it passed. Call before
+    /// [`Self::prepare_initial_state`].
+    pub fn set_popup(&mut self) {
+        self.is_popup = true;
+        let proxy = self.inner.proxy.clone();
+        let connected = popups::connect_to_opener(move |origin, message| {
+            let event = ReadmeEvent::OpenerMessage(origin, message);
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
+        if let Err(err) = connected {
+            warn!(error = %err, "popup has no channel to its opener");
+        }
+    }
+
+    /// Show only the site of `home` and the `kiosk_origins` preference, without the URL bar,
+    /// menus or browser shortcuts; see [`kiosk`](crate::kiosk). Call before
+    /// [`Self::prepare_initial_state`].
+    pub fn set_kiosk(&mut self, home: Url) {
+        info!(home = %home, "kiosk mode");
+        self.kiosk = Some(KioskPolicy::new(home, &self.preferences.kiosk_origins));
+    }
+
+    /// Whether kiosk mode keeps the browser from loading `target`.
+    fn kiosk_refuses(&self, target: &str) -> bool {
+        let refused = self
+            .kiosk
+            .as_ref()
+            .is_some_and(|kiosk| !kiosk.allows(target));
+        if refused {
+            info!(target, "kiosk mode refused a navigation");
+        }
+        refused
+    }
+
+    /// Record this session's input into `path`, written when the browser exits. Call before
+    /// [`Self::prepare_initial_state`].
+    pub fn set_recording(&mut self, path: PathBuf) {
+        info!(path = %path.display(), "recording session input");
+        self.recorder = Some(Recorder::new(path, self.current_input.clone()));
+    }
+
     #[allow(dead_code)]
     pub fn attach_automation(&mut self, state: AutomationStateHandle) {
         self.automation = Some(AutomationBindings { state });
+        // The HAR export can include response bodies, so keep them under automation.
+        self.network_log.set_capture_bodies(true);
+    }
+
+    /// Run the commands [`debug_channel`](crate::debug_channel) queues on `state`; it wakes
+    /// the event loop with an [`AutomationEvent`] after each one.
+    pub fn attach_debug_channel(&mut self, state: AutomationStateHandle) {
+        self.debug_channel = Some(state);
+    }
+
+    /// `attrs` with the size, position and maximized state the main window had when it last
+    /// closed. Popups, and everyone with `remember_window` turned off, get `attrs` unchanged.
+    pub fn restore_window_state(&mut self, attrs: WindowAttributes) -> WindowAttributes {
+        if !self.remembers_window() {
+            return attrs;
+        }
+        let Some(state) = self
+            .window_state_path
+            .as_deref()
+            .and_then(WindowState::load)
+        else {
+            return attrs;
+        };
+        let attrs = state.apply(attrs);
+        self.window_state = Some(state);
+        self.window_needs_placing = true;
+        attrs
+    }
+
+    /// Write the window geometry noted since the last save, e.g. when the browser exits.
+    pub fn save_window_state(&mut self) {
+        if self.window_state_changed_at.take().is_none() {
+            return;
+        }
+        let (Some(state), Some(path)) = (&self.window_state, &self.window_state_path) else {
+            return;
+        };
+        if let Err(err) = state.save(path) {
+            warn!(error = %err, "failed to save window state");
+        }
+    }
+
+    fn remembers_window(&self) -> bool {
+        !self.is_popup
+            && self.preferences.remember_window
+            && self.automation.is_none()
+            && self.kiosk.is_none()
+    }
+
+    /// Move a window restored at a saved position onto a connected monitor, in case the one it
+    /// was on is gone or has moved since.
+    fn place_restored_window(&mut self, event_loop: &ActiveEventLoop) {
+        if !std::mem::take(&mut self.window_needs_placing) {
+            return;
+        }
+        let (Some(state), Some(view)) = (&self.window_state, self.inner.windows.values().next())
+        else {
+            return;
+        };
+        let primary = event_loop.primary_monitor();
+        let mut monitors: Vec<MonitorArea> = primary.iter().map(MonitorArea::of).collect();
+        monitors.extend(
+            event_loop
+                .available_monitors()
+                .filter(|monitor| Some(monitor) != primary.as_ref())
+                .map(|monitor| MonitorArea::of(&monitor)),
+        );
+        let Some(position) = state.position_on(&monitors) else {
+            return;
+        };
+        if position != PhysicalPosition::new(state.x, state.y) {
+            view.window.set_outer_position(position);
+        }
+    }
+
+    /// Note the window's size and position after it moved or was resized.
+    /// [`Self::maybe_save_window_state`] writes them once the window has settled.
+    fn track_window_state(&mut self, window_id: WindowId) {
+        if !self.remembers_window() {
+            return;
+        }
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let window = &view.window;
+        // Wayland does not report window positions, and ignores them when restoring.
+        let position = window.outer_position().unwrap_or_default();
+        let monitor = window
+            .current_monitor()
+            .map(|monitor| MonitorArea::of(&monitor));
+        let state = self.window_state.get_or_insert_with(WindowState::default);
+        if state.update(
+            position,
+            window.inner_size(),
+            window.scale_factor(),
+            window.is_maximized(),
+            monitor,
+        ) {
+            self.window_state_changed_at = Some(Instant::now());
+        }
+    }
+
+    fn maybe_save_window_state(&mut self) {
+        if self
+            .window_state_changed_at
+            .is_some_and(|changed| changed.elapsed() >= WINDOW_STATE_SAVE_DELAY)
+        {
+            self.save_window_state();
+        }
     }
 
-    pub fn add_window(&mut self, window_config: WindowConfig<WindowRenderer>) {
+    pub fn add_window(&mut self, window_config: WindowConfig<R>) {
         self.inner.add_window(window_config);
     }
 
+    /// Hold relay work back until a page needs relays; firing `start` lets it begin.
+    pub fn set_relay_bootstrap(&mut self, start: oneshot::Sender<()>) {
+        self.relay_bootstrap = Some(start);
+    }
+
+    fn start_relays(&mut self) {
+        if let Some(start) = self.relay_bootstrap.take() {
+            let _ = start.send(());
+        }
+    }
+
     pub fn prepare_initial_state(&mut self, document: FetchedDocument) {
+        self.load_trace = LoadTrace::start(0, &document.display_url);
         self.set_document(document);
     }
 
@@ -140,7 +699,10 @@ impl ReadmeApplication {
 
         if self.chrome_handles.is_none() {
             match DocumentChromeHandles::compute(&mut doc) {
-                Ok(handles) => self.chrome_handles = Some(handles),
+                Ok(handles) => {
+                    self.chrome_handles = Some(handles);
+                    self.blocked_count_shown = None;
+                }
                 Err(err) => {
                     error!(
                         target = "quickjs",
@@ -152,14 +714,12 @@ impl ReadmeApplication {
             }
         }
 
+        let load_trace = self.load_trace.clone();
         let boxed_document: Box<dyn Document> =
             if let Some(runtime) = self.current_js_runtime.as_mut() {
-                let runtime_doc = runtime_document_with_environment(runtime, doc);
-                let mut boxed = Box::new(runtime_doc);
-                // Attach after boxing to ensure bridge pointer is valid at final heap location
-                runtime.attach_document(&mut boxed);
+                let boxed = RuntimeDocument::attach(doc, runtime);
                 // Run blocking scripts now that document is attached
-                match runtime.run_blocking_scripts() {
+                match load_trace.time(Phase::Scripts, || runtime.run_blocking_scripts()) {
                     Ok(Some(summary)) => {
                         self.log_script_summary(&base_url, &summary);
                     }
@@ -179,24 +739,102 @@ impl ReadmeApplication {
             };
 
         self.pending_document_reset = false;
+        self.awaiting_first_paint = true;
         boxed_document
     }
 
     fn set_document(&mut self, document: FetchedDocument) {
-        self.current_js_runtime = None;
+        let mut checks = self.frames.shutdown();
+        if let Some(runtime) = self.current_js_runtime.take() {
+            runtime.dispatch_lifecycle(LifecycleEvent::Unload);
+            checks.push(runtime.shutdown());
+        }
+        if !checks.is_empty() {
+            self.handle.spawn(async move {
+                tokio::time::sleep(TEARDOWN_GRACE).await;
+                for check in checks {
+                    check.verify();
+                }
+            });
+        }
+        self.popups.detach_all();
+        self.p2p = None;
+        self.frames_pending = true;
+        self.load_fired = false;
+        self.unload_prompted = false;
         self.prepared_document = None;
         self.pending_document_reset = true;
         self.chrome_handles = None;
+        self.network_panel_revision = None;
+        self.frame_overlay_drawn = None;
+        self.form_widget = None;
+        self.focused_control = None;
+        self.clicked_node = None;
+        self.range_drag = None;
+        self.element_states = ElementStates::default();
+        self.smooth_scroll.cancel();
+        self.pinch = None;
+        self.swipe = SwipeTracker::default();
+        self.frame_stats.reset();
+        self.damage.reset();
+        self.painted_scroll = None;
+        self.scroll_anchor.reset();
+        self.session_recorded_at = None;
 
         self.current_input = document.display_url.clone();
 
-        if !document.scripts.is_empty() {
-            match JsPageRuntime::new(
-                &document.contents,
-                &document.scripts,
-                Some(document.base_url.as_str()),
-            ) {
+        let page_url = Url::parse(&document.base_url).ok();
+        if self.is_popup {
+            let origin = page_url
+                .as_ref()
+                .map(|url| url.origin().ascii_serialization());
+            popups::report_location(origin.as_deref().unwrap_or("null"));
+        }
+        let content_rules = page_url
+            .as_ref()
+            .map(|url| self.permissions.content_rules(url))
+            .unwrap_or_default();
+        self.subresources
+            .set_page(page_url.clone(), content_rules.clone());
+        self.prefetch_scope = PrefetchScope {
+            page: page_url,
+            rules: content_rules.clone(),
+            blocklist: Arc::clone(&self.blocklist),
+            policy: document.content_security_policy(),
+        };
+
+        if !document.scripts.is_empty()
+            && self.preferences.javascript_enabled
+            && content_rules.allows_javascript()
+        {
+            let runtime = self
+                .load_trace
+                .time(Phase::Scripts, || JsPageRuntime::for_document(&document));
+            match runtime {
                 Ok(Some(runtime)) => {
+                    runtime.set_navigation_timing(&navigation_timing(
+                        &self.network_log,
+                        &document.base_url,
+                    ));
+                    runtime.set_network_log(self.network_log.clone());
+                    runtime.set_content_rules(content_rules.clone());
+                    runtime.set_blocklist(Arc::clone(&self.blocklist));
+                    runtime.set_user_agent(self.emulated_user_agent.clone());
+                    if let Some(coverage) = &self.js_coverage {
+                        runtime.set_coverage(coverage.clone());
+                    }
+                    if let Some(clock) = &self.virtual_clock {
+                        runtime.set_virtual_clock(clock.clone());
+                    }
+                    if let Some(metrics) = self.window_viewport() {
+                        runtime.set_viewport(metrics);
+                    }
+                    if let Some(preferences) = self.media_preferences() {
+                        runtime.set_media_preferences(preferences);
+                    }
+                    if self.is_popup {
+                        runtime.set_opened();
+                    }
                     self.current_js_runtime = Some(runtime);
                 }
                 Ok(None) => {}
@@ -214,16 +852,16 @@ impl ReadmeApplication {
         let base_url = document.base_url.clone();
         let contents = document.contents.clone();
 
-        let mut prepared_doc = self.build_document_with_chrome(&contents, &base_url);
+        let mut prepared_doc = self.load_trace.time(Phase::Parse, || {
+            self.build_document_with_chrome(&contents, &base_url)
+        });
 
-        // Note: We don't attach the document here because it will be moved/boxed later.
-        // The attachment happens when creating the final RuntimeDocument to ensure
-        // the bridge pointer is valid at the document's final heap location.
-        // Scripts will be run after the document is properly attached and boxed.
+        // Scripts run once `RuntimeDocument::attach` has boxed the document and attached it.
 
         match DocumentChromeHandles::compute(&mut prepared_doc) {
             Ok(handles) => {
                 self.chrome_handles = Some(handles);
+                self.blocked_count_shown = None;
             }
             Err(err) => {
                 error!(
@@ -236,169 +874,2412 @@ impl ReadmeApplication {
             }
         }
 
+        if self.prefetch_enabled() {
+            for url in collect_prefetch_links(&mut prepared_doc, &base_url) {
+                self.prefetcher.prefetch(
+                    &self.handle,
+                    url,
+                    &self.prefetch_scope,
+                    Arc::clone(&self.net_provider),
+                );
+            }
+        }
+
         self.prepared_document = Some(prepared_doc);
         self.current_document = Some(document);
     }
 
-    fn log_script_summary(&self, base_url: &str, summary: &ScriptExecutionSummary) {
-        info!(
-            target = "quickjs",
-            url = %base_url,
-            scripts = summary.executed_scripts,
-            dom_mutations = summary.dom_mutations,
-            "executed blocking inline scripts"
-        );
+    /// Follow the link under the pointer; once it has stayed there for
+    /// [`prefetch::HOVER_DELAY`], `ReadmeEvent::HoverSettled` prefetches it.
+    fn track_hovered_link(&mut self, window_id: WindowId) {
+        if !self.prefetch_enabled() {
+            return;
+        }
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let base = self
+            .current_document
+            .as_ref()
+            .and_then(|document| Url::parse(&document.base_url).ok());
+        let link = view
+            .doc
+            .get_hover_node_id()
+            .and_then(|node_id| link_target(&view.doc, node_id, base.as_ref()));
+        if link == self.hovered_link {
+            return;
+        }
+        self.hovered_link = link.clone();
+        let Some(url) = link else {
+            return;
+        };
+        let proxy = self.inner.proxy.clone();
+        self.handle.spawn(async move {
+            tokio::time::sleep(prefetch::HOVER_DELAY).await;
+            let event = ReadmeEvent::HoverSettled(url);
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
     }
 
-    fn window_mut(&mut self) -> &mut View<WindowRenderer> {
-        self.inner
-            .windows
-            .values_mut()
-            .next()
-            .expect("window available")
+    /// Carry pointer movement and primary button presses over to the `:hover`, `:active` and
+    /// `:focus` state of the page's elements, and repaint if any element's state changed.
+    fn sync_element_states(
+        &mut self,
+        window_id: WindowId,
+        pointer_moved: bool,
+        pointer_left: bool,
+        primary_button: Option<ElementState>,
+    ) {
+        let Some(view) = self.inner.windows.get_mut(&window_id) else {
+            return;
+        };
+        let mut changed = false;
+        {
+            let doc: &mut BaseDocument = &mut view.doc;
+            if pointer_moved || pointer_left {
+                let hovered = doc.get_hover_node_id().filter(|_| !pointer_left);
+                changed |= self.element_states.hover(doc, hovered);
+            }
+            match primary_button {
+                Some(ElementState::Pressed) => changed |= self.element_states.press(doc),
+                Some(ElementState::Released) => changed |= self.element_states.release(doc),
+                None => {}
+            }
+        }
+        if changed {
+            view.request_redraw();
+        }
     }
 
-    fn build_document_with_chrome(&self, contents: &str, base_url: &str) -> HtmlDocument {
-        let html = wrap_with_url_bar(contents, &self.current_input, None);
-        HtmlDocument::from_html(
-            &html,
-            DocumentConfig {
-                base_url: Some(base_url.to_string()),
-                ua_stylesheets: None,
-                net_provider: Some(self.net_provider.clone()),
-                navigation_provider: Some(self.navigation_provider.clone()),
-                ..Default::default()
-            },
-        )
+    /// Offer the files dropped onto the window to the page under the pointer, and open the
+    /// first one unless the page cancelled the drop.
+    fn handle_dropped_files(&mut self) {
+        let Some(&(window_id, _)) = self.dropped_files.first() else {
+            return;
+        };
+        let paths: Vec<PathBuf> = std::mem::take(&mut self.dropped_files)
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        let Some(view) = self.inner.windows.get_mut(&window_id) else {
+            return;
+        };
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            let target = view
+                .doc
+                .get_hover_node_id()
+                .unwrap_or_else(|| view.doc.root_element().id);
+            let chain = view.doc.node_chain(target);
+            let position = (self.pointer.x, self.pointer.y);
+            if runtime.drop_files(&paths, target, &chain, position) {
+                view.request_redraw();
+                return;
+            }
+        }
+        let Ok(url) = Url::from_file_path(&paths[0]) else {
+            warn!(path = %paths[0].display(), "ignoring dropped file without an absolute path");
+            return;
+        };
+        let doc: &BaseDocument = &view.doc;
+        let options = NavigationOptions::new(url, String::from("text/html"), doc.id());
+        self.navigation_provider.navigate_to(options);
     }
 
-    fn render_current_document(&mut self, retain_scroll: bool) {
-        if self.current_document.is_none() {
+    /// Show the native file dialog for the file input at `node_id` and make the chosen files
+    /// its selection. Automation sessions never get a dialog, which would block the event
+    /// loop; they set files with [`AutomationCommand::SetInputFiles`] instead.
+    fn open_file_picker(&mut self, window_id: WindowId, node_id: usize) {
+        if self.automation.is_some() {
+            info!(node_id, "not opening a file dialog under automation");
             return;
         }
-
-        if self.pending_document_reset {
-            let (base_url, contents) = {
-                let current = self
-                    .current_document
-                    .as_ref()
-                    .expect("current_document must be set");
-                (current.base_url.clone(), current.contents.clone())
-            };
-
-            let mut doc = self
-                .prepared_document
-                .take()
-                .unwrap_or_else(|| self.build_document_with_chrome(&contents, &base_url));
-
-            if self.chrome_handles.is_none() {
-                match DocumentChromeHandles::compute(&mut doc) {
-                    Ok(handles) => self.chrome_handles = Some(handles),
-                    Err(err) => {
-                        error!(
-                            target = "quickjs",
-                            url = %base_url,
-                            error = %err,
-                            "failed to compute chrome handles"
-                        );
-                    }
-                }
-            }
-
-            let boxed_document: Box<dyn Document> =
-                if let Some(runtime) = self.current_js_runtime.as_mut() {
-                    let runtime_doc = runtime_document_with_environment(runtime, doc);
-                    let mut boxed = Box::new(runtime_doc);
-                    // Attach after boxing to ensure bridge pointer is valid at final heap location
-                    runtime.attach_document(&mut boxed);
-                    // Run blocking scripts now that document is attached
-                    match runtime.run_blocking_scripts() {
-                        Ok(Some(summary)) => {
-                            self.log_script_summary(&base_url, &summary);
-                        }
-                        Ok(None) => {}
-                        Err(err) => {
-                            error!(
-                                target = "quickjs",
-                                url = %base_url,
-                                error = %err,
-                                "failed to execute blocking scripts"
-                            );
-                        }
-                    }
-                    boxed
-                } else {
-                    Box::new(doc)
-                };
-
-            self.window_mut()
-                .replace_document(boxed_document, retain_scroll);
-
-            self.pending_document_reset = false;
+        let (Some(runtime), Some(view)) = (
+            self.current_js_runtime.as_ref(),
+            self.inner.windows.get_mut(&window_id),
+        ) else {
+            return;
+        };
+        let Some(input) = file_input::file_input(&view.doc, node_id) else {
+            return;
+        };
+        let mut dialog = rfd::FileDialog::new().set_title(if input.multiple {
+            "Choose files"
+        } else {
+            "Choose a file"
+        });
+        if let Some(extensions) = &input.extensions {
+            dialog = dialog.add_filter("Accepted files", extensions);
+        }
+        let paths = if input.multiple {
+            dialog.pick_files()
+        } else {
+            dialog.pick_file().map(|path| vec![path])
+        };
+        let Some(paths) = paths else {
             return;
+        };
+        match runtime.set_input_files(node_id, &paths) {
+            Ok(()) => view.request_redraw(),
+            Err(err) => warn!(error = %err, "unable to select files"),
         }
+    }
 
-        {
-            let view = self.window_mut();
-            view.poll();
-            view.request_redraw();
+    /// Open the dialogs page script asked for with `click()` or `showPicker()`.
+    fn process_file_picker_requests(&mut self) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let requests = runtime.take_file_picker_requests();
+        let Some(window_id) = self.inner.windows.keys().next().copied() else {
+            return;
+        };
+        for node_id in requests {
+            self.open_file_picker(window_id, node_id);
         }
     }
 
-    fn reload_document(&mut self, retain_scroll: bool) {
-        let input = self.current_input.clone();
-        self.spawn_navigation(input, retain_scroll);
+    /// Scroll to the elements page script called `scrollIntoView` on.
+    fn process_scroll_requests(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let requests = runtime.take_scroll_requests();
+        let Some(window_id) = self.inner.windows.keys().next().copied() else {
+            return;
+        };
+        for request in requests {
+            self.scroll_into_view(event_loop, window_id, &request);
+        }
     }
 
-    fn spawn_navigation(&mut self, input: String, retain_scroll: bool) {
-        let net_provider = Arc::clone(&self.net_provider);
-        let proxy = self.inner.proxy.clone();
+    /// Lay out again for an `IntersectionObserver` that has not seen the page yet.
+    fn process_intersection_requests(&mut self) {
+        let wants_update = self
+            .current_js_runtime
+            .as_ref()
+            .is_some_and(JsPageRuntime::wants_intersection_update);
+        if !wants_update {
+            return;
+        }
+        if let Some(view) = self.inner.windows.values_mut().next() {
+            view.request_redraw();
+        }
+    }
 
-        self.handle.spawn(async move {
-            match prepare_navigation(&input).await {
-                Ok(NavigationPlan::Fetch(request)) => {
-                    let proxy_clone = proxy.clone();
-                    run_fetch_task(request, net_provider, proxy_clone, retain_scroll).await;
-                }
-                Err(err) => {
-                    let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Failed {
-                        message: err.to_string(),
-                    }));
-                    let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
-                }
+    /// Deliver `IntersectionObserver` entries for the frame just laid out.
+    fn update_intersections(&mut self, window_id: WindowId) {
+        if self.pending_document_reset {
+            return;
+        }
+        let changed = self
+            .current_js_runtime
+            .as_ref()
+            .is_some_and(JsPageRuntime::update_intersections);
+        if changed {
+            if let Some(view) = self.inner.windows.get_mut(&window_id) {
+                view.request_redraw();
             }
-        });
+        }
     }
 
-    fn handle_navigation_message(&mut self, message: NavigationMessage) {
-        match message {
-            NavigationMessage::Completed {
+    fn scroll_into_view(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        request: &ScrollIntoView,
+    ) {
+        let (Some(view), Some(viewport)) =
+            (self.inner.windows.get(&window_id), self.window_viewport())
+        else {
+            return;
+        };
+        let Some(distance) = smooth_scroll::into_view_distance(
+            &view.doc,
+            request.node,
+            request.block,
+            request.inline,
+            (viewport.width, viewport.height),
+        ) else {
+            return;
+        };
+        let instant = !request.smooth;
+        self.start_scroll(
+            event_loop,
+            window_id,
+            ScrollTarget::Viewport,
+            distance,
+            instant,
+        );
+    }
+
+    /// Scroll the page for an arrow, paging, Space, Home or End key the page did not use
+    /// itself, unless the focused element takes such keys.
+    fn handle_scroll_key(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, key: &Key) {
+        let mods = self.keyboard_modifiers.state();
+        if mods.control_key() || mods.super_key() || mods.alt_key() {
+            return;
+        }
+        let (Some(view), Some(viewport)) =
+            (self.inner.windows.get(&window_id), self.window_viewport())
+        else {
+            return;
+        };
+        let doc: &BaseDocument = &view.doc;
+        if let Some(focused) = self.element_states.focused() {
+            if element_state::takes_keys(doc, focused) {
+                return;
+            }
+        }
+        let scroll = doc.viewport_scroll();
+        let content_height = f64::from(doc.root_element().final_layout.size.height);
+        let Some(distance) = smooth_scroll::key_distance(
+            key,
+            mods.shift_key(),
+            (viewport.width, viewport.height),
+            (scroll.x, scroll.y),
+            content_height,
+        ) else {
+            return;
+        };
+        self.start_scroll(
+            event_loop,
+            window_id,
+            ScrollTarget::Viewport,
+            distance,
+            false,
+        );
+    }
+
+    /// Animate a scroll of `distance` CSS pixels, or apply it now when `instant`, animation is
+    /// off or the user asked for reduced motion.
+    fn start_scroll(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        target: ScrollTarget,
+        distance: (f64, f64),
+        instant: bool,
+    ) {
+        let instant = instant || self.reduced_motion;
+        match self
+            .smooth_scroll
+            .scroll_by(target, distance, Instant::now(), instant)
+        {
+            Some(distance) => self.apply_scroll(event_loop, window_id, target, distance),
+            None => {
+                if let Some(view) = self.inner.windows.get_mut(&window_id) {
+                    view.request_redraw();
+                }
+            }
+        }
+    }
+
+    /// Scroll by `distance`: the viewport directly, or the container under the pointer through
+    /// a pixel wheel event, which blitz routes like a trackpad's.
+    fn apply_scroll(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        target: ScrollTarget,
+        distance: (f64, f64),
+    ) {
+        let Some(view) = self.inner.windows.get_mut(&window_id) else {
+            return;
+        };
+        let scale = view.window.scale_factor();
+        if target == ScrollTarget::Viewport {
+            let doc: &mut BaseDocument = &mut view.doc;
+            let mut scroll = doc.viewport_scroll();
+            scroll.x = (scroll.x + distance.0).max(0.0);
+            scroll.y = (scroll.y + distance.1).max(0.0);
+            doc.set_viewport_scroll(scroll);
+            view.request_redraw();
+            return;
+        }
+        let delta: PhysicalPosition<f64> =
+            LogicalPosition::new(distance.0, distance.1).to_physical(scale);
+        self.inner.window_event(
+            event_loop,
+            window_id,
+            WindowEvent::MouseWheel {
+                device_id: DeviceId::dummy(),
+                delta: MouseScrollDelta::PixelDelta(delta),
+                phase: TouchPhase::Moved,
+            },
+        );
+    }
+
+    /// Move the running scroll animation on to the frame about to be drawn.
+    fn advance_smooth_scroll(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId) {
+        if let Some((target, distance)) = self.smooth_scroll.frame(Instant::now()) {
+            self.apply_scroll(event_loop, window_id, target, distance);
+        }
+    }
+
+    /// Offer a gesture event to the page at the element under the pointer. Returns whether the
+    /// page cancelled it.
+    fn dispatch_gesture(&mut self, window_id: WindowId, event: GestureEvent) -> bool {
+        let (Some(runtime), Some(view)) = (
+            self.current_js_runtime.as_ref(),
+            self.inner.windows.get_mut(&window_id),
+        ) else {
+            return false;
+        };
+        let target = view
+            .doc
+            .get_hover_node_id()
+            .unwrap_or_else(|| view.doc.root_element().id);
+        let chain = view.doc.node_chain(target);
+        let position = (self.pointer.x, self.pointer.y);
+        let cancelled = runtime.dispatch_gesture(event, target, &chain, position);
+        if cancelled {
+            view.request_redraw();
+        }
+        cancelled
+    }
+
+    /// One step of a trackpad pinch: fire the matching `gesture*` event, then zoom the page
+    /// unless the page cancelled any event of this pinch or pinch zoom is turned off.
+    fn handle_pinch(&mut self, window_id: WindowId, delta: f64, phase: TouchPhase) {
+        let (phase, (scale, cancelled)) = match phase {
+            TouchPhase::Started => (PinchPhase::Start, (1.0, false)),
+            TouchPhase::Moved => (PinchPhase::Change, self.pinch.unwrap_or((1.0, false))),
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                (PinchPhase::End, self.pinch.unwrap_or((1.0, false)))
+            }
+        };
+        let scale = scale * (1.0 + delta);
+        let cancelled =
+            self.dispatch_gesture(window_id, GestureEvent::Pinch { phase, scale }) || cancelled;
+        self.pinch = (phase != PinchPhase::End).then_some((scale, cancelled));
+        if cancelled || !self.preferences.gestures.pinch_zoom || delta == 0.0 {
+            return;
+        }
+        self.zoom_page(|zoom| gestures::pinch_zoom(zoom, delta));
+    }
+
+    /// Change the page zoom of the window to `zoom` of the current one, relaying the page out
+    /// and reporting the new viewport to script.
+    fn zoom_page(&mut self, zoom: impl FnOnce(f32) -> f32) {
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return;
+        };
+        let doc: &mut BaseDocument = &mut view.doc;
+        let zoom = zoom(doc.viewport().zoom());
+        doc.viewport_mut().set_zoom(zoom);
+        view.request_redraw();
+        self.sync_viewport();
+    }
+
+    /// Follow a two-finger scroll after blitz handled it. A swipe that ends far enough
+    /// sideways without having scrolled the page fires `swipe`, then goes back or forward
+    /// unless the page cancelled it or swipe navigation is turned off.
+    fn handle_swipe(
+        &mut self,
+        window_id: WindowId,
+        phase: TouchPhase,
+        delta: PhysicalPosition<f64>,
+    ) {
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let delta: LogicalPosition<f64> = delta.to_logical(view.window.scale_factor());
+        let doc: &BaseDocument = &view.doc;
+        let scroll_x = doc.viewport_scroll().x;
+        let settings = self.preferences.gestures;
+        let Some(direction) = self.swipe.update(
+            phase,
+            (delta.x, delta.y),
+            scroll_x,
+            settings.swipe_threshold,
+        ) else {
+            return;
+        };
+        if self.dispatch_gesture(window_id, GestureEvent::Swipe(direction))
+            || !settings.swipe_navigation
+        {
+            return;
+        }
+        match direction {
+            SwipeDirection::Back => self.go_back(),
+            SwipeDirection::Forward => self.go_forward(),
+        }
+        self.record_navigation();
+    }
+
+    /// A left click: pick from or dismiss the open form widget, open the dropdown, date picker
+    /// or file dialog of the control under the pointer, or toggle the checkbox or radio button
+    /// it (or its label) is.
+    fn handle_click(&mut self, window_id: WindowId) {
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let Some(hovered) = view.doc.get_hover_node_id() else {
+            self.focused_control = None;
+            self.close_form_widget();
+            return;
+        };
+        let control = form_widgets::control_at(&view.doc, hovered);
+        if let Some(widget) = self.form_widget.as_mut() {
+            let outcome = widget.click(&view.doc, hovered);
+            if outcome != WidgetOutcome::Ignored {
+                self.handle_widget_outcome(window_id, outcome);
+                return;
+            }
+            let toggled = control == Some(widget.control());
+            self.close_form_widget();
+            if toggled {
+                return;
+            }
+        }
+        self.focused_control = control;
+        if let Some(commit) = form_widgets::activate(&view.doc, hovered) {
+            self.focused_control = Some(commit.control());
+            self.commit_form_value(window_id, &commit);
+            return;
+        }
+        if let Some(control) = control {
+            self.open_form_widget(window_id, control);
+        }
+        self.open_file_picker(window_id, hovered);
+    }
+
+    /// Press, pointer move or release of a range slider drag. A press on a range input starts
+    /// a drag; while it lasts the value follows the pointer and `input` fires, and releasing it
+    /// fires `change` if the value moved.
+    fn handle_range_drag(&mut self, window_id: WindowId, event: &WindowEvent) {
+        let pressed = match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => Some(state.is_pressed()),
+            WindowEvent::CursorMoved { .. } => None,
+            _ => return,
+        };
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let value_of = |range: usize| {
+            view.doc
+                .get_node(range)
+                .and_then(|node| node.attr(local_name!("value")))
+                .unwrap_or_default()
+                .to_string()
+        };
+        match (pressed, &self.range_drag) {
+            (Some(true), _) => {
+                let range = view
+                    .doc
+                    .get_hover_node_id()
+                    .and_then(|hovered| form_widgets::control_at(&view.doc, hovered))
+                    .filter(|&control| form_widgets::is_range(&view.doc, control));
+                let Some(range) = range else {
+                    return;
+                };
+                self.range_drag = Some((range, value_of(range)));
+                self.focused_control = Some(range);
+                self.drag_range_to_pointer(window_id, range);
+            }
+            (Some(false), Some((range, start))) => {
+                let (range, moved) = (*range, value_of(*range) != *start);
+                self.range_drag = None;
+                if let (true, Some(runtime)) = (moved, self.current_js_runtime.as_ref()) {
+                    runtime.control_changed(range);
+                }
+            }
+            (None, Some(&(range, _))) => self.drag_range_to_pointer(window_id, range),
+            _ => {}
+        }
+    }
+
+    fn drag_range_to_pointer(&mut self, window_id: WindowId, range: usize) {
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let x = (self.pointer.x + view.doc.viewport_scroll().x) as f32;
+        let Some(commit) = form_widgets::range_value_at(&view.doc, range, x) else {
+            return;
+        };
+        let changed = self.apply_form_value(window_id, &commit);
+        if let (true, Some(runtime)) = (changed, self.current_js_runtime.as_ref()) {
+            runtime.control_input(range);
+        }
+    }
+
+    /// Open the dropdown or date picker of the control at `node_id`, if it has one.
+    fn open_form_widget(&mut self, window_id: WindowId, node_id: usize) {
+        if self.chrome_handles.is_none() {
+            return;
+        }
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        self.form_widget = FormWidget::open(&view.doc, node_id);
+        if self.form_widget.is_some() {
+            self.redraw_overlay();
+        }
+    }
+
+    fn close_form_widget(&mut self) {
+        if self.form_widget.take().is_some() {
+            self.redraw_overlay();
+        }
+    }
+
+    fn handle_widget_outcome(&mut self, window_id: WindowId, outcome: WidgetOutcome) {
+        match outcome {
+            WidgetOutcome::Redraw => self.redraw_overlay(),
+            WidgetOutcome::Commit(commit) => {
+                self.close_form_widget();
+                self.commit_form_value(window_id, &commit);
+            }
+            WidgetOutcome::Close | WidgetOutcome::Ignored => self.close_form_widget(),
+        }
+    }
+
+    /// Write a value the user chose into the document, and fire `input` and `change` when it
+    /// differs from the control's previous value.
+    fn commit_form_value(&mut self, window_id: WindowId, commit: &Commit) {
+        let changed = self.apply_form_value(window_id, commit);
+        if let (true, Some(runtime)) = (changed, self.current_js_runtime.as_ref()) {
+            runtime.control_changed(commit.control());
+        }
+    }
+
+    /// Write a value into the document and repaint. Returns whether the control changed.
+    fn apply_form_value(&mut self, window_id: WindowId, commit: &Commit) -> bool {
+        let Some(view) = self.inner.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let changed = {
+            let doc: &mut BaseDocument = &mut view.doc;
+            form_widgets::apply(doc, commit)
+        };
+        view.request_redraw();
+        changed
+    }
+
+    /// Send a key to the open form widget, or to the form control clicked last. Returns
+    /// whether the key was used, in which case the page never sees it.
+    fn handle_form_key(&mut self, window_id: WindowId, event: &KeyEvent) -> bool {
+        let mods = self.keyboard_modifiers.state();
+        let key = if mods.control_key() || mods.super_key() {
+            None
+        } else {
+            widget_key(&event.logical_key)
+        };
+        if let Some(widget) = self.form_widget.as_mut() {
+            let Some(key) = key else {
+                if event.state.is_pressed() {
+                    self.close_form_widget();
+                }
+                return false;
+            };
+            if event.state.is_pressed() {
+                let outcome = widget.key(key);
+                self.handle_widget_outcome(window_id, outcome);
+            }
+            return true;
+        }
+        let (Some(key), Some(control)) = (key, self.focused_control) else {
+            return false;
+        };
+        if !event.state.is_pressed() {
+            return false;
+        }
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return false;
+        };
+        let is_select = view.doc.get_node(control).is_some_and(|node| {
+            node.element_data()
+                .is_some_and(|element| element.name.local == local_name!("select"))
+        });
+        let opens = (key == WidgetKey::Down && mods.alt_key())
+            || (matches!(key, WidgetKey::Enter | WidgetKey::Space) && is_select);
+        if opens {
+            self.open_form_widget(window_id, control);
+            return self.form_widget.is_some();
+        }
+        if key == WidgetKey::Space {
+            let Some(commit) = form_widgets::activate(&view.doc, control) else {
+                return false;
+            };
+            self.commit_form_value(window_id, &commit);
+            return true;
+        }
+        let Some(direction) = form_widgets::arrow_direction(&view.doc, control, key) else {
+            return false;
+        };
+        if let Some(commit) = form_widgets::step(&view.doc, control, direction) {
+            // Arrows in a radio group move the focus along with the selection.
+            self.focused_control = Some(commit.control());
+            self.commit_form_value(window_id, &commit);
+        }
+        true
+    }
+
+    /// Cmd/Ctrl+Shift+E: show or hide the network inspector.
+    fn toggle_network_panel(&mut self) {
+        self.network_panel_open = !self.network_panel_open;
+        self.redraw_overlay();
+    }
+
+    /// Cmd/Ctrl+Shift+F: show or hide the frame timing overlay.
+    fn toggle_frame_overlay(&mut self) {
+        self.frame_overlay_open = !self.frame_overlay_open;
+        self.redraw_overlay();
+    }
+
+    /// Cmd/Ctrl+Shift+D: show or hide the outlines of what the latest frames repainted.
+    fn toggle_damage_overlay(&mut self) {
+        self.damage_overlay_open = !self.damage_overlay_open;
+        self.redraw_overlay();
+    }
+
+    /// Whether frames are compared for damage: only while someone is looking.
+    fn tracks_damage(&self) -> bool {
+        self.damage_overlay_open || self.automation.is_some()
+    }
+
+    /// Remember the scroll offset the frame just painted. Returns whether it differs from the
+    /// previous frame's.
+    fn note_painted_scroll(&mut self, window_id: WindowId) -> bool {
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return false;
+        };
+        let scroll = view.doc.viewport_scroll();
+        let previous = self.painted_scroll.replace((scroll.x, scroll.y));
+        previous.is_some_and(|previous| previous != (scroll.x, scroll.y))
+    }
+
+    /// Lay the document out ahead of painting and scroll by however far the scroll anchor
+    /// moved, so content that grew above the viewport does not push the page down.
+    fn keep_scroll_anchored(&mut self, window_id: WindowId) {
+        if self.pending_document_reset || self.awaiting_first_paint || !self.scroll_anchor.is_set()
+        {
+            return;
+        }
+        let Some(view) = self.inner.windows.get_mut(&window_id) else {
+            return;
+        };
+        let doc: &mut BaseDocument = &mut view.doc;
+        doc.resolve();
+        if let Some(distance) = self.scroll_anchor.adjust(doc) {
+            tracing::debug!(distance, "scroll anchoring adjusted the viewport");
+        }
+    }
+
+    fn select_scroll_anchor(&mut self, window_id: WindowId) {
+        if self.pending_document_reset {
+            return;
+        }
+        let (Some(view), Some(viewport)) =
+            (self.inner.windows.get(&window_id), self.window_viewport())
+        else {
+            return;
+        };
+        self.scroll_anchor.select(&view.doc, viewport.height);
+    }
+
+    /// Record what the frame just painted changed.
+    fn record_damage(&mut self, window_id: WindowId) {
+        if !self.tracks_damage() || self.pending_document_reset {
+            return;
+        }
+        let (Some(view), Some(viewport)) =
+            (self.inner.windows.get(&window_id), self.window_viewport())
+        else {
+            return;
+        };
+        let overlay_host = self
+            .chrome_handles
+            .as_ref()
+            .map(|handles| handles.overlay_host);
+        self.damage
+            .record_frame(&view.doc, (viewport.width, viewport.height), overlay_host);
+    }
+
+    /// Re-render the open panels when the network log changed or the frame or damage overlay
+    /// is due for a refresh.
+    fn sync_overlay(&mut self) {
+        if self.pending_document_reset {
+            return;
+        }
+        let network_due = self.network_panel_open
+            && self.network_panel_revision != Some(self.network_log.revision());
+        let frames_due = (self.frame_overlay_open || self.damage_overlay_open)
+            && self
+                .frame_overlay_drawn
+                .is_none_or(|drawn| drawn.elapsed() >= FRAME_OVERLAY_REFRESH);
+        if network_due || frames_due {
+            self.redraw_overlay();
+        }
+    }
+
+    fn redraw_overlay(&mut self) {
+        let revision = self.network_log.revision();
+        let mut html = String::new();
+        if self.network_panel_open {
+            html.push_str(&network_log::panel_html(&self.network_log.entries()));
+        }
+        if self.frame_overlay_open {
+            html.push_str(&frame_stats::overlay_html(&self.frame_stats.summary()));
+        }
+        if self.damage_overlay_open {
+            html.push_str(&damage::overlay_html(&self.damage.summary()));
+        }
+        if let Some(widget) = &self.form_widget {
+            html.push_str(&widget.html());
+        }
+        if self.set_overlay_html(&html) {
+            self.network_panel_revision = Some(revision);
+            self.frame_overlay_drawn = Some(Instant::now());
+        }
+    }
+
+    /// Show how many of the page's requests were blocked on the site-info button.
+    fn sync_blocked_count(&mut self) {
+        if self.pending_document_reset {
+            return;
+        }
+        let Some(node) = self
+            .chrome_handles
+            .as_ref()
+            .and_then(|handles| handles.blocked_count)
+        else {
+            return;
+        };
+        let count = self.subresources.blocked_count();
+        if self.blocked_count_shown == Some(count) {
+            return;
+        }
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return;
+        };
+        {
+            let doc: &mut BaseDocument = &mut view.doc;
+            let text = if count > 0 {
+                count.to_string()
+            } else {
+                String::new()
+            };
+            DocumentMutator::new(doc).set_inner_html(node, &text);
+        }
+        view.request_redraw();
+        self.blocked_count_shown = Some(count);
+    }
+
+    /// Replace the contents of the chrome's overlay host in the live document.
+    fn set_overlay_html(&mut self, html: &str) -> bool {
+        let Some(overlay_host) = self
+            .chrome_handles
+            .as_ref()
+            .map(|handles| handles.overlay_host)
+        else {
+            return false;
+        };
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return false;
+        };
+        {
+            let doc: &mut BaseDocument = &mut view.doc;
+            DocumentMutator::new(doc).set_inner_html(overlay_host, html);
+        }
+        view.request_redraw();
+        true
+    }
+
+    /// Prefetching would only reach the network, so it is off while working offline.
+    fn prefetch_enabled(&self) -> bool {
+        self.preferences.prefetch && !self.preferences.work_offline
+    }
+
+    /// Hovering is not a decision to visit, so only links on the page's own origin are
+    /// prefetched with the user's cookies.
+    fn handle_hover_settled(&mut self, url: Url) {
+        if self.hovered_link.as_ref() == Some(&url)
+            && self.prefetch_enabled()
+            && self.prefetch_scope.is_same_origin(&url)
+        {
+            self.prefetcher.prefetch(
+                &self.handle,
+                url,
+                &self.prefetch_scope,
+                Arc::clone(&self.net_provider),
+            );
+        }
+    }
+
+    fn log_script_summary(&self, base_url: &str, summary: &ScriptExecutionSummary) {
+        info!(
+            target = "quickjs",
+            url = %base_url,
+            scripts = summary.executed_scripts,
+            dom_mutations = summary.dom_mutations,
+            bytecode_hits = summary.compile.cache_hits,
+            bytecode_misses = summary.compile.cache_misses,
+            compile_ms = summary.compile.compile_ms,
+            bytecode_load_ms = summary.compile.load_ms,
+            "executed blocking inline scripts"
+        );
+    }
+
+    fn window_mut(&mut self) -> &mut View<R> {
+        self.inner
+            .windows
+            .values_mut()
+            .next()
+            .expect("window available")
+    }
+
+    fn build_document_with_chrome(&self, contents: &str, base_url: &str) -> HtmlDocument {
+        let identity = Url::parse(base_url)
+            .ok()
+            .and_then(|url| {
+                self.identities
+                    .for_origin(&url.origin().ascii_serialization())
+            })
+            .map(|identity| identity.label.as_str());
+        let notice = self.chrome_notice.as_ref().map(|notice| {
+            format!(
+                "<div id=\"chrome-notice\" role=\"status\">{}</div>",
+                encode_text(notice)
+            )
+        });
+        let contents = match notice {
+            Some(notice) => format!("{notice}{contents}"),
+            None => contents.to_string(),
+        };
+        let hiding = Url::parse(base_url)
+            .ok()
+            .and_then(|url| self.blocklist.hiding_css(&url));
+        let contents = match hiding {
+            Some(css) => format!("<style id=\"frontier-element-hiding\">{css}</style>{contents}"),
+            None => contents,
+        };
+        let html = if self.kiosk.is_some() {
+            wrap_for_kiosk(&contents, &self.current_input, None)
+        } else {
+            let menu = self.app_menu.chrome_html();
+            let site_info = Url::parse(base_url)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https" | "file"))
+                .map(|url| {
+                    let origin = url.origin().ascii_serialization();
+                    let label = if url.scheme() == "file" {
+                        "Local files"
+                    } else {
+                        origin.as_str()
+                    };
+                    site_info_html(label, &self.permissions.blocked(&origin))
+                });
+            wrap_with_chrome(
+                &contents,
+                &self.current_input,
+                None,
+                identity,
+                menu.as_deref(),
+                site_info.as_deref(),
+            )
+        };
+        HtmlDocument::from_html(
+            &html,
+            DocumentConfig {
+                base_url: Some(base_url.to_string()),
+                ua_stylesheets: None,
+                net_provider: Some(self.subresources.clone()),
+                navigation_provider: Some(self.navigation_provider.clone()),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn render_current_document(&mut self, retain_scroll: bool) {
+        if self.current_document.is_none() {
+            return;
+        }
+
+        if self.pending_document_reset {
+            let (base_url, contents) = {
+                let current = self
+                    .current_document
+                    .as_ref()
+                    .expect("current_document must be set");
+                (current.base_url.clone(), current.contents.clone())
+            };
+
+            let mut doc = self
+                .prepared_document
+                .take()
+                .unwrap_or_else(|| self.build_document_with_chrome(&contents, &base_url));
+
+            if self.chrome_handles.is_none() {
+                match DocumentChromeHandles::compute(&mut doc) {
+                    Ok(handles) => {
+                        self.chrome_handles = Some(handles);
+                        self.blocked_count_shown = None;
+                    }
+                    Err(err) => {
+                        error!(
+                            target = "quickjs",
+                            url = %base_url,
+                            error = %err,
+                            "failed to compute chrome handles"
+                        );
+                    }
+                }
+            }
+
+            let load_trace = self.load_trace.clone();
+            let boxed_document: Box<dyn Document> =
+                if let Some(runtime) = self.current_js_runtime.as_mut() {
+                    let boxed = RuntimeDocument::attach(doc, runtime);
+                    // Run blocking scripts now that document is attached
+                    match load_trace.time(Phase::Scripts, || runtime.run_blocking_scripts()) {
+                        Ok(Some(summary)) => {
+                            self.log_script_summary(&base_url, &summary);
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            error!(
+                                target = "quickjs",
+                                url = %base_url,
+                                error = %err,
+                                "failed to execute blocking scripts"
+                            );
+                        }
+                    }
+                    boxed
+                } else {
+                    Box::new(doc)
+                };
+
+            self.window_mut()
+                .replace_document(boxed_document, retain_scroll);
+
+            self.pending_document_reset = false;
+            self.awaiting_first_paint = true;
+            self.maybe_fire_load();
+            return;
+        }
+
+        {
+            let view = self.window_mut();
+            view.poll();
+            view.request_redraw();
+        }
+    }
+
+    /// Fire `load` once the page's blocking scripts ran and its subresources settled.
+    fn maybe_fire_load(&mut self) {
+        if self.load_fired || self.pending_document_reset || !self.subresources.is_idle() {
+            return;
+        }
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            self.load_fired = true;
+            runtime.dispatch_lifecycle(LifecycleEvent::Load);
+        }
+    }
+
+    /// Size of the first window's content area in CSS pixels, and its device pixel ratio: the
+    /// scale factor (or the one automation emulates) times the page zoom.
+    fn window_viewport(&self) -> Option<ViewportMetrics> {
+        let view = self.inner.windows.values().next()?;
+        let scale = view.window.scale_factor();
+        let doc: &BaseDocument = &view.doc;
+        let zoom = f64::from(doc.viewport().zoom());
+        let size = view.window.inner_size().to_logical::<f64>(scale);
+        Some(ViewportMetrics::new(
+            size.width / zoom,
+            size.height / zoom,
+            self.emulated_scale.unwrap_or(scale) * zoom,
+        ))
+    }
+
+    /// Push the current window metrics to page script after blitz relaid out the document.
+    fn sync_viewport(&self) {
+        let Some(metrics) = self.window_viewport() else {
+            return;
+        };
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            runtime.set_viewport(metrics);
+        }
+    }
+
+    /// Effective colour scheme (OS theme or the Cmd+T override) and motion preference.
+    fn media_preferences(&self) -> Option<MediaPreferences> {
+        let view = self.inner.windows.values().next()?;
+        let color_scheme = match view.current_theme() {
+            Theme::Light => ColorScheme::Light,
+            Theme::Dark => ColorScheme::Dark,
+        };
+        Some(MediaPreferences {
+            color_scheme,
+            reduced_motion: self.reduced_motion,
+        })
+    }
+
+    fn sync_media_preferences(&self) {
+        let Some(preferences) = self.media_preferences() else {
+            return;
+        };
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            runtime.set_media_preferences(preferences);
+        }
+    }
+
+    /// Send the emulated user agent with the window's subresource loads and the page's
+    /// `fetch()`, and show it to `navigator.userAgent`.
+    fn sync_user_agent(&self) {
+        self.subresources
+            .set_user_agent(self.emulated_user_agent.clone());
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            runtime.set_user_agent(self.emulated_user_agent.clone());
+        }
+    }
+
+    /// Run `beforeunload` before a user-initiated navigation. There is no modal dialog yet, so
+    /// a page that asks for confirmation blocks the first attempt and the user confirms by
+    /// repeating the navigation.
+    fn confirm_unload(&mut self) -> bool {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return true;
+        };
+        if self.unload_prompted || !runtime.dispatch_lifecycle(LifecycleEvent::BeforeUnload) {
+            return true;
+        }
+        self.unload_prompted = true;
+        warn!(
+            target = "quickjs",
+            url = %self.current_input,
+            "page asked to confirm leaving; repeat the navigation to leave"
+        );
+        false
+    }
+
+    fn reload_document(&mut self, retain_scroll: bool) {
+        let input = self.current_input.clone();
+        self.spawn_navigation(input, retain_scroll);
+    }
+
+    /// Start a navigation, aborting any that is still loading. Aborting the superseded task
+    /// drops its fetch future, so a late response from the net provider has no receiver and
+    /// is discarded; anything that still slips through is filtered by generation.
+    fn spawn_navigation(&mut self, input: String, retain_scroll: bool) {
+        // Taken before the page being left is torn down, since that is what it describes.
+        if self.kiosk_refuses(&input) {
+            return;
+        }
+        let memory_report = (input == MEMORY_URL).then(|| self.memory_report());
+        self.subresources.cancel_pending();
+        self.network_log.reset();
+        let generation = self.navigations.begin();
+        self.load_trace = LoadTrace::start(generation, &input);
+        self.awaiting_first_paint = false;
+        self.chrome_notice = None;
+        if relays::uses_relays(&input) {
+            self.start_relays();
+        }
+        if input == SETTINGS_URL {
+            self.show_settings(retain_scroll);
+            return;
+        }
+        if input == RELAYS_URL {
+            self.spawn_relays_page(retain_scroll);
+            return;
+        }
+        if input == PUBLISH_QUEUE_URL {
+            self.spawn_publish_queue_page(retain_scroll);
+            return;
+        }
+        if let Some(query) = quick_open::query_from_input(&input) {
+            self.show_quick_open(&query, retain_scroll);
+            return;
+        }
+        if let Some(term) = name_search::term_from_input(&input) {
+            self.spawn_names_page(term, retain_scroll);
+            return;
+        }
+        if input == IDENTITIES_URL {
+            self.show_identities(retain_scroll);
+            return;
+        }
+        if input == SIGNATURES_URL {
+            self.show_signatures(retain_scroll);
+            return;
+        }
+        if input == RESTORE_URL {
+            self.show_restore(retain_scroll);
+            return;
+        }
+        if let Some(report) = memory_report {
+            self.show_memory(&report, retain_scroll);
+            return;
+        }
+        if input == VERSION_URL {
+            self.show_version(retain_scroll);
+            return;
+        }
+        if input == GPU_URL {
+            self.spawn_gpu_page(retain_scroll);
+            return;
+        }
+        let net_provider = Arc::clone(&self.net_provider);
+        let proxy = self.inner.proxy.clone();
+        let options = FetchTaskOptions {
+            cache: self.page_cache.clone(),
+            work_offline: self.preferences.work_offline,
+            prefetcher: self
+                .preferences
+                .prefetch
+                .then(|| Arc::clone(&self.prefetcher)),
+            network_log: self.network_log.clone(),
+            load_trace: self.load_trace.clone(),
+        };
+        let search = self.preferences.search.clone();
+        let user_agent = self.emulated_user_agent.clone();
+
+        let task = self.handle.spawn(async move {
+            let plan = options
+                .load_trace
+                .time_async(Phase::Resolve, prepare_navigation(&input, &search))
+                .await;
+            match plan {
+                Ok(NavigationPlan::Fetch(mut request)) => {
+                    request.user_agent = user_agent;
+                    let proxy_clone = proxy.clone();
+                    run_fetch_task(
+                        request,
+                        net_provider,
+                        proxy_clone,
+                        retain_scroll,
+                        generation,
+                        options,
+                    )
+                    .await;
+                }
+                Err(err) => {
+                    let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Failed {
+                        message: err.to_string(),
+                        generation,
+                    }));
+                    let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+                }
+            }
+        });
+        self.navigations.track(task);
+    }
+
+    fn handle_navigation_message(&mut self, message: NavigationMessage) {
+        let generation = match &message {
+            NavigationMessage::Completed { generation, .. }
+            | NavigationMessage::Failed { generation, .. }
+            | NavigationMessage::Offline { generation, .. } => *generation,
+        };
+        if !self.navigations.finish(generation) {
+            info!(
+                generation,
+                current = self.navigations.generation(),
+                "ignoring result of superseded navigation"
+            );
+            return;
+        }
+
+        match message {
+            NavigationMessage::Completed {
                 document,
                 retain_scroll,
-            } => {
-                self.set_document(*document);
-                self.render_current_document(retain_scroll);
+                ..
+            } => {
+                // A redirect can end up where the kiosk may not go.
+                if self.kiosk_refuses(&document.base_url) {
+                    self.show_error(&format!("{} is not part of this kiosk", document.base_url));
+                    return;
+                }
+                self.set_document(*document);
+                self.render_current_document(retain_scroll);
+            }
+            NavigationMessage::Failed { message, .. } => {
+                self.show_error(&message);
+            }
+            NavigationMessage::Offline {
+                input,
+                url,
+                reason,
+                cached,
+                retain_scroll,
+                ..
+            } => {
+                if !self.preferences.work_offline {
+                    self.watch_connectivity(input, url);
+                }
+                match cached {
+                    Some(cached) => {
+                        self.chrome_notice = Some(format!(
+                            "Offline — showing cached copy from {}",
+                            format_timestamp(cached.fetched_at)
+                        ));
+                        self.set_document(cached.document);
+                        self.render_current_document(retain_scroll);
+                    }
+                    None => self.show_error(&format!("Offline and no cached copy: {reason}")),
+                }
+            }
+        }
+    }
+
+    /// Probe `url` until it answers, then retry it if the user is still on it.
+    fn watch_connectivity(&mut self, input: String, url: Url) {
+        self.offline_retry = Some(input);
+        if self.connectivity_probe.is_some() {
+            return;
+        }
+        let proxy = self.inner.proxy.clone();
+        info!(url = %url, "network unreachable; retrying when connectivity returns");
+        self.connectivity_probe = Some(self.handle.spawn(async move {
+            let client = match crate::http_client::shared() {
+                Ok(client) => client,
+                Err(err) => {
+                    warn!(error = %err, "cannot probe connectivity");
+                    return;
+                }
+            };
+            loop {
+                tokio::time::sleep(CONNECTIVITY_PROBE_INTERVAL).await;
+                if client.head(url.clone()).send().await.is_ok() {
+                    let event = ReadmeEvent::ConnectivityRestored;
+                    let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+                    return;
+                }
+            }
+        }));
+    }
+
+    fn handle_connectivity_restored(&mut self) {
+        self.connectivity_probe = None;
+        let Some(input) = self.offline_retry.take() else {
+            return;
+        };
+        if input == self.current_input && !self.preferences.work_offline {
+            info!(url = %input, "connectivity restored; reloading");
+            self.reload_document(true);
+        }
+    }
+
+    fn show_error(&mut self, message: &str) {
+        let escaped = encode_text(message);
+        let html = format!(
+            "<section class=\"error\"><h2>Navigation failed</h2><p>{escaped}</p></section>"
+        );
+        let document = FetchedDocument {
+            base_url: "about:error".into(),
+            contents: html,
+            file_path: None,
+            display_url: self.current_input.clone(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(false);
+    }
+
+    fn show_settings(&mut self, retain_scroll: bool) {
+        let html = self.preferences.settings_page(
+            self.preferences_path.as_deref(),
+            self.settings_status.take().as_deref(),
+        );
+        let document = FetchedDocument {
+            base_url: SETTINGS_URL.into(),
+            contents: html,
+            file_path: None,
+            display_url: SETTINGS_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    /// Apply a `frontier://settings?...` form submission to the running window and persist it.
+    fn apply_settings_form(&mut self, query: &str) {
+        let rejected = self.preferences.apply_form(query);
+        self.apply_theme_preference();
+        self.smooth_scroll
+            .set_easing(self.preferences.scroll_easing);
+        self.sync_background();
+        privacy::configure(self.preferences.privacy);
+        let cache_size = self.preferences.cache_size_bytes();
+        self.page_cache = self
+            .page_cache
+            .take()
+            .map(|cache| cache.with_limit(cache_size));
+        let saved = match self.preferences_path.as_deref() {
+            Some(path) => self.preferences.save(path),
+            None => Ok(()),
+        };
+        let status = match saved {
+            Err(err) => {
+                warn!(error = %err, "failed to save preferences");
+                format!("Could not save preferences: {err}")
+            }
+            Ok(()) if rejected.is_empty() => "Preferences saved.".to_string(),
+            Ok(()) => format!("Saved; ignored invalid {}.", rejected.join(", ")),
+        };
+        self.settings_status = Some(status);
+    }
+
+    fn show_quick_open(&mut self, query: &str, retain_scroll: bool) {
+        let html = quick_open::quick_open_page(query, &self.quick_open.search(query));
+        let display_url = quick_open::quick_open_url(query);
+        let document = FetchedDocument {
+            base_url: QUICK_OPEN_URL.into(),
+            contents: html,
+            file_path: None,
+            display_url,
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    fn show_identities(&mut self, retain_scroll: bool) {
+        let unlocked: Vec<String> = self.unlocked_identities.keys().cloned().collect();
+        let html = self
+            .identities
+            .page(self.identities_status.take().as_deref(), &unlocked);
+        let document = FetchedDocument {
+            base_url: IDENTITIES_URL.into(),
+            contents: html,
+            file_path: None,
+            display_url: IDENTITIES_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    /// Apply a `frontier://identities?...` form submission and persist the store. Unlocking
+    /// and locking only touch the in-memory keys.
+    fn apply_identities_form(&mut self, query: &str) {
+        let field = |name: &str| {
+            ::url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_default()
+        };
+        match field("action").as_str() {
+            "unlock" => {
+                let npub = field("npub");
+                let status = match self.identities.unlock(&npub, &field("password")) {
+                    Ok(keys) => {
+                        self.unlocked_identities.insert(npub.clone(), keys);
+                        format!("Unlocked {npub} until the browser closes.")
+                    }
+                    Err(err) => err.to_string(),
+                };
+                self.identities_status = Some(status);
+                return;
+            }
+            "lock" => {
+                let npub = field("npub");
+                self.unlocked_identities.remove(&npub);
+                self.identities_status = Some(format!("Locked {npub}."));
+                return;
+            }
+            _ => {}
+        }
+
+        let mut identities = self.identities.clone();
+        let status = match identities.apply_form(query) {
+            Ok(status) => {
+                let saved = match self.identities_path.as_deref() {
+                    Some(path) => identities.save(path),
+                    None => Ok(()),
+                };
+                match saved {
+                    Ok(()) => {
+                        self.identities = identities;
+                        status
+                    }
+                    Err(err) => {
+                        warn!(error = %err, "failed to save identities");
+                        format!("Could not save identities: {err}")
+                    }
+                }
+            }
+            Err(err) => err.to_string(),
+        };
+        self.unlocked_identities
+            .retain(|npub, _| self.identities.get(npub).is_some());
+        self.identities_status = Some(status);
+    }
+
+    fn show_signatures(&mut self, retain_scroll: bool) {
+        let document = FetchedDocument {
+            base_url: SIGNATURES_URL.into(),
+            contents: crate::signer::signatures_page(crate::signer::audit_log_path().as_deref()),
+            file_path: None,
+            display_url: SIGNATURES_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    fn show_restore(&mut self, retain_scroll: bool) {
+        let document = FetchedDocument {
+            base_url: RESTORE_URL.into(),
+            contents: crash_recovery::restore_page(crash_recovery::pending().as_ref()),
+            file_path: None,
+            display_url: RESTORE_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    /// Answer the restore page: reopen the saved session with its history, or start at the
+    /// homepage. Either way the recovery file is consumed.
+    fn apply_restore_action(&mut self, query: &str) {
+        let restore = ::url::form_urlencoded::parse(query.as_bytes())
+            .any(|(key, value)| key == "action" && value == "restore");
+        let snapshot = crash_recovery::pending();
+        crash_recovery::discard();
+        let target = match snapshot {
+            Some(snapshot) if restore => {
+                self.back_history = snapshot.back_history.clone();
+                self.forward_history = snapshot.forward_history.clone();
+                let target = snapshot.url.clone();
+                self.pending_restore = Some(snapshot);
+                target
+            }
+            _ => {
+                self.back_history.clear();
+                self.forward_history.clear();
+                self.preferences.homepage.clone()
+            }
+        };
+        self.current_input = target.clone();
+        self.spawn_navigation(target, false);
+    }
+
+    /// Refresh the session the panic hook saves, at most every [`SESSION_RECORD_INTERVAL`].
+    fn record_session(&mut self) {
+        if self.is_popup
+            || self.pending_document_reset
+            || self.current_input == RESTORE_URL
+            || self
+                .session_recorded_at
+                .is_some_and(|recorded| recorded.elapsed() < SESSION_RECORD_INTERVAL)
+        {
+            return;
+        }
+        let Some(view) = self.inner.windows.values().next() else {
+            return;
+        };
+        let doc: &BaseDocument = &view.doc;
+        let scroll = doc.viewport_scroll();
+        crash_recovery::record(SessionSnapshot {
+            url: self.current_input.clone(),
+            scroll_x: scroll.x,
+            scroll_y: scroll.y,
+            back_history: self.back_history.clone(),
+            forward_history: self.forward_history.clone(),
+            form_fields: crash_recovery::collect_form_fields(doc),
+        });
+        self.session_recorded_at = Some(Instant::now());
+    }
+
+    /// Put back the scroll position and form values of a restored session once its page has
+    /// painted and its subresources settled, so the layout they refer to exists.
+    fn apply_pending_restore(&mut self) {
+        let ready = !self.pending_document_reset
+            && !self.awaiting_first_paint
+            && self.subresources.is_idle();
+        let restored_page = match (&self.pending_restore, &self.current_document) {
+            (Some(snapshot), Some(document)) => document.display_url == snapshot.url,
+            _ => false,
+        };
+        if !ready || !restored_page {
+            return;
+        }
+        let Some(snapshot) = self.pending_restore.take() else {
+            return;
+        };
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return;
+        };
+        {
+            let doc: &mut BaseDocument = &mut view.doc;
+            crash_recovery::restore_form_fields(doc, &snapshot.form_fields);
+            let mut scroll = doc.viewport_scroll();
+            scroll.x = snapshot.scroll_x;
+            scroll.y = snapshot.scroll_y;
+            doc.set_viewport_scroll(scroll);
+        }
+        view.request_redraw();
+    }
+
+    fn show_memory(&mut self, report: &MemoryReport, retain_scroll: bool) {
+        let document = FetchedDocument {
+            base_url: MEMORY_URL.into(),
+            contents: crate::diagnostics::memory_page(report),
+            file_path: None,
+            display_url: MEMORY_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    fn show_version(&mut self, retain_scroll: bool) {
+        let document = FetchedDocument {
+            base_url: VERSION_URL.into(),
+            contents: crate::diagnostics::version_page(crate::renderer::active()),
+            file_path: None,
+            display_url: VERSION_URL.into(),
+            scripts: Vec::new(),
+            csp_headers: Vec::new(),
+            response: None,
+        };
+        self.set_document(document);
+        self.render_current_document(retain_scroll);
+    }
+
+    /// Build `frontier://gpu` off the UI thread, since listing adapters asks every driver.
+    fn spawn_gpu_page(&mut self, retain_scroll: bool) {
+        let generation = self.navigations.generation();
+        let proxy = self.inner.proxy.clone();
+
+        let task = self.handle.spawn(async move {
+            let adapters = tokio::task::spawn_blocking(crate::renderer::adapters)
+                .await
+                .unwrap_or_else(|err| Err(format!("the adapter query failed: {err}")));
+            let document = FetchedDocument {
+                base_url: GPU_URL.into(),
+                contents: crate::diagnostics::gpu_page(crate::renderer::active(), &adapters),
+                file_path: None,
+                display_url: GPU_URL.into(),
+                scripts: Vec::new(),
+                csp_headers: Vec::new(),
+                response: None,
+            };
+            let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Completed {
+                document: Box::new(document),
+                retain_scroll,
+                generation,
+            }));
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
+        self.navigations.track(task);
+    }
+
+    /// What the current page and the caches around it hold.
+    fn memory_report(&self) -> MemoryReport {
+        let (prefetched_documents, prefetched_bytes) = self.prefetcher.held();
+        let mut report = MemoryReport {
+            url: self.current_input.clone(),
+            js_heap: self
+                .current_js_runtime
+                .as_ref()
+                .map(|runtime| runtime.heap_usage()),
+            dom_nodes: self
+                .inner
+                .windows
+                .values()
+                .next()
+                .map(|view| {
+                    let doc: &BaseDocument = &view.doc;
+                    MemoryReport::dom_nodes(doc)
+                })
+                .unwrap_or_default(),
+            prefetched_documents,
+            prefetched_bytes,
+            origins: Isolation::shared().usage(),
+            ..Default::default()
+        };
+        report.record_assets(&self.network_log.entries());
+        report
+    }
+
+    fn current_origin(&self) -> String {
+        self.page_origin().ascii_serialization()
+    }
+
+    /// The site settings of the page in the window.
+    fn content_rules(&self) -> ContentRules {
+        self.current_document
+            .as_ref()
+            .and_then(|document| Url::parse(&document.base_url).ok())
+            .map(|url| self.permissions.content_rules(&url))
+            .unwrap_or_default()
+    }
+
+    fn page_origin(&self) -> Origin {
+        self.current_document
+            .as_ref()
+            .and_then(|document| Url::parse(&document.base_url).ok())
+            .unwrap_or_else(|| Url::parse("about:blank").expect("static url"))
+            .origin()
+    }
+
+    /// Start loading the iframes of a document that just went into the window.
+    fn load_frames(&mut self) {
+        if !self.frames_pending || self.pending_document_reset {
+            return;
+        }
+        let Some(base_url) = self
+            .current_document
+            .as_ref()
+            .map(|document| document.base_url.clone())
+        else {
+            return;
+        };
+        let Some(view) = self.inner.windows.values().next() else {
+            return;
+        };
+        self.frames_pending = false;
+        let sources = {
+            let doc: &BaseDocument = &view.doc;
+            frames::collect_frames(doc, &base_url)
+        };
+        if sources.is_empty() {
+            return;
+        }
+        let waker = FrameWaker(Mutex::new(self.inner.proxy.clone()));
+        self.frames
+            .set_waker(futures_util::task::waker(Arc::new(waker)));
+        let generation = self.navigations.generation();
+        let content_rules = self.content_rules();
+        let page_url = Url::parse(&base_url).ok();
+        for source in sources {
+            match source.request.clone() {
+                FrameRequest::Srcdoc(html) => {
+                    let document = frames::srcdoc_document(&base_url, html);
+                    self.attach_frame(source, document);
+                }
+                FrameRequest::Url(url) => {
+                    if let Some(setting) = content_rules.blocks(&url, false) {
+                        let setting = setting.name();
+                        warn!(url = %url, setting, "iframe blocked by site settings");
+                        continue;
+                    }
+                    if let Some(rule) = self.blocklist.blocking_rule(&url, page_url.as_ref()) {
+                        warn!(url = %url, rule, "iframe blocked by filter");
+                        continue;
+                    }
+                    let request = FetchRequest {
+                        source: FetchSource::Url(url.clone()),
+                        display_url: url.to_string(),
+                        user_agent: self.emulated_user_agent.clone(),
+                    };
+                    let net_provider = Arc::clone(&self.net_provider);
+                    let network_log = self.network_log.clone();
+                    let proxy = self.inner.proxy.clone();
+                    let task = self.handle.spawn(async move {
+                        let result = execute_fetch_logged(&request, net_provider, &network_log)
+                            .await
+                            .map(Box::new)
+                            .map_err(|err| err.to_string());
+                        let event = ReadmeEvent::FrameLoaded {
+                            source,
+                            result,
+                            generation,
+                        };
+                        let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+                    });
+                    self.frames.track_load(task);
+                }
+            }
+        }
+    }
+
+    fn handle_frame_loaded(
+        &mut self,
+        source: FrameSource,
+        result: Result<Box<FetchedDocument>, String>,
+        generation: u64,
+    ) {
+        if generation != self.navigations.generation() {
+            return;
+        }
+        match result {
+            Ok(document) => self.attach_frame(source, *document),
+            Err(err) => warn!(frame = source.node_id, error = %err, "failed to load iframe"),
+        }
+    }
+
+    fn attach_frame(&mut self, source: FrameSource, document: FetchedDocument) {
+        let content_rules = self.content_rules();
+        let options = FrameOptions {
+            javascript: self.preferences.javascript_enabled && content_rules.allows_javascript(),
+            scale: self
+                .window_viewport()
+                .map(|metrics| metrics.device_pixel_ratio)
+                .unwrap_or(1.0),
+            network_log: self.network_log.clone(),
+            content_rules,
+            blocklist: Arc::clone(&self.blocklist),
+            virtual_clock: self.virtual_clock.clone(),
+        };
+        let parent_origin = self.page_origin();
+        self.frames
+            .attach(source, document, &parent_origin, &options);
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            self.frames.expose_to(runtime);
+        }
+        self.service_frames();
+    }
+
+    /// Deliver `postMessage` traffic, run the frames' async work if their waker fired and
+    /// copy frames whose DOM changed into the page.
+    fn service_frames(&mut self) {
+        let parent_origin = self.page_origin();
+        let (popup_messages, parent_messages): (Vec<_>, Vec<_>) = self
+            .current_js_runtime
+            .as_ref()
+            .map(JsPageRuntime::take_posted_messages)
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|message| matches!(message.target, WindowRef::Popup(_) | WindowRef::Opener));
+        for message in popup_messages {
+            self.post_to_popup_window(message);
+        }
+        let delivered = self.frames.route_messages(
+            self.current_js_runtime.as_ref(),
+            parent_messages,
+            &parent_origin,
+        );
+        let woken = std::mem::take(&mut self.frames_woken);
+        let pumped = (woken || delivered) && self.frames.pump();
+        if self.pending_document_reset {
+            return;
+        }
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return;
+        };
+        let rendered = {
+            let doc: &mut BaseDocument = &mut view.doc;
+            self.frames.render_into(doc)
+        };
+        if delivered || pumped || rendered {
+            view.request_redraw();
+        }
+    }
+
+    /// Send `message` to a popup or the opener. The receiving process stamps it with the
+    /// origin it last saw this window load.
+    fn post_to_popup_window(&mut self, message: PostedMessage) {
+        let wire = WireMessage {
+            data: message.data,
+            target_origin: message.target_origin,
+        };
+        match message.target {
+            WindowRef::Popup(id) => {
+                if !self.popups.post(id, &wire) {
+                    warn!(popup = id, "postMessage to a popup that is not open");
+                }
+            }
+            WindowRef::Opener if self.is_popup => popups::post_to_opener(&wire),
+            _ => {}
+        }
+    }
+
+    /// Hand a message from a popup or opener process whose page has the serialized `origin`
+    /// to the page, if its `targetOrigin` allows it.
+    fn deliver_window_message(&mut self, origin: &str, message: &WireMessage, source: WindowRef) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let sender = Url::parse(origin)
+            .map(|url| url.origin())
+            .unwrap_or_else(|_| Url::parse("about:blank").expect("static url").origin());
+        let receiver = self.page_origin();
+        if !target_origin_allows(&message.target_origin, &sender, &receiver) {
+            warn!(
+                target = "quickjs",
+                target_origin = %message.target_origin,
+                recipient = %receiver.ascii_serialization(),
+                "postMessage target origin does not match the recipient window's origin"
+            );
+            return;
+        }
+        runtime.deliver_message(&message.data, origin, source);
+        if let Some(view) = self.inner.windows.values_mut().next() {
+            view.request_redraw();
+        }
+    }
+
+    fn handle_popup_event(&mut self, event: &PopupEvent) {
+        match event {
+            PopupEvent::Message {
+                id,
+                origin,
+                message,
+            } => self.deliver_window_message(origin, message, WindowRef::Popup(*id)),
+            PopupEvent::Closed { id } => {
+                self.popups.exited(*id);
+                if let Some(runtime) = self.current_js_runtime.as_ref() {
+                    runtime.set_popup_closed(*id);
+                }
+            }
+        }
+    }
+
+    /// Carry out the page's `window.open` and `close()` calls. Calls that target a new
+    /// window without a user gesture were already refused to the page; they are only logged.
+    fn process_window_requests(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let requests = runtime.take_window_requests();
+        for request in requests {
+            match request {
+                WindowRequest::Open { url, target, .. } if targets_current_window(&target) => {
+                    let Some(view) = self.inner.windows.values().next() else {
+                        continue;
+                    };
+                    let doc: &BaseDocument = &view.doc;
+                    let options = NavigationOptions::new(url, String::from("text/html"), doc.id());
+                    self.navigate_from(options, NavigationSource::Page);
+                }
+                WindowRequest::Open {
+                    id,
+                    url,
+                    target,
+                    user_activated,
+                } => self.open_popup(id, url, target, user_activated),
+                WindowRequest::Close { id } => self.popups.close(id),
+                WindowRequest::CloseSelf if self.is_popup => event_loop.exit(),
+                WindowRequest::CloseSelf => {
+                    info!("ignoring window.close() from a window not opened by script")
+                }
+            }
+        }
+    }
+
+    fn open_popup(&mut self, id: u32, url: Url, target: String, user_activated: bool) {
+        let mut record = PopupRecord {
+            url: url.to_string(),
+            target,
+            opener: self.current_origin(),
+            blocked: !user_activated,
+            reason: None,
+        };
+        if !user_activated {
+            info!(url = %url, "blocked popup opened without a user gesture");
+            record.reason =
+                Some("window.open was not called in response to a click or key press".to_string());
+        } else if self.kiosk.is_some() {
+            info!(url = %url, "blocked popup in kiosk mode");
+            record.blocked = true;
+            record.reason = Some("kiosk mode opens no other windows".to_string());
+            if let Some(runtime) = self.current_js_runtime.as_ref() {
+                runtime.set_popup_closed(id);
+            }
+        } else if self.automation.is_none() {
+            // Automation sessions only record popups; they drive a single window.
+            let proxy = self.inner.proxy.clone();
+            let launched = self.popups.launch(id, &url, &record.opener, move |event| {
+                let event = ReadmeEvent::Popup(event);
+                let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+            });
+            if let Err(err) = launched {
+                warn!(url = %url, error = %err, "failed to open popup");
+                record.reason = Some(format!("{err:#}"));
+                if let Some(runtime) = self.current_js_runtime.as_ref() {
+                    runtime.set_popup_closed(id);
+                }
+            }
+        }
+        self.popups.record(record);
+    }
+
+    /// Answer the page's pending `window.nostr` calls.
+    fn process_signer_requests(&mut self) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let requests = runtime.take_signer_requests();
+        if requests.is_empty() {
+            return;
+        }
+        let origin = self.current_origin();
+        for request in requests {
+            let result = self.answer_signer_request(&origin, request.method);
+            if let Some(runtime) = self.current_js_runtime.as_ref() {
+                runtime.settle_signer_request(request.id, result);
+            }
+        }
+        if let Some(view) = self.inner.windows.values_mut().next() {
+            view.request_redraw();
+        }
+    }
+
+    fn answer_signer_request(
+        &mut self,
+        origin: &str,
+        method: SignerMethod,
+    ) -> Result<serde_json::Value, String> {
+        let identity = self
+            .identities
+            .for_origin(origin)
+            .cloned()
+            .ok_or_else(|| format!("no identity for {origin}; add one in {IDENTITIES_URL}"))?;
+        let template = match method {
+            SignerMethod::GetPublicKey => {
+                if !self.may_share_public_key(origin, &identity.label) {
+                    return Err("the user declined to share their public key".to_string());
+                }
+                let public_key =
+                    PublicKey::from_bech32(&identity.npub).map_err(|err| err.to_string())?;
+                return Ok(serde_json::Value::String(public_key.to_hex()));
+            }
+            SignerMethod::SignEvent(template) => template,
+        };
+
+        let result = match self.unlocked_identities.get(&identity.npub).cloned() {
+            None if !identity.can_sign() => Err(format!("{} is read-only", identity.label)),
+            None => Err(format!(
+                "{} is locked; unlock it in {IDENTITIES_URL}",
+                identity.label
+            )),
+            Some(keys) => {
+                let outcome = if self.permissions.allows_signing(origin, template.kind) {
+                    SignatureOutcome::SignedByRule
+                } else if self.automation.is_some() {
+                    // Nobody is at the keyboard to answer a dialog.
+                    SignatureOutcome::Denied
+                } else {
+                    match crate::signer::prompt(origin, &identity.label, &template) {
+                        PromptDecision::Sign => SignatureOutcome::Signed,
+                        PromptDecision::AlwaysAllowKind => {
+                            self.permissions.allow_signing(origin, template.kind);
+                            self.save_permissions();
+                            SignatureOutcome::Signed
+                        }
+                        PromptDecision::Deny => SignatureOutcome::Denied,
+                    }
+                };
+                if outcome == SignatureOutcome::Denied {
+                    self.record_signature(SignatureRecord::now(
+                        origin,
+                        Some(&identity.npub),
+                        template.kind,
+                        None,
+                        outcome,
+                    ));
+                    return Err("the user rejected the signature request".to_string());
+                }
+                crate::signer::sign(&template, &keys)
+                    .map(|event| (event, outcome))
+                    .map_err(|err| err.to_string())
+            }
+        };
+
+        match result {
+            Ok((event, outcome)) => {
+                let event_id = event["id"].as_str().map(str::to_string);
+                self.record_signature(SignatureRecord::now(
+                    origin,
+                    Some(&identity.npub),
+                    template.kind,
+                    event_id,
+                    outcome,
+                ));
+                Ok(event)
+            }
+            Err(message) => {
+                self.record_signature(SignatureRecord::now(
+                    origin,
+                    Some(&identity.npub),
+                    template.kind,
+                    None,
+                    SignatureOutcome::Failed,
+                ));
+                Err(message)
+            }
+        }
+    }
+
+    /// Whether `origin` may read the public key of `identity`, asking the user the first time
+    /// in a session unless a saved rule allows it.
+    fn may_share_public_key(&mut self, origin: &str, identity: &str) -> bool {
+        if self.permissions.allows_public_key(origin) || self.public_key_origins.contains(origin) {
+            return true;
+        }
+        if self.automation.is_some() {
+            // Nobody is at the keyboard to answer a dialog.
+            return false;
+        }
+        match crate::signer::prompt_public_key(origin, identity) {
+            KeyDecision::Share => {
+                self.public_key_origins.insert(origin.to_string());
+                true
+            }
+            KeyDecision::AlwaysShare => {
+                self.permissions.allow_public_key(origin);
+                self.save_permissions();
+                true
+            }
+            KeyDecision::Deny => false,
+        }
+    }
+
+    /// Carry out the page's `frontier.connect` and channel calls.
+    fn process_p2p_requests(&mut self) {
+        let Some(runtime) = self.current_js_runtime.as_ref() else {
+            return;
+        };
+        let requests = runtime.take_p2p_requests();
+        if requests.is_empty() {
+            return;
+        }
+        let origin = self.current_origin();
+        for request in requests {
+            match request {
+                P2pRequest::Connect { channel, peer } => {
+                    if let Err(reason) = self.connect_channel(&origin, channel, peer) {
+                        if let Some(runtime) = self.current_js_runtime.as_ref() {
+                            runtime.deliver_p2p_event(channel, &ChannelEvent::Closed(reason));
+                        }
+                    }
+                }
+                P2pRequest::Send { channel, data } => {
+                    if let Some(session) = &self.p2p {
+                        session.send(channel, data);
+                    }
+                }
+                P2pRequest::Close { channel } => {
+                    if let Some(session) = &self.p2p {
+                        session.close(channel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ask the user whether `origin` may open `channel` to `peer` as its identity, and start
+    /// connecting if so.
+    fn connect_channel(
+        &mut self,
+        origin: &str,
+        channel: u32,
+        peer: PublicKey,
+    ) -> Result<(), String> {
+        let identity = self
+            .identities
+            .for_origin(origin)
+            .cloned()
+            .ok_or_else(|| format!("no identity for {origin}; add one in {IDENTITIES_URL}"))?;
+        let keys = match self.unlocked_identities.get(&identity.npub) {
+            Some(keys) => keys.clone(),
+            None if !identity.can_sign() => return Err(format!("{} is read-only", identity.label)),
+            None => {
+                return Err(format!(
+                    "{} is locked; unlock it in {IDENTITIES_URL}",
+                    identity.label
+                ))
+            }
+        };
+        let npub = peer.to_bech32().map_err(|err| err.to_string())?;
+        let allowed = if self.permissions.allows_connecting(origin, &npub) {
+            true
+        } else if self.automation.is_some() {
+            // Nobody is at the keyboard to answer a dialog.
+            false
+        } else {
+            match p2p::prompt(origin, &identity.label, &npub) {
+                ConnectDecision::Allow => true,
+                ConnectDecision::AlwaysAllow => {
+                    self.permissions.allow_connecting(origin, &npub);
+                    self.save_permissions();
+                    true
+                }
+                ConnectDecision::Deny => false,
+            }
+        };
+        if !allowed {
+            return Err("the user rejected the connection".to_string());
+        }
+        info!(origin, peer = %npub, identity = %identity.npub, "opening channel");
+
+        if self
+            .p2p
+            .as_ref()
+            .is_none_or(|session| session.identity() != keys.public_key())
+        {
+            let proxy = self.inner.proxy.clone();
+            self.p2p = Some(P2pSession::start(
+                &self.handle,
+                keys,
+                origin.to_string(),
+                self.preferences.relays.clone(),
+                move |event| {
+                    let event = ReadmeEvent::P2p(event);
+                    let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+                },
+            ));
+        }
+        if let Some(session) = &self.p2p {
+            session.connect(channel, peer);
+        }
+        Ok(())
+    }
+
+    fn handle_p2p_event(&mut self, event: &P2pEvent) {
+        if self.p2p.as_ref().map(P2pSession::id) != Some(event.session) {
+            return;
+        }
+        if let Some(runtime) = self.current_js_runtime.as_ref() {
+            runtime.deliver_p2p_event(event.channel, &event.event);
+        }
+        if let Some(view) = self.inner.windows.values_mut().next() {
+            view.request_redraw();
+        }
+    }
+
+    /// Apply a `frontier://site-settings?toggle=...` link from the site-info dropdown to the
+    /// page in the window, persist it, and reload the page so it takes effect.
+    fn apply_site_setting(&mut self, query: &str) {
+        let setting = ::url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "toggle")
+            .and_then(|(_, value)| ContentSetting::parse(&value));
+        let Some(setting) = setting else {
+            warn!(query, "ignoring unknown site setting");
+            return;
+        };
+        let origin = self.current_origin();
+        let blocked = self.permissions.toggle_blocked(&origin, setting);
+        info!(origin = %origin, setting = setting.name(), blocked, "changed site setting");
+        self.save_permissions();
+        self.reload_document(true);
+    }
+
+    fn save_permissions(&self) {
+        if let Some(path) = self.permissions_path.as_deref() {
+            if let Err(err) = self.permissions.save(path) {
+                warn!(error = %err, "failed to save permissions");
+            }
+        }
+    }
+
+    fn record_signature(&self, record: SignatureRecord) {
+        let Some(path) = crate::signer::audit_log_path() else {
+            return;
+        };
+        if let Err(err) = crate::signer::record(&path, &record) {
+            warn!(error = %err, "failed to record signature request");
+        }
+    }
+
+    /// Build `frontier://relays` off the UI thread: run a pending NIP-65 import, or probe every
+    /// saved relay and render the page.
+    fn spawn_relays_page(&mut self, retain_scroll: bool) {
+        let generation = self.navigations.generation();
+        let proxy = self.inner.proxy.clone();
+        let saved = self.preferences.relays.clone();
+        let import = self.pending_relay_import.take();
+        let status = self.relays_status.take();
+
+        let task = self.handle.spawn(async move {
+            let event = match import {
+                Some(npub) => {
+                    let result = crate::relays::fetch_relay_list(&npub, &saved)
+                        .await
+                        .map_err(|err| err.to_string());
+                    ReadmeEvent::RelayListImported {
+                        npub,
+                        result,
+                        generation,
+                    }
+                }
+                None => {
+                    let entries = crate::relays::probe_all(&saved).await;
+                    let document = FetchedDocument {
+                        base_url: RELAYS_URL.into(),
+                        contents: crate::relays::relays_page(&entries, status.as_deref()),
+                        file_path: None,
+                        display_url: RELAYS_URL.into(),
+                        scripts: Vec::new(),
+                        csp_headers: Vec::new(),
+                        response: None,
+                    };
+                    ReadmeEvent::Navigation(Box::new(NavigationMessage::Completed {
+                        document: Box::new(document),
+                        retain_scroll,
+                        generation,
+                    }))
+                }
+            };
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
+        self.navigations.track(task);
+    }
+
+    /// Search the saved relays for names starting with `term` off the UI thread and show the
+    /// candidates.
+    fn spawn_names_page(&mut self, term: String, retain_scroll: bool) {
+        let generation = self.navigations.generation();
+        let proxy = self.inner.proxy.clone();
+        let saved = self.preferences.relays.clone();
+
+        let task = self.handle.spawn(async move {
+            let result = name_search::search_names(&term, &saved)
+                .await
+                .map_err(|err| err.to_string());
+            let url = name_search::names_url(&term);
+            let document = FetchedDocument {
+                base_url: url.clone(),
+                contents: name_search::names_page(&term, &result),
+                file_path: None,
+                display_url: url,
+                scripts: Vec::new(),
+                csp_headers: Vec::new(),
+                response: None,
+            };
+            let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Completed {
+                document: Box::new(document),
+                retain_scroll,
+                generation,
+            }));
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
+        self.navigations.track(task);
+    }
+
+    /// Build `frontier://publish-queue` off the UI thread, first sending every queued batch
+    /// when "Retry now" was chosen.
+    fn spawn_publish_queue_page(&mut self, retain_scroll: bool) {
+        let generation = self.navigations.generation();
+        let proxy = self.inner.proxy.clone();
+        let retry = std::mem::take(&mut self.publish_queue_retry);
+
+        let task = self.handle.spawn(async move {
+            let status = if retry {
+                Some(match publish_queue::flush(true).await {
+                    Ok(report) => format!(
+                        "Sent {} events; {} still waiting.",
+                        report.sent, report.waiting
+                    ),
+                    Err(err) => format!("Retry failed: {err:#}"),
+                })
+            } else {
+                None
+            };
+            let queue = PublishQueue::default_path()
+                .map(|path| PublishQueue::load(&path))
+                .unwrap_or_else(|| Ok(PublishQueue::default()))
+                .map_err(|err| format!("{err:#}"));
+            let document = FetchedDocument {
+                base_url: PUBLISH_QUEUE_URL.into(),
+                contents: publish_queue::queue_page(&queue, status.as_deref()),
+                file_path: None,
+                display_url: PUBLISH_QUEUE_URL.into(),
+                scripts: Vec::new(),
+                csp_headers: Vec::new(),
+                response: None,
+            };
+            let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Completed {
+                document: Box::new(document),
+                retain_scroll,
+                generation,
+            }));
+            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        });
+        self.navigations.track(task);
+    }
+
+    /// Apply a `frontier://relays?...` action. Imports run when the page loads.
+    fn apply_relay_action(&mut self, query: &str) {
+        let status = match RelayAction::parse(Some(query)) {
+            Ok(None) => return,
+            Ok(Some(RelayAction::Import(npub))) => {
+                self.pending_relay_import = Some(npub);
+                return;
+            }
+            Ok(Some(RelayAction::Add(relay))) => {
+                if self.preferences.relays.contains(&relay) {
+                    format!("{relay} is already saved.")
+                } else {
+                    self.preferences.relays.push(relay.clone());
+                    self.save_relays(format!("Added {relay}."))
+                }
             }
-            NavigationMessage::Failed { message } => {
-                self.show_error(&message);
+            Ok(Some(RelayAction::Remove(relay))) => {
+                self.preferences.relays.retain(|saved| saved != &relay);
+                self.save_relays(format!("Removed {relay}."))
             }
+            Err(message) => message,
+        };
+        self.relays_status = Some(status);
+    }
+
+    fn handle_relay_import(
+        &mut self,
+        npub: &str,
+        result: Result<Vec<Url>, String>,
+        generation: u64,
+    ) {
+        if !self.navigations.finish(generation) {
+            return;
         }
+        let status = match result {
+            Ok(imported) => {
+                let before = self.preferences.relays.len();
+                for relay in imported {
+                    if !self.preferences.relays.contains(&relay) {
+                        self.preferences.relays.push(relay);
+                    }
+                }
+                let added = self.preferences.relays.len() - before;
+                self.save_relays(format!("Imported {added} new relays from {npub}."))
+            }
+            Err(err) => format!("Could not import relays from {npub}: {err}"),
+        };
+        self.relays_status = Some(status);
+        self.spawn_navigation(RELAYS_URL.to_string(), true);
     }
 
-    fn show_error(&mut self, message: &str) {
-        let escaped = encode_text(message);
-        let html = format!(
-            "<section class=\"error\"><h2>Navigation failed</h2><p>{escaped}</p></section>"
-        );
-        let document = FetchedDocument {
-            base_url: "about:error".into(),
-            contents: html,
-            file_path: None,
-            display_url: self.current_input.clone(),
-            scripts: Vec::new(),
+    /// Persist `preferences.relays`, returning `success` or the save error for the status line.
+    fn save_relays(&self, success: String) -> String {
+        let Some(path) = self.preferences_path.as_deref() else {
+            return success;
         };
-        self.set_document(document);
-        self.render_current_document(false);
+        match self.preferences.save(path) {
+            Ok(()) => success,
+            Err(err) => {
+                warn!(error = %err, "failed to save relays");
+                format!("Could not save relays: {err}")
+            }
+        }
+    }
+
+    fn apply_theme_preference(&mut self) {
+        let theme = match self.preferences.theme {
+            ThemePreference::System => None,
+            ThemePreference::Light => Some(Theme::Light),
+            ThemePreference::Dark => Some(Theme::Dark),
+        };
+        let Some(view) = self.inner.windows.values_mut().next() else {
+            return;
+        };
+        view.set_theme_override(theme);
+        self.damage.invalidate();
+        self.sync_media_preferences();
     }
 
     fn toggle_theme(&mut self) {
@@ -408,28 +3289,119 @@ impl ReadmeApplication {
             Theme::Dark => Theme::Light,
         };
         window.set_theme_override(Some(new_theme));
+        self.damage.invalidate();
+        self.sync_media_preferences();
+    }
+
+    /// Where the navigation to `url` blitz just asked for came from: the chrome when the last
+    /// click was on a chrome link to exactly `url`, otherwise the page in the window.
+    fn navigation_source(&mut self, url: &Url) -> NavigationSource {
+        let clicked = self.clicked_node.take();
+        let from_chrome = match (
+            clicked,
+            &self.chrome_handles,
+            self.inner.windows.values().next(),
+        ) {
+            (Some(node), Some(handles), Some(view)) => {
+                chrome_link_at(&view.doc, &handles.links, node) == Some(url)
+            }
+            _ => false,
+        };
+        if from_chrome {
+            NavigationSource::Chrome
+        } else if self
+            .current_document
+            .as_ref()
+            .is_some_and(|document| document.base_url.starts_with("frontier://"))
+        {
+            NavigationSource::InternalPage
+        } else {
+            NavigationSource::Page
+        }
     }
 
     fn navigate(&mut self, options: NavigationOptions) {
+        let source = self.navigation_source(&options.url);
+        self.navigate_from(options, source);
+    }
+
+    fn navigate_from(&mut self, options: NavigationOptions, source: NavigationSource) {
         let url = options.url.clone();
         let url_str = url.to_string();
 
-        if url_str == "frontier://back" {
-            self.go_back();
+        if !source.may_open(&url) {
+            warn!(url = %url, ?source, "ignored a navigation to a browser action");
+            return;
+        }
+
+        // The chrome's buttons and menu run commands through the same event as the menu bar.
+        if let Some(command) = MenuCommand::from_url(&url) {
+            let event = ReadmeEvent::Command(command);
+            let _ = self
+                .inner
+                .proxy
+                .send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+            return;
+        }
+
+        if self.kiosk_refuses(&url_str) {
+            return;
+        }
+
+        if let Some(query) = url
+            .query()
+            .filter(|_| is_internal_page(&url_str, RESTORE_URL))
+        {
+            self.apply_restore_action(query);
+            return;
+        }
+
+        if let Some(query) = url
+            .query()
+            .filter(|_| is_internal_page(&url_str, SITE_SETTINGS_URL))
+        {
+            self.apply_site_setting(query);
             return;
         }
 
-        if url_str == "frontier://forward" {
-            self.go_forward();
+        if !self.confirm_unload() {
             return;
         }
 
+        let url_str = if is_internal_page(&url_str, SETTINGS_URL) {
+            if let Some(query) = url.query() {
+                self.apply_settings_form(query);
+            }
+            SETTINGS_URL.to_string()
+        } else if is_internal_page(&url_str, RELAYS_URL) {
+            if let Some(query) = url.query() {
+                self.apply_relay_action(query);
+            }
+            RELAYS_URL.to_string()
+        } else if is_internal_page(&url_str, IDENTITIES_URL) {
+            if let Some(query) = url.query() {
+                self.apply_identities_form(query);
+            }
+            IDENTITIES_URL.to_string()
+        } else if is_internal_page(&url_str, SIGNATURES_URL) {
+            SIGNATURES_URL.to_string()
+        } else if is_internal_page(&url_str, PUBLISH_QUEUE_URL) {
+            self.publish_queue_retry = url.query().is_some();
+            PUBLISH_QUEUE_URL.to_string()
+        } else {
+            url_str
+        };
+
         let target = if url_str.contains("?url=") {
             if let Some(query) = url.query() {
-                ::url::form_urlencoded::parse(query.as_bytes())
+                let target = ::url::form_urlencoded::parse(query.as_bytes())
                     .find(|(key, _)| key == "url")
                     .map(|(_, value)| value.into_owned())
-                    .unwrap_or(url_str)
+                    .unwrap_or(url_str);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.navigated(&target);
+                }
+                target
             } else {
                 url_str
             }
@@ -437,6 +3409,11 @@ impl ReadmeApplication {
             url_str
         };
 
+        self.visit(target);
+    }
+
+    /// Go to `target` as a new history entry. Check [`Self::confirm_unload`] first.
+    fn visit(&mut self, target: String) {
         let previous = self.current_input.clone();
         if previous != target {
             self.back_history.push(previous);
@@ -446,7 +3423,225 @@ impl ReadmeApplication {
         self.spawn_navigation(target, false);
     }
 
+    /// Run a command from the menu, the chrome or a keyboard shortcut.
+    fn run_command(&mut self, event_loop: &ActiveEventLoop, command: MenuCommand) {
+        // Pages can still go back, forward and reload; nothing else leaves a kiosk's site.
+        if self.kiosk.is_some()
+            && !matches!(
+                command,
+                MenuCommand::Back | MenuCommand::Forward | MenuCommand::Reload
+            )
+        {
+            info!(?command, "kiosk mode ignored a browser command");
+            return;
+        }
+        match command {
+            MenuCommand::NewWindow => self.open_new_window(false),
+            MenuCommand::NewPrivateWindow => self.open_new_window(true),
+            MenuCommand::OpenFile => self.open_file(),
+            MenuCommand::QuickOpen => {
+                self.show_window();
+                if self.confirm_unload() {
+                    self.visit(QUICK_OPEN_URL.to_string());
+                    self.record_navigation();
+                }
+            }
+            MenuCommand::Bookmark => self.toggle_bookmark(),
+            MenuCommand::CloseWindow if self.background.keeps_running() => self.hide_window(),
+            MenuCommand::CloseWindow | MenuCommand::Quit => {
+                if self.confirm_unload() {
+                    event_loop.exit();
+                }
+            }
+            MenuCommand::ShowWindow => self.show_window(),
+            MenuCommand::Reload => {
+                self.reload_document(true);
+                self.record_navigation();
+            }
+            MenuCommand::ZoomIn => self.zoom_page(|zoom| gestures::step_zoom(zoom, true)),
+            MenuCommand::ZoomOut => self.zoom_page(|zoom| gestures::step_zoom(zoom, false)),
+            MenuCommand::ActualSize => self.zoom_page(|_| 1.0),
+            MenuCommand::ToggleTheme => self.toggle_theme(),
+            MenuCommand::Back => {
+                self.go_back();
+                self.record_navigation();
+            }
+            MenuCommand::Forward => {
+                self.go_forward();
+                self.record_navigation();
+            }
+            MenuCommand::Visit(target) => {
+                if self.confirm_unload() {
+                    self.visit(target);
+                    self.record_navigation();
+                }
+            }
+            MenuCommand::Help => {
+                if self.confirm_unload() {
+                    self.visit(app_menu::HELP_URL.to_string());
+                    self.record_navigation();
+                }
+            }
+        }
+    }
+
+    /// Start another browser process on the homepage.
+    /// Start another browser process on the homepage, a private session if `private`.
+    fn open_new_window(&self, private: bool) {
+        let started = std::env::current_exe()
+            .context("failed to locate the frontier executable")
+            .and_then(|exe| {
+                let mut command = std::process::Command::new(exe);
+                if private {
+                    command.arg(PRIVATE_FLAG);
+                }
+                command
+                    .arg(&self.preferences.homepage)
+                    .spawn()
+                    .context("failed to start the new window")
+            });
+        if let Err(err) = started {
+            warn!(error = %err, "could not open a new window");
+        }
+    }
+
+    /// Pick a local document with the native file dialog and open it. Automation sessions
+    /// never get a dialog, which would block the event loop.
+    fn open_file(&mut self) {
+        if self.automation.is_some() {
+            info!("not opening a file dialog under automation");
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Open File")
+            .add_filter("Documents", &["html", "htm", "md", "markdown", "txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        let Ok(url) = Url::from_file_path(&path) else {
+            warn!(path = %path.display(), "ignoring a file without an absolute path");
+            return;
+        };
+        if self.confirm_unload() {
+            self.visit(url.to_string());
+            self.record_navigation();
+        }
+    }
+
+    /// Show the native menu bar, where there is one.
+    fn install_menu(&mut self) {
+        if self.automation.is_some() || self.kiosk.is_some() {
+            return;
+        }
+        let send = self.command_sender();
+        let Some(view) = self.inner.windows.values().next() else {
+            return;
+        };
+        self.app_menu.install(&view.window, send);
+    }
+
+    /// Sends commands from the menus, the tray and the global shortcut to the event loop.
+    fn command_sender(&self) -> impl Fn(MenuCommand) + Clone + Send + Sync + 'static {
+        // They call back from other threads, and require a handler they could share.
+        let proxy = Arc::new(Mutex::new(self.inner.proxy.clone()));
+        move |command| {
+            let event = ReadmeEvent::Command(command);
+            if let Ok(proxy) = proxy.lock() {
+                let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+            }
+        }
+    }
+
+    /// Turn background mode on or off to match the preferences.
+    fn sync_background(&mut self) {
+        let enabled = self.preferences.background
+            && !self.is_popup
+            && self.automation.is_none()
+            && self.kiosk.is_none();
+        let send = self.command_sender();
+        self.background
+            .sync(enabled, &self.preferences.quick_open_shortcut, send);
+    }
+
+    /// Hide the window in background mode; the tray or the shortcut brings it back.
+    fn hide_window(&mut self) {
+        self.save_window_state();
+        if let Some(view) = self.inner.windows.values().next() {
+            view.window.set_visible(false);
+        }
+    }
+
+    fn show_window(&mut self) {
+        if let Some(view) = self.inner.windows.values().next() {
+            view.window.set_visible(true);
+            view.window.focus_window();
+        }
+    }
+
+    /// Bookmark the current page for the quick-open palette, or remove its bookmark.
+    fn toggle_bookmark(&mut self) {
+        let title = self
+            .inner
+            .windows
+            .values()
+            .next()
+            .and_then(|view| quick_open::document_title(&view.doc))
+            .unwrap_or_default();
+        let url = self.current_input.clone();
+        let bookmarked = self.quick_open.toggle_bookmark(&url, &title);
+        info!(url = %url, bookmarked, "toggled bookmark");
+        self.save_quick_open();
+    }
+
+    /// Add the page the window finished loading to the quick-open palette's recent pages.
+    /// Internal pages are left out, and so are automation runs.
+    fn record_visit(&mut self) {
+        if self.visit_recorded == self.navigations.generation()
+            || self.pending_document_reset
+            || self.is_popup
+            || self.automation.is_some()
+        {
+            return;
+        }
+        self.visit_recorded = self.navigations.generation();
+        if self.current_input.starts_with("frontier://") || self.current_input.starts_with("about:")
+        {
+            return;
+        }
+        let Some(view) = self.inner.windows.values().next() else {
+            return;
+        };
+        let title = quick_open::document_title(&view.doc).unwrap_or_default();
+        if self.quick_open.record_visit(&self.current_input, &title) {
+            self.save_quick_open();
+        }
+    }
+
+    fn save_quick_open(&self) {
+        if let Some(path) = &self.quick_open_path {
+            if let Err(err) = self.quick_open.save(path) {
+                warn!(error = %err, "failed to save quick-open history");
+            }
+        }
+    }
+
+    /// List the pages behind the current one in the History menu.
+    fn sync_history_menu(&mut self) {
+        let recent = self
+            .back_history
+            .iter()
+            .rev()
+            .take(app_menu::RECENT_ENTRIES)
+            .cloned()
+            .collect();
+        self.app_menu.set_recent(recent);
+    }
+
     fn go_back(&mut self) {
+        if self.back_history.is_empty() || !self.confirm_unload() {
+            return;
+        }
         if let Some(target) = self.back_history.pop() {
             let current = self.current_input.clone();
             self.forward_history.push(current);
@@ -456,6 +3651,9 @@ impl ReadmeApplication {
     }
 
     fn go_forward(&mut self) {
+        if self.forward_history.is_empty() || !self.confirm_unload() {
+            return;
+        }
         if let Some(target) = self.forward_history.pop() {
             let current = self.current_input.clone();
             self.back_history.push(current);
@@ -465,17 +3663,21 @@ impl ReadmeApplication {
     }
 
     fn process_automation_commands(&mut self, event_loop: &ActiveEventLoop) {
-        let state: AutomationStateHandle = match self.automation.as_ref() {
-            Some(bindings) => Arc::clone(&bindings.state),
-            None => return,
-        };
+        let queues: Vec<AutomationStateHandle> = self
+            .automation
+            .iter()
+            .map(|bindings| Arc::clone(&bindings.state))
+            .chain(self.debug_channel.iter().map(Arc::clone))
+            .collect();
 
-        loop {
-            let task = state.pop();
-            let Some(task) = task else { break };
-            let (command, responder) = task.into_parts();
-            let result = self.execute_automation_command(event_loop, command);
-            let _ = responder.send(result);
+        for state in queues {
+            loop {
+                let task = state.pop();
+                let Some(task) = task else { break };
+                let (command, responder) = task.into_parts();
+                let result = self.execute_automation_command(event_loop, command);
+                let _ = responder.send(result);
+            }
         }
     }
 
@@ -484,6 +3686,10 @@ impl ReadmeApplication {
         event_loop: &ActiveEventLoop,
         command: AutomationCommand,
     ) -> AutomationResult {
+        let mut images = Vec::new();
+        // Replays poll this between steps; a DOM snapshot for every poll would only fill
+        // the artifact directory.
+        let snapshot = !matches!(command, AutomationCommand::Busy);
         let response = match command {
             AutomationCommand::Click { selector } => {
                 let actions = vec![
@@ -553,20 +3759,338 @@ impl ReadmeApplication {
                 AutomationResponse::None
             }
             AutomationCommand::ScrollIntoView { selector } => {
-                self.automation_scroll_into_view(&selector)?;
+                self.automation_scroll_into_view(event_loop, &selector)?;
+                AutomationResponse::None
+            }
+            AutomationCommand::AccessibilityTree => {
+                let window_id = self
+                    .automation_first_window_id()
+                    .ok_or_else(|| anyhow!("automation window not ready"))?;
+                let view = self
+                    .inner
+                    .windows
+                    .get(&window_id)
+                    .ok_or_else(|| anyhow!("automation window missing"))?;
+                let tree = AccessibilityTree::from_document(&view.doc);
+                AutomationResponse::Text(serde_json::to_string(&tree)?)
+            }
+            AutomationCommand::SetInputFiles { selector, paths } => {
+                let selector_label = Self::describe_selector(&selector);
+                let (window_id, node_id) = self.automation_node_for_selector(&selector)?;
+                let input = self
+                    .inner
+                    .windows
+                    .get(&window_id)
+                    .and_then(|view| file_input::file_input(&view.doc, node_id))
+                    .ok_or_else(|| anyhow!("{selector_label} is not an enabled file input"))?;
+                if paths.len() > 1 && !input.multiple {
+                    return Err(anyhow!("{selector_label} accepts a single file"));
+                }
+                let runtime = self
+                    .current_js_runtime
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("page has no script runtime to hold the files"))?;
+                runtime.set_input_files(node_id, &paths)?;
+                self.automation_pump_for(Duration::from_millis(16));
+                AutomationResponse::None
+            }
+            AutomationCommand::SetViewport { width, height } => {
+                self.automation_set_viewport(event_loop, width, height)?;
+                AutomationResponse::None
+            }
+            AutomationCommand::NetworkLog => {
+                AutomationResponse::Text(serde_json::to_string(&self.network_log.entries())?)
+            }
+            AutomationCommand::ConsoleLog { limit } => AutomationResponse::Text(
+                serde_json::to_string(&ConsoleLog::shared().recent(limit))?,
+            ),
+            AutomationCommand::MemoryReport => {
+                AutomationResponse::Text(serde_json::to_string(&self.memory_report())?)
+            }
+            AutomationCommand::Popups => {
+                AutomationResponse::Text(serde_json::to_string(&self.popups.records())?)
+            }
+            AutomationCommand::RecordCoverage => {
+                self.js_coverage = Some(JsCoverage::default());
+                AutomationResponse::None
+            }
+            AutomationCommand::Coverage => {
+                let report = self.js_coverage.as_ref().map(JsCoverage::report);
+                AutomationResponse::Text(serde_json::to_string(&report)?)
+            }
+            AutomationCommand::FrameStats => {
+                AutomationResponse::Text(serde_json::to_string(&self.frame_stats.summary())?)
+            }
+            AutomationCommand::Damage => {
+                AutomationResponse::Text(serde_json::to_string(&self.damage.summary())?)
+            }
+            AutomationCommand::Har { content } => {
+                let archive = har::har(
+                    &self.network_log.entries(),
+                    &self.current_input,
+                    self.network_log.started_at(),
+                    content,
+                );
+                AutomationResponse::Text(serde_json::to_string(&archive)?)
+            }
+            AutomationCommand::CompareScreenshot {
+                baseline,
+                thresholds,
+                update,
+                selector,
+            } => {
+                let actual = self.automation_capture(selector.as_ref())?;
+                let (comparison, diff) =
+                    Self::automation_compare_screenshot(&actual, &baseline, &thresholds, update)?;
+                images = diff;
+                AutomationResponse::Text(serde_json::to_string(&comparison)?)
+            }
+            AutomationCommand::ElementRect { selector } => {
+                let rect = self.automation_element_rect(&selector)?;
+                AutomationResponse::Text(serde_json::to_string(&rect)?)
+            }
+            AutomationCommand::Screenshot { selector } => {
+                let capture = self.automation_capture(selector.as_ref())?;
+                images = vec![(
+                    "screenshot.png".to_string(),
+                    screenshot::encode_png(&capture)?,
+                )];
+                AutomationResponse::None
+            }
+            AutomationCommand::Cookies => {
+                let cookies = CookieJar::shared().cookies_for(&self.automation_page_url()?, false);
+                AutomationResponse::Text(serde_json::to_string(&cookies)?)
+            }
+            AutomationCommand::SetCookie { cookie } => {
+                let url = self.automation_page_url()?;
+                if !CookieJar::shared().set(&url, &cookie, false) {
+                    bail!("cookie {cookie:?} was rejected for {url}");
+                }
+                AutomationResponse::None
+            }
+            AutomationCommand::ClearCookies => {
+                CookieJar::shared().clear();
+                AutomationResponse::None
+            }
+            AutomationCommand::LocalStorage => {
+                let area = self.automation_storage_area()?;
+                let items = LocalStorage::shared().items(&area);
+                AutomationResponse::Text(serde_json::to_string(&items)?)
+            }
+            AutomationCommand::SetLocalStorage { items } => {
+                let area = self.automation_storage_area()?;
+                for (key, value) in &items {
+                    if !LocalStorage::shared().set(&area, key, value) {
+                        bail!("storing {key:?} would exceed the localStorage quota of {area}");
+                    }
+                }
+                AutomationResponse::None
+            }
+            AutomationCommand::ClearLocalStorage => {
+                LocalStorage::shared().clear(&self.automation_storage_area()?);
+                AutomationResponse::None
+            }
+            AutomationCommand::Emulate { emulation } => {
+                self.automation_emulate(event_loop, emulation)?;
+                AutomationResponse::None
+            }
+            AutomationCommand::ResetEmulation => {
+                self.automation_reset_emulation();
+                AutomationResponse::None
+            }
+            AutomationCommand::UseVirtualClock => {
+                self.virtual_clock.get_or_insert_with(VirtualClock::default);
+                AutomationResponse::None
+            }
+            AutomationCommand::AdvanceClock { to_ms } => {
+                self.automation_advance_clock(Duration::from_millis(to_ms))?;
                 AutomationResponse::None
             }
+            AutomationCommand::Busy => AutomationResponse::Bool(
+                self.navigations.is_loading()
+                    || self.frames.is_loading()
+                    || self
+                        .current_js_runtime
+                        .as_ref()
+                        .is_some_and(|runtime| runtime.environment().has_requests_in_flight()),
+            ),
             AutomationCommand::Shutdown => {
                 event_loop.exit();
                 AutomationResponse::None
             }
         };
 
-        let artifacts = self.automation_collect_artifacts();
-        Ok(AutomationReply {
-            response,
-            artifacts,
-        })
+        let mut artifacts = match snapshot {
+            true => self.automation_collect_artifacts(),
+            false => None,
+        };
+        if !images.is_empty() {
+            artifacts.get_or_insert_with(Default::default).images = images;
+        }
+        Ok(AutomationReply {
+            response,
+            artifacts,
+        })
+    }
+
+    /// Resize the content area to `width`×`height` logical pixels. The resize is delivered
+    /// straight away rather than waiting for the platform, which may clamp or ignore the
+    /// request for a headless or tiled window.
+    fn automation_set_viewport(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        width: f64,
+        height: f64,
+    ) -> anyhow::Result<()> {
+        if !(width.is_finite() && height.is_finite() && width >= 1.0 && height >= 1.0) {
+            return Err(anyhow!("invalid viewport {width}x{height}"));
+        }
+        let window_id = self
+            .automation_first_window_id()
+            .ok_or_else(|| anyhow!("automation window not ready"))?;
+        let physical: PhysicalSize<u32> = {
+            let view = self
+                .inner
+                .windows
+                .get(&window_id)
+                .ok_or_else(|| anyhow!("automation window missing"))?;
+            let physical = LogicalSize::new(width, height).to_physical(view.window.scale_factor());
+            let _ = view.window.request_inner_size(physical);
+            physical
+        };
+        self.window_event(event_loop, window_id, WindowEvent::Resized(physical));
+        Ok(())
+    }
+
+    /// Apply what `emulation` sets, leaving the rest as it is, and tell page script.
+    fn automation_emulate(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        emulation: Emulation,
+    ) -> anyhow::Result<()> {
+        if let Some(scale) = emulation.device_scale_factor {
+            if !(scale.is_finite() && scale > 0.0 && scale <= MAX_EMULATED_SCALE) {
+                bail!("invalid device scale factor {scale}");
+            }
+        }
+        let size = match (emulation.width, emulation.height) {
+            (Some(width), Some(height)) => Some((width, height)),
+            (None, None) => None,
+            _ => bail!("emulate width and height together"),
+        };
+        if let Some(user_agent) = emulation.user_agent {
+            if user_agent.is_empty() || user_agent.chars().any(char::is_control) {
+                bail!("invalid user agent {user_agent:?}");
+            }
+            self.emulated_user_agent = Some(user_agent);
+            self.sync_user_agent();
+        }
+        if let Some(color_scheme) = emulation.color_scheme {
+            let theme = match color_scheme {
+                ColorScheme::Light => Theme::Light,
+                ColorScheme::Dark => Theme::Dark,
+            };
+            self.window_mut().set_theme_override(Some(theme));
+            self.damage.invalidate();
+        }
+        if let Some(reduced_motion) = emulation.reduced_motion {
+            self.reduced_motion = reduced_motion;
+        }
+        if let Some(scale) = emulation.device_scale_factor {
+            self.emulated_scale = Some(scale);
+        }
+        self.sync_media_preferences();
+        match size {
+            Some((width, height)) => self.automation_set_viewport(event_loop, width, height)?,
+            None => self.sync_viewport(),
+        }
+        Ok(())
+    }
+
+    /// Drop everything [`Self::automation_emulate`] set except the viewport size, going back
+    /// to the theme preference, the environment's reduced motion and Frontier's user agent.
+    fn automation_reset_emulation(&mut self) {
+        self.emulated_scale = None;
+        self.emulated_user_agent = None;
+        self.reduced_motion = MediaPreferences::reduced_motion_from_env();
+        self.sync_user_agent();
+        self.apply_theme_preference();
+        self.sync_viewport();
+    }
+
+    /// Compare the first window against `baseline`. On a mismatch, also returns the capture
+    /// as `actual.png`, plus `diff.png` when the sizes agree, for the command's artifacts.
+    /// Paint the window as it is shown, cropped to `selector`'s element when one is given.
+    fn automation_capture(
+        &mut self,
+        selector: Option<&ElementSelector>,
+    ) -> anyhow::Result<RgbaImage> {
+        let rect = selector
+            .map(|selector| self.automation_element_rect(selector))
+            .transpose()?;
+        let window_id = self
+            .automation_first_window_id()
+            .ok_or_else(|| anyhow!("automation window not ready"))?;
+        let view = self
+            .inner
+            .windows
+            .get(&window_id)
+            .ok_or_else(|| anyhow!("automation window missing"))?;
+        let window_scale = view.window.scale_factor();
+        let scale = self.emulated_scale.unwrap_or(window_scale);
+        let size = view
+            .window
+            .inner_size()
+            .to_logical::<f64>(window_scale)
+            .to_physical::<u32>(scale);
+        let capture = screenshot::capture(&view.doc, scale, size.width, size.height)?;
+        match rect {
+            Some(rect) => screenshot::crop(&capture, &rect, scale),
+            None => Ok(capture),
+        }
+    }
+
+    fn automation_element_rect(
+        &mut self,
+        selector: &ElementSelector,
+    ) -> anyhow::Result<ElementRect> {
+        let (window_id, node_id) = self.automation_node_for_selector(selector)?;
+        let view = self
+            .inner
+            .windows
+            .get(&window_id)
+            .ok_or_else(|| anyhow!("automation window missing"))?;
+        ElementRect::of(&view.doc, node_id).ok_or_else(|| anyhow!("automation node disappeared"))
+    }
+
+    fn automation_page_url(&self) -> anyhow::Result<Url> {
+        self.current_document
+            .as_ref()
+            .and_then(|document| Url::parse(&document.base_url).ok())
+            .ok_or_else(|| anyhow!("no page is loaded"))
+    }
+
+    fn automation_storage_area(&self) -> anyhow::Result<String> {
+        let url = self.automation_page_url()?;
+        LocalStorage::area(&url).ok_or_else(|| anyhow!("{url} has no localStorage"))
+    }
+
+    fn automation_compare_screenshot(
+        actual: &RgbaImage,
+        baseline: &Path,
+        thresholds: &CompareThresholds,
+        update: bool,
+    ) -> anyhow::Result<(ScreenshotComparison, Vec<(String, Vec<u8>)>)> {
+        let (comparison, diff) =
+            screenshot::compare_with_baseline(actual, baseline, thresholds, update)?;
+        let mut images = Vec::new();
+        if !comparison.matched {
+            images.push(("actual.png".to_string(), screenshot::encode_png(actual)?));
+        }
+        if let Some(diff) = diff {
+            images.push(("diff.png".to_string(), screenshot::encode_png(&diff)?));
+        }
+        Ok((comparison, images))
     }
 
     fn automation_first_window_id(&self) -> Option<WindowId> {
@@ -617,6 +4141,16 @@ impl ReadmeApplication {
         Ok(text)
     }
 
+    /// Automation coordinates are CSS pixels, like the layout they come from; winit events
+    /// carry physical pixels, so every synthetic position is scaled by the window's DPR.
+    fn automation_scale_factor(&self, window_id: WindowId) -> f64 {
+        self.inner
+            .windows
+            .get(&window_id)
+            .map(|view| view.window.scale_factor())
+            .unwrap_or(1.0)
+    }
+
     fn automation_dispatch_cursor_move(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -624,16 +4158,8 @@ impl ReadmeApplication {
         x: f64,
         y: f64,
     ) {
-        let logical = LogicalPosition::new(x, y);
-        let physical = {
-            let scale = self
-                .inner
-                .windows
-                .get(&window_id)
-                .map(|view| view.window.scale_factor())
-                .unwrap_or(1.0);
-            logical.to_physical(scale)
-        };
+        let physical =
+            LogicalPosition::new(x, y).to_physical(self.automation_scale_factor(window_id));
         self.inner.window_event(
             event_loop,
             window_id,
@@ -674,12 +4200,14 @@ impl ReadmeApplication {
         delta_x: f64,
         delta_y: f64,
     ) {
+        let delta: PhysicalPosition<f64> = LogicalPosition::new(delta_x, delta_y)
+            .to_physical(self.automation_scale_factor(window_id));
         self.inner.window_event(
             event_loop,
             window_id,
             WindowEvent::MouseWheel {
                 device_id: DeviceId::dummy(),
-                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(delta_x, delta_y)),
+                delta: MouseScrollDelta::PixelDelta(delta),
                 phase: TouchPhase::Moved,
             },
         );
@@ -831,7 +4359,11 @@ impl ReadmeApplication {
             .map_err(|err| anyhow!("dispatching keyboard shortcut failed: {err}"))
     }
 
-    fn automation_scroll_into_view(&mut self, selector: &ElementSelector) -> anyhow::Result<()> {
+    fn automation_scroll_into_view(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        selector: &ElementSelector,
+    ) -> anyhow::Result<()> {
         if let Some(runtime) = self.current_js_runtime.as_ref() {
             let script = Self::selector_script(
                 selector,
@@ -842,6 +4374,7 @@ impl ReadmeApplication {
                 .eval_with::<bool>(&script, "automation-scroll.js")
                 .unwrap_or(false)
             {
+                self.process_scroll_requests(event_loop);
                 self.automation_pump_for(Duration::from_millis(16));
                 return Ok(());
             }
@@ -945,6 +4478,7 @@ impl ReadmeApplication {
         match runtime.environment().document_html() {
             Ok(dom) => Some(AutomationArtifacts {
                 dom_html: Some(dom),
+                images: Vec::new(),
             }),
             Err(err) => {
                 warn!(
@@ -957,6 +4491,114 @@ impl ReadmeApplication {
         }
     }
 
+    /// Add `event` to the session recording, in CSS pixels like automation input.
+    fn record_input(&mut self, window_id: WindowId, event: &WindowEvent) {
+        let Some(view) = self.inner.windows.get(&window_id) else {
+            return;
+        };
+        let scale = view.window.scale_factor();
+        let size: LogicalSize<f64> = view.window.inner_size().to_logical(scale);
+        let mods = self.keyboard_modifiers.state();
+        let secret = self
+            .element_states
+            .focused()
+            .is_some_and(|focused| element_state::is_password_field(&view.doc, focused));
+        let Some(recorder) = self.recorder.as_mut() else {
+            return;
+        };
+        recorder.set_viewport(size.width, size.height);
+        let text = |recorder: &mut Recorder, value: &str| match secret {
+            true => recorder.secret_text(value),
+            false => recorder.text(value),
+        };
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position: LogicalPosition<f64> = position.to_logical(scale);
+                recorder.cursor_moved(position.x, position.y);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Auxiliary,
+                    _ => return,
+                };
+                recorder.pointer_button(button, state.is_pressed());
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (
+                        f64::from(*x) * SCROLL_LINE_HEIGHT,
+                        f64::from(*y) * SCROLL_LINE_HEIGHT,
+                    ),
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        let delta: LogicalPosition<f64> = delta.to_logical(scale);
+                        (delta.x, delta.y)
+                    }
+                };
+                recorder.scroll(x, y);
+            }
+            WindowEvent::Ime(Ime::Commit(value)) => text(recorder, value),
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() => {
+                let shortcut = mods.control_key() || mods.super_key() || mods.alt_key();
+                match (&event.logical_key, event.text.as_deref()) {
+                    (_, Some(value)) if !shortcut && !value.chars().any(char::is_control) => {
+                        text(recorder, value)
+                    }
+                    (Key::Named(named), _) if !is_modifier_key(*named) => {
+                        recorder.key(format!("{named:?}"), modifier_names(mods))
+                    }
+                    (Key::Character(key), _) => recorder.key(key.to_string(), modifier_names(mods)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record the navigation a browser shortcut just started.
+    fn record_navigation(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.navigated(&self.current_input);
+        }
+    }
+
+    /// Move the virtual clock to `to`, stopping at each timer that falls due on the way so
+    /// timers of the page and its frames fire in order.
+    fn automation_advance_clock(&mut self, to: Duration) -> anyhow::Result<()> {
+        let clock = self
+            .virtual_clock
+            .clone()
+            .ok_or_else(|| anyhow!("the virtual clock is not in use"))?;
+        for _ in 0..MAX_CLOCK_STEPS {
+            let page_due = self
+                .current_js_runtime
+                .as_ref()
+                .and_then(|runtime| runtime.environment().next_timer_due());
+            let due = page_due
+                .into_iter()
+                .chain(self.frames.next_timer_due())
+                .min();
+            match due {
+                Some(due) if due <= to => clock.set(due),
+                _ => break,
+            }
+            self.automation_run_page_work();
+        }
+        clock.set(to);
+        self.automation_run_page_work();
+        Ok(())
+    }
+
+    /// Run the due timers, settled fetches and queued jobs of the page and its frames once.
+    fn automation_run_page_work(&mut self) {
+        for view in self.inner.windows.values_mut() {
+            view.poll();
+        }
+        self.frames_woken = true;
+        self.service_frames();
+    }
+
     fn automation_pump_for(&mut self, duration: Duration) {
         let end = Instant::now() + duration;
         while Instant::now() < end {
@@ -999,51 +4641,9 @@ impl ReadmeApplication {
     }
 
     fn lookup_role(doc: &mut dyn Document, role: &str, name: Option<&str>) -> Option<usize> {
-        let desired_name = name.map(Self::normalize_label);
-        let mut matches = Vec::new();
-        let root = doc.root_node().id;
-        doc.iter_subtree_mut(root, |node_id, document| {
-            if let Some(node) = document.get_node(node_id) {
-                if node.attr(local_name!("role")) == Some(role) {
-                    matches.push(node_id);
-                }
-            }
-        });
-        if let Some(target) = desired_name {
-            for node_id in matches {
-                if let Some(actual) = Self::node_accessible_name(doc, node_id) {
-                    if Self::normalize_label(&actual) == target {
-                        return Some(node_id);
-                    }
-                }
-            }
-            None
-        } else {
-            matches.into_iter().next()
-        }
-    }
-
-    fn node_accessible_name(doc: &mut dyn Document, node_id: usize) -> Option<String> {
-        let node = doc.get_node(node_id)?;
-
-        if let Some(label) = node.attr(local_name!("aria-label")) {
-            let trimmed = label.trim();
-            if !trimmed.is_empty() {
-                return Some(trimmed.to_string());
-            }
-        }
-
-        let text = node.text_content();
-        let trimmed = text.trim();
-        if trimmed.is_empty() {
-            None
-        } else {
-            Some(trimmed.to_string())
-        }
-    }
-
-    fn normalize_label(value: &str) -> String {
-        value.trim().to_ascii_lowercase()
+        AccessibilityTree::from_document(doc)
+            .find(role, name)
+            .map(|node| node.node_id)
     }
 
     fn describe_selector(selector: &ElementSelector) -> String {
@@ -1095,9 +4695,15 @@ impl ReadmeApplication {
     }
 }
 
-impl ApplicationHandler<BlitzShellEvent> for ReadmeApplication {
+impl<R: WindowRenderer> ApplicationHandler<BlitzShellEvent> for ReadmeApplication<R> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        self.inner.resumed(event_loop);
+        startup::time(Stage::Window, || self.inner.resumed(event_loop));
+        self.place_restored_window(event_loop);
+        self.install_menu();
+        self.sync_background();
+        if self.preferences.theme != ThemePreference::System {
+            self.apply_theme_preference();
+        }
     }
 
     fn suspended(&mut self, event_loop: &ActiveEventLoop) {
@@ -1108,6 +4714,26 @@ impl ApplicationHandler<BlitzShellEvent> for ReadmeApplication {
         self.inner.new_events(event_loop, cause);
     }
 
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        self.inner.about_to_wait(event_loop);
+        self.process_signer_requests();
+        self.process_p2p_requests();
+        self.process_window_requests(event_loop);
+        self.handle_dropped_files();
+        self.process_file_picker_requests();
+        self.process_scroll_requests(event_loop);
+        self.process_intersection_requests();
+        self.load_frames();
+        self.service_frames();
+        self.sync_overlay();
+        self.sync_blocked_count();
+        self.apply_pending_restore();
+        self.record_session();
+        self.record_visit();
+        self.maybe_save_window_state();
+        self.sync_history_menu();
+    }
+
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
@@ -1118,19 +4744,230 @@ impl ApplicationHandler<BlitzShellEvent> for ReadmeApplication {
             self.keyboard_modifiers = *new_state;
         }
 
+        if let WindowEvent::CursorMoved { position, .. } = &event {
+            if let Some(view) = self.inner.windows.get(&window_id) {
+                self.pointer = position.to_logical(view.window.scale_factor());
+            }
+        }
+
+        if let WindowEvent::DroppedFile(path) = &event {
+            self.dropped_files.push((window_id, path.clone()));
+        }
+
+        if let WindowEvent::Occluded(occluded) = &event {
+            if let Some(runtime) = self.current_js_runtime.as_ref() {
+                runtime.dispatch_lifecycle(LifecycleEvent::VisibilityChange { visible: !occluded });
+            }
+        }
+
+        let resized = matches!(
+            event,
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. }
+        );
+        let moved = matches!(event, WindowEvent::Moved(_));
+        let rescaled = matches!(event, WindowEvent::ScaleFactorChanged { .. });
+        let theme_changed = matches!(event, WindowEvent::ThemeChanged(_));
+        let cursor_moved = matches!(event, WindowEvent::CursorMoved { .. });
+        let cursor_left = matches!(event, WindowEvent::CursorLeft { .. });
+        // Losing the window also ends a press, whose release will go elsewhere.
+        let primary_button = match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => Some(state),
+            WindowEvent::Focused(false) => Some(ElementState::Released),
+            _ => None,
+        };
+        let clicked = matches!(
+            event,
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            }
+        );
+
+        if self.recorder.is_some() {
+            self.record_input(window_id, &event);
+        }
+        if clicked {
+            // Taken before blitz follows the link, which it does while handling the release.
+            self.clicked_node = self
+                .inner
+                .windows
+                .get(&window_id)
+                .and_then(|view| view.doc.get_hover_node_id());
+        }
+
         if let WindowEvent::KeyboardInput { event, .. } = &event {
             let mods = self.keyboard_modifiers.state();
-            if !event.state.is_pressed() && (mods.control_key() || mods.super_key()) {
+            if self.kiosk.is_some() && kiosk_swallows_key(mods, event.physical_key) {
+                return;
+            }
+            if self.kiosk.is_none()
+                && !event.state.is_pressed()
+                && (mods.control_key() || mods.super_key())
+            {
                 match event.physical_key {
-                    PhysicalKey::Code(KeyCode::KeyR) => self.reload_document(true),
-                    PhysicalKey::Code(KeyCode::KeyT) => self.toggle_theme(),
-                    PhysicalKey::Code(KeyCode::KeyB) => self.go_back(),
+                    PhysicalKey::Code(KeyCode::KeyR) => {
+                        self.run_command(event_loop, MenuCommand::Reload)
+                    }
+                    PhysicalKey::Code(KeyCode::KeyT) => {
+                        self.run_command(event_loop, MenuCommand::ToggleTheme)
+                    }
+                    PhysicalKey::Code(KeyCode::KeyB) => {
+                        self.run_command(event_loop, MenuCommand::Back)
+                    }
+                    // Where the menu is native its accelerators take these.
+                    PhysicalKey::Code(KeyCode::KeyK) if !app_menu::NATIVE => {
+                        self.run_command(event_loop, MenuCommand::QuickOpen)
+                    }
+                    PhysicalKey::Code(KeyCode::KeyN) if mods.shift_key() && !app_menu::NATIVE => {
+                        self.run_command(event_loop, MenuCommand::NewPrivateWindow)
+                    }
+                    PhysicalKey::Code(KeyCode::KeyD) if mods.shift_key() => {
+                        self.toggle_damage_overlay()
+                    }
+                    PhysicalKey::Code(KeyCode::KeyD) if !app_menu::NATIVE => {
+                        self.run_command(event_loop, MenuCommand::Bookmark)
+                    }
+                    PhysicalKey::Code(KeyCode::KeyE) if mods.shift_key() => {
+                        self.toggle_network_panel()
+                    }
+                    PhysicalKey::Code(KeyCode::KeyF) if mods.shift_key() => {
+                        self.toggle_frame_overlay()
+                    }
                     _ => {}
                 }
             }
         }
 
-        self.inner.window_event(event_loop, window_id, event);
+        if let WindowEvent::KeyboardInput { event, .. } = &event {
+            if self.handle_form_key(window_id, event) {
+                return;
+            }
+        }
+
+        if !cursor_moved || self.range_drag.is_some() {
+            self.handle_range_drag(window_id, &event);
+        }
+
+        if matches!(event, WindowEvent::CloseRequested) && self.background.keeps_running() {
+            self.hide_window();
+            return;
+        }
+
+        if let WindowEvent::PinchGesture { delta, phase, .. } = event {
+            self.handle_pinch(window_id, delta, phase);
+            return;
+        }
+        // Trackpads report two-finger scrolls as phased pixel deltas (iOS as pan gestures).
+        let swipe_step = match event {
+            WindowEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(delta),
+                phase,
+                ..
+            } => Some((phase, delta)),
+            WindowEvent::PanGesture { delta, phase, .. } => Some((
+                phase,
+                PhysicalPosition::new(f64::from(delta.x), f64::from(delta.y)),
+            )),
+            _ => None,
+        };
+
+        // A wheel that scrolls by lines jumps a whole line per notch, so animate it instead.
+        if let WindowEvent::MouseWheel {
+            delta: MouseScrollDelta::LineDelta(x, y),
+            ..
+        } = event
+        {
+            let distance = (
+                f64::from(x) * SCROLL_LINE_HEIGHT,
+                f64::from(y) * SCROLL_LINE_HEIGHT,
+            );
+            self.start_scroll(
+                event_loop,
+                window_id,
+                ScrollTarget::Pointer,
+                distance,
+                false,
+            );
+            return;
+        }
+
+        let scroll_key = match &event {
+            WindowEvent::KeyboardInput { event, .. } if event.state.is_pressed() => {
+                Some(event.logical_key.clone())
+            }
+            _ => None,
+        };
+
+        if matches!(event, WindowEvent::RedrawRequested) {
+            self.advance_smooth_scroll(event_loop, window_id);
+            let started = Instant::now();
+            self.keep_scroll_anchored(window_id);
+            if self.awaiting_first_paint && !self.pending_document_reset {
+                // blitz resolves style and layout as part of the redraw.
+                let load_trace = self.load_trace.clone();
+                startup::time(Stage::FirstPaint, || {
+                    load_trace.time(Phase::FirstPaint, || {
+                        self.inner.window_event(event_loop, window_id, event)
+                    })
+                });
+                self.awaiting_first_paint = false;
+                load_trace.finish();
+                startup::finish();
+            } else {
+                self.inner.window_event(event_loop, window_id, event);
+            }
+            let scrolled = self.note_painted_scroll(window_id);
+            self.select_scroll_anchor(window_id);
+            self.frame_stats
+                .record(started, started.elapsed(), scrolled);
+            self.record_damage(window_id);
+            self.update_intersections(window_id);
+            if self.smooth_scroll.is_animating() {
+                if let Some(view) = self.inner.windows.get_mut(&window_id) {
+                    view.request_redraw();
+                }
+            }
+        } else {
+            self.inner.window_event(event_loop, window_id, event);
+        }
+
+        if let Some(key) = scroll_key {
+            self.handle_scroll_key(event_loop, window_id, &key);
+        }
+        if let Some((phase, delta)) = swipe_step {
+            self.handle_swipe(window_id, phase, delta);
+        }
+
+        if rescaled {
+            // The renderer picked up the new scale; repaint so glyphs and images are
+            // rasterized at the new density rather than scaled from the old frame.
+            if let Some(view) = self.inner.windows.get_mut(&window_id) {
+                view.request_redraw();
+            }
+        }
+        if resized {
+            self.sync_viewport();
+        }
+        if resized || moved {
+            self.track_window_state(window_id);
+        }
+        if cursor_moved || cursor_left || primary_button.is_some() {
+            self.sync_element_states(window_id, cursor_moved, cursor_left, primary_button);
+        }
+        if cursor_moved {
+            self.track_hovered_link(window_id);
+        }
+        if clicked {
+            self.handle_click(window_id);
+        }
+        if theme_changed {
+            self.sync_media_preferences();
+        }
     }
 
     fn user_event(&mut self, event_loop: &ActiveEventLoop, event: BlitzShellEvent) {
@@ -1139,9 +4976,33 @@ impl ApplicationHandler<BlitzShellEvent> for ReadmeApplication {
                 if let Some(event) = event.downcast_ref::<ReadmeEvent>() {
                     match event {
                         ReadmeEvent::Refresh => self.reload_document(true),
+                        ReadmeEvent::ConnectivityRestored => self.handle_connectivity_restored(),
+                        ReadmeEvent::HoverSettled(url) => self.handle_hover_settled(url.clone()),
                         ReadmeEvent::Navigation(message) => {
                             self.handle_navigation_message((**message).clone())
                         }
+                        ReadmeEvent::RelayListImported {
+                            npub,
+                            result,
+                            generation,
+                        } => self.handle_relay_import(npub, result.clone(), *generation),
+                        ReadmeEvent::FrameLoaded {
+                            source,
+                            result,
+                            generation,
+                        } => self.handle_frame_loaded(source.clone(), result.clone(), *generation),
+                        ReadmeEvent::FrameActivity => {
+                            self.frames_woken = true;
+                            self.service_frames();
+                        }
+                        ReadmeEvent::Popup(event) => self.handle_popup_event(event),
+                        ReadmeEvent::P2p(event) => self.handle_p2p_event(event),
+                        ReadmeEvent::OpenerMessage(origin, message) => {
+                            self.deliver_window_message(origin, message, WindowRef::Opener)
+                        }
+                        ReadmeEvent::Command(command) => {
+                            self.run_command(event_loop, command.clone())
+                        }
                     }
                     return;
                 }
@@ -1153,17 +5014,77 @@ impl ApplicationHandler<BlitzShellEvent> for ReadmeApplication {
             BlitzShellEvent::Navigate(options) => {
                 self.navigate(*options);
             }
-            other => self.inner.user_event(event_loop, other),
+            other => {
+                self.inner.user_event(event_loop, other);
+                self.maybe_fire_load();
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Chords kiosk mode keeps from blitz, which toggles its layout debugging views on Alt+D,
+/// Alt+H and Alt+T and zooms on Ctrl/Cmd with `=`, `-` and `0`.
+fn kiosk_swallows_key(mods: ModifiersState, key: PhysicalKey) -> bool {
+    let PhysicalKey::Code(code) = key else {
+        return false;
+    };
+    if mods.control_key() || mods.super_key() {
+        return matches!(code, KeyCode::Equal | KeyCode::Minus | KeyCode::Digit0);
+    }
+    mods.alt_key() && matches!(code, KeyCode::KeyD | KeyCode::KeyH | KeyCode::KeyT)
+}
+
+fn is_modifier_key(key: NamedKey) -> bool {
+    matches!(
+        key,
+        NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super | NamedKey::Meta
+    )
+}
+
+/// The form widget key `key` stands for, if any.
+fn widget_key(key: &Key) -> Option<WidgetKey> {
+    Some(match key {
+        Key::Named(NamedKey::ArrowUp) => WidgetKey::Up,
+        Key::Named(NamedKey::ArrowDown) => WidgetKey::Down,
+        Key::Named(NamedKey::ArrowLeft) => WidgetKey::Left,
+        Key::Named(NamedKey::ArrowRight) => WidgetKey::Right,
+        Key::Named(NamedKey::Home) => WidgetKey::Home,
+        Key::Named(NamedKey::End) => WidgetKey::End,
+        Key::Named(NamedKey::PageUp) => WidgetKey::PageUp,
+        Key::Named(NamedKey::PageDown) => WidgetKey::PageDown,
+        Key::Named(NamedKey::Enter) => WidgetKey::Enter,
+        Key::Named(NamedKey::Space) => WidgetKey::Space,
+        Key::Named(NamedKey::Escape) => WidgetKey::Escape,
+        Key::Character(text) => WidgetKey::Character(text.chars().next()?),
+        _ => return None,
+    })
+}
+
+/// Modifier names as automation keyboard shortcuts spell them.
+fn modifier_names(mods: ModifiersState) -> Vec<String> {
+    [
+        (mods.control_key(), "ctrl"),
+        (mods.super_key(), "meta"),
+        (mods.alt_key(), "alt"),
+        (mods.shift_key(), "shift"),
+    ]
+    .into_iter()
+    .filter(|(held, _)| *held)
+    .map(|(_, name)| name.to_string())
+    .collect()
+}
+
+#[derive(Clone, Debug)]
 struct DocumentChromeHandles {
     #[allow(dead_code)]
     content_root: usize,
     #[allow(dead_code)]
     url_input: usize,
+    overlay_host: usize,
+    /// Badge with the page's blocked request count; only pages with site settings have one.
+    blocked_count: Option<usize>,
+    /// The chrome's links by node, as built and before any script could change them.
+    links: HashMap<usize, Url>,
 }
 
 impl DocumentChromeHandles {
@@ -1171,10 +5092,19 @@ impl DocumentChromeHandles {
         let content_root =
             find_node_by_id(document, "content").context("content container missing")?;
         let url_input = find_node_by_id(document, "url-input").context("url input missing")?;
+        let overlay_host =
+            find_node_by_id(document, "overlay-host").context("overlay host missing")?;
 
+        // The chrome's bar comes before the page's content, so the first match is the real one.
+        let links = find_node_by_id(document, "url-bar-container")
+            .map(|bar| chrome_links(document, bar))
+            .unwrap_or_default();
         Ok(Self {
             content_root,
             url_input,
+            overlay_host,
+            blocked_count: find_node_by_id(document, "blocked-count"),
+            links,
         })
     }
 }
@@ -1197,27 +5127,258 @@ fn find_node_by_id(document: &mut HtmlDocument, target: &str) -> Option<usize> {
     result
 }
 
+/// The absolute links under the chrome's bar at `bar`, by node.
+fn chrome_links(document: &mut HtmlDocument, bar: usize) -> HashMap<usize, Url> {
+    let mut links = HashMap::new();
+    document.iter_subtree_mut(bar, |node_id, doc| {
+        let href = doc
+            .get_node(node_id)
+            .filter(|node| {
+                node.element_data()
+                    .is_some_and(|element| element.name.local == local_name!("a"))
+            })
+            .and_then(|node| node.attr(local_name!("href")))
+            .and_then(|href| Url::parse(href).ok());
+        if let Some(href) = href {
+            links.insert(node_id, href);
+        }
+    });
+    links
+}
+
+/// The chrome link at or above `node_id`, as the chrome built it.
+fn chrome_link_at<'a>(
+    doc: &BaseDocument,
+    links: &'a HashMap<usize, Url>,
+    node_id: usize,
+) -> Option<&'a Url> {
+    let mut current = doc.get_node(node_id);
+    while let Some(node) = current {
+        if let Some(link) = links.get(&node.id) {
+            return Some(link);
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    None
+}
+
+/// Where a navigation came from, which decides what it may do (see
+/// [`NavigationSource::may_open`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NavigationSource {
+    /// A click on one of the chrome's own links: its menu, site settings or identity button.
+    Chrome,
+    /// A link or form of a `frontier://` page.
+    InternalPage,
+    /// A web page's link, form or `window.open`.
+    Page,
+}
+
+/// `frontier://` pages whose query changes browser state, such as a form's submission.
+const ACTION_PAGES: [&str; 6] = [
+    SETTINGS_URL,
+    RELAYS_URL,
+    IDENTITIES_URL,
+    RESTORE_URL,
+    SITE_SETTINGS_URL,
+    PUBLISH_QUEUE_URL,
+];
+
+impl NavigationSource {
+    /// Whether a navigation from here may go to `url`. Browser commands need the chrome. Web
+    /// pages may open the [`ACTION_PAGES`] only to show them; their actions need the chrome or
+    /// a `frontier://` page.
+    fn may_open(self, url: &Url) -> bool {
+        if MenuCommand::from_url(url).is_some() {
+            return self == NavigationSource::Chrome;
+        }
+        let action = url.query().is_some()
+            && ACTION_PAGES
+                .iter()
+                .any(|page| is_internal_page(url.as_str(), page));
+        self != NavigationSource::Page || !action
+    }
+}
+
+/// Timing of the current navigation from the network log: the document's fetch measured from
+/// when the log was reset.
+fn navigation_timing(log: &NetworkLog, url: &str) -> NavigationTiming {
+    let document = log.entries().into_iter().rev().find(|entry| {
+        entry.initiator == Initiator::Document
+            && entry.error.is_none()
+            && entry.duration_ms.is_some()
+    });
+    NavigationTiming {
+        time_origin: log.started(),
+        url: url.to_string(),
+        fetch_start: document.as_ref().map_or(0.0, |entry| entry.start_ms),
+        response_end: document.as_ref().map_or(0.0, |entry| {
+            entry.start_ms + entry.duration_ms.unwrap_or_default()
+        }),
+        transfer_size: document.and_then(|entry| entry.size).unwrap_or_default(),
+    }
+}
+
+/// Absolute URL of the `<a href>` at or above `node_id`, resolved against `base`.
+fn link_target(doc: &BaseDocument, node_id: usize, base: Option<&Url>) -> Option<Url> {
+    let mut current = doc.get_node(node_id);
+    while let Some(node) = current {
+        let is_anchor = node
+            .element_data()
+            .is_some_and(|element| element.name.local == local_name!("a"));
+        if is_anchor {
+            let href = node.attr(local_name!("href"))?;
+            return match base {
+                Some(base) => base.join(href).ok(),
+                None => Url::parse(href).ok(),
+            };
+        }
+        current = node.parent.and_then(|parent| doc.get_node(parent));
+    }
+    None
+}
+
+/// Targets of `<link rel="prefetch" href>` in `document`, resolved against `base_url`. The
+/// page chooses them, so [`Prefetcher::prefetch`] checks each against its [`PrefetchScope`].
+fn collect_prefetch_links(document: &mut HtmlDocument, base_url: &str) -> Vec<Url> {
+    let base = Url::parse(base_url).ok();
+    let mut links = Vec::new();
+    let root_id = document.root_node().id;
+    document.iter_subtree_mut(root_id, |node_id, doc| {
+        let Some(node) = doc.get_node(node_id) else {
+            return;
+        };
+        let is_link = node
+            .element_data()
+            .is_some_and(|element| element.name.local == local_name!("link"));
+        if !is_link
+            || !node
+                .attr(local_name!("rel"))
+                .is_some_and(prefetch::is_prefetch_rel)
+        {
+            return;
+        }
+        let Some(href) = node.attr(local_name!("href")) else {
+            return;
+        };
+        let resolved = match &base {
+            Some(base) => base.join(href).ok(),
+            None => Url::parse(href).ok(),
+        };
+        if let Some(url) = resolved {
+            links.push(url);
+        }
+    });
+    links
+}
+
+/// Whether `url` is the `frontier://` page `page`, with or without a form query.
+fn is_internal_page(url: &str, page: &str) -> bool {
+    match url.strip_prefix(page) {
+        Some(rest) => rest.is_empty() || rest.starts_with('?') || rest == "/",
+        None => false,
+    }
+}
+
+struct FetchTaskOptions {
+    cache: Option<PageCache>,
+    work_offline: bool,
+    prefetcher: Option<Arc<Prefetcher>>,
+    network_log: NetworkLog,
+    load_trace: LoadTrace,
+}
+
+/// Fetch `request`, keeping a copy of http(s) documents in `cache` and falling back to it
+/// when the network is unreachable or `work_offline` is set. A fresh prefetched copy is used
+/// instead of the network when there is one. Every load is recorded in `network_log`.
 async fn run_fetch_task(
     request: FetchRequest,
     net_provider: Arc<Provider<Resource>>,
     proxy: EventLoopProxy<BlitzShellEvent>,
     retain_scroll: bool,
+    generation: u64,
+    options: FetchTaskOptions,
 ) {
-    match execute_fetch(&request, net_provider).await {
+    let FetchTaskOptions {
+        cache,
+        work_offline,
+        prefetcher,
+        network_log,
+        load_trace,
+    } = options;
+    let cache_url = match &request.source {
+        FetchSource::Url(url) if PageCache::is_cacheable(url) => Some(url.clone()),
+        _ => None,
+    };
+    // Prefetches go out with Frontier's own user agent, which an emulating window must not use.
+    let prefetched = match (&prefetcher, &request.source) {
+        (Some(prefetcher), FetchSource::Url(url))
+            if !work_offline && request.user_agent.is_none() =>
+        {
+            prefetcher.take(url)
+        }
+        _ => None,
+    };
+    let result = if let Some(mut document) = prefetched {
+        if let FetchSource::Url(url) = &request.source {
+            network_log
+                .begin("GET", url, Initiator::Document, RequestSource::Prefetch)
+                .finish(None, Vec::new(), document.contents.as_bytes());
+        }
+        document.display_url = request.display_url.clone();
+        Ok(document)
+    } else if work_offline && cache_url.is_some() {
+        Err(FetchError::Network("working offline".to_string()))
+    } else {
+        load_trace
+            .time_async(
+                Phase::Fetch,
+                execute_fetch_logged(&request, net_provider, &network_log),
+            )
+            .await
+    };
+
+    let message = match result {
         Ok(document) => {
-            let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Completed {
+            if let (Some(cache), Some(url)) = (&cache, &cache_url) {
+                if let Err(err) = cache.store(url, &document) {
+                    warn!(url = %url, error = %err, "failed to cache page");
+                }
+            }
+            NavigationMessage::Completed {
                 document: Box::new(document),
                 retain_scroll,
-            }));
-            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+                generation,
+            }
         }
-        Err(err) => {
-            let event = ReadmeEvent::Navigation(Box::new(NavigationMessage::Failed {
-                message: err.to_string(),
-            }));
-            let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
+        Err(FetchError::Network(reason)) if cache_url.is_some() => {
+            let url = cache_url.expect("checked by the guard");
+            let cached = cache
+                .as_ref()
+                .and_then(|cache| cache.load(&url))
+                .map(|mut cached| {
+                    network_log
+                        .begin("GET", &url, Initiator::Document, RequestSource::Cache)
+                        .finish(None, Vec::new(), cached.document.contents.as_bytes());
+                    cached.document.display_url = request.display_url.clone();
+                    Box::new(cached)
+                });
+            NavigationMessage::Offline {
+                input: request.display_url.clone(),
+                url,
+                reason,
+                cached,
+                retain_scroll,
+                generation,
+            }
         }
-    }
+        Err(err) => NavigationMessage::Failed {
+            message: err.to_string(),
+            generation,
+        },
+    };
+    let event = ReadmeEvent::Navigation(Box::new(message));
+    let _ = proxy.send_event(BlitzShellEvent::Embedder(Arc::new(event)));
 }
 
 #[cfg(test)]
@@ -1226,7 +5387,6 @@ mod tests {
     use crate::create_default_event_loop;
     use crate::navigation::{execute_fetch, FetchRequest, FetchSource};
     use crate::WindowConfig;
-    use crate::WindowRenderer;
     use blitz_traits::net::DummyNetCallback;
     use std::path::Path;
     use std::sync::Arc;
@@ -1245,6 +5405,7 @@ mod tests {
         any(target_os = "macos", target_os = "linux"),
         ignore = "Winit requires the event loop to be created on the main thread"
     )]
+    #[cfg(feature = "cpu-base")]
     fn react_demo_navigation_replaces_document() {
         let runtime = Builder::new_current_thread().enable_all().build().unwrap();
         let (index_doc, timer_doc, display_url, net_provider) = runtime.block_on(async {
@@ -1257,6 +5418,7 @@ mod tests {
             let fetch_index = FetchRequest {
                 source: FetchSource::Url(index_url.clone()),
                 display_url: index_url.to_string(),
+                user_agent: None,
             };
             let index_doc = execute_fetch(&fetch_index, Arc::clone(&net_provider))
                 .await
@@ -1265,6 +5427,7 @@ mod tests {
             let fetch_timer = FetchRequest {
                 source: FetchSource::Url(timer_url.clone()),
                 display_url: timer_url.to_string(),
+                user_agent: None,
             };
             let timer_doc = execute_fetch(&fetch_timer, Arc::clone(&net_provider))
                 .await
@@ -1281,7 +5444,7 @@ mod tests {
             ReadmeApplication::new(proxy, display_url, Arc::clone(&net_provider), nav_provider);
         app.prepare_initial_state(index_doc);
         let initial_document = app.take_initial_document();
-        let renderer = WindowRenderer::new();
+        let renderer = crate::CpuRenderer::new();
         let attrs = WindowAttributes::default().with_title("React demos test harness");
         let window = WindowConfig::with_attributes(initial_document, renderer, attrs);
         app.add_window(window);
@@ -1290,7 +5453,102 @@ mod tests {
         app.handle_navigation_message(NavigationMessage::Completed {
             document: Box::new(timer_doc),
             retain_scroll: false,
+            generation: 0,
         });
         app.render_current_document(false);
     }
+
+    #[test]
+    fn page_links_cannot_run_browser_actions() {
+        let content = r#"<a id="remove" href="frontier://identities?action=remove&npub=npub1x">Win</a>
+            <nav id="url-bar-container"><a id="fake" href="frontier://identities?action=site">
+            Use</a></nav>"#;
+        let menu = r#"<a id="quit" href="frontier://command/quit">Quit</a>"#;
+        let html = wrap_with_chrome(
+            content,
+            "https://evil.example/",
+            None,
+            Some("Alice"),
+            Some(menu),
+            None,
+        );
+        let mut doc = HtmlDocument::from_html(&html, DocumentConfig::default());
+        let handles = DocumentChromeHandles::compute(&mut doc).unwrap();
+        let remove = find_node_by_id(&mut doc, "remove").unwrap();
+        let fake = find_node_by_id(&mut doc, "fake").unwrap();
+        let identity = find_node_by_id(&mut doc, "identity-button").unwrap();
+        let quit_link = find_node_by_id(&mut doc, "quit").unwrap();
+        let doc: &BaseDocument = &doc;
+
+        let identities = Url::parse(IDENTITIES_URL).unwrap();
+        assert_eq!(
+            chrome_link_at(doc, &handles.links, identity),
+            Some(&identities)
+        );
+        assert_eq!(chrome_link_at(doc, &handles.links, remove), None);
+        assert_eq!(
+            chrome_link_at(doc, &handles.links, fake),
+            None,
+            "a copy of the bar is no chrome"
+        );
+
+        let action = Url::parse("frontier://identities?action=remove&npub=npub1x").unwrap();
+        assert!(!NavigationSource::Page.may_open(&action));
+        assert!(NavigationSource::Page.may_open(&identities));
+        assert!(NavigationSource::Page.may_open(&Url::parse("https://a.example/?q=1").unwrap()));
+        assert!(NavigationSource::InternalPage.may_open(&action));
+        assert!(NavigationSource::Chrome.may_open(&action));
+
+        let search =
+            Url::parse("frontier://settings?search=https%3A%2F%2Fevil.example%2F%3Fq%3D%25s")
+                .unwrap();
+        assert!(!NavigationSource::Page.may_open(&search));
+        assert!(NavigationSource::InternalPage.may_open(&search));
+
+        let remove = Url::parse("frontier://relays?remove=wss%3A%2F%2Frelay.example").unwrap();
+        assert!(!NavigationSource::Page.may_open(&remove));
+        assert!(NavigationSource::InternalPage.may_open(&remove));
+
+        let discard = Url::parse("frontier://restore?action=discard").unwrap();
+        assert!(!NavigationSource::Page.may_open(&discard));
+        assert!(NavigationSource::InternalPage.may_open(&discard));
+
+        let retry = Url::parse("frontier://publish-queue?retry").unwrap();
+        assert!(!NavigationSource::Page.may_open(&retry));
+        assert!(NavigationSource::InternalPage.may_open(&retry));
+        assert!(NavigationSource::Page.may_open(&Url::parse(PUBLISH_QUEUE_URL).unwrap()));
+
+        let quit = Url::parse("frontier://command/quit").unwrap();
+        assert_eq!(chrome_link_at(doc, &handles.links, quit_link), Some(&quit));
+        assert!(!NavigationSource::Page.may_open(&quit));
+        assert!(!NavigationSource::InternalPage.may_open(&quit));
+        assert!(NavigationSource::Chrome.may_open(&quit));
+        let visit = Url::parse("frontier://command/visit?url=https%3A%2F%2Fa.example").unwrap();
+        assert!(!NavigationSource::Page.may_open(&visit));
+    }
+
+    #[test]
+    fn page_links_cannot_toggle_site_settings() {
+        let content = r#"<a id="toggle" href="frontier://site-settings?toggle=javascript">Go</a>"#;
+        let site_info = site_info_html("https://evil.example", &Default::default());
+        let html = wrap_with_chrome(
+            content,
+            "https://evil.example/",
+            None,
+            None,
+            None,
+            Some(&site_info),
+        );
+        let mut doc = HtmlDocument::from_html(&html, DocumentConfig::default());
+        let handles = DocumentChromeHandles::compute(&mut doc).unwrap();
+        let link = find_node_by_id(&mut doc, "toggle").unwrap();
+        let doc: &BaseDocument = &doc;
+
+        let toggle = Url::parse("frontier://site-settings?toggle=javascript").unwrap();
+        assert!(handles.links.values().any(|url| *url == toggle));
+        assert_eq!(chrome_link_at(doc, &handles.links, link), None);
+        assert!(!NavigationSource::Page.may_open(&toggle));
+        assert!(NavigationSource::Chrome.may_open(&toggle));
+        assert!(NavigationSource::Page.may_open(&Url::parse(SITE_SETTINGS_URL).unwrap()));
+    }
 }
@@ -0,0 +1,306 @@
+//! Recorded user sessions for reproducing bug reports.
+//!
+//! `frontier --record session.json <url>` captures the pointer, wheel and keyboard input the
+//! window receives, plus URL bar navigations, as a [`Recording`]: a JSON script of automation
+//! actions stamped with milliseconds since the recording started. Positions are CSS pixels in
+//! the content viewport, so a script replays on any display density. The automation host
+//! replays a script with `POST /session/:id/replay`, which restores the viewport and start
+//! URL and then runs each step once the page's virtual clock has been advanced to its
+//! timestamp, so timers fire at the same points between the steps however long the page
+//! takes to load. Text typed into password fields is recorded as asterisks.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::automation::{KeyboardAction, PointerAction, PointerButton, PointerTarget};
+
+/// Command-line flag that records the session into the file that follows it.
+pub const RECORD_FLAG: &str = "--record";
+
+/// Bumped when the step format changes incompatibly.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// Pointer moves with a button held are kept at most this often; other moves only matter
+/// for where the next press lands.
+const DRAG_MOVE_INTERVAL_MS: u64 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub start_url: String,
+    /// Content viewport in CSS pixels when recording started.
+    pub viewport: Option<Viewport>,
+    pub steps: Vec<RecordedStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedStep {
+    /// Milliseconds since the recording started.
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub action: RecordedAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedAction {
+    Pointer {
+        action: PointerAction,
+    },
+    Keyboard {
+        action: KeyboardAction,
+    },
+    /// A navigation typed into the URL bar or triggered by a browser shortcut. Link clicks
+    /// are not recorded here; replaying the click navigates again.
+    Navigate {
+        url: String,
+    },
+}
+
+/// Collects a [`Recording`] while the browser runs and writes it when dropped.
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    recording: Recording,
+    cursor: Option<(f64, f64)>,
+    /// Cursor position last written as a move step.
+    recorded_cursor: Option<(f64, f64)>,
+    buttons_down: usize,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf, start_url: String) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            recording: Recording {
+                version: RECORDING_VERSION,
+                start_url,
+                viewport: None,
+                steps: Vec::new(),
+            },
+            cursor: None,
+            recorded_cursor: None,
+            buttons_down: 0,
+        }
+    }
+
+    /// Remember the viewport the recording started with; later resizes are not replayed.
+    pub fn set_viewport(&mut self, width: f64, height: f64) {
+        if self.recording.viewport.is_none() {
+            self.recording.viewport = Some(Viewport { width, height });
+        }
+    }
+
+    pub fn cursor_moved(&mut self, x: f64, y: f64) {
+        self.cursor = Some((x, y));
+        let dragging = self.buttons_down > 0;
+        let due = self
+            .recording
+            .steps
+            .last()
+            .is_none_or(|step| self.elapsed_ms() >= step.at_ms + DRAG_MOVE_INTERVAL_MS);
+        if dragging && due {
+            self.push_move();
+        }
+    }
+
+    pub fn pointer_button(&mut self, button: PointerButton, pressed: bool) {
+        self.push_move();
+        let action = if pressed {
+            self.buttons_down += 1;
+            PointerAction::Down { button }
+        } else {
+            self.buttons_down = self.buttons_down.saturating_sub(1);
+            PointerAction::Up { button }
+        };
+        self.push(RecordedAction::Pointer { action });
+    }
+
+    pub fn scroll(&mut self, delta_x: f64, delta_y: f64) {
+        let origin = self.cursor.map(|(x, y)| PointerTarget::Viewport { x, y });
+        self.recorded_cursor = self.cursor;
+        self.push(RecordedAction::Pointer {
+            action: PointerAction::Scroll {
+                origin,
+                delta_x,
+                delta_y,
+            },
+        });
+    }
+
+    /// Text typed or committed by an input method. Consecutive characters share one step.
+    pub fn text(&mut self, value: &str) {
+        let at_ms = self.elapsed_ms();
+        if let Some(RecordedStep {
+            at_ms: last_at,
+            action:
+                RecordedAction::Keyboard {
+                    action: KeyboardAction::Text { value: typed },
+                },
+        }) = self.recording.steps.last_mut()
+        {
+            if at_ms.saturating_sub(*last_at) < 1_000 {
+                typed.push_str(value);
+                *last_at = at_ms;
+                return;
+            }
+        }
+        self.push(RecordedAction::Keyboard {
+            action: KeyboardAction::Text {
+                value: value.to_string(),
+            },
+        });
+    }
+
+    /// Text typed into a password field, recorded as one `*` per character so the script
+    /// replays the same keystrokes without holding the password.
+    pub fn secret_text(&mut self, value: &str) {
+        self.text(&"*".repeat(value.chars().count()));
+    }
+
+    pub fn key(&mut self, key: String, modifiers: Vec<String>) {
+        self.push(RecordedAction::Keyboard {
+            action: KeyboardAction::Shortcut { key, modifiers },
+        });
+    }
+
+    pub fn navigated(&mut self, url: &str) {
+        self.push(RecordedAction::Navigate {
+            url: url.to_string(),
+        });
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save(&self.path, &self.recording)
+    }
+
+    fn push_move(&mut self) {
+        let Some((x, y)) = self.cursor else {
+            return;
+        };
+        if self.recorded_cursor == self.cursor {
+            return;
+        }
+        self.recorded_cursor = self.cursor;
+        self.push(RecordedAction::Pointer {
+            action: PointerAction::Move {
+                to: PointerTarget::Viewport { x, y },
+            },
+        });
+    }
+
+    fn push(&mut self, action: RecordedAction) {
+        let at_ms = self.elapsed_ms();
+        self.recording.steps.push(RecordedStep { at_ms, action });
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        match self.save() {
+            Ok(()) => info!(
+                path = %self.path.display(),
+                steps = self.recording.steps.len(),
+                "saved session recording"
+            ),
+            Err(err) => warn!(error = %err, "failed to save session recording"),
+        }
+    }
+}
+
+fn save(path: &Path, recording: &Recording) -> Result<()> {
+    let raw = serde_json::to_string_pretty(recording).context("serialize recording")?;
+    std::fs::write(path, raw)
+        .with_context(|| format!("unable to write recording {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_clicks_typing_and_drags_as_automation_steps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        {
+            let mut recorder = Recorder::new(path.clone(), "https://example.com/".into());
+            recorder.set_viewport(800.0, 600.0);
+            recorder.cursor_moved(10.0, 10.0);
+            recorder.cursor_moved(40.0, 20.0);
+            recorder.pointer_button(PointerButton::Primary, true);
+            recorder.pointer_button(PointerButton::Primary, false);
+            recorder.text("h");
+            recorder.text("i");
+            recorder.key("Enter".into(), Vec::new());
+        }
+        let recording: Recording =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(recording.version, RECORDING_VERSION);
+        assert_eq!(
+            recording.viewport,
+            Some(Viewport {
+                width: 800.0,
+                height: 600.0
+            })
+        );
+        let actions: Vec<_> = recording
+            .steps
+            .iter()
+            .map(|step| step.action.clone())
+            .collect();
+        assert_eq!(actions.len(), 5);
+        assert!(matches!(
+            &actions[0],
+            RecordedAction::Pointer {
+                action: PointerAction::Move {
+                    to: PointerTarget::Viewport { x, y }
+                }
+            } if *x == 40.0 && *y == 20.0
+        ));
+        assert!(matches!(
+            &actions[3],
+            RecordedAction::Keyboard {
+                action: KeyboardAction::Text { value }
+            } if value == "hi"
+        ));
+        assert!(recording
+            .steps
+            .windows(2)
+            .all(|pair| pair[0].at_ms <= pair[1].at_ms));
+    }
+
+    #[test]
+    fn password_input_is_recorded_as_asterisks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        {
+            let mut recorder = Recorder::new(path.clone(), "https://example.com/".into());
+            recorder.text("me");
+            recorder.secret_text("hunter2");
+        }
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("hunter2"));
+        let recording: Recording = serde_json::from_str(&raw).unwrap();
+        assert!(matches!(
+            &recording.steps[0].action,
+            RecordedAction::Keyboard {
+                action: KeyboardAction::Text { value }
+            } if value == "me*******"
+        ));
+    }
+}
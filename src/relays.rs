@@ -0,0 +1,270 @@
+//! `frontier://relays`: the relay list saved in preferences, a reachability probe per relay and
+//! import of a user's NIP-65 relay list.
+//!
+//! Add, remove and import requests arrive as `frontier://relays?add=...`, `?remove=...` and
+//! `?import=<npub>` navigations, parsed by [`RelayAction::parse`], and only run when they
+//! come from the relays page or the chrome, never from a web page. Probing and importing talk to
+//! the network, so the page is built on the navigation task like any fetched document.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::future::join_all;
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use nostr_sdk::nips::nip65::extract_relay_list;
+use nostr_sdk::prelude::{Client, ClientBuilder, Filter, FromBech32, Kind, Options, PublicKey};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+use crate::name_search;
+use crate::proxy_tunnel;
+use crate::publish_queue::PUBLISH_QUEUE_URL;
+
+pub const RELAYS_URL: &str = "frontier://relays";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const IMPORT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Asked for relay lists when the user has not saved any relays yet.
+const INDEX_RELAYS: &[&str] = &["wss://purplepag.es", "wss://relay.damus.io"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayAction {
+    Add(Url),
+    Remove(Url),
+    /// Replace nothing, merge the NIP-65 list published by this npub (or hex pubkey).
+    Import(String),
+}
+
+impl RelayAction {
+    /// Parse the query of a `frontier://relays` navigation. `Ok(None)` means just show the page.
+    pub fn parse(query: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(query) = query else {
+            return Ok(None);
+        };
+        let Some((key, value)) = url::form_urlencoded::parse(query.as_bytes()).next() else {
+            return Ok(None);
+        };
+        let value = value.trim().to_string();
+        match key.as_ref() {
+            "add" => parse_relay_url(&value).map(|url| Some(RelayAction::Add(url))),
+            "remove" => parse_relay_url(&value).map(|url| Some(RelayAction::Remove(url))),
+            "import" if !value.is_empty() => Ok(Some(RelayAction::Import(value))),
+            "import" => Err("enter an npub to import".to_string()),
+            other => Err(format!("unknown relay action {other}")),
+        }
+    }
+}
+
+/// Whether loading `input` talks to relays: name searches, `nostr:` links and the relay and
+/// publish queue pages. The browser holds its own relay traffic back until one is requested.
+pub fn uses_relays(input: &str) -> bool {
+    let input = input.trim();
+    name_search::term_from_input(input).is_some()
+        || input.starts_with("nostr:")
+        || input.starts_with(RELAYS_URL)
+        || input.starts_with(PUBLISH_QUEUE_URL)
+}
+
+pub fn parse_relay_url(raw: &str) -> Result<Url, String> {
+    match Url::parse(raw.trim()) {
+        Ok(url) if matches!(url.scheme(), "ws" | "wss") && url.host_str().is_some() => Ok(url),
+        Ok(url) => Err(format!("{url} is not a ws:// or wss:// relay")),
+        Err(err) => Err(format!("invalid relay URL {raw}: {err}")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayHealth {
+    Connected { latency: Duration },
+    Failed(String),
+}
+
+/// Open a WebSocket to each relay concurrently, the way relay clients would reach it, and
+/// report how it went.
+pub async fn probe_all(relays: &[Url]) -> Vec<(Url, RelayHealth)> {
+    let probes = relays
+        .iter()
+        .map(|relay| async move { (relay.clone(), probe(relay).await) });
+    join_all(probes).await
+}
+
+async fn probe(relay: &Url) -> RelayHealth {
+    let started = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, open_websocket(relay)).await {
+        Ok(Ok(mut stream)) => {
+            let latency = started.elapsed();
+            let _ = stream.close(None).await;
+            RelayHealth::Connected { latency }
+        }
+        Ok(Err(err)) => RelayHealth::Failed(format!("{err:#}")),
+        Err(_) => RelayHealth::Failed(format!("no answer within {}s", PROBE_TIMEOUT.as_secs())),
+    }
+}
+
+/// A WebSocket to `relay` over [`proxy_tunnel::connect`], so the probe takes the proxy and
+/// bypass list into account.
+async fn open_websocket(
+    relay: &Url,
+) -> Result<WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>> {
+    let host = relay
+        .host_str()
+        .ok_or_else(|| anyhow!("{relay} has no host"))?;
+    let port = relay
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("{relay} has no port"))?;
+    let stream = proxy_tunnel::connect(host, port).await?;
+    let (socket, _) = tokio_tungstenite::client_async_tls(relay.as_str(), stream).await?;
+    Ok(socket)
+}
+
+/// Newest kind-10002 relay list published by `npub`, asked of `relays` (or well-known index
+/// relays when the list is empty).
+pub async fn fetch_relay_list(npub: &str, relays: &[Url]) -> Result<Vec<Url>> {
+    let author = PublicKey::from_bech32(npub)
+        .or_else(|_| PublicKey::from_hex(npub))
+        .map_err(|err| anyhow!("invalid npub {npub}: {err}"))?;
+
+    let client = connect(relays).await?;
+    let filter = Filter::new().author(author).kind(Kind::RelayList).limit(1);
+    let events = client
+        .get_events_of(vec![filter], Some(IMPORT_TIMEOUT))
+        .await
+        .context("query relay list");
+    let _ = client.disconnect().await;
+
+    let newest = events?
+        .into_iter()
+        .max_by_key(|event| event.created_at)
+        .ok_or_else(|| anyhow!("no relay list published by {npub}"))?;
+    let mut imported = Vec::new();
+    for (url, _) in extract_relay_list(&newest) {
+        if let Ok(url) = parse_relay_url(&url.to_string()) {
+            if !imported.contains(&url) {
+                imported.push(url);
+            }
+        }
+    }
+    Ok(imported)
+}
+
+/// A client connected to `relays`, or to well-known index relays when the list is empty,
+/// through the configured proxy.
+pub(crate) async fn connect(relays: &[Url]) -> Result<Client> {
+    let opts = Options::new().proxy(proxy_tunnel::relay_proxy()?);
+    let client = ClientBuilder::new().opts(opts).build();
+    let sources: Vec<String> = if relays.is_empty() {
+        INDEX_RELAYS.iter().map(|relay| relay.to_string()).collect()
+    } else {
+        relays.iter().map(|relay| relay.to_string()).collect()
+    };
+    for relay in &sources {
+        client.add_relay(relay.as_str()).await?;
+    }
+    client.connect().await;
+    Ok(client)
+}
+
+/// HTML body of `frontier://relays`.
+pub fn relays_page(entries: &[(Url, RelayHealth)], status: Option<&str>) -> String {
+    let rows = if entries.is_empty() {
+        "<tr><td colspan=\"3\">No relays saved yet.</td></tr>".to_string()
+    } else {
+        entries
+            .iter()
+            .map(|(relay, health)| {
+                let health = match health {
+                    RelayHealth::Connected { latency } => {
+                        format!("connected in {} ms", latency.as_millis())
+                    }
+                    RelayHealth::Failed(reason) => format!("unreachable: {}", encode_text(reason)),
+                };
+                let remove = url::form_urlencoded::Serializer::new(String::new())
+                    .append_pair("remove", relay.as_str())
+                    .finish();
+                format!(
+                    "<tr><td><code>{relay}</code></td><td>{health}</td><td><a href=\"{RELAYS_URL}?{remove}\">Remove</a></td></tr>",
+                    relay = encode_text(relay.as_str()),
+                    remove = encode_double_quoted_attribute(&remove),
+                )
+            })
+            .collect()
+    };
+    let status = status
+        .map(|message| {
+            format!(
+                "<p class=\"relays-status\" role=\"status\">{}</p>",
+                encode_text(message)
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        r#"<section class="relays">
+<h1>Relays</h1>
+{status}
+<table>
+<thead><tr><th>Relay</th><th>Health</th><th></th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+<form action="{RELAYS_URL}" method="get">
+<p><label for="add-relay">Add relay</label><br><input type="url" id="add-relay" name="add" placeholder="wss://relay.example"> <input type="submit" value="Add"></p>
+</form>
+<form action="{RELAYS_URL}" method="get">
+<p><label for="import-npub">Import NIP-65 relay list</label><br><input type="text" id="import-npub" name="import" placeholder="npub1..."> <input type="submit" value="Import"></p>
+</form>
+</section>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relay_actions() {
+        assert_eq!(RelayAction::parse(None), Ok(None));
+        assert_eq!(
+            RelayAction::parse(Some("add=wss%3A%2F%2Frelay.example")),
+            Ok(Some(RelayAction::Add(
+                Url::parse("wss://relay.example").unwrap()
+            )))
+        );
+        assert!(RelayAction::parse(Some("add=https%3A%2F%2Frelay.example")).is_err());
+        assert_eq!(
+            RelayAction::parse(Some("import=+npub1abc+")),
+            Ok(Some(RelayAction::Import("npub1abc".into())))
+        );
+        assert!(RelayAction::parse(Some("import=")).is_err());
+    }
+
+    #[test]
+    fn recognises_inputs_that_need_relays() {
+        assert!(uses_relays("~jack"));
+        assert!(uses_relays("frontier://names?q=jack"));
+        assert!(uses_relays("nostr:npub1abc"));
+        assert!(uses_relays("frontier://relays?add=wss%3A%2F%2Fr.example"));
+        assert!(uses_relays(PUBLISH_QUEUE_URL));
+        assert!(!uses_relays("https://example.com/~jack"));
+        assert!(!uses_relays("frontier://settings"));
+    }
+
+    #[test]
+    fn page_lists_health_and_remove_links() {
+        let relay = Url::parse("wss://relay.example").unwrap();
+        let html = relays_page(
+            &[
+                (
+                    relay.clone(),
+                    RelayHealth::Connected {
+                        latency: Duration::from_millis(42),
+                    },
+                ),
+                (relay, RelayHealth::Failed("<refused>".into())),
+            ],
+            Some("Imported 2 relays"),
+        );
+        assert!(html.contains("connected in 42 ms"));
+        assert!(html.contains("unreachable: &lt;refused&gt;"));
+        assert!(html.contains("frontier://relays?remove=wss%3A%2F%2Frelay.example"));
+        assert!(html.contains("role=\"status\">Imported 2 relays"));
+    }
+}
@@ -0,0 +1,219 @@
+//! Which renderer draws the window: vello on the GPU, or vello_cpu when there is no usable GPU.
+//!
+//! Both are built by default and the choice is made at startup. Before the window opens,
+//! [`select`] asks wgpu for an adapter and a device the way vello will; if there is none, or
+//! the only adapter is a software rasterizer, the window is drawn by the CPU renderer instead
+//! and the reason is kept. `frontier --renderer=gpu` or `--renderer=cpu` skips the probe, and
+//! `about:version` shows the backend in use and why (see
+//! [`crate::diagnostics::version_page`]). `frontier://gpu` lists every adapter wgpu can see
+//! (see [`adapters`]).
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+pub const RENDERER_FLAG: &str = "--renderer";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Gpu,
+    Cpu,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Gpu => "gpu",
+            Backend::Cpu => "cpu",
+        }
+    }
+
+    /// Whether this build includes the backend.
+    fn built(self) -> bool {
+        match self {
+            Backend::Gpu => cfg!(feature = "gpu"),
+            Backend::Cpu => cfg!(feature = "cpu-base"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> anyhow::Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "gpu" => Ok(Backend::Gpu),
+            "cpu" => Ok(Backend::Cpu),
+            other => anyhow::bail!("{RENDERER_FLAG} must be gpu or cpu, not {other:?}"),
+        }
+    }
+}
+
+/// The backend the window is drawn with, and how it was picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub backend: Backend,
+    /// Whether [`RENDERER_FLAG`] asked for it.
+    pub forced: bool,
+    /// The GPU adapter the probe found, such as `Apple M2 (metal)`.
+    pub adapter: Option<String>,
+    /// Why the GPU is not used, when it was wanted.
+    pub fallback: Option<String>,
+}
+
+/// An adapter wgpu can see, as `frontier://gpu` lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuAdapter {
+    pub name: String,
+    /// `discrete`, `integrated`, `virtual`, `software` or `other`.
+    pub device_type: &'static str,
+    /// The graphics API wgpu reaches it through, such as `vulkan` or `metal`.
+    pub backend: String,
+    /// PCI vendor and device ids; zero when the backend does not report them.
+    pub vendor: u32,
+    pub device: u32,
+    pub driver: String,
+    pub driver_info: String,
+    /// The largest 2D texture it can allocate, in pixels per side.
+    pub max_texture_size: u32,
+}
+
+static ACTIVE: OnceLock<Selection> = OnceLock::new();
+
+/// Pick the backend: `requested` if given and built, otherwise the GPU when the probe finds
+/// one that works, otherwise the CPU. The result is kept for [`active`].
+pub fn select(requested: Option<Backend>) -> Selection {
+    let selection = decide(requested, probe_gpu);
+    match &selection.fallback {
+        Some(reason) => tracing::warn!(reason = %reason, "drawing with the CPU renderer"),
+        None => tracing::info!(backend = selection.backend.name(), "renderer selected"),
+    }
+    let _ = ACTIVE.set(selection.clone());
+    selection
+}
+
+/// The backend [`select`] picked for this process, if a window has been set up.
+pub fn active() -> Option<&'static Selection> {
+    ACTIVE.get()
+}
+
+fn decide(requested: Option<Backend>, probe: impl FnOnce() -> Result<String, String>) -> Selection {
+    let selection = |backend, forced, adapter, fallback| Selection {
+        backend,
+        forced,
+        adapter,
+        fallback,
+    };
+    match requested {
+        Some(backend) if backend.built() => return selection(backend, true, None, None),
+        Some(backend) => tracing::warn!(
+            backend = backend.name(),
+            "this build has no such renderer; choosing one"
+        ),
+        None => {}
+    }
+    if !Backend::Gpu.built() {
+        let reason = "built without the gpu feature".to_string();
+        return selection(Backend::Cpu, false, None, Some(reason));
+    }
+    if !Backend::Cpu.built() {
+        return selection(Backend::Gpu, false, None, None);
+    }
+    match probe() {
+        Ok(adapter) => selection(Backend::Gpu, false, Some(adapter), None),
+        Err(reason) => selection(Backend::Cpu, false, None, Some(reason)),
+    }
+}
+
+/// Request an adapter and a device as vello does, naming the adapter, or saying why the GPU
+/// cannot draw the window.
+#[cfg(feature = "gpu")]
+fn probe_gpu() -> Result<String, String> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let options = wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::from_env().unwrap_or_default(),
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    };
+    let adapter = pollster::block_on(instance.request_adapter(&options))
+        .map_err(|err| format!("no GPU adapter: {err}"))?;
+    let info = adapter.get_info();
+    if info.device_type == wgpu::DeviceType::Cpu {
+        return Err(format!("only a software adapter: {}", info.name));
+    }
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+        .map_err(|err| format!("{} could not create a device: {err}", info.name))?;
+    Ok(format!("{} ({})", info.name, info.backend))
+}
+
+#[cfg(not(feature = "gpu"))]
+fn probe_gpu() -> Result<String, String> {
+    Err("built without the gpu feature".to_string())
+}
+
+/// Every adapter wgpu finds on any backend, software ones included, or why it cannot look.
+/// Asking the drivers can take a moment, so callers run it off the UI thread.
+#[cfg(feature = "gpu")]
+pub fn adapters() -> Result<Vec<GpuAdapter>, String> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let adapters = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            let device_type = match info.device_type {
+                wgpu::DeviceType::DiscreteGpu => "discrete",
+                wgpu::DeviceType::IntegratedGpu => "integrated",
+                wgpu::DeviceType::VirtualGpu => "virtual",
+                wgpu::DeviceType::Cpu => "software",
+                wgpu::DeviceType::Other => "other",
+            };
+            GpuAdapter {
+                name: info.name,
+                device_type,
+                backend: info.backend.to_string(),
+                vendor: info.vendor,
+                device: info.device,
+                driver: info.driver,
+                driver_info: info.driver_info,
+                max_texture_size: adapter.limits().max_texture_dimension_2d,
+            }
+        })
+        .collect();
+    Ok(adapters)
+}
+
+#[cfg(not(feature = "gpu"))]
+pub fn adapters() -> Result<Vec<GpuAdapter>, String> {
+    Err("built without the gpu feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "gpu", feature = "cpu-base"))]
+    fn falls_back_to_the_cpu_when_the_probe_fails_unless_a_backend_is_forced() {
+        assert_eq!("GPU".parse::<Backend>().unwrap(), Backend::Gpu);
+        assert_eq!(" cpu".parse::<Backend>().unwrap(), Backend::Cpu);
+        assert!("metal".parse::<Backend>().is_err());
+
+        let working = || Ok("Test Adapter (vulkan)".to_string());
+        let failing = || Err("no GPU adapter".to_string());
+        let unprobed = || -> Result<String, String> { panic!("the probe should not run") };
+
+        let picked = decide(None, working);
+        assert_eq!(picked.backend, Backend::Gpu);
+        assert_eq!(picked.adapter.as_deref(), Some("Test Adapter (vulkan)"));
+
+        let fallback = decide(None, failing);
+        assert_eq!(fallback.backend, Backend::Cpu);
+        assert_eq!(fallback.fallback.as_deref(), Some("no GPU adapter"));
+        assert!(!fallback.forced);
+
+        let forced = decide(Some(Backend::Cpu), unprobed);
+        assert_eq!((forced.backend, forced.forced), (Backend::Cpu, true));
+        let forced = decide(Some(Backend::Gpu), unprobed);
+        assert_eq!((forced.backend, forced.fallback), (Backend::Gpu, None));
+    }
+}
@@ -0,0 +1,340 @@
+//! Golden-image comparison for visual regression tests.
+//!
+//! The automation `screenshot/compare` endpoint paints the current document, browser chrome
+//! included, with the CPU renderer at the window's size and scale, then compares it against a
+//! baseline PNG. Two checks must pass: the share of pixels that differ by more than
+//! [`CompareThresholds::pixel_tolerance`] in any channel, and the mean structural similarity
+//! (SSIM) of the luma over 8×8 blocks. On a mismatch the host writes `actual.png` and a
+//! `diff.png` that highlights the differing pixels in red over a faded baseline.
+//!
+//! Given a selector, captures are cropped to the element's border box ([`ElementRect`]), so
+//! a test can pin down one component and ignore changes elsewhere in the window. Only the
+//! part of the element inside the viewport is captured; scroll it into view first.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use anyrender::render_to_buffer;
+use anyrender_vello_cpu::VelloCpuImageRenderer;
+use blitz_dom::BaseDocument;
+use blitz_paint::paint_scene;
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+const SSIM_BLOCK: u32 = 8;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompareThresholds {
+    /// Largest per-channel difference (0–255) that still counts as the same pixel, to absorb
+    /// anti-aliasing noise.
+    pub pixel_tolerance: u8,
+    /// Share of pixels, 0 to 1, allowed to differ beyond the tolerance.
+    pub max_diff_ratio: f64,
+    /// Lowest acceptable mean SSIM, 0 to 1.
+    pub min_ssim: f64,
+}
+
+impl Default for CompareThresholds {
+    fn default() -> Self {
+        Self {
+            pixel_tolerance: 8,
+            max_diff_ratio: 0.001,
+            min_ssim: 0.98,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScreenshotComparison {
+    pub matched: bool,
+    /// The capture was saved as the new baseline instead of being compared.
+    pub baseline_updated: bool,
+    pub width: u32,
+    pub height: u32,
+    pub baseline_width: u32,
+    pub baseline_height: u32,
+    pub differing_pixels: u64,
+    pub diff_ratio: f64,
+    pub ssim: f64,
+}
+
+/// An element's border box in CSS pixels, relative to the top left of the viewport.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ElementRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl ElementRect {
+    /// Where `node_id` was laid out, or `None` when it is not in `doc`.
+    pub fn of(doc: &BaseDocument, node_id: usize) -> Option<Self> {
+        let node = doc.get_node(node_id)?;
+        let position = node.absolute_position(0.0, 0.0);
+        let scroll = doc.viewport_scroll();
+        Some(Self {
+            x: f64::from(position.x) - scroll.x,
+            y: f64::from(position.y) - scroll.y,
+            width: f64::from(node.final_layout.size.width),
+            height: f64::from(node.final_layout.size.height),
+        })
+    }
+}
+
+/// Cut `rect` out of a capture painted at `scale`, clipped to the capture. Fails when no
+/// part of it is on screen.
+pub fn crop(image: &RgbaImage, rect: &ElementRect, scale: f64) -> Result<RgbaImage> {
+    let clamp = |value: f64, max: u32| (value * scale).round().clamp(0.0, f64::from(max)) as u32;
+    let left = clamp(rect.x, image.width());
+    let top = clamp(rect.y, image.height());
+    let right = clamp(rect.x + rect.width, image.width());
+    let bottom = clamp(rect.y + rect.height, image.height());
+    if right <= left || bottom <= top {
+        return Err(anyhow!(
+            "element at {},{} ({}x{}) is not in the viewport",
+            rect.x,
+            rect.y,
+            rect.width,
+            rect.height
+        ));
+    }
+    Ok(image::imageops::crop_imm(image, left, top, right - left, bottom - top).to_image())
+}
+
+/// Paint `doc` as the window shows it, `width`×`height` physical pixels at `scale`.
+pub fn capture(doc: &BaseDocument, scale: f64, width: u32, height: u32) -> Result<RgbaImage> {
+    let pixels = render_to_buffer::<VelloCpuImageRenderer, _>(
+        |scene| paint_scene(scene, doc, scale, width, height),
+        width,
+        height,
+    );
+    RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow!("renderer returned a short {width}x{height} buffer"))
+}
+
+pub fn encode_png(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("encode png")?;
+    Ok(png)
+}
+
+/// Compare `actual` against the PNG at `baseline`, or overwrite the baseline with it when
+/// `update` is set. A missing baseline is an error unless updating, so a new golden test
+/// never passes by accident. Returns the diff image on a mismatch.
+pub fn compare_with_baseline(
+    actual: &RgbaImage,
+    baseline: &Path,
+    thresholds: &CompareThresholds,
+    update: bool,
+) -> Result<(ScreenshotComparison, Option<RgbaImage>)> {
+    if update {
+        if let Some(parent) = baseline.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        std::fs::write(baseline, encode_png(actual)?)
+            .with_context(|| format!("unable to write baseline {}", baseline.display()))?;
+        let comparison = ScreenshotComparison {
+            matched: true,
+            baseline_updated: true,
+            width: actual.width(),
+            height: actual.height(),
+            baseline_width: actual.width(),
+            baseline_height: actual.height(),
+            differing_pixels: 0,
+            diff_ratio: 0.0,
+            ssim: 1.0,
+        };
+        return Ok((comparison, None));
+    }
+    let expected = image::open(baseline)
+        .with_context(|| format!("unable to read baseline {}", baseline.display()))?
+        .to_rgba8();
+    Ok(compare(actual, &expected, thresholds))
+}
+
+pub fn compare(
+    actual: &RgbaImage,
+    baseline: &RgbaImage,
+    thresholds: &CompareThresholds,
+) -> (ScreenshotComparison, Option<RgbaImage>) {
+    let mut comparison = ScreenshotComparison {
+        matched: false,
+        baseline_updated: false,
+        width: actual.width(),
+        height: actual.height(),
+        baseline_width: baseline.width(),
+        baseline_height: baseline.height(),
+        differing_pixels: 0,
+        diff_ratio: 1.0,
+        ssim: 0.0,
+    };
+    if actual.dimensions() != baseline.dimensions() {
+        return (comparison, None);
+    }
+
+    let tolerance = thresholds.pixel_tolerance;
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    for ((a, b), out) in actual
+        .pixels()
+        .zip(baseline.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let differs = a.0.iter().zip(b.0).any(|(x, y)| x.abs_diff(y) > tolerance);
+        *out = if differs {
+            comparison.differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let faded = 192 + luma(b) as u8 / 4;
+            Rgba([faded, faded, faded, 255])
+        };
+    }
+    let total = u64::from(actual.width()) * u64::from(actual.height());
+    comparison.diff_ratio = if total == 0 {
+        0.0
+    } else {
+        comparison.differing_pixels as f64 / total as f64
+    };
+    comparison.ssim = ssim(actual, baseline);
+    comparison.matched = comparison.diff_ratio <= thresholds.max_diff_ratio
+        && comparison.ssim >= thresholds.min_ssim;
+    let diff = (!comparison.matched).then_some(diff);
+    (comparison, diff)
+}
+
+/// Mean SSIM of the luma over non-overlapping blocks; edge blocks may be smaller.
+fn ssim(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let (width, height) = a.dimensions();
+    let mut total = 0.0;
+    let mut blocks = 0u32;
+    for top in (0..height).step_by(SSIM_BLOCK as usize) {
+        for left in (0..width).step_by(SSIM_BLOCK as usize) {
+            let pairs: Vec<(f64, f64)> = (top..(top + SSIM_BLOCK).min(height))
+                .flat_map(|y| {
+                    (left..(left + SSIM_BLOCK).min(width))
+                        .map(move |x| (luma(a.get_pixel(x, y)), luma(b.get_pixel(x, y))))
+                })
+                .collect();
+            let n = pairs.len() as f64;
+            let mean_a = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+            let mean_b = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (x, y) in &pairs {
+                var_a += (x - mean_a) * (x - mean_a);
+                var_b += (y - mean_b) * (y - mean_b);
+                covariance += (x - mean_a) * (y - mean_b);
+            }
+            var_a /= n;
+            var_b /= n;
+            covariance /= n;
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            blocks += 1;
+        }
+    }
+    if blocks == 0 {
+        1.0
+    } else {
+        total / f64::from(blocks)
+    }
+}
+
+fn luma(pixel: &Rgba<u8>) -> f64 {
+    let [r, g, b, _] = pixel.0;
+    0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkerboard(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            if (x / 4 + y / 4) % 2 == 0 {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([240, 240, 240, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn matches_within_tolerance_and_flags_real_changes() {
+        let baseline = checkerboard(64, 48);
+        let mut noisy = baseline.clone();
+        noisy.put_pixel(3, 3, Rgba([25, 22, 18, 255]));
+        let (comparison, diff) = compare(&noisy, &baseline, &CompareThresholds::default());
+        assert!(comparison.matched);
+        assert_eq!(comparison.differing_pixels, 0);
+        assert!(comparison.ssim > 0.99);
+        assert!(diff.is_none());
+
+        let mut changed = baseline.clone();
+        for x in 8..40 {
+            for y in 8..24 {
+                changed.put_pixel(x, y, Rgba([200, 30, 30, 255]));
+            }
+        }
+        let (comparison, diff) = compare(&changed, &baseline, &CompareThresholds::default());
+        assert!(!comparison.matched);
+        assert!(comparison.differing_pixels > 0);
+        assert!(comparison.ssim < 0.98);
+        assert_eq!(diff.unwrap().get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn size_changes_never_match() {
+        let (comparison, diff) = compare(
+            &checkerboard(64, 48),
+            &checkerboard(64, 40),
+            &CompareThresholds::default(),
+        );
+        assert!(!comparison.matched);
+        assert_eq!(comparison.baseline_height, 40);
+        assert!(diff.is_none());
+    }
+
+    #[test]
+    fn crops_to_the_visible_part_of_an_element() {
+        let image = checkerboard(64, 48);
+        let rect = ElementRect {
+            x: 2.0,
+            y: 2.0,
+            width: 8.0,
+            height: 4.0,
+        };
+        let cropped = crop(&image, &rect, 2.0).unwrap();
+        assert_eq!(cropped.dimensions(), (16, 8));
+        assert_eq!(cropped.get_pixel(0, 0), image.get_pixel(4, 4));
+
+        let partly_below = ElementRect { y: 22.0, ..rect };
+        assert_eq!(
+            crop(&image, &partly_below, 2.0).unwrap().dimensions(),
+            (16, 4)
+        );
+        let off_screen = ElementRect { y: -10.0, ..rect };
+        assert!(crop(&image, &off_screen, 2.0).is_err());
+    }
+
+    #[test]
+    fn updating_writes_a_baseline_that_later_captures_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("golden/home.png");
+        let image = checkerboard(16, 16);
+        let thresholds = CompareThresholds::default();
+        assert!(compare_with_baseline(&image, &path, &thresholds, false).is_err());
+
+        let (updated, _) = compare_with_baseline(&image, &path, &thresholds, true).unwrap();
+        assert!(updated.baseline_updated);
+        let (comparison, _) = compare_with_baseline(&image, &path, &thresholds, false).unwrap();
+        assert!(comparison.matched);
+        assert!(!comparison.baseline_updated);
+    }
+}
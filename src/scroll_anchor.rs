@@ -0,0 +1,233 @@
+//! Scroll anchoring: keeping what the reader is looking at in place while the page above it
+//! changes size.
+//!
+//! Images, fonts and stylesheets that finish loading above the viewport push everything below
+//! them down, so the text being read jumps. After each frame, [`ScrollAnchor::select`] picks an
+//! anchor the way CSS Scroll Anchoring does: walking the document in order, the first element
+//! wholly in the viewport, descending into elements only partly in it. Before the next frame is
+//! painted the host lays the document out, and [`ScrollAnchor::adjust`] scrolls by however far
+//! the anchor moved, so it stays where it was on screen.
+//!
+//! Nothing is anchored while the page is scrolled to the top, where content growing is
+//! expected to push the rest down. `overflow-anchor: none` leaves an element and its subtree
+//! out, and on the root element turns anchoring off; stylo does not compute the property, so
+//! only the element's `style` attribute is read. Fixed and sticky elements do not move with the
+//! document and are never anchors. Scroll containers inside the page are not anchored on their
+//! own.
+
+use blitz_dom::{local_name, BaseDocument, Node};
+use style::computed_values::position::T as Position;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Anchor {
+    node: usize,
+    /// Top of its border box in document coordinates, when it was picked.
+    top: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct ScrollAnchor {
+    anchor: Option<Anchor>,
+}
+
+impl ScrollAnchor {
+    /// Forget the anchor, for a new document.
+    pub fn reset(&mut self) {
+        self.anchor = None;
+    }
+
+    /// Whether an anchor was picked, so laying out ahead of painting is worth it.
+    pub fn is_set(&self) -> bool {
+        self.anchor.is_some()
+    }
+
+    /// Pick the anchor from the layout just painted, in a viewport `viewport_height` CSS
+    /// pixels tall.
+    pub fn select(&mut self, doc: &BaseDocument, viewport_height: f64) {
+        let scroll = doc.viewport_scroll().y;
+        // The root element never moves, so the search starts among its children.
+        let root = doc.root_element();
+        self.anchor = if scroll > 0.0 && !opts_out(root) {
+            find_anchor(doc, root, (scroll, scroll + viewport_height))
+        } else {
+            None
+        };
+    }
+
+    /// Scroll `doc`, laid out again since [`Self::select`], by however far the anchor moved.
+    /// Returns the distance, or `None` when the anchor did not move or is gone.
+    pub fn adjust(&mut self, doc: &mut BaseDocument) -> Option<f64> {
+        let anchor = self.anchor.as_mut()?;
+        let Some(top) = rendered_top(doc, anchor.node) else {
+            self.anchor = None;
+            return None;
+        };
+        let distance = top - anchor.top;
+        if distance == 0.0 {
+            return None;
+        }
+        anchor.top = top;
+        let mut scroll = doc.viewport_scroll();
+        scroll.y = (scroll.y + distance).max(0.0);
+        doc.set_viewport_scroll(scroll);
+        Some(distance)
+    }
+}
+
+/// The anchor among the children of `parent`, given the viewport's top and bottom.
+fn find_anchor(doc: &BaseDocument, parent: &Node, viewport: (f64, f64)) -> Option<Anchor> {
+    for &child_id in &parent.children {
+        let Some(child) = doc.get_node(child_id) else {
+            continue;
+        };
+        if !can_anchor(child) {
+            continue;
+        }
+        let top = f64::from(child.absolute_position(0.0, 0.0).y);
+        let height = f64::from(child.final_layout.size.height);
+        let bottom = top + height;
+        if height <= 0.0 || bottom <= viewport.0 || top >= viewport.1 {
+            continue;
+        }
+        let anchor = Anchor {
+            node: child_id,
+            top,
+        };
+        if top >= viewport.0 && bottom <= viewport.1 {
+            return Some(anchor);
+        }
+        // Partly in view: a descendant wholly in view is a steadier anchor.
+        return Some(find_anchor(doc, child, viewport).unwrap_or(anchor));
+    }
+    None
+}
+
+/// Whether `node` is an element that can be an anchor or hold one.
+fn can_anchor(node: &Node) -> bool {
+    if node.element_data().is_none() || opts_out(node) {
+        return false;
+    }
+    let Some(styles) = node.primary_styles() else {
+        return false;
+    };
+    let style = styles.get_box();
+    !style.display.is_none() && !matches!(style.position, Position::Fixed | Position::Sticky)
+}
+
+/// Whether the `style` attribute sets `overflow-anchor: none`.
+fn opts_out(node: &Node) -> bool {
+    node.attr(local_name!("style")).is_some_and(|style| {
+        style
+            .split(';')
+            .filter_map(|declaration| declaration.split_once(':'))
+            .any(|(name, value)| {
+                let value = value.trim().trim_end_matches("!important").trim_end();
+                name.trim().eq_ignore_ascii_case("overflow-anchor")
+                    && value.eq_ignore_ascii_case("none")
+            })
+    })
+}
+
+/// The top of `node_id`'s border box in document coordinates, or `None` when it is no longer
+/// in the document or no longer rendered.
+fn rendered_top(doc: &BaseDocument, node_id: usize) -> Option<f64> {
+    let root = doc.root_node().id;
+    let mut current = node_id;
+    while current != root {
+        let node = doc.get_node(current)?;
+        if !can_anchor(node) {
+            return None;
+        }
+        current = node.parent?;
+    }
+    let node = doc.get_node(node_id)?;
+    Some(f64::from(node.absolute_position(0.0, 0.0).y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blitz_dom::{ns, DocumentConfig, DocumentMutator, QualName};
+    use blitz_html::HtmlDocument;
+    use blitz_traits::shell::{ColorScheme, Viewport};
+
+    const PAGE: &str = r#"<html><body id="body" style="margin:0">
+        <div id="banner" style="height:100px"></div>
+        <p id="one" style="height:300px;margin:0"></p>
+        <p id="two" style="height:300px;margin:0"></p>
+        <p id="three" style="height:300px;margin:0"></p>
+    </body></html>"#;
+
+    fn find(doc: &BaseDocument, id: &str) -> usize {
+        let mut stack = vec![doc.root_node().id];
+        while let Some(node_id) = stack.pop() {
+            let node = doc.get_node(node_id).unwrap();
+            if node.attr(local_name!("id")) == Some(id) {
+                return node_id;
+            }
+            stack.extend(node.children.iter().copied());
+        }
+        panic!("no #{id}");
+    }
+
+    fn set_style(doc: &mut BaseDocument, id: &str, style: &str) {
+        let node_id = find(doc, id);
+        let name = QualName::new(None, ns!(), local_name!("style"));
+        DocumentMutator::new(doc).set_attribute(node_id, name, style);
+        doc.resolve();
+    }
+
+    fn scrolled_to(y: f64) -> HtmlDocument {
+        let mut doc = HtmlDocument::from_html(PAGE, DocumentConfig::default());
+        doc.set_viewport(Viewport::new(400, 300, 1.0, ColorScheme::Light));
+        doc.resolve();
+        let mut scroll = doc.viewport_scroll();
+        scroll.y = y;
+        doc.set_viewport_scroll(scroll);
+        doc
+    }
+
+    #[test]
+    fn content_growing_above_the_viewport_keeps_the_anchor_in_place() {
+        let mut doc = scrolled_to(400.0);
+        let mut anchor = ScrollAnchor::default();
+        anchor.select(&doc, 300.0);
+        assert_eq!(
+            anchor.anchor.map(|anchor| anchor.node),
+            Some(find(&doc, "two"))
+        );
+
+        set_style(&mut doc, "banner", "height:250px");
+        assert_eq!(anchor.adjust(&mut doc), Some(150.0));
+        assert_eq!(doc.viewport_scroll().y, 550.0);
+        assert_eq!(anchor.adjust(&mut doc), None);
+
+        // At the top of the page, growth pushes the content down as usual.
+        let mut doc = scrolled_to(0.0);
+        anchor.select(&doc, 300.0);
+        set_style(&mut doc, "banner", "height:250px");
+        assert!(!anchor.is_set());
+        assert_eq!(anchor.adjust(&mut doc), None);
+    }
+
+    #[test]
+    fn overflow_anchor_none_opts_out() {
+        let mut doc = scrolled_to(400.0);
+        set_style(
+            &mut doc,
+            "two",
+            "height:300px;margin:0;overflow-anchor: none !important",
+        );
+        let mut anchor = ScrollAnchor::default();
+        // #three starts where #two ends and runs past the bottom of a taller viewport.
+        anchor.select(&doc, 400.0);
+        assert_eq!(
+            anchor.anchor.map(|anchor| anchor.node),
+            Some(find(&doc, "three"))
+        );
+
+        set_style(&mut doc, "body", "margin:0;overflow-anchor:none");
+        anchor.select(&doc, 400.0);
+        assert!(!anchor.is_set());
+    }
+}
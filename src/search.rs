@@ -0,0 +1,167 @@
+//! URL bar searches.
+//!
+//! Input that is neither a URL, a file path nor something that could be a nostr name goes to
+//! the search engine in `frontier.toml` instead of failing to load. A `!keyword` at the start
+//! or end of the query picks one of the configured bangs; unknown bangs are left in the query
+//! so engines with their own bang syntax (like the default, DuckDuckGo) still handle them.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Stands for the percent-encoded search terms in a search URL template.
+pub const QUERY_PLACEHOLDER: &str = "{query}";
+const DEFAULT_TEMPLATE: &str = "https://duckduckgo.com/?q={query}";
+
+/// Schemes that keep input with spaces in it a URL rather than a search.
+const URL_SCHEMES: &[&str] = &["http", "https", "file", "frontier", "about", "data"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchEngine {
+    /// Search URL with [`QUERY_PLACEHOLDER`] where the terms go.
+    pub template: String,
+    /// `!keyword` shortcuts to other search URL templates.
+    pub bangs: BTreeMap<String, String>,
+}
+
+impl Default for SearchEngine {
+    fn default() -> Self {
+        let bangs = [
+            (
+                "w",
+                "https://en.wikipedia.org/wiki/Special:Search?search={query}",
+            ),
+            ("gh", "https://github.com/search?q={query}"),
+            ("crates", "https://crates.io/search?q={query}"),
+        ];
+        Self {
+            template: DEFAULT_TEMPLATE.to_string(),
+            bangs: bangs
+                .into_iter()
+                .map(|(keyword, template)| (keyword.to_string(), template.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl SearchEngine {
+    /// The search URL for URL bar `input`, or `None` when the input should load as an address.
+    pub fn url_for(&self, input: &str) -> Option<Url> {
+        let query = search_terms(input)?;
+        let (template, query) = self.pick_template(query);
+        expand(template, query)
+    }
+
+    /// Whether `template` is a usable search URL template.
+    pub fn is_valid_template(template: &str) -> bool {
+        template.contains(QUERY_PLACEHOLDER)
+            && expand(template, "test").is_some_and(|url| matches!(url.scheme(), "http" | "https"))
+    }
+
+    fn pick_template<'a>(&'a self, query: &'a str) -> (&'a str, &'a str) {
+        let bang = query
+            .split_whitespace()
+            .next()
+            .filter(|word| word.starts_with('!'))
+            .map(|word| (word, query[word.len()..].trim_start()))
+            .or_else(|| {
+                query
+                    .split_whitespace()
+                    .last()
+                    .filter(|word| word.starts_with('!'))
+                    .map(|word| (word, query[..query.len() - word.len()].trim_end()))
+            });
+        if let Some((word, rest)) = bang {
+            if let Some(template) = self.bangs.get(&word[1..].to_ascii_lowercase()) {
+                return (template, rest);
+            }
+        }
+        (&self.template, query)
+    }
+}
+
+/// The search terms in `input`, or `None` when it looks like an address or a bare name.
+///
+/// A leading `?` forces a search. Input with spaces is a search unless it starts with a URL
+/// scheme, and a single word is one when it cannot be a host or nostr name.
+fn search_terms(input: &str) -> Option<&str> {
+    let trimmed = input.trim();
+    if let Some(query) = trimmed.strip_prefix('?') {
+        let query = query.trim();
+        return (!query.is_empty()).then_some(query);
+    }
+    // `~term` is a name search, see `crate::name_search`.
+    if trimmed.is_empty()
+        || trimmed.starts_with('/')
+        || trimmed.starts_with('~')
+        || trimmed.starts_with("view-source:")
+    {
+        return None;
+    }
+    if trimmed.contains(char::is_whitespace) {
+        let is_url = Url::parse(trimmed).is_ok_and(|url| URL_SCHEMES.contains(&url.scheme()));
+        return (!is_url).then_some(trimmed);
+    }
+    if trimmed.starts_with('!') {
+        return Some(trimmed);
+    }
+    let name_like = trimmed
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/' | '@'));
+    (!name_like).then_some(trimmed)
+}
+
+fn expand(template: &str, query: &str) -> Option<Url> {
+    let encoded: String = url::form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    Url::parse(&template.replace(QUERY_PLACEHOLDER, &encoded)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_search_but_addresses_and_names_do_not() {
+        let engine = SearchEngine::default();
+        assert_eq!(
+            engine.url_for("rust borrow checker").unwrap().as_str(),
+            "https://duckduckgo.com/?q=rust+borrow+checker"
+        );
+        assert_eq!(
+            engine.url_for("?example.com").unwrap().as_str(),
+            "https://duckduckgo.com/?q=example.com"
+        );
+        assert_eq!(
+            engine.url_for("c++?").unwrap().as_str(),
+            "https://duckduckgo.com/?q=c%2B%2B%3F"
+        );
+        assert_eq!(engine.url_for("example.com"), None);
+        assert_eq!(engine.url_for("justinmoon"), None);
+        assert_eq!(engine.url_for("127.0.0.1:8080"), None);
+        assert_eq!(engine.url_for("file:///tmp/my notes.md"), None);
+        assert_eq!(engine.url_for("view-source:https://example.com"), None);
+    }
+
+    #[test]
+    fn bangs_pick_their_template() {
+        let engine = SearchEngine::default();
+        assert_eq!(
+            engine.url_for("!gh blitz").unwrap().as_str(),
+            "https://github.com/search?q=blitz"
+        );
+        assert_eq!(
+            engine.url_for("tokio !crates").unwrap().as_str(),
+            "https://crates.io/search?q=tokio"
+        );
+        assert_eq!(
+            engine.url_for("!yt cats").unwrap().as_str(),
+            "https://duckduckgo.com/?q=%21yt+cats"
+        );
+        assert!(SearchEngine::is_valid_template(
+            "https://search.example/?q={query}"
+        ));
+        assert!(!SearchEngine::is_valid_template("https://search.example/"));
+    }
+}
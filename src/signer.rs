@@ -0,0 +1,390 @@
+//! Host side of `window.nostr.signEvent`: a human-readable summary of the event, the native
+//! confirmation prompt, signing with the origin's identity and the append-only audit log behind
+//! `frontier://signatures`.
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use html_escape::encode_text;
+use nostr_sdk::prelude::{EventBuilder, Keys, Kind, PublicKey, Tag, Timestamp, ToBech32};
+use serde::{Deserialize, Serialize};
+
+use crate::js::nostr::UnsignedEventTemplate;
+use crate::storage_policy::StoragePolicy;
+
+pub const SIGNATURES_URL: &str = "frontier://signatures";
+const FILE_NAME: &str = "signatures.jsonl";
+const CONTENT_PREVIEW_CHARS: usize = 280;
+const PAGE_ENTRIES: usize = 200;
+
+fn kind_name(kind: u16) -> Option<&'static str> {
+    Some(match kind {
+        0 => "profile metadata",
+        1 => "short text note",
+        3 => "follow list",
+        4 => "encrypted direct message",
+        5 => "deletion request",
+        6 => "repost",
+        7 => "reaction",
+        1984 => "report",
+        9734 => "zap request",
+        10002 => "relay list",
+        22242 => "relay authentication",
+        24133 => "remote signer message",
+        27235 => "HTTP authentication",
+        30023 => "long-form article",
+        34128 => "site file manifest",
+        34256 => "site service record",
+        _ => return None,
+    })
+}
+
+/// Multi-line description of `template` for the signing prompt.
+pub fn describe_event(template: &UnsignedEventTemplate) -> String {
+    let mut lines = vec![match kind_name(template.kind) {
+        Some(name) => format!("Kind {} ({name})", template.kind),
+        None => format!("Kind {}", template.kind),
+    }];
+    if !template.content.is_empty() {
+        let mut preview: String = template
+            .content
+            .chars()
+            .take(CONTENT_PREVIEW_CHARS)
+            .collect();
+        if template.content.chars().count() > CONTENT_PREVIEW_CHARS {
+            preview.push('…');
+        }
+        lines.push(format!("Content: {preview}"));
+    }
+    for tag in &template.tags {
+        let Some((name, values)) = tag.split_first() else {
+            continue;
+        };
+        let first = values.first().map(String::as_str).unwrap_or("");
+        lines.push(match name.as_str() {
+            "p" => format!("Mentions {}", npub_or_raw(first)),
+            "e" => format!("References event {first}"),
+            "a" => format!("References {first}"),
+            "t" => format!("Topic #{first}"),
+            "r" => format!("Links {first}"),
+            _ => format!("Tag {name}: {}", values.join(", ")),
+        });
+    }
+    lines.join("\n")
+}
+
+fn npub_or_raw(hex: &str) -> String {
+    PublicKey::from_hex(hex)
+        .ok()
+        .and_then(|key| key.to_bech32().ok())
+        .unwrap_or_else(|| hex.to_string())
+}
+
+/// Sign `template` with `keys`, returning the event as NIP-07 hands it back to the page.
+pub fn sign(template: &UnsignedEventTemplate, keys: &Keys) -> Result<serde_json::Value> {
+    let tags = template
+        .tags
+        .iter()
+        .map(|tag| Tag::parse(tag).map_err(|err| anyhow!("invalid tag {tag:?}: {err}")))
+        .collect::<Result<Vec<_>>>()?;
+    let mut builder = EventBuilder::new(Kind::from(template.kind), &template.content, tags);
+    if let Some(created_at) = template.created_at {
+        builder = builder.custom_created_at(Timestamp::from(created_at));
+    }
+    let event = builder.to_event(keys).context("sign event")?;
+    serde_json::to_value(&event).context("serialize signed event")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptDecision {
+    Sign,
+    /// Sign, and stop asking for this kind on this origin.
+    AlwaysAllowKind,
+    Deny,
+}
+
+/// Ask the user with a native dialog. Blocks until answered.
+pub fn prompt(origin: &str, identity: &str, template: &UnsignedEventTemplate) -> PromptDecision {
+    let always = format!("Always allow kind {}", template.kind);
+    let labels = ["Sign", always.as_str(), "Deny"];
+    let result = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title(format!("{origin} wants to sign as {identity}"))
+        .set_description(describe_event(template))
+        .set_buttons(custom_buttons(labels))
+        .show();
+    match pressed_button(&result, labels) {
+        Some(0) => PromptDecision::Sign,
+        Some(1) => PromptDecision::AlwaysAllowKind,
+        _ => PromptDecision::Deny,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDecision {
+    /// Share the public key until the browser closes.
+    Share,
+    /// Share it, and stop asking this origin.
+    AlwaysShare,
+    Deny,
+}
+
+/// Ask the user whether `origin` may learn the public key of `identity`, which identifies the
+/// user to every site using it. Blocks until answered.
+pub fn prompt_public_key(origin: &str, identity: &str) -> KeyDecision {
+    let labels = ["Share", "Always share", "Deny"];
+    let result = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title(format!("{origin} wants your public key"))
+        .set_description(format!(
+            "Share the public key of {identity} with {origin}. Sites that see the same key \
+             can tell they are talking to the same person."
+        ))
+        .set_buttons(custom_buttons(labels))
+        .show();
+    match pressed_button(&result, labels) {
+        Some(0) => KeyDecision::Share,
+        Some(1) => KeyDecision::AlwaysShare,
+        _ => KeyDecision::Deny,
+    }
+}
+
+/// Three buttons with the given labels, answered with [`pressed_button`].
+pub(crate) fn custom_buttons([yes, no, cancel]: [&str; 3]) -> rfd::MessageButtons {
+    rfd::MessageButtons::YesNoCancelCustom(yes.to_string(), no.to_string(), cancel.to_string())
+}
+
+/// Which button of a `YesNoCancelCustom` dialog was pressed, counting from 0. Some backends
+/// report the button's label and others its position as `Yes`, `No` or `Cancel`; closing the
+/// dialog reports the last button or nothing.
+pub(crate) fn pressed_button(
+    result: &rfd::MessageDialogResult,
+    labels: [&str; 3],
+) -> Option<usize> {
+    match result {
+        rfd::MessageDialogResult::Custom(label) => {
+            labels.iter().position(|candidate| candidate == label)
+        }
+        rfd::MessageDialogResult::Yes => Some(0),
+        rfd::MessageDialogResult::No => Some(1),
+        rfd::MessageDialogResult::Cancel => Some(2),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureOutcome {
+    /// The user approved this request.
+    Signed,
+    /// An "always allow" rule approved it without asking.
+    SignedByRule,
+    Denied,
+    Failed,
+}
+
+impl SignatureOutcome {
+    fn label(self) -> &'static str {
+        match self {
+            SignatureOutcome::Signed => "signed",
+            SignatureOutcome::SignedByRule => "signed (rule)",
+            SignatureOutcome::Denied => "denied",
+            SignatureOutcome::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub origin: String,
+    pub npub: Option<String>,
+    pub kind: u16,
+    pub event_id: Option<String>,
+    pub outcome: SignatureOutcome,
+}
+
+impl SignatureRecord {
+    pub fn now(
+        origin: &str,
+        npub: Option<&str>,
+        kind: u16,
+        event_id: Option<String>,
+        outcome: SignatureOutcome,
+    ) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            origin: origin.to_string(),
+            npub: npub.map(str::to_string),
+            kind,
+            event_id,
+            outcome,
+        }
+    }
+}
+
+pub fn audit_log_path() -> Option<PathBuf> {
+    crate::preferences::data_file(FILE_NAME)
+}
+
+/// Append `record` as one JSON line. Does nothing in a private session.
+pub fn record(path: &Path, record: &SignatureRecord) -> Result<()> {
+    if !StoragePolicy::current().persists() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("unable to create {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("unable to open signature log {}", path.display()))?;
+    let line = serde_json::to_string(record).context("serialize signature record")?;
+    writeln!(file, "{line}")
+        .with_context(|| format!("unable to write signature log {}", path.display()))
+}
+
+/// The newest `limit` records, newest first. Unparseable lines are skipped.
+pub fn recent(path: &Path, limit: usize) -> Result<Vec<SignatureRecord>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("unable to read signature log {}", path.display()))
+        }
+    };
+    let mut records: Vec<SignatureRecord> = BufReader::new(file)
+        .lines()
+        .map_while(std::result::Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect();
+    records.reverse();
+    records.truncate(limit);
+    Ok(records)
+}
+
+/// HTML body of `frontier://signatures`.
+pub fn signatures_page(path: Option<&Path>) -> String {
+    let records = match path.map(|path| recent(path, PAGE_ENTRIES)) {
+        Some(Ok(records)) => records,
+        Some(Err(err)) => {
+            return format!(
+                "<section class=\"signatures\"><h1>Signatures</h1><p>{}</p></section>",
+                encode_text(&err.to_string())
+            )
+        }
+        None => Vec::new(),
+    };
+    let rows = if records.is_empty() {
+        "<tr><td colspan=\"6\">No signature requests yet.</td></tr>".to_string()
+    } else {
+        records
+            .iter()
+            .map(|record| {
+                format!(
+                    "<tr><td>{timestamp}</td><td><code>{origin}</code></td><td><code>{npub}</code></td><td>{kind}</td><td><code>{event}</code></td><td>{outcome}</td></tr>",
+                    timestamp = record.timestamp,
+                    origin = encode_text(&record.origin),
+                    npub = encode_text(record.npub.as_deref().unwrap_or("")),
+                    kind = record.kind,
+                    event = encode_text(record.event_id.as_deref().unwrap_or("")),
+                    outcome = record.outcome.label(),
+                )
+            })
+            .collect()
+    };
+    format!(
+        r#"<section class="signatures">
+<h1>Signatures</h1>
+<p>Every <code>window.nostr.signEvent</code> request, newest first.</p>
+<table>
+<thead><tr><th>Time (Unix)</th><th>Origin</th><th>Identity</th><th>Kind</th><th>Event</th><th>Outcome</th></tr></thead>
+<tbody>{rows}</tbody>
+</table>
+</section>"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> UnsignedEventTemplate {
+        UnsignedEventTemplate {
+            kind: 1,
+            content: "hello".into(),
+            tags: vec![vec!["t".into(), "frontier".into()]],
+            created_at: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn describes_kind_content_and_tags() {
+        let description = describe_event(&template());
+        assert_eq!(
+            description,
+            "Kind 1 (short text note)\nContent: hello\nTopic #frontier"
+        );
+    }
+
+    #[test]
+    fn signs_with_requested_fields() {
+        let keys = Keys::generate();
+        let event = sign(&template(), &keys).unwrap();
+        assert_eq!(event["kind"], 1);
+        assert_eq!(event["created_at"], 1_700_000_000);
+        assert_eq!(event["pubkey"], keys.public_key().to_hex());
+        assert_eq!(event["tags"][0][1], "frontier");
+    }
+
+    #[test]
+    fn dialog_buttons_map_by_label_or_position() {
+        use rfd::MessageDialogResult as Pressed;
+        let labels = ["Sign", "Always allow kind 1", "Deny"];
+        let custom = |label: &str| Pressed::Custom(label.to_string());
+        assert_eq!(pressed_button(&custom("Sign"), labels), Some(0));
+        assert_eq!(
+            pressed_button(&custom("Always allow kind 1"), labels),
+            Some(1)
+        );
+        assert_eq!(pressed_button(&custom("Deny"), labels), Some(2));
+        assert_eq!(pressed_button(&custom("Other"), labels), None);
+        assert_eq!(pressed_button(&Pressed::Yes, labels), Some(0));
+        assert_eq!(pressed_button(&Pressed::No, labels), Some(1));
+        assert_eq!(pressed_button(&Pressed::Cancel, labels), Some(2));
+        assert_eq!(pressed_button(&Pressed::Ok, labels), None);
+    }
+
+    #[test]
+    fn audit_log_lists_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(FILE_NAME);
+        for kind in [1, 7] {
+            record(
+                &path,
+                &SignatureRecord::now(
+                    "https://a.example",
+                    None,
+                    kind,
+                    None,
+                    SignatureOutcome::Denied,
+                ),
+            )
+            .unwrap();
+        }
+        let records = recent(&path, 10).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, 7);
+        assert!(signatures_page(Some(&path)).contains("https://a.example"));
+    }
+}
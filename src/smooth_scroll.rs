@@ -0,0 +1,341 @@
+//! Animated scrolling for wheel notches, scrolling keys and `scrollIntoView({ behavior:
+//! 'smooth' })`.
+//!
+//! Without it a notched wheel or Page Down moves the page a whole line or screen between two
+//! frames. [`SmoothScroll`] spreads each of those over a short animation instead, with the
+//! easing the user picked on the settings page (`scroll_easing` in `frontier.toml`), which can
+//! also turn animation off. The animation is advanced by the frame clock: the host asks
+//! [`SmoothScroll::frame`] for the distance to cover just before blitz draws a frame, and asks
+//! for another frame while the animation runs, so scrolling moves in step with painting
+//! rather than racing it. Scrolling again before an animation ends restarts it from where the
+//! page is towards the old target plus the new distance, so held keys and a spun wheel add up.
+//! Trackpads report smooth pixel deltas already and are left alone, and reduced motion makes
+//! every scroll instant.
+
+use std::time::{Duration, Instant};
+
+use blitz_dom::BaseDocument;
+use serde::{Deserialize, Serialize};
+use winit::keyboard::{Key, NamedKey};
+
+use crate::js::scroll::ScrollAlign;
+
+/// CSS pixels one arrow key press scrolls.
+pub const ARROW_KEY_DISTANCE: f64 = 40.0;
+
+/// Page Up/Down and Space scroll by this share of the viewport, keeping some context in view.
+const PAGE_FRACTION: f64 = 0.875;
+
+const MIN_DURATION: Duration = Duration::from_millis(150);
+const MAX_DURATION: Duration = Duration::from_millis(450);
+
+/// How an animated scroll speeds up and slows down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollEasing {
+    /// No animation: every scroll jumps.
+    Off,
+    Linear,
+    /// Fast start, gentle stop, like most browsers.
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl ScrollEasing {
+    pub const ALL: [ScrollEasing; 4] = [
+        ScrollEasing::Off,
+        ScrollEasing::Linear,
+        ScrollEasing::EaseOut,
+        ScrollEasing::EaseInOut,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ScrollEasing::Off => "off",
+            ScrollEasing::Linear => "linear",
+            ScrollEasing::EaseOut => "ease-out",
+            ScrollEasing::EaseInOut => "ease-in-out",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|easing| easing.as_str() == raw)
+    }
+
+    /// Share of the distance covered after `t` (0 to 1) of the duration.
+    fn progress(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            ScrollEasing::Off => 1.0,
+            ScrollEasing::Linear => t,
+            ScrollEasing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            ScrollEasing::EaseInOut if t < 0.5 => 4.0 * t.powi(3),
+            ScrollEasing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+/// What an animation scrolls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollTarget {
+    /// The scroll container under the pointer, like the wheel events the animation replaces.
+    /// Distances are in wheel terms: positive scrolls up and left.
+    Pointer,
+    /// The document viewport. Distances are in scroll offset terms: positive scrolls down and
+    /// right.
+    Viewport,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Animation {
+    target: ScrollTarget,
+    distance: (f64, f64),
+    covered: (f64, f64),
+    started: Instant,
+    duration: Duration,
+}
+
+impl Animation {
+    fn remaining(&self) -> (f64, f64) {
+        (
+            self.distance.0 - self.covered.0,
+            self.distance.1 - self.covered.1,
+        )
+    }
+}
+
+/// The scroll animation of one window.
+#[derive(Debug, Default)]
+pub struct SmoothScroll {
+    easing: ScrollEasing,
+    animation: Option<Animation>,
+}
+
+impl SmoothScroll {
+    pub fn new(easing: ScrollEasing) -> Self {
+        Self {
+            easing,
+            animation: None,
+        }
+    }
+
+    pub fn set_easing(&mut self, easing: ScrollEasing) {
+        self.easing = easing;
+    }
+
+    /// Scroll `target` by `distance` CSS pixels, starting at `now`. Returns the distance to
+    /// scroll right away instead when the scroll is `instant` or animation is off; an
+    /// animation of another target still running is dropped either way.
+    pub fn scroll_by(
+        &mut self,
+        target: ScrollTarget,
+        distance: (f64, f64),
+        now: Instant,
+        instant: bool,
+    ) -> Option<(f64, f64)> {
+        let pending = self
+            .animation
+            .take()
+            .filter(|animation| animation.target == target)
+            .map_or((0.0, 0.0), |animation| animation.remaining());
+        let distance = (distance.0 + pending.0, distance.1 + pending.1);
+        if instant || self.easing == ScrollEasing::Off {
+            return Some(distance);
+        }
+        self.animation = Some(Animation {
+            target,
+            distance,
+            covered: (0.0, 0.0),
+            started: now,
+            duration: duration_for(distance),
+        });
+        None
+    }
+
+    /// The distance to scroll in the frame drawn at `now`, and what it scrolls. The last frame
+    /// covers whatever is left, so the animation lands exactly on its target.
+    pub fn frame(&mut self, now: Instant) -> Option<(ScrollTarget, (f64, f64))> {
+        let animation = self.animation.as_mut()?;
+        let elapsed = now.saturating_duration_since(animation.started);
+        let t = elapsed.as_secs_f64() / animation.duration.as_secs_f64();
+        let share = self.easing.progress(t);
+        let reached = (animation.distance.0 * share, animation.distance.1 * share);
+        let step = (
+            reached.0 - animation.covered.0,
+            reached.1 - animation.covered.1,
+        );
+        animation.covered = reached;
+        let target = animation.target;
+        if t >= 1.0 {
+            self.animation = None;
+        }
+        Some((target, step))
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Stop where the page is now, e.g. when a new document replaces the old one.
+    pub fn cancel(&mut self) {
+        self.animation = None;
+    }
+}
+
+/// Longer distances take longer, up to [`MAX_DURATION`], so a jump across a long page does not
+/// blur past.
+fn duration_for(distance: (f64, f64)) -> Duration {
+    let length = distance.0.hypot(distance.1);
+    (MIN_DURATION + Duration::from_secs_f64(length / 10_000.0)).min(MAX_DURATION)
+}
+
+/// How far a scrolling key moves a viewport of `viewport` CSS pixels whose document is
+/// `content_height` tall and scrolled to `scroll`, in viewport terms. `None` for other keys.
+pub fn key_distance(
+    key: &Key,
+    shift: bool,
+    viewport: (f64, f64),
+    scroll: (f64, f64),
+    content_height: f64,
+) -> Option<(f64, f64)> {
+    let page = viewport.1 * PAGE_FRACTION;
+    Some(match key {
+        Key::Named(NamedKey::ArrowDown) => (0.0, ARROW_KEY_DISTANCE),
+        Key::Named(NamedKey::ArrowUp) => (0.0, -ARROW_KEY_DISTANCE),
+        Key::Named(NamedKey::ArrowRight) => (ARROW_KEY_DISTANCE, 0.0),
+        Key::Named(NamedKey::ArrowLeft) => (-ARROW_KEY_DISTANCE, 0.0),
+        Key::Named(NamedKey::PageDown) => (0.0, page),
+        Key::Named(NamedKey::PageUp) => (0.0, -page),
+        Key::Named(NamedKey::Space) if shift => (0.0, -page),
+        Key::Named(NamedKey::Space) => (0.0, page),
+        Key::Named(NamedKey::Home) => (0.0, -scroll.1),
+        Key::Named(NamedKey::End) => (0.0, (content_height - viewport.1 - scroll.1).max(0.0)),
+        _ => return None,
+    })
+}
+
+/// How far the viewport must scroll, in viewport terms, to bring the element at `node_id` to
+/// `block` vertically and `inline` horizontally in a viewport of `viewport` CSS pixels.
+pub fn into_view_distance(
+    doc: &BaseDocument,
+    node_id: usize,
+    block: ScrollAlign,
+    inline: ScrollAlign,
+    viewport: (f64, f64),
+) -> Option<(f64, f64)> {
+    let node = doc.get_node(node_id)?;
+    let position = node.absolute_position(0.0, 0.0);
+    let size = node.final_layout.size;
+    let scroll = doc.viewport_scroll();
+    let dx = align(
+        f64::from(position.x) - scroll.x,
+        f64::from(size.width),
+        viewport.0,
+        inline,
+    );
+    let dy = align(
+        f64::from(position.y) - scroll.y,
+        f64::from(size.height),
+        viewport.1,
+        block,
+    );
+    // The viewport cannot scroll above or left of the document.
+    Some((dx.max(-scroll.x), dy.max(-scroll.y)))
+}
+
+/// The scroll that moves a box at `offset` from the viewport's edge, `size` long, to `align`
+/// within a viewport `viewport` long.
+fn align(offset: f64, size: f64, viewport: f64, align: ScrollAlign) -> f64 {
+    let end = offset + size - viewport;
+    match align {
+        ScrollAlign::Start => offset,
+        ScrollAlign::Center => offset + (size - viewport) / 2.0,
+        ScrollAlign::End => end,
+        ScrollAlign::Nearest if offset >= 0.0 && end <= 0.0 => 0.0,
+        // Partly above, or too big to fit: show its start.
+        ScrollAlign::Nearest if offset < 0.0 || size > viewport => offset,
+        ScrollAlign::Nearest => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_and_finish_in_place() {
+        for easing in ScrollEasing::ALL {
+            assert_eq!(easing.progress(1.0), 1.0, "{easing:?}");
+            assert_eq!(ScrollEasing::parse(easing.as_str()), Some(easing));
+        }
+        assert_eq!(ScrollEasing::EaseOut.progress(0.0), 0.0);
+        assert!(ScrollEasing::EaseOut.progress(0.5) > 0.5);
+        assert!(ScrollEasing::EaseInOut.progress(0.25) < 0.25);
+        assert_eq!(ScrollEasing::EaseInOut.progress(0.5), 0.5);
+    }
+
+    #[test]
+    fn animations_land_on_target_and_accumulate() {
+        let start = Instant::now();
+        let mut scroll = SmoothScroll::new(ScrollEasing::EaseOut);
+        assert_eq!(
+            scroll.scroll_by(ScrollTarget::Viewport, (0.0, 100.0), start, false),
+            None
+        );
+        let (_, first) = scroll.frame(start + Duration::from_millis(50)).unwrap();
+        assert!(first.1 > 0.0 && first.1 < 100.0);
+
+        // A second press mid-animation carries on to the combined target.
+        let again = start + Duration::from_millis(50);
+        scroll.scroll_by(ScrollTarget::Viewport, (0.0, 100.0), again, false);
+        let mut total = first.1;
+        let mut now = again;
+        while scroll.is_animating() {
+            now += Duration::from_millis(16);
+            let (target, step) = scroll.frame(now).unwrap();
+            assert_eq!(target, ScrollTarget::Viewport);
+            total += step.1;
+        }
+        assert!((total - 200.0).abs() < 1e-9, "{total}");
+
+        // Another target replaces the animation; reduced motion jumps straight there.
+        scroll.scroll_by(ScrollTarget::Pointer, (0.0, -60.0), now, false);
+        assert_eq!(
+            scroll.scroll_by(ScrollTarget::Viewport, (0.0, 30.0), now, true),
+            Some((0.0, 30.0))
+        );
+        assert!(!scroll.is_animating());
+
+        scroll.set_easing(ScrollEasing::Off);
+        assert_eq!(
+            scroll.scroll_by(ScrollTarget::Pointer, (0.0, -60.0), now, false),
+            Some((0.0, -60.0))
+        );
+    }
+
+    #[test]
+    fn keys_page_and_jump_within_the_document() {
+        let viewport = (800.0, 600.0);
+        let distance = |key: NamedKey, shift: bool| {
+            key_distance(&Key::Named(key), shift, viewport, (0.0, 500.0), 3000.0)
+        };
+        assert_eq!(distance(NamedKey::ArrowDown, false), Some((0.0, 40.0)));
+        assert_eq!(distance(NamedKey::PageDown, false), Some((0.0, 525.0)));
+        assert_eq!(distance(NamedKey::Space, true), Some((0.0, -525.0)));
+        assert_eq!(distance(NamedKey::Home, false), Some((0.0, -500.0)));
+        assert_eq!(distance(NamedKey::End, false), Some((0.0, 1900.0)));
+        assert_eq!(distance(NamedKey::Enter, false), None);
+    }
+
+    #[test]
+    fn alignment_moves_boxes_into_view() {
+        assert_eq!(align(900.0, 100.0, 600.0, ScrollAlign::Start), 900.0);
+        assert_eq!(align(900.0, 100.0, 600.0, ScrollAlign::Center), 650.0);
+        assert_eq!(align(900.0, 100.0, 600.0, ScrollAlign::End), 400.0);
+        assert_eq!(align(900.0, 100.0, 600.0, ScrollAlign::Nearest), 400.0);
+        assert_eq!(align(-50.0, 100.0, 600.0, ScrollAlign::Nearest), -50.0);
+        assert_eq!(align(100.0, 100.0, 600.0, ScrollAlign::Nearest), 0.0);
+        assert_eq!(align(100.0, 900.0, 600.0, ScrollAlign::Nearest), 100.0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Timing for the stages of startup, from `main` to the first painted frame.
+//!
+//! The initial document is fetched on the runtime while the event loop, the application state
+//! (preferences, identities, permissions) and the renderer are set up, and relays are not
+//! contacted until a page needs them (see [`crate::publish_queue::spawn_deferred_flush`]), so
+//! stages overlap. Each is recorded with the time it started, relative to process start, and
+//! how long it took. `frontier --profile-startup` prints them to stderr after the first paint;
+//! otherwise they are logged at debug level.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+pub const PROFILE_FLAG: &str = "--profile-startup";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Building the tokio runtime.
+    Runtime,
+    /// Reading the proxy configuration.
+    Proxy,
+    EventLoop,
+    /// Loading preferences, identities and permissions.
+    AppState,
+    /// Probing the GPU to choose the renderer.
+    Renderer,
+    /// Resolving the first document and downloading it with its blocking scripts.
+    InitialFetch,
+    /// Parsing the first document and running its blocking scripts.
+    Document,
+    /// Creating the OS window.
+    Window,
+    /// Style, layout and paint of the first frame.
+    FirstPaint,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Runtime => "runtime",
+            Stage::Proxy => "proxy",
+            Stage::EventLoop => "event_loop",
+            Stage::AppState => "app_state",
+            Stage::Renderer => "renderer",
+            Stage::InitialFetch => "initial_fetch",
+            Stage::Document => "document",
+            Stage::Window => "window",
+            Stage::FirstPaint => "first_paint",
+        }
+    }
+}
+
+struct Profile {
+    started: Instant,
+    print: bool,
+    /// Each stage with its start, relative to `started`, and duration.
+    stages: Vec<(Stage, Duration, Duration)>,
+    finished: bool,
+}
+
+static PROFILE: OnceLock<Mutex<Profile>> = OnceLock::new();
+
+fn profile() -> &'static Mutex<Profile> {
+    PROFILE.get_or_init(|| {
+        Mutex::new(Profile {
+            started: Instant::now(),
+            print: false,
+            stages: Vec::new(),
+            finished: false,
+        })
+    })
+}
+
+/// Start the clock. Call first thing in `main`; `print` is whether [`PROFILE_FLAG`] was given.
+pub fn begin(print: bool) {
+    profile().lock().unwrap().print = print;
+}
+
+fn record(stage: Stage, started: Instant) {
+    let elapsed = started.elapsed();
+    let mut profile = profile().lock().unwrap();
+    if profile.finished {
+        return;
+    }
+    let offset = started.saturating_duration_since(profile.started);
+    profile.stages.push((stage, offset, elapsed));
+}
+
+/// Run `work` as `stage`. Stages after the first paint are not recorded.
+pub fn time<T>(stage: Stage, work: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = work();
+    record(stage, started);
+    result
+}
+
+/// [`time`] for a future.
+pub async fn time_async<F: std::future::Future>(stage: Stage, work: F) -> F::Output {
+    let started = Instant::now();
+    let result = work.await;
+    record(stage, started);
+    result
+}
+
+/// The first frame is on screen: report the stages. Only the first call does anything.
+pub fn finish() {
+    let mut profile = profile().lock().unwrap();
+    if profile.finished {
+        return;
+    }
+    profile.finished = true;
+    let report = report(&profile.stages, profile.started.elapsed());
+    if profile.print {
+        eprintln!("{report}");
+    } else {
+        debug!(target = "startup", "{report}");
+    }
+}
+
+fn report(stages: &[(Stage, Duration, Duration)], total: Duration) -> String {
+    let mut lines = vec![format!(
+        "{:<14} {:>9} {:>9}",
+        "stage", "start ms", "took ms"
+    )];
+    lines.extend(stages.iter().map(|(stage, offset, elapsed)| {
+        format!(
+            "{:<14} {:>9.1} {:>9.1}",
+            stage.name(),
+            millis(*offset),
+            millis(*elapsed)
+        )
+    }));
+    lines.push(format!("first frame after {:.1} ms", millis(total)));
+    lines.join("\n")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_overlapping_stages_in_the_order_they_ended() {
+        let ms = Duration::from_millis;
+        let report = report(
+            &[
+                (Stage::EventLoop, ms(2), ms(8)),
+                (Stage::AppState, ms(10), ms(5)),
+                (Stage::InitialFetch, ms(3), ms(40)),
+            ],
+            ms(120),
+        );
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("stage"));
+        assert_eq!(
+            lines[3].split_whitespace().collect::<Vec<_>>(),
+            ["initial_fetch", "3.0", "40.0"]
+        );
+        assert_eq!(lines[4], "first frame after 120.0 ms");
+    }
+}
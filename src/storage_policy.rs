@@ -0,0 +1,65 @@
+//! Whether the browser may write what it learns while browsing to disk.
+//!
+//! `frontier --private` chooses [`StoragePolicy::Private`] in `main`, before any store opens. A
+//! private session still reads the profile in the data directory, so bookmarks, identities,
+//! site permissions and cached pages are there. The stores that write to it check
+//! [`StoragePolicy::persists`] first and keep their changes in memory instead:
+//!
+//! - the page cache and the script bytecode cache;
+//! - quick-open history;
+//! - the crash recovery snapshot, so a private session is never offered for restore;
+//! - window geometry;
+//! - identities, site permissions and the signature log;
+//! - the publish queue.
+//!
+//! Cookies and `localStorage` live in memory in every session. Popups of a private window are
+//! started with [`PRIVATE_FLAG`] too.
+
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+
+/// Command-line flag that starts a private session.
+pub const PRIVATE_FLAG: &str = "--private";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoragePolicy {
+    /// Stores save their changes in the data directory.
+    #[default]
+    Persistent,
+    /// Nothing is written; what the session learns is gone when it exits.
+    Private,
+}
+
+static POLICY: OnceLock<StoragePolicy> = OnceLock::new();
+
+impl StoragePolicy {
+    /// The policy of this process: the one [`Self::set`] chose, otherwise persistent.
+    pub fn current() -> Self {
+        *POLICY.get_or_init(Self::default)
+    }
+
+    /// Choose the policy of this process. Fails once any store has consulted it, so nothing
+    /// can be written before a private session begins.
+    pub fn set(policy: Self) -> Result<()> {
+        POLICY
+            .set(policy)
+            .map_err(|_| anyhow!("the storage policy was already chosen"))
+    }
+
+    pub fn persists(self) -> bool {
+        self == Self::Persistent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_is_fixed_once_consulted() {
+        assert!(StoragePolicy::current().persists());
+        assert!(StoragePolicy::set(StoragePolicy::Private).is_err());
+        assert!(StoragePolicy::current().persists());
+    }
+}
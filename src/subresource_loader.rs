@@ -0,0 +1,584 @@
+//! Prioritised, per-host limited scheduling for document subresources.
+//!
+//! Blitz hands every stylesheet, font and image to the net provider as soon as it parses the
+//! reference, so an image-heavy page can queue dozens of requests ahead of the CSS that decides
+//! layout. [`SubresourceLoader`] sits between the document and the blitz provider: requests wait
+//! in a queue ordered by [`Priority`], and at most `max_per_host` are in flight per host.
+//! http(s) requests are made with [`http_client::shared`], so they take the configured proxy
+//! like every other fetch; other schemes are left to the blitz provider.
+//!
+//! Navigating away calls [`SubresourceLoader::cancel_pending`], which drops everything still
+//! queued and aborts the http(s) requests already on the wire, freeing their host slots at once.
+//! Responses to requests the blitz provider made for other schemes are discarded when they
+//! arrive.
+//!
+//! Every request is also recorded in the page's [`NetworkLog`] from the moment it is queued.
+//! Requests the page's site settings (see [`ContentRules`]) or the filter lists (see
+//! [`Blocklist`]) block are logged as failed and never queued, and counted for the chrome.
+//! The rest get the shared [`CookieJar`]'s `Cookie` header, and their `Referer` and
+//! `User-Agent` from [`privacy::apply_as`], before queueing; the `User-Agent` is the one the
+//! window emulates when it emulates one.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use blitz_net::Provider;
+use blitz_traits::net::{BoxedHandler, Bytes, NetHandler, NetProvider, Request, SharedCallback};
+use reqwest::header::{HeaderValue, COOKIE};
+use tokio::runtime::Handle;
+use tokio::task::AbortHandle;
+use tracing::debug;
+use url::Url;
+
+use crate::adblock::Blocklist;
+use crate::cookies::CookieJar;
+use crate::http_client;
+use crate::network_log::{Initiator, NetworkLog, PendingRequest, RequestSource};
+use crate::permissions::ContentRules;
+use crate::privacy;
+
+const DEFAULT_MAX_PER_HOST: usize = 6;
+
+/// Fetch order for subresources; higher variants go first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Other,
+    Image,
+    Font,
+    Blocking,
+}
+
+impl Priority {
+    /// Blitz does not tag requests with their destination, so classify by extension.
+    pub fn for_url(url: &Url) -> Self {
+        let extension = url
+            .path()
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("css") | Some("js") | Some("mjs") => Priority::Blocking,
+            Some("woff") | Some("woff2") | Some("ttf") | Some("otf") => Priority::Font,
+            Some("png") | Some("jpg") | Some("jpeg") | Some("gif") | Some("webp")
+            | Some("avif") | Some("svg") | Some("ico") | Some("bmp") => Priority::Image,
+            _ => Priority::Other,
+        }
+    }
+}
+
+struct Queued<T> {
+    priority: Priority,
+    seq: u64,
+    host: String,
+    item: T,
+}
+
+/// Queue state, kept free of blitz types so the ordering rules are testable on their own.
+struct Scheduler<T> {
+    max_per_host: usize,
+    queue: Vec<Queued<T>>,
+    active: HashMap<String, usize>,
+    next_seq: u64,
+    generation: u64,
+}
+
+impl<T> Scheduler<T> {
+    fn new(max_per_host: usize) -> Self {
+        Self {
+            max_per_host: max_per_host.max(1),
+            queue: Vec::new(),
+            active: HashMap::new(),
+            next_seq: 0,
+            generation: 0,
+        }
+    }
+
+    fn push(&mut self, priority: Priority, host: String, item: T) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Queued {
+            priority,
+            seq,
+            host,
+            item,
+        });
+    }
+
+    /// Remove every request that may start now, highest priority first and FIFO within a
+    /// priority, marking each as active against its host.
+    fn take_ready(&mut self) -> Vec<(String, T)> {
+        let mut ready = Vec::new();
+        loop {
+            let next = self
+                .queue
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| {
+                    self.active.get(&entry.host).copied().unwrap_or(0) < self.max_per_host
+                })
+                .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(b.seq.cmp(&a.seq)))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                break;
+            };
+            let entry = self.queue.remove(index);
+            *self.active.entry(entry.host.clone()).or_insert(0) += 1;
+            ready.push((entry.host, entry.item));
+        }
+        ready
+    }
+
+    fn finish(&mut self, host: &str) {
+        if let Some(count) = self.active.get_mut(host) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.active.remove(host);
+            }
+        }
+    }
+
+    fn cancel_pending(&mut self) -> usize {
+        self.generation += 1;
+        let dropped = self.queue.len();
+        self.queue.clear();
+        dropped
+    }
+}
+
+struct PendingFetch<D> {
+    doc_id: usize,
+    request: Request,
+    handler: BoxedHandler<D>,
+    logged: Option<PendingRequest>,
+}
+
+struct Shared<D> {
+    inner: Arc<Provider<D>>,
+    /// Receives the bytes of requests made with our own client.
+    callback: SharedCallback<D>,
+    runtime: Handle,
+    scheduler: Mutex<Scheduler<PendingFetch<D>>>,
+    /// Tasks fetching http(s) requests, aborted when the page goes away.
+    in_flight: Mutex<Vec<AbortHandle>>,
+    network_log: Option<NetworkLog>,
+    blocklist: Arc<Blocklist>,
+    page: Mutex<PageFilter>,
+    /// `User-Agent` the window emulates, sent in place of Frontier's own.
+    user_agent: Mutex<Option<String>>,
+    /// Requests of the current page that were blocked.
+    blocked: AtomicUsize,
+}
+
+/// The page whose requests are being checked, with its site settings.
+#[derive(Default)]
+struct PageFilter {
+    url: Option<Url>,
+    rules: ContentRules,
+}
+
+impl<D: Send + Sync + 'static> Shared<D> {
+    /// Why a request for `url` may not be made, if it may not.
+    fn block_reason(&self, url: &Url, priority: Priority) -> Option<String> {
+        let page = self.page.lock().ok()?;
+        if let Some(setting) = page.rules.blocks(url, priority == Priority::Image) {
+            return Some(format!("blocked by site settings ({})", setting.name()));
+        }
+        self.blocklist
+            .blocking_rule(url, page.url.as_ref())
+            .map(|rule| format!("blocked by filter {rule}"))
+    }
+
+    fn dispatch(self: &Arc<Self>) {
+        let (ready, generation) = {
+            let mut scheduler = self
+                .scheduler
+                .lock()
+                .expect("subresource scheduler poisoned");
+            (scheduler.take_ready(), scheduler.generation)
+        };
+        for (host, pending) in ready {
+            let handler = Box::new(TrackedHandler {
+                inner: Some(pending.handler),
+                logged: pending.logged,
+                host,
+                generation,
+                shared: Arc::clone(self),
+            });
+            if matches!(pending.request.url.scheme(), "http" | "https") {
+                let callback = Arc::clone(&self.callback);
+                let task = self.runtime.spawn(fetch_http(
+                    pending.doc_id,
+                    pending.request,
+                    handler,
+                    callback,
+                ));
+                if let Ok(mut in_flight) = self.in_flight.lock() {
+                    in_flight.retain(|task| !task.is_finished());
+                    in_flight.push(task.abort_handle());
+                }
+            } else {
+                self.inner.fetch(pending.doc_id, pending.request, handler);
+            }
+        }
+    }
+}
+
+/// Fetch an http(s) subresource through the shared, proxy-aware client. Like the blitz
+/// provider, the body is handed over whatever the status, and a failed request just drops
+/// the handler.
+async fn fetch_http<D: Send + Sync + 'static>(
+    doc_id: usize,
+    request: Request,
+    handler: Box<TrackedHandler<D>>,
+    callback: SharedCallback<D>,
+) {
+    let response = match http_client::shared() {
+        Ok(client) => client
+            .get(request.url.clone())
+            .headers(request.headers)
+            .send()
+            .await
+            .map_err(|err| err.to_string()),
+        Err(err) => Err(err.to_string()),
+    };
+    match response {
+        Ok(response) => match response.bytes().await {
+            Ok(bytes) => handler.bytes(doc_id, bytes, callback),
+            Err(err) => {
+                debug!(target = "subresources", url = %request.url, error = %err, "body failed")
+            }
+        },
+        Err(err) => {
+            debug!(target = "subresources", url = %request.url, error = %err, "request failed")
+        }
+    }
+}
+
+/// Wraps the document's handler so the host slot is released however the request ends:
+/// blitz drops the handler without calling it when a fetch fails.
+struct TrackedHandler<D: Send + Sync + 'static> {
+    inner: Option<BoxedHandler<D>>,
+    logged: Option<PendingRequest>,
+    host: String,
+    generation: u64,
+    shared: Arc<Shared<D>>,
+}
+
+impl<D: Send + Sync + 'static> NetHandler<D> for TrackedHandler<D> {
+    fn bytes(mut self: Box<Self>, doc_id: usize, bytes: Bytes, callback: SharedCallback<D>) {
+        let current = self
+            .shared
+            .scheduler
+            .lock()
+            .map(|scheduler| scheduler.generation)
+            .unwrap_or(self.generation);
+        if let Some(logged) = self.logged.take() {
+            logged.finish(None, Vec::new(), &bytes);
+        }
+        if let Some(inner) = self.inner.take() {
+            if current == self.generation {
+                inner.bytes(doc_id, bytes, callback);
+            } else {
+                debug!(target = "subresources", host = %self.host, "dropping response for previous page");
+            }
+        }
+    }
+}
+
+impl<D: Send + Sync + 'static> Drop for TrackedHandler<D> {
+    fn drop(&mut self) {
+        if let Some(logged) = self.logged.take() {
+            logged.fail("request failed");
+        }
+        if let Ok(mut scheduler) = self.shared.scheduler.lock() {
+            scheduler.finish(&self.host);
+        }
+        self.shared.dispatch();
+    }
+}
+
+/// Net provider for documents that schedules through a priority queue before handing
+/// requests to the underlying blitz provider.
+pub struct SubresourceLoader<D: Send + Sync + 'static> {
+    shared: Arc<Shared<D>>,
+}
+
+impl<D: Send + Sync + 'static> SubresourceLoader<D> {
+    /// Per-host concurrency comes from `FRONTIER_MAX_REQUESTS_PER_HOST`, defaulting to six
+    /// like other browsers. Requests are recorded in `network_log` when one is given, and
+    /// checked against `blocklist`. `callback` receives what is fetched without the blitz
+    /// provider. Call from within a tokio runtime, which runs those fetches.
+    pub fn new(
+        inner: Arc<Provider<D>>,
+        callback: SharedCallback<D>,
+        network_log: Option<NetworkLog>,
+        blocklist: Arc<Blocklist>,
+    ) -> Self {
+        let max_per_host = std::env::var("FRONTIER_MAX_REQUESTS_PER_HOST")
+            .ok()
+            .and_then(|raw| raw.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_PER_HOST);
+        Self {
+            shared: Arc::new(Shared {
+                inner,
+                callback,
+                runtime: Handle::current(),
+                scheduler: Mutex::new(Scheduler::new(max_per_host)),
+                in_flight: Mutex::new(Vec::new()),
+                network_log,
+                blocklist,
+                page: Mutex::new(PageFilter::default()),
+                user_agent: Mutex::new(None),
+                blocked: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Whether nothing is queued or in flight, i.e. the current page's subresources settled.
+    pub fn is_idle(&self) -> bool {
+        self.shared
+            .scheduler
+            .lock()
+            .map(|scheduler| scheduler.queue.is_empty() && scheduler.active.is_empty())
+            .unwrap_or(true)
+    }
+
+    /// Check requests as made by the page at `url`, with its site settings `rules`, from now
+    /// on, and start counting blocked requests again.
+    pub fn set_page(&self, url: Option<Url>, rules: ContentRules) {
+        if let Ok(mut page) = self.shared.page.lock() {
+            *page = PageFilter { url, rules };
+        }
+        self.shared.blocked.store(0, Ordering::Relaxed);
+    }
+
+    /// Send `user_agent` in place of Frontier's own from now on, or stop with `None`.
+    pub fn set_user_agent(&self, user_agent: Option<String>) {
+        if let Ok(mut current) = self.shared.user_agent.lock() {
+            *current = user_agent;
+        }
+    }
+
+    /// How many of the current page's requests were blocked.
+    pub fn blocked_count(&self) -> usize {
+        self.shared.blocked.load(Ordering::Relaxed)
+    }
+
+    /// Forget queued requests and abort those of the current page still in flight. An aborted
+    /// request drops its handler, which frees its host slot.
+    pub fn cancel_pending(&self) {
+        if let Ok(mut scheduler) = self.shared.scheduler.lock() {
+            let dropped = scheduler.cancel_pending();
+            if dropped > 0 {
+                debug!(
+                    target = "subresources",
+                    dropped, "cancelled queued subresource requests"
+                );
+            }
+        }
+        let aborted = self
+            .shared
+            .in_flight
+            .lock()
+            .map(|mut in_flight| std::mem::take(&mut *in_flight))
+            .unwrap_or_default();
+        for task in aborted {
+            task.abort();
+        }
+    }
+}
+
+impl<D: Send + Sync + 'static> NetProvider<D> for SubresourceLoader<D> {
+    fn fetch(&self, doc_id: usize, mut request: Request, handler: BoxedHandler<D>) {
+        let priority = Priority::for_url(&request.url);
+        let host = request.url.host_str().unwrap_or_default().to_string();
+        // Blitz only issues GETs for subresources.
+        let logged = self.shared.network_log.as_ref().map(|log| {
+            log.begin(
+                "GET",
+                &request.url,
+                Initiator::Subresource,
+                RequestSource::for_url(&request.url),
+            )
+        });
+        if let Some(reason) = self.shared.block_reason(&request.url, priority) {
+            debug!(target = "subresources", url = %request.url, %reason, "blocked request");
+            self.shared.blocked.fetch_add(1, Ordering::Relaxed);
+            if let Some(logged) = logged {
+                logged.fail(reason);
+            }
+            // The handler is dropped without being called, as when a fetch fails.
+            return;
+        }
+        let page = self
+            .shared
+            .page
+            .lock()
+            .ok()
+            .and_then(|page| page.url.clone());
+        // The provider has no jar of its own; cookies come from the one `fetch()` uses.
+        let cookie = CookieJar::shared()
+            .header_for(&request.url)
+            .and_then(|cookie| HeaderValue::from_str(&cookie).ok());
+        if let Some(cookie) = cookie {
+            request.headers.insert(COOKIE, cookie);
+        }
+        let user_agent = self
+            .shared
+            .user_agent
+            .lock()
+            .ok()
+            .and_then(|user_agent| user_agent.clone());
+        privacy::apply_as(
+            &mut request.headers,
+            &request.url,
+            page.as_ref(),
+            user_agent.as_deref(),
+        );
+        if let Ok(mut scheduler) = self.shared.scheduler.lock() {
+            scheduler.push(
+                priority,
+                host,
+                PendingFetch {
+                    doc_id,
+                    request,
+                    handler,
+                    logged,
+                },
+            );
+        }
+        self.shared.dispatch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use blitz_dom::net::Resource;
+    use blitz_traits::net::DummyNetCallback;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    struct Ignore;
+
+    impl NetHandler<Resource> for Ignore {
+        fn bytes(
+            self: Box<Self>,
+            _doc_id: usize,
+            _bytes: Bytes,
+            _callback: SharedCallback<Resource>,
+        ) {
+        }
+    }
+
+    fn url(raw: &str) -> Url {
+        Url::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn classifies_by_extension() {
+        assert_eq!(
+            Priority::for_url(&url("https://a.example/site.css?v=1")),
+            Priority::Blocking
+        );
+        assert_eq!(
+            Priority::for_url(&url("https://a.example/f.woff2")),
+            Priority::Font
+        );
+        assert_eq!(
+            Priority::for_url(&url("https://a.example/p.JPG")),
+            Priority::Image
+        );
+        assert_eq!(
+            Priority::for_url(&url("https://a.example/data")),
+            Priority::Other
+        );
+    }
+
+    #[test]
+    fn stylesheets_jump_ahead_of_images() {
+        let mut scheduler = Scheduler::new(1);
+        scheduler.push(Priority::Image, "a".into(), "img1");
+        scheduler.push(Priority::Image, "a".into(), "img2");
+        scheduler.push(Priority::Blocking, "a".into(), "css");
+        scheduler.push(Priority::Font, "b".into(), "font");
+
+        let first: Vec<_> = scheduler.take_ready().into_iter().map(|(_, i)| i).collect();
+        assert_eq!(first, vec!["css", "font"]);
+
+        scheduler.finish("a");
+        let next: Vec<_> = scheduler.take_ready().into_iter().map(|(_, i)| i).collect();
+        assert_eq!(next, vec!["img1"]);
+    }
+
+    #[test]
+    fn per_host_limit_is_enforced() {
+        let mut scheduler = Scheduler::new(2);
+        for index in 0..5 {
+            scheduler.push(Priority::Image, "a".into(), index);
+        }
+        assert_eq!(scheduler.take_ready().len(), 2);
+        assert!(scheduler.take_ready().is_empty());
+        scheduler.finish("a");
+        assert_eq!(scheduler.take_ready().len(), 1);
+    }
+
+    #[test]
+    fn cancel_drops_queue_and_bumps_generation() {
+        let mut scheduler = Scheduler::new(1);
+        scheduler.push(Priority::Image, "a".into(), 1);
+        scheduler.push(Priority::Image, "a".into(), 2);
+        scheduler.take_ready();
+        assert_eq!(scheduler.cancel_pending(), 1);
+        assert_eq!(scheduler.generation, 1);
+    }
+
+    #[tokio::test]
+    async fn cancelling_frees_the_slots_of_requests_in_flight() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let callback: SharedCallback<Resource> = Arc::new(DummyNetCallback);
+        let loader = SubresourceLoader::new(
+            Arc::new(Provider::new(Arc::clone(&callback))),
+            callback,
+            None,
+            Arc::new(Blocklist::default()),
+        );
+        loader.shared.scheduler.lock().unwrap().max_per_host = 1;
+        let request = |path: &str| Request::get(url(&format!("http://{addr}/{path}")));
+        let wait = Duration::from_secs(2);
+
+        // The server never answers, so the request holds the host's only slot.
+        loader.fetch(0, request("slow.png"), Box::new(Ignore));
+        let (mut slow, _) = timeout(wait, listener.accept()).await.unwrap().unwrap();
+        loader.cancel_pending();
+
+        let mut buffer = [0; 1024];
+        loop {
+            let read = timeout(wait, slow.read(&mut buffer))
+                .await
+                .expect("the cancelled request kept its connection open");
+            if read.unwrap_or(0) == 0 {
+                break;
+            }
+        }
+        for _ in 0..50 {
+            if loader.is_idle() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(loader.is_idle(), "the cancelled request kept its slot");
+
+        loader.fetch(0, request("next.png"), Box::new(Ignore));
+        timeout(wait, listener.accept())
+            .await
+            .expect("the next page's request waited for the old slot")
+            .unwrap();
+    }
+}
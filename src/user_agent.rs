@@ -0,0 +1,54 @@
+//! The `User-Agent` the browser presents.
+//!
+//! Document loads, `fetch()` and `navigator.userAgent` all present [`current`] unless the
+//! window they belong to emulates another device. An automation session's emulated string
+//! is kept with that session's window and handed to each request it makes (see
+//! [`privacy::apply_as`]), so it never reaches another window or a later session.
+//!
+//! With the `reduce_user_agent` privacy setting on, [`current`] is the [`reduced`] string
+//! instead, which every Frontier install presents alike.
+
+use crate::privacy;
+
+const REDUCED_PLATFORM: &str = "Windows NT 10.0; Win64; x64";
+
+/// Frontier's own string, in the `Mozilla/5.0 (<platform>) <product>` shape sites sniff for.
+pub fn default() -> String {
+    format!(
+        "Mozilla/5.0 ({}) Frontier/{}",
+        platform(),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// The platform and version frozen to the most common values, as Tor Browser does, so the
+/// string says nothing about the machine beyond the browser in use.
+pub fn reduced() -> String {
+    format!(
+        "Mozilla/5.0 ({}) Frontier/{}",
+        REDUCED_PLATFORM,
+        env!("CARGO_PKG_VERSION_MAJOR")
+    )
+}
+
+pub fn current() -> String {
+    if privacy::current().reduce_user_agent {
+        reduced()
+    } else {
+        default()
+    }
+}
+
+/// `emulated` when a window emulates another device, else [`current`].
+pub fn resolve(emulated: Option<&str>) -> String {
+    emulated.map_or_else(current, str::to_string)
+}
+
+fn platform() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "Macintosh; Intel Mac OS X 10_15_7",
+        "windows" => REDUCED_PLATFORM,
+        "android" => "Linux; Android 10; K",
+        _ => "X11; Linux x86_64",
+    }
+}
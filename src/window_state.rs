@@ -0,0 +1,268 @@
+//! The browser window's size, position and maximized state, remembered across launches.
+//!
+//! The window reports every move and resize; [`WindowState`] keeps the last geometry it had
+//! while not maximized, so un-maximizing a restored window brings back its old size. The state
+//! is written to `window-state.json` in the data directory a moment after the window settles
+//! and again at exit, and read back when the next window is created.
+//!
+//! Monitors come and go between launches, so the position is stored together with the name
+//! and origin of the monitor the window was on. [`WindowState::position_on`] puts it back at
+//! the same offset on that monitor wherever it now sits, and on the primary monitor when it is
+//! gone. `remember_window = false` in `frontier.toml` turns this off, and `frontier
+//! --reset-window` forgets the saved state.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::window::WindowAttributes;
+
+use crate::storage_policy::StoragePolicy;
+
+/// Removes the saved state before the window opens.
+pub const RESET_FLAG: &str = "--reset-window";
+const FILE_NAME: &str = "window-state.json";
+
+/// A window smaller than this is not worth restoring.
+const MIN_SIZE: f64 = 200.0;
+
+/// How much of the window must stay on a monitor, in physical pixels, for a restored position
+/// to count as reachable.
+const VISIBLE_MARGIN: i32 = 64;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    /// Outer position in physical pixels of the virtual desktop.
+    pub x: i32,
+    pub y: i32,
+    /// Inner size in logical pixels, so it survives a change of scale factor.
+    pub width: f64,
+    pub height: f64,
+    pub maximized: bool,
+    /// The monitor the window was on.
+    #[serde(default)]
+    pub monitor: Option<MonitorArea>,
+}
+
+/// A monitor's name and bounds in physical pixels of the virtual desktop.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorArea {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorArea {
+    pub fn of(monitor: &winit::monitor::MonitorHandle) -> Self {
+        let position = monitor.position();
+        let size = monitor.size();
+        Self {
+            name: monitor.name(),
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// Whether a window at `(x, y)` would show at least its top-left corner and title bar on
+    /// this monitor.
+    fn shows(&self, x: i32, y: i32) -> bool {
+        let right = self.x + self.width as i32;
+        let bottom = self.y + self.height as i32;
+        x + VISIBLE_MARGIN > self.x
+            && x + VISIBLE_MARGIN <= right
+            && y >= self.y - VISIBLE_MARGIN / 2
+            && y + VISIBLE_MARGIN <= bottom
+    }
+}
+
+impl WindowState {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::preferences::data_file(FILE_NAME)
+    }
+
+    /// The saved state at `path`; `None` when there is none or it cannot be read.
+    pub fn load(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        match serde_json::from_slice::<Self>(&bytes) {
+            Ok(state) if state.width >= MIN_SIZE && state.height >= MIN_SIZE => Some(state),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::warn!(path = %path.display(), error = %err, "ignoring window state");
+                None
+            }
+        }
+    }
+
+    /// Does nothing in a private session.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if !StoragePolicy::current().persists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_vec_pretty(self).context("serialize window state")?;
+        fs::write(path, json).with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Remove the saved state at `path`, if any.
+    pub fn forget(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| format!("unable to remove {}", path.display())),
+        }
+    }
+
+    /// `attrs` with the saved size, position and maximized state.
+    pub fn apply(&self, attrs: WindowAttributes) -> WindowAttributes {
+        attrs
+            .with_inner_size(LogicalSize::new(self.width, self.height))
+            .with_position(PhysicalPosition::new(self.x, self.y))
+            .with_maximized(self.maximized)
+    }
+
+    /// Where the window belongs on the connected `monitors`, the first of which is the
+    /// primary one: the saved offset on the monitor it was on, wherever that monitor now is,
+    /// or the saved position if it is still reachable, or the saved offset on the primary
+    /// monitor. The window is kept inside the monitor it lands on.
+    pub fn position_on(&self, monitors: &[MonitorArea]) -> Option<PhysicalPosition<i32>> {
+        let (offset_x, offset_y) = match &self.monitor {
+            Some(saved) => (self.x - saved.x, self.y - saved.y),
+            None => (self.x, self.y),
+        };
+        let named = self.monitor.as_ref().and_then(|saved| {
+            saved.name.as_ref().and_then(|name| {
+                monitors
+                    .iter()
+                    .find(|monitor| monitor.name.as_ref() == Some(name))
+            })
+        });
+        if named.is_none() && monitors.iter().any(|monitor| monitor.shows(self.x, self.y)) {
+            return Some(PhysicalPosition::new(self.x, self.y));
+        }
+        let monitor = named.or_else(|| monitors.first())?;
+        let max_x = (monitor.width as i32 - VISIBLE_MARGIN * 2).max(0);
+        let max_y = (monitor.height as i32 - VISIBLE_MARGIN).max(0);
+        Some(PhysicalPosition::new(
+            monitor.x + offset_x.clamp(0, max_x),
+            monitor.y + offset_y.clamp(0, max_y),
+        ))
+    }
+
+    /// Record a move or resize. While maximized only the flag changes, so the size and
+    /// position to return to are kept, unless there are none yet. Returns whether anything
+    /// changed.
+    pub fn update(
+        &mut self,
+        position: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+        scale: f64,
+        maximized: bool,
+        monitor: Option<MonitorArea>,
+    ) -> bool {
+        let mut next = self.clone();
+        next.maximized = maximized;
+        if !maximized || self.width < MIN_SIZE {
+            let size = size.to_logical::<f64>(scale);
+            next.x = position.x;
+            next.y = position.y;
+            next.width = size.width;
+            next.height = size.height;
+            next.monitor = monitor;
+        }
+        let changed = next != *self;
+        *self = next;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, y: i32) -> MonitorArea {
+        MonitorArea {
+            name: Some(name.to_string()),
+            x,
+            y,
+            width: 1920,
+            height: 1080,
+        }
+    }
+
+    fn state(x: i32, y: i32, monitor: Option<MonitorArea>) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width: 1024.0,
+            height: 768.0,
+            maximized: false,
+            monitor,
+        }
+    }
+
+    #[test]
+    fn restored_positions_follow_their_monitor_or_fall_back_to_the_primary() {
+        let laptop = monitor("Built-in", 0, 0);
+        let external = monitor("DELL U2720Q", 1920, 0);
+        let saved = state(2020, 50, Some(external.clone()));
+
+        let same = [laptop.clone(), external.clone()];
+        assert_eq!(
+            saved.position_on(&same),
+            Some(PhysicalPosition::new(2020, 50))
+        );
+
+        let moved = [laptop.clone(), monitor("DELL U2720Q", -1920, 0)];
+        assert_eq!(
+            saved.position_on(&moved),
+            Some(PhysicalPosition::new(-1820, 50))
+        );
+
+        let unplugged = [laptop.clone()];
+        assert_eq!(
+            saved.position_on(&unplugged),
+            Some(PhysicalPosition::new(100, 50))
+        );
+
+        let off_screen = state(5000, -400, None);
+        assert_eq!(
+            off_screen.position_on(&unplugged),
+            Some(PhysicalPosition::new(1792, 0))
+        );
+        assert_eq!(
+            state(40, 30, None).position_on(&unplugged),
+            Some(PhysicalPosition::new(40, 30))
+        );
+        assert_eq!(saved.position_on(&[]), None);
+    }
+
+    #[test]
+    fn maximizing_keeps_the_geometry_to_return_to_and_round_trips() {
+        let mut saved = state(10, 20, None);
+        let origin = PhysicalPosition::new(0, 0);
+        assert!(saved.update(origin, PhysicalSize::new(3840, 2160), 2.0, true, None));
+        assert!(saved.maximized);
+        assert_eq!((saved.x, saved.width), (10, 1024.0));
+        assert!(saved.update(origin, PhysicalSize::new(1600, 1200), 2.0, false, None));
+        assert_eq!((saved.x, saved.width, saved.height), (0, 800.0, 600.0));
+        assert!(!saved.update(origin, PhysicalSize::new(1600, 1200), 2.0, false, None));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(FILE_NAME);
+        assert_eq!(WindowState::load(&path), None);
+        saved.save(&path).unwrap();
+        assert_eq!(WindowState::load(&path), Some(saved));
+        WindowState::forget(&path).unwrap();
+        WindowState::forget(&path).unwrap();
+        assert_eq!(WindowState::load(&path), None);
+    }
+}
@@ -1 +1,2 @@
+pub mod reftest;
 pub mod runner;
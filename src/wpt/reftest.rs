@@ -0,0 +1,385 @@
+//! WPT reftests: render a test and its references headlessly and compare the pixels.
+//!
+//! A reftest names its references with `<link rel="match" href>` (must render the same) or
+//! `<link rel="mismatch" href>` (must render differently). [`ReftestRunner`] serves the WPT
+//! checkout over the loopback dev server so root-relative paths such as `/fonts/ahem.css`
+//! resolve as they would under `wptserve`, renders every file at 800×600 with the CPU
+//! renderer, and compares the images with [`screenshot::compare`]. A
+//! `<meta name="fuzzy" content="maxDifference=0-2;totalPixels=0-300">` annotation raises the
+//! tolerance to its upper bounds; per-reference fuzzy values and reference chains (references
+//! that themselves have references) are not supported.
+//!
+//! When an artifact directory is set, each failing comparison writes the test render, the
+//! reference render and, for size-matched `match` failures, a diff image.
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use blitz_dom::DocumentConfig;
+use blitz_html::HtmlDocument;
+use blitz_net::{MpscCallback, Provider};
+use blitz_traits::shell::{ColorScheme, Viewport};
+use image::RgbaImage;
+use kuchiki::parse_html;
+use kuchiki::traits::*;
+use url::Url;
+
+use crate::dev_server::{start_dev_server, DevServerHandle};
+use crate::screenshot::{self, CompareThresholds, ScreenshotComparison};
+use crate::wpt::runner::WptManifest;
+
+/// WPT renders reftests in an 800×600 viewport.
+pub const REFTEST_WIDTH: u32 = 800;
+pub const REFTEST_HEIGHT: u32 = 600;
+
+const DEFAULT_LOAD_TIMEOUT: Duration = Duration::from_secs(5);
+/// Only used to resolve reference hrefs against the test path.
+const RESOLVE_ORIGIN: &str = "http://web-platform.test/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    Match,
+    Mismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub kind: ReferenceKind,
+    /// Relative to the WPT root.
+    pub path: PathBuf,
+}
+
+/// How far a render may stray from a `match` reference and still pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReftestTolerance {
+    /// Largest per-channel difference (0–255) of a pixel that still counts as equal.
+    pub max_difference: u8,
+    /// Number of pixels allowed to exceed `max_difference`.
+    pub total_pixels: u64,
+}
+
+impl ReftestTolerance {
+    fn thresholds(self) -> CompareThresholds {
+        let pixels = u64::from(REFTEST_WIDTH) * u64::from(REFTEST_HEIGHT);
+        CompareThresholds {
+            pixel_tolerance: self.max_difference,
+            max_diff_ratio: self.total_pixels as f64 / pixels as f64,
+            min_ssim: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReferenceOutcome {
+    pub reference: Reference,
+    pub passed: bool,
+    pub comparison: ScreenshotComparison,
+    /// Directory holding the renders of a failed comparison, when artifacts are enabled.
+    pub artifacts: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReftestResult {
+    pub test: PathBuf,
+    pub references: Vec<ReferenceOutcome>,
+}
+
+impl ReftestResult {
+    pub fn success(&self) -> bool {
+        self.references.iter().all(|outcome| outcome.passed)
+    }
+}
+
+pub struct ReftestRunner {
+    root: PathBuf,
+    tolerance: ReftestTolerance,
+    artifact_dir: Option<PathBuf>,
+    load_timeout: Duration,
+}
+
+impl ReftestRunner {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            tolerance: ReftestTolerance::default(),
+            artifact_dir: None,
+            load_timeout: DEFAULT_LOAD_TIMEOUT,
+        }
+    }
+
+    /// Tolerance for tests without a fuzzy annotation. Exact by default, as in WPT.
+    pub fn with_tolerance(mut self, tolerance: ReftestTolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_artifact_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.artifact_dir = Some(dir.into());
+        self
+    }
+
+    /// How long to wait for stylesheets, fonts and images before rendering anyway.
+    pub fn with_load_timeout(mut self, timeout: Duration) -> Self {
+        self.load_timeout = timeout;
+        self
+    }
+
+    pub async fn run_reftest(&self, relative_path: &Path) -> Result<ReftestResult> {
+        let server = self.serve().await?;
+        let result = self.run_with_server(&server, relative_path).await;
+        server.shutdown().await;
+        result
+    }
+
+    pub async fn run_manifest(&self, manifest: &WptManifest) -> Result<Vec<ReftestResult>> {
+        let server = self.serve().await?;
+        let mut results = Vec::with_capacity(manifest.entries().len());
+        for entry in manifest.entries() {
+            match self.run_with_server(&server, entry).await {
+                Ok(result) => results.push(result),
+                Err(err) => {
+                    server.shutdown().await;
+                    return Err(err.context(format!("running reftest {}", entry.display())));
+                }
+            }
+        }
+        server.shutdown().await;
+        Ok(results)
+    }
+
+    async fn serve(&self) -> Result<DevServerHandle> {
+        start_dev_server(&self.root, SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .context("serving WPT root for reftests")
+    }
+
+    async fn run_with_server(
+        &self,
+        server: &DevServerHandle,
+        relative_path: &Path,
+    ) -> Result<ReftestResult> {
+        let path = self.root.join(relative_path);
+        let html = fs::read_to_string(&path)
+            .with_context(|| format!("reading reftest {}", path.display()))?;
+        let references = references(&html, relative_path)?;
+        if references.is_empty() {
+            return Err(anyhow!(
+                "{} has no rel=match or rel=mismatch reference",
+                relative_path.display()
+            ));
+        }
+        let thresholds = fuzzy_tolerance(&html)
+            .unwrap_or(self.tolerance)
+            .thresholds();
+
+        let test_image = self.render(server, relative_path, &html).await?;
+        let mut outcomes = Vec::with_capacity(references.len());
+        for reference in references {
+            let reference_html = fs::read_to_string(self.root.join(&reference.path))
+                .with_context(|| format!("reading reference {}", reference.path.display()))?;
+            let reference_image = self
+                .render(server, &reference.path, &reference_html)
+                .await?;
+            let (comparison, diff) =
+                screenshot::compare(&test_image, &reference_image, &thresholds);
+            let passed = match reference.kind {
+                ReferenceKind::Match => comparison.matched,
+                ReferenceKind::Mismatch => !comparison.matched,
+            };
+            let artifacts = match (&self.artifact_dir, passed) {
+                (Some(dir), false) => Some(write_artifacts(
+                    dir,
+                    relative_path,
+                    &reference.path,
+                    &test_image,
+                    &reference_image,
+                    diff.as_ref(),
+                )?),
+                _ => None,
+            };
+            outcomes.push(ReferenceOutcome {
+                reference,
+                passed,
+                comparison,
+                artifacts,
+            });
+        }
+
+        Ok(ReftestResult {
+            test: relative_path.to_path_buf(),
+            references: outcomes,
+        })
+    }
+
+    async fn render(
+        &self,
+        server: &DevServerHandle,
+        relative_path: &Path,
+        html: &str,
+    ) -> Result<RgbaImage> {
+        let url = Url::parse(&server.url())?.join(&relative_path.to_string_lossy())?;
+        let (mut resources, callback) = MpscCallback::new();
+        let net = Arc::new(Provider::new(Arc::new(callback)));
+        let mut document = HtmlDocument::from_html(
+            html,
+            DocumentConfig {
+                base_url: Some(url.to_string()),
+                net_provider: Some(net.clone()),
+                ..Default::default()
+            },
+        );
+
+        let deadline = tokio::time::Instant::now() + self.load_timeout;
+        while !net.is_empty() {
+            match tokio::time::timeout_at(deadline, resources.recv()).await {
+                Ok(Some((_, resource))) => document.load_resource(resource),
+                Ok(None) => break,
+                Err(_) => {
+                    tracing::warn!(
+                        target = "wpt",
+                        test = %relative_path.display(),
+                        "rendering before every subresource loaded"
+                    );
+                    break;
+                }
+            }
+        }
+
+        document.set_viewport(Viewport::new(
+            REFTEST_WIDTH,
+            REFTEST_HEIGHT,
+            1.0,
+            ColorScheme::Light,
+        ));
+        document.resolve();
+        screenshot::capture(&document, 1.0, REFTEST_WIDTH, REFTEST_HEIGHT)
+    }
+}
+
+/// The `rel=match` and `rel=mismatch` references of a test, relative to the WPT root.
+pub fn references(html: &str, test_path: &Path) -> Result<Vec<Reference>> {
+    let test_url = Url::parse(RESOLVE_ORIGIN)?.join(&test_path.to_string_lossy())?;
+    let document = parse_html().one(html);
+    let mut references = Vec::new();
+    let Ok(links) = document.select("link[rel][href]") else {
+        return Ok(references);
+    };
+    for link in links {
+        let attributes = link.attributes.borrow();
+        let rel = attributes.get("rel").unwrap_or_default();
+        let kind = if rel.split_ascii_whitespace().any(|rel| rel == "match") {
+            ReferenceKind::Match
+        } else if rel.split_ascii_whitespace().any(|rel| rel == "mismatch") {
+            ReferenceKind::Mismatch
+        } else {
+            continue;
+        };
+        let href = attributes.get("href").unwrap_or_default();
+        let resolved = test_url
+            .join(href)
+            .with_context(|| format!("resolving reference {href}"))?;
+        references.push(Reference {
+            kind,
+            path: PathBuf::from(resolved.path().trim_start_matches('/')),
+        });
+    }
+    Ok(references)
+}
+
+/// Upper bounds of a `<meta name="fuzzy">` annotation, e.g. `maxDifference=0-2;totalPixels=0-300`
+/// or the positional `0-2;0-300`.
+fn fuzzy_tolerance(html: &str) -> Option<ReftestTolerance> {
+    let document = parse_html().one(html);
+    let meta = document.select_first("meta[name=fuzzy][content]").ok()?;
+    let attributes = meta.attributes.borrow();
+    let content = attributes.get("content")?;
+    // A leading `reference.html:` scopes the values to one reference; apply them to all.
+    let values = content
+        .rsplit_once(':')
+        .map_or(content, |(_, values)| values);
+    let mut parts = values.split(';').map(str::trim);
+    let mut upper = |name: &str| -> Option<u64> {
+        let part = parts.next()?;
+        let range = part
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+            .unwrap_or(part);
+        range.rsplit('-').next()?.trim().parse().ok()
+    };
+    let max_difference = upper("maxDifference")?;
+    let total_pixels = upper("totalPixels")?;
+    Some(ReftestTolerance {
+        max_difference: max_difference.min(255) as u8,
+        total_pixels,
+    })
+}
+
+fn write_artifacts(
+    dir: &Path,
+    test: &Path,
+    reference: &Path,
+    test_image: &RgbaImage,
+    reference_image: &RgbaImage,
+    diff: Option<&RgbaImage>,
+) -> Result<PathBuf> {
+    let flatten = |path: &Path| path.to_string_lossy().replace(['/', '\\'], "_");
+    let target = dir.join(flatten(test)).join(flatten(reference));
+    fs::create_dir_all(&target).with_context(|| format!("creating {}", target.display()))?;
+    fs::write(target.join("test.png"), screenshot::encode_png(test_image)?)?;
+    fs::write(
+        target.join("reference.png"),
+        screenshot::encode_png(reference_image)?,
+    )?;
+    if let Some(diff) = diff {
+        fs::write(target.join("diff.png"), screenshot::encode_png(diff)?)?;
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_references_and_fuzzy_annotations() {
+        let html = r#"<!DOCTYPE html>
+            <link rel="help" href="https://drafts.csswg.org/css-flexbox/">
+            <link rel="match" href="reference/box-ref.html">
+            <link rel="mismatch" href="/css/reference/blank.html">
+            <meta name="fuzzy" content="maxDifference=0-2;totalPixels=0-300">
+            <div></div>"#;
+        let references = references(html, Path::new("css/css-flexbox/box.html")).unwrap();
+        assert_eq!(
+            references,
+            vec![
+                Reference {
+                    kind: ReferenceKind::Match,
+                    path: PathBuf::from("css/css-flexbox/reference/box-ref.html"),
+                },
+                Reference {
+                    kind: ReferenceKind::Mismatch,
+                    path: PathBuf::from("css/reference/blank.html"),
+                },
+            ]
+        );
+        assert_eq!(
+            fuzzy_tolerance(html),
+            Some(ReftestTolerance {
+                max_difference: 2,
+                total_pixels: 300,
+            })
+        );
+        assert_eq!(
+            fuzzy_tolerance(r#"<meta name="fuzzy" content="box-ref.html:5;10-20">"#),
+            Some(ReftestTolerance {
+                max_difference: 5,
+                total_pixels: 20,
+            })
+        );
+        assert_eq!(fuzzy_tolerance("<p>exact</p>"), None);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
@@ -9,6 +10,7 @@ use serde::Deserialize;
 use tokio::time::sleep;
 
 use crate::js::environment::JsDomEnvironment;
+use crate::js::runtime_document::RuntimeDocument;
 
 const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(5);
 const POLL_INTERVAL: Duration = Duration::from_millis(10);
@@ -202,10 +204,13 @@ impl WptRunner {
     }
 
     async fn run_inner(&self, source: &str, script_name: &str) -> Result<WptRun> {
-        let environment =
-            JsDomEnvironment::new(BASE_HTML).context("initialising QuickJS environment for WPT")?;
-        let mut document = HtmlDocument::from_html(BASE_HTML, DocumentConfig::default());
-        environment.attach_document(&mut document);
+        let environment = Rc::new(
+            JsDomEnvironment::new(BASE_HTML).context("initialising QuickJS environment for WPT")?,
+        );
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(BASE_HTML, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
         environment
             .eval(WINDOW_POLYFILL, "frontier-wpt-window-polyfill.js")
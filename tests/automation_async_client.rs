@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use frontier::automation_client::{
+    AsyncAutomationHost, AutomationError, AutomationHostConfig, ElementSelector, WaitOptions,
+};
+use url::Url;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_client_drives_the_form_and_reports_command_failures() -> Result<()> {
+    let asset_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/automation");
+    let form_url = Url::from_file_path(asset_root.join("form.html"))
+        .map_err(|_| anyhow!("unable to form file:// url for automation form"))?;
+
+    let host = AsyncAutomationHost::spawn(
+        AutomationHostConfig::default()
+            .with_asset_root(asset_root)
+            .with_initial_target(form_url.as_str().to_string()),
+    )
+    .await?;
+    let session = host.session_from_asset("form.html").await?;
+
+    let title = session
+        .wait_for_text(
+            &ElementSelector::css("#title"),
+            WaitOptions::default_text_wait(),
+        )
+        .await?;
+    assert!(!title.is_empty());
+    session.type_text_css("#name-input", "Ada").await?;
+
+    let err = session
+        .click_css("#does-not-exist")
+        .await
+        .expect_err("clicking a missing element fails");
+    assert!(
+        matches!(err, AutomationError::CommandFailed { .. }),
+        "expected a command failure, got {err}"
+    );
+    assert!(!err.is_host_crash());
+    assert!(host.is_running());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_client_sends_the_host_auth_token() -> Result<()> {
+    let asset_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/automation");
+    let host = AsyncAutomationHost::spawn(
+        AutomationHostConfig::default()
+            .with_asset_root(asset_root)
+            .with_auth_token("lab-secret"),
+    )
+    .await?;
+    let session = host.session_from_asset("form.html").await?;
+    session
+        .wait_for_element(
+            &ElementSelector::css("#title"),
+            WaitOptions::default_text_wait(),
+        )
+        .await?;
+    Ok(())
+}
@@ -3,8 +3,9 @@ use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use frontier::automation_client::{
-    AutomationHost, AutomationHostConfig, ElementSelector, KeyboardAction, PointerAction,
-    PointerButton, PointerTarget, WaitOptions,
+    AutomationHost, AutomationHostConfig, AutomationSession, ColorScheme, CompareThresholds,
+    ElementSelector, Emulation, KeyboardAction, PointerAction, PointerButton, PointerTarget,
+    WaitOptions,
 };
 use url::Url;
 
@@ -72,3 +73,148 @@ fn automation_form_interaction() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn automation_screenshot_matches_its_own_baseline() -> Result<()> {
+    // Baselines must live under the asset root, so serve a copy of the form from a scratch one.
+    let dir = tempfile::tempdir()?;
+    std::fs::copy(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/automation/form.html"),
+        dir.path().join("form.html"),
+    )?;
+    let host = AutomationHost::spawn(
+        AutomationHostConfig::default().with_asset_root(dir.path().to_path_buf()),
+    )?;
+    let session = host.session_from_asset("form.html")?;
+    session.set_viewport(640.0, 480.0)?;
+    session.wait_for_text(
+        &ElementSelector::css("#title"),
+        WaitOptions::default_text_wait(),
+    )?;
+
+    let thresholds = CompareThresholds::default();
+    let written = session.compare_screenshot("form.png", thresholds, true)?;
+    assert!(written.baseline_updated);
+    assert!(dir.path().join("form.png").exists());
+
+    let comparison = session.compare_screenshot("form.png", thresholds, false)?;
+    assert!(comparison.matched, "unchanged page differs: {comparison:?}");
+
+    let outside = tempfile::tempdir()?;
+    let escaped = outside.path().join("form.png");
+    assert!(session
+        .compare_screenshot(&escaped, thresholds, true)
+        .is_err());
+    assert!(session
+        .compare_screenshot("../form.png", thresholds, true)
+        .is_err());
+    assert!(!escaped.exists());
+
+    session.set_viewport(480.0, 480.0)?;
+    let resized = session.compare_screenshot("form.png", thresholds, false)?;
+    assert!(!resized.matched);
+    assert_ne!(resized.width, resized.baseline_width);
+    Ok(())
+}
+
+#[test]
+fn automation_renders_and_clicks_at_the_emulated_pixel_ratio() -> Result<()> {
+    let asset_root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/automation");
+    let host = AutomationHost::spawn(AutomationHostConfig::default().with_asset_root(asset_root))?;
+    let session = host.session_from_asset("form.html")?;
+    session.wait_for_text(
+        &ElementSelector::css("#title"),
+        WaitOptions::default_text_wait(),
+    )?;
+    session.emulate(&Emulation {
+        device_scale_factor: Some(1.0),
+        ..Emulation::default()
+    })?;
+    let standard = image::load_from_memory(&session.screenshot()?)?;
+
+    session.emulate(&Emulation {
+        device_scale_factor: Some(2.0),
+        ..Emulation::default()
+    })?;
+    let dense = image::load_from_memory(&session.screenshot()?)?;
+    assert_eq!(
+        (dense.width(), dense.height()),
+        (standard.width() * 2, standard.height() * 2),
+        "the page is rasterized at twice the density, not scaled up"
+    );
+
+    // Pointer input is still in CSS pixels and lands on the button.
+    let button = ElementSelector::css("#submit");
+    session.scroll_into_view(&button)?;
+    session.type_text_css("#name-input", "Ada")?;
+    session.click(&button)?;
+    session.wait_for_element(
+        &ElementSelector::Role {
+            role: "status".into(),
+            name: Some("Submitted".into()),
+        },
+        WaitOptions::new(Duration::from_secs(2), Duration::from_millis(100)),
+    )?;
+    Ok(())
+}
+
+const EMULATED_USER_AGENT: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) Test/1";
+
+fn emulation_page(tag: &str) -> Result<String> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/automation/emulation.html");
+    let mut url = Url::from_file_path(&path)
+        .map_err(|_| anyhow!("unable to form file:// url for emulation page"))?;
+    url.set_query(Some(tag));
+    Ok(url.to_string())
+}
+
+/// `devicePixelRatio|colour scheme|navigator.userAgent` as the page loaded with `?tag` saw them.
+fn device_traits(session: &AutomationSession<'_>, tag: &str) -> Result<String> {
+    let prefix = format!("?{tag}|");
+    let selector = ElementSelector::css("#traits");
+    for _ in 0..40 {
+        let text = session.wait_for_text(&selector, WaitOptions::default_text_wait())?;
+        if let Some(traits) = text.strip_prefix(&prefix) {
+            return Ok(traits.to_string());
+        }
+        session.pump(Duration::from_millis(100))?;
+    }
+    Err(anyhow!("page {tag} never reported its traits"))
+}
+
+#[test]
+fn automation_emulates_metrics_colour_scheme_and_user_agent() -> Result<()> {
+    let host = AutomationHost::spawn(AutomationHostConfig::default())?;
+    let session = host.session_from_url(emulation_page("before")?)?;
+    device_traits(&session, "before")?;
+
+    session.emulate(&Emulation {
+        device_scale_factor: Some(3.0),
+        color_scheme: Some(ColorScheme::Dark),
+        user_agent: Some(EMULATED_USER_AGENT.into()),
+        ..Emulation::default()
+    })?;
+    session.navigate_url(&emulation_page("emulated")?)?;
+    let traits = device_traits(&session, "emulated")?;
+    assert_eq!(traits, format!("3|dark|{EMULATED_USER_AGENT}"));
+    Ok(())
+}
+
+#[test]
+fn automation_reset_emulation_restores_the_window_traits() -> Result<()> {
+    let host = AutomationHost::spawn(AutomationHostConfig::default())?;
+    let session = host.session_from_url(emulation_page("before")?)?;
+    let before = device_traits(&session, "before")?;
+    assert!(!before.ends_with(EMULATED_USER_AGENT));
+
+    session.emulate(&Emulation {
+        device_scale_factor: Some(3.0),
+        color_scheme: Some(ColorScheme::Dark),
+        user_agent: Some(EMULATED_USER_AGENT.into()),
+        ..Emulation::default()
+    })?;
+    session.reset_emulation()?;
+    session.navigate_url(&emulation_page("reset")?)?;
+    assert_eq!(device_traits(&session, "reset")?, before);
+    Ok(())
+}
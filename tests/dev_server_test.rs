@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+
+use frontier::dev_server::start_dev_server;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn serves_files_with_mime_types_and_index_fallback() {
+    let dir = TempDir::new().expect("temp dir");
+    std::fs::write(dir.path().join("index.html"), "<h1>root</h1>").unwrap();
+    std::fs::create_dir(dir.path().join("docs")).unwrap();
+    std::fs::write(dir.path().join("docs/index.html"), "<h1>docs</h1>").unwrap();
+    std::fs::write(dir.path().join("app.js"), "console.log('hi');").unwrap();
+
+    let server = start_dev_server(dir.path(), SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .expect("start dev server");
+    let base = server.url();
+    assert!(server.addr().ip().is_loopback());
+    assert_ne!(server.addr().port(), 0);
+    assert_eq!(base, format!("http://{}/", server.addr()));
+    let client = reqwest::Client::new();
+
+    let root = client.get(&base).send().await.unwrap();
+    assert_eq!(root.status(), 200);
+    assert_eq!(root.headers()["content-type"], "text/html; charset=utf-8");
+    assert_eq!(root.text().await.unwrap(), "<h1>root</h1>");
+
+    let docs = client.get(format!("{base}docs/")).send().await.unwrap();
+    assert_eq!(docs.text().await.unwrap(), "<h1>docs</h1>");
+
+    let script = client.get(format!("{base}app.js")).send().await.unwrap();
+    assert_eq!(
+        script.headers()["content-type"],
+        "text/javascript; charset=utf-8"
+    );
+
+    let route = client
+        .get(format!("{base}some/client/route"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(route.text().await.unwrap(), "<h1>root</h1>");
+
+    let missing = client
+        .get(format!("{base}missing.css"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    server.shutdown().await;
+}
@@ -4,8 +4,9 @@ use blitz_traits::events::{
     BlitzMouseButtonEvent, DomEvent, DomEventData, MouseEventButton, MouseEventButtons,
 };
 use frontier::js::environment::JsDomEnvironment;
+use frontier::js::runtime_document::RuntimeDocument;
 use keyboard_types::Modifiers;
-use std::ops::DerefMut;
+use std::rc::Rc;
 use tokio::runtime::Builder;
 
 #[test]
@@ -23,9 +24,11 @@ fn bubbling_reaches_parent_without_target_listener() {
             </html>
         "#;
 
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
-        environment.attach_document(&mut document);
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
         environment
             .eval(
@@ -163,14 +166,10 @@ fn abort_signal_nested_listener_removed_without_error() {
     });
 }
 
-fn lookup_node_id<T>(document: &mut T, target: &str) -> Option<usize>
-where
-    T: DerefMut<Target = BaseDocument>,
-{
+fn lookup_node_id(document: &mut BaseDocument, target: &str) -> Option<usize> {
     let mut result = None;
-    let base: &mut BaseDocument = document.deref_mut();
-    let root = base.root_node().id;
-    base.iter_subtree_mut(root, |node_id, doc| {
+    let root = document.root_node().id;
+    document.iter_subtree_mut(root, |node_id, doc| {
         if result.is_some() {
             return;
         }
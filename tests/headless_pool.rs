@@ -0,0 +1,60 @@
+use std::sync::LazyLock;
+use std::thread;
+use std::time::Duration;
+
+use frontier::automation::HeadlessSessionPool;
+
+static POOL: LazyLock<HeadlessSessionPool> =
+    LazyLock::new(|| HeadlessSessionPool::new(2).expect("session pool"));
+
+#[test]
+fn pooled_sessions_start_blank_every_time() {
+    for _ in 0..3 {
+        let session = POOL.acquire().expect("session");
+        let leftover = session
+            .eval_string(
+                "String([typeof globalThis.leaked, localStorage.length, document.cookie, \
+                 document.body.childNodes.length])",
+            )
+            .expect("inspect session");
+        assert_eq!(leftover, "undefined,0,,0");
+
+        session
+            .eval(
+                "globalThis.leaked = true; localStorage.setItem('k', 'v'); \
+                 document.cookie = 'c=1'; document.body.append(document.createElement('p'));",
+            )
+            .expect("dirty session");
+    }
+}
+
+#[test]
+fn pooled_sessions_run_on_many_threads() {
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            thread::spawn(move || {
+                let session = POOL.acquire().expect("session");
+                session
+                    .set_body_html(&format!(
+                        "<button id=\"go\">go</button><span id=\"out\">{i}</span>"
+                    ))
+                    .expect("set body");
+                session
+                    .eval(
+                        "const out = document.getElementById('out'); \
+                         document.getElementById('go').addEventListener('click', () => \
+                         setTimeout(() => { out.textContent += '!'; }, 5));",
+                    )
+                    .expect("install listener");
+                session.click("#go").expect("click");
+                session.pump_for(Duration::from_millis(50)).expect("pump");
+                session.inner_text("#out").expect("read output")
+            })
+        })
+        .collect();
+    let outputs: Vec<String> = handles
+        .into_iter()
+        .map(|handle| handle.join().expect("test thread"))
+        .collect();
+    assert_eq!(outputs, ["0!", "1!", "2!", "3!"]);
+}
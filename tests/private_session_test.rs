@@ -0,0 +1,99 @@
+//! A private session against its own data directory. The storage policy is fixed for the
+//! whole process, so this runs as a test binary of its own.
+
+use std::time::Duration;
+
+use frontier::automation::HeadlessSessionPool;
+use frontier::crash_recovery::{self, SessionSnapshot};
+use frontier::identities::IdentityStore;
+use frontier::js::bytecode_cache::BytecodeCache;
+use frontier::navigation::FetchedDocument;
+use frontier::page_cache::PageCache;
+use frontier::permissions::Permissions;
+use frontier::publish_queue::PublishQueue;
+use frontier::quick_open::QuickOpenStore;
+use frontier::signer::{self, SignatureOutcome, SignatureRecord};
+use frontier::storage_policy::StoragePolicy;
+use frontier::window_state::WindowState;
+use url::Url;
+
+#[test]
+fn private_session_leaves_the_data_directory_empty() {
+    let data_dir = tempfile::tempdir().unwrap();
+    std::env::set_var("FRONTIER_DATA_DIR", data_dir.path());
+    StoragePolicy::set(StoragePolicy::Private).expect("nothing consulted the policy yet");
+
+    let pool = HeadlessSessionPool::new(1).expect("session pool");
+    let session = pool.acquire().expect("session");
+    let bundle = format!(
+        "globalThis.total = 0; {}",
+        "globalThis.total += 1;\n".repeat(1024)
+    );
+    session.eval(&bundle).expect("run a large script");
+    session
+        .eval("localStorage.setItem('k', 'v'); document.cookie = 'c=1';")
+        .expect("store page state");
+    session
+        .pump_for(Duration::from_millis(20))
+        .expect("pump session");
+    drop(session);
+
+    let url = Url::parse("https://example.com/").unwrap();
+    let document = FetchedDocument {
+        base_url: url.to_string(),
+        contents: "<p>cached</p>".to_string(),
+        file_path: None,
+        display_url: url.to_string(),
+        scripts: Vec::new(),
+        csp_headers: Vec::new(),
+        response: None,
+    };
+    PageCache::open_default()
+        .expect("page cache")
+        .store(&url, &document)
+        .unwrap();
+    assert!(BytecodeCache::shared().is_none());
+
+    let mut quick_open = QuickOpenStore::default();
+    quick_open.record_visit(url.as_str(), "Example");
+    quick_open
+        .save(&QuickOpenStore::default_path().unwrap())
+        .unwrap();
+    WindowState::default()
+        .save(&WindowState::default_path().unwrap())
+        .unwrap();
+    let mut permissions = Permissions::default();
+    permissions.allow_public_key("https://example.com");
+    permissions
+        .save(&Permissions::default_path().unwrap())
+        .unwrap();
+    signer::record(
+        &signer::audit_log_path().unwrap(),
+        &SignatureRecord::now(
+            "https://example.com",
+            None,
+            1,
+            None,
+            SignatureOutcome::Denied,
+        ),
+    )
+    .unwrap();
+    PublishQueue::default()
+        .save(&PublishQueue::default_path().unwrap())
+        .unwrap();
+    assert!(IdentityStore::default()
+        .save(&IdentityStore::default_path().unwrap())
+        .is_err());
+    crash_recovery::record(SessionSnapshot {
+        url: url.to_string(),
+        ..SessionSnapshot::default()
+    });
+    crash_recovery::clean_exit();
+    assert!(crash_recovery::pending().is_none());
+
+    let written: Vec<_> = std::fs::read_dir(data_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert!(written.is_empty(), "{written:?}");
+}
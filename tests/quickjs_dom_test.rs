@@ -6,16 +6,33 @@ use blitz_traits::events::{
     MouseEventButton, MouseEventButtons, UiEvent,
 };
 use blitz_traits::net::DummyNetCallback;
+use blitz_traits::shell::Viewport;
+use frontier::adblock::Blocklist;
+use frontier::form_widgets::{self, FormWidget, WidgetKey, WidgetOutcome};
+use frontier::gestures::SwipeDirection;
+use frontier::js::blob::LocalFile;
+use frontier::js::clock::VirtualClock;
 use frontier::js::environment::JsDomEnvironment;
+use frontier::js::gestures::{GestureEvent, PinchPhase};
+use frontier::js::lifecycle::LifecycleEvent;
+use frontier::js::media::{ColorScheme, MediaPreferences};
+use frontier::js::messaging::{PostedMessage, WindowRef};
+use frontier::js::nostr::SignerMethod;
+use frontier::js::performance::NavigationTiming;
 use frontier::js::processor;
 use frontier::js::runtime_document::RuntimeDocument;
+use frontier::js::scroll::{ScrollAlign, ScrollIntoView};
 use frontier::js::session::JsPageRuntime;
+use frontier::js::viewport::ViewportMetrics;
+use frontier::js::window_open::WindowRequest;
 use frontier::navigation::{self, FetchRequest, FetchSource, FetchedDocument};
+use frontier::permissions::{ContentSetting, Permissions};
 use keyboard_types::{Code, Key, Location, Modifiers};
-use std::ops::DerefMut;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::runtime::Builder;
 use tokio::time::sleep;
 use url::Url;
@@ -31,8 +48,10 @@ fn quickjs_demo_executes_script_and_mutates_dom() {
         let mut runtime = JsPageRuntime::new(&html, &scripts, None)
             .expect("create runtime")
             .expect("runtime available for scripts");
-        let mut runtime_doc = HtmlDocument::from_html(&html, DocumentConfig::default());
-        runtime.attach_document(&mut runtime_doc);
+        let _runtime_doc = RuntimeDocument::attach(
+            HtmlDocument::from_html(&html, DocumentConfig::default()),
+            &mut runtime,
+        );
         let runtime_summary = runtime
             .run_blocking_scripts()
             .expect("runtime execution")
@@ -49,6 +68,8 @@ fn quickjs_demo_executes_script_and_mutates_dom() {
             file_path: None,
             display_url: "file://demo/quickjs-demo.html".into(),
             scripts: scripts.clone(),
+            csp_headers: Vec::new(),
+            response: None,
         };
         let summary = processor::execute_inline_scripts(&mut document)
             .expect("processor execution")
@@ -66,10 +87,12 @@ fn dom_bridge_updates_live_document() {
     runtime.block_on(async {
         let html = "<!DOCTYPE html><html><body><h1 id=\"message\">Loading…</h1></body></html>";
 
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
-        environment.attach_document(&mut document);
         environment
             .eval(
                 "document.getElementById('message').textContent = 'Updated';",
@@ -108,9 +131,11 @@ fn dom_event_listener_runs_and_prevents_default() {
         </html>
     "#;
 
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
-        environment.attach_document(&mut document);
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
         environment
             .eval(
@@ -163,9 +188,11 @@ fn timers_execute_after_delay() {
             <html><body><div id="root">idle</div></body></html>
         "#;
 
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
-        environment.attach_document(&mut document);
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
         environment
             .eval(
@@ -192,6 +219,60 @@ fn timers_execute_after_delay() {
     });
 }
 
+#[test]
+fn virtual_clock_fires_timers_only_when_advanced_and_in_order() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<html><body><div id="root"></div></body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        let clock = VirtualClock::default();
+        environment.set_virtual_clock(clock.clone());
+
+        environment
+            .eval(
+                r#"
+                    const root = document.getElementById('root');
+                    const log = (label) => {
+                        root.textContent += `${label}@${Math.round(performance.now())} `;
+                    };
+                    setTimeout(() => log('b'), 50);
+                    setTimeout(() => log('a'), 20);
+                    let ticks = 0;
+                    const interval = setInterval(() => {
+                        log('i');
+                        if (++ticks === 2) clearInterval(interval);
+                    }, 30);
+                "#,
+                "virtual-clock.js",
+            )
+            .expect("evaluate script");
+
+        sleep(Duration::from_millis(80)).await;
+        environment.pump().expect("pump");
+        let root_id = lookup_node_id(&mut document, "root").expect("root id");
+        let text = |document: &BaseDocument| document.get_node(root_id).unwrap().text_content();
+        assert_eq!(text(&document), "");
+        assert_eq!(
+            environment.next_timer_due(),
+            Some(Duration::from_millis(20))
+        );
+
+        while let Some(due) = environment
+            .next_timer_due()
+            .filter(|due| *due <= Duration::from_millis(100))
+        {
+            clock.set(due);
+            environment.pump().expect("pump");
+        }
+        assert_eq!(text(&document), "a@20 i@30 b@50 i@60 ");
+        assert_eq!(environment.next_timer_due(), None);
+    });
+}
+
 #[test]
 fn intervals_floor_zero_delay() {
     let runtime = Builder::new_current_thread().enable_all().build().unwrap();
@@ -201,9 +282,11 @@ fn intervals_floor_zero_delay() {
             <html><body><div id="root">idle</div></body></html>
         "#;
 
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
-        environment.attach_document(&mut document);
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
         environment
             .eval(
@@ -246,14 +329,10 @@ fn intervals_floor_zero_delay() {
     });
 }
 
-fn lookup_node_id<T>(document: &mut T, target: &str) -> Option<usize>
-where
-    T: DerefMut<Target = BaseDocument>,
-{
+fn lookup_node_id(document: &mut BaseDocument, target: &str) -> Option<usize> {
     let mut result = None;
-    let base: &mut BaseDocument = document.deref_mut();
-    let root = base.root_node().id;
-    base.iter_subtree_mut(root, |node_id, doc| {
+    let root = document.root_node().id;
+    document.iter_subtree_mut(root, |node_id, doc| {
         if result.is_some() {
             return;
         }
@@ -293,10 +372,12 @@ fn dom_api_supports_creating_elements() {
     let runtime = Builder::new_current_thread().enable_all().build().unwrap();
     runtime.block_on(async {
         let html = "<!DOCTYPE html><html><body><div id=\"root\"></div></body></html>";
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
-        environment.attach_document(&mut document);
         environment
             .eval(
                 r#"
@@ -332,15 +413,629 @@ fn dom_api_supports_creating_elements() {
     });
 }
 
+#[test]
+fn insertions_that_would_make_the_tree_cyclic_throw() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><div id=\"outer\"><p id=\"inner\">x</p></div>\
+                    <div id=\"log\"></div></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        environment
+            .eval(
+                r#"
+                    const outer = document.getElementById('outer');
+                    const inner = document.getElementById('inner');
+                    const text = inner.firstChild;
+                    const attempts = [
+                        () => inner.appendChild(outer),
+                        () => outer.appendChild(outer),
+                        () => inner.insertBefore(document.body, null),
+                        () => text.appendChild(document.createElement('span')),
+                        () => outer.replaceChild(document.documentElement, inner),
+                    ];
+                    const results = attempts.map((attempt) => {
+                        try {
+                            attempt();
+                            return 'inserted';
+                        } catch (err) {
+                            return 'rejected';
+                        }
+                    });
+                    inner.insertBefore(inner.firstChild, inner.firstChild);
+                    document.getElementById('log').textContent = results.join(',');
+                "#,
+                "dom-cycles.js",
+            )
+            .expect("evaluate script");
+
+        let serialized = environment.document_html().expect("serialize");
+        assert!(
+            serialized.contains("rejected,rejected,rejected,rejected,rejected"),
+            "{serialized}"
+        );
+        assert!(serialized.contains("<div id=\"outer\"><p id=\"inner\">x</p></div>"));
+    });
+}
+
+#[test]
+fn split_text_normalize_and_ranges_edit_text_nodes() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><p id=\"para\">héllo 😀 world</p>\
+                    <p id=\"range\">one <b>two</b> three</p><div id=\"log\"></div></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let log: String = environment
+            .eval_with(
+                r#"
+                    const para = document.getElementById('para');
+                    const text = para.firstChild;
+                    const tail = text.splitText(8);
+                    const afterSplit = [text.data, tail.data, para.childNodes.length].join('|');
+                    let outOfRange = 'accepted';
+                    try {
+                        text.splitText(100);
+                    } catch (err) {
+                        outOfRange = err.name;
+                    }
+                    para.appendChild(document.createTextNode(''));
+                    para.appendChild(document.createTextNode('!'));
+                    para.normalize();
+                    const afterNormalize = [para.childNodes.length, text.data, text.length]
+                        .join('|');
+
+                    const target = document.getElementById('range');
+                    const range = document.createRange();
+                    range.setStart(target.firstChild, 2);
+                    range.setEnd(target.lastChild, 3);
+                    const selected = range.toString();
+                    const common = range.commonAncestorContainer === target;
+                    range.deleteContents();
+                    const marker = document.createElement('i');
+                    range.insertNode(marker);
+                    [afterSplit, outOfRange, afterNormalize, selected, common, range.collapsed]
+                        .join(';')
+                "#,
+                "text-ranges.js",
+            )
+            .expect("evaluate script");
+
+        assert_eq!(
+            log,
+            "héllo 😀| world|2;IndexSizeError;1|héllo 😀 world!|15;e two th;true;false"
+        );
+        let serialized = environment.document_html().expect("serialize");
+        assert!(
+            serialized.contains("<p id=\"para\">héllo 😀 world!</p>"),
+            "{serialized}"
+        );
+        assert!(
+            serialized.contains("<p id=\"range\">on<i></i>ree</p>"),
+            "{serialized}"
+        );
+    });
+}
+
+#[test]
+fn namespaced_attributes_round_trip() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><svg><use id=\"icon\" xlink:href=\"#a\"/></svg>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let log: String = environment
+            .eval_with(
+                r#"
+                    const XLINK = 'http://www.w3.org/1999/xlink';
+                    const icon = document.getElementById('icon');
+                    const parsed = [
+                        icon.getAttributeNS(XLINK, 'href'),
+                        icon.getAttribute('xlink:href'),
+                        icon.getAttribute('href'),
+                        icon.hasAttributeNS(null, 'href'),
+                    ].join('|');
+                    icon.setAttributeNS(XLINK, 'xlink:href', '#b');
+                    icon.setAttributeNS(XLINK, 'xl:title', 'Icon');
+                    icon.setAttributeNS(null, 'href', '#plain');
+                    const errors = [
+                        () => icon.setAttributeNS(null, 'xlink:href', '#c'),
+                        () => icon.setAttributeNS(XLINK, 'xmlns', '#c'),
+                    ].map((attempt) => {
+                        try {
+                            attempt();
+                            return 'set';
+                        } catch (err) {
+                            const message = String(err);
+                            return message.includes('NamespaceError') ? 'NamespaceError' : message;
+                        }
+                    });
+                    const updated = [
+                        icon.getAttributeNS(XLINK, 'href'),
+                        icon.getAttributeNS(XLINK, 'title'),
+                        icon.getAttribute('href'),
+                    ].join('|');
+                    icon.removeAttributeNS(XLINK, 'title');
+                    [parsed, errors.join(','), updated, icon.hasAttributeNS(XLINK, 'title')]
+                        .join(';')
+                "#,
+                "namespaced-attributes.js",
+            )
+            .expect("evaluate script");
+
+        assert_eq!(
+            log,
+            "#a|#a||false;NamespaceError,NamespaceError;#b|Icon|#plain;false"
+        );
+        let serialized = environment.document_html().expect("serialize");
+        assert!(serialized.contains("xlink:href=\"#b\""), "{serialized}");
+        assert!(serialized.contains(" href=\"#plain\""), "{serialized}");
+        assert!(!serialized.contains("title"), "{serialized}");
+        assert_eq!(serialized.matches("href=").count(), 2, "{serialized}");
+    });
+}
+
+#[test]
+fn html_strings_parse_in_context_and_graft_into_the_tree() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><div id=\"host\"><p id=\"target\">old</p></div>\
+                    <ul id=\"list\"><li>a</li></ul><table><tbody id=\"rows\"></tbody></table>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let log: String = environment
+            .eval_with(
+                r#"
+                    const target = document.getElementById('target');
+                    const before = target.outerHTML;
+                    const list = document.getElementById('list');
+                    list.insertAdjacentHTML('beforebegin', '<h2>Title</h2>');
+                    list.insertAdjacentHTML('afterbegin', '<li>first</li>');
+                    list.insertAdjacentHTML('beforeend', '<li>last<!--c--></li>');
+                    list.insertAdjacentHTML('afterend', '<p id="after">after</p>');
+                    let badPosition = 'accepted';
+                    try {
+                        list.insertAdjacentHTML('inside', '<li>x</li>');
+                    } catch (err) {
+                        badPosition = 'rejected';
+                    }
+                    target.outerHTML = '<span id="swapped">new</span> tail';
+
+                    const rows = document.getElementById('rows');
+                    const range = document.createRange();
+                    range.selectNodeContents(rows);
+                    const fragment = range.createContextualFragment('<tr><td>cell</td></tr>');
+                    const parsed = fragment.childNodes.map((node) => node.nodeName).join(',');
+                    rows.appendChild(fragment);
+                    [
+                        before,
+                        badPosition,
+                        target.parentNode === null,
+                        document.getElementById('swapped').textContent,
+                        parsed,
+                    ].join(';')
+                "#,
+                "html-strings.js",
+            )
+            .expect("evaluate script");
+
+        assert_eq!(log, "<p id=\"target\">old</p>;rejected;true;new;TR");
+        let serialized = environment.document_html().expect("serialize");
+        assert!(
+            serialized.contains(
+                "<div id=\"host\"><span id=\"swapped\">new</span> tail</div><h2>Title</h2>\
+                 <ul id=\"list\"><li>first</li><li>a</li><li>last<!--c--></li></ul>\
+                 <p id=\"after\">after</p>\
+                 <table><tbody id=\"rows\"><tr><td>cell</td></tr></tbody></table>"
+            ),
+            "{serialized}"
+        );
+    });
+}
+
+#[test]
+fn template_content_is_inert_until_cloned_into_the_document() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <template id="row"><li><span id="label">label</span><script>
+                window.runs = (window.runs || 0) + 1;
+            </script></li></template>
+            <ul id="list"></ul>
+            <script>
+                const template = document.getElementById('row');
+                const content = template.content;
+                const made = document.createElement('template');
+                made.innerHTML = '<b>x</b>';
+                const results = [
+                    template.childNodes.length,
+                    document.getElementById('label') === null,
+                    window.runs === undefined,
+                    content.nodeType,
+                    content instanceof DocumentFragment,
+                    content === template.content,
+                    content.firstElementChild.nodeName,
+                    made.childNodes.length,
+                    made.content.childNodes.length,
+                    made.innerHTML,
+                ];
+                const list = document.getElementById('list');
+                list.appendChild(content.cloneNode(true));
+                list.appendChild(template.cloneNode(true).content.firstElementChild);
+                let nested = 'accepted';
+                try {
+                    content.firstElementChild.appendChild(template);
+                } catch (err) {
+                    nested = 'rejected';
+                }
+                results.push(list.children.length, content.childNodes.length, window.runs, nested);
+                window.results = results.join(',');
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        let summary = runtime
+            .run_blocking_scripts()
+            .expect("run scripts")
+            .expect("scripts executed");
+        assert_eq!(summary.executed_scripts, 1);
+
+        let results: String = runtime
+            .environment()
+            .eval_with("window.results", "template.js")
+            .expect("read results");
+        assert_eq!(
+            results,
+            "0,true,true,11,true,true,LI,0,1,<b>x</b>,2,1,2,rejected"
+        );
+        let serialized = runtime.environment().document_html().expect("serialize");
+        assert!(
+            serialized.contains("<template id=\"row\"><li><span id=\"label\">label</span><script>"),
+            "{serialized}"
+        );
+    });
+}
+
+#[test]
+fn custom_elements_upgrade_and_get_lifecycle_callbacks() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><div id=\"host\">\
+                    <x-greeting id=\"early\" name=\"parsed\"></x-greeting></div>\
+                    <template id=\"tpl\"><x-greeting></x-greeting></template></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let log: String = environment
+            .eval_with(
+                r#"
+                    window.log = [];
+                    const early = document.getElementById('early');
+                    customElements.whenDefined('x-greeting').then(() => log.push('defined'));
+                    class Greeting extends HTMLElement {
+                        static get observedAttributes() {
+                            return ['name'];
+                        }
+                        constructor() {
+                            super();
+                            this.greeted = true;
+                            log.push('construct');
+                        }
+                        connectedCallback() {
+                            log.push(`connected:${this.id}`);
+                        }
+                        disconnectedCallback() {
+                            log.push(`disconnected:${this.id}`);
+                        }
+                        attributeChangedCallback(name, oldValue, newValue) {
+                            log.push(`${name}:${oldValue}->${newValue}`);
+                        }
+                    }
+                    customElements.define('x-greeting', Greeting);
+                    log.push(`upgraded:${early instanceof Greeting && early.greeted}`);
+
+                    const made = document.createElement('x-greeting');
+                    made.id = 'made';
+                    made.setAttribute('name', 'one');
+                    made.setAttribute('title', 'ignored');
+                    document.getElementById('host').appendChild(made);
+                    made.setAttribute('name', 'two');
+                    made.removeAttribute('name');
+                    made.parentNode.removeChild(made);
+
+                    const host = document.getElementById('host');
+                    host.innerHTML = '<x-greeting id="parsed"></x-greeting>';
+                    const direct = new Greeting();
+                    const template = document.getElementById('tpl').content.firstChild;
+
+                    let duplicate = '';
+                    try {
+                        customElements.define('x-greeting', class extends HTMLElement {});
+                    } catch (err) {
+                        duplicate = err.name;
+                    }
+                    let invalid = '';
+                    try {
+                        customElements.define('greeting', class extends HTMLElement {});
+                    } catch (err) {
+                        invalid = err.name;
+                    }
+                    log.push(
+                        customElements.get('x-greeting') === Greeting,
+                        direct instanceof Greeting,
+                        direct.isConnected,
+                        template instanceof Greeting,
+                        duplicate,
+                        invalid,
+                    );
+                    log.join(',')
+                "#,
+                "custom-elements.js",
+            )
+            .expect("evaluate script");
+
+        assert_eq!(
+            log,
+            "construct,name:null->parsed,connected:early,upgraded:true,\
+             construct,name:null->one,connected:made,name:one->two,name:two->null,\
+             disconnected:made,disconnected:early,construct,connected:parsed,construct,\
+             true,true,false,false,NotSupportedError,SyntaxError"
+        );
+        environment.pump().expect("pump jobs");
+        let defined: String = environment
+            .eval_with("log[log.length - 1]", "defined.js")
+            .expect("read log");
+        assert_eq!(defined, "defined");
+    });
+}
+
+#[test]
+fn shadow_roots_slot_light_children_and_scope_their_styles() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><div id=\"card\">\
+                    <span slot=\"title\" id=\"title\">Hi</span><i id=\"body\">text</i></div>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const card = document.getElementById('card');
+                    const open = { mode: 'open' };
+                    const root = card.attachShadow(open);
+                    root.innerHTML = '<style>p { color: red } ::slotted(span) { margin: 0 }</style>'
+                        + '<p id="inner"><slot name="title">untitled</slot></p><slot></slot>';
+                    const title = document.getElementById('title');
+                    const inner = root.getElementById('inner');
+                    const titleSlot = inner.firstChild;
+                    const errorName = (f) => {
+                        try {
+                            f();
+                        } catch (err) {
+                            return err.name;
+                        }
+                        return 'none';
+                    };
+                    [
+                        card.childNodes.length,
+                        card.shadowRoot === root,
+                        root.host === card,
+                        root.mode,
+                        root instanceof ShadowRoot,
+                        root.childNodes.length,
+                        title.parentNode === card,
+                        title.assignedSlot === titleSlot,
+                        titleSlot.assignedNodes().length,
+                        root.childNodes[2].assignedElements()[0].id,
+                        document.getElementById('inner') === null,
+                        inner.getRootNode() === root,
+                        inner.isConnected,
+                        card.innerHTML,
+                        root.firstChild.textContent,
+                        card.getAttribute('frontier-shadow-host'),
+                        errorName(() => card.attachShadow(open)),
+                        errorName(() => card.attachShadow({ mode: 'sideways' })),
+                        errorName(() => document.createElement('img').attachShadow(open)),
+                    ].join('|')
+                "#,
+                "shadow.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "2|true|true|open|true|3|true|true|1|body|true|true|true|\
+             <span slot=\"title\" id=\"title\">Hi</span><i id=\"body\">text</i>|\
+             p { color: red } ::slotted(span) { margin: 0 }||NotSupportedError|TypeError|\
+             NotSupportedError"
+        );
+
+        // Layout sees the composed tree: light children inside their slots, and a style
+        // that only matches below the host.
+        let card = lookup_node_id(&mut document, "card").expect("card");
+        let title = lookup_node_id(&mut document, "title").expect("title");
+        let slot = document
+            .get_node(title)
+            .and_then(|node| node.parent)
+            .expect("slot");
+        let inner = document
+            .get_node(slot)
+            .and_then(|node| node.parent)
+            .expect("inner");
+        assert_eq!(lookup_node_id(&mut document, "inner"), Some(inner));
+        let style = document.get_node(card).expect("card node").children[0];
+        let style_text = document.get_node(style).expect("style node").text_content();
+        let host = format!("[frontier-shadow-host=\"{card}\"]");
+        assert_eq!(
+            style_text,
+            format!("{host} p{{ color: red }} {host} slot > span{{ margin: 0 }}")
+        );
+    });
+}
+
+#[test]
+fn events_retarget_across_shadow_boundaries() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><div id=\"card\">\
+                    <span slot=\"title\" id=\"title\">Hi</span></div>\
+                    <div id=\"sealed\"><u id=\"light\"></u></div>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        // After WPT shadow-dom/event-composed-path.html and event-composed.html.
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const card = document.getElementById('card');
+                    const root = card.attachShadow({ mode: 'open' });
+                    root.innerHTML = '<p id="inner"><slot name="title"></slot></p>';
+                    const inner = root.getElementById('inner');
+                    const seen = [];
+                    const names = (event) => event.composedPath().map((node) => node.nodeName);
+                    const record = (label) => (event) => {
+                        seen.push(`${label}:${event.target.nodeName}:${event.eventPhase}`);
+                    };
+                    inner.addEventListener('ping', record('inner'));
+                    root.addEventListener('ping', record('root'));
+                    card.addEventListener('ping', record('card'));
+                    document.addEventListener('ping', record('document'));
+                    document.addEventListener('ping', (event) => seen.push(names(event).join(',')));
+
+                    const composed = new Event('ping', { bubbles: true, composed: true });
+                    inner.dispatchEvent(composed);
+                    seen.push(String(composed.target));
+                    inner.dispatchEvent(new Event('ping', { bubbles: true }));
+
+                    const sealed = document.getElementById('sealed');
+                    const closed = sealed.attachShadow({ mode: 'closed' });
+                    closed.innerHTML = '<b>secret</b><slot></slot>';
+                    let inside = '';
+                    let outside = '';
+                    closed.firstChild.addEventListener('ping', (event) => {
+                        inside = names(event).join(',');
+                    });
+                    sealed.addEventListener('ping', (event) => {
+                        outside = names(event).join(',');
+                    });
+                    closed.firstChild.dispatchEvent(
+                        new Event('ping', { bubbles: true, composed: true }),
+                    );
+                    const light = document.getElementById('light');
+                    [seen.join(' '), inside, outside, light.assignedSlot === null].join('|')
+                "#,
+                "retarget.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "inner:P:2 root:P:3 card:DIV:2 document:DIV:3 \
+             P,#document-fragment,DIV,BODY,HTML,#document null \
+             inner:P:2 root:P:3|\
+             B,#document-fragment,DIV,BODY,HTML,#document|DIV,BODY,HTML,#document|true"
+        );
+
+        // Hit testing hands over the rendered chain; listeners get the event path through the
+        // slot and shadow root, and a cancelled pointerdown swallows the mouse events.
+        environment
+            .eval(
+                r#"
+                    globalThis.pointerLog = [];
+                    let cancel = true;
+                    for (const type of ['pointerdown', 'mousedown', 'pointerup', 'mouseup']) {
+                        document.addEventListener(type, (event) => {
+                            const path = event.composedPath().map((node) => node.nodeName);
+                            pointerLog.push(`${type}:${event.target.id}:${path.join(',')}`);
+                            if (type === 'pointerdown' && cancel) {
+                                cancel = false;
+                                event.preventDefault();
+                            }
+                        });
+                    }
+                "#,
+                "pointer.js",
+            )
+            .expect("register listeners");
+
+        let title = lookup_node_id(&mut document, "title").expect("title");
+        let chain = document.node_chain(title);
+        let mouse = || BlitzMouseButtonEvent {
+            x: 0.0,
+            y: 0.0,
+            button: MouseEventButton::Main,
+            buttons: MouseEventButtons::Primary,
+            mods: Modifiers::default(),
+        };
+        for data in [
+            DomEventData::MouseDown(mouse()),
+            DomEventData::MouseUp(mouse()),
+            DomEventData::MouseDown(mouse()),
+        ] {
+            environment
+                .dispatch_dom_event(&DomEvent::new(title, data), &chain)
+                .expect("dispatch");
+        }
+
+        let log: String = environment
+            .eval_with("pointerLog.join(' ')", "pointer-log.js")
+            .expect("read log");
+        let path = "SPAN,SLOT,P,#document-fragment,DIV,BODY,HTML,#document";
+        assert_eq!(
+            log,
+            format!(
+                "pointerdown:title:{path} pointerup:title:{path} \
+                 pointerdown:title:{path} mousedown:title:{path}"
+            )
+        );
+    });
+}
+
 #[test]
 fn comment_nodes_preserve_payload() {
     let runtime = Builder::new_current_thread().enable_all().build().unwrap();
     runtime.block_on(async {
         let html = "<!DOCTYPE html><html><body></body></html>";
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(html, DocumentConfig::default());
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
 
-        environment.attach_document(&mut document);
         environment
             .eval(
                 r#"
@@ -371,16 +1066,15 @@ fn comment_nodes_survive_inner_html_round_trip() {
     let runtime = Builder::new_current_thread().enable_all().build().unwrap();
     runtime.block_on(async {
         let html = "<!DOCTYPE html><html><body><div id=\"host\"></div></body></html>";
-        let environment = JsDomEnvironment::new(html).expect("environment");
-        let mut document = HtmlDocument::from_html(
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(HtmlDocument::from_html(
             html,
             DocumentConfig {
                 html_parser_provider: Some(Arc::new(HtmlProvider)),
                 ..Default::default()
             },
-        );
+        ), Rc::clone(&environment));
 
-        environment.attach_document(&mut document);
         environment
             .eval(
                 r#"
@@ -454,11 +1148,11 @@ fn runtime_document_handles_keyboard_and_ime_events() {
         let mut runtime = JsPageRuntime::new(html, &scripts, None)
             .expect("create runtime")
             .expect("runtime available");
-        let mut html_doc = HtmlDocument::from_html(html, DocumentConfig::default());
-        runtime.attach_document(&mut html_doc);
+        let mut runtime_doc = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
         runtime.run_blocking_scripts().expect("execute scripts");
-        let environment = runtime.environment();
-        let mut runtime_doc = RuntimeDocument::new(html_doc, environment);
 
         let field_id = lookup_node_id(&mut runtime_doc, "field").expect("field id");
         runtime_doc.set_focus_to(field_id);
@@ -507,6 +1201,7 @@ fn react_counter_sample_executes() {
         let fetch_request = FetchRequest {
             source: FetchSource::Url(file_url.clone()),
             display_url: file_url.to_string(),
+            user_agent: None,
         };
 
         let net_callback = Arc::new(DummyNetCallback);
@@ -525,8 +1220,10 @@ fn react_counter_sample_executes() {
         )
         .expect("create runtime")
         .expect("runtime with scripts");
-        let mut html_doc = HtmlDocument::from_html(&document.contents, DocumentConfig::default());
-        runtime.attach_document(&mut html_doc);
+        let mut html_doc = RuntimeDocument::attach(
+            HtmlDocument::from_html(&document.contents, DocumentConfig::default()),
+            &mut runtime,
+        );
         let summary = runtime
             .run_blocking_scripts()
             .expect("run blocking scripts")
@@ -582,6 +1279,7 @@ fn react_timer_demo_runs_when_started() {
         let fetch_request = FetchRequest {
             source: FetchSource::Url(file_url.clone()),
             display_url: file_url.to_string(),
+            user_agent: None,
         };
 
         let net_callback = Arc::new(DummyNetCallback);
@@ -601,8 +1299,10 @@ fn react_timer_demo_runs_when_started() {
         .expect("create runtime")
         .expect("runtime with scripts");
 
-        let mut html_doc = HtmlDocument::from_html(&document.contents, DocumentConfig::default());
-        runtime.attach_document(&mut html_doc);
+        let mut html_doc = RuntimeDocument::attach(
+            HtmlDocument::from_html(&document.contents, DocumentConfig::default()),
+            &mut runtime,
+        );
         let summary = runtime
             .run_blocking_scripts()
             .expect("execute scripts")
@@ -709,6 +1409,7 @@ fn react_timer_demo_resolve_after_start_does_not_panic() {
         let fetch_request = FetchRequest {
             source: FetchSource::Url(file_url.clone()),
             display_url: file_url.to_string(),
+            user_agent: None,
         };
 
         let net_callback = Arc::new(DummyNetCallback);
@@ -728,8 +1429,10 @@ fn react_timer_demo_resolve_after_start_does_not_panic() {
         .expect("create runtime")
         .expect("runtime with scripts");
 
-        let mut html_doc = HtmlDocument::from_html(&document.contents, DocumentConfig::default());
-        runtime.attach_document(&mut html_doc);
+        let mut html_doc = RuntimeDocument::attach(
+            HtmlDocument::from_html(&document.contents, DocumentConfig::default()),
+            &mut runtime,
+        );
         let summary = runtime
             .run_blocking_scripts()
             .expect("execute scripts")
@@ -803,3 +1506,2257 @@ fn react_timer_demo_resolve_after_start_does_not_panic() {
         assert!(after_stop_text.starts_with("Elapsed:"));
     });
 }
+
+#[test]
+fn shutdown_cancels_timers_jobs_and_listeners() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"
+            <!DOCTYPE html>
+            <html><body><div id="root">idle</div></body></html>
+        "#;
+
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        environment
+            .eval(
+                r#"
+                    const root = document.getElementById('root');
+                    setInterval(() => { root.textContent = 'interval'; }, 1);
+                    setTimeout(() => { root.textContent = 'timeout'; }, 5);
+                    Promise.resolve().then(() => { root.textContent = 'job'; });
+                    root.addEventListener('click', () => { root.textContent = 'click'; });
+                "#,
+                "teardown.js",
+            )
+            .expect("evaluate script");
+        assert!(environment.is_listening("click"));
+
+        let check = environment.shutdown();
+        assert!(environment.is_closed());
+        assert!(!environment.is_listening("click"));
+
+        sleep(Duration::from_millis(10)).await;
+        assert!(!environment.pump().expect("pump after shutdown"));
+        assert_eq!(check.outstanding(), 0);
+
+        let root_id = lookup_node_id(&mut document, "root").expect("root id");
+        let text = document
+            .get_node(root_id)
+            .expect("root node")
+            .text_content();
+        assert_eq!(text, "idle");
+    });
+}
+
+#[test]
+fn timers_that_outlive_their_document_get_detached_errors() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body><div id="root">idle</div></body></html>"#;
+
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        environment
+            .eval(
+                r#"
+                    const root = document.getElementById('root');
+                    globalThis.late = 'pending';
+                    setTimeout(() => {
+                        try {
+                            root.textContent = 'late';
+                            globalThis.late = 'mutated';
+                        } catch (err) {
+                            globalThis.late = String(err);
+                        }
+                    }, 5);
+                "#,
+                "late-timer.js",
+            )
+            .expect("evaluate script");
+
+        // The page is navigated away from while its timer is pending.
+        drop(document);
+
+        sleep(Duration::from_millis(20)).await;
+        environment.pump().expect("pump after detach");
+        let late: String = environment
+            .eval_with("globalThis.late", "late.js")
+            .expect("read result");
+        assert_eq!(late, "document is detached");
+
+        let lookup = environment.eval("document.getElementById('root');", "lookup.js");
+        assert!(lookup.is_err(), "lookups throw once detached");
+
+        // Reattaching a detached page is refused, so it cannot reach a later document.
+        let _next = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        let write = environment.eval("document.body.setAttribute('data-late', '1');", "write.js");
+        assert!(write.is_err());
+        assert!(!environment
+            .document_html()
+            .expect("html")
+            .contains("data-late"));
+    });
+}
+
+#[test]
+fn post_message_queues_for_the_host_and_delivers_message_events() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(HtmlDocument::from_html(html, DocumentConfig::default()), Rc::clone(&environment));
+        environment.set_framed(true).expect("mark framed");
+
+        environment
+            .eval(
+                r#"
+                    window.addEventListener('message', (event) => {
+                        const log = document.getElementById('log');
+                        log.textContent = `${event.origin}:${event.data.reply}:${event.source === window.parent}`;
+                    });
+                    window.parent.postMessage({ hello: 'parent' }, 'https://example.com');
+                "#,
+                "frame.js",
+            )
+            .expect("evaluate script");
+
+        let posted = environment.take_posted_messages();
+        assert_eq!(
+            posted,
+            vec![PostedMessage {
+                target: WindowRef::Parent,
+                data: r#"{"hello":"parent"}"#.to_string(),
+                target_origin: "https://example.com".to_string(),
+            }]
+        );
+
+        environment
+            .deliver_message(r#"{"reply":"hi"}"#, "https://example.com", WindowRef::Parent)
+            .expect("deliver message");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("https://example.com:hi:true"), "{html}");
+    });
+}
+
+#[test]
+fn structured_clone_keeps_types_and_transfers_buffers() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment.set_framed(true).expect("mark framed");
+
+        let summary: String = environment
+            .eval_with(
+                r#"
+                    (() => {
+                        const shared = { name: 'shared' };
+                        const source = {
+                            when: new Date(5),
+                            tags: new Set(['a']),
+                            index: new Map([[1, shared]]),
+                            numbers: new Uint16Array([1, 2, 3]),
+                            shared,
+                            nothing: undefined,
+                        };
+                        source.self = source;
+                        const copy = structuredClone(source);
+                        const buffer = new Uint8Array([7, 8]).buffer;
+                        const moved = structuredClone(buffer, { transfer: [buffer] });
+                        let error = '';
+                        try {
+                            structuredClone({ callback: () => 1 });
+                        } catch (err) {
+                            error = err.name;
+                        }
+                        return [
+                            copy !== source && copy.self === copy,
+                            copy.when instanceof Date && copy.when.getTime(),
+                            copy.tags.has('a'),
+                            copy.index.get(1) === copy.shared,
+                            copy.numbers instanceof Uint16Array && copy.numbers[2],
+                            'nothing' in copy,
+                            buffer.byteLength,
+                            new Uint8Array(moved)[1],
+                            error,
+                        ].join(',');
+                    })()
+                "#,
+                "clone.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(summary, "true,5,true,true,3,true,0,8,DataCloneError");
+
+        environment
+            .eval(
+                r#"
+                    const log = (entry) => {
+                        document.getElementById('log').textContent += entry + ';';
+                    };
+                    window.addEventListener('message', (event) => {
+                        log(`window:${event.data instanceof Map && event.data.get('k')}`);
+                    });
+                    const channel = new MessageChannel();
+                    channel.port2.onmessage = (event) => log(`port:${event.data.count}`);
+                    const payload = { count: 1 };
+                    channel.port1.postMessage(payload);
+                    payload.count = 2;
+                    window.parent.postMessage(new Map([['k', 'v']]), '*');
+                "#,
+                "messages.js",
+            )
+            .expect("evaluate script");
+        environment.pump().expect("pump");
+
+        let posted = environment.take_posted_messages();
+        assert_eq!(posted.len(), 1);
+        assert!(
+            posted[0].data.starts_with(r#"{"$frontierClone":"#),
+            "{}",
+            posted[0].data
+        );
+        environment
+            .deliver_message(&posted[0].data, "https://example.com", WindowRef::Parent)
+            .expect("deliver message");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("port:1;"), "{html}");
+        assert!(html.contains("window:v;"), "{html}");
+    });
+}
+
+#[test]
+fn fetch_sends_form_data_files_as_multipart() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|line| {
+                            let line = line.to_ascii_lowercase();
+                            let value = line.strip_prefix("content-length:")?;
+                            value.trim().parse::<usize>().ok()
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                if read == 0 {
+                    break;
+                }
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+
+        let html = r#"<!DOCTYPE html><html><body>
+            <form id="form">
+                <input name="title" value="Hello"><input type="checkbox" name="skip">
+            </form>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const log = document.getElementById('log');
+                    const data = new FormData(document.getElementById('form'));
+                    const file = new File(['abc', new Uint8Array([100, 101])], 'notes.txt', {
+                        type: 'text/plain',
+                    });
+                    data.append('upload', file);
+                    log.textContent = `${file.size}:${data.get('upload').name}:${data.has('skip')}`;
+                    file.slice(1, 4).text().then((text) => {
+                        log.textContent += `:${text}`;
+                    });
+                    fetch('/upload', { method: 'POST', body: data })
+                        .then((response) => response.text())
+                        .then((text) => {
+                            log.textContent += `:${text}`;
+                        });
+                "#,
+                "upload.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains(":ok") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(html.contains("5:notes.txt:false:bcd:ok"), "{html}");
+        let request = server.await.unwrap();
+        assert!(
+            request.contains("multipart/form-data; boundary=----FrontierFormBoundary"),
+            "{request}"
+        );
+        assert!(
+            request.contains("name=\"title\"\r\n\r\nHello\r\n"),
+            "{request}"
+        );
+        assert!(
+            request.contains("filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\nabcde\r\n"),
+            "{request}"
+        );
+    });
+}
+
+#[test]
+fn fetch_keeps_binary_response_bodies_intact() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let _ = socket.read(&mut buffer).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            socket.write_all(&[0xff, 0x00, 0x80, 0x41]).await.unwrap();
+        });
+
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    fetch('/image.bin')
+                        .then((response) => response.arrayBuffer())
+                        .then((buffer) => {
+                            const bytes = Array.from(new Uint8Array(buffer));
+                            document.getElementById('log').textContent = `bytes:${bytes.join(',')}`;
+                        });
+                "#,
+                "binary.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains("bytes:") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(html.contains("bytes:255,0,128,65"), "{html}");
+    });
+}
+
+#[test]
+fn fetch_and_inserted_scripts_follow_site_settings() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let base = Url::parse("https://a.example/").unwrap();
+        let mut permissions = Permissions::default();
+        permissions.toggle_blocked("https://a.example", ContentSetting::ThirdParty);
+
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base.clone())).expect("environment"));
+        environment.set_content_rules(permissions.content_rules(&base));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const log = document.getElementById('log');
+                    fetch('https://tracker.example/pixel').catch((error) => {
+                        log.textContent += `fetch:${error.message};`;
+                    });
+                    const script = document.createElement('script');
+                    script.setAttribute('src', 'https://tracker.example/track.js');
+                    script.addEventListener('error', () => {
+                        log.textContent += 'script:error;';
+                    });
+                    document.body.appendChild(script);
+                "#,
+                "blocked.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains("fetch:") && html.contains("script:") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            html.contains("blocked by site settings (third-party)"),
+            "{html}"
+        );
+        assert!(html.contains("script:error;"), "{html}");
+    });
+}
+
+#[test]
+fn fetch_and_inserted_stylesheets_follow_filter_lists() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let lists = tempfile::tempdir().unwrap();
+        std::fs::write(lists.path().join("ads.txt"), "||ads.example^\n").unwrap();
+        let blocklist = Arc::new(Blocklist::load_dir(lists.path()).unwrap());
+
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let base = Url::parse("https://a.example/").unwrap();
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        environment.set_blocklist(blocklist);
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const log = document.getElementById('log');
+                    fetch('https://ads.example/banner.json').catch((error) => {
+                        log.textContent += `fetch:${error.message};`;
+                    });
+                    const link = document.createElement('link');
+                    link.setAttribute('rel', 'stylesheet');
+                    link.setAttribute('href', 'https://ads.example/banner.css');
+                    link.addEventListener('error', () => {
+                        log.textContent += 'style:error;';
+                    });
+                    document.head.appendChild(link);
+                "#,
+                "ads.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains("fetch:") && html.contains("style:") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(html.contains("blocked by filter ||ads.example^"), "{html}");
+        assert!(html.contains("style:error;"), "{html}");
+    });
+}
+
+#[test]
+fn page_script_cannot_reach_fetch_hooks_or_skip_cors() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}/data", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let _ = socket.read(&mut buffer).await.unwrap();
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 6\r\nconnection: close\r\n\r\nsecret",
+                )
+                .await
+                .unwrap();
+        });
+
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let base = Url::parse("https://a.example/").unwrap();
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                &format!(
+                    r#"
+                        const log = document.getElementById('log');
+                        const hooks = ['__frontier_fetch_start', '__frontier_fetch_script',
+                            '__frontier_fetch_style', '__frontier_fetch_abort'];
+                        log.textContent = hooks.map((name) => typeof globalThis[name]).join(',');
+                        frontier.__resolveFetch = () => {{
+                            log.textContent += ';hijacked';
+                        }};
+                        fetch('{target}').then(
+                            (response) => response.text().then((text) => {{
+                                log.textContent += `;read:${{text}}`;
+                            }}),
+                            (error) => {{
+                                log.textContent += `;cors:${{error.message}}`;
+                            }},
+                        );
+                    "#
+                ),
+                "hooks.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains(";cors:") || html.contains(";read:") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(
+            html.contains("undefined,undefined,undefined,undefined"),
+            "{html}"
+        );
+        assert!(html.contains("blocked by CORS policy"), "{html}");
+        assert!(
+            !html.contains("hijacked") && !html.contains(";read:"),
+            "{html}"
+        );
+    });
+}
+
+#[test]
+fn fetch_checks_cors_after_a_cross_origin_redirect() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let page = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let other = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}/", page.local_addr().unwrap())).unwrap();
+        let target = format!("http://{}/data", other.local_addr().unwrap());
+        let respond = |listener: tokio::net::TcpListener, response: String| async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let _ = socket.read(&mut buffer).await.unwrap();
+            socket.write_all(response.as_bytes()).await.unwrap();
+        };
+        tokio::spawn(respond(
+            page,
+            format!(
+                "HTTP/1.1 302 Found\r\nlocation: {target}\r\n\
+                 content-length: 0\r\nconnection: close\r\n\r\n"
+            ),
+        ));
+        tokio::spawn(respond(
+            other,
+            "HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\nleak".to_string(),
+        ));
+
+        let html = r#"<!DOCTYPE html><html><body><p id="log"></p></body></html>"#;
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const log = document.getElementById('log');
+                    fetch('/go').then(
+                        (response) => response.text().then((text) => {
+                            log.textContent = `read:${text}`;
+                        }),
+                        (error) => {
+                            log.textContent = `failed:${error.message}`;
+                        },
+                    );
+                "#,
+                "redirect.js",
+            )
+            .expect("evaluate script");
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains("read:") || html.contains("failed:") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(html.contains("failed:"), "{html}");
+        assert!(html.contains("blocked by CORS policy"), "{html}");
+    });
+}
+
+#[test]
+fn dropped_files_reach_drop_listeners_as_files() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <div id="zone">drop here</div>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const zone = document.getElementById('zone');
+                    const log = document.getElementById('log');
+                    let accept = true;
+                    zone.addEventListener('dragenter', (event) => {
+                        const transfer = event.dataTransfer;
+                        log.textContent = `${transfer.types}:${transfer.files.length}`;
+                    });
+                    zone.addEventListener('dragover', (event) => {
+                        if (accept) {
+                            event.preventDefault();
+                        }
+                    });
+                    document.body.addEventListener('drop', (event) => {
+                        event.preventDefault();
+                        const file = event.dataTransfer.files[0];
+                        log.textContent += `:${file instanceof File}:${file.name}:${file.type}`;
+                        log.textContent += `:${file.size}:${event.clientX}`;
+                        file.text().then((text) => {
+                            log.textContent += `:${text}`;
+                            accept = false;
+                        });
+                    });
+                "#,
+                "drop.js",
+            )
+            .expect("evaluate script");
+
+        let zone_id = lookup_node_id(&mut document, "zone").expect("zone id");
+        let chain = document.node_chain(zone_id);
+        let files = [LocalFile {
+            name: String::from("notes.md"),
+            mime: String::from("text/markdown"),
+            last_modified: 0.0,
+            bytes: b"# Notes".to_vec(),
+        }];
+        let taken = environment
+            .drop_files(&files, zone_id, &chain, (12.0, 34.0))
+            .expect("drop files");
+        assert!(taken, "page cancelled the drop");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(
+            html.contains("Files:0:true:notes.md:text/markdown:7:12:# Notes"),
+            "{html}"
+        );
+
+        let taken = environment
+            .drop_files(&files, zone_id, &chain, (12.0, 34.0))
+            .expect("drop files");
+        assert!(!taken, "drop only fires once dragover is cancelled");
+    });
+}
+
+#[test]
+fn file_inputs_expose_selected_files_to_script_and_form_data() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <form id="form"><input id="upload" type="file" name="upload" multiple></form>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    globalThis.form = document.getElementById('form');
+                    globalThis.upload = document.getElementById('upload');
+                    upload.addEventListener('change', () => {
+                        const names = Array.from(upload.files).map((file) => file.name);
+                        document.getElementById('log').textContent = names.join(',');
+                    });
+                "#,
+                "upload.js",
+            )
+            .expect("evaluate script");
+
+        let empty: String = environment
+            .eval_with(
+                "`${upload.files.length}:${new FormData(form).get('upload').size}`",
+                "empty.js",
+            )
+            .expect("read empty selection");
+        assert_eq!(empty, "0:0");
+        let blocked: String = environment
+            .eval_with(
+                "try { upload.showPicker(); 'opened' } catch (err) { err.name }",
+                "picker.js",
+            )
+            .expect("call showPicker");
+        assert_eq!(blocked, "NotAllowedError");
+        assert!(environment.take_file_picker_requests().is_empty());
+
+        let upload_id = lookup_node_id(&mut document, "upload").expect("upload id");
+        let files = [
+            LocalFile {
+                name: String::from("a.txt"),
+                mime: String::from("text/plain"),
+                last_modified: 1.0,
+                bytes: b"first".to_vec(),
+            },
+            LocalFile {
+                name: String::from("b.png"),
+                mime: String::from("image/png"),
+                last_modified: 2.0,
+                bytes: vec![0x89, b'P', b'N', b'G'],
+            },
+        ];
+        assert!(environment
+            .set_input_files(upload_id, &files)
+            .expect("set files"));
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("a.txt,b.png"), "{html}");
+        let entries: String = environment
+            .eval_with(
+                "new FormData(form).getAll('upload').map((f) => `${f.name}:${f.size}`).join()",
+                "entries.js",
+            )
+            .expect("read form data");
+        assert_eq!(entries, "a.txt:5,b.png:4");
+
+        let click = DomEvent::new(
+            upload_id,
+            DomEventData::Click(BlitzMouseButtonEvent {
+                x: 0.0,
+                y: 0.0,
+                button: MouseEventButton::Main,
+                buttons: MouseEventButtons::Primary,
+                mods: Modifiers::default(),
+            }),
+        );
+        environment
+            .dispatch_dom_event(&click, &document.node_chain(upload_id))
+            .expect("dispatch click");
+        environment
+            .eval("upload.click();", "click.js")
+            .expect("click input");
+        assert_eq!(environment.take_file_picker_requests(), vec![upload_id]);
+    });
+}
+
+#[test]
+fn select_dropdown_choices_reach_script_and_form_data() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <form id="form"><select id="size" name="size">
+                <option value="s">Small</option>
+                <option value="m" selected>Medium</option>
+                <option value="l">Large</option>
+            </select></form>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    globalThis.form = document.getElementById('form');
+                    globalThis.size = document.getElementById('size');
+                    size.addEventListener('change', () => {
+                        const submitted = new FormData(form).get('size');
+                        document.getElementById('log').textContent =
+                            `${size.value}:${size.selectedIndex}:${submitted}`;
+                    });
+                "#,
+                "select.js",
+            )
+            .expect("evaluate script");
+        let initial: String = environment
+            .eval_with("`${size.value}:${size.options.length}`", "initial.js")
+            .expect("read select");
+        assert_eq!(initial, "m:3");
+
+        let size_id = lookup_node_id(&mut document, "size").expect("select id");
+        let mut widget = FormWidget::open(&document, size_id).expect("dropdown");
+        assert_eq!(widget.key(WidgetKey::Down), WidgetOutcome::Redraw);
+        let WidgetOutcome::Commit(commit) = widget.key(WidgetKey::Enter) else {
+            panic!("expected Enter to choose the highlighted option");
+        };
+        assert!(form_widgets::apply(&mut document, &commit));
+        assert!(environment
+            .control_changed(commit.control())
+            .expect("fire change"));
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("l:2:l"), "{html}");
+
+        let scripted: String = environment
+            .eval_with(
+                "size.value = 's'; `${size.selectedIndex}:${size.options[2].selected}`",
+                "scripted.js",
+            )
+            .expect("set select value");
+        assert_eq!(scripted, "0:false");
+        let widget = FormWidget::open(&document, size_id).expect("dropdown");
+        assert!(widget.html().contains(
+            r#"class="widget-option selected highlighted" role="option" aria-selected="true""#
+        ));
+    });
+}
+
+#[test]
+fn checkboxes_radios_and_ranges_reflect_user_changes_to_script() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <form id="form">
+                <label id="agree-label">
+                    <input id="agree" type="checkbox" name="agree"> Agree
+                </label>
+                <input id="small" type="radio" name="size" value="s" checked>
+                <input id="large" type="radio" name="size" value="l">
+                <input id="volume" type="range" name="volume" min="0" max="10" step="2">
+            </form>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    globalThis.form = document.getElementById('form');
+                    globalThis.agree = document.getElementById('agree');
+                    agree.indeterminate = true;
+                    globalThis.seen = [];
+                    form.addEventListener('input', (event) => {
+                        seen.push(`input:${event.target.id}`);
+                    });
+                    form.addEventListener('change', (event) => {
+                        seen.push(`change:${event.target.id}`);
+                        const data = new FormData(form);
+                        document.getElementById('log').textContent = [
+                            agree.checked,
+                            agree.indeterminate,
+                            data.get('size'),
+                            data.get('volume'),
+                            seen.join(','),
+                        ].join('|');
+                    });
+                "#,
+                "controls.js",
+            )
+            .expect("evaluate script");
+        let initial: String = environment
+            .eval_with(
+                "`${agree.checked}:${agree.value}:${document.getElementById('volume').value}`",
+                "initial.js",
+            )
+            .expect("read controls");
+        assert_eq!(initial, "false:on:5");
+
+        let label = lookup_node_id(&mut document, "agree-label").expect("label id");
+        let commit = form_widgets::activate(&document, label).expect("label toggles its box");
+        assert!(form_widgets::apply(&mut document, &commit));
+        assert!(environment
+            .control_changed(commit.control())
+            .expect("fire change"));
+        let html = environment.document_html().expect("serialize dom");
+        assert!(
+            html.contains("true|false|s|5|input:agree,change:agree"),
+            "{html}"
+        );
+
+        let small = lookup_node_id(&mut document, "small").expect("radio id");
+        let commit = form_widgets::step(&document, small, 1).expect("next radio");
+        assert!(form_widgets::apply(&mut document, &commit));
+        environment
+            .control_changed(commit.control())
+            .expect("fire change");
+        let volume = lookup_node_id(&mut document, "volume").expect("range id");
+        let commit = form_widgets::step(&document, volume, 1).expect("step range");
+        assert!(form_widgets::apply(&mut document, &commit));
+        environment.control_input(volume).expect("fire input");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("true|false|l|5|"), "{html}");
+        assert!(!html.contains("change:volume"), "{html}");
+
+        let scripted: String = environment
+            .eval_with(
+                r#"
+                    document.getElementById('small').checked = true;
+                    const data = new FormData(form);
+                    const large = document.getElementById('large');
+                    `${large.checked}:${data.getAll('size')}:${data.get('volume')}`
+                "#,
+                "scripted.js",
+            )
+            .expect("check radio from script");
+        assert_eq!(scripted, "false:s:6");
+    });
+}
+
+#[test]
+fn scroll_into_view_queues_validated_requests_for_the_host() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <section id="target">Target</section>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        let rejected: String = environment
+            .eval_with(
+                r#"
+                    const target = document.getElementById('target');
+                    target.scrollIntoView();
+                    target.scrollIntoView(false);
+                    target.scrollIntoView({ behavior: 'smooth', block: 'center' });
+                    try {
+                        target.scrollIntoView({ block: 'middle' });
+                        'accepted';
+                    } catch (error) {
+                        error.name;
+                    }
+                "#,
+                "scroll.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(rejected, "TypeError");
+
+        let node = lookup_node_id(&mut document, "target").expect("target id");
+        let request = |smooth, block| ScrollIntoView {
+            node,
+            smooth,
+            block,
+            inline: ScrollAlign::Nearest,
+        };
+        assert_eq!(
+            environment.take_scroll_requests(),
+            vec![
+                request(false, ScrollAlign::Start),
+                request(false, ScrollAlign::End),
+                request(true, ScrollAlign::Center),
+            ]
+        );
+        assert!(environment.take_scroll_requests().is_empty());
+    });
+}
+
+#[test]
+fn trackpad_gestures_bubble_to_script_and_can_be_cancelled() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <div id="map">map</div>
+            <p id="log"></p>
+        </body></html>"#;
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        environment
+            .eval(
+                r#"
+                    const log = document.getElementById('log');
+                    for (const type of ['gesturestart', 'gesturechange', 'gestureend']) {
+                        document.body.addEventListener(type, (event) => {
+                            log.textContent += `${event.type}:${event.scale}:${event.clientX};`;
+                        });
+                    }
+                    document.getElementById('map').addEventListener('gesturechange', (event) => {
+                        event.preventDefault();
+                    });
+                    document.body.addEventListener('swipe', (event) => {
+                        log.textContent += `swipe:${event.direction};`;
+                    });
+                "#,
+                "gestures.js",
+            )
+            .expect("evaluate script");
+
+        let map = lookup_node_id(&mut document, "map").expect("map id");
+        let chain = document.node_chain(map);
+        let pinch = |phase, scale| GestureEvent::Pinch { phase, scale };
+        let fire = |event| {
+            environment
+                .dispatch_gesture(event, map, &chain, (5.0, 6.0))
+                .expect("dispatch gesture")
+        };
+        assert!(!fire(pinch(PinchPhase::Start, 1.0)));
+        assert!(
+            fire(pinch(PinchPhase::Change, 1.5)),
+            "map cancelled the zoom"
+        );
+        assert!(!fire(pinch(PinchPhase::End, 1.5)));
+        assert!(!fire(GestureEvent::Swipe(SwipeDirection::Back)));
+        let html = environment.document_html().expect("serialize dom");
+        assert!(
+            html.contains("gesturestart:1:5;gesturechange:1.5:5;gestureend:1.5:5;swipe:right;"),
+            "{html}"
+        );
+    });
+}
+
+#[test]
+fn window_open_requires_a_user_gesture() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <button id="open">Open</button><p id="log"></p>
+        </body></html>"#;
+        let base = Url::parse("https://example.com/app/").unwrap();
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base.clone())).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(HtmlDocument::from_html(html, DocumentConfig::default()), Rc::clone(&environment));
+
+        environment
+            .eval(
+                "document.getElementById('log').textContent = `blocked:${window.open('popup.html') === null}`;",
+                "blocked.js",
+            )
+            .expect("evaluate script");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("blocked:true"), "{html}");
+        assert_eq!(
+            environment.take_window_requests(),
+            vec![WindowRequest::Open {
+                id: 1,
+                url: base.join("popup.html").unwrap(),
+                target: "_blank".to_string(),
+                user_activated: false,
+            }]
+        );
+
+        let button_id = lookup_node_id(&mut document, "open").expect("button id");
+        let chain = document.node_chain(button_id);
+        let click = DomEvent::new(
+            button_id,
+            DomEventData::Click(BlitzMouseButtonEvent {
+                x: 0.0,
+                y: 0.0,
+                button: MouseEventButton::Main,
+                buttons: MouseEventButtons::Primary,
+                mods: Modifiers::default(),
+            }),
+        );
+        environment
+            .dispatch_dom_event(&click, &chain)
+            .expect("dispatch click");
+
+        environment
+            .eval(
+                r#"
+                    globalThis.popup = window.open('https://other.example/', 'preview');
+                    globalThis.second = window.open('again.html');
+                    popup.postMessage({ hello: 'popup' }, '*');
+                    popup.close();
+                    document.getElementById('log').textContent =
+                        `popup:${typeof popup.postMessage}:${popup.closed}:${second === null}`;
+                "#,
+                "open.js",
+            )
+            .expect("evaluate script");
+        let html = environment.document_html().expect("serialize dom");
+        assert!(html.contains("popup:function:true:true"), "{html}");
+        let requests = environment.take_window_requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(
+            requests[0],
+            WindowRequest::Open {
+                id: 2,
+                url: Url::parse("https://other.example/").unwrap(),
+                target: "preview".to_string(),
+                user_activated: true,
+            }
+        );
+        assert!(matches!(
+            requests[1],
+            WindowRequest::Open {
+                user_activated: false,
+                ..
+            }
+        ));
+        assert_eq!(requests[2], WindowRequest::Close { id: 2 });
+        assert_eq!(
+            environment.take_posted_messages(),
+            vec![PostedMessage {
+                target: WindowRef::Popup(2),
+                data: r#"{"hello":"popup"}"#.to_string(),
+                target_origin: "*".to_string(),
+            }]
+        );
+    });
+}
+
+#[test]
+fn lifecycle_events_reach_page_listeners() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                document.addEventListener('DOMContentLoaded', () => log('ready'));
+                window.onload = () => log('load');
+                window.addEventListener('beforeunload', (event) => event.preventDefault());
+                document.addEventListener('visibilitychange', () => log(document.visibilityState));
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
+        runtime.dispatch_lifecycle(LifecycleEvent::VisibilityChange { visible: false });
+        assert!(runtime.dispatch_lifecycle(LifecycleEvent::BeforeUnload));
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains("ready;load;hidden;"),
+            "unexpected log: {html}"
+        );
+    });
+}
+
+#[test]
+fn scripts_run_in_html_order_with_ready_state_transitions() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <script>
+                window.order = ['inline1:' + document.readyState];
+                document.addEventListener('readystatechange', () => order.push('state:' + document.readyState));
+                document.addEventListener('DOMContentLoaded', () => order.push('dcl'));
+            </script>
+            <script defer src="data:text/javascript,order.push('defer:'+document.readyState)"></script>
+            <script async src="data:text/javascript,order.push('async')"></script>
+            <script>
+                const dynamic = document.createElement('script');
+                dynamic.textContent = "order.push('dynamic:' + (document.currentScript === dynamic))";
+                document.body.appendChild(dynamic);
+                order.push('inline2');
+                document.body.appendChild(dynamic);
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(HtmlDocument::from_html(html, DocumentConfig::default()), &mut runtime);
+        let summary = runtime
+            .run_blocking_scripts()
+            .expect("run scripts")
+            .expect("scripts executed");
+        assert_eq!(summary.executed_scripts, 4);
+
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
+        let order: String = runtime
+            .environment()
+            .eval_with("order.join(',')", "order.js")
+            .expect("read order");
+        assert_eq!(
+            order,
+            "inline1:loading,dynamic:true,inline2,state:interactive,defer:interactive,dcl,async,state:complete"
+        );
+    });
+}
+
+#[test]
+fn inserted_inline_scripts_follow_csp_and_markup_scripts_never_run() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><head>
+            <meta http-equiv="Content-Security-Policy" content="script-src 'nonce-ok'">
+        </head><body>
+            <script nonce="ok">
+                window.order = [];
+                const plain = document.createElement('script');
+                plain.textContent = "order.push('plain')";
+                document.body.appendChild(plain);
+                const trusted = document.createElement('script');
+                trusted.setAttribute('nonce', 'ok');
+                trusted.textContent = "order.push('nonce')";
+                document.body.appendChild(trusted);
+                const holder = document.createElement('div');
+                holder.innerHTML = "<script nonce=ok>order.push('markup')<\/script>";
+                document.body.appendChild(holder);
+                document.body.appendChild(holder.firstChild);
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        let order: String = runtime
+            .environment()
+            .eval_with("order.join(',')", "order.js")
+            .expect("read order");
+        assert_eq!(order, "nonce");
+    });
+}
+
+#[test]
+fn eval_follows_unsafe_eval_from_headers_and_meta_tags() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body><script>
+            const outcome = (compile) => {
+                try {
+                    return String(compile());
+                } catch (err) {
+                    return err instanceof EvalError ? 'refused' : String(err);
+                }
+            };
+            window.results = [
+                outcome(() => eval('1 + 1')),
+                outcome(() => new Function('return 3')()),
+                outcome(() => (function () {}).constructor('return 4')()),
+                outcome(() => typeof Object.getPrototypeOf(async function () {}).constructor('')),
+                String((() => 5) instanceof Function),
+            ].join(',');
+        </script></body></html>"#;
+        let results = |csp_headers: Vec<String>, html: &str| {
+            let document = FetchedDocument {
+                base_url: "https://site.example/".into(),
+                contents: html.to_string(),
+                file_path: None,
+                display_url: "https://site.example/".into(),
+                scripts: processor::collect_scripts(html).expect("collect scripts"),
+                csp_headers,
+                response: None,
+            };
+            let mut runtime = JsPageRuntime::for_document(&document)
+                .expect("create runtime")
+                .expect("runtime available for scripts");
+            let _dom = RuntimeDocument::attach(HtmlDocument::from_html(html, DocumentConfig::default()), &mut runtime);
+            runtime.run_blocking_scripts().expect("run scripts");
+            runtime
+                .environment()
+                .eval_with::<String>("results", "results.js")
+                .expect("read results")
+        };
+
+        assert_eq!(results(Vec::new(), html), "2,3,4,function,true");
+        assert_eq!(
+            results(vec!["script-src 'unsafe-inline'".into()], html),
+            "refused,refused,refused,refused,true"
+        );
+        assert_eq!(
+            results(vec!["script-src 'unsafe-inline' 'unsafe-eval'".into()], html),
+            "2,3,4,function,true"
+        );
+        let meta = html.replace(
+            "<body>",
+            r#"<head><meta http-equiv="Content-Security-Policy" content="default-src 'self' 'unsafe-inline'"></head><body>"#,
+        );
+        assert_eq!(
+            results(Vec::new(), &meta),
+            "refused,refused,refused,refused,true"
+        );
+    });
+}
+
+#[test]
+fn resize_updates_viewport_and_fires_resize_handlers() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                log(`${innerWidth}x${innerHeight}@${devicePixelRatio}`);
+                window.addEventListener('resize', () => log(`listener@${devicePixelRatio}`));
+                window.onresize = () =>
+                    log(`${window.innerWidth}x${window.innerHeight}:${document.documentElement.clientWidth}`);
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(HtmlDocument::from_html(html, DocumentConfig::default()), &mut runtime);
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        runtime.set_viewport(ViewportMetrics::new(375.0, 667.0, 2.0));
+        // Neither an unchanged size nor a DPR change alone fires again.
+        runtime.set_viewport(ViewportMetrics::new(375.0, 667.0, 3.0));
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains("800x600@1;listener@2;375x667:375;"),
+            "unexpected log: {html}"
+        );
+    });
+}
+
+#[test]
+fn intersection_observers_follow_layout_and_scroll() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><head><style>\
+                    body { margin: 0 } div { height: 100px } #spacer { height: 1000px }\
+                    </style></head><body><div id=\"spacer\"></div><div id=\"target\"></div>\
+                    <div id=\"hidden\" style=\"display: none\"></div>\
+                    <div style=\"height: 500px\"></div></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        document.set_viewport(Viewport::new(
+            800,
+            600,
+            1.0,
+            blitz_traits::shell::ColorScheme::Light,
+        ));
+        document.resolve(0.0);
+
+        let setup: String = environment
+            .eval_with(
+                r#"
+                    globalThis.entries = [];
+                    const record = (name) => (list) => {
+                        for (const entry of list) {
+                            const { target, isIntersecting } = entry;
+                            const ratio = entry.intersectionRatio.toFixed(2);
+                            entries.push(`${name}:${target.id}:${isIntersecting}:${ratio}`);
+                        }
+                    };
+                    const target = document.getElementById('target');
+                    const viewport = new IntersectionObserver(record('viewport'), {
+                        threshold: [1, 0, 0.5],
+                    });
+                    viewport.observe(target);
+                    viewport.observe(document.getElementById('hidden'));
+                    const margin = new IntersectionObserver(record('margin'), {
+                        rootMargin: '0px 0px 450px',
+                    });
+                    margin.observe(target);
+                    const errorName = (f) => {
+                        try {
+                            f();
+                        } catch (err) {
+                            return err.name;
+                        }
+                        return 'none';
+                    };
+                    [
+                        viewport.thresholds.join(','),
+                        margin.rootMargin,
+                        errorName(() => new IntersectionObserver(() => {}, { threshold: 2 })),
+                        errorName(() => new IntersectionObserver(() => {}, { rootMargin: '1em' })),
+                        errorName(() => viewport.observe(document)),
+                    ].join('|')
+                "#,
+                "intersection.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            setup,
+            "0,0.5,1|0px 0px 450px 0px|RangeError|SyntaxError|TypeError"
+        );
+        assert!(environment.wants_intersection_update());
+
+        // Every target reports its first state; half the target lies inside the grown root.
+        // Scrolling it fully into the grown root later crosses none of that observer's
+        // thresholds.
+        assert!(environment.update_intersections().expect("first update"));
+        assert!(!environment.wants_intersection_update());
+        // Nothing crossed a threshold since.
+        assert!(!environment
+            .update_intersections()
+            .expect("unchanged update"));
+
+        let mut scroll = document.viewport_scroll();
+        scroll.y = 450.0;
+        document.set_viewport_scroll(scroll);
+        assert!(environment.update_intersections().expect("scrolled update"));
+
+        let log: String = environment
+            .eval_with("entries.join(' ')", "entries.js")
+            .expect("read entries");
+        assert_eq!(
+            log,
+            "viewport:target:false:0.00 viewport:hidden:false:0.00 margin:target:true:0.50 \
+             viewport:target:true:0.50"
+        );
+    });
+}
+
+#[test]
+fn cssom_edits_and_adopted_sheets_reach_the_style_engine() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><head><style id=\"base\">p { color: red }</style>\
+                    <link rel=\"stylesheet\" href=\"site.css\"></head>\
+                    <body><p id=\"text\">hi</p><div id=\"card\"></div></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const style = document.getElementById('base');
+                    const sheet = style.sheet;
+                    const errorName = (f) => {
+                        try {
+                            f();
+                        } catch (err) {
+                            return err.name;
+                        }
+                        return 'none';
+                    };
+                    const media = '@media (min-width: 1px) { p { margin: 0 } }';
+                    const index = sheet.insertRule(media, 1);
+                    sheet.cssRules[0].style.color = 'blue';
+                    sheet.insertRule('.later { padding: 1px }', sheet.cssRules.length);
+                    sheet.deleteRule(2);
+                    const constructed = new CSSStyleSheet();
+                    constructed.replaceSync('p { font-weight: bold }');
+                    document.adoptedStyleSheets = [constructed];
+                    const root = document.getElementById('card').attachShadow({ mode: 'open' });
+                    root.adoptedStyleSheets.push(constructed);
+                    constructed.insertRule('em { color: green }');
+                    [
+                        document.styleSheets.length,
+                        document.styleSheets[0] === sheet,
+                        document.styleSheets[1].href,
+                        errorName(() => document.styleSheets[1].cssRules),
+                        index,
+                        sheet.cssRules.length,
+                        sheet.cssRules[1].media.mediaText,
+                        sheet.cssRules[1].cssRules[0].selectorText,
+                        style.textContent,
+                        style.sheet === sheet,
+                        errorName(() => sheet.insertRule('not a rule', 0)),
+                        errorName(() => sheet.insertRule('p {}', 9)),
+                        errorName(() => sheet.replaceSync('')),
+                        errorName(() => {
+                            document.adoptedStyleSheets = [sheet];
+                        }),
+                        constructed.cssRules[0].cssText,
+                        root.adoptedStyleSheets.length,
+                        root.styleSheets.length,
+                        root.childNodes.length,
+                        document.lastChild === document.documentElement,
+                    ].join('|')
+                "#,
+                "cssom.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "2|true|site.css|SecurityError|1|2|(min-width: 1px)|p|p { color: red }|true|\
+             SyntaxError|IndexSizeError|NotAllowedError|NotAllowedError|\
+             em { color: green; }|1|0|0|true"
+        );
+
+        // Stylo sees the edited rules, and the adopted sheets after everything else.
+        let base = lookup_node_id(&mut document, "base").expect("base style");
+        let text_of = |document: &BaseDocument, node_id: usize| {
+            document.get_node(node_id).expect("node").text_content()
+        };
+        assert_eq!(
+            text_of(&document, base),
+            "p { color: blue; }\n@media (min-width: 1px) {\n  p { margin: 0; }\n}"
+        );
+        let adopted = *document.root_node().children.last().expect("adopted style");
+        assert_eq!(
+            text_of(&document, adopted),
+            "em { color: green; }\np { font-weight: bold; }"
+        );
+        let card = lookup_node_id(&mut document, "card").expect("card");
+        let shadow_style = *document
+            .get_node(card)
+            .expect("card node")
+            .children
+            .last()
+            .expect("shadow adopted style");
+        let scoped = text_of(&document, shadow_style);
+        assert!(scoped.contains(&format!("[frontier-shadow-host=\"{card}\"] em")));
+
+        // New text starts a new sheet; an empty list drops the adopted style.
+        let reset: String = environment
+            .eval_with(
+                r#"
+                    const reset = document.getElementById('base');
+                    const old = reset.sheet;
+                    reset.textContent = 'p { color: black }';
+                    document.adoptedStyleSheets = [];
+                    [reset.sheet !== old, reset.sheet.cssRules.length].join('|')
+                "#,
+                "cssom-reset.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(reset, "true|1");
+        assert_eq!(text_of(&document, base), "p { color: black }");
+        assert_ne!(document.root_node().children.last(), Some(&adopted));
+    });
+}
+
+#[test]
+fn styles_and_stylesheet_links_inserted_by_script_apply_while_connected() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let dir = tempfile::tempdir().expect("temp dir");
+        std::fs::write(dir.path().join("site.css"), "p { color: green }").expect("write css");
+        let base = Url::from_directory_path(dir.path()).expect("base url");
+        let html = "<!DOCTYPE html><html><head></head>\
+                    <body><p id=\"text\">hi</p></body></html>";
+        let environment =
+            Rc::new(JsDomEnvironment::new_with_url(html, Some(base)).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+        let text_of = |document: &BaseDocument, node_id: usize| {
+            document.get_node(node_id).expect("node").text_content()
+        };
+
+        // A style filled while detached keeps its text for scripts but renders nothing.
+        let handle: String = environment
+            .eval_with(
+                r#"
+                    const style = document.createElement('style');
+                    style.textContent = 'p { color: red }';
+                    style.appendChild(document.createTextNode(' em { color: blue }'));
+                    window.dynamicStyle = style;
+                    frontier.__handleOf(style)
+                "#,
+                "style-detached.js",
+            )
+            .expect("evaluate script");
+        let style: usize = handle.parse().expect("style handle");
+        assert_eq!(text_of(&document, style), "");
+
+        let attached: String = environment
+            .eval_with(
+                r#"
+                    document.head.appendChild(dynamicStyle);
+                    const link = document.createElement('link');
+                    link.id = 'sheet';
+                    link.setAttribute('rel', 'stylesheet');
+                    link.setAttribute('href', 'site.css');
+                    link.addEventListener('load', () => {
+                        document.getElementById('text').textContent = 'loaded';
+                    });
+                    document.head.appendChild(link);
+                    window.sheetLink = link;
+                    [dynamicStyle.textContent, dynamicStyle.sheet.cssRules.length].join('|')
+                "#,
+                "style-attached.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(attached, "p { color: red } em { color: blue }|2");
+        assert_eq!(
+            text_of(&document, style),
+            "p { color: red } em { color: blue }"
+        );
+
+        let mut html = String::new();
+        for _ in 0..100 {
+            environment.pump().expect("pump");
+            html = environment.document_html().expect("serialize dom");
+            if html.contains("loaded") {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        assert!(html.contains(">loaded<"), "{html}");
+        assert!(!html.contains("color: green"), "{html}");
+        let link = lookup_node_id(&mut document, "sheet").expect("link");
+        let carrier = *document
+            .get_node(link)
+            .expect("link node")
+            .children
+            .first()
+            .expect("linked style");
+        assert_eq!(text_of(&document, carrier), "p { color: green }");
+
+        // Removal empties what the engine sees; scripts still read the text.
+        let removed: String = environment
+            .eval_with(
+                r#"
+                    dynamicStyle.remove();
+                    sheetLink.remove();
+                    [sheetLink.childNodes.length, dynamicStyle.textContent].join('|')
+                "#,
+                "style-removed.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(removed, "0|p { color: red } em { color: blue }");
+        assert_eq!(text_of(&document, style), "");
+        assert_eq!(text_of(&document, carrier), "");
+
+        // Put back, both render again without loading the link a second time.
+        environment
+            .eval(
+                "document.head.append(dynamicStyle, sheetLink);",
+                "style-back.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            text_of(&document, style),
+            "p { color: red } em { color: blue }"
+        );
+        assert_eq!(text_of(&document, carrier), "p { color: green }");
+    });
+}
+
+#[test]
+fn match_media_follows_color_scheme_and_motion_preferences() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                const dark = matchMedia('(prefers-color-scheme: dark)');
+                const calm = matchMedia('screen and (prefers-reduced-motion: reduce)');
+                log(`dark:${dark.matches},calm:${calm.matches}`);
+                log(`bogus:${matchMedia('(min-colour: 3)').matches}`);
+                dark.addEventListener('change', (event) => log(`dark-change:${event.matches}`));
+                calm.onchange = (event) => log(`calm-change:${event.matches}`);
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        runtime.set_media_preferences(MediaPreferences {
+            color_scheme: ColorScheme::Dark,
+            reduced_motion: false,
+        });
+        runtime.set_media_preferences(MediaPreferences {
+            color_scheme: ColorScheme::Dark,
+            reduced_motion: true,
+        });
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains("dark:false,calm:false;bogus:false;dark-change:true;calm-change:true;"),
+            "unexpected log: {html}"
+        );
+    });
+}
+
+#[test]
+fn match_media_follows_viewport_size_and_pixel_ratio() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                const narrow = matchMedia('(max-width: 600px)');
+                const band = matchMedia('(400px <= width < 800px) and (orientation: portrait)');
+                const retina = matchMedia('(min-resolution: 2dppx)');
+                log(`${narrow.matches},${band.matches},${retina.matches}`);
+                const wide = matchMedia('(min-aspect-ratio: 4/3)');
+                log(`${wide.matches},${matchMedia('(width: 40)').matches}`);
+                window.addEventListener('resize', () => log('resize'));
+                narrow.addEventListener('change', (event) => log(`narrow:${event.matches}`));
+                band.onchange = (event) => log(`band:${event.matches}`);
+                retina.addListener((event) => log(`retina:${event.matches}`));
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        runtime.set_viewport(ViewportMetrics::new(500.0, 900.0, 1.0));
+        // A scale factor change alone reaches resolution queries without a resize.
+        runtime.set_viewport(ViewportMetrics::new(500.0, 900.0, 2.0));
+        runtime.set_viewport(ViewportMetrics::new(1024.0, 768.0, 2.0));
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains(
+                "false,false,false;true,false;resize;narrow:true;band:true;retina:true;\
+                 resize;narrow:false;band:false;"
+            ),
+            "unexpected log: {html}"
+        );
+    });
+}
+
+#[test]
+fn canvas_contexts_measure_text_in_their_font() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><canvas id=\"chart\"></canvas><p id=\"p\"></p>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const canvas = document.getElementById('chart');
+                    const ctx = canvas.getContext('2d');
+                    const initial = ctx.font;
+                    ctx.font = 'italic bold 12pt/2 Georgia, serif';
+                    const parsed = ctx.font;
+                    ctx.font = 'not a font';
+                    const narrow = ctx.measureText('iiii');
+                    const wide = ctx.measureText('WWWW');
+                    const empty = ctx.measureText('');
+                    ctx.textAlign = 'center';
+                    ctx.textBaseline = 'top';
+                    const centered = ctx.measureText('WWWW');
+                    [
+                        initial,
+                        ctx.font === parsed ? parsed : ctx.font,
+                        canvas.getContext('2d') === ctx,
+                        ctx.canvas === canvas,
+                        canvas.getContext('webgl'),
+                        document.getElementById('p').getContext('2d'),
+                        wide instanceof TextMetrics,
+                        narrow.width <= wide.width,
+                        empty.width,
+                        empty.fontBoundingBoxAscent > 0,
+                        centered.actualBoundingBoxLeft === wide.width / 2,
+                        centered.fontBoundingBoxAscent,
+                        centered.fontBoundingBoxDescent ===
+                            wide.fontBoundingBoxAscent + wide.fontBoundingBoxDescent,
+                    ].join('|')
+                "#,
+                "canvas-text.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "10px sans-serif|italic bold 16px Georgia, serif|true|true|||\
+             true|true|0|true|true|0|true"
+        );
+    });
+}
+
+#[test]
+fn oversized_canvases_and_image_data_fail_cleanly() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body>\
+                    <canvas id=\"huge\" width=\"20000\" height=\"20000\"></canvas>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const canvas = document.getElementById('huge');
+                    const ctx = canvas.getContext('2d');
+                    const fails = (call) => {
+                        try {
+                            call();
+                            return 'ok';
+                        } catch (err) {
+                            return err.name;
+                        }
+                    };
+                    ctx.fillRect(0, 0, 10, 10);
+                    canvas.width = 1e12;
+                    [
+                        canvas.width,
+                        fails(() => ctx.getImageData(0, 0, 20000, 20000)),
+                        fails(() => new ImageData(40000, 1)),
+                        fails(() => ctx.createImageData(16384, 16385)),
+                        Array.from(ctx.getImageData(0, 0, 1, 1).data).join(','),
+                    ].join('|')
+                "#,
+                "canvas-oversized.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "4294967295|RangeError|RangeError|RangeError|0,0,0,0"
+        );
+    });
+}
+
+#[test]
+fn page_script_cannot_reach_native_hooks() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body>\
+                    <canvas id=\"chart\" width=\"2\" height=\"2\"></canvas></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const hooks = Object.getOwnPropertyNames(globalThis)
+                        .filter((name) => name.startsWith('__frontier_'));
+                    const ctx = document.getElementById('chart').getContext('2d');
+                    const image = ctx.createImageData(1, 1);
+                    image.data.set([255, 0, 0, 255]);
+                    ctx.putImageData(image, 1, 1);
+                    const p = document.createElement('p');
+                    p.textContent = 'still works';
+                    document.body.appendChild(p);
+                    [
+                        hooks.join(',') || 'none',
+                        typeof __frontier_canvas_put_pixels,
+                        Array.from(ctx.getImageData(1, 1, 1, 1).data).join(','),
+                        document.body.lastChild.textContent,
+                    ].join('|')
+                "#,
+                "hooks.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(results, "none|undefined|255,0,0,255|still works");
+    });
+}
+
+#[test]
+fn canvas_contexts_draw_into_a_bitmap_the_page_shows() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body>\
+                    <canvas id=\"chart\" width=\"20\" height=\"10\"></canvas></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const canvas = document.getElementById('chart');
+                    const ctx = canvas.getContext('2d');
+                    const pixel = (x, y) => Array.from(ctx.getImageData(x, y, 1, 1).data).join(',');
+                    ctx.fillStyle = 'red';
+                    const fillStyle = ctx.fillStyle;
+                    ctx.fillRect(0, 0, 10, 10);
+                    ctx.save();
+                    ctx.translate(10, 0);
+                    ctx.globalAlpha = 0.5;
+                    ctx.fillStyle = '#0000ff';
+                    ctx.fillRect(0, 0, 10, 10);
+                    ctx.restore();
+                    ctx.clearRect(0, 0, 2, 2);
+                    const path = new Path2D('M0 0 L10 0 L10 10 Z');
+                    [
+                        canvas.width,
+                        canvas.height,
+                        fillStyle,
+                        ctx.globalAlpha,
+                        pixel(5, 5),
+                        // Half-transparent blue, give or take rounding.
+                        pixel(15, 5)
+                            .split(',')
+                            .map((value, i) => Math.abs(value - [0, 0, 255, 128][i]) <= 2)
+                            .every(Boolean),
+                        pixel(0, 0),
+                        ctx.isPointInPath(path, 8, 2),
+                        ctx.isPointInPath(path, 2, 8),
+                        canvas.toDataURL().startsWith('data:image/png;base64,'),
+                    ].join('|')
+                "#,
+                "canvas-draw.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "20|10|#ff0000|1|255,0,0,255|true|0,0,0,0|true|false|true"
+        );
+
+        // The bitmap is shown through a stylesheet scripts never see.
+        environment.pump().expect("pump");
+        let canvas = lookup_node_id(&mut document, "chart").expect("canvas");
+        let node = document.get_node(canvas).expect("canvas node");
+        assert_eq!(
+            node.attr(LocalName::from("frontier-canvas")),
+            Some(canvas.to_string().as_str())
+        );
+        let carrier = *node.children.first().expect("bitmap style");
+        let css = document.get_node(carrier).expect("style").text_content();
+        assert!(css.contains("data:image/png;base64,"), "{css}");
+        let hidden: String = environment
+            .eval_with(
+                r#"
+                    const shown = document.getElementById('chart');
+                    [
+                        shown.getAttribute('frontier-canvas'),
+                        shown.childNodes.length,
+                        shown.outerHTML,
+                        shown.cloneNode().toDataURL() === shown.toDataURL(),
+                    ].join('|')
+                "#,
+                "canvas-hidden.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            hidden,
+            "|0|<canvas id=\"chart\" width=\"20\" height=\"10\"></canvas>|false"
+        );
+
+        // Resizing clears the bitmap.
+        let cleared: String = environment
+            .eval_with(
+                r#"
+                    const resized = document.getElementById('chart');
+                    resized.width = 4;
+                    const data = resized.getContext('2d').getImageData(1, 1, 1, 1).data;
+                    [resized.width, Array.from(data).join(',')].join('|')
+                "#,
+                "canvas-resize.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(cleared, "4|0,0,0,0");
+    });
+}
+
+#[test]
+fn offscreen_canvases_and_image_bitmaps_carry_pixels() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><canvas id=\"view\" width=\"8\" height=\"8\">\
+                    </canvas><canvas id=\"target\" width=\"8\" height=\"8\"></canvas>\
+                    </body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let mut document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const pixelOf = (context, x, y) =>
+                        Array.from(context.getImageData(x, y, 1, 1).data).join(',');
+                    const offscreen = new OffscreenCanvas(4, 2);
+                    const painter = offscreen.getContext('2d');
+                    painter.fillStyle = '#00ff00';
+                    painter.fillRect(0, 0, 2, 2);
+                    const bitmap = offscreen.transferToImageBitmap();
+                    const target = document.getElementById('target').getContext('2d');
+                    target.drawImage(bitmap, 0, 0);
+                    const drawn = pixelOf(target, 1, 1);
+                    bitmap.close();
+                    let closed;
+                    try {
+                        target.drawImage(bitmap, 0, 0);
+                    } catch (error) {
+                        closed = error.name;
+                    }
+                    const view = document.getElementById('view');
+                    const control = view.transferControlToOffscreen();
+                    let refused;
+                    try {
+                        view.getContext('2d');
+                    } catch (error) {
+                        refused = error.name;
+                    }
+                    control.getContext('2d').fillRect(0, 0, 8, 8);
+                    let cloned;
+                    try {
+                        structuredClone(offscreen);
+                    } catch (error) {
+                        cloned = error.name;
+                    }
+                    window.results = [];
+                    offscreen
+                        .getContext('2d')
+                        .fillRect(0, 0, 1, 1);
+                    offscreen
+                        .convertToBlob()
+                        .then((blob) => createImageBitmap(blob, 0, 0, 1, 1, {
+                            resizeWidth: 2,
+                            resizeQuality: 'pixelated',
+                        }))
+                        .then((fromBlob) => {
+                            results.push(`${fromBlob.width}x${fromBlob.height}`);
+                            target.drawImage(fromBlob, 4, 4);
+                            results.push(pixelOf(target, 5, 5));
+                        });
+                    [
+                        offscreen.width,
+                        painter instanceof OffscreenCanvasRenderingContext2D,
+                        painter.canvas === offscreen,
+                        `${bitmap.width}x${bitmap.height}`,
+                        drawn,
+                        pixelOf(painter, 0, 0),
+                        closed,
+                        refused,
+                        pixelOf(control.getContext('2d'), 7, 7),
+                        pixelOf(target, 5, 5),
+                        cloned,
+                    ].join('|')
+                "#,
+                "offscreen.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "4|true|true|0x0|0,255,0,255|0,0,0,0|InvalidStateError|InvalidStateError|\
+             0,0,0,255|0,0,0,0|DataCloneError"
+        );
+
+        // The placeholder shows what its offscreen canvas drew.
+        environment.pump().expect("pump");
+        let view = lookup_node_id(&mut document, "view").expect("view");
+        let carrier = *document
+            .get_node(view)
+            .expect("view node")
+            .children
+            .first()
+            .expect("bitmap style");
+        let css = document.get_node(carrier).expect("style").text_content();
+        assert!(css.contains("width: 8px"), "{css}");
+
+        let decoded: String = environment
+            .eval_with("results.join('|')", "offscreen-blob.js")
+            .expect("read results");
+        assert_eq!(decoded, "2x2|0,0,0,255");
+    });
+}
+
+#[test]
+fn webgl_contexts_are_refused_with_a_creation_error() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = "<!DOCTYPE html><html><body><canvas id=\"gl\"></canvas></body></html>";
+        let environment = Rc::new(JsDomEnvironment::new(html).expect("environment"));
+        let _document = RuntimeDocument::attach_to(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            Rc::clone(&environment),
+        );
+
+        let results: String = environment
+            .eval_with(
+                r#"
+                    const canvas = document.getElementById('gl');
+                    const errors = [];
+                    canvas.addEventListener('webglcontextcreationerror', (event) => {
+                        errors.push(event instanceof WebGLContextEvent && event.statusMessage);
+                    });
+                    [
+                        canvas.getContext('webgl'),
+                        canvas.getContext('webgl2'),
+                        canvas.getContext('2d') instanceof CanvasRenderingContext2D,
+                        new OffscreenCanvas(1, 1).getContext('webgl'),
+                        errors.length,
+                        errors[0],
+                    ].join('|')
+                "#,
+                "webgl.js",
+            )
+            .expect("evaluate script");
+        assert_eq!(
+            results,
+            "||true||2|WebGL is not supported; see frontier://gpu."
+        );
+    });
+}
+
+#[test]
+fn window_nostr_requests_wait_for_the_host() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                window.nostr
+                    .signEvent({ kind: 1, content: 'hi', tags: [['t', 'x']] })
+                    .then((event) => log(`signed:${event.id}`));
+                window.nostr.getPublicKey().catch((err) => log(`denied:${err.message}`));
+                log('queued');
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        let _document = RuntimeDocument::attach(
+            HtmlDocument::from_html(html, DocumentConfig::default()),
+            &mut runtime,
+        );
+        runtime.run_blocking_scripts().expect("run scripts");
+
+        let requests = runtime.take_signer_requests();
+        assert_eq!(requests.len(), 2);
+        match &requests[0].method {
+            SignerMethod::SignEvent(template) => {
+                assert_eq!(template.kind, 1);
+                assert_eq!(template.tags, vec![vec!["t".to_string(), "x".to_string()]]);
+            }
+            other => panic!("unexpected request {other:?}"),
+        }
+        assert_eq!(requests[1].method, SignerMethod::GetPublicKey);
+        assert!(runtime.take_signer_requests().is_empty(), "requests drain");
+
+        runtime.settle_signer_request(requests[0].id, Ok(serde_json::json!({ "id": "abc" })));
+        runtime.settle_signer_request(requests[1].id, Err("no identity".into()));
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains("queued;signed:abc;denied:no identity;"),
+            "unexpected log: {html}"
+        );
+    });
+}
+
+#[test]
+fn performance_timing_follows_the_navigation() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let html = r#"<!DOCTYPE html><html><body>
+            <p id="log"></p>
+            <script>
+                const log = (entry) => {
+                    const el = document.getElementById('log');
+                    el.textContent = el.textContent + entry + ';';
+                };
+                const first = performance.now();
+                performance.mark('a');
+                performance.mark('b');
+                const measure = performance.measure('a-to-b', 'a', 'b');
+                log(`now:${first >= 50 && performance.now() >= first}`);
+                log(`marks:${performance.getEntriesByType('mark').map((e) => e.name).join(',')}`);
+                log(`measure:${measure.duration >= 0 && measure.entryType}`);
+                try {
+                    performance.measure('bad', 'missing');
+                } catch (err) {
+                    log(`missing:${err.name}`);
+                }
+                window.addEventListener('load', () => {
+                    const [nav] = performance.getEntriesByType('navigation');
+                    log(`nav:${nav.name}:${nav.fetchStart}:${nav.responseEnd}:${nav.transferSize}`);
+                    log(`dcl:${nav.domContentLoadedEventStart >= nav.responseEnd}`);
+                    log(`load:${nav.loadEventStart > 0 && nav.loadEventEnd === 0}`);
+                });
+            </script>
+        </body></html>"#;
+        let scripts = processor::collect_scripts(html).expect("collect scripts");
+        let mut runtime = JsPageRuntime::new(html, &scripts, None)
+            .expect("create runtime")
+            .expect("runtime available for scripts");
+        runtime.set_navigation_timing(&NavigationTiming {
+            time_origin: std::time::Instant::now() - Duration::from_millis(60),
+            url: "https://example.com/".into(),
+            fetch_start: 5.0,
+            response_end: 20.0,
+            transfer_size: 1234,
+        });
+        let _document = RuntimeDocument::attach(HtmlDocument::from_html(html, DocumentConfig::default()), &mut runtime);
+        runtime.run_blocking_scripts().expect("run scripts");
+        runtime.dispatch_lifecycle(LifecycleEvent::DomContentLoaded);
+        runtime.dispatch_lifecycle(LifecycleEvent::Load);
+
+        let html = runtime.document_html().expect("serialize dom");
+        assert!(
+            html.contains(
+                "now:true;marks:a,b;measure:measure;missing:SyntaxError;nav:https://example.com/:5:20:1234;dcl:true;load:true;"
+            ),
+            "unexpected log: {html}"
+        );
+    });
+}
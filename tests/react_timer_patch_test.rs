@@ -22,7 +22,8 @@ fn react_timer_patch_sequence_resolves() {
     );
 
     let mut dom_state = DomState::new(&timer_html);
-    dom_state.attach_document(&mut document);
+    // SAFETY: `document` is declared first, so it stays put and outlives the state.
+    unsafe { dom_state.attach_document(&mut document) };
 
     let root_handle = dom_state
         .handle_from_element_id("root")
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::Path;
+
+use frontier::wpt::reftest::{ReftestRunner, ReftestTolerance};
+use frontier::wpt::runner::WptManifest;
+use tokio::runtime::Builder;
+
+fn runtime() -> tokio::runtime::Runtime {
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("runtime")
+}
+
+const GREEN_SQUARE: &str = r#"<!DOCTYPE html>
+<style>body { margin: 0 } div { width: 100px; height: 100px; background: green }</style>
+<div></div>"#;
+
+fn write(root: &Path, name: &str, contents: &str) {
+    let path = root.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+#[test]
+fn reftests_compare_renders_against_their_references() {
+    let rt = runtime();
+    rt.block_on(async {
+        let root = tempfile::tempdir().expect("wpt root");
+        let artifacts = tempfile::tempdir().expect("artifact dir");
+        write(
+            root.path(),
+            "css/border-square.html",
+            r#"<!DOCTYPE html>
+<link rel="match" href="reference/green-square.html">
+<link rel="mismatch" href="/blank.html">
+<style>body { margin: 0 } div { width: 0; height: 0; border: 50px solid green }</style>
+<div></div>"#,
+        );
+        write(root.path(), "css/reference/green-square.html", GREEN_SQUARE);
+        write(root.path(), "blank.html", "<!DOCTYPE html>");
+        write(
+            root.path(),
+            "css/red-square.html",
+            r#"<!DOCTYPE html>
+<link rel="match" href="reference/green-square.html">
+<style>body { margin: 0 } div { width: 100px; height: 100px; background: red }</style>
+<div></div>"#,
+        );
+
+        let runner = ReftestRunner::new(root.path()).with_artifact_dir(artifacts.path());
+        let passing = runner
+            .run_reftest(Path::new("css/border-square.html"))
+            .await
+            .expect("run passing reftest");
+        assert!(passing.success(), "expected a pass: {passing:?}");
+        assert_eq!(passing.references.len(), 2);
+
+        let failing = runner
+            .run_reftest(Path::new("css/red-square.html"))
+            .await
+            .expect("run failing reftest");
+        assert!(!failing.success());
+        let outcome = &failing.references[0];
+        assert_eq!(outcome.comparison.differing_pixels, 100 * 100);
+        let artifact_dir = outcome.artifacts.as_ref().expect("failure artifacts");
+        assert!(artifact_dir.join("diff.png").exists());
+        assert!(artifact_dir.join("reference.png").exists());
+
+        let lenient = ReftestRunner::new(root.path()).with_tolerance(ReftestTolerance {
+            max_difference: 0,
+            total_pixels: 100 * 100,
+        });
+        let tolerated = lenient
+            .run_reftest(Path::new("css/red-square.html"))
+            .await
+            .expect("run tolerated reftest");
+        assert!(tolerated.success());
+    });
+}
+
+#[test]
+#[ignore]
+fn wpt_reftest_manifest_summary() {
+    let rt = runtime();
+    rt.block_on(async {
+        let runner = ReftestRunner::new("third_party/wpt").with_artifact_dir("target/wpt-reftests");
+        let manifest = WptManifest::load_from_file("tests/wpt/reftest_manifest.txt")
+            .expect("load reftest manifest");
+        let results = runner.run_manifest(&manifest).await.expect("run reftests");
+        let passed = results.iter().filter(|result| result.success()).count();
+        for result in &results {
+            let status = if result.success() { "PASS" } else { "FAIL" };
+            println!("{status} {}", result.test.display());
+            for outcome in result.references.iter().filter(|outcome| !outcome.passed) {
+                println!(
+                    "     {:?} {}: {} pixels differ{}",
+                    outcome.reference.kind,
+                    outcome.reference.path.display(),
+                    outcome.comparison.differing_pixels,
+                    outcome
+                        .artifacts
+                        .as_ref()
+                        .map(|dir| format!(" (see {})", dir.display()))
+                        .unwrap_or_default()
+                );
+            }
+        }
+        println!("{passed}/{} reftests passed", results.len());
+    });
+}